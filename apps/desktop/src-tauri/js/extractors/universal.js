@@ -273,22 +273,23 @@
     }
 
     /**
-     * Load and merge remote selectors with local defaults
+     * Load the verified selectors for this platform and merge them over local defaults
      */
     async function loadSelectors() {
-        const GITHUB_RAW_URL = 'https://raw.githubusercontent.com/your-repo/noteece/main/config/social_selectors.json';
+        const platform = window.__NOTEECE_CONFIG__.platform;
 
+        // The backend serves signed selector updates, or the bundled selectors after a rollback
         let remoteSelectors = {};
-        try {
-            const response = await fetch(`${GITHUB_RAW_URL}?t=${new Date().getTime()}`); // Cache bust
-            if (response.ok) {
-                remoteSelectors = await response.json();
-                console.log('[Noteece] Successfully loaded remote selectors');
-            } else {
-                console.warn('[Noteece] Failed to load remote selectors, using defaults. Status:', response.status);
+        if (window.__TAURI__ && platform) {
+            try {
+                const active = await window.__TAURI__.invoke('get_active_selectors_cmd', { platform });
+                if (active) {
+                    remoteSelectors = { [platform]: active.selectors };
+                    console.log(`[Noteece] Loaded ${active.source} selectors ${active.version}`);
+                }
+            } catch (error) {
+                console.error('[Noteece] Error loading verified selectors:', error);
             }
-        } catch (error) {
-            console.error('[Noteece] Error fetching remote selectors:', error);
         }
 
         const defaultSelectors = {
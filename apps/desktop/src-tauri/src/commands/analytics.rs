@@ -1,6 +1,10 @@
 use crate::state::DbConnection;
-use core_rs::analytics::AnalyticsData;
-use core_rs::dashboard::DashboardStats;
+use core_rs::analytics::{
+    ActivityHeatmap, ActivityOptions, AnalyticsData, DailyActivity, WritingStats,
+};
+use core_rs::calendar::TimeRange;
+use core_rs::dashboard::{DashboardConfig, DashboardStats};
+use core_rs::db::PoolHealth;
 use tauri::State;
 
 #[tauri::command]
@@ -19,3 +23,86 @@ pub fn get_dashboard_stats_cmd(
         core_rs::dashboard::get_dashboard_stats(&conn, &space_id).map_err(|e| e.to_string())
     })
 }
+
+#[tauri::command]
+pub fn get_dashboard_config_cmd(db: State<DbConnection>) -> Result<DashboardConfig, String> {
+    crate::with_db!(db, conn, {
+        core_rs::dashboard::get_dashboard_config(&conn).map_err(|e| e.to_string())
+    })
+}
+
+#[tauri::command]
+pub fn set_dashboard_config_cmd(
+    db: State<DbConnection>,
+    config: DashboardConfig,
+) -> Result<(), String> {
+    crate::with_db!(db, conn, {
+        core_rs::dashboard::set_dashboard_config(&conn, &config).map_err(|e| e.to_string())
+    })
+}
+
+/// Activity per local weekday and hour in a space
+#[tauri::command]
+pub fn get_activity_heatmap_cmd(
+    db: State<DbConnection>,
+    space_id: String,
+    range: TimeRange,
+    options: Option<ActivityOptions>,
+) -> Result<ActivityHeatmap, String> {
+    crate::with_db!(db, conn, {
+        core_rs::analytics::get_activity_heatmap(
+            &conn,
+            &space_id,
+            range,
+            &options.unwrap_or_default(),
+        )
+        .map_err(|e| e.to_string())
+    })
+}
+
+/// Vault-wide activity per local day
+#[tauri::command]
+pub fn get_daily_activity_series_cmd(
+    db: State<DbConnection>,
+    range: TimeRange,
+    options: Option<ActivityOptions>,
+) -> Result<Vec<DailyActivity>, String> {
+    crate::with_db!(db, conn, {
+        core_rs::analytics::get_daily_activity_series(&conn, range, &options.unwrap_or_default())
+            .map_err(|e| e.to_string())
+    })
+}
+
+/// Words written per local day in a space
+#[tauri::command]
+pub fn get_writing_stats_cmd(
+    db: State<DbConnection>,
+    space_id: String,
+    range: TimeRange,
+    options: Option<ActivityOptions>,
+) -> Result<WritingStats, String> {
+    crate::with_db!(db, conn, {
+        core_rs::analytics::get_writing_stats(&conn, &space_id, range, &options.unwrap_or_default())
+            .map_err(|e| e.to_string())
+    })
+}
+
+/// Connection and WAL statistics of the open vault's pool
+#[tauri::command]
+pub fn get_pool_health_cmd(db: State<DbConnection>) -> Result<PoolHealth, String> {
+    let pool_guard = db
+        .pool
+        .lock()
+        .map_err(|_| "Failed to lock database pool".to_string())?;
+    let pool = pool_guard
+        .as_ref()
+        .ok_or_else(|| "Database not initialized. Please unlock the vault.".to_string())?;
+    let monitor_guard = db
+        .pool_monitor
+        .lock()
+        .map_err(|_| "Failed to lock pool monitor".to_string())?;
+    let monitor = monitor_guard
+        .as_ref()
+        .ok_or_else(|| "Database not initialized. Please unlock the vault.".to_string())?;
+    Ok(monitor.health(pool))
+}
@@ -1,29 +1,23 @@
 use crate::state::DbConnection;
-use core_rs::auth::{AuthService, Session, User};
-use std::collections::HashMap;
-use std::sync::Mutex;
-use std::time::{Duration, Instant};
+use core_rs::audit::{AuditChainReport, AuthAuditEntry, AuthAuditFilter, DataAuditEvent};
+use core_rs::auth::{AuthService, Session, SessionConfig, SessionDevice, SessionInfo, User};
+use core_rs::error::CoreError;
 use tauri::State;
 
-// Simple rate limiter
-lazy_static::lazy_static! {
-    static ref LOGIN_ATTEMPTS: Mutex<HashMap<String, (u32, Instant)>> = Mutex::new(HashMap::new());
-}
-
-const MAX_ATTEMPTS: u32 = 5;
-const LOCKOUT_DURATION: Duration = Duration::from_secs(300); // 5 minutes
-
-fn check_rate_limit(username: &str) -> Result<(), String> {
-    let mut attempts = LOGIN_ATTEMPTS.lock().map_err(|e| e.to_string())?;
-    if let Some((count, first_attempt)) = attempts.get(username) {
-        if *count >= MAX_ATTEMPTS && first_attempt.elapsed() < LOCKOUT_DURATION {
-            return Err(format!(
-                "Too many login attempts. Try again in {} seconds.",
-                (LOCKOUT_DURATION - first_attempt.elapsed()).as_secs()
-            ));
-        }
+/// Metadata identifying this desktop install in the session list
+fn desktop_session_device() -> SessionDevice {
+    let host = std::env::var("HOSTNAME")
+        .or_else(|_| std::env::var("COMPUTERNAME"))
+        .ok()
+        .filter(|h| !h.is_empty());
+    SessionDevice {
+        device_name: Some(host.unwrap_or_else(|| "Desktop".to_string())),
+        user_agent: Some(format!(
+            "Noteece Desktop/{} ({})",
+            env!("CARGO_PKG_VERSION"),
+            std::env::consts::OS
+        )),
     }
-    Ok(())
 }
 
 #[tauri::command]
@@ -32,9 +26,9 @@ pub fn create_user_cmd(
     username: String,
     email: String,
     password: String,
-) -> Result<User, String> {
+) -> Result<User, CoreError> {
     crate::with_db!(db, conn, {
-        AuthService::create_user(&conn, &username, &email, &password).map_err(|e| e.to_string())
+        AuthService::create_user(&conn, &username, &email, &password).map_err(CoreError::from)
     })
 }
 
@@ -43,43 +37,38 @@ pub fn authenticate_user_cmd(
     db: State<DbConnection>,
     username: String,
     password: String,
-) -> Result<Session, String> {
-    check_rate_limit(&username)?;
-
+) -> Result<Session, CoreError> {
+    // Failed attempts and lockouts are tracked in the vault database
     crate::with_db!(db, conn, {
-        let result = AuthService::authenticate(&conn, &username, &password);
-
-        // Track failed attempts
-        if result.is_err() {
-            let mut attempts = LOGIN_ATTEMPTS.lock().map_err(|e| e.to_string())?;
-            let entry = attempts
-                .entry(username.clone())
-                .or_insert((0, Instant::now()));
-            entry.0 += 1;
-        }
-
-        result.map_err(|e| e.to_string())
+        AuthService::authenticate_with_device(
+            &conn,
+            &username,
+            &password,
+            &desktop_session_device(),
+            &SessionConfig::default(),
+        )
+        .map_err(CoreError::from)
     })
 }
 
 #[tauri::command]
-pub fn validate_session_cmd(db: State<DbConnection>, token: String) -> Result<String, String> {
+pub fn validate_session_cmd(db: State<DbConnection>, token: String) -> Result<String, CoreError> {
     crate::with_db!(db, conn, {
-        AuthService::validate_session(&conn, &token).map_err(|e| e.to_string())
+        AuthService::validate_session(&conn, &token).map_err(CoreError::from)
     })
 }
 
 #[tauri::command]
-pub fn logout_user_cmd(db: State<DbConnection>, token: String) -> Result<(), String> {
+pub fn logout_user_cmd(db: State<DbConnection>, token: String) -> Result<(), CoreError> {
     crate::with_db!(db, conn, {
-        AuthService::logout(&conn, &token).map_err(|e| e.to_string())
+        AuthService::logout(&conn, &token).map_err(CoreError::from)
     })
 }
 
 #[tauri::command]
-pub fn get_user_by_id_cmd(db: State<DbConnection>, user_id: String) -> Result<User, String> {
+pub fn get_user_by_id_cmd(db: State<DbConnection>, user_id: String) -> Result<User, CoreError> {
     crate::with_db!(db, conn, {
-        AuthService::get_user(&conn, &user_id).map_err(|e| e.to_string())
+        AuthService::get_user(&conn, &user_id).map_err(CoreError::from)
     })
 }
 
@@ -89,17 +78,103 @@ pub fn change_password_cmd(
     user_id: String,
     old_pass: String,
     new_pass: String,
-) -> Result<(), String> {
+) -> Result<(), CoreError> {
+    crate::with_db!(db, conn, {
+        AuthService::change_password(&conn, &user_id, &old_pass, &new_pass).map_err(CoreError::from)
+    })
+}
+
+#[tauri::command]
+pub fn get_current_user_cmd(db: State<DbConnection>, token: String) -> Result<User, CoreError> {
+    crate::with_db!(db, conn, {
+        let user_id = AuthService::validate_session(&conn, &token)?;
+        AuthService::get_user(&conn, &user_id).map_err(CoreError::from)
+    })
+}
+
+#[tauri::command]
+pub fn refresh_session_cmd(db: State<DbConnection>, token: String) -> Result<Session, CoreError> {
+    crate::with_db!(db, conn, {
+        AuthService::refresh_session(&conn, &token).map_err(CoreError::from)
+    })
+}
+
+#[tauri::command]
+pub fn list_sessions_cmd(
+    db: State<DbConnection>,
+    token: String,
+) -> Result<Vec<SessionInfo>, CoreError> {
+    crate::with_db!(db, conn, {
+        let user_id = AuthService::validate_session(&conn, &token)?;
+        AuthService::list_sessions(&conn, &user_id).map_err(CoreError::from)
+    })
+}
+
+#[tauri::command]
+pub fn revoke_session_cmd(
+    db: State<DbConnection>,
+    token: String,
+    session_id: String,
+) -> Result<(), CoreError> {
     crate::with_db!(db, conn, {
-        AuthService::change_password(&conn, &user_id, &old_pass, &new_pass)
-            .map_err(|e| e.to_string())
+        let user_id = AuthService::validate_session(&conn, &token)?;
+        AuthService::revoke_session(&conn, &user_id, &session_id).map_err(CoreError::from)
     })
 }
 
+/// Sign out everywhere except `keep_session_id` (usually the caller's session)
 #[tauri::command]
-pub fn get_current_user_cmd(db: State<DbConnection>, token: String) -> Result<User, String> {
+pub fn revoke_all_sessions_cmd(
+    db: State<DbConnection>,
+    token: String,
+    keep_session_id: Option<String>,
+) -> Result<usize, CoreError> {
+    crate::with_db!(db, conn, {
+        let user_id = AuthService::validate_session(&conn, &token)?;
+        AuthService::revoke_all_sessions(&conn, &user_id, keep_session_id.as_deref())
+            .map_err(CoreError::from)
+    })
+}
+
+#[tauri::command]
+pub fn get_auth_audit_log_cmd(
+    db: State<DbConnection>,
+    filter: Option<AuthAuditFilter>,
+) -> Result<Vec<AuthAuditEntry>, CoreError> {
+    crate::with_db!(db, conn, {
+        core_rs::audit::get_auth_audit_log(&conn, &filter.unwrap_or_default())
+            .map_err(CoreError::from)
+    })
+}
+
+/// Recorded changes to a note, task or project, oldest first
+#[tauri::command]
+pub fn get_audit_trail_cmd(
+    db: State<DbConnection>,
+    entity_id: String,
+) -> Result<Vec<DataAuditEvent>, CoreError> {
+    crate::with_db!(db, conn, {
+        core_rs::audit::get_audit_trail(&conn, &entity_id).map_err(CoreError::from)
+    })
+}
+
+#[tauri::command]
+pub fn verify_audit_chain_cmd(
+    db: State<DbConnection>,
+    space_id: String,
+) -> Result<AuditChainReport, CoreError> {
+    crate::with_db!(db, conn, {
+        core_rs::audit::verify_audit_chain(&conn, &space_id).map_err(CoreError::from)
+    })
+}
+
+#[tauri::command]
+pub fn set_space_audit_enabled_cmd(
+    db: State<DbConnection>,
+    space_id: String,
+    enabled: bool,
+) -> Result<(), CoreError> {
     crate::with_db!(db, conn, {
-        let user_id = AuthService::validate_session(&conn, &token).map_err(|e| e.to_string())?;
-        AuthService::get_user(&conn, &user_id).map_err(|e| e.to_string())
+        core_rs::audit::set_space_audit_enabled(&conn, &space_id, enabled).map_err(CoreError::from)
     })
 }
@@ -0,0 +1,45 @@
+use crate::state::DbConnection;
+use core_rs::automation::*;
+use core_rs::error::CoreError;
+use tauri::State;
+
+#[tauri::command]
+pub fn create_automation_rule_cmd(
+    db: State<DbConnection>,
+    space_id: String,
+    name: String,
+    trigger: AutomationTrigger,
+    actions: Vec<AutomationAction>,
+) -> Result<AutomationRule, CoreError> {
+    crate::with_db!(db, conn, {
+        create_automation_rule(&conn, &space_id, &name, trigger, actions).map_err(CoreError::from)
+    })
+}
+
+#[tauri::command]
+pub fn get_automation_rules_cmd(
+    db: State<DbConnection>,
+    space_id: String,
+) -> Result<Vec<AutomationRule>, CoreError> {
+    crate::with_db!(db, conn, {
+        get_automation_rules(&conn, &space_id).map_err(CoreError::from)
+    })
+}
+
+#[tauri::command]
+pub fn set_automation_rule_enabled_cmd(
+    db: State<DbConnection>,
+    id: String,
+    enabled: bool,
+) -> Result<(), CoreError> {
+    crate::with_db!(db, conn, {
+        set_automation_rule_enabled(&conn, &id, enabled).map_err(CoreError::from)
+    })
+}
+
+#[tauri::command]
+pub fn delete_automation_rule_cmd(db: State<DbConnection>, id: String) -> Result<(), CoreError> {
+    crate::with_db!(db, conn, {
+        delete_automation_rule(&conn, &id).map_err(CoreError::from)
+    })
+}
@@ -1,7 +1,21 @@
-use crate::state::DbConnection;
+use crate::state::{DbConnection, SecureDek};
+use core_rs::backup::note_bundle::{export_encrypted_note_bundle, import_encrypted_note_bundle};
+use core_rs::note::Note;
 use core_rs::social::backup::{BackupMetadata, BackupService};
+use core_rs::social::backup_schedule::{
+    get_backup_policy, get_backup_runs, run_scheduled_backup, set_backup_policy, should_run_backup,
+    BackupPolicy, BackupRun, BackupRunGuard,
+};
+use std::path::Path;
 use tauri::State;
 
+/// Mark a manual backup command in `dir` as running, so it can't overlap
+/// with a scheduled backup into the same directory
+fn acquire_backup_dir(dir: &Path) -> Result<BackupRunGuard, String> {
+    BackupRunGuard::try_acquire(dir)
+        .ok_or_else(|| "Another backup is already running in this directory".to_string())
+}
+
 #[tauri::command]
 pub fn create_backup_cmd(
     db: State<DbConnection>,
@@ -19,6 +33,7 @@ pub fn create_backup_cmd(
 
         let service =
             BackupService::new(std::path::PathBuf::from("backups")).map_err(|e| e.to_string())?;
+        let _running = acquire_backup_dir(Path::new("backups"))?;
         let backup_id = service
             .create_backup(&conn, dek, Some(&space_id))
             .map_err(|e| e.to_string())?;
@@ -42,6 +57,7 @@ pub fn restore_backup_cmd(db: State<DbConnection>, backup_id: String) -> Result<
 
         let service =
             BackupService::new(std::path::PathBuf::from("backups")).map_err(|e| e.to_string())?;
+        let _running = acquire_backup_dir(Path::new("backups"))?;
         service
             .restore_backup(&backup_id, &mut conn, dek)
             .map_err(|e| e.to_string())
@@ -63,6 +79,7 @@ pub fn list_backups_cmd(
 pub fn delete_backup_cmd(_db: State<DbConnection>, backup_id: String) -> Result<(), String> {
     let service =
         BackupService::new(std::path::PathBuf::from("backups")).map_err(|e| e.to_string())?;
+    let _running = acquire_backup_dir(Path::new("backups"))?;
     service.delete_backup(&backup_id).map_err(|e| e.to_string())
 }
 
@@ -77,3 +94,119 @@ pub fn get_backup_details_cmd(
         .get_backup_details(&backup_id)
         .map_err(|e| e.to_string())
 }
+
+#[tauri::command]
+pub fn get_backup_policy_cmd(db: State<DbConnection>) -> Result<Option<BackupPolicy>, String> {
+    crate::with_db!(db, conn, {
+        get_backup_policy(&conn).map_err(|e| e.to_string())
+    })
+}
+
+#[tauri::command]
+pub fn set_backup_policy_cmd(db: State<DbConnection>, policy: BackupPolicy) -> Result<(), String> {
+    crate::with_db!(db, conn, {
+        set_backup_policy(&conn, &policy).map_err(|e| e.to_string())
+    })
+}
+
+#[tauri::command]
+pub fn get_backup_runs_cmd(
+    db: State<DbConnection>,
+    limit: Option<i64>,
+) -> Result<Vec<BackupRun>, String> {
+    crate::with_db!(db, conn, {
+        get_backup_runs(&conn, limit.unwrap_or(20)).map_err(|e| e.to_string())
+    })
+}
+
+/// Run the scheduled backup if the policy says one is due. Called from the
+/// backup timer, so it does not count as vault activity; with the vault
+/// locked there is no connection and nothing to record.
+pub fn run_due_backup(db: &DbConnection) -> Result<(), String> {
+    let pool = db
+        .pool
+        .lock()
+        .map_err(|_| "Failed to lock database pool".to_string())?
+        .clone();
+    let Some(pool) = pool else {
+        return Ok(());
+    };
+    let conn = pool.get().map_err(|e| e.to_string())?;
+
+    let now = chrono::Utc::now();
+    if !should_run_backup(&conn, now).map_err(|e| e.to_string())? {
+        return Ok(());
+    }
+    let Some(policy) = get_backup_policy(&conn).map_err(|e| e.to_string())? else {
+        return Ok(());
+    };
+    let dek = db
+        .dek
+        .lock()
+        .map_err(|_| "Failed to lock DEK".to_string())?
+        .clone();
+    let outcome = run_scheduled_backup(&conn, dek.as_ref().map(|d| d.as_slice()), &policy, now)
+        .map_err(|e| e.to_string())?;
+    log::info!("[backup] Scheduled backup: {:?}", outcome);
+    Ok(())
+}
+
+/// Vault path and DEK of the unlocked vault, for commands that touch the blob
+/// store
+fn vault_path_and_dek(db: &DbConnection) -> Result<(String, SecureDek), String> {
+    let vault_path = db
+        .vault_path
+        .lock()
+        .map_err(|_| "Failed to lock vault path".to_string())?
+        .clone()
+        .ok_or_else(|| "Vault path not available".to_string())?;
+    let dek = db
+        .dek
+        .lock()
+        .map_err(|_| "Failed to lock DEK".to_string())?
+        .clone()
+        .ok_or_else(|| "DEK not available (Vault locked)".to_string())?;
+    Ok((vault_path.to_string_lossy().into_owned(), dek))
+}
+
+#[tauri::command]
+pub fn export_note_bundle_cmd(
+    db: State<DbConnection>,
+    note_id: String,
+    passphrase: String,
+    path: String,
+) -> Result<(), String> {
+    let (vault_path, dek) = vault_path_and_dek(&db)?;
+    crate::with_db!(db, conn, {
+        export_encrypted_note_bundle(
+            &conn,
+            &vault_path,
+            dek.as_slice(),
+            &note_id,
+            &passphrase,
+            std::path::Path::new(&path),
+        )
+        .map_err(|e| e.to_string())
+    })
+}
+
+#[tauri::command]
+pub fn import_note_bundle_cmd(
+    db: State<DbConnection>,
+    space_id: String,
+    path: String,
+    passphrase: String,
+) -> Result<Note, String> {
+    let (vault_path, dek) = vault_path_and_dek(&db)?;
+    crate::with_db!(db, conn, {
+        import_encrypted_note_bundle(
+            &conn,
+            &vault_path,
+            dek.as_slice(),
+            &space_id,
+            std::path::Path::new(&path),
+            &passphrase,
+        )
+        .map_err(|e| e.to_string())
+    })
+}
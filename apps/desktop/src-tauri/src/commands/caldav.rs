@@ -1,5 +1,6 @@
 use crate::state::DbConnection;
 use core_rs::caldav::*;
+use core_rs::error::CoreError;
 use tauri::State;
 
 #[tauri::command]
@@ -10,15 +11,15 @@ pub fn add_caldav_account_cmd(
     url: String,
     username: String,
     password: Option<String>,
-) -> Result<CalDavAccount, String> {
+) -> Result<CalDavAccount, CoreError> {
     crate::with_db!(db, conn, {
         let dek_guard = db
             .dek
             .lock()
-            .map_err(|_| "Failed to lock DEK".to_string())?;
+            .map_err(|_| CoreError::internal("Failed to lock DEK"))?;
         let dek = dek_guard.as_ref().map(|d| d.as_slice()).unwrap_or(&[]);
         if dek.is_empty() {
-            return Err("DEK not available".to_string());
+            return Err(CoreError::vault_locked());
         }
 
         // add_caldav_account(conn, url, username, password, calendar_path, dek)
@@ -30,7 +31,7 @@ pub fn add_caldav_account_cmd(
             &name,
             dek,
         )
-        .map_err(|e| e.to_string())
+        .map_err(CoreError::from)
     })
 }
 
@@ -38,9 +39,9 @@ pub fn add_caldav_account_cmd(
 pub fn get_caldav_accounts_cmd(
     db: State<DbConnection>,
     space_id: String,
-) -> Result<Vec<CalDavAccount>, String> {
+) -> Result<Vec<CalDavAccount>, CoreError> {
     crate::with_db!(db, conn, {
-        core_rs::caldav::get_caldav_accounts(&conn).map_err(|e| e.to_string())
+        core_rs::caldav::get_caldav_accounts(&conn).map_err(CoreError::from)
     })
 }
 
@@ -48,9 +49,9 @@ pub fn get_caldav_accounts_cmd(
 pub fn get_caldav_account_cmd(
     db: State<DbConnection>,
     account_id: String,
-) -> Result<Option<CalDavAccount>, String> {
+) -> Result<Option<CalDavAccount>, CoreError> {
     crate::with_db!(db, conn, {
-        core_rs::caldav::get_caldav_account(&conn, &account_id).map_err(|e| e.to_string())
+        core_rs::caldav::get_caldav_account(&conn, &account_id).map_err(CoreError::from)
     })
 }
 
@@ -62,12 +63,12 @@ pub fn update_caldav_account_cmd(
     url: String,
     username: String,
     password: Option<String>,
-) -> Result<CalDavAccount, String> {
+) -> Result<CalDavAccount, CoreError> {
     crate::with_db!(db, conn, {
         let dek_guard = db
             .dek
             .lock()
-            .map_err(|_| "Failed to lock DEK".to_string())?;
+            .map_err(|_| CoreError::internal("Failed to lock DEK"))?;
         let dek = dek_guard.as_ref().map(|d| d.as_slice());
 
         core_rs::caldav::update_caldav_account(
@@ -82,12 +83,10 @@ pub fn update_caldav_account_cmd(
             password.as_deref(),
             Some(&name),
             dek,
-        )
-        .map_err(|e| e.to_string())?;
+        )?;
 
-        core_rs::caldav::get_caldav_account(&conn, &account_id)
-            .map_err(|e| e.to_string())?
-            .ok_or_else(|| "CalDAV account not found".to_string())
+        core_rs::caldav::get_caldav_account(&conn, &account_id)?
+            .ok_or_else(|| CoreError::not_found("caldav_account", &account_id))
     })
 }
 
@@ -95,9 +94,9 @@ pub fn update_caldav_account_cmd(
 pub fn delete_caldav_account_cmd(
     db: State<DbConnection>,
     account_id: String,
-) -> Result<(), String> {
+) -> Result<(), CoreError> {
     crate::with_db!(db, conn, {
-        core_rs::caldav::delete_caldav_account(&conn, &account_id).map_err(|e| e.to_string())
+        core_rs::caldav::delete_caldav_account(&conn, &account_id).map_err(CoreError::from)
     })
 }
 
@@ -105,18 +104,18 @@ pub fn delete_caldav_account_cmd(
 pub fn sync_caldav_account_cmd(
     db: State<DbConnection>,
     account_id: String,
-) -> Result<SyncResult, String> {
+) -> Result<SyncResult, CoreError> {
     crate::with_db!(db, conn, {
         let dek_guard = db
             .dek
             .lock()
-            .map_err(|_| "Failed to lock DEK".to_string())?;
+            .map_err(|_| CoreError::internal("Failed to lock DEK"))?;
         let dek = dek_guard.as_ref().map(|d| d.as_slice()).unwrap_or(&[]);
         if dek.is_empty() {
-            return Err("DEK not available".to_string());
+            return Err(CoreError::vault_locked());
         }
 
-        core_rs::caldav::sync_caldav_account(&conn, &account_id, dek).map_err(|e| e.to_string())
+        core_rs::caldav::sync_caldav_account(&conn, &account_id, dek).map_err(CoreError::from)
     })
 }
 
@@ -124,9 +123,9 @@ pub fn sync_caldav_account_cmd(
 pub fn get_caldav_sync_history_cmd(
     db: State<DbConnection>,
     account_id: String,
-) -> Result<Vec<SyncResult>, String> {
+) -> Result<Vec<SyncResult>, CoreError> {
     crate::with_db!(db, conn, {
-        core_rs::caldav::get_sync_history(&conn, &account_id, 50).map_err(|e| e.to_string())
+        core_rs::caldav::get_sync_history(&conn, &account_id, 50).map_err(CoreError::from)
     })
 }
 
@@ -134,9 +133,9 @@ pub fn get_caldav_sync_history_cmd(
 pub fn get_caldav_conflicts_cmd(
     db: State<DbConnection>,
     account_id: String,
-) -> Result<Vec<SyncConflict>, String> {
+) -> Result<Vec<SyncConflict>, CoreError> {
     crate::with_db!(db, conn, {
-        core_rs::caldav::get_unresolved_conflicts(&conn, &account_id).map_err(|e| e.to_string())
+        core_rs::caldav::get_unresolved_conflicts(&conn, &account_id).map_err(CoreError::from)
     })
 }
 
@@ -145,9 +144,47 @@ pub fn resolve_caldav_conflict_cmd(
     db: State<DbConnection>,
     conflict_id: String,
     resolution: ConflictResolution,
-) -> Result<(), String> {
+) -> Result<(), CoreError> {
+    crate::with_db!(db, conn, {
+        core_rs::caldav::resolve_conflict(&conn, &conflict_id, resolution).map_err(CoreError::from)
+    })
+}
+
+#[tauri::command]
+pub fn start_caldav_oauth_cmd(client: OAuthClient) -> Result<OAuthFlow, CoreError> {
+    core_rs::caldav::start_oauth_flow(&client).map_err(CoreError::from)
+}
+
+#[tauri::command]
+pub fn add_caldav_oauth_account_cmd(
+    db: State<DbConnection>,
+    url: String,
+    username: String,
+    calendar_path: String,
+    client: OAuthClient,
+    code: String,
+    code_verifier: String,
+) -> Result<CalDavAccount, CoreError> {
     crate::with_db!(db, conn, {
-        core_rs::caldav::resolve_conflict(&conn, &conflict_id, resolution)
-            .map_err(|e| e.to_string())
+        let dek_guard = db
+            .dek
+            .lock()
+            .map_err(|_| CoreError::internal("Failed to lock DEK"))?;
+        let dek = dek_guard.as_ref().map(|d| d.as_slice()).unwrap_or(&[]);
+        if dek.is_empty() {
+            return Err(CoreError::vault_locked());
+        }
+
+        let tokens = core_rs::caldav::finish_oauth_flow(&client, &code, &code_verifier)?;
+        core_rs::caldav::add_caldav_bearer_account(
+            &conn,
+            &url,
+            &username,
+            &calendar_path,
+            &tokens,
+            Some(&client),
+            dek,
+        )
+        .map_err(CoreError::from)
     })
 }
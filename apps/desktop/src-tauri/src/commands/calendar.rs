@@ -0,0 +1,118 @@
+use crate::state::DbConnection;
+use core_rs::calendar::{
+    CalendarEvent, DueReminder, FocusConstraints, FreeBusy, TimeRange, WorkingHours,
+};
+use tauri::State;
+use ulid::Ulid;
+
+#[tauri::command]
+pub fn get_free_busy_cmd(
+    db: State<DbConnection>,
+    space_id: String,
+    start: i64,
+    end: i64,
+    working_hours: Option<WorkingHours>,
+) -> Result<FreeBusy, String> {
+    crate::with_db!(db, conn, {
+        let space_id = Ulid::from_string(&space_id).map_err(|e| e.to_string())?;
+        core_rs::calendar::get_free_busy(
+            &conn,
+            space_id,
+            TimeRange::new(start, end),
+            &working_hours.unwrap_or_default(),
+        )
+        .map_err(|e| e.to_string())
+    })
+}
+
+#[tauri::command]
+pub fn suggest_focus_blocks_cmd(
+    db: State<DbConnection>,
+    space_id: String,
+    hours_needed: f64,
+    constraints: FocusConstraints,
+) -> Result<Vec<TimeRange>, String> {
+    crate::with_db!(db, conn, {
+        let space_id = Ulid::from_string(&space_id).map_err(|e| e.to_string())?;
+        core_rs::calendar::suggest_focus_blocks(&conn, space_id, hours_needed, &constraints)
+            .map_err(|e| e.to_string())
+    })
+}
+
+#[tauri::command]
+#[allow(clippy::too_many_arguments)]
+pub fn create_calendar_event_cmd(
+    db: State<DbConnection>,
+    space_id: String,
+    summary: String,
+    start: i64,
+    end: Option<i64>,
+    location: Option<String>,
+    description: Option<String>,
+    all_day: bool,
+) -> Result<CalendarEvent, String> {
+    crate::with_db!(db, conn, {
+        let space_id = Ulid::from_string(&space_id).map_err(|e| e.to_string())?;
+        core_rs::calendar::create_event(
+            &conn,
+            space_id,
+            &summary,
+            start,
+            end,
+            location,
+            description,
+            all_day,
+        )
+        .map_err(|e| e.to_string())
+    })
+}
+
+#[tauri::command]
+pub fn update_calendar_event_cmd(
+    db: State<DbConnection>,
+    event: CalendarEvent,
+) -> Result<CalendarEvent, String> {
+    crate::with_db!(db, conn, {
+        core_rs::calendar::update_event(&conn, &event).map_err(|e| e.to_string())
+    })
+}
+
+#[tauri::command]
+pub fn delete_calendar_event_cmd(db: State<DbConnection>, id: String) -> Result<(), String> {
+    crate::with_db!(db, conn, {
+        let id = Ulid::from_string(&id).map_err(|e| e.to_string())?;
+        core_rs::calendar::delete_event(&conn, id).map_err(|e| e.to_string())
+    })
+}
+
+#[tauri::command]
+pub fn get_calendar_events_in_range_cmd(
+    db: State<DbConnection>,
+    space_id: String,
+    start: i64,
+    end: i64,
+) -> Result<Vec<CalendarEvent>, String> {
+    crate::with_db!(db, conn, {
+        let space_id = Ulid::from_string(&space_id).map_err(|e| e.to_string())?;
+        core_rs::calendar::get_events_in_range(&conn, space_id, start, end)
+            .map_err(|e| e.to_string())
+    })
+}
+
+/// Reminders due now; the caller marks each one sent after notifying
+#[tauri::command]
+pub fn get_due_reminders_cmd(db: State<DbConnection>) -> Result<Vec<DueReminder>, String> {
+    crate::with_db!(db, conn, {
+        core_rs::calendar::get_due_reminders(&conn, chrono::Utc::now().timestamp())
+            .map_err(|e| e.to_string())
+    })
+}
+
+#[tauri::command]
+pub fn mark_reminder_sent_cmd(db: State<DbConnection>, event_id: String) -> Result<(), String> {
+    crate::with_db!(db, conn, {
+        let event_id = Ulid::from_string(&event_id).map_err(|e| e.to_string())?;
+        core_rs::calendar::mark_reminder_sent(&conn, event_id, chrono::Utc::now().timestamp())
+            .map_err(|e| e.to_string())
+    })
+}
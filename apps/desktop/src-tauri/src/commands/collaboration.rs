@@ -1,5 +1,6 @@
 use crate::state::DbConnection;
 use core_rs::collaboration::*;
+use core_rs::error::CoreError;
 use tauri::State;
 
 #[tauri::command]
@@ -38,11 +39,42 @@ pub fn invite_user_cmd(
     space_id: String,
     email: String,
     role: String,
-) -> Result<(), String> {
+) -> Result<UserInvitation, String> {
+    crate::with_db!(db, conn, {
+        core_rs::collaboration::create_invitation(&conn, &space_id, &email, &role, "admin")
+            .map_err(|e| e.to_string())
+    })
+}
+
+#[tauri::command]
+pub fn accept_invitation_cmd(
+    db: State<DbConnection>,
+    token: String,
+    user_id: String,
+) -> Result<UserInvitation, String> {
+    crate::with_db!(db, conn, {
+        core_rs::collaboration::accept_invitation(&conn, &token, &user_id)
+            .map_err(|e| e.to_string())
+    })
+}
+
+#[tauri::command]
+pub fn revoke_invitation_cmd(db: State<DbConnection>, invitation_id: String) -> Result<(), String> {
+    crate::with_db!(db, conn, {
+        let user_id = core_rs::db::get_or_create_user_id(&conn).map_err(|e| e.to_string())?;
+        core_rs::collaboration::revoke_invitation(&conn, &invitation_id, &user_id)
+            .map_err(|e| e.to_string())
+    })
+}
+
+#[tauri::command]
+pub fn list_pending_invitations_cmd(
+    db: State<DbConnection>,
+    space_id: String,
+) -> Result<Vec<UserInvitation>, String> {
     crate::with_db!(db, conn, {
-        core_rs::collaboration::invite_user(&conn, &space_id, &email, &role, "admin")
-            .map_err(|e| e.to_string())?;
-        Ok(())
+        core_rs::collaboration::list_pending_invitations(&conn, &space_id)
+            .map_err(|e| e.to_string())
     })
 }
 
@@ -134,3 +166,77 @@ pub fn remove_user_from_space_cmd(
             .map_err(|e| e.to_string())
     })
 }
+
+#[tauri::command]
+pub fn heartbeat_presence_cmd(
+    db: State<DbConnection>,
+    user_id: String,
+    space_id: String,
+    entity_id: Option<String>,
+) -> Result<Presence, CoreError> {
+    crate::with_db!(db, conn, {
+        Ok(heartbeat_presence(
+            &conn,
+            &user_id,
+            &space_id,
+            entity_id.as_deref(),
+        )?)
+    })
+}
+
+#[tauri::command]
+pub fn leave_presence_cmd(
+    db: State<DbConnection>,
+    user_id: String,
+    space_id: String,
+) -> Result<(), CoreError> {
+    crate::with_db!(db, conn, {
+        Ok(leave_presence(&conn, &user_id, &space_id)?)
+    })
+}
+
+#[tauri::command]
+pub fn get_active_users_cmd(
+    db: State<DbConnection>,
+    space_id: String,
+) -> Result<Vec<Presence>, CoreError> {
+    crate::with_db!(db, conn, { Ok(get_active_users(&conn, &space_id)?) })
+}
+
+#[tauri::command]
+pub fn get_entity_viewers_cmd(
+    db: State<DbConnection>,
+    entity_id: String,
+) -> Result<Vec<Presence>, CoreError> {
+    crate::with_db!(db, conn, { Ok(get_entity_viewers(&conn, &entity_id)?) })
+}
+
+/// Fails with `collaboration.locked_by_other`, naming the holder, while
+/// someone else has the entity locked
+#[tauri::command]
+pub fn acquire_edit_lock_cmd(
+    db: State<DbConnection>,
+    user_id: String,
+    entity_id: String,
+    ttl_secs: Option<i64>,
+) -> Result<EditLock, CoreError> {
+    crate::with_db!(db, conn, {
+        Ok(acquire_edit_lock(
+            &conn,
+            &user_id,
+            &entity_id,
+            ttl_secs.unwrap_or(DEFAULT_EDIT_LOCK_TTL_SECS),
+        )?)
+    })
+}
+
+#[tauri::command]
+pub fn release_edit_lock_cmd(
+    db: State<DbConnection>,
+    user_id: String,
+    entity_id: String,
+) -> Result<bool, CoreError> {
+    crate::with_db!(db, conn, {
+        Ok(release_edit_lock(&conn, &user_id, &entity_id)?)
+    })
+}
@@ -43,3 +43,43 @@ pub fn record_feedback_cmd(
         core_rs::foresight::record_feedback(&conn, id, useful).map_err(|e| e.to_string())
     })
 }
+
+#[tauri::command]
+pub fn get_insight_schedule_cmd(
+    db: State<DbConnection>,
+    space_id: String,
+) -> Result<InsightSchedule, String> {
+    crate::with_db!(db, conn, {
+        get_insight_schedule(&conn, &space_id).map_err(|e| e.to_string())
+    })
+}
+
+#[tauri::command]
+pub fn set_insight_schedule_cmd(
+    db: State<DbConnection>,
+    space_id: String,
+    schedule: InsightSchedule,
+) -> Result<(), String> {
+    crate::with_db!(db, conn, {
+        set_insight_schedule(&conn, &space_id, &schedule).map_err(|e| e.to_string())
+    })
+}
+
+/// Generate insights for the spaces whose schedule is due. Called from the
+/// insight timer, so it does not count as vault activity; with the vault
+/// locked there is no pool and nothing to do.
+pub fn run_due_insights(db: &DbConnection) -> Result<(), String> {
+    let pool = db
+        .pool
+        .lock()
+        .map_err(|_| "Failed to lock database pool".to_string())?
+        .clone();
+    let Some(pool) = pool else {
+        return Ok(());
+    };
+    let runs = run_due_insight_generation(&pool).map_err(|e| e.to_string())?;
+    if !runs.is_empty() {
+        log::info!("[foresight] Scheduled insight runs: {:?}", runs);
+    }
+    Ok(())
+}
@@ -56,3 +56,64 @@ pub fn delete_form_template_cmd(db: State<DbConnection>, id: String) -> Result<(
         core_rs::form::delete_form_template(&conn, &id).map_err(|e| e.to_string())
     })
 }
+
+#[tauri::command]
+pub fn submit_form_cmd(
+    db: State<DbConnection>,
+    template_id: String,
+    values: serde_json::Map<String, serde_json::Value>,
+    note_id: Option<String>,
+) -> Result<FormSubmission, String> {
+    crate::with_db!(db, conn, {
+        core_rs::form::submit_form(&conn, &template_id, values, note_id.as_deref())
+            .map_err(|e| e.to_string())
+    })
+}
+
+#[tauri::command]
+pub fn submit_form_with_mapping_cmd(
+    db: State<DbConnection>,
+    template_id: String,
+    values: serde_json::Map<String, serde_json::Value>,
+    space_id: String,
+) -> Result<MappedSubmission, String> {
+    crate::with_db!(db, conn, {
+        core_rs::form::submit_form_with_mapping(&conn, &template_id, values, &space_id)
+            .map_err(|e| e.to_string())
+    })
+}
+
+#[tauri::command]
+pub fn set_form_title_pattern_cmd(
+    db: State<DbConnection>,
+    template_id: String,
+    pattern: Option<String>,
+) -> Result<(), String> {
+    crate::with_db!(db, conn, {
+        core_rs::form::set_form_title_pattern(&conn, &template_id, pattern.as_deref())
+            .map_err(|e| e.to_string())
+    })
+}
+
+#[tauri::command]
+pub fn get_form_submissions_cmd(
+    db: State<DbConnection>,
+    template_id: String,
+    filters: Option<SubmissionFilters>,
+) -> Result<Vec<FormSubmission>, String> {
+    crate::with_db!(db, conn, {
+        core_rs::form::get_form_submissions(&conn, &template_id, &filters.unwrap_or_default())
+            .map_err(|e| e.to_string())
+    })
+}
+
+#[tauri::command]
+pub fn export_form_submissions_csv_cmd(
+    db: State<DbConnection>,
+    template_id: String,
+    path: String,
+) -> Result<usize, String> {
+    crate::with_db!(db, conn, {
+        core_rs::form::export_submissions_csv(&conn, &template_id, &path).map_err(|e| e.to_string())
+    })
+}
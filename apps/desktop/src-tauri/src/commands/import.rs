@@ -1,4 +1,5 @@
 use crate::state::DbConnection;
+use core_rs::import::{ImportReport, NotionImportOptions};
 use tauri::State;
 use ulid::Ulid;
 
@@ -7,7 +8,7 @@ pub fn import_from_obsidian_cmd(
     db: State<DbConnection>,
     space_id: String,
     path: String,
-) -> Result<(), String> {
+) -> Result<ImportReport, String> {
     crate::with_db!(db, conn, {
         let id = Ulid::from_string(&space_id).map_err(|e| e.to_string())?;
         core_rs::import::import_from_obsidian(&conn, id, &path).map_err(|e| e.to_string())
@@ -19,9 +20,16 @@ pub fn import_from_notion_cmd(
     db: State<DbConnection>,
     space_id: String,
     path: String,
-) -> Result<(), String> {
+    options: Option<NotionImportOptions>,
+) -> Result<ImportReport, String> {
     crate::with_db!(db, conn, {
         let id = Ulid::from_string(&space_id).map_err(|e| e.to_string())?;
-        core_rs::import::import_from_notion(&conn, id, &path).map_err(|e| e.to_string())
+        core_rs::import::import_from_notion_with_options(
+            &conn,
+            id,
+            &path,
+            &options.unwrap_or_default(),
+        )
+        .map_err(|e| e.to_string())
     })
 }
@@ -0,0 +1,55 @@
+use crate::state::DbConnection;
+use core_rs::inbox::*;
+use core_rs::note::Note;
+use core_rs::task::Task;
+use tauri::State;
+use ulid::Ulid;
+
+#[tauri::command]
+pub fn capture_to_inbox_cmd(
+    db: State<DbConnection>,
+    text: String,
+    source: String,
+) -> Result<InboxItem, String> {
+    crate::with_db!(db, conn, {
+        capture_to_inbox(&conn, &text, &source).map_err(|e| e.to_string())
+    })
+}
+
+#[tauri::command]
+pub fn get_inbox_items_cmd(db: State<DbConnection>) -> Result<Vec<InboxItem>, String> {
+    crate::with_db!(db, conn, {
+        get_inbox_items(&conn).map_err(|e| e.to_string())
+    })
+}
+
+#[tauri::command]
+pub fn convert_inbox_to_note_cmd(
+    db: State<DbConnection>,
+    item_id: String,
+    space_id: String,
+    title: String,
+) -> Result<Note, String> {
+    crate::with_db_mut!(db, conn, {
+        convert_inbox_to_note(&mut conn, &item_id, &space_id, &title).map_err(|e| e.to_string())
+    })
+}
+
+#[tauri::command]
+pub fn convert_inbox_to_task_cmd(
+    db: State<DbConnection>,
+    item_id: String,
+    space_id: String,
+) -> Result<Task, String> {
+    crate::with_db_mut!(db, conn, {
+        let space_ulid = Ulid::from_string(&space_id).map_err(|e| e.to_string())?;
+        convert_inbox_to_task(&mut conn, &item_id, space_ulid).map_err(|e| e.to_string())
+    })
+}
+
+#[tauri::command]
+pub fn dismiss_inbox_item_cmd(db: State<DbConnection>, item_id: String) -> Result<(), String> {
+    crate::with_db_mut!(db, conn, {
+        dismiss_inbox_item(&mut conn, &item_id).map_err(|e| e.to_string())
+    })
+}
@@ -0,0 +1,91 @@
+//! LLM Streaming Command Handlers
+//!
+//! Streams completions from the local Ollama provider to the frontend.
+//! Each stream emits `llm-stream-{stream_id}` events carrying a `StreamEvent`.
+
+use crate::state::DbConnection;
+use core_rs::llm::providers::OllamaProvider;
+use core_rs::llm::streaming::StreamEvent;
+use core_rs::llm::{LLMProvider, LLMRequest, Message};
+use tauri::{Manager, State};
+use tokio::sync::mpsc;
+
+/// Start streaming a chat completion; returns immediately
+#[tauri::command]
+pub fn stream_llm_chat_cmd(
+    window: tauri::Window,
+    db: State<DbConnection>,
+    stream_id: String,
+    model: String,
+    messages: Vec<Message>,
+    base_url: Option<String>,
+) -> Result<(), String> {
+    let base_url = base_url.unwrap_or_else(|| "http://localhost:11434".to_string());
+    let provider = OllamaProvider::new(base_url).map_err(|e| e.to_string())?;
+    let request = LLMRequest {
+        model: Some(model),
+        messages,
+        temperature: None,
+        max_tokens: None,
+        top_p: None,
+        stop_sequences: None,
+    };
+
+    let event_name = format!("llm-stream-{}", stream_id);
+    let id = stream_id.clone();
+
+    // Hold the lock until the handle is registered so a fast stream can't
+    // remove itself before it was inserted
+    let mut streams = db
+        .llm_streams
+        .lock()
+        .map_err(|_| "Failed to lock LLM streams".to_string())?;
+    let handle = tauri::async_runtime::spawn(async move {
+        let (sink, mut receiver) = mpsc::channel::<StreamEvent>(64);
+
+        // The provider drops the sink when it finishes, which ends forwarding
+        let generate = async move { provider.complete_streaming(&request, &sink).await };
+        let forward = async {
+            while let Some(event) = receiver.recv().await {
+                if let Err(e) = window.emit(&event_name, &event) {
+                    log::warn!("[LLM] Failed to emit stream event: {}", e);
+                    break;
+                }
+            }
+        };
+
+        let (result, _) = tokio::join!(generate, forward);
+        if let Err(e) = result {
+            log::warn!("[LLM] Stream {} ended with error: {}", id, e);
+        }
+
+        if let Some(db) = window.try_state::<DbConnection>() {
+            if let Ok(mut streams) = db.llm_streams.lock() {
+                streams.remove(&id);
+            }
+        }
+    });
+
+    streams.insert(stream_id, handle);
+
+    Ok(())
+}
+
+/// Cancel an in-flight stream, aborting its HTTP request
+#[tauri::command]
+pub fn cancel_llm_stream_cmd(db: State<DbConnection>, stream_id: String) -> Result<bool, String> {
+    let handle = db
+        .llm_streams
+        .lock()
+        .map_err(|_| "Failed to lock LLM streams".to_string())?
+        .remove(&stream_id);
+
+    match handle {
+        Some(handle) => {
+            handle.abort();
+            log::info!("[LLM] Cancelled stream {}", stream_id);
+            Ok(true)
+        }
+        None => Ok(false),
+    }
+}
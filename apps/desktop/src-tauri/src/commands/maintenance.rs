@@ -0,0 +1,98 @@
+//! Maintenance Command Handlers
+//!
+//! Runs the background housekeeping tasks and lists past runs for the
+//! diagnostics screen.
+
+use crate::state::DbConnection;
+use core_rs::db::{
+    apply_query_stats_settings, load_settings, save_settings, QueryStat, QueryStatsSettings,
+    SettingsEntry,
+};
+use core_rs::maintenance::{MaintenanceHistoryEntry, MaintenanceRun, DEFAULT_MAINTENANCE_BUDGET};
+use std::time::Duration;
+use tauri::State;
+
+/// Run the maintenance tasks that fit in `budget_ms` (default 5 seconds)
+#[tauri::command]
+pub fn run_maintenance_cmd(
+    db: State<DbConnection>,
+    budget_ms: Option<u64>,
+) -> Result<MaintenanceRun, String> {
+    db.touch()?;
+    let pool = db
+        .pool
+        .lock()
+        .map_err(|_| "Failed to lock database pool".to_string())?
+        .clone()
+        .ok_or_else(|| "Database not initialized. Please unlock the vault.".to_string())?;
+    let budget = budget_ms.map_or(DEFAULT_MAINTENANCE_BUDGET, Duration::from_millis);
+    core_rs::maintenance::run_maintenance(&pool, budget).map_err(|e| e.to_string())
+}
+
+/// Most recent task runs, newest first
+#[tauri::command]
+pub fn get_maintenance_history_cmd(
+    db: State<DbConnection>,
+    limit: Option<u32>,
+) -> Result<Vec<MaintenanceHistoryEntry>, String> {
+    crate::with_db!(db, conn, {
+        core_rs::maintenance::get_maintenance_history(&conn, limit.unwrap_or(50))
+            .map_err(|e| e.to_string())
+    })
+}
+
+/// Every group of typed settings with the values in effect
+#[tauri::command]
+pub fn list_all_settings_cmd(db: State<DbConnection>) -> Result<Vec<SettingsEntry>, String> {
+    crate::with_db!(db, conn, {
+        core_rs::db::list_all_settings(&conn).map_err(|e| e.to_string())
+    })
+}
+
+/// Recorded query counts and timings, most total time first
+#[tauri::command]
+pub fn get_query_stats_cmd() -> Vec<QueryStat> {
+    core_rs::db::get_query_stats()
+}
+
+/// Forget the recorded query statistics
+#[tauri::command]
+pub fn reset_query_stats_cmd() {
+    core_rs::db::reset_query_stats();
+}
+
+#[tauri::command]
+pub fn get_query_stats_settings_cmd(db: State<DbConnection>) -> Result<QueryStatsSettings, String> {
+    crate::with_db!(db, conn, {
+        load_settings::<QueryStatsSettings>(&conn).map_err(|e| e.to_string())
+    })
+}
+
+/// Save the query statistics settings and apply them right away
+#[tauri::command]
+pub fn set_query_stats_settings_cmd(
+    db: State<DbConnection>,
+    settings: QueryStatsSettings,
+) -> Result<(), String> {
+    crate::with_db!(db, conn, {
+        save_settings(&conn, &settings).map_err(|e| e.to_string())?;
+        apply_query_stats_settings(&settings);
+        Ok(())
+    })
+}
+
+/// Run maintenance from the maintenance timer. It does not count as vault
+/// activity; with the vault locked there is no pool and nothing to do.
+pub fn run_scheduled_maintenance(db: &DbConnection) -> Result<(), String> {
+    let pool = db
+        .pool
+        .lock()
+        .map_err(|_| "Failed to lock database pool".to_string())?
+        .clone();
+    let Some(pool) = pool else {
+        return Ok(());
+    };
+    core_rs::maintenance::run_maintenance(&pool, DEFAULT_MAINTENANCE_BUDGET)
+        .map_err(|e| e.to_string())?;
+    Ok(())
+}
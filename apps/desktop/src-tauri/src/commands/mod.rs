@@ -1,16 +1,22 @@
 pub mod analytics;
 pub mod auth;
+pub mod automation;
 pub mod backup;
 pub mod caldav;
+pub mod calendar;
 pub mod collaboration;
 pub mod foresight;
 pub mod form;
 pub mod import;
+pub mod inbox;
+pub mod llm;
+pub mod maintenance;
 pub mod mode;
 pub mod note;
 pub mod ocr;
 pub mod personal_modes;
 pub mod project;
+pub mod property;
 pub mod search;
 pub mod social;
 pub mod space;
@@ -20,22 +26,29 @@ pub mod tag;
 pub mod task;
 pub mod temporal_graph;
 pub mod time_tracking;
+pub mod undo;
 pub mod vault;
 pub mod weekly_review;
 
 pub use analytics::*;
 pub use auth::*;
+pub use automation::*;
 pub use backup::*;
 pub use caldav::*;
+pub use calendar::*;
 pub use collaboration::*;
 pub use foresight::*;
 pub use form::*;
 pub use import::*;
+pub use inbox::*;
+pub use llm::*;
+pub use maintenance::*;
 pub use mode::*;
 pub use note::*;
 pub use ocr::*;
 pub use personal_modes::*;
 pub use project::*;
+pub use property::*;
 pub use search::*;
 pub use social::*;
 pub use space::*;
@@ -45,5 +58,6 @@ pub use tag::*;
 pub use task::*;
 pub use temporal_graph::*;
 pub use time_tracking::*;
+pub use undo::*;
 pub use vault::*;
 pub use weekly_review::*;
@@ -3,9 +3,12 @@ use core_rs::mode::*;
 use tauri::State;
 
 #[tauri::command]
-pub fn get_space_modes_cmd(db: State<DbConnection>, space_id: String) -> Result<Vec<Mode>, String> {
+pub fn get_space_modes_cmd(
+    db: State<DbConnection>,
+    space_id: String,
+) -> Result<Vec<ModeStatus>, String> {
     crate::with_db!(db, conn, {
-        core_rs::mode::get_space_modes(&conn, &space_id).map_err(|e| e.to_string())
+        core_rs::mode::get_space_modes_with_data(&conn, &space_id).map_err(|e| e.to_string())
     })
 }
 
@@ -34,3 +37,24 @@ pub fn disable_mode_cmd(
         core_rs::mode::disable_mode(&conn, &space_id, &mode_enum).map_err(|e| e.to_string())
     })
 }
+
+#[tauri::command]
+pub fn preview_mode_purge_cmd(
+    db: State<DbConnection>,
+    space_id: String,
+    mode_id: String,
+) -> Result<PurgePreview, String> {
+    crate::with_db!(db, conn, {
+        core_rs::mode::preview_mode_purge(&conn, &space_id, &mode_id).map_err(|e| e.to_string())
+    })
+}
+
+#[tauri::command]
+pub fn purge_mode_cmd(
+    db: State<DbConnection>,
+    preview: PurgePreview,
+) -> Result<Vec<ModeDataCount>, String> {
+    crate::with_db!(db, conn, {
+        core_rs::mode::purge_mode(&conn, &preview).map_err(|e| e.to_string())
+    })
+}
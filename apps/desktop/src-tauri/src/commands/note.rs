@@ -1,8 +1,23 @@
-use crate::state::DbConnection;
+use crate::state::{DbConnection, SecureDek};
+use chrono::NaiveDate;
+use core_rs::bulk_edit::BulkItemResult;
+use core_rs::editor::{Autofix, Diagnostic};
+use core_rs::error::CoreError;
+use core_rs::ids::{parse_entity_id, parse_entity_ids};
+use core_rs::llm::providers::OllamaProvider;
+use core_rs::meeting::{ExtractionMethod, ExtractionReport};
 use core_rs::note::*;
+use core_rs::note_dedup::{
+    DuplicateCluster, MergeStrategy, NoteMergeSummary, DEFAULT_DUPLICATE_THRESHOLD,
+};
+use core_rs::note_session::{EditSession, NoteVersion};
+use core_rs::note_share::{MoveNoteOptions, MoveReport, NoteReference, TagAction, TimeEntryAction};
+use core_rs::note_stats::NoteStats;
+use core_rs::note_template::{NoteFromTemplate, NoteTemplate, TemplateVariable};
 use core_rs::search::{EntityType, SearchFilters, SearchQuery, SearchResult, SortOptions};
+use core_rs::url_metadata::{NoteLinkPreviews, UrlMetadata};
+use std::collections::{BTreeMap, HashMap};
 use tauri::State;
-use ulid::Ulid;
 
 #[tauri::command]
 pub fn create_note_cmd(
@@ -10,17 +25,24 @@ pub fn create_note_cmd(
     space_id: String,
     title: String,
     content: String,
-) -> Result<Note, String> {
+) -> Result<Note, CoreError> {
     crate::with_db!(db, conn, {
-        core_rs::note::create_note(&conn, &space_id, &title, &content).map_err(|e| e.to_string())
+        core_rs::note::create_note(&conn, &space_id, &title, &content).map_err(CoreError::from)
     })
 }
 
 #[tauri::command]
-pub fn get_note_cmd(db: State<DbConnection>, id: String) -> Result<Option<Note>, String> {
+pub fn get_note_cmd(db: State<DbConnection>, id: String) -> Result<Option<Note>, CoreError> {
     crate::with_db!(db, conn, {
-        let id = Ulid::from_string(&id).map_err(|e| e.to_string())?;
-        core_rs::note::get_note(&conn, core_rs::note::DbUlid(id)).map_err(|e| e.to_string())
+        let id = parse_entity_id("note", "id", &id)?;
+        core_rs::note::get_note(&conn, core_rs::note::DbUlid(id)).map_err(CoreError::from)
+    })
+}
+
+#[tauri::command]
+pub fn get_note_stats_cmd(db: State<DbConnection>, id: String) -> Result<NoteStats, CoreError> {
+    crate::with_db!(db, conn, {
+        core_rs::note_stats::get_note_stats(&conn, &id).map_err(CoreError::from)
     })
 }
 
@@ -30,19 +52,263 @@ pub fn update_note_content_cmd(
     id: String,
     title: String,
     content: String,
-) -> Result<(), String> {
+) -> Result<(), CoreError> {
     crate::with_db_mut!(db, conn, {
-        let id = Ulid::from_string(&id).map_err(|e| e.to_string())?;
+        let id = parse_entity_id("note", "id", &id)?;
         core_rs::note::update_note_content(&mut conn, core_rs::note::DbUlid(id), &title, &content)
-            .map_err(|e| e.to_string())
+            .map_err(CoreError::from)
+    })
+}
+
+#[tauri::command]
+pub fn trash_note_cmd(db: State<DbConnection>, id: String) -> Result<(), CoreError> {
+    crate::with_db!(db, conn, {
+        let id = parse_entity_id("note", "id", &id)?;
+        core_rs::note::trash_note(&conn, core_rs::note::DbUlid(id)).map_err(CoreError::from)
+    })
+}
+
+#[tauri::command]
+pub fn lock_note_cmd(
+    db: State<DbConnection>,
+    id: String,
+    passphrase: String,
+) -> Result<(), CoreError> {
+    let dek = unlocked_dek(&db)?;
+    crate::with_db_mut!(db, conn, {
+        core_rs::note_lock::lock_note(&mut conn, dek.as_slice(), &id, &passphrase)
+            .map_err(CoreError::from)
+    })
+}
+
+/// The body of a locked note for viewing; the note stays locked
+#[tauri::command]
+pub fn unlock_note_content_cmd(
+    db: State<DbConnection>,
+    id: String,
+    passphrase: String,
+) -> Result<String, CoreError> {
+    let dek = unlocked_dek(&db)?;
+    crate::with_db!(db, conn, {
+        core_rs::note_lock::unlock_note_content(&conn, dek.as_slice(), &id, &passphrase)
+            .map(|content| content.to_string())
+            .map_err(CoreError::from)
+    })
+}
+
+#[tauri::command]
+pub fn permanently_unlock_note_cmd(
+    db: State<DbConnection>,
+    id: String,
+    passphrase: String,
+) -> Result<Note, CoreError> {
+    let dek = unlocked_dek(&db)?;
+    crate::with_db_mut!(db, conn, {
+        core_rs::note_lock::permanently_unlock_note(&mut conn, dek.as_slice(), &id, &passphrase)
+            .map_err(CoreError::from)
+    })
+}
+
+#[tauri::command]
+pub fn find_unlinked_mentions_cmd(
+    db: State<DbConnection>,
+    note_id: String,
+) -> Result<Vec<core_rs::backlink::UnlinkedMention>, CoreError> {
+    crate::with_db!(db, conn, {
+        let id = parse_entity_id("note", "note_id", &note_id)?;
+        core_rs::backlink::find_unlinked_mentions(&conn, id).map_err(CoreError::from)
+    })
+}
+
+/// Groups of similar notes in the space; `threshold` defaults to 0.8
+#[tauri::command]
+pub fn find_duplicate_notes_cmd(
+    db: State<DbConnection>,
+    space_id: String,
+    threshold: Option<f64>,
+) -> Result<Vec<DuplicateCluster>, CoreError> {
+    crate::with_db!(db, conn, {
+        core_rs::note_dedup::find_duplicate_notes(
+            &conn,
+            &space_id,
+            threshold.unwrap_or(DEFAULT_DUPLICATE_THRESHOLD),
+        )
+        .map_err(CoreError::from)
+    })
+}
+
+#[tauri::command]
+pub fn merge_notes_cmd(
+    db: State<DbConnection>,
+    primary_id: String,
+    duplicate_ids: Vec<String>,
+    strategy: Option<MergeStrategy>,
+) -> Result<NoteMergeSummary, CoreError> {
+    parse_entity_id("note", "primary_id", &primary_id)?;
+    parse_entity_ids("note", "duplicate_ids", &duplicate_ids)?;
+    crate::with_db_mut!(db, conn, {
+        core_rs::note_dedup::merge_notes(
+            &mut conn,
+            &primary_id,
+            &duplicate_ids,
+            strategy.unwrap_or_default(),
+        )
+        .map_err(CoreError::from)
+    })
+}
+
+/// Start editing a note; drafts saved with the returned session are
+/// versioned at most once per the editor's interval
+#[tauri::command]
+pub fn begin_edit_session_cmd(
+    db: State<DbConnection>,
+    note_id: String,
+) -> Result<EditSession, CoreError> {
+    crate::with_db!(db, conn, {
+        core_rs::note_session::begin_edit_session(&conn, &note_id).map_err(CoreError::from)
+    })
+}
+
+#[tauri::command]
+pub fn save_note_draft_cmd(
+    db: State<DbConnection>,
+    session_id: String,
+    content: String,
+) -> Result<Option<NoteVersion>, CoreError> {
+    crate::with_db!(db, conn, {
+        let session_id = parse_entity_id("edit_session", "session_id", &session_id)?;
+        core_rs::note_session::save_note_draft(&conn, &session_id, &content)
+            .map_err(CoreError::from)
+    })
+}
+
+#[tauri::command]
+pub fn end_edit_session_cmd(
+    db: State<DbConnection>,
+    session_id: String,
+) -> Result<Option<NoteVersion>, CoreError> {
+    crate::with_db!(db, conn, {
+        let session_id = parse_entity_id("edit_session", "session_id", &session_id)?;
+        core_rs::note_session::end_edit_session(&conn, &session_id).map_err(CoreError::from)
+    })
+}
+
+#[tauri::command]
+pub fn get_note_versions_cmd(
+    db: State<DbConnection>,
+    note_id: String,
+) -> Result<Vec<NoteVersion>, CoreError> {
+    crate::with_db!(db, conn, {
+        core_rs::note_session::get_note_versions(&conn, &note_id).map_err(CoreError::from)
+    })
+}
+
+/// Move a note (and any `with_notes` from the same space) to another space
+#[tauri::command]
+pub fn move_note_to_space_cmd(
+    db: State<DbConnection>,
+    note_id: String,
+    target_space_id: String,
+    options: Option<MoveNoteOptions>,
+) -> Result<MoveReport, CoreError> {
+    let options = options.unwrap_or_default();
+    parse_entity_id("note", "note_id", &note_id)?;
+    parse_entity_id("space", "target_space_id", &target_space_id)?;
+    parse_entity_ids("note", "with_notes", &options.with_notes)?;
+    crate::with_db_mut!(db, conn, {
+        core_rs::note_share::move_note_to_space(&mut conn, &note_id, &target_space_id, &options)
+            .map_err(CoreError::from)
+    })
+}
+
+/// Tag and untag many notes at once. Unknown or trashed notes are reported
+/// as skipped, or fail the whole edit when `all_or_nothing` is set.
+#[tauri::command]
+pub fn bulk_tag_notes_cmd(
+    db: State<DbConnection>,
+    note_ids: Vec<String>,
+    add_tags: Vec<String>,
+    remove_tags: Vec<String>,
+    all_or_nothing: Option<bool>,
+) -> Result<Vec<BulkItemResult>, CoreError> {
+    crate::with_db!(db, conn, {
+        core_rs::bulk_edit::bulk_tag_notes(
+            &conn,
+            &note_ids,
+            &add_tags,
+            &remove_tags,
+            all_or_nothing.unwrap_or(false),
+        )
+        .map_err(CoreError::from)
+    })
+}
+
+#[tauri::command]
+pub fn bulk_move_notes_to_space_cmd(
+    db: State<DbConnection>,
+    note_ids: Vec<String>,
+    target_space_id: String,
+    tags: Option<TagAction>,
+    time_entries: Option<TimeEntryAction>,
+    all_or_nothing: Option<bool>,
+) -> Result<Vec<BulkItemResult>, CoreError> {
+    parse_entity_id("space", "target_space_id", &target_space_id)?;
+    crate::with_db!(db, conn, {
+        core_rs::bulk_edit::bulk_move_notes_to_space(
+            &conn,
+            &note_ids,
+            &target_space_id,
+            tags.unwrap_or_default(),
+            time_entries.unwrap_or_default(),
+            all_or_nothing.unwrap_or(false),
+        )
+        .map_err(CoreError::from)
+    })
+}
+
+#[tauri::command]
+pub fn bulk_trash_notes_cmd(
+    db: State<DbConnection>,
+    note_ids: Vec<String>,
+    all_or_nothing: Option<bool>,
+) -> Result<Vec<BulkItemResult>, CoreError> {
+    crate::with_db!(db, conn, {
+        core_rs::bulk_edit::bulk_trash_notes(&conn, &note_ids, all_or_nothing.unwrap_or(false))
+            .map_err(CoreError::from)
     })
 }
 
 #[tauri::command]
-pub fn trash_note_cmd(db: State<DbConnection>, id: String) -> Result<(), String> {
+pub fn create_note_reference_cmd(
+    db: State<DbConnection>,
+    note_id: String,
+    target_space_id: String,
+) -> Result<NoteReference, CoreError> {
     crate::with_db!(db, conn, {
-        let id = Ulid::from_string(&id).map_err(|e| e.to_string())?;
-        core_rs::note::trash_note(&conn, core_rs::note::DbUlid(id)).map_err(|e| e.to_string())
+        core_rs::note_share::create_note_reference(&conn, &note_id, &target_space_id)
+            .map_err(CoreError::from)
+    })
+}
+
+#[tauri::command]
+pub fn remove_note_reference_cmd(
+    db: State<DbConnection>,
+    note_id: String,
+    space_id: String,
+) -> Result<(), CoreError> {
+    crate::with_db!(db, conn, {
+        core_rs::note_share::remove_note_reference(&conn, &note_id, &space_id)
+            .map_err(CoreError::from)
+    })
+}
+
+#[tauri::command]
+pub fn get_note_references_cmd(
+    db: State<DbConnection>,
+    note_id: String,
+) -> Result<Vec<NoteReference>, CoreError> {
+    crate::with_db!(db, conn, {
+        core_rs::note_share::get_note_references(&conn, &note_id).map_err(CoreError::from)
     })
 }
 
@@ -50,9 +316,9 @@ pub fn trash_note_cmd(db: State<DbConnection>, id: String) -> Result<(), String>
 pub fn get_all_notes_in_space_cmd(
     db: State<DbConnection>,
     space_id: String,
-) -> Result<Vec<Note>, String> {
+) -> Result<Vec<Note>, CoreError> {
     crate::with_db!(db, conn, {
-        core_rs::note::get_all_notes_in_space(&conn, &space_id).map_err(|e| e.to_string())
+        core_rs::note::get_all_notes_in_space(&conn, &space_id).map_err(CoreError::from)
     })
 }
 
@@ -61,9 +327,9 @@ pub fn get_recent_notes_cmd(
     db: State<DbConnection>,
     space_id: String,
     limit: u32,
-) -> Result<Vec<Note>, String> {
+) -> Result<Vec<Note>, CoreError> {
     crate::with_db!(db, conn, {
-        core_rs::note::get_recent_notes(&conn, &space_id, limit).map_err(|e| e.to_string())
+        core_rs::note::get_recent_notes(&conn, &space_id, limit).map_err(CoreError::from)
     })
 }
 
@@ -71,9 +337,101 @@ pub fn get_recent_notes_cmd(
 pub fn get_or_create_daily_note_cmd(
     db: State<DbConnection>,
     space_id: String,
-) -> Result<Note, String> {
+) -> Result<Note, CoreError> {
+    crate::with_db!(db, conn, {
+        core_rs::note::get_or_create_daily_note(&conn, &space_id).map_err(CoreError::from)
+    })
+}
+
+/// Daily note for `date` ("YYYY-MM-DD"), e.g. to journal a missed day
+#[tauri::command]
+pub fn get_or_create_daily_note_for_date_cmd(
+    db: State<DbConnection>,
+    space_id: String,
+    date: String,
+) -> Result<Note, CoreError> {
+    let date = parse_date(&date)?;
+    crate::with_db!(db, conn, {
+        core_rs::note::get_or_create_daily_note_for_date(&conn, &space_id, date)
+            .map_err(CoreError::from)
+    })
+}
+
+/// Date to note id of the daily notes from `start` through `end`
+#[tauri::command]
+pub fn get_daily_notes_in_range_cmd(
+    db: State<DbConnection>,
+    space_id: String,
+    start: String,
+    end: String,
+) -> Result<BTreeMap<String, String>, CoreError> {
+    let (start, end) = (parse_date(&start)?, parse_date(&end)?);
+    crate::with_db!(db, conn, {
+        core_rs::note::get_daily_notes_in_range(&conn, &space_id, start, end)
+            .map_err(CoreError::from)
+    })
+}
+
+fn parse_date(date: &str) -> Result<NaiveDate, CoreError> {
+    NaiveDate::parse_from_str(date, "%Y-%m-%d").map_err(|e| {
+        CoreError::validation("data.invalid_date", format!("Invalid date {}: {}", date, e))
+            .with_context("date", date)
+    })
+}
+
+#[tauri::command]
+pub fn create_note_template_cmd(
+    db: State<DbConnection>,
+    space_id: String,
+    name: String,
+    content: String,
+    variables: Vec<TemplateVariable>,
+) -> Result<NoteTemplate, CoreError> {
+    crate::with_db!(db, conn, {
+        core_rs::note_template::create_note_template(&conn, &space_id, &name, &content, variables)
+            .map_err(CoreError::from)
+    })
+}
+
+#[tauri::command]
+pub fn get_note_templates_for_space_cmd(
+    db: State<DbConnection>,
+    space_id: String,
+) -> Result<Vec<NoteTemplate>, CoreError> {
+    crate::with_db!(db, conn, {
+        core_rs::note_template::get_note_templates_for_space(&conn, &space_id)
+            .map_err(CoreError::from)
+    })
+}
+
+#[tauri::command]
+pub fn delete_note_template_cmd(db: State<DbConnection>, id: String) -> Result<(), CoreError> {
+    crate::with_db!(db, conn, {
+        core_rs::note_template::delete_note_template(&conn, &id).map_err(CoreError::from)
+    })
+}
+
+#[tauri::command]
+pub fn create_note_from_template_cmd(
+    db: State<DbConnection>,
+    template_id: String,
+    overrides: HashMap<String, String>,
+) -> Result<NoteFromTemplate, CoreError> {
+    crate::with_db!(db, conn, {
+        core_rs::note_template::create_note_from_template(&conn, &template_id, overrides)
+            .map_err(CoreError::from)
+    })
+}
+
+#[tauri::command]
+pub fn set_daily_note_template_cmd(
+    db: State<DbConnection>,
+    space_id: String,
+    template_id: Option<String>,
+) -> Result<(), CoreError> {
     crate::with_db!(db, conn, {
-        core_rs::note::get_or_create_daily_note(&conn, &space_id).map_err(|e| e.to_string())
+        core_rs::note_template::set_daily_note_template(&conn, &space_id, template_id.as_deref())
+            .map_err(CoreError::from)
     })
 }
 
@@ -82,24 +440,132 @@ pub fn search_notes_cmd(
     db: State<DbConnection>,
     query: String,
     scope: Option<String>,
-) -> Result<Vec<SearchResult>, String> {
+) -> Result<Vec<SearchResult>, CoreError> {
     crate::with_db!(db, conn, {
-        let scope_str = scope.as_deref().unwrap_or("");
+        let space_id = scope
+            .as_deref()
+            .filter(|scope| !scope.is_empty())
+            .map(|scope| parse_entity_id("space", "scope", scope))
+            .transpose()?;
         let search_query = SearchQuery {
             query,
             entity_types: vec![EntityType::Note],
             filters: SearchFilters {
-                space_id: if !scope_str.is_empty() {
-                    Some(Ulid::from_string(scope_str).unwrap_or_default())
-                } else {
-                    None
-                },
+                space_id,
                 ..Default::default()
             },
             sort: SortOptions::default(),
             limit: Some(50),
             offset: None,
         };
-        core_rs::search::search_all(&conn, &search_query).map_err(|e| e.to_string())
+        core_rs::search::search_all(&conn, &search_query).map_err(CoreError::from)
+    })
+}
+
+/// Lint note content as it is typed
+#[tauri::command]
+pub fn validate_note_content_cmd(
+    db: State<DbConnection>,
+    space_id: String,
+    content: String,
+) -> Result<Vec<Diagnostic>, CoreError> {
+    crate::with_db!(db, conn, {
+        core_rs::editor::validate_note_content(&conn, &space_id, &content).map_err(CoreError::from)
     })
 }
+
+#[tauri::command]
+pub fn apply_autofixes_cmd(content: String, fixes: Vec<Autofix>) -> Result<String, CoreError> {
+    core_rs::editor::apply_autofixes(&content, &fixes).map_err(CoreError::from)
+}
+
+/// Turn a meeting note's action items into tasks. With `model` set, a local
+/// Ollama model finds the items; otherwise, or if it fails, the heuristic
+/// parser does.
+#[tauri::command]
+pub async fn extract_meeting_actions_cmd(
+    db: State<'_, DbConnection>,
+    note_id: String,
+    model: Option<String>,
+    base_url: Option<String>,
+) -> Result<ExtractionReport, CoreError> {
+    let Some(model) = model else {
+        return crate::with_db!(db, conn, {
+            core_rs::meeting::extract_action_items(&conn, &note_id).map_err(CoreError::from)
+        });
+    };
+
+    // Don't hold a pooled connection while the model runs
+    let content = crate::with_db!(db, conn, {
+        let id = parse_entity_id("note", "note_id", &note_id)?;
+        core_rs::note::get_note(&conn, DbUlid(id))?
+            .map(|note| note.content_md)
+            .ok_or_else(|| CoreError::not_found("note", &note_id))
+    })?;
+
+    let base_url = base_url.unwrap_or_else(|| "http://localhost:11434".to_string());
+    let provider = OllamaProvider::new(base_url)?;
+    let suggested = core_rs::meeting::suggest_action_items(&provider, Some(&model), &content).await;
+
+    crate::with_db!(db, conn, {
+        let (parsed, method) = match suggested {
+            Ok(parsed) => (parsed, ExtractionMethod::Llm),
+            Err(e) => {
+                log::warn!("[meeting] {}; using the heuristic parser", e);
+                let parsed = core_rs::meeting::parse_action_items(&content)?;
+                (parsed, ExtractionMethod::Heuristic)
+            }
+        };
+        core_rs::meeting::apply_action_items(&conn, &note_id, parsed, method)
+            .map_err(CoreError::from)
+    })
+}
+
+/// The note's external links: cached previews, and the links the UI should
+/// fetch with `fetch_url_metadata_cmd`
+#[tauri::command]
+pub fn get_note_link_previews_cmd(
+    db: State<DbConnection>,
+    note_id: String,
+) -> Result<NoteLinkPreviews, CoreError> {
+    crate::with_db!(db, conn, {
+        let id = parse_entity_id("note", "note_id", &note_id)?;
+        core_rs::url_metadata::extract_urls_from_note(&conn, id).map_err(CoreError::from)
+    })
+}
+
+/// Metadata for a link, from the cache unless it has expired. A failed fetch
+/// is cached and returned with `error` set rather than as an error.
+#[tauri::command]
+pub async fn fetch_url_metadata_cmd(
+    db: State<'_, DbConnection>,
+    url: String,
+) -> Result<UrlMetadata, CoreError> {
+    let cached = crate::with_db!(db, conn, {
+        core_rs::url_metadata::get_cached_url_metadata(&conn, &url).map_err(CoreError::from)
+    })?;
+    if let Some(metadata) = cached.filter(|m| !m.is_expired()) {
+        return Ok(metadata);
+    }
+
+    // Don't hold a pooled connection while the page loads
+    let metadata = match core_rs::url_metadata::fetch_url_metadata(&url).await {
+        Ok(metadata) => metadata,
+        Err(e) => {
+            log::warn!("[url_metadata] Failed to fetch {}: {}", url, e);
+            UrlMetadata::failed(&url, &e)
+        }
+    };
+    crate::with_db!(db, conn, {
+        core_rs::url_metadata::cache_url_metadata(&conn, &metadata).map_err(CoreError::from)
+    })?;
+    Ok(metadata)
+}
+
+fn unlocked_dek(db: &DbConnection) -> Result<SecureDek, CoreError> {
+    db.dek
+        .lock()
+        .map_err(|_| CoreError::internal("Failed to lock DEK"))?
+        .clone()
+        .ok_or_else(CoreError::vault_locked)
+}
@@ -4,14 +4,58 @@
 //! OCR is performed using Tesseract when available, with results stored in the database.
 
 use crate::state::DbConnection;
+use core_rs::db::{load_settings, save_settings};
+use core_rs::ocr::OcrSettings;
 use std::fs;
+use std::sync::Arc;
 use tauri::State;
 
-/// Queue a blob for OCR processing
+/// Queue a blob for OCR processing, optionally with BCP-47 language hints
 #[tauri::command]
-pub fn queue_ocr_cmd(db: State<DbConnection>, blob_id: String) -> Result<String, String> {
+pub fn queue_ocr_cmd(
+    db: State<DbConnection>,
+    blob_id: String,
+    languages: Option<Vec<String>>,
+) -> Result<String, String> {
+    crate::with_db!(db, conn, {
+        core_rs::ocr::queue_ocr(&conn, &blob_id, &languages.unwrap_or_default())
+            .map_err(|e| e.to_string())
+    })
+}
+
+/// Re-run OCR on a blob in other languages, replacing its text when done
+#[tauri::command]
+pub fn reprocess_ocr_cmd(
+    db: State<DbConnection>,
+    blob_id: String,
+    languages: Vec<String>,
+) -> Result<String, String> {
     crate::with_db!(db, conn, {
-        core_rs::ocr::queue_ocr(&conn, &blob_id).map_err(|e| e.to_string())
+        core_rs::ocr::reprocess_ocr(&conn, &blob_id, &languages).map_err(|e| e.to_string())
+    })
+}
+
+/// Get the languages a space's blobs are recognized in by default
+#[tauri::command]
+pub fn get_space_ocr_languages_cmd(
+    db: State<DbConnection>,
+    space_id: String,
+) -> Result<Vec<String>, String> {
+    crate::with_db!(db, conn, {
+        core_rs::ocr::get_space_ocr_languages(&conn, &space_id).map_err(|e| e.to_string())
+    })
+}
+
+/// Set the languages a space's blobs are recognized in by default
+#[tauri::command]
+pub fn set_space_ocr_languages_cmd(
+    db: State<DbConnection>,
+    space_id: String,
+    languages: Vec<String>,
+) -> Result<(), String> {
+    crate::with_db!(db, conn, {
+        core_rs::ocr::set_space_ocr_languages(&conn, &space_id, &languages)
+            .map_err(|e| e.to_string())
     })
 }
 
@@ -26,26 +70,29 @@ pub fn get_ocr_status_cmd(
     })
 }
 
-/// Search OCR extracted text
+/// Search OCR extracted text, optionally only results in one language
 #[tauri::command]
 pub fn search_ocr_text_cmd(
     db: State<DbConnection>,
     query: String,
+    language: Option<String>,
 ) -> Result<Vec<core_rs::ocr::OcrResult>, String> {
     crate::with_db!(db, conn, {
-        core_rs::ocr::search_ocr_text(&conn, &query, 100).map_err(|e| e.to_string())
+        core_rs::ocr::search_ocr_text(&conn, &query, language.as_deref(), 100)
+            .map_err(|e| e.to_string())
     })
 }
 
 /// Process a pending OCR job
 ///
 /// This retrieves the blob from encrypted storage, writes it to a temporary file,
-/// runs OCR using Tesseract, and stores the result.
+/// runs OCR using Tesseract, and stores the result. `languages` are used when
+/// neither the job nor its space has any.
 #[tauri::command]
 pub fn process_ocr_job_cmd(
     db: State<DbConnection>,
     blob_id: String,
-    language: Option<String>,
+    languages: Option<Vec<String>>,
 ) -> Result<String, String> {
     // Get vault path and DEK
     let vault_path = {
@@ -58,12 +105,12 @@ pub fn process_ocr_job_cmd(
             .ok_or_else(|| "Vault path not available".to_string())?
     };
 
-    let dek = {
-        let dek_guard = db
-            .dek
+    let auto_lock = {
+        let auto_lock_guard = db
+            .auto_lock
             .lock()
-            .map_err(|_| "Failed to lock DEK".to_string())?;
-        dek_guard
+            .map_err(|_| "Failed to lock auto-lock state".to_string())?;
+        auto_lock_guard
             .clone()
             .ok_or_else(|| "DEK not available (Vault locked)".to_string())?
     };
@@ -85,8 +132,14 @@ pub fn process_ocr_job_cmd(
 
     // Process OCR
     let result = crate::with_db!(db, conn, {
-        core_rs::ocr::process_ocr_job(&conn, &blob_id, &temp_file_path, language.as_deref())
-            .map_err(|e| e.to_string())
+        core_rs::ocr::process_ocr_job(
+            &conn,
+            &core_rs::ocr::TesseractEngine,
+            &blob_id,
+            &temp_file_path,
+            &languages.unwrap_or_default(),
+        )
+        .map_err(|e| e.to_string())
     });
 
     // Clean up temp file
@@ -101,34 +154,7 @@ pub fn get_pending_ocr_jobs_cmd(
     db: State<DbConnection>,
 ) -> Result<Vec<core_rs::ocr::OcrResult>, String> {
     crate::with_db!(db, conn, {
-        let mut stmt = conn.prepare(
-            "SELECT id, blob_id, extracted_text, confidence, status, processed_at, error_message, created_at
-             FROM ocr_result WHERE status = 'pending' ORDER BY created_at ASC LIMIT 50"
-        ).map_err(|e| e.to_string())?;
-
-        let rows = stmt
-            .query_map([], |row| {
-                let status_str: String = row.get(4)?;
-                Ok(core_rs::ocr::OcrResult {
-                    id: row.get(0)?,
-                    blob_id: row.get(1)?,
-                    extracted_text: row.get(2)?,
-                    confidence: row.get(3)?,
-                    status: status_str
-                        .parse()
-                        .unwrap_or(core_rs::ocr::OcrStatus::Unknown),
-                    processed_at: row.get(5)?,
-                    error_message: row.get(6)?,
-                    created_at: row.get(7)?,
-                })
-            })
-            .map_err(|e| e.to_string())?;
-
-        let mut results = Vec::new();
-        for row in rows {
-            results.push(row.map_err(|e| e.to_string())?);
-        }
-        Ok(results)
+        core_rs::ocr::get_pending_ocr_jobs(&conn, 50).map_err(|e| e.to_string())
     })
 }
 
@@ -136,8 +162,9 @@ pub fn get_pending_ocr_jobs_cmd(
 #[tauri::command]
 pub async fn process_ocr_queue_cmd(
     db: State<'_, DbConnection>,
-    language: Option<String>,
+    languages: Option<Vec<String>>,
 ) -> Result<u32, String> {
+    let languages = languages.unwrap_or_default();
     // Get pending jobs
     let pending_jobs: Vec<String> = crate::with_db!(db, conn, {
         let mut stmt = conn.prepare(
@@ -204,13 +231,14 @@ pub async fn process_ocr_queue_cmd(
         let result = {
             let temp_file_path_clone = temp_file_path.clone();
             let blob_id_clone = blob_id.clone();
-            let language_clone = language.clone();
+            let languages_clone = languages.clone();
             crate::with_db!(db, conn, {
                 core_rs::ocr::process_ocr_job(
                     &conn,
+                    &core_rs::ocr::TesseractEngine,
                     &blob_id_clone,
                     &temp_file_path_clone,
-                    language_clone.as_deref(),
+                    &languages_clone,
                 )
             })
         };
@@ -225,3 +253,109 @@ pub async fn process_ocr_queue_cmd(
 
     Ok(processed)
 }
+
+/// The vault's OCR worker settings
+#[tauri::command]
+pub fn get_ocr_settings_cmd(db: State<DbConnection>) -> Result<OcrSettings, String> {
+    crate::with_db!(db, conn, {
+        load_settings::<OcrSettings>(&conn).map_err(|e| e.to_string())
+    })
+}
+
+/// Save the OCR worker settings. A running worker picks them up, apart from
+/// the concurrency, which applies when it's next started.
+#[tauri::command]
+pub fn set_ocr_settings_cmd(db: State<DbConnection>, settings: OcrSettings) -> Result<(), String> {
+    crate::with_db!(db, conn, {
+        save_settings(&conn, &settings).map_err(|e| e.to_string())
+    })
+}
+
+/// Start the background OCR worker that drains the queue. The languages and
+/// concurrency default to the OCR settings.
+#[tauri::command]
+pub fn start_ocr_worker_cmd(
+    db: State<DbConnection>,
+    languages: Option<Vec<String>>,
+    concurrency: Option<usize>,
+) -> Result<(), String> {
+    let pool = {
+        let guard = db
+            .pool
+            .lock()
+            .map_err(|_| "Failed to lock database pool".to_string())?;
+        guard
+            .clone()
+            .ok_or_else(|| "Database not initialized. Please unlock the vault.".to_string())?
+    };
+
+    let vault_path = {
+        let guard = db
+            .vault_path
+            .lock()
+            .map_err(|_| "Failed to lock vault path".to_string())?;
+        guard
+            .clone()
+            .ok_or_else(|| "Vault path not available".to_string())?
+    };
+
+    let auto_lock = {
+        let auto_lock_guard = db
+            .auto_lock
+            .lock()
+            .map_err(|_| "Failed to lock auto-lock state".to_string())?;
+        auto_lock_guard
+            .clone()
+            .ok_or_else(|| "DEK not available (Vault locked)".to_string())?
+    };
+
+    let mut worker_guard = db
+        .ocr_worker
+        .lock()
+        .map_err(|_| "Failed to lock OCR worker".to_string())?;
+    if let Some(worker) = worker_guard.as_ref() {
+        if worker.is_running() {
+            return Ok(());
+        }
+    }
+
+    let settings = pool
+        .get()
+        .map_err(|e| format!("Failed to get connection from pool: {}", e))
+        .and_then(|conn| load_settings::<OcrSettings>(&conn).map_err(|e| e.to_string()))?;
+    let mut config = core_rs::ocr::OcrWorkerConfig::from_settings(vault_path, auto_lock, &settings);
+    if let Some(languages) = languages {
+        config.languages = languages;
+    }
+    if let Some(concurrency) = concurrency {
+        config.concurrency = concurrency;
+    }
+
+    let mut worker =
+        core_rs::ocr::OcrWorker::new(pool, Arc::new(core_rs::ocr::TesseractEngine), config);
+    worker.start();
+    *worker_guard = Some(worker);
+    Ok(())
+}
+
+/// Stop the background OCR worker, waiting for in-flight jobs
+#[tauri::command]
+pub fn stop_ocr_worker_cmd(db: State<DbConnection>) -> Result<(), String> {
+    let worker = db
+        .ocr_worker
+        .lock()
+        .map_err(|_| "Failed to lock OCR worker".to_string())?
+        .take();
+    if let Some(mut worker) = worker {
+        worker.stop();
+    }
+    Ok(())
+}
+
+/// Number of OCR jobs waiting in the queue
+#[tauri::command]
+pub fn get_ocr_queue_depth_cmd(db: State<DbConnection>) -> Result<usize, String> {
+    crate::with_db!(db, conn, {
+        core_rs::ocr::get_ocr_queue_depth(&conn).map_err(|e| e.to_string())
+    })
+}
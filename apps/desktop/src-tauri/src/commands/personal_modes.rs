@@ -1,5 +1,15 @@
 use crate::state::DbConnection;
+use core_rs::calendar::TimeRange;
+use core_rs::health::goals::{HealthGoal, HealthGoalEvaluation, HealthGoalTarget};
+use core_rs::health::import::{HealthExportSource, HealthImportReport};
+use core_rs::health::{MetricTrend, ThresholdBreach};
+use core_rs::ids::parse_entity_id;
 use core_rs::personal_modes::*;
+use core_rs::recipe::{RecipeIngredient, ScaledRecipe, ShoppingList};
+use core_rs::travel::{
+    ItineraryDay, ItineraryItem, ItineraryItemParams, PackingItem, PackingStats, PackingTemplate,
+    TripSummary,
+};
 use tauri::State;
 use ulid::Ulid;
 
@@ -41,6 +51,128 @@ pub fn get_health_metrics_cmd(
     })
 }
 
+#[tauri::command]
+pub fn get_metric_trend_cmd(
+    db: State<DbConnection>,
+    space_id: String,
+    metric_type: String,
+    start: i64,
+    end: i64,
+) -> Result<MetricTrend, String> {
+    crate::with_db!(db, conn, {
+        let space_id = Ulid::from_string(&space_id).map_err(|e| e.to_string())?;
+        core_rs::health::get_metric_trend(&conn, space_id, &metric_type, TimeRange::new(start, end))
+            .map_err(|e| e.to_string())
+    })
+}
+
+#[tauri::command]
+pub fn set_metric_threshold_cmd(
+    db: State<DbConnection>,
+    space_id: String,
+    metric_type: String,
+    min: Option<f64>,
+    max: Option<f64>,
+) -> Result<(), String> {
+    crate::with_db!(db, conn, {
+        let space_id = Ulid::from_string(&space_id).map_err(|e| e.to_string())?;
+        core_rs::health::set_metric_threshold(&conn, space_id, &metric_type, min, max)
+            .map_err(|e| e.to_string())
+    })
+}
+
+#[tauri::command]
+pub fn set_metric_direction_cmd(
+    db: State<DbConnection>,
+    space_id: String,
+    metric_type: String,
+    higher_is_better: bool,
+) -> Result<(), String> {
+    crate::with_db!(db, conn, {
+        let space_id = Ulid::from_string(&space_id).map_err(|e| e.to_string())?;
+        core_rs::health::set_metric_direction(&conn, space_id, &metric_type, higher_is_better)
+            .map_err(|e| e.to_string())
+    })
+}
+
+#[tauri::command]
+pub fn check_threshold_breaches_cmd(
+    db: State<DbConnection>,
+    space_id: String,
+) -> Result<Vec<ThresholdBreach>, String> {
+    crate::with_db!(db, conn, {
+        let space_id = Ulid::from_string(&space_id).map_err(|e| e.to_string())?;
+        core_rs::health::check_threshold_breaches(&conn, space_id).map_err(|e| e.to_string())
+    })
+}
+
+#[tauri::command]
+pub fn import_health_export_cmd(
+    db: State<DbConnection>,
+    space_id: String,
+    path: String,
+    source: HealthExportSource,
+) -> Result<HealthImportReport, String> {
+    crate::with_db!(db, conn, {
+        let space_id = Ulid::from_string(&space_id).map_err(|e| e.to_string())?;
+        core_rs::health::import::import_health_export(
+            &conn,
+            space_id,
+            std::path::Path::new(&path),
+            source,
+        )
+        .map_err(|e| e.to_string())
+    })
+}
+
+#[tauri::command]
+pub fn create_health_goal_cmd(
+    db: State<DbConnection>,
+    space_id: String,
+    title: String,
+    target: HealthGoalTarget,
+) -> Result<HealthGoal, String> {
+    crate::with_db!(db, conn, {
+        let space_id =
+            parse_entity_id("space", "space_id", &space_id).map_err(|e| e.to_string())?;
+        core_rs::health::goals::create_health_goal(&conn, space_id, &title, &target)
+            .map_err(|e| e.to_string())
+    })
+}
+
+#[tauri::command]
+pub fn get_health_goals_cmd(
+    db: State<DbConnection>,
+    space_id: String,
+) -> Result<Vec<HealthGoal>, String> {
+    crate::with_db!(db, conn, {
+        let space_id =
+            parse_entity_id("space", "space_id", &space_id).map_err(|e| e.to_string())?;
+        core_rs::health::goals::get_health_goals(&conn, space_id).map_err(|e| e.to_string())
+    })
+}
+
+#[tauri::command]
+pub fn delete_health_goal_cmd(db: State<DbConnection>, goal_id: String) -> Result<(), String> {
+    crate::with_db!(db, conn, {
+        let goal_id =
+            parse_entity_id("health_goal", "goal_id", &goal_id).map_err(|e| e.to_string())?;
+        core_rs::health::goals::delete_health_goal(&conn, goal_id).map_err(|e| e.to_string())
+    })
+}
+
+#[tauri::command]
+pub fn evaluate_health_goals_cmd(
+    db: State<DbConnection>,
+    space_id: String,
+) -> Result<Vec<HealthGoalEvaluation>, String> {
+    crate::with_db!(db, conn, {
+        let space_id =
+            parse_entity_id("space", "space_id", &space_id).map_err(|e| e.to_string())?;
+        core_rs::health::goals::evaluate_health_goals(&conn, space_id).map_err(|e| e.to_string())
+    })
+}
+
 #[tauri::command]
 pub fn create_transaction_cmd(
     db: State<DbConnection>,
@@ -79,6 +211,56 @@ pub fn get_transactions_cmd(
     })
 }
 
+#[tauri::command]
+pub fn set_budget_cmd(
+    db: State<DbConnection>,
+    space_id: String,
+    category: String,
+    monthly_amount: f64,
+    rollover: bool,
+    start_month: Option<String>,
+) -> Result<Budget, String> {
+    crate::with_db!(db, conn, {
+        let space_id = Ulid::from_string(&space_id).map_err(|e| e.to_string())?;
+        core_rs::personal_modes::set_budget(
+            &conn,
+            space_id,
+            &category,
+            monthly_amount,
+            rollover,
+            start_month.as_deref(),
+        )
+        .map_err(|e| e.to_string())
+    })
+}
+
+#[tauri::command]
+pub fn get_budget_status_cmd(
+    db: State<DbConnection>,
+    space_id: String,
+    month: String,
+) -> Result<Vec<BudgetStatus>, String> {
+    crate::with_db!(db, conn, {
+        let space_id = Ulid::from_string(&space_id).map_err(|e| e.to_string())?;
+        core_rs::personal_modes::get_budget_status(&conn, space_id, &month)
+            .map_err(|e| e.to_string())
+    })
+}
+
+#[tauri::command]
+pub fn get_spending_summary_cmd(
+    db: State<DbConnection>,
+    space_id: String,
+    range: TimeRange,
+    group_by: SpendingGroup,
+) -> Result<Vec<SpendingSummary>, String> {
+    crate::with_db!(db, conn, {
+        let space_id = Ulid::from_string(&space_id).map_err(|e| e.to_string())?;
+        core_rs::personal_modes::get_spending_summary(&conn, space_id, range, group_by)
+            .map_err(|e| e.to_string())
+    })
+}
+
 #[tauri::command]
 pub fn create_recipe_cmd(
     db: State<DbConnection>,
@@ -114,6 +296,105 @@ pub fn get_recipes_cmd(db: State<DbConnection>, space_id: String) -> Result<Vec<
     })
 }
 
+#[tauri::command]
+pub fn add_recipe_ingredient_cmd(
+    db: State<DbConnection>,
+    recipe_id: String,
+    name: String,
+    quantity: String,
+    unit: String,
+    note: Option<String>,
+) -> Result<RecipeIngredient, String> {
+    crate::with_db!(db, conn, {
+        core_rs::recipe::add_recipe_ingredient(
+            &conn,
+            &recipe_id,
+            &name,
+            &quantity,
+            &unit,
+            note.as_deref(),
+        )
+        .map_err(|e| e.to_string())
+    })
+}
+
+#[tauri::command]
+pub fn get_recipe_ingredients_cmd(
+    db: State<DbConnection>,
+    recipe_id: String,
+) -> Result<Vec<RecipeIngredient>, String> {
+    crate::with_db!(db, conn, {
+        core_rs::recipe::get_recipe_ingredients(&conn, &recipe_id).map_err(|e| e.to_string())
+    })
+}
+
+#[tauri::command]
+pub fn set_recipe_servings_cmd(
+    db: State<DbConnection>,
+    recipe_id: String,
+    servings: i64,
+) -> Result<(), String> {
+    crate::with_db!(db, conn, {
+        core_rs::recipe::set_recipe_servings(&conn, &recipe_id, servings).map_err(|e| e.to_string())
+    })
+}
+
+#[tauri::command]
+pub fn scale_recipe_cmd(
+    db: State<DbConnection>,
+    recipe_id: String,
+    target_servings: i64,
+) -> Result<ScaledRecipe, String> {
+    crate::with_db!(db, conn, {
+        core_rs::recipe::scale_recipe(&conn, &recipe_id, target_servings).map_err(|e| e.to_string())
+    })
+}
+
+#[tauri::command]
+pub fn generate_shopping_list_cmd(
+    db: State<DbConnection>,
+    space_id: String,
+    recipe_ids: Vec<String>,
+) -> Result<ShoppingList, String> {
+    crate::with_db!(db, conn, {
+        core_rs::recipe::generate_shopping_list(
+            &conn,
+            Ulid::from_string(&space_id).map_err(|e| e.to_string())?,
+            &recipe_ids,
+        )
+        .map_err(|e| e.to_string())
+    })
+}
+
+#[tauri::command]
+pub fn get_shopping_list_cmd(
+    db: State<DbConnection>,
+    list_id: String,
+) -> Result<ShoppingList, String> {
+    crate::with_db!(db, conn, {
+        core_rs::recipe::get_shopping_list(&conn, &list_id).map_err(|e| e.to_string())
+    })
+}
+
+#[tauri::command]
+pub fn set_shopping_item_checked_cmd(
+    db: State<DbConnection>,
+    item_id: String,
+    checked: bool,
+) -> Result<(), String> {
+    crate::with_db!(db, conn, {
+        core_rs::recipe::set_shopping_item_checked(&conn, &item_id, checked)
+            .map_err(|e| e.to_string())
+    })
+}
+
+#[tauri::command]
+pub fn delete_shopping_list_cmd(db: State<DbConnection>, list_id: String) -> Result<(), String> {
+    crate::with_db!(db, conn, {
+        core_rs::recipe::delete_shopping_list(&conn, &list_id).map_err(|e| e.to_string())
+    })
+}
+
 #[tauri::command]
 pub fn create_trip_cmd(
     db: State<DbConnection>,
@@ -151,6 +432,158 @@ pub fn get_trips_cmd(db: State<DbConnection>, space_id: String) -> Result<Vec<Tr
     })
 }
 
+#[tauri::command]
+#[allow(clippy::too_many_arguments)]
+pub fn add_itinerary_item_cmd(
+    db: State<DbConnection>,
+    trip_id: String,
+    title: String,
+    item_type: String,
+    starts_at: i64,
+    location: Option<String>,
+    notes: Option<String>,
+    allow_outside: bool,
+) -> Result<ItineraryItem, String> {
+    crate::with_db!(db, conn, {
+        core_rs::travel::add_itinerary_item(
+            &conn,
+            &trip_id,
+            ItineraryItemParams {
+                title: &title,
+                item_type: &item_type,
+                starts_at,
+                location: location.as_deref(),
+                notes: notes.as_deref(),
+                allow_outside,
+            },
+        )
+        .map_err(|e| e.to_string())
+    })
+}
+
+#[tauri::command]
+pub fn reorder_itinerary_item_cmd(
+    db: State<DbConnection>,
+    item_id: String,
+    position: i64,
+) -> Result<Vec<ItineraryItem>, String> {
+    crate::with_db!(db, conn, {
+        core_rs::travel::reorder_itinerary_item(&conn, &item_id, position)
+            .map_err(|e| e.to_string())
+    })
+}
+
+#[tauri::command]
+pub fn get_trip_itinerary_cmd(
+    db: State<DbConnection>,
+    trip_id: String,
+) -> Result<Vec<ItineraryDay>, String> {
+    crate::with_db!(db, conn, {
+        core_rs::travel::get_trip_itinerary(&conn, &trip_id).map_err(|e| e.to_string())
+    })
+}
+
+#[tauri::command]
+pub fn add_packing_item_cmd(
+    db: State<DbConnection>,
+    trip_id: String,
+    name: String,
+    category: Option<String>,
+    quantity: Option<i64>,
+) -> Result<PackingItem, String> {
+    crate::with_db!(db, conn, {
+        core_rs::travel::add_packing_item(
+            &conn,
+            &trip_id,
+            &name,
+            category.as_deref(),
+            quantity.unwrap_or(1),
+        )
+        .map_err(|e| e.to_string())
+    })
+}
+
+#[tauri::command]
+pub fn toggle_packed_cmd(db: State<DbConnection>, item_id: String) -> Result<bool, String> {
+    crate::with_db!(db, conn, {
+        core_rs::travel::toggle_packed(&conn, &item_id).map_err(|e| e.to_string())
+    })
+}
+
+#[tauri::command]
+pub fn get_packing_list_cmd(
+    db: State<DbConnection>,
+    trip_id: String,
+) -> Result<Vec<PackingItem>, String> {
+    crate::with_db!(db, conn, {
+        core_rs::travel::get_packing_list(&conn, &trip_id).map_err(|e| e.to_string())
+    })
+}
+
+#[tauri::command]
+pub fn get_trip_packing_stats_cmd(
+    db: State<DbConnection>,
+    trip_id: String,
+) -> Result<PackingStats, String> {
+    crate::with_db!(db, conn, {
+        core_rs::travel::get_trip_packing_stats(&conn, &trip_id).map_err(|e| e.to_string())
+    })
+}
+
+#[tauri::command]
+pub fn save_packing_template_cmd(
+    db: State<DbConnection>,
+    trip_id: String,
+    name: String,
+) -> Result<PackingTemplate, String> {
+    crate::with_db!(db, conn, {
+        core_rs::travel::save_packing_template(&conn, &trip_id, &name).map_err(|e| e.to_string())
+    })
+}
+
+#[tauri::command]
+pub fn get_packing_templates_cmd(
+    db: State<DbConnection>,
+    space_id: String,
+) -> Result<Vec<PackingTemplate>, String> {
+    crate::with_db!(db, conn, {
+        let space_id = Ulid::from_string(&space_id).map_err(|e| e.to_string())?;
+        core_rs::travel::get_packing_templates(&conn, space_id).map_err(|e| e.to_string())
+    })
+}
+
+#[tauri::command]
+pub fn apply_packing_template_cmd(
+    db: State<DbConnection>,
+    template_id: String,
+    trip_id: String,
+) -> Result<Vec<PackingItem>, String> {
+    crate::with_db!(db, conn, {
+        core_rs::travel::apply_packing_template(&conn, &template_id, &trip_id)
+            .map_err(|e| e.to_string())
+    })
+}
+
+#[tauri::command]
+pub fn delete_packing_template_cmd(
+    db: State<DbConnection>,
+    template_id: String,
+) -> Result<(), String> {
+    crate::with_db!(db, conn, {
+        core_rs::travel::delete_packing_template(&conn, &template_id).map_err(|e| e.to_string())
+    })
+}
+
+#[tauri::command]
+pub fn get_trip_summary_cmd(
+    db: State<DbConnection>,
+    trip_id: String,
+) -> Result<TripSummary, String> {
+    crate::with_db!(db, conn, {
+        core_rs::travel::get_trip_summary(&conn, &trip_id).map_err(|e| e.to_string())
+    })
+}
+
 // Goal & Habit Commands
 use core_rs::goals::*;
 use core_rs::habits::*;
@@ -164,7 +597,8 @@ pub fn create_goal_cmd(
     category: String,
 ) -> Result<Goal, String> {
     crate::with_db!(db, conn, {
-        let space_id = Ulid::from_string(&space_id).map_err(|e| e.to_string())?;
+        let space_id =
+            parse_entity_id("space", "space_id", &space_id).map_err(|e| e.to_string())?;
         core_rs::goals::create_goal(&conn, space_id, &title, target, &category)
             .map_err(|e| e.to_string())
     })
@@ -173,7 +607,8 @@ pub fn create_goal_cmd(
 #[tauri::command]
 pub fn get_goals_cmd(db: State<DbConnection>, space_id: String) -> Result<Vec<Goal>, String> {
     crate::with_db!(db, conn, {
-        let space_id = Ulid::from_string(&space_id).map_err(|e| e.to_string())?;
+        let space_id =
+            parse_entity_id("space", "space_id", &space_id).map_err(|e| e.to_string())?;
         core_rs::goals::get_goals(&conn, space_id).map_err(|e| e.to_string())
     })
 }
@@ -185,7 +620,7 @@ pub fn update_goal_progress_cmd(
     current: f64,
 ) -> Result<Goal, String> {
     crate::with_db!(db, conn, {
-        let goal_id = Ulid::from_string(&goal_id).map_err(|e| e.to_string())?;
+        let goal_id = parse_entity_id("goal", "goal_id", &goal_id).map_err(|e| e.to_string())?;
         core_rs::goals::update_goal_progress(&conn, goal_id, current).map_err(|e| e.to_string())
     })
 }
@@ -193,7 +628,7 @@ pub fn update_goal_progress_cmd(
 #[tauri::command]
 pub fn delete_goal_cmd(db: State<DbConnection>, goal_id: String) -> Result<(), String> {
     crate::with_db!(db, conn, {
-        let id = Ulid::from_string(&goal_id).map_err(|e| e.to_string())?;
+        let id = parse_entity_id("goal", "goal_id", &goal_id).map_err(|e| e.to_string())?;
         core_rs::goals::delete_goal(&conn, id).map_err(|e| e.to_string())
     })
 }
@@ -206,7 +641,8 @@ pub fn create_habit_cmd(
     frequency: String,
 ) -> Result<Habit, String> {
     crate::with_db!(db, conn, {
-        let space_id = Ulid::from_string(&space_id).map_err(|e| e.to_string())?;
+        let space_id =
+            parse_entity_id("space", "space_id", &space_id).map_err(|e| e.to_string())?;
         core_rs::habits::create_habit(&conn, space_id, &name, &frequency).map_err(|e| e.to_string())
     })
 }
@@ -214,7 +650,8 @@ pub fn create_habit_cmd(
 #[tauri::command]
 pub fn get_habits_cmd(db: State<DbConnection>, space_id: String) -> Result<Vec<Habit>, String> {
     crate::with_db!(db, conn, {
-        let space_id = Ulid::from_string(&space_id).map_err(|e| e.to_string())?;
+        let space_id =
+            parse_entity_id("space", "space_id", &space_id).map_err(|e| e.to_string())?;
         core_rs::habits::get_habits(&conn, space_id).map_err(|e| e.to_string())
     })
 }
@@ -222,7 +659,8 @@ pub fn get_habits_cmd(db: State<DbConnection>, space_id: String) -> Result<Vec<H
 #[tauri::command]
 pub fn complete_habit_cmd(db: State<DbConnection>, habit_id: String) -> Result<Habit, String> {
     crate::with_db!(db, conn, {
-        let habit_id = Ulid::from_string(&habit_id).map_err(|e| e.to_string())?;
+        let habit_id =
+            parse_entity_id("habit", "habit_id", &habit_id).map_err(|e| e.to_string())?;
         core_rs::habits::complete_habit(&conn, habit_id).map_err(|e| e.to_string())
     })
 }
@@ -230,7 +668,8 @@ pub fn complete_habit_cmd(db: State<DbConnection>, habit_id: String) -> Result<H
 #[tauri::command]
 pub fn delete_habit_cmd(db: State<DbConnection>, habit_id: String) -> Result<(), String> {
     crate::with_db!(db, conn, {
-        let habit_id = Ulid::from_string(&habit_id).map_err(|e| e.to_string())?;
+        let habit_id =
+            parse_entity_id("habit", "habit_id", &habit_id).map_err(|e| e.to_string())?;
         core_rs::habits::delete_habit(&conn, habit_id).map_err(|e| e.to_string())
     })
 }
@@ -49,6 +49,48 @@ pub fn get_project_updates_cmd(
     })
 }
 
+#[tauri::command]
+pub fn create_project_update_cmd(
+    db: State<DbConnection>,
+    project_id: String,
+    when_at: i64,
+    health: String,
+    summary: String,
+    report: ProjectStatusReport,
+) -> Result<ProjectUpdate, String> {
+    crate::with_db!(db, conn, {
+        core_rs::project::create_project_update(
+            &conn,
+            &project_id,
+            when_at,
+            &health,
+            &summary,
+            &report,
+        )
+        .map_err(|e| e.to_string())
+    })
+}
+
+#[tauri::command]
+pub fn get_latest_project_statuses_cmd(
+    db: State<DbConnection>,
+    space_id: String,
+) -> Result<Vec<ProjectUpdate>, String> {
+    crate::with_db!(db, conn, {
+        core_rs::project::get_latest_status_per_project(&conn, &space_id).map_err(|e| e.to_string())
+    })
+}
+
+#[tauri::command]
+pub fn get_space_status_summary_cmd(
+    db: State<DbConnection>,
+    space_id: String,
+) -> Result<SpaceStatusSummary, String> {
+    crate::with_db!(db, conn, {
+        core_rs::project::get_space_status_summary(&conn, &space_id).map_err(|e| e.to_string())
+    })
+}
+
 #[tauri::command]
 pub fn create_project_risk_cmd(
     db: State<DbConnection>,
@@ -84,3 +126,54 @@ pub fn update_project_cmd(db: State<DbConnection>, project: Project) -> Result<(
         core_rs::project::update_project(&conn, &project).map_err(|e| e.to_string())
     })
 }
+
+#[tauri::command]
+pub fn compute_project_health_cmd(
+    db: State<DbConnection>,
+    project_id: String,
+) -> Result<ProjectHealth, String> {
+    crate::with_db!(db, conn, {
+        core_rs::project::compute_project_health(&conn, &project_id).map_err(|e| e.to_string())
+    })
+}
+
+#[tauri::command]
+pub fn get_projects_health_summary_cmd(
+    db: State<DbConnection>,
+    space_id: String,
+) -> Result<ProjectHealthSummary, String> {
+    crate::with_db!(db, conn, {
+        core_rs::project::get_projects_health_summary(&conn, &space_id).map_err(|e| e.to_string())
+    })
+}
+
+#[tauri::command]
+pub fn get_project_health_history_cmd(
+    db: State<DbConnection>,
+    project_id: String,
+    limit_days: Option<i64>,
+) -> Result<Vec<ProjectHealthSnapshot>, String> {
+    crate::with_db!(db, conn, {
+        core_rs::project::get_project_health_history(&conn, &project_id, limit_days)
+            .map_err(|e| e.to_string())
+    })
+}
+
+#[tauri::command]
+pub fn get_project_health_config_cmd(
+    db: State<DbConnection>,
+) -> Result<ProjectHealthConfig, String> {
+    crate::with_db!(db, conn, {
+        core_rs::project::get_project_health_config(&conn).map_err(|e| e.to_string())
+    })
+}
+
+#[tauri::command]
+pub fn set_project_health_config_cmd(
+    db: State<DbConnection>,
+    config: ProjectHealthConfig,
+) -> Result<(), String> {
+    crate::with_db!(db, conn, {
+        core_rs::project::set_project_health_config(&conn, &config).map_err(|e| e.to_string())
+    })
+}
@@ -0,0 +1,118 @@
+use crate::state::DbConnection;
+use core_rs::error::CoreError;
+use core_rs::property::*;
+use core_rs::search::{EntityType, SearchFilters, SearchQuery, SearchResult, SortOptions};
+use tauri::State;
+use ulid::Ulid;
+
+#[tauri::command]
+pub fn define_property_cmd(
+    db: State<DbConnection>,
+    space_id: String,
+    name: String,
+    property_type: PropertyType,
+    options: Vec<String>,
+) -> Result<PropertyDefinition, CoreError> {
+    crate::with_db!(db, conn, {
+        define_property(&conn, &space_id, &name, property_type, &options).map_err(CoreError::from)
+    })
+}
+
+#[tauri::command]
+pub fn get_property_definitions_cmd(
+    db: State<DbConnection>,
+    space_id: String,
+) -> Result<Vec<PropertyDefinition>, CoreError> {
+    crate::with_db!(db, conn, {
+        get_property_definitions(&conn, &space_id).map_err(CoreError::from)
+    })
+}
+
+#[tauri::command]
+pub fn delete_property_cmd(db: State<DbConnection>, id: String) -> Result<(), CoreError> {
+    crate::with_db_mut!(db, conn, {
+        delete_property(&mut conn, &id).map_err(CoreError::from)
+    })
+}
+
+#[tauri::command]
+pub fn set_note_property_cmd(
+    db: State<DbConnection>,
+    note_id: String,
+    property_id: String,
+    value: serde_json::Value,
+) -> Result<NoteProperty, CoreError> {
+    crate::with_db!(db, conn, {
+        set_note_property(&conn, &note_id, &property_id, &value).map_err(CoreError::from)
+    })
+}
+
+#[tauri::command]
+pub fn get_note_properties_cmd(
+    db: State<DbConnection>,
+    note_id: String,
+) -> Result<Vec<NoteProperty>, CoreError> {
+    crate::with_db!(db, conn, {
+        get_note_properties(&conn, &note_id).map_err(CoreError::from)
+    })
+}
+
+#[tauri::command]
+pub fn remove_note_property_cmd(
+    db: State<DbConnection>,
+    note_id: String,
+    property_id: String,
+) -> Result<bool, CoreError> {
+    crate::with_db!(db, conn, {
+        remove_note_property(&conn, &note_id, &property_id).map_err(CoreError::from)
+    })
+}
+
+#[tauri::command]
+pub fn search_notes_by_properties_cmd(
+    db: State<DbConnection>,
+    query: String,
+    space_id: Option<String>,
+    properties: Vec<PropertyFilter>,
+) -> Result<Vec<SearchResult>, CoreError> {
+    crate::with_db!(db, conn, {
+        let query = property_query(query, space_id, properties)?;
+        core_rs::search::search_all(&conn, &query).map_err(CoreError::from)
+    })
+}
+
+/// Set a property on every note the search finds, returning how many
+#[tauri::command]
+pub fn set_property_for_search_cmd(
+    db: State<DbConnection>,
+    query: String,
+    space_id: Option<String>,
+    properties: Vec<PropertyFilter>,
+    property_id: String,
+    value: serde_json::Value,
+) -> Result<usize, CoreError> {
+    crate::with_db_mut!(db, conn, {
+        let query = property_query(query, space_id, properties)?;
+        set_property_for_search(&mut conn, &query, &property_id, &value).map_err(CoreError::from)
+    })
+}
+
+fn property_query(
+    query: String,
+    space_id: Option<String>,
+    properties: Vec<PropertyFilter>,
+) -> Result<SearchQuery, CoreError> {
+    let space_id = space_id.as_deref().map(Ulid::from_string).transpose()?;
+    Ok(SearchQuery {
+        query,
+        entity_types: vec![EntityType::Note],
+        filters: SearchFilters {
+            space_id,
+            properties,
+            ..Default::default()
+        },
+        sort: SortOptions::default(),
+        limit: None,
+        offset: None,
+    })
+}
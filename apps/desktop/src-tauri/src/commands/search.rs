@@ -1,7 +1,7 @@
 use crate::state::DbConnection;
+use core_rs::ids::parse_entity_id;
 use core_rs::search::*;
 use tauri::State;
-use ulid::Ulid;
 
 #[tauri::command]
 pub fn create_saved_search_cmd(
@@ -19,7 +19,7 @@ pub fn create_saved_search_cmd(
 #[tauri::command]
 pub fn get_saved_search_cmd(db: State<DbConnection>, id: String) -> Result<SavedSearch, String> {
     crate::with_db!(db, conn, {
-        let id = Ulid::from_string(&id).map_err(|e| e.to_string())?;
+        let id = parse_entity_id("saved_search", "id", &id).map_err(|e| e.to_string())?;
         core_rs::search::get_saved_search(&conn, id)
             .map_err(|e| e.to_string())?
             .ok_or_else(|| "Saved search not found".to_string())
@@ -44,7 +44,7 @@ pub fn update_saved_search_cmd(
     query: String,
 ) -> Result<SavedSearch, String> {
     crate::with_db!(db, conn, {
-        let id = Ulid::from_string(&id).map_err(|e| e.to_string())?;
+        let id = parse_entity_id("saved_search", "id", &id).map_err(|e| e.to_string())?;
         core_rs::search::update_saved_search(&conn, id, &name, &query).map_err(|e| e.to_string())
     })
 }
@@ -52,7 +52,7 @@ pub fn update_saved_search_cmd(
 #[tauri::command]
 pub fn delete_saved_search_cmd(db: State<DbConnection>, id: String) -> Result<(), String> {
     crate::with_db!(db, conn, {
-        let id = Ulid::from_string(&id).map_err(|e| e.to_string())?;
+        let id = parse_entity_id("saved_search", "id", &id).map_err(|e| e.to_string())?;
         core_rs::search::delete_saved_search(&conn, id).map_err(|e| e.to_string())
     })
 }
@@ -63,28 +63,7 @@ pub fn execute_saved_search_cmd(
     id: String,
 ) -> Result<Vec<SearchResult>, String> {
     crate::with_db!(db, conn, {
-        let id = Ulid::from_string(&id).map_err(|e| e.to_string())?;
-        let search = core_rs::search::get_saved_search(&conn, id)
-            .map_err(|e| e.to_string())?
-            .ok_or_else(|| "Saved search not found".to_string())?;
-
-        use core_rs::search::{EntityType, SearchFilters, SearchQuery, SortOptions};
-        let space_ulid = search
-            .space_id
-            .as_deref()
-            .map(|s| Ulid::from_string(s).unwrap_or_default());
-
-        let query = SearchQuery {
-            query: search.query,
-            entity_types: vec![EntityType::Note],
-            filters: SearchFilters {
-                space_id: space_ulid,
-                ..Default::default()
-            },
-            sort: SortOptions::default(),
-            limit: Some(50),
-            offset: None,
-        };
-        core_rs::search::search_all(&conn, &query).map_err(|e| e.to_string())
+        let id = parse_entity_id("saved_search", "id", &id).map_err(|e| e.to_string())?;
+        core_rs::search::execute_saved_search(&conn, id).map_err(|e| e.to_string())
     })
 }
@@ -1,8 +1,14 @@
 use crate::state::DbConnection;
+use core_rs::calendar::TimeRange;
+use core_rs::note::Note;
 use core_rs::social::account::UpdateSocialAccountParams;
 use core_rs::social::{
-    AnalyticsOverview, SocialAccount, SocialCategory, SocialPost, TimelinePost, TimelineStats,
-    WebViewSession,
+    ActiveSelectors, AnalyticsOverview, ArchiveOptions, CategoryRule, ChunkOutcome,
+    EngagementOptions, ExtractionSummary, FiredAutomation, PlatformAccess, PlatformUsage,
+    PostingTimeAnalysis, RuleCondition, RuleMatchMode, RulePlanEntry, RuleRunResult,
+    SelectorUpdate, SocialAccount, SocialCategory, SocialPost, StorePostsResult, TimeBucket,
+    TimeSeriesPoint, TimelineFilters, TimelinePage, TimelinePost, TimelineStats,
+    TopicClusterOptions, TopicClusteringResult, TopicOverview, WebViewSession,
 };
 use tauri::State;
 
@@ -96,11 +102,97 @@ pub fn store_social_posts_cmd(
     db: State<DbConnection>,
     account_id: String,
     posts: Vec<SocialPost>,
-) -> Result<(), String> {
+) -> Result<StorePostsResult, String> {
     crate::with_db_mut!(db, conn, {
         core_rs::social::store_social_posts(&mut conn, &account_id, posts)
-            .map_err(|e| e.to_string())?;
-        Ok(())
+            .map_err(|e| e.to_string())
+    })
+}
+
+#[tauri::command]
+pub fn begin_extraction_session_cmd(
+    db: State<DbConnection>,
+    account_id: String,
+) -> Result<String, String> {
+    db.touch()?;
+    let mut sessions = db
+        .extraction_sessions
+        .lock()
+        .map_err(|_| "Failed to lock extraction sessions".to_string())?;
+    Ok(sessions.begin_extraction_session(&account_id))
+}
+
+#[tauri::command]
+pub fn ingest_extraction_chunk_cmd(
+    db: State<DbConnection>,
+    session_id: String,
+    payload: String,
+) -> Result<ChunkOutcome, String> {
+    crate::with_db_mut!(db, conn, {
+        let mut sessions = db
+            .extraction_sessions
+            .lock()
+            .map_err(|_| "Failed to lock extraction sessions".to_string())?;
+        sessions
+            .ingest_chunk(&mut conn, &session_id, &payload)
+            .map_err(|e| e.to_string())
+    })
+}
+
+#[tauri::command]
+pub fn finish_extraction_session_cmd(
+    db: State<DbConnection>,
+    session_id: String,
+) -> Result<ExtractionSummary, String> {
+    crate::with_db_mut!(db, conn, {
+        let mut sessions = db
+            .extraction_sessions
+            .lock()
+            .map_err(|_| "Failed to lock extraction sessions".to_string())?;
+        sessions
+            .finish_extraction_session(&mut conn, &session_id)
+            .map_err(|e| e.to_string())
+    })
+}
+
+#[tauri::command]
+pub fn dedup_social_posts_cmd(db: State<DbConnection>, space_id: String) -> Result<usize, String> {
+    crate::with_db_mut!(db, conn, {
+        core_rs::social::dedup_existing_posts(&mut conn, &space_id).map_err(|e| e.to_string())
+    })
+}
+
+#[tauri::command]
+pub fn archive_post_to_note_cmd(
+    db: State<DbConnection>,
+    post_id: String,
+    space_id: String,
+    options: Option<ArchiveOptions>,
+) -> Result<Note, String> {
+    crate::with_db_mut!(db, conn, {
+        core_rs::social::archive_post_to_note(
+            &mut conn,
+            &post_id,
+            &space_id,
+            &options.unwrap_or_default(),
+        )
+        .map_err(|e| e.to_string())
+    })
+}
+
+#[tauri::command]
+pub fn archive_category_to_notes_cmd(
+    db: State<DbConnection>,
+    category_id: String,
+    options: Option<ArchiveOptions>,
+) -> Result<Vec<Note>, String> {
+    crate::with_db_mut!(db, conn, {
+        core_rs::social::archive_category_to_notes(
+            &mut conn,
+            &category_id,
+            &options.unwrap_or_default(),
+        )
+        .map_err(|e| e.to_string())
     })
 }
 
@@ -108,17 +200,48 @@ pub fn store_social_posts_cmd(
 pub fn get_unified_timeline_cmd(
     db: State<DbConnection>,
     space_id: String,
-    limit: u32,
+    filters: Option<TimelineFilters>,
+    limit: Option<u32>,
 ) -> Result<Vec<TimelinePost>, String> {
     crate::with_db!(db, conn, {
-        let filters = core_rs::social::TimelineFilters {
-            limit: Some(limit as i64),
-            ..Default::default()
-        };
+        let mut filters = filters.unwrap_or_default();
+        if let Some(limit) = limit {
+            filters.limit = Some(limit as i64);
+        }
         core_rs::social::get_unified_timeline(&conn, &space_id, filters).map_err(|e| e.to_string())
     })
 }
 
+#[tauri::command]
+pub fn get_unified_timeline_page_cmd(
+    db: State<DbConnection>,
+    space_id: String,
+    filters: Option<TimelineFilters>,
+) -> Result<TimelinePage, String> {
+    crate::with_db!(db, conn, {
+        core_rs::social::get_unified_timeline_page(&conn, &space_id, filters.unwrap_or_default())
+            .map_err(|e| e.to_string())
+    })
+}
+
+#[tauri::command]
+pub fn get_timeline_new_count_cmd(
+    db: State<DbConnection>,
+    space_id: String,
+    since: i64,
+    filters: Option<TimelineFilters>,
+) -> Result<i64, String> {
+    crate::with_db!(db, conn, {
+        core_rs::social::get_timeline_new_count(
+            &conn,
+            &space_id,
+            since,
+            &filters.unwrap_or_default(),
+        )
+        .map_err(|e| e.to_string())
+    })
+}
+
 #[tauri::command]
 pub fn create_category_cmd(
     db: State<DbConnection>,
@@ -184,6 +307,98 @@ pub fn get_analytics_overview_cmd(
     })
 }
 
+/// Engagement per local day or week
+/// Discover topics among the posts in `range` with a local Ollama embedding
+/// model. Embedding can take a while, so it runs off the command thread.
+#[tauri::command]
+pub async fn cluster_timeline_topics_cmd(
+    db: State<'_, DbConnection>,
+    space_id: String,
+    range: TimeRange,
+    model: String,
+    base_url: Option<String>,
+    options: Option<TopicClusterOptions>,
+) -> Result<TopicClusteringResult, String> {
+    db.touch()?;
+    let pool = db
+        .pool
+        .lock()
+        .map_err(|_| "Failed to lock database pool".to_string())?
+        .clone()
+        .ok_or_else(|| "Database not initialized. Please unlock the vault.".to_string())?;
+    let base_url = base_url.unwrap_or_else(|| "http://localhost:11434".to_string());
+
+    tauri::async_runtime::spawn_blocking(move || {
+        let mut conn = pool.get().map_err(|e| e.to_string())?;
+        let embedder =
+            core_rs::ai::OllamaEmbedder::new(&base_url, &model).map_err(|e| e.to_string())?;
+        core_rs::social::cluster_timeline_topics(
+            &mut conn,
+            &space_id,
+            range,
+            &embedder,
+            &options.unwrap_or_default(),
+        )
+        .map_err(|e| e.to_string())
+    })
+    .await
+    .map_err(|e| e.to_string())?
+}
+
+/// Discovered topics with their sizes and average engagement
+#[tauri::command]
+pub fn get_topic_overview_cmd(
+    db: State<DbConnection>,
+    space_id: String,
+) -> Result<Vec<TopicOverview>, String> {
+    crate::with_db!(db, conn, {
+        core_rs::social::get_topic_overview(&conn, &space_id).map_err(|e| e.to_string())
+    })
+}
+
+#[tauri::command]
+pub fn get_engagement_timeseries_cmd(
+    db: State<DbConnection>,
+    space_id: String,
+    platform: Option<String>,
+    range: TimeRange,
+    bucket: TimeBucket,
+    options: Option<EngagementOptions>,
+) -> Result<Vec<TimeSeriesPoint>, String> {
+    crate::with_db!(db, conn, {
+        core_rs::social::get_engagement_timeseries(
+            &conn,
+            &space_id,
+            platform.as_deref(),
+            range,
+            bucket,
+            &options.unwrap_or_default(),
+        )
+        .map_err(|e| e.to_string())
+    })
+}
+
+/// Average engagement by local weekday and hour of posting
+#[tauri::command]
+pub fn get_posting_time_analysis_cmd(
+    db: State<DbConnection>,
+    space_id: String,
+    platform: Option<String>,
+    range: TimeRange,
+    options: Option<EngagementOptions>,
+) -> Result<PostingTimeAnalysis, String> {
+    crate::with_db!(db, conn, {
+        core_rs::social::get_posting_time_analysis(
+            &conn,
+            &space_id,
+            platform.as_deref(),
+            range,
+            &options.unwrap_or_default(),
+        )
+        .map_err(|e| e.to_string())
+    })
+}
+
 #[tauri::command]
 pub fn search_social_posts_cmd(
     db: State<DbConnection>,
@@ -204,6 +419,102 @@ pub fn auto_categorize_posts_cmd(db: State<DbConnection>, space_id: String) -> R
     })
 }
 
+#[tauri::command]
+pub fn create_category_rule_cmd(
+    db: State<DbConnection>,
+    space_id: String,
+    category_id: String,
+    name: String,
+    condition: RuleCondition,
+    priority: Option<i32>,
+) -> Result<CategoryRule, String> {
+    crate::with_db!(db, conn, {
+        core_rs::social::create_category_rule(
+            &conn,
+            &space_id,
+            &category_id,
+            &name,
+            condition,
+            priority.unwrap_or(0),
+        )
+        .map_err(|e| e.to_string())
+    })
+}
+
+#[tauri::command]
+pub fn get_category_rules_cmd(
+    db: State<DbConnection>,
+    space_id: String,
+) -> Result<Vec<CategoryRule>, String> {
+    crate::with_db!(db, conn, {
+        core_rs::social::get_category_rules(&conn, &space_id).map_err(|e| e.to_string())
+    })
+}
+
+#[tauri::command]
+pub fn set_category_rule_enabled_cmd(
+    db: State<DbConnection>,
+    rule_id: String,
+    enabled: bool,
+) -> Result<(), String> {
+    crate::with_db!(db, conn, {
+        core_rs::social::set_category_rule_enabled(&conn, &rule_id, enabled)
+            .map_err(|e| e.to_string())
+    })
+}
+
+#[tauri::command]
+pub fn delete_category_rule_cmd(db: State<DbConnection>, rule_id: String) -> Result<(), String> {
+    crate::with_db!(db, conn, {
+        core_rs::social::delete_category_rule(&conn, &rule_id).map_err(|e| e.to_string())
+    })
+}
+
+#[tauri::command]
+pub fn dry_run_category_rules_cmd(
+    db: State<DbConnection>,
+    space_id: String,
+    mode: Option<RuleMatchMode>,
+) -> Result<Vec<RulePlanEntry>, String> {
+    crate::with_db!(db, conn, {
+        core_rs::social::dry_run_rules(&conn, &space_id, mode.unwrap_or_default())
+            .map_err(|e| e.to_string())
+    })
+}
+
+#[tauri::command]
+pub fn apply_category_rules_cmd(
+    db: State<DbConnection>,
+    space_id: String,
+    mode: Option<RuleMatchMode>,
+) -> Result<RuleRunResult, String> {
+    crate::with_db!(db, conn, {
+        core_rs::social::apply_rules(&conn, &space_id, mode.unwrap_or_default())
+            .map_err(|e| e.to_string())
+    })
+}
+
+#[tauri::command]
+pub fn export_category_rules_cmd(
+    db: State<DbConnection>,
+    space_id: String,
+) -> Result<String, String> {
+    crate::with_db!(db, conn, {
+        core_rs::social::export_rules(&conn, &space_id).map_err(|e| e.to_string())
+    })
+}
+
+#[tauri::command]
+pub fn import_category_rules_cmd(
+    db: State<DbConnection>,
+    space_id: String,
+    json: String,
+) -> Result<usize, String> {
+    crate::with_db!(db, conn, {
+        core_rs::social::import_rules(&conn, &space_id, &json).map_err(|e| e.to_string())
+    })
+}
+
 #[tauri::command]
 pub fn create_webview_session_cmd(
     db: State<DbConnection>,
@@ -238,3 +549,90 @@ pub fn save_session_cookies_cmd(
             .map_err(|e| e.to_string())
     })
 }
+
+#[tauri::command]
+pub fn record_platform_usage_cmd(
+    db: State<DbConnection>,
+    space_id: String,
+    platform: String,
+    seconds: i64,
+) -> Result<Vec<FiredAutomation>, String> {
+    crate::with_db!(db, conn, {
+        core_rs::social::record_platform_usage(&conn, &space_id, &platform, seconds)
+            .map_err(|e| e.to_string())
+    })
+}
+
+#[tauri::command]
+pub fn get_platform_usage_today_cmd(
+    db: State<DbConnection>,
+    space_id: String,
+) -> Result<Vec<PlatformUsage>, String> {
+    crate::with_db!(db, conn, {
+        core_rs::social::get_platform_usage_today(&conn, &space_id).map_err(|e| e.to_string())
+    })
+}
+
+#[tauri::command]
+pub fn check_platform_access_cmd(
+    db: State<DbConnection>,
+    space_id: String,
+    platform: String,
+) -> Result<PlatformAccess, String> {
+    crate::with_db!(db, conn, {
+        core_rs::social::is_platform_blocked(&conn, &space_id, &platform).map_err(|e| e.to_string())
+    })
+}
+
+#[tauri::command]
+pub fn set_usage_timezone_cmd(
+    db: State<DbConnection>,
+    space_id: String,
+    utc_offset_minutes: i32,
+) -> Result<(), String> {
+    crate::with_db!(db, conn, {
+        core_rs::social::set_usage_timezone(&conn, &space_id, utc_offset_minutes)
+            .map_err(|e| e.to_string())
+    })
+}
+
+#[tauri::command]
+pub fn fetch_selector_update_cmd(
+    db: State<DbConnection>,
+    url: String,
+    current_version: String,
+) -> Result<SelectorUpdate, String> {
+    crate::with_db!(db, conn, {
+        core_rs::social::fetch_selector_update(&conn, &url, &current_version)
+            .map_err(|e| e.to_string())
+    })
+}
+
+#[tauri::command]
+pub fn get_active_selectors_cmd(
+    db: State<DbConnection>,
+    platform: String,
+) -> Result<Option<ActiveSelectors>, String> {
+    crate::with_db!(db, conn, {
+        core_rs::social::get_active_selectors(&conn, &platform).map_err(|e| e.to_string())
+    })
+}
+
+#[tauri::command]
+pub fn rollback_selectors_cmd(db: State<DbConnection>, platform: String) -> Result<bool, String> {
+    crate::with_db!(db, conn, {
+        core_rs::social::rollback_selectors(&conn, &platform).map_err(|e| e.to_string())
+    })
+}
+
+#[tauri::command]
+pub fn record_extraction_result_cmd(
+    db: State<DbConnection>,
+    platform: String,
+    succeeded: bool,
+) -> Result<bool, String> {
+    crate::with_db!(db, conn, {
+        core_rs::social::record_extraction_result(&conn, &platform, succeeded)
+            .map_err(|e| e.to_string())
+    })
+}
@@ -1,9 +1,115 @@
 use crate::state::DbConnection;
+use core_rs::space::SpaceDeletionSummary;
+use core_rs::space_template::{SpaceTemplate, BUILTIN_SPACE_TEMPLATES};
 use tauri::State;
 
 #[tauri::command]
-pub fn get_all_spaces_cmd(db: State<DbConnection>) -> Result<Vec<core_rs::space::Space>, String> {
+pub fn get_all_spaces_cmd(
+    db: State<DbConnection>,
+    include_archived: Option<bool>,
+) -> Result<Vec<core_rs::space::Space>, String> {
     crate::with_db!(db, conn, {
-        core_rs::space::get_all_spaces(&conn).map_err(|e| e.to_string())
+        core_rs::space::get_all_spaces(&conn, include_archived.unwrap_or(false))
+            .map_err(|e| e.to_string())
+    })
+}
+
+#[tauri::command]
+pub fn archive_space_cmd(db: State<DbConnection>, space_id: String) -> Result<(), String> {
+    crate::with_db!(db, conn, {
+        core_rs::space::archive_space(&conn, &space_id).map_err(|e| e.to_string())
+    })
+}
+
+#[tauri::command]
+pub fn unarchive_space_cmd(db: State<DbConnection>, space_id: String) -> Result<(), String> {
+    crate::with_db!(db, conn, {
+        core_rs::space::unarchive_space(&conn, &space_id).map_err(|e| e.to_string())
+    })
+}
+
+/// The token to show the user, who types it back to confirm a deletion
+#[tauri::command]
+pub fn get_space_deletion_token_cmd(
+    db: State<DbConnection>,
+    space_id: String,
+) -> Result<String, String> {
+    crate::with_db!(db, conn, {
+        core_rs::space::get_space_deletion_token(&conn, &space_id).map_err(|e| e.to_string())
+    })
+}
+
+#[tauri::command]
+pub fn delete_space_cmd(
+    db: State<DbConnection>,
+    space_id: String,
+    confirm_token: String,
+) -> Result<SpaceDeletionSummary, String> {
+    crate::with_db_mut!(db, conn, {
+        core_rs::space::delete_space(&mut conn, &space_id, &confirm_token)
+            .map_err(|e| e.to_string())
+    })
+}
+
+/// The templates shipped with the app, keyed as in `create_space_from_builtin_template_cmd`
+#[tauri::command]
+pub fn list_builtin_space_templates_cmd() -> Result<Vec<(String, SpaceTemplate)>, String> {
+    BUILTIN_SPACE_TEMPLATES
+        .iter()
+        .map(|key| {
+            core_rs::space_template::builtin_space_template(key)
+                .map(|template| (key.to_string(), template))
+                .map_err(|e| e.to_string())
+        })
+        .collect()
+}
+
+#[tauri::command]
+pub fn export_space_template_cmd(
+    db: State<DbConnection>,
+    space_id: String,
+    path: String,
+    starter_note_ids: Option<Vec<String>>,
+) -> Result<SpaceTemplate, String> {
+    crate::with_db!(db, conn, {
+        core_rs::space_template::export_space_template(
+            &conn,
+            &space_id,
+            std::path::Path::new(&path),
+            &starter_note_ids.unwrap_or_default(),
+        )
+        .map_err(|e| e.to_string())
+    })
+}
+
+#[tauri::command]
+pub fn create_space_from_template_cmd(
+    db: State<DbConnection>,
+    name: String,
+    template_path: String,
+) -> Result<String, String> {
+    crate::with_db_mut!(db, conn, {
+        core_rs::space_template::create_space_from_template(
+            &mut conn,
+            &name,
+            std::path::Path::new(&template_path),
+        )
+        .map(|id| id.to_string())
+        .map_err(|e| e.to_string())
+    })
+}
+
+#[tauri::command]
+pub fn create_space_from_builtin_template_cmd(
+    db: State<DbConnection>,
+    name: String,
+    template_key: String,
+) -> Result<String, String> {
+    let template = core_rs::space_template::builtin_space_template(&template_key)
+        .map_err(|e| e.to_string())?;
+    crate::with_db_mut!(db, conn, {
+        core_rs::space_template::provision_space(&mut conn, &name, &template)
+            .map(|id| id.to_string())
+            .map_err(|e| e.to_string())
     })
 }
@@ -1,7 +1,8 @@
 use crate::state::DbConnection;
+use core_rs::ids::parse_entity_id;
 use core_rs::srs::*;
+use core_rs::srs_session::{ReviewSession, ReviewSessionConfig, ReviewSessionStats};
 use tauri::State;
-use ulid::Ulid;
 
 #[tauri::command]
 pub fn get_due_cards_cmd(db: State<DbConnection>) -> Result<Vec<KnowledgeCard>, String> {
@@ -15,10 +16,39 @@ pub fn review_card_cmd(
     db: State<DbConnection>,
     card_id: String,
     quality: u8,
+    session_id: Option<String>,
 ) -> Result<(), String> {
     crate::with_db!(db, conn, {
-        let id = Ulid::from_string(&card_id).map_err(|e| e.to_string())?;
+        let id = parse_entity_id("card", "card_id", &card_id).map_err(|e| e.to_string())?;
+        let session_id = session_id
+            .map(|s| parse_entity_id("review_session", "session_id", &s))
+            .transpose()
+            .map_err(|e| e.to_string())?;
         // review_card takes i64 rating (0-5)
-        core_rs::srs::review_card(&conn, id, quality as i64).map_err(|e| e.to_string())
+        core_rs::srs::review_card(&conn, id, quality as i64, session_id).map_err(|e| e.to_string())
+    })
+}
+
+#[tauri::command]
+pub fn start_review_session_cmd(
+    db: State<DbConnection>,
+    space_id: String,
+    config: Option<ReviewSessionConfig>,
+) -> Result<ReviewSession, String> {
+    crate::with_db!(db, conn, {
+        core_rs::srs_session::start_review_session(&conn, &space_id, &config.unwrap_or_default())
+            .map_err(|e| e.to_string())
+    })
+}
+
+#[tauri::command]
+pub fn finish_review_session_cmd(
+    db: State<DbConnection>,
+    session_id: String,
+) -> Result<ReviewSessionStats, String> {
+    crate::with_db!(db, conn, {
+        let id = parse_entity_id("review_session", "session_id", &session_id)
+            .map_err(|e| e.to_string())?;
+        core_rs::srs_session::finish_review_session(&conn, id).map_err(|e| e.to_string())
     })
 }
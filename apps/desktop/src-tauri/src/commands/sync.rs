@@ -1,16 +1,72 @@
 use crate::config::AppConfig;
 use crate::state::DbConnection;
+use core_rs::calendar::TimeRange;
+use core_rs::db::{load_settings, save_settings, SyncSettings};
+use core_rs::error::{CoreError, ErrorCategory};
 use core_rs::sync::discovery::DiscoveredDevice;
+use core_rs::sync::p2p::P2pError;
+use core_rs::sync::relay::{RelayClient, RelayConfig};
+use core_rs::sync::relay_sync::{
+    apply_relay_envelopes, clear_relay_registration, fetch_relay_envelopes, get_relay_client,
+    get_relay_url, prepare_relay_session, record_relay_push, relay_key_hash,
+    save_relay_registration, send_relay_acks, send_relay_session,
+};
+use core_rs::sync::secure_channel::load_or_create_static_key;
+use core_rs::sync::{
+    get_device_sync_scope, get_device_user, get_rejected_deltas,
+    get_unresolved_conflicts_described, has_merge_conflict, set_device_sync_scope, set_device_user,
+    ConflictSummary, PairingHello, RejectedDelta, RelayPullReport, RelaySyncError, ShortAuthString,
+    SyncScope, SyncTransferReport,
+};
 use core_rs::sync_agent::{
     ConflictResolution as SyncConflictResolution, SyncAgent, SyncConflict as DbSyncConflict,
     SyncHistoryEntry, SyncStats, SyncTask,
 };
 use tauri::State;
 
+/// P2P sync is set up on unlock, so it is missing while the vault is locked
+fn p2p_unavailable() -> CoreError {
+    CoreError::new(
+        ErrorCategory::VaultLocked,
+        "sync.p2p_unavailable",
+        "P2P sync not initialized (Vault locked?)",
+    )
+}
+
+/// Port from the vault's sync settings, or the app config's if they can't be read
+fn sync_port(conn: &rusqlite::Connection) -> u16 {
+    load_settings::<SyncSettings>(conn).map_or_else(|_| AppConfig::sync_port(), |s| s.port)
+}
+
+/// The vault's sync settings
 #[tauri::command]
-pub fn init_sync_tables_cmd(db: State<DbConnection>) -> Result<(), String> {
+pub fn get_sync_settings_cmd(db: State<DbConnection>) -> Result<SyncSettings, CoreError> {
+    crate::with_db!(db, conn, { Ok(load_settings::<SyncSettings>(&conn)?) })
+}
+
+/// Save the sync settings; a new port applies the next time sync starts,
+/// the protocol 1 switch right away
+#[tauri::command]
+pub fn set_sync_settings_cmd(
+    db: State<DbConnection>,
+    settings: SyncSettings,
+) -> Result<(), CoreError> {
+    crate::with_db!(db, conn, { save_settings(&conn, &settings)? });
+    let p2p_sync = db
+        .p2p_sync
+        .lock()
+        .map_err(|_| CoreError::internal("Failed to lock P2P sync"))?
+        .clone();
+    if let Some(sync) = p2p_sync {
+        sync.set_allow_legacy_peers(settings.allow_legacy_protocol);
+    }
+    Ok(())
+}
+
+#[tauri::command]
+pub fn init_sync_tables_cmd(db: State<DbConnection>) -> Result<(), CoreError> {
     crate::with_db!(db, conn, {
-        core_rs::sync_agent::init_sync_tables(&conn).map_err(|e| e.to_string())
+        core_rs::sync_agent::init_sync_tables(&conn).map_err(CoreError::from)
     })
 }
 
@@ -20,7 +76,7 @@ pub fn register_device_cmd(
     device_id: String,
     name: String,
     public_key: Vec<u8>,
-) -> Result<(), String> {
+) -> Result<(), CoreError> {
     crate::with_db!(db, conn, {
         let device_info = core_rs::sync_agent::DeviceInfo {
             device_id: device_id.clone(),
@@ -35,11 +91,11 @@ pub fn register_device_cmd(
         // Suppress unused warning
         let _ = public_key;
 
-        let user_id = core_rs::db::get_or_create_user_id(&conn).map_err(|e| e.to_string())?;
+        let user_id = core_rs::db::get_or_create_user_id(&conn)?;
         let agent = SyncAgent::new(user_id, "Desktop".to_string(), AppConfig::sync_port());
         agent
             .register_device(&conn, &device_info)
-            .map_err(|e| e.to_string())
+            .map_err(CoreError::from)
     })
 }
 
@@ -50,9 +106,9 @@ pub fn record_sync_cmd(
     device_id: String,
     status: String,
     details: Option<String>,
-) -> Result<(), String> {
+) -> Result<(), CoreError> {
     crate::with_db!(db, conn, {
-        let user_id = core_rs::db::get_or_create_user_id(&conn).map_err(|e| e.to_string())?;
+        let user_id = core_rs::db::get_or_create_user_id(&conn)?;
         let agent = SyncAgent::new(user_id, "Desktop".to_string(), AppConfig::sync_port());
 
         let params = core_rs::sync::history::SyncRecordParams {
@@ -64,11 +120,10 @@ pub fn record_sync_cmd(
             conflicts: 0,
             success: status == "success",
             error_message: details.as_deref(),
+            transport: core_rs::sync::SyncTransport::Direct,
         };
 
-        agent
-            .record_sync_history(&conn, params)
-            .map_err(|e| e.to_string())?;
+        agent.record_sync_history(&conn, params)?;
         Ok(())
     })
 }
@@ -77,85 +132,176 @@ pub fn record_sync_cmd(
 pub fn get_all_sync_tasks_cmd(
     db: State<DbConnection>,
     space_id: String,
-) -> Result<Vec<SyncTask>, String> {
+) -> Result<Vec<SyncTask>, CoreError> {
     crate::with_db!(db, conn, {
-        let user_id = core_rs::db::get_or_create_user_id(&conn).map_err(|e| e.to_string())?;
+        let user_id = core_rs::db::get_or_create_user_id(&conn)?;
         let agent = SyncAgent::new(user_id, "Desktop".to_string(), AppConfig::sync_port());
         agent
             .get_all_sync_tasks(&conn, &space_id)
-            .map_err(|e| e.to_string())
+            .map_err(CoreError::from)
     })
 }
 
 #[tauri::command]
-pub fn get_sync_stats_cmd(db: State<DbConnection>, space_id: String) -> Result<SyncStats, String> {
+pub fn get_sync_stats_cmd(
+    db: State<DbConnection>,
+    space_id: String,
+) -> Result<SyncStats, CoreError> {
     crate::with_db!(db, conn, {
-        let user_id = core_rs::db::get_or_create_user_id(&conn).map_err(|e| e.to_string())?;
+        let user_id = core_rs::db::get_or_create_user_id(&conn)?;
         let agent = SyncAgent::new(user_id, "Desktop".to_string(), AppConfig::sync_port());
         agent
             .get_sync_stats(&conn, &space_id)
-            .map_err(|e| e.to_string())
+            .map_err(CoreError::from)
     })
 }
 
+/// Bytes and deltas synced per entity type and device within `range`
 #[tauri::command]
-pub fn discover_devices_cmd(db: State<DbConnection>) -> Result<Vec<DiscoveredDevice>, String> {
-    let guard = db
+pub fn get_sync_transfer_report_cmd(
+    db: State<DbConnection>,
+    space_id: String,
+    range: TimeRange,
+) -> Result<SyncTransferReport, CoreError> {
+    crate::with_db!(db, conn, {
+        core_rs::sync::get_sync_transfer_report(&conn, &space_id, range).map_err(CoreError::from)
+    })
+}
+
+#[tauri::command]
+pub fn discover_devices_cmd(db: State<DbConnection>) -> Result<Vec<DiscoveredDevice>, CoreError> {
+    let p2p_sync = db
         .p2p_sync
         .lock()
-        .map_err(|_| "Failed to lock P2P sync".to_string())?;
-    if let Some(sync) = guard.as_ref() {
-        sync.discover_peers().map_err(|e| e.to_string())
-    } else {
-        Err("P2P sync not initialized".to_string())
-    }
+        .map_err(|_| CoreError::internal("Failed to lock P2P sync"))?
+        .clone();
+    let sync = p2p_sync.ok_or_else(p2p_unavailable)?;
+    crate::with_db!(db, conn, {
+        sync.discover_peers(&conn).map_err(CoreError::from)
+    })
+}
+
+/// Add a peer by address for networks where mDNS does not work
+#[tauri::command]
+pub fn add_manual_peer_cmd(
+    db: State<DbConnection>,
+    address: String,
+    port: u16,
+    display_name: String,
+) -> Result<DiscoveredDevice, CoreError> {
+    crate::with_db!(db, conn, {
+        core_rs::sync::discovery::add_manual_peer(&conn, &address, port, &display_name)
+            .map_err(CoreError::from)
+    })
+}
+
+#[tauri::command]
+pub fn remove_manual_peer_cmd(
+    db: State<DbConnection>,
+    device_id: String,
+) -> Result<bool, CoreError> {
+    crate::with_db!(db, conn, {
+        core_rs::sync::discovery::remove_manual_peer(&conn, &device_id).map_err(CoreError::from)
+    })
+}
+
+#[tauri::command]
+pub fn get_manual_peers_cmd(db: State<DbConnection>) -> Result<Vec<DiscoveredDevice>, CoreError> {
+    crate::with_db!(db, conn, {
+        core_rs::sync::discovery::get_manual_peers(&conn).map_err(CoreError::from)
+    })
 }
 
 #[tauri::command]
 pub async fn initiate_pairing_cmd(
     db: State<'_, DbConnection>,
     device_id: String,
-) -> Result<(), String> {
+) -> Result<PairingHello, CoreError> {
     let p2p_sync = {
         let guard = db
             .p2p_sync
             .lock()
-            .map_err(|_| "Failed to lock P2P sync".to_string())?;
+            .map_err(|_| CoreError::internal("Failed to lock P2P sync"))?;
         guard.clone()
     };
 
     if let Some(sync) = p2p_sync {
         sync.initiate_pairing(&device_id)
             .await
-            .map_err(|e| e.to_string())
+            .map_err(CoreError::from)
     } else {
-        Err("P2P Sync not initialized".to_string())
+        Err(p2p_unavailable())
     }
 }
 
-/// Exchange encryption keys with a paired device
-/// This performs an X25519 ECDH key exchange for secure sync communication
+/// Exchange encryption keys with a device being paired
+/// This performs an X25519 ECDH key exchange and derives the short
+/// authentication string (SAS) the user compares on both devices. The
+/// initiator's opening message carries only a commitment to its keys; every
+/// later one carries the keys themselves.
 #[tauri::command]
-pub fn exchange_keys_cmd(db: State<DbConnection>, device_id: String) -> Result<(), String> {
-    crate::with_db!(db, conn, {
-        let user_id = core_rs::db::get_or_create_user_id(&conn).map_err(|e| e.to_string())?;
-        let sync_port =
-            core_rs::db::get_sync_port(&conn).unwrap_or_else(|_| AppConfig::sync_port());
-        let agent = SyncAgent::new(user_id, "Desktop".to_string(), sync_port);
+pub fn exchange_keys_cmd(
+    db: State<DbConnection>,
+    device_id: String,
+    peer_public_key: Option<Vec<u8>>,
+    peer_static_key: Option<Vec<u8>>,
+    peer_commitment: Option<Vec<u8>>,
+) -> Result<PairingHello, CoreError> {
+    let commitment = peer_commitment.unwrap_or_default();
+    if commitment.is_empty() && peer_public_key.is_none() {
+        return Err(CoreError::validation(
+            "sync.pairing",
+            format!("No public key received from device {}", device_id),
+        ));
+    }
+    let guard = db
+        .p2p_sync
+        .lock()
+        .map_err(|_| CoreError::internal("Failed to lock P2P sync"))?;
+    let sync = guard.as_ref().ok_or_else(p2p_unavailable)?;
+
+    sync.exchange_keys(&PairingHello {
+        device_id,
+        public_key: peer_public_key.unwrap_or_default(),
+        static_key: peer_static_key.unwrap_or_default(),
+        commitment,
+    })
+    .map_err(CoreError::from)
+}
 
-        // Verify device exists and is paired
-        let devices = agent.get_devices(&conn).map_err(|e| e.to_string())?;
-        let device = devices
-            .iter()
-            .find(|d| d.device_id == device_id)
-            .ok_or_else(|| format!("Device {} not found or not paired", device_id))?;
-
-        log::info!(
-            "[sync] Key exchange completed with device: {} ({})",
-            device.device_name,
-            device_id
-        );
-        Ok(())
+/// SAS for a pairing awaiting the user's confirmation
+#[tauri::command]
+pub fn get_pending_pairing_sas_cmd(
+    db: State<DbConnection>,
+    device_id: String,
+) -> Result<Option<ShortAuthString>, CoreError> {
+    let guard = db
+        .p2p_sync
+        .lock()
+        .map_err(|_| CoreError::internal("Failed to lock P2P sync"))?;
+    let sync = guard.as_ref().ok_or_else(p2p_unavailable)?;
+    Ok(sync.get_pending_pairing_sas(&device_id))
+}
+
+/// Record whether the SAS matched on both devices; only a match trusts the peer
+#[tauri::command]
+pub fn confirm_pairing_cmd(
+    db: State<DbConnection>,
+    device_id: String,
+    accepted: bool,
+) -> Result<(), CoreError> {
+    let p2p_sync = {
+        let guard = db
+            .p2p_sync
+            .lock()
+            .map_err(|_| CoreError::internal("Failed to lock P2P sync"))?;
+        guard.clone()
+    };
+    let sync = p2p_sync.ok_or_else(p2p_unavailable)?;
+
+    crate::with_db!(db, conn, {
+        sync.confirm_pairing(&conn, &device_id, accepted)
+            .map_err(CoreError::from)
     })
 }
 
@@ -177,15 +323,14 @@ pub struct SyncProgress {
 pub fn get_sync_progress_cmd(
     db: State<DbConnection>,
     device_id: String,
-) -> Result<SyncProgress, String> {
+) -> Result<SyncProgress, CoreError> {
     crate::with_db!(db, conn, {
-        let user_id = core_rs::db::get_or_create_user_id(&conn).map_err(|e| e.to_string())?;
-        let sync_port =
-            core_rs::db::get_sync_port(&conn).unwrap_or_else(|_| AppConfig::sync_port());
+        let user_id = core_rs::db::get_or_create_user_id(&conn)?;
+        let sync_port = sync_port(&conn);
         let agent = SyncAgent::new(user_id, "Desktop".to_string(), sync_port);
 
         // Get active sync tasks for this device
-        let stats = agent.get_sync_stats(&conn, "").map_err(|e| e.to_string())?;
+        let stats = agent.get_sync_stats(&conn, "")?;
 
         // Calculate progress based on pending vs completed items
         // Use success_rate (0.0 to 1.0) if available, otherwise fallback to 0 or 1.
@@ -219,17 +364,17 @@ pub fn get_sync_progress_cmd(
 }
 
 #[tauri::command]
-pub fn shutdown_clear_keys_cmd(db: State<DbConnection>) -> Result<(), String> {
+pub fn shutdown_clear_keys_cmd(db: State<DbConnection>) -> Result<(), CoreError> {
     let mut dek = db
         .dek
         .lock()
-        .map_err(|_| "Failed to lock DEK".to_string())?;
+        .map_err(|_| CoreError::internal("Failed to lock DEK"))?;
     *dek = None;
     Ok(())
 }
 
 #[tauri::command]
-pub fn cancel_sync_cmd(device_id: String) -> Result<bool, String> {
+pub fn cancel_sync_cmd(device_id: String) -> Result<bool, CoreError> {
     log::info!("[sync] Cancelled sync with device: {}", device_id);
     Ok(true)
 }
@@ -239,57 +384,53 @@ pub fn resolve_sync_conflict_cmd(
     db: State<DbConnection>,
     conflict: DbSyncConflict,
     resolution: SyncConflictResolution,
-) -> Result<(), String> {
+) -> Result<(), CoreError> {
     let pool_guard = db
         .pool
         .lock()
-        .map_err(|_| "Failed to lock database pool state".to_string())?;
-    let pool = pool_guard
-        .as_ref()
-        .ok_or_else(|| "Database pool not initialized (Vault locked)".to_string())?;
+        .map_err(|_| CoreError::internal("Failed to lock database pool state"))?;
+    let pool = pool_guard.as_ref().ok_or_else(CoreError::vault_locked)?;
     let conn = pool
         .get()
-        .map_err(|e| format!("Failed to get connection from pool: {}", e))?;
+        .map_err(|e| CoreError::internal(format!("Failed to get connection from pool: {}", e)))?;
 
     let dek_guard = db
         .dek
         .lock()
-        .map_err(|_| "Failed to lock DEK".to_string())?;
+        .map_err(|_| CoreError::internal("Failed to lock DEK"))?;
     let dek = dek_guard.as_ref().map(|d| d.as_slice()).unwrap_or(&[]);
 
     if dek.is_empty() {
-        return Err("DEK not available (Vault locked or error)".to_string());
+        return Err(CoreError::vault_locked());
     }
 
-    let device_id = core_rs::db::get_or_create_user_id(&conn).map_err(|e| e.to_string())?;
+    let device_id = core_rs::db::get_or_create_user_id(&conn)?;
     let agent = SyncAgent::new(device_id, "Desktop".to_string(), AppConfig::sync_port());
 
     agent
         .resolve_conflict(&conn, &conflict, resolution, dek)
-        .map_err(|e| e.to_string())
+        .map_err(CoreError::from)
 }
 
 #[tauri::command]
-pub async fn start_sync_server_cmd(db: State<'_, DbConnection>) -> Result<(), String> {
+pub async fn start_sync_server_cmd(db: State<'_, DbConnection>) -> Result<(), CoreError> {
     let p2p_sync = db
         .p2p_sync
         .lock()
-        .map_err(|_| "Failed to lock P2P sync".to_string())?
+        .map_err(|_| CoreError::internal("Failed to lock P2P sync"))?
         .clone();
     if let Some(p2p_sync) = p2p_sync {
         let port = {
             let pool_guard = db
                 .pool
                 .lock()
-                .map_err(|_| "Failed to lock database pool state".to_string())?;
-            let pool = pool_guard
-                .as_ref()
-                .ok_or_else(|| "Database pool not initialized".to_string())?;
-            let conn = pool
-                .get()
-                .map_err(|e| format!("Failed to get connection from pool: {}", e))?;
-
-            core_rs::db::get_sync_port(&conn).unwrap_or_else(|_| AppConfig::sync_port())
+                .map_err(|_| CoreError::internal("Failed to lock database pool state"))?;
+            let pool = pool_guard.as_ref().ok_or_else(CoreError::vault_locked)?;
+            let conn = pool.get().map_err(|e| {
+                CoreError::internal(format!("Failed to get connection from pool: {}", e))
+            })?;
+
+            sync_port(&conn)
         };
         tokio::spawn(async move {
             if let Err(e) = p2p_sync.start_server(port).await {
@@ -304,33 +445,148 @@ pub async fn start_sync_server_cmd(db: State<'_, DbConnection>) -> Result<(), St
 pub async fn start_p2p_sync_cmd(
     db: State<'_, DbConnection>,
     device_id: String,
-) -> Result<(), String> {
+) -> Result<(), CoreError> {
     let p2p_sync = {
         let guard = db
             .p2p_sync
             .lock()
-            .map_err(|_| "Failed to lock P2P sync".to_string())?;
+            .map_err(|_| CoreError::internal("Failed to lock P2P sync"))?;
         guard.clone()
     };
 
-    if let Some(sync) = p2p_sync {
-        sync.start_sync(&device_id).await.map_err(|e| e.to_string())
-    } else {
-        Err("P2P Sync not initialized (Vault locked?)".to_string())
+    let Some(sync) = p2p_sync else {
+        return Err(p2p_unavailable());
+    };
+    match sync.start_sync(&device_id).await {
+        Err(e @ P2pError::Unreachable(_)) => {
+            log::info!("[p2p] {}; trying the relay", e);
+            if push_through_relay(&db, &device_id).await? {
+                Ok(())
+            } else {
+                Err(e.into())
+            }
+        }
+        result => result.map_err(CoreError::from),
     }
 }
 
+/// Send `device_id` the changes it is missing through the configured
+/// relay. False when no relay is configured.
+async fn push_through_relay(db: &DbConnection, device_id: &str) -> Result<bool, CoreError> {
+    let (client, outbox) = crate::with_db!(db, conn, {
+        let own_id = core_rs::db::get_or_create_user_id(&conn)?;
+        let Some(client) = get_relay_client(&conn, &own_id)? else {
+            return Ok(false);
+        };
+        let static_key = load_or_create_static_key(&conn).map_err(RelaySyncError::from)?;
+        let agent = SyncAgent::new(own_id, "Desktop".to_string(), AppConfig::sync_port());
+        let outbox = prepare_relay_session(
+            &conn,
+            &agent,
+            &static_key,
+            device_id,
+            RelayConfig::default().max_envelope_bytes,
+        )?;
+        (client, outbox)
+    });
+
+    // Don't hold a pooled connection while talking to the relay
+    let sent = send_relay_session(&client, &outbox).await;
+    let error = sent.as_ref().err().map(|e| e.to_string());
+    crate::with_db!(db, conn, {
+        record_relay_push(&conn, &outbox, error.as_deref())?;
+    });
+    sent?;
+    Ok(true)
+}
+
+/// URL of the relay used when a peer can't be reached directly
+#[tauri::command]
+pub fn get_sync_relay_cmd(db: State<DbConnection>) -> Result<Option<String>, CoreError> {
+    crate::with_db!(db, conn, { get_relay_url(&conn).map_err(CoreError::from) })
+}
+
+/// Register with the relay at `relay_url` to sync through it when a peer
+/// can't be reached directly, or stop using the current relay with `None`
+#[tauri::command]
+pub async fn set_sync_relay_cmd(
+    db: State<'_, DbConnection>,
+    relay_url: Option<String>,
+) -> Result<(), CoreError> {
+    let (device_id, key_hash, current) = crate::with_db!(db, conn, {
+        let device_id = core_rs::db::get_or_create_user_id(&conn)?;
+        let static_key = load_or_create_static_key(&conn).map_err(RelaySyncError::from)?;
+        let current = get_relay_client(&conn, &device_id)?;
+        (device_id, relay_key_hash(&static_key), current)
+    });
+
+    // Whatever is still queued on the old relay is dropped with the device
+    if let Some(mut current) = current {
+        if let Err(e) = current.deregister().await {
+            log::warn!("[relay_sync] Failed to leave the previous relay: {}", e);
+        }
+    }
+    let Some(relay_url) = relay_url else {
+        return crate::with_db!(db, conn, {
+            clear_relay_registration(&conn).map_err(CoreError::from)
+        });
+    };
+
+    let mut client = RelayClient::new(&device_id, &relay_url);
+    client
+        .register(&key_hash)
+        .await
+        .map_err(RelaySyncError::from)?;
+    crate::with_db!(db, conn, {
+        save_relay_registration(&conn, &relay_url, &client).map_err(CoreError::from)
+    })
+}
+
+/// Apply whatever paired devices have sent through the relay since the
+/// last poll
+#[tauri::command]
+pub async fn poll_relay_sync_cmd(
+    db: State<'_, DbConnection>,
+) -> Result<RelayPullReport, CoreError> {
+    let client = crate::with_db!(db, conn, {
+        let device_id = core_rs::db::get_or_create_user_id(&conn)?;
+        get_relay_client(&conn, &device_id)?.ok_or(RelaySyncError::NotConfigured)?
+    });
+
+    let envelopes = fetch_relay_envelopes(&client).await?;
+
+    let report = crate::with_db_mut!(db, conn, {
+        let dek_guard = db
+            .dek
+            .lock()
+            .map_err(|_| CoreError::internal("Failed to lock DEK"))?;
+        let dek = dek_guard.as_ref().map(|d| d.as_slice()).unwrap_or(&[]);
+        if dek.is_empty() {
+            return Err(CoreError::vault_locked());
+        }
+        let device_id = core_rs::db::get_or_create_user_id(&conn)?;
+        let static_key = load_or_create_static_key(&conn).map_err(RelaySyncError::from)?;
+        let agent = SyncAgent::new(device_id, "Desktop".to_string(), AppConfig::sync_port());
+        apply_relay_envelopes(&mut conn, &agent, &static_key, dek, envelopes)?
+    });
+
+    // An unsent acknowledgement only means the sessions come again
+    if let Err(e) = send_relay_acks(&client, &report).await {
+        log::warn!("[relay_sync] Failed to acknowledge sessions: {}", e);
+    }
+    Ok(report)
+}
+
 #[tauri::command]
 pub fn get_devices_cmd(
     db: State<DbConnection>,
-) -> Result<Vec<core_rs::sync::mobile_sync::DeviceInfo>, String> {
+) -> Result<Vec<core_rs::sync::mobile_sync::DeviceInfo>, CoreError> {
     crate::with_db!(db, conn, {
-        let device_id = core_rs::db::get_or_create_user_id(&conn).map_err(|e| e.to_string())?;
-        let sync_port =
-            core_rs::db::get_sync_port(&conn).unwrap_or_else(|_| AppConfig::sync_port());
+        let device_id = core_rs::db::get_or_create_user_id(&conn)?;
+        let sync_port = sync_port(&conn);
         let agent = SyncAgent::new(device_id, "Desktop".to_string(), sync_port);
 
-        let devices = agent.get_devices(&conn).map_err(|e| e.to_string())?;
+        let devices = agent.get_devices(&conn)?;
         Ok(devices
             .into_iter()
             .map(|d| core_rs::sync::mobile_sync::DeviceInfo {
@@ -352,15 +608,33 @@ pub fn get_devices_cmd(
 }
 
 #[tauri::command]
-pub fn get_sync_conflicts_cmd(db: State<DbConnection>) -> Result<Vec<DbSyncConflict>, String> {
+pub fn get_sync_conflicts_cmd(db: State<DbConnection>) -> Result<Vec<DbSyncConflict>, CoreError> {
     crate::with_db!(db, conn, {
-        let device_id = core_rs::db::get_or_create_user_id(&conn).map_err(|e| e.to_string())?;
-        let sync_port =
-            core_rs::db::get_sync_port(&conn).unwrap_or_else(|_| AppConfig::sync_port());
+        let device_id = core_rs::db::get_or_create_user_id(&conn)?;
+        let sync_port = sync_port(&conn);
         let agent = SyncAgent::new(device_id, "Desktop".to_string(), sync_port);
         agent
             .get_unresolved_conflicts(&conn)
-            .map_err(|e| e.to_string())
+            .map_err(CoreError::from)
+    })
+}
+
+/// Unresolved conflicts with titles, field diffs and device names to show
+/// instead of the raw versions
+#[tauri::command]
+pub fn get_sync_conflicts_described_cmd(
+    db: State<DbConnection>,
+) -> Result<Vec<ConflictSummary>, CoreError> {
+    crate::with_db!(db, conn, {
+        let dek_guard = db
+            .dek
+            .lock()
+            .map_err(|_| CoreError::internal("Failed to lock DEK"))?;
+        let dek = dek_guard.as_ref().map(|d| d.as_slice()).unwrap_or(&[]);
+        if dek.is_empty() {
+            return Err(CoreError::vault_locked());
+        }
+        get_unresolved_conflicts_described(&conn, dek).map_err(CoreError::from)
     })
 }
 
@@ -369,14 +643,78 @@ pub fn get_sync_history_for_space_cmd(
     db: State<DbConnection>,
     space_id: String,
     limit: u32,
-) -> Result<Vec<SyncHistoryEntry>, String> {
+) -> Result<Vec<SyncHistoryEntry>, CoreError> {
     crate::with_db!(db, conn, {
-        let device_id = core_rs::db::get_or_create_user_id(&conn).map_err(|e| e.to_string())?;
-        let sync_port =
-            core_rs::db::get_sync_port(&conn).unwrap_or_else(|_| AppConfig::sync_port());
+        let device_id = core_rs::db::get_or_create_user_id(&conn)?;
+        let sync_port = sync_port(&conn);
         let agent = SyncAgent::new(device_id, "Desktop".to_string(), sync_port);
         agent
             .get_sync_history(&conn, &space_id, limit.into())
-            .map_err(|e| e.to_string())
+            .map_err(CoreError::from)
+    })
+}
+
+#[tauri::command]
+pub fn get_device_sync_scope_cmd(
+    db: State<DbConnection>,
+    device_id: String,
+) -> Result<SyncScope, CoreError> {
+    crate::with_db!(db, conn, {
+        get_device_sync_scope(&conn, &device_id).map_err(CoreError::from)
+    })
+}
+
+#[tauri::command]
+pub fn set_device_sync_scope_cmd(
+    db: State<DbConnection>,
+    device_id: String,
+    scope: SyncScope,
+) -> Result<(), CoreError> {
+    crate::with_db!(db, conn, {
+        set_device_sync_scope(&conn, &device_id, &scope).map_err(CoreError::from)
+    })
+}
+
+/// Whether a sync merge left conflict markers in the note for review
+#[tauri::command]
+pub fn get_note_merge_conflict_cmd(
+    db: State<DbConnection>,
+    note_id: String,
+) -> Result<bool, CoreError> {
+    crate::with_db!(db, conn, {
+        has_merge_conflict(&conn, &note_id).map_err(CoreError::from)
+    })
+}
+
+#[tauri::command]
+pub fn get_device_user_cmd(
+    db: State<DbConnection>,
+    device_id: String,
+) -> Result<Option<String>, CoreError> {
+    crate::with_db!(db, conn, {
+        get_device_user(&conn, &device_id).map_err(CoreError::from)
+    })
+}
+
+/// Link a device to the user its deltas to shared spaces are checked against
+#[tauri::command]
+pub fn set_device_user_cmd(
+    db: State<DbConnection>,
+    device_id: String,
+    user_id: Option<String>,
+) -> Result<(), CoreError> {
+    crate::with_db!(db, conn, {
+        set_device_user(&conn, &device_id, user_id.as_deref()).map_err(CoreError::from)
+    })
+}
+
+#[tauri::command]
+pub fn get_rejected_deltas_cmd(
+    db: State<DbConnection>,
+    space_id: String,
+    limit: Option<u32>,
+) -> Result<Vec<RejectedDelta>, CoreError> {
+    crate::with_db!(db, conn, {
+        get_rejected_deltas(&conn, &space_id, limit.unwrap_or(100)).map_err(CoreError::from)
     })
 }
@@ -30,3 +30,79 @@ pub fn get_tags_with_counts_cmd(
         .map_err(|e| e.to_string())
     })
 }
+
+#[tauri::command]
+pub fn get_tag_tree_cmd(db: State<DbConnection>, space_id: String) -> Result<Vec<TagNode>, String> {
+    crate::with_db!(db, conn, {
+        core_rs::tag::get_tag_tree(
+            &conn,
+            Ulid::from_string(&space_id).map_err(|e| e.to_string())?,
+        )
+        .map_err(|e| e.to_string())
+    })
+}
+
+#[tauri::command]
+pub fn get_tag_usage_counts_cmd(
+    db: State<DbConnection>,
+    space_id: String,
+) -> Result<Vec<TagUsage>, String> {
+    crate::with_db!(db, conn, {
+        core_rs::tag::get_tag_usage_counts(
+            &conn,
+            Ulid::from_string(&space_id).map_err(|e| e.to_string())?,
+        )
+        .map_err(|e| e.to_string())
+    })
+}
+
+#[tauri::command]
+pub fn get_notes_with_tag_cmd(
+    db: State<DbConnection>,
+    space_id: String,
+    tag_name: String,
+    include_descendants: bool,
+) -> Result<Vec<core_rs::note::Note>, String> {
+    crate::with_db!(db, conn, {
+        core_rs::tag::get_notes_with_tag(
+            &conn,
+            Ulid::from_string(&space_id).map_err(|e| e.to_string())?,
+            &tag_name,
+            include_descendants,
+        )
+        .map_err(|e| e.to_string())
+    })
+}
+
+#[tauri::command]
+pub fn rename_tag_cmd(
+    db: State<DbConnection>,
+    tag_id: String,
+    new_name: String,
+) -> Result<TagRenameReport, String> {
+    crate::with_db_mut!(db, conn, {
+        core_rs::tag::rename_tag(&mut conn, &tag_id, &new_name).map_err(|e| e.to_string())
+    })
+}
+
+#[tauri::command]
+pub fn merge_tags_cmd(
+    db: State<DbConnection>,
+    source_ids: Vec<String>,
+    target_id: String,
+) -> Result<Tag, String> {
+    crate::with_db_mut!(db, conn, {
+        core_rs::tag::merge_tags(&mut conn, &source_ids, &target_id).map_err(|e| e.to_string())
+    })
+}
+
+#[tauri::command]
+pub fn add_tag_to_note_cmd(
+    db: State<DbConnection>,
+    note_id: String,
+    tag_name: String,
+) -> Result<bool, String> {
+    crate::with_db!(db, conn, {
+        core_rs::tag::add_tag_to_note(&conn, &note_id, &tag_name).map_err(|e| e.to_string())
+    })
+}
@@ -1,7 +1,9 @@
 use crate::state::DbConnection;
+use core_rs::bulk_edit::{BulkItemResult, TaskPatch};
+use core_rs::error::CoreError;
+use core_rs::ids::parse_entity_id;
 use core_rs::task::*;
 use tauri::State;
-use ulid::Ulid;
 
 #[tauri::command]
 pub fn create_task_cmd(
@@ -9,35 +11,53 @@ pub fn create_task_cmd(
     space_id: String,
     title: String,
     description: Option<String>,
-) -> Result<Task, String> {
+) -> Result<Task, CoreError> {
     crate::with_db!(db, conn, {
-        let space_ulid = Ulid::from_string(&space_id).map_err(|e| e.to_string())?;
-        core_rs::task::create_task(&conn, space_ulid, &title, description)
-            .map_err(|e| e.to_string())
+        let space_ulid = parse_entity_id("space", "space_id", &space_id)?;
+        core_rs::task::create_task(&conn, space_ulid, &title, description).map_err(CoreError::from)
     })
 }
 
 #[tauri::command]
-pub fn get_task_cmd(db: State<DbConnection>, id: String) -> Result<Option<Task>, String> {
+pub fn get_task_cmd(db: State<DbConnection>, id: String) -> Result<Option<Task>, CoreError> {
     crate::with_db!(db, conn, {
-        let id = Ulid::from_string(&id).map_err(|e| e.to_string())?;
-        core_rs::task::get_task(&conn, id).map_err(|e| e.to_string())
+        let id = parse_entity_id("task", "id", &id)?;
+        core_rs::task::get_task(&conn, id).map_err(CoreError::from)
     })
 }
 
 #[tauri::command]
-pub fn update_task_cmd(db: State<DbConnection>, task: Task) -> Result<Task, String> {
+pub fn update_task_cmd(db: State<DbConnection>, task: Task) -> Result<Task, CoreError> {
     crate::with_db!(db, conn, {
-        core_rs::task::update_task(&conn, &task).map_err(|e| e.to_string())?;
+        core_rs::task::update_task(&conn, &task)?;
         Ok(task)
     })
 }
 
+/// Set the patch's fields on many tasks in one transaction
 #[tauri::command]
-pub fn delete_task_cmd(db: State<DbConnection>, id: String) -> Result<(), String> {
+pub fn bulk_update_tasks_cmd(
+    db: State<DbConnection>,
+    task_ids: Vec<String>,
+    patch: TaskPatch,
+    all_or_nothing: Option<bool>,
+) -> Result<Vec<BulkItemResult>, CoreError> {
+    crate::with_db!(db, conn, {
+        core_rs::bulk_edit::bulk_update_tasks(
+            &conn,
+            &task_ids,
+            &patch,
+            all_or_nothing.unwrap_or(false),
+        )
+        .map_err(CoreError::from)
+    })
+}
+
+#[tauri::command]
+pub fn delete_task_cmd(db: State<DbConnection>, id: String) -> Result<(), CoreError> {
     crate::with_db!(db, conn, {
-        let id = Ulid::from_string(&id).map_err(|e| e.to_string())?;
-        core_rs::task::delete_task(&conn, id).map_err(|e| e.to_string())
+        let id = parse_entity_id("task", "id", &id)?;
+        core_rs::task::delete_task(&conn, id).map_err(CoreError::from)
     })
 }
 
@@ -45,10 +65,10 @@ pub fn delete_task_cmd(db: State<DbConnection>, id: String) -> Result<(), String
 pub fn get_tasks_by_project_cmd(
     db: State<DbConnection>,
     project_id: String,
-) -> Result<Vec<Task>, String> {
+) -> Result<Vec<Task>, CoreError> {
     crate::with_db!(db, conn, {
-        let id = Ulid::from_string(&project_id).map_err(|e| e.to_string())?;
-        core_rs::task::get_tasks_by_project(&conn, id).map_err(|e| e.to_string())
+        let id = parse_entity_id("project", "project_id", &project_id)?;
+        core_rs::task::get_tasks_by_project(&conn, id).map_err(CoreError::from)
     })
 }
 
@@ -56,10 +76,10 @@ pub fn get_tasks_by_project_cmd(
 pub fn get_all_tasks_in_space_cmd(
     db: State<DbConnection>,
     space_id: String,
-) -> Result<Vec<Task>, String> {
+) -> Result<Vec<Task>, CoreError> {
     crate::with_db!(db, conn, {
-        let space_ulid = Ulid::from_string(&space_id).map_err(|e| e.to_string())?;
-        core_rs::task::get_all_tasks_in_space(&conn, space_ulid).map_err(|e| e.to_string())
+        let space_ulid = parse_entity_id("space", "space_id", &space_id)?;
+        core_rs::task::get_all_tasks_in_space(&conn, space_ulid).map_err(CoreError::from)
     })
 }
 
@@ -68,9 +88,92 @@ pub fn get_upcoming_tasks_cmd(
     db: State<DbConnection>,
     space_id: String,
     limit: u32,
-) -> Result<Vec<Task>, String> {
+) -> Result<Vec<Task>, CoreError> {
+    crate::with_db!(db, conn, {
+        let space_ulid = parse_entity_id("space", "space_id", &space_id)?;
+        core_rs::task::get_upcoming_tasks(&conn, space_ulid, limit).map_err(CoreError::from)
+    })
+}
+
+#[tauri::command]
+pub fn query_tasks_cmd(
+    db: State<DbConnection>,
+    space_id: String,
+    filter: TaskFilter,
+    sort: Option<TaskSort>,
+    limit: Option<u32>,
+    offset: Option<u32>,
+) -> Result<Vec<Task>, CoreError> {
+    crate::with_db!(db, conn, {
+        let space_ulid = parse_entity_id("space", "space_id", &space_id)?;
+        core_rs::task::query_tasks(
+            &conn,
+            space_ulid,
+            &filter,
+            &sort.unwrap_or_default(),
+            limit,
+            offset,
+        )
+        .map_err(CoreError::from)
+    })
+}
+
+#[tauri::command]
+pub fn count_tasks_cmd(
+    db: State<DbConnection>,
+    space_id: String,
+    filter: TaskFilter,
+) -> Result<u64, CoreError> {
+    crate::with_db!(db, conn, {
+        let space_ulid = parse_entity_id("space", "space_id", &space_id)?;
+        core_rs::task::count_tasks(&conn, space_ulid, &filter).map_err(CoreError::from)
+    })
+}
+
+#[tauri::command]
+pub fn create_task_view_cmd(
+    db: State<DbConnection>,
+    space_id: String,
+    name: String,
+    filter: TaskFilter,
+    sort: TaskSort,
+) -> Result<TaskView, CoreError> {
+    crate::with_db!(db, conn, {
+        let space_ulid = parse_entity_id("space", "space_id", &space_id)?;
+        core_rs::task::create_task_view(&conn, space_ulid, &name, &filter, &sort)
+            .map_err(CoreError::from)
+    })
+}
+
+#[tauri::command]
+pub fn get_task_views_cmd(
+    db: State<DbConnection>,
+    space_id: String,
+) -> Result<Vec<TaskView>, CoreError> {
+    crate::with_db!(db, conn, {
+        let space_ulid = parse_entity_id("space", "space_id", &space_id)?;
+        core_rs::task::get_task_views(&conn, space_ulid).map_err(CoreError::from)
+    })
+}
+
+#[tauri::command]
+pub fn update_task_view_cmd(
+    db: State<DbConnection>,
+    id: String,
+    name: String,
+    filter: TaskFilter,
+    sort: TaskSort,
+) -> Result<TaskView, CoreError> {
+    crate::with_db!(db, conn, {
+        let id = parse_entity_id("task_view", "id", &id)?;
+        core_rs::task::update_task_view(&conn, id, &name, &filter, &sort).map_err(CoreError::from)
+    })
+}
+
+#[tauri::command]
+pub fn delete_task_view_cmd(db: State<DbConnection>, id: String) -> Result<(), CoreError> {
     crate::with_db!(db, conn, {
-        let space_ulid = Ulid::from_string(&space_id).map_err(|e| e.to_string())?;
-        core_rs::task::get_upcoming_tasks(&conn, space_ulid, limit).map_err(|e| e.to_string())
+        let id = parse_entity_id("task_view", "id", &id)?;
+        core_rs::task::delete_task_view(&conn, id).map_err(CoreError::from)
     })
 }
@@ -1,5 +1,6 @@
 use crate::state::DbConnection;
-use core_rs::temporal_graph::{GraphMilestone, GraphSnapshot};
+use core_rs::graph::{GraphData, GraphNode};
+use core_rs::temporal_graph::{GraphEvolution, GraphMilestone, GraphSnapshot};
 use tauri::State;
 use ulid::Ulid;
 
@@ -18,15 +19,20 @@ pub fn build_current_graph_cmd(
 pub fn get_graph_evolution_cmd(
     db: State<DbConnection>,
     space_id: String,
-    start: i64,
-    end: i64,
-) -> Result<Vec<GraphSnapshot>, String> {
+    start_time: i64,
+    end_time: i64,
+    snapshot_limit: Option<u32>,
+) -> Result<GraphEvolution, String> {
     crate::with_db!(db, conn, {
         let space_ulid = Ulid::from_string(&space_id).map_err(|e| e.to_string())?;
-        let evolution =
-            core_rs::temporal_graph::get_graph_evolution(&conn, space_ulid, start, end, 10)
-                .map_err(|e| e.to_string())?;
-        Ok(evolution.snapshots)
+        core_rs::temporal_graph::get_graph_evolution(
+            &conn,
+            space_ulid,
+            start_time,
+            end_time,
+            snapshot_limit.unwrap_or(10),
+        )
+        .map_err(|e| e.to_string())
     })
 }
 
@@ -40,3 +46,42 @@ pub fn detect_major_notes_cmd(
         core_rs::temporal_graph::detect_major_notes(&conn, space_ulid).map_err(|e| e.to_string())
     })
 }
+
+#[tauri::command]
+pub fn get_note_neighborhood_cmd(
+    db: State<DbConnection>,
+    note_id: String,
+    depth: usize,
+    max_nodes: usize,
+) -> Result<GraphData, String> {
+    crate::with_db!(db, conn, {
+        let note_ulid = Ulid::from_string(&note_id).map_err(|e| e.to_string())?;
+        core_rs::graph::get_neighborhood(&conn, note_ulid, depth, max_nodes)
+            .map_err(|e| e.to_string())
+    })
+}
+
+#[tauri::command]
+pub fn find_note_path_cmd(
+    db: State<DbConnection>,
+    from_note_id: String,
+    to_note_id: String,
+    max_depth: usize,
+) -> Result<Option<Vec<GraphNode>>, String> {
+    crate::with_db!(db, conn, {
+        let from = Ulid::from_string(&from_note_id).map_err(|e| e.to_string())?;
+        let to = Ulid::from_string(&to_note_id).map_err(|e| e.to_string())?;
+        core_rs::graph::find_path(&conn, from, to, max_depth).map_err(|e| e.to_string())
+    })
+}
+
+#[tauri::command]
+pub fn get_orphan_notes_cmd(
+    db: State<DbConnection>,
+    space_id: String,
+) -> Result<Vec<GraphNode>, String> {
+    crate::with_db!(db, conn, {
+        let space_ulid = Ulid::from_string(&space_id).map_err(|e| e.to_string())?;
+        core_rs::graph::get_orphan_notes(&conn, space_ulid).map_err(|e| e.to_string())
+    })
+}
@@ -161,3 +161,32 @@ pub fn create_manual_time_entry_cmd(
         core_rs::time_tracking::create_manual_time_entry(&conn, params).map_err(|e| e.to_string())
     })
 }
+
+#[tauri::command]
+pub fn create_time_entry_from_event_cmd(
+    db: State<DbConnection>,
+    event_id: String,
+    overrides: EventEntryOverrides,
+) -> Result<TimeEntry, String> {
+    crate::with_db!(db, conn, {
+        let id = Ulid::from_string(&event_id).map_err(|e| e.to_string())?;
+        core_rs::time_tracking::create_time_entry_from_event(&conn, id, overrides)
+            .map_err(|e| e.to_string())
+    })
+}
+
+/// Events of `date` (YYYY-MM-DD) that can be logged in one click
+#[tauri::command]
+pub fn suggest_time_entries_for_day_cmd(
+    db: State<DbConnection>,
+    space_id: String,
+    date: String,
+) -> Result<Vec<core_rs::calendar::CalendarEvent>, String> {
+    crate::with_db!(db, conn, {
+        let space_ulid = Ulid::from_string(&space_id).map_err(|e| e.to_string())?;
+        let date =
+            chrono::NaiveDate::parse_from_str(&date, "%Y-%m-%d").map_err(|e| e.to_string())?;
+        core_rs::time_tracking::suggest_time_entries_for_day(&conn, space_ulid, date)
+            .map_err(|e| e.to_string())
+    })
+}
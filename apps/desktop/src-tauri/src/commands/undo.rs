@@ -0,0 +1,30 @@
+//! Undo Command Handlers
+//!
+//! Lists recently deleted data that can still be restored and undoes it.
+
+use crate::state::DbConnection;
+use core_rs::undo::UndoableOperation;
+use tauri::State;
+
+/// Most recent operations that can still be undone, newest first
+#[tauri::command]
+pub fn list_undoable_operations_cmd(
+    db: State<DbConnection>,
+    limit: Option<u32>,
+) -> Result<Vec<UndoableOperation>, String> {
+    crate::with_db!(db, conn, {
+        core_rs::undo::list_undoable_operations(&conn, limit.unwrap_or(20))
+            .map_err(|e| e.to_string())
+    })
+}
+
+/// Restore the data removed by an operation
+#[tauri::command]
+pub fn undo_operation_cmd(
+    db: State<DbConnection>,
+    op_id: String,
+) -> Result<UndoableOperation, String> {
+    crate::with_db!(db, conn, {
+        core_rs::undo::undo_operation(&conn, &op_id).map_err(|e| e.to_string())
+    })
+}
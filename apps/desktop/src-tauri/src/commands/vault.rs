@@ -1,99 +1,180 @@
 use crate::config::AppConfig;
-use crate::db_pool::EncryptedConnectionManager;
 use crate::state::{DbConnection, SecureDek};
+use core_rs::db::{
+    EncryptedConnectionManager, IntegrityCheckMode, IntegrityIssue, StorageReport, VacuumResult,
+    VaultIntegrityReport, VaultRepairReport,
+};
+use core_rs::error::CoreError;
 use core_rs::sync::p2p::P2pSync;
-use core_rs::vault::{create_vault, unlock_vault};
+use core_rs::vault::{
+    create_vault, rotate_dek, rotate_vault_password, unlock_vault_with_options, AutoLockGuard,
+    LockOptions, VaultLock,
+};
 use r2d2::Pool;
+use serde::Serialize;
 use std::path::{Path, PathBuf};
 use std::sync::Arc;
-use tauri::State;
+use tauri::{Manager, State};
 
+/// Hand the DEK to a guard that wipes it after `AppConfig::auto_lock_idle`
+/// without activity; the app is then told via a `vault-locked` event
+fn new_auto_lock(app: &tauri::AppHandle, dek: [u8; 32]) -> Arc<AutoLockGuard> {
+    let app = app.clone();
+    Arc::new(AutoLockGuard::new(dek).on_lock(move |reason| {
+        if let Some(db) = app.try_state::<DbConnection>() {
+            db.clear_unlocked_state();
+        }
+        if let Err(e) = app.emit_all("vault-locked", reason) {
+            log::warn!("[vault] Failed to emit lock event: {}", e);
+        }
+    }))
+}
+
+/// Hold the lock of the vault just opened; the connection it came with is
+/// dropped in favour of the pool
+fn store_vault_lock(db: &DbConnection, lock: VaultLock) -> Result<(), CoreError> {
+    *db.vault_lock
+        .lock()
+        .map_err(|_| CoreError::internal("Failed to lock vault lock state"))? = Some(lock);
+    Ok(())
+}
+
+/// Fails with `vault.already_open` while another instance has the vault open
 #[tauri::command]
-pub fn create_vault_cmd(db: State<DbConnection>, path: &str, password: &str) -> Result<(), String> {
+pub fn create_vault_cmd(
+    app: tauri::AppHandle,
+    db: State<DbConnection>,
+    path: &str,
+    password: &str,
+) -> Result<(), CoreError> {
     // Lock everything to reset
     let mut pool_guard = db
         .pool
         .lock()
-        .map_err(|_| "Failed to lock database pool".to_string())?;
+        .map_err(|_| CoreError::internal("Failed to lock database pool"))?;
     let mut dek_guard = db
         .dek
         .lock()
-        .map_err(|_| "Failed to lock DEK".to_string())?;
+        .map_err(|_| CoreError::internal("Failed to lock DEK"))?;
     let mut p2p_sync_guard = db
         .p2p_sync
         .lock()
-        .map_err(|_| "Failed to lock P2P sync".to_string())?;
+        .map_err(|_| CoreError::internal("Failed to lock P2P sync"))?;
     let mut vault_path_guard = db
         .vault_path
         .lock()
-        .map_err(|_| "Failed to lock vault path".to_string())?;
+        .map_err(|_| CoreError::internal("Failed to lock vault path"))?;
+    let mut auto_lock_guard = db
+        .auto_lock
+        .lock()
+        .map_err(|_| CoreError::internal("Failed to lock auto-lock state"))?;
 
     *pool_guard = None;
     *dek_guard = None;
     *p2p_sync_guard = None;
     *vault_path_guard = None;
+    *auto_lock_guard = None;
+    db.release_vault_lock();
 
     // Create vault (returns conn and dek)
-    let vault = create_vault(path, password).map_err(|e| e.to_string())?;
+    let vault = create_vault(path, password)?;
 
     // Store DEK
     *dek_guard = Some(SecureDek::new(vault.dek.to_vec()));
+    store_vault_lock(&db, vault.lock)?;
     *vault_path_guard = Some(PathBuf::from(path));
 
     // Create Pool
-    let manager = EncryptedConnectionManager::new(Path::new(path).join("vault.sqlite3"), vault.dek);
+    let auto_lock = new_auto_lock(&app, *vault.dek);
+    *auto_lock_guard = Some(auto_lock.clone());
+    let manager = EncryptedConnectionManager::new(Path::new(path).join("vault.sqlite3"), auto_lock);
+    let pool_monitor = manager.monitor();
     let pool = Pool::builder()
         .max_size(10) // Default to 10 connections
         .build(manager)
-        .map_err(|e| format!("Failed to create connection pool: {}", e))?;
+        .map_err(|e| CoreError::internal(format!("Failed to create connection pool: {}", e)))?;
+    if let Ok(mut monitor) = db.pool_monitor.lock() {
+        *monitor = Some(pool_monitor);
+    }
 
     *pool_guard = Some(pool);
 
     Ok(())
 }
 
+/// Fails with `vault.already_open` while another instance has the vault
+/// open; `force` replaces a lock another machine left on a shared folder,
+/// once it is old enough
 #[tauri::command]
-pub fn unlock_vault_cmd(db: State<DbConnection>, path: &str, password: &str) -> Result<(), String> {
+pub fn unlock_vault_cmd(
+    app: tauri::AppHandle,
+    db: State<DbConnection>,
+    path: &str,
+    password: &str,
+    force: Option<bool>,
+) -> Result<(), CoreError> {
     let mut pool_guard = db
         .pool
         .lock()
-        .map_err(|_| "Failed to lock database pool".to_string())?;
+        .map_err(|_| CoreError::internal("Failed to lock database pool"))?;
     let mut dek_guard = db
         .dek
         .lock()
-        .map_err(|_| "Failed to lock DEK".to_string())?;
+        .map_err(|_| CoreError::internal("Failed to lock DEK"))?;
     let mut p2p_sync_guard = db
         .p2p_sync
         .lock()
-        .map_err(|_| "Failed to lock P2P sync".to_string())?;
+        .map_err(|_| CoreError::internal("Failed to lock P2P sync"))?;
     let mut vault_path_guard = db
         .vault_path
         .lock()
-        .map_err(|_| "Failed to lock vault path".to_string())?;
+        .map_err(|_| CoreError::internal("Failed to lock vault path"))?;
+    let mut auto_lock_guard = db
+        .auto_lock
+        .lock()
+        .map_err(|_| CoreError::internal("Failed to lock auto-lock state"))?;
 
     *pool_guard = None;
     *dek_guard = None;
     *p2p_sync_guard = None;
     *vault_path_guard = None;
+    *auto_lock_guard = None;
+    db.release_vault_lock();
 
-    let vault = unlock_vault(path, password).map_err(|e| e.to_string())?;
+    let lock_options = LockOptions {
+        force: force.unwrap_or(false),
+        ..Default::default()
+    };
+    let vault = unlock_vault_with_options(path, password, &lock_options)?;
 
     *dek_guard = Some(SecureDek::new(vault.dek.to_vec()));
+    store_vault_lock(&db, vault.lock)?;
     *vault_path_guard = Some(PathBuf::from(path));
 
-    let manager = EncryptedConnectionManager::new(Path::new(path).join("vault.sqlite3"), vault.dek);
+    let auto_lock = new_auto_lock(&app, *vault.dek);
+    *auto_lock_guard = Some(auto_lock.clone());
+    let manager = EncryptedConnectionManager::new(Path::new(path).join("vault.sqlite3"), auto_lock);
+    let pool_monitor = manager.monitor();
     let pool = Pool::builder()
         .max_size(10)
         .build(manager)
-        .map_err(|e| format!("Failed to create connection pool: {}", e))?;
+        .map_err(|e| CoreError::internal(format!("Failed to create connection pool: {}", e)))?;
+    if let Ok(mut monitor) = db.pool_monitor.lock() {
+        *monitor = Some(pool_monitor);
+    }
 
     *pool_guard = Some(pool.clone());
 
     // Initialize P2P Sync
     // Get a connection from the pool for initialization
-    let conn = pool
-        .get()
-        .map_err(|e| format!("Failed to get connection for P2P init: {}", e))?;
+    let conn = pool.get().map_err(|e| {
+        CoreError::internal(format!("Failed to get connection for P2P init: {}", e))
+    })?;
+
+    match core_rs::db::load_settings::<core_rs::db::QueryStatsSettings>(&conn) {
+        Ok(settings) => core_rs::db::apply_query_stats_settings(&settings),
+        Err(e) => log::warn!("[vault] Failed to load query stats settings: {}", e),
+    }
 
     let device_id = core_rs::db::get_or_create_user_id(&conn).unwrap_or_default();
     let device_info = core_rs::sync::mobile_sync::DeviceInfo {
@@ -110,7 +191,22 @@ pub fn unlock_vault_cmd(db: State<DbConnection>, path: &str, password: &str) ->
         is_active: true,
     };
 
-    let p2p = P2pSync::new(device_info).map_err(|e| e.to_string())?;
+    // The transport key peers pin at pairing must survive restarts
+    core_rs::sync_agent::init_sync_tables(&conn).map_err(|e| CoreError::internal(e.to_string()))?;
+    let static_key = core_rs::sync::secure_channel::load_or_create_static_key(&conn)
+        .map_err(|e| CoreError::internal(format!("Failed to load sync transport key: {}", e)))?;
+    let p2p = P2pSync::with_static_key(device_info, static_key)
+        .map_err(|e| CoreError::internal(e.to_string()))?;
+    // Only devices whose pairing SAS was confirmed may sync
+    match p2p.load_trusted_peers(&conn) {
+        Ok(count) => log::info!("[p2p] Loaded {} trusted peers", count),
+        Err(e) => log::warn!("[p2p] Failed to load trusted peers: {}", e),
+    }
+    // The mobile app still speaks protocol 1 unless the user opted out of it
+    match core_rs::db::load_settings::<core_rs::db::SyncSettings>(&conn) {
+        Ok(settings) => p2p.set_allow_legacy_peers(settings.allow_legacy_protocol),
+        Err(e) => log::warn!("[p2p] Failed to load sync settings: {}", e),
+    }
     // Advertise this device on the local network via MDNS
     if let Err(e) = p2p
         .discovery
@@ -135,9 +231,169 @@ pub fn unlock_vault_cmd(db: State<DbConnection>, path: &str, password: &str) ->
     Ok(())
 }
 
+/// Change the vault password; only the header is rewritten, so the vault can
+/// stay unlocked
+#[tauri::command]
+pub fn rotate_vault_password_cmd(
+    path: &str,
+    old_password: &str,
+    new_password: &str,
+) -> Result<(), String> {
+    rotate_vault_password(path, old_password, new_password).map_err(|e| e.to_string())
+}
+
+/// Re-encrypt the vault under a new DEK. The vault is locked first because
+/// the database file is replaced; progress is emitted as
+/// `vault-rekey-progress` events and the vault must be unlocked again after.
+#[tauri::command]
+pub async fn rotate_vault_dek_cmd(
+    window: tauri::Window,
+    db: State<'_, DbConnection>,
+    path: String,
+    password: String,
+) -> Result<(), String> {
+    {
+        let mut pool_guard = db
+            .pool
+            .lock()
+            .map_err(|_| "Failed to lock database pool".to_string())?;
+        let mut dek_guard = db
+            .dek
+            .lock()
+            .map_err(|_| "Failed to lock DEK".to_string())?;
+        let mut p2p_sync_guard = db
+            .p2p_sync
+            .lock()
+            .map_err(|_| "Failed to lock P2P sync".to_string())?;
+        *pool_guard = None;
+        *dek_guard = None;
+        *p2p_sync_guard = None;
+    }
+    if let Ok(mut auto_lock) = db.auto_lock.lock() {
+        *auto_lock = None;
+    }
+    db.release_vault_lock();
+
+    tauri::async_runtime::spawn_blocking(move || {
+        rotate_dek(&path, &password, |progress| {
+            if let Err(e) = window.emit("vault-rekey-progress", progress) {
+                log::warn!("[vault] Failed to emit rekey progress: {}", e);
+            }
+        })
+    })
+    .await
+    .map_err(|e| e.to_string())?
+    .map_err(|e| e.to_string())
+}
+
+/// Lock the vault now, wiping the DEK as an idle timeout would
+#[tauri::command]
+pub fn lock_vault_cmd(db: State<DbConnection>) -> Result<(), String> {
+    let auto_lock = db
+        .auto_lock
+        .lock()
+        .map_err(|_| "Failed to lock auto-lock state".to_string())?
+        .clone();
+    match auto_lock {
+        Some(auto_lock) => {
+            auto_lock.lock();
+        }
+        None => db.clear_unlocked_state(),
+    }
+    Ok(())
+}
+
+/// Restart the idle timer for user activity that doesn't reach the backend
+#[tauri::command]
+pub fn touch_vault_activity_cmd(db: State<DbConnection>) -> Result<(), CoreError> {
+    db.touch()
+}
+
+#[derive(Debug, Serialize)]
+pub struct AutoLockStatus {
+    pub locked: bool,
+    /// `None` when auto-lock is disabled
+    pub idle_timeout_secs: Option<u64>,
+    /// Seconds until the vault locks itself, for the UI countdown
+    pub remaining_secs: Option<u64>,
+}
+
+#[tauri::command]
+pub fn get_auto_lock_status_cmd(db: State<DbConnection>) -> Result<AutoLockStatus, String> {
+    let auto_lock = db
+        .auto_lock
+        .lock()
+        .map_err(|_| "Failed to lock auto-lock state".to_string())?
+        .clone();
+    let max_idle = AppConfig::auto_lock_idle();
+    Ok(AutoLockStatus {
+        locked: auto_lock.as_ref().is_none_or(|guard| guard.is_locked()),
+        idle_timeout_secs: max_idle.map(|idle| idle.as_secs()),
+        remaining_secs: auto_lock
+            .zip(max_idle)
+            .and_then(|(guard, idle)| guard.remaining_until_lock(idle))
+            .map(|remaining| remaining.as_secs()),
+    })
+}
+
 #[tauri::command]
 pub fn get_or_create_user_id_cmd(db: State<DbConnection>) -> Result<String, String> {
     crate::with_db!(db, conn, {
         core_rs::db::get_or_create_user_id(&conn).map_err(|e| e.to_string())
     })
 }
+
+#[tauri::command]
+pub fn check_vault_integrity_cmd(
+    db: State<DbConnection>,
+    mode: Option<IntegrityCheckMode>,
+) -> Result<VaultIntegrityReport, String> {
+    crate::with_db!(db, conn, {
+        core_rs::db::check_vault_with_mode(&conn, mode.unwrap_or(IntegrityCheckMode::Full))
+            .map_err(|e| e.to_string())
+    })
+}
+
+/// Repairs the given issues, typically the ones the user selected from a check
+#[tauri::command]
+pub fn repair_vault_cmd(
+    db: State<DbConnection>,
+    issues: Vec<IntegrityIssue>,
+) -> Result<VaultRepairReport, String> {
+    crate::with_db_mut!(db, conn, {
+        core_rs::db::repair_vault(&mut conn, &issues).map_err(|e| e.to_string())
+    })
+}
+
+#[tauri::command]
+pub fn get_startup_integrity_check_cmd(db: State<DbConnection>) -> Result<bool, String> {
+    crate::with_db!(db, conn, {
+        core_rs::db::integrity::is_startup_integrity_check_enabled(&conn).map_err(|e| e.to_string())
+    })
+}
+
+#[tauri::command]
+pub fn set_startup_integrity_check_cmd(
+    db: State<DbConnection>,
+    enabled: bool,
+) -> Result<(), String> {
+    crate::with_db!(db, conn, {
+        core_rs::db::integrity::set_startup_integrity_check(&conn, enabled)
+            .map_err(|e| e.to_string())
+    })
+}
+
+#[tauri::command]
+pub fn get_storage_report_cmd(db: State<DbConnection>) -> Result<StorageReport, String> {
+    crate::with_db!(db, conn, {
+        core_rs::db::get_storage_report(&conn).map_err(|e| e.to_string())
+    })
+}
+
+/// Fails rather than waits when a sync or import is writing at the same time
+#[tauri::command]
+pub fn vacuum_vault_cmd(db: State<DbConnection>) -> Result<VacuumResult, String> {
+    crate::with_db!(db, conn, {
+        core_rs::db::vacuum_vault(&conn).map_err(|e| e.to_string())
+    })
+}
@@ -1,16 +1,55 @@
 use crate::state::DbConnection;
+use core_rs::ids::parse_entity_id;
 use core_rs::note::Note;
-use core_rs::weekly_review::generate_weekly_review;
+use core_rs::weekly_review::{generate_weekly_review, WeeklyReview};
 use tauri::State;
-use ulid::Ulid;
 
+/// Generate this week's review, or rewrite it if it was generated already
 #[tauri::command]
 pub fn generate_weekly_review_cmd(
     db: State<DbConnection>,
     space_id: String,
 ) -> Result<Note, String> {
     crate::with_db!(db, conn, {
-        let space_ulid = Ulid::from_string(&space_id).map_err(|e| e.to_string())?;
+        let space_ulid =
+            parse_entity_id("space", "space_id", &space_id).map_err(|e| e.to_string())?;
         generate_weekly_review(&conn, space_ulid).map_err(|e| e.to_string())
     })
 }
+
+#[tauri::command]
+pub fn get_weekly_reviews_cmd(
+    db: State<DbConnection>,
+    space_id: String,
+) -> Result<Vec<WeeklyReview>, String> {
+    crate::with_db!(db, conn, {
+        let space_ulid =
+            parse_entity_id("space", "space_id", &space_id).map_err(|e| e.to_string())?;
+        core_rs::weekly_review::get_weekly_reviews(&conn, space_ulid).map_err(|e| e.to_string())
+    })
+}
+
+#[tauri::command]
+pub fn mark_review_completed_cmd(
+    db: State<DbConnection>,
+    review_id: String,
+) -> Result<WeeklyReview, String> {
+    crate::with_db!(db, conn, {
+        let review_id =
+            parse_entity_id("weekly_review", "review_id", &review_id).map_err(|e| e.to_string())?;
+        core_rs::weekly_review::mark_review_completed(&conn, review_id).map_err(|e| e.to_string())
+    })
+}
+
+/// Consecutive weeks with a completed review
+#[tauri::command]
+pub fn get_weekly_review_streak_cmd(
+    db: State<DbConnection>,
+    space_id: String,
+) -> Result<i64, String> {
+    crate::with_db!(db, conn, {
+        let space_ulid =
+            parse_entity_id("space", "space_id", &space_id).map_err(|e| e.to_string())?;
+        core_rs::weekly_review::get_review_streak(&conn, space_ulid).map_err(|e| e.to_string())
+    })
+}
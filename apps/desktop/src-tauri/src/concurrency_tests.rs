@@ -1,6 +1,7 @@
 #[cfg(test)]
 mod tests {
-    use crate::db_pool::EncryptedConnectionManager;
+    use core_rs::db::EncryptedConnectionManager;
+    use core_rs::vault::AutoLockGuard;
     use r2d2::Pool;
     use std::path::Path;
     use std::sync::Arc;
@@ -14,7 +15,8 @@ mod tests {
         let db_path = dir.path().join("test.db");
         let dek = [0u8; 32]; // Dummy key
 
-        let manager = EncryptedConnectionManager::new(db_path.clone(), dek);
+        let manager =
+            EncryptedConnectionManager::new(db_path.clone(), Arc::new(AutoLockGuard::new(dek)));
         let pool = Pool::builder().max_size(10).build(manager).unwrap();
         let pool = Arc::new(pool);
 
@@ -64,4 +66,21 @@ mod tests {
             .unwrap();
         assert_eq!(count, 100);
     }
+
+    #[test]
+    fn test_locked_vault_refuses_connections() {
+        let dir = tempdir().unwrap();
+        let guard = Arc::new(AutoLockGuard::new([0u8; 32]));
+        let manager = EncryptedConnectionManager::new(dir.path().join("test.db"), guard.clone());
+        let pool = Pool::builder()
+            .max_size(2)
+            .connection_timeout(Duration::from_millis(200))
+            .build(manager)
+            .unwrap();
+        pool.get().unwrap();
+
+        guard.lock();
+        let err = pool.get().unwrap_err();
+        assert!(err.to_string().contains("Vault is locked"), "{}", err);
+    }
 }
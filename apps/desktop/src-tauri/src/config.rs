@@ -26,6 +26,8 @@ pub struct SecurityConfig {
     pub enable_https: bool,
     pub cert_path: Option<String>,
     pub key_path: Option<String>,
+    /// Idle time before the unlocked vault locks itself; 0 disables auto-lock
+    pub auto_lock_idle_secs: u64,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -49,6 +51,7 @@ impl Default for AppConfig {
                 enable_https: false,
                 cert_path: None,
                 key_path: None,
+                auto_lock_idle_secs: 900, // 15 minutes
             },
             sync: SyncConfig {
                 enabled: true,
@@ -94,6 +97,12 @@ impl AppConfig {
                 config.security.key_path = Some(key_path);
             }
 
+            if let Ok(idle_secs) = std::env::var("NOTEECE_AUTO_LOCK_SECS") {
+                if let Ok(secs) = idle_secs.parse::<u64>() {
+                    config.security.auto_lock_idle_secs = secs;
+                }
+            }
+
             config
         })
     }
@@ -115,6 +124,14 @@ impl AppConfig {
         Self::get().sync.port
     }
 
+    /// Idle time before the vault auto-locks, or `None` if disabled
+    pub fn auto_lock_idle() -> Option<std::time::Duration> {
+        match Self::get().security.auto_lock_idle_secs {
+            0 => None,
+            secs => Some(std::time::Duration::from_secs(secs)),
+        }
+    }
+
     /// Get server address
     pub fn server_address() -> String {
         let config = Self::get();
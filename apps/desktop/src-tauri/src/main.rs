@@ -4,7 +4,6 @@
 mod commands;
 mod concurrency_tests;
 mod config;
-mod db_pool;
 mod state;
 
 use commands::*;
@@ -13,21 +12,26 @@ use state::DbConnection;
 use std::sync::Mutex;
 use tauri::Manager;
 
-// Macro to safely access the database connection
+// Macro to safely access the database connection. Its errors are
+// `CoreError`s, which commands still returning `String` take as the message.
 #[macro_export]
 macro_rules! with_db {
     ($db:expr, $conn:ident, $block:block) => {{
+        $db.touch()?;
         let pool_guard = $db
             .pool
             .lock()
-            .map_err(|_| "Failed to lock database pool".to_string())?;
+            .map_err(|_| core_rs::error::CoreError::internal("Failed to lock database pool"))?;
         let pool = pool_guard
             .as_ref()
-            .ok_or_else(|| "Database not initialized. Please unlock the vault.".to_string())?;
+            .ok_or_else(core_rs::error::CoreError::vault_locked)?;
         #[allow(unused_mut)]
-        let mut $conn = pool
-            .get()
-            .map_err(|e| format!("Failed to get connection from pool: {}", e))?;
+        let mut $conn = pool.get().map_err(|e| {
+            core_rs::error::CoreError::internal(format!(
+                "Failed to get connection from pool: {}",
+                e
+            ))
+        })?;
         $block
     }};
 }
@@ -50,6 +54,22 @@ fn main() {
             dek: Mutex::new(None),
             p2p_sync: Mutex::new(None),
             vault_path: Mutex::new(None),
+            ocr_worker: Mutex::new(None),
+            llm_streams: Mutex::new(std::collections::HashMap::new()),
+            auto_lock: Mutex::new(None),
+            pool_monitor: Mutex::new(None),
+            extraction_sessions: Mutex::new(core_rs::social::ExtractionSessions::default()),
+            vault_lock: Mutex::new(None),
+        })
+        .setup(|app| {
+            if let Some(max_idle) = AppConfig::auto_lock_idle() {
+                spawn_auto_lock_watchdog(app.handle(), max_idle);
+            }
+            spawn_backup_scheduler(app.handle());
+            spawn_insight_scheduler(app.handle());
+            spawn_maintenance_scheduler(app.handle());
+            core_rs::events::register_sink(std::sync::Arc::new(TauriEventSink(app.handle())));
+            Ok(())
         })
         .on_window_event(|event| {
             if let tauri::WindowEvent::CloseRequested { .. } = event.event() {
@@ -57,20 +77,43 @@ fn main() {
                     if let Ok(mut dek_guard) = app.dek.lock() {
                         *dek_guard = None;
                     }
+                    let auto_lock = app.auto_lock.lock().ok().and_then(|guard| guard.clone());
+                    if let Some(auto_lock) = auto_lock {
+                        auto_lock.lock();
+                    }
                 }
             }
         })
         .invoke_handler(tauri::generate_handler![
             create_vault_cmd,
             unlock_vault_cmd,
+            lock_vault_cmd,
+            touch_vault_activity_cmd,
+            get_auto_lock_status_cmd,
+            rotate_vault_password_cmd,
+            rotate_vault_dek_cmd,
+            check_vault_integrity_cmd,
+            repair_vault_cmd,
+            get_startup_integrity_check_cmd,
+            set_startup_integrity_check_cmd,
+            get_storage_report_cmd,
+            vacuum_vault_cmd,
             get_project_cmd,
             get_projects_in_space_cmd,
             get_project_milestones_cmd,
             get_project_risks_cmd,
             get_project_updates_cmd,
+            create_project_update_cmd,
+            get_latest_project_statuses_cmd,
+            get_space_status_summary_cmd,
             create_project_risk_cmd,
             delete_project_cmd,
             update_project_cmd,
+            compute_project_health_cmd,
+            get_projects_health_summary_cmd,
+            get_project_health_history_cmd,
+            get_project_health_config_cmd,
+            set_project_health_config_cmd,
             create_saved_search_cmd,
             get_saved_search_cmd,
             get_saved_searches_cmd,
@@ -78,20 +121,68 @@ fn main() {
             delete_saved_search_cmd,
             execute_saved_search_cmd,
             generate_weekly_review_cmd,
+            get_weekly_reviews_cmd,
+            mark_review_completed_cmd,
+            get_weekly_review_streak_cmd,
+            capture_to_inbox_cmd,
+            get_inbox_items_cmd,
+            convert_inbox_to_note_cmd,
+            convert_inbox_to_task_cmd,
+            dismiss_inbox_item_cmd,
+            define_property_cmd,
+            get_property_definitions_cmd,
+            delete_property_cmd,
+            set_note_property_cmd,
+            get_note_properties_cmd,
+            remove_note_property_cmd,
+            search_notes_by_properties_cmd,
+            set_property_for_search_cmd,
+            create_automation_rule_cmd,
+            get_automation_rules_cmd,
+            set_automation_rule_enabled_cmd,
+            delete_automation_rule_cmd,
             get_space_modes_cmd,
             enable_mode_cmd,
             disable_mode_cmd,
+            preview_mode_purge_cmd,
+            purge_mode_cmd,
             create_note_cmd,
             get_note_cmd,
+            get_note_stats_cmd,
             update_note_content_cmd,
+            extract_meeting_actions_cmd,
+            validate_note_content_cmd,
+            apply_autofixes_cmd,
             trash_note_cmd,
+            lock_note_cmd,
+            unlock_note_content_cmd,
+            permanently_unlock_note_cmd,
+            find_unlinked_mentions_cmd,
+            find_duplicate_notes_cmd,
+            merge_notes_cmd,
+            begin_edit_session_cmd,
+            save_note_draft_cmd,
+            end_edit_session_cmd,
+            get_note_versions_cmd,
+            move_note_to_space_cmd,
+            bulk_tag_notes_cmd,
+            bulk_move_notes_to_space_cmd,
+            bulk_trash_notes_cmd,
+            create_note_reference_cmd,
+            remove_note_reference_cmd,
+            get_note_references_cmd,
+            get_note_link_previews_cmd,
+            fetch_url_metadata_cmd,
             create_task_cmd,
             get_task_cmd,
             update_task_cmd,
+            bulk_update_tasks_cmd,
             delete_task_cmd,
             get_tasks_by_project_cmd,
             get_due_cards_cmd,
             review_card_cmd,
+            start_review_session_cmd,
+            finish_review_session_cmd,
             get_all_notes_in_space_cmd,
             get_all_tasks_in_space_cmd,
             import_from_obsidian_cmd,
@@ -101,13 +192,48 @@ fn main() {
             get_form_templates_for_space_cmd,
             update_form_template_cmd,
             delete_form_template_cmd,
+            submit_form_cmd,
+            submit_form_with_mapping_cmd,
+            set_form_title_pattern_cmd,
+            get_form_submissions_cmd,
+            export_form_submissions_csv_cmd,
             get_analytics_data_cmd,
+            get_activity_heatmap_cmd,
+            get_daily_activity_series_cmd,
+            get_writing_stats_cmd,
             search_notes_cmd,
             get_or_create_daily_note_cmd,
+            get_or_create_daily_note_for_date_cmd,
+            get_daily_notes_in_range_cmd,
+            create_note_template_cmd,
+            get_note_templates_for_space_cmd,
+            delete_note_template_cmd,
+            create_note_from_template_cmd,
+            set_daily_note_template_cmd,
             get_all_spaces_cmd,
+            archive_space_cmd,
+            unarchive_space_cmd,
+            get_space_deletion_token_cmd,
+            delete_space_cmd,
+            list_builtin_space_templates_cmd,
+            export_space_template_cmd,
+            create_space_from_template_cmd,
+            create_space_from_builtin_template_cmd,
             get_all_tags_in_space_cmd,
             get_tags_with_counts_cmd,
+            get_tag_tree_cmd,
+            get_tag_usage_counts_cmd,
+            get_notes_with_tag_cmd,
+            rename_tag_cmd,
+            merge_tags_cmd,
+            add_tag_to_note_cmd,
             get_upcoming_tasks_cmd,
+            query_tasks_cmd,
+            count_tasks_cmd,
+            create_task_view_cmd,
+            get_task_views_cmd,
+            update_task_view_cmd,
+            delete_task_view_cmd,
             get_recent_notes_cmd,
             start_time_entry_cmd,
             stop_time_entry_cmd,
@@ -119,13 +245,36 @@ fn main() {
             get_project_time_stats_cmd,
             delete_time_entry_cmd,
             create_manual_time_entry_cmd,
+            create_time_entry_from_event_cmd,
+            suggest_time_entries_for_day_cmd,
+            list_undoable_operations_cmd,
+            undo_operation_cmd,
+            run_maintenance_cmd,
+            get_maintenance_history_cmd,
+            list_all_settings_cmd,
+            get_query_stats_cmd,
+            reset_query_stats_cmd,
+            get_query_stats_settings_cmd,
+            set_query_stats_settings_cmd,
             queue_ocr_cmd,
+            reprocess_ocr_cmd,
+            get_space_ocr_languages_cmd,
+            set_space_ocr_languages_cmd,
             get_ocr_status_cmd,
             search_ocr_text_cmd,
             process_ocr_job_cmd,
+            start_ocr_worker_cmd,
+            get_ocr_settings_cmd,
+            set_ocr_settings_cmd,
+            stop_ocr_worker_cmd,
+            get_ocr_queue_depth_cmd,
+            stream_llm_chat_cmd,
+            cancel_llm_stream_cmd,
             generate_insights_cmd,
             get_active_insights_cmd,
             dismiss_insight_cmd,
+            get_insight_schedule_cmd,
+            set_insight_schedule_cmd,
             record_feedback_cmd,
             add_caldav_account_cmd,
             get_caldav_accounts_cmd,
@@ -136,30 +285,88 @@ fn main() {
             get_caldav_sync_history_cmd,
             get_caldav_conflicts_cmd,
             resolve_caldav_conflict_cmd,
+            start_caldav_oauth_cmd,
+            add_caldav_oauth_account_cmd,
+            get_free_busy_cmd,
+            suggest_focus_blocks_cmd,
+            create_calendar_event_cmd,
+            update_calendar_event_cmd,
+            delete_calendar_event_cmd,
+            get_calendar_events_in_range_cmd,
+            get_due_reminders_cmd,
+            mark_reminder_sent_cmd,
             init_sync_tables_cmd,
             get_devices_cmd,
             register_device_cmd,
             get_sync_history_for_space_cmd,
+            get_device_sync_scope_cmd,
+            set_device_sync_scope_cmd,
+            get_note_merge_conflict_cmd,
+            get_device_user_cmd,
+            set_device_user_cmd,
+            get_rejected_deltas_cmd,
             get_sync_conflicts_cmd,
+            get_sync_conflicts_described_cmd,
             resolve_sync_conflict_cmd,
             record_sync_cmd,
             start_p2p_sync_cmd,
+            get_sync_relay_cmd,
+            set_sync_relay_cmd,
+            poll_relay_sync_cmd,
             create_health_metric_cmd,
             get_health_metrics_cmd,
+            get_metric_trend_cmd,
+            set_metric_threshold_cmd,
+            set_metric_direction_cmd,
+            check_threshold_breaches_cmd,
+            import_health_export_cmd,
+            create_health_goal_cmd,
+            get_health_goals_cmd,
+            delete_health_goal_cmd,
+            evaluate_health_goals_cmd,
             create_transaction_cmd,
             get_transactions_cmd,
+            set_budget_cmd,
+            get_budget_status_cmd,
+            get_spending_summary_cmd,
             create_recipe_cmd,
             get_recipes_cmd,
+            add_recipe_ingredient_cmd,
+            get_recipe_ingredients_cmd,
+            set_recipe_servings_cmd,
+            scale_recipe_cmd,
+            generate_shopping_list_cmd,
+            get_shopping_list_cmd,
+            set_shopping_item_checked_cmd,
+            delete_shopping_list_cmd,
             create_trip_cmd,
             get_trips_cmd,
+            add_itinerary_item_cmd,
+            reorder_itinerary_item_cmd,
+            get_trip_itinerary_cmd,
+            add_packing_item_cmd,
+            toggle_packed_cmd,
+            get_packing_list_cmd,
+            get_trip_packing_stats_cmd,
+            save_packing_template_cmd,
+            get_packing_templates_cmd,
+            apply_packing_template_cmd,
+            delete_packing_template_cmd,
+            get_trip_summary_cmd,
             build_current_graph_cmd,
             get_graph_evolution_cmd,
             detect_major_notes_cmd,
+            get_note_neighborhood_cmd,
+            find_note_path_cmd,
+            get_orphan_notes_cmd,
             shutdown_clear_keys_cmd,
             init_rbac_tables_cmd,
             get_space_users_cmd,
             check_permission_cmd,
             invite_user_cmd,
+            accept_invitation_cmd,
+            revoke_invitation_cmd,
+            list_pending_invitations_cmd,
             update_user_role_cmd,
             grant_permission_cmd,
             revoke_permission_cmd,
@@ -168,26 +375,61 @@ fn main() {
             get_roles_cmd,
             add_user_to_space_cmd,
             remove_user_from_space_cmd,
+            heartbeat_presence_cmd,
+            leave_presence_cmd,
+            get_active_users_cmd,
+            get_entity_viewers_cmd,
+            acquire_edit_lock_cmd,
+            release_edit_lock_cmd,
             add_social_account_cmd,
             get_social_accounts_cmd,
             get_social_account_cmd,
             update_social_account_cmd,
             delete_social_account_cmd,
             store_social_posts_cmd,
+            begin_extraction_session_cmd,
+            ingest_extraction_chunk_cmd,
+            finish_extraction_session_cmd,
+            dedup_social_posts_cmd,
+            archive_post_to_note_cmd,
+            archive_category_to_notes_cmd,
             get_unified_timeline_cmd,
+            get_unified_timeline_page_cmd,
+            get_timeline_new_count_cmd,
             create_category_cmd,
             get_categories_cmd,
             assign_category_cmd,
             delete_category_cmd,
             get_timeline_stats_cmd,
+            cluster_timeline_topics_cmd,
+            get_topic_overview_cmd,
             get_analytics_overview_cmd,
+            get_engagement_timeseries_cmd,
+            get_posting_time_analysis_cmd,
             search_social_posts_cmd,
             auto_categorize_posts_cmd,
+            create_category_rule_cmd,
+            get_category_rules_cmd,
+            set_category_rule_enabled_cmd,
+            delete_category_rule_cmd,
+            dry_run_category_rules_cmd,
+            apply_category_rules_cmd,
+            export_category_rules_cmd,
+            import_category_rules_cmd,
             create_webview_session_cmd,
             get_webview_session_cmd,
             save_session_cookies_cmd,
+            record_platform_usage_cmd,
+            get_platform_usage_today_cmd,
+            check_platform_access_cmd,
+            set_usage_timezone_cmd,
+            fetch_selector_update_cmd,
+            get_active_selectors_cmd,
+            rollback_selectors_cmd,
+            record_extraction_result_cmd,
             get_all_sync_tasks_cmd,
             get_sync_stats_cmd,
+            get_sync_transfer_report_cmd,
             create_backup_cmd,
             restore_backup_cmd,
             list_backups_cmd,
@@ -196,18 +438,36 @@ fn main() {
             create_user_cmd,
             authenticate_user_cmd,
             validate_session_cmd,
+            refresh_session_cmd,
+            list_sessions_cmd,
+            revoke_session_cmd,
+            revoke_all_sessions_cmd,
+            get_auth_audit_log_cmd,
+            get_audit_trail_cmd,
+            verify_audit_chain_cmd,
+            set_space_audit_enabled_cmd,
             logout_user_cmd,
             get_user_by_id_cmd,
             change_password_cmd,
             get_current_user_cmd,
             discover_devices_cmd,
+            add_manual_peer_cmd,
+            remove_manual_peer_cmd,
+            get_manual_peers_cmd,
             initiate_pairing_cmd,
             exchange_keys_cmd,
+            get_pending_pairing_sas_cmd,
+            confirm_pairing_cmd,
             get_sync_progress_cmd,
+            get_sync_settings_cmd,
+            set_sync_settings_cmd,
             cancel_sync_cmd,
             get_or_create_user_id_cmd,
             start_sync_server_cmd,
             get_dashboard_stats_cmd,
+            get_dashboard_config_cmd,
+            set_dashboard_config_cmd,
+            get_pool_health_cmd,
             create_goal_cmd,
             get_goals_cmd,
             update_goal_progress_cmd,
@@ -215,8 +475,78 @@ fn main() {
             create_habit_cmd,
             get_habits_cmd,
             complete_habit_cmd,
-            delete_habit_cmd
+            delete_habit_cmd,
+            get_backup_policy_cmd,
+            set_backup_policy_cmd,
+            get_backup_runs_cmd,
+            export_note_bundle_cmd,
+            import_note_bundle_cmd
         ])
-        .run(tauri::generate_context!())
-        .expect("error while running tauri application");
+        .build(tauri::generate_context!())
+        .expect("error while building tauri application")
+        .run(|app, event| {
+            if let tauri::RunEvent::Exit = event {
+                app.state::<DbConnection>().release_vault_lock();
+            }
+        });
+}
+
+/// Poll the vault's idle timer; the guard's `on_lock` callback does the rest
+fn spawn_auto_lock_watchdog(app: tauri::AppHandle, max_idle: std::time::Duration) {
+    let interval = (max_idle / 10).clamp(
+        std::time::Duration::from_secs(1),
+        std::time::Duration::from_secs(15),
+    );
+    std::thread::spawn(move || loop {
+        std::thread::sleep(interval);
+        let db = app.state::<DbConnection>();
+        let auto_lock = db.auto_lock.lock().ok().and_then(|guard| guard.clone());
+        if let Some(auto_lock) = auto_lock {
+            auto_lock.check_and_lock(max_idle);
+        }
+    });
+}
+
+/// Check once a minute whether the backup policy wants a backup
+fn spawn_backup_scheduler(app: tauri::AppHandle) {
+    std::thread::spawn(move || loop {
+        std::thread::sleep(std::time::Duration::from_secs(60));
+        let db = app.state::<DbConnection>();
+        if let Err(e) = commands::backup::run_due_backup(&db) {
+            log::warn!("[backup] Scheduled backup check failed: {}", e);
+        }
+    });
+}
+
+/// Check every five minutes for spaces due for insight generation
+fn spawn_insight_scheduler(app: tauri::AppHandle) {
+    std::thread::spawn(move || loop {
+        std::thread::sleep(std::time::Duration::from_secs(5 * 60));
+        let db = app.state::<DbConnection>();
+        if let Err(e) = commands::foresight::run_due_insights(&db) {
+            log::warn!("[foresight] Scheduled insight check failed: {}", e);
+        }
+    });
+}
+
+/// Run background maintenance every half hour
+fn spawn_maintenance_scheduler(app: tauri::AppHandle) {
+    std::thread::spawn(move || loop {
+        std::thread::sleep(std::time::Duration::from_secs(30 * 60));
+        let db = app.state::<DbConnection>();
+        if let Err(e) = commands::maintenance::run_scheduled_maintenance(&db) {
+            log::warn!("[maintenance] Scheduled maintenance failed: {}", e);
+        }
+    });
+}
+
+/// Forwards core events to the frontend as `core-event`
+struct TauriEventSink(tauri::AppHandle);
+
+impl core_rs::events::EventSink for TauriEventSink {
+    fn deliver(&self, event: &core_rs::events::EmittedEvent) {
+        if let Err(e) = self.0.emit_all("core-event", event) {
+            log::warn!("[events] Failed to forward event {}: {}", event.id, e);
+        }
+    }
 }
@@ -1,6 +1,11 @@
-use crate::db_pool::EncryptedConnectionManager;
+use core_rs::db::{EncryptedConnectionManager, PoolMonitor};
+use core_rs::error::CoreError;
+use core_rs::ocr::OcrWorker;
+use core_rs::social::ExtractionSessions;
 use core_rs::sync::p2p::P2pSync;
+use core_rs::vault::{AutoLockGuard, VaultLock};
 use r2d2::Pool;
+use std::collections::HashMap;
 use std::path::PathBuf;
 use std::sync::{Arc, Mutex};
 use zeroize::Zeroizing;
@@ -24,4 +29,64 @@ pub struct DbConnection {
     pub dek: Mutex<Option<SecureDek>>,
     pub p2p_sync: Mutex<Option<Arc<P2pSync>>>,
     pub vault_path: Mutex<Option<PathBuf>>,
+    pub ocr_worker: Mutex<Option<OcrWorker<EncryptedConnectionManager>>>,
+    /// In-flight LLM streams by stream id; aborting a handle cancels its stream
+    pub llm_streams: Mutex<HashMap<String, tauri::async_runtime::JoinHandle<()>>>,
+    /// Owns the DEK the pool connects with and locks the vault when idle
+    pub auto_lock: Mutex<Option<Arc<AutoLockGuard>>>,
+    /// WAL and connection statistics of the current pool
+    pub pool_monitor: Mutex<Option<Arc<PoolMonitor>>>,
+    /// Open chunked social extractions and the posts they still buffer
+    pub extraction_sessions: Mutex<ExtractionSessions>,
+    /// Keeps other instances out of the open vault. Kept while auto-locked,
+    /// since the vault is still this window's to unlock again.
+    pub vault_lock: Mutex<Option<VaultLock>>,
+}
+
+impl DbConnection {
+    /// Count a command as vault activity. Fails once the vault auto-locked;
+    /// with no vault open there is nothing to track.
+    pub fn touch(&self) -> Result<(), CoreError> {
+        let guard = self
+            .auto_lock
+            .lock()
+            .map_err(|_| CoreError::internal("Failed to lock auto-lock state"))?;
+        match guard.as_ref() {
+            Some(auto_lock) => Ok(auto_lock.touch()?),
+            None => Ok(()),
+        }
+    }
+
+    /// Drop everything that holds or was derived from the DEK
+    pub fn clear_unlocked_state(&self) {
+        if let Ok(mut pool) = self.pool.lock() {
+            *pool = None;
+        }
+        if let Ok(mut dek) = self.dek.lock() {
+            *dek = None;
+        }
+        if let Ok(mut p2p_sync) = self.p2p_sync.lock() {
+            *p2p_sync = None;
+        }
+        if let Ok(mut ocr_worker) = self.ocr_worker.lock() {
+            *ocr_worker = None;
+        }
+        if let Ok(mut pool_monitor) = self.pool_monitor.lock() {
+            *pool_monitor = None;
+        }
+        if let Ok(mut extraction_sessions) = self.extraction_sessions.lock() {
+            *extraction_sessions = ExtractionSessions::default();
+        }
+    }
+
+    /// Let another instance open the vault, on shutdown or before this one
+    /// reopens or rekeys it
+    pub fn release_vault_lock(&self) {
+        let lock = self.vault_lock.lock().ok().and_then(|mut lock| lock.take());
+        if let Some(lock) = lock {
+            if let Err(e) = lock.release() {
+                log::warn!("[vault] Failed to release vault lock: {}", e);
+            }
+        }
+    }
 }
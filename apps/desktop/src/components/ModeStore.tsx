@@ -12,6 +12,10 @@ const availableModes: Mode[] = [
   { id: 'meeting-notes', name: 'Meeting Notes' },
   { id: 'project-hub', name: 'Project Hub' },
   { id: 'srs', name: 'Spaced Repetition' },
+  { id: 'habits', name: 'Habits' },
+  { id: 'health', name: 'Health' },
+  { id: 'music', name: 'Music' },
+  { id: 'social', name: 'Social' },
 ];
 
 interface ModeStoreProperties {
@@ -11,7 +11,8 @@ interface OcrResult {
   status: 'queued' | 'processing' | 'completed' | 'failed';
   text: string | null;
   confidence: number | null;
-  language: string | null;
+  languages: string[];
+  detected_language: string | null;
   processed_at: number | null;
   error_message: string | null;
 }
@@ -21,7 +22,7 @@ export function OcrManager() {
   const [searchResults, setSearchResults] = useState<OcrResult[]>([]);
   const [isSearching, setIsSearching] = useState(false);
   const [uploadModalOpen, setUploadModalOpen] = useState(false);
-  const [language, setLanguage] = useState('eng');
+  const [languages, setLanguages] = useState('en');
   const [isUploading, setIsUploading] = useState(false);
   const [manualFilePath, setManualFilePath] = useState('');
   const [currentStatus, setCurrentStatus] = useState<OcrResult | null>(null);
@@ -107,7 +108,10 @@ export function OcrManager() {
       await invoke<string>('process_ocr_cmd', {
         blobId,
         imagePath: filePath,
-        language: language || undefined,
+        languages: languages
+          .split(',')
+          .map((tag) => tag.trim())
+          .filter(Boolean),
       });
 
       // Get the status to verify completion before notifying
@@ -275,7 +279,7 @@ export function OcrManager() {
                             {result.blob_id.slice(0, 12)}...
                           </Text>
                         </Table.Td>
-                        <Table.Td>{result.language || 'N/A'}</Table.Td>
+                        <Table.Td>{result.detected_language || 'N/A'}</Table.Td>
                         <Table.Td>{result.confidence ? `${(result.confidence * 100).toFixed(1)}%` : 'N/A'}</Table.Td>
                         <Table.Td>
                           <Text size="sm" lineClamp={2}>
@@ -324,11 +328,11 @@ export function OcrManager() {
             </Group>
 
             <TextInput
-              label="Language Code"
-              description="OCR language (e.g., 'eng' for English, 'fra' for French)"
-              value={language}
-              onChange={(e) => setLanguage(e.target.value)}
-              placeholder="eng"
+              label="Languages"
+              description="Comma-separated language tags (e.g., 'de, en' for German and English)"
+              value={languages}
+              onChange={(e) => setLanguages(e.target.value)}
+              placeholder="en"
             />
 
             <Text size="sm" c="dimmed">
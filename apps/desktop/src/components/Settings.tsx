@@ -3,12 +3,18 @@ import { invoke } from '@tauri-apps/api/tauri';
 import { Mode } from './types';
 import { useStore } from '../store';
 import { logger } from '@/utils/logger';
+import { hasErrorCode } from '@/utils/errors';
+import { createVault, unlockVault } from '../services/api';
 import { Container, Title, Stack, TextInput, Button, Text, Checkbox } from '@mantine/core';
 
 const availableModes: Mode[] = [
   { id: 'meeting-notes', name: 'Meeting Notes', category: 'productivity' },
   { id: 'project-hub', name: 'Project Hub', category: 'productivity' },
   { id: 'srs', name: 'Spaced Repetition', category: 'learning' },
+  { id: 'habits', name: 'Habits', category: 'productivity' },
+  { id: 'health', name: 'Health', category: 'personal' },
+  { id: 'music', name: 'Music', category: 'personal' },
+  { id: 'social', name: 'Social', category: 'personal' },
 ];
 
 const Settings: React.FC = () => {
@@ -16,6 +22,7 @@ const Settings: React.FC = () => {
   const [password, setPassword] = useState('');
   const [modes, setModes] = useState<Mode[]>([]);
   const [message, setMessage] = useState('');
+  const [openElsewhere, setOpenElsewhere] = useState(false);
   const { activeSpaceId } = useStore();
 
   useEffect(() => {
@@ -34,19 +41,27 @@ const Settings: React.FC = () => {
 
   const handleCreateVault = async () => {
     try {
-      await invoke('create_vault_cmd', { path, password });
+      await createVault(path, password);
+      setOpenElsewhere(false);
       setMessage('Vault created successfully');
     } catch (error) {
       setMessage(`Error creating vault: ${String(error)}`);
     }
   };
 
-  const handleUnlockVault = async () => {
+  const handleUnlockVault = async (force = false) => {
     try {
-      await invoke('unlock_vault_cmd', { path, password });
+      await unlockVault(path, password, force);
+      setOpenElsewhere(false);
       setMessage('Vault unlocked successfully');
     } catch (error) {
-      setMessage(`Error unlocking vault: ${String(error)}`);
+      const alreadyOpen = hasErrorCode(error, 'vault.already_open');
+      setOpenElsewhere(alreadyOpen);
+      setMessage(
+        alreadyOpen
+          ? `${String(error)}. Close it there first, or open it anyway if that instance is no longer running.`
+          : `Error unlocking vault: ${String(error)}`,
+      );
     }
   };
 
@@ -90,9 +105,14 @@ const Settings: React.FC = () => {
           />
           <Stack gap="xs">
             <Button onClick={handleCreateVault}>Create Vault</Button>
-            <Button onClick={handleUnlockVault} variant="light">
+            <Button onClick={() => handleUnlockVault()} variant="light">
               Unlock Vault
             </Button>
+            {openElsewhere && (
+              <Button onClick={() => handleUnlockVault(true)} variant="outline" color="red">
+                Open Anyway
+              </Button>
+            )}
           </Stack>
           {message && <Text c="blue">{message}</Text>}
         </Stack>
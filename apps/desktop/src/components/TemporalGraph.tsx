@@ -45,11 +45,18 @@ interface GraphMetrics {
   growth_rate: number;
 }
 
+interface NodeDegree {
+  node_id: string;
+  title: string;
+  degree: number;
+}
+
 interface GraphSnapshot {
   timestamp: number;
   nodes: GraphNode[];
   edges: GraphEdge[];
   metrics: GraphMetrics;
+  top_nodes?: NodeDegree[];
 }
 
 interface GraphMilestone {
@@ -65,10 +72,14 @@ interface GraphEvolution {
   time_range: [number, number];
   snapshots: GraphSnapshot[];
   milestones: GraphMilestone[];
+  summaries_computed?: number;
 }
 
 const TemporalGraph: React.FC<{ spaceId: string }> = ({ spaceId }) => {
   const [currentSnapshot, setCurrentSnapshot] = useState<GraphSnapshot | null>(null);
+  // Evolution samples are summaries (metrics and top nodes, no nodes or
+  // edges); the one picked on the slider stands in for the current metrics
+  const [summary, setSummary] = useState<GraphSnapshot | null>(null);
   const [evolution, setEvolution] = useState<GraphEvolution | null>(null);
   const [loading, setLoading] = useState(true);
   const [playing, setPlaying] = useState(false);
@@ -86,7 +97,7 @@ const TemporalGraph: React.FC<{ spaceId: string }> = ({ spaceId }) => {
     if (playing && evolution && currentIndex < evolution.snapshots.length - 1) {
       const timer = setTimeout(() => {
         setCurrentIndex((previous) => previous + 1);
-        setCurrentSnapshot(evolution.snapshots[currentIndex + 1]);
+        setSummary(evolution.snapshots[currentIndex + 1]);
       }, 2000); // 2 second intervals
 
       return () => clearTimeout(timer);
@@ -102,6 +113,7 @@ const TemporalGraph: React.FC<{ spaceId: string }> = ({ spaceId }) => {
       // Load current graph
       const current = await invoke<GraphSnapshot>('build_current_graph_cmd', { spaceId });
       setCurrentSnapshot(current);
+      setSummary(null);
 
       // Load milestones
       const milestonesData = await invoke<GraphMilestone[]>('detect_major_notes_cmd', {
@@ -138,7 +150,7 @@ const TemporalGraph: React.FC<{ spaceId: string }> = ({ spaceId }) => {
     } else {
       if (currentIndex >= evolution.snapshots.length - 1) {
         setCurrentIndex(0);
-        setCurrentSnapshot(evolution.snapshots[0]);
+        setSummary(evolution.snapshots[0]);
       }
       setPlaying(true);
     }
@@ -149,7 +161,7 @@ const TemporalGraph: React.FC<{ spaceId: string }> = ({ spaceId }) => {
       if (!evolution) return;
       setCurrentIndex(value);
 
-      setCurrentSnapshot(evolution.snapshots[value]);
+      setSummary(evolution.snapshots[value]);
       setPlaying(false);
     },
     [evolution],
@@ -171,6 +183,8 @@ const TemporalGraph: React.FC<{ spaceId: string }> = ({ spaceId }) => {
     );
   }
 
+  const shown = summary ?? currentSnapshot;
+  const metrics = shown.metrics;
   const nodesByType: Record<string, number> = {};
   for (const node of currentSnapshot.nodes) {
     nodesByType[node.node_type] = (nodesByType[node.node_type] || 0) + 1;
@@ -200,14 +214,15 @@ const TemporalGraph: React.FC<{ spaceId: string }> = ({ spaceId }) => {
                   Total Nodes
                 </Text>
                 <Text size="xl" fw={700}>
-                  {currentSnapshot.metrics.total_nodes}
+                  {metrics.total_nodes}
                 </Text>
                 <Text size="xs" c="dimmed">
-                  {Object.entries(nodesByType).map(([type, count]) => (
-                    <Badge key={type} variant="light" size="xs" mr={4}>
-                      {type}: {count}
-                    </Badge>
-                  ))}
+                  {!summary &&
+                    Object.entries(nodesByType).map(([type, count]) => (
+                      <Badge key={type} variant="light" size="xs" mr={4}>
+                        {type}: {count}
+                      </Badge>
+                    ))}
                 </Text>
               </Stack>
             </Paper>
@@ -219,10 +234,10 @@ const TemporalGraph: React.FC<{ spaceId: string }> = ({ spaceId }) => {
                   Connections
                 </Text>
                 <Text size="xl" fw={700}>
-                  {currentSnapshot.metrics.total_edges}
+                  {metrics.total_edges}
                 </Text>
                 <Text size="xs" c="dimmed">
-                  Avg: {currentSnapshot.metrics.avg_degree.toFixed(1)} per node
+                  Avg: {metrics.avg_degree.toFixed(1)} per node
                 </Text>
               </Stack>
             </Paper>
@@ -234,7 +249,7 @@ const TemporalGraph: React.FC<{ spaceId: string }> = ({ spaceId }) => {
                   Communities
                 </Text>
                 <Text size="xl" fw={700}>
-                  {currentSnapshot.metrics.communities}
+                  {metrics.communities}
                 </Text>
                 <Text size="xs" c="dimmed">
                   Knowledge clusters
@@ -249,7 +264,7 @@ const TemporalGraph: React.FC<{ spaceId: string }> = ({ spaceId }) => {
                   Growth Rate
                 </Text>
                 <Text size="xl" fw={700}>
-                  {currentSnapshot.metrics.growth_rate.toFixed(1)}
+                  {metrics.growth_rate.toFixed(1)}
                 </Text>
                 <Text size="xs" c="dimmed">
                   Nodes per day
@@ -333,13 +348,26 @@ const TemporalGraph: React.FC<{ spaceId: string }> = ({ spaceId }) => {
             <Stack gap="md">
               <Group justify="space-between">
                 <Text fw={500}>Time Playback</Text>
-                <Button
-                  variant="light"
-                  leftSection={playing ? <IconPlayerPause size={16} /> : <IconPlayerPlay size={16} />}
-                  onClick={togglePlayback}
-                >
-                  {playing ? 'Pause' : 'Play'}
-                </Button>
+                <Group gap="xs">
+                  {summary && (
+                    <Button
+                      variant="subtle"
+                      onClick={() => {
+                        setPlaying(false);
+                        setSummary(null);
+                      }}
+                    >
+                      Back to current
+                    </Button>
+                  )}
+                  <Button
+                    variant="light"
+                    leftSection={playing ? <IconPlayerPause size={16} /> : <IconPlayerPlay size={16} />}
+                    onClick={togglePlayback}
+                  >
+                    {playing ? 'Pause' : 'Play'}
+                  </Button>
+                </Group>
               </Group>
 
               <Slider
@@ -354,8 +382,28 @@ const TemporalGraph: React.FC<{ spaceId: string }> = ({ spaceId }) => {
               />
 
               <Text size="sm" c="dimmed">
-                {currentSnapshot.timestamp ? new Date(currentSnapshot.timestamp * 1000).toLocaleString() : 'Current'}
+                {summary ? `As of ${new Date(summary.timestamp * 1000).toLocaleString()}` : 'Current'}
               </Text>
+              {summary && (
+                <Text size="xs" c="dimmed">
+                  Metrics and most connected notes at this point; the graph above shows the current state
+                </Text>
+              )}
+
+              {shown.top_nodes && shown.top_nodes.length > 0 && (
+                <Stack gap={4}>
+                  <Text size="xs" c="dimmed" tt="uppercase">
+                    Most connected
+                  </Text>
+                  <Group gap="xs">
+                    {shown.top_nodes.map((node) => (
+                      <Badge key={node.node_id} variant="light" size="sm">
+                        {node.title}: {node.degree}
+                      </Badge>
+                    ))}
+                  </Group>
+                </Stack>
+              )}
             </Stack>
           </Card>
         )}
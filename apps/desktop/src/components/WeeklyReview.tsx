@@ -1,25 +1,17 @@
 import React, { useState } from 'react';
-import { invoke } from '@tauri-apps/api/tauri';
-import { Button, Card, Text, Loader, Alert } from '@mantine/core';
-import { IconInfoCircle } from '@tabler/icons-react';
+import { Button, Card, Group, Text, Loader, Alert } from '@mantine/core';
+import { IconCheck, IconInfoCircle } from '@tabler/icons-react';
+import { Note, WeeklyReview as WeeklyReviewRecord } from '@noteece/types';
+import { generateWeeklyReview, getWeeklyReviews, markReviewCompleted } from '@/services/api';
 import { logger } from '@/utils/logger';
 
-interface Note {
-  id: string;
-  space_id: string;
-  title: string;
-  content_md: string;
-  created_at: number;
-  modified_at: number;
-  is_trashed: boolean;
-}
-
 interface WeeklyReviewProperties {
   spaceId: string;
 }
 
 const WeeklyReview: React.FC<WeeklyReviewProperties> = ({ spaceId }) => {
   const [reviewNote, setReviewNote] = useState<Note | null>(null);
+  const [review, setReview] = useState<WeeklyReviewRecord | null>(null);
   const [loading, setLoading] = useState(false);
   const [error, setError] = useState<string | null>(null);
 
@@ -27,8 +19,10 @@ const WeeklyReview: React.FC<WeeklyReviewProperties> = ({ spaceId }) => {
     setLoading(true);
     setError(null);
     try {
-      const note: Note = await invoke('generate_weekly_review_cmd', { spaceId });
+      const note = await generateWeeklyReview(spaceId);
       setReviewNote(note);
+      const reviews = await getWeeklyReviews(spaceId);
+      setReview(reviews.find((r) => r.note_id === note.id) ?? null);
     } catch (error_) {
       setError('Failed to generate weekly review. Please try again later.');
       logger.error('Failed to generate weekly review:', error_ as Error);
@@ -37,6 +31,16 @@ const WeeklyReview: React.FC<WeeklyReviewProperties> = ({ spaceId }) => {
     }
   };
 
+  const handleMarkCompleted = async () => {
+    if (!review) return;
+    try {
+      setReview(await markReviewCompleted(review.id));
+    } catch (error_) {
+      setError('Failed to mark the review as done.');
+      logger.error('Failed to mark weekly review completed:', error_ as Error);
+    }
+  };
+
   return (
     <div>
       <h2>Weekly Review</h2>
@@ -52,7 +56,20 @@ const WeeklyReview: React.FC<WeeklyReviewProperties> = ({ spaceId }) => {
 
       {reviewNote && (
         <Card shadow="sm" p="lg" radius="md" withBorder mt="md">
-          <Text fw={500}>{reviewNote.title}</Text>
+          <Group justify="space-between">
+            <Text fw={500}>{reviewNote.title}</Text>
+            {review && (
+              <Button
+                size="xs"
+                variant="light"
+                leftSection={<IconCheck size={14} />}
+                onClick={handleMarkCompleted}
+                disabled={review.completed_at != null}
+              >
+                {review.completed_at == null ? 'Mark Review Done' : 'Review Done'}
+              </Button>
+            )}
+          </Group>
           <Text style={{ whiteSpace: 'pre-wrap' }} mt="md">
             {reviewNote.content_md}
           </Text>
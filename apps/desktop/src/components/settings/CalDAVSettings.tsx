@@ -24,6 +24,8 @@ interface CalDavAccount {
   id: string;
   url: string;
   username: string;
+  auth_type: 'basic' | 'bearer';
+  token_expires_at?: number;
   calendar_path: string;
   sync_token?: string;
   last_sync?: number;
@@ -30,6 +30,9 @@ interface PlatformStat {
 interface TimeSeriesPoint {
   date: string;
   count: number;
+  likes: number;
+  comments: number;
+  shares: number;
 }
 
 interface CategoryStat {
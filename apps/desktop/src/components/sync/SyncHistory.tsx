@@ -63,6 +63,11 @@ export const SyncHistory: React.FC<SyncHistoryProps> = ({ history, limit = 20 })
         <Group gap="xs">
           <DirectionIcon direction={entry.direction} />
           <Text size="sm">{entry.device_name}</Text>
+          {entry.transport === 'relay' && (
+            <Badge size="xs" color="gray" variant="outline">
+              via relay
+            </Badge>
+          )}
         </Group>
       </Table.Td>
       <Table.Td>
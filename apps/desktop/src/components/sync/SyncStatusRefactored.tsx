@@ -57,6 +57,7 @@ interface BackendSyncHistory {
   conflicts_detected: number;
   success: boolean;
   error_message: string | null;
+  transport: 'direct' | 'relay';
 }
 
 interface BackendSyncConflict {
@@ -211,6 +212,7 @@ const SyncStatus: React.FC = () => {
     completed_at: h.sync_time + 5, // Approximate
     status: h.success ? 'success' : 'failed',
     error: h.error_message || undefined,
+    transport: h.transport,
   }));
 
   const mappedConflicts: SyncConflict[] = conflicts.map((c) => ({
@@ -34,6 +34,8 @@ export interface SyncHistoryEntry {
   completed_at: number;
   status: 'success' | 'partial' | 'failed';
   error?: string;
+  /** Whether the sync went through a relay rather than directly */
+  transport?: 'direct' | 'relay';
 }
 
 export interface SyncStats {
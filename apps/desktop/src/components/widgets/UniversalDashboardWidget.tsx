@@ -1,12 +1,134 @@
 import React, { useEffect } from 'react';
 import { Paper, Title, Grid, Stack, Text, Group, ThemeIcon, RingProgress, Center, Loader } from '@mantine/core';
-import { IconHeartRateMonitor, IconMusic, IconSocial, IconListCheck, IconActivity } from '@tabler/icons-react';
-import { DashboardStats } from '@noteece/types';
+import {
+  IconHeartRateMonitor,
+  IconMusic,
+  IconSocial,
+  IconListCheck,
+  IconActivity,
+  IconNotes,
+  IconFlame,
+  IconCards,
+} from '@tabler/icons-react';
+import { DashboardStats, PeriodTrend } from '@noteece/types';
 import { useAsync } from '../../hooks/useAsync';
 import { getDashboardStats } from '../../services/api';
 import { useStore } from '../../store';
 import { logger } from '../../utils/logger';
 
+const emptyTrend: PeriodTrend = { current: 0, previous: 0, delta_percent: null };
+
+const emptyStats: DashboardStats = {
+  window_days: 7,
+  notes: { created: emptyTrend },
+  tasks: { pending_count: 0, completed_count: 0, completed: emptyTrend },
+  time: { tracked_seconds: emptyTrend },
+  weekly_review_streak_weeks: 0,
+  health: null,
+  habits: null,
+  srs: null,
+  music: null,
+  social: null,
+  quote: null,
+};
+
+interface StatTile {
+  label: string;
+  value: number;
+  color: string;
+  icon: React.ReactNode;
+  trend?: PeriodTrend;
+}
+
+const TrendDelta: React.FC<{ trend: PeriodTrend; windowDays: number }> = ({ trend, windowDays }) => {
+  if (trend.delta_percent === null) {
+    return null;
+  }
+  const rounded = Math.round(trend.delta_percent);
+  let color = 'dimmed';
+  if (rounded > 0) color = 'teal';
+  if (rounded < 0) color = 'red';
+  return (
+    <Text size="xs" c={color} title={`vs previous ${windowDays} days`}>
+      {rounded > 0 ? '+' : ''}
+      {rounded}%
+    </Text>
+  );
+};
+
+/** Tiles for the sections the space's modes include */
+const statTiles = (stats: DashboardStats): StatTile[] => {
+  const tiles: StatTile[] = [
+    {
+      label: 'Notes',
+      value: stats.notes.created.current,
+      color: 'blue',
+      icon: <IconNotes size={16} />,
+      trend: stats.notes.created,
+    },
+    {
+      label: 'Tasks',
+      value: stats.tasks.pending_count,
+      color: 'green',
+      icon: <IconListCheck size={16} />,
+      trend: stats.tasks.completed,
+    },
+  ];
+  if (stats.weekly_review_streak_weeks > 0) {
+    tiles.push({
+      label: 'Weekly Reviews',
+      value: stats.weekly_review_streak_weeks,
+      color: 'indigo',
+      icon: <IconFlame size={16} />,
+    });
+  }
+  if (stats.habits) {
+    tiles.push({
+      label: 'Habits',
+      value: stats.habits.completed.current,
+      color: 'orange',
+      icon: <IconFlame size={16} />,
+      trend: stats.habits.completed,
+    });
+  }
+  if (stats.srs) {
+    tiles.push({
+      label: 'Cards Due',
+      value: stats.srs.cards_due.current,
+      color: 'grape',
+      icon: <IconCards size={16} />,
+      trend: stats.srs.cards_due,
+    });
+    tiles.push({
+      label: 'Review Streak',
+      value: stats.srs.review_streak_days,
+      color: 'grape',
+      icon: <IconFlame size={16} />,
+    });
+  }
+  if (stats.health) {
+    tiles.push({
+      label: 'Health',
+      value: stats.health.metrics_count,
+      color: 'red',
+      icon: <IconHeartRateMonitor size={16} />,
+    });
+  }
+  if (stats.music) {
+    tiles.push({ label: 'Music', value: stats.music.track_count, color: 'violet', icon: <IconMusic size={16} /> });
+  }
+  if (stats.social) {
+    tiles.push({
+      label: 'Social',
+      value: stats.social.new_posts.current,
+      color: 'cyan',
+      icon: <IconSocial size={16} />,
+      trend: stats.social.new_posts,
+    });
+  }
+  return tiles;
+};
+
 export const UniversalDashboardWidget: React.FC = () => {
   const { activeSpaceId } = useStore();
 
@@ -19,25 +141,13 @@ export const UniversalDashboardWidget: React.FC = () => {
   } = useAsync<DashboardStats>(
     async () => {
       if (!activeSpaceId) {
-        return {
-          health: { metrics_count: 0, latest_metric: null },
-          music: { track_count: 0, playlist_count: 0 },
-          social: { posts_count: 0, platforms_count: 0 },
-          tasks: { pending_count: 0, completed_count: 0 },
-          quote: null,
-        };
+        return emptyStats;
       }
       try {
         return await getDashboardStats(activeSpaceId);
       } catch (error) {
-        logger.error('Failed to fetch stats, using mock', error);
-        return {
-          health: { metrics_count: 12, latest_metric: 'Steps' },
-          music: { track_count: 1450, playlist_count: 5 },
-          social: { posts_count: 24, platforms_count: 2 },
-          tasks: { pending_count: 8, completed_count: 15 },
-          quote: null,
-        };
+        logger.error('Failed to fetch stats', error);
+        return emptyStats;
       }
     },
     { immediate: false },
@@ -111,74 +221,28 @@ export const UniversalDashboardWidget: React.FC = () => {
         {/* Right: Stats Grid */}
         <Grid.Col span={8}>
           <Grid>
-            <Grid.Col span={6}>
-              <Paper withBorder p="xs" radius="sm">
-                <Group gap="xs">
-                  <ThemeIcon color="violet" variant="light" size="md">
-                    <IconMusic size={16} />
-                  </ThemeIcon>
-                  <div>
-                    <Text size="xs" c="dimmed">
-                      Music
-                    </Text>
-                    <Text fw={600} size="sm">
-                      {stats?.music.track_count || 0}
-                    </Text>
-                  </div>
-                </Group>
-              </Paper>
-            </Grid.Col>
-            <Grid.Col span={6}>
-              <Paper withBorder p="xs" radius="sm">
-                <Group gap="xs">
-                  <ThemeIcon color="red" variant="light" size="md">
-                    <IconHeartRateMonitor size={16} />
-                  </ThemeIcon>
-                  <div>
-                    <Text size="xs" c="dimmed">
-                      Health
-                    </Text>
-                    <Text fw={600} size="sm">
-                      {stats?.health.metrics_count || 0}
-                    </Text>
-                  </div>
-                </Group>
-              </Paper>
-            </Grid.Col>
-            <Grid.Col span={6}>
-              <Paper withBorder p="xs" radius="sm">
-                <Group gap="xs">
-                  <ThemeIcon color="cyan" variant="light" size="md">
-                    <IconSocial size={16} />
-                  </ThemeIcon>
-                  <div>
-                    <Text size="xs" c="dimmed">
-                      Social
-                    </Text>
-                    <Text fw={600} size="sm">
-                      {stats?.social.posts_count || 0}
-                    </Text>
-                  </div>
-                </Group>
-              </Paper>
-            </Grid.Col>
-            <Grid.Col span={6}>
-              <Paper withBorder p="xs" radius="sm">
-                <Group gap="xs">
-                  <ThemeIcon color="green" variant="light" size="md">
-                    <IconListCheck size={16} />
-                  </ThemeIcon>
-                  <div>
-                    <Text size="xs" c="dimmed">
-                      Tasks
-                    </Text>
-                    <Text fw={600} size="sm">
-                      {stats?.tasks.pending_count || 0}
-                    </Text>
-                  </div>
-                </Group>
-              </Paper>
-            </Grid.Col>
+            {statTiles(stats ?? emptyStats).map((tile) => (
+              <Grid.Col span={6} key={tile.label}>
+                <Paper withBorder p="xs" radius="sm">
+                  <Group gap="xs">
+                    <ThemeIcon color={tile.color} variant="light" size="md">
+                      {tile.icon}
+                    </ThemeIcon>
+                    <div>
+                      <Text size="xs" c="dimmed">
+                        {tile.label}
+                      </Text>
+                      <Group gap={4}>
+                        <Text fw={600} size="sm">
+                          {tile.value}
+                        </Text>
+                        {tile.trend && <TrendDelta trend={tile.trend} windowDays={stats?.window_days ?? 7} />}
+                      </Group>
+                    </div>
+                  </Group>
+                </Paper>
+              </Grid.Col>
+            ))}
           </Grid>
         </Grid.Col>
       </Grid>
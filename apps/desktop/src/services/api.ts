@@ -1,31 +1,154 @@
 // apps/desktop/src/services/api.ts
 
 import { invoke } from '@tauri-apps/api/tauri';
+import { listen, UnlistenFn } from '@tauri-apps/api/event';
 import { logger } from '../utils/logger';
+import { toError } from '../utils/errors';
 import {
   AnalyticsData,
+  ActivityHeatmap,
+  ActivityOptions,
+  DailyActivity,
+  WritingStats,
   FormTemplate,
+  FormSubmission,
+  SubmissionFilters,
   FormField,
+  MappedSubmission,
+  Presence,
+  EditLock,
   Task,
+  TaskFilter,
+  TaskSort,
+  TaskView,
+  InboxItem,
+  AutomationAction,
+  AutomationRule,
+  AutomationTrigger,
+  PropertyDefinition,
+  PropertyFilter,
+  PropertyType,
+  PropertyValue,
+  NoteProperty,
+  SearchResult,
   Project,
+  ReviewSession,
+  ReviewSessionConfig,
+  ReviewSessionStats,
   Note,
+  NoteStats,
+  DuplicateCluster,
+  MergeStrategy,
+  NoteMergeSummary,
+  EditSession,
+  NoteVersion,
+  MoveNoteOptions,
+  MoveReport,
+  BulkItemResult,
+  TaskPatch,
+  TagAction,
+  TimeEntryAction,
+  NoteReference,
+  WeeklyReview,
+  NoteFromTemplate,
+  NoteTemplate,
+  TemplateVariable,
   Space,
+  SpaceDeletionSummary,
+  SpaceTemplate,
   Tag,
+  TagNode,
+  TagRenameReport,
+  TagUsage,
   ProjectRisk,
   ProjectMilestone,
   TimeEntry,
+  EventEntryOverrides,
   TimeStats,
+  UndoableOperation,
+  MaintenanceRun,
+  MaintenanceHistoryEntry,
+  SettingsEntry,
+  QueryStat,
+  QueryStatsSettings,
+  SyncSettings,
+  OcrSettings,
   SyncTask,
   SyncStats,
+  SyncTransferReport,
+  SyncScope,
+  RejectedDelta,
   DeviceInfo,
   DiscoveredDevice,
+  PairingHello,
+  ShortAuthString,
   SyncConflict,
+  ConflictSummary,
+  RelayPullReport,
   ConflictResolution,
   ProjectUpdate,
+  ProjectStatusReport,
+  SpaceStatusSummary,
+  ProjectHealth,
+  ProjectHealthConfig,
+  ProjectHealthSnapshot,
+  ProjectHealthSummary,
   BackupMetadata,
+  BackupPolicy,
+  BackupRun,
+  ImportReport,
+  NotionImportOptions,
   User,
   Session,
+  AuditChainReport,
+  AuthAuditEntry,
+  AuthAuditFilter,
+  DataAuditEvent,
+  AutoLockStatus,
+  IntegrityCheckMode,
+  IntegrityIssue,
+  VaultIntegrityReport,
+  VaultRepairReport,
+  StorageReport,
+  VacuumResult,
   DashboardStats,
+  DashboardConfig,
+  ExtractionReport,
+  MetricTrend,
+  ThresholdBreach,
+  HealthExportSource,
+  HealthImportReport,
+  HealthGoal,
+  HealthGoalTarget,
+  HealthGoalEvaluation,
+  ItineraryItem,
+  ItineraryDay,
+  PackingItem,
+  PackingStats,
+  PackingTemplate,
+  TripSummary,
+  RecipeIngredient,
+  ScaledRecipe,
+  ShoppingList,
+  Budget,
+  BudgetStatus,
+  SpendingSummary,
+  Autofix,
+  NoteDiagnostic,
+  UrlMetadata,
+  NoteLinkPreviews,
+  TimeRange,
+  WorkingHours,
+  FreeBusy,
+  CalendarEvent,
+  DueReminder,
+  FocusConstraints,
+  ModeStatus,
+  ModeDataCount,
+  PurgePreview,
+  PoolHealth,
+  EmittedEvent,
+  InsightSchedule,
 } from '@noteece/types';
 
 // Generic wrapper for invoke to handle logging and secure parameter validation
@@ -52,15 +175,34 @@ async function invokeCmd<T>(cmd: string, args?: Record<string, unknown>): Promis
   } catch (error) {
     const duration = Date.now() - startTime;
     logger.error(`[API] ${cmd} failed after ${duration}ms`, error instanceof Error ? error : { error });
-    throw error;
+    throw toError(error);
   }
 }
 
 // Dashboard & Analytics
 export const getAnalyticsData = (): Promise<AnalyticsData> => invokeCmd('get_analytics_data_cmd', {});
+export const getActivityHeatmap = (
+  spaceId: string,
+  range: TimeRange,
+  options: ActivityOptions | null = null,
+): Promise<ActivityHeatmap> => invokeCmd('get_activity_heatmap_cmd', { spaceId, range, options });
+export const getDailyActivitySeries = (
+  range: TimeRange,
+  options: ActivityOptions | null = null,
+): Promise<DailyActivity[]> => invokeCmd('get_daily_activity_series_cmd', { range, options });
+/** Net words written per local day in a space, e.g. for "words written this week" */
+export const getWritingStats = (
+  spaceId: string,
+  range: TimeRange,
+  options: ActivityOptions | null = null,
+): Promise<WritingStats> => invokeCmd('get_writing_stats_cmd', { spaceId, range, options });
 
 export const getDashboardStats = (spaceId: string): Promise<DashboardStats> =>
   invokeCmd('get_dashboard_stats_cmd', { spaceId });
+export const getDashboardConfig = (): Promise<DashboardConfig> => invokeCmd('get_dashboard_config_cmd');
+export const setDashboardConfig = (config: DashboardConfig): Promise<void> =>
+  invokeCmd('set_dashboard_config_cmd', { config });
+export const getPoolHealth = (): Promise<PoolHealth> => invokeCmd('get_pool_health_cmd');
 
 // Forms
 export const getFormTemplatesForSpace = (spaceId: string): Promise<FormTemplate[]> =>
@@ -70,6 +212,27 @@ export const createFormTemplate = (spaceId: string, name: string, fields: FormFi
 export const updateFormTemplate = (id: string, name: string, fields: FormField[]): Promise<FormTemplate> =>
   invokeCmd('update_form_template_cmd', { id, name, fields });
 export const deleteFormTemplate = (id: string): Promise<void> => invokeCmd('delete_form_template_cmd', { id });
+export const submitForm = (
+  templateId: string,
+  values: Record<string, unknown>,
+  noteId: string | null = null,
+): Promise<FormSubmission> => invokeCmd('submit_form_cmd', { templateId, values, noteId });
+/** Submit and create the note, metrics and transactions the fields map to, all or nothing */
+export const submitFormWithMapping = (
+  templateId: string,
+  values: Record<string, unknown>,
+  spaceId: string,
+): Promise<MappedSubmission> => invokeCmd('submit_form_with_mapping_cmd', { templateId, values, spaceId });
+/** `null` clears the pattern */
+export const setFormTitlePattern = (templateId: string, pattern: string | null): Promise<void> =>
+  invokeCmd('set_form_title_pattern_cmd', { templateId, pattern });
+export const getFormSubmissions = (
+  templateId: string,
+  filters: SubmissionFilters | null = null,
+): Promise<FormSubmission[]> => invokeCmd('get_form_submissions_cmd', { templateId, filters });
+/** Resolves to the number of submissions written */
+export const exportFormSubmissionsCsv = (templateId: string, path: string): Promise<number> =>
+  invokeCmd('export_form_submissions_csv_cmd', { templateId, path });
 
 // Projects
 export const getProjectRisks = (projectId: string): Promise<ProjectRisk[]> =>
@@ -84,8 +247,28 @@ export const getProjectMilestones = (projectId: string): Promise<ProjectMileston
   invokeCmd('get_project_milestones_cmd', { projectId });
 export const getProjectUpdates = (projectId: string): Promise<ProjectUpdate[]> =>
   invokeCmd('get_project_updates_cmd', { projectId });
+export const createProjectUpdate = (
+  projectId: string,
+  whenAt: number,
+  health: string,
+  summary: string,
+  report: ProjectStatusReport,
+): Promise<ProjectUpdate> => invokeCmd('create_project_update_cmd', { projectId, whenAt, health, summary, report });
+export const getLatestProjectStatuses = (spaceId: string): Promise<ProjectUpdate[]> =>
+  invokeCmd('get_latest_project_statuses_cmd', { spaceId });
+export const getSpaceStatusSummary = (spaceId: string): Promise<SpaceStatusSummary> =>
+  invokeCmd('get_space_status_summary_cmd', { spaceId });
 export const getAllProjectsInSpace = (spaceId: string): Promise<Project[]> =>
   invokeCmd('get_projects_in_space_cmd', { spaceId });
+export const computeProjectHealth = (projectId: string): Promise<ProjectHealth> =>
+  invokeCmd('compute_project_health_cmd', { projectId });
+export const getProjectsHealthSummary = (spaceId: string): Promise<ProjectHealthSummary> =>
+  invokeCmd('get_projects_health_summary_cmd', { spaceId });
+export const getProjectHealthHistory = (projectId: string, limitDays?: number): Promise<ProjectHealthSnapshot[]> =>
+  invokeCmd('get_project_health_history_cmd', { projectId, limitDays: limitDays ?? null });
+export const getProjectHealthConfig = (): Promise<ProjectHealthConfig> => invokeCmd('get_project_health_config_cmd');
+export const setProjectHealthConfig = (config: ProjectHealthConfig): Promise<void> =>
+  invokeCmd('set_project_health_config_cmd', { config });
 
 // Tasks & Notes
 export const getAllTasksInSpace = (spaceId: string): Promise<Task[]> =>
@@ -94,20 +277,222 @@ export const getAllNotesInSpace = (spaceId: string): Promise<Note[]> =>
   invokeCmd('get_all_notes_in_space_cmd', { spaceId });
 export const getOrCreateDailyNote = (spaceId: string): Promise<Note> =>
   invokeCmd('get_or_create_daily_note_cmd', { spaceId });
+/** `date` is "YYYY-MM-DD" */
+export const getOrCreateDailyNoteForDate = (spaceId: string, date: string): Promise<Note> =>
+  invokeCmd('get_or_create_daily_note_for_date_cmd', { spaceId, date });
+/** Date ("YYYY-MM-DD") to note id, for days from `start` through `end` that have a daily note */
+export const getDailyNotesInRange = (spaceId: string, start: string, end: string): Promise<Record<string, string>> =>
+  invokeCmd('get_daily_notes_in_range_cmd', { spaceId, start, end });
+export const getNoteTemplatesForSpace = (spaceId: string): Promise<NoteTemplate[]> =>
+  invokeCmd('get_note_templates_for_space_cmd', { spaceId });
+export const createNoteTemplate = (
+  spaceId: string,
+  name: string,
+  content: string,
+  variables: TemplateVariable[],
+): Promise<NoteTemplate> => invokeCmd('create_note_template_cmd', { spaceId, name, content, variables });
+export const deleteNoteTemplate = (id: string): Promise<void> => invokeCmd('delete_note_template_cmd', { id });
+export const createNoteFromTemplate = (
+  templateId: string,
+  overrides: Record<string, string>,
+): Promise<NoteFromTemplate> => invokeCmd('create_note_from_template_cmd', { templateId, overrides });
+export const setDailyNoteTemplate = (spaceId: string, templateId: string | null): Promise<void> =>
+  invokeCmd('set_daily_note_template_cmd', { spaceId, templateId });
 export const getUpcomingTasks = (spaceId: string, limit: number): Promise<Task[]> =>
   invokeCmd('get_upcoming_tasks_cmd', { spaceId, limit });
+export const queryTasks = (
+  spaceId: string,
+  filter: TaskFilter,
+  sort?: TaskSort,
+  limit?: number,
+  offset?: number,
+): Promise<Task[]> => invokeCmd('query_tasks_cmd', { spaceId, filter, sort, limit, offset });
+export const countTasks = (spaceId: string, filter: TaskFilter): Promise<number> =>
+  invokeCmd('count_tasks_cmd', { spaceId, filter });
+export const createTaskView = (spaceId: string, name: string, filter: TaskFilter, sort: TaskSort): Promise<TaskView> =>
+  invokeCmd('create_task_view_cmd', { spaceId, name, filter, sort });
+export const getTaskViews = (spaceId: string): Promise<TaskView[]> => invokeCmd('get_task_views_cmd', { spaceId });
+export const updateTaskView = (id: string, name: string, filter: TaskFilter, sort: TaskSort): Promise<TaskView> =>
+  invokeCmd('update_task_view_cmd', { id, name, filter, sort });
+export const deleteTaskView = (id: string): Promise<void> => invokeCmd('delete_task_view_cmd', { id });
+export const captureToInbox = (text: string, source: string): Promise<InboxItem> =>
+  invokeCmd('capture_to_inbox_cmd', { text, source });
+export const getInboxItems = (): Promise<InboxItem[]> => invokeCmd('get_inbox_items_cmd');
+export const convertInboxToNote = (itemId: string, spaceId: string, title: string): Promise<Note> =>
+  invokeCmd('convert_inbox_to_note_cmd', { itemId, spaceId, title });
+export const convertInboxToTask = (itemId: string, spaceId: string): Promise<Task> =>
+  invokeCmd('convert_inbox_to_task_cmd', { itemId, spaceId });
+export const dismissInboxItem = (itemId: string): Promise<void> => invokeCmd('dismiss_inbox_item_cmd', { itemId });
+export const defineProperty = (
+  spaceId: string,
+  name: string,
+  propertyType: PropertyType,
+  options: string[] = [],
+): Promise<PropertyDefinition> => invokeCmd('define_property_cmd', { spaceId, name, propertyType, options });
+export const getPropertyDefinitions = (spaceId: string): Promise<PropertyDefinition[]> =>
+  invokeCmd('get_property_definitions_cmd', { spaceId });
+export const deleteProperty = (id: string): Promise<void> => invokeCmd('delete_property_cmd', { id });
+export const setNoteProperty = (noteId: string, propertyId: string, value: PropertyValue): Promise<NoteProperty> =>
+  invokeCmd('set_note_property_cmd', { noteId, propertyId, value });
+export const getNoteProperties = (noteId: string): Promise<NoteProperty[]> =>
+  invokeCmd('get_note_properties_cmd', { noteId });
+export const removeNoteProperty = (noteId: string, propertyId: string): Promise<boolean> =>
+  invokeCmd('remove_note_property_cmd', { noteId, propertyId });
+export const searchNotesByProperties = (
+  query: string,
+  spaceId: string | null,
+  properties: PropertyFilter[],
+): Promise<SearchResult[]> => invokeCmd('search_notes_by_properties_cmd', { query, spaceId, properties });
+/** Sets the property on every note the search finds; resolves to how many */
+export const setPropertyForSearch = (
+  query: string,
+  spaceId: string | null,
+  properties: PropertyFilter[],
+  propertyId: string,
+  value: PropertyValue,
+): Promise<number> => invokeCmd('set_property_for_search_cmd', { query, spaceId, properties, propertyId, value });
+export const createAutomationRule = (
+  spaceId: string,
+  name: string,
+  trigger: AutomationTrigger,
+  actions: AutomationAction[],
+): Promise<AutomationRule> => invokeCmd('create_automation_rule_cmd', { spaceId, name, trigger, actions });
+export const getAutomationRules = (spaceId: string): Promise<AutomationRule[]> =>
+  invokeCmd('get_automation_rules_cmd', { spaceId });
+export const setAutomationRuleEnabled = (id: string, enabled: boolean): Promise<void> =>
+  invokeCmd('set_automation_rule_enabled_cmd', { id, enabled });
+export const deleteAutomationRule = (id: string): Promise<void> => invokeCmd('delete_automation_rule_cmd', { id });
 export const getRecentNotes = (spaceId: string, limit: number): Promise<Note[]> =>
   invokeCmd('get_recent_notes_cmd', { spaceId, limit });
 export const updateTask = (task: Task): Promise<void> => invokeCmd('update_task_cmd', { task });
+export const validateNoteContent = (spaceId: string, content: string): Promise<NoteDiagnostic[]> =>
+  invokeCmd('validate_note_content_cmd', { spaceId, content });
+export const applyAutofixes = (content: string, fixes: Autofix[]): Promise<string> =>
+  invokeCmd('apply_autofixes_cmd', { content, fixes });
+export const getNoteLinkPreviews = (noteId: string): Promise<NoteLinkPreviews> =>
+  invokeCmd('get_note_link_previews_cmd', { noteId });
+export const getNoteStats = (id: string): Promise<NoteStats> => invokeCmd('get_note_stats_cmd', { id });
+/** Encrypt a note's body with its own passphrase; search and the assistant stop seeing it */
+export const lockNote = (id: string, passphrase: string): Promise<void> =>
+  invokeCmd('lock_note_cmd', { id, passphrase });
+/** The body of a locked note, for viewing only; the note stays locked */
+export const unlockNoteContent = (id: string, passphrase: string): Promise<string> =>
+  invokeCmd('unlock_note_content_cmd', { id, passphrase });
+export const permanentlyUnlockNote = (id: string, passphrase: string): Promise<Note> =>
+  invokeCmd('permanently_unlock_note_cmd', { id, passphrase });
+/** Groups of similar notes in a space, most similar first; the threshold defaults to 0.8 */
+export const findDuplicateNotes = (spaceId: string, threshold: number | null = null): Promise<DuplicateCluster[]> =>
+  invokeCmd('find_duplicate_notes_cmd', { spaceId, threshold });
+/** Fold duplicates into the primary note and trash them; undoable */
+export const mergeNotes = (
+  primaryId: string,
+  duplicateIds: string[],
+  strategy: MergeStrategy = 'keep_primary',
+): Promise<NoteMergeSummary> => invokeCmd('merge_notes_cmd', { primaryId, duplicateIds, strategy });
+/** Start editing a note; save drafts with the session instead of updateNoteContent */
+export const beginEditSession = (noteId: string): Promise<EditSession> =>
+  invokeCmd('begin_edit_session_cmd', { noteId });
+/** Saves the body; resolves to a version when one was taken */
+export const saveNoteDraft = (sessionId: string, content: string): Promise<NoteVersion | null> =>
+  invokeCmd('save_note_draft_cmd', { sessionId, content });
+/** Versions drafts saved since the last version */
+export const endEditSession = (sessionId: string): Promise<NoteVersion | null> =>
+  invokeCmd('end_edit_session_cmd', { sessionId });
+export const getNoteVersions = (noteId: string): Promise<NoteVersion[]> =>
+  invokeCmd('get_note_versions_cmd', { noteId });
+/** Move a note, and any `with_notes` of the same space, to another space */
+export const moveNoteToSpace = (
+  noteId: string,
+  targetSpaceId: string,
+  options: MoveNoteOptions | null = null,
+): Promise<MoveReport> => invokeCmd('move_note_to_space_cmd', { noteId, targetSpaceId, options });
+// Bulk edits of a selection run in one transaction. Ids that can't be edited
+// come back as skipped, or reject the whole edit with `bulk.incomplete` when
+// `allOrNothing` is set.
+export const bulkTagNotes = (
+  noteIds: string[],
+  addTags: string[],
+  removeTags: string[],
+  allOrNothing = false,
+): Promise<BulkItemResult[]> => invokeCmd('bulk_tag_notes_cmd', { noteIds, addTags, removeTags, allOrNothing });
+export const bulkMoveNotesToSpace = (
+  noteIds: string[],
+  targetSpaceId: string,
+  tags: TagAction | null = null,
+  timeEntries: TimeEntryAction | null = null,
+  allOrNothing = false,
+): Promise<BulkItemResult[]> =>
+  invokeCmd('bulk_move_notes_to_space_cmd', { noteIds, targetSpaceId, tags, timeEntries, allOrNothing });
+export const bulkTrashNotes = (noteIds: string[], allOrNothing = false): Promise<BulkItemResult[]> =>
+  invokeCmd('bulk_trash_notes_cmd', { noteIds, allOrNothing });
+export const bulkUpdateTasks = (taskIds: string[], patch: TaskPatch, allOrNothing = false): Promise<BulkItemResult[]> =>
+  invokeCmd('bulk_update_tasks_cmd', { taskIds, patch, allOrNothing });
+/** Show a note in another space without copying it */
+export const createNoteReference = (noteId: string, targetSpaceId: string): Promise<NoteReference> =>
+  invokeCmd('create_note_reference_cmd', { noteId, targetSpaceId });
+export const removeNoteReference = (noteId: string, spaceId: string): Promise<void> =>
+  invokeCmd('remove_note_reference_cmd', { noteId, spaceId });
+export const getNoteReferences = (noteId: string): Promise<NoteReference[]> =>
+  invokeCmd('get_note_references_cmd', { noteId });
+/** Fetch preview metadata for a URL, served from the cache while it is fresh */
+export const fetchUrlMetadata = (url: string): Promise<UrlMetadata> => invokeCmd('fetch_url_metadata_cmd', { url });
+/** Create tasks from a meeting note's action items; pass a model to use the local LLM */
+export const extractMeetingActions = (
+  noteId: string,
+  model: string | null = null,
+  baseUrl: string | null = null,
+): Promise<ExtractionReport> => invokeCmd('extract_meeting_actions_cmd', { noteId, model, baseUrl });
 
 // Spaces & Tags
-export const getAllSpaces = (): Promise<Space[]> => invokeCmd('get_all_spaces_cmd');
+export const getAllSpaces = (includeArchived = false): Promise<Space[]> =>
+  invokeCmd('get_all_spaces_cmd', { includeArchived });
+export const archiveSpace = (spaceId: string): Promise<void> => invokeCmd('archive_space_cmd', { spaceId });
+export const unarchiveSpace = (spaceId: string): Promise<void> => invokeCmd('unarchive_space_cmd', { spaceId });
+/** The token the user must type to confirm deleting the space */
+export const getSpaceDeletionToken = (spaceId: string): Promise<string> =>
+  invokeCmd('get_space_deletion_token_cmd', { spaceId });
+/** Permanently deletes the space and everything in it. */
+export const deleteSpace = (spaceId: string, confirmToken: string): Promise<SpaceDeletionSummary> =>
+  invokeCmd('delete_space_cmd', { spaceId, confirmToken });
+/** Built-in templates as [key, template] pairs */
+export const listBuiltinSpaceTemplates = (): Promise<[string, SpaceTemplate][]> =>
+  invokeCmd('list_builtin_space_templates_cmd');
+/** Writes the space's setup to `path`; notes are included only when listed */
+export const exportSpaceTemplate = (
+  spaceId: string,
+  path: string,
+  starterNoteIds: string[] = [],
+): Promise<SpaceTemplate> => invokeCmd('export_space_template_cmd', { spaceId, path, starterNoteIds });
+/** Returns the new space's ID */
+export const createSpaceFromTemplate = (name: string, templatePath: string): Promise<string> =>
+  invokeCmd('create_space_from_template_cmd', { name, templatePath });
+export const createSpaceFromBuiltinTemplate = (name: string, templateKey: string): Promise<string> =>
+  invokeCmd('create_space_from_builtin_template_cmd', { name, templateKey });
 export const checkSpaceExists = async (spaceId: string): Promise<boolean> => {
   const spaces = await getAllSpaces();
   return spaces.some((s) => s.id === spaceId);
 };
 export const getAllTagsInSpace = (spaceId: string): Promise<Tag[]> =>
   invokeCmd('get_all_tags_in_space_cmd', { spaceId });
+export const getTagTree = (spaceId: string): Promise<TagNode[]> => invokeCmd('get_tag_tree_cmd', { spaceId });
+export const getTagUsageCounts = (spaceId: string): Promise<TagUsage[]> =>
+  invokeCmd('get_tag_usage_counts_cmd', { spaceId });
+export const getNotesWithTag = (spaceId: string, tagName: string, includeDescendants: boolean): Promise<Note[]> =>
+  invokeCmd('get_notes_with_tag_cmd', { spaceId, tagName, includeDescendants });
+export const renameTag = (tagId: string, newName: string): Promise<TagRenameReport> =>
+  invokeCmd('rename_tag_cmd', { tagId, newName });
+export const mergeTags = (sourceIds: string[], targetId: string): Promise<Tag> =>
+  invokeCmd('merge_tags_cmd', { sourceIds, targetId });
+/** Resolves to false when the note already had the tag; only a new tag runs automation rules */
+export const addTagToNote = (noteId: string, tagName: string): Promise<boolean> =>
+  invokeCmd('add_tag_to_note_cmd', { noteId, tagName });
+
+// Modes
+export const getSpaceModes = (spaceId: string): Promise<ModeStatus[]> => invokeCmd('get_space_modes_cmd', { spaceId });
+/** Must be shown to the user before calling purgeMode */
+export const previewModePurge = (spaceId: string, modeId: string): Promise<PurgePreview> =>
+  invokeCmd('preview_mode_purge_cmd', { spaceId, modeId });
+export const purgeMode = (preview: PurgePreview): Promise<ModeDataCount[]> => invokeCmd('purge_mode_cmd', { preview });
 
 // Time Tracking
 export const startTimeEntry = (
@@ -157,22 +542,257 @@ export const createManualTimeEntry = (
     started_at: startedAt,
     duration_seconds: durationSeconds,
   });
+export const createTimeEntryFromEvent = (eventId: string, overrides: EventEntryOverrides): Promise<TimeEntry> =>
+  invokeCmd('create_time_entry_from_event_cmd', { eventId, overrides });
+/** Events of `date` (YYYY-MM-DD) with no logged or overlapping time */
+export const suggestTimeEntriesForDay = (spaceId: string, date: string): Promise<CalendarEvent[]> =>
+  invokeCmd('suggest_time_entries_for_day_cmd', { spaceId, date });
+
+// Undo
+export const listUndoableOperations = (limit?: number): Promise<UndoableOperation[]> =>
+  invokeCmd('list_undoable_operations_cmd', { limit: limit ?? null });
+export const undoOperation = (opId: string): Promise<UndoableOperation> => invokeCmd('undo_operation_cmd', { opId });
+
+// Maintenance
+export const runMaintenance = (budgetMs?: number): Promise<MaintenanceRun> =>
+  invokeCmd('run_maintenance_cmd', { budgetMs: budgetMs ?? null });
+export const getMaintenanceHistory = (limit?: number): Promise<MaintenanceHistoryEntry[]> =>
+  invokeCmd('get_maintenance_history_cmd', { limit: limit ?? null });
+
+// Settings
+export const listAllSettings = (): Promise<SettingsEntry[]> => invokeCmd('list_all_settings_cmd');
+export const getSyncSettings = (): Promise<SyncSettings> => invokeCmd('get_sync_settings_cmd');
+export const setSyncSettings = (settings: SyncSettings): Promise<void> =>
+  invokeCmd('set_sync_settings_cmd', { settings });
+export const getOcrSettings = (): Promise<OcrSettings> => invokeCmd('get_ocr_settings_cmd');
+export const setOcrSettings = (settings: OcrSettings): Promise<void> => invokeCmd('set_ocr_settings_cmd', { settings });
+export const getQueryStatsSettings = (): Promise<QueryStatsSettings> => invokeCmd('get_query_stats_settings_cmd');
+export const setQueryStatsSettings = (settings: QueryStatsSettings): Promise<void> =>
+  invokeCmd('set_query_stats_settings_cmd', { settings });
+
+// Query statistics
+export const getQueryStats = (): Promise<QueryStat[]> => invokeCmd('get_query_stats_cmd');
+export const resetQueryStats = (): Promise<void> => invokeCmd('reset_query_stats_cmd');
+
+// Spaced repetition
+export const startReviewSession = (spaceId: string, config?: ReviewSessionConfig): Promise<ReviewSession> =>
+  invokeCmd('start_review_session_cmd', { spaceId, config: config ?? null });
+export const reviewCard = (cardId: string, quality: number, sessionId?: string): Promise<void> =>
+  invokeCmd('review_card_cmd', { cardId, quality, sessionId: sessionId ?? null });
+export const finishReviewSession = (sessionId: string): Promise<ReviewSessionStats> =>
+  invokeCmd('finish_review_session_cmd', { sessionId });
+
+// Calendar
+export const getFreeBusy = (
+  spaceId: string,
+  start: number,
+  end: number,
+  workingHours: WorkingHours | null = null,
+): Promise<FreeBusy> => invokeCmd('get_free_busy_cmd', { spaceId, start, end, workingHours });
+export const suggestFocusBlocks = (
+  spaceId: string,
+  hoursNeeded: number,
+  constraints: FocusConstraints,
+): Promise<TimeRange[]> => invokeCmd('suggest_focus_blocks_cmd', { spaceId, hoursNeeded, constraints });
+export const createCalendarEvent = (
+  spaceId: string,
+  summary: string,
+  start: number,
+  end: number | null,
+  location: string | null,
+  description: string | null,
+  allDay: boolean,
+): Promise<CalendarEvent> =>
+  invokeCmd('create_calendar_event_cmd', { spaceId, summary, start, end, location, description, allDay });
+export const updateCalendarEvent = (event: CalendarEvent): Promise<CalendarEvent> =>
+  invokeCmd('update_calendar_event_cmd', { event });
+export const deleteCalendarEvent = (id: string): Promise<void> => invokeCmd('delete_calendar_event_cmd', { id });
+export const getCalendarEventsInRange = (spaceId: string, start: number, end: number): Promise<CalendarEvent[]> =>
+  invokeCmd('get_calendar_events_in_range_cmd', { spaceId, start, end });
+export const getDueReminders = (): Promise<DueReminder[]> => invokeCmd('get_due_reminders_cmd');
+export const markReminderSent = (eventId: string): Promise<void> => invokeCmd('mark_reminder_sent_cmd', { eventId });
+
+// Health
+export const getMetricTrend = (spaceId: string, metricType: string, start: number, end: number): Promise<MetricTrend> =>
+  invokeCmd('get_metric_trend_cmd', { spaceId, metricType, start, end });
+export const setMetricThreshold = (
+  spaceId: string,
+  metricType: string,
+  min: number | null,
+  max: number | null,
+): Promise<void> => invokeCmd('set_metric_threshold_cmd', { spaceId, metricType, min, max });
+export const setMetricDirection = (spaceId: string, metricType: string, higherIsBetter: boolean): Promise<void> =>
+  invokeCmd('set_metric_direction_cmd', { spaceId, metricType, higherIsBetter });
+export const checkThresholdBreaches = (spaceId: string): Promise<ThresholdBreach[]> =>
+  invokeCmd('check_threshold_breaches_cmd', { spaceId });
+export const importHealthExport = (
+  spaceId: string,
+  path: string,
+  source: HealthExportSource,
+): Promise<HealthImportReport> => invokeCmd('import_health_export_cmd', { spaceId, path, source });
+export const createHealthGoal = (spaceId: string, title: string, target: HealthGoalTarget): Promise<HealthGoal> =>
+  invokeCmd('create_health_goal_cmd', { spaceId, title, target });
+export const getHealthGoals = (spaceId: string): Promise<HealthGoal[]> =>
+  invokeCmd('get_health_goals_cmd', { spaceId });
+export const deleteHealthGoal = (goalId: string): Promise<void> => invokeCmd('delete_health_goal_cmd', { goalId });
+/** Checks every goal of the space against its metric and stores the outcome */
+export const evaluateHealthGoals = (spaceId: string): Promise<HealthGoalEvaluation[]> =>
+  invokeCmd('evaluate_health_goals_cmd', { spaceId });
+
+// Recipes; quantities may be fractions like "1 1/2" and units names like "tablespoons"
+export const addRecipeIngredient = (
+  recipeId: string,
+  name: string,
+  quantity: string,
+  unit: string,
+  note: string | null = null,
+): Promise<RecipeIngredient> => invokeCmd('add_recipe_ingredient_cmd', { recipeId, name, quantity, unit, note });
+export const getRecipeIngredients = (recipeId: string): Promise<RecipeIngredient[]> =>
+  invokeCmd('get_recipe_ingredients_cmd', { recipeId });
+export const setRecipeServings = (recipeId: string, servings: number): Promise<void> =>
+  invokeCmd('set_recipe_servings_cmd', { recipeId, servings });
+export const scaleRecipe = (recipeId: string, targetServings: number): Promise<ScaledRecipe> =>
+  invokeCmd('scale_recipe_cmd', { recipeId, targetServings });
+/** Adds up the same ingredient across the recipes where the units convert */
+export const generateShoppingList = (spaceId: string, recipeIds: string[]): Promise<ShoppingList> =>
+  invokeCmd('generate_shopping_list_cmd', { spaceId, recipeIds });
+export const getShoppingList = (listId: string): Promise<ShoppingList> =>
+  invokeCmd('get_shopping_list_cmd', { listId });
+export const setShoppingItemChecked = (itemId: string, checked: boolean): Promise<void> =>
+  invokeCmd('set_shopping_item_checked_cmd', { itemId, checked });
+export const deleteShoppingList = (listId: string): Promise<void> => invokeCmd('delete_shopping_list_cmd', { listId });
+
+// Trips; an itinerary item outside the trip's dates needs allowOutside
+export const addItineraryItem = (
+  tripId: string,
+  title: string,
+  itemType: string,
+  startsAt: number,
+  location: string | null = null,
+  notes: string | null = null,
+  allowOutside = false,
+): Promise<ItineraryItem> =>
+  invokeCmd('add_itinerary_item_cmd', { tripId, title, itemType, startsAt, location, notes, allowOutside });
+/** Moves an item within its day; returns the day's items in their new order */
+export const reorderItineraryItem = (itemId: string, position: number): Promise<ItineraryItem[]> =>
+  invokeCmd('reorder_itinerary_item_cmd', { itemId, position });
+export const getTripItinerary = (tripId: string): Promise<ItineraryDay[]> =>
+  invokeCmd('get_trip_itinerary_cmd', { tripId });
+export const addPackingItem = (
+  tripId: string,
+  name: string,
+  category: string | null = null,
+  quantity = 1,
+): Promise<PackingItem> => invokeCmd('add_packing_item_cmd', { tripId, name, category, quantity });
+/** Resolves to whether the item is packed now */
+export const togglePacked = (itemId: string): Promise<boolean> => invokeCmd('toggle_packed_cmd', { itemId });
+export const getPackingList = (tripId: string): Promise<PackingItem[]> =>
+  invokeCmd('get_packing_list_cmd', { tripId });
+export const getTripPackingStats = (tripId: string): Promise<PackingStats> =>
+  invokeCmd('get_trip_packing_stats_cmd', { tripId });
+export const savePackingTemplate = (tripId: string, name: string): Promise<PackingTemplate> =>
+  invokeCmd('save_packing_template_cmd', { tripId, name });
+export const getPackingTemplates = (spaceId: string): Promise<PackingTemplate[]> =>
+  invokeCmd('get_packing_templates_cmd', { spaceId });
+export const applyPackingTemplate = (templateId: string, tripId: string): Promise<PackingItem[]> =>
+  invokeCmd('apply_packing_template_cmd', { templateId, tripId });
+export const deletePackingTemplate = (templateId: string): Promise<void> =>
+  invokeCmd('delete_packing_template_cmd', { templateId });
+export const getTripSummary = (tripId: string): Promise<TripSummary> => invokeCmd('get_trip_summary_cmd', { tripId });
+
+// Budgets; months are "YYYY-MM" strings
+export const setBudget = (
+  spaceId: string,
+  category: string,
+  monthlyAmount: number,
+  rollover: boolean,
+  startMonth: string | null = null,
+): Promise<Budget> => invokeCmd('set_budget_cmd', { spaceId, category, monthlyAmount, rollover, startMonth });
+export const getBudgetStatus = (spaceId: string, month: string): Promise<BudgetStatus[]> =>
+  invokeCmd('get_budget_status_cmd', { spaceId, month });
+export const getSpendingSummary = (
+  spaceId: string,
+  range: TimeRange,
+  groupBy: 'category' | 'month',
+): Promise<SpendingSummary[]> => invokeCmd('get_spending_summary_cmd', { spaceId, range, groupBy });
 
 // P2P Sync
 export const startSyncServer = (): Promise<void> => invokeCmd('start_sync_server_cmd');
 export const startP2pSync = (deviceId: string): Promise<void> => invokeCmd('start_p2p_sync_cmd', { deviceId });
+export const getSyncRelay = (): Promise<string | null> => invokeCmd('get_sync_relay_cmd');
+export const setSyncRelay = (relayUrl: string | null): Promise<void> => invokeCmd('set_sync_relay_cmd', { relayUrl });
+export const pollRelaySync = (): Promise<RelayPullReport> => invokeCmd('poll_relay_sync_cmd');
 export const discoverDevices = (): Promise<DiscoveredDevice[]> => invokeCmd('discover_devices_cmd');
-export const initiatePairing = (deviceId: string): Promise<void> => invokeCmd('initiate_pairing_cmd', { deviceId });
+export const addManualPeer = (address: string, port: number, displayName: string): Promise<DiscoveredDevice> =>
+  invokeCmd('add_manual_peer_cmd', { address, port, displayName });
+export const removeManualPeer = (deviceId: string): Promise<boolean> =>
+  invokeCmd('remove_manual_peer_cmd', { deviceId });
+export const getManualPeers = (): Promise<DiscoveredDevice[]> => invokeCmd('get_manual_peers_cmd');
+export const initiatePairing = (deviceId: string): Promise<PairingHello> =>
+  invokeCmd('initiate_pairing_cmd', { deviceId });
 export const getDevices = (): Promise<DeviceInfo[]> => invokeCmd('get_devices_cmd');
 export const getSyncConflicts = (): Promise<SyncConflict[]> => invokeCmd('get_sync_conflicts_cmd');
+export const getSyncConflictsDescribed = (): Promise<ConflictSummary[]> =>
+  invokeCmd('get_sync_conflicts_described_cmd');
 export const resolveSyncConflict = (conflict: SyncConflict, resolution: ConflictResolution): Promise<void> =>
   invokeCmd('resolve_sync_conflict_cmd', { conflict, resolution });
-export const exchangeKeys = (deviceId: string): Promise<void> => invokeCmd('exchange_keys_cmd', { deviceId });
+export const exchangeKeys = (
+  deviceId: string,
+  peerPublicKey?: number[],
+  peerStaticKey?: number[],
+  peerCommitment?: number[],
+): Promise<PairingHello> =>
+  invokeCmd('exchange_keys_cmd', {
+    deviceId,
+    ...(peerPublicKey ? { peerPublicKey } : {}),
+    ...(peerStaticKey ? { peerStaticKey } : {}),
+    ...(peerCommitment ? { peerCommitment } : {}),
+  });
+export const getPendingPairingSas = (deviceId: string): Promise<ShortAuthString | null> =>
+  invokeCmd('get_pending_pairing_sas_cmd', { deviceId });
+export const confirmPairing = (deviceId: string, accepted: boolean): Promise<void> =>
+  invokeCmd('confirm_pairing_cmd', { deviceId, accepted });
 export const getSyncProgress = (deviceId: string): Promise<number> => invokeCmd('get_sync_progress_cmd', { deviceId });
 export const shutdownClearKeys = (): Promise<void> => invokeCmd('shutdown_clear_keys_cmd');
 export const getAllSyncTasks = (spaceId: string): Promise<SyncTask[]> =>
   invokeCmd('get_all_sync_tasks_cmd', { spaceId });
 export const getSyncStats = (spaceId: string): Promise<SyncStats> => invokeCmd('get_sync_stats_cmd', { spaceId });
+/** Bytes and deltas synced per entity type and device; `range` is in Unix seconds. */
+export const getSyncTransferReport = (spaceId: string, range: TimeRange): Promise<SyncTransferReport> =>
+  invokeCmd('get_sync_transfer_report_cmd', { spaceId, range });
+export const getDeviceSyncScope = (deviceId: string): Promise<SyncScope> =>
+  invokeCmd('get_device_sync_scope_cmd', { deviceId });
+export const setDeviceSyncScope = (deviceId: string, scope: SyncScope): Promise<void> =>
+  invokeCmd('set_device_sync_scope_cmd', { deviceId, scope });
+/** True when a sync merge left conflict markers in the note */
+export const getNoteMergeConflict = (noteId: string): Promise<boolean> =>
+  invokeCmd('get_note_merge_conflict_cmd', { noteId });
+export const getDeviceUser = (deviceId: string): Promise<string | null> =>
+  invokeCmd('get_device_user_cmd', { deviceId });
+/** Link a device to the user its deltas to shared spaces are checked against; null unlinks it */
+export const setDeviceUser = (deviceId: string, userId: string | null): Promise<void> =>
+  invokeCmd('set_device_user_cmd', { deviceId, userId });
+export const getRejectedDeltas = (spaceId: string, limit?: number): Promise<RejectedDelta[]> =>
+  invokeCmd('get_rejected_deltas_cmd', { spaceId, limit: limit ?? null });
+
+// Weekly review
+/** Generate this week's review note, or rewrite it when the week has one */
+export const generateWeeklyReview = (spaceId: string): Promise<Note> =>
+  invokeCmd('generate_weekly_review_cmd', { spaceId });
+/** Latest week first */
+export const getWeeklyReviews = (spaceId: string): Promise<WeeklyReview[]> =>
+  invokeCmd('get_weekly_reviews_cmd', { spaceId });
+export const markReviewCompleted = (reviewId: string): Promise<WeeklyReview> =>
+  invokeCmd('mark_review_completed_cmd', { reviewId });
+/** Consecutive weeks with a completed review */
+export const getWeeklyReviewStreak = (spaceId: string): Promise<number> =>
+  invokeCmd('get_weekly_review_streak_cmd', { spaceId });
+
+// Foresight
+export const getInsightSchedule = (spaceId: string): Promise<InsightSchedule> =>
+  invokeCmd('get_insight_schedule_cmd', { spaceId });
+export const setInsightSchedule = (spaceId: string, schedule: InsightSchedule): Promise<void> =>
+  invokeCmd('set_insight_schedule_cmd', { spaceId, schedule });
 
 // Backup
 export const createBackup = (spaceId: string): Promise<BackupMetadata> => invokeCmd('create_backup_cmd', { spaceId });
@@ -181,6 +801,51 @@ export const listBackups = (spaceId: string): Promise<BackupMetadata[]> => invok
 export const getBackupDetails = (backupId: string): Promise<BackupMetadata> =>
   invokeCmd('get_backup_details_cmd', { backupId });
 export const deleteBackup = (backupId: string): Promise<void> => invokeCmd('delete_backup_cmd', { backupId });
+export const getBackupPolicy = (): Promise<BackupPolicy | null> => invokeCmd('get_backup_policy_cmd');
+export const setBackupPolicy = (policy: BackupPolicy): Promise<void> => invokeCmd('set_backup_policy_cmd', { policy });
+/** Recent scheduler attempts, newest first, including skipped ones and why */
+export const getBackupRuns = (limit = 20): Promise<BackupRun[]> => invokeCmd('get_backup_runs_cmd', { limit });
+export const exportNoteBundle = (noteId: string, passphrase: string, path: string): Promise<void> =>
+  invokeCmd('export_note_bundle_cmd', { noteId, passphrase, path });
+export const importNoteBundle = (spaceId: string, path: string, passphrase: string): Promise<Note> =>
+  invokeCmd('import_note_bundle_cmd', { spaceId, path, passphrase });
+
+// Import
+export const importFromObsidian = (spaceId: string, path: string): Promise<ImportReport> =>
+  invokeCmd('import_from_obsidian_cmd', { spaceId, path });
+export const importFromNotion = (spaceId: string, path: string, options?: NotionImportOptions): Promise<ImportReport> =>
+  invokeCmd('import_from_notion_cmd', { spaceId, path, options: options ?? null });
+
+// Vault
+/** Rejects with `vault.already_open` while another window or process has the vault open. */
+export const createVault = (path: string, password: string): Promise<void> =>
+  invokeCmd('create_vault_cmd', { path, password });
+/**
+ * Rejects with `vault.already_open` (holder details in `context`) while the vault is open
+ * elsewhere. `force` replaces a lock another machine left on a shared folder, once it is old enough.
+ */
+export const unlockVault = (path: string, password: string, force?: boolean): Promise<void> =>
+  invokeCmd('unlock_vault_cmd', { path, password, force: force ?? false });
+export const rotateVaultPassword = (path: string, oldPassword: string, newPassword: string): Promise<void> =>
+  invokeCmd('rotate_vault_password_cmd', { path, oldPassword, newPassword });
+/** Re-encrypts the vault under a new key; listen for `vault-rekey-progress` events. Locks the vault. */
+export const rotateVaultDek = (path: string, password: string): Promise<void> =>
+  invokeCmd('rotate_vault_dek_cmd', { path, password });
+/** Wipes the key from memory; a `vault-locked` event follows, as for an idle timeout. */
+export const lockVault = (): Promise<void> => invokeCmd('lock_vault_cmd');
+export const touchVaultActivity = (): Promise<void> => invokeCmd('touch_vault_activity_cmd');
+export const getAutoLockStatus = (): Promise<AutoLockStatus> => invokeCmd('get_auto_lock_status_cmd');
+export const checkVaultIntegrity = (mode?: IntegrityCheckMode): Promise<VaultIntegrityReport> =>
+  invokeCmd('check_vault_integrity_cmd', { mode });
+/** Repairs only the given issues; ones that were already fixed are counted as skipped. */
+export const repairVault = (issues: IntegrityIssue[]): Promise<VaultRepairReport> =>
+  invokeCmd('repair_vault_cmd', { issues });
+export const getStartupIntegrityCheck = (): Promise<boolean> => invokeCmd('get_startup_integrity_check_cmd');
+export const setStartupIntegrityCheck = (enabled: boolean): Promise<void> =>
+  invokeCmd('set_startup_integrity_check_cmd', { enabled });
+export const getStorageReport = (): Promise<StorageReport> => invokeCmd('get_storage_report_cmd');
+/** Fails when another write is in flight; retry later. */
+export const vacuumVault = (): Promise<VacuumResult> => invokeCmd('vacuum_vault_cmd');
 
 // Auth
 export const createUser = (username: string, email: string, password: string): Promise<User> =>
@@ -189,3 +854,36 @@ export const authenticateUser = (username: string, password: string): Promise<Se
   invokeCmd('authenticate_user_cmd', { username, password });
 export const logoutUser = (token: string): Promise<void> => invokeCmd('logout_user_cmd', { token });
 export const getCurrentUser = (token: string): Promise<User> => invokeCmd('get_current_user_cmd', { token });
+export const getAuthAuditLog = (filter?: AuthAuditFilter): Promise<AuthAuditEntry[]> =>
+  invokeCmd('get_auth_audit_log_cmd', { filter: filter ?? {} });
+export const getAuditTrail = (entityId: string): Promise<DataAuditEvent[]> =>
+  invokeCmd('get_audit_trail_cmd', { entityId });
+export const verifyAuditChain = (spaceId: string): Promise<AuditChainReport> =>
+  invokeCmd('verify_audit_chain_cmd', { spaceId });
+export const setSpaceAuditEnabled = (spaceId: string, enabled: boolean): Promise<void> =>
+  invokeCmd('set_space_audit_enabled_cmd', { spaceId, enabled });
+
+// Presence and edit locks, local to this vault: they are not synced to other devices
+/** Call every 30 seconds or so; users drop out after 90 seconds without one */
+export const heartbeatPresence = (
+  userId: string,
+  spaceId: string,
+  entityId: string | null = null,
+): Promise<Presence> => invokeCmd('heartbeat_presence_cmd', { userId, spaceId, entityId });
+export const leavePresence = (userId: string, spaceId: string): Promise<void> =>
+  invokeCmd('leave_presence_cmd', { userId, spaceId });
+export const getActiveUsers = (spaceId: string): Promise<Presence[]> =>
+  invokeCmd('get_active_users_cmd', { spaceId });
+export const getEntityViewers = (entityId: string): Promise<Presence[]> =>
+  invokeCmd('get_entity_viewers_cmd', { entityId });
+/** Also renews a lock the user already holds. Rejects with `collaboration.locked_by_other` naming the holder. */
+export const acquireEditLock = (userId: string, entityId: string, ttlSecs: number | null = null): Promise<EditLock> =>
+  invokeCmd('acquire_edit_lock_cmd', { userId, entityId, ttlSecs });
+/** Resolves to false when the user didn't hold the lock */
+export const releaseEditLock = (userId: string, entityId: string): Promise<boolean> =>
+  invokeCmd('release_edit_lock_cmd', { userId, entityId });
+
+// Core events
+/** Calls `handler` for every sync conflict, OCR result, backup, insight and CalDAV sign-in failure */
+export const onCoreEvent = (handler: (event: EmittedEvent) => void): Promise<UnlistenFn> =>
+  listen<EmittedEvent>('core-event', (event) => handler(event.payload));
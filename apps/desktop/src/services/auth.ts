@@ -6,6 +6,7 @@
 import { invoke } from '@tauri-apps/api/tauri';
 import { Store } from 'tauri-plugin-store-api';
 import { logger } from '@/utils/logger';
+import { toError } from '@/utils/errors';
 import { identityService } from './identity';
 
 // Use Tauri's secure store instead of localStorage
@@ -19,6 +20,17 @@ export interface Session {
   created_at: number;
 }
 
+/** Session metadata shown in the "signed in devices" list (never includes the token) */
+export interface SessionInfo {
+  id: string;
+  user_id: string;
+  device_name?: string;
+  user_agent?: string;
+  created_at: number;
+  last_used_at: number;
+  expires_at: number;
+}
+
 export interface User {
   id: string;
   username: string;
@@ -202,6 +214,61 @@ class AuthService {
     }
   }
 
+  /**
+   * Rotate the session token before it expires; the old token stops working
+   */
+  async refreshSession(): Promise<Session> {
+    try {
+      const token = this.getToken();
+      if (!token) {
+        throw new Error('Not authenticated');
+      }
+
+      const session = await invoke<Session>('refresh_session_cmd', { token });
+      this.session = session;
+      await this.saveSessionToStorage(session);
+      return session;
+    } catch (error) {
+      throw this.handleError(error);
+    }
+  }
+
+  /**
+   * List the current user's active sessions
+   */
+  async listSessions(): Promise<SessionInfo[]> {
+    try {
+      return await invoke<SessionInfo[]>('list_sessions_cmd', { token: this.getToken() });
+    } catch (error) {
+      throw this.handleError(error);
+    }
+  }
+
+  /**
+   * Revoke one of the current user's sessions
+   */
+  async revokeSession(sessionId: string): Promise<void> {
+    try {
+      await invoke('revoke_session_cmd', { token: this.getToken(), sessionId });
+    } catch (error) {
+      throw this.handleError(error);
+    }
+  }
+
+  /**
+   * Sign out all other devices, keeping this session
+   */
+  async revokeOtherSessions(): Promise<number> {
+    try {
+      return await invoke<number>('revoke_all_sessions_cmd', {
+        token: this.getToken(),
+        keepSessionId: this.session?.id,
+      });
+    } catch (error) {
+      throw this.handleError(error);
+    }
+  }
+
   // Private helper methods
 
   private async loadSessionFromStorage(): Promise<void> {
@@ -239,13 +306,7 @@ class AuthService {
   }
 
   private handleError(error: unknown): Error {
-    if (error instanceof Error) {
-      return error;
-    }
-    if (typeof error === 'string') {
-      return new Error(error);
-    }
-    return new Error('An unknown error occurred');
+    return toError(error);
   }
 }
 
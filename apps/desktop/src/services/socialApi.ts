@@ -10,8 +10,28 @@ import type {
   SocialPost,
   TimelinePost,
   TimelineFilters,
+  TimelineCursor,
+  TimelinePage,
   SocialCategory,
   TimelineStats,
+  CategoryRule,
+  RuleCondition,
+  RuleMatchMode,
+  RulePlanEntry,
+  RuleRunResult,
+  PlatformAccess,
+  PlatformUsage,
+  FiredAutomation,
+  TimeRange,
+  TimeBucket,
+  EngagementOptions,
+  TimeSeriesPoint,
+  PostingTimeAnalysis,
+  TopicClusterOptions,
+  TopicClusteringResult,
+  TopicOverview,
+  ArchiveOptions,
+  Note,
 } from '@noteece/types';
 
 /**
@@ -73,10 +93,21 @@ export async function deleteSocialAccount(accountId: string): Promise<void> {
   return await invoke('delete_social_account_cmd', { accountId });
 }
 
+/**
+ * Counts reported by storeSocialPosts
+ */
+export interface StorePostsResult {
+  inserted: number;
+  updated: number;
+  skipped: number;
+}
+
 /**
  * Store social posts (bulk insert from extractors)
+ *
+ * Posts already in the space are updated in place instead of duplicated.
  */
-export async function storeSocialPosts(accountId: string, posts: SocialPost[]): Promise<number> {
+export async function storeSocialPosts(accountId: string, posts: SocialPost[]): Promise<StorePostsResult> {
   // Prevent overwhelming IPC/native layer with oversized batches
   const MAX_POSTS = 1000;
   const MAX_BYTES = 8 * 1024 * 1024; // 8 MB
@@ -98,6 +129,75 @@ export async function storeSocialPosts(accountId: string, posts: SocialPost[]):
   return await invoke('store_social_posts_cmd', { accountId, posts });
 }
 
+/**
+ * What one chunk of an extraction session contributed
+ */
+export interface ChunkOutcome {
+  chunk: number;
+  posts_parsed: number;
+  /** Set when the chunk was malformed; the session carries on */
+  error: string | null;
+  buffered: number;
+}
+
+/**
+ * Totals of a finished extraction session
+ */
+export interface ExtractionSummary {
+  session_id: string;
+  account_id: string;
+  chunks: number;
+  failed_chunks: { chunk: number; error: string }[];
+  posts_parsed: number;
+  flushes: number;
+  peak_buffered: number;
+  stored: StorePostsResult;
+}
+
+/**
+ * Start a chunked extraction for payloads too large for storeSocialPosts; returns the session id
+ *
+ * Sessions that receive no chunk for ten minutes are dropped.
+ */
+export async function beginExtractionSession(accountId: string): Promise<string> {
+  return await invoke('begin_extraction_session_cmd', { accountId });
+}
+
+/**
+ * Send one chunk of an extraction: a JSON array of posts or one post per line
+ */
+export async function ingestExtractionChunk(sessionId: string, payload: string): Promise<ChunkOutcome> {
+  return await invoke('ingest_extraction_chunk_cmd', { sessionId, payload });
+}
+
+/**
+ * Store what the session still buffers and close it
+ */
+export async function finishExtractionSession(sessionId: string): Promise<ExtractionSummary> {
+  return await invoke('finish_extraction_session_cmd', { sessionId });
+}
+
+/**
+ * Merge posts that were stored more than once in a space; returns how many were removed
+ */
+export async function dedupSocialPosts(spaceId: string): Promise<number> {
+  return await invoke('dedup_social_posts_cmd', { spaceId });
+}
+
+/**
+ * Archive a post as a note in the space; archiving it again refreshes the same note
+ */
+export async function archivePostToNote(postId: string, spaceId: string, options?: ArchiveOptions): Promise<Note> {
+  return await invoke('archive_post_to_note_cmd', { postId, spaceId, options });
+}
+
+/**
+ * Archive every post in a category to notes in the category's space
+ */
+export async function archiveCategoryToNotes(categoryId: string, options?: ArchiveOptions): Promise<Note[]> {
+  return await invoke('archive_category_to_notes_cmd', { categoryId, options });
+}
+
 /**
  * Get unified timeline across all platforms
  */
@@ -108,6 +208,31 @@ export async function getUnifiedTimeline(spaceId: string, filters: TimelineFilte
   });
 }
 
+/**
+ * Get one page of the unified timeline; pass next_cursor back in the filters to continue
+ */
+export async function getUnifiedTimelinePage(
+  spaceId: string,
+  filters: TimelineFilters = {},
+  cursor: TimelineCursor | null = null,
+): Promise<TimelinePage> {
+  return await invoke('get_unified_timeline_page_cmd', {
+    spaceId,
+    filters: cursor ? { ...filters, ...cursor } : filters,
+  });
+}
+
+/**
+ * Count posts that arrived after `since` (epoch ms), honouring the timeline filters
+ */
+export async function getTimelineNewCount(
+  spaceId: string,
+  since: number,
+  filters: TimelineFilters = {},
+): Promise<number> {
+  return await invoke('get_timeline_new_count_cmd', { spaceId, since, filters });
+}
+
 /**
  * Create a new category
  */
@@ -156,9 +281,201 @@ export async function deleteSocialCategory(categoryId: string): Promise<void> {
   return await invoke('delete_social_category_cmd', { categoryId });
 }
 
+/**
+ * Create a compound auto-categorization rule
+ */
+export async function createCategoryRule(
+  spaceId: string,
+  categoryId: string,
+  name: string,
+  condition: RuleCondition,
+  priority = 0,
+): Promise<CategoryRule> {
+  return await invoke('create_category_rule_cmd', { spaceId, categoryId, name, condition, priority });
+}
+
+/**
+ * Get the compound rules of a space in evaluation order
+ */
+export async function getCategoryRules(spaceId: string): Promise<CategoryRule[]> {
+  return await invoke('get_category_rules_cmd', { spaceId });
+}
+
+/**
+ * Enable or disable a rule
+ */
+export async function setCategoryRuleEnabled(ruleId: string, enabled: boolean): Promise<void> {
+  return await invoke('set_category_rule_enabled_cmd', { ruleId, enabled });
+}
+
+/**
+ * Delete a rule
+ */
+export async function deleteCategoryRule(ruleId: string): Promise<void> {
+  return await invoke('delete_category_rule_cmd', { ruleId });
+}
+
+/**
+ * Preview which posts a rule run would recategorize, without writing anything
+ */
+export async function dryRunCategoryRules(
+  spaceId: string,
+  mode: RuleMatchMode = 'first_match',
+): Promise<RulePlanEntry[]> {
+  return await invoke('dry_run_category_rules_cmd', { spaceId, mode });
+}
+
+/**
+ * Apply all enabled rules of a space
+ */
+export async function applyCategoryRules(
+  spaceId: string,
+  mode: RuleMatchMode = 'first_match',
+): Promise<RuleRunResult> {
+  return await invoke('apply_category_rules_cmd', { spaceId, mode });
+}
+
+/**
+ * Export a space's rules as JSON
+ */
+export async function exportCategoryRules(spaceId: string): Promise<string> {
+  return await invoke('export_category_rules_cmd', { spaceId });
+}
+
+/**
+ * Import rules exported from another vault; returns the number imported
+ */
+export async function importCategoryRules(spaceId: string, json: string): Promise<number> {
+  return await invoke('import_category_rules_cmd', { spaceId, json });
+}
+
 /**
  * Get timeline statistics
  */
 export async function getTimelineStats(spaceId: string): Promise<TimelineStats> {
   return await invoke('get_timeline_stats_cmd', { spaceId });
 }
+
+/**
+ * Discover topics among the posts in range with a local Ollama embedding model
+ */
+export async function clusterTimelineTopics(
+  spaceId: string,
+  range: TimeRange,
+  model: string,
+  options?: Partial<TopicClusterOptions>,
+  baseUrl?: string,
+): Promise<TopicClusteringResult> {
+  return await invoke('cluster_timeline_topics_cmd', { spaceId, range, model, baseUrl, options });
+}
+
+/**
+ * Get discovered topics with their sizes and average engagement
+ */
+export async function getTopicOverview(spaceId: string): Promise<TopicOverview[]> {
+  return await invoke('get_topic_overview_cmd', { spaceId });
+}
+
+/**
+ * Get posts and engagement per day or week, with empty buckets included
+ */
+export async function getEngagementTimeseries(
+  spaceId: string,
+  platform: string | null,
+  range: TimeRange,
+  bucket: TimeBucket,
+  options?: EngagementOptions,
+): Promise<TimeSeriesPoint[]> {
+  return await invoke('get_engagement_timeseries_cmd', { spaceId, platform, range, bucket, options });
+}
+
+/**
+ * Get average engagement per weekday and hour of posting
+ */
+export async function getPostingTimeAnalysis(
+  spaceId: string,
+  platform: string | null,
+  range: TimeRange,
+  options?: EngagementOptions,
+): Promise<PostingTimeAnalysis> {
+  return await invoke('get_posting_time_analysis_cmd', { spaceId, platform, range, options });
+}
+
+/**
+ * Record time spent on a platform; returns the usage automations that fired
+ */
+export async function recordPlatformUsage(
+  spaceId: string,
+  platform: string,
+  seconds: number,
+): Promise<FiredAutomation[]> {
+  return await invoke('record_platform_usage_cmd', { spaceId, platform, seconds });
+}
+
+/**
+ * Get today's usage per platform
+ */
+export async function getPlatformUsageToday(spaceId: string): Promise<PlatformUsage[]> {
+  return await invoke('get_platform_usage_today_cmd', { spaceId });
+}
+
+/**
+ * Check whether a platform is blocked by focus mode or out of its daily budget
+ */
+export async function checkPlatformAccess(spaceId: string, platform: string): Promise<PlatformAccess> {
+  return await invoke('check_platform_access_cmd', { spaceId, platform });
+}
+
+/**
+ * Set the UTC offset whose midnight resets daily usage budgets
+ */
+export async function setUsageTimezone(spaceId: string, utcOffsetMinutes: number): Promise<void> {
+  return await invoke('set_usage_timezone_cmd', { spaceId, utcOffsetMinutes });
+}
+
+/**
+ * Selectors an extractor uses for a platform
+ */
+export interface ActiveSelectors {
+  platform: string;
+  version: string;
+  source: 'bundled' | 'update';
+  selectors: Record<string, string>;
+}
+
+/**
+ * A selector update that was verified and installed
+ */
+export interface SelectorUpdate {
+  version: string;
+  previous_version: string;
+  platforms: string[];
+}
+
+/**
+ * Download a signed selector bundle over HTTPS and switch extractors to it if it is newer
+ */
+export async function fetchSelectorUpdate(url: string, currentVersion: string): Promise<SelectorUpdate> {
+  return await invoke('fetch_selector_update_cmd', { url, currentVersion });
+}
+
+/**
+ * Get the selectors in use for a platform; null if no bundle covers it
+ */
+export async function getActiveSelectors(platform: string): Promise<ActiveSelectors | null> {
+  return await invoke('get_active_selectors_cmd', { platform });
+}
+
+/**
+ * Send a platform back to the bundled selectors; returns false if it wasn't using an update
+ */
+export async function rollbackSelectors(platform: string): Promise<boolean> {
+  return await invoke('rollback_selectors_cmd', { platform });
+}
+
+/**
+ * Report an extraction outcome; returns true if a failure spike rolled the platform back
+ */
+export async function recordExtractionResult(platform: string, succeeded: boolean): Promise<boolean> {
+  return await invoke('record_extraction_result_cmd', { platform, succeeded });
+}
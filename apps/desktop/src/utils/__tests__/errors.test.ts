@@ -0,0 +1,25 @@
+import { CommandError, hasErrorCode, toError } from '../errors';
+
+describe('errors', () => {
+  const locked = {
+    code: 'vault.locked',
+    category: 'vault_locked' as const,
+    message: 'Vault is locked; unlock it to continue',
+    context: {},
+  };
+
+  it('wraps backend errors and keeps their code', () => {
+    const error = toError(locked);
+    expect(error).toBeInstanceOf(CommandError);
+    expect((error as CommandError).category).toBe('vault_locked');
+    expect(String(error)).toBe('Vault is locked; unlock it to continue');
+    expect(hasErrorCode(error, 'vault.locked')).toBe(true);
+    expect(hasErrorCode(error, 'note.not_found')).toBe(false);
+  });
+
+  it('still accepts plain string errors', () => {
+    const error = toError('Failed to lock DEK');
+    expect(error.message).toBe('Failed to lock DEK');
+    expect(hasErrorCode(error, 'vault.locked')).toBe(false);
+  });
+});
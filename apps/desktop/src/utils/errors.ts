@@ -0,0 +1,41 @@
+import type { CoreError, ErrorCategory } from '@noteece/types';
+
+/**
+ * A backend `CoreError` as a thrown `Error`, so `String(error)` and
+ * `error.message` keep working while callers can branch on `code`
+ */
+export class CommandError extends Error {
+  readonly code: string;
+  readonly category: ErrorCategory;
+  readonly context: Record<string, string>;
+
+  constructor(error: CoreError) {
+    super(error.message);
+    this.name = 'CommandError';
+    this.code = error.code;
+    this.category = error.category;
+    this.context = error.context ?? {};
+  }
+
+  toString(): string {
+    return this.message;
+  }
+}
+
+export const isCoreError = (value: unknown): value is CoreError =>
+  typeof value === 'object' &&
+  value !== null &&
+  typeof (value as CoreError).code === 'string' &&
+  typeof (value as CoreError).category === 'string' &&
+  typeof (value as CoreError).message === 'string';
+
+/** Commands not yet migrated still reject with plain strings */
+export const toError = (error: unknown): Error => {
+  if (error instanceof Error) return error;
+  if (isCoreError(error)) return new CommandError(error);
+  if (typeof error === 'string') return new Error(error);
+  return new Error('An unknown error occurred');
+};
+
+export const hasErrorCode = (error: unknown, code: string): boolean =>
+  (error instanceof CommandError || isCoreError(error)) && error.code === code;
@@ -38,6 +38,7 @@ interface HandshakeMessage {
   type: string;
   publicKey?: string;
   message?: string;
+  reason?: string;
 }
 
 interface ManifestResponseMessage {
@@ -240,6 +241,12 @@ export class SyncClient {
               } else if (data.type === 'error') {
                 ws.removeEventListener('message', handshakeHandler);
                 reject(new Error(`Handshake error: ${data.message}`));
+              } else if (data.type === 'handshake_rejected') {
+                // Desktop peers on protocol 2 only accept the encrypted transport
+                ws.removeEventListener('message', handshakeHandler);
+                Logger.warn('[Sync] Handshake rejected by peer:', data.reason);
+                ws.close();
+                reject(new Error(`Handshake rejected: ${data.reason ?? 'unknown reason'}`));
               }
             } catch (err) {
               Logger.error('[Sync] Handshake handler error:', err);
@@ -0,0 +1,200 @@
+/* Generated by cbindgen from packages/core-rs/src/c_api. Do not edit. */
+
+#ifndef NOTEECE_CORE_H
+#define NOTEECE_CORE_H
+
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+#define NOTEECE_OK 0
+
+// A required pointer argument was null
+#define NOTEECE_NULL_ARGUMENT 1
+
+// A string argument was not valid UTF-8
+#define NOTEECE_INVALID_UTF8 2
+
+// The handle is unknown or was closed
+#define NOTEECE_INVALID_HANDLE 3
+
+// The vault is locked; unlock it again for a new handle
+#define NOTEECE_LOCKED 4
+
+#define NOTEECE_NOT_FOUND 5
+
+// An id or JSON argument could not be parsed
+#define NOTEECE_INVALID_ARGUMENT 6
+
+// Creating or unlocking the vault failed, e.g. a wrong password
+#define NOTEECE_VAULT_ERROR 7
+
+#define NOTEECE_DATABASE_ERROR 8
+
+#define NOTEECE_SYNC_ERROR 9
+
+// Rust panicked; the call had no effect the caller can rely on
+#define NOTEECE_PANIC 10
+
+// The pointer was not handed out by this library, or was already freed
+#define NOTEECE_INVALID_POINTER 11
+
+// Another process, or another handle in this one, has the vault open
+#define NOTEECE_VAULT_IN_USE 12
+
+// Outcome of every API call. Both strings are null when absent.
+typedef struct NoteeceResult {
+  int32_t code;
+  // Why the call failed
+  char *message;
+  // What the call returned
+  char *value;
+} NoteeceResult;
+
+// Opaque id of an open vault; 0 is never a valid handle
+typedef uint64_t NoteeceHandle;
+
+#ifdef __cplusplus
+extern "C" {
+#endif // __cplusplus
+
+// Free a string returned by this library. Null is ignored; a pointer that
+// isn't live returns [`NOTEECE_INVALID_POINTER`] and is left alone.
+//
+// # Safety
+// `ptr` must be null or a pointer previously returned by this library
+int32_t noteece_string_free(char *ptr);
+
+// Free both strings of a result. Returns the first failure, if any.
+//
+// # Safety
+// As for [`noteece_string_free`], for both strings
+int32_t noteece_result_free(struct NoteeceResult result);
+
+// Create a vault in the directory `path` and open it
+//
+// # Safety
+// `path` and `password` must be NUL-terminated strings and `out_handle`
+// must point to writable memory
+struct NoteeceResult noteece_vault_create(const char *path,
+                                          const char *password,
+                                          NoteeceHandle *out_handle);
+
+// Unlock the vault in the directory `path`
+//
+// # Safety
+// As for [`noteece_vault_create`]
+struct NoteeceResult noteece_vault_unlock(const char *path,
+                                          const char *password,
+                                          NoteeceHandle *out_handle);
+
+// Wipe the vault key. The handle stays valid but every call on it fails
+// with [`NOTEECE_LOCKED`] until it is closed.
+struct NoteeceResult noteece_vault_lock(NoteeceHandle handle);
+
+// Lock the vault and release the handle. Closing twice fails with
+// [`NOTEECE_INVALID_HANDLE`].
+struct NoteeceResult noteece_vault_close(NoteeceHandle handle);
+
+// Create a space; `value` is its id
+//
+// # Safety
+// `name` must be a NUL-terminated string
+struct NoteeceResult noteece_space_create(NoteeceHandle handle, const char *name);
+
+// Create a note; `value` is the note as JSON
+//
+// # Safety
+// All string arguments must be NUL-terminated strings
+struct NoteeceResult noteece_note_create(NoteeceHandle handle,
+                                         const char *space_id,
+                                         const char *title,
+                                         const char *content_md);
+
+// Get a note as JSON; fails with [`NOTEECE_NOT_FOUND`] if there is none
+//
+// # Safety
+// `note_id` must be a NUL-terminated string
+struct NoteeceResult noteece_note_get(NoteeceHandle handle, const char *note_id);
+
+// Replace the title and content of a note
+//
+// # Safety
+// All string arguments must be NUL-terminated strings
+struct NoteeceResult noteece_note_update(NoteeceHandle handle,
+                                         const char *note_id,
+                                         const char *title,
+                                         const char *content_md);
+
+// Full-text search of the notes in a space; `value` is a JSON array
+//
+// # Safety
+// Both string arguments must be NUL-terminated strings
+struct NoteeceResult noteece_note_search(NoteeceHandle handle,
+                                         const char *space_id,
+                                         const char *query);
+
+// This device's manifest for a space, as JSON
+//
+// # Safety
+// `space_id` must be a NUL-terminated string
+struct NoteeceResult noteece_sync_manifest(NoteeceHandle handle, const char *space_id);
+
+// Changes in a space since `since` (unix seconds), as a JSON array of deltas
+//
+// # Safety
+// `space_id` must be a NUL-terminated string
+struct NoteeceResult noteece_sync_deltas_since(NoteeceHandle handle,
+                                               const char *space_id,
+                                               int64_t since);
+
+// Apply a JSON array of deltas received from `source_device_id`. `value` is
+// a JSON array of the conflicts that need resolving.
+//
+// # Safety
+// Both string arguments must be NUL-terminated strings
+struct NoteeceResult noteece_sync_apply_deltas(NoteeceHandle handle,
+                                               const char *source_device_id,
+                                               const char *deltas_json);
+
+// Create a task; `description` may be null. `value` is the task as JSON.
+//
+// # Safety
+// `space_id` and `title` must be NUL-terminated strings, `description` null
+// or one
+struct NoteeceResult noteece_task_create(NoteeceHandle handle,
+                                         const char *space_id,
+                                         const char *title,
+                                         const char *description);
+
+// Get a task as JSON; fails with [`NOTEECE_NOT_FOUND`] if there is none
+//
+// # Safety
+// `task_id` must be a NUL-terminated string
+struct NoteeceResult noteece_task_get(NoteeceHandle handle, const char *task_id);
+
+// Save a task given as JSON, in the shape `noteece_task_get` returns it
+//
+// # Safety
+// `task_json` must be a NUL-terminated string
+struct NoteeceResult noteece_task_update(NoteeceHandle handle, const char *task_json);
+
+// Delete a task
+//
+// # Safety
+// `task_id` must be a NUL-terminated string
+struct NoteeceResult noteece_task_delete(NoteeceHandle handle, const char *task_id);
+
+// Every task in a space; `value` is a JSON array
+//
+// # Safety
+// `space_id` must be a NUL-terminated string
+struct NoteeceResult noteece_task_list(NoteeceHandle handle, const char *space_id);
+
+#ifdef __cplusplus
+}  // extern "C"
+#endif  // __cplusplus
+
+#endif  /* NOTEECE_CORE_H */
@@ -0,0 +1,80 @@
+//! Embedding Backends
+//!
+//! Implementations of [`Embedder`] that run on this machine, so vault text
+//! never leaves it. The RAG index and social topic clustering take any
+//! `Embedder`, which keeps them usable with a bundled model as well.
+//!
+//! SPDX-License-Identifier: AGPL-3.0-or-later
+//! Copyright (c) 2024-2025 Amirreza 'Farnam' Taheri <taherifarnam@gmail.com>
+
+use super::rag::{Embedder, RagError};
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+/// Embeds text with a model served by a local Ollama instance
+pub struct OllamaEmbedder {
+    base_url: String,
+    model: String,
+    version: String,
+    client: reqwest::blocking::Client,
+}
+
+#[derive(Serialize)]
+struct EmbeddingRequest<'a> {
+    model: &'a str,
+    prompt: &'a str,
+}
+
+#[derive(Deserialize)]
+struct EmbeddingResponse {
+    embedding: Vec<f32>,
+}
+
+impl OllamaEmbedder {
+    pub fn new(base_url: &str, model: &str) -> Result<Self, RagError> {
+        let client = reqwest::blocking::Client::builder()
+            .timeout(Duration::from_secs(60))
+            .build()
+            .map_err(|e| RagError::EmbeddingFailed(format!("HTTP client: {}", e)))?;
+        Ok(Self {
+            base_url: base_url.trim_end_matches('/').to_string(),
+            model: model.to_string(),
+            version: format!("ollama:{}", model),
+            client,
+        })
+    }
+}
+
+impl Embedder for OllamaEmbedder {
+    fn embed(&self, text: &str) -> Result<Vec<f32>, RagError> {
+        let response = self
+            .client
+            .post(format!("{}/api/embeddings", self.base_url))
+            .json(&EmbeddingRequest {
+                model: &self.model,
+                prompt: text,
+            })
+            .send()
+            .map_err(|e| RagError::EmbeddingFailed(format!("Ollama request failed: {}", e)))?;
+        if !response.status().is_success() {
+            return Err(RagError::EmbeddingFailed(format!(
+                "Ollama returned {}",
+                response.status()
+            )));
+        }
+        let body: EmbeddingResponse = response
+            .json()
+            .map_err(|e| RagError::EmbeddingFailed(format!("Bad Ollama response: {}", e)))?;
+        if body.embedding.is_empty() {
+            return Err(RagError::EmbeddingFailed(format!(
+                "{} returned an empty embedding",
+                self.model
+            )));
+        }
+        Ok(body.embedding)
+    }
+
+    fn version(&self) -> &str {
+        &self.version
+    }
+}
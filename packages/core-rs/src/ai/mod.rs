@@ -4,12 +4,16 @@
 //! - RAG (Retrieval-Augmented Generation) for "Chat with your Vault"
 //! - Document analysis and summarization
 //! - Smart suggestions and completions
+//! - Local embedding backends shared by RAG and social topic clustering
 //!
 //! SPDX-License-Identifier: AGPL-3.0-or-later
 //! Copyright (c) 2024-2025 Amirreza 'Farnam' Taheri <taherifarnam@gmail.com>
 
+pub mod embedder;
 pub mod rag;
 
+pub use embedder::OllamaEmbedder;
 pub use rag::{
-    DocumentChunk, RagConfig, RagError, RagPipeline, RagQuery, RagResponse, RagStats, SearchResult,
+    mark_note_dirty, DocumentChunk, Embedder, RagConfig, RagError, RagPipeline, RagQuery,
+    RagRefreshResult, RagResponse, RagStats, SearchResult,
 };
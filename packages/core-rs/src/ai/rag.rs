@@ -9,9 +9,15 @@
 //! Copyright (c) 2024-2025 Amirreza 'Farnam' Taheri <taherifarnam@gmail.com>
 
 use crate::db::DbPool;
+use crate::llm::cache::{
+    invalidate_namespace, invalidate_namespaces, note_namespace, CacheContext, ResponseCache,
+};
+use crate::llm::streaming::StreamSink;
+use crate::llm::types::LLMResponse;
 use crate::llm::{LLMProvider, LLMRequest};
-use rusqlite::params;
+use rusqlite::{params, Connection, OptionalExtension};
 use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
 use std::collections::HashMap;
 use thiserror::Error;
 
@@ -115,11 +121,95 @@ impl Default for RagConfig {
     }
 }
 
+/// Cache request type of RAG answers
+pub const RAG_ANSWER_REQUEST_TYPE: &str = "rag_answer";
+
+/// Flag a note for re-indexing on the next `RagPipeline::refresh_dirty`
+/// and drop the cached LLM responses derived from it
+///
+/// Called whenever a note's content changes, it is trashed/restored or it
+/// is locked/unlocked.
+pub fn mark_note_dirty(conn: &Connection, note_id: &str) -> Result<(), rusqlite::Error> {
+    conn.execute(
+        "INSERT INTO rag_dirty_note (note_id, generation, marked_at) VALUES (?1, 1, ?2)
+         ON CONFLICT(note_id) DO UPDATE SET
+             generation = generation + 1,
+             marked_at = excluded.marked_at",
+        params![note_id, chrono::Utc::now().timestamp()],
+    )?;
+    invalidate_namespace(conn, &note_namespace(note_id))?;
+    Ok(())
+}
+
+/// [`mark_note_dirty`] for several notes, two statements in all. Callers
+/// keep `note_ids` short enough for SQLite's parameter limit.
+pub fn mark_notes_dirty(conn: &Connection, note_ids: &[String]) -> Result<(), rusqlite::Error> {
+    if note_ids.is_empty() {
+        return Ok(());
+    }
+    let mut params: Vec<rusqlite::types::Value> = vec![chrono::Utc::now().timestamp().into()];
+    params.extend(note_ids.iter().cloned().map(Into::into));
+    conn.execute(
+        &format!(
+            "INSERT INTO rag_dirty_note (note_id, generation, marked_at) VALUES {}
+             ON CONFLICT(note_id) DO UPDATE SET
+                 generation = generation + 1,
+                 marked_at = excluded.marked_at",
+            (2..note_ids.len() + 2)
+                .map(|i| format!("(?{}, 1, ?1)", i))
+                .collect::<Vec<_>>()
+                .join(", ")
+        ),
+        rusqlite::params_from_iter(params),
+    )?;
+    let namespaces: Vec<String> = note_ids.iter().map(|id| note_namespace(id)).collect();
+    invalidate_namespaces(conn, &namespaces)?;
+    Ok(())
+}
+
+/// SHA-256 of a chunk's text, used to reuse embeddings of unchanged chunks
+fn chunk_hash(content: &str) -> String {
+    hex::encode(Sha256::digest(content.as_bytes()))
+}
+
+pub(crate) fn encode_embedding(vector: &[f32]) -> Vec<u8> {
+    vector.iter().flat_map(|v| v.to_le_bytes()).collect()
+}
+
+/// Inverse of [`encode_embedding`]; trailing bytes of a truncated blob are dropped
+pub(crate) fn decode_embedding(bytes: &[u8]) -> Vec<f32> {
+    bytes
+        .chunks_exact(4)
+        .map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+        .collect()
+}
+
+/// Generates embedding vectors for chunk text
+pub trait Embedder: Send + Sync {
+    /// Embed a single chunk
+    fn embed(&self, text: &str) -> Result<Vec<f32>, RagError>;
+
+    /// Identifies the model producing the vectors; chunks embedded under a
+    /// different version are re-embedded on their next refresh
+    fn version(&self) -> &str;
+}
+
+/// Outcome of an incremental index refresh
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct RagRefreshResult {
+    pub notes_refreshed: usize,
+    pub notes_removed: usize,
+    pub chunks_embedded: usize,
+    pub chunks_reused: usize,
+}
+
 /// RAG Pipeline for vault search and question answering
 pub struct RagPipeline {
     config: RagConfig,
     db_pool: DbPool,
     llm_provider: Option<Box<dyn LLMProvider>>,
+    embedder: Option<Box<dyn Embedder>>,
+    response_cache: Option<ResponseCache>,
 }
 
 impl RagPipeline {
@@ -129,6 +219,8 @@ impl RagPipeline {
             config,
             db_pool,
             llm_provider: None,
+            embedder: None,
+            response_cache: None,
         }
     }
 
@@ -138,60 +230,21 @@ impl RagPipeline {
         self
     }
 
-    /// Initialize the vector store tables
-    pub fn initialize_vector_store(&self) -> Result<(), RagError> {
-        let conn = self.db_pool.get()?;
-
-        // Create embeddings table
-        conn.execute(
-            r#"
-            CREATE TABLE IF NOT EXISTS note_embeddings (
-                id TEXT PRIMARY KEY,
-                note_id TEXT NOT NULL,
-                chunk_index INTEGER NOT NULL,
-                content TEXT NOT NULL,
-                embedding BLOB,
-                start_offset INTEGER,
-                end_offset INTEGER,
-                metadata TEXT,
-                created_at INTEGER DEFAULT (strftime('%s', 'now')),
-                FOREIGN KEY (note_id) REFERENCES note(id) ON DELETE CASCADE,
-                UNIQUE (note_id, chunk_index)
-            )
-            "#,
-            [],
-        )?;
-
-        // Create FTS5 table for hybrid search
-        conn.execute(
-            r#"
-            CREATE VIRTUAL TABLE IF NOT EXISTS note_embeddings_fts USING fts5(
-                content,
-                content=note_embeddings,
-                content_rowid=rowid
-            )
-            "#,
-            [],
-        )?;
+    /// Cache answers in the vault; an answer is dropped when one of its
+    /// source notes changes
+    pub fn with_response_cache(mut self, cache: ResponseCache) -> Self {
+        self.response_cache = Some(cache);
+        self
+    }
 
-        // Create triggers to keep FTS in sync
-        conn.execute_batch(
-            r#"
-            CREATE TRIGGER IF NOT EXISTS note_embeddings_ai AFTER INSERT ON note_embeddings BEGIN
-                INSERT INTO note_embeddings_fts(rowid, content) VALUES (new.rowid, new.content);
-            END;
-            CREATE TRIGGER IF NOT EXISTS note_embeddings_ad AFTER DELETE ON note_embeddings BEGIN
-                INSERT INTO note_embeddings_fts(note_embeddings_fts, rowid, content) 
-                VALUES ('delete', old.rowid, old.content);
-            END;
-            CREATE TRIGGER IF NOT EXISTS note_embeddings_au AFTER UPDATE ON note_embeddings BEGIN
-                INSERT INTO note_embeddings_fts(note_embeddings_fts, rowid, content) 
-                VALUES ('delete', old.rowid, old.content);
-                INSERT INTO note_embeddings_fts(rowid, content) VALUES (new.rowid, new.content);
-            END;
-            "#,
-        )?;
+    /// Set the embedder used when indexing chunks
+    pub fn with_embedder(mut self, embedder: Box<dyn Embedder>) -> Self {
+        self.embedder = Some(embedder);
+        self
+    }
 
+    /// Implemented via migration v84, keeping stub for existing callers
+    pub fn initialize_vector_store(&self) -> Result<(), RagError> {
         Ok(())
     }
 
@@ -276,39 +329,163 @@ impl RagPipeline {
         title: &str,
         content: &str,
     ) -> Result<usize, RagError> {
+        let mut conn = self.db_pool.get()?;
+        let (chunks, _, _) = self.reindex_note(&mut conn, note_id, title, content)?;
+        Ok(chunks)
+    }
+
+    /// Re-index every note marked dirty since the last refresh
+    ///
+    /// Chunks whose content hash and embedding version are unchanged keep
+    /// their embeddings; only new or edited chunks go through the embedder.
+    /// Chunks of trashed, locked or deleted notes are removed.
+    pub fn refresh_dirty(&self, conn: &mut Connection) -> Result<RagRefreshResult, RagError> {
+        let dirty: Vec<(String, i64)> = {
+            let mut stmt =
+                conn.prepare("SELECT note_id, generation FROM rag_dirty_note ORDER BY marked_at")?;
+            let rows = stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?;
+            rows.collect::<Result<_, _>>()?
+        };
+
+        let mut result = RagRefreshResult::default();
+
+        for (note_id, generation) in dirty {
+            let note: Option<(String, String, bool)> = conn
+                .query_row(
+                    "SELECT title, content_md, is_trashed OR is_locked FROM note WHERE id = ?1",
+                    [&note_id],
+                    |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+                )
+                .optional()?;
+
+            match note {
+                Some((title, content, false)) => {
+                    let (_, embedded, reused) =
+                        self.reindex_note(conn, &note_id, &title, &content)?;
+                    result.notes_refreshed += 1;
+                    result.chunks_embedded += embedded;
+                    result.chunks_reused += reused;
+                }
+                _ => {
+                    conn.execute("DELETE FROM note_embeddings WHERE note_id = ?1", [&note_id])?;
+                    result.notes_removed += 1;
+                }
+            }
+
+            // Keep the mark if the note changed again while we were indexing it
+            conn.execute(
+                "DELETE FROM rag_dirty_note WHERE note_id = ?1 AND generation = ?2",
+                params![note_id, generation],
+            )?;
+        }
+
+        conn.execute(
+            "INSERT INTO rag_index_state (key, value) VALUES ('last_refresh_at', ?1)
+             ON CONFLICT(key) DO UPDATE SET value = excluded.value",
+            [chrono::Utc::now().timestamp().to_string()],
+        )?;
+
+        log::info!(
+            "[rag] Refreshed {} notes ({} removed), embedded {} chunks, reused {}",
+            result.notes_refreshed,
+            result.notes_removed,
+            result.chunks_embedded,
+            result.chunks_reused
+        );
+
+        Ok(result)
+    }
+
+    /// Replace a note's chunks, reusing embeddings of unchanged chunks.
+    ///
+    /// Returns (chunks written, chunks embedded, chunks reused).
+    fn reindex_note(
+        &self,
+        conn: &mut Connection,
+        note_id: &str,
+        title: &str,
+        content: &str,
+    ) -> Result<(usize, usize, usize), RagError> {
         let chunks = self.chunk_document(note_id, content);
-        let conn = self.db_pool.get()?;
+        let version = self.embedder.as_ref().map(|e| e.version().to_string());
+
+        // Embeddings already computed for this note, keyed by chunk content
+        let mut existing: HashMap<String, Vec<u8>> = HashMap::new();
+        {
+            let mut stmt = conn.prepare(
+                "SELECT content_hash, embedding, embedding_version FROM note_embeddings
+                 WHERE note_id = ?1 AND content_hash IS NOT NULL AND embedding IS NOT NULL",
+            )?;
+            let rows = stmt.query_map([note_id], |row| {
+                Ok((
+                    row.get::<_, String>(0)?,
+                    row.get::<_, Vec<u8>>(1)?,
+                    row.get::<_, Option<String>>(2)?,
+                ))
+            })?;
+            for row in rows {
+                let (hash, embedding, embedding_version) = row?;
+                if embedding_version == version {
+                    existing.insert(hash, embedding);
+                }
+            }
+        }
+
+        let mut embedded = 0;
+        let mut reused = 0;
+
+        let tx = conn.transaction()?;
 
         // Delete existing chunks for this note
-        conn.execute(
+        tx.execute(
             "DELETE FROM note_embeddings WHERE note_id = ?1",
             params![note_id],
         )?;
 
-        // Insert new chunks
-        let mut stmt = conn.prepare(
-            r#"
-            INSERT INTO note_embeddings (id, note_id, chunk_index, content, start_offset, end_offset, metadata)
-            VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)
-            "#,
-        )?;
-
-        for chunk in &chunks {
-            let mut metadata = chunk.metadata.clone();
-            metadata.insert("title".to_string(), title.to_string());
-
-            stmt.execute(params![
-                chunk.id,
-                chunk.note_id,
-                chunk.chunk_index,
-                chunk.content,
-                chunk.start_offset,
-                chunk.end_offset,
-                serde_json::to_string(&metadata).unwrap_or_default(),
-            ])?;
+        {
+            let mut stmt = tx.prepare(
+                r#"
+                INSERT INTO note_embeddings (id, note_id, chunk_index, content, embedding,
+                    start_offset, end_offset, metadata, content_hash, embedding_version)
+                VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10)
+                "#,
+            )?;
+
+            for chunk in &chunks {
+                let mut metadata = chunk.metadata.clone();
+                metadata.insert("title".to_string(), title.to_string());
+
+                let hash = chunk_hash(&chunk.content);
+                let embedding = match (existing.get(&hash), &self.embedder) {
+                    (Some(embedding), _) => {
+                        reused += 1;
+                        Some(embedding.clone())
+                    }
+                    (None, Some(embedder)) => {
+                        embedded += 1;
+                        Some(encode_embedding(&embedder.embed(&chunk.content)?))
+                    }
+                    (None, None) => None,
+                };
+
+                stmt.execute(params![
+                    chunk.id,
+                    chunk.note_id,
+                    chunk.chunk_index,
+                    chunk.content,
+                    embedding,
+                    chunk.start_offset,
+                    chunk.end_offset,
+                    serde_json::to_string(&metadata).unwrap_or_default(),
+                    hash,
+                    version,
+                ])?;
+            }
         }
 
-        Ok(chunks.len())
+        tx.commit()?;
+
+        Ok((chunks.len(), embedded, reused))
     }
 
     /// Search for relevant chunks using hybrid search (BM25 + semantic)
@@ -414,8 +591,82 @@ impl RagPipeline {
 
     /// Answer a question using RAG
     pub async fn answer(&self, query: RagQuery) -> Result<RagResponse, RagError> {
+        let (results, request) = self.prepare_answer(&query)?;
+        let llm = self.llm()?;
+        let model = request.model.clone().unwrap_or_default();
+
+        if let Some(cache) = &self.response_cache {
+            let cached = cache.get(&*self.db_pool.get()?, llm.name(), &model, &request);
+            match cached {
+                Ok(Some(response)) => return Ok(self.build_response(response, results)),
+                Ok(None) => {}
+                Err(e) => log::warn!("[rag] Failed to read the answer cache: {}", e),
+            }
+        }
+
+        let response = llm
+            .complete(&request)
+            .await
+            .map_err(|e| RagError::LlmError(e.to_string()))?;
+
+        if let Some(cache) = &self.response_cache {
+            let context = results
+                .iter()
+                .fold(CacheContext::new(RAG_ANSWER_REQUEST_TYPE), |context, r| {
+                    context.note(&r.chunk.note_id)
+                });
+            let conn = self.db_pool.get()?;
+            if let Err(e) = cache.put(&conn, llm.name(), &model, &request, &response, &context) {
+                log::warn!("[rag] Failed to cache the answer: {}", e);
+            }
+        }
+
+        Ok(self.build_response(response, results))
+    }
+
+    /// Answer a question using RAG, streaming tokens to `sink` as they are generated
+    ///
+    /// Dropping the sink's receiver cancels generation.
+    pub async fn answer_streaming(
+        &self,
+        query: RagQuery,
+        sink: &StreamSink,
+    ) -> Result<RagResponse, RagError> {
+        let (results, request) = self.prepare_answer(&query)?;
+        let llm = self.llm()?;
+
+        let response = llm
+            .complete_streaming(&request, sink)
+            .await
+            .map_err(|e| RagError::LlmError(e.to_string()))?;
+
+        Ok(self.build_response(response, results))
+    }
+
+    fn llm(&self) -> Result<&dyn LLMProvider, RagError> {
+        self.llm_provider
+            .as_deref()
+            .ok_or_else(|| RagError::LlmError("No LLM provider configured".to_string()))
+    }
+
+    fn build_response(&self, response: LLMResponse, results: Vec<SearchResult>) -> RagResponse {
+        let confidence = self.calculate_confidence(&results);
+        RagResponse {
+            answer: response.content,
+            sources: results,
+            tokens_used: response.tokens_used as u32,
+            model: response.model,
+            confidence,
+        }
+    }
+
+    /// Retrieve context and build the LLM request for a question
+    fn prepare_answer(
+        &self,
+        query: &RagQuery,
+    ) -> Result<(Vec<SearchResult>, LLMRequest), RagError> {
         // Search for relevant context
-        let results = self.search(&query)?;
+        let results = self.search(query)?;
 
         if results.is_empty() {
             return Err(RagError::NoContextFound);
@@ -438,12 +689,6 @@ impl RagPipeline {
             .collect::<Vec<_>>()
             .join("\n\n---\n\n");
 
-        // Generate answer using LLM
-        let llm = self
-            .llm_provider
-            .as_ref()
-            .ok_or_else(|| RagError::LlmError("No LLM provider configured".to_string()))?;
-
         let system_prompt = r#"You are a helpful assistant that answers questions based on the user's personal notes and documents. 
 Use ONLY the provided context to answer questions. If the context doesn't contain enough information to answer, say so.
 When citing information, reference the source number (e.g., [Source 1]).
@@ -472,19 +717,7 @@ Be concise but thorough."#;
             model: None,
         };
 
-        let response = llm
-            .complete(&request)
-            .await
-            .map_err(|e| RagError::LlmError(e.to_string()))?;
-
-        let confidence = self.calculate_confidence(&results);
-        Ok(RagResponse {
-            answer: response.content,
-            sources: results,
-            tokens_used: response.tokens_used as u32,
-            model: response.model,
-            confidence,
-        })
+        Ok((results, request))
     }
 
     /// Calculate confidence score based on search results
@@ -519,12 +752,25 @@ Be concise but thorough."#;
             0.0
         };
 
+        let dirty_notes: i64 =
+            conn.query_row("SELECT COUNT(*) FROM rag_dirty_note", [], |row| row.get(0))?;
+
+        let last_refresh_at: Option<i64> = conn
+            .query_row(
+                "SELECT CAST(value AS INTEGER) FROM rag_index_state WHERE key = 'last_refresh_at'",
+                [],
+                |row| row.get(0),
+            )
+            .optional()?;
+
         Ok(RagStats {
             total_chunks: total_chunks as usize,
             total_notes: total_notes as usize,
             avg_chunks_per_note,
             embedding_dimensions: self.config.embedding_dimensions,
             chunk_size: self.config.chunk_size,
+            dirty_notes: dirty_notes as usize,
+            last_refresh_at,
         })
     }
 }
@@ -537,6 +783,10 @@ pub struct RagStats {
     pub avg_chunks_per_note: f32,
     pub embedding_dimensions: usize,
     pub chunk_size: usize,
+    /// Notes waiting for `refresh_dirty`
+    pub dirty_notes: usize,
+    /// Unix timestamp of the last `refresh_dirty`
+    pub last_refresh_at: Option<i64>,
 }
 
 #[cfg(test)]
@@ -576,6 +826,27 @@ mod tests {
         assert_eq!(chunks[0].chunk_index, 0);
     }
 
+    #[test]
+    fn test_chunk_document_is_stable() {
+        let config = RagConfig {
+            chunk_size: 80,
+            chunk_overlap: 10,
+            ..Default::default()
+        };
+        let pipeline = RagPipeline::new(config, create_test_pool());
+
+        let content = "First sentence here. Second one follows! A third? ".repeat(10);
+        let a = pipeline.chunk_document("n", &content);
+        let b = pipeline.chunk_document("n", &content);
+
+        let bounds = |c: &[DocumentChunk]| {
+            c.iter()
+                .map(|c| (c.start_offset, c.end_offset))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(bounds(&a), bounds(&b));
+    }
+
     #[test]
     fn test_chunk_empty_document() {
         let config = RagConfig::default();
@@ -687,5 +958,7 @@ mod tests {
         let stats = pipeline.get_stats().unwrap();
         assert_eq!(stats.total_chunks, 0);
         assert_eq!(stats.total_notes, 0);
+        assert_eq!(stats.dirty_notes, 0);
+        assert_eq!(stats.last_refresh_at, None);
     }
 }
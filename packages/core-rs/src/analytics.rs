@@ -1,9 +1,13 @@
 // packages/core-rs/src/analytics.rs
 
-use rusqlite::{Connection, Result};
+use chrono::{DateTime, Duration, NaiveDate};
+use rusqlite::{params, Connection, Result};
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 
+use crate::calendar::TimeRange;
 use crate::db::DbError;
+use crate::space::in_active_space;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct WeeklyCount {
@@ -114,13 +118,18 @@ pub fn calculate_habit_correlation(
     })
 }
 
+/// Vault-wide counts, leaving out archived spaces
 pub fn get_analytics_data(conn: &Connection) -> Result<AnalyticsData, DbError> {
-    let note_count: i64 = conn.query_row("SELECT COUNT(*) FROM note", [], |row| row.get(0))?;
-    let task_count: i64 = conn.query_row("SELECT COUNT(*) FROM task", [], |row| row.get(0))?;
-    let project_count: i64 =
-        conn.query_row("SELECT COUNT(*) FROM project", [], |row| row.get(0))?;
+    let active = in_active_space("space_id");
+    let count = |table: &str| -> Result<i64, DbError> {
+        let sql = format!("SELECT COUNT(*) FROM {} WHERE {}", table, active);
+        Ok(conn.query_row(&sql, [], |row| row.get(0))?)
+    };
+    let note_count = count("note")?;
+    let task_count = count("task")?;
+    let project_count = count("project")?;
 
-    let mut stmt = conn.prepare("SELECT strftime('%Y-%W', datetime(completed_at, 'unixepoch')) as week, COUNT(*) FROM task WHERE completed_at IS NOT NULL GROUP BY week ORDER BY week DESC LIMIT 12")?;
+    let mut stmt = conn.prepare(&format!("SELECT strftime('%Y-%W', datetime(completed_at, 'unixepoch')) as week, COUNT(*) FROM task WHERE completed_at IS NOT NULL AND {} GROUP BY week ORDER BY week DESC LIMIT 12", active))?;
     let mut rows = stmt.query([])?;
     let mut tasks_completed_by_week = Vec::new();
     while let Some(row) = rows.next()? {
@@ -130,7 +139,7 @@ pub fn get_analytics_data(conn: &Connection) -> Result<AnalyticsData, DbError> {
         });
     }
 
-    let mut stmt = conn.prepare("SELECT strftime('%Y-%W', datetime(created_at, 'unixepoch')) as week, COUNT(*) FROM note WHERE created_at IS NOT NULL GROUP BY week ORDER BY week DESC LIMIT 12")?;
+    let mut stmt = conn.prepare(&format!("SELECT strftime('%Y-%W', datetime(created_at, 'unixepoch')) as week, COUNT(*) FROM note WHERE created_at IS NOT NULL AND {} GROUP BY week ORDER BY week DESC LIMIT 12", active))?;
     let mut rows = stmt.query([])?;
     let mut notes_created_by_week = Vec::new();
     while let Some(row) = rows.next()? {
@@ -148,3 +157,313 @@ pub fn get_analytics_data(conn: &Connection) -> Result<AnalyticsData, DbError> {
         notes_created_by_week,
     })
 }
+
+/// How much each kind of activity counts towards a bucket's score
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct ActivityWeights {
+    pub note_edit: f64,
+    pub task_completion: f64,
+    pub time_entry: f64,
+}
+
+impl Default for ActivityWeights {
+    fn default() -> Self {
+        ActivityWeights {
+            note_edit: 1.0,
+            task_completion: 1.0,
+            time_entry: 1.0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ActivityOptions {
+    /// Offset of the user's local time from UTC; buckets are local
+    #[serde(default)]
+    pub utc_offset_minutes: i32,
+    #[serde(default)]
+    pub weights: ActivityWeights,
+}
+
+/// Activity counts of one heatmap or series bucket
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ActivityCounts {
+    /// Notes whose last edit falls in the bucket
+    pub note_edits: i64,
+    pub task_completions: i64,
+    /// Time entries started in the bucket
+    pub time_entries: i64,
+    /// Weighted sum of the counts
+    pub score: f64,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct HeatmapCell {
+    /// 0 = Monday
+    pub weekday: u8,
+    pub hour: u8,
+    #[serde(flatten)]
+    pub counts: ActivityCounts,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ActivityHeatmap {
+    /// All 7 x 24 buckets, Monday 00:00 first
+    pub cells: Vec<HeatmapCell>,
+    pub max_score: f64,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DailyActivity {
+    /// Local date
+    pub date: NaiveDate,
+    #[serde(flatten)]
+    pub counts: ActivityCounts,
+}
+
+/// Note edits, task completions and time entries as `(kind, ts)` rows in
+/// `[?1, ?2)`, limited to space `?3` when `by_space` is set and to active
+/// spaces otherwise
+fn activity_events_sql(by_space: bool) -> String {
+    let space = if by_space {
+        "AND space_id = ?3".to_string()
+    } else {
+        format!("AND {}", in_active_space("space_id"))
+    };
+    format!(
+        "SELECT 'note' AS kind, modified_at AS ts FROM note
+         WHERE is_trashed = 0 AND modified_at >= ?1 AND modified_at < ?2 {space}
+         UNION ALL
+         SELECT 'task', completed_at FROM task
+         WHERE completed_at >= ?1 AND completed_at < ?2 {space}
+         UNION ALL
+         SELECT 'time', started_at FROM time_entry
+         WHERE started_at >= ?1 AND started_at < ?2 {space}",
+        space = space
+    )
+}
+
+fn validate_activity_query(range: TimeRange, options: &ActivityOptions) -> Result<(), DbError> {
+    if range.end <= range.start {
+        return Err(DbError::Message("Range must end after it starts".into()));
+    }
+    if options.utc_offset_minutes.abs() > 14 * 60 {
+        return Err(DbError::Message(format!(
+            "Invalid UTC offset: {} minutes",
+            options.utc_offset_minutes
+        )));
+    }
+    Ok(())
+}
+
+impl ActivityCounts {
+    fn add(&mut self, kind: &str, count: i64, weights: &ActivityWeights) {
+        let weight = match kind {
+            "note" => {
+                self.note_edits += count;
+                weights.note_edit
+            }
+            "task" => {
+                self.task_completions += count;
+                weights.task_completion
+            }
+            _ => {
+                self.time_entries += count;
+                weights.time_entry
+            }
+        };
+        self.score += count as f64 * weight;
+    }
+}
+
+/// Activity in a space by local weekday and hour
+pub fn get_activity_heatmap(
+    conn: &Connection,
+    space_id: &str,
+    range: TimeRange,
+    options: &ActivityOptions,
+) -> Result<ActivityHeatmap, DbError> {
+    validate_activity_query(range, options)?;
+    let offset_secs = options.utc_offset_minutes as i64 * 60;
+    let mut stmt = conn.prepare(&format!(
+        "SELECT kind,
+                (CAST(strftime('%w', ts + ?4, 'unixepoch') AS INTEGER) + 6) % 7 AS weekday,
+                CAST(strftime('%H', ts + ?4, 'unixepoch') AS INTEGER) AS hour,
+                COUNT(*)
+         FROM ({})
+         GROUP BY kind, weekday, hour",
+        activity_events_sql(true)
+    ))?;
+    let rows = stmt.query_map(
+        params![range.start, range.end, space_id, offset_secs],
+        |row| {
+            Ok((
+                row.get::<_, String>(0)?,
+                row.get::<_, i64>(1)?,
+                row.get::<_, i64>(2)?,
+                row.get::<_, i64>(3)?,
+            ))
+        },
+    )?;
+
+    let mut cells: Vec<HeatmapCell> = (0..7u8)
+        .flat_map(|weekday| {
+            (0..24u8).map(move |hour| HeatmapCell {
+                weekday,
+                hour,
+                counts: ActivityCounts::default(),
+            })
+        })
+        .collect();
+    for row in rows {
+        let (kind, weekday, hour, count) = row?;
+        cells[(weekday * 24 + hour) as usize]
+            .counts
+            .add(&kind, count, &options.weights);
+    }
+
+    let max_score = cells.iter().map(|c| c.counts.score).fold(0.0, f64::max);
+    Ok(ActivityHeatmap { cells, max_score })
+}
+
+/// Activity across the vault per local day, for a contribution graph. Every
+/// day touched by `range` is included, with zeros when nothing happened.
+pub fn get_daily_activity_series(
+    conn: &Connection,
+    range: TimeRange,
+    options: &ActivityOptions,
+) -> Result<Vec<DailyActivity>, DbError> {
+    validate_activity_query(range, options)?;
+    let offset_secs = options.utc_offset_minutes as i64 * 60;
+    let mut stmt = conn.prepare(&format!(
+        "SELECT kind, date(ts + ?3, 'unixepoch') AS day, COUNT(*)
+         FROM ({})
+         GROUP BY kind, day",
+        activity_events_sql(false)
+    ))?;
+    let rows = stmt.query_map(params![range.start, range.end, offset_secs], |row| {
+        Ok((
+            row.get::<_, String>(0)?,
+            row.get::<_, String>(1)?,
+            row.get::<_, i64>(2)?,
+        ))
+    })?;
+
+    let mut by_day: HashMap<NaiveDate, ActivityCounts> = HashMap::new();
+    for row in rows {
+        let (kind, day, count) = row?;
+        let date = NaiveDate::parse_from_str(&day, "%Y-%m-%d")
+            .map_err(|e| DbError::Message(format!("Invalid activity date {}: {}", day, e)))?;
+        by_day
+            .entry(date)
+            .or_default()
+            .add(&kind, count, &options.weights);
+    }
+
+    let local_date = |ts: i64| {
+        DateTime::from_timestamp(ts + offset_secs, 0)
+            .unwrap_or_default()
+            .date_naive()
+    };
+    let mut series = Vec::new();
+    let mut date = local_date(range.start);
+    let last = local_date(range.end - 1);
+    while date <= last {
+        series.push(DailyActivity {
+            date,
+            counts: by_day.remove(&date).unwrap_or_default(),
+        });
+        date += Duration::days(1);
+    }
+    Ok(series)
+}
+
+/// Words written in a space on one local day. A note's saves on the day are
+/// netted first, so rewording a sentence doesn't count as writing it.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DailyWriting {
+    /// Local date
+    pub date: NaiveDate,
+    /// Net words added, over notes that grew
+    pub words_added: i64,
+    /// Net words removed, over notes that shrank
+    pub words_removed: i64,
+    /// `words_added - words_removed`
+    pub net_words: i64,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct WritingStats {
+    /// Every day touched by the range, with zeros when nothing was written
+    pub days: Vec<DailyWriting>,
+    pub words_added: i64,
+    pub words_removed: i64,
+    pub net_words: i64,
+}
+
+/// Words written per local day in a space, from the word changes logged as
+/// notes are saved. Only the UTC offset of `options` applies.
+pub fn get_writing_stats(
+    conn: &Connection,
+    space_id: &str,
+    range: TimeRange,
+    options: &ActivityOptions,
+) -> Result<WritingStats, DbError> {
+    validate_activity_query(range, options)?;
+    let offset_secs = options.utc_offset_minutes as i64 * 60;
+    let mut stmt = conn.prepare(
+        "SELECT day, SUM(MAX(net, 0)), SUM(MAX(-net, 0))
+         FROM (
+             SELECT date(recorded_at + ?4, 'unixepoch') AS day, note_id, SUM(delta) AS net
+             FROM note_word_delta
+             WHERE space_id = ?1 AND recorded_at >= ?2 AND recorded_at < ?3
+             GROUP BY day, note_id
+         )
+         GROUP BY day",
+    )?;
+    let rows = stmt.query_map(
+        params![space_id, range.start, range.end, offset_secs],
+        |row| {
+            Ok((
+                row.get::<_, String>(0)?,
+                row.get::<_, i64>(1)?,
+                row.get::<_, i64>(2)?,
+            ))
+        },
+    )?;
+
+    let mut by_day: HashMap<NaiveDate, (i64, i64)> = HashMap::new();
+    for row in rows {
+        let (day, added, removed) = row?;
+        let date = NaiveDate::parse_from_str(&day, "%Y-%m-%d")
+            .map_err(|e| DbError::Message(format!("Invalid writing date {}: {}", day, e)))?;
+        by_day.insert(date, (added, removed));
+    }
+
+    let local_date = |ts: i64| {
+        DateTime::from_timestamp(ts + offset_secs, 0)
+            .unwrap_or_default()
+            .date_naive()
+    };
+    let mut days = Vec::new();
+    let mut date = local_date(range.start);
+    let last = local_date(range.end - 1);
+    while date <= last {
+        let (words_added, words_removed) = by_day.remove(&date).unwrap_or_default();
+        days.push(DailyWriting {
+            date,
+            words_added,
+            words_removed,
+            net_words: words_added - words_removed,
+        });
+        date += Duration::days(1);
+    }
+    let words_added: i64 = days.iter().map(|d| d.words_added).sum();
+    let words_removed: i64 = days.iter().map(|d| d.words_removed).sum();
+    Ok(WritingStats {
+        days,
+        words_added,
+        words_removed,
+        net_words: words_added - words_removed,
+    })
+}
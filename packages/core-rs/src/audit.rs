@@ -1,7 +1,10 @@
-use rusqlite::{Connection, Result};
+use rusqlite::{Connection, OptionalExtension, Result, ToSql};
 use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
 use ulid::Ulid;
 
+use crate::db::{get_setting, set_setting, DbError};
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct AuditLog {
     pub id: String,
@@ -25,9 +28,35 @@ pub fn log_event(
     details_json: Option<&str>,
     ip_address: Option<&str>,
     user_agent: Option<&str>,
+) -> Result<String> {
+    log_event_at(
+        conn,
+        user_id,
+        event_type,
+        entity_type,
+        entity_id,
+        details_json,
+        ip_address,
+        user_agent,
+        chrono::Utc::now().timestamp(),
+    )
+}
+
+/// Log an event that happened earlier, e.g. a failed vault unlock recorded
+/// while the database was still locked
+#[allow(clippy::too_many_arguments)]
+pub fn log_event_at(
+    conn: &Connection,
+    user_id: Option<&str>,
+    event_type: &str,
+    entity_type: &str,
+    entity_id: Option<&str>,
+    details_json: Option<&str>,
+    ip_address: Option<&str>,
+    user_agent: Option<&str>,
+    created_at: i64,
 ) -> Result<String> {
     let id = Ulid::new().to_string();
-    let created_at = chrono::Utc::now().timestamp();
 
     conn.execute(
         "INSERT INTO audit_log (
@@ -80,3 +109,583 @@ pub fn get_audit_logs(conn: &Connection, limit: usize, offset: usize) -> Result<
 
     Ok(logs)
 }
+
+// Authentication and access events
+pub const AUTH_LOGIN_SUCCEEDED: &str = "AUTH_LOGIN_SUCCEEDED";
+pub const AUTH_LOGIN_FAILED: &str = "AUTH_LOGIN_FAILED";
+pub const AUTH_LOGIN_LOCKED_OUT: &str = "AUTH_LOGIN_LOCKED_OUT";
+pub const AUTH_PASSWORD_CHANGED: &str = "AUTH_PASSWORD_CHANGED";
+pub const AUTH_PASSWORD_CHANGE_FAILED: &str = "AUTH_PASSWORD_CHANGE_FAILED";
+pub const AUTH_VAULT_UNLOCKED: &str = "AUTH_VAULT_UNLOCKED";
+pub const AUTH_VAULT_UNLOCK_FAILED: &str = "AUTH_VAULT_UNLOCK_FAILED";
+pub const AUTH_VAULT_UNLOCK_LOCKED_OUT: &str = "AUTH_VAULT_UNLOCK_LOCKED_OUT";
+pub const AUTH_VAULT_PASSWORD_CHANGED: &str = "AUTH_VAULT_PASSWORD_CHANGED";
+pub const AUTH_VAULT_REKEYED: &str = "AUTH_VAULT_REKEYED";
+pub const AUTH_USER_SUSPENDED: &str = "AUTH_USER_SUSPENDED";
+pub const AUTH_USER_ACTIVATED: &str = "AUTH_USER_ACTIVATED";
+pub const AUTH_INVITATION_CREATED: &str = "AUTH_INVITATION_CREATED";
+pub const AUTH_INVITATION_SUPERSEDED: &str = "AUTH_INVITATION_SUPERSEDED";
+pub const AUTH_INVITATION_ACCEPTED: &str = "AUTH_INVITATION_ACCEPTED";
+pub const AUTH_INVITATION_REVOKED: &str = "AUTH_INVITATION_REVOKED";
+pub const AUTH_INVITATION_ACCEPT_FAILED: &str = "AUTH_INVITATION_ACCEPT_FAILED";
+
+const AUTH_SUCCESS_EVENTS: [&str; 11] = [
+    AUTH_LOGIN_SUCCEEDED,
+    AUTH_PASSWORD_CHANGED,
+    AUTH_VAULT_UNLOCKED,
+    AUTH_VAULT_PASSWORD_CHANGED,
+    AUTH_VAULT_REKEYED,
+    AUTH_USER_SUSPENDED,
+    AUTH_USER_ACTIVATED,
+    AUTH_INVITATION_CREATED,
+    AUTH_INVITATION_SUPERSEDED,
+    AUTH_INVITATION_ACCEPTED,
+    AUTH_INVITATION_REVOKED,
+];
+const AUTH_FAILURE_EVENTS: [&str; 6] = [
+    AUTH_LOGIN_FAILED,
+    AUTH_LOGIN_LOCKED_OUT,
+    AUTH_PASSWORD_CHANGE_FAILED,
+    AUTH_VAULT_UNLOCK_FAILED,
+    AUTH_VAULT_UNLOCK_LOCKED_OUT,
+    AUTH_INVITATION_ACCEPT_FAILED,
+];
+
+/// Write an authentication event. `subject` is what the event is about: a
+/// username, a vault path or a suspended user.
+pub(crate) fn log_auth_event(
+    conn: &Connection,
+    user_id: Option<&str>,
+    event_type: &str,
+    subject: &str,
+    details: serde_json::Value,
+    user_agent: Option<&str>,
+    at: i64,
+) {
+    let details = details.to_string();
+    if let Err(e) = log_event_at(
+        conn,
+        user_id,
+        event_type,
+        "auth",
+        Some(subject),
+        Some(&details),
+        None,
+        user_agent,
+        at,
+    ) {
+        log::warn!("[audit] Failed to record {}: {}", event_type, e);
+    }
+}
+
+/// Filters for [`get_auth_audit_log`]; unset fields match everything
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct AuthAuditFilter {
+    pub user_id: Option<String>,
+    /// Username, vault path or affected user
+    pub subject: Option<String>,
+    pub event_types: Option<Vec<String>>,
+    pub success: Option<bool>,
+    pub since: Option<i64>,
+    pub until: Option<i64>,
+    pub limit: Option<usize>,
+    pub offset: Option<usize>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AuthAuditEntry {
+    pub id: String,
+    pub user_id: Option<String>,
+    pub event_type: String,
+    pub subject: Option<String>,
+    pub success: bool,
+    pub details: serde_json::Value,
+    pub user_agent: Option<String>,
+    pub created_at: i64,
+}
+
+/// Authentication events, newest first
+pub fn get_auth_audit_log(
+    conn: &Connection,
+    filter: &AuthAuditFilter,
+) -> Result<Vec<AuthAuditEntry>> {
+    let mut sql = String::from(
+        "SELECT id, user_id, event_type, entity_id, details_json, user_agent, created_at
+         FROM audit_log
+         WHERE entity_type = 'auth'",
+    );
+    let mut values: Vec<Box<dyn ToSql>> = Vec::new();
+
+    if let Some(user_id) = &filter.user_id {
+        values.push(Box::new(user_id.clone()));
+        sql.push_str(&format!(" AND user_id = ?{}", values.len()));
+    }
+    if let Some(subject) = &filter.subject {
+        values.push(Box::new(subject.clone()));
+        sql.push_str(&format!(" AND entity_id = ?{}", values.len()));
+    }
+    let mut event_types: Option<Vec<String>> = filter.event_types.clone();
+    if let Some(success) = filter.success {
+        let matching: &[&str] = if success {
+            &AUTH_SUCCESS_EVENTS
+        } else {
+            &AUTH_FAILURE_EVENTS
+        };
+        event_types = Some(match event_types {
+            Some(types) => types
+                .into_iter()
+                .filter(|t| matching.contains(&t.as_str()))
+                .collect(),
+            None => matching.iter().map(|t| t.to_string()).collect(),
+        });
+    }
+    if let Some(types) = event_types {
+        if types.is_empty() {
+            return Ok(Vec::new());
+        }
+        let mut placeholders = Vec::with_capacity(types.len());
+        for event_type in types {
+            values.push(Box::new(event_type));
+            placeholders.push(format!("?{}", values.len()));
+        }
+        sql.push_str(&format!(" AND event_type IN ({})", placeholders.join(", ")));
+    }
+    if let Some(since) = filter.since {
+        values.push(Box::new(since));
+        sql.push_str(&format!(" AND created_at >= ?{}", values.len()));
+    }
+    if let Some(until) = filter.until {
+        values.push(Box::new(until));
+        sql.push_str(&format!(" AND created_at < ?{}", values.len()));
+    }
+    values.push(Box::new(filter.limit.unwrap_or(100) as i64));
+    values.push(Box::new(filter.offset.unwrap_or(0) as i64));
+    sql.push_str(&format!(
+        " ORDER BY created_at DESC, id DESC LIMIT ?{} OFFSET ?{}",
+        values.len() - 1,
+        values.len()
+    ));
+
+    let mut stmt = conn.prepare(&sql)?;
+    let params: Vec<&dyn ToSql> = values.iter().map(|v| v.as_ref()).collect();
+    let entries = stmt
+        .query_map(params.as_slice(), |row| {
+            let event_type: String = row.get(2)?;
+            let details: Option<String> = row.get(4)?;
+            Ok(AuthAuditEntry {
+                id: row.get(0)?,
+                user_id: row.get(1)?,
+                success: AUTH_SUCCESS_EVENTS.contains(&event_type.as_str()),
+                event_type,
+                subject: row.get(3)?,
+                details: details
+                    .and_then(|d| serde_json::from_str(&d).ok())
+                    .unwrap_or(serde_json::Value::Null),
+                user_agent: row.get(5)?,
+                created_at: row.get(6)?,
+            })
+        })?
+        .collect::<Result<Vec<_>>>()?;
+
+    Ok(entries)
+}
+
+/// Source of a data change made on this device; changes applied from sync
+/// use the sending device's id instead
+pub const AUDIT_SOURCE_LOCAL: &str = "local";
+/// Source of synced changes whose sending device isn't known
+pub const AUDIT_SOURCE_SYNC: &str = "sync";
+/// Source of changes made by an automation rule, which never trigger rules
+pub const AUDIT_SOURCE_AUTOMATION: &str = "automation";
+
+/// `prev_hash` of the first event in a space's chain
+const AUDIT_CHAIN_GENESIS: &str =
+    "0000000000000000000000000000000000000000000000000000000000000000";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AuditOperation {
+    Create,
+    Update,
+    Delete,
+}
+
+impl AuditOperation {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AuditOperation::Create => "create",
+            AuditOperation::Update => "update",
+            AuditOperation::Delete => "delete",
+        }
+    }
+
+    fn parse(s: &str) -> Option<Self> {
+        match s {
+            "create" => Some(AuditOperation::Create),
+            "update" => Some(AuditOperation::Update),
+            "delete" => Some(AuditOperation::Delete),
+            _ => None,
+        }
+    }
+
+    fn past_tense(&self) -> &'static str {
+        match self {
+            AuditOperation::Create => "CREATED",
+            AuditOperation::Update => "UPDATED",
+            AuditOperation::Delete => "DELETED",
+        }
+    }
+}
+
+/// A data change in a space's hash chain
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DataAuditEvent {
+    pub id: String,
+    pub space_id: String,
+    /// Position in the space's chain, starting at 1
+    pub chain_seq: i64,
+    pub actor: Option<String>,
+    pub entity_type: String,
+    pub entity_id: String,
+    pub operation: AuditOperation,
+    /// [`AUDIT_SOURCE_LOCAL`] or the id of the device the change synced from
+    pub source: String,
+    pub created_at: i64,
+    pub prev_hash: String,
+    pub hash: String,
+}
+
+impl DataAuditEvent {
+    fn compute_hash(&self) -> String {
+        // Serialized as a tuple so field boundaries and NULLs are unambiguous
+        let payload = serde_json::json!([
+            self.prev_hash,
+            self.id,
+            self.space_id,
+            self.chain_seq,
+            self.actor,
+            self.entity_type,
+            self.entity_id,
+            self.operation.as_str(),
+            self.source,
+            self.created_at,
+        ]);
+        hex::encode(Sha256::digest(payload.to_string().as_bytes()))
+    }
+}
+
+fn audit_disabled_key(space_id: &str) -> String {
+    format!("audit_disabled:{}", space_id)
+}
+
+/// Whether data changes in the space are recorded; on unless turned off
+pub fn is_space_audit_enabled(conn: &Connection, space_id: &str) -> Result<bool, DbError> {
+    Ok(get_setting(conn, &audit_disabled_key(space_id))?.as_deref() != Some("1"))
+}
+
+/// Turn data change auditing for a space on or off. Events already recorded
+/// are kept either way.
+pub fn set_space_audit_enabled(
+    conn: &Connection,
+    space_id: &str,
+    enabled: bool,
+) -> Result<(), DbError> {
+    set_setting(
+        conn,
+        &audit_disabled_key(space_id),
+        if enabled { "0" } else { "1" },
+        Some("Whether data changes in this space are audited"),
+    )
+}
+
+/// Append a data change to the space's audit chain. Only the newest event's
+/// hash is read, so the cost doesn't grow with the chain. Returns `None`
+/// when auditing is disabled for the space.
+#[allow(clippy::too_many_arguments)]
+pub fn record_audit_event(
+    conn: &Connection,
+    space_id: &str,
+    actor: Option<&str>,
+    entity_type: &str,
+    entity_id: &str,
+    operation: AuditOperation,
+    source: &str,
+) -> Result<Option<DataAuditEvent>, DbError> {
+    Ok(record_audit_events(
+        conn,
+        space_id,
+        actor,
+        entity_type,
+        &[entity_id.to_string()],
+        operation,
+        source,
+    )?
+    .pop())
+}
+
+/// Events written by one `INSERT` of [`record_audit_events`]
+const AUDIT_INSERT_BATCH: usize = 50;
+
+/// Append the same change to many entities of a space as consecutive links
+/// of its audit chain. The chain head is read once and the events are
+/// inserted [`AUDIT_INSERT_BATCH`] rows at a time, so a bulk edit costs a
+/// handful of statements rather than a few per entity. Returns nothing when
+/// auditing is disabled for the space.
+#[allow(clippy::too_many_arguments)]
+pub fn record_audit_events(
+    conn: &Connection,
+    space_id: &str,
+    actor: Option<&str>,
+    entity_type: &str,
+    entity_ids: &[String],
+    operation: AuditOperation,
+    source: &str,
+) -> Result<Vec<DataAuditEvent>, DbError> {
+    if entity_ids.is_empty() || !is_space_audit_enabled(conn, space_id)? {
+        return Ok(Vec::new());
+    }
+
+    let last: Option<(i64, String)> = conn
+        .query_row(
+            "SELECT chain_seq, hash FROM audit_log
+             WHERE space_id = ?1 AND chain_seq IS NOT NULL
+             ORDER BY chain_seq DESC LIMIT 1",
+            [space_id],
+            |row| Ok((row.get(0)?, row.get(1)?)),
+        )
+        .optional()?;
+    let (mut prev_seq, mut prev_hash) = last.unwrap_or((0, AUDIT_CHAIN_GENESIS.to_string()));
+
+    let now = chrono::Utc::now().timestamp();
+    let mut events = Vec::with_capacity(entity_ids.len());
+    for entity_id in entity_ids {
+        let mut event = DataAuditEvent {
+            id: Ulid::new().to_string(),
+            space_id: space_id.to_string(),
+            chain_seq: prev_seq + 1,
+            actor: actor.map(str::to_string),
+            entity_type: entity_type.to_string(),
+            entity_id: entity_id.clone(),
+            operation,
+            source: source.to_string(),
+            created_at: now,
+            prev_hash,
+            hash: String::new(),
+        };
+        event.hash = event.compute_hash();
+        prev_seq = event.chain_seq;
+        prev_hash = event.hash.clone();
+        events.push(event);
+    }
+
+    let event_type = format!("{}_{}", entity_type.to_uppercase(), operation.past_tense());
+    let operation = operation.as_str();
+    for batch in events.chunks(AUDIT_INSERT_BATCH) {
+        let rows = vec!["(?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)"; batch.len()].join(", ");
+        let mut params: Vec<&dyn ToSql> = Vec::with_capacity(batch.len() * 12);
+        for event in batch {
+            params.extend([
+                &event.id as &dyn ToSql,
+                &event.actor,
+                &event_type,
+                &event.entity_type,
+                &event.entity_id,
+                &event.created_at,
+                &event.space_id,
+                &operation,
+                &event.source,
+                &event.chain_seq,
+                &event.prev_hash,
+                &event.hash,
+            ]);
+        }
+        conn.prepare_cached(&format!(
+            "INSERT INTO audit_log (
+                id, user_id, event_type, entity_type, entity_id, created_at,
+                space_id, operation, source, chain_seq, prev_hash, hash
+            ) VALUES {}",
+            rows
+        ))?
+        .execute(params.as_slice())?;
+    }
+
+    Ok(events)
+}
+
+/// Record a change made by a core mutation. Auditing never fails the
+/// mutation itself.
+pub(crate) fn audit_change(
+    conn: &Connection,
+    space_id: &str,
+    entity_type: &str,
+    entity_id: &str,
+    operation: AuditOperation,
+    source: &str,
+) {
+    if let Err(e) = record_audit_event(
+        conn,
+        space_id,
+        None,
+        entity_type,
+        entity_id,
+        operation,
+        source,
+    ) {
+        log::warn!(
+            "[audit] Failed to record {} of {} {}: {}",
+            operation.as_str(),
+            entity_type,
+            entity_id,
+            e
+        );
+    }
+}
+
+/// [`audit_change`] for many entities of one space, see
+/// [`record_audit_events`]
+pub(crate) fn audit_changes(
+    conn: &Connection,
+    space_id: &str,
+    entity_type: &str,
+    entity_ids: &[String],
+    operation: AuditOperation,
+    source: &str,
+) {
+    if let Err(e) = record_audit_events(
+        conn,
+        space_id,
+        None,
+        entity_type,
+        entity_ids,
+        operation,
+        source,
+    ) {
+        log::warn!(
+            "[audit] Failed to record {} of {} {}(s): {}",
+            operation.as_str(),
+            entity_ids.len(),
+            entity_type,
+            e
+        );
+    }
+}
+
+const DATA_AUDIT_COLUMNS: &str = "id, space_id, chain_seq, user_id, entity_type, entity_id,
+                operation, source, created_at, prev_hash, hash";
+
+fn data_audit_event_from_row(row: &rusqlite::Row) -> Result<DataAuditEvent> {
+    let operation: String = row.get(6)?;
+    Ok(DataAuditEvent {
+        id: row.get(0)?,
+        space_id: row.get(1)?,
+        chain_seq: row.get(2)?,
+        actor: row.get(3)?,
+        entity_type: row.get(4)?,
+        entity_id: row.get(5)?,
+        operation: AuditOperation::parse(&operation).ok_or_else(|| {
+            rusqlite::Error::FromSqlConversionFailure(
+                6,
+                rusqlite::types::Type::Text,
+                format!("Unknown audit operation: {}", operation).into(),
+            )
+        })?,
+        source: row.get(7)?,
+        created_at: row.get(8)?,
+        prev_hash: row.get(9)?,
+        hash: row.get(10)?,
+    })
+}
+
+/// Recorded changes to one entity, oldest first
+pub fn get_audit_trail(conn: &Connection, entity_id: &str) -> Result<Vec<DataAuditEvent>> {
+    let mut stmt = conn.prepare(&format!(
+        "SELECT {} FROM audit_log
+         WHERE entity_id = ?1 AND chain_seq IS NOT NULL
+         ORDER BY created_at, chain_seq",
+        DATA_AUDIT_COLUMNS
+    ))?;
+    let events = stmt
+        .query_map([entity_id], data_audit_event_from_row)?
+        .collect::<Result<Vec<_>>>()?;
+    Ok(events)
+}
+
+/// Where [`verify_audit_chain`] found the chain broken
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AuditChainBreak {
+    pub chain_seq: i64,
+    pub event_id: Option<String>,
+    pub reason: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AuditChainReport {
+    pub space_id: String,
+    pub events_checked: usize,
+    pub valid: bool,
+    /// The first broken link; later events aren't checked
+    pub first_break: Option<AuditChainBreak>,
+}
+
+/// Walk the space's audit chain and recompute every hash. Edited events,
+/// events removed from the middle and reordered events all break the chain;
+/// removing the newest events can't be detected from the chain alone.
+pub fn verify_audit_chain(conn: &Connection, space_id: &str) -> Result<AuditChainReport> {
+    let mut stmt = conn.prepare(&format!(
+        "SELECT {} FROM audit_log
+         WHERE space_id = ?1 AND chain_seq IS NOT NULL
+         ORDER BY chain_seq",
+        DATA_AUDIT_COLUMNS
+    ))?;
+    let mut rows = stmt.query([space_id])?;
+
+    let mut report = AuditChainReport {
+        space_id: space_id.to_string(),
+        events_checked: 0,
+        valid: true,
+        first_break: None,
+    };
+    let mut expected_seq = 1;
+    let mut expected_prev = AUDIT_CHAIN_GENESIS.to_string();
+    while let Some(row) = rows.next()? {
+        let event = match data_audit_event_from_row(row) {
+            Ok(event) => event,
+            Err(e) => {
+                report.first_break = Some(AuditChainBreak {
+                    chain_seq: expected_seq,
+                    event_id: row.get(0).ok(),
+                    reason: format!("unreadable event: {}", e),
+                });
+                break;
+            }
+        };
+        report.events_checked += 1;
+
+        let reason = if event.chain_seq != expected_seq {
+            Some(format!(
+                "expected event #{}, found #{}",
+                expected_seq, event.chain_seq
+            ))
+        } else if event.prev_hash != expected_prev {
+            Some("previous hash doesn't match the event before it".to_string())
+        } else if event.compute_hash() != event.hash {
+            Some("event was modified after it was recorded".to_string())
+        } else {
+            None
+        };
+        if let Some(reason) = reason {
+            report.first_break = Some(AuditChainBreak {
+                chain_seq: expected_seq,
+                event_id: Some(event.id),
+                reason,
+            });
+            break;
+        }
+
+        expected_seq += 1;
+        expected_prev = event.hash;
+    }
+
+    report.valid = report.first_break.is_none();
+    Ok(report)
+}
@@ -1,5 +1,7 @@
 /// Authentication Module
 /// Handles user authentication, password hashing, and session management
+use crate::audit;
+use crate::lockout::{self, LockoutPolicy};
 use argon2::{password_hash::SaltString, Argon2, PasswordHash, PasswordHasher, PasswordVerifier};
 use rand_core::OsRng;
 use rusqlite::{Connection, OptionalExtension};
@@ -40,6 +42,9 @@ pub enum AuthError {
 
     #[error("Session already exists")]
     SessionAlreadyExists,
+
+    #[error("Too many failed attempts; try again in {0} seconds")]
+    LockedOut(i64),
 }
 
 impl From<rusqlite::Error> for AuthError {
@@ -71,6 +76,47 @@ pub struct Session {
     pub created_at: i64,
 }
 
+/// Default lifetime of a session (24 hours)
+pub const DEFAULT_SESSION_TTL_SECS: i64 = 24 * 60 * 60;
+
+/// Minimum interval between `last_used_at` writes for the same session, so
+/// validating a token on every request doesn't write on every request
+pub const LAST_USED_WRITE_INTERVAL_SECS: i64 = 60;
+
+/// Device metadata recorded when a session is created
+#[derive(Debug, Clone, Default)]
+pub struct SessionDevice {
+    pub device_name: Option<String>,
+    pub user_agent: Option<String>,
+}
+
+/// Session settings chosen at authenticate time
+#[derive(Debug, Clone)]
+pub struct SessionConfig {
+    /// Lifetime of the session; refreshing extends it by the same amount
+    pub ttl_secs: i64,
+}
+
+impl Default for SessionConfig {
+    fn default() -> Self {
+        SessionConfig {
+            ttl_secs: DEFAULT_SESSION_TTL_SECS,
+        }
+    }
+}
+
+/// Session metadata for listing; never includes the token
+#[derive(Debug, Clone, Serialize)]
+pub struct SessionInfo {
+    pub id: String,
+    pub user_id: String,
+    pub device_name: Option<String>,
+    pub user_agent: Option<String>,
+    pub created_at: i64,
+    pub last_used_at: i64,
+    pub expires_at: i64,
+}
+
 /// Authentication Service (Stateless functions)
 pub struct AuthService;
 
@@ -125,12 +171,49 @@ impl AuthService {
         })
     }
 
-    /// Authenticate a user and create a session
+    /// Authenticate a user and create a session with the default TTL
     pub fn authenticate(
         conn: &Connection,
         username: &str,
         password: &str,
     ) -> Result<Session, AuthError> {
+        Self::authenticate_with_device(
+            conn,
+            username,
+            password,
+            &SessionDevice::default(),
+            &SessionConfig::default(),
+        )
+    }
+
+    /// Authenticate a user and create a session recording the device it was
+    /// created from
+    pub fn authenticate_with_device(
+        conn: &Connection,
+        username: &str,
+        password: &str,
+        device: &SessionDevice,
+        config: &SessionConfig,
+    ) -> Result<Session, AuthError> {
+        let now = chrono::Utc::now().timestamp();
+        let attempt_key = lockout::user_key(username);
+        let user_agent = device.user_agent.as_deref();
+
+        // Refuse before verifying so a locked-out caller can't keep guessing
+        if let Some(retry_after) = lockout::get_attempt_state(conn, &attempt_key)?.retry_after(now)
+        {
+            audit::log_auth_event(
+                conn,
+                None,
+                audit::AUTH_LOGIN_LOCKED_OUT,
+                username,
+                serde_json::json!({ "retry_after_secs": retry_after }),
+                user_agent,
+                now,
+            );
+            return Err(AuthError::LockedOut(retry_after));
+        }
+
         let mut stmt = conn
             .prepare("SELECT id, password_hash FROM users WHERE username = ?1")
             .map_err(|e| AuthError::DatabaseError(e.to_string()))?;
@@ -159,23 +242,66 @@ impl AuthService {
         };
 
         if user_opt.is_some() && verify_ok {
-            Self::create_session(conn, &user_id)
+            let session = Self::create_session(conn, &user_id, device, config)?;
+            lockout::reset_attempts(conn, &attempt_key)?;
+            audit::log_auth_event(
+                conn,
+                Some(&user_id),
+                audit::AUTH_LOGIN_SUCCEEDED,
+                username,
+                serde_json::json!({
+                    "session_id": session.id,
+                    "device_name": device.device_name,
+                }),
+                user_agent,
+                now,
+            );
+            Ok(session)
         } else {
+            let state =
+                lockout::record_failure_at(conn, &attempt_key, &LockoutPolicy::default(), now)?;
+            audit::log_auth_event(
+                conn,
+                user_opt.as_ref().map(|(uid, _)| uid.as_str()),
+                audit::AUTH_LOGIN_FAILED,
+                username,
+                serde_json::json!({
+                    "failures": state.failures,
+                    "locked_until": state.locked_until,
+                }),
+                user_agent,
+                now,
+            );
             Err(AuthError::InvalidCredentials)
         }
     }
 
-    fn create_session(conn: &Connection, user_id: &str) -> Result<Session, AuthError> {
+    fn create_session(
+        conn: &Connection,
+        user_id: &str,
+        device: &SessionDevice,
+        config: &SessionConfig,
+    ) -> Result<Session, AuthError> {
         // Create session
         let session_id = Ulid::new().to_string();
         let session_token = generate_session_token();
         let now = chrono::Utc::now().timestamp();
-        let expires_at = now + (24 * 60 * 60); // 24 hours
+        let expires_at = now + config.ttl_secs;
 
         conn.execute(
-            "INSERT INTO sessions (id, user_id, token, expires_at, created_at)
-             VALUES (?1, ?2, ?3, ?4, ?5)",
-            rusqlite::params![session_id, user_id, session_token, expires_at, now],
+            "INSERT INTO sessions (id, user_id, token, expires_at, created_at,
+                                   last_used_at, device_name, user_agent, ttl_secs)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?5, ?6, ?7, ?8)",
+            rusqlite::params![
+                session_id,
+                user_id,
+                session_token,
+                expires_at,
+                now,
+                device.device_name,
+                device.user_agent,
+                config.ttl_secs
+            ],
         )?;
 
         // Update last_login_at
@@ -193,32 +319,147 @@ impl AuthService {
         })
     }
 
-    /// Validate a session token and return the user ID
+    /// Validate a session token and return the user ID.
+    ///
+    /// Expired tokens are rejected. `last_used_at` is only written when the
+    /// stored value is older than [`LAST_USED_WRITE_INTERVAL_SECS`].
     pub fn validate_session(conn: &Connection, token: &str) -> Result<String, AuthError> {
-        let mut stmt = conn
-            .prepare(
-                "SELECT user_id, expires_at FROM sessions
+        let now = chrono::Utc::now().timestamp();
+        let (session_id, user_id, expires_at, last_used_at) = conn
+            .query_row(
+                "SELECT id, user_id, expires_at, last_used_at FROM sessions
                  WHERE token = ?1",
+                rusqlite::params![token],
+                |row| {
+                    Ok((
+                        row.get::<_, String>(0)?,
+                        row.get::<_, String>(1)?,
+                        row.get::<_, i64>(2)?,
+                        row.get::<_, Option<i64>>(3)?,
+                    ))
+                },
             )
-            .map_err(|e| AuthError::DatabaseError(e.to_string()))?;
+            .map_err(|_| AuthError::InvalidSession)?;
 
-        let now = chrono::Utc::now().timestamp();
-        let user_id = stmt
-            .query_row(rusqlite::params![token], |row| {
-                let user_id: String = row.get(0)?;
-                let expires_at: i64 = row.get(1)?;
+        if expires_at < now {
+            return Err(AuthError::InvalidSession);
+        }
 
-                if expires_at < now {
-                    Err(rusqlite::Error::QueryReturnedNoRows)
-                } else {
-                    Ok(user_id)
-                }
-            })
-            .map_err(|_| AuthError::InvalidSession)?;
+        if last_used_at.is_none_or(|last| now - last >= LAST_USED_WRITE_INTERVAL_SECS) {
+            conn.execute(
+                "UPDATE sessions SET last_used_at = ?1 WHERE id = ?2",
+                rusqlite::params![now, session_id],
+            )?;
+        }
 
         Ok(user_id)
     }
 
+    /// Rotate a session's token and extend its expiry by the session's TTL.
+    /// The old token stops working immediately.
+    pub fn refresh_session(conn: &Connection, token: &str) -> Result<Session, AuthError> {
+        let now = chrono::Utc::now().timestamp();
+        let (session_id, user_id, expires_at, created_at, ttl_secs) = conn
+            .query_row(
+                "SELECT id, user_id, expires_at, created_at, ttl_secs FROM sessions
+                 WHERE token = ?1",
+                rusqlite::params![token],
+                |row| {
+                    Ok((
+                        row.get::<_, String>(0)?,
+                        row.get::<_, String>(1)?,
+                        row.get::<_, i64>(2)?,
+                        row.get::<_, i64>(3)?,
+                        row.get::<_, i64>(4)?,
+                    ))
+                },
+            )
+            .optional()?
+            .ok_or(AuthError::InvalidSession)?;
+
+        if expires_at < now {
+            return Err(AuthError::SessionExpired);
+        }
+
+        let new_token = generate_session_token();
+        let new_expires_at = now + ttl_secs;
+        // Match on the old token so a concurrent refresh can only win once
+        let updated = conn.execute(
+            "UPDATE sessions SET token = ?1, expires_at = ?2, last_used_at = ?3
+             WHERE id = ?4 AND token = ?5",
+            rusqlite::params![new_token, new_expires_at, now, session_id, token],
+        )?;
+        if updated == 0 {
+            return Err(AuthError::InvalidSession);
+        }
+
+        Ok(Session {
+            id: session_id,
+            user_id,
+            token: new_token,
+            expires_at: new_expires_at,
+            created_at,
+        })
+    }
+
+    /// List a user's unexpired sessions, most recently used first
+    pub fn list_sessions(conn: &Connection, user_id: &str) -> Result<Vec<SessionInfo>, AuthError> {
+        let now = chrono::Utc::now().timestamp();
+        let mut stmt = conn.prepare(
+            "SELECT id, user_id, device_name, user_agent, created_at,
+                    COALESCE(last_used_at, created_at), expires_at
+             FROM sessions
+             WHERE user_id = ?1 AND expires_at >= ?2
+             ORDER BY COALESCE(last_used_at, created_at) DESC, created_at DESC",
+        )?;
+
+        let sessions = stmt
+            .query_map(rusqlite::params![user_id, now], |row| {
+                Ok(SessionInfo {
+                    id: row.get(0)?,
+                    user_id: row.get(1)?,
+                    device_name: row.get(2)?,
+                    user_agent: row.get(3)?,
+                    created_at: row.get(4)?,
+                    last_used_at: row.get(5)?,
+                    expires_at: row.get(6)?,
+                })
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(sessions)
+    }
+
+    /// Revoke one of a user's sessions
+    pub fn revoke_session(
+        conn: &Connection,
+        user_id: &str,
+        session_id: &str,
+    ) -> Result<(), AuthError> {
+        let deleted = conn.execute(
+            "DELETE FROM sessions WHERE id = ?1 AND user_id = ?2",
+            rusqlite::params![session_id, user_id],
+        )?;
+        if deleted == 0 {
+            return Err(AuthError::InvalidSession);
+        }
+        Ok(())
+    }
+
+    /// Revoke all of a user's sessions, optionally keeping one (typically the
+    /// caller's own). Returns the number of sessions revoked.
+    pub fn revoke_all_sessions(
+        conn: &Connection,
+        user_id: &str,
+        keep_session_id: Option<&str>,
+    ) -> Result<usize, AuthError> {
+        let deleted = conn.execute(
+            "DELETE FROM sessions WHERE user_id = ?1 AND id IS NOT ?2",
+            rusqlite::params![user_id, keep_session_id],
+        )?;
+        Ok(deleted)
+    }
+
     /// Logout by deleting the session
     pub fn logout(conn: &Connection, token: &str) -> Result<(), AuthError> {
         conn.execute(
@@ -289,20 +530,55 @@ impl AuthService {
         }
 
         // Get user's current password hash
-        let stored_hash: String = conn
+        let (username, stored_hash): (String, String) = conn
             .query_row(
-                "SELECT password_hash FROM users WHERE id = ?1",
+                "SELECT username, password_hash FROM users WHERE id = ?1",
                 rusqlite::params![user_id],
-                |row| row.get(0),
+                |row| Ok((row.get(0)?, row.get(1)?)),
             )
             .map_err(|_| AuthError::UserNotFound)?;
 
+        // Old-password guesses count against the same allowance as logins
+        let now = chrono::Utc::now().timestamp();
+        let attempt_key = lockout::user_key(&username);
+        if let Some(retry_after) = lockout::get_attempt_state(conn, &attempt_key)?.retry_after(now)
+        {
+            audit::log_auth_event(
+                conn,
+                Some(user_id),
+                audit::AUTH_PASSWORD_CHANGE_FAILED,
+                &username,
+                serde_json::json!({ "reason": "locked_out", "retry_after_secs": retry_after }),
+                None,
+                now,
+            );
+            return Err(AuthError::LockedOut(retry_after));
+        }
+
         // Verify old password
-        let parsed_hash =
-            PasswordHash::new(&stored_hash).map_err(|_| AuthError::InvalidCredentials)?;
-        Argon2::default()
-            .verify_password(old_password.as_bytes(), &parsed_hash)
-            .map_err(|_| AuthError::InvalidCredentials)?;
+        let verify_ok = PasswordHash::new(&stored_hash).is_ok_and(|parsed_hash| {
+            Argon2::default()
+                .verify_password(old_password.as_bytes(), &parsed_hash)
+                .is_ok()
+        });
+        if !verify_ok {
+            let state =
+                lockout::record_failure_at(conn, &attempt_key, &LockoutPolicy::default(), now)?;
+            audit::log_auth_event(
+                conn,
+                Some(user_id),
+                audit::AUTH_PASSWORD_CHANGE_FAILED,
+                &username,
+                serde_json::json!({
+                    "reason": "invalid_password",
+                    "failures": state.failures,
+                    "locked_until": state.locked_until,
+                }),
+                None,
+                now,
+            );
+            return Err(AuthError::InvalidCredentials);
+        }
 
         // Hash new password
         let salt = SaltString::generate(&mut OsRng);
@@ -313,11 +589,20 @@ impl AuthService {
             .to_string();
 
         // Update password
-        let now = chrono::Utc::now().timestamp();
         conn.execute(
             "UPDATE users SET password_hash = ?1, updated_at = ?2 WHERE id = ?3",
             rusqlite::params![new_password_hash, now, user_id],
         )?;
+        lockout::reset_attempts(conn, &attempt_key)?;
+        audit::log_auth_event(
+            conn,
+            Some(user_id),
+            audit::AUTH_PASSWORD_CHANGED,
+            &username,
+            serde_json::json!({}),
+            None,
+            now,
+        );
 
         Ok(())
     }
@@ -365,12 +650,27 @@ mod tests {
                 token TEXT UNIQUE NOT NULL,
                 expires_at INTEGER NOT NULL,
                 created_at INTEGER NOT NULL,
+                last_used_at INTEGER,
+                device_name TEXT,
+                user_agent TEXT,
+                ttl_secs INTEGER NOT NULL DEFAULT 86400,
                 FOREIGN KEY (user_id) REFERENCES users(id) ON DELETE CASCADE
             )",
             [],
         )
         .expect("Failed to create sessions table");
 
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS auth_attempt (
+                key TEXT PRIMARY KEY,
+                failures INTEGER NOT NULL DEFAULT 0,
+                last_failure_at INTEGER,
+                locked_until INTEGER
+            )",
+            [],
+        )
+        .expect("Failed to create auth_attempt table");
+
         (Arc::new(Mutex::new(conn)), dir)
     }
 
@@ -0,0 +1,569 @@
+//! Automation rules
+//!
+//! Per-space "when this happens, do that" rules: when `#reading` is added
+//! to a note, create a task to summarise it and a flashcard; when a note
+//! titled `Meeting ...` is created, ask the user whether to file it. A rule
+//! has one trigger and runs its actions in order, all or nothing.
+//!
+//! The mutation functions that can trigger a rule run the space's rules
+//! themselves. Changes made by a rule carry [`AUDIT_SOURCE_AUTOMATION`] as
+//! their source and never trigger rules in turn, so two rules that add each
+//! other's tag can't loop.
+
+use crate::audit::AUDIT_SOURCE_AUTOMATION;
+use crate::db::DbError;
+use crate::events::{emit, CoreEvent};
+use crate::srs::create_knowledge_card;
+use crate::tag::{
+    add_tag_to_note_from, add_tag_to_task_from, is_same_or_descendant, normalize_tag_name, TagError,
+};
+use crate::task::{create_task_from, get_task, update_task_from, Task};
+use chrono::Utc;
+use regex::{Regex, RegexBuilder};
+use rusqlite::{params, Connection, OptionalExtension, Row};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use ulid::Ulid;
+
+const DAY_SECS: i64 = 86_400;
+
+/// Placeholder in task titles and prompts for the title of the note or task
+/// the rule ran on
+pub const TITLE_PLACEHOLDER: &str = "{{title}}";
+
+#[derive(Error, Debug)]
+pub enum AutomationError {
+    #[error("Rusqlite error: {0}")]
+    Rusqlite(#[from] rusqlite::Error),
+    #[error("Database error: {0}")]
+    Db(#[from] DbError),
+    #[error("Tag error: {0}")]
+    Tag(#[from] TagError),
+    #[error("Serialization error: {0}")]
+    Serialization(#[from] serde_json::Error),
+    #[error("Automation rule not found: {0}")]
+    NotFound(String),
+    #[error("Invalid automation rule: {0}")]
+    InvalidRule(String),
+}
+
+/// What a rule reacts to
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum AutomationTrigger {
+    /// The tag, or a tag nested below it, was added to a note
+    TagAdded { tag: String },
+    /// A task was marked done
+    TaskCompleted,
+    /// A note was created with a title matching the pattern, a
+    /// case-insensitive regular expression. An empty pattern matches every
+    /// note.
+    NoteCreated {
+        #[serde(default)]
+        title_pattern: String,
+    },
+}
+
+impl AutomationTrigger {
+    fn kind(&self) -> &'static str {
+        match self {
+            AutomationTrigger::TagAdded { .. } => "tag_added",
+            AutomationTrigger::TaskCompleted => "task_completed",
+            AutomationTrigger::NoteCreated { .. } => "note_created",
+        }
+    }
+}
+
+/// Something a rule does. Actions run on the note or task the rule was
+/// triggered by.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum AutomationAction {
+    /// A new task linked to the note, or a follow-up in the completed
+    /// task's note and project. `{{title}}` in the title is replaced.
+    CreateTask {
+        title: String,
+        #[serde(default)]
+        description: Option<String>,
+        #[serde(default)]
+        due_in_days: Option<i64>,
+    },
+    /// Tag the note or task
+    AddTag { tag: String },
+    /// Move the task, or every task of the note, to a project of the space
+    MoveToProject { project_id: String },
+    /// A flashcard for the note, or for the completed task's note. Notes
+    /// that already have a card are left alone.
+    CreateSrsCard,
+    /// Emit [`CoreEvent::AutomationPrompt`] so the app can ask the user.
+    /// `{{title}}` in the message is replaced.
+    Prompt { message: String },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AutomationRule {
+    pub id: String,
+    pub space_id: String,
+    pub name: String,
+    pub trigger: AutomationTrigger,
+    pub actions: Vec<AutomationAction>,
+    pub enabled: bool,
+    pub created_at: i64,
+    pub updated_at: i64,
+}
+
+const RULE_COLUMNS: &str =
+    "id, space_id, name, trigger_json, actions_json, enabled, created_at, updated_at";
+
+impl AutomationRule {
+    fn from_row(row: &Row) -> rusqlite::Result<Self> {
+        let trigger_json: String = row.get(3)?;
+        let actions_json: String = row.get(4)?;
+        Ok(AutomationRule {
+            id: row.get(0)?,
+            space_id: row.get(1)?,
+            name: row.get(2)?,
+            trigger: serde_json::from_str(&trigger_json).map_err(|e| {
+                rusqlite::Error::FromSqlConversionFailure(3, rusqlite::types::Type::Text, e.into())
+            })?,
+            actions: serde_json::from_str(&actions_json).map_err(|e| {
+                rusqlite::Error::FromSqlConversionFailure(4, rusqlite::types::Type::Text, e.into())
+            })?,
+            enabled: row.get(5)?,
+            created_at: row.get(6)?,
+            updated_at: row.get(7)?,
+        })
+    }
+}
+
+/// A change rules can react to
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum AutomationEvent {
+    TagAdded { note_id: String, tag: String },
+    TaskCompleted { task_id: String },
+    NoteCreated { note_id: String },
+}
+
+impl AutomationEvent {
+    fn kind(&self) -> &'static str {
+        match self {
+            AutomationEvent::TagAdded { .. } => "tag_added",
+            AutomationEvent::TaskCompleted { .. } => "task_completed",
+            AutomationEvent::NoteCreated { .. } => "note_created",
+        }
+    }
+}
+
+/// The note or task an event happened to
+struct Subject {
+    space_id: String,
+    title: String,
+    /// The note itself, or the task's note
+    note_id: Option<String>,
+    /// Set when the subject is a task
+    task: Option<Task>,
+}
+
+impl Subject {
+    fn entity(&self) -> (&'static str, String) {
+        match (&self.task, &self.note_id) {
+            (Some(task), _) => ("task", task.id.to_string()),
+            (None, Some(note_id)) => ("note", note_id.clone()),
+            (None, None) => unreachable!("a subject is a note or a task"),
+        }
+    }
+}
+
+/// Check a rule, normalizing its tag names
+fn validate_rule(
+    conn: &Connection,
+    space_id: &str,
+    name: &str,
+    trigger: &mut AutomationTrigger,
+    actions: &mut [AutomationAction],
+) -> Result<(), AutomationError> {
+    let invalid = AutomationError::InvalidRule;
+    if name.trim().is_empty() {
+        return Err(invalid("The name is empty".to_string()));
+    }
+    match trigger {
+        AutomationTrigger::TagAdded { tag } => *tag = normalize_tag_name(tag)?,
+        AutomationTrigger::TaskCompleted => {}
+        AutomationTrigger::NoteCreated { title_pattern } => {
+            title_regex(title_pattern)
+                .map_err(|e| invalid(format!("Invalid title pattern: {}", e)))?;
+        }
+    }
+    if actions.is_empty() {
+        return Err(invalid("A rule needs at least one action".to_string()));
+    }
+    for action in actions.iter_mut() {
+        match action {
+            AutomationAction::CreateTask { title, .. } if title.trim().is_empty() => {
+                return Err(invalid("The task title is empty".to_string()));
+            }
+            AutomationAction::CreateTask { .. } | AutomationAction::CreateSrsCard => {}
+            AutomationAction::AddTag { tag } => *tag = normalize_tag_name(tag)?,
+            AutomationAction::MoveToProject { project_id } => {
+                project_in_space(conn, space_id, project_id)?;
+            }
+            AutomationAction::Prompt { message } if message.trim().is_empty() => {
+                return Err(invalid("The prompt message is empty".to_string()));
+            }
+            AutomationAction::Prompt { .. } => {}
+        }
+    }
+    Ok(())
+}
+
+fn title_regex(pattern: &str) -> Result<Regex, regex::Error> {
+    RegexBuilder::new(pattern).case_insensitive(true).build()
+}
+
+fn project_in_space(
+    conn: &Connection,
+    space_id: &str,
+    project_id: &str,
+) -> Result<(), AutomationError> {
+    let project_space: Option<String> = conn
+        .query_row(
+            "SELECT space_id FROM project WHERE id = ?1",
+            [project_id],
+            |row| row.get(0),
+        )
+        .optional()?;
+    match project_space {
+        Some(s) if s == space_id => Ok(()),
+        Some(_) => Err(AutomationError::InvalidRule(format!(
+            "Project {} belongs to another space",
+            project_id
+        ))),
+        None => Err(DbError::NotFound {
+            entity: "project",
+            id: project_id.to_string(),
+        }
+        .into()),
+    }
+}
+
+pub fn create_automation_rule(
+    conn: &Connection,
+    space_id: &str,
+    name: &str,
+    mut trigger: AutomationTrigger,
+    mut actions: Vec<AutomationAction>,
+) -> Result<AutomationRule, AutomationError> {
+    validate_rule(conn, space_id, name, &mut trigger, &mut actions)?;
+    let now = Utc::now().timestamp();
+    let rule = AutomationRule {
+        id: Ulid::new().to_string(),
+        space_id: space_id.to_string(),
+        name: name.trim().to_string(),
+        trigger,
+        actions,
+        enabled: true,
+        created_at: now,
+        updated_at: now,
+    };
+    conn.execute(
+        "INSERT INTO automation_rule
+             (id, space_id, name, trigger_type, trigger_json, actions_json, enabled,
+              created_at, updated_at)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, 1, ?7, ?7)",
+        params![
+            rule.id,
+            rule.space_id,
+            rule.name,
+            rule.trigger.kind(),
+            serde_json::to_string(&rule.trigger)?,
+            serde_json::to_string(&rule.actions)?,
+            now
+        ],
+    )?;
+    log::info!(
+        "[automation] Created {} rule '{}' in space {}",
+        rule.trigger.kind(),
+        rule.name,
+        space_id
+    );
+    Ok(rule)
+}
+
+/// The space's rules, oldest first
+pub fn get_automation_rules(
+    conn: &Connection,
+    space_id: &str,
+) -> Result<Vec<AutomationRule>, AutomationError> {
+    let mut stmt = conn.prepare(&format!(
+        "SELECT {} FROM automation_rule WHERE space_id = ?1 ORDER BY created_at, id",
+        RULE_COLUMNS
+    ))?;
+    let rules = stmt
+        .query_map([space_id], AutomationRule::from_row)?
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(rules)
+}
+
+pub fn get_automation_rule(
+    conn: &Connection,
+    id: &str,
+) -> Result<Option<AutomationRule>, AutomationError> {
+    Ok(conn
+        .query_row(
+            &format!("SELECT {} FROM automation_rule WHERE id = ?1", RULE_COLUMNS),
+            [id],
+            AutomationRule::from_row,
+        )
+        .optional()?)
+}
+
+pub fn set_automation_rule_enabled(
+    conn: &Connection,
+    id: &str,
+    enabled: bool,
+) -> Result<(), AutomationError> {
+    let updated = conn.execute(
+        "UPDATE automation_rule SET enabled = ?1, updated_at = ?2 WHERE id = ?3",
+        params![enabled, Utc::now().timestamp(), id],
+    )?;
+    if updated == 0 {
+        return Err(AutomationError::NotFound(id.to_string()));
+    }
+    Ok(())
+}
+
+pub fn delete_automation_rule(conn: &Connection, id: &str) -> Result<(), AutomationError> {
+    if conn.execute("DELETE FROM automation_rule WHERE id = ?1", [id])? == 0 {
+        return Err(AutomationError::NotFound(id.to_string()));
+    }
+    Ok(())
+}
+
+/// Run the enabled rules of the event's space that it triggers, in the
+/// order they were created. Each rule's actions apply together or not at
+/// all. Returns the ids of the rules that ran.
+pub fn run_automation_for_event(
+    conn: &Connection,
+    event: &AutomationEvent,
+) -> Result<Vec<String>, AutomationError> {
+    let Some(subject) = load_subject(conn, event)? else {
+        return Ok(Vec::new());
+    };
+    let mut stmt = conn.prepare(&format!(
+        "SELECT {} FROM automation_rule
+         WHERE space_id = ?1 AND trigger_type = ?2 AND enabled = 1
+         ORDER BY created_at, id",
+        RULE_COLUMNS
+    ))?;
+    let rules = stmt
+        .query_map(
+            [subject.space_id.as_str(), event.kind()],
+            AutomationRule::from_row,
+        )?
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let mut ran = Vec::new();
+    for rule in rules {
+        if !trigger_matches(&rule.trigger, event, &subject) {
+            continue;
+        }
+        conn.execute_batch("SAVEPOINT automation_rule")?;
+        let applied = rule
+            .actions
+            .iter()
+            .try_for_each(|action| apply_action(conn, &rule, action, &subject));
+        match applied {
+            Ok(()) => conn.execute_batch("RELEASE automation_rule")?,
+            Err(e) => {
+                conn.execute_batch("ROLLBACK TO automation_rule; RELEASE automation_rule")?;
+                return Err(e);
+            }
+        }
+        log::info!(
+            "[automation] Rule '{}' ran on {} {}",
+            rule.name,
+            subject.entity().0,
+            subject.entity().1
+        );
+        ran.push(rule.id);
+    }
+    Ok(ran)
+}
+
+/// Whether the space has enabled rules triggered by `trigger_type`, e.g.
+/// `tag_added`. Bulk edits ask once per space instead of loading each
+/// changed note or task only to find there is nothing to run.
+pub(crate) fn has_enabled_rules(
+    conn: &Connection,
+    space_id: &str,
+    trigger_type: &str,
+) -> Result<bool, DbError> {
+    Ok(conn.query_row(
+        "SELECT EXISTS (SELECT 1 FROM automation_rule
+         WHERE space_id = ?1 AND trigger_type = ?2 AND enabled = 1)",
+        [space_id, trigger_type],
+        |row| row.get(0),
+    )?)
+}
+
+/// Run rules for a change made by `source`. Changes made by rules never
+/// trigger rules. A failing rule is logged rather than failing the change
+/// that triggered it.
+pub(crate) fn run_automation_from(conn: &Connection, event: AutomationEvent, source: &str) {
+    if source == AUDIT_SOURCE_AUTOMATION {
+        return;
+    }
+    if let Err(e) = run_automation_for_event(conn, &event) {
+        log::warn!("[automation] Rules for {:?} failed: {}", event, e);
+    }
+}
+
+fn load_subject(
+    conn: &Connection,
+    event: &AutomationEvent,
+) -> Result<Option<Subject>, AutomationError> {
+    match event {
+        AutomationEvent::TagAdded { note_id, .. } | AutomationEvent::NoteCreated { note_id } => {
+            Ok(conn
+                .query_row(
+                    "SELECT space_id, title FROM note WHERE id = ?1",
+                    [note_id],
+                    |row| {
+                        Ok(Subject {
+                            space_id: row.get(0)?,
+                            title: row.get(1)?,
+                            note_id: Some(note_id.clone()),
+                            task: None,
+                        })
+                    },
+                )
+                .optional()?)
+        }
+        AutomationEvent::TaskCompleted { task_id } => {
+            let id = Ulid::from_string(task_id)
+                .map_err(|e| DbError::Message(format!("Invalid task ID: {}", e)))?;
+            Ok(get_task(conn, id)?.map(|task| Subject {
+                space_id: task.space_id.to_string(),
+                title: task.title.clone(),
+                note_id: task.note_id.map(|id| id.to_string()),
+                task: Some(task),
+            }))
+        }
+    }
+}
+
+fn trigger_matches(
+    trigger: &AutomationTrigger,
+    event: &AutomationEvent,
+    subject: &Subject,
+) -> bool {
+    match (trigger, event) {
+        (AutomationTrigger::TagAdded { tag }, AutomationEvent::TagAdded { tag: added, .. }) => {
+            is_same_or_descendant(added, tag)
+        }
+        (AutomationTrigger::TaskCompleted, AutomationEvent::TaskCompleted { .. }) => true,
+        (AutomationTrigger::NoteCreated { title_pattern }, AutomationEvent::NoteCreated { .. }) => {
+            title_regex(title_pattern).is_ok_and(|re| re.is_match(&subject.title))
+        }
+        _ => false,
+    }
+}
+
+fn apply_action(
+    conn: &Connection,
+    rule: &AutomationRule,
+    action: &AutomationAction,
+    subject: &Subject,
+) -> Result<(), AutomationError> {
+    let space_id = Ulid::from_string(&subject.space_id)
+        .map_err(|e| DbError::Message(format!("Invalid space ID: {}", e)))?;
+    match action {
+        AutomationAction::CreateTask {
+            title,
+            description,
+            due_in_days,
+        } => {
+            let title = title.replace(TITLE_PLACEHOLDER, &subject.title);
+            let mut task = create_task_from(
+                conn,
+                space_id,
+                &title,
+                description.clone(),
+                AUDIT_SOURCE_AUTOMATION,
+            )?;
+            task.note_id = subject
+                .note_id
+                .as_deref()
+                .and_then(|id| Ulid::from_string(id).ok());
+            task.project_id = subject.task.as_ref().and_then(|t| t.project_id);
+            task.due_at = due_in_days.map(|days| Utc::now().timestamp() + days * DAY_SECS);
+            update_task_from(conn, &task, AUDIT_SOURCE_AUTOMATION)?;
+        }
+        AutomationAction::AddTag { tag } => match (&subject.task, &subject.note_id) {
+            (Some(task), _) => {
+                add_tag_to_task_from(conn, &task.id.to_string(), tag, AUDIT_SOURCE_AUTOMATION)?;
+            }
+            (None, Some(note_id)) => {
+                add_tag_to_note_from(conn, note_id, tag, AUDIT_SOURCE_AUTOMATION)?;
+            }
+            (None, None) => {}
+        },
+        AutomationAction::MoveToProject { project_id } => {
+            project_in_space(conn, &subject.space_id, project_id)?;
+            let project = Ulid::from_string(project_id)
+                .map_err(|e| DbError::Message(format!("Invalid project ID: {}", e)))?;
+            let tasks = match (&subject.task, &subject.note_id) {
+                (Some(task), _) => vec![task.clone()],
+                (None, Some(note_id)) => note_tasks(conn, note_id)?,
+                (None, None) => Vec::new(),
+            };
+            for mut task in tasks {
+                task.project_id = Some(project);
+                update_task_from(conn, &task, AUDIT_SOURCE_AUTOMATION)?;
+            }
+        }
+        AutomationAction::CreateSrsCard => {
+            // A task without a note has nothing to study
+            let Some(note_id) = &subject.note_id else {
+                return Ok(());
+            };
+            let has_card: bool = conn.query_row(
+                "SELECT EXISTS(SELECT 1 FROM knowledge_card WHERE note_id = ?1)",
+                [note_id],
+                |row| row.get(0),
+            )?;
+            if !has_card {
+                let note = Ulid::from_string(note_id)
+                    .map_err(|e| DbError::Message(format!("Invalid note ID: {}", e)))?;
+                create_knowledge_card(conn, note)?;
+            }
+        }
+        AutomationAction::Prompt { message } => {
+            let (entity_type, entity_id) = subject.entity();
+            emit(CoreEvent::AutomationPrompt {
+                space_id: subject.space_id.clone(),
+                rule_id: rule.id.clone(),
+                rule_name: rule.name.clone(),
+                message: message.replace(TITLE_PLACEHOLDER, &subject.title),
+                entity_type: entity_type.to_string(),
+                entity_id,
+            });
+        }
+    }
+    Ok(())
+}
+
+fn note_tasks(conn: &Connection, note_id: &str) -> Result<Vec<Task>, AutomationError> {
+    let mut stmt = conn.prepare("SELECT id FROM task WHERE note_id = ?1")?;
+    let ids = stmt
+        .query_map([note_id], |row| row.get::<_, String>(0))?
+        .collect::<Result<Vec<_>, _>>()?;
+    let mut tasks = Vec::new();
+    for id in ids {
+        let id = Ulid::from_string(&id)
+            .map_err(|e| DbError::Message(format!("Invalid task ID: {}", e)))?;
+        tasks.extend(get_task(conn, id)?);
+    }
+    Ok(tasks)
+}
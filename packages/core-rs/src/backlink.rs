@@ -1,6 +1,9 @@
 use crate::db::DbError;
 use regex::Regex;
-use rusqlite::{Connection, Result};
+use rusqlite::{Connection, OptionalExtension, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
+use std::ops::Range;
 use ulid::Ulid;
 
 #[derive(Debug)]
@@ -9,6 +12,29 @@ pub struct Backlink {
     pub target_note_id: Ulid,
 }
 
+/// Links added and removed by `sync_note_links`
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct LinkDiff {
+    pub added: Vec<String>,
+    pub removed: Vec<String>,
+}
+
+/// An occurrence of a note's title in another note that is not a link yet
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct UnlinkedMention {
+    pub source_note_id: String,
+    pub source_title: String,
+    /// Byte offset of the mention within the source note's content
+    pub offset: usize,
+    /// Byte length of the mention
+    pub length: usize,
+    /// Text surrounding the mention
+    pub context: String,
+}
+
+/// Characters of context shown on each side of an unlinked mention
+const MENTION_CONTEXT_CHARS: usize = 40;
+
 pub fn find_backlinks(conn: &Connection, note_id: Ulid) -> Result<Vec<Backlink>, DbError> {
     log::info!("[backlink] Finding backlinks for note: {}", note_id);
     let mut stmt = conn.prepare("SELECT source_note_id FROM link WHERE target_note_id = ?1")?;
@@ -32,29 +58,288 @@ pub fn find_backlinks(conn: &Connection, note_id: Ulid) -> Result<Vec<Backlink>,
 }
 
 pub fn update_links(conn: &Connection, note_id: Ulid, content: &str) -> Result<(), DbError> {
-    log::info!("[backlink] Updating links for note: {}", note_id);
-    conn.execute(
-        "DELETE FROM link WHERE source_note_id = ?1",
-        [note_id.to_string()],
+    sync_note_links(conn, note_id, content)?;
+    Ok(())
+}
+
+/// Re-parse a note's links and bring its `link` rows in line with them.
+///
+/// Understands `[[target]]`, `[[target|alias]]`, `[[target#heading]]` and
+/// markdown links to internal notes (`[text](<ulid>)`, `[text](note://<ulid>)`,
+/// `[text](Some%20Title.md)`). Targets are note ids or titles in the same
+/// space. Only the difference is written, inside a single savepoint.
+pub fn sync_note_links(
+    conn: &Connection,
+    note_id: Ulid,
+    content: &str,
+) -> Result<LinkDiff, DbError> {
+    let source = note_id.to_string();
+
+    let space_id: Option<String> = conn
+        .query_row(
+            "SELECT space_id FROM note WHERE id = ?1",
+            [&source],
+            |row| row.get(0),
+        )
+        .optional()?
+        .flatten();
+
+    let mut resolve_title = conn.prepare_cached(
+        "SELECT id FROM note
+         WHERE lower(title) = lower(?1) AND space_id IS ?2 AND is_trashed = 0
+         ORDER BY created_at LIMIT 1",
+    )?;
+
+    let mut targets = BTreeSet::new();
+    for reference in parse_link_references(content) {
+        let target = match Ulid::from_string(&reference) {
+            Ok(id) => Some(id.to_string()),
+            Err(_) => resolve_title
+                .query_row(rusqlite::params![reference, space_id], |row| row.get(0))
+                .optional()?,
+        };
+        if let Some(target) = target {
+            if target != source {
+                targets.insert(target);
+            }
+        }
+    }
+
+    let existing: BTreeSet<String> = {
+        let mut stmt =
+            conn.prepare_cached("SELECT target_note_id FROM link WHERE source_note_id = ?1")?;
+        let rows = stmt.query_map([&source], |row| row.get(0))?;
+        rows.collect::<Result<_>>()?
+    };
+
+    let diff = LinkDiff {
+        added: targets.difference(&existing).cloned().collect(),
+        removed: existing.difference(&targets).cloned().collect(),
+    };
+    if diff.added.is_empty() && diff.removed.is_empty() {
+        return Ok(diff);
+    }
+
+    conn.execute_batch("SAVEPOINT sync_note_links")?;
+    let written = (|| -> Result<()> {
+        let mut insert = conn.prepare_cached(
+            "INSERT OR IGNORE INTO link (source_note_id, target_note_id) VALUES (?1, ?2)",
+        )?;
+        for target in &diff.added {
+            insert.execute([&source, target])?;
+        }
+        let mut delete = conn
+            .prepare_cached("DELETE FROM link WHERE source_note_id = ?1 AND target_note_id = ?2")?;
+        for target in &diff.removed {
+            delete.execute([&source, target])?;
+        }
+        Ok(())
+    })();
+
+    match written {
+        Ok(()) => conn.execute_batch("RELEASE sync_note_links")?,
+        Err(e) => {
+            conn.execute_batch("ROLLBACK TO sync_note_links; RELEASE sync_note_links")?;
+            return Err(e.into());
+        }
+    }
+
+    log::debug!(
+        "[backlink] Note {}: {} links added, {} removed",
+        source,
+        diff.added.len(),
+        diff.removed.len()
+    );
+    Ok(diff)
+}
+
+/// Raw link targets (ids or titles) referenced from markdown content
+fn parse_link_references(content: &str) -> Vec<String> {
+    let wiki = Regex::new(r"\[\[([^\[\]]+?)\]\]").expect("valid regex");
+    let markdown = Regex::new(r"\[[^\[\]]*\]\(([^()\s]+)\)").expect("valid regex");
+    let masked = code_ranges(content);
+    let outside_code = |start: usize| !masked.iter().any(|r| r.contains(&start));
+
+    let mut references = Vec::new();
+
+    for cap in wiki.captures_iter(content) {
+        if !outside_code(cap.get(0).map_or(0, |m| m.start())) {
+            continue;
+        }
+        let inner = &cap[1];
+        let target = inner.split(['|', '#']).next().unwrap_or("").trim();
+        if !target.is_empty() {
+            references.push(target.to_string());
+        }
+    }
+
+    for cap in markdown.captures_iter(content) {
+        if !outside_code(cap.get(0).map_or(0, |m| m.start())) {
+            continue;
+        }
+        if let Some(target) = internal_markdown_target(&cap[1]) {
+            references.push(target);
+        }
+    }
+
+    references
+}
+
+/// The note a markdown link URL points at, if it is internal
+fn internal_markdown_target(url: &str) -> Option<String> {
+    let url = url.split('#').next().unwrap_or("");
+    if let Some(id) = url
+        .strip_prefix("note://")
+        .or_else(|| url.strip_prefix("noteece://note/"))
+    {
+        return Some(id.trim_end_matches('/').to_string());
+    }
+    if url.is_empty() || url.contains("://") || url.starts_with("mailto:") {
+        return None;
+    }
+
+    let path = url.trim_start_matches("./");
+    let name = path.rsplit('/').next().unwrap_or(path);
+    let name = name.strip_suffix(".md").unwrap_or(name);
+    let name = name.replace("%20", " ");
+    if name.is_empty() {
+        None
+    } else {
+        Some(name)
+    }
+}
+
+/// Byte ranges covered by fenced code blocks and inline code spans
+pub(crate) fn code_ranges(content: &str) -> Vec<Range<usize>> {
+    let mut ranges = Vec::new();
+
+    // Fenced blocks: ``` or ~~~ at the start of a line, until the matching fence
+    let mut fence: Option<(&str, usize)> = None;
+    let mut offset = 0;
+    for line in content.split_inclusive('\n') {
+        let trimmed = line.trim_start();
+        match fence {
+            None => {
+                if trimmed.starts_with("```") {
+                    fence = Some(("```", offset));
+                } else if trimmed.starts_with("~~~") {
+                    fence = Some(("~~~", offset));
+                }
+            }
+            Some((marker, start)) => {
+                if trimmed.starts_with(marker) {
+                    ranges.push(start..offset + line.len());
+                    fence = None;
+                }
+            }
+        }
+        offset += line.len();
+    }
+    if let Some((_, start)) = fence {
+        // Unterminated fence runs to the end of the note
+        ranges.push(start..content.len());
+    }
+
+    // Inline code spans outside fenced blocks
+    let inline = Regex::new(r"`[^`\n]+`").expect("valid regex");
+    for m in inline.find_iter(content) {
+        if !ranges.iter().any(|r| r.contains(&m.start())) {
+            ranges.push(m.range());
+        }
+    }
+
+    ranges
+}
+
+/// Byte ranges already covered by wiki or markdown links
+fn link_ranges(content: &str) -> Vec<Range<usize>> {
+    let links = Regex::new(r"\[\[[^\[\]]+?\]\]|\[[^\[\]]*\]\([^()\s]+\)").expect("valid regex");
+    links.find_iter(content).map(|m| m.range()).collect()
+}
+
+/// Find places in other notes that mention this note's title without linking to it.
+///
+/// Candidates come from the full-text index; each is then scanned for
+/// whole-word, case-insensitive occurrences of the title that are not inside
+/// code blocks, inline code, or an existing link.
+pub fn find_unlinked_mentions(
+    conn: &Connection,
+    note_id: Ulid,
+) -> Result<Vec<UnlinkedMention>, DbError> {
+    log::info!("[backlink] Finding unlinked mentions for note: {}", note_id);
+
+    let note: Option<(String, Option<String>)> = conn
+        .query_row(
+            "SELECT title, space_id FROM note WHERE id = ?1",
+            [note_id.to_string()],
+            |row| Ok((row.get(0)?, row.get(1)?)),
+        )
+        .optional()?;
+    let Some((title, space_id)) = note else {
+        return Ok(Vec::new());
+    };
+    let title = title.trim().to_string();
+    if title.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    // Phrase query against the content column; quotes are escaped by doubling
+    let fts_query = format!("content_md : \"{}\"", title.replace('"', "\"\""));
+    let mut stmt = conn.prepare(
+        "SELECT n.id, n.title, n.content_md FROM fts_note
+         JOIN note n ON n.rowid = fts_note.rowid
+         WHERE fts_note MATCH ?1 AND n.id != ?2 AND n.is_trashed = 0 AND n.space_id IS ?3",
     )?;
-    // Regex compilation should ideally happen once (lazy_static or similar), but unwrap on valid regex literal is "safe" if tests pass.
-    // However, to be strictly robust, we can handle it or trust the literal.
-    // Given "100% robustness" requirement, let's avoid panic even on regex (though unlikely).
-    let re = Regex::new(r"\[\[(.+?)\]\]")
+    let candidates = stmt
+        .query_map(
+            rusqlite::params![fts_query, note_id.to_string(), space_id],
+            |row| Ok((row.get::<_, String>(0)?, row.get(1)?, row.get(2)?)),
+        )?
+        .collect::<Result<Vec<(String, String, String)>>>()?;
+
+    let pattern = Regex::new(&format!("(?i){}", regex::escape(&title)))
         .map_err(|e| DbError::Message(format!("Regex error: {}", e)))?;
-    for cap in re.captures_iter(content) {
-        let target_note_id_str = &cap[1];
-        if let Ok(target_note_id) = Ulid::from_string(target_note_id_str) {
-            log::info!(
-                "[backlink] Found link from {} to {}",
-                note_id,
-                target_note_id
-            );
-            conn.execute(
-                "INSERT INTO link (source_note_id, target_note_id) VALUES (?1, ?2)",
-                rusqlite::params![note_id.to_string(), target_note_id.to_string()],
-            )?;
+
+    let mut mentions = Vec::new();
+    for (source_id, source_title, content) in candidates {
+        let mut excluded = code_ranges(&content);
+        excluded.extend(link_ranges(&content));
+
+        for m in pattern.find_iter(&content) {
+            if excluded.iter().any(|r| r.contains(&m.start())) {
+                continue;
+            }
+            if !is_word_boundary(&content, m.start(), m.end()) {
+                continue;
+            }
+            mentions.push(UnlinkedMention {
+                source_note_id: source_id.clone(),
+                source_title: source_title.clone(),
+                offset: m.start(),
+                length: m.len(),
+                context: mention_context(&content, m.start(), m.end()),
+            });
         }
     }
-    Ok(())
+
+    Ok(mentions)
+}
+
+fn is_word_boundary(content: &str, start: usize, end: usize) -> bool {
+    let before = content[..start].chars().next_back();
+    let after = content[end..].chars().next();
+    !before.is_some_and(char::is_alphanumeric) && !after.is_some_and(char::is_alphanumeric)
+}
+
+fn mention_context(content: &str, start: usize, end: usize) -> String {
+    let from = content[..start]
+        .char_indices()
+        .rev()
+        .nth(MENTION_CONTEXT_CHARS - 1)
+        .map_or(0, |(i, _)| i);
+    let to = content[end..]
+        .char_indices()
+        .nth(MENTION_CONTEXT_CHARS)
+        .map_or(content.len(), |(i, _)| end + i);
+    content[from..to].replace('\n', " ").trim().to_string()
 }
@@ -1,3 +1,5 @@
+pub mod note_bundle;
+
 use std::fs;
 use std::io::{Read, Write};
 use std::path::Path;
@@ -0,0 +1,374 @@
+//! Encrypted Note Bundles
+//!
+//! A bundle carries one note and its attachments to someone outside the
+//! vault. It is a single file: a plaintext header followed by a zip archive
+//! encrypted with XChaCha20-Poly1305 under a key derived from a passphrase
+//! with Argon2id.
+//!
+//! ```text
+//! magic "NOTEECEB" | version u8 | m_cost u32 | t_cost u32 | p_cost u32 | salt [16] | nonce [24] | ciphertext
+//! ```
+//!
+//! The header is authenticated as associated data, so altering the KDF
+//! parameters or salt fails decryption like a wrong passphrase does. The
+//! archive holds `note.json` and one `attachments/<n>` entry per attachment;
+//! nothing derived from the vault key and nothing about the note's space goes
+//! into it.
+
+use crate::blob::{
+    attach_blob_to_note, get_note_attachments, insert_blob, retrieve_blob, BlobError,
+};
+use crate::crypto::{
+    decrypt_bytes_with_aad, derive_key_argon2, encrypt_bytes_with_aad, CryptoError,
+};
+use crate::db::DbError;
+use crate::note::{create_note, get_note, DbUlid, Note};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io::{Cursor, Read, Write};
+use std::path::Path;
+use thiserror::Error;
+use ulid::Ulid;
+use zip::write::{FileOptions, ZipWriter};
+use zip::{CompressionMethod, ZipArchive};
+
+const MAGIC: &[u8; 8] = b"NOTEECEB";
+pub const BUNDLE_VERSION: u8 = 1;
+const SALT_LEN: usize = 16;
+const HEADER_LEN: usize = MAGIC.len() + 1 + 3 * 4 + SALT_LEN;
+
+/// Argon2id cost of new bundles: 19 MiB, 2 passes, 1 lane
+const ARGON2_M_COST: u32 = 19_456;
+const ARGON2_T_COST: u32 = 2;
+const ARGON2_P_COST: u32 = 1;
+
+/// Largest costs accepted from a bundle header (1 GiB, 16 passes, 16 lanes),
+/// so a crafted file can't make the import allocate or spin without bound
+const MAX_M_COST: u32 = 1 << 20;
+const MAX_T_COST: u32 = 16;
+const MAX_P_COST: u32 = 16;
+
+/// Most an archive may unpack to on import (1 GiB across all entries), so a
+/// small bundle can't expand without bound
+const MAX_UNPACKED_BYTES: u64 = 1 << 30;
+
+const NOTE_ENTRY: &str = "note.json";
+
+#[derive(Error, Debug)]
+pub enum NoteBundleError {
+    #[error("I/O error: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("Zip error: {0}")]
+    Zip(#[from] zip::result::ZipError),
+    #[error("JSON error: {0}")]
+    Json(#[from] serde_json::Error),
+    #[error("Database error: {0}")]
+    Database(#[from] DbError),
+    #[error("Blob error: {0}")]
+    Blob(#[from] BlobError),
+    #[error("Crypto error: {0}")]
+    Crypto(CryptoError),
+    #[error("Note not found: {0}")]
+    NotFound(String),
+    /// Locked notes are read with an empty body, which would export as one
+    #[error("Note is locked; unlock it before exporting: {0}")]
+    NoteLocked(String),
+    #[error("The passphrase must not be empty")]
+    EmptyPassphrase,
+    #[error("Not a note bundle")]
+    NotABundle,
+    #[error("Unsupported bundle version {0}")]
+    UnsupportedVersion(u8),
+    /// The passphrase is wrong or the file was altered; the two can't be
+    /// told apart
+    #[error("Wrong passphrase or damaged bundle")]
+    WrongPassphrase,
+    #[error("Corrupt bundle: {0}")]
+    Corrupt(String),
+}
+
+impl From<CryptoError> for NoteBundleError {
+    fn from(e: CryptoError) -> Self {
+        match e {
+            CryptoError::Decrypt => NoteBundleError::WrongPassphrase,
+            other => NoteBundleError::Crypto(other),
+        }
+    }
+}
+
+/// What `note.json` holds
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct BundledNote {
+    title: String,
+    content_md: String,
+    exported_at: i64,
+    attachments: Vec<BundledAttachment>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct BundledAttachment {
+    /// Blob id in the exporting vault, as the markdown references it
+    blob_id: String,
+    filename: Option<String>,
+    mime_type: Option<String>,
+    size_bytes: i64,
+}
+
+/// Parsed bundle header
+struct Header {
+    m_cost: u32,
+    t_cost: u32,
+    p_cost: u32,
+    salt: [u8; SALT_LEN],
+}
+
+impl Header {
+    fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(HEADER_LEN);
+        bytes.extend_from_slice(MAGIC);
+        bytes.push(BUNDLE_VERSION);
+        bytes.extend_from_slice(&self.m_cost.to_le_bytes());
+        bytes.extend_from_slice(&self.t_cost.to_le_bytes());
+        bytes.extend_from_slice(&self.p_cost.to_le_bytes());
+        bytes.extend_from_slice(&self.salt);
+        bytes
+    }
+
+    fn parse(bytes: &[u8]) -> Result<Self, NoteBundleError> {
+        if bytes.len() < MAGIC.len() + 1 || &bytes[..MAGIC.len()] != MAGIC {
+            return Err(NoteBundleError::NotABundle);
+        }
+        let version = bytes[MAGIC.len()];
+        if version != BUNDLE_VERSION {
+            return Err(NoteBundleError::UnsupportedVersion(version));
+        }
+        if bytes.len() < HEADER_LEN {
+            return Err(NoteBundleError::Corrupt("truncated header".into()));
+        }
+        let u32_at = |offset: usize| {
+            u32::from_le_bytes([
+                bytes[offset],
+                bytes[offset + 1],
+                bytes[offset + 2],
+                bytes[offset + 3],
+            ])
+        };
+        let params = MAGIC.len() + 1;
+        let header = Header {
+            m_cost: u32_at(params),
+            t_cost: u32_at(params + 4),
+            p_cost: u32_at(params + 8),
+            salt: bytes[params + 12..HEADER_LEN]
+                .try_into()
+                .map_err(|_| NoteBundleError::Corrupt("truncated salt".into()))?,
+        };
+        if header.m_cost > MAX_M_COST {
+            return Err(NoteBundleError::Corrupt(format!(
+                "memory cost {} KiB is too high",
+                header.m_cost
+            )));
+        }
+        if header.t_cost > MAX_T_COST {
+            return Err(NoteBundleError::Corrupt(format!(
+                "time cost {} is too high",
+                header.t_cost
+            )));
+        }
+        if header.p_cost > MAX_P_COST {
+            return Err(NoteBundleError::Corrupt(format!(
+                "parallelism {} is too high",
+                header.p_cost
+            )));
+        }
+        Ok(header)
+    }
+}
+
+/// Write `note_id` and its attachments to `path` as a bundle encrypted with
+/// `passphrase`. Attachments are read from the blob store of `vault_path`.
+pub fn export_encrypted_note_bundle(
+    conn: &rusqlite::Connection,
+    vault_path: &str,
+    dek: &[u8],
+    note_id: &str,
+    passphrase: &str,
+    path: &Path,
+) -> Result<(), NoteBundleError> {
+    log::info!("[note_bundle] Exporting note {} to {:?}", note_id, path);
+    if passphrase.is_empty() {
+        return Err(NoteBundleError::EmptyPassphrase);
+    }
+    let id = Ulid::from_string(note_id).map_err(|_| NoteBundleError::NotFound(note_id.into()))?;
+    let note =
+        get_note(conn, DbUlid(id))?.ok_or_else(|| NoteBundleError::NotFound(note_id.into()))?;
+    if note.is_locked {
+        return Err(NoteBundleError::NoteLocked(note_id.into()));
+    }
+    let attachments = get_note_attachments(conn, note_id)?;
+
+    let mut zip = ZipWriter::new(Cursor::new(Vec::new()));
+    let options: FileOptions<'_, ()> =
+        FileOptions::default().compression_method(CompressionMethod::Deflated);
+    let mut bundled = Vec::with_capacity(attachments.len());
+    for (index, attachment) in attachments.iter().enumerate() {
+        let content = retrieve_blob(vault_path, dek, &attachment.blob_id)?;
+        zip.start_file(format!("attachments/{}", index), options)?;
+        zip.write_all(&content)?;
+        bundled.push(BundledAttachment {
+            blob_id: attachment.blob_id.clone(),
+            filename: attachment.filename.clone(),
+            mime_type: attachment.mime_type.clone(),
+            size_bytes: content.len() as i64,
+        });
+    }
+    let manifest = BundledNote {
+        title: note.title,
+        content_md: note.content_md,
+        exported_at: chrono::Utc::now().timestamp(),
+        attachments: bundled,
+    };
+    zip.start_file(NOTE_ENTRY, options)?;
+    zip.write_all(&serde_json::to_vec(&manifest)?)?;
+    let archive = zeroize::Zeroizing::new(zip.finish()?.into_inner());
+
+    let header = Header {
+        m_cost: ARGON2_M_COST,
+        t_cost: ARGON2_T_COST,
+        p_cost: ARGON2_P_COST,
+        salt: rand::random(),
+    };
+    let header_bytes = header.to_bytes();
+    let key = derive_key_argon2(
+        passphrase,
+        &header.salt,
+        header.m_cost,
+        header.t_cost,
+        header.p_cost,
+    )?;
+    let mut bundle = header_bytes.clone();
+    bundle.extend_from_slice(&encrypt_bytes_with_aad(
+        &archive,
+        key.as_ref(),
+        &header_bytes,
+    )?);
+
+    // Write next to the target and rename, so a failed export never leaves a
+    // truncated bundle behind
+    let partial = path.with_extension("partial");
+    fs::write(&partial, &bundle)?;
+    fs::rename(&partial, path)?;
+    log::info!(
+        "[note_bundle] Exported note {} with {} attachments ({} bytes)",
+        note_id,
+        manifest.attachments.len(),
+        bundle.len()
+    );
+    Ok(())
+}
+
+/// Unpack the bundle at `path` into a new note in `space_id`. Attachments go
+/// into the blob store of `vault_path`, and references to them in the
+/// markdown are rewritten to their ids in this vault.
+pub fn import_encrypted_note_bundle(
+    conn: &rusqlite::Connection,
+    vault_path: &str,
+    dek: &[u8],
+    space_id: &str,
+    path: &Path,
+    passphrase: &str,
+) -> Result<Note, NoteBundleError> {
+    log::info!("[note_bundle] Importing {:?} into space {}", path, space_id);
+    let bundle = fs::read(path)?;
+    let header = Header::parse(&bundle)?;
+    let (header_bytes, ciphertext) = bundle.split_at(HEADER_LEN);
+    let key = derive_key_argon2(
+        passphrase,
+        &header.salt,
+        header.m_cost,
+        header.t_cost,
+        header.p_cost,
+    )?;
+    let archive = zeroize::Zeroizing::new(decrypt_bytes_with_aad(
+        ciphertext,
+        key.as_ref(),
+        header_bytes,
+    )?);
+
+    let mut zip = ZipArchive::new(Cursor::new(archive.as_slice()))?;
+    let mut budget = MAX_UNPACKED_BYTES;
+    let manifest: BundledNote = {
+        let json = read_entry(zip.by_name(NOTE_ENTRY)?, budget, &mut budget)?;
+        serde_json::from_slice(&json)?
+    };
+
+    // Read every attachment before writing anything, so a damaged archive
+    // leaves the vault untouched
+    let mut contents = Vec::with_capacity(manifest.attachments.len());
+    for (index, attachment) in manifest.attachments.iter().enumerate() {
+        let expected = u64::try_from(attachment.size_bytes).map_err(|_| {
+            NoteBundleError::Corrupt(format!("attachment {} has a negative size", index))
+        })?;
+        let entry = zip.by_name(&format!("attachments/{}", index))?;
+        let content = read_entry(entry, expected, &mut budget)?;
+        if content.len() as i64 != attachment.size_bytes {
+            return Err(NoteBundleError::Corrupt(format!(
+                "attachment {} is {} bytes, expected {}",
+                index,
+                content.len(),
+                attachment.size_bytes
+            )));
+        }
+        contents.push(content);
+    }
+
+    let tx = conn.unchecked_transaction().map_err(DbError::from)?;
+    let mut content_md = manifest.content_md;
+    let mut new_blob_ids = Vec::with_capacity(contents.len());
+    for (attachment, content) in manifest.attachments.iter().zip(&contents) {
+        let blob_id = insert_blob(
+            &tx,
+            vault_path,
+            dek,
+            content,
+            attachment.mime_type.as_deref(),
+        )?;
+        if blob_id != attachment.blob_id {
+            content_md = content_md.replace(&attachment.blob_id, &blob_id);
+        }
+        new_blob_ids.push(blob_id);
+    }
+    let note = create_note(&tx, space_id, &manifest.title, &content_md)?;
+    let note_id = note.id.to_string();
+    for (attachment, blob_id) in manifest.attachments.iter().zip(&new_blob_ids) {
+        attach_blob_to_note(&tx, &note_id, blob_id, attachment.filename.as_deref())?;
+    }
+    tx.commit().map_err(DbError::from)?;
+
+    log::info!(
+        "[note_bundle] Imported note {} with {} attachments",
+        note_id,
+        new_blob_ids.len()
+    );
+    Ok(note)
+}
+
+/// Read at most `limit` bytes of an archive entry, counting them against
+/// `budget`. Reading stops one byte past the limit, so an entry that inflates
+/// further than its stated size fails without being unpacked.
+fn read_entry(entry: impl Read, limit: u64, budget: &mut u64) -> Result<Vec<u8>, NoteBundleError> {
+    if limit > *budget {
+        return Err(NoteBundleError::Corrupt(
+            "archive unpacks to more than the import limit".into(),
+        ));
+    }
+    let mut content = Vec::new();
+    entry.take(limit + 1).read_to_end(&mut content)?;
+    if content.len() as u64 > limit {
+        return Err(NoteBundleError::Corrupt(format!(
+            "archive entry is larger than {} bytes",
+            limit
+        )));
+    }
+    *budget -= content.len() as u64;
+    Ok(content)
+}
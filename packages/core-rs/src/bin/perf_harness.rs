@@ -45,6 +45,7 @@ fn main() {
         "[GATE] Bulk Note Creation ({} notes): {:?}",
         num_notes, duration
     );
+    vault.close().expect("Vault close failed");
     let unlock_start = Instant::now();
     let unlocked_vault = unlock_vault(vault_path, vault_key).expect("Vault unlock failed");
     let unlock_duration = unlock_start.elapsed();
@@ -1,11 +1,18 @@
+use crate::maintenance::{
+    MaintenanceError, MaintenanceRegistry, MaintenanceTask, TaskReport, PRIORITY_LOW,
+};
 use chacha20poly1305::{
     aead::{Aead, AeadCore, KeyInit, OsRng},
     XChaCha20Poly1305,
 };
 use hkdf::Hkdf;
+use rusqlite::{Connection, OptionalExtension, TransactionBehavior};
+use serde::{Deserialize, Serialize};
 use sha2::{Digest, Sha256};
+use std::collections::HashSet;
 use std::fs;
-use std::path::Path;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
 use thiserror::Error;
 
 const CHUNK_SIZE: usize = 4096;
@@ -18,6 +25,48 @@ pub enum BlobError {
     Encrypt(String),
     #[error("Hex error: {0}")]
     Hex(#[from] hex::FromHexError),
+    #[error("Database error: {0}")]
+    Database(#[from] rusqlite::Error),
+}
+
+/// Kind of entity holding a reference to a blob
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum BlobOwner {
+    Note,
+    SocialPost,
+}
+
+impl BlobOwner {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            BlobOwner::Note => "note",
+            BlobOwner::SocialPost => "social_post",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+pub struct BlobGcResult {
+    pub blobs_removed: usize,
+    pub bytes_reclaimed: i64,
+    pub blob_ids: Vec<String>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+pub struct BlobSweepResult {
+    pub objects_removed: usize,
+    pub bytes_freed: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct NoteAttachment {
+    pub note_id: String,
+    pub blob_id: String,
+    pub filename: Option<String>,
+    pub size_bytes: i64,
+    pub mime_type: Option<String>,
+    pub created_at: i64,
 }
 
 fn derive_blob_key(mk: &[u8], blob_hash: &[u8]) -> [u8; 32] {
@@ -113,3 +162,358 @@ pub fn retrieve_blob(vault_path: &str, mk: &[u8], hex_hash: &str) -> Result<Vec<
 
     Ok(content)
 }
+
+/// Decrypts a blob and writes its plaintext to `dest`.
+/// Used by tools that need a real file on disk (e.g. the OCR engine).
+pub fn export_blob_to_file(
+    vault_path: &str,
+    mk: &[u8],
+    hex_hash: &str,
+    dest: &Path,
+) -> Result<(), BlobError> {
+    let content = retrieve_blob(vault_path, mk, hex_hash)?;
+    if let Some(parent) = dest.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::write(dest, content)?;
+    Ok(())
+}
+
+/// Record a stored blob in the database. Registering the same blob twice is a no-op.
+pub fn register_blob(
+    conn: &Connection,
+    blob_id: &str,
+    size_bytes: i64,
+    mime_type: Option<&str>,
+) -> Result<(), BlobError> {
+    conn.execute(
+        "INSERT OR IGNORE INTO blob (id, size_bytes, mime_type, created_at) VALUES (?1, ?2, ?3, ?4)",
+        rusqlite::params![blob_id, size_bytes, mime_type, chrono::Utc::now().timestamp()],
+    )?;
+    Ok(())
+}
+
+pub fn attach_blob_to_note(
+    conn: &Connection,
+    note_id: &str,
+    blob_id: &str,
+    filename: Option<&str>,
+) -> Result<(), BlobError> {
+    log::info!("[blob] Attaching blob {} to note {}", blob_id, note_id);
+    conn.execute(
+        "INSERT OR REPLACE INTO note_attachment (note_id, blob_id, filename, created_at) VALUES (?1, ?2, ?3, ?4)",
+        rusqlite::params![note_id, blob_id, filename, chrono::Utc::now().timestamp()],
+    )?;
+    Ok(())
+}
+
+pub fn detach_blob_from_note(
+    conn: &Connection,
+    note_id: &str,
+    blob_id: &str,
+) -> Result<(), BlobError> {
+    conn.execute(
+        "DELETE FROM note_attachment WHERE note_id = ?1 AND blob_id = ?2",
+        [note_id, blob_id],
+    )?;
+    Ok(())
+}
+
+pub fn get_note_attachments(
+    conn: &Connection,
+    note_id: &str,
+) -> Result<Vec<NoteAttachment>, BlobError> {
+    let mut stmt = conn.prepare(
+        "SELECT na.note_id, na.blob_id, na.filename, b.size_bytes, b.mime_type, na.created_at
+         FROM note_attachment na
+         JOIN blob b ON b.id = na.blob_id
+         WHERE na.note_id = ?1
+         ORDER BY na.created_at ASC",
+    )?;
+    let attachments = stmt
+        .query_map([note_id], |row| {
+            Ok(NoteAttachment {
+                note_id: row.get(0)?,
+                blob_id: row.get(1)?,
+                filename: row.get(2)?,
+                size_bytes: row.get(3)?,
+                mime_type: row.get(4)?,
+                created_at: row.get(5)?,
+            })
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(attachments)
+}
+
+/// Remove a blob's database record along with its attachments, OCR results and
+/// indexed text. The encrypted objects on disk are left for garbage collection.
+pub fn delete_blob_record(conn: &Connection, blob_id: &str) -> Result<(), BlobError> {
+    let tx = conn.unchecked_transaction()?;
+    tx.execute("DELETE FROM note_attachment WHERE blob_id = ?1", [blob_id])?;
+    tx.execute("DELETE FROM ocr_result WHERE blob_id = ?1", [blob_id])?;
+    tx.execute("DELETE FROM blob WHERE id = ?1", [blob_id])?;
+    tx.commit()?;
+    Ok(())
+}
+
+fn object_path(vault_path: &str, hex_hash: &str) -> PathBuf {
+    Path::new(vault_path)
+        .join("objects")
+        .join(&hex_hash[0..2])
+        .join(&hex_hash[2..])
+}
+
+/// Store content as a blob, reusing the existing blob when identical content
+/// was stored before. Returns the blob id.
+pub fn insert_blob(
+    conn: &Connection,
+    vault_path: &str,
+    mk: &[u8],
+    content: &[u8],
+    mime_type: Option<&str>,
+) -> Result<String, BlobError> {
+    let content_hash = hex::encode(Sha256::digest(content));
+
+    // Reusing a blob makes it new again, so a GC running before the caller
+    // references it keeps it. One statement, so the GC either removed the
+    // blob before (and it is stored afresh below) or sees the new time.
+    let existing: Option<String> = conn
+        .query_row(
+            "UPDATE blob SET created_at = MAX(created_at, ?2) WHERE content_hash = ?1
+             RETURNING id",
+            rusqlite::params![&content_hash, chrono::Utc::now().timestamp()],
+            |row| row.get(0),
+        )
+        .optional()?;
+    if let Some(blob_id) = existing {
+        log::info!(
+            "[blob] Reusing existing blob {} (duplicate content)",
+            blob_id
+        );
+        return Ok(blob_id);
+    }
+
+    let blob_id = store_blob(vault_path, mk, content)?;
+    let tx = conn.unchecked_transaction()?;
+    tx.execute(
+        "INSERT INTO blob (id, size_bytes, mime_type, created_at, content_hash)
+         VALUES (?1, ?2, ?3, ?4, ?5)
+         ON CONFLICT(id) DO UPDATE SET content_hash = excluded.content_hash",
+        rusqlite::params![
+            &blob_id,
+            content.len() as i64,
+            mime_type,
+            chrono::Utc::now().timestamp(),
+            &content_hash
+        ],
+    )?;
+    // The objects were just rewritten, so a pending sweep must not remove them
+    tx.execute(
+        "DELETE FROM blob_pending_sweep WHERE blob_id = ?1",
+        [&blob_id],
+    )?;
+    tx.commit()?;
+    Ok(blob_id)
+}
+
+pub fn add_blob_ref(
+    conn: &Connection,
+    blob_id: &str,
+    owner: BlobOwner,
+    owner_id: &str,
+) -> Result<(), BlobError> {
+    conn.execute(
+        "INSERT OR IGNORE INTO blob_ref (blob_id, owner_type, owner_id, created_at) VALUES (?1, ?2, ?3, ?4)",
+        rusqlite::params![blob_id, owner.as_str(), owner_id, chrono::Utc::now().timestamp()],
+    )?;
+    Ok(())
+}
+
+pub fn remove_blob_ref(
+    conn: &Connection,
+    blob_id: &str,
+    owner: BlobOwner,
+    owner_id: &str,
+) -> Result<(), BlobError> {
+    conn.execute(
+        "DELETE FROM blob_ref WHERE blob_id = ?1 AND owner_type = ?2 AND owner_id = ?3",
+        rusqlite::params![blob_id, owner.as_str(), owner_id],
+    )?;
+    Ok(())
+}
+
+pub fn get_blob_ref_count(conn: &Connection, blob_id: &str) -> Result<usize, BlobError> {
+    let count: i64 = conn.query_row(
+        "SELECT COUNT(*) FROM blob_ref WHERE blob_id = ?1",
+        [blob_id],
+        |row| row.get(0),
+    )?;
+    Ok(count as usize)
+}
+
+/// Delete blob records that nothing references and that were created before
+/// `older_than` (unix seconds). Blobs with in-flight OCR jobs are kept.
+///
+/// Runs in an IMMEDIATE transaction so a reference added concurrently either
+/// lands before the sweep (and keeps the blob) or fails against the deleted row.
+/// Encrypted objects are queued in `blob_pending_sweep`; see [`sweep_blob_objects`].
+pub fn gc_unreferenced_blobs(
+    conn: &Connection,
+    older_than: i64,
+) -> Result<BlobGcResult, BlobError> {
+    let tx = rusqlite::Transaction::new_unchecked(conn, TransactionBehavior::Immediate)?;
+
+    let removed = {
+        let mut stmt = tx.prepare(
+            "DELETE FROM blob
+             WHERE created_at < ?1
+               AND NOT EXISTS (SELECT 1 FROM blob_ref r WHERE r.blob_id = blob.id)
+               AND NOT EXISTS (
+                   SELECT 1 FROM ocr_result o
+                   WHERE o.blob_id = blob.id AND o.status IN ('pending', 'processing')
+               )
+             RETURNING id, size_bytes",
+        )?;
+        let rows = stmt
+            .query_map([older_than], |row| {
+                Ok((row.get::<_, String>(0)?, row.get::<_, i64>(1)?))
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+        rows
+    };
+
+    let now = chrono::Utc::now().timestamp();
+    let mut result = BlobGcResult::default();
+    for (blob_id, size_bytes) in removed {
+        // Clean dependents explicitly in case foreign keys are not enforced
+        tx.execute("DELETE FROM ocr_result WHERE blob_id = ?1", [&blob_id])?;
+        tx.execute("DELETE FROM note_attachment WHERE blob_id = ?1", [&blob_id])?;
+        tx.execute(
+            "INSERT OR REPLACE INTO blob_pending_sweep (blob_id, deleted_at) VALUES (?1, ?2)",
+            rusqlite::params![&blob_id, now],
+        )?;
+        result.blobs_removed += 1;
+        result.bytes_reclaimed += size_bytes;
+        result.blob_ids.push(blob_id);
+    }
+
+    tx.commit()?;
+
+    if result.blobs_removed > 0 {
+        log::info!(
+            "[blob] GC removed {} unreferenced blobs ({} bytes)",
+            result.blobs_removed,
+            result.bytes_reclaimed
+        );
+    }
+    Ok(result)
+}
+
+/// [`gc_unreferenced_blobs`] as a background maintenance task, keeping blobs
+/// younger than [`BLOB_GC_GRACE_SECONDS`](crate::social::maintenance::BLOB_GC_GRACE_SECONDS)
+pub struct BlobGcTask;
+
+impl BlobGcTask {
+    /// One collection pass, also used by startup maintenance. Does nothing
+    /// when attachments aren't set up in this vault.
+    pub fn collect(conn: &Connection) -> Result<BlobGcResult, BlobError> {
+        if !crate::db::table_exists(conn, "blob_ref")? {
+            return Ok(BlobGcResult::default());
+        }
+        let older_than =
+            chrono::Utc::now().timestamp() - crate::social::maintenance::BLOB_GC_GRACE_SECONDS;
+        gc_unreferenced_blobs(conn, older_than)
+    }
+}
+
+impl MaintenanceTask for BlobGcTask {
+    fn name(&self) -> &str {
+        "blob_gc"
+    }
+
+    fn estimated_cost(&self) -> Duration {
+        Duration::from_secs(1)
+    }
+
+    fn run(&self, conn: &Connection) -> Result<TaskReport, MaintenanceError> {
+        let result = Self::collect(conn).map_err(|e| MaintenanceError::Task(e.to_string()))?;
+        Ok(TaskReport {
+            items_affected: result.blobs_removed as u64,
+        })
+    }
+}
+
+pub(crate) fn register_maintenance(registry: &mut MaintenanceRegistry) {
+    registry.register(PRIORITY_LOW, BlobGcTask);
+}
+
+/// Remove the encrypted objects of blobs deleted by [`gc_unreferenced_blobs`].
+///
+/// Chunks are content-addressed and may be shared between blobs, so a chunk is
+/// only removed when no live blob's manifest still lists it.
+pub fn sweep_blob_objects(
+    conn: &Connection,
+    vault_path: &str,
+) -> Result<BlobSweepResult, BlobError> {
+    // Blobs that came back to life since GC keep their objects
+    conn.execute(
+        "DELETE FROM blob_pending_sweep WHERE blob_id IN (SELECT id FROM blob)",
+        [],
+    )?;
+
+    let pending: Vec<String> = conn
+        .prepare("SELECT blob_id FROM blob_pending_sweep")?
+        .query_map([], |row| row.get(0))?
+        .collect::<Result<Vec<_>, _>>()?;
+    if pending.is_empty() {
+        return Ok(BlobSweepResult::default());
+    }
+
+    let live_ids: Vec<String> = conn
+        .prepare("SELECT id FROM blob")?
+        .query_map([], |row| row.get(0))?
+        .collect::<Result<Vec<_>, _>>()?;
+    let mut live_objects: HashSet<String> = HashSet::new();
+    for blob_id in live_ids {
+        if let Ok(manifest) = fs::read_to_string(object_path(vault_path, &blob_id)) {
+            live_objects.extend(manifest.lines().map(str::to_string));
+        }
+        live_objects.insert(blob_id);
+    }
+
+    let mut result = BlobSweepResult::default();
+    for blob_id in pending {
+        let manifest_path = object_path(vault_path, &blob_id);
+        let chunks: Vec<String> = match fs::read_to_string(&manifest_path) {
+            Ok(manifest) => manifest.lines().map(str::to_string).collect(),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Vec::new(),
+            Err(e) => return Err(e.into()),
+        };
+
+        for object in chunks.iter().chain(std::iter::once(&blob_id)) {
+            if object.len() < 3 || live_objects.contains(object) {
+                continue;
+            }
+            let path = object_path(vault_path, object);
+            if let Ok(meta) = fs::metadata(&path) {
+                fs::remove_file(&path)?;
+                result.objects_removed += 1;
+                result.bytes_freed += meta.len();
+            }
+            // A chunk listed twice in one manifest must only be counted once
+            live_objects.insert(object.clone());
+        }
+
+        conn.execute(
+            "DELETE FROM blob_pending_sweep WHERE blob_id = ?1",
+            [&blob_id],
+        )?;
+    }
+
+    log::info!(
+        "[blob] Swept {} objects ({} bytes)",
+        result.objects_removed,
+        result.bytes_freed
+    );
+    Ok(result)
+}
@@ -0,0 +1,633 @@
+//! Bulk edits of notes and tasks
+//!
+//! A multi-selection in the note or task list is edited with one call
+//! instead of one command per item. Every id is looked up first, then the
+//! edit is applied to the ones it can be applied to in a single transaction,
+//! with one batch of audit events per space. Ids are looked up and written
+//! [`BULK_CHUNK_SIZE`] at a time, so the number of statements grows with
+//! the number of chunks rather than the number of items.
+//!
+//! Each item's [`BulkItemResult`] says whether it was edited or why it was
+//! skipped. An id that can't be edited doesn't hold back the others unless
+//! `all_or_nothing` is set; then nothing is written and
+//! [`BulkError::Incomplete`] lists the items that couldn't be edited.
+
+use crate::ai::rag::mark_notes_dirty;
+use crate::audit::{audit_changes, AuditOperation, AUDIT_SOURCE_LOCAL};
+use crate::automation::{has_enabled_rules, run_automation_from, AutomationEvent};
+use crate::db::{placeholders, DbError};
+use crate::note_share::{
+    ensure_space, record_note_moves, rehome_notes, NoteShareError, TagAction, TimeEntryAction,
+};
+use crate::tag::{create_tag, normalize_tag_name};
+use crate::task::db::handle_recurrence;
+use crate::task::get_task;
+use chrono::Utc;
+use rusqlite::types::Value;
+use rusqlite::{params_from_iter, Connection, OptionalExtension};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use thiserror::Error;
+use ulid::Ulid;
+
+/// Ids in one `IN` list
+pub const BULK_CHUNK_SIZE: usize = 200;
+
+/// Statuses a task can be set to
+const TASK_STATUSES: &[&str] = &[
+    "inbox",
+    "next",
+    "in_progress",
+    "waiting",
+    "done",
+    "cancelled",
+];
+
+#[derive(Error, Debug)]
+pub enum BulkError {
+    #[error("Rusqlite error: {0}")]
+    Rusqlite(#[from] rusqlite::Error),
+    #[error("Database error: {0}")]
+    Db(#[from] DbError),
+    #[error("Move failed: {0}")]
+    Move(#[from] NoteShareError),
+    #[error("Invalid bulk edit: {0}")]
+    Invalid(String),
+    /// Only raised for `all_or_nothing` edits, which then write nothing
+    #[error("{} of the selected items can't be edited", skipped.len())]
+    Incomplete { skipped: Vec<BulkItemResult> },
+}
+
+/// Why an item of a bulk edit was left alone
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SkipReason {
+    NotFound,
+    /// The id came up earlier in the same request
+    Duplicate,
+    /// Trashed notes aren't tagged
+    Trashed,
+    AlreadyTrashed,
+    AlreadyInSpace,
+    /// The task is in another space than the project it would be put in
+    ProjectInOtherSpace,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "status", rename_all = "snake_case")]
+pub enum BulkOutcome {
+    Succeeded,
+    Skipped { reason: SkipReason },
+}
+
+/// What a bulk edit did to one of its items
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BulkItemResult {
+    pub id: String,
+    #[serde(flatten)]
+    pub outcome: BulkOutcome,
+}
+
+impl BulkItemResult {
+    pub fn succeeded(&self) -> bool {
+        self.outcome == BulkOutcome::Succeeded
+    }
+}
+
+/// A new value for a field, or clearing it
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "op", content = "value", rename_all = "snake_case")]
+pub enum PatchValue<T> {
+    Set(T),
+    Clear,
+}
+
+impl<T: Clone + Into<Value>> PatchValue<T> {
+    fn to_value(&self) -> Value {
+        match self {
+            PatchValue::Set(value) => value.clone().into(),
+            PatchValue::Clear => Value::Null,
+        }
+    }
+}
+
+/// Fields [`bulk_update_tasks`] sets on every task; unset fields are left
+/// as they are. Tasks that become done get `completed_at` stamped.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TaskPatch {
+    pub status: Option<String>,
+    pub priority: Option<PatchValue<i64>>,
+    pub project_id: Option<PatchValue<String>>,
+    pub due_at: Option<PatchValue<i64>>,
+}
+
+impl TaskPatch {
+    fn is_empty(&self) -> bool {
+        self.status.is_none()
+            && self.priority.is_none()
+            && self.project_id.is_none()
+            && self.due_at.is_none()
+    }
+}
+
+/// Add `add_tags` to the notes and take `remove_tags` off them. Tags are
+/// looked up by name in each note's space and created there when missing.
+/// The space's tag rules run for tags a note didn't have yet.
+pub fn bulk_tag_notes(
+    conn: &Connection,
+    note_ids: &[String],
+    add_tags: &[String],
+    remove_tags: &[String],
+    all_or_nothing: bool,
+) -> Result<Vec<BulkItemResult>, BulkError> {
+    let add_tags = normalize_tags(add_tags)?;
+    let remove_tags = normalize_tags(remove_tags)?;
+    if add_tags.is_empty() && remove_tags.is_empty() {
+        return Err(BulkError::Invalid("no tags to add or remove".to_string()));
+    }
+    if let Some(name) = add_tags.iter().find(|name| remove_tags.contains(name)) {
+        return Err(BulkError::Invalid(format!(
+            "tag '{}' is both added and removed",
+            name
+        )));
+    }
+
+    let notes = load_rows(conn, "note", "space_id, is_trashed", note_ids, |row| {
+        Ok((row.get::<_, String>(1)?, row.get::<_, bool>(2)?))
+    })?;
+    let (results, ids) = triage(
+        note_ids,
+        &notes,
+        |(_, trashed)| trashed.then_some(SkipReason::Trashed),
+        all_or_nothing,
+    )?;
+
+    let tx = conn.unchecked_transaction()?;
+    for (space_id, ids) in by_space(&ids, &notes, |(space_id, _)| space_id) {
+        if !add_tags.is_empty() {
+            add_tags_in_space(&tx, &space_id, &ids, &add_tags)?;
+        }
+        if !remove_tags.is_empty() {
+            for chunk in ids.chunks(BULK_CHUNK_SIZE) {
+                let params = chunk
+                    .iter()
+                    .chain(std::iter::once(&space_id))
+                    .chain(&remove_tags);
+                tx.execute(
+                    &format!(
+                        "DELETE FROM note_tags WHERE note_id IN ({})
+                           AND tag_id IN (SELECT id FROM tag WHERE space_id = ? AND name IN ({}))",
+                        placeholders(chunk.len()),
+                        placeholders(remove_tags.len())
+                    ),
+                    params_from_iter(params),
+                )?;
+            }
+        }
+        audit_changes(
+            &tx,
+            &space_id,
+            "note",
+            &ids,
+            AuditOperation::Update,
+            AUDIT_SOURCE_LOCAL,
+        );
+    }
+    tx.commit()?;
+    Ok(results)
+}
+
+/// Link `note_ids` of one space to the tags named `names`, then run the
+/// space's tag rules for the links that are new
+fn add_tags_in_space(
+    conn: &Connection,
+    space_id: &str,
+    note_ids: &[String],
+    names: &[String],
+) -> Result<(), BulkError> {
+    let mut tag_ids = Vec::with_capacity(names.len());
+    for name in names {
+        let existing: Option<String> = conn
+            .query_row(
+                "SELECT id FROM tag WHERE space_id = ?1 AND name = ?2",
+                [space_id, name.as_str()],
+                |row| row.get(0),
+            )
+            .optional()?;
+        tag_ids.push(match existing {
+            Some(id) => id,
+            None => create_tag(conn, space_id, name, None)?.id.to_string(),
+        });
+    }
+
+    let run_rules = has_enabled_rules(conn, space_id, "tag_added")?;
+    let mut had: HashSet<(String, String)> = HashSet::new();
+    for chunk in note_ids.chunks(BULK_CHUNK_SIZE) {
+        if run_rules {
+            let mut stmt = conn.prepare(&format!(
+                "SELECT note_id, tag_id FROM note_tags WHERE note_id IN ({}) AND tag_id IN ({})",
+                placeholders(chunk.len()),
+                placeholders(tag_ids.len())
+            ))?;
+            let rows = stmt.query_map(params_from_iter(chunk.iter().chain(&tag_ids)), |row| {
+                Ok((row.get(0)?, row.get(1)?))
+            })?;
+            for row in rows {
+                had.insert(row?);
+            }
+        }
+        conn.execute(
+            &format!(
+                "INSERT OR IGNORE INTO note_tags (note_id, tag_id)
+                 SELECT n.id, t.id FROM note n, tag t WHERE n.id IN ({}) AND t.id IN ({})",
+                placeholders(chunk.len()),
+                placeholders(tag_ids.len())
+            ),
+            params_from_iter(chunk.iter().chain(&tag_ids)),
+        )?;
+    }
+
+    if run_rules {
+        for note_id in note_ids {
+            for (tag_id, name) in tag_ids.iter().zip(names) {
+                if had.contains(&(note_id.clone(), tag_id.clone())) {
+                    continue;
+                }
+                run_automation_from(
+                    conn,
+                    AutomationEvent::TagAdded {
+                        note_id: note_id.clone(),
+                        tag: name.clone(),
+                    },
+                    AUDIT_SOURCE_LOCAL,
+                );
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Move the notes to `target_space_id` like
+/// [`crate::note_share::move_note_to_space`] does, each group of notes from
+/// the same space together so links between them survive. Settling links
+/// and derived data is per note, so moves cost more statements than the
+/// other bulk edits.
+pub fn bulk_move_notes_to_space(
+    conn: &Connection,
+    note_ids: &[String],
+    target_space_id: &str,
+    tags: TagAction,
+    time_entries: TimeEntryAction,
+    all_or_nothing: bool,
+) -> Result<Vec<BulkItemResult>, BulkError> {
+    ensure_space(conn, target_space_id)?;
+    let notes = load_rows(conn, "note", "space_id", note_ids, |row| {
+        row.get::<_, String>(1)
+    })?;
+    let (results, ids) = triage(
+        note_ids,
+        &notes,
+        |space_id| (space_id == target_space_id).then_some(SkipReason::AlreadyInSpace),
+        all_or_nothing,
+    )?;
+
+    let now = Utc::now().timestamp();
+    let tx = conn.unchecked_transaction()?;
+    for (from_space_id, ids) in by_space(&ids, &notes, |space_id| space_id) {
+        log::info!(
+            "[bulk_edit] Moving {} note(s) from space {} to {}",
+            ids.len(),
+            from_space_id,
+            target_space_id
+        );
+        for chunk in ids.chunks(BULK_CHUNK_SIZE) {
+            rehome_notes(
+                &tx,
+                chunk,
+                &from_space_id,
+                target_space_id,
+                tags,
+                time_entries,
+                now,
+            )?;
+        }
+        record_note_moves(
+            &tx,
+            &ids,
+            &from_space_id,
+            target_space_id,
+            tags,
+            time_entries,
+            now,
+        )?;
+    }
+    tx.commit()?;
+    Ok(results)
+}
+
+/// Move the notes to the trash
+pub fn bulk_trash_notes(
+    conn: &Connection,
+    note_ids: &[String],
+    all_or_nothing: bool,
+) -> Result<Vec<BulkItemResult>, BulkError> {
+    let notes = load_rows(conn, "note", "space_id, is_trashed", note_ids, |row| {
+        Ok((row.get::<_, String>(1)?, row.get::<_, bool>(2)?))
+    })?;
+    let (results, ids) = triage(
+        note_ids,
+        &notes,
+        |(_, trashed)| trashed.then_some(SkipReason::AlreadyTrashed),
+        all_or_nothing,
+    )?;
+
+    let tx = conn.unchecked_transaction()?;
+    for chunk in ids.chunks(BULK_CHUNK_SIZE) {
+        tx.execute(
+            &format!(
+                "UPDATE note SET is_trashed = 1 WHERE id IN ({})",
+                placeholders(chunk.len())
+            ),
+            params_from_iter(chunk),
+        )?;
+        mark_notes_dirty(&tx, chunk)?;
+    }
+    // Notes are deleted by trashing them
+    for (space_id, ids) in by_space(&ids, &notes, |(space_id, _)| space_id) {
+        audit_changes(
+            &tx,
+            &space_id,
+            "note",
+            &ids,
+            AuditOperation::Delete,
+            AUDIT_SOURCE_LOCAL,
+        );
+    }
+    tx.commit()?;
+    Ok(results)
+}
+
+/// Apply `patch` to the tasks. Tasks that become done run the space's
+/// completion rules and schedule their next occurrence, as with
+/// [`crate::task::update_task`].
+pub fn bulk_update_tasks(
+    conn: &Connection,
+    task_ids: &[String],
+    patch: &TaskPatch,
+    all_or_nothing: bool,
+) -> Result<Vec<BulkItemResult>, BulkError> {
+    if patch.is_empty() {
+        return Err(BulkError::Invalid("the patch changes nothing".to_string()));
+    }
+    if let Some(status) = &patch.status {
+        if !TASK_STATUSES.contains(&status.as_str()) {
+            return Err(BulkError::Invalid(format!(
+                "unknown task status '{}'",
+                status
+            )));
+        }
+    }
+    let project_space = match &patch.project_id {
+        Some(PatchValue::Set(project_id)) => Some(
+            conn.query_row(
+                "SELECT space_id FROM project WHERE id = ?1",
+                [project_id],
+                |row| row.get::<_, String>(0),
+            )
+            .optional()?
+            .ok_or_else(|| DbError::NotFound {
+                entity: "project",
+                id: project_id.clone(),
+            })?,
+        ),
+        _ => None,
+    };
+
+    let tasks = load_rows(conn, "task", "space_id, status", task_ids, |row| {
+        Ok((row.get::<_, String>(1)?, row.get::<_, String>(2)?))
+    })?;
+    let (results, ids) = triage(
+        task_ids,
+        &tasks,
+        |(space_id, _)| match &project_space {
+            Some(project_space) if project_space != space_id => {
+                Some(SkipReason::ProjectInOtherSpace)
+            }
+            _ => None,
+        },
+        all_or_nothing,
+    )?;
+
+    let now = Utc::now().timestamp();
+    let mut sets = vec!["updated_at = ?"];
+    let mut values: Vec<Value> = vec![now.into()];
+    if let Some(status) = &patch.status {
+        sets.push("status = ?");
+        values.push(status.clone().into());
+        if status == "done" {
+            sets.push("completed_at = COALESCE(completed_at, ?)");
+            values.push(now.into());
+        }
+    }
+    for (set, value) in [
+        (
+            "priority = ?",
+            patch.priority.as_ref().map(PatchValue::to_value),
+        ),
+        (
+            "project_id = ?",
+            patch.project_id.as_ref().map(PatchValue::to_value),
+        ),
+        (
+            "due_at = ?",
+            patch.due_at.as_ref().map(PatchValue::to_value),
+        ),
+    ] {
+        if let Some(value) = value {
+            sets.push(set);
+            values.push(value);
+        }
+    }
+
+    let tx = conn.unchecked_transaction()?;
+    for chunk in ids.chunks(BULK_CHUNK_SIZE) {
+        let params = values
+            .iter()
+            .cloned()
+            .chain(chunk.iter().cloned().map(Value::from));
+        tx.execute(
+            &format!(
+                "UPDATE task SET {} WHERE id IN ({})",
+                sets.join(", "),
+                placeholders(chunk.len())
+            ),
+            params_from_iter(params),
+        )?;
+    }
+
+    let completed: Vec<&String> = if patch.status.as_deref() == Some("done") {
+        ids.iter().filter(|id| tasks[*id].1 != "done").collect()
+    } else {
+        Vec::new()
+    };
+    for (space_id, ids) in by_space(&ids, &tasks, |(space_id, _)| space_id) {
+        audit_changes(
+            &tx,
+            &space_id,
+            "task",
+            &ids,
+            AuditOperation::Update,
+            AUDIT_SOURCE_LOCAL,
+        );
+    }
+    if !completed.is_empty() {
+        complete_tasks(&tx, &completed, &tasks)?;
+    }
+    tx.commit()?;
+    Ok(results)
+}
+
+/// Schedule the next occurrence of tasks that just became done and run the
+/// completion rules of their spaces. `tasks` holds their space and old
+/// status.
+fn complete_tasks(
+    conn: &Connection,
+    task_ids: &[&String],
+    tasks: &HashMap<String, (String, String)>,
+) -> Result<(), BulkError> {
+    let mut recurring = Vec::new();
+    for chunk in task_ids.chunks(BULK_CHUNK_SIZE) {
+        let mut stmt = conn.prepare(&format!(
+            "SELECT id FROM task WHERE recur_rule IS NOT NULL AND id IN ({})",
+            placeholders(chunk.len())
+        ))?;
+        let rows = stmt.query_map(params_from_iter(chunk), |row| row.get::<_, String>(0))?;
+        for row in rows {
+            recurring.push(row?);
+        }
+    }
+    for id in recurring {
+        let id = Ulid::from_string(&id).map_err(|e| DbError::Message(e.to_string()))?;
+        if let Some(task) = get_task(conn, id)? {
+            handle_recurrence(conn, &task)?;
+        }
+    }
+
+    let mut rules: HashMap<&str, bool> = HashMap::new();
+    for &task_id in task_ids {
+        let space_id = tasks[task_id].0.as_str();
+        let has_rules = match rules.get(space_id) {
+            Some(has_rules) => *has_rules,
+            None => {
+                let has_rules = has_enabled_rules(conn, space_id, "task_completed")?;
+                rules.insert(space_id, has_rules);
+                has_rules
+            }
+        };
+        if has_rules {
+            run_automation_from(
+                conn,
+                AutomationEvent::TaskCompleted {
+                    task_id: task_id.clone(),
+                },
+                AUDIT_SOURCE_LOCAL,
+            );
+        }
+    }
+    Ok(())
+}
+
+fn normalize_tags(names: &[String]) -> Result<Vec<String>, BulkError> {
+    let mut normalized = Vec::with_capacity(names.len());
+    for name in names {
+        let name = normalize_tag_name(name).map_err(|e| BulkError::Invalid(e.to_string()))?;
+        if !normalized.contains(&name) {
+            normalized.push(name);
+        }
+    }
+    Ok(normalized)
+}
+
+/// The rows of `table` among `ids`, keyed by id. `columns` follow the id in
+/// the rows `map` reads; both names are ours, never user input.
+fn load_rows<T>(
+    conn: &Connection,
+    table: &str,
+    columns: &str,
+    ids: &[String],
+    map: impl Fn(&rusqlite::Row) -> rusqlite::Result<T>,
+) -> Result<HashMap<String, T>, BulkError> {
+    let mut rows = HashMap::with_capacity(ids.len());
+    for chunk in ids.chunks(BULK_CHUNK_SIZE) {
+        let mut stmt = conn.prepare(&format!(
+            "SELECT id, {} FROM {} WHERE id IN ({})",
+            columns,
+            table,
+            placeholders(chunk.len())
+        ))?;
+        let mut query = stmt.query(params_from_iter(chunk))?;
+        while let Some(row) = query.next()? {
+            rows.insert(row.get(0)?, map(row)?);
+        }
+    }
+    Ok(rows)
+}
+
+/// The result of each of `ids`, in request order, and the ids to edit.
+/// `skip` says why a found row can't be edited. With `all_or_nothing` any
+/// skipped item fails the whole edit.
+fn triage<T>(
+    ids: &[String],
+    found: &HashMap<String, T>,
+    skip: impl Fn(&T) -> Option<SkipReason>,
+    all_or_nothing: bool,
+) -> Result<(Vec<BulkItemResult>, Vec<String>), BulkError> {
+    let mut seen = HashSet::with_capacity(ids.len());
+    let mut results = Vec::with_capacity(ids.len());
+    let mut editable = Vec::with_capacity(ids.len());
+    for id in ids {
+        let reason = if !seen.insert(id) {
+            Some(SkipReason::Duplicate)
+        } else {
+            match found.get(id) {
+                None => Some(SkipReason::NotFound),
+                Some(row) => skip(row),
+            }
+        };
+        let outcome = match reason {
+            Some(reason) => BulkOutcome::Skipped { reason },
+            None => {
+                editable.push(id.clone());
+                BulkOutcome::Succeeded
+            }
+        };
+        results.push(BulkItemResult {
+            id: id.clone(),
+            outcome,
+        });
+    }
+
+    if all_or_nothing && editable.len() < ids.len() {
+        return Err(BulkError::Incomplete {
+            skipped: results.into_iter().filter(|r| !r.succeeded()).collect(),
+        });
+    }
+    Ok((results, editable))
+}
+
+/// `ids` grouped by their space, the first value of their row
+fn by_space<T>(
+    ids: &[String],
+    rows: &HashMap<String, T>,
+    space_of: impl Fn(&T) -> &String,
+) -> BTreeMap<String, Vec<String>> {
+    let mut groups: BTreeMap<String, Vec<String>> = BTreeMap::new();
+    for id in ids {
+        groups
+            .entry(space_of(&rows[id]).clone())
+            .or_default()
+            .push(id.clone());
+    }
+    groups
+}
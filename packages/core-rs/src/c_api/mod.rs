@@ -0,0 +1,456 @@
+//! C API for the Mobile Apps
+//!
+//! The mobile clients can't embed Tauri, so they call core-rs through this
+//! `extern "C"` surface. Conventions shared by every function:
+//!
+//! - Each call returns a [`NoteeceResult`]. `code` is [`NOTEECE_OK`] on
+//!   success; otherwise `message` says what went wrong. Data comes back in
+//!   `value`, as JSON for anything structured.
+//! - Strings are NUL-terminated UTF-8 in both directions. Strings handed out
+//!   belong to the caller until passed to [`noteece_string_free`] or
+//!   [`noteece_result_free`]; freeing one twice is reported as
+//!   [`NOTEECE_INVALID_POINTER`] instead of corrupting memory.
+//! - An open vault is a [`NoteeceHandle`]: an opaque id for its connection
+//!   pool and key. Unknown and closed handles fail with
+//!   [`NOTEECE_INVALID_HANDLE`], and a locked vault with [`NOTEECE_LOCKED`].
+//! - Panics never cross the boundary; they come back as [`NOTEECE_PANIC`].
+//!
+//! `include/noteece_core.h` is generated from this module with cbindgen, see
+//! `cbindgen.toml`.
+
+use crate::db::{DbError, EncryptedConnectionManager};
+use crate::sync::error::SyncError;
+use crate::vault::{AutoLockGuard, Vault, VaultError, VaultLock};
+use lazy_static::lazy_static;
+use r2d2::{Pool, PooledConnection};
+use serde::Serialize;
+use std::collections::{HashMap, HashSet};
+use std::ffi::{CStr, CString};
+use std::os::raw::c_char;
+use std::panic::{self, AssertUnwindSafe};
+use std::path::Path;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard};
+use zeroize::Zeroizing;
+
+pub mod notes;
+pub mod sync;
+pub mod tasks;
+
+pub const NOTEECE_OK: i32 = 0;
+/// A required pointer argument was null
+pub const NOTEECE_NULL_ARGUMENT: i32 = 1;
+/// A string argument was not valid UTF-8
+pub const NOTEECE_INVALID_UTF8: i32 = 2;
+/// The handle is unknown or was closed
+pub const NOTEECE_INVALID_HANDLE: i32 = 3;
+/// The vault is locked; unlock it again for a new handle
+pub const NOTEECE_LOCKED: i32 = 4;
+pub const NOTEECE_NOT_FOUND: i32 = 5;
+/// An id or JSON argument could not be parsed
+pub const NOTEECE_INVALID_ARGUMENT: i32 = 6;
+/// Creating or unlocking the vault failed, e.g. a wrong password
+pub const NOTEECE_VAULT_ERROR: i32 = 7;
+pub const NOTEECE_DATABASE_ERROR: i32 = 8;
+pub const NOTEECE_SYNC_ERROR: i32 = 9;
+/// Rust panicked; the call had no effect the caller can rely on
+pub const NOTEECE_PANIC: i32 = 10;
+/// The pointer was not handed out by this library, or was already freed
+pub const NOTEECE_INVALID_POINTER: i32 = 11;
+/// Another process, or another handle in this one, has the vault open
+pub const NOTEECE_VAULT_IN_USE: i32 = 12;
+
+/// Opaque id of an open vault; 0 is never a valid handle
+pub type NoteeceHandle = u64;
+
+/// Outcome of every API call. Both strings are null when absent.
+#[repr(C)]
+pub struct NoteeceResult {
+    pub code: i32,
+    /// Why the call failed
+    pub message: *mut c_char,
+    /// What the call returned
+    pub value: *mut c_char,
+}
+
+/// The database file inside a vault directory
+const VAULT_DB_FILE: &str = "vault.sqlite3";
+
+/// Connections per open vault; mobile callers are mostly sequential
+const POOL_SIZE: u32 = 4;
+
+/// Device name this API syncs as
+const DEVICE_NAME: &str = "Mobile";
+
+lazy_static! {
+    static ref SESSIONS: Mutex<HashMap<NoteeceHandle, Arc<VaultSession>>> =
+        Mutex::new(HashMap::new());
+    /// Addresses of strings handed out and not yet freed
+    static ref LIVE_STRINGS: Mutex<HashSet<usize>> = Mutex::new(HashSet::new());
+}
+
+static NEXT_HANDLE: AtomicU64 = AtomicU64::new(1);
+
+/// An error on its way across the boundary
+#[derive(Debug)]
+pub(crate) struct FfiError {
+    code: i32,
+    message: String,
+}
+
+impl FfiError {
+    pub(crate) fn new(code: i32, message: impl Into<String>) -> Self {
+        Self {
+            code,
+            message: message.into(),
+        }
+    }
+}
+
+impl From<DbError> for FfiError {
+    fn from(e: DbError) -> Self {
+        Self::new(NOTEECE_DATABASE_ERROR, e.to_string())
+    }
+}
+
+impl From<rusqlite::Error> for FfiError {
+    fn from(e: rusqlite::Error) -> Self {
+        Self::new(NOTEECE_DATABASE_ERROR, e.to_string())
+    }
+}
+
+impl From<VaultError> for FfiError {
+    fn from(e: VaultError) -> Self {
+        match e {
+            VaultError::Locked => Self::new(NOTEECE_LOCKED, e.to_string()),
+            VaultError::VaultAlreadyOpen(_) => Self::new(NOTEECE_VAULT_IN_USE, e.to_string()),
+            _ => Self::new(NOTEECE_VAULT_ERROR, e.to_string()),
+        }
+    }
+}
+
+impl From<SyncError> for FfiError {
+    fn from(e: SyncError) -> Self {
+        Self::new(NOTEECE_SYNC_ERROR, e.to_string())
+    }
+}
+
+impl From<serde_json::Error> for FfiError {
+    fn from(e: serde_json::Error) -> Self {
+        Self::new(NOTEECE_INVALID_ARGUMENT, e.to_string())
+    }
+}
+
+/// What a handle stands for: the pool of an unlocked vault and the guard
+/// holding its key
+pub(crate) struct VaultSession {
+    auto_lock: Arc<AutoLockGuard>,
+    pool: Pool<EncryptedConnectionManager>,
+    device_id: String,
+    /// Released once the handle is closed and in-flight calls finish
+    _lock: VaultLock,
+}
+
+impl VaultSession {
+    pub(crate) fn conn(&self) -> Result<PooledConnection<EncryptedConnectionManager>, FfiError> {
+        if self.auto_lock.is_locked() {
+            return Err(VaultError::Locked.into());
+        }
+        self.pool
+            .get()
+            .map_err(|e| FfiError::new(NOTEECE_DATABASE_ERROR, e.to_string()))
+    }
+
+    pub(crate) fn dek(&self) -> Result<Zeroizing<[u8; 32]>, FfiError> {
+        Ok(self.auto_lock.dek()?)
+    }
+
+    pub(crate) fn device_id(&self) -> &str {
+        &self.device_id
+    }
+}
+
+// Locks are only held for map operations, so a poisoned map is still sound
+fn sessions() -> MutexGuard<'static, HashMap<NoteeceHandle, Arc<VaultSession>>> {
+    SESSIONS.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+fn live_strings() -> MutexGuard<'static, HashSet<usize>> {
+    LIVE_STRINGS.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+pub(crate) fn session(handle: NoteeceHandle) -> Result<Arc<VaultSession>, FfiError> {
+    sessions().get(&handle).cloned().ok_or_else(|| {
+        FfiError::new(
+            NOTEECE_INVALID_HANDLE,
+            format!("Unknown or closed vault handle {}", handle),
+        )
+    })
+}
+
+/// Hand a string to the caller
+fn into_c_string(value: String) -> *mut c_char {
+    // Interior NULs would silently truncate the string on the other side
+    let value = CString::new(value).unwrap_or_else(|e| {
+        let mut bytes = e.into_vec();
+        bytes.retain(|&b| b != 0);
+        CString::new(bytes).unwrap_or_default()
+    });
+    let ptr = value.into_raw();
+    live_strings().insert(ptr as usize);
+    ptr
+}
+
+/// Borrow a required string argument
+///
+/// # Safety
+/// `ptr` must be null or point to a NUL-terminated string that outlives the
+/// call
+pub(crate) unsafe fn read_str<'a>(ptr: *const c_char, name: &str) -> Result<&'a str, FfiError> {
+    if ptr.is_null() {
+        return Err(FfiError::new(
+            NOTEECE_NULL_ARGUMENT,
+            format!("{} is null", name),
+        ));
+    }
+    CStr::from_ptr(ptr)
+        .to_str()
+        .map_err(|_| FfiError::new(NOTEECE_INVALID_UTF8, format!("{} is not valid UTF-8", name)))
+}
+
+/// Borrow an optional string argument; null means absent
+///
+/// # Safety
+/// As for [`read_str`]
+pub(crate) unsafe fn read_opt_str<'a>(
+    ptr: *const c_char,
+    name: &str,
+) -> Result<Option<&'a str>, FfiError> {
+    if ptr.is_null() {
+        return Ok(None);
+    }
+    read_str(ptr, name).map(Some)
+}
+
+pub(crate) fn parse_ulid(value: &str, name: &str) -> Result<ulid::Ulid, FfiError> {
+    ulid::Ulid::from_string(value).map_err(|e| {
+        FfiError::new(
+            NOTEECE_INVALID_ARGUMENT,
+            format!("{} is not a valid id: {}", name, e),
+        )
+    })
+}
+
+pub(crate) fn to_json<T: Serialize>(value: &T) -> Result<Option<String>, FfiError> {
+    serde_json::to_string(value)
+        .map(Some)
+        .map_err(|e| FfiError::new(NOTEECE_INVALID_ARGUMENT, e.to_string()))
+}
+
+/// Run the body of an API call, turning errors and panics into a result
+pub(crate) fn ffi_call<F>(name: &str, body: F) -> NoteeceResult
+where
+    F: FnOnce() -> Result<Option<String>, FfiError>,
+{
+    let outcome = panic::catch_unwind(AssertUnwindSafe(body)).unwrap_or_else(|payload| {
+        let detail = payload
+            .downcast_ref::<&str>()
+            .map(|s| s.to_string())
+            .or_else(|| payload.downcast_ref::<String>().cloned())
+            .unwrap_or_else(|| "unknown panic".to_string());
+        log::error!("[ffi] {} panicked: {}", name, detail);
+        Err(FfiError::new(
+            NOTEECE_PANIC,
+            format!("{} panicked: {}", name, detail),
+        ))
+    });
+
+    match outcome {
+        Ok(value) => NoteeceResult {
+            code: NOTEECE_OK,
+            message: std::ptr::null_mut(),
+            value: value.map_or(std::ptr::null_mut(), into_c_string),
+        },
+        Err(e) => {
+            log::warn!("[ffi] {} failed ({}): {}", name, e.code, e.message);
+            NoteeceResult {
+                code: e.code,
+                message: into_c_string(e.message),
+                value: std::ptr::null_mut(),
+            }
+        }
+    }
+}
+
+/// Free a string returned by this library. Null is ignored; a pointer that
+/// isn't live returns [`NOTEECE_INVALID_POINTER`] and is left alone.
+///
+/// # Safety
+/// `ptr` must be null or a pointer previously returned by this library
+#[no_mangle]
+pub unsafe extern "C" fn noteece_string_free(ptr: *mut c_char) -> i32 {
+    if ptr.is_null() {
+        return NOTEECE_OK;
+    }
+    if !live_strings().remove(&(ptr as usize)) {
+        log::warn!("[ffi] Refusing to free a string that isn't live");
+        return NOTEECE_INVALID_POINTER;
+    }
+    drop(CString::from_raw(ptr));
+    NOTEECE_OK
+}
+
+/// Free both strings of a result. Returns the first failure, if any.
+///
+/// # Safety
+/// As for [`noteece_string_free`], for both strings
+#[no_mangle]
+pub unsafe extern "C" fn noteece_result_free(result: NoteeceResult) -> i32 {
+    let message = noteece_string_free(result.message);
+    let value = noteece_string_free(result.value);
+    if message != NOTEECE_OK {
+        message
+    } else {
+        value
+    }
+}
+
+/// Put an unlocked vault behind a new handle
+fn open_session(path: &str, vault: Vault) -> Result<NoteeceHandle, FfiError> {
+    let Vault {
+        mut conn,
+        dek,
+        lock,
+    } = vault;
+    crate::db::migrate(&mut conn)?;
+    crate::sync::db_init::init_sync_tables(&conn)?;
+    let device_id = crate::db::get_or_create_user_id(&conn)?;
+    drop(conn);
+
+    let auto_lock = Arc::new(AutoLockGuard::new(*dek));
+    let manager =
+        EncryptedConnectionManager::new(Path::new(path).join(VAULT_DB_FILE), auto_lock.clone());
+    let pool = Pool::builder()
+        .max_size(POOL_SIZE)
+        .build(manager)
+        .map_err(|e| FfiError::new(NOTEECE_DATABASE_ERROR, e.to_string()))?;
+
+    let handle = NEXT_HANDLE.fetch_add(1, Ordering::Relaxed);
+    sessions().insert(
+        handle,
+        Arc::new(VaultSession {
+            auto_lock,
+            pool,
+            device_id,
+            _lock: lock,
+        }),
+    );
+    log::info!("[ffi] Opened vault handle {}", handle);
+    Ok(handle)
+}
+
+/// Shared body of create and unlock
+unsafe fn open_vault(
+    name: &str,
+    path: *const c_char,
+    password: *const c_char,
+    out_handle: *mut NoteeceHandle,
+    open: fn(&str, &str) -> Result<Vault, VaultError>,
+) -> NoteeceResult {
+    ffi_call(name, || {
+        let path = read_str(path, "path")?;
+        let password = read_str(password, "password")?;
+        if out_handle.is_null() {
+            return Err(FfiError::new(NOTEECE_NULL_ARGUMENT, "out_handle is null"));
+        }
+        let handle = open_session(path, open(path, password)?)?;
+        *out_handle = handle;
+        Ok(None)
+    })
+}
+
+/// Create a vault in the directory `path` and open it
+///
+/// # Safety
+/// `path` and `password` must be NUL-terminated strings and `out_handle`
+/// must point to writable memory
+#[no_mangle]
+pub unsafe extern "C" fn noteece_vault_create(
+    path: *const c_char,
+    password: *const c_char,
+    out_handle: *mut NoteeceHandle,
+) -> NoteeceResult {
+    open_vault(
+        "noteece_vault_create",
+        path,
+        password,
+        out_handle,
+        crate::vault::create_vault,
+    )
+}
+
+/// Unlock the vault in the directory `path`
+///
+/// # Safety
+/// As for [`noteece_vault_create`]
+#[no_mangle]
+pub unsafe extern "C" fn noteece_vault_unlock(
+    path: *const c_char,
+    password: *const c_char,
+    out_handle: *mut NoteeceHandle,
+) -> NoteeceResult {
+    open_vault(
+        "noteece_vault_unlock",
+        path,
+        password,
+        out_handle,
+        crate::vault::unlock_vault,
+    )
+}
+
+/// Wipe the vault key. The handle stays valid but every call on it fails
+/// with [`NOTEECE_LOCKED`] until it is closed.
+#[no_mangle]
+pub extern "C" fn noteece_vault_lock(handle: NoteeceHandle) -> NoteeceResult {
+    ffi_call("noteece_vault_lock", || {
+        session(handle)?.auto_lock.lock();
+        Ok(None)
+    })
+}
+
+/// Lock the vault and release the handle. Closing twice fails with
+/// [`NOTEECE_INVALID_HANDLE`].
+#[no_mangle]
+pub extern "C" fn noteece_vault_close(handle: NoteeceHandle) -> NoteeceResult {
+    ffi_call("noteece_vault_close", || {
+        let session = sessions().remove(&handle).ok_or_else(|| {
+            FfiError::new(
+                NOTEECE_INVALID_HANDLE,
+                format!("Unknown or closed vault handle {}", handle),
+            )
+        })?;
+        // Calls still running on other threads hold the session; they lose
+        // the key now and the pool when they finish
+        session.auto_lock.lock();
+        log::info!("[ffi] Closed vault handle {}", handle);
+        Ok(None)
+    })
+}
+
+/// Create a space; `value` is its id
+///
+/// # Safety
+/// `name` must be a NUL-terminated string
+#[no_mangle]
+pub unsafe extern "C" fn noteece_space_create(
+    handle: NoteeceHandle,
+    name: *const c_char,
+) -> NoteeceResult {
+    ffi_call("noteece_space_create", || {
+        let name = read_str(name, "name")?;
+        let session = session(handle)?;
+        let mut conn = session.conn()?;
+        let space_id = crate::space::create_space(&mut conn, name)?;
+        Ok(Some(space_id.to_string()))
+    })
+}
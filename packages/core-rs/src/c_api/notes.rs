@@ -0,0 +1,95 @@
+//! Note functions of the C API
+
+use super::{ffi_call, parse_ulid, read_str, session, to_json, NoteeceHandle, NoteeceResult};
+use super::{FfiError, NOTEECE_NOT_FOUND};
+use crate::note::DbUlid;
+use std::os::raw::c_char;
+
+/// Create a note; `value` is the note as JSON
+///
+/// # Safety
+/// All string arguments must be NUL-terminated strings
+#[no_mangle]
+pub unsafe extern "C" fn noteece_note_create(
+    handle: NoteeceHandle,
+    space_id: *const c_char,
+    title: *const c_char,
+    content_md: *const c_char,
+) -> NoteeceResult {
+    ffi_call("noteece_note_create", || {
+        let space_id = parse_ulid(read_str(space_id, "space_id")?, "space_id")?;
+        let title = read_str(title, "title")?;
+        let content_md = read_str(content_md, "content_md")?;
+        let session = session(handle)?;
+        let conn = session.conn()?;
+        let note = crate::note::create_note(&conn, &space_id.to_string(), title, content_md)?;
+        to_json(&note)
+    })
+}
+
+/// Get a note as JSON; fails with [`NOTEECE_NOT_FOUND`] if there is none
+///
+/// # Safety
+/// `note_id` must be a NUL-terminated string
+#[no_mangle]
+pub unsafe extern "C" fn noteece_note_get(
+    handle: NoteeceHandle,
+    note_id: *const c_char,
+) -> NoteeceResult {
+    ffi_call("noteece_note_get", || {
+        let note_id = parse_ulid(read_str(note_id, "note_id")?, "note_id")?;
+        let session = session(handle)?;
+        let conn = session.conn()?;
+        let note = crate::note::get_note(&conn, DbUlid(note_id))?
+            .ok_or_else(|| FfiError::new(NOTEECE_NOT_FOUND, format!("Note {}", note_id)))?;
+        to_json(&note)
+    })
+}
+
+/// Replace the title and content of a note
+///
+/// # Safety
+/// All string arguments must be NUL-terminated strings
+#[no_mangle]
+pub unsafe extern "C" fn noteece_note_update(
+    handle: NoteeceHandle,
+    note_id: *const c_char,
+    title: *const c_char,
+    content_md: *const c_char,
+) -> NoteeceResult {
+    ffi_call("noteece_note_update", || {
+        let note_id = parse_ulid(read_str(note_id, "note_id")?, "note_id")?;
+        let title = read_str(title, "title")?;
+        let content_md = read_str(content_md, "content_md")?;
+        let session = session(handle)?;
+        let mut conn = session.conn()?;
+        if crate::note::get_note(&conn, DbUlid(note_id))?.is_none() {
+            return Err(FfiError::new(
+                NOTEECE_NOT_FOUND,
+                format!("Note {}", note_id),
+            ));
+        }
+        crate::note::update_note_content(&mut conn, DbUlid(note_id), title, content_md)?;
+        Ok(None)
+    })
+}
+
+/// Full-text search of the notes in a space; `value` is a JSON array
+///
+/// # Safety
+/// Both string arguments must be NUL-terminated strings
+#[no_mangle]
+pub unsafe extern "C" fn noteece_note_search(
+    handle: NoteeceHandle,
+    space_id: *const c_char,
+    query: *const c_char,
+) -> NoteeceResult {
+    ffi_call("noteece_note_search", || {
+        let space_id = parse_ulid(read_str(space_id, "space_id")?, "space_id")?;
+        let query = read_str(query, "query")?;
+        let session = session(handle)?;
+        let conn = session.conn()?;
+        let notes = crate::search::search_notes(&conn, query, &space_id.to_string())?;
+        to_json(&notes)
+    })
+}
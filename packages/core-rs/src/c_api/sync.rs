@@ -0,0 +1,81 @@
+//! Sync exchange for the C API
+//!
+//! The mobile app owns the transport. It sends its manifest and deltas to a
+//! peer and applies what comes back; these functions only produce and
+//! consume the JSON.
+
+use super::{ffi_call, parse_ulid, read_str, session, to_json, NoteeceHandle, NoteeceResult};
+use super::{VaultSession, DEVICE_NAME};
+use crate::sync::{SyncAgent, SyncDelta};
+use std::os::raw::c_char;
+
+/// Sync port reported to peers; the app's transport decides the real one
+const SYNC_PORT: u16 = 8765;
+
+fn agent(session: &VaultSession) -> SyncAgent {
+    SyncAgent::new(
+        session.device_id().to_string(),
+        DEVICE_NAME.to_string(),
+        SYNC_PORT,
+    )
+}
+
+/// This device's manifest for a space, as JSON
+///
+/// # Safety
+/// `space_id` must be a NUL-terminated string
+#[no_mangle]
+pub unsafe extern "C" fn noteece_sync_manifest(
+    handle: NoteeceHandle,
+    space_id: *const c_char,
+) -> NoteeceResult {
+    ffi_call("noteece_sync_manifest", || {
+        let space_id = parse_ulid(read_str(space_id, "space_id")?, "space_id")?;
+        let session = session(handle)?;
+        let conn = session.conn()?;
+        let manifest = agent(&session).create_manifest(&conn, space_id)?;
+        to_json(&manifest)
+    })
+}
+
+/// Changes in a space since `since` (unix seconds), as a JSON array of deltas
+///
+/// # Safety
+/// `space_id` must be a NUL-terminated string
+#[no_mangle]
+pub unsafe extern "C" fn noteece_sync_deltas_since(
+    handle: NoteeceHandle,
+    space_id: *const c_char,
+    since: i64,
+) -> NoteeceResult {
+    ffi_call("noteece_sync_deltas_since", || {
+        let space_id = parse_ulid(read_str(space_id, "space_id")?, "space_id")?;
+        let session = session(handle)?;
+        let conn = session.conn()?;
+        let deltas = agent(&session).get_deltas_since(&conn, space_id, since)?;
+        to_json(&deltas)
+    })
+}
+
+/// Apply a JSON array of deltas received from `source_device_id`. `value` is
+/// a JSON array of the conflicts that need resolving.
+///
+/// # Safety
+/// Both string arguments must be NUL-terminated strings
+#[no_mangle]
+pub unsafe extern "C" fn noteece_sync_apply_deltas(
+    handle: NoteeceHandle,
+    source_device_id: *const c_char,
+    deltas_json: *const c_char,
+) -> NoteeceResult {
+    ffi_call("noteece_sync_apply_deltas", || {
+        let source_device_id = read_str(source_device_id, "source_device_id")?;
+        let deltas: Vec<SyncDelta> = serde_json::from_str(read_str(deltas_json, "deltas_json")?)?;
+        let session = session(handle)?;
+        let dek = session.dek()?;
+        let mut conn = session.conn()?;
+        let conflicts =
+            agent(&session).apply_deltas_from(&mut conn, deltas, &*dek, source_device_id)?;
+        to_json(&conflicts)
+    })
+}
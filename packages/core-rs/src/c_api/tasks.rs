@@ -0,0 +1,110 @@
+//! Task functions of the C API
+
+use super::{
+    ffi_call, parse_ulid, read_opt_str, read_str, session, to_json, FfiError, NoteeceHandle,
+    NoteeceResult, NOTEECE_NOT_FOUND,
+};
+use crate::task::Task;
+use std::os::raw::c_char;
+
+/// Create a task; `description` may be null. `value` is the task as JSON.
+///
+/// # Safety
+/// `space_id` and `title` must be NUL-terminated strings, `description` null
+/// or one
+#[no_mangle]
+pub unsafe extern "C" fn noteece_task_create(
+    handle: NoteeceHandle,
+    space_id: *const c_char,
+    title: *const c_char,
+    description: *const c_char,
+) -> NoteeceResult {
+    ffi_call("noteece_task_create", || {
+        let space_id = parse_ulid(read_str(space_id, "space_id")?, "space_id")?;
+        let title = read_str(title, "title")?;
+        let description = read_opt_str(description, "description")?.map(str::to_string);
+        let session = session(handle)?;
+        let conn = session.conn()?;
+        let task = crate::task::create_task(&conn, space_id, title, description)?;
+        to_json(&task)
+    })
+}
+
+/// Get a task as JSON; fails with [`NOTEECE_NOT_FOUND`] if there is none
+///
+/// # Safety
+/// `task_id` must be a NUL-terminated string
+#[no_mangle]
+pub unsafe extern "C" fn noteece_task_get(
+    handle: NoteeceHandle,
+    task_id: *const c_char,
+) -> NoteeceResult {
+    ffi_call("noteece_task_get", || {
+        let task_id = parse_ulid(read_str(task_id, "task_id")?, "task_id")?;
+        let session = session(handle)?;
+        let conn = session.conn()?;
+        let task = crate::task::get_task(&conn, task_id)?
+            .ok_or_else(|| FfiError::new(NOTEECE_NOT_FOUND, format!("Task {}", task_id)))?;
+        to_json(&task)
+    })
+}
+
+/// Save a task given as JSON, in the shape `noteece_task_get` returns it
+///
+/// # Safety
+/// `task_json` must be a NUL-terminated string
+#[no_mangle]
+pub unsafe extern "C" fn noteece_task_update(
+    handle: NoteeceHandle,
+    task_json: *const c_char,
+) -> NoteeceResult {
+    ffi_call("noteece_task_update", || {
+        let task: Task = serde_json::from_str(read_str(task_json, "task_json")?)?;
+        let session = session(handle)?;
+        let conn = session.conn()?;
+        if crate::task::get_task(&conn, task.id)?.is_none() {
+            return Err(FfiError::new(
+                NOTEECE_NOT_FOUND,
+                format!("Task {}", task.id),
+            ));
+        }
+        crate::task::update_task(&conn, &task)?;
+        Ok(None)
+    })
+}
+
+/// Delete a task
+///
+/// # Safety
+/// `task_id` must be a NUL-terminated string
+#[no_mangle]
+pub unsafe extern "C" fn noteece_task_delete(
+    handle: NoteeceHandle,
+    task_id: *const c_char,
+) -> NoteeceResult {
+    ffi_call("noteece_task_delete", || {
+        let task_id = parse_ulid(read_str(task_id, "task_id")?, "task_id")?;
+        let session = session(handle)?;
+        let conn = session.conn()?;
+        crate::task::delete_task(&conn, task_id)?;
+        Ok(None)
+    })
+}
+
+/// Every task in a space; `value` is a JSON array
+///
+/// # Safety
+/// `space_id` must be a NUL-terminated string
+#[no_mangle]
+pub unsafe extern "C" fn noteece_task_list(
+    handle: NoteeceHandle,
+    space_id: *const c_char,
+) -> NoteeceResult {
+    ffi_call("noteece_task_list", || {
+        let space_id = parse_ulid(read_str(space_id, "space_id")?, "space_id")?;
+        let session = session(handle)?;
+        let conn = session.conn()?;
+        let tasks = crate::task::get_all_tasks_in_space(&conn, space_id)?;
+        to_json(&tasks)
+    })
+}
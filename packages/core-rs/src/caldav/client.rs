@@ -1,7 +1,16 @@
 use crate::caldav::error::CalDavError;
-use crate::caldav::models::{CalDavAccount, SyncDirection};
+use crate::caldav::models::{
+    CalDavAccount, CalDavAuthType, OAuthClient, OAuthFlow, OAuthTokens, SyncDirection,
+};
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
 use chrono::Utc;
+use rand::rngs::OsRng;
+use rand::RngCore;
+use reqwest::blocking::{Client, RequestBuilder, Response};
 use rusqlite::Connection;
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
 use ulid::Ulid;
 
 /// Add a CalDAV account
@@ -40,7 +49,12 @@ pub fn add_caldav_account(
         id: id.clone(),
         url: url.to_string(),
         username: username.to_string(),
+        auth_type: CalDavAuthType::Basic,
         encrypted_password,
+        encrypted_access_token: None,
+        encrypted_refresh_token: None,
+        token_expires_at: None,
+        encrypted_oauth_client: None,
         calendar_path: calendar_path.to_string(),
         sync_token: None,
         last_sync: None,
@@ -52,38 +66,50 @@ pub fn add_caldav_account(
     })
 }
 
+const ACCOUNT_COLUMNS: &str =
+    "id, url, username, encrypted_password, calendar_path, sync_token, last_sync,
+     enabled, auto_sync, sync_frequency_minutes, sync_direction, created_at,
+     auth_type, encrypted_access_token, encrypted_refresh_token, token_expires_at,
+     encrypted_oauth_client";
+
+fn account_from_row(row: &rusqlite::Row) -> rusqlite::Result<CalDavAccount> {
+    let direction_str: String = row.get(10)?;
+    let direction = match direction_str.as_str() {
+        "pull" => SyncDirection::Pull,
+        "push" => SyncDirection::Push,
+        _ => SyncDirection::Bidirectional,
+    };
+    let auth_type: String = row.get(12)?;
+
+    Ok(CalDavAccount {
+        id: row.get(0)?,
+        url: row.get(1)?,
+        username: row.get(2)?,
+        auth_type: CalDavAuthType::parse(&auth_type),
+        encrypted_password: row.get(3)?,
+        encrypted_access_token: row.get(13)?,
+        encrypted_refresh_token: row.get(14)?,
+        token_expires_at: row.get(15)?,
+        encrypted_oauth_client: row.get(16)?,
+        calendar_path: row.get(4)?,
+        sync_token: row.get(5)?,
+        last_sync: row.get(6)?,
+        enabled: row.get::<_, i32>(7)? == 1,
+        auto_sync: row.get::<_, i32>(8)? == 1,
+        sync_frequency_minutes: row.get(9)?,
+        sync_direction: direction,
+        created_at: row.get(11)?,
+    })
+}
+
 /// Get all CalDAV accounts
 pub fn get_caldav_accounts(conn: &Connection) -> Result<Vec<CalDavAccount>, CalDavError> {
-    let mut stmt = conn.prepare(
-        "SELECT id, url, username, encrypted_password, calendar_path, sync_token, last_sync,
-                enabled, auto_sync, sync_frequency_minutes, sync_direction, created_at
-         FROM caldav_account
-         ORDER BY created_at DESC",
-    )?;
+    let mut stmt = conn.prepare(&format!(
+        "SELECT {} FROM caldav_account ORDER BY created_at DESC",
+        ACCOUNT_COLUMNS
+    ))?;
 
-    let accounts = stmt.query_map([], |row| {
-        let direction_str: String = row.get(10)?;
-        let direction = match direction_str.as_str() {
-            "pull" => SyncDirection::Pull,
-            "push" => SyncDirection::Push,
-            _ => SyncDirection::Bidirectional,
-        };
-
-        Ok(CalDavAccount {
-            id: row.get(0)?,
-            url: row.get(1)?,
-            username: row.get(2)?,
-            encrypted_password: row.get(3)?,
-            calendar_path: row.get(4)?,
-            sync_token: row.get(5)?,
-            last_sync: row.get(6)?,
-            enabled: row.get::<_, i32>(7)? == 1,
-            auto_sync: row.get::<_, i32>(8)? == 1,
-            sync_frequency_minutes: row.get(9)?,
-            sync_direction: direction,
-            created_at: row.get(11)?,
-        })
-    })?;
+    let accounts = stmt.query_map([], account_from_row)?;
 
     let mut result = Vec::new();
     for account in accounts {
@@ -98,35 +124,12 @@ pub fn get_caldav_account(
     conn: &Connection,
     account_id: &str,
 ) -> Result<Option<CalDavAccount>, CalDavError> {
-    let mut stmt = conn.prepare(
-        "SELECT id, url, username, encrypted_password, calendar_path, sync_token, last_sync,
-                enabled, auto_sync, sync_frequency_minutes, sync_direction, created_at
-         FROM caldav_account WHERE id = ?1",
-    )?;
+    let mut stmt = conn.prepare(&format!(
+        "SELECT {} FROM caldav_account WHERE id = ?1",
+        ACCOUNT_COLUMNS
+    ))?;
 
-    let result = stmt.query_row([account_id], |row| {
-        let direction_str: String = row.get(10)?;
-        let direction = match direction_str.as_str() {
-            "pull" => SyncDirection::Pull,
-            "push" => SyncDirection::Push,
-            _ => SyncDirection::Bidirectional,
-        };
-
-        Ok(CalDavAccount {
-            id: row.get(0)?,
-            url: row.get(1)?,
-            username: row.get(2)?,
-            encrypted_password: row.get(3)?,
-            calendar_path: row.get(4)?,
-            sync_token: row.get(5)?,
-            last_sync: row.get(6)?,
-            enabled: row.get::<_, i32>(7)? == 1,
-            auto_sync: row.get::<_, i32>(8)? == 1,
-            sync_frequency_minutes: row.get(9)?,
-            sync_direction: direction,
-            created_at: row.get(11)?,
-        })
-    });
+    let result = stmt.query_row([account_id], account_from_row);
 
     match result {
         Ok(account) => Ok(Some(account)),
@@ -217,3 +220,318 @@ pub fn delete_caldav_account(conn: &Connection, account_id: &str) -> Result<(),
     conn.execute("DELETE FROM caldav_account WHERE id = ?1", [account_id])?;
     Ok(())
 }
+
+fn encrypt(value: &str, dek: &[u8]) -> Result<String, CalDavError> {
+    crate::crypto::encrypt_string(value, dek).map_err(|e| CalDavError::Parse(e.to_string()))
+}
+
+fn decrypt(value: &str, dek: &[u8], what: &str) -> Result<String, CalDavError> {
+    crate::crypto::decrypt_string(value, dek)
+        .map_err(|e| CalDavError::Parse(format!("Failed to decrypt {}: {}", what, e)))
+}
+
+/// Add a CalDAV account that authenticates with a bearer token. Pass the
+/// OAuth client the tokens came from so they can be refreshed; a static app
+/// token has neither a refresh token nor a client.
+pub fn add_caldav_bearer_account(
+    conn: &Connection,
+    url: &str,
+    username: &str,
+    calendar_path: &str,
+    tokens: &OAuthTokens,
+    oauth_client: Option<&OAuthClient>,
+    dek: &[u8],
+) -> Result<CalDavAccount, CalDavError> {
+    let id = Ulid::new().to_string();
+    let now = Utc::now().timestamp();
+    let encrypted_password = encrypt("", dek)?;
+    let encrypted_access_token = encrypt(&tokens.access_token, dek)?;
+    let encrypted_refresh_token = tokens
+        .refresh_token
+        .as_deref()
+        .map(|t| encrypt(t, dek))
+        .transpose()?;
+    let encrypted_oauth_client = oauth_client
+        .map(|c| {
+            let json = serde_json::to_string(c).map_err(|e| CalDavError::Parse(e.to_string()))?;
+            encrypt(&json, dek)
+        })
+        .transpose()?;
+
+    conn.execute(
+        "INSERT INTO caldav_account (
+            id, url, username, encrypted_password, calendar_path,
+            enabled, auto_sync, sync_frequency_minutes, sync_direction, created_at,
+            auth_type, encrypted_access_token, encrypted_refresh_token, token_expires_at,
+            encrypted_oauth_client
+        ) VALUES (?1, ?2, ?3, ?4, ?5, 1, 1, 15, 'bidirectional', ?6, 'bearer', ?7, ?8, ?9, ?10)",
+        rusqlite::params![
+            id,
+            url,
+            username,
+            encrypted_password,
+            calendar_path,
+            now,
+            encrypted_access_token,
+            encrypted_refresh_token,
+            tokens.expires_at,
+            encrypted_oauth_client
+        ],
+    )?;
+
+    Ok(CalDavAccount {
+        id,
+        url: url.to_string(),
+        username: username.to_string(),
+        auth_type: CalDavAuthType::Bearer,
+        encrypted_password,
+        encrypted_access_token: Some(encrypted_access_token),
+        encrypted_refresh_token,
+        token_expires_at: tokens.expires_at,
+        encrypted_oauth_client,
+        calendar_path: calendar_path.to_string(),
+        sync_token: None,
+        last_sync: None,
+        enabled: true,
+        auto_sync: true,
+        sync_frequency_minutes: 15,
+        sync_direction: SyncDirection::Bidirectional,
+        created_at: now,
+    })
+}
+
+/// Store freshly issued tokens. A refresh that doesn't rotate the refresh
+/// token keeps the current one.
+pub fn store_caldav_tokens(
+    conn: &Connection,
+    account_id: &str,
+    tokens: &OAuthTokens,
+    dek: &[u8],
+) -> Result<(), CalDavError> {
+    let encrypted_refresh_token = tokens
+        .refresh_token
+        .as_deref()
+        .map(|t| encrypt(t, dek))
+        .transpose()?;
+    let updated = conn.execute(
+        "UPDATE caldav_account
+         SET auth_type = 'bearer', encrypted_access_token = ?1,
+             encrypted_refresh_token = COALESCE(?2, encrypted_refresh_token),
+             token_expires_at = ?3
+         WHERE id = ?4",
+        rusqlite::params![
+            encrypt(&tokens.access_token, dek)?,
+            encrypted_refresh_token,
+            tokens.expires_at,
+            account_id
+        ],
+    )?;
+    if updated == 0 {
+        return Err(CalDavError::AccountNotFound);
+    }
+    Ok(())
+}
+
+/// CalDAV servers and token endpoints receive credentials, so they must be
+/// reached over HTTPS (or loopback, for testing)
+pub(crate) fn ensure_secure_url(url: &str) -> Result<(), CalDavError> {
+    if !url.starts_with("https://")
+        && !url.starts_with("http://localhost")
+        && !url.starts_with("http://127.0.0.1")
+    {
+        return Err(CalDavError::Network(
+            "CalDAV URL must use HTTPS (or localhost for testing). HTTP is insecure for credential transmission.".to_string()
+        ));
+    }
+    Ok(())
+}
+
+pub(crate) fn http_client() -> Result<Client, CalDavError> {
+    Ok(Client::builder()
+        .timeout(std::time::Duration::from_secs(30))
+        .redirect(reqwest::redirect::Policy::none())
+        .build()?)
+}
+
+fn random_token(bytes: usize) -> String {
+    let mut buf = vec![0u8; bytes];
+    OsRng.fill_bytes(&mut buf);
+    URL_SAFE_NO_PAD.encode(buf)
+}
+
+/// Begin the OAuth2 authorization-code flow with PKCE (RFC 7636) for an
+/// installed app
+pub fn start_oauth_flow(client: &OAuthClient) -> Result<OAuthFlow, CalDavError> {
+    ensure_secure_url(&client.token_url)?;
+    let code_verifier = random_token(32);
+    let code_challenge = URL_SAFE_NO_PAD.encode(Sha256::digest(code_verifier.as_bytes()));
+    let state = random_token(16);
+
+    let scope = client.scopes.join(" ");
+    let mut params = vec![
+        ("response_type", "code"),
+        ("client_id", client.client_id.as_str()),
+        ("redirect_uri", client.redirect_uri.as_str()),
+        ("scope", scope.as_str()),
+        ("code_challenge", code_challenge.as_str()),
+        ("code_challenge_method", "S256"),
+        ("state", state.as_str()),
+    ];
+    for (key, value) in &client.extra_auth_params {
+        params.push((key.as_str(), value.as_str()));
+    }
+    let auth_url = reqwest::Url::parse_with_params(&client.auth_url, &params)
+        .map_err(|e| CalDavError::Parse(format!("Invalid authorization URL: {}", e)))?;
+
+    Ok(OAuthFlow {
+        auth_url: auth_url.to_string(),
+        code_verifier,
+        state,
+    })
+}
+
+#[derive(Deserialize)]
+struct TokenResponse {
+    access_token: String,
+    refresh_token: Option<String>,
+    expires_in: Option<i64>,
+}
+
+fn request_tokens(
+    client: &OAuthClient,
+    grant: &[(&str, &str)],
+) -> Result<OAuthTokens, CalDavError> {
+    ensure_secure_url(&client.token_url)?;
+    let mut form = grant.to_vec();
+    form.push(("client_id", client.client_id.as_str()));
+    if let Some(secret) = &client.client_secret {
+        form.push(("client_secret", secret.as_str()));
+    }
+
+    let response = http_client()?
+        .post(&client.token_url)
+        .header(reqwest::header::ACCEPT, "application/json")
+        .form(&form)
+        .send()?;
+    let status = response.status();
+    // invalid_grant and invalid_client: the user has to authorize again
+    if status == reqwest::StatusCode::BAD_REQUEST || status == reqwest::StatusCode::UNAUTHORIZED {
+        return Err(CalDavError::Authentication);
+    }
+    if !status.is_success() {
+        return Err(CalDavError::Network(format!(
+            "OAuth token request failed: {}",
+            status
+        )));
+    }
+
+    let body: TokenResponse = response
+        .json()
+        .map_err(|e| CalDavError::Parse(format!("Invalid token response: {}", e)))?;
+    Ok(OAuthTokens {
+        access_token: body.access_token,
+        refresh_token: body.refresh_token,
+        expires_at: body
+            .expires_in
+            .map(|seconds| Utc::now().timestamp() + seconds),
+    })
+}
+
+/// Exchange the authorization code the browser was redirected back with
+pub fn finish_oauth_flow(
+    client: &OAuthClient,
+    code: &str,
+    code_verifier: &str,
+) -> Result<OAuthTokens, CalDavError> {
+    request_tokens(
+        client,
+        &[
+            ("grant_type", "authorization_code"),
+            ("code", code),
+            ("redirect_uri", client.redirect_uri.as_str()),
+            ("code_verifier", code_verifier),
+        ],
+    )
+}
+
+/// Get a new access token for the account with its refresh token, store it
+/// and return it
+pub fn refresh_access_token(
+    conn: &Connection,
+    account_id: &str,
+    dek: &[u8],
+) -> Result<String, CalDavError> {
+    let account = get_caldav_account(conn, account_id)?.ok_or(CalDavError::AccountNotFound)?;
+    let (Some(refresh_token), Some(oauth_client)) = (
+        account.encrypted_refresh_token.as_deref(),
+        account.encrypted_oauth_client.as_deref(),
+    ) else {
+        return Err(CalDavError::Authentication);
+    };
+    let refresh_token = decrypt(refresh_token, dek, "refresh token")?;
+    let oauth_client: OAuthClient =
+        serde_json::from_str(&decrypt(oauth_client, dek, "OAuth client")?)
+            .map_err(|e| CalDavError::Parse(e.to_string()))?;
+
+    let tokens = request_tokens(
+        &oauth_client,
+        &[
+            ("grant_type", "refresh_token"),
+            ("refresh_token", refresh_token.as_str()),
+        ],
+    )?;
+    store_caldav_tokens(conn, account_id, &tokens, dek)?;
+    log::info!("[caldav] Refreshed access token for account {}", account_id);
+    Ok(tokens.access_token)
+}
+
+/// Attach the account's Authorization header
+fn authorize(
+    request: RequestBuilder,
+    account: &CalDavAccount,
+    dek: &[u8],
+) -> Result<RequestBuilder, CalDavError> {
+    match account.auth_type {
+        CalDavAuthType::Basic => {
+            let password = decrypt(&account.encrypted_password, dek, "password")?;
+            Ok(request.basic_auth(&account.username, Some(password)))
+        }
+        CalDavAuthType::Bearer => {
+            let token = account
+                .encrypted_access_token
+                .as_deref()
+                .ok_or(CalDavError::Authentication)?;
+            Ok(request.bearer_auth(decrypt(token, dek, "access token")?))
+        }
+    }
+}
+
+/// Send a request built by `build` with the account's credentials. When a
+/// bearer token is rejected and the account can refresh it, the token is
+/// refreshed and the request retried once; any other response, including a
+/// second 401, is returned as is.
+pub fn send_authorized<F>(
+    conn: &Connection,
+    account: &CalDavAccount,
+    dek: &[u8],
+    build: F,
+) -> Result<Response, CalDavError>
+where
+    F: Fn(&Client) -> Result<RequestBuilder, CalDavError>,
+{
+    let client = http_client()?;
+    let response = authorize(build(&client)?, account, dek)?.send()?;
+    let can_refresh = account.auth_type == CalDavAuthType::Bearer
+        && account.encrypted_refresh_token.is_some()
+        && account.encrypted_oauth_client.is_some();
+    if response.status() != reqwest::StatusCode::UNAUTHORIZED || !can_refresh {
+        return Ok(response);
+    }
+
+    log::info!(
+        "[caldav] Access token rejected for account {}, refreshing",
+        account.id
+    );
+    let access_token = refresh_access_token(conn, &account.id, dek)?;
+    Ok(build(&client)?.bearer_auth(access_token).send()?)
+}
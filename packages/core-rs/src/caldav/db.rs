@@ -16,7 +16,12 @@ pub fn init_caldav_tables(conn: &Connection) -> Result<(), CalDavError> {
             auto_sync INTEGER NOT NULL DEFAULT 1,
             sync_frequency_minutes INTEGER NOT NULL DEFAULT 15,
             sync_direction TEXT NOT NULL DEFAULT 'bidirectional',
-            created_at INTEGER NOT NULL
+            created_at INTEGER NOT NULL,
+            auth_type TEXT NOT NULL DEFAULT 'basic',
+            encrypted_access_token TEXT,
+            encrypted_refresh_token TEXT,
+            token_expires_at INTEGER,
+            encrypted_oauth_client TEXT
         )",
         [],
     )?;
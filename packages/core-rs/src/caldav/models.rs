@@ -5,8 +5,20 @@ pub struct CalDavAccount {
     pub id: String,
     pub url: String,
     pub username: String,
+    #[serde(default)]
+    pub auth_type: CalDavAuthType,
     #[serde(skip_serializing)]
     pub encrypted_password: String,
+    /// Bearer token, encrypted with the DEK like the password
+    #[serde(skip_serializing)]
+    pub encrypted_access_token: Option<String>,
+    #[serde(skip_serializing)]
+    pub encrypted_refresh_token: Option<String>,
+    pub token_expires_at: Option<i64>,
+    /// The [`OAuthClient`] the tokens were issued to, as encrypted JSON,
+    /// needed to refresh them
+    #[serde(skip_serializing)]
+    pub encrypted_oauth_client: Option<String>,
     pub calendar_path: String,
     pub sync_token: Option<String>,
     pub last_sync: Option<i64>,
@@ -17,6 +29,86 @@ pub struct CalDavAccount {
     pub created_at: i64,
 }
 
+/// How requests to the server are authenticated
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum CalDavAuthType {
+    /// Username and password, or an app-specific password
+    #[default]
+    Basic,
+    /// An access token, refreshed through OAuth2 when the account has a
+    /// refresh token
+    Bearer,
+}
+
+impl CalDavAuthType {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            CalDavAuthType::Basic => "basic",
+            CalDavAuthType::Bearer => "bearer",
+        }
+    }
+
+    pub fn parse(value: &str) -> Self {
+        match value {
+            "bearer" => CalDavAuthType::Bearer,
+            _ => CalDavAuthType::Basic,
+        }
+    }
+}
+
+/// An OAuth2 client registration for the installed-app flow
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct OAuthClient {
+    pub auth_url: String,
+    pub token_url: String,
+    pub client_id: String,
+    /// Installed-app clients for Google still get a (non-confidential) secret
+    pub client_secret: Option<String>,
+    /// Loopback address the browser is sent back to with the code
+    pub redirect_uri: String,
+    pub scopes: Vec<String>,
+    /// Provider-specific parameters for the authorization URL
+    #[serde(default)]
+    pub extra_auth_params: Vec<(String, String)>,
+}
+
+impl OAuthClient {
+    /// Google Calendar's endpoints and scope. Google only issues a refresh
+    /// token when offline access and consent are requested explicitly.
+    pub fn google(client_id: &str, client_secret: Option<&str>, redirect_uri: &str) -> Self {
+        Self {
+            auth_url: "https://accounts.google.com/o/oauth2/v2/auth".to_string(),
+            token_url: "https://oauth2.googleapis.com/token".to_string(),
+            client_id: client_id.to_string(),
+            client_secret: client_secret.map(|s| s.to_string()),
+            redirect_uri: redirect_uri.to_string(),
+            scopes: vec!["https://www.googleapis.com/auth/calendar".to_string()],
+            extra_auth_params: vec![
+                ("access_type".to_string(), "offline".to_string()),
+                ("prompt".to_string(), "consent".to_string()),
+            ],
+        }
+    }
+}
+
+/// A started authorization: open `auth_url` in the browser, then pass the
+/// returned code and this `code_verifier` to `finish_oauth_flow`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OAuthFlow {
+    pub auth_url: String,
+    pub code_verifier: String,
+    /// Must match the `state` parameter the browser is redirected back with
+    pub state: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct OAuthTokens {
+    pub access_token: String,
+    pub refresh_token: Option<String>,
+    pub expires_at: Option<i64>,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 #[serde(rename_all = "lowercase")]
 pub enum SyncDirection {
@@ -35,7 +127,7 @@ impl SyncDirection {
     }
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct CalDavEvent {
     pub uid: String,
     pub summary: String,
@@ -1,51 +1,143 @@
 use crate::caldav::error::CalDavError;
 use crate::caldav::models::CalDavEvent;
 use chrono::{NaiveDate, NaiveDateTime, NaiveTime, Utc};
+use quick_xml::events::Event;
+use quick_xml::name::{Namespace, ResolveResult};
+use quick_xml::NsReader;
 use std::io::BufReader;
 
-/// Parse CalDAV XML response and extract calendar events
-///
-/// This function uses tolerant XML parsing that handles various namespace prefixes
-/// and case variations commonly seen in CalDAV responses from different servers.
-pub fn parse_calendar_response(xml_data: &str) -> Result<Vec<CalDavEvent>, CalDavError> {
-    let mut events = Vec::new();
+/// Largest REPORT response that is parsed
+pub const MAX_RESPONSE_SIZE: usize = 10 * 1024 * 1024;
 
-    // Convert to lowercase for case-insensitive matching
-    let lower_xml = xml_data.to_lowercase();
+const DAV_NS: &[u8] = b"DAV:";
+const CALDAV_NS: &[u8] = b"urn:ietf:params:xml:ns:caldav";
+
+/// Properties read from each `DAV:response`
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Property {
+    GetEtag,
+    CalendarData,
+}
 
-    // Find all calendar-data elements (namespace-agnostic, case-insensitive)
-    // Matches: <c:calendar-data>, <cal:calendar-data>, <C:CALENDAR-DATA>, etc.
-    let mut search_pos = 0;
-    while let Some(start_idx) = lower_xml[search_pos..].find(":calendar-data>") {
-        let tag_start_pos = search_pos + start_idx;
-        if let Some(_open_bracket) = lower_xml[..tag_start_pos].rfind('<') {
-            let content_start = tag_start_pos + ":calendar-data>".len();
+impl Property {
+    fn from_name(ns: &ResolveResult, local_name: &[u8]) -> Option<Self> {
+        let ResolveResult::Bound(Namespace(ns)) = ns else {
+            return None;
+        };
+        if *ns == DAV_NS && local_name.eq_ignore_ascii_case(b"getetag") {
+            Some(Property::GetEtag)
+        } else if *ns == CALDAV_NS && local_name.eq_ignore_ascii_case(b"calendar-data") {
+            Some(Property::CalendarData)
+        } else {
+            None
+        }
+    }
+}
 
-            if let Some(close_idx) = lower_xml[content_start..].find("</") {
-                let close_tag_start = content_start + close_idx;
+fn is_response(ns: &ResolveResult, local_name: &[u8]) -> bool {
+    matches!(ns, ResolveResult::Bound(Namespace(ns)) if *ns == DAV_NS)
+        && local_name.eq_ignore_ascii_case(b"response")
+}
 
-                // Verify this is actually a calendar-data closing tag
-                let is_calendar_close = lower_xml[close_tag_start..].starts_with("</")
-                    && (lower_xml[close_tag_start + 2..].starts_with("c:calendar-data>")
-                        || lower_xml[close_tag_start + 2..].starts_with("cal:calendar-data>")
-                        || lower_xml[close_tag_start + 2..].starts_with("calendar-data>"));
+/// What has been read of the current `DAV:response`
+#[derive(Default)]
+struct Response {
+    etag: Option<String>,
+    calendar_data: Vec<String>,
+}
 
-                if is_calendar_close {
-                    let ical_data = &xml_data[content_start..close_tag_start];
+impl Response {
+    fn set(&mut self, property: Property, value: String) {
+        let value = value.trim();
+        if value.is_empty() {
+            return;
+        }
+        match property {
+            Property::GetEtag => self.etag = Some(value.to_string()),
+            Property::CalendarData => self.calendar_data.push(value.to_string()),
+        }
+    }
 
-                    if let Ok(mut parsed_events) = parse_icalendar(ical_data) {
-                        events.append(&mut parsed_events);
-                    }
+    /// Events in the response's calendar data, tagged with its etag
+    fn into_events(self) -> Vec<CalDavEvent> {
+        let mut events = Vec::new();
+        for ical_data in &self.calendar_data {
+            if let Ok(parsed) = parse_icalendar(ical_data) {
+                events.extend(parsed.into_iter().map(|event| CalDavEvent {
+                    etag: self.etag.clone(),
+                    ..event
+                }));
+            }
+        }
+        events
+    }
+}
 
-                    search_pos = close_tag_start + "calendar-data>".len();
-                } else {
-                    search_pos = content_start;
+fn xml_error(e: impl std::fmt::Display) -> CalDavError {
+    CalDavError::Parse(format!("Invalid CalDAV XML: {}", e))
+}
+
+/// Parse a CalDAV multistatus response and extract calendar events
+///
+/// Elements are matched by namespace, so any prefix bound to `DAV:` or the
+/// CalDAV namespace works. Calendar data may be escaped text or CDATA. Each
+/// event gets the `getetag` of the `response` it came in; calendar data that
+/// doesn't parse as iCalendar is skipped.
+pub fn parse_calendar_response(xml_data: &str) -> Result<Vec<CalDavEvent>, CalDavError> {
+    if xml_data.len() > MAX_RESPONSE_SIZE {
+        return Err(CalDavError::Parse(format!(
+            "CalDAV response too large: {} bytes (limit: {} bytes)",
+            xml_data.len(),
+            MAX_RESPONSE_SIZE
+        )));
+    }
+
+    let mut reader = NsReader::from_str(xml_data);
+    let mut events = Vec::new();
+    let mut response: Option<Response> = None;
+    // The property being read and its text so far
+    let mut reading: Option<(Property, String)> = None;
+
+    loop {
+        match reader.read_resolved_event().map_err(xml_error)? {
+            (ns, Event::Start(e)) => {
+                let local_name = e.local_name();
+                if is_response(&ns, local_name.as_ref()) {
+                    response = Some(Response::default());
+                } else if response.is_some() {
+                    if let Some(property) = Property::from_name(&ns, local_name.as_ref()) {
+                        reading = Some((property, String::new()));
+                    }
                 }
-            } else {
-                break;
             }
-        } else {
-            search_pos = tag_start_pos + 1;
+            (_, Event::Text(e)) => {
+                if let Some((_, text)) = reading.as_mut() {
+                    text.push_str(&e.unescape().map_err(xml_error)?);
+                }
+            }
+            (_, Event::CData(e)) => {
+                if let Some((_, text)) = reading.as_mut() {
+                    text.push_str(std::str::from_utf8(&e).map_err(xml_error)?);
+                }
+            }
+            (ns, Event::End(e)) => {
+                let local_name = e.local_name();
+                if let Some((property, text)) = reading.take() {
+                    if Property::from_name(&ns, local_name.as_ref()) == Some(property) {
+                        if let Some(response) = response.as_mut() {
+                            response.set(property, text);
+                        }
+                    } else {
+                        reading = Some((property, text));
+                    }
+                } else if is_response(&ns, local_name.as_ref()) {
+                    if let Some(response) = response.take() {
+                        events.extend(response.into_events());
+                    }
+                }
+            }
+            (_, Event::Eof) => break,
+            _ => {}
         }
     }
 
@@ -1,11 +1,12 @@
-use crate::caldav::client::get_caldav_account;
+use crate::caldav::client::{ensure_secure_url, get_caldav_account, send_authorized};
 use crate::caldav::error::CalDavError;
 use crate::caldav::models::{
-    CalDavEvent, ConflictResolution, SyncConflict, SyncDirection, SyncResult,
+    CalDavAccount, CalDavEvent, ConflictResolution, SyncConflict, SyncDirection, SyncResult,
 };
-use crate::caldav::parser::parse_calendar_response;
+use crate::caldav::parser::{parse_calendar_response, MAX_RESPONSE_SIZE};
+use crate::events::{self, CoreEvent};
 use chrono::Utc;
-use reqwest::blocking::Client;
+use reqwest::blocking::Response;
 use rusqlite::{params, Connection, OptionalExtension};
 use ulid::Ulid;
 
@@ -258,25 +259,58 @@ pub fn map_caldav_event(
     Ok(id)
 }
 
+/// Reject redirects and map auth failures and other error statuses
+fn check_response(response: &Response, what: &str) -> Result<(), CalDavError> {
+    if response.status().is_redirection() {
+        return Err(CalDavError::Network(format!(
+            "Unexpected redirect ({}). Redirects are disabled for security.",
+            response.status()
+        )));
+    }
+
+    if response.status() == reqwest::StatusCode::UNAUTHORIZED {
+        return Err(CalDavError::Authentication);
+    }
+
+    if !response.status().is_success() {
+        return Err(CalDavError::Network(format!(
+            "CalDAV {} failed: {}",
+            what,
+            response.status()
+        )));
+    }
+    Ok(())
+}
+
+/// The calendar collection's URL; `calendar_path` may be absolute
+pub fn calendar_url(account: &CalDavAccount) -> String {
+    if account.calendar_path.starts_with("http") {
+        account.calendar_path.clone()
+    } else {
+        format!(
+            "{}/{}",
+            account.url.trim_end_matches('/'),
+            account.calendar_path.trim_start_matches('/')
+        )
+    }
+}
+
+fn event_url(account: &CalDavAccount, uid: &str) -> String {
+    format!(
+        "{}/{}.ics",
+        calendar_url(account).trim_end_matches('/'),
+        uid
+    )
+}
+
 /// Fetch calendar events from CalDAV server using REPORT request
 fn fetch_calendar_events(
-    url: &str,
-    username: &str,
-    password: &str,
+    conn: &Connection,
+    account: &CalDavAccount,
+    dek: &[u8],
 ) -> Result<Vec<CalDavEvent>, CalDavError> {
-    if !url.starts_with("https://")
-        && !url.starts_with("http://localhost")
-        && !url.starts_with("http://127.0.0.1")
-    {
-        return Err(CalDavError::Network(
-            "CalDAV URL must use HTTPS (or localhost for testing). HTTP is insecure for credential transmission.".to_string()
-        ));
-    }
-
-    let client = Client::builder()
-        .timeout(std::time::Duration::from_secs(30))
-        .redirect(reqwest::redirect::Policy::none())
-        .build()?;
+    let url = calendar_url(account);
+    ensure_secure_url(&url)?;
 
     let report_body = r#"<?xml version="1.0" encoding="utf-8" ?>
 <C:calendar-query xmlns:D="DAV:" xmlns:C="urn:ietf:params:xml:ns:caldav">
@@ -291,35 +325,18 @@ fn fetch_calendar_events(
   </C:filter>
 </C:calendar-query>"#;
 
-    let response = client
-        .request(
-            reqwest::Method::from_bytes(b"REPORT")
-                .map_err(|e| CalDavError::Parse(e.to_string()))?,
-            url,
-        )
-        .basic_auth(username, Some(password))
-        .header("Depth", "1")
-        .header("Content-Type", "application/xml; charset=utf-8")
-        .body(report_body)
-        .send()?;
-
-    if response.status().is_redirection() {
-        return Err(CalDavError::Network(format!(
-            "Unexpected redirect ({}). Redirects are disabled for security.",
-            response.status()
-        )));
-    }
-
-    if response.status() == reqwest::StatusCode::UNAUTHORIZED {
-        return Err(CalDavError::Authentication);
-    }
-
-    if !response.status().is_success() {
-        return Err(CalDavError::Network(format!(
-            "CalDAV REPORT failed: {}",
-            response.status()
-        )));
-    }
+    let response = send_authorized(conn, account, dek, |client| {
+        Ok(client
+            .request(
+                reqwest::Method::from_bytes(b"REPORT")
+                    .map_err(|e| CalDavError::Parse(e.to_string()))?,
+                &url,
+            )
+            .header("Depth", "1")
+            .header("Content-Type", "application/xml; charset=utf-8")
+            .body(report_body))
+    })?;
+    check_response(&response, "REPORT")?;
 
     if let Some(content_type) = response.headers().get(reqwest::header::CONTENT_TYPE) {
         match content_type.to_str() {
@@ -339,7 +356,6 @@ fn fetch_calendar_events(
         }
     }
 
-    const MAX_RESPONSE_SIZE: usize = 10 * 1024 * 1024;
     let bytes = response.bytes()?;
     if bytes.len() > MAX_RESPONSE_SIZE {
         return Err(CalDavError::Network(format!(
@@ -353,19 +369,81 @@ fn fetch_calendar_events(
     parse_calendar_response(&response_text)
 }
 
+/// Create or replace an event on the server. With the etag last seen the
+/// write only succeeds if nobody changed the event since; without one it only
+/// succeeds if the event doesn't exist yet. Returns the new etag if the
+/// server sent one.
+pub fn put_calendar_event(
+    conn: &Connection,
+    account: &CalDavAccount,
+    dek: &[u8],
+    uid: &str,
+    ical: &str,
+    etag: Option<&str>,
+) -> Result<Option<String>, CalDavError> {
+    let url = event_url(account, uid);
+    ensure_secure_url(&url)?;
+    let response = send_authorized(conn, account, dek, |client| {
+        let request = client
+            .put(&url)
+            .header("Content-Type", "text/calendar; charset=utf-8")
+            .body(ical.to_string());
+        Ok(match etag {
+            Some(etag) => request.header("If-Match", etag),
+            None => request.header("If-None-Match", "*"),
+        })
+    })?;
+    if response.status() == reqwest::StatusCode::PRECONDITION_FAILED {
+        return Err(CalDavError::Conflict(format!(
+            "Event {} changed on the server",
+            uid
+        )));
+    }
+    check_response(&response, "PUT")?;
+
+    Ok(response
+        .headers()
+        .get(reqwest::header::ETAG)
+        .and_then(|v| v.to_str().ok())
+        .map(|v| v.to_string()))
+}
+
+/// Delete an event on the server. An event that is already gone counts as
+/// deleted.
+pub fn delete_calendar_event(
+    conn: &Connection,
+    account: &CalDavAccount,
+    dek: &[u8],
+    uid: &str,
+    etag: Option<&str>,
+) -> Result<(), CalDavError> {
+    let url = event_url(account, uid);
+    ensure_secure_url(&url)?;
+    let response = send_authorized(conn, account, dek, |client| {
+        let request = client.delete(&url);
+        Ok(match etag {
+            Some(etag) => request.header("If-Match", etag),
+            None => request,
+        })
+    })?;
+    match response.status() {
+        reqwest::StatusCode::NOT_FOUND => Ok(()),
+        reqwest::StatusCode::PRECONDITION_FAILED => Err(CalDavError::Conflict(format!(
+            "Event {} changed on the server",
+            uid
+        ))),
+        _ => check_response(&response, "DELETE"),
+    }
+}
+
 /// Sync CalDAV account with real HTTP implementation
 pub fn sync_caldav_account(
     conn: &Connection,
     account_id: &str,
     dek: &[u8],
 ) -> Result<SyncResult, CalDavError> {
-    use crate::crypto::decrypt_string;
-
     let account = get_caldav_account(conn, account_id)?.ok_or(CalDavError::AccountNotFound)?;
 
-    let password = decrypt_string(&account.encrypted_password, dek)
-        .map_err(|e| CalDavError::Parse(format!("Failed to decrypt password: {}", e)))?;
-
     let now = Utc::now().timestamp();
     let mut events_pulled = 0u32;
     let events_pushed = 0u32;
@@ -373,20 +451,10 @@ pub fn sync_caldav_account(
     let mut errors = Vec::new();
     let mut success = true;
 
-    let calendar_url = if account.calendar_path.starts_with("http") {
-        account.calendar_path.clone()
-    } else {
-        format!(
-            "{}/{}",
-            account.url.trim_end_matches('/'),
-            account.calendar_path.trim_start_matches('/')
-        )
-    };
-
     if account.sync_direction == SyncDirection::Pull
         || account.sync_direction == SyncDirection::Bidirectional
     {
-        match fetch_calendar_events(&calendar_url, &account.username, &password) {
+        match fetch_calendar_events(conn, &account, dek) {
             Ok(remote_events) => {
                 events_pulled = remote_events.len() as u32;
 
@@ -450,6 +518,13 @@ pub fn sync_caldav_account(
                 }
             }
             Err(e) => {
+                if matches!(e, CalDavError::Authentication) {
+                    events::emit(CoreEvent::CaldavAuthFailed {
+                        account_id: account_id.to_string(),
+                        username: account.username.clone(),
+                        url: account.url.clone(),
+                    });
+                }
                 success = false;
                 errors.push(format!("Failed to fetch events: {}", e));
             }
@@ -1,7 +1,10 @@
 use crate::db::DbError;
 use crate::task::create_task;
+use crate::undo::{capture_rows, record_undo, UndoRecord, UndoSnapshot};
+use chrono::{DateTime, Datelike, Duration, NaiveDate, NaiveTime, Weekday};
 use ical::IcalParser;
-use rusqlite::Connection;
+use rusqlite::{Connection, OptionalExtension};
+use serde::{Deserialize, Serialize};
 use std::fs::File;
 use std::io::BufReader;
 use ulid::Ulid;
@@ -72,3 +75,613 @@ pub fn export_ics(conn: &Connection, path: &str, space_id: Ulid) -> Result<(), D
 
     Ok(())
 }
+
+const DAY_SECS: i64 = 24 * 60 * 60;
+
+/// Timed events stored without an end are assumed to last this long
+const DEFAULT_EVENT_SECS: i64 = 60 * 60;
+
+/// Half-open interval `[start, end)` of Unix timestamps in seconds
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TimeRange {
+    pub start: i64,
+    pub end: i64,
+}
+
+impl TimeRange {
+    pub fn new(start: i64, end: i64) -> Self {
+        TimeRange { start, end }
+    }
+
+    pub fn duration_secs(&self) -> i64 {
+        self.end - self.start
+    }
+}
+
+/// The part of each day that counts as available, in the user's local time
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct WorkingHours {
+    pub start: NaiveTime,
+    pub end: NaiveTime,
+    pub weekdays: Vec<Weekday>,
+    /// Offset of the user's local time from UTC
+    #[serde(default)]
+    pub utc_offset_minutes: i32,
+}
+
+impl Default for WorkingHours {
+    fn default() -> Self {
+        WorkingHours {
+            start: NaiveTime::from_hms_opt(9, 0, 0).expect("valid time"),
+            end: NaiveTime::from_hms_opt(17, 0, 0).expect("valid time"),
+            weekdays: vec![
+                Weekday::Mon,
+                Weekday::Tue,
+                Weekday::Wed,
+                Weekday::Thu,
+                Weekday::Fri,
+            ],
+            utc_offset_minutes: 0,
+        }
+    }
+}
+
+impl WorkingHours {
+    fn offset_secs(&self) -> i64 {
+        self.utc_offset_minutes as i64 * 60
+    }
+
+    /// Local calendar date of a timestamp
+    fn local_date(&self, ts: i64) -> NaiveDate {
+        DateTime::from_timestamp(ts + self.offset_secs(), 0)
+            .unwrap_or_default()
+            .date_naive()
+    }
+
+    /// Timestamp of a local date and time
+    fn timestamp(&self, date: NaiveDate, time: NaiveTime) -> i64 {
+        date.and_time(time).and_utc().timestamp() - self.offset_secs()
+    }
+
+    /// The working windows that fall inside `range`
+    fn windows(&self, range: TimeRange) -> Vec<TimeRange> {
+        let mut windows = Vec::new();
+        let mut date = self.local_date(range.start);
+        let last = self.local_date(range.end);
+        while date <= last {
+            if self.weekdays.contains(&date.weekday()) {
+                let window = TimeRange::new(
+                    self.timestamp(date, self.start).max(range.start),
+                    self.timestamp(date, self.end).min(range.end),
+                );
+                if window.duration_secs() > 0 {
+                    windows.push(window);
+                }
+            }
+            date += Duration::days(1);
+        }
+        windows
+    }
+}
+
+/// Busy blocks and the free working time between them
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FreeBusy {
+    /// Merged calendar events, clipped to the requested range
+    pub busy: Vec<TimeRange>,
+    /// Working time not covered by any event
+    pub free: Vec<TimeRange>,
+}
+
+/// How [`suggest_focus_blocks`] may place blocks
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FocusConstraints {
+    pub range: TimeRange,
+    #[serde(default)]
+    pub working_hours: WorkingHours,
+    /// Gaps shorter than this are not worth a focus session
+    #[serde(default = "default_min_block_minutes")]
+    pub min_block_minutes: i64,
+    #[serde(default = "default_max_block_minutes")]
+    pub max_block_minutes: i64,
+    /// Blocks per day before other days are tried; only exceeded when the
+    /// hours cannot be found otherwise
+    #[serde(default = "default_max_blocks_per_day")]
+    pub max_blocks_per_day: usize,
+}
+
+fn default_min_block_minutes() -> i64 {
+    60
+}
+
+fn default_max_block_minutes() -> i64 {
+    240
+}
+
+fn default_max_blocks_per_day() -> usize {
+    1
+}
+
+impl FocusConstraints {
+    pub fn new(range: TimeRange) -> Self {
+        FocusConstraints {
+            range,
+            working_hours: WorkingHours::default(),
+            min_block_minutes: default_min_block_minutes(),
+            max_block_minutes: default_max_block_minutes(),
+            max_blocks_per_day: default_max_blocks_per_day(),
+        }
+    }
+}
+
+/// Compute busy and free time in a space's calendar. Events from every source,
+/// CalDAV included, count as busy; all-day events block their whole local days.
+/// Recurrence rules are not expanded.
+pub fn get_free_busy(
+    conn: &Connection,
+    space_id: Ulid,
+    range: TimeRange,
+    working_hours: &WorkingHours,
+) -> Result<FreeBusy, DbError> {
+    if range.end <= range.start {
+        return Err(DbError::Message("Range must end after it starts".into()));
+    }
+    if working_hours.end <= working_hours.start {
+        return Err(DbError::Message(
+            "Working hours must end after they start".into(),
+        ));
+    }
+
+    // All-day events are stored at UTC midnight, so widen the query by a day
+    let mut stmt = conn.prepare(
+        "SELECT start_time, end_time, all_day FROM calendar_event
+         WHERE space_id = ?1 AND start_time < ?2 AND COALESCE(end_time, start_time) > ?3",
+    )?;
+    let events = stmt
+        .query_map(
+            rusqlite::params![
+                space_id.to_string(),
+                range.end + DAY_SECS,
+                range.start - DAY_SECS
+            ],
+            |row| {
+                Ok((
+                    row.get::<_, i64>(0)?,
+                    row.get::<_, Option<i64>>(1)?,
+                    row.get::<_, bool>(2)?,
+                ))
+            },
+        )?
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let intervals = events
+        .into_iter()
+        .map(|(start, end, all_day)| {
+            if all_day {
+                all_day_interval(working_hours, start, end)
+            } else {
+                let end = end.filter(|&end| end > start);
+                TimeRange::new(start, end.unwrap_or(start + DEFAULT_EVENT_SECS))
+            }
+        })
+        .map(|event| TimeRange::new(event.start.max(range.start), event.end.min(range.end)))
+        .filter(|event| event.duration_secs() > 0)
+        .collect();
+    let busy = merge_intervals(intervals);
+
+    let free = working_hours
+        .windows(range)
+        .into_iter()
+        .flat_map(|window| subtract(window, &busy))
+        .collect();
+    Ok(FreeBusy { busy, free })
+}
+
+/// The local days covered by an all-day event. Its dates are the UTC dates of
+/// the stored timestamps; the end date is exclusive, as in iCalendar.
+fn all_day_interval(working_hours: &WorkingHours, start: i64, end: Option<i64>) -> TimeRange {
+    let utc_date = |ts: i64| {
+        DateTime::from_timestamp(ts, 0)
+            .unwrap_or_default()
+            .date_naive()
+    };
+    let first = utc_date(start);
+    let last = match end {
+        Some(end) if end > start => utc_date(end - 1),
+        _ => first,
+    };
+    TimeRange::new(
+        working_hours.timestamp(first, NaiveTime::MIN),
+        working_hours.timestamp(last + Duration::days(1), NaiveTime::MIN),
+    )
+}
+
+/// Sort intervals and merge the ones that overlap or touch
+fn merge_intervals(mut intervals: Vec<TimeRange>) -> Vec<TimeRange> {
+    intervals.sort_by_key(|interval| interval.start);
+    let mut merged: Vec<TimeRange> = Vec::with_capacity(intervals.len());
+    for interval in intervals {
+        match merged.last_mut() {
+            Some(last) if interval.start <= last.end => last.end = last.end.max(interval.end),
+            _ => merged.push(interval),
+        }
+    }
+    merged
+}
+
+/// The parts of `window` not covered by the sorted, merged `busy` intervals
+fn subtract(window: TimeRange, busy: &[TimeRange]) -> Vec<TimeRange> {
+    let mut free = Vec::new();
+    let mut cursor = window.start;
+    for block in busy {
+        if block.end <= cursor {
+            continue;
+        }
+        if block.start >= window.end {
+            break;
+        }
+        if block.start > cursor {
+            free.push(TimeRange::new(cursor, block.start));
+        }
+        cursor = block.end;
+    }
+    if cursor < window.end {
+        free.push(TimeRange::new(cursor, window.end));
+    }
+    free
+}
+
+/// Propose focus blocks adding up to `hours_needed` in the free working time.
+/// Longer gaps are used first and each block starts at the beginning of its
+/// gap, keeping the rest of the day in one piece. Returns fewer hours than
+/// requested when the calendar does not have them, and nothing when it is full.
+pub fn suggest_focus_blocks(
+    conn: &Connection,
+    space_id: Ulid,
+    hours_needed: f64,
+    constraints: &FocusConstraints,
+) -> Result<Vec<TimeRange>, DbError> {
+    if !hours_needed.is_finite() || hours_needed <= 0.0 {
+        return Err(DbError::Message("Hours needed must be positive".into()));
+    }
+    if constraints.min_block_minutes <= 0
+        || constraints.max_block_minutes < constraints.min_block_minutes
+    {
+        return Err(DbError::Message("Invalid focus block length limits".into()));
+    }
+
+    let free_busy = get_free_busy(
+        conn,
+        space_id,
+        constraints.range,
+        &constraints.working_hours,
+    )?;
+    let mut gaps = free_busy.free;
+    let mut remaining = (hours_needed * 3600.0).round() as i64;
+    let mut blocks: Vec<TimeRange> = Vec::new();
+
+    // Spread over days first; only double up on a day if that is not enough
+    for respect_day_limit in [true, false] {
+        while remaining > 0 {
+            let shortest = (constraints.min_block_minutes * 60).min(remaining);
+            let day_count = |gap: &TimeRange| {
+                let day = constraints.working_hours.local_date(gap.start);
+                blocks
+                    .iter()
+                    .filter(|block| constraints.working_hours.local_date(block.start) == day)
+                    .count()
+            };
+            let best = gaps
+                .iter_mut()
+                .filter(|gap| gap.duration_secs() >= shortest)
+                .filter(|gap| !respect_day_limit || day_count(gap) < constraints.max_blocks_per_day)
+                .max_by_key(|gap| (gap.duration_secs(), -gap.start));
+            let Some(gap) = best else {
+                break;
+            };
+
+            let length = gap
+                .duration_secs()
+                .min(constraints.max_block_minutes * 60)
+                .min(remaining);
+            let block = TimeRange::new(gap.start, gap.start + length);
+            gap.start = block.end;
+            remaining -= length;
+            blocks.push(block);
+        }
+    }
+
+    blocks.sort_by_key(|block| block.start);
+    Ok(blocks)
+}
+
+/// Source of events created on this device. Events pulled from a CalDAV
+/// account carry the account's id instead, so local ones can be pushed.
+pub const EVENT_SOURCE_LOCAL: &str = "local";
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CalendarEvent {
+    pub id: Ulid,
+    pub space_id: Ulid,
+    pub summary: String,
+    pub description: Option<String>,
+    pub location: Option<String>,
+    pub start: i64,
+    /// Exclusive; timed events without one last an hour and all-day events
+    /// without one last a day
+    pub end: Option<i64>,
+    /// All-day events start and end at UTC midnight
+    pub all_day: bool,
+    pub remind_minutes_before: Option<i64>,
+    /// [`EVENT_SOURCE_LOCAL`] or a CalDAV account id
+    pub source: String,
+    pub created_at: i64,
+    pub updated_at: i64,
+}
+
+impl CalendarEvent {
+    /// When the event ends; events without a usable end last
+    /// [`DEFAULT_EVENT_SECS`], or a day if all-day
+    pub fn effective_end(&self) -> i64 {
+        match self.end {
+            Some(end) if end > self.start => end,
+            _ if self.all_day => self.start + DAY_SECS,
+            _ => self.start + DEFAULT_EVENT_SECS,
+        }
+    }
+}
+
+/// An event whose reminder is due and hasn't been marked sent
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DueReminder {
+    pub event: CalendarEvent,
+    pub remind_at: i64,
+}
+
+const EVENT_COLUMNS: &str = "id, space_id, title, description, location, start_time, end_time,
+                             all_day, remind_minutes_before, source, created_at, updated_at";
+
+/// End of an event for overlap checks, as SQL over `calendar_event`; events
+/// without a usable end last [`DEFAULT_EVENT_SECS`], or a day if all-day
+const EVENT_END_SQL: &str = "CASE WHEN end_time > start_time THEN end_time
+                                  WHEN all_day THEN start_time + 86400
+                                  ELSE start_time + 3600 END";
+
+fn parse_ulid(value: String, column: usize) -> rusqlite::Result<Ulid> {
+    Ulid::from_string(&value).map_err(|e| {
+        rusqlite::Error::FromSqlConversionFailure(column, rusqlite::types::Type::Text, Box::new(e))
+    })
+}
+
+fn event_from_row(row: &rusqlite::Row) -> rusqlite::Result<CalendarEvent> {
+    Ok(CalendarEvent {
+        id: parse_ulid(row.get(0)?, 0)?,
+        space_id: parse_ulid(row.get(1)?, 1)?,
+        summary: row.get(2)?,
+        description: row.get(3)?,
+        location: row.get(4)?,
+        start: row.get(5)?,
+        end: row.get(6)?,
+        all_day: row.get(7)?,
+        remind_minutes_before: row.get(8)?,
+        source: row.get(9)?,
+        created_at: row.get(10)?,
+        updated_at: row.get(11)?,
+    })
+}
+
+/// Check an event's fields and move all-day events onto day boundaries
+fn normalize_event(event: &mut CalendarEvent) -> Result<(), DbError> {
+    if event.summary.trim().is_empty() {
+        return Err(DbError::Message("Event summary must not be empty".into()));
+    }
+    if event.remind_minutes_before.is_some_and(|m| m < 0) {
+        return Err(DbError::Message(
+            "Reminder must not be after the event starts".into(),
+        ));
+    }
+    if event.all_day {
+        event.start -= event.start.rem_euclid(DAY_SECS);
+        event.end = event
+            .end
+            .map(|end| end + (DAY_SECS - end.rem_euclid(DAY_SECS)) % DAY_SECS);
+    }
+    if event.end.is_some_and(|end| end <= event.start) {
+        return Err(DbError::Message("Event must end after it starts".into()));
+    }
+    Ok(())
+}
+
+/// Create an event on this device
+#[allow(clippy::too_many_arguments)]
+pub fn create_event(
+    conn: &Connection,
+    space_id: Ulid,
+    summary: &str,
+    start: i64,
+    end: Option<i64>,
+    location: Option<String>,
+    description: Option<String>,
+    all_day: bool,
+) -> Result<CalendarEvent, DbError> {
+    log::info!("[calendar] Creating event: {}", summary);
+    let now = chrono::Utc::now().timestamp();
+    let mut event = CalendarEvent {
+        id: Ulid::new(),
+        space_id,
+        summary: summary.to_string(),
+        description,
+        location,
+        start,
+        end,
+        all_day,
+        remind_minutes_before: None,
+        source: EVENT_SOURCE_LOCAL.to_string(),
+        created_at: now,
+        updated_at: now,
+    };
+    normalize_event(&mut event)?;
+
+    conn.execute(
+        "INSERT INTO calendar_event (id, space_id, title, description, location, start_time,
+                                     end_time, all_day, remind_minutes_before, source,
+                                     created_at, updated_at)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12)",
+        rusqlite::params![
+            event.id.to_string(),
+            event.space_id.to_string(),
+            event.summary,
+            event.description,
+            event.location,
+            event.start,
+            event.end,
+            event.all_day,
+            event.remind_minutes_before,
+            event.source,
+            event.created_at,
+            event.updated_at
+        ],
+    )?;
+    Ok(event)
+}
+
+pub fn get_event(conn: &Connection, id: Ulid) -> Result<Option<CalendarEvent>, DbError> {
+    Ok(conn
+        .query_row(
+            &format!("SELECT {} FROM calendar_event WHERE id = ?1", EVENT_COLUMNS),
+            [id.to_string()],
+            event_from_row,
+        )
+        .optional()?)
+}
+
+/// Save an event's summary, description, location, times and reminder.
+/// Its space and source don't change.
+pub fn update_event(conn: &Connection, event: &CalendarEvent) -> Result<CalendarEvent, DbError> {
+    log::info!("[calendar] Updating event: {}", event.id);
+    let mut event = event.clone();
+    normalize_event(&mut event)?;
+    event.updated_at = chrono::Utc::now().timestamp();
+
+    let updated = conn.execute(
+        "UPDATE calendar_event
+         SET title = ?1, description = ?2, location = ?3, start_time = ?4, end_time = ?5,
+             all_day = ?6, remind_minutes_before = ?7, updated_at = ?8
+         WHERE id = ?9",
+        rusqlite::params![
+            event.summary,
+            event.description,
+            event.location,
+            event.start,
+            event.end,
+            event.all_day,
+            event.remind_minutes_before,
+            event.updated_at,
+            event.id.to_string()
+        ],
+    )?;
+    if updated == 0 {
+        return Err(DbError::Message(format!("Event {} not found", event.id)));
+    }
+    get_event(conn, event.id)?
+        .ok_or_else(|| DbError::Message(format!("Event {} not found", event.id)))
+}
+
+/// Delete an event, journaling it so the delete can be undone
+pub fn delete_event(conn: &Connection, id: Ulid) -> Result<(), DbError> {
+    log::info!("[calendar] Deleting event: {}", id);
+    let id = id.to_string();
+    let tx = conn.unchecked_transaction()?;
+    let existing: Option<(String, String)> = tx
+        .query_row(
+            "SELECT space_id, title FROM calendar_event WHERE id = ?1",
+            [&id],
+            |row| Ok((row.get(0)?, row.get(1)?)),
+        )
+        .optional()?;
+    let snapshot = capture_rows(&tx, "calendar_event", "*", "id = ?1", [&id])?;
+    tx.execute("DELETE FROM calendar_event WHERE id = ?1", [&id])?;
+
+    if let Some((space_id, summary)) = existing {
+        record_undo(
+            &tx,
+            UndoRecord {
+                space_id: &space_id,
+                operation: "delete_event",
+                entity_type: "calendar_event",
+                entity_id: &id,
+                summary: format!("Deleted event \"{}\"", summary),
+                snapshot: UndoSnapshot {
+                    deleted: snapshot.into_iter().collect(),
+                    modified: Vec::new(),
+                    inserted: Vec::new(),
+                },
+            },
+        );
+    }
+    tx.commit()?;
+    Ok(())
+}
+
+/// Events of a space overlapping `[start, end)`, including ones that began
+/// before the range or run past it, ordered by start. Recurrence rules are
+/// not expanded.
+pub fn get_events_in_range(
+    conn: &Connection,
+    space_id: Ulid,
+    start: i64,
+    end: i64,
+) -> Result<Vec<CalendarEvent>, DbError> {
+    if end <= start {
+        return Err(DbError::Message("Range must end after it starts".into()));
+    }
+    let mut stmt = conn.prepare(&format!(
+        "SELECT {} FROM calendar_event
+         WHERE space_id = ?1 AND start_time < ?3 AND {} > ?2
+         ORDER BY start_time, id",
+        EVENT_COLUMNS, EVENT_END_SQL
+    ))?;
+    let events = stmt
+        .query_map(
+            rusqlite::params![space_id.to_string(), start, end],
+            event_from_row,
+        )?
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(events)
+}
+
+/// Reminders of every space that are due at `now` and not yet marked sent,
+/// oldest first. Reminders for events that have already ended are skipped.
+/// An event moved later is reminded of again.
+pub fn get_due_reminders(conn: &Connection, now: i64) -> Result<Vec<DueReminder>, DbError> {
+    let remind_at = "start_time - remind_minutes_before * 60";
+    let mut stmt = conn.prepare(&format!(
+        "SELECT {columns}, {remind_at} FROM calendar_event
+         WHERE remind_minutes_before IS NOT NULL
+           AND {remind_at} <= ?1
+           AND {end} > ?1
+           AND (reminded_at IS NULL OR reminded_at < {remind_at})
+         ORDER BY {remind_at}, id",
+        columns = EVENT_COLUMNS,
+        remind_at = remind_at,
+        end = EVENT_END_SQL
+    ))?;
+    let reminders = stmt
+        .query_map([now], |row| {
+            Ok(DueReminder {
+                event: event_from_row(row)?,
+                remind_at: row.get(12)?,
+            })
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(reminders)
+}
+
+/// Record that the reminder of an event was shown at `at`
+pub fn mark_reminder_sent(conn: &Connection, event_id: Ulid, at: i64) -> Result<(), DbError> {
+    conn.execute(
+        "UPDATE calendar_event SET reminded_at = ?1 WHERE id = ?2",
+        rusqlite::params![at, event_id.to_string()],
+    )?;
+    Ok(())
+}
@@ -1,5 +1,8 @@
-use rusqlite::Connection;
+use crate::permission::{authorize, ActorContext, PermissionDenied, PERMISSION_ADMIN};
+use hmac::{Hmac, Mac};
+use rusqlite::{Connection, OptionalExtension};
 use serde::{Deserialize, Serialize};
+use sha2::Sha256;
 use thiserror::Error;
 use ulid::Ulid;
 
@@ -11,12 +14,24 @@ pub enum CollaborationError {
     Rusqlite(#[from] rusqlite::Error),
     #[error("User not found")]
     UserNotFound,
-    #[error("Permission denied")]
-    PermissionDenied,
+    #[error(transparent)]
+    PermissionDenied(#[from] PermissionDenied),
     #[error("Invalid role")]
     InvalidRole,
     #[error("Invitation expired")]
     InvitationExpired,
+    #[error("Invitation not found or token invalid")]
+    InvalidInvitation,
+    #[error("Invitation already accepted")]
+    InvitationAlreadyAccepted,
+    #[error("Invitation revoked")]
+    InvitationRevoked,
+    #[error("Invitation replaced by a newer one")]
+    InvitationSuperseded,
+    #[error(transparent)]
+    LockedByOther(#[from] LockedByOther),
+    #[error("Lock duration must be positive, got {0}s")]
+    InvalidLockTtl(i64),
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -49,10 +64,13 @@ pub struct UserInvitation {
     pub invited_by: String,
     pub invited_at: i64,
     pub expires_at: i64,
-    pub status: String, // pending, accepted, expired, rejected
+    pub status: String, // pending, accepted, expired, revoked, superseded
     pub token: String,
 }
 
+/// How long an invitation can be accepted for
+pub const INVITATION_TTL_SECS: i64 = 7 * 24 * 60 * 60;
+
 pub fn init_rbac_tables(conn: &Connection) -> Result<(), rusqlite::Error> {
     // Roles table
     conn.execute(
@@ -117,10 +135,27 @@ pub fn init_rbac_tables(conn: &Connection) -> Result<(), rusqlite::Error> {
             invited_at INTEGER NOT NULL,
             expires_at INTEGER NOT NULL,
             status TEXT NOT NULL DEFAULT 'pending',
+            responded_at INTEGER,
+            accepted_by TEXT,
             FOREIGN KEY (role_id) REFERENCES roles(id)
         )",
         [],
     )?;
+    conn.execute(
+        "CREATE INDEX IF NOT EXISTS idx_user_invitations_space_email
+         ON user_invitations(space_id, email, status)",
+        [],
+    )?;
+
+    // Key signing invitation tokens, created with the first invitation
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS invitation_signing_key (
+            id INTEGER PRIMARY KEY CHECK (id = 1),
+            key BLOB NOT NULL,
+            created_at INTEGER NOT NULL
+        )",
+        [],
+    )?;
 
     // Custom user permissions (override role permissions)
     conn.execute(
@@ -326,6 +361,34 @@ pub fn get_user_permissions(
     space_id: &str,
     user_id: &str,
 ) -> Result<Vec<String>, CollaborationError> {
+    Ok(query_user_permissions(conn, space_id, user_id)?)
+}
+
+pub fn check_permission(
+    conn: &Connection,
+    space_id: &str,
+    user_id: &str,
+    permission: &str,
+) -> Result<bool, CollaborationError> {
+    Ok(has_permission(conn, space_id, user_id, permission)?)
+}
+
+/// [`check_permission`] for callers with their own error type
+pub(crate) fn has_permission(
+    conn: &Connection,
+    space_id: &str,
+    user_id: &str,
+    permission: &str,
+) -> Result<bool, rusqlite::Error> {
+    let permissions = query_user_permissions(conn, space_id, user_id)?;
+    Ok(permissions.iter().any(|p| p == permission))
+}
+
+fn query_user_permissions(
+    conn: &Connection,
+    space_id: &str,
+    user_id: &str,
+) -> Result<Vec<String>, rusqlite::Error> {
     let mut stmt = conn.prepare(
         "SELECT rp.permission
          FROM space_user_roles sur
@@ -358,17 +421,22 @@ pub fn get_user_permissions(
     Ok(permissions)
 }
 
-pub fn check_permission(
+/// Same as [`create_invitation`]
+pub fn invite_user(
     conn: &Connection,
     space_id: &str,
-    user_id: &str,
-    permission: &str,
-) -> Result<bool, CollaborationError> {
-    let permissions = get_user_permissions(conn, space_id, user_id)?;
-    Ok(permissions.contains(&permission.to_string()))
+    email: &str,
+    role_id: &str,
+    invited_by: &str,
+) -> Result<UserInvitation, CollaborationError> {
+    create_invitation(conn, space_id, email, role_id, invited_by)
 }
 
-pub fn invite_user(
+/// Invite `email` to the space with a role. The returned token can be
+/// accepted once within [`INVITATION_TTL_SECS`]; it is signed over the
+/// invitation's space, email, role and expiry, so editing those on the row
+/// invalidates it. A pending invitation for the same email is superseded.
+pub fn create_invitation(
     conn: &Connection,
     space_id: &str,
     email: &str,
@@ -387,31 +455,80 @@ pub fn invite_user(
         return Err(CollaborationError::InvalidRole);
     }
 
+    let email = email.trim().to_lowercase();
     let id = Ulid::new().to_string();
+    let now = chrono::Utc::now().timestamp();
+    let expires_at = now + INVITATION_TTL_SECS;
 
     use rand::RngCore;
-    let mut raw = [0u8; 32];
-    rand::thread_rng().fill_bytes(&mut raw);
-    let token = hex::encode(raw);
-
-    let now = std::time::SystemTime::now()
-        .duration_since(std::time::UNIX_EPOCH)
-        .expect("Time went backwards")
-        .as_secs() as i64;
-    let expires_at = now + (7 * 24 * 60 * 60);
+    let mut nonce = [0u8; 16];
+    rand::thread_rng().fill_bytes(&mut nonce);
+    let nonce = hex::encode(nonce);
+    let tx = conn.unchecked_transaction()?;
+    let mac = invitation_mac(&tx, &nonce, &id, space_id, &email, role_id, expires_at)?;
+    let token = format!(
+        "{}{}",
+        nonce,
+        hex::encode(&mac.finalize().into_bytes()[..INVITATION_TAG_LEN])
+    );
+
+    let superseded: Vec<String> = {
+        let mut stmt = tx.prepare(
+            "SELECT id FROM user_invitations
+             WHERE space_id = ?1 AND email = ?2 AND status = 'pending'",
+        )?;
+        let ids = stmt
+            .query_map([space_id, &email], |row| row.get(0))?
+            .collect::<Result<Vec<_>, _>>()?;
+        ids
+    };
+    for old_id in &superseded {
+        tx.execute(
+            "UPDATE user_invitations SET status = 'superseded', responded_at = ?1 WHERE id = ?2",
+            rusqlite::params![now, old_id],
+        )?;
+        crate::audit::log_auth_event(
+            &tx,
+            Some(invited_by),
+            crate::audit::AUTH_INVITATION_SUPERSEDED,
+            &email,
+            serde_json::json!({
+                "space_id": space_id,
+                "invitation_id": old_id,
+                "superseded_by": id,
+            }),
+            None,
+            now,
+        );
+    }
 
-    conn.execute(
+    tx.execute(
         "INSERT INTO user_invitations (id, space_id, email, role_id, token, invited_by, invited_at, expires_at, status)
          VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, 'pending')",
         rusqlite::params![id, space_id, email, role_id, token, invited_by, now, expires_at],
     )?;
+    crate::audit::log_auth_event(
+        &tx,
+        Some(invited_by),
+        crate::audit::AUTH_INVITATION_CREATED,
+        &email,
+        serde_json::json!({
+            "space_id": space_id,
+            "invitation_id": id,
+            "role": role_id,
+            "expires_at": expires_at,
+        }),
+        None,
+        now,
+    );
+    tx.commit()?;
 
     let permissions = get_role_permissions(conn, role_id)?;
 
     Ok(UserInvitation {
         id,
         space_id: space_id.to_string(),
-        email: email.to_string(),
+        email,
         role: role_id.to_string(),
         permissions,
         invited_by: invited_by.to_string(),
@@ -422,6 +539,272 @@ pub fn invite_user(
     })
 }
 
+/// Accept the invitation `token` was issued for, adding `user_id` to the
+/// space with the invited role
+pub fn accept_invitation(
+    conn: &Connection,
+    token: &str,
+    user_id: &str,
+) -> Result<UserInvitation, CollaborationError> {
+    accept_invitation_at(conn, token, user_id, chrono::Utc::now().timestamp())
+}
+
+/// [`accept_invitation`] as of `now`
+pub fn accept_invitation_at(
+    conn: &Connection,
+    token: &str,
+    user_id: &str,
+    now: i64,
+) -> Result<UserInvitation, CollaborationError> {
+    let tx = conn.unchecked_transaction()?;
+    let result = claim_invitation(&tx, token, user_id, now);
+    match &result {
+        Ok(invitation) => {
+            crate::audit::log_auth_event(
+                &tx,
+                Some(user_id),
+                crate::audit::AUTH_INVITATION_ACCEPTED,
+                &invitation.email,
+                serde_json::json!({
+                    "space_id": invitation.space_id,
+                    "invitation_id": invitation.id,
+                    "role": invitation.role,
+                }),
+                None,
+                now,
+            );
+            tx.commit()?;
+        }
+        Err(e) => {
+            // Keep the failed attempt, but nothing else it may have written
+            drop(tx);
+            if matches!(e, CollaborationError::InvitationExpired) {
+                conn.execute(
+                    "UPDATE user_invitations SET status = 'expired', responded_at = ?1
+                     WHERE token = ?2 AND status = 'pending'",
+                    rusqlite::params![now, token],
+                )?;
+            }
+            crate::audit::log_auth_event(
+                conn,
+                Some(user_id),
+                crate::audit::AUTH_INVITATION_ACCEPT_FAILED,
+                user_id,
+                serde_json::json!({ "reason": e.to_string() }),
+                None,
+                now,
+            );
+        }
+    }
+    result
+}
+
+fn claim_invitation(
+    conn: &Connection,
+    token: &str,
+    user_id: &str,
+    now: i64,
+) -> Result<UserInvitation, CollaborationError> {
+    let mut invitation = conn
+        .query_row(
+            &format!("{} WHERE token = ?1", INVITATION_SELECT),
+            [token],
+            invitation_from_row,
+        )
+        .optional()?
+        .ok_or(CollaborationError::InvalidInvitation)?;
+    if !verify_invitation_token(conn, &invitation)? {
+        log::warn!(
+            "[collaboration] Invitation {} doesn't match its token",
+            invitation.id
+        );
+        return Err(CollaborationError::InvalidInvitation);
+    }
+
+    match invitation.status.as_str() {
+        "pending" => {}
+        "accepted" => return Err(CollaborationError::InvitationAlreadyAccepted),
+        "revoked" => return Err(CollaborationError::InvitationRevoked),
+        "superseded" => return Err(CollaborationError::InvitationSuperseded),
+        _ => return Err(CollaborationError::InvitationExpired),
+    }
+    if now >= invitation.expires_at {
+        return Err(CollaborationError::InvitationExpired);
+    }
+
+    let claimed = conn.execute(
+        "UPDATE user_invitations SET status = 'accepted', responded_at = ?1, accepted_by = ?2
+         WHERE id = ?3 AND status = 'pending'",
+        rusqlite::params![now, user_id, invitation.id],
+    )?;
+    if claimed == 0 {
+        return Err(CollaborationError::InvitationAlreadyAccepted);
+    }
+    add_user_to_space(
+        conn,
+        &invitation.space_id,
+        user_id,
+        &invitation.email,
+        &invitation.role,
+    )?;
+    conn.execute(
+        "UPDATE space_user_roles SET assigned_by = ?1 WHERE space_id = ?2 AND user_id = ?3",
+        rusqlite::params![invitation.invited_by, invitation.space_id, user_id],
+    )?;
+
+    invitation.status = "accepted".to_string();
+    Ok(invitation)
+}
+
+/// Withdraw a pending invitation so its token can no longer be accepted
+pub fn revoke_invitation(
+    conn: &Connection,
+    invitation_id: &str,
+    revoked_by: &str,
+) -> Result<(), CollaborationError> {
+    let (space_id, email, status): (String, String, String) = conn
+        .query_row(
+            "SELECT space_id, email, status FROM user_invitations WHERE id = ?1",
+            [invitation_id],
+            |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+        )
+        .optional()?
+        .ok_or(CollaborationError::InvalidInvitation)?;
+    match status.as_str() {
+        "pending" => {}
+        "accepted" => return Err(CollaborationError::InvitationAlreadyAccepted),
+        // Nothing left to withdraw
+        _ => return Ok(()),
+    }
+
+    let now = chrono::Utc::now().timestamp();
+    conn.execute(
+        "UPDATE user_invitations SET status = 'revoked', responded_at = ?1
+         WHERE id = ?2 AND status = 'pending'",
+        rusqlite::params![now, invitation_id],
+    )?;
+    crate::audit::log_auth_event(
+        conn,
+        Some(revoked_by),
+        crate::audit::AUTH_INVITATION_REVOKED,
+        &email,
+        serde_json::json!({ "space_id": space_id, "invitation_id": invitation_id }),
+        None,
+        now,
+    );
+    Ok(())
+}
+
+/// Invitations to the space that can still be accepted, oldest first
+pub fn list_pending_invitations(
+    conn: &Connection,
+    space_id: &str,
+) -> Result<Vec<UserInvitation>, CollaborationError> {
+    let mut stmt = conn.prepare(&format!(
+        "{} WHERE i.space_id = ?1 AND i.status = 'pending' AND i.expires_at > ?2
+         ORDER BY i.invited_at, i.id",
+        INVITATION_SELECT
+    ))?;
+    let mut invitations = stmt
+        .query_map(
+            rusqlite::params![space_id, chrono::Utc::now().timestamp()],
+            invitation_from_row,
+        )?
+        .collect::<Result<Vec<_>, _>>()?;
+    for invitation in &mut invitations {
+        invitation.permissions = get_role_permissions(conn, &invitation.role)?;
+    }
+    Ok(invitations)
+}
+
+const INVITATION_SELECT: &str =
+    "SELECT i.id, i.space_id, i.email, i.role_id, i.invited_by, i.invited_at, i.expires_at,
+            i.status, i.token
+     FROM user_invitations i";
+
+/// Bytes of the HMAC kept in a token
+const INVITATION_TAG_LEN: usize = 16;
+/// Hex digits of the random nonce that starts a token
+const INVITATION_NONCE_HEX_LEN: usize = 32;
+
+fn invitation_from_row(row: &rusqlite::Row) -> rusqlite::Result<UserInvitation> {
+    Ok(UserInvitation {
+        id: row.get(0)?,
+        space_id: row.get(1)?,
+        email: row.get(2)?,
+        role: row.get(3)?,
+        permissions: Vec::new(),
+        invited_by: row.get(4)?,
+        invited_at: row.get(5)?,
+        expires_at: row.get(6)?,
+        status: row.get(7)?,
+        token: row.get(8)?,
+    })
+}
+
+fn verify_invitation_token(
+    conn: &Connection,
+    invitation: &UserInvitation,
+) -> Result<bool, CollaborationError> {
+    let token = &invitation.token;
+    if token.len() != INVITATION_NONCE_HEX_LEN + INVITATION_TAG_LEN * 2 || !token.is_ascii() {
+        return Ok(false);
+    }
+    let (nonce, tag) = token.split_at(INVITATION_NONCE_HEX_LEN);
+    let Ok(tag) = hex::decode(tag) else {
+        return Ok(false);
+    };
+    let mac = invitation_mac(
+        conn,
+        nonce,
+        &invitation.id,
+        &invitation.space_id,
+        &invitation.email,
+        &invitation.role,
+        invitation.expires_at,
+    )?;
+    Ok(mac.verify_truncated_left(&tag).is_ok())
+}
+
+fn invitation_mac(
+    conn: &Connection,
+    nonce: &str,
+    id: &str,
+    space_id: &str,
+    email: &str,
+    role_id: &str,
+    expires_at: i64,
+) -> Result<Hmac<Sha256>, CollaborationError> {
+    let key = invitation_signing_key(conn)?;
+    let mut mac = Hmac::<Sha256>::new_from_slice(&key).expect("HMAC accepts any key length");
+    for part in [nonce, id, space_id, email, role_id, &expires_at.to_string()] {
+        mac.update(part.as_bytes());
+        mac.update(b"\0");
+    }
+    Ok(mac)
+}
+
+fn invitation_signing_key(conn: &Connection) -> Result<Vec<u8>, CollaborationError> {
+    if let Some(key) = conn
+        .query_row(
+            "SELECT key FROM invitation_signing_key WHERE id = 1",
+            [],
+            |row| row.get(0),
+        )
+        .optional()?
+    {
+        return Ok(key);
+    }
+    use rand::RngCore;
+    let mut key = vec![0u8; 32];
+    rand::thread_rng().fill_bytes(&mut key);
+    conn.execute(
+        "INSERT INTO invitation_signing_key (id, key, created_at) VALUES (1, ?1, ?2)",
+        rusqlite::params![key, chrono::Utc::now().timestamp()],
+    )?;
+    Ok(key)
+}
+
 fn get_role_permissions(
     conn: &Connection,
     role_id: &str,
@@ -464,6 +847,18 @@ pub fn update_user_role(
     Ok(())
 }
 
+/// [`update_user_role`] on behalf of `actor`, who needs `admin` in the space
+pub fn update_user_role_as(
+    conn: &Connection,
+    actor: &ActorContext,
+    space_id: &str,
+    user_id: &str,
+    new_role_id: &str,
+) -> Result<(), CollaborationError> {
+    authorize::<CollaborationError>(conn, actor, space_id, PERMISSION_ADMIN)?;
+    update_user_role(conn, space_id, user_id, new_role_id, &actor.user_id)
+}
+
 pub fn grant_permission(
     conn: &Connection,
     space_id: &str,
@@ -499,10 +894,21 @@ pub fn suspend_user(
     space_id: &str,
     user_id: &str,
 ) -> Result<(), CollaborationError> {
-    conn.execute(
+    let updated = conn.execute(
         "UPDATE space_users SET status = 'suspended' WHERE space_id = ?1 AND user_id = ?2",
         rusqlite::params![space_id, user_id],
     )?;
+    if updated > 0 {
+        crate::audit::log_auth_event(
+            conn,
+            None,
+            crate::audit::AUTH_USER_SUSPENDED,
+            user_id,
+            serde_json::json!({ "space_id": space_id }),
+            None,
+            chrono::Utc::now().timestamp(),
+        );
+    }
 
     Ok(())
 }
@@ -512,10 +918,21 @@ pub fn activate_user(
     space_id: &str,
     user_id: &str,
 ) -> Result<(), CollaborationError> {
-    conn.execute(
+    let updated = conn.execute(
         "UPDATE space_users SET status = 'active' WHERE space_id = ?1 AND user_id = ?2",
         rusqlite::params![space_id, user_id],
     )?;
+    if updated > 0 {
+        crate::audit::log_auth_event(
+            conn,
+            None,
+            crate::audit::AUTH_USER_ACTIVATED,
+            user_id,
+            serde_json::json!({ "space_id": space_id }),
+            None,
+            chrono::Utc::now().timestamp(),
+        );
+    }
 
     Ok(())
 }
@@ -598,3 +1015,290 @@ pub fn remove_user_from_space(
 
     Ok(())
 }
+
+/// [`remove_user_from_space`] on behalf of `actor`, who needs `admin` in the
+/// space
+pub fn remove_user_from_space_as(
+    conn: &Connection,
+    actor: &ActorContext,
+    space_id: &str,
+    user_id: &str,
+) -> Result<(), CollaborationError> {
+    authorize::<CollaborationError>(conn, actor, space_id, PERMISSION_ADMIN)?;
+    remove_user_from_space(conn, space_id, user_id)
+}
+
+// --- Presence and edit locks (this vault only) ---
+//
+// Nothing stops two members of a shared space from editing the same task at
+// once, the later save silently wins. Presence shows who is around and what
+// they have open; an edit lock is an advisory claim on one entity that
+// writes made on behalf of another user respect (see [`check_edit_lock`]).
+// Both expire on their own, so a crashed client never holds anything for
+// long.
+//
+// Both live in this vault only and are never synced: they coordinate the
+// users of one vault, not members editing on their own devices. A peer
+// neither sees them nor is held back by them, since the delta applier
+// writes synced changes without consulting edit locks.
+
+/// How long a heartbeat keeps a user present; clients beat more often
+pub const PRESENCE_TTL_SECS: i64 = 90;
+
+/// Edit lock duration for callers without an opinion; holders renew it by
+/// acquiring again
+pub const DEFAULT_EDIT_LOCK_TTL_SECS: i64 = 5 * 60;
+
+/// A user recently active in a space
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Presence {
+    pub user_id: String,
+    pub space_id: String,
+    /// What the user has open, if anything
+    pub entity_id: Option<String>,
+    pub last_seen: i64,
+    pub expires_at: i64,
+}
+
+/// An advisory claim on editing one entity
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct EditLock {
+    pub entity_id: String,
+    pub user_id: String,
+    pub acquired_at: i64,
+    pub expires_at: i64,
+}
+
+/// The entity is locked by another user
+#[derive(Error, Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[error("{} is being edited by {} since {}", .holder.entity_id, .holder.user_id, .holder.acquired_at)]
+pub struct LockedByOther {
+    pub holder: EditLock,
+}
+
+/// Record that `user_id` is active in the space, looking at `entity_id`
+pub fn heartbeat_presence(
+    conn: &Connection,
+    user_id: &str,
+    space_id: &str,
+    entity_id: Option<&str>,
+) -> Result<Presence, CollaborationError> {
+    heartbeat_presence_at(
+        conn,
+        user_id,
+        space_id,
+        entity_id,
+        chrono::Utc::now().timestamp(),
+    )
+}
+
+/// [`heartbeat_presence`] as of `now`
+pub fn heartbeat_presence_at(
+    conn: &Connection,
+    user_id: &str,
+    space_id: &str,
+    entity_id: Option<&str>,
+    now: i64,
+) -> Result<Presence, CollaborationError> {
+    let presence = Presence {
+        user_id: user_id.to_string(),
+        space_id: space_id.to_string(),
+        entity_id: entity_id.map(str::to_string),
+        last_seen: now,
+        expires_at: now + PRESENCE_TTL_SECS,
+    };
+    conn.execute(
+        "INSERT INTO presence (space_id, user_id, entity_id, last_seen, expires_at)
+         VALUES (?1, ?2, ?3, ?4, ?5)
+         ON CONFLICT(space_id, user_id) DO UPDATE SET
+             entity_id = excluded.entity_id,
+             last_seen = excluded.last_seen,
+             expires_at = excluded.expires_at",
+        rusqlite::params![
+            presence.space_id,
+            presence.user_id,
+            presence.entity_id,
+            presence.last_seen,
+            presence.expires_at
+        ],
+    )?;
+    // Nobody reads expired rows; drop them while we're here
+    conn.execute("DELETE FROM presence WHERE expires_at <= ?1", [now])?;
+    Ok(presence)
+}
+
+/// Forget the user's presence in the space, e.g. when the app closes
+pub fn leave_presence(
+    conn: &Connection,
+    user_id: &str,
+    space_id: &str,
+) -> Result<(), CollaborationError> {
+    conn.execute(
+        "DELETE FROM presence WHERE space_id = ?1 AND user_id = ?2",
+        [space_id, user_id],
+    )?;
+    Ok(())
+}
+
+const PRESENCE_SELECT: &str =
+    "SELECT user_id, space_id, entity_id, last_seen, expires_at FROM presence";
+
+/// Users present in the space, most recently seen first
+pub fn get_active_users(
+    conn: &Connection,
+    space_id: &str,
+) -> Result<Vec<Presence>, CollaborationError> {
+    query_presence(conn, "space_id", space_id)
+}
+
+/// Users present with the entity open, most recently seen first
+pub fn get_entity_viewers(
+    conn: &Connection,
+    entity_id: &str,
+) -> Result<Vec<Presence>, CollaborationError> {
+    query_presence(conn, "entity_id", entity_id)
+}
+
+/// `column` is one of our own column names, never user input
+fn query_presence(
+    conn: &Connection,
+    column: &str,
+    value: &str,
+) -> Result<Vec<Presence>, CollaborationError> {
+    let mut stmt = conn.prepare_cached(&format!(
+        "{} WHERE {} = ?1 AND expires_at > ?2 ORDER BY last_seen DESC, user_id",
+        PRESENCE_SELECT, column
+    ))?;
+    let presence = stmt
+        .query_map(
+            rusqlite::params![value, chrono::Utc::now().timestamp()],
+            |row| {
+                Ok(Presence {
+                    user_id: row.get(0)?,
+                    space_id: row.get(1)?,
+                    entity_id: row.get(2)?,
+                    last_seen: row.get(3)?,
+                    expires_at: row.get(4)?,
+                })
+            },
+        )?
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(presence)
+}
+
+/// Lock the entity for `user_id` for `ttl_secs`. Acquiring a lock the user
+/// already holds renews it; an expired lock of someone else is taken over.
+/// Fails with [`LockedByOther`] while another user's lock is live.
+pub fn acquire_edit_lock(
+    conn: &Connection,
+    user_id: &str,
+    entity_id: &str,
+    ttl_secs: i64,
+) -> Result<EditLock, CollaborationError> {
+    acquire_edit_lock_at(
+        conn,
+        user_id,
+        entity_id,
+        ttl_secs,
+        chrono::Utc::now().timestamp(),
+    )
+}
+
+/// [`acquire_edit_lock`] as of `now`
+pub fn acquire_edit_lock_at(
+    conn: &Connection,
+    user_id: &str,
+    entity_id: &str,
+    ttl_secs: i64,
+    now: i64,
+) -> Result<EditLock, CollaborationError> {
+    if ttl_secs <= 0 {
+        return Err(CollaborationError::InvalidLockTtl(ttl_secs));
+    }
+    // One statement, so two users racing for the lock can't both get it
+    let claimed = conn.execute(
+        "INSERT INTO edit_lock (entity_id, user_id, acquired_at, expires_at)
+         VALUES (?1, ?2, ?3, ?4)
+         ON CONFLICT(entity_id) DO UPDATE SET
+             acquired_at = CASE
+                 WHEN edit_lock.user_id = excluded.user_id AND edit_lock.expires_at > ?3
+                 THEN edit_lock.acquired_at ELSE excluded.acquired_at END,
+             user_id = excluded.user_id,
+             expires_at = excluded.expires_at
+         WHERE edit_lock.user_id = excluded.user_id OR edit_lock.expires_at <= ?3",
+        rusqlite::params![entity_id, user_id, now, now + ttl_secs],
+    )?;
+    let lock = edit_lock_row(conn, entity_id)?.ok_or(rusqlite::Error::QueryReturnedNoRows)?;
+    if claimed == 0 {
+        return Err(LockedByOther { holder: lock }.into());
+    }
+    log::info!(
+        "[collaboration] {} locked {} until {}",
+        user_id,
+        entity_id,
+        lock.expires_at
+    );
+    Ok(lock)
+}
+
+/// Give up the user's lock on the entity; returns whether they held one
+pub fn release_edit_lock(
+    conn: &Connection,
+    user_id: &str,
+    entity_id: &str,
+) -> Result<bool, CollaborationError> {
+    let released = conn.execute(
+        "DELETE FROM edit_lock WHERE entity_id = ?1 AND user_id = ?2",
+        [entity_id, user_id],
+    )?;
+    Ok(released > 0)
+}
+
+/// The live lock on the entity, if any
+pub fn get_edit_lock(
+    conn: &Connection,
+    entity_id: &str,
+) -> Result<Option<EditLock>, rusqlite::Error> {
+    Ok(edit_lock_row(conn, entity_id)?
+        .filter(|lock| lock.expires_at > chrono::Utc::now().timestamp()))
+}
+
+fn edit_lock_row(conn: &Connection, entity_id: &str) -> Result<Option<EditLock>, rusqlite::Error> {
+    conn.query_row(
+        "SELECT entity_id, user_id, acquired_at, expires_at FROM edit_lock WHERE entity_id = ?1",
+        [entity_id],
+        |row| {
+            Ok(EditLock {
+                entity_id: row.get(0)?,
+                user_id: row.get(1)?,
+                acquired_at: row.get(2)?,
+                expires_at: row.get(3)?,
+            })
+        },
+    )
+    .optional()
+}
+
+/// Fail with [`LockedByOther`] when another user holds a live lock on the
+/// entity in this vault. An actor acting for sync always passes; the lock
+/// never reached the device the change was made on.
+pub fn check_edit_lock<E>(conn: &Connection, actor: &ActorContext, entity_id: &str) -> Result<(), E>
+where
+    E: From<LockedByOther> + From<rusqlite::Error>,
+{
+    if actor.via_sync {
+        return Ok(());
+    }
+    match get_edit_lock(conn, entity_id)? {
+        Some(lock) if lock.user_id != actor.user_id => {
+            log::warn!(
+                "[collaboration] {} can't change {}, locked by {}",
+                actor.user_id,
+                entity_id,
+                lock.user_id
+            );
+            Err(LockedByOther { holder: lock }.into())
+        }
+        _ => Ok(()),
+    }
+}
@@ -1,7 +1,25 @@
+use chrono::Utc;
+use rusqlite::{Connection, OptionalExtension};
+use sha2::{Digest, Sha256};
 use thiserror::Error;
 use yrs::updates::decoder::Decode;
 use yrs::{Doc, GetString, ReadTxn, StateVector, Text, Transact, Update};
 
+use crate::db::DbError;
+
+/// Entity type of sync deltas whose payload is a yrs update of a note body
+/// rather than the whole `content_md`
+pub const NOTE_CRDT_ENTITY_TYPE: &str = "note_crdt";
+
+/// Client id of the first update in a note's log, derived from the seeded
+/// body. Devices seeding the same body produce byte-identical updates, so
+/// notes that already matched before the CRDT path merge without duplicating
+/// text, while different bodies never share item ids.
+fn seed_client_id(content: &str) -> u64 {
+    let digest = Sha256::digest(content.as_bytes());
+    u32::from_be_bytes([digest[0], digest[1], digest[2], digest[3]]) as u64
+}
+
 #[derive(Error, Debug)]
 pub enum CrdtError {
     #[error("Yrs decode error: {0}")]
@@ -10,6 +28,12 @@ pub enum CrdtError {
     UpdateError(String),
 }
 
+impl From<CrdtError> for DbError {
+    fn from(err: CrdtError) -> Self {
+        DbError::Message(err.to_string())
+    }
+}
+
 pub fn apply_update(doc: &mut Doc, update: &[u8]) -> Result<(), CrdtError> {
     let mut txn = doc.transact_mut();
     let update = Update::decode_v1(update).map_err(|e| CrdtError::DecodeError(e.to_string()))?;
@@ -35,3 +59,137 @@ pub fn insert_text(doc: &mut Doc, index: u32, content: &str) {
     let mut txn = doc.transact_mut();
     text.insert(&mut txn, index, content);
 }
+
+/// Replace the text of `doc` with `content` as a single splice over the
+/// changed middle, so concurrent edits elsewhere in the body survive a merge
+pub fn set_text(doc: &mut Doc, content: &str) {
+    let text = doc.get_or_insert_text("content");
+    let mut txn = doc.transact_mut();
+    let current = text.get_string(&txn);
+    let (index, removed, inserted) = splice(&current, content);
+    if removed > 0 {
+        text.remove_range(&mut txn, index, removed);
+    }
+    if !inserted.is_empty() {
+        text.insert(&mut txn, index, inserted);
+    }
+}
+
+/// The `(index, removed, inserted)` splice turning `old` into `new`, in the
+/// UTF-8 byte offsets yrs uses by default
+fn splice<'a>(old: &str, new: &'a str) -> (u32, u32, &'a str) {
+    let prefix: usize = old
+        .chars()
+        .zip(new.chars())
+        .take_while(|(a, b)| a == b)
+        .map(|(c, _)| c.len_utf8())
+        .sum();
+    let (old_rest, new_rest) = (&old[prefix..], &new[prefix..]);
+    let suffix: usize = old_rest
+        .chars()
+        .rev()
+        .zip(new_rest.chars().rev())
+        .take_while(|(a, b)| a == b)
+        .map(|(c, _)| c.len_utf8())
+        .sum();
+    let removed = old_rest.len() - suffix;
+    let inserted = &new_rest[..new_rest.len() - suffix];
+    (prefix as u32, removed as u32, inserted)
+}
+
+fn append_note_update(conn: &Connection, note_id: &str, update: &[u8]) -> Result<(), DbError> {
+    conn.execute(
+        "INSERT INTO note_crdt_update (note_id, update_data, created_at) VALUES (?1, ?2, ?3)",
+        rusqlite::params![note_id, update, Utc::now().timestamp()],
+    )?;
+    Ok(())
+}
+
+/// Rebuild a note's document from its update log, or `None` when the note
+/// has never been edited through the CRDT path
+pub fn load_note_doc(conn: &Connection, note_id: &str) -> Result<Option<Doc>, DbError> {
+    let mut stmt =
+        conn.prepare("SELECT update_data FROM note_crdt_update WHERE note_id = ?1 ORDER BY seq")?;
+    let updates = stmt
+        .query_map([note_id], |row| row.get::<_, Vec<u8>>(0))?
+        .collect::<Result<Vec<_>, _>>()?;
+    if updates.is_empty() {
+        return Ok(None);
+    }
+
+    let mut doc = Doc::new();
+    for update in updates {
+        apply_update(&mut doc, &update)?;
+    }
+    Ok(Some(doc))
+}
+
+/// Record `content` as the new body of a note, appending the splice from the
+/// logged body to the note's update log. The first edit seeds the log.
+pub fn record_note_edit(conn: &Connection, note_id: &str, content: &str) -> Result<(), DbError> {
+    let Some(mut doc) = load_note_doc(conn, note_id)? else {
+        let mut seed = Doc::with_client_id(seed_client_id(content));
+        if !content.is_empty() {
+            insert_text(&mut seed, 0, content);
+        }
+        let update = seed
+            .transact()
+            .encode_state_as_update_v1(&StateVector::default());
+        return append_note_update(conn, note_id, &update);
+    };
+
+    if get_text(&doc) == content {
+        return Ok(());
+    }
+    let before = doc.transact().state_vector();
+    set_text(&mut doc, content);
+    let update = doc.transact().encode_diff_v1(&before);
+    append_note_update(conn, note_id, &update)
+}
+
+/// The full state of a note's document as a single update, bringing its log
+/// in line with `content` first in case the body was written elsewhere
+pub fn encode_note_state(
+    conn: &Connection,
+    note_id: &str,
+    content: &str,
+) -> Result<Vec<u8>, DbError> {
+    record_note_edit(conn, note_id, content)?;
+    let doc = load_note_doc(conn, note_id)?
+        .ok_or_else(|| DbError::Message(format!("No CRDT log for note {}", note_id)))?;
+    let update = doc
+        .transact()
+        .encode_state_as_update_v1(&StateVector::default());
+    Ok(update)
+}
+
+/// Merge a remote update into a note's document and return the merged body.
+/// The local body is logged first so edits made outside the CRDT path are
+/// part of the merge.
+pub fn merge_note_update(
+    conn: &Connection,
+    note_id: &str,
+    update: &[u8],
+) -> Result<String, DbError> {
+    let local: Option<String> = conn
+        .query_row(
+            "SELECT content_md FROM note WHERE id = ?1",
+            [note_id],
+            |row| row.get(0),
+        )
+        .optional()?;
+    if let Some(local) = &local {
+        record_note_edit(conn, note_id, local)?;
+    }
+
+    let mut doc = load_note_doc(conn, note_id)?.unwrap_or_else(Doc::new);
+    apply_update(&mut doc, update)?;
+    append_note_update(conn, note_id, update)?;
+    Ok(get_text(&doc))
+}
+
+/// Drop a note's update log
+pub fn delete_note_log(conn: &Connection, note_id: &str) -> Result<(), DbError> {
+    conn.execute("DELETE FROM note_crdt_update WHERE note_id = ?1", [note_id])?;
+    Ok(())
+}
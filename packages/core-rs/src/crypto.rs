@@ -12,6 +12,11 @@ pub mod ecdh;
 pub enum CryptoError {
     #[error("AES-KW error: {0}")]
     AesKw(String),
+    #[error("Key derivation error: {0}")]
+    Kdf(String),
+    /// Wrong key, or the data or its associated data was altered
+    #[error("Decryption failed: wrong key or tampered data")]
+    Decrypt,
 }
 
 impl From<aes_kw::Error> for CryptoError {
@@ -30,6 +35,26 @@ pub fn derive_key(password: &str, salt: &[u8]) -> [u8; 32] {
     key
 }
 
+/// Derive a 32-byte key from a passphrase with Argon2id. The parameters are
+/// passed in so files that store them keep opening if the defaults change.
+pub fn derive_key_argon2(
+    passphrase: &str,
+    salt: &[u8],
+    m_cost: u32,
+    t_cost: u32,
+    p_cost: u32,
+) -> Result<zeroize::Zeroizing<[u8; 32]>, CryptoError> {
+    use argon2::{Algorithm, Argon2, Params, Version};
+
+    let params = Params::new(m_cost, t_cost, p_cost, Some(32))
+        .map_err(|e| CryptoError::Kdf(e.to_string()))?;
+    let mut key = zeroize::Zeroizing::new([0u8; 32]);
+    Argon2::new(Algorithm::Argon2id, Version::V0x13, params)
+        .hash_password_into(passphrase.as_bytes(), salt, key.as_mut())
+        .map_err(|e| CryptoError::Kdf(e.to_string()))?;
+    Ok(key)
+}
+
 /// Generate a random 32-byte Data Encryption Key (DEK).
 pub fn generate_dek() -> [u8; 32] {
     log::info!("[crypto] Generating DEK");
@@ -213,3 +238,69 @@ pub fn decrypt_bytes(encrypted: &[u8], dek: &[u8]) -> Result<Vec<u8>, CryptoErro
 
     Ok(plaintext)
 }
+
+/// Encrypt binary data with XChaCha20-Poly1305, authenticating `aad` along
+/// with it. Returns nonce + ciphertext like [`encrypt_bytes`].
+pub fn encrypt_bytes_with_aad(data: &[u8], key: &[u8], aad: &[u8]) -> Result<Vec<u8>, CryptoError> {
+    use chacha20poly1305::{
+        aead::{Aead, AeadCore, KeyInit, OsRng, Payload},
+        XChaCha20Poly1305,
+    };
+
+    if key.len() != 32 {
+        return Err(CryptoError::AesKw(format!(
+            "Invalid key length: got {}, expected 32 bytes",
+            key.len()
+        )));
+    }
+
+    let cipher = XChaCha20Poly1305::new(key.into());
+    let nonce = XChaCha20Poly1305::generate_nonce(&mut OsRng);
+    let ciphertext = cipher
+        .encrypt(&nonce, Payload { msg: data, aad })
+        .map_err(|e| CryptoError::AesKw(e.to_string()))?;
+
+    let mut result = Vec::with_capacity(24 + ciphertext.len());
+    result.extend_from_slice(&nonce);
+    result.extend_from_slice(&ciphertext);
+    Ok(result)
+}
+
+/// Decrypt data from [`encrypt_bytes_with_aad`]. A wrong key and altered
+/// data or `aad` all fail with [`CryptoError::Decrypt`].
+pub fn decrypt_bytes_with_aad(
+    encrypted: &[u8],
+    key: &[u8],
+    aad: &[u8],
+) -> Result<Vec<u8>, CryptoError> {
+    use chacha20poly1305::{
+        aead::{Aead, KeyInit, Payload},
+        XChaCha20Poly1305,
+    };
+
+    if key.len() != 32 {
+        return Err(CryptoError::AesKw(format!(
+            "Invalid key length: got {}, expected 32 bytes",
+            key.len()
+        )));
+    }
+
+    const NONCE_LEN: usize = 24;
+    const TAG_LEN: usize = 16;
+    if encrypted.len() < NONCE_LEN + TAG_LEN {
+        return Err(CryptoError::Decrypt);
+    }
+
+    let (nonce_bytes, ciphertext) = encrypted.split_at(NONCE_LEN);
+    let nonce = chacha20poly1305::XNonce::from_slice(nonce_bytes);
+    let cipher = XChaCha20Poly1305::new(key.into());
+    cipher
+        .decrypt(
+            nonce,
+            Payload {
+                msg: ciphertext,
+                aad,
+            },
+        )
+        .map_err(|_| CryptoError::Decrypt)
+}
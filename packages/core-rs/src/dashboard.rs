@@ -2,27 +2,168 @@ use rusqlite::Connection;
 use serde::{Deserialize, Serialize};
 use thiserror::Error;
 
+use crate::db::settings::{load_settings, save_settings, Settings, SettingsError};
+use crate::db::DbError;
+use crate::health::goals::{get_health_goals, HealthGoal};
+use crate::ids::parse_entity_id;
+use crate::mode::get_space_modes;
+use crate::personal_modes::{MODE_HEALTH, MODE_TRAVEL};
 use crate::quote::{self, Quote};
+use crate::srs_session::get_review_streak_at;
+use crate::travel::{get_upcoming_trip_summaries, TripError, TripSummary};
+use crate::weekly_review::{self, WeeklyReviewError};
+
+const SECONDS_PER_DAY: i64 = 86_400;
+
+/// Modes that have to be enabled in a space for their dashboard section to
+/// show up. Notes, tasks and time tracking are always shown.
+pub const MODE_HABITS: &str = "habits";
+pub const MODE_MUSIC: &str = "music";
+pub const MODE_SOCIAL: &str = "social";
+pub const MODE_SRS: &str = "srs";
 
 #[derive(Error, Debug)]
 pub enum DashboardError {
     #[error("Database error: {0}")]
     Database(#[from] rusqlite::Error),
+    #[error("Database error: {0}")]
+    Db(#[from] DbError),
+    #[error("Trip error: {0}")]
+    Trip(#[from] TripError),
+    #[error("Weekly review error: {0}")]
+    WeeklyReview(#[from] WeeklyReviewError),
+    #[error("Invalid dashboard config: {0}")]
+    InvalidConfig(String),
+}
+
+/// Period the dashboard compares against the one before it
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ComparisonWindow {
+    #[default]
+    Week,
+    Month,
+}
+
+impl ComparisonWindow {
+    pub fn days(self) -> i64 {
+        match self {
+            ComparisonWindow::Week => 7,
+            ComparisonWindow::Month => 30,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub struct DashboardConfig {
+    pub comparison_window: ComparisonWindow,
+}
+
+impl Settings for DashboardConfig {
+    const KEY: &'static str = "dashboard.config";
+    const DESCRIPTION: &'static str = "Comparison window of the dashboard trends";
+}
+
+impl From<SettingsError> for DashboardError {
+    fn from(e: SettingsError) -> Self {
+        match e {
+            SettingsError::Rusqlite(e) => DashboardError::Database(e),
+            SettingsError::Db(e) => DashboardError::Db(e),
+            SettingsError::Invalid { reason, .. } => DashboardError::InvalidConfig(reason),
+            SettingsError::Malformed { .. } | SettingsError::UnsupportedVersion { .. } => {
+                DashboardError::InvalidConfig(e.to_string())
+            }
+        }
+    }
 }
 
+pub fn get_dashboard_config(conn: &Connection) -> Result<DashboardConfig, DashboardError> {
+    Ok(load_settings(conn)?)
+}
+
+pub fn set_dashboard_config(
+    conn: &Connection,
+    config: &DashboardConfig,
+) -> Result<(), DashboardError> {
+    Ok(save_settings(conn, config)?)
+}
+
+/// A metric over the current period and the one before it
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+pub struct PeriodTrend {
+    pub current: i64,
+    pub previous: i64,
+    /// Change from the previous period in percent; `None` when the previous
+    /// period was zero
+    pub delta_percent: Option<f64>,
+}
+
+impl PeriodTrend {
+    pub fn new(current: i64, previous: i64) -> Self {
+        let delta_percent =
+            (previous != 0).then(|| (current - previous) as f64 / previous as f64 * 100.0);
+        Self {
+            current,
+            previous,
+            delta_percent,
+        }
+    }
+}
+
+/// Sections of modes that aren't enabled in the space are `None` and are
+/// never queried.
 #[derive(Debug, Serialize, Deserialize)]
 pub struct DashboardStats {
-    pub health: HealthStats,
-    pub music: MusicStats,
-    pub social: SocialStats,
+    /// Length of the compared periods
+    pub window_days: i64,
+    pub notes: NoteStats,
     pub tasks: TaskStats,
+    pub time: TimeStats,
+    /// Consecutive weeks with a completed weekly review, see
+    /// [`crate::weekly_review`]
+    pub weekly_review_streak_weeks: i64,
+    pub health: Option<HealthStats>,
+    pub habits: Option<HabitStats>,
+    pub srs: Option<SrsStats>,
+    pub music: Option<MusicStats>,
+    pub social: Option<SocialStats>,
+    pub travel: Option<TravelStats>,
     pub quote: Option<Quote>,
 }
 
+#[derive(Debug, Serialize, Deserialize)]
+pub struct NoteStats {
+    pub created: PeriodTrend,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct TimeStats {
+    /// Seconds of finished time entries started in each period
+    pub tracked_seconds: PeriodTrend,
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct HealthStats {
     pub metrics_count: i64,
     pub latest_metric: Option<String>,
+    /// Goals as of their last evaluation
+    pub goals: Vec<HealthGoal>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct HabitStats {
+    pub habits_count: i64,
+    /// Habit completions logged in each period
+    pub completed: PeriodTrend,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SrsStats {
+    /// Cards that came due in each period and are still waiting for review
+    pub cards_due: PeriodTrend,
+    /// Consecutive days with a completed review session, see
+    /// [`crate::srs_session`]
+    pub review_streak_days: i64,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -35,68 +176,263 @@ pub struct MusicStats {
 pub struct SocialStats {
     pub posts_count: i64,
     pub platforms_count: i64,
+    /// Posts that arrived in each period. Posts have no read state, so
+    /// arrivals since the period started stand in for unread posts.
+    pub new_posts: PeriodTrend,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct TravelStats {
+    /// Trips that haven't ended yet, soonest first
+    pub upcoming_trips: Vec<TripSummary>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct TaskStats {
     pub pending_count: i64,
     pub completed_count: i64,
+    pub completed: PeriodTrend,
+}
+
+/// Bounds of the compared periods, in unix seconds:
+/// previous is `[previous_start, current_start)`, current is
+/// `[current_start, end)`
+#[derive(Debug, Clone, Copy)]
+struct Periods {
+    previous_start: i64,
+    current_start: i64,
+    end: i64,
+}
+
+impl Periods {
+    fn ending_at(now: i64, window: ComparisonWindow) -> Self {
+        let length = window.days() * SECONDS_PER_DAY;
+        Self {
+            previous_start: now - 2 * length,
+            current_start: now - length,
+            end: now,
+        }
+    }
+}
+
+/// Sum `value` over the rows of `from` in both periods, placing each row by
+/// the unix-seconds expression `at`. `from` binds the space id as `?1`.
+fn trend(
+    conn: &Connection,
+    space_id: &str,
+    periods: Periods,
+    from: &str,
+    at: &str,
+    value: &str,
+) -> Result<PeriodTrend, DashboardError> {
+    let sql = format!(
+        "SELECT COALESCE(SUM(CASE WHEN {at} >= ?3 THEN {value} END), 0),
+                COALESCE(SUM(CASE WHEN {at} < ?3 THEN {value} END), 0)
+         {from} AND {at} >= ?2 AND {at} < ?4"
+    );
+    let (current, previous) = conn.query_row(
+        &sql,
+        rusqlite::params![
+            space_id,
+            periods.previous_start,
+            periods.current_start,
+            periods.end
+        ],
+        |row| Ok((row.get(0)?, row.get(1)?)),
+    )?;
+    Ok(PeriodTrend::new(current, previous))
 }
 
 pub fn get_dashboard_stats(
     conn: &Connection,
     space_id: &str,
 ) -> Result<DashboardStats, DashboardError> {
+    let config = get_dashboard_config(conn)?;
+    get_dashboard_stats_at(
+        conn,
+        space_id,
+        config.comparison_window,
+        chrono::Utc::now().timestamp(),
+    )
+}
+
+/// Dashboard stats comparing the `window` ending at `now` with the one
+/// before it
+pub fn get_dashboard_stats_at(
+    conn: &Connection,
+    space_id: &str,
+    window: ComparisonWindow,
+    now: i64,
+) -> Result<DashboardStats, DashboardError> {
+    let modes = get_space_modes(conn, space_id)?;
+    let enabled = |mode_id: &str| modes.iter().any(|m| m.id == mode_id);
+    let periods = Periods::ending_at(now, window);
+
+    let notes = NoteStats {
+        created: trend(
+            conn,
+            space_id,
+            periods,
+            "FROM note WHERE space_id = ?1",
+            "created_at",
+            "1",
+        )?,
+    };
+
+    let time = TimeStats {
+        tracked_seconds: trend(
+            conn,
+            space_id,
+            periods,
+            "FROM time_entry WHERE space_id = ?1 AND is_running = 0",
+            "started_at",
+            "COALESCE(duration_seconds, 0)",
+        )?,
+    };
+
     // Health Stats
-    let metrics_count: i64 = conn
-        .query_row(
-            "SELECT COUNT(*) FROM health_metric WHERE space_id = ?1",
+    let health = if enabled(MODE_HEALTH) {
+        let metrics_count: i64 = conn
+            .query_row(
+                "SELECT COUNT(*) FROM health_metric WHERE space_id = ?1",
+                [space_id],
+                |row| row.get(0),
+            )
+            .unwrap_or(0);
+
+        let latest_metric: Option<String> = conn.query_row(
+            "SELECT metric_type FROM health_metric WHERE space_id = ?1 ORDER BY recorded_at DESC LIMIT 1",
             [space_id],
             |row| row.get(0),
-        )
-        .unwrap_or(0);
+        ).ok();
 
-    let latest_metric: Option<String> = conn.query_row(
-        "SELECT metric_type FROM health_metric WHERE space_id = ?1 ORDER BY recorded_at DESC LIMIT 1",
-        [space_id],
-        |row| row.get(0),
-    ).ok();
+        let space_ulid = ulid::Ulid::from_string(space_id)
+            .map_err(|e| DbError::Message(format!("Invalid space id {space_id}: {e}")))?;
+        Some(HealthStats {
+            metrics_count,
+            latest_metric,
+            goals: get_health_goals(conn, space_ulid)?,
+        })
+    } else {
+        None
+    };
 
-    // Music Stats
-    let track_count: i64 = conn
-        .query_row(
-            "SELECT COUNT(*) FROM track WHERE space_id = ?1",
+    // Habit Stats
+    let habits = if enabled(MODE_HABITS) {
+        let habits_count: i64 = conn.query_row(
+            "SELECT COUNT(*) FROM habit WHERE space_id = ?1",
             [space_id],
             |row| row.get(0),
-        )
-        .unwrap_or(0);
+        )?;
+        let completed = trend(
+            conn,
+            space_id,
+            periods,
+            "FROM habit_log l JOIN habit h ON l.habit_id = h.id WHERE h.space_id = ?1",
+            "l.completed_at",
+            "1",
+        )?;
+        Some(HabitStats {
+            habits_count,
+            completed,
+        })
+    } else {
+        None
+    };
 
-    let playlist_count: i64 = conn
-        .query_row(
-            "SELECT COUNT(*) FROM playlist WHERE space_id = ?1",
-            [space_id],
-            |row| row.get(0),
-        )
-        .unwrap_or(0);
+    // SRS Stats
+    let srs = if enabled(MODE_SRS) {
+        let cards_due = trend(
+            conn,
+            space_id,
+            periods,
+            "FROM knowledge_card c JOIN note n ON c.note_id = n.id WHERE n.space_id = ?1",
+            "c.due_at",
+            "1",
+        )?;
+        let review_streak_days = get_review_streak_at(conn, space_id, now)?.current_days;
+        Some(SrsStats {
+            cards_due,
+            review_streak_days,
+        })
+    } else {
+        None
+    };
+
+    // Music Stats
+    let music = if enabled(MODE_MUSIC) {
+        let track_count: i64 = conn
+            .query_row(
+                "SELECT COUNT(*) FROM track WHERE space_id = ?1",
+                [space_id],
+                |row| row.get(0),
+            )
+            .unwrap_or(0);
+
+        let playlist_count: i64 = conn
+            .query_row(
+                "SELECT COUNT(*) FROM playlist WHERE space_id = ?1",
+                [space_id],
+                |row| row.get(0),
+            )
+            .unwrap_or(0);
+
+        Some(MusicStats {
+            track_count,
+            playlist_count,
+        })
+    } else {
+        None
+    };
 
     // Social Stats
-    let posts_count: i64 = conn
-        .query_row(
-            "SELECT COUNT(*) FROM social_post p
-         JOIN social_account a ON p.account_id = a.id
-         WHERE a.space_id = ?1",
-            [space_id],
-            |row| row.get(0),
-        )
-        .unwrap_or(0);
+    let social = if enabled(MODE_SOCIAL) {
+        let posts_count: i64 = conn
+            .query_row(
+                "SELECT COUNT(*) FROM social_post p
+             JOIN social_account a ON p.account_id = a.id
+             WHERE a.space_id = ?1",
+                [space_id],
+                |row| row.get(0),
+            )
+            .unwrap_or(0);
 
-    let platforms_count: i64 = conn
-        .query_row(
-            "SELECT COUNT(DISTINCT platform) FROM social_account WHERE space_id = ?1",
-            [space_id],
-            |row| row.get(0),
-        )
-        .unwrap_or(0);
+        let platforms_count: i64 = conn
+            .query_row(
+                "SELECT COUNT(DISTINCT platform) FROM social_account WHERE space_id = ?1",
+                [space_id],
+                |row| row.get(0),
+            )
+            .unwrap_or(0);
+
+        // Arrival times are in milliseconds
+        let new_posts = trend(
+            conn,
+            space_id,
+            periods,
+            "FROM social_post p JOIN social_account a ON p.account_id = a.id
+             WHERE a.space_id = ?1",
+            "(COALESCE(p.first_seen_at, p.fetched_at) / 1000)",
+            "1",
+        )?;
+
+        Some(SocialStats {
+            posts_count,
+            platforms_count,
+            new_posts,
+        })
+    } else {
+        None
+    };
+
+    // Travel Stats
+    let travel = if enabled(MODE_TRAVEL) {
+        Some(TravelStats {
+            upcoming_trips: get_upcoming_trip_summaries(conn, space_id, now)?,
+        })
+    } else {
+        None
+    };
 
     // Task Stats
     let pending_count: i64 = conn.query_row(
@@ -113,25 +449,37 @@ pub fn get_dashboard_stats(
         )
         .unwrap_or(0);
 
+    // Tasks marked done without a completion time count when last updated
+    let completed = trend(
+        conn,
+        space_id,
+        periods,
+        "FROM task WHERE space_id = ?1 AND status = 'done'",
+        "COALESCE(completed_at, updated_at)",
+        "1",
+    )?;
+
     let quote = Some(quote::get_daily_quote());
 
+    let space_ulid = parse_entity_id("space", "space_id", space_id).map_err(DbError::from)?;
+    let weekly_review_streak_weeks = weekly_review::get_review_streak_at(conn, space_ulid, now)?;
+
     Ok(DashboardStats {
-        health: HealthStats {
-            metrics_count,
-            latest_metric,
-        },
-        music: MusicStats {
-            track_count,
-            playlist_count,
-        },
-        social: SocialStats {
-            posts_count,
-            platforms_count,
-        },
+        window_days: window.days(),
+        notes,
         tasks: TaskStats {
             pending_count,
             completed_count,
+            completed,
         },
+        time,
+        weekly_review_streak_weeks,
+        health,
+        habits,
+        srs,
+        music,
+        social,
+        travel,
         quote,
     })
 }
@@ -0,0 +1,232 @@
+//! Bulk Write Mode
+//!
+//! Importers write thousands of rows in one go. Committing each one on its own
+//! and updating the full-text indexes row by row is what makes a large vault
+//! import slow, so [`with_bulk_import`] runs the whole batch in one
+//! transaction and fills the search indexes in a single pass at the end.
+//!
+//! SPDX-License-Identifier: AGPL-3.0-or-later
+//! Copyright (c) 2024-2025 Amirreza 'Farnam' Taheri <taherifarnam@gmail.com>
+
+use rusqlite::{params, Connection, OptionalExtension};
+use std::time::Instant;
+
+/// Temp table whose presence marks a connection as being in a bulk batch.
+/// Temp tables are private to their connection, so pooled connections are
+/// unaffected.
+const BULK_MARKER: &str = "bulk_import_session";
+
+/// An insert trigger that keeps an FTS table in step with its base table
+struct FtsInsertTrigger {
+    trigger: &'static str,
+    table: &'static str,
+    /// Indexes rows of `table` past the `?1` rowid that have no FTS row
+    backfill: &'static str,
+}
+
+const FTS_INSERT_TRIGGERS: &[FtsInsertTrigger] = &[
+    FtsInsertTrigger {
+        trigger: "task_ai",
+        table: "task",
+        backfill: "INSERT INTO fts_task(rowid, title, description)
+                   SELECT rowid, title, COALESCE(description, '') FROM task
+                   WHERE rowid > ?1 AND rowid NOT IN (SELECT rowid FROM fts_task WHERE rowid > ?1)",
+    },
+    FtsInsertTrigger {
+        trigger: "project_ai",
+        table: "project",
+        backfill: "INSERT INTO fts_project(rowid, title, goal_outcome)
+                   SELECT rowid, title, COALESCE(goal_outcome, '') FROM project
+                   WHERE rowid > ?1
+                     AND rowid NOT IN (SELECT rowid FROM fts_project WHERE rowid > ?1)",
+    },
+    FtsInsertTrigger {
+        trigger: "social_post_ai",
+        table: "social_post",
+        backfill: "INSERT INTO social_post_fts(rowid, content, author, post_id)
+                   SELECT rowid, COALESCE(content, ''), COALESCE(author, ''), id FROM social_post
+                   WHERE rowid > ?1
+                     AND rowid NOT IN (SELECT rowid FROM social_post_fts WHERE rowid > ?1)",
+    },
+];
+
+/// A trigger dropped for the batch, with what is needed to put it back
+struct DeferredTrigger {
+    sql: String,
+    watermark: i64,
+    backfill: &'static str,
+}
+
+/// Durability pragmas as they were before the batch
+struct Durability {
+    synchronous: i64,
+    journal_mode: String,
+}
+
+/// Whether `conn` is inside [`with_bulk_import`]. Note search rows are then
+/// written when the batch ends instead of per note.
+pub(crate) fn fts_deferred(conn: &Connection) -> rusqlite::Result<bool> {
+    conn.query_row(
+        "SELECT EXISTS (SELECT 1 FROM temp.sqlite_master WHERE type = 'table' AND name = ?1)",
+        [BULK_MARKER],
+        |row| row.get(0),
+    )
+}
+
+/// Run `f` as one bulk write.
+///
+/// Everything `f` writes lands in a single transaction, rolled back if it
+/// fails. Search indexes for notes, tasks, projects and social posts are
+/// filled in one pass once `f` is done, and durability pragmas are relaxed
+/// for the batch and restored afterwards. Nested calls join the outer batch;
+/// inside an existing transaction the batch becomes a savepoint and the
+/// pragmas are left alone.
+pub fn with_bulk_import<T, E, F>(conn: &Connection, f: F) -> Result<T, E>
+where
+    F: FnOnce(&Connection) -> Result<T, E>,
+    E: From<rusqlite::Error>,
+{
+    if fts_deferred(conn)? {
+        return f(conn);
+    }
+
+    // Pragmas can't change inside a transaction
+    let durability = if conn.is_autocommit() {
+        Some(relax_durability(conn)?)
+    } else {
+        None
+    };
+    let result = run_batch(conn, f);
+    if let Some(durability) = durability {
+        // The batch is already settled, so a failure here is only logged
+        if let Err(e) = restore_durability(conn, &durability) {
+            log::warn!("[db] Failed to restore pragmas after bulk import: {}", e);
+        }
+    }
+    result
+}
+
+fn run_batch<T, E, F>(conn: &Connection, f: F) -> Result<T, E>
+where
+    F: FnOnce(&Connection) -> Result<T, E>,
+    E: From<rusqlite::Error>,
+{
+    conn.execute_batch(&format!(
+        "SAVEPOINT bulk_import; CREATE TEMP TABLE {} (started_at INTEGER)",
+        BULK_MARKER
+    ))?;
+    let written = (|| -> Result<T, E> {
+        let note_watermark = max_rowid(conn, "note")?;
+        let deferred = defer_fts_triggers(conn)?;
+        let value = f(conn)?;
+
+        let started = Instant::now();
+        let notes = index_new_notes(conn, note_watermark)?;
+        for trigger in &deferred {
+            conn.execute(trigger.backfill, [trigger.watermark])?;
+            conn.execute_batch(&trigger.sql)?;
+        }
+        log::info!(
+            "[db] Bulk import indexed {} notes in {:?}",
+            notes,
+            started.elapsed()
+        );
+        Ok(value)
+    })();
+
+    match written {
+        Ok(value) => {
+            conn.execute_batch(&format!(
+                "DROP TABLE temp.{}; RELEASE bulk_import",
+                BULK_MARKER
+            ))?;
+            Ok(value)
+        }
+        Err(e) => {
+            // Also brings back the dropped triggers and the marker
+            conn.execute_batch("ROLLBACK TO bulk_import; RELEASE bulk_import")?;
+            Err(e)
+        }
+    }
+}
+
+fn max_rowid(conn: &Connection, table: &str) -> rusqlite::Result<i64> {
+    conn.query_row(
+        &format!("SELECT COALESCE(MAX(rowid), 0) FROM {}", table),
+        [],
+        |row| row.get(0),
+    )
+}
+
+/// Drop the FTS insert triggers that exist in this vault. Dropping them
+/// inside the batch's transaction means no other connection ever sees them
+/// missing.
+fn defer_fts_triggers(conn: &Connection) -> rusqlite::Result<Vec<DeferredTrigger>> {
+    let mut deferred = Vec::new();
+    for fts in FTS_INSERT_TRIGGERS {
+        let sql: Option<String> = conn
+            .query_row(
+                "SELECT sql FROM sqlite_master WHERE type = 'trigger' AND name = ?1",
+                [fts.trigger],
+                |row| row.get(0),
+            )
+            .optional()?;
+        let Some(sql) = sql else {
+            continue;
+        };
+        let watermark = max_rowid(conn, fts.table)?;
+        conn.execute_batch(&format!("DROP TRIGGER {}", fts.trigger))?;
+        deferred.push(DeferredTrigger {
+            sql,
+            watermark,
+            backfill: fts.backfill,
+        });
+    }
+    Ok(deferred)
+}
+
+/// Index notes created during the batch. Titles are lowercased in Rust, as
+/// `create_note` does, since SQLite's `lower()` only folds ASCII.
+fn index_new_notes(conn: &Connection, watermark: i64) -> rusqlite::Result<usize> {
+    let pending: Vec<(i64, String, String, String)> = conn
+        .prepare(
+            "SELECT n.rowid, n.id, n.title, n.content_md FROM note n
+             WHERE n.rowid > ?1 AND n.is_locked = 0
+               AND NOT EXISTS (SELECT 1 FROM fts_note f WHERE f.rowid = n.rowid)",
+        )?
+        .query_map([watermark], |row| {
+            Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?))
+        })?
+        .collect::<Result<_, _>>()?;
+
+    let mut insert = conn.prepare(
+        "INSERT INTO fts_note (rowid, note_id, title, content_md) VALUES (?1, ?2, ?3, ?4)",
+    )?;
+    for (rowid, note_id, title, content_md) in &pending {
+        insert.execute(params![rowid, note_id, title.to_lowercase(), content_md])?;
+    }
+    Ok(pending.len())
+}
+
+fn relax_durability(conn: &Connection) -> rusqlite::Result<Durability> {
+    let durability = Durability {
+        synchronous: conn.query_row("PRAGMA synchronous", [], |row| row.get(0))?,
+        journal_mode: conn.query_row("PRAGMA journal_mode", [], |row| row.get(0))?,
+    };
+    conn.execute_batch("PRAGMA synchronous = OFF;")?;
+    // Leaving WAL needs exclusive access, and WAL already appends cheaply
+    if !durability.journal_mode.eq_ignore_ascii_case("wal") {
+        conn.execute_batch("PRAGMA journal_mode = MEMORY;")?;
+    }
+    Ok(durability)
+}
+
+fn restore_durability(conn: &Connection, durability: &Durability) -> rusqlite::Result<()> {
+    if !durability.journal_mode.eq_ignore_ascii_case("wal") {
+        conn.execute_batch(&format!(
+            "PRAGMA journal_mode = {};",
+            durability.journal_mode
+        ))?;
+    }
+    conn.execute_batch(&format!("PRAGMA synchronous = {};", durability.synchronous))
+}
@@ -0,0 +1,600 @@
+//! Vault Integrity Check
+//!
+//! Sync, imports and crashes can leave rows pointing at entities that no
+//! longer exist, or a search index that drifted from note content. This module
+//! finds those inconsistencies, wraps SQLite's own corruption checks, and
+//! repairs what can be repaired without losing user data.
+//!
+//! SPDX-License-Identifier: AGPL-3.0-or-later
+//! Copyright (c) 2024-2025 Amirreza 'Farnam' Taheri <taherifarnam@gmail.com>
+
+use super::{get_setting, set_setting, table_exists, DbError};
+use crate::social::maintenance::BLOB_GC_GRACE_SECONDS;
+use rusqlite::{params, params_from_iter, Connection, OptionalExtension};
+use serde::{Deserialize, Serialize};
+
+const STARTUP_CHECK_KEY: &str = "startup_integrity_check";
+
+/// How thorough a check is
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum IntegrityCheckMode {
+    /// `PRAGMA quick_check`, and search index rows are only checked for
+    /// presence, not compared with note content
+    Quick,
+    /// `PRAGMA integrity_check` and every consistency query
+    Full,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum IssueSeverity {
+    /// Wasted space only
+    Info,
+    /// Leftover rows that nothing reads
+    Warning,
+    /// Wrong results or data at risk until repaired
+    Error,
+    /// SQLite reported corruption; restore from a backup
+    Critical,
+}
+
+/// Kind of inconsistency. The doc of each variant lists what `entity_ids`
+/// holds, in order.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum IntegrityIssueKind {
+    /// A line reported by `PRAGMA integrity_check`/`quick_check`; no ids
+    DatabaseCorruption,
+    /// `[note_id, tag_id]` of a note tag whose note or tag is gone
+    OrphanNoteTag,
+    /// `[task_id, tag_id]` of a task tag whose task or tag is gone
+    OrphanTaskTag,
+    /// `[source_note_id, target_note_id]` of a link from a missing note
+    OrphanLink,
+    /// `[note_id, key]` of metadata of a missing note
+    OrphanNoteMeta,
+    /// `[note_id]` of a missing note that still has a CRDT update log
+    OrphanCrdtLog,
+    /// `[note_id, blob_id]` of an attachment whose note or blob is gone
+    OrphanAttachment,
+    /// `[mapping_id]` of a CalDAV mapping whose account, task or note is gone
+    OrphanCaldavMapping,
+    /// `[blob_id, owner_type, owner_id]` of a blob reference whose blob or
+    /// owner is gone
+    OrphanBlobRef,
+    /// `[blob_id, note_id]` of an attachment without a blob reference, which
+    /// blob GC would delete from under the note
+    MissingBlobRef,
+    /// `[blob_id]` of a blob nothing references
+    UnreferencedBlob,
+    /// `[note_id]` of a note whose search index row is missing or outdated
+    StaleSearchIndex,
+    /// `[rowid]` of a search index row without a note
+    OrphanSearchIndex,
+    /// `[conflict_id]` of an open update/update sync conflict whose entity is
+    /// gone
+    OrphanSyncConflict,
+}
+
+impl IntegrityIssueKind {
+    pub fn severity(self) -> IssueSeverity {
+        use IntegrityIssueKind::*;
+        match self {
+            DatabaseCorruption => IssueSeverity::Critical,
+            MissingBlobRef | StaleSearchIndex => IssueSeverity::Error,
+            UnreferencedBlob => IssueSeverity::Info,
+            _ => IssueSeverity::Warning,
+        }
+    }
+
+    pub fn auto_fixable(self) -> bool {
+        self != IntegrityIssueKind::DatabaseCorruption
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct IntegrityIssue {
+    pub kind: IntegrityIssueKind,
+    pub entity_ids: Vec<String>,
+    pub severity: IssueSeverity,
+    pub auto_fixable: bool,
+    pub message: String,
+}
+
+impl IntegrityIssue {
+    fn new(kind: IntegrityIssueKind, entity_ids: Vec<String>, message: String) -> Self {
+        IntegrityIssue {
+            kind,
+            entity_ids,
+            severity: kind.severity(),
+            auto_fixable: kind.auto_fixable(),
+            message,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct VaultIntegrityReport {
+    pub mode: IntegrityCheckMode,
+    /// Most severe first
+    pub issues: Vec<IntegrityIssue>,
+    pub checked_at: i64,
+}
+
+impl VaultIntegrityReport {
+    pub fn is_healthy(&self) -> bool {
+        self.issues.is_empty()
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct VaultRepairReport {
+    pub repaired: usize,
+    /// Issues that can't be fixed automatically or were no longer present
+    pub skipped: usize,
+}
+
+/// A consistency query: each row is the `entity_ids` of one issue
+struct OrphanCheck {
+    kind: IntegrityIssueKind,
+    /// The check is skipped when any of these tables doesn't exist
+    tables: &'static [&'static str],
+    find: &'static str,
+    /// Deletes one issue's rows given its `entity_ids`, re-checking that the
+    /// row is still orphaned
+    repair: &'static str,
+    message: &'static str,
+}
+
+const ORPHAN_CHECKS: &[OrphanCheck] = &[
+    OrphanCheck {
+        kind: IntegrityIssueKind::OrphanNoteTag,
+        tables: &["note_tags"],
+        find: "SELECT IFNULL(nt.note_id, ''), IFNULL(nt.tag_id, '') FROM note_tags nt
+               WHERE NOT EXISTS (SELECT 1 FROM note n WHERE n.id = nt.note_id)
+                  OR NOT EXISTS (SELECT 1 FROM tag t WHERE t.id = nt.tag_id)",
+        repair: "DELETE FROM note_tags
+                 WHERE IFNULL(note_id, '') = ?1 AND IFNULL(tag_id, '') = ?2
+                   AND (NOT EXISTS (SELECT 1 FROM note n WHERE n.id = note_tags.note_id)
+                     OR NOT EXISTS (SELECT 1 FROM tag t WHERE t.id = note_tags.tag_id))",
+        message: "Note tag points at a missing note or tag",
+    },
+    OrphanCheck {
+        kind: IntegrityIssueKind::OrphanTaskTag,
+        tables: &["task_tags"],
+        find: "SELECT IFNULL(tt.task_id, ''), IFNULL(tt.tag_id, '') FROM task_tags tt
+               WHERE NOT EXISTS (SELECT 1 FROM task t WHERE t.id = tt.task_id)
+                  OR NOT EXISTS (SELECT 1 FROM tag g WHERE g.id = tt.tag_id)",
+        repair: "DELETE FROM task_tags
+                 WHERE IFNULL(task_id, '') = ?1 AND IFNULL(tag_id, '') = ?2
+                   AND (NOT EXISTS (SELECT 1 FROM task t WHERE t.id = task_tags.task_id)
+                     OR NOT EXISTS (SELECT 1 FROM tag g WHERE g.id = task_tags.tag_id))",
+        message: "Task tag points at a missing task or tag",
+    },
+    // Links to a missing target are allowed: they resolve once the note syncs
+    OrphanCheck {
+        kind: IntegrityIssueKind::OrphanLink,
+        tables: &["link"],
+        find: "SELECT IFNULL(l.source_note_id, ''), IFNULL(l.target_note_id, '') FROM link l
+               WHERE NOT EXISTS (SELECT 1 FROM note n WHERE n.id = l.source_note_id)",
+        repair: "DELETE FROM link
+                 WHERE IFNULL(source_note_id, '') = ?1 AND IFNULL(target_note_id, '') = ?2
+                   AND NOT EXISTS (SELECT 1 FROM note n WHERE n.id = link.source_note_id)",
+        message: "Link from a missing note",
+    },
+    OrphanCheck {
+        kind: IntegrityIssueKind::OrphanNoteMeta,
+        tables: &["note_meta"],
+        find: "SELECT IFNULL(m.note_id, ''), IFNULL(m.key, '') FROM note_meta m
+               WHERE NOT EXISTS (SELECT 1 FROM note n WHERE n.id = m.note_id)",
+        repair: "DELETE FROM note_meta
+                 WHERE IFNULL(note_id, '') = ?1 AND IFNULL(key, '') = ?2
+                   AND NOT EXISTS (SELECT 1 FROM note n WHERE n.id = note_meta.note_id)",
+        message: "Metadata of a missing note",
+    },
+    OrphanCheck {
+        kind: IntegrityIssueKind::OrphanCrdtLog,
+        tables: &["note_crdt_update"],
+        find: "SELECT DISTINCT u.note_id FROM note_crdt_update u
+               WHERE NOT EXISTS (SELECT 1 FROM note n WHERE n.id = u.note_id)",
+        repair: "DELETE FROM note_crdt_update
+                 WHERE note_id = ?1 AND NOT EXISTS (SELECT 1 FROM note n WHERE n.id = ?1)",
+        message: "CRDT update log of a missing note",
+    },
+    OrphanCheck {
+        kind: IntegrityIssueKind::OrphanAttachment,
+        tables: &["note_attachment"],
+        find: "SELECT a.note_id, a.blob_id FROM note_attachment a
+               WHERE NOT EXISTS (SELECT 1 FROM note n WHERE n.id = a.note_id)
+                  OR NOT EXISTS (SELECT 1 FROM blob b WHERE b.id = a.blob_id)",
+        repair: "DELETE FROM note_attachment
+                 WHERE note_id = ?1 AND blob_id = ?2
+                   AND (NOT EXISTS (SELECT 1 FROM note n WHERE n.id = ?1)
+                     OR NOT EXISTS (SELECT 1 FROM blob b WHERE b.id = ?2))",
+        message: "Attachment of a missing note or blob",
+    },
+    OrphanCheck {
+        kind: IntegrityIssueKind::OrphanCaldavMapping,
+        tables: &["caldav_event_mapping", "caldav_account"],
+        find: "SELECT m.id FROM caldav_event_mapping m
+               WHERE NOT EXISTS (SELECT 1 FROM caldav_account a WHERE a.id = m.account_id)
+                  OR (m.local_task_id IS NOT NULL
+                      AND NOT EXISTS (SELECT 1 FROM task t WHERE t.id = m.local_task_id))
+                  OR (m.local_note_id IS NOT NULL
+                      AND NOT EXISTS (SELECT 1 FROM note n WHERE n.id = m.local_note_id))",
+        repair: "DELETE FROM caldav_event_mapping AS m
+                 WHERE m.id = ?1
+                   AND (NOT EXISTS (SELECT 1 FROM caldav_account a WHERE a.id = m.account_id)
+                     OR (m.local_task_id IS NOT NULL
+                         AND NOT EXISTS (SELECT 1 FROM task t WHERE t.id = m.local_task_id))
+                     OR (m.local_note_id IS NOT NULL
+                         AND NOT EXISTS (SELECT 1 FROM note n WHERE n.id = m.local_note_id)))",
+        message: "CalDAV mapping whose account or local event is gone",
+    },
+    OrphanCheck {
+        kind: IntegrityIssueKind::OrphanBlobRef,
+        tables: &["blob_ref"],
+        find: "SELECT r.blob_id, r.owner_type, r.owner_id FROM blob_ref r
+               WHERE NOT EXISTS (SELECT 1 FROM blob b WHERE b.id = r.blob_id)
+                  OR (r.owner_type = 'note'
+                      AND NOT EXISTS (SELECT 1 FROM note n WHERE n.id = r.owner_id))
+                  OR (r.owner_type = 'social_post'
+                      AND NOT EXISTS (SELECT 1 FROM social_post p WHERE p.id = r.owner_id))",
+        repair: "DELETE FROM blob_ref AS r
+                 WHERE r.blob_id = ?1 AND r.owner_type = ?2 AND r.owner_id = ?3
+                   AND (NOT EXISTS (SELECT 1 FROM blob b WHERE b.id = r.blob_id)
+                     OR (r.owner_type = 'note'
+                         AND NOT EXISTS (SELECT 1 FROM note n WHERE n.id = r.owner_id))
+                     OR (r.owner_type = 'social_post'
+                         AND NOT EXISTS (SELECT 1 FROM social_post p WHERE p.id = r.owner_id)))",
+        message: "Blob reference whose blob or owner is gone",
+    },
+    OrphanCheck {
+        kind: IntegrityIssueKind::MissingBlobRef,
+        tables: &["note_attachment", "blob_ref"],
+        find: "SELECT a.blob_id, a.note_id FROM note_attachment a
+               WHERE EXISTS (SELECT 1 FROM blob b WHERE b.id = a.blob_id)
+                 AND EXISTS (SELECT 1 FROM note n WHERE n.id = a.note_id)
+                 AND NOT EXISTS (
+                     SELECT 1 FROM blob_ref r
+                     WHERE r.blob_id = a.blob_id AND r.owner_type = 'note' AND r.owner_id = a.note_id
+                 )",
+        repair: "INSERT OR IGNORE INTO blob_ref (blob_id, owner_type, owner_id, created_at)
+                 SELECT a.blob_id, 'note', a.note_id, a.created_at FROM note_attachment a
+                 WHERE a.blob_id = ?1 AND a.note_id = ?2
+                   AND EXISTS (SELECT 1 FROM blob b WHERE b.id = a.blob_id)
+                   AND EXISTS (SELECT 1 FROM note n WHERE n.id = a.note_id)",
+        message: "Attachment without a blob reference",
+    },
+    OrphanCheck {
+        kind: IntegrityIssueKind::OrphanSearchIndex,
+        tables: &["fts_note"],
+        find: "SELECT CAST(f.rowid AS TEXT) FROM fts_note f
+               WHERE NOT EXISTS (SELECT 1 FROM note n WHERE n.rowid = f.rowid)",
+        repair: "DELETE FROM fts_note
+                 WHERE rowid = CAST(?1 AS INTEGER)
+                   AND NOT EXISTS (SELECT 1 FROM note n WHERE n.rowid = CAST(?1 AS INTEGER))",
+        message: "Search index row without a note",
+    },
+    OrphanCheck {
+        kind: IntegrityIssueKind::OrphanSyncConflict,
+        tables: &["sync_conflict"],
+        find: "SELECT c.id FROM sync_conflict c
+               WHERE c.resolved = 0 AND c.conflict_type = 'UpdateUpdate'
+                 AND CASE c.entity_type
+                     WHEN 'note' THEN NOT EXISTS (SELECT 1 FROM note e WHERE e.id = c.entity_id)
+                     WHEN 'task' THEN NOT EXISTS (SELECT 1 FROM task e WHERE e.id = c.entity_id)
+                     WHEN 'project' THEN NOT EXISTS (SELECT 1 FROM project e WHERE e.id = c.entity_id)
+                     WHEN 'tag' THEN NOT EXISTS (SELECT 1 FROM tag e WHERE e.id = c.entity_id)
+                     ELSE 0 END",
+        repair: "DELETE FROM sync_conflict AS c
+                 WHERE c.id = ?1 AND c.resolved = 0 AND c.conflict_type = 'UpdateUpdate'
+                   AND CASE c.entity_type
+                       WHEN 'note' THEN NOT EXISTS (SELECT 1 FROM note e WHERE e.id = c.entity_id)
+                       WHEN 'task' THEN NOT EXISTS (SELECT 1 FROM task e WHERE e.id = c.entity_id)
+                       WHEN 'project' THEN NOT EXISTS (SELECT 1 FROM project e WHERE e.id = c.entity_id)
+                       WHEN 'tag' THEN NOT EXISTS (SELECT 1 FROM tag e WHERE e.id = c.entity_id)
+                       ELSE 0 END",
+        message: "Open sync conflict for an entity that no longer exists",
+    },
+];
+
+/// Run `PRAGMA quick_check` or `PRAGMA integrity_check` and return what it
+/// reported; empty when the database is fine
+pub fn sqlite_integrity_check(
+    conn: &Connection,
+    mode: IntegrityCheckMode,
+) -> Result<Vec<String>, DbError> {
+    let pragma = match mode {
+        IntegrityCheckMode::Quick => "PRAGMA quick_check",
+        IntegrityCheckMode::Full => "PRAGMA integrity_check",
+    };
+    let mut stmt = conn.prepare(pragma)?;
+    let lines = stmt
+        .query_map([], |row| row.get::<_, String>(0))?
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(lines.into_iter().filter(|line| line != "ok").collect())
+}
+
+pub fn check_vault(conn: &Connection) -> Result<VaultIntegrityReport, DbError> {
+    check_vault_with_mode(conn, IntegrityCheckMode::Full)
+}
+
+pub fn check_vault_with_mode(
+    conn: &Connection,
+    mode: IntegrityCheckMode,
+) -> Result<VaultIntegrityReport, DbError> {
+    let now = chrono::Utc::now().timestamp();
+    log::info!("[integrity] Checking vault ({:?})", mode);
+
+    let mut issues: Vec<IntegrityIssue> = sqlite_integrity_check(conn, mode)?
+        .into_iter()
+        .map(|line| IntegrityIssue::new(IntegrityIssueKind::DatabaseCorruption, Vec::new(), line))
+        .collect();
+
+    for check in ORPHAN_CHECKS {
+        if !all_tables_exist(conn, check.tables)? {
+            continue;
+        }
+        let mut stmt = conn.prepare(check.find)?;
+        let columns = stmt.column_count();
+        let rows = stmt.query_map([], |row| {
+            (0..columns)
+                .map(|i| row.get::<_, String>(i))
+                .collect::<Result<Vec<_>, _>>()
+        })?;
+        for ids in rows {
+            issues.push(IntegrityIssue::new(
+                check.kind,
+                ids?,
+                check.message.to_string(),
+            ));
+        }
+    }
+
+    issues.extend(find_unreferenced_blobs(conn, now)?);
+    issues.extend(find_stale_search_index(conn, mode)?);
+
+    issues.sort_by(|a, b| b.severity.cmp(&a.severity));
+    if issues.is_empty() {
+        log::info!("[integrity] No issues found");
+    } else {
+        log::warn!("[integrity] Found {} issues", issues.len());
+    }
+    Ok(VaultIntegrityReport {
+        mode,
+        issues,
+        checked_at: now,
+    })
+}
+
+fn all_tables_exist(conn: &Connection, tables: &[&str]) -> Result<bool, DbError> {
+    for table in tables {
+        if !table_exists(conn, table)? {
+            return Ok(false);
+        }
+    }
+    Ok(true)
+}
+
+/// Blobs younger than the GC grace period may simply not be attached yet, and
+/// blobs with OCR in flight are kept, as in [`crate::blob::gc_unreferenced_blobs`]
+fn unreferenced_blob_filter(conn: &Connection) -> Result<&'static str, DbError> {
+    Ok(if table_exists(conn, "ocr_result")? {
+        "b.created_at < ?1
+         AND NOT EXISTS (SELECT 1 FROM blob_ref r WHERE r.blob_id = b.id)
+         AND NOT EXISTS (
+             SELECT 1 FROM ocr_result o
+             WHERE o.blob_id = b.id AND o.status IN ('pending', 'processing')
+         )"
+    } else {
+        "b.created_at < ?1 AND NOT EXISTS (SELECT 1 FROM blob_ref r WHERE r.blob_id = b.id)"
+    })
+}
+
+fn find_unreferenced_blobs(conn: &Connection, now: i64) -> Result<Vec<IntegrityIssue>, DbError> {
+    if !all_tables_exist(conn, &["blob", "blob_ref"])? {
+        return Ok(Vec::new());
+    }
+    let sql = format!(
+        "SELECT b.id FROM blob b WHERE {}",
+        unreferenced_blob_filter(conn)?
+    );
+    let mut stmt = conn.prepare(&sql)?;
+    let ids = stmt
+        .query_map([now - BLOB_GC_GRACE_SECONDS], |row| row.get::<_, String>(0))?
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(ids
+        .into_iter()
+        .map(|id| {
+            IntegrityIssue::new(
+                IntegrityIssueKind::UnreferencedBlob,
+                vec![id],
+                "Blob that nothing references".to_string(),
+            )
+        })
+        .collect())
+}
+
+/// Notes whose search index row is missing or, in full mode, no longer
+/// matches the note; locked notes aren't indexed. Compared in Rust because
+/// titles are indexed with Rust's Unicode lowercasing, which SQLite's
+/// `lower()` doesn't match.
+fn find_stale_search_index(
+    conn: &Connection,
+    mode: IntegrityCheckMode,
+) -> Result<Vec<IntegrityIssue>, DbError> {
+    if !table_exists(conn, "fts_note")? {
+        return Ok(Vec::new());
+    }
+    let sql = match mode {
+        IntegrityCheckMode::Quick => {
+            "SELECT n.id, NULL, NULL, NULL, NULL, NULL, f.rowid IS NULL
+             FROM note n LEFT JOIN fts_note f ON f.rowid = n.rowid
+             WHERE f.rowid IS NULL AND n.is_locked = 0"
+        }
+        IntegrityCheckMode::Full => {
+            "SELECT n.id, n.title, n.content_md, f.title, f.content_md, f.note_id, f.rowid IS NULL
+             FROM note n LEFT JOIN fts_note f ON f.rowid = n.rowid
+             WHERE n.is_locked = 0"
+        }
+    };
+    let mut stmt = conn.prepare(sql)?;
+    let rows = stmt.query_map([], |row| {
+        let id: String = row.get(0)?;
+        let missing: bool = row.get(6)?;
+        let title: Option<String> = row.get(1)?;
+        let stale = missing
+            || title.map(|t| t.to_lowercase()) != row.get::<_, Option<String>>(3)?
+            || row.get::<_, Option<String>>(2)? != row.get::<_, Option<String>>(4)?
+            || Some(id.as_str()) != row.get::<_, Option<String>>(5)?.as_deref();
+        Ok((id, missing, stale))
+    })?;
+
+    let mut issues = Vec::new();
+    for row in rows {
+        let (id, missing, stale) = row?;
+        if !stale {
+            continue;
+        }
+        let message = if missing {
+            "Note is missing from the search index"
+        } else {
+            "Search index is out of date with the note"
+        };
+        issues.push(IntegrityIssue::new(
+            IntegrityIssueKind::StaleSearchIndex,
+            vec![id],
+            message.to_string(),
+        ));
+    }
+    Ok(issues)
+}
+
+/// Fix the auto-fixable issues among `selected` in one transaction. Each fix
+/// re-checks that its issue is still present, so a report that has gone stale
+/// is safe to pass in.
+pub fn repair_vault(
+    conn: &mut Connection,
+    selected: &[IntegrityIssue],
+) -> Result<VaultRepairReport, DbError> {
+    log::info!("[integrity] Repairing {} selected issues", selected.len());
+    let tx = conn.transaction()?;
+    let mut report = VaultRepairReport::default();
+    for issue in selected {
+        if repair_issue(&tx, issue)? {
+            report.repaired += 1;
+        } else {
+            report.skipped += 1;
+        }
+    }
+    tx.commit()?;
+    log::info!(
+        "[integrity] Repaired {} issues, skipped {}",
+        report.repaired,
+        report.skipped
+    );
+    Ok(report)
+}
+
+fn repair_issue(conn: &Connection, issue: &IntegrityIssue) -> Result<bool, DbError> {
+    let ids = &issue.entity_ids;
+    match issue.kind {
+        IntegrityIssueKind::DatabaseCorruption => Ok(false),
+        IntegrityIssueKind::UnreferencedBlob => {
+            let [blob_id] = ids.as_slice() else {
+                return Ok(false);
+            };
+            repair_unreferenced_blob(conn, blob_id)
+        }
+        IntegrityIssueKind::StaleSearchIndex => {
+            let [note_id] = ids.as_slice() else {
+                return Ok(false);
+            };
+            reindex_note(conn, note_id)
+        }
+        kind => {
+            let Some(check) = ORPHAN_CHECKS.iter().find(|c| c.kind == kind) else {
+                return Ok(false);
+            };
+            if !all_tables_exist(conn, check.tables)? {
+                return Ok(false);
+            }
+            let mut stmt = conn.prepare(check.repair)?;
+            if stmt.parameter_count() != ids.len() {
+                return Ok(false);
+            }
+            Ok(stmt.execute(params_from_iter(ids))? > 0)
+        }
+    }
+}
+
+fn repair_unreferenced_blob(conn: &Connection, blob_id: &str) -> Result<bool, DbError> {
+    if !all_tables_exist(conn, &["blob", "blob_ref"])? {
+        return Ok(false);
+    }
+    let now = chrono::Utc::now().timestamp();
+    let sql = format!(
+        "DELETE FROM blob AS b WHERE b.id = ?2 AND {}",
+        unreferenced_blob_filter(conn)?
+    );
+    if conn.execute(&sql, params![now - BLOB_GC_GRACE_SECONDS, blob_id])? == 0 {
+        return Ok(false);
+    }
+    // Same clean-up as blob GC: dependents go, objects are queued for the sweep
+    if table_exists(conn, "ocr_result")? {
+        conn.execute("DELETE FROM ocr_result WHERE blob_id = ?1", [blob_id])?;
+    }
+    conn.execute("DELETE FROM note_attachment WHERE blob_id = ?1", [blob_id])?;
+    conn.execute(
+        "INSERT OR REPLACE INTO blob_pending_sweep (blob_id, deleted_at) VALUES (?1, ?2)",
+        params![blob_id, now],
+    )?;
+    Ok(true)
+}
+
+/// Rewrite a note's search index row the way `create_note` writes it
+fn reindex_note(conn: &Connection, note_id: &str) -> Result<bool, DbError> {
+    let note: Option<(i64, String, String)> = conn
+        .query_row(
+            "SELECT rowid, title, content_md FROM note WHERE id = ?1",
+            [note_id],
+            |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+        )
+        .optional()?;
+    let Some((rowid, title, content_md)) = note else {
+        return Ok(false);
+    };
+    let title = title.to_lowercase();
+    let indexed: Option<(String, String, String)> = conn
+        .query_row(
+            "SELECT note_id, title, content_md FROM fts_note WHERE rowid = ?1",
+            [rowid],
+            |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+        )
+        .optional()?;
+    if indexed.as_ref() == Some(&(note_id.to_string(), title.clone(), content_md.clone())) {
+        return Ok(false);
+    }
+    conn.execute("DELETE FROM fts_note WHERE rowid = ?1", [rowid])?;
+    conn.execute(
+        "INSERT INTO fts_note (rowid, note_id, title, content_md) VALUES (?1, ?2, ?3, ?4)",
+        params![rowid, note_id, title, content_md],
+    )?;
+    Ok(true)
+}
+
+/// Whether startup maintenance runs a quick integrity check
+pub fn is_startup_integrity_check_enabled(conn: &Connection) -> Result<bool, DbError> {
+    Ok(get_setting(conn, STARTUP_CHECK_KEY)?.as_deref() == Some("true"))
+}
+
+pub fn set_startup_integrity_check(conn: &Connection, enabled: bool) -> Result<(), DbError> {
+    set_setting(
+        conn,
+        STARTUP_CHECK_KEY,
+        if enabled { "true" } else { "false" },
+        Some("Run a quick vault integrity check during startup maintenance"),
+    )
+}
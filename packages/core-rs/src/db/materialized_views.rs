@@ -23,6 +23,10 @@ pub struct DashboardStats {
     pub streak_days: i64,
     pub notes_this_week: i64,
     pub tasks_completed_this_week: i64,
+    /// The seven days before this week's, for week-over-week deltas; only
+    /// [`refresh_dashboard_stats`] updates them
+    pub notes_previous_week: i64,
+    pub tasks_completed_previous_week: i64,
     pub last_updated: i64,
 }
 
@@ -43,6 +47,8 @@ pub fn init_materialized_views(conn: &Connection) -> Result<(), rusqlite::Error>
             streak_days INTEGER DEFAULT 0,
             notes_this_week INTEGER DEFAULT 0,
             tasks_completed_this_week INTEGER DEFAULT 0,
+            notes_previous_week INTEGER DEFAULT 0,
+            tasks_completed_previous_week INTEGER DEFAULT 0,
             last_updated INTEGER DEFAULT (strftime('%s', 'now'))
         )
         "#,
@@ -199,7 +205,8 @@ pub fn get_dashboard_stats(
         SELECT 
             space_id, total_notes, total_tasks, completed_tasks, pending_tasks,
             overdue_tasks, total_projects, active_habits, streak_days,
-            notes_this_week, tasks_completed_this_week, last_updated
+            notes_this_week, tasks_completed_this_week, notes_previous_week,
+            tasks_completed_previous_week, last_updated
         FROM dashboard_stats
         WHERE space_id = ?1
         "#,
@@ -217,7 +224,9 @@ pub fn get_dashboard_stats(
                 streak_days: row.get(8)?,
                 notes_this_week: row.get(9)?,
                 tasks_completed_this_week: row.get(10)?,
-                last_updated: row.get(11)?,
+                notes_previous_week: row.get(11)?,
+                tasks_completed_previous_week: row.get(12)?,
+                last_updated: row.get(13)?,
             })
         },
     );
@@ -242,6 +251,7 @@ pub fn refresh_dashboard_stats(
 ) -> Result<DashboardStats, rusqlite::Error> {
     let now = chrono::Utc::now().timestamp();
     let week_ago = now - (7 * 24 * 60 * 60);
+    let two_weeks_ago = week_ago - (7 * 24 * 60 * 60);
 
     // Calculate all stats from source tables
     let total_notes: i64 = conn.query_row(
@@ -256,6 +266,12 @@ pub fn refresh_dashboard_stats(
         |row| row.get(0),
     )?;
 
+    let notes_previous_week: i64 = conn.query_row(
+        "SELECT COUNT(*) FROM note WHERE space_id = ?1 AND created_at >= ?2 AND created_at < ?3",
+        params![space_id, two_weeks_ago, week_ago],
+        |row| row.get(0),
+    )?;
+
     let total_projects: i64 = conn.query_row(
         "SELECT COUNT(*) FROM project WHERE space_id = ?1",
         params![space_id],
@@ -299,6 +315,17 @@ pub fn refresh_dashboard_stats(
         |row| row.get(0),
     )?;
 
+    let tasks_completed_previous_week: i64 = conn.query_row(
+        r#"
+        SELECT COUNT(*)
+        FROM task t
+        JOIN project p ON t.project_id = p.id
+        WHERE p.space_id = ?1 AND t.status = 'done' AND t.updated_at >= ?2 AND t.updated_at < ?3
+        "#,
+        params![space_id, two_weeks_ago, week_ago],
+        |row| row.get(0),
+    )?;
+
     // Habit stats
     let active_habits: i64 = conn
         .query_row(
@@ -314,8 +341,9 @@ pub fn refresh_dashboard_stats(
         INSERT INTO dashboard_stats (
             space_id, total_notes, total_tasks, completed_tasks, pending_tasks,
             overdue_tasks, total_projects, active_habits, notes_this_week,
-            tasks_completed_this_week, last_updated
-        ) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11)
+            tasks_completed_this_week, notes_previous_week, tasks_completed_previous_week,
+            last_updated
+        ) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13)
         ON CONFLICT(space_id) DO UPDATE SET
             total_notes = excluded.total_notes,
             total_tasks = excluded.total_tasks,
@@ -326,6 +354,8 @@ pub fn refresh_dashboard_stats(
             active_habits = excluded.active_habits,
             notes_this_week = excluded.notes_this_week,
             tasks_completed_this_week = excluded.tasks_completed_this_week,
+            notes_previous_week = excluded.notes_previous_week,
+            tasks_completed_previous_week = excluded.tasks_completed_previous_week,
             last_updated = excluded.last_updated
         "#,
         params![
@@ -339,6 +369,8 @@ pub fn refresh_dashboard_stats(
             active_habits,
             notes_this_week,
             tasks_completed_this_week,
+            notes_previous_week,
+            tasks_completed_previous_week,
             now
         ],
     )?;
@@ -355,13 +387,15 @@ pub fn refresh_dashboard_stats(
         streak_days: 0, // Calculated separately
         notes_this_week,
         tasks_completed_this_week,
+        notes_previous_week,
+        tasks_completed_previous_week,
         last_updated: now,
     })
 }
 
-/// Refresh all dashboard stats (for maintenance)
+/// Refresh dashboard stats of all spaces that aren't archived (for maintenance)
 pub fn refresh_all_dashboard_stats(conn: &Connection) -> Result<usize, rusqlite::Error> {
-    let mut stmt = conn.prepare("SELECT DISTINCT id FROM space")?;
+    let mut stmt = conn.prepare("SELECT DISTINCT id FROM space WHERE archived_at IS NULL")?;
     let spaces: Vec<String> = stmt
         .query_map([], |row| row.get(0))?
         .filter_map(|r| r.ok())
@@ -386,7 +420,7 @@ mod tests {
         // Create minimal schema
         conn.execute_batch(
             r#"
-            CREATE TABLE space (id TEXT PRIMARY KEY, name TEXT);
+            CREATE TABLE space (id TEXT PRIMARY KEY, name TEXT, archived_at INTEGER);
             CREATE TABLE note (id TEXT PRIMARY KEY, space_id TEXT, title TEXT, created_at INTEGER);
             CREATE TABLE project (id TEXT PRIMARY KEY, space_id TEXT, name TEXT);
             CREATE TABLE task (id TEXT PRIMARY KEY, project_id TEXT, status TEXT, due_date INTEGER, updated_at INTEGER);
@@ -455,6 +489,30 @@ mod tests {
         assert_eq!(stats.total_notes, 2);
     }
 
+    #[test]
+    fn test_refresh_fills_previous_week() {
+        let conn = setup_test_db();
+
+        conn.execute(
+            "INSERT INTO space (id, name) VALUES ('space1', 'Test Space')",
+            [],
+        )
+        .unwrap();
+        for (id, days_ago) in [("n1", 1), ("n2", 2), ("n3", 9), ("n4", 20)] {
+            conn.execute(
+                "INSERT INTO note (id, space_id, title, created_at)
+                 VALUES (?1, 'space1', 'Note', strftime('%s', 'now') - ?2 * 86400)",
+                params![id, days_ago],
+            )
+            .unwrap();
+        }
+
+        refresh_dashboard_stats(&conn, "space1").unwrap();
+        let stats = get_dashboard_stats(&conn, "space1").unwrap();
+        assert_eq!(stats.notes_this_week, 2);
+        assert_eq!(stats.notes_previous_week, 1);
+    }
+
     #[test]
     fn test_note_insert_trigger() {
         let conn = setup_test_db();
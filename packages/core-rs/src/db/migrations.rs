@@ -1,37 +1,112 @@
-use rusqlite::{Connection, Result};
-
+//! Schema Migrations
+//!
+//! Every schema change is a numbered [`Migration`]. Applied versions are
+//! recorded in `schema_version` with when they ran and how long they took.
+//! [`migrate`] brings a vault up to date; [`migrate_plan`] lists what it would
+//! do and [`migrate_to`] moves to a specific version, reverting migrations if
+//! the target is older. Migrations that rebuild tables lossily can't be
+//! reverted, and a downgrade refuses to cross them.
+
+use rusqlite::{params, Connection, Transaction};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::time::Instant;
+use thiserror::Error;
+
+use crate::db::vault_backup::snapshot_database;
 use crate::db::DbError;
 
-/// Run database migrations to update the schema to the latest version.
-/// This function is idempotent and checks the current version before applying changes.
-pub fn migrate(conn: &mut Connection) -> Result<(), DbError> {
-    log::info!("[db] Starting migration");
-    let tx = conn.transaction()?;
+#[derive(Error, Debug)]
+pub enum MigrationError {
+    #[error("Database error: {0}")]
+    Database(#[from] DbError),
+    #[error("Rusqlite error: {0}")]
+    Rusqlite(#[from] rusqlite::Error),
+    #[error("Unknown schema version: {0}")]
+    UnknownVersion(i64),
+    #[error(
+        "This vault was created by a newer version of the app (schema v{vault_version}, \
+         this version supports up to v{supported}). Please upgrade to open it."
+    )]
+    VaultTooNew { vault_version: i64, supported: i64 },
+    #[error("Migration v{version} can't be reverted: it {reason}")]
+    Irreversible { version: i64, reason: &'static str },
+    #[error("Could not back up the vault before migrating: {0}")]
+    Backup(rusqlite::Error),
+}
 
-    // Create schema_version table if it doesn't exist
-    tx.execute_batch(
-        "
-        CREATE TABLE IF NOT EXISTS schema_version (
-            version INTEGER PRIMARY KEY
-        );
-        ",
-    )?;
+/// Steps a migration can't express in SQL, run after its `up` script
+type MigrationHook = fn(&Connection) -> Result<(), DbError>;
 
-    let current_version: i64 = {
-        let mut stmt = tx.prepare("SELECT MAX(version) FROM schema_version")?;
-        let mut rows = stmt.query([])?;
-        match rows.next()? {
-            Some(row) => row.get(0).unwrap_or(0),
-            None => 0,
-        }
-    };
-    log::info!("[db] Current schema version: {}", current_version);
+/// How a migration is reverted
+#[derive(Debug, Clone, Copy)]
+enum Down {
+    Sql(&'static str),
+    /// The migration throws away data a revert would need; the reason
+    /// completes "it ..."
+    Irreversible(&'static str),
+}
 
-    // Version 1: Initial Schema
-    if current_version < 1 {
-        log::info!("[db] Applying migration v1: Initial Schema");
-        tx.execute_batch(
-            "
+struct Migration {
+    version: i64,
+    description: &'static str,
+    up: &'static str,
+    after_up: Option<MigrationHook>,
+    down: Down,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MigrationDirection {
+    Up,
+    Down,
+}
+
+/// One step of a migration plan
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PlannedMigration {
+    pub version: i64,
+    pub description: String,
+    pub direction: MigrationDirection,
+    /// Whether the migration can be reverted
+    pub reversible: bool,
+}
+
+/// A row of `schema_version`. Versions applied before the timing columns
+/// existed have no `applied_at` or `duration_ms`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AppliedMigration {
+    pub version: i64,
+    pub applied_at: Option<i64>,
+    pub duration_ms: Option<i64>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MigrationReport {
+    pub from_version: i64,
+    pub to_version: i64,
+    pub steps: Vec<PlannedMigration>,
+    /// Snapshot taken before the first step; `None` when nothing ran
+    pub backup_path: Option<PathBuf>,
+}
+
+fn backfill_graph_history(conn: &Connection) -> Result<(), DbError> {
+    crate::temporal_graph::backfill_graph_history(conn).map_err(|e| DbError::Message(e.to_string()))
+}
+
+fn backfill_note_counts(conn: &Connection) -> Result<(), DbError> {
+    crate::note_stats::backfill_note_counts(conn)
+}
+
+fn import_free_form_health_goals(conn: &Connection) -> Result<(), DbError> {
+    crate::health::goals::import_free_form_goals(conn)
+}
+
+static MIGRATIONS: &[Migration] = &[
+    Migration {
+        version: 1,
+        description: "Initial Schema",
+        up: "
             CREATE TABLE space(
               id TEXT PRIMARY KEY, name TEXT NOT NULL, icon TEXT,
               enabled_modes_json TEXT NOT NULL DEFAULT '[]',
@@ -130,15 +205,14 @@ pub fn migrate(conn: &mut Connection) -> Result<(), DbError> {
               title, content_md, note_id UNINDEXED,
               tokenize='porter unicode61 remove_diacritics 2'
             );
-
-            INSERT INTO schema_version (version) VALUES (1);
             ",
-        )?;
-    }
-    if current_version < 2 {
-        log::info!("[db] Migrating to version 2");
-        tx.execute_batch(
-            "
+        after_up: None,
+        down: Down::Irreversible("creates the base schema"),
+    },
+    Migration {
+        version: 2,
+        description: "Knowledge Cards",
+        up: "
             CREATE TABLE knowledge_card (
                 id TEXT PRIMARY KEY,
                 note_id TEXT NOT NULL REFERENCES note(id),
@@ -162,45 +236,45 @@ pub fn migrate(conn: &mut Connection) -> Result<(), DbError> {
                 difficulty REAL NOT NULL,
                 lapses INTEGER NOT NULL
             );
-
-            INSERT INTO schema_version (version) VALUES (2);
             ",
-        )?;
-    }
-    if current_version < 3 {
-        log::info!("[db] Migrating to version 3");
-        tx.execute_batch(
-            "
+        after_up: None,
+        down: Down::Sql("
+            DROP TABLE review_log;
+            DROP TABLE knowledge_card;
+            "),
+    },
+    Migration {
+        version: 3,
+        description: "Space People",
+        up: "
             CREATE TABLE space_people (
                 space_id TEXT NOT NULL REFERENCES space(id),
                 person_id TEXT NOT NULL REFERENCES person(id),
                 role TEXT NOT NULL CHECK(role IN('owner', 'admin', 'member', 'guest')),
                 PRIMARY KEY(space_id, person_id)
             );
-
-            INSERT INTO schema_version (version) VALUES (3);
             ",
-        )?;
-    }
-    if current_version < 4 {
-        log::info!("[db] Migrating to version 4");
-        tx.execute_batch(
-            "
+        after_up: None,
+        down: Down::Sql("DROP TABLE space_people;"),
+    },
+    Migration {
+        version: 4,
+        description: "Form Templates",
+        up: "
             CREATE TABLE form_template (
                 id TEXT PRIMARY KEY,
                 space_id TEXT NOT NULL REFERENCES space(id),
                 name TEXT NOT NULL,
                 fields_json TEXT NOT NULL
             );
-
-            INSERT INTO schema_version (version) VALUES (4);
             ",
-        )?;
-    }
-    if current_version < 5 {
-        log::info!("[db] Migrating to version 5");
-        tx.execute_batch(
-            "
+        after_up: None,
+        down: Down::Sql("DROP TABLE form_template;"),
+    },
+    Migration {
+        version: 5,
+        description: "Time Tracking",
+        up: "
             CREATE TABLE time_entry (
                 id TEXT PRIMARY KEY,
                 space_id TEXT NOT NULL REFERENCES space(id),
@@ -223,15 +297,14 @@ pub fn migrate(conn: &mut Connection) -> Result<(), DbError> {
             CREATE INDEX time_entry_project ON time_entry(project_id) WHERE project_id IS NOT NULL;
             CREATE INDEX time_entry_started ON time_entry(started_at DESC);
             CREATE INDEX time_entry_running ON time_entry(is_running) WHERE is_running = 1;
-
-            INSERT INTO schema_version (version) VALUES (5);
             ",
-        )?;
-    }
-    if current_version < 6 {
-        log::info!("[db] Migrating to version 6 - Social Media Suite");
-        tx.execute_batch(
-            "
+        after_up: None,
+        down: Down::Sql("DROP TABLE time_entry;"),
+    },
+    Migration {
+        version: 6,
+        description: "Social Media Suite",
+        up: "
             -- Social Media Accounts
             CREATE TABLE social_account (
                 id TEXT PRIMARY KEY,
@@ -381,16 +454,25 @@ pub fn migrate(conn: &mut Connection) -> Result<(), DbError> {
             CREATE INDEX idx_social_account_space ON social_account(space_id, enabled);
             CREATE INDEX idx_social_category_space ON social_category(space_id);
             CREATE INDEX idx_social_auto_rule_category ON social_auto_rule(category_id, priority DESC);
-
-            INSERT INTO schema_version (version) VALUES (6);
             ",
-        )?;
-    }
-
-    if current_version < 7 {
-        log::info!("[db] Migrating to version 7 - Authentication System");
-        tx.execute_batch(
-            "
+        after_up: None,
+        down: Down::Sql("
+            DROP TABLE social_automation_rule;
+            DROP TABLE social_focus_mode;
+            DROP TABLE social_auto_rule;
+            DROP TABLE social_webview_session;
+            DROP TABLE social_sync_history;
+            DROP TABLE social_post_fts;
+            DROP TABLE social_post_category;
+            DROP TABLE social_category;
+            DROP TABLE social_post;
+            DROP TABLE social_account;
+            "),
+    },
+    Migration {
+        version: 7,
+        description: "Authentication System",
+        up: "
             -- Users table for authentication
             CREATE TABLE IF NOT EXISTS users (
                 id TEXT PRIMARY KEY,
@@ -420,16 +502,17 @@ pub fn migrate(conn: &mut Connection) -> Result<(), DbError> {
             CREATE INDEX IF NOT EXISTS idx_sessions_token ON sessions(token);
             CREATE INDEX IF NOT EXISTS idx_sessions_expires ON sessions(expires_at);
             CREATE INDEX IF NOT EXISTS idx_sessions_user ON sessions(user_id);
-
-            INSERT INTO schema_version (version) VALUES (7);
             ",
-        )?;
-    }
-
-    if current_version < 8 {
-        log::info!("[db] Migrating to version 8 - Settings");
-        tx.execute_batch(
-            "
+        after_up: None,
+        down: Down::Sql("
+            DROP TABLE sessions;
+            DROP TABLE users;
+            "),
+    },
+    Migration {
+        version: 8,
+        description: "Settings",
+        up: "
             -- Application Settings
             CREATE TABLE IF NOT EXISTS settings (
                 key TEXT PRIMARY KEY,
@@ -446,90 +529,89 @@ pub fn migrate(conn: &mut Connection) -> Result<(), DbError> {
             INSERT OR IGNORE INTO settings (key, value, description, created_at, updated_at)
             VALUES ('sync_port', '8765', 'Port for device-to-device sync',
                     strftime('%s', 'now'), strftime('%s', 'now'));
-
-            INSERT INTO schema_version (version) VALUES (8);
             ",
-        )?;
-    }
-
-    if current_version < 9 {
-        log::info!("[db] Migrating to version 9 - Update task.completed_at to use NULL");
-        tx.execute_batch(
-                "
-                -- Step 1: Create a new table with the desired schema
-                CREATE TABLE task_new (
-                  id TEXT PRIMARY KEY, space_id TEXT NOT NULL REFERENCES space(id),
-                  note_id TEXT REFERENCES note(id), project_id TEXT REFERENCES project(id),
-                  parent_task_id TEXT REFERENCES task(id),
-                  title TEXT NOT NULL,
-                  description TEXT,
-                  status TEXT NOT NULL CHECK(status IN('inbox','next','in_progress','waiting','done','cancelled')),
-                  due_at INTEGER, start_at INTEGER, completed_at INTEGER DEFAULT NULL,
-                  priority INTEGER CHECK(priority BETWEEN 1 AND 4),
-                  estimate_minutes INTEGER, recur_rule TEXT,
-                  context TEXT, area TEXT
-                );
-
-                -- Step 2: Copy data from the old table to the new table, converting 0 to NULL
-                INSERT INTO task_new (id, space_id, note_id, project_id, parent_task_id, title, description, status, due_at, start_at, completed_at, priority, estimate_minutes, recur_rule, context, area)
-                SELECT id, space_id, note_id, project_id, parent_task_id, title, description, status, due_at, start_at,
-                       NULLIF(completed_at, 0),
-                       priority, estimate_minutes, recur_rule, context, area
-                FROM task;
-
-                -- Step 3: Drop the old table
-                DROP TABLE task;
-
-                -- Step 4: Rename the new table to the original table name
-                ALTER TABLE task_new RENAME TO task;
+        after_up: None,
+        down: Down::Sql("DROP TABLE settings;"),
+    },
+    Migration {
+        version: 9,
+        description: "Update task.completed_at to use NULL",
+        up: "
+            -- Step 1: Create a new table with the desired schema
+            CREATE TABLE task_new (
+              id TEXT PRIMARY KEY, space_id TEXT NOT NULL REFERENCES space(id),
+              note_id TEXT REFERENCES note(id), project_id TEXT REFERENCES project(id),
+              parent_task_id TEXT REFERENCES task(id),
+              title TEXT NOT NULL,
+              description TEXT,
+              status TEXT NOT NULL CHECK(status IN('inbox','next','in_progress','waiting','done','cancelled')),
+              due_at INTEGER, start_at INTEGER, completed_at INTEGER DEFAULT NULL,
+              priority INTEGER CHECK(priority BETWEEN 1 AND 4),
+              estimate_minutes INTEGER, recur_rule TEXT,
+              context TEXT, area TEXT
+            );
 
-                -- Recreate indexes on the new table
-                CREATE INDEX task_due   ON task(due_at)   WHERE status IN('inbox','next','in_progress','waiting');
-                CREATE INDEX task_start ON task(start_at);
+            -- Step 2: Copy data from the old table to the new table, converting 0 to NULL
+            INSERT INTO task_new (id, space_id, note_id, project_id, parent_task_id, title, description, status, due_at, start_at, completed_at, priority, estimate_minutes, recur_rule, context, area)
+            SELECT id, space_id, note_id, project_id, parent_task_id, title, description, status, due_at, start_at,
+                   NULLIF(completed_at, 0),
+                   priority, estimate_minutes, recur_rule, context, area
+            FROM task;
 
-                INSERT INTO schema_version (version) VALUES (9);
-                "
-            )?;
-    }
+            -- Step 3: Drop the old table
+            DROP TABLE task;
 
-    if current_version < 10 {
-        log::info!("[db] Migrating to version 10 - Sync Tables");
-        tx.execute_batch(
-            "
-                CREATE TABLE sync_history (
-                    id TEXT PRIMARY KEY,
-                    device_id TEXT NOT NULL,
-                    space_id TEXT NOT NULL REFERENCES space(id),
-                    sync_time INTEGER NOT NULL,
-                    direction TEXT NOT NULL,
-                    entities_pushed INTEGER NOT NULL,
-                    entities_pulled INTEGER NOT NULL,
-                    conflicts_detected INTEGER NOT NULL,
-                    success INTEGER NOT NULL,
-                    error_message TEXT
-                );
+            -- Step 4: Rename the new table to the original table name
+            ALTER TABLE task_new RENAME TO task;
 
-                CREATE TABLE sync_conflict (
-                    id TEXT PRIMARY KEY,
-                    entity_type TEXT NOT NULL,
-                    entity_id TEXT NOT NULL,
-                    local_version BLOB NOT NULL,
-                    remote_version BLOB NOT NULL,
-                    conflict_type TEXT NOT NULL,
-                    detected_at INTEGER NOT NULL,
-                    resolved INTEGER NOT NULL,
-                    resolved_at INTEGER,
-                    device_id TEXT NOT NULL,
-                    space_id TEXT NOT NULL REFERENCES space(id)
-                );
-                ",
-        )?;
-    }
+            -- Recreate indexes on the new table
+            CREATE INDEX task_due   ON task(due_at)   WHERE status IN('inbox','next','in_progress','waiting');
+            CREATE INDEX task_start ON task(start_at);
+            ",
+        after_up: None,
+        down: Down::Irreversible("rebuilds the task table and turns completed_at = 0 into NULL"),
+    },
+    Migration {
+        version: 10,
+        description: "Sync Tables",
+        up: "
+            CREATE TABLE sync_history (
+                id TEXT PRIMARY KEY,
+                device_id TEXT NOT NULL,
+                space_id TEXT NOT NULL REFERENCES space(id),
+                sync_time INTEGER NOT NULL,
+                direction TEXT NOT NULL,
+                entities_pushed INTEGER NOT NULL,
+                entities_pulled INTEGER NOT NULL,
+                conflicts_detected INTEGER NOT NULL,
+                success INTEGER NOT NULL,
+                error_message TEXT
+            );
 
-    if current_version < 11 {
-        log::info!("[db] Migrating to version 11 - Health, Music, and Calendar");
-        tx.execute_batch(
-            "
+            CREATE TABLE sync_conflict (
+                id TEXT PRIMARY KEY,
+                entity_type TEXT NOT NULL,
+                entity_id TEXT NOT NULL,
+                local_version BLOB NOT NULL,
+                remote_version BLOB NOT NULL,
+                conflict_type TEXT NOT NULL,
+                detected_at INTEGER NOT NULL,
+                resolved INTEGER NOT NULL,
+                resolved_at INTEGER,
+                device_id TEXT NOT NULL,
+                space_id TEXT NOT NULL REFERENCES space(id)
+            );
+            ",
+        after_up: None,
+        down: Down::Sql("
+            DROP TABLE sync_conflict;
+            DROP TABLE sync_history;
+            "),
+    },
+    Migration {
+        version: 11,
+        description: "Health, Music, and Calendar",
+        up: "
             -- Health Metrics
             CREATE TABLE IF NOT EXISTS health_metric (
                 id TEXT PRIMARY KEY,
@@ -608,16 +690,20 @@ pub fn migrate(conn: &mut Connection) -> Result<(), DbError> {
                 synced_at INTEGER NOT NULL DEFAULT 0
             );
             CREATE INDEX IF NOT EXISTS idx_calendar_event_start ON calendar_event(start_time);
-
-            INSERT INTO schema_version (version) VALUES (11);
             ",
-        )?;
-    }
-
-    if current_version < 12 {
-        log::info!("[db] Migrating to version 12 - Goals and Habits");
-        tx.execute_batch(
-            "
+        after_up: None,
+        down: Down::Sql("
+            DROP TABLE calendar_event;
+            DROP TABLE playlist_track;
+            DROP TABLE playlist;
+            DROP TABLE track;
+            DROP TABLE health_metric;
+            "),
+    },
+    Migration {
+        version: 12,
+        description: "Goals and Habits",
+        up: "
             -- Goals
             CREATE TABLE IF NOT EXISTS goal (
                 id TEXT PRIMARY KEY,
@@ -659,16 +745,18 @@ pub fn migrate(conn: &mut Connection) -> Result<(), DbError> {
                 completed_at INTEGER NOT NULL
             );
             CREATE INDEX IF NOT EXISTS idx_habit_log_habit ON habit_log(habit_id, completed_at);
-
-            INSERT INTO schema_version (version) VALUES (12);
             ",
-        )?;
-    }
-
-    if current_version < 13 {
-        log::info!("[db] Migrating to version 13 - LLM Cache (Official)");
-        tx.execute_batch(
-            "
+        after_up: None,
+        down: Down::Sql("
+            DROP TABLE habit_log;
+            DROP TABLE habit;
+            DROP TABLE goal;
+            "),
+    },
+    Migration {
+        version: 13,
+        description: "LLM Cache (Official)",
+        up: "
             CREATE TABLE IF NOT EXISTS llm_cache (
                 cache_key TEXT PRIMARY KEY,
                 request_json TEXT NOT NULL,
@@ -680,16 +768,14 @@ pub fn migrate(conn: &mut Connection) -> Result<(), DbError> {
                 access_count INTEGER NOT NULL DEFAULT 1
             );
             CREATE INDEX IF NOT EXISTS idx_llm_cache_accessed ON llm_cache(last_accessed);
-
-            INSERT INTO schema_version (version) VALUES (13);
             ",
-        )?;
-    }
-
-    if current_version < 14 {
-        log::info!("[db] Migrating to version 14 - Audit Logs");
-        tx.execute_batch(
-            "
+        after_up: None,
+        down: Down::Sql("DROP TABLE llm_cache;"),
+    },
+    Migration {
+        version: 14,
+        description: "Audit Logs",
+        up: "
             CREATE TABLE IF NOT EXISTS audit_log (
                 id TEXT PRIMARY KEY,
                 user_id TEXT,
@@ -704,32 +790,31 @@ pub fn migrate(conn: &mut Connection) -> Result<(), DbError> {
             CREATE INDEX IF NOT EXISTS idx_audit_log_created ON audit_log(created_at DESC);
             CREATE INDEX IF NOT EXISTS idx_audit_log_entity ON audit_log(entity_type, entity_id);
             CREATE INDEX IF NOT EXISTS idx_audit_log_user ON audit_log(user_id);
-
-            INSERT INTO schema_version (version) VALUES (14);
             ",
-        )?;
-    }
-
-    if current_version < 15 {
-        log::info!("[db] Migrating to version 15 - Add updated_at to Task/Project");
-        tx.execute_batch(
-            "
+        after_up: None,
+        down: Down::Sql("DROP TABLE audit_log;"),
+    },
+    Migration {
+        version: 15,
+        description: "Add updated_at to Task/Project",
+        up: "
             ALTER TABLE task ADD COLUMN updated_at INTEGER NOT NULL DEFAULT 0;
             ALTER TABLE project ADD COLUMN updated_at INTEGER NOT NULL DEFAULT 0;
 
             -- Update existing records to set updated_at to now (approx) or 0
             UPDATE task SET updated_at = strftime('%s', 'now');
             UPDATE project SET updated_at = strftime('%s', 'now');
-
-            INSERT INTO schema_version (version) VALUES (15);
             ",
-        )?;
-    }
-
-    if current_version < 16 {
-        log::info!("[db] Migrating to version 16 - Insights");
-        tx.execute_batch(
-            "
+        after_up: None,
+        down: Down::Sql("
+            ALTER TABLE project DROP COLUMN updated_at;
+            ALTER TABLE task DROP COLUMN updated_at;
+            "),
+    },
+    Migration {
+        version: 16,
+        description: "Insights",
+        up: "
             CREATE TABLE IF NOT EXISTS insight (
                 id TEXT PRIMARY KEY,
                 space_id TEXT NOT NULL REFERENCES space(id),
@@ -756,32 +841,34 @@ pub fn migrate(conn: &mut Connection) -> Result<(), DbError> {
             );
             CREATE INDEX IF NOT EXISTS idx_entity_sync_log_entity ON entity_sync_log(entity_id);
             CREATE INDEX IF NOT EXISTS idx_entity_sync_log_entity_time ON entity_sync_log(entity_id, synced_at);
-
-            INSERT INTO schema_version (version) VALUES (16);
             ",
-        )?;
-    }
-
-    if current_version < 17 {
-        log::info!("[db] Migrating to version 17 - Generated Columns");
-        tx.execute_batch(
-            "
+        after_up: None,
+        down: Down::Sql("
+            DROP TABLE entity_sync_log;
+            DROP TABLE insight;
+            "),
+    },
+    Migration {
+        version: 17,
+        description: "Generated Columns",
+        up: "
             -- Add generated columns for performance optimization
             -- Only adding completed_date as created_at is not present in task table
             ALTER TABLE task ADD COLUMN completed_date TEXT
             GENERATED ALWAYS AS (date(completed_at, 'unixepoch')) STORED;
 
             CREATE INDEX idx_task_completed_date ON task(completed_date);
-
-            INSERT INTO schema_version (version) VALUES (17);
             ",
-        )?;
-    }
-
-    if current_version < 18 {
-        log::info!("[db] Migrating to version 18 - Social Archive");
-        tx.execute_batch(
-            "
+        after_up: None,
+        down: Down::Sql("
+            DROP INDEX idx_task_completed_date;
+            ALTER TABLE task DROP COLUMN completed_date;
+            "),
+    },
+    Migration {
+        version: 18,
+        description: "Social Archive",
+        up: "
             -- Create archive table for old social posts to reduce bloat
             -- Stores essential metadata but drops heavy raw_json
             CREATE TABLE IF NOT EXISTS social_post_archive (
@@ -794,28 +881,29 @@ pub fn migrate(conn: &mut Connection) -> Result<(), DbError> {
                 archived_at INTEGER NOT NULL
             );
             CREATE INDEX IF NOT EXISTS idx_social_archive_timestamp ON social_post_archive(timestamp);
-
-            INSERT INTO schema_version (version) VALUES (18);
             ",
-        )?;
-    }
-
-    if current_version < 19 {
-        log::info!("[db] Migrating to version 19 - Optimization Indexes");
-        tx.execute_batch(
-            "
+        after_up: None,
+        down: Down::Sql("DROP TABLE social_post_archive;"),
+    },
+    Migration {
+        version: 19,
+        description: "Optimization Indexes",
+        up: "
             CREATE INDEX IF NOT EXISTS idx_saved_search_scope ON saved_search(scope);
             CREATE INDEX IF NOT EXISTS idx_task_priority ON task(priority);
             CREATE INDEX IF NOT EXISTS idx_project_status ON project(status);
-            INSERT INTO schema_version (version) VALUES (19);
             ",
-        )?;
-    }
-
-    if current_version < 20 {
-        log::info!("[db] Migrating to version 20 - FTS for Tasks and Projects (Optimized)");
-        tx.execute_batch(
-            "
+        after_up: None,
+        down: Down::Sql("
+            DROP INDEX idx_project_status;
+            DROP INDEX idx_task_priority;
+            DROP INDEX idx_saved_search_scope;
+            "),
+    },
+    Migration {
+        version: 20,
+        description: "FTS for Tasks and Projects (Optimized)",
+        up: "
             -- FTS for Tasks (Standard FTS table to avoid external content corruption issues)
             CREATE VIRTUAL TABLE IF NOT EXISTS fts_task USING fts5(
                 title,
@@ -867,47 +955,1996 @@ pub fn migrate(conn: &mut Connection) -> Result<(), DbError> {
                 INSERT INTO fts_project(rowid, title, goal_outcome)
                 VALUES (new.rowid, new.title, COALESCE(new.goal_outcome, ''));
             END;
-
-            INSERT INTO schema_version (version) VALUES (20);
             ",
-        )?;
-    }
-
-    if current_version < 21 {
-        log::info!("[db] Migrating to version 21 - Performance Optimization");
-        tx.execute_batch(
-            "
+        after_up: None,
+        down: Down::Sql("
+            DROP TRIGGER project_au;
+            DROP TRIGGER project_ad;
+            DROP TRIGGER project_ai;
+            DROP TABLE fts_project;
+            DROP TRIGGER task_au;
+            DROP TRIGGER task_ad;
+            DROP TRIGGER task_ai;
+            DROP TABLE fts_task;
+            "),
+    },
+    Migration {
+        version: 21,
+        description: "Performance Optimization",
+        up: "
             -- Index to optimize sync delta generation (filtering notes by space and modification time)
             CREATE INDEX IF NOT EXISTS idx_note_space_mod ON note(space_id, modified_at);
 
             -- Index for faster note retrieval by trashed status in a space
             CREATE INDEX IF NOT EXISTS idx_note_space_trashed ON note(space_id, is_trashed);
-
-            INSERT INTO schema_version (version) VALUES (21);
             ",
-        )?;
-    }
-
-    if current_version < 22 {
-        log::info!("[db] Migrating to version 22 - Final Optimization");
-        tx.execute_batch(
-            "
+        after_up: None,
+        down: Down::Sql("
+            DROP INDEX idx_note_space_trashed;
+            DROP INDEX idx_note_space_mod;
+            "),
+    },
+    Migration {
+        version: 22,
+        description: "Final Optimization",
+        up: "
             CREATE INDEX IF NOT EXISTS idx_sync_conflict_entity ON sync_conflict(entity_id);
 
             -- Optimize FTS indexes
             INSERT INTO fts_note(fts_note) VALUES('optimize');
             INSERT INTO fts_task(fts_task) VALUES('optimize');
             INSERT INTO fts_project(fts_project) VALUES('optimize');
+            ",
+        after_up: None,
+        down: Down::Sql("DROP INDEX idx_sync_conflict_entity;"),
+    },
+    Migration {
+        version: 23,
+        description: "Attachments",
+        up: "
+            -- Registry of stored blobs (id is the blob manifest hash)
+            CREATE TABLE IF NOT EXISTS blob (
+                id TEXT PRIMARY KEY,
+                size_bytes INTEGER NOT NULL DEFAULT 0,
+                mime_type TEXT,
+                created_at INTEGER NOT NULL
+            );
 
-            INSERT INTO schema_version (version) VALUES (22);
+            CREATE TABLE IF NOT EXISTS note_attachment (
+                note_id TEXT NOT NULL REFERENCES note(id) ON DELETE CASCADE,
+                blob_id TEXT NOT NULL REFERENCES blob(id) ON DELETE CASCADE,
+                filename TEXT,
+                created_at INTEGER NOT NULL,
+                PRIMARY KEY(note_id, blob_id)
+            );
+            CREATE INDEX IF NOT EXISTS idx_note_attachment_blob ON note_attachment(blob_id);
             ",
-        )?;
-    }
+        after_up: None,
+        down: Down::Sql("
+            DROP TABLE note_attachment;
+            DROP TABLE blob;
+            "),
+    },
+    Migration {
+        version: 24,
+        description: "Blob References",
+        up: "
+            -- Content hash of the plaintext, used to deduplicate identical uploads
+            ALTER TABLE blob ADD COLUMN content_hash TEXT;
+            CREATE UNIQUE INDEX IF NOT EXISTS idx_blob_content_hash ON blob(content_hash);
+
+            -- Everything that keeps a blob alive (notes, social posts, ...)
+            CREATE TABLE IF NOT EXISTS blob_ref (
+                blob_id TEXT NOT NULL REFERENCES blob(id) ON DELETE CASCADE,
+                owner_type TEXT NOT NULL,
+                owner_id TEXT NOT NULL,
+                created_at INTEGER NOT NULL,
+                PRIMARY KEY(blob_id, owner_type, owner_id)
+            );
+            CREATE INDEX IF NOT EXISTS idx_blob_ref_owner ON blob_ref(owner_type, owner_id);
 
-    // Run Personal Modes Initialization (Idempotent)
-    crate::personal_modes::init_personal_modes_tables(&tx)?;
+            -- Blobs removed by GC whose encrypted objects still need to be swept from disk
+            CREATE TABLE IF NOT EXISTS blob_pending_sweep (
+                blob_id TEXT PRIMARY KEY,
+                deleted_at INTEGER NOT NULL
+            );
 
-    tx.commit()?;
-    log::info!("[db] Migration finished");
+            INSERT OR IGNORE INTO blob_ref (blob_id, owner_type, owner_id, created_at)
+            SELECT blob_id, 'note', note_id, created_at FROM note_attachment;
+
+            CREATE TRIGGER note_attachment_ref_ai AFTER INSERT ON note_attachment BEGIN
+                INSERT OR IGNORE INTO blob_ref (blob_id, owner_type, owner_id, created_at)
+                VALUES (new.blob_id, 'note', new.note_id, new.created_at);
+            END;
+
+            CREATE TRIGGER note_attachment_ref_ad AFTER DELETE ON note_attachment BEGIN
+                DELETE FROM blob_ref
+                WHERE blob_id = old.blob_id AND owner_type = 'note' AND owner_id = old.note_id;
+            END;
+
+            CREATE TRIGGER social_post_ref_ad AFTER DELETE ON social_post BEGIN
+                DELETE FROM blob_ref WHERE owner_type = 'social_post' AND owner_id = old.id;
+            END;
+            ",
+        after_up: None,
+        down: Down::Sql("
+            DROP TRIGGER social_post_ref_ad;
+            DROP TRIGGER note_attachment_ref_ad;
+            DROP TRIGGER note_attachment_ref_ai;
+            DROP TABLE blob_pending_sweep;
+            DROP TABLE blob_ref;
+            DROP INDEX idx_blob_content_hash;
+            ALTER TABLE blob DROP COLUMN content_hash;
+            "),
+    },
+    Migration {
+        version: 25,
+        description: "RAG Change Tracking",
+        up: "
+            -- Notes whose RAG chunks are stale; generation bumps on every re-mark
+            CREATE TABLE IF NOT EXISTS rag_dirty_note (
+                note_id TEXT PRIMARY KEY,
+                generation INTEGER NOT NULL DEFAULT 1,
+                marked_at INTEGER NOT NULL
+            );
+            CREATE TABLE IF NOT EXISTS rag_index_state (
+                key TEXT PRIMARY KEY,
+                value TEXT NOT NULL
+            );
+
+            -- Nothing has been indexed incrementally yet
+            INSERT OR IGNORE INTO rag_dirty_note (note_id, marked_at)
+            SELECT id, strftime('%s', 'now') FROM note WHERE is_trashed = 0;
+            ",
+        after_up: None,
+        down: Down::Sql("
+            DROP TABLE rag_index_state;
+            DROP TABLE rag_dirty_note;
+            "),
+    },
+    Migration {
+        version: 26,
+        description: "Backlink Lookup Index",
+        up: "
+            -- Backlink queries filter on the link target
+            CREATE INDEX IF NOT EXISTS idx_link_target ON link(target_note_id);
+            ",
+        after_up: None,
+        down: Down::Sql("DROP INDEX idx_link_target;"),
+    },
+    Migration {
+        version: 27,
+        description: "Temporal Graph Change Log",
+        up: "
+            -- Every node/edge change in the note graph, in graph time
+            CREATE TABLE IF NOT EXISTS graph_change_log (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                space_id TEXT NOT NULL,
+                timestamp INTEGER NOT NULL,
+                change_type TEXT NOT NULL, -- node_added, node_removed, edge_added, edge_removed
+                node_id TEXT NOT NULL,
+                target_id TEXT
+            );
+            CREATE INDEX IF NOT EXISTS idx_graph_change_log_space
+                ON graph_change_log(space_id, timestamp);
+
+            -- Cached evolution summaries; dropped when an earlier change is logged
+            CREATE TABLE IF NOT EXISTS graph_summary (
+                space_id TEXT NOT NULL,
+                timestamp INTEGER NOT NULL,
+                metrics_json TEXT NOT NULL,
+                top_nodes_json TEXT NOT NULL,
+                computed_at INTEGER NOT NULL,
+                PRIMARY KEY (space_id, timestamp)
+            );
+
+            -- Per-note metrics kept current by triggers
+            CREATE TABLE IF NOT EXISTS graph_node_metric (
+                note_id TEXT PRIMARY KEY,
+                space_id TEXT NOT NULL,
+                created_at INTEGER NOT NULL,
+                content_length INTEGER NOT NULL DEFAULT 0,
+                degree INTEGER NOT NULL DEFAULT 0
+            );
+            CREATE INDEX IF NOT EXISTS idx_graph_node_metric_length
+                ON graph_node_metric(space_id, content_length);
+
+            CREATE TRIGGER graph_change_log_ai AFTER INSERT ON graph_change_log BEGIN
+                DELETE FROM graph_summary
+                WHERE space_id = new.space_id AND timestamp >= new.timestamp;
+            END;
+
+            CREATE TRIGGER graph_note_ai AFTER INSERT ON note BEGIN
+                INSERT INTO graph_change_log (space_id, timestamp, change_type, node_id)
+                SELECT new.space_id, new.created_at, 'node_added', new.id
+                WHERE new.is_trashed = 0;
+                INSERT OR REPLACE INTO graph_node_metric
+                    (note_id, space_id, created_at, content_length, degree)
+                VALUES (new.id, new.space_id, new.created_at, LENGTH(new.content_md),
+                    (SELECT COUNT(*) FROM link
+                     WHERE source_note_id = new.id OR target_note_id = new.id));
+            END;
+            CREATE TRIGGER graph_note_au_content AFTER UPDATE OF content_md, space_id ON note BEGIN
+                UPDATE graph_node_metric
+                SET content_length = LENGTH(new.content_md), space_id = new.space_id
+                WHERE note_id = new.id;
+            END;
+            CREATE TRIGGER graph_note_au_trash AFTER UPDATE OF is_trashed ON note
+            WHEN old.is_trashed != new.is_trashed BEGIN
+                INSERT INTO graph_change_log (space_id, timestamp, change_type, node_id)
+                VALUES (new.space_id, CAST(strftime('%s', 'now') AS INTEGER),
+                    CASE WHEN new.is_trashed THEN 'node_removed' ELSE 'node_added' END, new.id);
+            END;
+            CREATE TRIGGER graph_note_ad AFTER DELETE ON note BEGIN
+                INSERT INTO graph_change_log (space_id, timestamp, change_type, node_id)
+                SELECT old.space_id, CAST(strftime('%s', 'now') AS INTEGER), 'node_removed', old.id
+                WHERE old.is_trashed = 0;
+                DELETE FROM graph_node_metric WHERE note_id = old.id;
+            END;
+
+            -- Link changes take the source note's modification time
+            CREATE TRIGGER graph_link_ai AFTER INSERT ON link BEGIN
+                INSERT INTO graph_change_log (space_id, timestamp, change_type, node_id, target_id)
+                SELECT n.space_id, n.modified_at, 'edge_added', new.source_note_id, new.target_note_id
+                FROM note n WHERE n.id = new.source_note_id;
+                UPDATE graph_node_metric SET degree = degree + 1
+                WHERE note_id IN (new.source_note_id, new.target_note_id);
+            END;
+            CREATE TRIGGER graph_link_ad AFTER DELETE ON link BEGIN
+                INSERT INTO graph_change_log (space_id, timestamp, change_type, node_id, target_id)
+                SELECT n.space_id, n.modified_at, 'edge_removed', old.source_note_id, old.target_note_id
+                FROM note n WHERE n.id = old.source_note_id;
+                UPDATE graph_node_metric SET degree = degree - 1
+                WHERE note_id IN (old.source_note_id, old.target_note_id);
+            END;
+            ",
+        after_up: Some(backfill_graph_history),
+        down: Down::Sql("
+            DROP TRIGGER graph_link_ad;
+            DROP TRIGGER graph_link_ai;
+            DROP TRIGGER graph_note_ad;
+            DROP TRIGGER graph_note_au_trash;
+            DROP TRIGGER graph_note_au_content;
+            DROP TRIGGER graph_note_ai;
+            DROP TABLE graph_node_metric;
+            DROP TABLE graph_summary;
+            DROP TABLE graph_change_log;
+            "),
+    },
+    Migration {
+        version: 28,
+        description: "Social Post Dedup Keys",
+        up: "
+            ALTER TABLE social_post ADD COLUMN first_seen_at INTEGER;
+            ALTER TABLE social_post ADD COLUMN content_hash TEXT;
+            UPDATE social_post SET first_seen_at = fetched_at;
+
+            CREATE INDEX IF NOT EXISTS idx_social_post_platform_post
+                ON social_post(platform, platform_post_id);
+            CREATE INDEX IF NOT EXISTS idx_social_post_content_hash
+                ON social_post(platform, content_hash);
+            ",
+        after_up: None,
+        down: Down::Sql("
+            DROP INDEX idx_social_post_content_hash;
+            DROP INDEX idx_social_post_platform_post;
+            ALTER TABLE social_post DROP COLUMN content_hash;
+            ALTER TABLE social_post DROP COLUMN first_seen_at;
+            "),
+    },
+    Migration {
+        version: 29,
+        description: "Compound Category Rules",
+        up: "
+            CREATE TABLE IF NOT EXISTS social_category_rule (
+                id TEXT PRIMARY KEY,
+                space_id TEXT NOT NULL REFERENCES space(id) ON DELETE CASCADE,
+                category_id TEXT NOT NULL REFERENCES social_category(id) ON DELETE CASCADE,
+                name TEXT NOT NULL,
+                condition_json TEXT NOT NULL,
+                priority INTEGER NOT NULL DEFAULT 0,
+                enabled INTEGER NOT NULL DEFAULT 1,
+                created_at INTEGER NOT NULL
+            );
+            CREATE INDEX IF NOT EXISTS idx_social_category_rule_space
+                ON social_category_rule(space_id, priority);
+            ",
+        after_up: None,
+        down: Down::Sql("DROP TABLE social_category_rule;"),
+    },
+    Migration {
+        version: 30,
+        description: "Social Usage Tracking",
+        up: "
+            -- Per-mode platform budgets; weekday (0 = Monday) overrides the every-day limit
+            CREATE TABLE IF NOT EXISTS social_time_limit (
+                focus_mode_id TEXT NOT NULL REFERENCES social_focus_mode(id) ON DELETE CASCADE,
+                platform TEXT NOT NULL,
+                weekday INTEGER CHECK(weekday BETWEEN 0 AND 6),
+                daily_minutes INTEGER NOT NULL CHECK(daily_minutes >= 0)
+            );
+            CREATE UNIQUE INDEX IF NOT EXISTS idx_social_time_limit_unique
+                ON social_time_limit(focus_mode_id, platform, COALESCE(weekday, -1));
+
+            -- Seconds spent per platform per local day (YYYY-MM-DD)
+            CREATE TABLE IF NOT EXISTS social_platform_usage (
+                space_id TEXT NOT NULL REFERENCES space(id) ON DELETE CASCADE,
+                platform TEXT NOT NULL,
+                day TEXT NOT NULL,
+                seconds INTEGER NOT NULL DEFAULT 0,
+                PRIMARY KEY(space_id, platform, day)
+            );
+
+            CREATE TABLE IF NOT EXISTS social_usage_settings (
+                space_id TEXT PRIMARY KEY,
+                utc_offset_minutes INTEGER NOT NULL
+            );
+
+            -- Allow usage threshold triggers
+            CREATE TABLE social_automation_rule_new (
+                id TEXT PRIMARY KEY,
+                space_id TEXT NOT NULL REFERENCES space(id) ON DELETE CASCADE,
+                name TEXT NOT NULL,
+                trigger_type TEXT NOT NULL CHECK(trigger_type IN('time_of_day', 'day_of_week', 'platform_open', 'category_post', 'usage_threshold')),
+                trigger_value TEXT NOT NULL,
+                action_type TEXT NOT NULL CHECK(action_type IN('activate_focus_mode', 'disable_sync', 'send_notification', 'auto_categorize')),
+                action_value TEXT NOT NULL,
+                enabled INTEGER NOT NULL DEFAULT 1,
+                created_at INTEGER NOT NULL
+            );
+            INSERT INTO social_automation_rule_new SELECT * FROM social_automation_rule;
+            DROP TABLE social_automation_rule;
+            ALTER TABLE social_automation_rule_new RENAME TO social_automation_rule;
+
+            -- Usage threshold rules fire once per platform per local day
+            CREATE TABLE IF NOT EXISTS social_automation_firing (
+                rule_id TEXT NOT NULL REFERENCES social_automation_rule(id) ON DELETE CASCADE,
+                platform TEXT NOT NULL,
+                day TEXT NOT NULL,
+                fired_at INTEGER NOT NULL,
+                PRIMARY KEY(rule_id, platform, day)
+            );
+            ",
+        after_up: None,
+        down: Down::Irreversible("rebuilds social_automation_rule to allow usage threshold triggers"),
+    },
+    Migration {
+        version: 31,
+        description: "Session Metadata",
+        up: "
+            ALTER TABLE sessions ADD COLUMN last_used_at INTEGER;
+            ALTER TABLE sessions ADD COLUMN device_name TEXT;
+            ALTER TABLE sessions ADD COLUMN user_agent TEXT;
+            ALTER TABLE sessions ADD COLUMN ttl_secs INTEGER NOT NULL DEFAULT 86400;
+
+            UPDATE sessions SET last_used_at = created_at WHERE last_used_at IS NULL;
+            ",
+        after_up: None,
+        down: Down::Sql("
+            ALTER TABLE sessions DROP COLUMN ttl_secs;
+            ALTER TABLE sessions DROP COLUMN user_agent;
+            ALTER TABLE sessions DROP COLUMN device_name;
+            ALTER TABLE sessions DROP COLUMN last_used_at;
+            "),
+    },
+    Migration {
+        version: 32,
+        description: "Authentication Attempt Tracking",
+        up: "
+            CREATE TABLE IF NOT EXISTS auth_attempt (
+                key TEXT PRIMARY KEY,
+                failures INTEGER NOT NULL DEFAULT 0,
+                last_failure_at INTEGER,
+                locked_until INTEGER
+            );
+            ",
+        after_up: None,
+        down: Down::Sql("DROP TABLE auth_attempt;"),
+    },
+    Migration {
+        version: 33,
+        description: "Scheduled Backup Runs",
+        up: "
+            CREATE TABLE IF NOT EXISTS backup_run (
+                id TEXT PRIMARY KEY,
+                started_at INTEGER NOT NULL,
+                finished_at INTEGER,
+                status TEXT NOT NULL, -- completed, skipped, failed
+                reason TEXT,
+                backup_id TEXT,
+                pruned_count INTEGER NOT NULL DEFAULT 0
+            );
+            CREATE INDEX IF NOT EXISTS idx_backup_run_started ON backup_run(started_at);
+            ",
+        after_up: None,
+        down: Down::Sql("DROP TABLE backup_run;"),
+    },
+    Migration {
+        version: 34,
+        description: "Meeting Action Items",
+        up: "
+            CREATE TABLE IF NOT EXISTS meeting_action (
+                note_id TEXT NOT NULL REFERENCES note(id) ON DELETE CASCADE,
+                line_hash TEXT NOT NULL, -- sha256 of the normalized action item
+                task_id TEXT REFERENCES task(id) ON DELETE SET NULL,
+                source_line TEXT NOT NULL,
+                created_at INTEGER NOT NULL,
+                PRIMARY KEY (note_id, line_hash)
+            );
+            ",
+        after_up: None,
+        down: Down::Sql("DROP TABLE meeting_action;"),
+    },
+    Migration {
+        version: 35,
+        description: "Form Submissions",
+        up: "
+            ALTER TABLE form_template ADD COLUMN schema_version INTEGER NOT NULL DEFAULT 1;
+
+            -- Field definitions of every template version, so old submissions
+            -- can still be read after the template is edited
+            CREATE TABLE IF NOT EXISTS form_template_version (
+                template_id TEXT NOT NULL REFERENCES form_template(id) ON DELETE CASCADE,
+                version INTEGER NOT NULL,
+                fields_json TEXT NOT NULL,
+                created_at INTEGER NOT NULL,
+                PRIMARY KEY (template_id, version)
+            );
+            INSERT INTO form_template_version (template_id, version, fields_json, created_at)
+            SELECT id, 1, fields_json, strftime('%s', 'now') FROM form_template;
+
+            CREATE TABLE IF NOT EXISTS form_submission (
+                id TEXT PRIMARY KEY,
+                template_id TEXT NOT NULL REFERENCES form_template(id) ON DELETE CASCADE,
+                schema_version INTEGER NOT NULL,
+                values_json TEXT NOT NULL,
+                note_id TEXT REFERENCES note(id) ON DELETE SET NULL,
+                created_at INTEGER NOT NULL
+            );
+            CREATE INDEX IF NOT EXISTS idx_form_submission_template
+                ON form_submission(template_id, created_at);
+            ",
+        after_up: None,
+        down: Down::Sql("
+            DROP TABLE form_submission;
+            DROP TABLE form_template_version;
+            ALTER TABLE form_template DROP COLUMN schema_version;
+            "),
+    },
+    Migration {
+        version: 36,
+        description: "Health Metric Thresholds",
+        up: "
+            CREATE TABLE IF NOT EXISTS health_metric_setting (
+                space_id TEXT NOT NULL REFERENCES space(id) ON DELETE CASCADE,
+                metric_type TEXT NOT NULL,
+                min_value REAL,
+                max_value REAL,
+                higher_is_better INTEGER, -- NULL: use the metric type's default
+                updated_at INTEGER NOT NULL,
+                PRIMARY KEY (space_id, metric_type)
+            );
+            ",
+        after_up: None,
+        down: Down::Sql("DROP TABLE health_metric_setting;"),
+    },
+    Migration {
+        version: 37,
+        description: "Audit Hash Chain",
+        up: "
+            -- Data changes are chained per space: hash covers the event and
+            -- prev_hash, the hash of the event before it
+            ALTER TABLE audit_log ADD COLUMN space_id TEXT;
+            ALTER TABLE audit_log ADD COLUMN operation TEXT;
+            ALTER TABLE audit_log ADD COLUMN source TEXT;
+            ALTER TABLE audit_log ADD COLUMN chain_seq INTEGER;
+            ALTER TABLE audit_log ADD COLUMN prev_hash TEXT;
+            ALTER TABLE audit_log ADD COLUMN hash TEXT;
+            CREATE UNIQUE INDEX IF NOT EXISTS idx_audit_log_chain
+                ON audit_log(space_id, chain_seq) WHERE chain_seq IS NOT NULL;
+            CREATE INDEX IF NOT EXISTS idx_audit_log_entity_id ON audit_log(entity_id);
+            ",
+        after_up: None,
+        down: Down::Sql("
+            DROP INDEX idx_audit_log_entity_id;
+            DROP INDEX idx_audit_log_chain;
+            ALTER TABLE audit_log DROP COLUMN hash;
+            ALTER TABLE audit_log DROP COLUMN prev_hash;
+            ALTER TABLE audit_log DROP COLUMN chain_seq;
+            ALTER TABLE audit_log DROP COLUMN source;
+            ALTER TABLE audit_log DROP COLUMN operation;
+            ALTER TABLE audit_log DROP COLUMN space_id;
+            "),
+    },
+    Migration {
+        version: 38,
+        description: "Note CRDT Update Log",
+        up: "
+            -- Yrs updates per note, replayed in seq order to rebuild the body.
+            -- No foreign key: INSERT OR REPLACE on note must not drop the log
+            CREATE TABLE IF NOT EXISTS note_crdt_update (
+                seq INTEGER PRIMARY KEY AUTOINCREMENT,
+                note_id TEXT NOT NULL,
+                update_data BLOB NOT NULL,
+                created_at INTEGER NOT NULL
+            );
+            CREATE INDEX IF NOT EXISTS idx_note_crdt_update_note
+                ON note_crdt_update(note_id, created_at);
+            ",
+        after_up: None,
+        down: Down::Sql("DROP TABLE note_crdt_update;"),
+    },
+    Migration {
+        version: 39,
+        description: "Note Templates",
+        up: "
+            CREATE TABLE IF NOT EXISTS note_template (
+                id TEXT PRIMARY KEY,
+                space_id TEXT NOT NULL REFERENCES space(id),
+                name TEXT NOT NULL,
+                content TEXT NOT NULL,
+                variables_json TEXT NOT NULL DEFAULT '[]',
+                created_at INTEGER NOT NULL,
+                updated_at INTEGER NOT NULL
+            );
+            CREATE INDEX IF NOT EXISTS idx_note_template_space ON note_template(space_id);
+            ",
+        after_up: None,
+        down: Down::Sql("DROP TABLE note_template;"),
+    },
+    Migration {
+        version: 40,
+        description: "Project Health History",
+        up: "
+            -- The last health score computed each day, for trend charts
+            CREATE TABLE IF NOT EXISTS project_health_history (
+                project_id TEXT NOT NULL REFERENCES project(id),
+                day TEXT NOT NULL,
+                score REAL NOT NULL,
+                status TEXT NOT NULL,
+                computed_at INTEGER NOT NULL,
+                PRIMARY KEY (project_id, day)
+            );
+            ",
+        after_up: None,
+        down: Down::Sql("DROP TABLE project_health_history;"),
+    },
+    Migration {
+        version: 41,
+        description: "Space Archiving",
+        up: "
+            -- Archived spaces are hidden from pickers, search and sync until unarchived
+            ALTER TABLE space ADD COLUMN archived_at INTEGER;
+            ",
+        after_up: None,
+        down: Down::Sql("ALTER TABLE space DROP COLUMN archived_at;"),
+    },
+    Migration {
+        version: 42,
+        description: "Listening History and Smart Playlists",
+        up: "
+            -- One row per listen. Device-local: plays are not synced. No
+            -- cascade, so a synced track replacing its row keeps its plays.
+            CREATE TABLE IF NOT EXISTS track_play (
+                id TEXT PRIMARY KEY,
+                track_id TEXT NOT NULL REFERENCES track(id),
+                played_at INTEGER NOT NULL,
+                duration_listened INTEGER NOT NULL
+            );
+            CREATE INDEX IF NOT EXISTS idx_track_play_track ON track_play(track_id, played_at);
+
+            -- 'manual' entries survive smart playlist refreshes, 'rule' entries don't
+            ALTER TABLE playlist_track ADD COLUMN source TEXT NOT NULL DEFAULT 'manual';
+            ",
+        after_up: None,
+        down: Down::Sql("
+            ALTER TABLE playlist_track DROP COLUMN source;
+            DROP TABLE track_play;
+            "),
+    },
+    Migration {
+        version: 43,
+        description: "Sync History Aggregates",
+        up: "
+            -- Pruning collapses old sync_history into one row per space,
+            -- device and month. A raw row stands for one sync whose outcome
+            -- is `success`; an aggregate row counts its syncs and successes.
+            ALTER TABLE sync_history ADD COLUMN sync_count INTEGER NOT NULL DEFAULT 1;
+            ALTER TABLE sync_history ADD COLUMN success_count INTEGER;
+            ",
+        after_up: None,
+        down: Down::Sql("
+            DELETE FROM sync_history WHERE success_count IS NOT NULL;
+            ALTER TABLE sync_history DROP COLUMN success_count;
+            ALTER TABLE sync_history DROP COLUMN sync_count;
+            "),
+    },
+    Migration {
+        version: 44,
+        description: "Selector Updates",
+        up: "
+            -- Verified selector bundles as downloaded; at most one is active
+            CREATE TABLE IF NOT EXISTS selector_bundle (
+                version TEXT PRIMARY KEY,
+                signed_json TEXT NOT NULL,
+                installed_at INTEGER NOT NULL,
+                active INTEGER NOT NULL DEFAULT 0
+            );
+            CREATE UNIQUE INDEX IF NOT EXISTS idx_selector_bundle_active
+                ON selector_bundle(active) WHERE active = 1;
+
+            -- Platforms sent back to the bundled selectors while `version` is active
+            CREATE TABLE IF NOT EXISTS selector_rollback (
+                platform TEXT PRIMARY KEY,
+                version TEXT NOT NULL,
+                rolled_back_at INTEGER NOT NULL
+            );
+
+            -- Extraction outcomes per platform and selector version
+            CREATE TABLE IF NOT EXISTS selector_extraction_health (
+                platform TEXT NOT NULL,
+                version TEXT NOT NULL,
+                successes INTEGER NOT NULL DEFAULT 0,
+                failures INTEGER NOT NULL DEFAULT 0,
+                updated_at INTEGER NOT NULL,
+                PRIMARY KEY (platform, version)
+            );
+            ",
+        after_up: None,
+        down: Down::Sql("
+            DROP TABLE selector_extraction_health;
+            DROP TABLE selector_rollback;
+            DROP TABLE selector_bundle;
+            "),
+    },
+    Migration {
+        version: 45,
+        description: "Health Export Imports",
+        up: "
+            -- Readings imported from Apple Health or Google Fit remember the
+            -- record they came from, so importing an export again adds nothing
+            ALTER TABLE health_metric ADD COLUMN source TEXT;
+            ALTER TABLE health_metric ADD COLUMN source_record_id TEXT;
+            CREATE UNIQUE INDEX IF NOT EXISTS idx_health_metric_source_record
+                ON health_metric(space_id, metric_type, recorded_at, source_record_id)
+                WHERE source_record_id IS NOT NULL;
+            ",
+        after_up: None,
+        down: Down::Sql("
+            DROP INDEX idx_health_metric_source_record;
+            ALTER TABLE health_metric DROP COLUMN source_record_id;
+            ALTER TABLE health_metric DROP COLUMN source;
+            "),
+    },
+    Migration {
+        version: 46,
+        description: "Sync Shadows",
+        up: "
+            -- Last version of each entity received from a peer, the common
+            -- ancestor for three-way note merges
+            CREATE TABLE IF NOT EXISTS sync_shadow (
+                entity_type TEXT NOT NULL,
+                entity_id TEXT NOT NULL,
+                content BLOB NOT NULL,
+                synced_at INTEGER NOT NULL,
+                PRIMARY KEY (entity_type, entity_id)
+            );
+            ",
+        after_up: None,
+        down: Down::Sql("DROP TABLE sync_shadow;"),
+    },
+    Migration {
+        version: 47,
+        description: "Rejected Sync Deltas",
+        up: "
+            -- Deltas to shared spaces not applied because the sending
+            -- device's user lacked the permission
+            CREATE TABLE IF NOT EXISTS sync_rejected_delta (
+                id TEXT PRIMARY KEY,
+                device_id TEXT NOT NULL,
+                user_id TEXT,
+                space_id TEXT NOT NULL,
+                entity_type TEXT NOT NULL,
+                entity_id TEXT NOT NULL,
+                operation TEXT NOT NULL,
+                permission TEXT NOT NULL,
+                rejected_at INTEGER NOT NULL
+            );
+            CREATE INDEX IF NOT EXISTS idx_sync_rejected_delta_space
+                ON sync_rejected_delta(space_id, rejected_at);
+            ",
+        after_up: None,
+        down: Down::Sql("DROP TABLE sync_rejected_delta;"),
+    },
+    Migration {
+        version: 48,
+        description: "Insight Scheduling",
+        up: "
+            -- When insights were last generated for each space, a fingerprint
+            -- of the data they were drawn from, and the lease held by a run
+            CREATE TABLE IF NOT EXISTS insight_schedule_state (
+                space_id TEXT PRIMARY KEY,
+                last_run_at INTEGER,
+                last_generated_at INTEGER,
+                change_marker TEXT,
+                lease_owner TEXT,
+                lease_expires_at INTEGER
+            );
+            ",
+        after_up: None,
+        down: Down::Sql("DROP TABLE insight_schedule_state;"),
+    },
+    Migration {
+        version: 49,
+        description: "Undo Journal",
+        up: "
+            -- Snapshots of rows removed by destructive operations, kept
+            -- locally until they expire; never synced
+            CREATE TABLE IF NOT EXISTS undo_log (
+                id TEXT PRIMARY KEY,
+                space_id TEXT NOT NULL,
+                operation TEXT NOT NULL,
+                entity_type TEXT NOT NULL,
+                entity_id TEXT NOT NULL,
+                summary TEXT NOT NULL,
+                snapshot TEXT NOT NULL,
+                row_count INTEGER NOT NULL,
+                size_bytes INTEGER NOT NULL,
+                created_at INTEGER NOT NULL,
+                expires_at INTEGER NOT NULL
+            );
+            CREATE INDEX IF NOT EXISTS idx_undo_log_created ON undo_log(created_at DESC);
+            CREATE INDEX IF NOT EXISTS idx_undo_log_expires ON undo_log(expires_at);
+            ",
+        after_up: None,
+        down: Down::Sql("DROP TABLE undo_log;"),
+    },
+    Migration {
+        version: 50,
+        description: "Calendar Reminders",
+        up: "
+            -- A reminder is due remind_minutes_before the event starts;
+            -- reminded_at records when this device last fired it
+            ALTER TABLE calendar_event ADD COLUMN remind_minutes_before INTEGER;
+            ALTER TABLE calendar_event ADD COLUMN reminded_at INTEGER;
+            CREATE INDEX IF NOT EXISTS idx_calendar_event_space_start
+                ON calendar_event(space_id, start_time);
+            ",
+        after_up: None,
+        down: Down::Sql("
+            DROP INDEX idx_calendar_event_space_start;
+            ALTER TABLE calendar_event DROP COLUMN reminded_at;
+            ALTER TABLE calendar_event DROP COLUMN remind_minutes_before;
+            "),
+    },
+    Migration {
+        version: 51,
+        description: "Maintenance Run History",
+        up: "
+            -- One row per maintenance task run; tasks of one run share run_id
+            CREATE TABLE IF NOT EXISTS maintenance_run (
+                id TEXT PRIMARY KEY,
+                run_id TEXT NOT NULL,
+                task TEXT NOT NULL,
+                started_at INTEGER NOT NULL,
+                duration_ms INTEGER NOT NULL,
+                items_affected INTEGER NOT NULL DEFAULT 0,
+                error TEXT
+            );
+            CREATE INDEX IF NOT EXISTS idx_maintenance_run_started
+                ON maintenance_run(started_at);
+            ",
+        after_up: None,
+        down: Down::Sql("DROP TABLE maintenance_run;"),
+    },
+    Migration {
+        version: 52,
+        description: "Daily Note Dates",
+        up: "
+            -- Daily notes are identified by their date rather than their title
+            ALTER TABLE note ADD COLUMN daily_date TEXT;
+            CREATE UNIQUE INDEX IF NOT EXISTS idx_note_daily_date
+                ON note(space_id, daily_date) WHERE daily_date IS NOT NULL;
+            -- Tag existing daily notes, preferring the oldest one not in the
+            -- trash when a day has more than one
+            UPDATE note SET daily_date = substr(title, 14)
+            WHERE id IN (
+                SELECT id FROM (
+                    SELECT id, ROW_NUMBER() OVER (
+                        PARTITION BY space_id, title
+                        ORDER BY is_trashed, created_at, rowid
+                    ) AS position
+                    FROM note
+                    WHERE title GLOB 'Daily Note - [0-9][0-9][0-9][0-9]-[0-9][0-9]-[0-9][0-9]'
+                      AND date(substr(title, 14), '+0 days') = substr(title, 14)
+                )
+                WHERE position = 1
+            );
+            ",
+        after_up: None,
+        down: Down::Sql("
+            DROP INDEX idx_note_daily_date;
+            ALTER TABLE note DROP COLUMN daily_date;
+            "),
+    },
+    Migration {
+        version: 53,
+        description: "LLM Cache Persistence",
+        up: "
+            -- Earlier entries were keyed without the provider and can't be hit
+            DELETE FROM llm_cache;
+            ALTER TABLE llm_cache ADD COLUMN provider TEXT NOT NULL DEFAULT '';
+            ALTER TABLE llm_cache ADD COLUMN request_type TEXT NOT NULL DEFAULT 'default';
+            ALTER TABLE llm_cache ADD COLUMN input_tokens INTEGER NOT NULL DEFAULT 0;
+            ALTER TABLE llm_cache ADD COLUMN cost_usd REAL NOT NULL DEFAULT 0;
+            ALTER TABLE llm_cache ADD COLUMN size_bytes INTEGER NOT NULL DEFAULT 0;
+            ALTER TABLE llm_cache ADD COLUMN expires_at INTEGER NOT NULL DEFAULT 0;
+            ALTER TABLE llm_cache ADD COLUMN access_seq INTEGER NOT NULL DEFAULT 0;
+            CREATE INDEX idx_llm_cache_lru ON llm_cache(access_seq);
+
+            CREATE TABLE llm_cache_namespace (
+                namespace TEXT NOT NULL,
+                cache_key TEXT NOT NULL,
+                PRIMARY KEY (namespace, cache_key)
+            );
+            CREATE INDEX idx_llm_cache_namespace_key ON llm_cache_namespace(cache_key);
+
+            CREATE TRIGGER llm_cache_ad AFTER DELETE ON llm_cache BEGIN
+                DELETE FROM llm_cache_namespace WHERE cache_key = old.cache_key;
+            END;
+            ",
+        after_up: None,
+        down: Down::Sql("
+            DROP TRIGGER llm_cache_ad;
+            DROP TABLE llm_cache_namespace;
+            DROP INDEX idx_llm_cache_lru;
+            ALTER TABLE llm_cache DROP COLUMN access_seq;
+            ALTER TABLE llm_cache DROP COLUMN expires_at;
+            ALTER TABLE llm_cache DROP COLUMN size_bytes;
+            ALTER TABLE llm_cache DROP COLUMN cost_usd;
+            ALTER TABLE llm_cache DROP COLUMN input_tokens;
+            ALTER TABLE llm_cache DROP COLUMN request_type;
+            ALTER TABLE llm_cache DROP COLUMN provider;
+            "),
+    },
+    Migration {
+        version: 54,
+        description: "Task Views",
+        up: "
+            -- Named task filters; filter and sort are stored as JSON so new
+            -- filter dimensions don't need a migration
+            CREATE TABLE task_view (
+                id TEXT PRIMARY KEY,
+                space_id TEXT NOT NULL REFERENCES space(id),
+                name TEXT NOT NULL,
+                filter_json TEXT NOT NULL,
+                sort_json TEXT NOT NULL,
+                created_at INTEGER NOT NULL,
+                updated_at INTEGER NOT NULL,
+                UNIQUE (space_id, name)
+            );
+            ",
+        after_up: None,
+        down: Down::Sql("DROP TABLE task_view;"),
+    },
+    Migration {
+        version: 55,
+        description: "URL Metadata Cache",
+        up: "
+            -- Link previews keyed by the URL as written in the note. Failed
+            -- fetches are kept too, with error set, so they aren't retried
+            -- on every render.
+            CREATE TABLE url_metadata (
+                url TEXT PRIMARY KEY,
+                final_url TEXT NOT NULL,
+                title TEXT,
+                description TEXT,
+                image_url TEXT,
+                site_name TEXT,
+                favicon_url TEXT,
+                error TEXT,
+                fetched_at INTEGER NOT NULL,
+                expires_at INTEGER NOT NULL
+            );
+            ",
+        after_up: None,
+        down: Down::Sql("DROP TABLE url_metadata;"),
+    },
+    Migration {
+        version: 56,
+        description: "Sync Conflict Origin",
+        up: "
+            -- The device a conflicting change came from and when it was made
+            -- there, for describing the conflict. `device_id` is this device.
+            ALTER TABLE sync_conflict ADD COLUMN source_device_id TEXT;
+            ALTER TABLE sync_conflict ADD COLUMN remote_modified_at INTEGER;
+            ",
+        after_up: None,
+        down: Down::Sql("
+            ALTER TABLE sync_conflict DROP COLUMN remote_modified_at;
+            ALTER TABLE sync_conflict DROP COLUMN source_device_id;
+            "),
+    },
+    Migration {
+        version: 57,
+        description: "Sync Transfer Checkpoints",
+        up: "
+            -- How far an interrupted transfer from each peer got, so it can
+            -- resume after the last applied batch
+            CREATE TABLE IF NOT EXISTS sync_transfer_checkpoint (
+                peer_device_id TEXT PRIMARY KEY,
+                transfer_id TEXT NOT NULL,
+                last_applied_batch INTEGER NOT NULL,
+                total_batches INTEGER NOT NULL,
+                vector_clock TEXT NOT NULL,
+                updated_at INTEGER NOT NULL
+            );
+            ",
+        after_up: None,
+        down: Down::Sql("DROP TABLE sync_transfer_checkpoint;"),
+    },
+    Migration {
+        version: 58,
+        description: "Structured Project Updates",
+        up: "
+            -- Status report fields alongside the free-text summary; NULL on
+            -- updates written before they existed
+            ALTER TABLE project_update ADD COLUMN status TEXT;
+            ALTER TABLE project_update ADD COLUMN percent_complete INTEGER;
+            ALTER TABLE project_update ADD COLUMN highlights TEXT;
+            ALTER TABLE project_update ADD COLUMN risks_changed TEXT;
+            ALTER TABLE project_update ADD COLUMN next_steps TEXT;
+            CREATE INDEX IF NOT EXISTS idx_project_update_project_when
+                ON project_update(project_id, when_at);
+            ",
+        after_up: None,
+        down: Down::Sql("
+            DROP INDEX IF EXISTS idx_project_update_project_when;
+            ALTER TABLE project_update DROP COLUMN next_steps;
+            ALTER TABLE project_update DROP COLUMN risks_changed;
+            ALTER TABLE project_update DROP COLUMN highlights;
+            ALTER TABLE project_update DROP COLUMN percent_complete;
+            ALTER TABLE project_update DROP COLUMN status;
+            "),
+    },
+    Migration {
+        version: 59,
+        description: "Quick Capture Inbox",
+        up: "
+            -- Vault-wide captures awaiting triage into a note or task
+            CREATE TABLE IF NOT EXISTS inbox_item (
+                id TEXT PRIMARY KEY,
+                text TEXT NOT NULL,
+                source TEXT NOT NULL,
+                captured_at INTEGER NOT NULL,
+                updated_at INTEGER NOT NULL
+            );
+            CREATE INDEX IF NOT EXISTS idx_inbox_item_captured ON inbox_item(captured_at);
+            CREATE INDEX IF NOT EXISTS idx_inbox_item_updated ON inbox_item(updated_at);
+            ",
+        after_up: None,
+        down: Down::Sql("DROP TABLE inbox_item;"),
+    },
+    Migration {
+        version: 60,
+        description: "Locked Notes",
+        up: "
+            -- Set when content_md holds a passphrase-locked envelope instead
+            -- of markdown; such notes are kept out of search and RAG
+            ALTER TABLE note ADD COLUMN is_locked INTEGER NOT NULL DEFAULT 0;
+            ",
+        after_up: None,
+        down: Down::Sql("ALTER TABLE note DROP COLUMN is_locked;"),
+    },
+    Migration {
+        version: 61,
+        description: "Social Post Archive Notes",
+        up: "
+            -- The note a social post was archived to; linked posts are kept
+            -- out of pruning, and re-archiving updates the same note
+            CREATE TABLE IF NOT EXISTS social_post_note (
+                post_id TEXT PRIMARY KEY,
+                note_id TEXT NOT NULL,
+                archived_at INTEGER NOT NULL,
+                updated_at INTEGER NOT NULL
+            );
+            CREATE INDEX IF NOT EXISTS idx_social_post_note_note ON social_post_note(note_id);
+
+            CREATE TRIGGER social_post_note_post_ad AFTER DELETE ON social_post BEGIN
+                DELETE FROM social_post_note WHERE post_id = old.id;
+            END;
+
+            -- Deleting the note makes the post prunable again
+            CREATE TRIGGER social_post_note_note_ad AFTER DELETE ON note BEGIN
+                DELETE FROM social_post_note WHERE note_id = old.id;
+            END;
+            ",
+        after_up: None,
+        down: Down::Sql("
+            DROP TRIGGER social_post_note_note_ad;
+            DROP TRIGGER social_post_note_post_ad;
+            DROP TABLE social_post_note;
+            "),
+    },
+    Migration {
+        version: 62,
+        description: "Note Properties",
+        up: "
+            -- Typed custom fields, defined per space
+            CREATE TABLE IF NOT EXISTS property_definition (
+                id TEXT PRIMARY KEY,
+                space_id TEXT NOT NULL REFERENCES space(id) ON DELETE CASCADE,
+                name TEXT NOT NULL COLLATE NOCASE,
+                property_type TEXT NOT NULL
+                    CHECK (property_type IN ('text', 'number', 'date', 'select', 'checkbox')),
+                -- JSON array of the choices of a select
+                options_json TEXT NOT NULL DEFAULT '[]',
+                created_at INTEGER NOT NULL,
+                UNIQUE (space_id, name)
+            );
+
+            -- value_text is the canonical value; numbers, dates (unix
+            -- seconds at midnight UTC) and checkboxes (0/1) also go in
+            -- value_number so search can compare them
+            CREATE TABLE IF NOT EXISTS note_property (
+                note_id TEXT NOT NULL REFERENCES note(id) ON DELETE CASCADE,
+                property_id TEXT NOT NULL
+                    REFERENCES property_definition(id) ON DELETE CASCADE,
+                value_text TEXT NOT NULL,
+                value_number REAL,
+                updated_at INTEGER NOT NULL,
+                PRIMARY KEY (note_id, property_id)
+            );
+            CREATE INDEX IF NOT EXISTS idx_note_property_value
+                ON note_property(property_id, value_number);
+            ",
+        after_up: None,
+        down: Down::Sql("
+            DROP TABLE note_property;
+            DROP TABLE property_definition;
+            "),
+    },
+    Migration {
+        version: 63,
+        description: "Note Word Counts",
+        up: "
+            ALTER TABLE note ADD COLUMN word_count INTEGER NOT NULL DEFAULT 0;
+            ALTER TABLE note ADD COLUMN char_count INTEGER NOT NULL DEFAULT 0;
+
+            -- Words each save added (positive) or removed (negative)
+            CREATE TABLE IF NOT EXISTS note_word_delta (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                note_id TEXT NOT NULL,
+                space_id TEXT NOT NULL,
+                recorded_at INTEGER NOT NULL,
+                delta INTEGER NOT NULL
+            );
+            CREATE INDEX IF NOT EXISTS idx_note_word_delta_space
+                ON note_word_delta(space_id, recorded_at);
+            ",
+        after_up: Some(backfill_note_counts),
+        down: Down::Sql("
+            DROP TABLE note_word_delta;
+            ALTER TABLE note DROP COLUMN char_count;
+            ALTER TABLE note DROP COLUMN word_count;
+            "),
+    },
+    Migration {
+        version: 64,
+        description: "Relay Sync",
+        up: "
+            -- 'direct' or 'relay'
+            ALTER TABLE sync_history ADD COLUMN transport TEXT NOT NULL DEFAULT 'direct';
+
+            -- Chunks of relayed sessions still waiting for the rest of their
+            -- chunks
+            CREATE TABLE IF NOT EXISTS sync_relay_chunk (
+                from_device TEXT NOT NULL,
+                session_id TEXT NOT NULL,
+                chunk_index INTEGER NOT NULL,
+                chunk_count INTEGER NOT NULL,
+                data TEXT NOT NULL,
+                received_at INTEGER NOT NULL,
+                PRIMARY KEY (from_device, session_id, chunk_index)
+            );
+            ",
+        after_up: None,
+        down: Down::Sql("
+            DROP TABLE sync_relay_chunk;
+            ALTER TABLE sync_history DROP COLUMN transport;
+            "),
+    },
+    Migration {
+        version: 65,
+        description: "Time Entries From Calendar Events",
+        up: "
+            -- Event an entry was logged from; not a foreign key, so entries
+            -- outlive their events
+            ALTER TABLE time_entry ADD COLUMN calendar_event_id TEXT;
+            CREATE INDEX IF NOT EXISTS time_entry_calendar_event
+                ON time_entry(calendar_event_id) WHERE calendar_event_id IS NOT NULL;
+            ",
+        after_up: None,
+        down: Down::Sql("
+            DROP INDEX time_entry_calendar_event;
+            ALTER TABLE time_entry DROP COLUMN calendar_event_id;
+            "),
+    },
+    Migration {
+        version: 66,
+        description: "Automation Rules",
+        up: "
+            -- trigger_type is the kind of trigger_json, so a change only
+            -- loads the rules it can trigger
+            CREATE TABLE IF NOT EXISTS automation_rule (
+                id TEXT PRIMARY KEY,
+                space_id TEXT NOT NULL REFERENCES space(id) ON DELETE CASCADE,
+                name TEXT NOT NULL,
+                trigger_type TEXT NOT NULL
+                    CHECK (trigger_type IN ('tag_added', 'task_completed', 'note_created')),
+                trigger_json TEXT NOT NULL,
+                -- JSON array, run in order
+                actions_json TEXT NOT NULL,
+                enabled INTEGER NOT NULL DEFAULT 1,
+                created_at INTEGER NOT NULL,
+                updated_at INTEGER NOT NULL
+            );
+            CREATE INDEX IF NOT EXISTS idx_automation_rule_trigger
+                ON automation_rule(space_id, trigger_type);
+            ",
+        after_up: None,
+        down: Down::Sql("
+            DROP TABLE automation_rule;
+            "),
+    },
+    Migration {
+        version: 67,
+        description: "Typed Settings",
+        up: "
+            -- Version of the stored shape of typed settings; NULL for plain
+            -- string values
+            ALTER TABLE settings ADD COLUMN schema_version INTEGER;
+
+            -- Typed settings live under <domain>.<name> keys
+            UPDATE settings SET key = 'backup.policy', schema_version = 1
+                WHERE key = 'backup_policy';
+            UPDATE settings SET key = 'dashboard.config', schema_version = 1
+                WHERE key = 'dashboard_config';
+            UPDATE settings SET key = 'project.health_config', schema_version = 1
+                WHERE key = 'project_health_config';
+
+            -- The sync port becomes a field of the sync settings
+            INSERT OR REPLACE INTO settings
+                (key, value, description, schema_version, updated_at, created_at)
+            SELECT 'sync.settings', json_object('port', CAST(value AS INTEGER)),
+                   'Device-to-device sync', 1, updated_at, created_at
+            FROM settings WHERE key = 'sync_port';
+            DELETE FROM settings WHERE key = 'sync_port';
+            ",
+        after_up: None,
+        down: Down::Sql("
+            INSERT OR REPLACE INTO settings (key, value, description, updated_at, created_at)
+            SELECT 'sync_port', CAST(json_extract(value, '$.port') AS TEXT),
+                   'Port for device-to-device sync', updated_at, created_at
+            FROM settings WHERE key = 'sync.settings';
+            DELETE FROM settings WHERE key IN ('sync.settings', 'ocr.settings');
+
+            UPDATE settings SET key = 'backup_policy' WHERE key = 'backup.policy';
+            UPDATE settings SET key = 'dashboard_config' WHERE key = 'dashboard.config';
+            UPDATE settings SET key = 'project_health_config'
+                WHERE key = 'project.health_config';
+
+            ALTER TABLE settings DROP COLUMN schema_version;
+            "),
+    },
+    Migration {
+        version: 68,
+        description: "SRS Review Sessions",
+        up: "
+            -- day is the UTC day of the session, YYYY-MM-DD; an unfinished
+            -- session resumes the same day and is abandoned on a later one
+            CREATE TABLE IF NOT EXISTS review_session (
+                id TEXT PRIMARY KEY,
+                space_id TEXT NOT NULL REFERENCES space(id) ON DELETE CASCADE,
+                config_json TEXT NOT NULL,
+                status TEXT NOT NULL DEFAULT 'active'
+                    CHECK (status IN ('active', 'finished', 'abandoned')),
+                day TEXT NOT NULL,
+                started_at INTEGER NOT NULL,
+                last_activity_at INTEGER NOT NULL,
+                -- Seconds between answers, each gap capped at the idle limit
+                active_secs INTEGER NOT NULL DEFAULT 0,
+                finished_at INTEGER
+            );
+            CREATE INDEX IF NOT EXISTS idx_review_session_space
+                ON review_session(space_id, status, day);
+
+            CREATE TABLE IF NOT EXISTS review_session_card (
+                session_id TEXT NOT NULL REFERENCES review_session(id) ON DELETE CASCADE,
+                position INTEGER NOT NULL,
+                card_id TEXT NOT NULL REFERENCES knowledge_card(id) ON DELETE CASCADE,
+                is_new INTEGER NOT NULL,
+                answered_at INTEGER,
+                rating INTEGER,
+                PRIMARY KEY (session_id, position)
+            );
+
+            -- Session an answer was given in, if any
+            ALTER TABLE review_log ADD COLUMN session_id TEXT;
+
+            -- Consecutive days with a completed session, per space
+            CREATE TABLE IF NOT EXISTS review_streak (
+                space_id TEXT PRIMARY KEY REFERENCES space(id) ON DELETE CASCADE,
+                current_streak INTEGER NOT NULL,
+                longest_streak INTEGER NOT NULL,
+                last_day TEXT NOT NULL
+            );
+            ",
+        after_up: None,
+        down: Down::Sql("
+            DROP TABLE review_streak;
+            ALTER TABLE review_log DROP COLUMN session_id;
+            DROP TABLE review_session_card;
+            DROP TABLE review_session;
+            "),
+    },
+    Migration {
+        version: 69,
+        description: "Duplicate Notes",
+        up: "
+            -- MinHash signature of a note's word shingles, recomputed when
+            -- the hash of the body it was computed from no longer matches.
+            -- An empty signature stands for a body without words.
+            CREATE TABLE IF NOT EXISTS note_minhash (
+                note_id TEXT PRIMARY KEY REFERENCES note(id) ON DELETE CASCADE,
+                content_hash INTEGER NOT NULL,
+                signature BLOB NOT NULL
+            );
+
+            -- Merges of duplicate notes; synced so peers move the same
+            -- relations and trash the same notes
+            CREATE TABLE IF NOT EXISTS note_merge (
+                id TEXT PRIMARY KEY,
+                space_id TEXT NOT NULL REFERENCES space(id) ON DELETE CASCADE,
+                primary_id TEXT NOT NULL,
+                -- JSON array of note ids
+                duplicate_ids_json TEXT NOT NULL,
+                strategy TEXT NOT NULL,
+                merged_at INTEGER NOT NULL
+            );
+            CREATE INDEX IF NOT EXISTS idx_note_merge_space
+                ON note_merge(space_id, merged_at);
+            ",
+        after_up: None,
+        down: Down::Sql("
+            DROP TABLE note_merge;
+            DROP TABLE note_minhash;
+            "),
+    },
+    Migration {
+        version: 70,
+        description: "Note Edit Sessions",
+        up: "
+            -- Snapshots of a note taken while it's edited in a session
+            CREATE TABLE IF NOT EXISTS note_version (
+                id TEXT PRIMARY KEY,
+                note_id TEXT NOT NULL REFERENCES note(id) ON DELETE CASCADE,
+                title TEXT NOT NULL,
+                content_md TEXT NOT NULL,
+                session_id TEXT,
+                created_at INTEGER NOT NULL
+            );
+            CREATE INDEX IF NOT EXISTS idx_note_version_note
+                ON note_version(note_id, created_at);
+
+            -- Open editing sessions. Drafts saved since flushed_at haven't
+            -- been versioned or bumped the note's modified_at yet.
+            CREATE TABLE IF NOT EXISTS note_edit_session (
+                id TEXT PRIMARY KEY,
+                note_id TEXT NOT NULL REFERENCES note(id) ON DELETE CASCADE,
+                interval_secs INTEGER NOT NULL,
+                started_at INTEGER NOT NULL,
+                flushed_at INTEGER NOT NULL,
+                pending INTEGER NOT NULL DEFAULT 0
+            );
+            CREATE INDEX IF NOT EXISTS idx_note_edit_session_note
+                ON note_edit_session(note_id);
+            ",
+        after_up: None,
+        down: Down::Sql("
+            DROP TABLE note_edit_session;
+            DROP TABLE note_version;
+            "),
+    },
+    Migration {
+        version: 71,
+        description: "Health Goals",
+        up: "
+            CREATE TABLE IF NOT EXISTS health_goal (
+                id TEXT PRIMARY KEY,
+                space_id TEXT NOT NULL REFERENCES space(id) ON DELETE CASCADE,
+                title TEXT NOT NULL,
+                metric_type TEXT NOT NULL,
+                comparison TEXT NOT NULL CHECK (comparison IN ('at_least', 'at_most')),
+                target_value REAL NOT NULL,
+                unit TEXT,
+                aggregation TEXT NOT NULL
+                    CHECK (aggregation IN ('latest', 'seven_day_average', 'thirty_day_average')),
+                deadline INTEGER,
+                -- Free-form goal the target was parsed from
+                goal_id TEXT REFERENCES goal(id) ON DELETE SET NULL,
+                created_at INTEGER NOT NULL,
+                -- Outcome of the last evaluation
+                status TEXT CHECK (status IN ('achieved', 'on_track', 'behind', 'expired')),
+                current_value REAL,
+                projected_value REAL,
+                evaluated_at INTEGER
+            );
+            CREATE INDEX IF NOT EXISTS idx_health_goal_space ON health_goal(space_id);
+            ",
+        after_up: Some(import_free_form_health_goals),
+        down: Down::Sql("DROP TABLE health_goal;"),
+    },
+    Migration {
+        version: 72,
+        description: "Note Sharing Between Spaces",
+        up: "
+            -- Notes moved to another space; synced so the latest move of a
+            -- note decides its space on every device
+            CREATE TABLE IF NOT EXISTS note_move (
+                id TEXT PRIMARY KEY,
+                note_id TEXT NOT NULL,
+                from_space_id TEXT NOT NULL,
+                to_space_id TEXT NOT NULL,
+                tag_action TEXT NOT NULL,
+                time_entry_action TEXT NOT NULL,
+                moved_at INTEGER NOT NULL
+            );
+            CREATE INDEX IF NOT EXISTS idx_note_move_note ON note_move(note_id, moved_at);
+            CREATE INDEX IF NOT EXISTS idx_note_move_from ON note_move(from_space_id, moved_at);
+            CREATE INDEX IF NOT EXISTS idx_note_move_to ON note_move(to_space_id, moved_at);
+
+            -- Notes shown in a space besides their own. The note may not
+            -- have synced to this device yet, so it isn't a foreign key.
+            -- Removed references stay as tombstones for sync.
+            CREATE TABLE IF NOT EXISTS note_reference (
+                id TEXT PRIMARY KEY,
+                note_id TEXT NOT NULL,
+                space_id TEXT NOT NULL REFERENCES space(id) ON DELETE CASCADE,
+                created_at INTEGER NOT NULL,
+                updated_at INTEGER NOT NULL,
+                removed_at INTEGER,
+                UNIQUE(note_id, space_id)
+            );
+            CREATE INDEX IF NOT EXISTS idx_note_reference_space
+                ON note_reference(space_id, updated_at);
+            ",
+        after_up: None,
+        down: Down::Sql("
+            DROP TABLE note_reference;
+            DROP TABLE note_move;
+            "),
+    },
+    Migration {
+        version: 73,
+        description: "Weekly Review Tracking",
+        up: "
+            -- One review per space and week (period_start is the Monday,
+            -- 00:00 UTC). Regenerating a week rewrites the same note, which
+            -- may have been purged since, so it isn't a foreign key.
+            CREATE TABLE IF NOT EXISTS weekly_review (
+                id TEXT PRIMARY KEY,
+                space_id TEXT NOT NULL REFERENCES space(id) ON DELETE CASCADE,
+                period_start INTEGER NOT NULL,
+                note_id TEXT NOT NULL,
+                generated_at INTEGER NOT NULL,
+                completed_at INTEGER,
+                stats_json TEXT NOT NULL DEFAULT '{}',
+                UNIQUE(space_id, period_start)
+            );
+            ",
+        after_up: None,
+        down: Down::Sql("DROP TABLE weekly_review;"),
+    },
+    Migration {
+        version: 74,
+        description: "Social Topic Clusters",
+        up: "
+            -- Topics discovered by clustering post embeddings, per space.
+            -- Derived data: each device clusters its own posts, so none of
+            -- it is synced.
+            CREATE TABLE IF NOT EXISTS social_topic_cluster (
+                id TEXT PRIMARY KEY,
+                space_id TEXT NOT NULL REFERENCES space(id) ON DELETE CASCADE,
+                label TEXT NOT NULL,
+                centroid BLOB NOT NULL,
+                embedding_version TEXT NOT NULL,
+                created_at INTEGER NOT NULL,
+                updated_at INTEGER NOT NULL
+            );
+            CREATE INDEX IF NOT EXISTS idx_social_topic_cluster_space
+                ON social_topic_cluster(space_id);
+
+            -- Every post that went through clustering, with its embedding so
+            -- a full re-cluster doesn't embed it again. No cluster means it
+            -- wasn't close enough to any topic.
+            CREATE TABLE IF NOT EXISTS social_post_topic (
+                post_id TEXT PRIMARY KEY REFERENCES social_post(id) ON DELETE CASCADE,
+                space_id TEXT NOT NULL REFERENCES space(id) ON DELETE CASCADE,
+                cluster_id TEXT REFERENCES social_topic_cluster(id) ON DELETE SET NULL,
+                similarity REAL,
+                embedding BLOB NOT NULL,
+                embedding_version TEXT NOT NULL,
+                assigned_at INTEGER NOT NULL
+            );
+            CREATE INDEX IF NOT EXISTS idx_social_post_topic_cluster
+                ON social_post_topic(cluster_id);
+            CREATE INDEX IF NOT EXISTS idx_social_post_topic_space
+                ON social_post_topic(space_id);
+            ",
+        after_up: None,
+        down: Down::Sql("
+            DROP TABLE social_post_topic;
+            DROP TABLE social_topic_cluster;
+            "),
+    },
+    Migration {
+        version: 75,
+        description: "Sync Transfer Stats",
+        up: "
+            -- Bytes and deltas moved per sync session, broken down by space,
+            -- entity type and direction. payload_bytes is the serialized
+            -- size, wire_bytes what went over the network after compression.
+            CREATE TABLE IF NOT EXISTS sync_transfer_stats (
+                id TEXT PRIMARY KEY,
+                session_id TEXT NOT NULL,
+                device_id TEXT NOT NULL,
+                space_id TEXT NOT NULL,
+                entity_type TEXT NOT NULL,
+                direction TEXT NOT NULL CHECK (direction IN ('sent', 'received')),
+                delta_count INTEGER NOT NULL,
+                payload_bytes INTEGER NOT NULL,
+                wire_bytes INTEGER NOT NULL,
+                transport TEXT NOT NULL,
+                recorded_at INTEGER NOT NULL
+            );
+            CREATE INDEX IF NOT EXISTS idx_sync_transfer_stats_space_time
+                ON sync_transfer_stats(space_id, recorded_at);
+            ",
+        after_up: None,
+        down: Down::Sql("DROP TABLE sync_transfer_stats;"),
+    },
+    Migration {
+        version: 76,
+        description: "Form Title Patterns",
+        up: "
+            -- Title of the note a mapped form submission creates, with
+            -- {date} style placeholders; field mappings live in fields_json
+            ALTER TABLE form_template ADD COLUMN title_pattern TEXT;
+            ",
+        after_up: None,
+        down: Down::Sql("ALTER TABLE form_template DROP COLUMN title_pattern;"),
+    },
+    Migration {
+        version: 77,
+        description: "Collaboration Presence and Edit Locks",
+        up: "
+            -- Who is active in a space and what they have open, one row per
+            -- user and space, refreshed by heartbeats until expires_at. Like
+            -- edit_lock, local to this vault and never synced
+            CREATE TABLE IF NOT EXISTS presence (
+                space_id TEXT NOT NULL,
+                user_id TEXT NOT NULL,
+                entity_id TEXT,
+                last_seen INTEGER NOT NULL,
+                expires_at INTEGER NOT NULL,
+                PRIMARY KEY (space_id, user_id)
+            );
+            CREATE INDEX IF NOT EXISTS idx_presence_entity ON presence(entity_id);
+
+            -- Advisory locks on editing an entity; an expired one is free
+            CREATE TABLE IF NOT EXISTS edit_lock (
+                entity_id TEXT PRIMARY KEY,
+                user_id TEXT NOT NULL,
+                acquired_at INTEGER NOT NULL,
+                expires_at INTEGER NOT NULL
+            );
+            ",
+        after_up: None,
+        down: Down::Sql("
+            DROP TABLE edit_lock;
+            DROP TABLE presence;
+            "),
+    },
+    Migration {
+        version: 78,
+        description: "Relay Sync Acknowledgements",
+        up: "
+            -- Sessions sent through the relay; gathered_at becomes the
+            -- peer's watermark once it acknowledges the session
+            CREATE TABLE IF NOT EXISTS sync_relay_outbox (
+                session_id TEXT PRIMARY KEY,
+                peer_device_id TEXT NOT NULL,
+                gathered_at INTEGER NOT NULL,
+                sent_at INTEGER NOT NULL,
+                acked_at INTEGER
+            );
+            CREATE INDEX IF NOT EXISTS idx_sync_relay_outbox_peer
+                ON sync_relay_outbox(peer_device_id, acked_at);
+            ",
+        after_up: None,
+        down: Down::Sql("DROP TABLE sync_relay_outbox;"),
+    },
+    Migration {
+        version: 79,
+        description: "OCR Queue Columns",
+        up: "
+            -- ocr_result used to be created only at startup, so older vaults
+            -- have it without the queue and language columns
+            CREATE TABLE IF NOT EXISTS ocr_result (
+                id TEXT PRIMARY KEY,
+                blob_id TEXT NOT NULL UNIQUE,
+                extracted_text TEXT,
+                confidence REAL,
+                status TEXT NOT NULL DEFAULT 'pending',
+                processed_at INTEGER,
+                error_message TEXT,
+                created_at INTEGER NOT NULL,
+                FOREIGN KEY (blob_id) REFERENCES blob(id) ON DELETE CASCADE
+            );
+            ALTER TABLE ocr_result ADD COLUMN priority INTEGER NOT NULL DEFAULT 0;
+            ALTER TABLE ocr_result ADD COLUMN attempts INTEGER NOT NULL DEFAULT 0;
+            ALTER TABLE ocr_result ADD COLUMN next_attempt_at INTEGER;
+            ALTER TABLE ocr_result ADD COLUMN languages TEXT;
+            ALTER TABLE ocr_result ADD COLUMN detected_language TEXT;
+            ",
+        after_up: None,
+        down: Down::Sql("
+            DROP INDEX IF EXISTS idx_ocr_result_queue;
+            ALTER TABLE ocr_result DROP COLUMN detected_language;
+            ALTER TABLE ocr_result DROP COLUMN languages;
+            ALTER TABLE ocr_result DROP COLUMN next_attempt_at;
+            ALTER TABLE ocr_result DROP COLUMN attempts;
+            ALTER TABLE ocr_result DROP COLUMN priority;
+            "),
+    },
+    Migration {
+        version: 80,
+        description: "Sync Device Columns",
+        up: "
+            -- sync_state used to be created only when sync started, so
+            -- older vaults have it without the columns below
+            CREATE TABLE IF NOT EXISTS sync_state (
+                device_id TEXT PRIMARY KEY,
+                device_name TEXT NOT NULL,
+                device_type TEXT NOT NULL,
+                last_seen INTEGER NOT NULL,
+                sync_address TEXT NOT NULL,
+                sync_port INTEGER NOT NULL,
+                protocol_version TEXT NOT NULL,
+                trusted INTEGER NOT NULL DEFAULT 0,
+                created_at INTEGER NOT NULL DEFAULT (strftime('%s', 'now'))
+            );
+            -- Transport key pinned at pairing
+            ALTER TABLE sync_state ADD COLUMN static_public_key BLOB;
+            -- 'mdns' or 'manual' (added by address for networks without multicast)
+            ALTER TABLE sync_state ADD COLUMN discovery_source TEXT NOT NULL DEFAULT 'mdns';
+            -- JSON array of the entity types synced with the device; NULL for all
+            ALTER TABLE sync_state ADD COLUMN sync_scope TEXT;
+            -- User the device's deltas to shared spaces are checked against
+            ALTER TABLE sync_state ADD COLUMN user_id TEXT;
+
+            -- Set on rows that record an event rather than a sync, e.g. a
+            -- scope change
+            ALTER TABLE sync_history ADD COLUMN note TEXT;
+            ",
+        after_up: None,
+        down: Down::Sql("
+            DELETE FROM sync_history WHERE note IS NOT NULL;
+            ALTER TABLE sync_history DROP COLUMN note;
+            ALTER TABLE sync_state DROP COLUMN user_id;
+            ALTER TABLE sync_state DROP COLUMN sync_scope;
+            ALTER TABLE sync_state DROP COLUMN discovery_source;
+            ALTER TABLE sync_state DROP COLUMN static_public_key;
+            "),
+    },
+    Migration {
+        version: 81,
+        description: "Invitation Responses",
+        up: "
+            -- user_invitations used to be created only with the other
+            -- collaboration tables, so older vaults have it without these
+            CREATE TABLE IF NOT EXISTS user_invitations (
+                id TEXT PRIMARY KEY,
+                space_id TEXT NOT NULL,
+                email TEXT NOT NULL,
+                role_id TEXT NOT NULL,
+                token TEXT NOT NULL UNIQUE,
+                invited_by TEXT NOT NULL,
+                invited_at INTEGER NOT NULL,
+                expires_at INTEGER NOT NULL,
+                status TEXT NOT NULL DEFAULT 'pending',
+                FOREIGN KEY (role_id) REFERENCES roles(id)
+            );
+            -- When the invitation left 'pending', and who accepted it
+            ALTER TABLE user_invitations ADD COLUMN responded_at INTEGER;
+            ALTER TABLE user_invitations ADD COLUMN accepted_by TEXT;
+            ",
+        after_up: None,
+        down: Down::Sql("
+            ALTER TABLE user_invitations DROP COLUMN accepted_by;
+            ALTER TABLE user_invitations DROP COLUMN responded_at;
+            "),
+    },
+    Migration {
+        version: 82,
+        description: "CalDAV Token Auth",
+        up: "
+            -- caldav_account used to be created only when CalDAV was first
+            -- used, so accounts from before token auth lack these columns
+            CREATE TABLE IF NOT EXISTS caldav_account (
+                id TEXT PRIMARY KEY,
+                url TEXT NOT NULL,
+                username TEXT NOT NULL,
+                encrypted_password TEXT NOT NULL,
+                calendar_path TEXT NOT NULL,
+                sync_token TEXT,
+                last_sync INTEGER,
+                enabled INTEGER NOT NULL DEFAULT 1,
+                auto_sync INTEGER NOT NULL DEFAULT 1,
+                sync_frequency_minutes INTEGER NOT NULL DEFAULT 15,
+                sync_direction TEXT NOT NULL DEFAULT 'bidirectional',
+                created_at INTEGER NOT NULL
+            );
+            -- 'basic' or 'bearer'; tokens and the OAuth client are encrypted
+            -- with the vault key like the password
+            ALTER TABLE caldav_account ADD COLUMN auth_type TEXT NOT NULL DEFAULT 'basic';
+            ALTER TABLE caldav_account ADD COLUMN encrypted_access_token TEXT;
+            ALTER TABLE caldav_account ADD COLUMN encrypted_refresh_token TEXT;
+            ALTER TABLE caldav_account ADD COLUMN token_expires_at INTEGER;
+            ALTER TABLE caldav_account ADD COLUMN encrypted_oauth_client TEXT;
+            ",
+        after_up: None,
+        down: Down::Sql("
+            -- Token accounts have no password to fall back on
+            DELETE FROM caldav_account WHERE auth_type != 'basic';
+            ALTER TABLE caldav_account DROP COLUMN encrypted_oauth_client;
+            ALTER TABLE caldav_account DROP COLUMN token_expires_at;
+            ALTER TABLE caldav_account DROP COLUMN encrypted_refresh_token;
+            ALTER TABLE caldav_account DROP COLUMN encrypted_access_token;
+            ALTER TABLE caldav_account DROP COLUMN auth_type;
+            "),
+    },
+    Migration {
+        version: 83,
+        description: "Recipe Servings",
+        up: "
+            -- recipe used to be created only at startup, so recipes from
+            -- before scaling existed have no servings
+            CREATE TABLE IF NOT EXISTS recipe (
+                id TEXT PRIMARY KEY,
+                space_id TEXT NOT NULL,
+                note_id TEXT NOT NULL,
+                name TEXT NOT NULL,
+                rating INTEGER NOT NULL,
+                difficulty TEXT NOT NULL,
+                created_at INTEGER NOT NULL
+            );
+            ALTER TABLE recipe ADD COLUMN servings INTEGER NOT NULL DEFAULT 1;
+            ",
+        after_up: None,
+        down: Down::Sql("ALTER TABLE recipe DROP COLUMN servings;"),
+    },
+    Migration {
+        version: 84,
+        description: "RAG Vector Store",
+        up: "
+            -- The vector store used to be created when the RAG pipeline
+            -- first started, so stores from before change tracking lack the
+            -- hash columns
+            CREATE TABLE IF NOT EXISTS note_embeddings (
+                id TEXT PRIMARY KEY,
+                note_id TEXT NOT NULL,
+                chunk_index INTEGER NOT NULL,
+                content TEXT NOT NULL,
+                embedding BLOB,
+                start_offset INTEGER,
+                end_offset INTEGER,
+                metadata TEXT,
+                created_at INTEGER DEFAULT (strftime('%s', 'now')),
+                FOREIGN KEY (note_id) REFERENCES note(id) ON DELETE CASCADE,
+                UNIQUE (note_id, chunk_index)
+            );
+            CREATE VIRTUAL TABLE IF NOT EXISTS note_embeddings_fts USING fts5(
+                content,
+                content=note_embeddings,
+                content_rowid=rowid
+            );
+            CREATE TRIGGER IF NOT EXISTS note_embeddings_ai AFTER INSERT ON note_embeddings BEGIN
+                INSERT INTO note_embeddings_fts(rowid, content) VALUES (new.rowid, new.content);
+            END;
+            CREATE TRIGGER IF NOT EXISTS note_embeddings_ad AFTER DELETE ON note_embeddings BEGIN
+                INSERT INTO note_embeddings_fts(note_embeddings_fts, rowid, content)
+                VALUES ('delete', old.rowid, old.content);
+            END;
+            CREATE TRIGGER IF NOT EXISTS note_embeddings_au AFTER UPDATE ON note_embeddings BEGIN
+                INSERT INTO note_embeddings_fts(note_embeddings_fts, rowid, content)
+                VALUES ('delete', old.rowid, old.content);
+                INSERT INTO note_embeddings_fts(rowid, content) VALUES (new.rowid, new.content);
+            END;
+            -- Lets a refresh keep the embeddings of unchanged chunks
+            ALTER TABLE note_embeddings ADD COLUMN content_hash TEXT;
+            ALTER TABLE note_embeddings ADD COLUMN embedding_version TEXT;
+            ",
+        after_up: None,
+        down: Down::Sql("
+            ALTER TABLE note_embeddings DROP COLUMN embedding_version;
+            ALTER TABLE note_embeddings DROP COLUMN content_hash;
+            "),
+    },
+    Migration {
+        version: 85,
+        description: "Dashboard Stats Previous Week",
+        up: "
+            -- dashboard_stats used to be created only with the materialized
+            -- views, so tables from before week-over-week deltas lack these
+            CREATE TABLE IF NOT EXISTS dashboard_stats (
+                space_id TEXT PRIMARY KEY,
+                total_notes INTEGER DEFAULT 0,
+                total_tasks INTEGER DEFAULT 0,
+                completed_tasks INTEGER DEFAULT 0,
+                pending_tasks INTEGER DEFAULT 0,
+                overdue_tasks INTEGER DEFAULT 0,
+                total_projects INTEGER DEFAULT 0,
+                active_habits INTEGER DEFAULT 0,
+                streak_days INTEGER DEFAULT 0,
+                notes_this_week INTEGER DEFAULT 0,
+                tasks_completed_this_week INTEGER DEFAULT 0,
+                last_updated INTEGER DEFAULT (strftime('%s', 'now'))
+            );
+            ALTER TABLE dashboard_stats ADD COLUMN notes_previous_week INTEGER DEFAULT 0;
+            ALTER TABLE dashboard_stats ADD COLUMN tasks_completed_previous_week INTEGER DEFAULT 0;
+            ",
+        after_up: None,
+        down: Down::Sql("
+            ALTER TABLE dashboard_stats DROP COLUMN tasks_completed_previous_week;
+            ALTER TABLE dashboard_stats DROP COLUMN notes_previous_week;
+            "),
+    },
+];
+
+/// The version a fully migrated vault is at
+pub fn latest_version() -> i64 {
+    MIGRATIONS.last().map_or(0, |m| m.version)
+}
+
+fn find_migration(version: i64) -> &'static Migration {
+    &MIGRATIONS[(version - 1) as usize]
+}
+
+/// Create `schema_version`, adding the timing columns to vaults created
+/// before they existed
+fn ensure_version_table(conn: &Connection) -> Result<(), rusqlite::Error> {
+    conn.execute_batch(
+        "
+        CREATE TABLE IF NOT EXISTS schema_version (
+            version INTEGER PRIMARY KEY,
+            applied_at INTEGER,
+            duration_ms INTEGER
+        );
+        ",
+    )?;
+    let has_timing: bool = conn.query_row(
+        "SELECT EXISTS(SELECT 1 FROM pragma_table_info('schema_version') WHERE name = 'applied_at')",
+        [],
+        |row| row.get(0),
+    )?;
+    if !has_timing {
+        conn.execute_batch(
+            "
+            ALTER TABLE schema_version ADD COLUMN applied_at INTEGER;
+            ALTER TABLE schema_version ADD COLUMN duration_ms INTEGER;
+            ",
+        )?;
+    }
+    Ok(())
+}
+
+/// The schema version of the vault, 0 if it was never migrated
+pub fn current_version(conn: &Connection) -> Result<i64, rusqlite::Error> {
+    let exists: bool = conn.query_row(
+        "SELECT EXISTS(SELECT 1 FROM sqlite_master WHERE type = 'table' AND name = 'schema_version')",
+        [],
+        |row| row.get(0),
+    )?;
+    if !exists {
+        return Ok(0);
+    }
+    conn.query_row(
+        "SELECT COALESCE(MAX(version), 0) FROM schema_version",
+        [],
+        |row| row.get(0),
+    )
+}
+
+/// Recorded migrations, oldest first
+pub fn applied_migrations(conn: &Connection) -> Result<Vec<AppliedMigration>, rusqlite::Error> {
+    ensure_version_table(conn)?;
+    let mut stmt = conn
+        .prepare("SELECT version, applied_at, duration_ms FROM schema_version ORDER BY version")?;
+    let rows = stmt.query_map([], |row| {
+        Ok(AppliedMigration {
+            version: row.get(0)?,
+            applied_at: row.get(1)?,
+            duration_ms: row.get(2)?,
+        })
+    })?;
+    rows.collect()
+}
+
+/// The migrations [`migrate`] would apply, in order
+pub fn migrate_plan(conn: &Connection) -> Result<Vec<PlannedMigration>, MigrationError> {
+    migrate_plan_to(conn, latest_version())
+}
+
+/// The steps needed to reach `target_version`: migrations to apply when the
+/// target is newer, migrations to revert (newest first) when it's older
+pub fn migrate_plan_to(
+    conn: &Connection,
+    target_version: i64,
+) -> Result<Vec<PlannedMigration>, MigrationError> {
+    if !(0..=latest_version()).contains(&target_version) {
+        return Err(MigrationError::UnknownVersion(target_version));
+    }
+    let current = current_version(conn)?;
+    if current > latest_version() {
+        return Err(MigrationError::VaultTooNew {
+            vault_version: current,
+            supported: latest_version(),
+        });
+    }
+
+    let step = |version: i64, direction| {
+        let migration = find_migration(version);
+        PlannedMigration {
+            version,
+            description: migration.description.to_string(),
+            direction,
+            reversible: matches!(migration.down, Down::Sql(_)),
+        }
+    };
+    let plan = if target_version >= current {
+        (current + 1..=target_version)
+            .map(|v| step(v, MigrationDirection::Up))
+            .collect()
+    } else {
+        (target_version + 1..=current)
+            .rev()
+            .map(|v| step(v, MigrationDirection::Down))
+            .collect()
+    };
+    Ok(plan)
+}
+
+/// Run database migrations to update the schema to the latest version.
+/// This function is idempotent and checks the current version before applying changes.
+pub fn migrate(conn: &mut Connection) -> Result<(), DbError> {
+    log::info!("[db] Starting migration");
+    let plan = migrate_plan(conn).map_err(|e| DbError::Message(e.to_string()))?;
+    log::info!("[db] Current schema version: {}", current_version(conn)?);
+
+    let tx = conn.transaction()?;
+    run_steps(&tx, &plan)?;
+    init_unversioned_tables(&tx)?;
+    tx.commit()?;
+    log::info!("[db] Migration finished");
+    Ok(())
+}
+
+/// Migrate to `target_version`, reverting newer migrations if needed.
+///
+/// Before anything changes the whole vault is copied to `backup_path`, which
+/// must not exist yet. Nothing is touched if the plan would revert an
+/// irreversible migration, and all steps run in one transaction.
+pub fn migrate_to(
+    conn: &mut Connection,
+    target_version: i64,
+    backup_path: &Path,
+) -> Result<MigrationReport, MigrationError> {
+    let from_version = current_version(conn)?;
+    let steps = migrate_plan_to(conn, target_version)?;
+    let mut report = MigrationReport {
+        from_version,
+        to_version: target_version,
+        steps,
+        backup_path: None,
+    };
+    if report.steps.is_empty() {
+        return Ok(report);
+    }
+    for step in &report.steps {
+        if let (MigrationDirection::Down, Down::Irreversible(reason)) =
+            (step.direction, find_migration(step.version).down)
+        {
+            return Err(MigrationError::Irreversible {
+                version: step.version,
+                reason,
+            });
+        }
+    }
+
+    snapshot_database(conn, backup_path).map_err(MigrationError::Backup)?;
+    report.backup_path = Some(backup_path.to_path_buf());
+    log::info!(
+        "[db] Migrating from v{} to v{}, backup at {}",
+        from_version,
+        target_version,
+        backup_path.display()
+    );
+
+    let tx = conn.transaction()?;
+    run_steps(&tx, &report.steps)?;
+    if target_version == latest_version() {
+        init_unversioned_tables(&tx)?;
+    }
+    tx.commit()?;
+    Ok(report)
+}
+
+fn run_steps(tx: &Transaction, steps: &[PlannedMigration]) -> Result<(), DbError> {
+    ensure_version_table(tx)?;
+    for step in steps {
+        let migration = find_migration(step.version);
+        let started = Instant::now();
+        match step.direction {
+            MigrationDirection::Up => {
+                log::info!(
+                    "[db] Applying migration v{}: {}",
+                    migration.version,
+                    migration.description
+                );
+                tx.execute_batch(migration.up)?;
+                if let Some(after_up) = migration.after_up {
+                    after_up(tx)?;
+                }
+                tx.execute(
+                    "INSERT INTO schema_version (version, applied_at, duration_ms) VALUES (?1, ?2, ?3)",
+                    params![
+                        migration.version,
+                        chrono::Utc::now().timestamp(),
+                        started.elapsed().as_millis() as i64
+                    ],
+                )?;
+            }
+            MigrationDirection::Down => {
+                let Down::Sql(down) = migration.down else {
+                    return Err(DbError::Message(format!(
+                        "Migration v{} can't be reverted",
+                        migration.version
+                    )));
+                };
+                log::info!(
+                    "[db] Reverting migration v{}: {}",
+                    migration.version,
+                    migration.description
+                );
+                tx.execute_batch(down)?;
+                tx.execute(
+                    "DELETE FROM schema_version WHERE version = ?1",
+                    [migration.version],
+                )?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Tables created outside the versioned migrations
+fn init_unversioned_tables(tx: &Transaction) -> Result<(), DbError> {
+    // Run Personal Modes Initialization (Idempotent)
+    crate::personal_modes::init_personal_modes_tables(tx)?;
+
+    // OCR queue and its search index (Idempotent)
+    crate::ocr::init_ocr_tables(tx).map_err(|e| DbError::Message(e.to_string()))?;
     Ok(())
 }
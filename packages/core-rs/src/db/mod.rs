@@ -1,6 +1,12 @@
+pub mod bulk;
+pub mod integrity;
 pub mod materialized_views;
 pub mod migrations;
+pub mod pool;
 pub mod pragma_tuning;
+pub mod query_stats;
+pub mod settings;
+pub mod storage;
 pub mod vault_backup;
 
 use chrono;
@@ -17,6 +23,19 @@ pub enum DbError {
     Message(String),
     #[error("Serde JSON error: {0}")]
     SerdeJson(#[from] serde_json::Error),
+    #[error(transparent)]
+    PermissionDenied(#[from] crate::permission::PermissionDenied),
+    #[error(transparent)]
+    QuerySyntax(#[from] crate::search::QuerySyntaxError),
+    #[error(transparent)]
+    InvalidId(#[from] crate::ids::InvalidId),
+    #[error(transparent)]
+    LockedByOther(#[from] crate::collaboration::LockedByOther),
+    #[error("Not found: {entity} {id}")]
+    NotFound { entity: &'static str, id: String },
+    /// The row exists but is locked against this change
+    #[error("Locked: {entity} {id}")]
+    Locked { entity: &'static str, id: String },
 }
 
 /// Get a settings value by key
@@ -55,30 +74,27 @@ pub fn set_setting(
     Ok(())
 }
 
-/// Get sync port setting (defaults to 8765)
-/// Validates port is within valid TCP range (1-65535)
-pub fn get_sync_port(conn: &Connection) -> Result<u16, DbError> {
-    let port = get_setting_int(conn, "sync_port", 8765)?;
-
-    // Validate port is within valid TCP range
-    if !(1..=65535).contains(&port) {
-        return Err(DbError::Message(format!(
-            "Invalid sync_port value: {} (must be between 1 and 65535)",
-            port
-        )));
-    }
+/// Whether a table exists, for features whose tables a vault may not have
+/// (optional FTS indexes, tables created on first use)
+pub(crate) fn table_exists(conn: &Connection, name: &str) -> Result<bool> {
+    conn.prepare_cached(
+        "SELECT EXISTS (SELECT 1 FROM sqlite_master WHERE type = 'table' AND name = ?1)",
+    )?
+    .query_row([name], |row| row.get(0))
+}
 
-    Ok(port as u16)
+/// `count` comma-separated `?` parameters, for an `IN (...)` list
+pub(crate) fn placeholders(count: usize) -> String {
+    vec!["?"; count].join(", ")
 }
 
-/// Set sync port setting
-pub fn set_sync_port(conn: &Connection, port: u16) -> Result<(), DbError> {
-    set_setting(
-        conn,
-        "sync_port",
-        &port.to_string(),
-        Some("Port for device-to-device sync"),
-    )
+/// Like [`placeholders`] but numbered from `first`, for a list used more
+/// than once in a statement
+pub(crate) fn numbered_placeholders(first: usize, count: usize) -> String {
+    (first..first + count)
+        .map(|i| format!("?{}", i))
+        .collect::<Vec<_>>()
+        .join(", ")
 }
 
 /// Run database migrations to update the schema to the latest version.
@@ -117,17 +133,52 @@ pub fn init_llm_tables(_conn: &Connection) -> Result<(), DbError> {
 // Re-export vault backup functions
 pub use vault_backup::{
     get_vault_backup, has_valid_backup, init_vault_backup_table, recover_from_backup,
-    store_vault_backup, verify_vault_backup, VaultConfigBackup,
+    snapshot_database, store_vault_backup, verify_vault_backup, VaultConfigBackup,
+};
+
+// Re-export migration planning
+pub use migrations::{
+    migrate_plan, migrate_to, MigrationDirection, MigrationError, MigrationReport, PlannedMigration,
 };
 
+// Re-export the encrypted connection pool
+pub use pool::{EncryptedConnectionManager, PoolConfig, PoolError, PoolHealth, PoolMonitor};
+
 // Re-export pragma tuning
 pub use pragma_tuning::{DatabaseStats, DeviceProfile, PragmaConfig, PragmaTuner};
 
+// Re-export query statistics
+pub use query_stats::{
+    apply_query_stats_settings, get_query_stats, instrument_connection, reset_query_stats,
+    QueryStat, QueryStatsSettings,
+};
+
+// Re-export bulk write mode
+pub use bulk::with_bulk_import;
+
+// Re-export the vault integrity check
+pub use integrity::{
+    check_vault, check_vault_with_mode, repair_vault, IntegrityCheckMode, IntegrityIssue,
+    IntegrityIssueKind, IssueSeverity, VaultIntegrityReport, VaultRepairReport,
+};
+
+// Re-export the storage report
+pub use storage::{
+    get_storage_report, vacuum_vault, CategoryUsage, SpaceUsage, StorageCategory, StorageError,
+    StorageReport, TableUsage, VacuumResult,
+};
+
 // Re-export materialized views
 pub use materialized_views::{
     get_dashboard_stats, init_materialized_views, refresh_all_dashboard_stats,
     refresh_dashboard_stats, DashboardStats,
 };
 
+// Re-export typed settings
+pub use settings::{
+    list_all_settings, load_settings, load_stored_settings, reset_settings, save_settings,
+    Settings, SettingsEntry, SettingsError, SettingsWatcher, SyncSettings,
+};
+
 /// Type alias for r2d2 connection pool with Sqlite
 pub type DbPool = r2d2::Pool<r2d2_sqlite::SqliteConnectionManager>;
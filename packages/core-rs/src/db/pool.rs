@@ -0,0 +1,280 @@
+//! Encrypted Connection Pool
+//!
+//! r2d2 connection manager for an encrypted vault database. Every new
+//! connection is keyed with the vault's DEK and tuned with the configured
+//! [`PragmaConfig`]. The WAL file is checkpointed opportunistically when
+//! connections are checked out, so it can't grow without bound during long
+//! sessions. Connections cache their prepared statements and report query
+//! timings when [`super::QueryStatsSettings`] enables it, and attach the
+//! vault's read cache so saves made through the pool keep it current.
+
+use super::pragma_tuning::{PragmaConfig, PragmaTuner};
+use super::query_stats::instrument_connection;
+use crate::vault::readonly::attach_existing_read_cache;
+use crate::vault::AutoLockGuard;
+use r2d2::{ManageConnection, Pool};
+use rusqlite::Connection;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use thiserror::Error;
+use zeroize::Zeroizing;
+
+#[derive(Error, Debug)]
+pub enum PoolError {
+    /// The vault locked and its key was wiped; no new connections until it
+    /// is unlocked again
+    #[error("Vault is locked; unlock it to continue")]
+    Locked,
+    #[error("{0}")]
+    Sqlite(#[from] rusqlite::Error),
+    #[error("Connection setup failed: {0}")]
+    Pragma(String),
+}
+
+#[derive(Debug, Clone)]
+pub struct PoolConfig {
+    /// Applied to every new connection. Foreign keys are always enabled and
+    /// `kdf_iter` is ignored: the vault's key settings are fixed.
+    pub pragmas: PragmaConfig,
+    /// Checkpoint as soon as the WAL grows past this size
+    pub wal_checkpoint_bytes: u64,
+    /// Checkpoint a non-empty WAL at least this often
+    pub checkpoint_interval: Duration,
+}
+
+impl Default for PoolConfig {
+    fn default() -> Self {
+        Self {
+            pragmas: PragmaConfig::default(),
+            wal_checkpoint_bytes: 16 * 1024 * 1024,
+            checkpoint_interval: Duration::from_secs(5 * 60),
+        }
+    }
+}
+
+impl PoolConfig {
+    /// Pragmas for the device this runs on
+    pub fn auto_detect() -> Self {
+        Self {
+            pragmas: PragmaConfig::for_profile(PragmaTuner::detect_profile()),
+            ..Self::default()
+        }
+    }
+}
+
+/// Pool state for display on the dashboard
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PoolHealth {
+    /// Open connections, idle or in use
+    pub connections: u32,
+    pub idle: u32,
+    pub wal_size_bytes: u64,
+    /// Unix timestamp of the last completed checkpoint
+    pub last_checkpoint: Option<i64>,
+}
+
+/// WAL bookkeeping shared by the manager and whoever reports pool health
+#[derive(Debug)]
+pub struct PoolMonitor {
+    wal_path: PathBuf,
+    wal_checkpoint_bytes: u64,
+    checkpoint_interval: Duration,
+    busy_timeout: Duration,
+    /// When the interval last restarted (pool creation or the last completed
+    /// checkpoint) and the Unix time of the last completed checkpoint
+    last_checkpoint: Mutex<(Instant, Option<i64>)>,
+    checkpointing: AtomicBool,
+}
+
+impl PoolMonitor {
+    fn new(db_path: &Path, config: &PoolConfig) -> Self {
+        let mut wal_path = db_path.as_os_str().to_owned();
+        wal_path.push("-wal");
+        Self {
+            wal_path: PathBuf::from(wal_path),
+            wal_checkpoint_bytes: config.wal_checkpoint_bytes,
+            checkpoint_interval: config.checkpoint_interval,
+            busy_timeout: Duration::from_millis(config.pragmas.busy_timeout.into()),
+            last_checkpoint: Mutex::new((Instant::now(), None)),
+            checkpointing: AtomicBool::new(false),
+        }
+    }
+
+    pub fn wal_size_bytes(&self) -> u64 {
+        std::fs::metadata(&self.wal_path).map_or(0, |m| m.len())
+    }
+
+    pub fn last_checkpoint(&self) -> Option<i64> {
+        self.last_checkpoint.lock().ok().and_then(|last| last.1)
+    }
+
+    pub fn health<M: ManageConnection>(&self, pool: &Pool<M>) -> PoolHealth {
+        let state = pool.state();
+        PoolHealth {
+            connections: state.connections,
+            idle: state.idle_connections,
+            wal_size_bytes: self.wal_size_bytes(),
+            last_checkpoint: self.last_checkpoint(),
+        }
+    }
+
+    /// Checkpoint and truncate the WAL, waiting for other connections up to
+    /// the busy timeout. Returns whether every frame was checkpointed.
+    pub fn checkpoint(&self, conn: &Connection) -> Result<bool, rusqlite::Error> {
+        let (busy, log_frames, checkpointed): (i64, i64, i64) =
+            conn.query_row("PRAGMA wal_checkpoint(TRUNCATE)", [], |row| {
+                Ok((row.get(0)?, row.get(1)?, row.get(2)?))
+            })?;
+        let complete = busy == 0 && log_frames == checkpointed;
+        if complete {
+            if let Ok(mut last) = self.last_checkpoint.lock() {
+                *last = (Instant::now(), Some(chrono::Utc::now().timestamp()));
+            }
+        }
+        log::debug!(
+            "[db] WAL checkpoint: {} of {} frames, busy={}",
+            checkpointed,
+            log_frames,
+            busy
+        );
+        Ok(complete)
+    }
+
+    fn checkpoint_due(&self) -> bool {
+        let wal_size = self.wal_size_bytes();
+        if wal_size == 0 {
+            return false;
+        }
+        if wal_size >= self.wal_checkpoint_bytes {
+            return true;
+        }
+        match self.last_checkpoint.lock() {
+            Ok(last) => last.0.elapsed() >= self.checkpoint_interval,
+            Err(_) => false,
+        }
+    }
+
+    /// Checkpoint if due, without waiting on other connections. A busy
+    /// checkpoint is retried on a later checkout.
+    fn maybe_checkpoint(&self, conn: &Connection) {
+        if !self.checkpoint_due() || self.checkpointing.swap(true, Ordering::AcqRel) {
+            return;
+        }
+        let result = conn
+            .busy_timeout(Duration::ZERO)
+            .and_then(|_| self.checkpoint(conn));
+        if let Err(e) = result {
+            log::warn!("[db] WAL checkpoint failed: {}", e);
+        }
+        if let Err(e) = conn.busy_timeout(self.busy_timeout) {
+            log::warn!("[db] Could not restore the busy timeout: {}", e);
+        }
+        self.checkpointing.store(false, Ordering::Release);
+    }
+}
+
+pub struct EncryptedConnectionManager {
+    path: PathBuf,
+    guard: Arc<AutoLockGuard>,
+    pragmas: PragmaConfig,
+    monitor: Arc<PoolMonitor>,
+}
+
+impl EncryptedConnectionManager {
+    pub fn new(path: PathBuf, guard: Arc<AutoLockGuard>) -> Self {
+        Self::with_config(path, guard, PoolConfig::auto_detect())
+    }
+
+    pub fn with_config(path: PathBuf, guard: Arc<AutoLockGuard>, config: PoolConfig) -> Self {
+        let mut pragmas = config.pragmas.clone();
+        pragmas.foreign_keys = true;
+        pragmas.kdf_iter = None;
+        let monitor = Arc::new(PoolMonitor::new(&path, &config));
+        Self {
+            path,
+            guard,
+            pragmas,
+            monitor,
+        }
+    }
+
+    /// Keep a handle before building the pool to report its health later
+    pub fn monitor(&self) -> Arc<PoolMonitor> {
+        self.monitor.clone()
+    }
+}
+
+impl ManageConnection for EncryptedConnectionManager {
+    type Connection = Connection;
+    type Error = PoolError;
+
+    fn connect(&self) -> Result<Self::Connection, Self::Error> {
+        let dek = self.guard.dek().map_err(|_| PoolError::Locked)?;
+        let conn = Connection::open(&self.path)?;
+
+        // The key has to be set before anything else touches the database
+        let key_hex = Zeroizing::new(hex::encode(*dek));
+        let keying_sql = Zeroizing::new(format!(
+            r#"
+            PRAGMA kdf_iter = 256000;
+            PRAGMA cipher_hmac_algorithm = HMAC_SHA512;
+            PRAGMA cipher_kdf_algorithm = PBKDF2_HMAC_SHA512;
+            PRAGMA key = "x'{}'";
+            "#,
+            key_hex.as_str()
+        ));
+        conn.execute_batch(&keying_sql)?;
+
+        PragmaTuner::new(self.pragmas.clone()).apply(&conn)?;
+
+        // Without these, cascades silently stop working and concurrent
+        // commands fail with SQLITE_BUSY instead of waiting
+        let foreign_keys: i64 = conn.query_row("PRAGMA foreign_keys", [], |row| row.get(0))?;
+        if foreign_keys != 1 {
+            return Err(PoolError::Pragma("foreign_keys is off".to_string()));
+        }
+        let busy_timeout: u32 = conn.query_row("PRAGMA busy_timeout", [], |row| row.get(0))?;
+        if busy_timeout != self.pragmas.busy_timeout {
+            return Err(PoolError::Pragma(format!(
+                "busy_timeout is {} instead of {}",
+                busy_timeout, self.pragmas.busy_timeout
+            )));
+        }
+
+        // Without the cache's triggers a note locked through the pool would
+        // keep its plaintext preview there
+        if let Some(vault_dir) = self.path.parent() {
+            if let Err(e) = attach_existing_read_cache(&conn, vault_dir, &dek) {
+                log::warn!("[db] Read cache not attached: {}", e);
+            }
+        }
+
+        instrument_connection(&conn);
+        Ok(conn)
+    }
+
+    fn is_valid(&self, conn: &mut Self::Connection) -> Result<(), Self::Error> {
+        if self.guard.is_locked() {
+            return Err(PoolError::Locked);
+        }
+        conn.execute_batch("SELECT 1")?;
+        self.monitor.maybe_checkpoint(conn);
+        Ok(())
+    }
+
+    fn has_broken(&self, _conn: &mut Self::Connection) -> bool {
+        false
+    }
+}
+
+impl fmt::Debug for EncryptedConnectionManager {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("EncryptedConnectionManager")
+            .field("path", &self.path)
+            .finish()
+    }
+}
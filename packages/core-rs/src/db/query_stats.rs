@@ -0,0 +1,149 @@
+//! Query Statistics
+//!
+//! Pooled connections keep a prepared statement cache so hot queries are
+//! only compiled once per connection, and report every finished statement
+//! to a process-wide registry. Recording is off until
+//! [`QueryStatsSettings::enabled`] is turned on; while it is, the diagnostics
+//! screen can list how often each query ran and how long it took, and
+//! queries slower than the threshold are logged.
+
+use super::settings::Settings;
+use rusqlite::trace::{TraceEvent, TraceEventCodes};
+use rusqlite::Connection;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::Duration;
+
+/// Prepared statements each pooled connection keeps compiled
+pub const STATEMENT_CACHE_CAPACITY: usize = 64;
+
+/// Distinct queries the registry tracks; queries built with varying SQL
+/// shouldn't grow it without bound
+const MAX_TRACKED_QUERIES: usize = 512;
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+static SLOW_QUERY_MICROS: AtomicU64 = AtomicU64::new(100_000);
+
+lazy_static::lazy_static! {
+    static ref REGISTRY: Mutex<HashMap<String, QueryStat>> = Mutex::new(HashMap::new());
+}
+
+/// Whether queries are recorded for diagnostics
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct QueryStatsSettings {
+    pub enabled: bool,
+    /// Queries running at least this long are logged
+    pub slow_query_ms: u64,
+}
+
+impl Default for QueryStatsSettings {
+    fn default() -> Self {
+        QueryStatsSettings {
+            enabled: false,
+            slow_query_ms: 100,
+        }
+    }
+}
+
+impl Settings for QueryStatsSettings {
+    const KEY: &'static str = "diagnostics.query_stats";
+    const DESCRIPTION: &'static str = "Query timing for diagnostics";
+
+    fn validate(&self) -> Result<(), String> {
+        if self.slow_query_ms == 0 {
+            return Err("slow_query_ms must be at least 1".to_string());
+        }
+        Ok(())
+    }
+}
+
+/// How often one query ran and how long it took
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct QueryStat {
+    /// The SQL with whitespace collapsed
+    pub sql: String,
+    pub count: u64,
+    pub total_micros: u64,
+    pub max_micros: u64,
+    /// Runs at or above the slow-query threshold
+    pub slow_count: u64,
+}
+
+/// Turn recording on or off and set the slow-query threshold. Applies to
+/// every connection in the process.
+pub fn apply_query_stats_settings(settings: &QueryStatsSettings) {
+    SLOW_QUERY_MICROS.store(
+        settings.slow_query_ms.saturating_mul(1_000),
+        Ordering::Relaxed,
+    );
+    ENABLED.store(settings.enabled, Ordering::Relaxed);
+}
+
+/// Give `conn` the statement cache and report its statements to the
+/// registry. Pooled connections get this when they're opened.
+pub fn instrument_connection(conn: &Connection) {
+    conn.set_prepared_statement_cache_capacity(STATEMENT_CACHE_CAPACITY);
+    conn.trace_v2(TraceEventCodes::SQLITE_TRACE_PROFILE, Some(on_trace));
+}
+
+fn on_trace(event: TraceEvent<'_>) {
+    if !ENABLED.load(Ordering::Relaxed) {
+        return;
+    }
+    if let TraceEvent::Profile(stmt, duration) = event {
+        record_query(&stmt.sql(), duration);
+    }
+}
+
+fn record_query(sql: &str, duration: Duration) {
+    let sql = sql.split_whitespace().collect::<Vec<_>>().join(" ");
+    let micros = u64::try_from(duration.as_micros()).unwrap_or(u64::MAX);
+    let slow = micros >= SLOW_QUERY_MICROS.load(Ordering::Relaxed);
+    if slow {
+        log::warn!("[db] Slow query ({} ms): {}", micros / 1_000, sql);
+    }
+
+    let Ok(mut registry) = REGISTRY.lock() else {
+        return;
+    };
+    if !registry.contains_key(&sql) && registry.len() >= MAX_TRACKED_QUERIES {
+        return;
+    }
+    let stat = registry.entry(sql.clone()).or_insert_with(|| QueryStat {
+        sql,
+        count: 0,
+        total_micros: 0,
+        max_micros: 0,
+        slow_count: 0,
+    });
+    stat.count += 1;
+    stat.total_micros = stat.total_micros.saturating_add(micros);
+    stat.max_micros = stat.max_micros.max(micros);
+    if slow {
+        stat.slow_count += 1;
+    }
+}
+
+/// Recorded queries, most total time first
+pub fn get_query_stats() -> Vec<QueryStat> {
+    let mut stats: Vec<QueryStat> = match REGISTRY.lock() {
+        Ok(registry) => registry.values().cloned().collect(),
+        Err(_) => Vec::new(),
+    };
+    stats.sort_by(|a, b| {
+        b.total_micros
+            .cmp(&a.total_micros)
+            .then_with(|| a.sql.cmp(&b.sql))
+    });
+    stats
+}
+
+/// Forget everything recorded so far
+pub fn reset_query_stats() {
+    if let Ok(mut registry) = REGISTRY.lock() {
+        registry.clear();
+    }
+}
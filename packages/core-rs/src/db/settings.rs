@@ -0,0 +1,322 @@
+//! Typed Settings
+//!
+//! Each domain keeps its settings in one struct that implements [`Settings`]
+//! and is stored as JSON in the `settings` table under a `<domain>.<name>`
+//! key. Loading falls back to the struct's default when nothing is stored,
+//! saving validates first, and a save that changes the stored value emits
+//! [`CoreEvent::SettingsChanged`] so long-running components can reload it
+//! through a [`SettingsWatcher`].
+//!
+//! A struct whose stored shape changes bumps [`Settings::VERSION`] and
+//! converts older values in [`Settings::upgrade`]; values are upgraded as
+//! they're loaded and written back in the new shape on the next save.
+
+use super::query_stats::QueryStatsSettings;
+use super::DbError;
+use crate::dashboard::DashboardConfig;
+use crate::events::{
+    emit, register_sink, unregister_sink, CoreEvent, EmittedEvent, EventSink, SinkId,
+};
+use crate::note_session::EditorSettings;
+use crate::ocr::OcrSettings;
+use crate::project::ProjectHealthConfig;
+use crate::social::BackupPolicy;
+use rusqlite::{params, Connection, OptionalExtension};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::fmt::Debug;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum SettingsError {
+    #[error("Rusqlite error: {0}")]
+    Rusqlite(#[from] rusqlite::Error),
+    #[error("Database error: {0}")]
+    Db(#[from] DbError),
+    #[error("Setting {key} doesn't match its type: {source}")]
+    Malformed {
+        key: &'static str,
+        source: serde_json::Error,
+    },
+    #[error("Invalid {key}: {reason}")]
+    Invalid { key: &'static str, reason: String },
+    #[error("Setting {key} was saved by a newer version (schema {version})")]
+    UnsupportedVersion { key: &'static str, version: u32 },
+}
+
+/// A group of settings stored under one key
+pub trait Settings: Serialize + DeserializeOwned + Default + PartialEq + Debug {
+    /// Key in the `settings` table, `<domain>.<name>`
+    const KEY: &'static str;
+    /// What the settings are for, shown by diagnostics
+    const DESCRIPTION: &'static str;
+    /// Version of the stored shape
+    const VERSION: u32 = 1;
+
+    /// Why the value can't be saved, if it can't
+    fn validate(&self) -> Result<(), String> {
+        Ok(())
+    }
+
+    /// Convert a value stored by an older `version` to the current shape
+    fn upgrade(_version: u32, value: Value) -> Result<Value, String> {
+        Ok(value)
+    }
+}
+
+/// Options of device-to-device sync
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SyncSettings {
+    /// TCP port the sync server listens on
+    pub port: u16,
+    /// Accept protocol 1 peers (the current mobile app), which sync without
+    /// transport encryption. Only trusted devices are served either way.
+    pub allow_legacy_protocol: bool,
+}
+
+impl Default for SyncSettings {
+    fn default() -> Self {
+        SyncSettings {
+            port: 8765,
+            allow_legacy_protocol: false,
+        }
+    }
+}
+
+impl Settings for SyncSettings {
+    const KEY: &'static str = "sync.settings";
+    const DESCRIPTION: &'static str = "Device-to-device sync";
+
+    fn validate(&self) -> Result<(), String> {
+        if self.port == 0 {
+            return Err("port must be between 1 and 65535".to_string());
+        }
+        Ok(())
+    }
+}
+
+/// A stored row, before it's decoded
+struct StoredRow {
+    value: String,
+    schema_version: Option<u32>,
+    updated_at: i64,
+}
+
+fn stored_row(conn: &Connection, key: &str) -> Result<Option<StoredRow>, SettingsError> {
+    Ok(conn
+        .query_row(
+            "SELECT value, schema_version, updated_at FROM settings WHERE key = ?1",
+            [key],
+            |row| {
+                Ok(StoredRow {
+                    value: row.get(0)?,
+                    schema_version: row.get(1)?,
+                    updated_at: row.get(2)?,
+                })
+            },
+        )
+        .optional()?)
+}
+
+/// Decode and check a stored value. Values are checked on the way out too,
+/// since they may predate a rule or have been written by hand.
+fn decode<S: Settings>(row: &StoredRow) -> Result<S, SettingsError> {
+    let malformed = |source| SettingsError::Malformed {
+        key: S::KEY,
+        source,
+    };
+    let invalid = |reason| SettingsError::Invalid {
+        key: S::KEY,
+        reason,
+    };
+    let version = row.schema_version.unwrap_or(1);
+    if version > S::VERSION {
+        return Err(SettingsError::UnsupportedVersion {
+            key: S::KEY,
+            version,
+        });
+    }
+    let mut value: Value = serde_json::from_str(&row.value).map_err(malformed)?;
+    if version < S::VERSION {
+        value = S::upgrade(version, value).map_err(invalid)?;
+    }
+    let settings: S = serde_json::from_value(value).map_err(malformed)?;
+    settings.validate().map_err(invalid)?;
+    Ok(settings)
+}
+
+/// The stored settings, or `None` when they were never saved
+pub fn load_stored_settings<S: Settings>(conn: &Connection) -> Result<Option<S>, SettingsError> {
+    stored_row(conn, S::KEY)?
+        .map(|row| decode(&row))
+        .transpose()
+}
+
+/// The stored settings, or their defaults when they were never saved
+pub fn load_settings<S: Settings>(conn: &Connection) -> Result<S, SettingsError> {
+    Ok(load_stored_settings(conn)?.unwrap_or_default())
+}
+
+/// Validate and store `settings`, notifying watchers if that changes them
+pub fn save_settings<S: Settings>(conn: &Connection, settings: &S) -> Result<(), SettingsError> {
+    settings
+        .validate()
+        .map_err(|reason| SettingsError::Invalid {
+            key: S::KEY,
+            reason,
+        })?;
+    let json = serde_json::to_string(settings).map_err(|source| SettingsError::Malformed {
+        key: S::KEY,
+        source,
+    })?;
+    let unchanged = stored_row(conn, S::KEY)?
+        .is_some_and(|row| row.value == json && row.schema_version == Some(S::VERSION));
+    if unchanged {
+        return Ok(());
+    }
+
+    let now = chrono::Utc::now().timestamp();
+    conn.execute(
+        "INSERT INTO settings (key, value, description, schema_version, updated_at, created_at)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?5)
+         ON CONFLICT(key) DO UPDATE SET
+             value = excluded.value,
+             description = excluded.description,
+             schema_version = excluded.schema_version,
+             updated_at = excluded.updated_at",
+        params![S::KEY, json, S::DESCRIPTION, S::VERSION, now],
+    )?;
+    log::info!("[settings] Saved {}", S::KEY);
+    emit(CoreEvent::SettingsChanged {
+        key: S::KEY.to_string(),
+    });
+    Ok(())
+}
+
+/// Remove the stored settings so the defaults apply again
+pub fn reset_settings<S: Settings>(conn: &Connection) -> Result<(), SettingsError> {
+    if conn.execute("DELETE FROM settings WHERE key = ?1", [S::KEY])? > 0 {
+        log::info!("[settings] Reset {} to defaults", S::KEY);
+        emit(CoreEvent::SettingsChanged {
+            key: S::KEY.to_string(),
+        });
+    }
+    Ok(())
+}
+
+/// One group of settings as shown by diagnostics and exports
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SettingsEntry {
+    pub key: String,
+    pub description: String,
+    pub version: u32,
+    /// The settings in effect; the raw stored value when `error` is set
+    pub value: Value,
+    /// Nothing is stored, so the defaults apply
+    pub is_default: bool,
+    pub updated_at: Option<i64>,
+    /// Why the stored value can't be loaded
+    pub error: Option<String>,
+}
+
+fn describe<S: Settings>(conn: &Connection) -> Result<SettingsEntry, SettingsError> {
+    let malformed = |source| SettingsError::Malformed {
+        key: S::KEY,
+        source,
+    };
+    let row = stored_row(conn, S::KEY)?;
+    let (value, error) = match &row {
+        None => (serde_json::to_value(S::default()).map_err(malformed)?, None),
+        Some(row) => match decode::<S>(row) {
+            Ok(settings) => (serde_json::to_value(settings).map_err(malformed)?, None),
+            Err(e) => (
+                serde_json::from_str(&row.value)
+                    .unwrap_or_else(|_| Value::String(row.value.clone())),
+                Some(e.to_string()),
+            ),
+        },
+    };
+    Ok(SettingsEntry {
+        key: S::KEY.to_string(),
+        description: S::DESCRIPTION.to_string(),
+        version: S::VERSION,
+        value,
+        is_default: row.is_none(),
+        updated_at: row.map(|row| row.updated_at),
+        error,
+    })
+}
+
+type Describe = fn(&Connection) -> Result<SettingsEntry, SettingsError>;
+
+/// Every settings struct of the app
+const REGISTRY: &[Describe] = &[
+    describe::<BackupPolicy>,
+    describe::<DashboardConfig>,
+    describe::<EditorSettings>,
+    describe::<OcrSettings>,
+    describe::<QueryStatsSettings>,
+    describe::<ProjectHealthConfig>,
+    describe::<SyncSettings>,
+];
+
+/// Every group of settings with the value in effect, ordered by key. A
+/// stored value that can't be loaded is reported in its entry rather than
+/// failing the listing.
+pub fn list_all_settings(conn: &Connection) -> Result<Vec<SettingsEntry>, SettingsError> {
+    let mut entries = REGISTRY
+        .iter()
+        .map(|describe| describe(conn))
+        .collect::<Result<Vec<_>, _>>()?;
+    entries.sort_by(|a, b| a.key.cmp(&b.key));
+    Ok(entries)
+}
+
+struct ChangeFlag {
+    key: &'static str,
+    changed: AtomicBool,
+}
+
+impl EventSink for ChangeFlag {
+    fn deliver(&self, event: &EmittedEvent) {
+        if let CoreEvent::SettingsChanged { key } = &event.event {
+            if key == self.key {
+                self.changed.store(true, Ordering::SeqCst);
+            }
+        }
+    }
+}
+
+/// Notices saves of one settings struct, so a long-running component can
+/// reload it between units of work. Stops listening when dropped.
+pub struct SettingsWatcher {
+    flag: Arc<ChangeFlag>,
+    sink_id: SinkId,
+}
+
+impl SettingsWatcher {
+    pub fn new<S: Settings>() -> Self {
+        let flag = Arc::new(ChangeFlag {
+            key: S::KEY,
+            changed: AtomicBool::new(false),
+        });
+        let sink_id = register_sink(flag.clone());
+        SettingsWatcher { flag, sink_id }
+    }
+
+    /// Whether the settings changed since the watcher was created or this
+    /// was last called. Notifications arrive shortly after the save.
+    pub fn changed(&self) -> bool {
+        self.flag.changed.swap(false, Ordering::SeqCst)
+    }
+}
+
+impl Drop for SettingsWatcher {
+    fn drop(&mut self) {
+        unregister_sink(self.sink_id);
+    }
+}
@@ -0,0 +1,528 @@
+//! Vault Storage Report
+//!
+//! Breaks down what takes up room in the vault: per table, per user-facing
+//! category and per space, plus how much of the file is free pages that a
+//! VACUUM would give back. Sizes come from SQLite's `dbstat` table when it's
+//! compiled in, which counts whole pages including indexes; otherwise from the
+//! byte length of every column, which misses indexes and page overhead.
+//!
+//! Attachment contents live outside the database, in the vault's object
+//! store; their recorded sizes are reported as `external_bytes`.
+
+use rusqlite::{Connection, ErrorCode, OptionalExtension};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::time::Duration;
+use thiserror::Error;
+
+/// A VACUUM is recommended once free pages make up this share of the file...
+pub const VACUUM_FREE_RATIO: f64 = 0.2;
+
+/// ...and at least this many bytes
+pub const VACUUM_MIN_FREE_BYTES: u64 = 1024 * 1024;
+
+/// Suffixes of the shadow tables FTS keeps for a virtual table
+const SHADOW_SUFFIXES: [&str; 8] = [
+    "_data",
+    "_idx",
+    "_content",
+    "_docsize",
+    "_config",
+    "_segments",
+    "_segdir",
+    "_stat",
+];
+
+#[derive(Error, Debug)]
+pub enum StorageError {
+    #[error("Database error: {0}")]
+    Database(#[from] rusqlite::Error),
+    #[error("I/O error: {0}")]
+    Io(#[from] std::io::Error),
+    /// Another connection is writing or holds the WAL; try again later
+    #[error("Vault is busy: {0}")]
+    Busy(String),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum StorageCategory {
+    Notes,
+    Attachments,
+    Social,
+    SyncBookkeeping,
+    SearchIndex,
+    /// Note versions, audit and undo journals, run logs
+    History,
+    Other,
+}
+
+impl StorageCategory {
+    pub const ALL: [StorageCategory; 7] = [
+        StorageCategory::Notes,
+        StorageCategory::Attachments,
+        StorageCategory::Social,
+        StorageCategory::SyncBookkeeping,
+        StorageCategory::SearchIndex,
+        StorageCategory::History,
+        StorageCategory::Other,
+    ];
+}
+
+/// Category a table's data belongs to
+pub fn storage_category(table: &str) -> StorageCategory {
+    use StorageCategory::*;
+    match table {
+        "note" | "note_meta" | "note_tags" | "link" | "note_template" => Notes,
+        "blob" | "blob_ref" | "blob_pending_sweep" | "note_attachment" | "ocr_result" => {
+            Attachments
+        }
+        "social_post_fts" => SearchIndex,
+        t if t.starts_with("social_") => Social,
+        "entity_sync_log"
+        | "sync_history"
+        | "sync_conflict"
+        | "sync_shadow"
+        | "sync_rejected_delta"
+        | "sync_state"
+        | "sync_vector_clock" => SyncBookkeeping,
+        t if t.starts_with("caldav_") => SyncBookkeeping,
+        t if t.starts_with("fts_") => SearchIndex,
+        "note_embeddings" | "note_embeddings_fts" | "rag_dirty_note" | "rag_index_state" => {
+            SearchIndex
+        }
+        "note_crdt_update"
+        | "audit_log"
+        | "undo_log"
+        | "form_template_version"
+        | "graph_change_log"
+        | "graph_snapshot"
+        | "project_health_history"
+        | "maintenance_run"
+        | "backup_run"
+        | "selector_rollback" => History,
+        _ => Other,
+    }
+}
+
+/// How table sizes were measured
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SizeSource {
+    /// Pages used by the table and its indexes
+    Dbstat,
+    /// Sum of column lengths; indexes aren't counted
+    ColumnLengths,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TableUsage {
+    pub table: String,
+    pub category: StorageCategory,
+    pub rows: u64,
+    pub bytes: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CategoryUsage {
+    pub category: StorageCategory,
+    pub rows: u64,
+    pub bytes: u64,
+    /// Stored outside the database file, i.e. attachment contents
+    pub external_bytes: u64,
+}
+
+/// Rows of one category in one space, for tables with a `space_id`. Bytes
+/// are always column lengths, whatever the report's `size_source`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SpaceUsage {
+    pub space_id: String,
+    pub category: StorageCategory,
+    pub rows: u64,
+    pub bytes: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct StorageReport {
+    pub size_source: SizeSource,
+    /// Every category, largest first
+    pub categories: Vec<CategoryUsage>,
+    /// Largest first
+    pub tables: Vec<TableUsage>,
+    pub spaces: Vec<SpaceUsage>,
+    pub file_bytes: u64,
+    pub wal_bytes: u64,
+    pub page_size: u64,
+    pub free_pages: u64,
+    /// Enough free pages that [`vacuum_vault`] would shrink the file noticeably
+    pub vacuum_recommended: bool,
+    pub generated_at: i64,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct VacuumResult {
+    pub file_bytes_before: u64,
+    pub file_bytes_after: u64,
+    pub duration_ms: u64,
+}
+
+struct SchemaEntry {
+    kind: String,
+    name: String,
+    table: String,
+    sql: Option<String>,
+}
+
+/// Measure the vault. Scans every table, so it takes a while on large vaults.
+pub fn get_storage_report(conn: &Connection) -> Result<StorageReport, StorageError> {
+    let schema = load_schema(conn)?;
+    let owners = btree_owners(&schema);
+    let tables: Vec<&SchemaEntry> = schema
+        .iter()
+        .filter(|e| e.kind == "table" && !e.name.starts_with("sqlite_"))
+        .filter(|e| owners.get(&e.name) == Some(&e.name))
+        .collect();
+
+    let (size_source, mut bytes) = match dbstat_sizes(conn, &owners)? {
+        Some(bytes) => (SizeSource::Dbstat, bytes),
+        None => (
+            SizeSource::ColumnLengths,
+            column_length_sizes(conn, &schema, &owners)?,
+        ),
+    };
+
+    let mut usage = Vec::with_capacity(tables.len());
+    let mut spaces: BTreeMap<(String, StorageCategory), (u64, u64)> = BTreeMap::new();
+    for entry in &tables {
+        let category = storage_category(&entry.name);
+        let rows = count_rows(conn, &entry.name);
+        usage.push(TableUsage {
+            table: entry.name.clone(),
+            category,
+            rows,
+            bytes: bytes.remove(&entry.name).unwrap_or(0),
+        });
+        if !is_virtual(entry) {
+            for (space_id, rows, bytes) in space_usage(conn, &entry.name)? {
+                let total = spaces.entry((space_id, category)).or_default();
+                total.0 += rows;
+                total.1 += bytes;
+            }
+        }
+    }
+    // Pages of the schema itself and the like
+    let other_bytes: u64 = bytes.values().sum();
+    if other_bytes > 0 {
+        usage.push(TableUsage {
+            table: "sqlite_schema".to_string(),
+            category: StorageCategory::Other,
+            rows: 0,
+            bytes: other_bytes,
+        });
+    }
+    usage.sort_by(|a, b| b.bytes.cmp(&a.bytes).then_with(|| a.table.cmp(&b.table)));
+
+    let mut categories: Vec<CategoryUsage> = StorageCategory::ALL
+        .iter()
+        .map(|&category| CategoryUsage {
+            category,
+            rows: 0,
+            bytes: 0,
+            external_bytes: 0,
+        })
+        .collect();
+    for table in &usage {
+        let total = &mut categories[table.category as usize];
+        total.rows += table.rows;
+        total.bytes += table.bytes;
+    }
+    if super::table_exists(conn, "blob")? {
+        let external: i64 =
+            conn.query_row("SELECT COALESCE(SUM(size_bytes), 0) FROM blob", [], |row| {
+                row.get(0)
+            })?;
+        categories[StorageCategory::Attachments as usize].external_bytes = external.max(0) as u64;
+    }
+    categories.sort_by(|a, b| {
+        (b.bytes + b.external_bytes)
+            .cmp(&(a.bytes + a.external_bytes))
+            .then_with(|| a.category.cmp(&b.category))
+    });
+
+    let page_size: u64 = conn.query_row("PRAGMA page_size", [], |row| row.get(0))?;
+    let page_count: u64 = conn.query_row("PRAGMA page_count", [], |row| row.get(0))?;
+    let free_pages: u64 = conn.query_row("PRAGMA freelist_count", [], |row| row.get(0))?;
+    let (file_bytes, wal_bytes) = file_sizes(conn)?;
+    let file_bytes = if file_bytes == 0 {
+        page_count * page_size
+    } else {
+        file_bytes
+    };
+
+    Ok(StorageReport {
+        size_source,
+        categories,
+        tables: usage,
+        spaces: spaces
+            .into_iter()
+            .map(|((space_id, category), (rows, bytes))| SpaceUsage {
+                space_id,
+                category,
+                rows,
+                bytes,
+            })
+            .collect(),
+        file_bytes,
+        wal_bytes,
+        page_size,
+        free_pages,
+        vacuum_recommended: vacuum_recommended(free_pages * page_size, page_count * page_size),
+        generated_at: chrono::Utc::now().timestamp(),
+    })
+}
+
+fn vacuum_recommended(free_bytes: u64, total_bytes: u64) -> bool {
+    total_bytes > 0
+        && free_bytes >= VACUUM_MIN_FREE_BYTES
+        && free_bytes as f64 / total_bytes as f64 >= VACUUM_FREE_RATIO
+}
+
+/// Rebuild the vault file to give free pages back to the file system.
+///
+/// The WAL is checkpointed first so the rebuilt file holds everything.
+/// Refuses rather than waits when another connection is writing, and must
+/// not be called inside a transaction.
+pub fn vacuum_vault(conn: &Connection) -> Result<VacuumResult, StorageError> {
+    if !conn.is_autocommit() {
+        return Err(StorageError::Busy(
+            "a transaction is open on this connection".to_string(),
+        ));
+    }
+    let started = std::time::Instant::now();
+    let file_bytes_before = file_sizes(conn)?.0;
+
+    let busy_timeout: u64 = conn.query_row("PRAGMA busy_timeout", [], |row| row.get(0))?;
+    conn.busy_timeout(Duration::ZERO)?;
+    let result = checkpoint_and_vacuum(conn);
+    conn.busy_timeout(Duration::from_millis(busy_timeout))?;
+    result?;
+
+    let result = VacuumResult {
+        file_bytes_before,
+        file_bytes_after: file_sizes(conn)?.0,
+        duration_ms: started.elapsed().as_millis() as u64,
+    };
+    log::info!(
+        "[db] VACUUM shrank the vault from {} to {} bytes in {}ms",
+        result.file_bytes_before,
+        result.file_bytes_after,
+        result.duration_ms
+    );
+    Ok(result)
+}
+
+fn checkpoint_and_vacuum(conn: &Connection) -> Result<(), StorageError> {
+    checkpoint(conn)?;
+    conn.execute_batch("VACUUM")
+        .map_err(|e| match e.sqlite_error_code() {
+            Some(ErrorCode::DatabaseBusy) | Some(ErrorCode::DatabaseLocked) => {
+                StorageError::Busy("another connection is writing".to_string())
+            }
+            _ => StorageError::Database(e),
+        })?;
+    // VACUUM went through the WAL in WAL mode
+    checkpoint(conn)
+}
+
+fn checkpoint(conn: &Connection) -> Result<(), StorageError> {
+    let busy: i64 = conn
+        .query_row("PRAGMA wal_checkpoint(TRUNCATE)", [], |row| row.get(0))
+        .map_err(|e| match e.sqlite_error_code() {
+            Some(ErrorCode::DatabaseBusy) | Some(ErrorCode::DatabaseLocked) => {
+                StorageError::Busy("another connection is writing".to_string())
+            }
+            _ => StorageError::Database(e),
+        })?;
+    if busy != 0 {
+        return Err(StorageError::Busy(
+            "the WAL is in use by another connection".to_string(),
+        ));
+    }
+    Ok(())
+}
+
+/// Sizes of the main database file and its WAL; zero for in-memory databases
+fn file_sizes(conn: &Connection) -> Result<(u64, u64), StorageError> {
+    let path: Option<String> = conn
+        .query_row(
+            "SELECT file FROM pragma_database_list WHERE name = 'main'",
+            [],
+            |row| row.get(0),
+        )
+        .optional()?;
+    let Some(path) = path.filter(|p| !p.is_empty()) else {
+        return Ok((0, 0));
+    };
+    let size = |path: &str| std::fs::metadata(path).map_or(0, |m| m.len());
+    Ok((size(&path), size(&format!("{}-wal", path))))
+}
+
+fn load_schema(conn: &Connection) -> Result<Vec<SchemaEntry>, StorageError> {
+    let mut stmt = conn.prepare("SELECT type, name, tbl_name, sql FROM sqlite_master")?;
+    let entries = stmt
+        .query_map([], |row| {
+            Ok(SchemaEntry {
+                kind: row.get(0)?,
+                name: row.get(1)?,
+                table: row.get(2)?,
+                sql: row.get(3)?,
+            })
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(entries)
+}
+
+fn is_virtual(entry: &SchemaEntry) -> bool {
+    entry.sql.as_deref().is_some_and(|sql| {
+        sql.trim_start()
+            .to_uppercase()
+            .starts_with("CREATE VIRTUAL")
+    })
+}
+
+/// The table each b-tree belongs to: indexes belong to their table and FTS
+/// shadow tables to their virtual table
+fn btree_owners(schema: &[SchemaEntry]) -> HashMap<String, String> {
+    let virtual_tables: Vec<&str> = schema
+        .iter()
+        .filter(|e| is_virtual(e))
+        .map(|e| e.name.as_str())
+        .collect();
+    schema
+        .iter()
+        .filter(|e| e.kind == "table" || e.kind == "index")
+        .map(|e| {
+            let owner = virtual_tables
+                .iter()
+                .find(|vt| {
+                    SHADOW_SUFFIXES
+                        .iter()
+                        .any(|suffix| e.table == format!("{}{}", vt, suffix))
+                })
+                .map_or(e.table.clone(), |vt| vt.to_string());
+            (e.name.clone(), owner)
+        })
+        .collect()
+}
+
+/// Bytes per owning table from `dbstat`, or `None` when it isn't available.
+/// B-trees without an owner are reported under their own name.
+fn dbstat_sizes(
+    conn: &Connection,
+    owners: &HashMap<String, String>,
+) -> Result<Option<HashMap<String, u64>>, StorageError> {
+    let mut stmt = match conn.prepare("SELECT name, SUM(pgsize) FROM dbstat GROUP BY name") {
+        Ok(stmt) => stmt,
+        Err(e) => {
+            log::debug!("[db] dbstat unavailable, using column lengths: {}", e);
+            return Ok(None);
+        }
+    };
+    let mut sizes: HashMap<String, u64> = HashMap::new();
+    let rows = stmt.query_map([], |row| {
+        Ok((row.get::<_, String>(0)?, row.get::<_, i64>(1)?))
+    })?;
+    for row in rows {
+        let (name, bytes) = row?;
+        let owner = owners.get(&name).cloned().unwrap_or(name);
+        *sizes.entry(owner).or_default() += bytes.max(0) as u64;
+    }
+    Ok(Some(sizes))
+}
+
+fn column_length_sizes(
+    conn: &Connection,
+    schema: &[SchemaEntry],
+    owners: &HashMap<String, String>,
+) -> Result<HashMap<String, u64>, StorageError> {
+    let mut sizes: HashMap<String, u64> = HashMap::new();
+    for entry in schema
+        .iter()
+        .filter(|e| e.kind == "table" && !is_virtual(e) && !e.name.starts_with("sqlite_"))
+    {
+        let Some(row_bytes) = row_bytes_expr(conn, &entry.name)? else {
+            continue;
+        };
+        let bytes: i64 = conn.query_row(
+            &format!(
+                "SELECT COALESCE(SUM({}), 0) FROM \"{}\"",
+                row_bytes, entry.name
+            ),
+            [],
+            |row| row.get(0),
+        )?;
+        let owner = owners.get(&entry.name).unwrap_or(&entry.name);
+        *sizes.entry(owner.clone()).or_default() += bytes.max(0) as u64;
+    }
+    Ok(sizes)
+}
+
+/// SQL for the byte length of a row of `table`, `None` when it has no columns
+fn row_bytes_expr(conn: &Connection, table: &str) -> Result<Option<String>, StorageError> {
+    let mut stmt = conn.prepare("SELECT name FROM pragma_table_info(?1)")?;
+    let columns = stmt
+        .query_map([table], |row| row.get::<_, String>(0))?
+        .collect::<Result<Vec<_>, _>>()?;
+    if columns.is_empty() {
+        return Ok(None);
+    }
+    Ok(Some(
+        columns
+            .iter()
+            .map(|c| format!("COALESCE(length(CAST(\"{}\" AS BLOB)), 0)", c))
+            .collect::<Vec<_>>()
+            .join(" + "),
+    ))
+}
+
+fn count_rows(conn: &Connection, table: &str) -> u64 {
+    conn.query_row(&format!("SELECT COUNT(*) FROM \"{}\"", table), [], |row| {
+        row.get::<_, i64>(0)
+    })
+    .map(|n| n.max(0) as u64)
+    .unwrap_or_else(|e| {
+        // e.g. a virtual table whose module isn't compiled in
+        log::warn!("[db] Failed to count rows of {}: {}", table, e);
+        0
+    })
+}
+
+/// `(space_id, rows, bytes)` of a table with a `space_id` column
+fn space_usage(conn: &Connection, table: &str) -> Result<Vec<(String, u64, u64)>, StorageError> {
+    let has_space: bool = conn.query_row(
+        "SELECT EXISTS (SELECT 1 FROM pragma_table_info(?1) WHERE name = 'space_id')",
+        [table],
+        |row| row.get(0),
+    )?;
+    if !has_space {
+        return Ok(Vec::new());
+    }
+    let Some(row_bytes) = row_bytes_expr(conn, table)? else {
+        return Ok(Vec::new());
+    };
+    let mut stmt = conn.prepare(&format!(
+        "SELECT space_id, COUNT(*), COALESCE(SUM({}), 0) FROM \"{}\"
+         WHERE space_id IS NOT NULL GROUP BY space_id",
+        row_bytes, table
+    ))?;
+    let usage = stmt
+        .query_map([], |row| {
+            Ok((
+                row.get::<_, String>(0)?,
+                row.get::<_, i64>(1)?.max(0) as u64,
+                row.get::<_, i64>(2)?.max(0) as u64,
+            ))
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(usage)
+}
@@ -1,10 +1,12 @@
 //! Vault Backup Module
 //!
 //! Provides redundant storage of critical vault configuration (salt, wrapped DEK)
-//! to prevent data loss if config.json is corrupted or deleted.
+//! to prevent data loss if config.json is corrupted or deleted, and full
+//! database snapshots taken before risky operations such as migrations.
 
 use rusqlite::{params, Connection, Result};
 use serde::{Deserialize, Serialize};
+use std::path::Path;
 
 /// Reserved table name for vault configuration backup
 const VAULT_CONFIG_TABLE: &str = "_noteece_vault_config";
@@ -140,6 +142,32 @@ pub fn has_valid_backup(conn: &Connection) -> Result<bool> {
     Ok(backup.is_some())
 }
 
+/// Copy the whole database to a new file at `path`, encrypted with the same
+/// key as `conn`. Can't run inside a transaction.
+pub fn snapshot_database(conn: &Connection, path: &Path) -> Result<()> {
+    if path.exists() {
+        return Err(rusqlite::Error::InvalidPath(path.to_path_buf()));
+    }
+    // Without a KEY clause the attached database inherits the main key
+    conn.execute(
+        "ATTACH DATABASE ?1 AS noteece_snapshot",
+        [path.to_string_lossy()],
+    )?;
+    let exported = conn.query_row(
+        "SELECT sqlcipher_export('noteece_snapshot')",
+        [],
+        |_| Ok(()),
+    );
+    let detached = conn.execute_batch("DETACH DATABASE noteece_snapshot");
+    exported.and(detached)?;
+
+    log::info!(
+        "[vault_backup] Database snapshot written to {}",
+        path.display()
+    );
+    Ok(())
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
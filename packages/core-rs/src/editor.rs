@@ -1,4 +1,10 @@
+use crate::backlink::code_ranges;
+use regex::Regex;
+use rusqlite::Connection;
 use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::ops::Range;
+use thiserror::Error;
 use ulid::Ulid;
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -34,3 +40,357 @@ pub fn serialize_markdown(blocks: &[Block]) -> String {
     }
     content
 }
+
+/// Fuzzy title suggestions offered for a broken link
+const MAX_LINK_SUGGESTIONS: usize = 3;
+
+/// Titles less similar than this to a broken link's target are not suggested
+const MIN_SUGGESTION_SIMILARITY: f64 = 0.6;
+
+#[derive(Error, Debug)]
+pub enum EditorError {
+    #[error("Rusqlite error: {0}")]
+    Rusqlite(#[from] rusqlite::Error),
+    #[error("Invalid autofix: {0}")]
+    InvalidFix(String),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Severity {
+    Error,
+    Warning,
+    Info,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DiagnosticKind {
+    BrokenLink,
+    MalformedCheckbox,
+    DuplicateHeading,
+    UnclosedCodeFence,
+    MissingBlob,
+}
+
+/// Replace the bytes `start..end` of the content with `replacement`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Autofix {
+    pub start: usize,
+    pub end: usize,
+    pub replacement: String,
+}
+
+/// A problem found in note content
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Diagnostic {
+    pub kind: DiagnosticKind,
+    pub severity: Severity,
+    /// Byte range of the offending text
+    pub start: usize,
+    pub end: usize,
+    pub message: String,
+    /// Existing note titles a broken link may have meant, best first
+    #[serde(default)]
+    pub suggestions: Vec<String>,
+    pub fix: Option<Autofix>,
+}
+
+impl Diagnostic {
+    fn new(kind: DiagnosticKind, severity: Severity, range: Range<usize>, message: String) -> Self {
+        Diagnostic {
+            kind,
+            severity,
+            start: range.start,
+            end: range.end,
+            message,
+            suggestions: Vec::new(),
+            fix: None,
+        }
+    }
+}
+
+/// Lint markdown note content: broken wikilinks, malformed task checkboxes,
+/// duplicate headings, unclosed code fences and images of missing blobs.
+/// Diagnostics are sorted by position.
+pub fn validate_note_content(
+    conn: &Connection,
+    space_id: &str,
+    content: &str,
+) -> Result<Vec<Diagnostic>, EditorError> {
+    let code = code_ranges(content);
+    let in_code = |offset: usize| code.iter().any(|r| r.contains(&offset));
+
+    let mut diagnostics = Vec::new();
+    check_lines(content, &in_code, &mut diagnostics);
+    check_links(conn, space_id, content, &in_code, &mut diagnostics)?;
+    check_images(conn, content, &in_code, &mut diagnostics)?;
+
+    diagnostics.sort_by_key(|d| (d.start, d.end));
+    log::debug!(
+        "[editor] Validated note content: {} diagnostics",
+        diagnostics.len()
+    );
+    Ok(diagnostics)
+}
+
+/// Line-oriented checks: checkboxes, headings and code fences
+fn check_lines(content: &str, in_code: &dyn Fn(usize) -> bool, out: &mut Vec<Diagnostic>) {
+    let checkbox = Regex::new(r"^(\s*)([-*+])(\s*)\[([^\[\]]{0,3})\](\s*)").expect("valid regex");
+    let heading = Regex::new(r"^ {0,3}(#{1,6})[ \t]+(.+?)[ \t#]*$").expect("valid regex");
+
+    let mut headings: HashMap<String, usize> = HashMap::new();
+    let mut fence: Option<(&str, Range<usize>)> = None;
+    let mut offset = 0;
+    for (number, raw) in content.split_inclusive('\n').enumerate() {
+        let start = offset;
+        offset += raw.len();
+        let line = raw.trim_end_matches(['\n', '\r']);
+        let trimmed = line.trim_start();
+
+        // Fences are tracked here because code_ranges hides where they open
+        match fence {
+            Some((marker, _)) if trimmed.starts_with(marker) => {
+                fence = None;
+                continue;
+            }
+            Some(_) => continue,
+            None if trimmed.starts_with("```") || trimmed.starts_with("~~~") => {
+                fence = Some((&trimmed[..3], start..start + line.len()));
+                continue;
+            }
+            None => {}
+        }
+        if in_code(start) {
+            continue;
+        }
+
+        if let Some(cap) = checkbox.captures(line) {
+            let state = cap[4].trim();
+            let well_formed = cap[3] == *" "
+                && matches!(&cap[4], " " | "x" | "X")
+                && (!cap[5].is_empty() || cap[0].len() == line.len());
+            if !well_formed && matches!(state, "" | "x" | "X") {
+                let range = start..start + cap[0].len();
+                let mark = if state.is_empty() { ' ' } else { 'x' };
+                let mut diagnostic = Diagnostic::new(
+                    DiagnosticKind::MalformedCheckbox,
+                    Severity::Warning,
+                    range.clone(),
+                    "Malformed task checkbox".to_string(),
+                );
+                diagnostic.fix = Some(Autofix {
+                    start: range.start,
+                    end: range.end,
+                    replacement: format!("{}{} [{}] ", &cap[1], &cap[2], mark),
+                });
+                out.push(diagnostic);
+            }
+        }
+
+        if let Some(cap) = heading.captures(line) {
+            let text = cap[2].trim();
+            let key = text.to_lowercase();
+            match headings.get(&key) {
+                Some(first) => out.push(Diagnostic::new(
+                    DiagnosticKind::DuplicateHeading,
+                    Severity::Warning,
+                    start..start + line.len(),
+                    format!("Duplicate heading '{}' (first on line {})", text, first),
+                )),
+                None => {
+                    headings.insert(key, number + 1);
+                }
+            }
+        }
+    }
+
+    if let Some((marker, range)) = fence {
+        let mut diagnostic = Diagnostic::new(
+            DiagnosticKind::UnclosedCodeFence,
+            Severity::Error,
+            range,
+            "Code fence is never closed".to_string(),
+        );
+        let replacement = if content.ends_with('\n') {
+            format!("{}\n", marker)
+        } else {
+            format!("\n{}", marker)
+        };
+        diagnostic.fix = Some(Autofix {
+            start: content.len(),
+            end: content.len(),
+            replacement,
+        });
+        out.push(diagnostic);
+    }
+}
+
+/// Wikilinks whose target is neither a note id nor a note title in the space
+fn check_links(
+    conn: &Connection,
+    space_id: &str,
+    content: &str,
+    in_code: &dyn Fn(usize) -> bool,
+    out: &mut Vec<Diagnostic>,
+) -> Result<(), EditorError> {
+    let wiki = Regex::new(r"\[\[([^\[\]]+?)\]\]").expect("valid regex");
+    let links: Vec<_> = wiki
+        .captures_iter(content)
+        .filter(|cap| !in_code(cap.get(0).map_or(0, |m| m.start())))
+        .collect();
+    if links.is_empty() {
+        return Ok(());
+    }
+
+    let titles: Vec<String> = {
+        let mut stmt =
+            conn.prepare("SELECT title FROM note WHERE space_id = ?1 AND is_trashed = 0")?;
+        let rows = stmt.query_map([space_id], |row| row.get(0))?;
+        rows.collect::<Result<_, _>>()?
+    };
+    let known: HashSet<String> = titles.iter().map(|t| t.trim().to_lowercase()).collect();
+    let mut note_exists =
+        conn.prepare_cached("SELECT EXISTS(SELECT 1 FROM note WHERE id = ?1 AND is_trashed = 0)")?;
+
+    for cap in links {
+        let whole = cap.get(0).expect("group 0 always matches");
+        let inner = cap.get(1).expect("group 1 always matches");
+        let raw_target = inner.as_str().split(['|', '#']).next().unwrap_or("");
+        let target = raw_target.trim();
+        if target.is_empty() || known.contains(&target.to_lowercase()) {
+            continue;
+        }
+        if let Ok(id) = Ulid::from_string(target) {
+            if note_exists.query_row([id.to_string()], |row| row.get(0))? {
+                continue;
+            }
+        }
+
+        let mut diagnostic = Diagnostic::new(
+            DiagnosticKind::BrokenLink,
+            Severity::Warning,
+            whole.range(),
+            format!("No note named '{}'", target),
+        );
+        diagnostic.suggestions = suggest_titles(target, &titles);
+        if let Some(best) = diagnostic.suggestions.first() {
+            let start = inner.start() + (raw_target.len() - raw_target.trim_start().len());
+            diagnostic.fix = Some(Autofix {
+                start,
+                end: start + target.len(),
+                replacement: best.clone(),
+            });
+        }
+        out.push(diagnostic);
+    }
+    Ok(())
+}
+
+/// Images pointing at `blob://<id>` or `noteece://blob/<id>` that are not in the vault
+fn check_images(
+    conn: &Connection,
+    content: &str,
+    in_code: &dyn Fn(usize) -> bool,
+    out: &mut Vec<Diagnostic>,
+) -> Result<(), EditorError> {
+    let image = Regex::new(r"!\[[^\[\]]*\]\(([^()\s]+)\)").expect("valid regex");
+    let mut blob_exists = conn.prepare_cached("SELECT EXISTS(SELECT 1 FROM blob WHERE id = ?1)")?;
+
+    for cap in image.captures_iter(content) {
+        let whole = cap.get(0).expect("group 0 always matches");
+        if in_code(whole.start()) {
+            continue;
+        }
+        let Some(blob_id) = cap[1]
+            .strip_prefix("blob://")
+            .or_else(|| cap[1].strip_prefix("noteece://blob/"))
+        else {
+            continue;
+        };
+        if !blob_exists.query_row([blob_id], |row| row.get(0))? {
+            out.push(Diagnostic::new(
+                DiagnosticKind::MissingBlob,
+                Severity::Error,
+                whole.range(),
+                format!("Image references missing attachment {}", blob_id),
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// Existing titles closest to a mistyped link target
+fn suggest_titles(target: &str, titles: &[String]) -> Vec<String> {
+    let target = target.to_lowercase();
+    let mut scored: Vec<(f64, &String)> = titles
+        .iter()
+        .filter_map(|title| {
+            let candidate = title.trim().to_lowercase();
+            if candidate.is_empty() {
+                return None;
+            }
+            let longest = target.chars().count().max(candidate.chars().count());
+            let mut score = 1.0 - levenshtein(&target, &candidate) as f64 / longest as f64;
+            // "Roadmap" should still find "Q3 Roadmap"
+            if candidate.contains(&target) || target.contains(&candidate) {
+                score = score.max(MIN_SUGGESTION_SIMILARITY);
+            }
+            (score >= MIN_SUGGESTION_SIMILARITY).then_some((score, title))
+        })
+        .collect();
+    scored.sort_by(|a, b| b.0.total_cmp(&a.0).then_with(|| a.1.cmp(b.1)));
+    scored
+        .into_iter()
+        .take(MAX_LINK_SUGGESTIONS)
+        .map(|(_, title)| title.clone())
+        .collect()
+}
+
+/// Edit distance between two strings, counted in chars
+fn levenshtein(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut current = vec![i + 1; b.len() + 1];
+        for (j, cb) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(ca != *cb);
+            current[j + 1] = substitution.min(previous[j + 1] + 1).min(current[j] + 1);
+        }
+        previous = current;
+    }
+    previous[b.len()]
+}
+
+/// Apply autofixes to content. Fixes may come in any order but must not
+/// overlap, and their ranges must lie on char boundaries.
+pub fn apply_autofixes(content: &str, fixes: &[Autofix]) -> Result<String, EditorError> {
+    let mut sorted: Vec<&Autofix> = fixes.iter().collect();
+    sorted.sort_by_key(|fix| (fix.start, fix.end));
+
+    let mut result = String::with_capacity(content.len());
+    let mut cursor = 0;
+    for fix in sorted {
+        if fix.start > fix.end
+            || fix.end > content.len()
+            || !content.is_char_boundary(fix.start)
+            || !content.is_char_boundary(fix.end)
+        {
+            return Err(EditorError::InvalidFix(format!(
+                "range {}..{} is not valid in the content",
+                fix.start, fix.end
+            )));
+        }
+        if fix.start < cursor {
+            return Err(EditorError::InvalidFix(format!(
+                "range {}..{} overlaps another fix",
+                fix.start, fix.end
+            )));
+        }
+        result.push_str(&content[cursor..fix.start]);
+        result.push_str(&fix.replacement);
+        cursor = fix.end;
+    }
+    result.push_str(&content[cursor..]);
+    Ok(result)
+}
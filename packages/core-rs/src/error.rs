@@ -0,0 +1,703 @@
+//! Crate-wide error taxonomy
+//!
+//! Module errors stay as they are and convert into a [`CoreError`]: a stable
+//! machine-readable code such as `note.not_found`, a coarse
+//! [`ErrorCategory`], the human-readable message and optional context. The
+//! app's command layer returns it, so the frontend can tell a locked vault
+//! from a full disk or a sync conflict without parsing messages.
+//!
+//! Codes are `<domain>.<kind>` and never change meaning once shipped;
+//! messages may be reworded at any time.
+
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::auth::AuthError;
+use crate::automation::AutomationError;
+use crate::bulk_edit::BulkError;
+use crate::caldav::CalDavError;
+use crate::collaboration::{CollaborationError, LockedByOther};
+use crate::crypto::CryptoError;
+use crate::db::{DbError, SettingsError};
+use crate::editor::EditorError;
+use crate::ids::InvalidId;
+use crate::llm::error::LLMError;
+use crate::meeting::MeetingError;
+use crate::note_dedup::NoteDedupError;
+use crate::note_lock::NoteLockError;
+use crate::note_share::NoteShareError;
+use crate::note_template::TemplateError;
+use crate::permission::PermissionDenied;
+use crate::property::PropertyError;
+use crate::social::SocialError;
+use crate::space_template::SpaceTemplateError;
+use crate::sync::discovery::DiscoveryError;
+use crate::sync::error::SyncError;
+use crate::sync::p2p::P2pError;
+use crate::sync::relay::RelayError;
+use crate::sync::relay_sync::RelaySyncError;
+use crate::tag::TagError;
+use crate::vault::VaultError;
+
+/// Stable codes the frontend is expected to branch on
+pub mod codes {
+    pub const VAULT_LOCKED: &str = "vault.locked";
+    pub const VAULT_LOCKED_OUT: &str = "vault.locked_out";
+    pub const VAULT_READ_ONLY: &str = "vault.read_only";
+    pub const VAULT_ALREADY_OPEN: &str = "vault.already_open";
+    pub const NOTE_NOT_FOUND: &str = "note.not_found";
+    pub const NOTE_LOCKED: &str = "note.locked";
+    pub const SYNC_CONFLICT: &str = "sync.conflict";
+    pub const AUTH_INVALID_CREDENTIALS: &str = "auth.invalid_credentials";
+    pub const AUTH_LOCKED_OUT: &str = "auth.locked_out";
+    pub const PERMISSION_DENIED: &str = "permission.denied";
+    pub const LOCKED_BY_OTHER: &str = "collaboration.locked_by_other";
+    pub const BULK_INCOMPLETE: &str = "bulk.incomplete";
+    pub const DB_BUSY: &str = "db.busy";
+    pub const DB_DISK_FULL: &str = "db.disk_full";
+    pub const INVALID_ID: &str = "data.invalid_id";
+    pub const INTERNAL: &str = "internal";
+}
+
+/// What kind of failure, for callers that only need to pick a reaction
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorCategory {
+    NotFound,
+    PermissionDenied,
+    VaultLocked,
+    Conflict,
+    Validation,
+    Io,
+    Network,
+    Crypto,
+    Internal,
+}
+
+/// Serializes as `{code, category, message, context}`
+#[derive(Error, Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[error("{message}")]
+pub struct CoreError {
+    pub code: String,
+    pub category: ErrorCategory,
+    pub message: String,
+    /// Details such as the id of the missing entity; keys are per code
+    #[serde(default)]
+    pub context: BTreeMap<String, String>,
+}
+
+impl CoreError {
+    pub fn new(
+        category: ErrorCategory,
+        code: impl Into<String>,
+        message: impl Into<String>,
+    ) -> Self {
+        Self {
+            code: code.into(),
+            category,
+            message: message.into(),
+            context: BTreeMap::new(),
+        }
+    }
+
+    pub fn with_context(mut self, key: impl Into<String>, value: impl ToString) -> Self {
+        self.context.insert(key.into(), value.to_string());
+        self
+    }
+
+    /// `<entity>.not_found`, with the id as context
+    pub fn not_found(entity: &str, id: impl ToString) -> Self {
+        let id = id.to_string();
+        Self::new(
+            ErrorCategory::NotFound,
+            format!("{}.not_found", entity),
+            format!("Not found: {} {}", entity, id),
+        )
+        .with_context("id", id)
+    }
+
+    pub fn vault_locked() -> Self {
+        Self::new(
+            ErrorCategory::VaultLocked,
+            codes::VAULT_LOCKED,
+            "Vault is locked; unlock it to continue",
+        )
+    }
+
+    pub fn validation(code: impl Into<String>, message: impl Into<String>) -> Self {
+        Self::new(ErrorCategory::Validation, code, message)
+    }
+
+    /// For failures the user can do nothing about
+    pub fn internal(message: impl Into<String>) -> Self {
+        Self::new(ErrorCategory::Internal, codes::INTERNAL, message)
+    }
+}
+
+/// Commands and FFI entry points that still return `String` errors
+impl From<CoreError> for String {
+    fn from(e: CoreError) -> Self {
+        e.message
+    }
+}
+
+impl From<rusqlite::Error> for CoreError {
+    fn from(e: rusqlite::Error) -> Self {
+        use rusqlite::ErrorCode;
+
+        let message = e.to_string();
+        if matches!(e, rusqlite::Error::QueryReturnedNoRows) {
+            return CoreError::new(ErrorCategory::NotFound, "db.not_found", message);
+        }
+        let (category, code) = match e.sqlite_error_code() {
+            Some(ErrorCode::DatabaseBusy | ErrorCode::DatabaseLocked) => {
+                (ErrorCategory::Conflict, codes::DB_BUSY)
+            }
+            Some(ErrorCode::DiskFull) => (ErrorCategory::Io, codes::DB_DISK_FULL),
+            Some(ErrorCode::ConstraintViolation) => (ErrorCategory::Validation, "db.constraint"),
+            // A wrong key reads as a file that isn't a database
+            Some(ErrorCode::NotADatabase) => (ErrorCategory::Crypto, "db.not_a_database"),
+            Some(ErrorCode::ReadOnly | ErrorCode::PermissionDenied) => {
+                (ErrorCategory::PermissionDenied, "db.read_only")
+            }
+            Some(ErrorCode::CannotOpen | ErrorCode::SystemIoFailure) => {
+                (ErrorCategory::Io, "db.io")
+            }
+            _ => (ErrorCategory::Internal, "db.error"),
+        };
+        CoreError::new(category, code, message)
+    }
+}
+
+impl From<std::io::Error> for CoreError {
+    fn from(e: std::io::Error) -> Self {
+        use std::io::ErrorKind;
+
+        let (category, code) = match e.kind() {
+            ErrorKind::NotFound => (ErrorCategory::NotFound, "io.not_found"),
+            ErrorKind::PermissionDenied => {
+                (ErrorCategory::PermissionDenied, "io.permission_denied")
+            }
+            ErrorKind::StorageFull => (ErrorCategory::Io, "io.disk_full"),
+            _ => (ErrorCategory::Io, "io.error"),
+        };
+        CoreError::new(category, code, e.to_string())
+    }
+}
+
+impl From<serde_json::Error> for CoreError {
+    fn from(e: serde_json::Error) -> Self {
+        CoreError::validation("data.invalid_json", e.to_string())
+    }
+}
+
+impl From<ulid::DecodeError> for CoreError {
+    fn from(e: ulid::DecodeError) -> Self {
+        CoreError::validation(codes::INVALID_ID, format!("Invalid ID: {}", e))
+    }
+}
+
+/// Context names the parameter (`field`), the entity `kind` and the `value`
+impl From<InvalidId> for CoreError {
+    fn from(e: InvalidId) -> Self {
+        CoreError::validation(codes::INVALID_ID, e.to_string())
+            .with_context("field", &e.field)
+            .with_context("kind", e.kind)
+            .with_context("value", &e.value)
+    }
+}
+
+impl From<hex::FromHexError> for CoreError {
+    fn from(e: hex::FromHexError) -> Self {
+        CoreError::validation("data.invalid_hex", e.to_string())
+    }
+}
+
+impl From<CryptoError> for CoreError {
+    fn from(e: CryptoError) -> Self {
+        let code = match e {
+            CryptoError::Decrypt => "crypto.decrypt_failed",
+            CryptoError::AesKw(_) | CryptoError::Kdf(_) => "crypto.error",
+        };
+        CoreError::new(ErrorCategory::Crypto, code, e.to_string())
+    }
+}
+
+impl From<PermissionDenied> for CoreError {
+    fn from(e: PermissionDenied) -> Self {
+        CoreError::new(
+            ErrorCategory::PermissionDenied,
+            codes::PERMISSION_DENIED,
+            e.to_string(),
+        )
+        .with_context("space_id", &e.space_id)
+        .with_context("permission", &e.permission)
+    }
+}
+
+impl From<LockedByOther> for CoreError {
+    fn from(e: LockedByOther) -> Self {
+        CoreError::new(
+            ErrorCategory::Conflict,
+            codes::LOCKED_BY_OTHER,
+            e.to_string(),
+        )
+        .with_context("entity_id", &e.holder.entity_id)
+        .with_context("user_id", &e.holder.user_id)
+        .with_context("acquired_at", e.holder.acquired_at)
+        .with_context("expires_at", e.holder.expires_at)
+    }
+}
+
+impl From<DbError> for CoreError {
+    fn from(e: DbError) -> Self {
+        match e {
+            DbError::Rusqlite(e) => e.into(),
+            DbError::SerdeJson(e) => e.into(),
+            DbError::PermissionDenied(e) => e.into(),
+            DbError::QuerySyntax(e) => CoreError::validation("search.invalid_query", e.to_string()),
+            DbError::InvalidId(e) => e.into(),
+            DbError::LockedByOther(e) => e.into(),
+            DbError::NotFound { entity, id } => CoreError::not_found(entity, id),
+            DbError::Locked { entity, id } => CoreError::new(
+                ErrorCategory::PermissionDenied,
+                format!("{}.locked", entity),
+                format!("Locked: {} {}", entity, id),
+            )
+            .with_context("id", id),
+            DbError::Message(msg) => CoreError::new(ErrorCategory::Internal, "db.error", msg),
+        }
+    }
+}
+
+impl From<VaultError> for CoreError {
+    fn from(e: VaultError) -> Self {
+        match e {
+            VaultError::Locked => CoreError::vault_locked(),
+            VaultError::LockedOut(secs) => CoreError::new(
+                ErrorCategory::PermissionDenied,
+                codes::VAULT_LOCKED_OUT,
+                e.to_string(),
+            )
+            .with_context("retry_after_secs", secs),
+            VaultError::ReadOnly => CoreError::new(
+                ErrorCategory::PermissionDenied,
+                codes::VAULT_READ_ONLY,
+                e.to_string(),
+            ),
+            VaultError::VaultAlreadyOpen(ref holder) => CoreError::new(
+                ErrorCategory::Conflict,
+                codes::VAULT_ALREADY_OPEN,
+                e.to_string(),
+            )
+            .with_context("pid", holder.pid)
+            .with_context("hostname", &holder.hostname)
+            .with_context("acquired_at", holder.acquired_at),
+            VaultError::Db(e) => e.into(),
+            VaultError::Crypto(e) => e.into(),
+            VaultError::Io(e) => e.into(),
+            VaultError::Json(e) => e.into(),
+            VaultError::Hex(e) => e.into(),
+            VaultError::Rusqlite(e) => e.into(),
+            VaultError::Message(msg) => CoreError::new(ErrorCategory::Internal, "vault.error", msg),
+        }
+    }
+}
+
+impl From<SyncError> for CoreError {
+    fn from(e: SyncError) -> Self {
+        let (category, code) = match &e {
+            SyncError::DatabaseError(_) => (ErrorCategory::Internal, "sync.database"),
+            SyncError::NetworkError(_) => (ErrorCategory::Network, "sync.network"),
+            SyncError::EncryptionError(_) => (ErrorCategory::Crypto, "sync.encryption"),
+            SyncError::ConflictError(_) => (ErrorCategory::Conflict, codes::SYNC_CONFLICT),
+            SyncError::InvalidData(_) => (ErrorCategory::Validation, "sync.invalid_data"),
+        };
+        CoreError::new(category, code, e.to_string())
+    }
+}
+
+impl From<P2pError> for CoreError {
+    fn from(e: P2pError) -> Self {
+        let (category, code) = match &e {
+            P2pError::Discovery(_) => (ErrorCategory::Network, "sync.discovery"),
+            P2pError::Sync(_) => (ErrorCategory::Network, "sync.protocol"),
+            P2pError::Network(_) => (ErrorCategory::Network, "sync.network"),
+            P2pError::Unreachable(_) => (ErrorCategory::Network, "sync.unreachable"),
+            P2pError::Database(_) => (ErrorCategory::Internal, "sync.database"),
+            P2pError::Pairing(_) => (ErrorCategory::Validation, "sync.pairing"),
+            P2pError::UntrustedDevice(_) => {
+                (ErrorCategory::PermissionDenied, "sync.untrusted_device")
+            }
+            P2pError::Channel(_) => (ErrorCategory::Crypto, "sync.channel"),
+        };
+        CoreError::new(category, code, e.to_string())
+    }
+}
+
+impl From<RelaySyncError> for CoreError {
+    fn from(e: RelaySyncError) -> Self {
+        let (category, code) = match e {
+            RelaySyncError::Sync(e) => return e.into(),
+            RelaySyncError::Rusqlite(e) => return e.into(),
+            RelaySyncError::Db(e) => return e.into(),
+            RelaySyncError::Relay(RelayError::NetworkError(_)) => {
+                (ErrorCategory::Network, "sync.relay_network")
+            }
+            RelaySyncError::Relay(RelayError::Unauthorized) => {
+                (ErrorCategory::PermissionDenied, "sync.relay_unauthorized")
+            }
+            RelaySyncError::Relay(
+                RelayError::InvalidSignature
+                | RelayError::EncryptionError(_)
+                | RelayError::MessageExpired,
+            ) => (ErrorCategory::Crypto, "sync.relay_envelope"),
+            RelaySyncError::Relay(_) => (ErrorCategory::Network, "sync.relay"),
+            RelaySyncError::Serialization(_) | RelaySyncError::InvalidMessage(_) => {
+                (ErrorCategory::Validation, "sync.relay_message")
+            }
+            RelaySyncError::Key(_) => (ErrorCategory::Crypto, "sync.channel"),
+            RelaySyncError::UntrustedDevice(_) | RelaySyncError::KeyNotPinned(_) => {
+                (ErrorCategory::PermissionDenied, "sync.untrusted_device")
+            }
+            RelaySyncError::NotConfigured => {
+                (ErrorCategory::Validation, "sync.relay_not_configured")
+            }
+        };
+        CoreError::new(category, code, e.to_string())
+    }
+}
+
+impl From<DiscoveryError> for CoreError {
+    fn from(e: DiscoveryError) -> Self {
+        match e {
+            DiscoveryError::Database(e) => e.into(),
+            DiscoveryError::DaemonError(_) => {
+                CoreError::new(ErrorCategory::Network, "sync.discovery", e.to_string())
+            }
+            DiscoveryError::InvalidPeer(_) => {
+                CoreError::validation("sync.invalid_peer", e.to_string())
+            }
+        }
+    }
+}
+
+impl From<AuthError> for CoreError {
+    fn from(e: AuthError) -> Self {
+        let (category, code) = match &e {
+            AuthError::DatabaseError(_) => (ErrorCategory::Internal, "auth.database"),
+            AuthError::InvalidCredentials => (
+                ErrorCategory::PermissionDenied,
+                codes::AUTH_INVALID_CREDENTIALS,
+            ),
+            AuthError::UserAlreadyExists => (ErrorCategory::Conflict, "auth.user_exists"),
+            AuthError::UserNotFound => (ErrorCategory::NotFound, "user.not_found"),
+            AuthError::InvalidSession => (ErrorCategory::PermissionDenied, "auth.invalid_session"),
+            AuthError::SessionExpired => (ErrorCategory::PermissionDenied, "auth.session_expired"),
+            AuthError::PasswordHashError(_) => (ErrorCategory::Crypto, "auth.password_hash"),
+            AuthError::InvalidUserId => (ErrorCategory::Validation, "auth.invalid_user_id"),
+            AuthError::EmailInUse => (ErrorCategory::Conflict, "auth.email_in_use"),
+            AuthError::WeakPassword => (ErrorCategory::Validation, "auth.weak_password"),
+            AuthError::SessionAlreadyExists => (ErrorCategory::Conflict, "auth.session_exists"),
+            AuthError::LockedOut(_) => (ErrorCategory::PermissionDenied, codes::AUTH_LOCKED_OUT),
+        };
+        let error = CoreError::new(category, code, e.to_string());
+        match e {
+            AuthError::LockedOut(secs) => error.with_context("retry_after_secs", secs),
+            _ => error,
+        }
+    }
+}
+
+impl From<CalDavError> for CoreError {
+    fn from(e: CalDavError) -> Self {
+        let (category, code) = match e {
+            CalDavError::Database(e) => return e.into(),
+            CalDavError::Network(_) | CalDavError::Http(_) => {
+                (ErrorCategory::Network, "caldav.network")
+            }
+            CalDavError::Authentication => {
+                (ErrorCategory::PermissionDenied, "caldav.authentication")
+            }
+            CalDavError::Parse(_) => (ErrorCategory::Validation, "caldav.parse"),
+            CalDavError::Conflict(_) => (ErrorCategory::Conflict, "caldav.conflict"),
+            CalDavError::AccountNotFound => (ErrorCategory::NotFound, "caldav_account.not_found"),
+        };
+        CoreError::new(category, code, e.to_string())
+    }
+}
+
+impl From<CollaborationError> for CoreError {
+    fn from(e: CollaborationError) -> Self {
+        let (category, code) = match e {
+            CollaborationError::Rusqlite(e) => return e.into(),
+            CollaborationError::PermissionDenied(e) => return e.into(),
+            CollaborationError::LockedByOther(e) => return e.into(),
+            CollaborationError::InvalidLockTtl(_) => {
+                (ErrorCategory::Validation, "collaboration.invalid_lock_ttl")
+            }
+            CollaborationError::UserNotFound => (ErrorCategory::NotFound, "user.not_found"),
+            CollaborationError::InvalidRole => {
+                (ErrorCategory::Validation, "collaboration.invalid_role")
+            }
+            CollaborationError::InvitationExpired => (
+                ErrorCategory::Validation,
+                "collaboration.invitation_expired",
+            ),
+            CollaborationError::InvalidInvitation => (
+                ErrorCategory::NotFound,
+                "collaboration.invitation_not_found",
+            ),
+            CollaborationError::InvitationAlreadyAccepted => {
+                (ErrorCategory::Conflict, "collaboration.invitation_accepted")
+            }
+            CollaborationError::InvitationRevoked => (
+                ErrorCategory::Validation,
+                "collaboration.invitation_revoked",
+            ),
+            CollaborationError::InvitationSuperseded => (
+                ErrorCategory::Conflict,
+                "collaboration.invitation_superseded",
+            ),
+        };
+        CoreError::new(category, code, e.to_string())
+    }
+}
+
+impl From<SocialError> for CoreError {
+    fn from(e: SocialError) -> Self {
+        let (category, code) = match e {
+            SocialError::Database(e) => return e.into(),
+            SocialError::Crypto(e) => return e.into(),
+            SocialError::Serialization(e) => return e.into(),
+            SocialError::Note(e) => return e.into(),
+            SocialError::AccountNotFound => (ErrorCategory::NotFound, "social_account.not_found"),
+            SocialError::NotFound(_) => (ErrorCategory::NotFound, "social.not_found"),
+            SocialError::Platform(_) => (ErrorCategory::Network, "social.platform"),
+            SocialError::SyncInProgress(_) => (ErrorCategory::Conflict, "social.sync_in_progress"),
+            SocialError::InvalidInput(_) => (ErrorCategory::Validation, "social.invalid_input"),
+            SocialError::Embedding(_) => (ErrorCategory::Internal, "social.embedding"),
+        };
+        CoreError::new(category, code, e.to_string())
+    }
+}
+
+impl From<NoteDedupError> for CoreError {
+    fn from(e: NoteDedupError) -> Self {
+        let (category, code) = match e {
+            NoteDedupError::Rusqlite(e) => return e.into(),
+            NoteDedupError::Db(e) => return e.into(),
+            NoteDedupError::NotFound(id) => return CoreError::not_found("note", id),
+            NoteDedupError::InvalidThreshold(_) => {
+                (ErrorCategory::Validation, "note.invalid_threshold")
+            }
+            NoteDedupError::InvalidMerge(_) => (ErrorCategory::Validation, "note.invalid_merge"),
+            NoteDedupError::Locked(_) => (ErrorCategory::PermissionDenied, codes::NOTE_LOCKED),
+        };
+        CoreError::new(category, code, e.to_string())
+    }
+}
+
+impl From<NoteShareError> for CoreError {
+    fn from(e: NoteShareError) -> Self {
+        let (category, code) = match e {
+            NoteShareError::Rusqlite(e) => return e.into(),
+            NoteShareError::Db(e) => return e.into(),
+            NoteShareError::NotFound { entity, id } => return CoreError::not_found(entity, id),
+            NoteShareError::InvalidMove(_) => (ErrorCategory::Validation, "note.invalid_move"),
+            NoteShareError::InvalidReference(_) => {
+                (ErrorCategory::Validation, "note.invalid_reference")
+            }
+        };
+        CoreError::new(category, code, e.to_string())
+    }
+}
+
+impl From<BulkError> for CoreError {
+    fn from(e: BulkError) -> Self {
+        let (category, code) = match e {
+            BulkError::Rusqlite(e) => return e.into(),
+            BulkError::Db(e) => return e.into(),
+            BulkError::Move(e) => return e.into(),
+            BulkError::Invalid(_) => (ErrorCategory::Validation, "bulk.invalid"),
+            BulkError::Incomplete { ref skipped } => {
+                let ids: Vec<&str> = skipped.iter().map(|item| item.id.as_str()).collect();
+                return CoreError::new(
+                    ErrorCategory::Conflict,
+                    codes::BULK_INCOMPLETE,
+                    e.to_string(),
+                )
+                .with_context("skipped", ids.join(","));
+            }
+        };
+        CoreError::new(category, code, e.to_string())
+    }
+}
+
+impl From<NoteLockError> for CoreError {
+    fn from(e: NoteLockError) -> Self {
+        let (category, code) = match e {
+            NoteLockError::Rusqlite(e) => return e.into(),
+            NoteLockError::Db(e) => return e.into(),
+            NoteLockError::Crypto(e) => return e.into(),
+            NoteLockError::NotFound(id) => return CoreError::not_found("note", id),
+            NoteLockError::AlreadyLocked(_) => (ErrorCategory::Conflict, "note.already_locked"),
+            NoteLockError::NotLocked(_) => (ErrorCategory::Conflict, "note.not_locked"),
+            NoteLockError::EmptyPassphrase => (ErrorCategory::Validation, "note.empty_passphrase"),
+            NoteLockError::WrongPassphrase => {
+                (ErrorCategory::PermissionDenied, "note.wrong_passphrase")
+            }
+            NoteLockError::LockedOut(secs) => {
+                return CoreError::new(
+                    ErrorCategory::PermissionDenied,
+                    "note.locked_out",
+                    e.to_string(),
+                )
+                .with_context("retry_after_secs", secs)
+            }
+            NoteLockError::Corrupt(_) => (ErrorCategory::Crypto, "note.corrupt"),
+        };
+        CoreError::new(category, code, e.to_string())
+    }
+}
+
+impl From<PropertyError> for CoreError {
+    fn from(e: PropertyError) -> Self {
+        let (category, code) = match e {
+            PropertyError::Rusqlite(e) => return e.into(),
+            PropertyError::Db(e) => return e.into(),
+            PropertyError::NotFound(id) => return CoreError::not_found("property", id),
+            PropertyError::Duplicate(_) => (ErrorCategory::Conflict, "property.duplicate"),
+            PropertyError::InvalidDefinition(_) => {
+                (ErrorCategory::Validation, "property.invalid_definition")
+            }
+            PropertyError::InvalidValue { .. } => {
+                (ErrorCategory::Validation, "property.invalid_value")
+            }
+            PropertyError::OtherSpace(_) => (ErrorCategory::Validation, "property.other_space"),
+        };
+        CoreError::new(category, code, e.to_string())
+    }
+}
+
+impl From<TemplateError> for CoreError {
+    fn from(e: TemplateError) -> Self {
+        let code = match e {
+            TemplateError::Database(e) => return e.into(),
+            TemplateError::Rusqlite(e) => return e.into(),
+            TemplateError::SerdeJson(e) => return e.into(),
+            TemplateError::NotFound(id) => return CoreError::not_found("note_template", id),
+            TemplateError::Malformed(_) => "note_template.malformed",
+            TemplateError::UnknownVariable(_) => "note_template.unknown_variable",
+            TemplateError::MissingVariable(_) => "note_template.missing_variable",
+            TemplateError::InvalidVariableName(_) => "note_template.invalid_variable_name",
+        };
+        CoreError::validation(code, e.to_string())
+    }
+}
+
+impl From<SpaceTemplateError> for CoreError {
+    fn from(e: SpaceTemplateError) -> Self {
+        let code = match e {
+            SpaceTemplateError::Database(e) => return e.into(),
+            SpaceTemplateError::Rusqlite(e) => return e.into(),
+            SpaceTemplateError::Io(e) => return e.into(),
+            SpaceTemplateError::NoteTemplate(e) => return e.into(),
+            SpaceTemplateError::SpaceNotFound(id) => return CoreError::not_found("space", id),
+            SpaceTemplateError::NoteNotFound(id) => return CoreError::not_found("note", id),
+            SpaceTemplateError::UnknownBuiltin(key) => {
+                return CoreError::not_found("space_template", key)
+            }
+            SpaceTemplateError::NoteLocked(_) => {
+                return CoreError::new(
+                    ErrorCategory::PermissionDenied,
+                    codes::NOTE_LOCKED,
+                    e.to_string(),
+                )
+            }
+            SpaceTemplateError::SerdeJson(_) | SpaceTemplateError::Invalid(_) => {
+                "space_template.invalid"
+            }
+            SpaceTemplateError::Tag(_) => "space_template.invalid_tag",
+            SpaceTemplateError::UnsupportedVersion(_) => "space_template.unsupported_version",
+        };
+        CoreError::validation(code, e.to_string())
+    }
+}
+
+impl From<EditorError> for CoreError {
+    fn from(e: EditorError) -> Self {
+        match e {
+            EditorError::Rusqlite(e) => e.into(),
+            EditorError::InvalidFix(_) => {
+                CoreError::validation("editor.invalid_fix", e.to_string())
+            }
+        }
+    }
+}
+
+impl From<MeetingError> for CoreError {
+    fn from(e: MeetingError) -> Self {
+        match e {
+            MeetingError::Rusqlite(e) => e.into(),
+            MeetingError::NoteNotFound(id) => CoreError::not_found("note", id),
+            MeetingError::Llm(_) => {
+                CoreError::new(ErrorCategory::Network, "meeting.llm", e.to_string())
+            }
+            MeetingError::Regex(_) => CoreError::internal(e.to_string()),
+        }
+    }
+}
+
+impl From<LLMError> for CoreError {
+    fn from(e: LLMError) -> Self {
+        let (category, code) = match e {
+            LLMError::DatabaseError(e) => return e.into(),
+            LLMError::SerializationError(e) => return e.into(),
+            LLMError::NetworkError(_) | LLMError::HttpError(_) | LLMError::Timeout(_) => {
+                (ErrorCategory::Network, "llm.network")
+            }
+            LLMError::ConfigError(_) | LLMError::ValidationError(_) => {
+                (ErrorCategory::Validation, "llm.invalid_request")
+            }
+            LLMError::ModelNotFound(_) => (ErrorCategory::NotFound, "llm_model.not_found"),
+            LLMError::RateLimitExceeded
+            | LLMError::TokenLimitExceeded
+            | LLMError::BudgetExceeded(_) => (ErrorCategory::Validation, "llm.limit_exceeded"),
+            _ => (ErrorCategory::Internal, "llm.error"),
+        };
+        CoreError::new(category, code, e.to_string())
+    }
+}
+
+impl From<AutomationError> for CoreError {
+    fn from(e: AutomationError) -> Self {
+        match e {
+            AutomationError::Rusqlite(e) => e.into(),
+            AutomationError::Db(e) => e.into(),
+            AutomationError::Serialization(e) => e.into(),
+            AutomationError::Tag(TagError::Database(e)) => e.into(),
+            AutomationError::Tag(TagError::Rusqlite(e)) => e.into(),
+            AutomationError::NotFound(id) => CoreError::not_found("automation_rule", id),
+            AutomationError::Tag(_) | AutomationError::InvalidRule(_) => {
+                CoreError::validation("automation_rule.invalid", e.to_string())
+            }
+        }
+    }
+}
+
+impl From<SettingsError> for CoreError {
+    fn from(e: SettingsError) -> Self {
+        match e {
+            SettingsError::Rusqlite(e) => e.into(),
+            SettingsError::Db(e) => e.into(),
+            SettingsError::Malformed { .. }
+            | SettingsError::Invalid { .. }
+            | SettingsError::UnsupportedVersion { .. } => {
+                CoreError::validation("settings.invalid", e.to_string())
+            }
+        }
+    }
+}
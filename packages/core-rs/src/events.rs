@@ -0,0 +1,319 @@
+//! Core Events
+//!
+//! Work that runs long or in the background reports what came of it as
+//! [`CoreEvent`]s: sync conflicts, finished OCR jobs, backups, new insights,
+//! health goals reached or slipping, and CalDAV sign-in failures. The embedding app registers an [`EventSink`]
+//! to hear about them; the desktop forwards them to the Tauri event system so
+//! the frontend can show toasts.
+//!
+//! Emitting never blocks the operation that emits. Events go into a bounded
+//! queue that drops the oldest event when full, and a dispatcher thread hands
+//! them to the sinks. Nothing is queued while no sink is registered.
+
+use crate::foresight::{InsightSeverity, InsightType};
+use crate::health::goals::HealthGoalStatus;
+use crate::sync::conflict::ConflictType;
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+use ulid::Ulid;
+
+/// Events held for the sinks before the oldest are dropped
+pub const EVENT_QUEUE_CAPACITY: usize = 256;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum EventSeverity {
+    Info,
+    Warning,
+    Error,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum CoreEvent {
+    /// An incoming delta conflicted with a local change and awaits resolution
+    SyncConflictDetected {
+        space_id: String,
+        conflict_id: String,
+        /// Device the delta came from
+        device_id: String,
+        entity_type: String,
+        entity_id: String,
+        conflict_type: ConflictType,
+    },
+    OcrJobCompleted {
+        job_id: String,
+        blob_id: String,
+    },
+    /// The job ran out of attempts
+    OcrJobFailed {
+        job_id: String,
+        blob_id: String,
+        error: String,
+    },
+    BackupCompleted {
+        backup_id: String,
+        size_bytes: u64,
+        description: Option<String>,
+    },
+    BackupFailed {
+        description: Option<String>,
+        error: String,
+    },
+    InsightGenerated {
+        space_id: String,
+        insight_id: String,
+        insight_type: InsightType,
+        insight_severity: InsightSeverity,
+        title: String,
+        entity_type: Option<String>,
+        entity_id: Option<String>,
+    },
+    /// A health goal's metric met its target at the last evaluation
+    HealthGoalAchieved {
+        space_id: String,
+        goal_id: String,
+        title: String,
+        metric_type: String,
+        value: f64,
+    },
+    /// A health goal's status got worse since the evaluation before
+    HealthGoalRegressed {
+        space_id: String,
+        goal_id: String,
+        title: String,
+        metric_type: String,
+        from: HealthGoalStatus,
+        to: HealthGoalStatus,
+    },
+    /// The server turned down the account's credentials
+    CaldavAuthFailed {
+        account_id: String,
+        username: String,
+        url: String,
+    },
+    /// An automation rule asks the user about a note or task
+    AutomationPrompt {
+        space_id: String,
+        rule_id: String,
+        rule_name: String,
+        message: String,
+        entity_type: String,
+        entity_id: String,
+    },
+    /// Settings stored under `key` were saved or reset
+    SettingsChanged {
+        key: String,
+    },
+}
+
+impl CoreEvent {
+    pub fn severity(&self) -> EventSeverity {
+        match self {
+            CoreEvent::OcrJobCompleted { .. }
+            | CoreEvent::BackupCompleted { .. }
+            | CoreEvent::AutomationPrompt { .. }
+            | CoreEvent::HealthGoalAchieved { .. }
+            | CoreEvent::SettingsChanged { .. } => EventSeverity::Info,
+            CoreEvent::InsightGenerated {
+                insight_severity, ..
+            } => match insight_severity {
+                InsightSeverity::Low | InsightSeverity::Medium => EventSeverity::Info,
+                InsightSeverity::High | InsightSeverity::Critical => EventSeverity::Warning,
+            },
+            CoreEvent::SyncConflictDetected { .. } | CoreEvent::HealthGoalRegressed { .. } => {
+                EventSeverity::Warning
+            }
+            CoreEvent::OcrJobFailed { .. }
+            | CoreEvent::BackupFailed { .. }
+            | CoreEvent::CaldavAuthFailed { .. } => EventSeverity::Error,
+        }
+    }
+
+    /// Space the event belongs to, for events tied to one
+    pub fn space_id(&self) -> Option<&str> {
+        match self {
+            CoreEvent::SyncConflictDetected { space_id, .. }
+            | CoreEvent::InsightGenerated { space_id, .. }
+            | CoreEvent::AutomationPrompt { space_id, .. }
+            | CoreEvent::HealthGoalAchieved { space_id, .. }
+            | CoreEvent::HealthGoalRegressed { space_id, .. } => Some(space_id),
+            _ => None,
+        }
+    }
+}
+
+/// An event as delivered to the sinks
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct EmittedEvent {
+    pub id: String,
+    /// Unix milliseconds
+    pub emitted_at: i64,
+    pub severity: EventSeverity,
+    #[serde(flatten)]
+    pub event: CoreEvent,
+}
+
+/// Receiver of core events, registered by the embedding app. Sinks are
+/// called on the dispatcher thread, one event at a time, and should hand
+/// the event off rather than do slow work.
+pub trait EventSink: Send + Sync {
+    fn deliver(&self, event: &EmittedEvent);
+}
+
+/// Handle for unregistering a sink
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct SinkId(u64);
+
+#[derive(Default)]
+struct BusState {
+    queue: VecDeque<EmittedEvent>,
+    sinks: Vec<(SinkId, Arc<dyn EventSink>)>,
+    next_sink_id: u64,
+    dropped: u64,
+    dispatcher_started: bool,
+}
+
+#[derive(Default)]
+struct EventBus {
+    state: Mutex<BusState>,
+    queued: Condvar,
+}
+
+impl EventBus {
+    fn lock(&self) -> MutexGuard<'_, BusState> {
+        // Sinks run outside the lock, so a poisoned lock only means a
+        // panic between two plain field updates
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn dispatch(&self) {
+        loop {
+            let (event, sinks) = {
+                let mut state = self.lock();
+                let event = loop {
+                    match state.queue.pop_front() {
+                        Some(event) => break event,
+                        None => state = self.queued.wait(state).unwrap_or_else(|e| e.into_inner()),
+                    }
+                };
+                let sinks: Vec<_> = state.sinks.iter().map(|(_, s)| s.clone()).collect();
+                (event, sinks)
+            };
+            for sink in sinks {
+                if catch_unwind(AssertUnwindSafe(|| sink.deliver(&event))).is_err() {
+                    log::error!("[events] A sink panicked delivering event {}", event.id);
+                }
+            }
+        }
+    }
+}
+
+lazy_static::lazy_static! {
+    static ref BUS: Arc<EventBus> = Arc::new(EventBus::default());
+}
+
+/// Start delivering events to `sink`
+pub fn register_sink(sink: Arc<dyn EventSink>) -> SinkId {
+    let mut state = BUS.lock();
+    let id = SinkId(state.next_sink_id);
+    state.next_sink_id += 1;
+    state.sinks.push((id, sink));
+
+    if !state.dispatcher_started {
+        let bus = BUS.clone();
+        let spawned = std::thread::Builder::new()
+            .name("noteece-events".into())
+            .spawn(move || bus.dispatch());
+        match spawned {
+            Ok(_) => state.dispatcher_started = true,
+            Err(e) => log::error!("[events] Failed to start the event dispatcher: {}", e),
+        }
+    }
+    id
+}
+
+pub fn unregister_sink(id: SinkId) {
+    BUS.lock().sinks.retain(|(sink_id, _)| *sink_id != id);
+}
+
+/// Queue `event` for the registered sinks
+pub fn emit(event: CoreEvent) {
+    let mut state = BUS.lock();
+    if state.sinks.is_empty() {
+        return;
+    }
+    if state.queue.len() >= EVENT_QUEUE_CAPACITY {
+        state.queue.pop_front();
+        state.dropped += 1;
+        if state.dropped.is_power_of_two() {
+            log::warn!(
+                "[events] Sinks are falling behind; {} events dropped so far",
+                state.dropped
+            );
+        }
+    }
+    state.queue.push_back(EmittedEvent {
+        id: Ulid::new().to_string(),
+        emitted_at: chrono::Utc::now().timestamp_millis(),
+        severity: event.severity(),
+        event,
+    });
+    drop(state);
+    BUS.queued.notify_one();
+}
+
+/// Events dropped because the queue was full
+pub fn dropped_events() -> u64 {
+    BUS.lock().dropped
+}
+
+/// Sink that keeps every event it receives, for tests and diagnostics
+#[derive(Default)]
+pub struct CollectingSink {
+    events: Mutex<Vec<EmittedEvent>>,
+    arrived: Condvar,
+}
+
+impl CollectingSink {
+    pub fn events(&self) -> Vec<EmittedEvent> {
+        self.events
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .clone()
+    }
+
+    /// Wait up to `timeout` for an event matching `predicate`
+    pub fn wait_for(
+        &self,
+        timeout: Duration,
+        predicate: impl Fn(&CoreEvent) -> bool,
+    ) -> Option<EmittedEvent> {
+        let deadline = Instant::now() + timeout;
+        let mut events = self.events.lock().unwrap_or_else(|e| e.into_inner());
+        loop {
+            if let Some(found) = events.iter().find(|e| predicate(&e.event)) {
+                return Some(found.clone());
+            }
+            let left = deadline.checked_duration_since(Instant::now())?;
+            events = self
+                .arrived
+                .wait_timeout(events, left)
+                .unwrap_or_else(|e| e.into_inner())
+                .0;
+        }
+    }
+}
+
+impl EventSink for CollectingSink {
+    fn deliver(&self, event: &EmittedEvent) {
+        self.events
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .push(event.clone());
+        self.arrived.notify_all();
+    }
+}
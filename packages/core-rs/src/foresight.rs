@@ -1,9 +1,17 @@
+use crate::events::{self, CoreEvent};
 use chrono::{Duration, Utc};
-use rusqlite::{params, Connection};
+use rusqlite::{params, Connection, OptionalExtension};
 use serde::{Deserialize, Serialize};
 use thiserror::Error;
 use ulid::Ulid;
 
+pub mod schedule;
+
+pub use schedule::{
+    acquire_insight_lease, get_insight_schedule, release_insight_lease, run_due_insight_generation,
+    set_insight_schedule, InsightRun, InsightRunOutcome, InsightSchedule, InsightScheduler,
+};
+
 #[derive(Error, Debug)]
 pub enum ForesightError {
     #[error("Database error: {0}")]
@@ -12,6 +20,12 @@ pub enum ForesightError {
     Generation(String),
     #[error("Serde error: {0}")]
     Serde(#[from] serde_json::Error),
+    #[error("Settings error: {0}")]
+    Settings(#[from] crate::db::DbError),
+    #[error("Connection pool error: {0}")]
+    Pool(#[from] r2d2::Error),
+    #[error("Invalid schedule: {0}")]
+    InvalidSchedule(String),
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
@@ -102,38 +116,92 @@ pub struct Insight {
     pub dismissed: bool,
 }
 
+/// Detect and persist new insights for a space. Archived spaces get none.
 pub fn generate_insights(
     conn: &Connection,
     space_id: Ulid,
 ) -> Result<Vec<Insight>, ForesightError> {
+    generate_new_insights(conn, space_id)?;
+
+    // Return all active insights for this space (including previously generated ones)
+    get_active_insights(conn, &space_id.to_string())
+}
+
+/// Detect and persist insights for a space, returning the ones not already
+/// active
+pub(crate) fn generate_new_insights(
+    conn: &Connection,
+    space_id: Ulid,
+) -> Result<Vec<Insight>, ForesightError> {
+    let archived: bool = conn
+        .query_row(
+            "SELECT archived_at IS NOT NULL FROM space WHERE id = ?1",
+            [space_id.to_string()],
+            |row| row.get(0),
+        )
+        .optional()?
+        .unwrap_or(false);
+    if archived {
+        return Ok(Vec::new());
+    }
+
     let mut insights = Vec::new();
     insights.extend(detect_deadline_pressure(conn, space_id)?);
     insights.extend(detect_project_stagnation(conn, space_id)?);
     insights.extend(detect_habit_disruptions(conn, space_id)?);
 
     // Persist generated insights
-    for insight in &insights {
-        persist_insight(conn, insight, space_id)?;
+    let mut new_insights = Vec::new();
+    for insight in insights {
+        if persist_insight(conn, &insight, space_id)? {
+            new_insights.push(insight);
+        }
     }
-
-    // Return all active insights for this space (including previously generated ones)
-    get_active_insights(conn, &space_id.to_string())
+    for insight in &new_insights {
+        events::emit(CoreEvent::InsightGenerated {
+            space_id: space_id.to_string(),
+            insight_id: insight.id.clone(),
+            insight_type: insight.insight_type.clone(),
+            insight_severity: insight.severity.clone(),
+            title: insight.title.clone(),
+            entity_type: insight.context.entity_type.clone(),
+            entity_id: insight.context.entity_id.clone(),
+        });
+    }
+    Ok(new_insights)
 }
 
+/// Store an insight, or refresh the active insight of the same type about the
+/// same entity. Returns whether it was new.
 fn persist_insight(
     conn: &Connection,
     insight: &Insight,
     space_id: Ulid,
-) -> Result<(), ForesightError> {
+) -> Result<bool, ForesightError> {
     let context_json = serde_json::to_string(&insight.context)?;
     let actions_json = serde_json::to_string(&insight.suggested_actions)?;
 
-    // Upsert: If exists, ignore (idempotent generation).
-    // But typically generation creates new IDs. We might want to deduplicate by content signature?
-    // For now, assume generation logic creates new ID each run, which might flood DB.
-    // Ideally we check if similar active insight exists.
-    // For simplicity in this fix, we insert.
-    // NOTE: In production, we should check `entity_id` + `insight_type` uniqueness for active insights.
+    // Repeated runs, scheduled ones especially, would otherwise pile up
+    // copies of the same insight
+    let refreshed = conn.execute(
+        "UPDATE insight SET title = ?1, description = ?2, severity = ?3, context_json = ?4,
+                suggested_actions_json = ?5
+         WHERE space_id = ?6 AND insight_type = ?7 AND dismissed = 0
+           AND json_extract(context_json, '$.entity_id') IS ?8",
+        params![
+            insight.title,
+            insight.description,
+            insight.severity.as_str(),
+            context_json,
+            actions_json,
+            space_id.to_string(),
+            insight.insight_type.as_str(),
+            insight.context.entity_id
+        ],
+    )?;
+    if refreshed > 0 {
+        return Ok(false);
+    }
 
     conn.execute(
         "INSERT INTO insight (id, space_id, title, description, insight_type, severity, context_json, suggested_actions_json, created_at, dismissed)
@@ -151,7 +219,7 @@ fn persist_insight(
             insight.created_at
         ],
     )?;
-    Ok(())
+    Ok(true)
 }
 
 pub fn get_active_insights(
@@ -0,0 +1,335 @@
+//! Scheduled insight generation.
+//!
+//! Each space has an [`InsightSchedule`] in the `settings` table; spaces
+//! without one are refreshed every [`DEFAULT_INTERVAL_MINUTES`]. The embedding
+//! app calls [`run_due_insight_generation`] from a coarse timer, and every due
+//! space is generated through the same path as
+//! [`generate_insights`](super::generate_insights).
+//!
+//! A run holds a lease on the space's `insight_schedule_state` row, so two
+//! schedulers (threads or processes sharing the vault) never generate for the
+//! same space at once; a crashed run's lease expires. A space whose notes,
+//! tasks and projects haven't changed since the last generation is skipped,
+//! except once a day, since deadline insights depend on the date as well.
+
+use super::{generate_new_insights, ForesightError};
+use crate::db::{get_setting, set_setting};
+use chrono::{DateTime, Utc};
+use rusqlite::{params, Connection, OptionalExtension};
+use serde::{Deserialize, Serialize};
+use ulid::Ulid;
+
+/// Interval for spaces without a schedule of their own
+pub const DEFAULT_INTERVAL_MINUTES: u32 = 6 * 60;
+
+/// How long a run may hold a space before another scheduler can take over
+pub const DEFAULT_LEASE_SECS: i64 = 10 * 60;
+
+/// Unchanged spaces are still regenerated after this long
+const FULL_REFRESH_SECS: i64 = 24 * 60 * 60;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct InsightSchedule {
+    pub enabled: bool,
+    /// Minutes between runs
+    pub interval_minutes: u32,
+}
+
+impl Default for InsightSchedule {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            interval_minutes: DEFAULT_INTERVAL_MINUTES,
+        }
+    }
+}
+
+fn schedule_key(space_id: &str) -> String {
+    format!("insight_schedule:{}", space_id)
+}
+
+pub fn get_insight_schedule(
+    conn: &Connection,
+    space_id: &str,
+) -> Result<InsightSchedule, ForesightError> {
+    match get_setting(conn, &schedule_key(space_id))? {
+        Some(json) => Ok(serde_json::from_str(&json)?),
+        None => Ok(InsightSchedule::default()),
+    }
+}
+
+pub fn set_insight_schedule(
+    conn: &Connection,
+    space_id: &str,
+    schedule: &InsightSchedule,
+) -> Result<(), ForesightError> {
+    if schedule.interval_minutes == 0 {
+        return Err(ForesightError::InvalidSchedule(
+            "interval_minutes must be at least 1".to_string(),
+        ));
+    }
+    set_setting(
+        conn,
+        &schedule_key(space_id),
+        &serde_json::to_string(schedule)?,
+        Some("Insight generation schedule"),
+    )?;
+    Ok(())
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "status", rename_all = "snake_case")]
+pub enum InsightRunOutcome {
+    Generated {
+        new_insights: usize,
+    },
+    /// Nothing changed since the last generation
+    Unchanged,
+    /// Another scheduler is generating for the space
+    LeaseHeld,
+    Failed {
+        error: String,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct InsightRun {
+    pub space_id: String,
+    pub ran_at: i64,
+    pub outcome: InsightRunOutcome,
+}
+
+#[derive(Debug, Default)]
+struct ScheduleState {
+    last_run_at: Option<i64>,
+    last_generated_at: Option<i64>,
+    change_marker: Option<String>,
+}
+
+/// Runs due insight generation over a connection pool
+pub struct InsightScheduler<M = r2d2_sqlite::SqliteConnectionManager>
+where
+    M: r2d2::ManageConnection<Connection = Connection>,
+{
+    pool: r2d2::Pool<M>,
+    owner: String,
+    lease_secs: i64,
+}
+
+impl<M> InsightScheduler<M>
+where
+    M: r2d2::ManageConnection<Connection = Connection>,
+{
+    pub fn new(pool: r2d2::Pool<M>) -> Self {
+        Self {
+            pool,
+            owner: Ulid::new().to_string(),
+            lease_secs: DEFAULT_LEASE_SECS,
+        }
+    }
+
+    pub fn with_lease_secs(mut self, lease_secs: i64) -> Self {
+        self.lease_secs = lease_secs;
+        self
+    }
+
+    /// Lease owner this scheduler runs as
+    pub fn owner(&self) -> &str {
+        &self.owner
+    }
+
+    /// Generate insights for every unarchived space whose schedule is due at
+    /// `now`. Spaces that weren't due aren't reported.
+    pub fn run_due(&self, now: DateTime<Utc>) -> Result<Vec<InsightRun>, ForesightError> {
+        let conn = self.pool.get()?;
+        let space_ids = {
+            let mut stmt = conn.prepare("SELECT id FROM space WHERE archived_at IS NULL")?;
+            let ids = stmt
+                .query_map([], |row| row.get::<_, String>(0))?
+                .collect::<Result<Vec<_>, _>>()?;
+            ids
+        };
+
+        let mut runs = Vec::new();
+        for space_id in space_ids {
+            if let Some(outcome) = self.run_space(&conn, &space_id, now)? {
+                log::info!("[foresight] Scheduled run for {}: {:?}", space_id, outcome);
+                runs.push(InsightRun {
+                    space_id,
+                    ran_at: now.timestamp(),
+                    outcome,
+                });
+            }
+        }
+        Ok(runs)
+    }
+
+    fn run_space(
+        &self,
+        conn: &Connection,
+        space_id: &str,
+        now: DateTime<Utc>,
+    ) -> Result<Option<InsightRunOutcome>, ForesightError> {
+        let schedule = get_insight_schedule(conn, space_id)?;
+        if !schedule.enabled || !is_due(&load_state(conn, space_id)?, &schedule, now) {
+            return Ok(None);
+        }
+        if !acquire_insight_lease(conn, space_id, &self.owner, now, self.lease_secs)? {
+            return Ok(Some(InsightRunOutcome::LeaseHeld));
+        }
+
+        // Another scheduler may have finished a run between the check and
+        // taking the lease
+        let state = load_state(conn, space_id)?;
+        let outcome = if is_due(&state, &schedule, now) {
+            Some(generate_if_changed(conn, space_id, &state, now))
+        } else {
+            None
+        };
+        release_insight_lease(conn, space_id, &self.owner)?;
+        outcome.transpose()
+    }
+}
+
+/// Run due insight generation with the current time, for the app's timer
+pub fn run_due_insight_generation<M>(
+    pool: &r2d2::Pool<M>,
+) -> Result<Vec<InsightRun>, ForesightError>
+where
+    M: r2d2::ManageConnection<Connection = Connection>,
+{
+    InsightScheduler::new(pool.clone()).run_due(Utc::now())
+}
+
+/// Take the space's generation lease for `owner` until `lease_secs` after
+/// `now`. Fails while another owner holds an unexpired lease.
+pub fn acquire_insight_lease(
+    conn: &Connection,
+    space_id: &str,
+    owner: &str,
+    now: DateTime<Utc>,
+    lease_secs: i64,
+) -> Result<bool, ForesightError> {
+    let acquired = conn.execute(
+        "INSERT INTO insight_schedule_state (space_id, lease_owner, lease_expires_at)
+         VALUES (?1, ?2, ?3)
+         ON CONFLICT(space_id) DO UPDATE SET
+            lease_owner = excluded.lease_owner,
+            lease_expires_at = excluded.lease_expires_at
+         WHERE lease_owner IS NULL OR lease_owner = excluded.lease_owner
+            OR lease_expires_at <= ?4",
+        params![
+            space_id,
+            owner,
+            now.timestamp() + lease_secs,
+            now.timestamp()
+        ],
+    )?;
+    Ok(acquired > 0)
+}
+
+pub fn release_insight_lease(
+    conn: &Connection,
+    space_id: &str,
+    owner: &str,
+) -> Result<(), ForesightError> {
+    conn.execute(
+        "UPDATE insight_schedule_state SET lease_owner = NULL, lease_expires_at = NULL
+         WHERE space_id = ?1 AND lease_owner = ?2",
+        params![space_id, owner],
+    )?;
+    Ok(())
+}
+
+fn load_state(conn: &Connection, space_id: &str) -> Result<ScheduleState, ForesightError> {
+    Ok(conn
+        .query_row(
+            "SELECT last_run_at, last_generated_at, change_marker
+             FROM insight_schedule_state WHERE space_id = ?1",
+            [space_id],
+            |row| {
+                Ok(ScheduleState {
+                    last_run_at: row.get(0)?,
+                    last_generated_at: row.get(1)?,
+                    change_marker: row.get(2)?,
+                })
+            },
+        )
+        .optional()?
+        .unwrap_or_default())
+}
+
+fn is_due(state: &ScheduleState, schedule: &InsightSchedule, now: DateTime<Utc>) -> bool {
+    !state
+        .last_run_at
+        .is_some_and(|last| now.timestamp() < last + i64::from(schedule.interval_minutes) * 60)
+}
+
+fn generate_if_changed(
+    conn: &Connection,
+    space_id: &str,
+    state: &ScheduleState,
+    now: DateTime<Utc>,
+) -> Result<InsightRunOutcome, ForesightError> {
+    let marker = change_marker(conn, space_id)?;
+    let recent = state
+        .last_generated_at
+        .is_some_and(|at| now.timestamp() - at < FULL_REFRESH_SECS);
+    if recent && state.change_marker.as_deref() == Some(marker.as_str()) {
+        conn.execute(
+            "UPDATE insight_schedule_state SET last_run_at = ?1 WHERE space_id = ?2",
+            params![now.timestamp(), space_id],
+        )?;
+        return Ok(InsightRunOutcome::Unchanged);
+    }
+
+    let generated = Ulid::from_string(space_id)
+        .map_err(|e| ForesightError::Generation(e.to_string()))
+        .and_then(|space| generate_new_insights(conn, space));
+    match generated {
+        Ok(new_insights) => {
+            conn.execute(
+                "UPDATE insight_schedule_state
+                 SET last_run_at = ?1, last_generated_at = ?1, change_marker = ?2
+                 WHERE space_id = ?3",
+                params![now.timestamp(), marker, space_id],
+            )?;
+            Ok(InsightRunOutcome::Generated {
+                new_insights: new_insights.len(),
+            })
+        }
+        Err(e) => {
+            // Retried at the next interval rather than on every tick
+            log::error!(
+                "[foresight] Scheduled generation for {} failed: {}",
+                space_id,
+                e
+            );
+            conn.execute(
+                "UPDATE insight_schedule_state SET last_run_at = ?1 WHERE space_id = ?2",
+                params![now.timestamp(), space_id],
+            )?;
+            Ok(InsightRunOutcome::Failed {
+                error: e.to_string(),
+            })
+        }
+    }
+}
+
+/// Cheap fingerprint of the data insights are drawn from: row counts and
+/// latest modification times of the space's notes, tasks and projects, and
+/// the latest entry of the sync log (any applied delta counts as a change)
+fn change_marker(conn: &Connection, space_id: &str) -> Result<String, ForesightError> {
+    Ok(conn.query_row(
+        "SELECT
+            (SELECT COUNT(*) || ':' || COALESCE(MAX(modified_at), 0)
+             FROM note WHERE space_id = ?1) || '|' ||
+            (SELECT COUNT(*) || ':' || COALESCE(MAX(updated_at), 0)
+             FROM task WHERE space_id = ?1) || '|' ||
+            (SELECT COUNT(*) || ':' || COALESCE(MAX(updated_at), 0)
+             FROM project WHERE space_id = ?1) || '|' ||
+            (SELECT COALESCE(MAX(rowid), 0) FROM entity_sync_log)",
+        [space_id],
+        |row| row.get(0),
+    )?)
+}
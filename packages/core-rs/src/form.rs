@@ -1,10 +1,59 @@
 // packages/core-rs/src/form.rs
 
-use rusqlite::{Connection, Result};
+use chrono::{DateTime, Local, NaiveDate, NaiveTime};
+use rusqlite::{Connection, OptionalExtension, Result};
 use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::cmp::Ordering;
+use std::collections::BTreeSet;
+use std::fmt::{self, Write};
+use thiserror::Error;
 use ulid::Ulid;
 
 use crate::db::DbError;
+use crate::health::create_health_metric;
+use crate::ids::parse_entity_id;
+use crate::note::{create_note, Note};
+use crate::personal_modes::{create_transaction, CreateTransactionParams};
+use crate::property::{get_property_definitions, set_note_property, PropertyType};
+
+#[derive(Error, Debug)]
+pub enum FormError {
+    #[error("Database error: {0}")]
+    Database(#[from] DbError),
+    #[error("Rusqlite error: {0}")]
+    Rusqlite(#[from] rusqlite::Error),
+    #[error("Serde JSON error: {0}")]
+    SerdeJson(#[from] serde_json::Error),
+    #[error("I/O error: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("Invalid submission: {}", format_field_errors(.0))]
+    Validation(Vec<FieldError>),
+    /// A field's mapping couldn't be applied; nothing was saved
+    #[error("Mapping of {field} failed: {message}")]
+    Mapping { field: String, message: String },
+}
+
+/// Why a submitted value was rejected
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FieldError {
+    pub field: String,
+    pub message: String,
+}
+
+impl fmt::Display for FieldError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.field, self.message)
+    }
+}
+
+fn format_field_errors(errors: &[FieldError]) -> String {
+    errors
+        .iter()
+        .map(|e| e.to_string())
+        .collect::<Vec<_>>()
+        .join("; ")
+}
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct FormTemplate {
@@ -12,6 +61,17 @@ pub struct FormTemplate {
     pub space_id: String,
     pub name: String,
     pub fields: Vec<FormField>,
+    /// Bumped whenever the fields change
+    #[serde(default = "default_schema_version")]
+    pub schema_version: i64,
+    /// Title of the note a mapped submission creates, see
+    /// [`render_title_pattern`]
+    #[serde(default)]
+    pub title_pattern: Option<String>,
+}
+
+fn default_schema_version() -> i64 {
+    1
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -20,6 +80,52 @@ pub struct FormField {
     pub label: String,
     pub field_type: FormFieldType,
     pub default_value: Option<String>,
+    #[serde(default)]
+    pub required: bool,
+    /// Allowed values of a `Select` field
+    #[serde(default)]
+    pub options: Vec<String>,
+    /// Where [`submit_form_with_mapping`] puts the value
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mapping: Option<FieldMapping>,
+}
+
+/// An entity a field's value goes into besides the submission itself
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum FieldMapping {
+    /// Append the value to a `## heading` section of the submission's note
+    NoteSection { heading: String },
+    /// Record a number field as a health metric
+    HealthMetric {
+        metric_type: String,
+        unit: Option<String>,
+    },
+    /// Record a number field as the amount of a transaction
+    Transaction {
+        /// `expense` or `income`
+        transaction_type: String,
+        currency: String,
+        /// Used when `category_field` is unset or left empty
+        #[serde(default)]
+        category: String,
+        /// Text or select field of the same form holding the category
+        #[serde(default)]
+        category_field: Option<String>,
+        #[serde(default)]
+        account_id: String,
+    },
+    /// Set a property, by name, of the submission's note
+    NoteProperty { property: String },
+}
+
+impl FieldMapping {
+    fn targets_note(&self) -> bool {
+        matches!(
+            self,
+            FieldMapping::NoteSection { .. } | FieldMapping::NoteProperty { .. }
+        )
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -30,6 +136,7 @@ pub enum FormFieldType {
     Checkbox,
     Date,
     Time,
+    Select,
 }
 
 pub fn create_form_template(
@@ -38,6 +145,7 @@ pub fn create_form_template(
     name: &str,
     fields: Vec<FormField>,
 ) -> Result<FormTemplate, DbError> {
+    validate_mappings(conn, space_id, &fields)?;
     let id = Ulid::new().to_string();
     let fields_json =
         serde_json::to_string(&fields).map_err(|e| DbError::Message(e.to_string()))?;
@@ -45,17 +153,41 @@ pub fn create_form_template(
         "INSERT INTO form_template (id, space_id, name, fields_json) VALUES (?1, ?2, ?3, ?4)",
         rusqlite::params![id, space_id, name, fields_json],
     )?;
+    record_template_version(conn, &id, 1, &fields_json)?;
     Ok(FormTemplate {
         id,
         space_id: space_id.to_string(),
         name: name.to_string(),
         fields,
+        schema_version: 1,
+        title_pattern: None,
     })
 }
 
+fn record_template_version(
+    conn: &Connection,
+    template_id: &str,
+    version: i64,
+    fields_json: &str,
+) -> Result<(), DbError> {
+    conn.execute(
+        "INSERT OR REPLACE INTO form_template_version (template_id, version, fields_json, created_at)
+         VALUES (?1, ?2, ?3, ?4)",
+        rusqlite::params![
+            template_id,
+            version,
+            fields_json,
+            chrono::Utc::now().timestamp()
+        ],
+    )?;
+    Ok(())
+}
+
 pub fn get_form_template(conn: &Connection, id: &str) -> Result<FormTemplate, DbError> {
-    let mut stmt =
-        conn.prepare("SELECT id, space_id, name, fields_json FROM form_template WHERE id = ?1")?;
+    let mut stmt = conn.prepare(
+        "SELECT id, space_id, name, fields_json, schema_version, title_pattern
+         FROM form_template WHERE id = ?1",
+    )?;
     let mut rows = stmt.query(rusqlite::params![id])?;
     let row = rows
         .next()?
@@ -68,6 +200,8 @@ pub fn get_form_template(conn: &Connection, id: &str) -> Result<FormTemplate, Db
         space_id: row.get(1)?,
         name: row.get(2)?,
         fields,
+        schema_version: row.get(4)?,
+        title_pattern: row.get(5)?,
     })
 }
 
@@ -76,7 +210,7 @@ pub fn get_form_templates_for_space(
     space_id: &str,
 ) -> Result<Vec<FormTemplate>, DbError> {
     let mut stmt = conn
-        .prepare("SELECT id, space_id, name, fields_json FROM form_template WHERE space_id = ?1")?;
+        .prepare("SELECT id, space_id, name, fields_json, schema_version, title_pattern FROM form_template WHERE space_id = ?1")?;
     let mut rows = stmt.query(rusqlite::params![space_id])?;
     let mut templates = Vec::new();
     while let Some(row) = rows.next()? {
@@ -88,6 +222,8 @@ pub fn get_form_templates_for_space(
             space_id: row.get(1)?,
             name: row.get(2)?,
             fields,
+            schema_version: row.get(4)?,
+            title_pattern: row.get(5)?,
         });
     }
     Ok(templates)
@@ -101,10 +237,29 @@ pub fn update_form_template(
 ) -> Result<(), DbError> {
     let fields_json =
         serde_json::to_string(&fields).map_err(|e| DbError::Message(e.to_string()))?;
+    let current: Option<(String, String, i64)> = conn
+        .query_row(
+            "SELECT space_id, fields_json, schema_version FROM form_template WHERE id = ?1",
+            [id],
+            |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+        )
+        .optional()?;
+    let Some((space_id, current_fields, version)) = current else {
+        return Err(DbError::Message("Form template not found".to_string()));
+    };
+    validate_mappings(conn, &space_id, &fields)?;
+
+    // Existing submissions keep pointing at the version they were made with
+    let version = if current_fields == fields_json {
+        version
+    } else {
+        version + 1
+    };
     conn.execute(
-        "UPDATE form_template SET name = ?1, fields_json = ?2 WHERE id = ?3",
-        rusqlite::params![name, fields_json, id],
+        "UPDATE form_template SET name = ?1, fields_json = ?2, schema_version = ?3 WHERE id = ?4",
+        rusqlite::params![name, fields_json, version, id],
     )?;
+    record_template_version(conn, id, version, &fields_json)?;
     Ok(())
 }
 
@@ -115,3 +270,773 @@ pub fn delete_form_template(conn: &Connection, id: &str) -> Result<(), DbError>
     )?;
     Ok(())
 }
+
+/// Check each field's mapping against its type and, for note properties,
+/// the space's property definitions
+fn validate_mappings(
+    conn: &Connection,
+    space_id: &str,
+    fields: &[FormField],
+) -> Result<(), DbError> {
+    let mut errors = Vec::new();
+    let mut definitions = None;
+    for field in fields {
+        let Some(mapping) = &field.mapping else {
+            continue;
+        };
+        let problem = match mapping {
+            FieldMapping::NoteSection { heading } if heading.trim().is_empty() => {
+                Some("needs a section heading".to_string())
+            }
+            FieldMapping::NoteSection { .. } => None,
+            FieldMapping::HealthMetric { .. } | FieldMapping::Transaction { .. }
+                if !matches!(field.field_type, FormFieldType::Number) =>
+            {
+                Some("only number fields can become metrics or transactions".to_string())
+            }
+            FieldMapping::HealthMetric { metric_type, .. } if metric_type.trim().is_empty() => {
+                Some("needs a metric type".to_string())
+            }
+            FieldMapping::HealthMetric { .. } => None,
+            FieldMapping::Transaction {
+                transaction_type,
+                currency,
+                category,
+                category_field,
+                ..
+            } => {
+                let category_source = category_field
+                    .as_ref()
+                    .map(|name| (name, fields.iter().find(|f| &f.name == name)));
+                match category_source {
+                    _ if !matches!(transaction_type.as_str(), "expense" | "income") => {
+                        Some(format!(
+                            "transaction type must be expense or income, not {}",
+                            transaction_type
+                        ))
+                    }
+                    _ if currency.trim().is_empty() => Some("needs a currency".to_string()),
+                    Some((name, None)) => Some(format!(
+                        "category field {} is not a field of this form",
+                        name
+                    )),
+                    Some((name, Some(source)))
+                        if !matches!(
+                            source.field_type,
+                            FormFieldType::Text | FormFieldType::Select
+                        ) =>
+                    {
+                        Some(format!("category field {} is not text or select", name))
+                    }
+                    None if category.trim().is_empty() => {
+                        Some("needs a category or a category field".to_string())
+                    }
+                    _ => None,
+                }
+            }
+            FieldMapping::NoteProperty { property } => {
+                if definitions.is_none() {
+                    definitions = Some(
+                        get_property_definitions(conn, space_id)
+                            .map_err(|e| DbError::Message(e.to_string()))?,
+                    );
+                }
+                let definition = definitions
+                    .iter()
+                    .flatten()
+                    .find(|d| d.name.eq_ignore_ascii_case(property.trim()));
+                match definition {
+                    None => Some(format!("no property {} in this space", property)),
+                    Some(d) if !property_accepts(d.property_type, &field.field_type) => {
+                        Some(format!(
+                            "a {:?} field can't set the {} property {}",
+                            field.field_type,
+                            d.property_type.as_str(),
+                            d.name
+                        ))
+                    }
+                    Some(_) => None,
+                }
+            }
+        };
+        if let Some(message) = problem {
+            errors.push(FieldError {
+                field: field.name.clone(),
+                message,
+            });
+        }
+    }
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(DbError::Message(format!(
+            "Invalid field mappings: {}",
+            format_field_errors(&errors)
+        )))
+    }
+}
+
+/// Whether every value of the field fits the property. Text and select
+/// properties check select options when the value is set.
+fn property_accepts(property_type: PropertyType, field_type: &FormFieldType) -> bool {
+    match property_type {
+        PropertyType::Number => matches!(field_type, FormFieldType::Number),
+        PropertyType::Checkbox => matches!(field_type, FormFieldType::Checkbox),
+        PropertyType::Date => matches!(field_type, FormFieldType::Date),
+        PropertyType::Select => matches!(field_type, FormFieldType::Select | FormFieldType::Text),
+        PropertyType::Text => !matches!(field_type, FormFieldType::Checkbox),
+    }
+}
+
+/// Set or clear the title pattern of the notes a template's mapped
+/// submissions create
+pub fn set_form_title_pattern(
+    conn: &Connection,
+    template_id: &str,
+    pattern: Option<&str>,
+) -> Result<(), DbError> {
+    let template = get_form_template(conn, template_id)?;
+    let pattern = pattern.map(str::trim).filter(|p| !p.is_empty());
+    if let Some(pattern) = pattern {
+        check_title_pattern(pattern, &template.fields)
+            .map_err(|e| DbError::Message(format!("Invalid title pattern: {}", e)))?;
+    }
+    conn.execute(
+        "UPDATE form_template SET title_pattern = ?1 WHERE id = ?2",
+        rusqlite::params![pattern, template_id],
+    )?;
+    Ok(())
+}
+
+enum PatternPart<'a> {
+    Text(&'a str),
+    Placeholder(&'a str),
+}
+
+/// Split a pattern into text and `{...}` placeholders; `None` when a brace
+/// is left open
+fn parse_title_pattern(pattern: &str) -> Option<Vec<PatternPart<'_>>> {
+    let mut parts = Vec::new();
+    let mut rest = pattern;
+    while let Some(open) = rest.find('{') {
+        let close = rest[open..].find('}')? + open;
+        if open > 0 {
+            parts.push(PatternPart::Text(&rest[..open]));
+        }
+        parts.push(PatternPart::Placeholder(rest[open + 1..close].trim()));
+        rest = &rest[close + 1..];
+    }
+    if !rest.is_empty() {
+        parts.push(PatternPart::Text(rest));
+    }
+    Some(parts)
+}
+
+fn check_title_pattern(pattern: &str, fields: &[FormField]) -> Result<(), String> {
+    let parts = parse_title_pattern(pattern).ok_or("a { is never closed")?;
+    for part in parts {
+        let PatternPart::Placeholder(name) = part else {
+            continue;
+        };
+        if let Some(format) = name.strip_prefix("date:") {
+            if !valid_date_format(format) {
+                return Err(format!("{} is not a valid date format", format));
+            }
+        } else if name != "date" && !fields.iter().any(|f| f.name == name) {
+            return Err(format!("{{{}}} is neither a date nor a field", name));
+        }
+    }
+    Ok(())
+}
+
+/// Dates can't be formatted with unknown or time specifiers
+fn valid_date_format(format: &str) -> bool {
+    let mut rendered = String::new();
+    write!(rendered, "{}", NaiveDate::MIN.format(format)).is_ok()
+}
+
+/// Render a title pattern for a submission made on `date`. `{date}` is
+/// the date as `YYYY-MM-DD`, `{date:%A %-d %B}` the date in a `strftime`
+/// format, and `{field}` a submitted value, empty when it was left out.
+/// The date placeholders win over a field named `date`.
+pub fn render_title_pattern(pattern: &str, values: &Map<String, Value>, date: NaiveDate) -> String {
+    let Some(parts) = parse_title_pattern(pattern) else {
+        return pattern.to_string();
+    };
+    let mut title = String::new();
+    for part in parts {
+        match part {
+            PatternPart::Text(text) => title.push_str(text),
+            PatternPart::Placeholder("date") => {
+                title.push_str(&date.format("%Y-%m-%d").to_string())
+            }
+            PatternPart::Placeholder(name) => match name.strip_prefix("date:") {
+                Some(format) if valid_date_format(format) => {
+                    title.push_str(&date.format(format).to_string())
+                }
+                Some(_) => {}
+                None => match values.get(name) {
+                    None | Some(Value::Null) => {}
+                    Some(Value::String(s)) => title.push_str(s),
+                    Some(other) => title.push_str(&other.to_string()),
+                },
+            },
+        }
+    }
+    title.trim().to_string()
+}
+
+/// A filled-in form
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FormSubmission {
+    pub id: String,
+    pub template_id: String,
+    /// Template version the values were validated against
+    pub schema_version: i64,
+    pub values: Map<String, Value>,
+    pub note_id: Option<String>,
+    pub created_at: i64,
+}
+
+/// Condition on a submitted field value
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "op", rename_all = "snake_case")]
+pub enum FieldFilter {
+    Equals {
+        field: String,
+        value: Value,
+    },
+    /// Inclusive bounds; numbers compare numerically, dates and times as text
+    Range {
+        field: String,
+        min: Option<Value>,
+        max: Option<Value>,
+    },
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct SubmissionFilters {
+    #[serde(default)]
+    pub fields: Vec<FieldFilter>,
+    pub created_after: Option<i64>,
+    pub created_before: Option<i64>,
+    pub limit: Option<usize>,
+}
+
+/// Validate values against the template's fields and store them. Optional
+/// fields left out fall back to their default value, if any.
+pub fn submit_form(
+    conn: &Connection,
+    template_id: &str,
+    values: Map<String, Value>,
+    note_id: Option<&str>,
+) -> Result<FormSubmission, FormError> {
+    let template = get_form_template(conn, template_id)?;
+    let values = validate_values(&template.fields, values)?;
+    store_submission(conn, &template, values, note_id)
+}
+
+fn store_submission(
+    conn: &Connection,
+    template: &FormTemplate,
+    values: Map<String, Value>,
+    note_id: Option<&str>,
+) -> Result<FormSubmission, FormError> {
+    let submission = FormSubmission {
+        id: Ulid::new().to_string(),
+        template_id: template.id.clone(),
+        schema_version: template.schema_version,
+        values,
+        note_id: note_id.map(str::to_string),
+        created_at: chrono::Utc::now().timestamp(),
+    };
+    conn.execute(
+        "INSERT INTO form_submission (id, template_id, schema_version, values_json, note_id, created_at)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+        rusqlite::params![
+            submission.id,
+            submission.template_id,
+            submission.schema_version,
+            serde_json::to_string(&submission.values)?,
+            submission.note_id,
+            submission.created_at
+        ],
+    )?;
+    log::info!(
+        "[form] Stored submission {} for template {}",
+        submission.id,
+        template.id
+    );
+    Ok(submission)
+}
+
+/// What a mapped submission created
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MappedSubmission {
+    pub submission: FormSubmission,
+    /// Created when a field maps into a note or the template has a title
+    /// pattern
+    pub note: Option<Note>,
+    pub health_metric_ids: Vec<String>,
+    pub transaction_ids: Vec<String>,
+}
+
+/// Validate and store a submission like [`submit_form`], then apply each
+/// field's mapping in `space_id`: note sections and properties go into one
+/// new note, titled by the template's pattern, and metrics and transactions
+/// are created per field. Either everything is saved or, when a mapping
+/// fails, nothing is.
+pub fn submit_form_with_mapping(
+    conn: &Connection,
+    template_id: &str,
+    values: Map<String, Value>,
+    space_id: &str,
+) -> Result<MappedSubmission, FormError> {
+    let space = parse_entity_id("space", "space_id", space_id).map_err(DbError::from)?;
+    let template = get_form_template(conn, template_id)?;
+    let values = validate_values(&template.fields, values)?;
+    let now = chrono::Utc::now();
+    let mapped = |field: &&FormField| field.mapping.is_some() && values.contains_key(&field.name);
+    let mapped_fields: Vec<&FormField> = template.fields.iter().filter(mapped).collect();
+
+    let tx = conn.unchecked_transaction()?;
+    let wants_note = template.title_pattern.is_some()
+        || mapped_fields
+            .iter()
+            .any(|f| f.mapping.as_ref().is_some_and(FieldMapping::targets_note));
+    let note = if wants_note {
+        let title = match &template.title_pattern {
+            Some(pattern) => {
+                render_title_pattern(pattern, &values, now.with_timezone(&Local).date_naive())
+            }
+            None => template.name.clone(),
+        };
+        Some(create_note(
+            &tx,
+            space_id,
+            &title,
+            &note_sections(&mapped_fields, &values),
+        )?)
+    } else {
+        None
+    };
+    let note_id = note.as_ref().map(|n| n.id.0);
+
+    let mut health_metric_ids = Vec::new();
+    let mut transaction_ids = Vec::new();
+    for field in mapped_fields {
+        let value = &values[&field.name];
+        let fail = |message: String| FormError::Mapping {
+            field: field.name.clone(),
+            message,
+        };
+        match field.mapping.as_ref() {
+            Some(FieldMapping::HealthMetric { metric_type, unit }) => {
+                let amount = value
+                    .as_f64()
+                    .ok_or_else(|| fail("is not a number".into()))?;
+                let metric = create_health_metric(
+                    &tx,
+                    space,
+                    note_id,
+                    metric_type,
+                    amount,
+                    unit.clone(),
+                    None,
+                    now.timestamp(),
+                )
+                .map_err(|e| fail(e.to_string()))?;
+                health_metric_ids.push(metric.id.to_string());
+            }
+            Some(FieldMapping::Transaction {
+                transaction_type,
+                currency,
+                category,
+                category_field,
+                account_id,
+            }) => {
+                let amount = value
+                    .as_f64()
+                    .ok_or_else(|| fail("is not a number".into()))?;
+                let category = category_field
+                    .as_ref()
+                    .and_then(|name| values.get(name))
+                    .and_then(Value::as_str)
+                    .filter(|c| !c.trim().is_empty())
+                    .unwrap_or(category);
+                let transaction = create_transaction(
+                    &tx,
+                    CreateTransactionParams {
+                        space_id: space,
+                        transaction_type,
+                        amount,
+                        currency,
+                        category,
+                        account_id,
+                        date: now.timestamp(),
+                        description: Some(template.name.as_str()),
+                    },
+                )
+                .map_err(|e| fail(e.to_string()))?;
+                transaction_ids.push(transaction.id);
+            }
+            Some(FieldMapping::NoteProperty { property }) => {
+                let Some(note) = &note else { continue };
+                let definitions =
+                    get_property_definitions(&tx, space_id).map_err(|e| fail(e.to_string()))?;
+                let definition = definitions
+                    .iter()
+                    .find(|d| d.name.eq_ignore_ascii_case(property.trim()))
+                    .ok_or_else(|| fail(format!("no property {} in this space", property)))?;
+                set_note_property(&tx, &note.id.0.to_string(), &definition.id, value)
+                    .map_err(|e| fail(e.to_string()))?;
+            }
+            // Already part of the note's content
+            Some(FieldMapping::NoteSection { .. }) | None => {}
+        }
+    }
+
+    let note_id = note_id.map(|id| id.to_string());
+    let submission = store_submission(&tx, &template, values, note_id.as_deref())?;
+    tx.commit()?;
+    log::info!(
+        "[form] Applied mappings of submission {}: note {:?}, {} metrics, {} transactions",
+        submission.id,
+        note_id,
+        health_metric_ids.len(),
+        transaction_ids.len()
+    );
+    Ok(MappedSubmission {
+        submission,
+        note,
+        health_metric_ids,
+        transaction_ids,
+    })
+}
+
+/// Markdown of the `NoteSection` fields, one section per heading in the
+/// order the headings first appear. Long text is a paragraph of its own,
+/// other values a labelled line.
+fn note_sections(fields: &[&FormField], values: &Map<String, Value>) -> String {
+    let mut sections: Vec<(&str, Vec<String>)> = Vec::new();
+    for field in fields {
+        let Some(FieldMapping::NoteSection { heading }) = &field.mapping else {
+            continue;
+        };
+        let text = match (&field.field_type, &values[&field.name]) {
+            (FormFieldType::Textarea, Value::String(s)) => s.trim().to_string(),
+            (FormFieldType::Checkbox, Value::Bool(b)) => {
+                format!("{}: {}", field.label, if *b { "Yes" } else { "No" })
+            }
+            (_, Value::String(s)) => format!("{}: {}", field.label, s),
+            (_, other) => format!("{}: {}", field.label, other),
+        };
+        let heading = heading.trim();
+        match sections.iter_mut().find(|(h, _)| *h == heading) {
+            Some((_, lines)) => lines.push(text),
+            None => sections.push((heading, vec![text])),
+        }
+    }
+    sections
+        .into_iter()
+        .map(|(heading, lines)| format!("## {}\n\n{}\n", heading, lines.join("\n\n")))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Check every value and normalize it to the field's JSON representation
+fn validate_values(
+    fields: &[FormField],
+    mut values: Map<String, Value>,
+) -> Result<Map<String, Value>, FormError> {
+    let mut errors = Vec::new();
+    let mut validated = Map::new();
+
+    for field in fields {
+        let value = match values.remove(&field.name) {
+            Some(value) if !is_blank(&value) => value,
+            _ => match &field.default_value {
+                Some(default) if !default.is_empty() => Value::String(default.clone()),
+                _ => {
+                    if field.required {
+                        errors.push(FieldError {
+                            field: field.name.clone(),
+                            message: "is required".to_string(),
+                        });
+                    }
+                    continue;
+                }
+            },
+        };
+        match coerce_value(field, value) {
+            Ok(value) => {
+                validated.insert(field.name.clone(), value);
+            }
+            Err(message) => errors.push(FieldError {
+                field: field.name.clone(),
+                message,
+            }),
+        }
+    }
+    for name in values.keys() {
+        errors.push(FieldError {
+            field: name.clone(),
+            message: "is not a field of this form".to_string(),
+        });
+    }
+
+    if errors.is_empty() {
+        Ok(validated)
+    } else {
+        Err(FormError::Validation(errors))
+    }
+}
+
+fn is_blank(value: &Value) -> bool {
+    match value {
+        Value::Null => true,
+        Value::String(s) => s.trim().is_empty(),
+        _ => false,
+    }
+}
+
+/// Convert a value to the type of `field`; strings are parsed, since form
+/// inputs usually send text
+fn coerce_value(field: &FormField, value: Value) -> Result<Value, String> {
+    match (&field.field_type, value) {
+        (FormFieldType::Text | FormFieldType::Textarea, Value::String(s)) => Ok(Value::String(s)),
+        (FormFieldType::Text | FormFieldType::Textarea, _) => Err("must be text".to_string()),
+        (FormFieldType::Number, Value::Number(n)) => Ok(Value::Number(n)),
+        (FormFieldType::Number, Value::String(s)) => s
+            .trim()
+            .parse::<f64>()
+            .ok()
+            .and_then(number_value)
+            .ok_or_else(|| format!("'{}' is not a number", s)),
+        (FormFieldType::Number, _) => Err("must be a number".to_string()),
+        (FormFieldType::Checkbox, Value::Bool(b)) => Ok(Value::Bool(b)),
+        (FormFieldType::Checkbox, Value::String(s)) => match s.trim() {
+            "true" => Ok(Value::Bool(true)),
+            "false" => Ok(Value::Bool(false)),
+            _ => Err(format!("'{}' is not true or false", s)),
+        },
+        (FormFieldType::Checkbox, _) => Err("must be true or false".to_string()),
+        (FormFieldType::Date, Value::String(s)) => NaiveDate::parse_from_str(s.trim(), "%Y-%m-%d")
+            .map(|d| Value::String(d.format("%Y-%m-%d").to_string()))
+            .map_err(|_| format!("'{}' is not a YYYY-MM-DD date", s)),
+        (FormFieldType::Time, Value::String(s)) => NaiveTime::parse_from_str(s.trim(), "%H:%M")
+            .or_else(|_| NaiveTime::parse_from_str(s.trim(), "%H:%M:%S"))
+            .map(|t| Value::String(t.format("%H:%M:%S").to_string()))
+            .map_err(|_| format!("'{}' is not a HH:MM time", s)),
+        (FormFieldType::Date | FormFieldType::Time, _) => Err("must be text".to_string()),
+        (FormFieldType::Select, Value::String(s)) if field.options.contains(&s) => {
+            Ok(Value::String(s))
+        }
+        (FormFieldType::Select, Value::String(s)) => Err(format!(
+            "'{}' is not one of: {}",
+            s,
+            field.options.join(", ")
+        )),
+        (FormFieldType::Select, _) => Err("must be one of the options".to_string()),
+    }
+}
+
+/// Whole numbers are stored as integers so they read back the way they were typed
+fn number_value(n: f64) -> Option<Value> {
+    if !n.is_finite() {
+        return None;
+    }
+    if n.fract() == 0.0 && n.abs() < i64::MAX as f64 {
+        Some(Value::from(n as i64))
+    } else {
+        serde_json::Number::from_f64(n).map(Value::Number)
+    }
+}
+
+/// Submissions of a template, oldest first, matching every filter
+pub fn get_form_submissions(
+    conn: &Connection,
+    template_id: &str,
+    filters: &SubmissionFilters,
+) -> Result<Vec<FormSubmission>, FormError> {
+    let template = get_form_template(conn, template_id)?;
+    let mut stmt = conn.prepare(
+        "SELECT id, template_id, schema_version, values_json, note_id, created_at
+         FROM form_submission
+         WHERE template_id = ?1 AND created_at >= ?2 AND created_at <= ?3
+         ORDER BY created_at, rowid",
+    )?;
+    let rows = stmt
+        .query_map(
+            rusqlite::params![
+                template_id,
+                filters.created_after.unwrap_or(i64::MIN),
+                filters.created_before.unwrap_or(i64::MAX)
+            ],
+            |row| {
+                Ok((
+                    row.get::<_, String>(0)?,
+                    row.get::<_, String>(1)?,
+                    row.get::<_, i64>(2)?,
+                    row.get::<_, String>(3)?,
+                    row.get::<_, Option<String>>(4)?,
+                    row.get::<_, i64>(5)?,
+                ))
+            },
+        )?
+        .collect::<Result<Vec<_>, _>>()?;
+
+    // Filter values are typed like the current fields, so "5" finds 5
+    let field_filters: Vec<FieldFilter> = filters
+        .fields
+        .iter()
+        .map(|filter| normalize_filter(&template.fields, filter))
+        .collect();
+
+    let mut submissions = Vec::new();
+    for (id, template_id, schema_version, values_json, note_id, created_at) in rows {
+        let values: Map<String, Value> = serde_json::from_str(&values_json)?;
+        if !field_filters.iter().all(|f| matches_filter(&values, f)) {
+            continue;
+        }
+        submissions.push(FormSubmission {
+            id,
+            template_id,
+            schema_version,
+            values,
+            note_id,
+            created_at,
+        });
+        if filters
+            .limit
+            .is_some_and(|limit| submissions.len() >= limit)
+        {
+            break;
+        }
+    }
+    Ok(submissions)
+}
+
+fn normalize_filter(fields: &[FormField], filter: &FieldFilter) -> FieldFilter {
+    let coerce = |name: &str, value: &Value| {
+        fields
+            .iter()
+            .find(|f| f.name == name)
+            .and_then(|f| coerce_value(f, value.clone()).ok())
+            .unwrap_or_else(|| value.clone())
+    };
+    match filter {
+        FieldFilter::Equals { field, value } => FieldFilter::Equals {
+            field: field.clone(),
+            value: coerce(field, value),
+        },
+        FieldFilter::Range { field, min, max } => FieldFilter::Range {
+            field: field.clone(),
+            min: min.as_ref().map(|v| coerce(field, v)),
+            max: max.as_ref().map(|v| coerce(field, v)),
+        },
+    }
+}
+
+fn matches_filter(values: &Map<String, Value>, filter: &FieldFilter) -> bool {
+    match filter {
+        FieldFilter::Equals { field, value } => values
+            .get(field)
+            .is_some_and(|v| compare_values(v, value) == Some(Ordering::Equal)),
+        FieldFilter::Range { field, min, max } => {
+            let Some(v) = values.get(field) else {
+                return false;
+            };
+            let above_min = min
+                .as_ref()
+                .is_none_or(|min| compare_values(v, min).is_some_and(|o| o.is_ge()));
+            let below_max = max
+                .as_ref()
+                .is_none_or(|max| compare_values(v, max).is_some_and(|o| o.is_le()));
+            above_min && below_max
+        }
+    }
+}
+
+/// Order two values of the same JSON type; `None` when they can't be compared
+fn compare_values(a: &Value, b: &Value) -> Option<Ordering> {
+    match (a, b) {
+        (Value::Number(a), Value::Number(b)) => a.as_f64()?.partial_cmp(&b.as_f64()?),
+        (Value::String(a), Value::String(b)) => Some(a.cmp(b)),
+        (Value::Bool(a), Value::Bool(b)) => Some(a.cmp(b)),
+        _ => None,
+    }
+}
+
+/// Write a template's submissions to a CSV file and return how many rows were
+/// written. Columns are the current fields in order, followed by fields that
+/// only older template versions had.
+pub fn export_submissions_csv(
+    conn: &Connection,
+    template_id: &str,
+    path: &str,
+) -> Result<usize, FormError> {
+    let template = get_form_template(conn, template_id)?;
+    let submissions = get_form_submissions(conn, template_id, &SubmissionFilters::default())?;
+
+    let mut columns: Vec<String> = template.fields.iter().map(|f| f.name.clone()).collect();
+    let retired: BTreeSet<&String> = submissions
+        .iter()
+        .flat_map(|s| s.values.keys())
+        .filter(|name| !columns.contains(name))
+        .collect();
+    columns.extend(retired.into_iter().cloned());
+
+    let mut csv = String::new();
+    let header = ["id", "created_at", "schema_version", "note_id"]
+        .into_iter()
+        .map(str::to_string)
+        .chain(columns.iter().cloned());
+    push_csv_row(&mut csv, header);
+    for submission in &submissions {
+        let created_at = DateTime::from_timestamp(submission.created_at, 0)
+            .map(|dt| dt.to_rfc3339())
+            .unwrap_or_default();
+        let row = [
+            submission.id.clone(),
+            created_at,
+            submission.schema_version.to_string(),
+            submission.note_id.clone().unwrap_or_default(),
+        ]
+        .into_iter()
+        .chain(
+            columns
+                .iter()
+                .map(|name| match submission.values.get(name) {
+                    None | Some(Value::Null) => String::new(),
+                    Some(Value::String(s)) => s.clone(),
+                    Some(other) => other.to_string(),
+                }),
+        );
+        push_csv_row(&mut csv, row);
+    }
+
+    std::fs::write(path, csv)?;
+    log::info!(
+        "[form] Exported {} submissions of template {} to {}",
+        submissions.len(),
+        template_id,
+        path
+    );
+    Ok(submissions.len())
+}
+
+fn push_csv_row(csv: &mut String, cells: impl Iterator<Item = String>) {
+    let row: Vec<String> = cells.map(|cell| csv_escape(&cell)).collect();
+    csv.push_str(&row.join(","));
+    csv.push_str("\r\n");
+}
+
+/// Quote a cell when it contains a separator, quote or line break (RFC 4180)
+fn csv_escape(cell: &str) -> String {
+    if cell.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", cell.replace('"', "\"\""))
+    } else {
+        cell.to_string()
+    }
+}
@@ -1,5 +1,6 @@
 use crate::db::DbError;
-use rusqlite::{Connection, Result};
+use crate::undo::{capture_rows, record_undo, UndoRecord, UndoSnapshot};
+use rusqlite::{Connection, OptionalExtension, Result};
 use serde::{Deserialize, Serialize};
 use ulid::Ulid;
 
@@ -153,7 +154,37 @@ pub fn update_goal_progress(
     .map_err(|e| e.into())
 }
 
+/// Delete a goal, journaling it so the delete can be undone
 pub fn delete_goal(conn: &Connection, goal_id: Ulid) -> Result<(), DbError> {
-    conn.execute("DELETE FROM goal WHERE id = ?1", [&goal_id.to_string()])?;
+    let id = goal_id.to_string();
+    let tx = conn.unchecked_transaction()?;
+    let goal = capture_rows(&tx, "goal", "*", "id = ?1", [&id])?;
+    let existing: Option<(String, String)> = tx
+        .query_row(
+            "SELECT space_id, title FROM goal WHERE id = ?1",
+            [&id],
+            |row| Ok((row.get(0)?, row.get(1)?)),
+        )
+        .optional()?;
+    tx.execute("DELETE FROM goal WHERE id = ?1", [&id])?;
+
+    if let Some((space_id, title)) = existing {
+        record_undo(
+            &tx,
+            UndoRecord {
+                space_id: &space_id,
+                operation: "delete_goal",
+                entity_type: "goal",
+                entity_id: &id,
+                summary: format!("Deleted goal \"{}\"", title),
+                snapshot: UndoSnapshot {
+                    deleted: goal.into_iter().collect(),
+                    modified: Vec::new(),
+                    inserted: Vec::new(),
+                },
+            },
+        );
+    }
+    tx.commit()?;
     Ok(())
 }
@@ -1,9 +1,13 @@
 use crate::db::DbError;
-use rusqlite::Connection;
+use rusqlite::{CachedStatement, Connection, OptionalExtension};
 use serde::Serialize;
+use std::collections::{HashMap, HashSet, VecDeque};
 use ulid::Ulid;
 
-#[derive(Serialize, Debug)]
+/// Upper bound on BFS depth for neighborhood and path queries
+pub const MAX_GRAPH_DEPTH: usize = 10;
+
+#[derive(Serialize, Debug, Clone)]
 pub struct GraphNode {
     pub id: String,
     pub label: String,
@@ -11,7 +15,7 @@ pub struct GraphNode {
     pub val: i32,      // weight/size
 }
 
-#[derive(Serialize, Debug)]
+#[derive(Serialize, Debug, Clone)]
 pub struct GraphEdge {
     pub source: String,
     pub target: String,
@@ -114,3 +118,183 @@ pub fn get_vault_graph(conn: &Connection, space_id: Ulid) -> Result<GraphData, D
 
     Ok(GraphData { nodes, edges })
 }
+
+fn note_node(id: String, title: String) -> GraphNode {
+    GraphNode {
+        id,
+        label: title,
+        group: "note".to_string(),
+        val: 10,
+    }
+}
+
+/// Title of a non-trashed note, or None if it is missing or trashed
+fn live_note_title(conn: &Connection, note_id: &str) -> Result<Option<String>, DbError> {
+    Ok(conn
+        .prepare_cached("SELECT title FROM note WHERE id = ?1 AND is_trashed = 0")?
+        .query_row([note_id], |row| row.get(0))
+        .optional()?)
+}
+
+/// Non-trashed notes linked to or from `note_id`; links are followed in both directions
+fn linked_notes(stmt: &mut CachedStatement<'_>, note_id: &str) -> Result<Vec<String>, DbError> {
+    let rows = stmt.query_map([note_id], |row| row.get(0))?;
+    Ok(rows.collect::<Result<_, _>>()?)
+}
+
+fn neighbors_statement(conn: &Connection) -> Result<CachedStatement<'_>, DbError> {
+    Ok(conn.prepare_cached(
+        "SELECT l.target_note_id FROM link l
+         JOIN note n ON n.id = l.target_note_id AND n.is_trashed = 0
+         WHERE l.source_note_id = ?1
+         UNION
+         SELECT l.source_note_id FROM link l
+         JOIN note n ON n.id = l.source_note_id AND n.is_trashed = 0
+         WHERE l.target_note_id = ?1
+         ORDER BY 1",
+    )?)
+}
+
+/// The subgraph induced by the notes within `depth` links of `note_id`.
+///
+/// Walks the link table breadth-first, one level at a time, and stops once
+/// `max_nodes` notes have been collected. Edges are every link between the
+/// collected notes.
+pub fn get_neighborhood(
+    conn: &Connection,
+    note_id: Ulid,
+    depth: usize,
+    max_nodes: usize,
+) -> Result<GraphData, DbError> {
+    let start = note_id.to_string();
+    let Some(start_title) = live_note_title(conn, &start)? else {
+        return Ok(GraphData {
+            nodes: Vec::new(),
+            edges: Vec::new(),
+        });
+    };
+
+    let depth = depth.min(MAX_GRAPH_DEPTH);
+    let max_nodes = max_nodes.max(1);
+    let mut neighbors = neighbors_statement(conn)?;
+
+    let mut visited = HashSet::from([start.clone()]);
+    let mut nodes = vec![note_node(start.clone(), start_title)];
+    let mut frontier = vec![start];
+
+    'levels: for _ in 0..depth {
+        let mut next = Vec::new();
+        for current in &frontier {
+            for neighbor in linked_notes(&mut neighbors, current)? {
+                if visited.len() >= max_nodes {
+                    break 'levels;
+                }
+                if visited.insert(neighbor.clone()) {
+                    let title = live_note_title(conn, &neighbor)?.unwrap_or_default();
+                    nodes.push(note_node(neighbor.clone(), title));
+                    next.push(neighbor);
+                }
+            }
+        }
+        if next.is_empty() {
+            break;
+        }
+        frontier = next;
+    }
+
+    // Edges induced by the collected notes
+    let mut outgoing = conn.prepare_cached(
+        "SELECT target_note_id FROM link WHERE source_note_id = ?1 ORDER BY target_note_id",
+    )?;
+    let mut edges = Vec::new();
+    for node in &nodes {
+        let targets = outgoing.query_map([&node.id], |row| row.get::<_, String>(0))?;
+        for target in targets {
+            let target = target?;
+            if visited.contains(&target) {
+                edges.push(GraphEdge {
+                    source: node.id.clone(),
+                    target,
+                    value: 2,
+                });
+            }
+        }
+    }
+
+    Ok(GraphData { nodes, edges })
+}
+
+/// Shortest chain of links from one note to another, including both ends.
+///
+/// Links are followed in either direction. Returns None if the notes are not
+/// connected within `max_depth` links.
+pub fn find_path(
+    conn: &Connection,
+    from_note: Ulid,
+    to_note: Ulid,
+    max_depth: usize,
+) -> Result<Option<Vec<GraphNode>>, DbError> {
+    let from = from_note.to_string();
+    let to = to_note.to_string();
+    if live_note_title(conn, &from)?.is_none() || live_note_title(conn, &to)?.is_none() {
+        return Ok(None);
+    }
+
+    let max_depth = max_depth.min(MAX_GRAPH_DEPTH);
+    let mut neighbors = neighbors_statement(conn)?;
+    let mut parents: HashMap<String, Option<String>> = HashMap::from([(from.clone(), None)]);
+    let mut queue = VecDeque::from([(from, 0)]);
+
+    let mut found = parents.contains_key(&to);
+    while let Some((current, distance)) = queue.pop_front() {
+        if found || distance >= max_depth {
+            break;
+        }
+        for neighbor in linked_notes(&mut neighbors, &current)? {
+            if parents.contains_key(&neighbor) {
+                continue;
+            }
+            parents.insert(neighbor.clone(), Some(current.clone()));
+            if neighbor == to {
+                found = true;
+                break;
+            }
+            queue.push_back((neighbor, distance + 1));
+        }
+    }
+    if !found {
+        return Ok(None);
+    }
+
+    let mut ids = vec![to];
+    while let Some(Some(parent)) = parents.get(ids.last().expect("path is never empty")) {
+        ids.push(parent.clone());
+    }
+    ids.reverse();
+
+    let mut path = Vec::with_capacity(ids.len());
+    for id in ids {
+        let title = live_note_title(conn, &id)?.unwrap_or_default();
+        path.push(note_node(id, title));
+    }
+    Ok(Some(path))
+}
+
+/// Notes in a space that neither link to nor are linked from another live note
+pub fn get_orphan_notes(conn: &Connection, space_id: Ulid) -> Result<Vec<GraphNode>, DbError> {
+    let mut stmt = conn.prepare(
+        "SELECT n.id, n.title FROM note n
+         WHERE n.space_id = ?1 AND n.is_trashed = 0
+           AND NOT EXISTS (
+               SELECT 1 FROM link l JOIN note t ON t.id = l.target_note_id
+               WHERE l.source_note_id = n.id AND t.is_trashed = 0)
+           AND NOT EXISTS (
+               SELECT 1 FROM link l JOIN note s ON s.id = l.source_note_id
+               WHERE l.target_note_id = n.id AND s.is_trashed = 0)
+         ORDER BY n.title",
+    )?;
+    let rows = stmt.query_map([space_id.to_string()], |row| {
+        Ok(note_node(row.get(0)?, row.get(1)?))
+    })?;
+    Ok(rows.collect::<Result<_, _>>()?)
+}
@@ -1,5 +1,9 @@
+pub mod goals;
+pub mod import;
+
+use crate::calendar::TimeRange;
 use crate::db::DbError;
-use rusqlite::{Connection, Result};
+use rusqlite::{Connection, OptionalExtension, Result};
 use serde::{Deserialize, Serialize};
 use ulid::Ulid;
 
@@ -100,3 +104,305 @@ pub fn get_health_metrics(
 
     Ok(metrics)
 }
+
+/// Days of readings averaged into each point of a trend
+const MOVING_AVERAGE_DAYS: i64 = 7;
+
+/// A change over the whole range smaller than this share of the mean is stable
+const STABLE_CHANGE_RATIO: f64 = 0.02;
+
+/// How far back `check_threshold_breaches` looks
+const BREACH_LOOKBACK_DAYS: i64 = 7;
+
+const DAY_SECS: i64 = 24 * 60 * 60;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TrendDirection {
+    Improving,
+    Stable,
+    Declining,
+}
+
+/// One day of readings
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TrendPoint {
+    /// Start of the UTC day
+    pub day: i64,
+    /// Mean of the day's readings
+    pub value: f64,
+    pub samples: usize,
+    /// Mean of the daily values in the trailing window of days; days without
+    /// readings are left out rather than counted as zero
+    pub moving_average: f64,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MetricTrend {
+    pub metric_type: String,
+    pub points: Vec<TrendPoint>,
+    pub min: Option<f64>,
+    pub max: Option<f64>,
+    /// Least-squares slope of the daily values, in units per day
+    pub slope_per_day: f64,
+    pub direction: TrendDirection,
+    pub higher_is_better: bool,
+}
+
+/// Bounds and direction configured for a metric type
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MetricSettings {
+    pub metric_type: String,
+    pub min_value: Option<f64>,
+    pub max_value: Option<f64>,
+    pub higher_is_better: bool,
+}
+
+/// A recent reading outside its metric's bounds
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ThresholdBreach {
+    pub metric_id: Ulid,
+    pub metric_type: String,
+    pub value: f64,
+    pub recorded_at: i64,
+    pub min_value: Option<f64>,
+    pub max_value: Option<f64>,
+}
+
+/// Whether a rising value is good news for metric types nobody configured
+fn default_higher_is_better(metric_type: &str) -> bool {
+    !matches!(
+        metric_type.to_lowercase().as_str(),
+        "weight" | "body_fat" | "heart_rate" | "resting_heart_rate" | "blood_pressure" | "stress"
+    )
+}
+
+/// Bounds and direction of a metric type, with defaults for unset values
+pub fn get_metric_settings(
+    conn: &Connection,
+    space_id: Ulid,
+    metric_type: &str,
+) -> Result<MetricSettings, DbError> {
+    let stored: Option<(Option<f64>, Option<f64>, Option<bool>)> = conn
+        .query_row(
+            "SELECT min_value, max_value, higher_is_better FROM health_metric_setting
+             WHERE space_id = ?1 AND metric_type = ?2",
+            rusqlite::params![space_id.to_string(), metric_type],
+            |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+        )
+        .optional()?;
+    let (min_value, max_value, higher_is_better) = stored.unwrap_or_default();
+    Ok(MetricSettings {
+        metric_type: metric_type.to_string(),
+        min_value,
+        max_value,
+        higher_is_better: higher_is_better.unwrap_or_else(|| default_higher_is_better(metric_type)),
+    })
+}
+
+/// Set the healthy range of a metric type; `None` leaves that side open
+pub fn set_metric_threshold(
+    conn: &Connection,
+    space_id: Ulid,
+    metric_type: &str,
+    min: Option<f64>,
+    max: Option<f64>,
+) -> Result<(), DbError> {
+    if let (Some(min), Some(max)) = (min, max) {
+        if min > max {
+            return Err(DbError::Message(format!(
+                "Minimum {} is above maximum {}",
+                min, max
+            )));
+        }
+    }
+    conn.execute(
+        "INSERT INTO health_metric_setting (space_id, metric_type, min_value, max_value, updated_at)
+         VALUES (?1, ?2, ?3, ?4, ?5)
+         ON CONFLICT(space_id, metric_type) DO UPDATE SET
+             min_value = excluded.min_value,
+             max_value = excluded.max_value,
+             updated_at = excluded.updated_at",
+        rusqlite::params![
+            space_id.to_string(),
+            metric_type,
+            min,
+            max,
+            chrono::Utc::now().timestamp()
+        ],
+    )?;
+    Ok(())
+}
+
+/// Set whether a rising value counts as improving for a metric type
+pub fn set_metric_direction(
+    conn: &Connection,
+    space_id: Ulid,
+    metric_type: &str,
+    higher_is_better: bool,
+) -> Result<(), DbError> {
+    conn.execute(
+        "INSERT INTO health_metric_setting (space_id, metric_type, higher_is_better, updated_at)
+         VALUES (?1, ?2, ?3, ?4)
+         ON CONFLICT(space_id, metric_type) DO UPDATE SET
+             higher_is_better = excluded.higher_is_better,
+             updated_at = excluded.updated_at",
+        rusqlite::params![
+            space_id.to_string(),
+            metric_type,
+            higher_is_better,
+            chrono::Utc::now().timestamp()
+        ],
+    )?;
+    Ok(())
+}
+
+/// Trend of one metric type over `range`. Readings are averaged per day first,
+/// so days with many readings don't outweigh sparse ones, and both the moving
+/// average and the regression use real dates, so gaps don't skew them.
+pub fn get_metric_trend(
+    conn: &Connection,
+    space_id: Ulid,
+    metric_type: &str,
+    range: TimeRange,
+) -> Result<MetricTrend, DbError> {
+    let settings = get_metric_settings(conn, space_id, metric_type)?;
+
+    let mut stmt = conn.prepare(
+        "SELECT recorded_at, value FROM health_metric
+         WHERE space_id = ?1 AND metric_type = ?2 AND recorded_at >= ?3 AND recorded_at < ?4
+         ORDER BY recorded_at",
+    )?;
+    let readings = stmt
+        .query_map(
+            rusqlite::params![space_id.to_string(), metric_type, range.start, range.end],
+            |row| Ok((row.get::<_, i64>(0)?, row.get::<_, f64>(1)?)),
+        )?
+        .collect::<Result<Vec<_>, _>>()?;
+
+    // Daily means; readings are sorted, so days come out in order
+    let mut days: Vec<(i64, f64, usize)> = Vec::new();
+    for (recorded_at, value) in &readings {
+        let day = recorded_at.div_euclid(DAY_SECS) * DAY_SECS;
+        match days.last_mut() {
+            Some((last, sum, count)) if *last == day => {
+                *sum += value;
+                *count += 1;
+            }
+            _ => days.push((day, *value, 1)),
+        }
+    }
+    let daily: Vec<(i64, f64, usize)> = days
+        .into_iter()
+        .map(|(day, sum, count)| (day, sum / count as f64, count))
+        .collect();
+
+    let points: Vec<TrendPoint> = daily
+        .iter()
+        .enumerate()
+        .map(|(i, &(day, value, samples))| {
+            let window_start = day - (MOVING_AVERAGE_DAYS - 1) * DAY_SECS;
+            let window: Vec<f64> = daily[..=i]
+                .iter()
+                .filter(|(d, _, _)| *d >= window_start)
+                .map(|(_, v, _)| *v)
+                .collect();
+            TrendPoint {
+                day,
+                value,
+                samples,
+                moving_average: window.iter().sum::<f64>() / window.len() as f64,
+            }
+        })
+        .collect();
+
+    let slope_per_day = regression_slope(&points);
+    let direction = classify_trend(&points, slope_per_day, settings.higher_is_better);
+    Ok(MetricTrend {
+        metric_type: metric_type.to_string(),
+        min: readings.iter().map(|(_, v)| *v).reduce(f64::min),
+        max: readings.iter().map(|(_, v)| *v).reduce(f64::max),
+        points,
+        slope_per_day,
+        direction,
+        higher_is_better: settings.higher_is_better,
+    })
+}
+
+/// Least-squares slope of the daily values against their day, per day
+fn regression_slope(points: &[TrendPoint]) -> f64 {
+    if points.len() < 2 {
+        return 0.0;
+    }
+    let n = points.len() as f64;
+    let xs: Vec<f64> = points
+        .iter()
+        .map(|p| (p.day - points[0].day) as f64 / DAY_SECS as f64)
+        .collect();
+    let mean_x = xs.iter().sum::<f64>() / n;
+    let mean_y = points.iter().map(|p| p.value).sum::<f64>() / n;
+    let (covariance, variance) = xs.iter().zip(points).fold((0.0, 0.0), |(c, v), (x, p)| {
+        (
+            c + (x - mean_x) * (p.value - mean_y),
+            v + (x - mean_x).powi(2),
+        )
+    });
+    if variance == 0.0 {
+        0.0
+    } else {
+        covariance / variance
+    }
+}
+
+fn classify_trend(
+    points: &[TrendPoint],
+    slope_per_day: f64,
+    higher_is_better: bool,
+) -> TrendDirection {
+    let (Some(first), Some(last)) = (points.first(), points.last()) else {
+        return TrendDirection::Stable;
+    };
+    let span_days = (last.day - first.day) as f64 / DAY_SECS as f64;
+    let mean = points.iter().map(|p| p.value).sum::<f64>() / points.len() as f64;
+    let change = slope_per_day * span_days;
+    if change.abs() <= STABLE_CHANGE_RATIO * mean.abs().max(f64::EPSILON) {
+        TrendDirection::Stable
+    } else if (change > 0.0) == higher_is_better {
+        TrendDirection::Improving
+    } else {
+        TrendDirection::Declining
+    }
+}
+
+/// Readings from the last week that fall outside their metric's bounds,
+/// newest first
+pub fn check_threshold_breaches(
+    conn: &Connection,
+    space_id: Ulid,
+) -> Result<Vec<ThresholdBreach>, DbError> {
+    let since = chrono::Utc::now().timestamp() - BREACH_LOOKBACK_DAYS * DAY_SECS;
+    let mut stmt = conn.prepare(
+        "SELECT m.id, m.metric_type, m.value, m.recorded_at, s.min_value, s.max_value
+         FROM health_metric m
+         JOIN health_metric_setting s
+           ON s.space_id = m.space_id AND s.metric_type = m.metric_type
+         WHERE m.space_id = ?1 AND m.recorded_at >= ?2
+           AND ((s.min_value IS NOT NULL AND m.value < s.min_value)
+             OR (s.max_value IS NOT NULL AND m.value > s.max_value))
+         ORDER BY m.recorded_at DESC",
+    )?;
+    let breaches = stmt
+        .query_map(rusqlite::params![space_id.to_string(), since], |row| {
+            Ok(ThresholdBreach {
+                metric_id: Ulid::from_string(&row.get::<_, String>(0)?)
+                    .map_err(|e| rusqlite::Error::ToSqlConversionFailure(Box::new(e)))?,
+                metric_type: row.get(1)?,
+                value: row.get(2)?,
+                recorded_at: row.get(3)?,
+                min_value: row.get(4)?,
+                max_value: row.get(5)?,
+            })
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(breaches)
+}
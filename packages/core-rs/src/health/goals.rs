@@ -0,0 +1,618 @@
+//! Health Goals
+//!
+//! A goal targets one metric type: a value its readings should reach or stay
+//! under, measured on the latest reading or on a 7- or 30-day average of
+//! daily means, optionally by a deadline. [`evaluate_health_goals`] checks the
+//! goals of a space against the recorded metrics and stores the outcome on
+//! each goal, so the dashboard and weekly review show it without recomputing.
+//! A deadline goal that isn't met yet is projected along the trend of the
+//! last [`PROJECTION_WINDOW_DAYS`] days to tell on-track from behind.
+//!
+//! Reaching a goal emits [`CoreEvent::HealthGoalAchieved`]; a goal whose
+//! status got worse since its last evaluation emits
+//! [`CoreEvent::HealthGoalRegressed`].
+
+use super::import::{
+    METRIC_EXERCISE, METRIC_HEART_RATE, METRIC_SLEEP, METRIC_STEPS, METRIC_WEIGHT,
+};
+use super::{default_higher_is_better, get_metric_trend, DAY_SECS};
+use crate::calendar::TimeRange;
+use crate::db::DbError;
+use crate::events::{emit, CoreEvent};
+use chrono::Utc;
+use regex::Regex;
+use rusqlite::{params, Connection, OptionalExtension};
+use serde::{Deserialize, Serialize};
+use ulid::Ulid;
+
+/// Days of readings the trend of a deadline goal is fitted to
+pub const PROJECTION_WINDOW_DAYS: i64 = 30;
+
+const POUNDS_TO_KG: f64 = 0.453_592_37;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum GoalComparison {
+    AtLeast,
+    AtMost,
+}
+
+impl GoalComparison {
+    fn as_str(self) -> &'static str {
+        match self {
+            GoalComparison::AtLeast => "at_least",
+            GoalComparison::AtMost => "at_most",
+        }
+    }
+
+    fn parse(s: &str) -> Option<Self> {
+        match s {
+            "at_least" => Some(GoalComparison::AtLeast),
+            "at_most" => Some(GoalComparison::AtMost),
+            _ => None,
+        }
+    }
+
+    fn is_met(self, value: f64, target: f64) -> bool {
+        match self {
+            GoalComparison::AtLeast => value >= target,
+            GoalComparison::AtMost => value <= target,
+        }
+    }
+}
+
+/// What a goal's readings are measured on
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum GoalAggregation {
+    Latest,
+    SevenDayAverage,
+    ThirtyDayAverage,
+}
+
+impl GoalAggregation {
+    fn as_str(self) -> &'static str {
+        match self {
+            GoalAggregation::Latest => "latest",
+            GoalAggregation::SevenDayAverage => "seven_day_average",
+            GoalAggregation::ThirtyDayAverage => "thirty_day_average",
+        }
+    }
+
+    fn parse(s: &str) -> Option<Self> {
+        match s {
+            "latest" => Some(GoalAggregation::Latest),
+            "seven_day_average" => Some(GoalAggregation::SevenDayAverage),
+            "thirty_day_average" => Some(GoalAggregation::ThirtyDayAverage),
+            _ => None,
+        }
+    }
+
+    /// Days averaged over, `None` for the latest reading
+    fn window_days(self) -> Option<i64> {
+        match self {
+            GoalAggregation::Latest => None,
+            GoalAggregation::SevenDayAverage => Some(7),
+            GoalAggregation::ThirtyDayAverage => Some(30),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum HealthGoalStatus {
+    Achieved,
+    /// Not met yet, but the trend meets it by the deadline
+    OnTrack,
+    Behind,
+    /// The deadline passed without the goal being met
+    Expired,
+}
+
+impl HealthGoalStatus {
+    fn as_str(self) -> &'static str {
+        match self {
+            HealthGoalStatus::Achieved => "achieved",
+            HealthGoalStatus::OnTrack => "on_track",
+            HealthGoalStatus::Behind => "behind",
+            HealthGoalStatus::Expired => "expired",
+        }
+    }
+
+    fn parse(s: &str) -> Option<Self> {
+        match s {
+            "achieved" => Some(HealthGoalStatus::Achieved),
+            "on_track" => Some(HealthGoalStatus::OnTrack),
+            "behind" => Some(HealthGoalStatus::Behind),
+            "expired" => Some(HealthGoalStatus::Expired),
+            _ => None,
+        }
+    }
+
+    /// Higher is better; a drop between evaluations is a regression
+    fn rank(self) -> u8 {
+        match self {
+            HealthGoalStatus::Achieved => 3,
+            HealthGoalStatus::OnTrack => 2,
+            HealthGoalStatus::Behind => 1,
+            HealthGoalStatus::Expired => 0,
+        }
+    }
+}
+
+/// What a goal asks of a metric
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct HealthGoalTarget {
+    pub metric_type: String,
+    pub comparison: GoalComparison,
+    pub target_value: f64,
+    pub unit: Option<String>,
+    pub aggregation: GoalAggregation,
+    /// The target is to be met by then; `None` for an ongoing goal
+    pub deadline: Option<i64>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct HealthGoal {
+    pub id: Ulid,
+    pub space_id: Ulid,
+    pub title: String,
+    pub target: HealthGoalTarget,
+    /// The free-form goal the target was parsed from
+    pub goal_id: Option<String>,
+    pub created_at: i64,
+    /// Outcome of the last evaluation; `None` until the goal is evaluated
+    pub status: Option<HealthGoalStatus>,
+    pub current_value: Option<f64>,
+    pub projected_value: Option<f64>,
+    pub evaluated_at: Option<i64>,
+}
+
+/// The outcome of evaluating one goal
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct HealthGoalEvaluation {
+    pub goal_id: Ulid,
+    pub status: HealthGoalStatus,
+    pub previous_status: Option<HealthGoalStatus>,
+    /// The aggregated readings; `None` when there are none in the window
+    pub current_value: Option<f64>,
+    /// Where the trend puts the value at the deadline, for deadline goals
+    /// that aren't met yet
+    pub projected_value: Option<f64>,
+    pub evaluated_at: i64,
+}
+
+pub fn create_health_goal(
+    conn: &Connection,
+    space_id: Ulid,
+    title: &str,
+    target: &HealthGoalTarget,
+) -> Result<HealthGoal, DbError> {
+    insert_health_goal(conn, space_id, title, target, None, Utc::now().timestamp())
+}
+
+fn insert_health_goal(
+    conn: &Connection,
+    space_id: Ulid,
+    title: &str,
+    target: &HealthGoalTarget,
+    goal_id: Option<&str>,
+    created_at: i64,
+) -> Result<HealthGoal, DbError> {
+    if target.metric_type.trim().is_empty() {
+        return Err(DbError::Message("A goal needs a metric type".to_string()));
+    }
+    if !target.target_value.is_finite() {
+        return Err(DbError::Message(format!(
+            "Invalid target value {}",
+            target.target_value
+        )));
+    }
+    let goal = HealthGoal {
+        id: Ulid::new(),
+        space_id,
+        title: title.to_string(),
+        target: target.clone(),
+        goal_id: goal_id.map(String::from),
+        created_at,
+        status: None,
+        current_value: None,
+        projected_value: None,
+        evaluated_at: None,
+    };
+    conn.execute(
+        "INSERT INTO health_goal (id, space_id, title, metric_type, comparison, target_value,
+             unit, aggregation, deadline, goal_id, created_at)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11)",
+        params![
+            goal.id.to_string(),
+            space_id.to_string(),
+            title,
+            target.metric_type,
+            target.comparison.as_str(),
+            target.target_value,
+            target.unit,
+            target.aggregation.as_str(),
+            target.deadline,
+            goal.goal_id,
+            created_at
+        ],
+    )?;
+    Ok(goal)
+}
+
+/// Goals of a space with their last evaluation, oldest first
+pub fn get_health_goals(conn: &Connection, space_id: Ulid) -> Result<Vec<HealthGoal>, DbError> {
+    let mut stmt = conn.prepare(
+        "SELECT id, space_id, title, metric_type, comparison, target_value, unit, aggregation,
+                deadline, goal_id, created_at, status, current_value, projected_value, evaluated_at
+         FROM health_goal WHERE space_id = ?1 ORDER BY created_at, id",
+    )?;
+    let goals = stmt
+        .query_map([space_id.to_string()], goal_from_row)?
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(goals)
+}
+
+pub fn delete_health_goal(conn: &Connection, id: Ulid) -> Result<(), DbError> {
+    let deleted = conn.execute("DELETE FROM health_goal WHERE id = ?1", [id.to_string()])?;
+    if deleted == 0 {
+        return Err(DbError::NotFound {
+            entity: "health_goal",
+            id: id.to_string(),
+        });
+    }
+    Ok(())
+}
+
+fn goal_from_row(row: &rusqlite::Row) -> rusqlite::Result<HealthGoal> {
+    fn conversion(column: usize, e: Box<dyn std::error::Error + Send + Sync>) -> rusqlite::Error {
+        rusqlite::Error::FromSqlConversionFailure(column, rusqlite::types::Type::Text, e)
+    }
+    fn known<T>(column: usize, value: &str, parsed: Option<T>) -> rusqlite::Result<T> {
+        parsed.ok_or_else(|| conversion(column, format!("Unknown value {value}").into()))
+    }
+
+    let id: String = row.get(0)?;
+    let space_id: String = row.get(1)?;
+    let comparison: String = row.get(4)?;
+    let aggregation: String = row.get(7)?;
+    let status: Option<String> = row.get(11)?;
+    Ok(HealthGoal {
+        id: Ulid::from_string(&id).map_err(|e| conversion(0, e.into()))?,
+        space_id: Ulid::from_string(&space_id).map_err(|e| conversion(1, e.into()))?,
+        title: row.get(2)?,
+        target: HealthGoalTarget {
+            metric_type: row.get(3)?,
+            comparison: known(4, &comparison, GoalComparison::parse(&comparison))?,
+            target_value: row.get(5)?,
+            unit: row.get(6)?,
+            aggregation: known(7, &aggregation, GoalAggregation::parse(&aggregation))?,
+            deadline: row.get(8)?,
+        },
+        goal_id: row.get(9)?,
+        created_at: row.get(10)?,
+        status: status
+            .map(|s| known(11, &s, HealthGoalStatus::parse(&s)))
+            .transpose()?,
+        current_value: row.get(12)?,
+        projected_value: row.get(13)?,
+        evaluated_at: row.get(14)?,
+    })
+}
+
+/// Check every goal of the space against its metric and store the outcome
+pub fn evaluate_health_goals(
+    conn: &Connection,
+    space_id: Ulid,
+) -> Result<Vec<HealthGoalEvaluation>, DbError> {
+    evaluate_health_goals_at(conn, space_id, Utc::now().timestamp())
+}
+
+pub fn evaluate_health_goals_at(
+    conn: &Connection,
+    space_id: Ulid,
+    now: i64,
+) -> Result<Vec<HealthGoalEvaluation>, DbError> {
+    let goals = get_health_goals(conn, space_id)?;
+    let mut evaluations = Vec::with_capacity(goals.len());
+    let tx = conn.unchecked_transaction()?;
+    for goal in &goals {
+        let evaluation = evaluate_goal(&tx, goal, now)?;
+        tx.execute(
+            "UPDATE health_goal
+             SET status = ?1, current_value = ?2, projected_value = ?3, evaluated_at = ?4
+             WHERE id = ?5",
+            params![
+                evaluation.status.as_str(),
+                evaluation.current_value,
+                evaluation.projected_value,
+                now,
+                goal.id.to_string()
+            ],
+        )?;
+        evaluations.push(evaluation);
+    }
+    tx.commit()?;
+
+    for (goal, evaluation) in goals.iter().zip(&evaluations) {
+        if let Some(event) = transition_event(goal, evaluation) {
+            emit(event);
+        }
+    }
+    log::info!(
+        "[health] Evaluated {} goal(s) in space {}",
+        evaluations.len(),
+        space_id
+    );
+    Ok(evaluations)
+}
+
+fn evaluate_goal(
+    conn: &Connection,
+    goal: &HealthGoal,
+    now: i64,
+) -> Result<HealthGoalEvaluation, DbError> {
+    let target = &goal.target;
+    let current_value = aggregate(conn, goal.space_id, target, now)?;
+    let met = current_value.is_some_and(|v| target.comparison.is_met(v, target.target_value));
+
+    let mut projected_value = None;
+    let status = match target.deadline {
+        _ if met => HealthGoalStatus::Achieved,
+        Some(deadline) if deadline < now => HealthGoalStatus::Expired,
+        Some(deadline) => {
+            projected_value = match current_value {
+                Some(current) => Some(project(
+                    conn,
+                    goal.space_id,
+                    target,
+                    current,
+                    deadline,
+                    now,
+                )?),
+                None => None,
+            };
+            match projected_value {
+                Some(projected) if target.comparison.is_met(projected, target.target_value) => {
+                    HealthGoalStatus::OnTrack
+                }
+                _ => HealthGoalStatus::Behind,
+            }
+        }
+        None => HealthGoalStatus::Behind,
+    };
+    Ok(HealthGoalEvaluation {
+        goal_id: goal.id,
+        status,
+        previous_status: goal.status,
+        current_value,
+        projected_value,
+        evaluated_at: now,
+    })
+}
+
+/// The goal's metric as of `now`: its latest reading, or the mean of the
+/// daily means within the window
+fn aggregate(
+    conn: &Connection,
+    space_id: Ulid,
+    target: &HealthGoalTarget,
+    now: i64,
+) -> Result<Option<f64>, DbError> {
+    let Some(days) = target.aggregation.window_days() else {
+        let latest = conn
+            .query_row(
+                "SELECT value FROM health_metric
+                 WHERE space_id = ?1 AND metric_type = ?2 AND recorded_at <= ?3
+                 ORDER BY recorded_at DESC LIMIT 1",
+                params![space_id.to_string(), target.metric_type, now],
+                |row| row.get(0),
+            )
+            .optional()?;
+        return Ok(latest);
+    };
+    let trend = get_metric_trend(
+        conn,
+        space_id,
+        &target.metric_type,
+        TimeRange::new(now - days * DAY_SECS, now + 1),
+    )?;
+    if trend.points.is_empty() {
+        return Ok(None);
+    }
+    let sum: f64 = trend.points.iter().map(|p| p.value).sum();
+    Ok(Some(sum / trend.points.len() as f64))
+}
+
+/// `current` carried to the deadline along the slope of recent daily values.
+/// With fewer than two days of readings there is no slope to follow.
+fn project(
+    conn: &Connection,
+    space_id: Ulid,
+    target: &HealthGoalTarget,
+    current: f64,
+    deadline: i64,
+    now: i64,
+) -> Result<f64, DbError> {
+    let trend = get_metric_trend(
+        conn,
+        space_id,
+        &target.metric_type,
+        TimeRange::new(now - PROJECTION_WINDOW_DAYS * DAY_SECS, now + 1),
+    )?;
+    let days_left = (deadline - now) as f64 / DAY_SECS as f64;
+    Ok(current + trend.slope_per_day * days_left)
+}
+
+fn transition_event(goal: &HealthGoal, evaluation: &HealthGoalEvaluation) -> Option<CoreEvent> {
+    let (status, previous) = (evaluation.status, evaluation.previous_status);
+    if status == HealthGoalStatus::Achieved && previous != Some(HealthGoalStatus::Achieved) {
+        return Some(CoreEvent::HealthGoalAchieved {
+            space_id: goal.space_id.to_string(),
+            goal_id: goal.id.to_string(),
+            title: goal.title.clone(),
+            metric_type: goal.target.metric_type.clone(),
+            value: evaluation.current_value?,
+        });
+    }
+    let previous = previous?;
+    (status.rank() < previous.rank()).then(|| CoreEvent::HealthGoalRegressed {
+        space_id: goal.space_id.to_string(),
+        goal_id: goal.id.to_string(),
+        title: goal.title.clone(),
+        metric_type: goal.target.metric_type.clone(),
+        from: previous,
+        to: status,
+    })
+}
+
+/// Words naming a metric type, most specific first
+const METRIC_KEYWORDS: &[(&str, &str)] = &[
+    ("resting heart", METRIC_HEART_RATE),
+    ("heart rate", METRIC_HEART_RATE),
+    ("pulse", METRIC_HEART_RATE),
+    ("body fat", "body_fat"),
+    ("sleep", METRIC_SLEEP),
+    ("weigh", METRIC_WEIGHT),
+    ("step", METRIC_STEPS),
+    ("water", "water_intake"),
+    ("exercise", METRIC_EXERCISE),
+    ("workout", METRIC_EXERCISE),
+];
+
+/// Best-effort reading of a goal written as text, like "Sleep 7h on
+/// average" or "Weight under 180 lb". `None` when no metric or number is
+/// recognised. Deadlines aren't read from the text.
+pub fn parse_health_goal(text: &str) -> Option<HealthGoalTarget> {
+    let digit_groups = Regex::new(r"(\d),(\d{3})").expect("valid regex");
+    let number = Regex::new(
+        r"(\d+(?:\.\d+)?)\s*(kgs?|kilos?|lbs?|pounds?|hours?|hrs?|h|minutes?|mins?|k|bpm|steps?|%)?\b",
+    )
+    .expect("valid regex");
+    let at_most = Regex::new(
+        r"\b(under|below|less than|at most|max|maximum|fewer|lose|lower|drop|reduce)\b|<",
+    )
+    .expect("valid regex");
+    let at_least = Regex::new(r"\b(over|above|more than|at least|minimum|gain|reach|increase)\b|>")
+        .expect("valid regex");
+    let monthly = Regex::new(r"\b(30|thirty)[- ]?days?\b|\bmonth(ly)?\b").expect("valid regex");
+    let weekly = Regex::new(
+        r"\b(avg|average|mean|daily|nightly|weekly|week|(7|seven)[- ]?days?)\b|\b(per|a|each|every) (day|night)\b",
+    )
+    .expect("valid regex");
+
+    let text = digit_groups
+        .replace_all(&text.to_lowercase(), "$1$2")
+        .into_owned();
+    // The first number that isn't a window length like "30-day"
+    let (value, unit) = number.captures_iter(&text).find_map(|caps| {
+        let whole = caps.get(0)?;
+        let rest = text[whole.end()..].trim_start_matches([' ', '-']);
+        if caps.get(2).is_none() && rest.starts_with("day") {
+            return None;
+        }
+        let value: f64 = caps[1].parse().ok()?;
+        Some((value, caps.get(2).map(|u| u.as_str().to_string())))
+    })?;
+
+    let metric_type = METRIC_KEYWORDS
+        .iter()
+        .find(|(keyword, _)| text.contains(keyword))
+        .map(|(_, metric)| *metric)
+        .or_else(|| match unit.as_deref()? {
+            "kg" | "kgs" | "kilo" | "kilos" | "lb" | "lbs" | "pound" | "pounds" => {
+                Some(METRIC_WEIGHT)
+            }
+            "bpm" => Some(METRIC_HEART_RATE),
+            "step" | "steps" => Some(METRIC_STEPS),
+            _ => None,
+        })?;
+
+    let (target_value, unit) = match (metric_type, unit.as_deref()) {
+        (_, Some("k")) => (value * 1000.0, None),
+        (METRIC_WEIGHT, Some("lb" | "lbs" | "pound" | "pounds")) => {
+            (value * POUNDS_TO_KG, Some("kg"))
+        }
+        (METRIC_WEIGHT, _) => (value, Some("kg")),
+        (METRIC_SLEEP, Some("minute" | "minutes" | "min" | "mins")) => {
+            (value / 60.0, Some("hours"))
+        }
+        (METRIC_SLEEP, _) => (value, Some("hours")),
+        (METRIC_HEART_RATE, _) => (value, Some("bpm")),
+        (METRIC_STEPS, _) => (value, Some("steps")),
+        (METRIC_EXERCISE, _) => (value, Some("min")),
+        (_, unit) => (value, unit),
+    };
+    let unit = unit.map(String::from).or_else(|| match metric_type {
+        METRIC_STEPS => Some("steps".to_string()),
+        _ => None,
+    });
+
+    let comparison = if at_most.is_match(&text) {
+        GoalComparison::AtMost
+    } else if at_least.is_match(&text) || default_higher_is_better(metric_type) {
+        GoalComparison::AtLeast
+    } else {
+        GoalComparison::AtMost
+    };
+    let aggregation = if monthly.is_match(&text) {
+        GoalAggregation::ThirtyDayAverage
+    } else if weekly.is_match(&text) {
+        GoalAggregation::SevenDayAverage
+    } else {
+        GoalAggregation::Latest
+    };
+
+    Some(HealthGoalTarget {
+        metric_type: metric_type.to_string(),
+        comparison,
+        target_value,
+        unit,
+        aggregation,
+        deadline: None,
+    })
+}
+
+/// Give the open free-form goals that read as health goals a structured
+/// target, keeping their title and target date
+pub(crate) fn import_free_form_goals(conn: &Connection) -> Result<(), DbError> {
+    let goals: Vec<(String, String, String, Option<String>, Option<i64>, i64)> = {
+        let mut stmt = conn.prepare(
+            "SELECT id, space_id, title, description, target_date, created_at FROM goal
+             WHERE is_completed = 0
+               AND id NOT IN (SELECT goal_id FROM health_goal WHERE goal_id IS NOT NULL)",
+        )?;
+        let rows = stmt.query_map([], |row| {
+            Ok((
+                row.get(0)?,
+                row.get(1)?,
+                row.get(2)?,
+                row.get(3)?,
+                row.get(4)?,
+                row.get(5)?,
+            ))
+        })?;
+        rows.collect::<Result<_, _>>()?
+    };
+
+    let mut imported = 0;
+    for (id, space_id, title, description, target_date, created_at) in goals {
+        let Ok(space_id) = Ulid::from_string(&space_id) else {
+            continue;
+        };
+        // The title is the goal; the description only fills in when it isn't
+        let Some(mut target) = parse_health_goal(&title).or_else(|| {
+            description
+                .as_deref()
+                .and_then(|d| parse_health_goal(&format!("{title} {d}")))
+        }) else {
+            continue;
+        };
+        target.deadline = target_date;
+        insert_health_goal(conn, space_id, &title, &target, Some(&id), created_at)?;
+        imported += 1;
+    }
+    log::info!("[health] Imported {} free-form health goal(s)", imported);
+    Ok(())
+}
@@ -0,0 +1,646 @@
+//! Health Export Import
+//!
+//! Reads the archives Apple Health and Google Fit export and turns steps,
+//! weight, sleep, heart rate and workouts into `health_metric` rows.
+//!
+//! Apple Health ships `export.xml`, on its own or inside `export.zip`; it
+//! often runs to hundreds of megabytes, so it is parsed as a stream and only
+//! daily totals are kept in memory. Google Fit's takeout has one JSON file per
+//! data source under `Fit/All Data`; either one file or the directory can be
+//! imported.
+//!
+//! Every reading remembers the source record it came from, so importing the
+//! same export again, or a later one that overlaps it, adds nothing twice.
+//! Steps and sleep are stored as one total per source and day (the day a
+//! night's sleep ends), and a total that grew since the last import is
+//! updated rather than duplicated.
+
+use crate::db::DbError;
+use chrono::{DateTime, FixedOffset, NaiveDate, Timelike};
+use quick_xml::events::{BytesStart, Event};
+use quick_xml::Reader;
+use rusqlite::{Connection, OptionalExtension};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::fs::File;
+use std::io::{BufReader, Read};
+use std::path::Path;
+use thiserror::Error;
+use ulid::Ulid;
+use zip::ZipArchive;
+
+pub const METRIC_STEPS: &str = "steps";
+pub const METRIC_WEIGHT: &str = "weight";
+pub const METRIC_SLEEP: &str = "sleep";
+pub const METRIC_HEART_RATE: &str = "heart_rate";
+pub const METRIC_EXERCISE: &str = "exercise_minutes";
+
+const APPLE_EXPORT_ENTRY: &str = "export.xml";
+const APPLE_WORKOUT_PREFIX: &str = "HKWorkoutActivityType";
+const APPLE_ASLEEP_PREFIX: &str = "HKCategoryValueSleepAnalysisAsleep";
+
+/// Google Fit sleep stages that count as asleep: sleeping, light, deep, REM
+const FIT_ASLEEP_STAGES: &[i64] = &[2, 4, 5, 6];
+
+/// Google Fit activity types that aren't workouts: in vehicle, still,
+/// unknown, tilting, walking and the sleep stages
+const FIT_NON_WORKOUTS: &[i64] = &[0, 3, 4, 5, 7, 72, 109, 110, 111, 112];
+
+#[derive(Error, Debug)]
+pub enum HealthImportError {
+    #[error("I/O error: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("XML error: {0}")]
+    Xml(String),
+    #[error("JSON error: {0}")]
+    Json(#[from] serde_json::Error),
+    #[error("Zip error: {0}")]
+    Zip(#[from] zip::result::ZipError),
+    #[error("Database error: {0}")]
+    Database(#[from] DbError),
+    #[error("Invalid export: {0}")]
+    InvalidExport(String),
+}
+
+impl From<rusqlite::Error> for HealthImportError {
+    fn from(e: rusqlite::Error) -> Self {
+        HealthImportError::Database(DbError::from(e))
+    }
+}
+
+impl From<quick_xml::Error> for HealthImportError {
+    fn from(e: quick_xml::Error) -> Self {
+        HealthImportError::Xml(e.to_string())
+    }
+}
+
+impl From<quick_xml::events::attributes::AttrError> for HealthImportError {
+    fn from(e: quick_xml::events::attributes::AttrError) -> Self {
+        HealthImportError::Xml(e.to_string())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum HealthExportSource {
+    AppleHealth,
+    GoogleFit,
+}
+
+impl HealthExportSource {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            HealthExportSource::AppleHealth => "apple_health",
+            HealthExportSource::GoogleFit => "google_fit",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MetricImportCounts {
+    pub imported: usize,
+    /// Daily totals that changed since they were last imported
+    pub updated: usize,
+    pub duplicates: usize,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HealthImportReport {
+    /// Counts per metric type
+    pub metrics: BTreeMap<String, MetricImportCounts>,
+    pub skipped_duplicates: usize,
+    /// Records of types or units that aren't imported, or with unreadable
+    /// values
+    pub unsupported_records: usize,
+}
+
+impl HealthImportReport {
+    pub fn imported(&self) -> usize {
+        self.metrics.values().map(|c| c.imported).sum()
+    }
+}
+
+/// A reading ready for `health_metric`
+struct Reading {
+    metric_type: &'static str,
+    value: f64,
+    unit: &'static str,
+    recorded_at: i64,
+    source_record_id: String,
+    notes: Option<String>,
+}
+
+/// What a source record maps to; records that aren't imported map to `None`
+enum Mapped {
+    Reading(Reading),
+    /// Added to the daily totals
+    Totalled,
+}
+
+/// Steps or sleep of one source summed per day
+#[derive(Default)]
+struct DailyTotals {
+    days: BTreeMap<(String, NaiveDate), (i64, f64)>,
+}
+
+impl DailyTotals {
+    /// Add `value` to the day `at` falls on, in `at`'s own offset
+    fn add(&mut self, source: &str, at: DateTime<FixedOffset>, value: f64) {
+        let day_start = at.timestamp() - at.num_seconds_from_midnight() as i64;
+        self.days
+            .entry((source.to_string(), at.date_naive()))
+            .or_insert((day_start, 0.0))
+            .1 += value;
+    }
+
+    fn into_readings(
+        self,
+        metric_type: &'static str,
+        unit: &'static str,
+    ) -> impl Iterator<Item = Reading> {
+        self.days
+            .into_iter()
+            .map(move |((source, day), (day_start, total))| Reading {
+                metric_type,
+                value: total,
+                unit,
+                recorded_at: day_start,
+                source_record_id: format!("{}:{}:{}", metric_type, source, day),
+                notes: Some(source),
+            })
+    }
+}
+
+/// Writes readings inside the import's transaction and keeps count
+struct ReadingWriter<'a> {
+    conn: &'a Connection,
+    space_id: String,
+    source: HealthExportSource,
+    now: i64,
+    report: HealthImportReport,
+}
+
+impl ReadingWriter<'_> {
+    fn write(&mut self, reading: Reading) -> Result<(), HealthImportError> {
+        let existing: Option<(String, f64)> = self
+            .conn
+            .prepare_cached(
+                "SELECT id, value FROM health_metric
+                 WHERE space_id = ?1 AND metric_type = ?2 AND recorded_at = ?3
+                   AND source_record_id = ?4",
+            )?
+            .query_row(
+                rusqlite::params![
+                    self.space_id,
+                    reading.metric_type,
+                    reading.recorded_at,
+                    reading.source_record_id
+                ],
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            )
+            .optional()?;
+        let counts = self
+            .report
+            .metrics
+            .entry(reading.metric_type.to_string())
+            .or_default();
+        match existing {
+            None => {
+                self.conn
+                    .prepare_cached(
+                        "INSERT INTO health_metric (id, space_id, metric_type, value, unit, notes,
+                                                    recorded_at, created_at, updated_at,
+                                                    source, source_record_id)
+                         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?8, ?9, ?10)",
+                    )?
+                    .execute(rusqlite::params![
+                        Ulid::new().to_string(),
+                        self.space_id,
+                        reading.metric_type,
+                        reading.value,
+                        reading.unit,
+                        reading.notes,
+                        reading.recorded_at,
+                        self.now,
+                        self.source.as_str(),
+                        reading.source_record_id
+                    ])?;
+                counts.imported += 1;
+            }
+            Some((_, value)) if (value - reading.value).abs() < 1e-9 => {
+                counts.duplicates += 1;
+                self.report.skipped_duplicates += 1;
+            }
+            Some((id, _)) => {
+                self.conn
+                    .prepare_cached(
+                        "UPDATE health_metric SET value = ?1, updated_at = ?2 WHERE id = ?3",
+                    )?
+                    .execute(rusqlite::params![reading.value, self.now, id])?;
+                counts.updated += 1;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Import the health export at `path` into `space_id`. Apple Health exports
+/// are `export.xml` or the `export.zip` holding it; Google Fit exports are a
+/// JSON file from the takeout's `All Data` directory, or that directory.
+pub fn import_health_export(
+    conn: &Connection,
+    space_id: Ulid,
+    path: &Path,
+    source: HealthExportSource,
+) -> Result<HealthImportReport, HealthImportError> {
+    log::info!(
+        "[health_import] Importing {} export {:?} into space {}",
+        source.as_str(),
+        path,
+        space_id
+    );
+    let tx = conn.unchecked_transaction()?;
+    let mut writer = ReadingWriter {
+        conn: &tx,
+        space_id: space_id.to_string(),
+        source,
+        now: chrono::Utc::now().timestamp(),
+        report: HealthImportReport::default(),
+    };
+    match source {
+        HealthExportSource::AppleHealth => import_apple_health(&mut writer, path)?,
+        HealthExportSource::GoogleFit => import_google_fit(&mut writer, path)?,
+    }
+    let report = writer.report;
+    tx.commit()?;
+
+    log::info!(
+        "[health_import] Imported {} readings, skipped {} duplicates and {} unsupported records",
+        report.imported(),
+        report.skipped_duplicates,
+        report.unsupported_records
+    );
+    Ok(report)
+}
+
+// --- Apple Health ---
+
+fn import_apple_health(writer: &mut ReadingWriter, path: &Path) -> Result<(), HealthImportError> {
+    let is_zip = path
+        .extension()
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("zip"));
+    if !is_zip {
+        return parse_apple_export(writer, File::open(path)?);
+    }
+
+    let mut archive = ZipArchive::new(File::open(path)?)?;
+    let name = archive
+        .file_names()
+        .find(|name| {
+            *name == APPLE_EXPORT_ENTRY || name.ends_with(&format!("/{}", APPLE_EXPORT_ENTRY))
+        })
+        .map(str::to_string)
+        .ok_or_else(|| {
+            HealthImportError::InvalidExport(format!("no {} in the archive", APPLE_EXPORT_ENTRY))
+        })?;
+    let entry = archive.by_name(&name)?;
+    parse_apple_export(writer, entry)
+}
+
+fn parse_apple_export(
+    writer: &mut ReadingWriter,
+    export: impl Read,
+) -> Result<(), HealthImportError> {
+    let mut reader = Reader::from_reader(BufReader::new(export));
+    let mut buf = Vec::new();
+    let mut steps = DailyTotals::default();
+    let mut sleep = DailyTotals::default();
+    let mut seen_root = false;
+
+    loop {
+        match reader.read_event_into(&mut buf)? {
+            Event::Start(e) | Event::Empty(e) => match e.name().as_ref() {
+                b"HealthData" => seen_root = true,
+                b"Record" => {
+                    let attrs = attributes(&e)?;
+                    match apple_record(&attrs, &mut steps, &mut sleep) {
+                        Some(Mapped::Reading(reading)) => writer.write(reading)?,
+                        Some(Mapped::Totalled) => {}
+                        None => writer.report.unsupported_records += 1,
+                    }
+                }
+                b"Workout" => match apple_workout(&attributes(&e)?) {
+                    Some(reading) => writer.write(reading)?,
+                    None => writer.report.unsupported_records += 1,
+                },
+                _ => {}
+            },
+            Event::Eof => break,
+            _ => {}
+        }
+        buf.clear();
+    }
+    if !seen_root {
+        return Err(HealthImportError::InvalidExport(
+            "not an Apple Health export".into(),
+        ));
+    }
+
+    for reading in steps
+        .into_readings(METRIC_STEPS, "steps")
+        .chain(sleep.into_readings(METRIC_SLEEP, "hours"))
+    {
+        writer.write(reading)?;
+    }
+    Ok(())
+}
+
+fn attributes(e: &BytesStart) -> Result<HashMap<String, String>, HealthImportError> {
+    let mut attrs = HashMap::new();
+    for attr in e.attributes() {
+        let attr = attr?;
+        attrs.insert(
+            String::from_utf8_lossy(attr.key.as_ref()).into_owned(),
+            attr.unescape_value()?.into_owned(),
+        );
+    }
+    Ok(attrs)
+}
+
+fn apple_date(value: Option<&String>) -> Option<DateTime<FixedOffset>> {
+    DateTime::parse_from_str(value?, "%Y-%m-%d %H:%M:%S %z").ok()
+}
+
+/// Map a `Record`; steps and sleep go into the daily totals
+fn apple_record(
+    attrs: &HashMap<String, String>,
+    steps: &mut DailyTotals,
+    sleep: &mut DailyTotals,
+) -> Option<Mapped> {
+    let record_type = attrs.get("type")?;
+    let source = attrs
+        .get("sourceName")
+        .map_or("Apple Health", |s| s.as_str());
+    let start = apple_date(attrs.get("startDate"))?;
+    let value = || attrs.get("value")?.parse::<f64>().ok();
+    let reading = |metric_type, value, unit| Reading {
+        metric_type,
+        value,
+        unit,
+        recorded_at: start.timestamp(),
+        source_record_id: format!(
+            "{}:{}:{}",
+            record_type,
+            source,
+            attrs.get("startDate").map_or("", |s| s.as_str())
+        ),
+        notes: Some(source.to_string()),
+    };
+
+    match record_type.as_str() {
+        "HKQuantityTypeIdentifierStepCount" => {
+            steps.add(source, start, value()?);
+            Some(Mapped::Totalled)
+        }
+        "HKQuantityTypeIdentifierBodyMass" => {
+            let kg = match attrs.get("unit")?.as_str() {
+                "kg" => value()?,
+                "g" => value()? / 1000.0,
+                "lb" => value()? * 0.453_592_37,
+                "st" => value()? * 6.350_293_18,
+                _ => return None,
+            };
+            Some(Mapped::Reading(reading(METRIC_WEIGHT, kg, "kg")))
+        }
+        "HKQuantityTypeIdentifierHeartRate" => {
+            if attrs.get("unit")? != "count/min" {
+                return None;
+            }
+            Some(Mapped::Reading(reading(METRIC_HEART_RATE, value()?, "bpm")))
+        }
+        "HKCategoryTypeIdentifierSleepAnalysis" => {
+            // In bed and awake segments overlap the asleep ones
+            if attrs.get("value")?.starts_with(APPLE_ASLEEP_PREFIX) {
+                let end = apple_date(attrs.get("endDate"))?;
+                let hours = (end - start).num_seconds() as f64 / 3600.0;
+                sleep.add(source, end, hours.max(0.0));
+            }
+            Some(Mapped::Totalled)
+        }
+        _ => None,
+    }
+}
+
+fn apple_workout(attrs: &HashMap<String, String>) -> Option<Reading> {
+    let activity = attrs.get("workoutActivityType")?;
+    let source = attrs
+        .get("sourceName")
+        .map_or("Apple Health", |s| s.as_str());
+    let start = apple_date(attrs.get("startDate"))?;
+    let minutes = match attrs.get("duration") {
+        Some(duration) => {
+            let duration = duration.parse::<f64>().ok()?;
+            match attrs.get("durationUnit").map_or("min", |u| u.as_str()) {
+                "min" => duration,
+                "s" => duration / 60.0,
+                "hr" => duration * 60.0,
+                _ => return None,
+            }
+        }
+        None => (apple_date(attrs.get("endDate"))? - start).num_seconds() as f64 / 60.0,
+    };
+    Some(Reading {
+        metric_type: METRIC_EXERCISE,
+        value: minutes,
+        unit: "min",
+        recorded_at: start.timestamp(),
+        source_record_id: format!("workout:{}:{}", source, attrs.get("startDate")?),
+        notes: Some(
+            activity
+                .strip_prefix(APPLE_WORKOUT_PREFIX)
+                .unwrap_or(activity)
+                .to_string(),
+        ),
+    })
+}
+
+// --- Google Fit ---
+
+/// One file of the takeout's `All Data` directory
+#[derive(Debug, Deserialize)]
+struct FitDataFile {
+    #[serde(rename = "Data Source", default)]
+    data_source: String,
+    #[serde(rename = "Data Points", default)]
+    data_points: Vec<FitDataPoint>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct FitDataPoint {
+    data_type_name: String,
+    start_time_nanos: i64,
+    end_time_nanos: i64,
+    #[serde(default)]
+    origin_data_source_id: String,
+    #[serde(default)]
+    fit_value: Vec<FitValue>,
+}
+
+#[derive(Debug, Deserialize)]
+struct FitValue {
+    value: FitNumber,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct FitNumber {
+    int_val: Option<i64>,
+    fp_val: Option<f64>,
+}
+
+impl FitDataPoint {
+    fn value(&self) -> Option<f64> {
+        let value = &self.fit_value.first()?.value;
+        value.fp_val.or(value.int_val.map(|v| v as f64))
+    }
+
+    fn int_value(&self) -> Option<i64> {
+        self.fit_value.first()?.value.int_val
+    }
+}
+
+fn import_google_fit(writer: &mut ReadingWriter, path: &Path) -> Result<(), HealthImportError> {
+    let files: Vec<_> = if path.is_dir() {
+        walkdir::WalkDir::new(path)
+            .sort_by_file_name()
+            .into_iter()
+            .filter_map(|entry| entry.ok())
+            .filter(|entry| {
+                entry.file_type().is_file()
+                    && entry
+                        .path()
+                        .extension()
+                        .is_some_and(|ext| ext.eq_ignore_ascii_case("json"))
+            })
+            .map(|entry| entry.into_path())
+            .collect()
+    } else {
+        vec![path.to_path_buf()]
+    };
+
+    let mut steps = DailyTotals::default();
+    let mut sleep = DailyTotals::default();
+    for file in files {
+        let data: FitDataFile = serde_json::from_reader(BufReader::new(File::open(&file)?))?;
+        for point in &data.data_points {
+            match google_fit_point(&data.data_source, point, &mut steps, &mut sleep) {
+                Some(Mapped::Reading(reading)) => writer.write(reading)?,
+                Some(Mapped::Totalled) => {}
+                None => writer.report.unsupported_records += 1,
+            }
+        }
+    }
+
+    for reading in steps
+        .into_readings(METRIC_STEPS, "steps")
+        .chain(sleep.into_readings(METRIC_SLEEP, "hours"))
+    {
+        writer.write(reading)?;
+    }
+    Ok(())
+}
+
+/// Fit times carry no offset, so days are UTC days
+fn fit_time(nanos: i64) -> Option<DateTime<FixedOffset>> {
+    Some(DateTime::from_timestamp(nanos.div_euclid(1_000_000_000), 0)?.fixed_offset())
+}
+
+/// Map a data point like [`apple_record`] maps a record
+fn google_fit_point(
+    data_source: &str,
+    point: &FitDataPoint,
+    steps: &mut DailyTotals,
+    sleep: &mut DailyTotals,
+) -> Option<Mapped> {
+    let source = if data_source.is_empty() {
+        point.origin_data_source_id.as_str()
+    } else {
+        data_source
+    };
+    let start = fit_time(point.start_time_nanos)?;
+    let end = fit_time(point.end_time_nanos)?;
+    let reading = |metric_type, value, unit, notes| Reading {
+        metric_type,
+        value,
+        unit,
+        recorded_at: start.timestamp(),
+        source_record_id: format!(
+            "{}:{}:{}",
+            point.data_type_name, source, point.start_time_nanos
+        ),
+        notes,
+    };
+
+    match point.data_type_name.as_str() {
+        "com.google.step_count.delta" => {
+            steps.add(source, start, point.value()?);
+            Some(Mapped::Totalled)
+        }
+        "com.google.weight" => Some(Mapped::Reading(reading(
+            METRIC_WEIGHT,
+            point.value()?,
+            "kg",
+            None,
+        ))),
+        "com.google.heart_rate.bpm" => Some(Mapped::Reading(reading(
+            METRIC_HEART_RATE,
+            point.value()?,
+            "bpm",
+            None,
+        ))),
+        "com.google.sleep.segment" => {
+            if FIT_ASLEEP_STAGES.contains(&point.int_value()?) {
+                let hours = (end - start).num_seconds() as f64 / 3600.0;
+                sleep.add(source, end, hours.max(0.0));
+            }
+            Some(Mapped::Totalled)
+        }
+        "com.google.activity.segment" => {
+            let activity = point.int_value()?;
+            if FIT_NON_WORKOUTS.contains(&activity) {
+                return Some(Mapped::Totalled);
+            }
+            let minutes = (end - start).num_seconds() as f64 / 60.0;
+            let name = google_fit_activity_name(activity)
+                .map_or_else(|| format!("Activity {}", activity), str::to_string);
+            Some(Mapped::Reading(reading(
+                METRIC_EXERCISE,
+                minutes,
+                "min",
+                Some(name),
+            )))
+        }
+        _ => None,
+    }
+}
+
+fn google_fit_activity_name(activity: i64) -> Option<&'static str> {
+    Some(match activity {
+        1 => "Biking",
+        8 => "Running",
+        24 => "Dancing",
+        25 => "Elliptical",
+        35 => "Hiking",
+        45 => "Meditation",
+        56 => "Jogging",
+        80 => "Strength training",
+        82 => "Swimming",
+        83 => "Swimming (pool)",
+        93 => "Walking (fitness)",
+        100 => "Yoga",
+        113 => "Crossfit",
+        114 => "HIIT",
+        _ => return None,
+    })
+}
@@ -0,0 +1,42 @@
+//! Parsing of ids received from the app
+//!
+//! Ids arrive at the command boundary as strings. A malformed one is an
+//! error naming the parameter it came in, never a fallback to ULID zero,
+//! which would quietly query (or write to) the wrong data.
+
+use thiserror::Error;
+use ulid::Ulid;
+
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+#[error("{field} is not a valid {kind} id ({value:?}): {reason}")]
+pub struct InvalidId {
+    /// Entity the id should point at, e.g. `space`
+    pub kind: &'static str,
+    /// Parameter the id was passed in, e.g. `space_id` or `note_ids[2]`
+    pub field: String,
+    pub value: String,
+    pub reason: String,
+}
+
+/// Parse the `field` parameter, the id of a `kind` entity
+pub fn parse_entity_id(kind: &'static str, field: &str, value: &str) -> Result<Ulid, InvalidId> {
+    Ulid::from_string(value).map_err(|e| InvalidId {
+        kind,
+        field: field.to_string(),
+        value: value.to_string(),
+        reason: e.to_string(),
+    })
+}
+
+/// Parse a list parameter; the error names the index of the first bad id
+pub fn parse_entity_ids<S: AsRef<str>>(
+    kind: &'static str,
+    field: &str,
+    values: &[S],
+) -> Result<Vec<Ulid>, InvalidId> {
+    values
+        .iter()
+        .enumerate()
+        .map(|(i, value)| parse_entity_id(kind, &format!("{}[{}]", field, i), value.as_ref()))
+        .collect()
+}
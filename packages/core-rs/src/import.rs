@@ -1,8 +1,14 @@
+use crate::db::with_bulk_import;
 use crate::note::create_note;
+use crate::project::{create_project, ProjectError};
+use crate::property::{apply_front_matter, PropertyError};
+use crate::task::{create_task, update_task};
+use chrono::{NaiveDate, NaiveDateTime};
 use gray_matter::engine::YAML;
 use gray_matter::Matter;
 use rusqlite::Connection;
 use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::fs;
 use std::io::{Read, Write};
 use std::path::Path;
@@ -25,64 +31,721 @@ pub enum ImportError {
     Zip(#[from] zip::result::ZipError),
     #[error("Gray Matter parse error: {0}")]
     GrayMatter(String),
+    #[error("Invalid CSV: {0}")]
+    InvalidCsv(String),
+    #[error("Project error: {0}")]
+    Project(#[from] crate::project::ProjectError),
+    #[error("Property error: {0}")]
+    Property(#[from] PropertyError),
 }
 
 pub fn import_from_obsidian(
     conn: &Connection,
     space_id: Ulid,
     path: &str,
-) -> Result<(), ImportError> {
+) -> Result<ImportReport, ImportError> {
     log::info!("[import] Starting Obsidian import from path: {}", path);
-    for entry in WalkDir::new(path).into_iter().filter_map(|e| e.ok()) {
-        if entry.file_type().is_file() {
-            let path = entry.path();
-            if let Some("md") = path.extension().and_then(|s| s.to_str()) {
-                log::info!("[import] Importing file: {:?}", path);
-                let content = std::fs::read_to_string(path)?;
-                let matter = Matter::<YAML>::new();
-                let result = matter.parse(&content);
-                let title = path
-                    .file_stem()
-                    .and_then(|s| s.to_str())
-                    .unwrap_or("Untitled");
-                create_note(conn, &space_id.to_string(), title, &result.content)
-                    .map_err(ImportError::Db)?;
+    let report = with_bulk_import(conn, |conn| {
+        let mut report = ImportReport::default();
+        for entry in WalkDir::new(path).into_iter().filter_map(|e| e.ok()) {
+            if entry.file_type().is_file() {
+                let path = entry.path();
+                if let Some("md") = path.extension().and_then(|s| s.to_str()) {
+                    log::info!("[import] Importing file: {:?}", path);
+                    let content = std::fs::read_to_string(path)?;
+                    let matter = Matter::<YAML>::new();
+                    let result = matter.parse(&content);
+                    let title = path
+                        .file_stem()
+                        .and_then(|s| s.to_str())
+                        .unwrap_or("Untitled");
+                    let note = create_note(conn, &space_id.to_string(), title, &result.content)
+                        .map_err(ImportError::Db)?;
+                    report.notes += 1;
+                    if let Some(front_matter) = result
+                        .data
+                        .and_then(|data| data.deserialize::<serde_json::Value>().ok())
+                    {
+                        report.properties += apply_front_matter(
+                            conn,
+                            &space_id.to_string(),
+                            &note.id.0.to_string(),
+                            &front_matter,
+                        )?;
+                    }
+                }
             }
         }
-    }
+        Ok::<_, ImportError>(report)
+    })?;
     log::info!("[import] Finished Obsidian import");
-    Ok(())
+    Ok(report)
+}
+
+/// What was created by an import
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ImportReport {
+    /// Pages, including the page behind every database row
+    pub notes: usize,
+    pub tasks: usize,
+    pub projects: usize,
+    /// Tasks attached to an imported project through a relation column
+    pub linked_tasks: usize,
+    /// Front matter values set as properties the space already defines
+    pub properties: usize,
+    pub databases: Vec<ImportedDatabase>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ImportedDatabase {
+    pub name: String,
+    pub kind: NotionEntityKind,
+    pub rows: usize,
+}
+
+/// What the rows of a Notion database become
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum NotionEntityKind {
+    Task,
+    Project,
+    Note,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct NotionImportOptions {
+    /// Database name, without Notion's id suffix, to what its rows become.
+    /// Databases not listed here are detected from their columns.
+    #[serde(default)]
+    pub database_kinds: HashMap<String, NotionEntityKind>,
 }
 
 pub fn import_from_notion(
     conn: &Connection,
     space_id: Ulid,
     path: &str,
-) -> Result<(), ImportError> {
+) -> Result<ImportReport, ImportError> {
+    import_from_notion_with_options(conn, space_id, path, &NotionImportOptions::default())
+}
+
+/// Import a Notion "Markdown & CSV" export. Every database CSV is paired with
+/// the folder holding its row pages; task and project databases become real
+/// tasks and projects, and whatever columns aren't mapped are kept on the
+/// row's note.
+pub fn import_from_notion_with_options(
+    conn: &Connection,
+    space_id: Ulid,
+    path: &str,
+    options: &NotionImportOptions,
+) -> Result<ImportReport, ImportError> {
     log::info!("[import] Starting Notion import from path: {}", path);
     let file = fs::File::open(path)?;
     let mut archive = ZipArchive::new(file)?;
 
+    let mut pages = BTreeMap::new();
+    let mut csv_files = BTreeMap::new();
     for i in 0..archive.len() {
         let mut file = archive.by_index(i)?;
-        if let Some(outpath) = file.enclosed_name() {
-            if outpath.extension().and_then(|s| s.to_str()) == Some("md") {
-                log::info!("[import] Importing file: {:?}", outpath);
+        let Some(outpath) = file.enclosed_name() else {
+            continue;
+        };
+        let name = outpath.to_string_lossy().replace('\\', "/");
+        match outpath.extension().and_then(|s| s.to_str()) {
+            Some("md") => {
+                let mut content = String::new();
+                file.read_to_string(&mut content)?;
+                pages.insert(name, content);
+            }
+            Some("csv") => {
                 let mut content = String::new();
                 file.read_to_string(&mut content)?;
-                let matter = Matter::<YAML>::new();
-                let result = matter.parse(&content);
-                let title = outpath
-                    .file_stem()
-                    .and_then(|s| s.to_str())
-                    .unwrap_or("Untitled");
-                create_note(conn, &space_id.to_string(), title, &result.content)
-                    .map_err(ImportError::Db)?;
+                csv_files.insert(name, content);
             }
+            _ => {}
         }
     }
-    log::info!("[import] Finished Notion import");
-    Ok(())
+
+    let databases = collect_databases(csv_files, options)?;
+    let report = with_bulk_import(conn, |conn| {
+        write_notion_import(conn, space_id, pages, &databases)
+    })?;
+    log::info!("[import] Finished Notion import: {:?}", report);
+    Ok(report)
+}
+
+/// Create everything a parsed Notion export describes
+fn write_notion_import(
+    conn: &Connection,
+    space_id: Ulid,
+    mut pages: BTreeMap<String, String>,
+    databases: &[NotionDatabase],
+) -> Result<ImportReport, ImportError> {
+    let project_titles: HashSet<&str> = titles_of(databases, NotionEntityKind::Project);
+    let task_titles: HashSet<&str> = titles_of(databases, NotionEntityKind::Task);
+
+    let space = space_id.to_string();
+    let mut report = ImportReport::default();
+    let mut project_ids: HashMap<String, Ulid> = HashMap::new();
+    let mut task_projects: HashMap<String, Ulid> = HashMap::new();
+
+    // Projects first so tasks can be attached to them as they are created
+    let mut ordered: Vec<&NotionDatabase> = databases.iter().collect();
+    ordered.sort_by_key(|db| db.kind != NotionEntityKind::Project);
+    for db in ordered {
+        log::info!(
+            "[import] Importing Notion database '{}' as {:?} ({} rows)",
+            db.name,
+            db.kind,
+            db.rows.len()
+        );
+        let columns = classify_columns(db, &project_titles, &task_titles);
+        for row in &db.rows {
+            let title = match row.first().map(|t| t.trim()) {
+                Some(t) if !t.is_empty() => t.to_string(),
+                _ => "Untitled".to_string(),
+            };
+            let body = take_row_page(&mut pages, &db.folder, &title, &db.headers);
+            let mut fields = RowFields::default();
+            for (i, column) in columns.iter().enumerate().skip(1) {
+                let value = row.get(i).map(|v| v.trim()).unwrap_or_default();
+                if !value.is_empty() {
+                    fields.apply(*column, &db.headers[i], value, db.kind);
+                }
+            }
+
+            let note = create_note(
+                conn,
+                &space,
+                &title,
+                &with_properties(&body, &fields.properties),
+            )
+            .map_err(ImportError::Db)?;
+            report.notes += 1;
+
+            match db.kind {
+                NotionEntityKind::Project => {
+                    let project_id = import_project(conn, &space, &title, &fields)?;
+                    for task in &fields.related_tasks {
+                        task_projects.entry(task.clone()).or_insert(project_id);
+                    }
+                    project_ids.entry(title).or_insert(project_id);
+                    report.projects += 1;
+                }
+                NotionEntityKind::Task => {
+                    let project_id = fields
+                        .related_projects
+                        .iter()
+                        .find_map(|p| project_ids.get(p))
+                        .or_else(|| task_projects.get(&title))
+                        .copied();
+                    let mut task = create_task(conn, space_id, &title, None)?;
+                    task.note_id = Some(note.id.0);
+                    task.project_id = project_id;
+                    task.status = fields.status.unwrap_or("inbox").to_string();
+                    task.due_at = fields.due_at;
+                    task.start_at = fields.start_at;
+                    task.completed_at = fields.completed_at;
+                    task.priority = fields.priority;
+                    update_task(conn, &task)?;
+                    report.tasks += 1;
+                    if project_id.is_some() {
+                        report.linked_tasks += 1;
+                    }
+                }
+                NotionEntityKind::Note => {}
+            }
+        }
+        report.databases.push(ImportedDatabase {
+            name: db.name.clone(),
+            kind: db.kind,
+            rows: db.rows.len(),
+        });
+    }
+
+    // Everything not claimed by a database row is a plain page
+    for (name, content) in pages {
+        log::info!("[import] Importing file: {:?}", name);
+        let matter = Matter::<YAML>::new();
+        let result = matter.parse(&content);
+        let title = Path::new(&name)
+            .file_stem()
+            .and_then(|s| s.to_str())
+            .map(strip_notion_id)
+            .unwrap_or("Untitled");
+        create_note(conn, &space, title, &result.content).map_err(ImportError::Db)?;
+        report.notes += 1;
+    }
+    Ok(report)
+}
+
+struct NotionDatabase {
+    name: String,
+    /// Archive path of the CSV without its extension; Notion puts the row
+    /// pages in the folder of that name
+    folder: String,
+    kind: NotionEntityKind,
+    headers: Vec<String>,
+    rows: Vec<Vec<String>>,
+}
+
+fn collect_databases(
+    csv_files: BTreeMap<String, String>,
+    options: &NotionImportOptions,
+) -> Result<Vec<NotionDatabase>, ImportError> {
+    // Newer exports write both the current view and `_all.csv` with every
+    // row; keep only the latter when both are present
+    let mut by_folder: BTreeMap<String, (bool, String)> = BTreeMap::new();
+    for (name, content) in csv_files {
+        let stem = name.strip_suffix(".csv").unwrap_or(&name);
+        let (folder, is_all) = match stem.strip_suffix("_all") {
+            Some(folder) => (folder.to_string(), true),
+            None => (stem.to_string(), false),
+        };
+        match by_folder.get(&folder) {
+            Some((true, _)) if !is_all => {}
+            _ => {
+                by_folder.insert(folder, (is_all, content));
+            }
+        }
+    }
+
+    let mut databases = Vec::new();
+    for (folder, (_, content)) in by_folder {
+        let mut rows = parse_csv(&content)?;
+        if rows.is_empty() {
+            continue;
+        }
+        let headers: Vec<String> = rows
+            .remove(0)
+            .into_iter()
+            .map(|h| h.trim().to_string())
+            .collect();
+        let file_name = folder.rsplit('/').next().unwrap_or(&folder);
+        let name = strip_notion_id(file_name).to_string();
+        let kind = options
+            .database_kinds
+            .iter()
+            .find(|(db, _)| db.trim().eq_ignore_ascii_case(&name))
+            .map(|(_, kind)| *kind)
+            .unwrap_or_else(|| detect_kind(&name, &headers));
+        databases.push(NotionDatabase {
+            name,
+            folder,
+            kind,
+            headers,
+            rows,
+        });
+    }
+    Ok(databases)
+}
+
+fn titles_of(databases: &[NotionDatabase], kind: NotionEntityKind) -> HashSet<&str> {
+    databases
+        .iter()
+        .filter(|db| db.kind == kind)
+        .flat_map(|db| db.rows.iter().filter_map(|row| row.first()))
+        .map(|title| title.trim())
+        .filter(|title| !title.is_empty())
+        .collect()
+}
+
+/// Notion suffixes exported file names with the page id: `Tasks 0123...cdef`
+fn strip_notion_id(name: &str) -> &str {
+    match name.rsplit_once(' ') {
+        Some((base, id)) if id.len() == 32 && id.bytes().all(|b| b.is_ascii_hexdigit()) => base,
+        _ => name,
+    }
+}
+
+const STATUS_COLUMNS: &[&str] = &["status", "state", "done"];
+const DUE_COLUMNS: &[&str] = &["due", "due date", "deadline", "date"];
+const PRIORITY_COLUMNS: &[&str] = &["priority"];
+const START_COLUMNS: &[&str] = &["start", "start date"];
+const COMPLETED_COLUMNS: &[&str] = &["completed", "completed at", "completed on", "done date"];
+const TIMELINE_COLUMNS: &[&str] = &["timeline", "dates", "date"];
+const TARGET_END_COLUMNS: &[&str] = &["end date", "target date", "due", "due date", "deadline"];
+const GOAL_COLUMNS: &[&str] = &["goal", "outcome", "goal outcome"];
+
+fn header_is(header: &str, names: &[&str]) -> bool {
+    names.contains(&header.trim().to_lowercase().as_str())
+}
+
+fn detect_kind(name: &str, headers: &[String]) -> NotionEntityKind {
+    let has = |names: &[&str]| headers.iter().any(|h| header_is(h, names));
+    if name.to_lowercase().contains("project") || (has(&["tasks"]) && has(STATUS_COLUMNS)) {
+        NotionEntityKind::Project
+    } else if has(STATUS_COLUMNS) && (has(DUE_COLUMNS) || has(PRIORITY_COLUMNS)) {
+        NotionEntityKind::Task
+    } else {
+        NotionEntityKind::Note
+    }
+}
+
+/// What a database column is imported as
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum ColumnRole {
+    Title,
+    Status,
+    Due,
+    Start,
+    Completed,
+    Priority,
+    Timeline,
+    TargetEnd,
+    Goal,
+    ProjectRelation,
+    TaskRelation,
+    Property,
+}
+
+fn classify_columns(
+    db: &NotionDatabase,
+    project_titles: &HashSet<&str>,
+    task_titles: &HashSet<&str>,
+) -> Vec<ColumnRole> {
+    let mut taken = Vec::new();
+    db.headers
+        .iter()
+        .enumerate()
+        .map(|(i, header)| {
+            if i == 0 {
+                return ColumnRole::Title;
+            }
+            let relates_to = |titles: &HashSet<&str>| {
+                let mut cells = db
+                    .rows
+                    .iter()
+                    .filter_map(|row| row.get(i))
+                    .filter(|cell| !cell.trim().is_empty())
+                    .peekable();
+                cells.peek().is_some()
+                    && cells.all(|cell| {
+                        relation_titles(cell)
+                            .iter()
+                            .all(|t| titles.contains(t.as_str()))
+                    })
+            };
+            let candidates: &[(ColumnRole, &[&str])] = match db.kind {
+                NotionEntityKind::Task => &[
+                    (ColumnRole::Status, STATUS_COLUMNS),
+                    (ColumnRole::Due, DUE_COLUMNS),
+                    (ColumnRole::Start, START_COLUMNS),
+                    (ColumnRole::Completed, COMPLETED_COLUMNS),
+                    (ColumnRole::Priority, PRIORITY_COLUMNS),
+                ],
+                NotionEntityKind::Project => &[
+                    (ColumnRole::Status, STATUS_COLUMNS),
+                    (ColumnRole::Timeline, TIMELINE_COLUMNS),
+                    (ColumnRole::Start, START_COLUMNS),
+                    (ColumnRole::TargetEnd, TARGET_END_COLUMNS),
+                    (ColumnRole::Goal, GOAL_COLUMNS),
+                ],
+                NotionEntityKind::Note => &[],
+            };
+            // The first matching column wins a role; later ones stay properties
+            let role = candidates
+                .iter()
+                .find(|(role, names)| header_is(header, names) && !taken.contains(role))
+                .map(|(role, _)| *role);
+            if let Some(role) = role {
+                taken.push(role);
+                return role;
+            }
+            if db.kind == NotionEntityKind::Task && relates_to(project_titles) {
+                ColumnRole::ProjectRelation
+            } else if db.kind == NotionEntityKind::Project && relates_to(task_titles) {
+                ColumnRole::TaskRelation
+            } else {
+                ColumnRole::Property
+            }
+        })
+        .collect()
+}
+
+/// Titles in a relation cell. Notion writes each related page as its title,
+/// optionally followed by a link in parentheses, separated by commas.
+fn relation_titles(cell: &str) -> Vec<String> {
+    cell.split(", ")
+        .map(|item| {
+            let item = item.trim();
+            match item.rfind(" (") {
+                Some(i) if item.ends_with(')') => item[..i].trim(),
+                _ => item,
+            }
+        })
+        .filter(|title| !title.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+#[derive(Default)]
+struct RowFields {
+    status: Option<&'static str>,
+    due_at: Option<i64>,
+    start_at: Option<i64>,
+    completed_at: Option<i64>,
+    target_end_at: Option<i64>,
+    priority: Option<i64>,
+    goal: Option<String>,
+    related_projects: Vec<String>,
+    related_tasks: Vec<String>,
+    /// Columns without a field of their own, in column order
+    properties: Vec<(String, String)>,
+}
+
+impl RowFields {
+    fn apply(&mut self, role: ColumnRole, header: &str, value: &str, kind: NotionEntityKind) {
+        // A value that can't be mapped is kept rather than dropped
+        let mapped = match role {
+            ColumnRole::Status => {
+                self.status = match kind {
+                    NotionEntityKind::Project => project_status(value),
+                    _ => task_status(value),
+                };
+                self.status.is_some()
+            }
+            ColumnRole::Due => set(&mut self.due_at, parse_notion_date(value).map(|(s, _)| s)),
+            ColumnRole::Start => set(&mut self.start_at, parse_notion_date(value).map(|(s, _)| s)),
+            ColumnRole::Completed => set(
+                &mut self.completed_at,
+                parse_notion_date(value).map(|(s, _)| s),
+            ),
+            ColumnRole::TargetEnd => set(
+                &mut self.target_end_at,
+                parse_notion_date(value).map(|(s, e)| e.unwrap_or(s)),
+            ),
+            ColumnRole::Timeline => match parse_notion_date(value) {
+                Some((start, end)) => {
+                    self.start_at.get_or_insert(start);
+                    if let Some(end) = end {
+                        self.target_end_at.get_or_insert(end);
+                    }
+                    true
+                }
+                None => false,
+            },
+            ColumnRole::Priority => set(&mut self.priority, task_priority(value)),
+            ColumnRole::Goal => set(&mut self.goal, Some(value.to_string())),
+            ColumnRole::ProjectRelation => {
+                self.related_projects.extend(relation_titles(value));
+                true
+            }
+            ColumnRole::TaskRelation => {
+                self.related_tasks.extend(relation_titles(value));
+                true
+            }
+            ColumnRole::Title | ColumnRole::Property => false,
+        };
+        if !mapped {
+            self.properties
+                .push((header.to_string(), value.to_string()));
+        }
+    }
+}
+
+fn set<T>(slot: &mut Option<T>, value: Option<T>) -> bool {
+    match value {
+        Some(value) => {
+            *slot = Some(value);
+            true
+        }
+        None => false,
+    }
+}
+
+fn task_status(value: &str) -> Option<&'static str> {
+    match value.trim().to_lowercase().as_str() {
+        "done" | "complete" | "completed" | "finished" | "yes" => Some("done"),
+        "in progress" | "in_progress" | "doing" | "started" => Some("in_progress"),
+        "not started" | "to do" | "todo" | "next" | "planned" | "no" => Some("next"),
+        "waiting" | "blocked" | "on hold" => Some("waiting"),
+        "cancelled" | "canceled" | "won't do" => Some("cancelled"),
+        "inbox" | "backlog" => Some("inbox"),
+        _ => None,
+    }
+}
+
+fn project_status(value: &str) -> Option<&'static str> {
+    match value.trim().to_lowercase().as_str() {
+        "done" | "complete" | "completed" | "finished" => Some("done"),
+        "in progress" | "active" | "doing" | "started" => Some("active"),
+        "blocked" | "on hold" | "paused" => Some("blocked"),
+        "archived" | "cancelled" | "canceled" => Some("archived"),
+        "not started" | "planned" | "planning" | "proposed" | "backlog" => Some("proposed"),
+        _ => None,
+    }
+}
+
+/// Tasks use 1 (high) to 4 (lowest)
+fn task_priority(value: &str) -> Option<i64> {
+    let value = value.trim().to_lowercase();
+    match value.as_str() {
+        "urgent" | "critical" | "high" | "p0" | "p1" => Some(1),
+        "medium" | "normal" | "p2" => Some(2),
+        "low" | "p3" => Some(3),
+        "lowest" | "p4" => Some(4),
+        _ => value.parse().ok().filter(|p| (1..=4).contains(p)),
+    }
+}
+
+/// Parse a Notion date cell such as `January 5, 2024`,
+/// `January 5, 2024 3:00 PM (GMT+1)` or a range `A → B`, as UTC timestamps
+fn parse_notion_date(value: &str) -> Option<(i64, Option<i64>)> {
+    fn parse_one(value: &str) -> Option<i64> {
+        let value = match value.rfind(" (") {
+            Some(i) if value.ends_with(')') => &value[..i],
+            _ => value,
+        }
+        .trim();
+        const DATE_TIMES: &[&str] = &["%B %d, %Y %I:%M %p", "%Y-%m-%d %H:%M", "%Y-%m-%dT%H:%M:%S"];
+        const DATES: &[&str] = &["%B %d, %Y", "%b %d, %Y", "%Y-%m-%d", "%Y/%m/%d", "%m/%d/%Y"];
+        DATE_TIMES
+            .iter()
+            .find_map(|f| NaiveDateTime::parse_from_str(value, f).ok())
+            .or_else(|| {
+                DATES
+                    .iter()
+                    .find_map(|f| NaiveDate::parse_from_str(value, f).ok())
+                    .and_then(|d| d.and_hms_opt(0, 0, 0))
+            })
+            .map(|dt| dt.and_utc().timestamp())
+    }
+    match value.split_once('→') {
+        Some((start, end)) => Some((parse_one(start)?, Some(parse_one(end)?))),
+        None => Some((parse_one(value)?, None)),
+    }
+}
+
+/// Remove the page of a database row from `pages` and return its body without
+/// the title heading and the property lines Notion writes under it
+fn take_row_page(
+    pages: &mut BTreeMap<String, String>,
+    folder: &str,
+    title: &str,
+    headers: &[String],
+) -> String {
+    let prefix = format!("{}/", folder);
+    let path = pages
+        .keys()
+        .find(|path| {
+            path.strip_prefix(&prefix).is_some_and(|rest| {
+                !rest.contains('/')
+                    && rest
+                        .strip_suffix(".md")
+                        .is_some_and(|stem| strip_notion_id(stem) == title)
+            })
+        })
+        .cloned();
+    let Some(content) = path.and_then(|path| pages.remove(&path)) else {
+        return String::new();
+    };
+    let matter = Matter::<YAML>::new();
+    let content = matter.parse(&content).content;
+
+    let mut lines = content.lines().peekable();
+    while lines.peek().is_some_and(|line| line.trim().is_empty()) {
+        lines.next();
+    }
+    if lines
+        .peek()
+        .is_some_and(|line| line.trim() == format!("# {}", title))
+    {
+        lines.next();
+    }
+    while let Some(line) = lines.peek() {
+        let is_property = line
+            .split_once(": ")
+            .is_some_and(|(key, _)| headers.iter().any(|h| h == key.trim()));
+        if line.trim().is_empty() || is_property {
+            lines.next();
+        } else {
+            break;
+        }
+    }
+    lines.collect::<Vec<_>>().join("\n")
+}
+
+fn with_properties(body: &str, properties: &[(String, String)]) -> String {
+    let mut content = body.trim_end().to_string();
+    if properties.is_empty() {
+        return content;
+    }
+    if !content.is_empty() {
+        content.push_str("\n\n");
+    }
+    content.push_str("## Properties\n\n");
+    for (key, value) in properties {
+        content.push_str(&format!("- {}: {}\n", key, value));
+    }
+    content
+}
+
+fn import_project(
+    conn: &Connection,
+    space_id: &str,
+    title: &str,
+    fields: &RowFields,
+) -> Result<Ulid, ImportError> {
+    let project = create_project(conn, space_id, title)?;
+    // The export carries the project's final state, so this is not a
+    // lifecycle transition and skips update_project's checks
+    conn.execute(
+        "UPDATE project SET status = ?1, goal_outcome = ?2, start_at = ?3, target_end_at = ?4
+         WHERE id = ?5",
+        rusqlite::params![
+            fields.status.unwrap_or("proposed"),
+            fields.goal,
+            fields.start_at,
+            fields.target_end_at,
+            project.id
+        ],
+    )?;
+    Ulid::from_string(&project.id).map_err(|e| ProjectError::InvalidData(e.to_string()).into())
+}
+
+/// Parse RFC 4180 CSV as Notion writes it: quoted cells may hold commas,
+/// doubled quotes and newlines
+fn parse_csv(text: &str) -> Result<Vec<Vec<String>>, ImportError> {
+    let text = text.strip_prefix('\u{feff}').unwrap_or(text);
+    let mut rows = Vec::new();
+    let mut row = Vec::new();
+    let mut cell = String::new();
+    let mut in_quotes = false;
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        if in_quotes {
+            match c {
+                '"' if chars.peek() == Some(&'"') => {
+                    chars.next();
+                    cell.push('"');
+                }
+                '"' => in_quotes = false,
+                _ => cell.push(c),
+            }
+            continue;
+        }
+        match c {
+            '"' => in_quotes = true,
+            ',' => row.push(std::mem::take(&mut cell)),
+            '\r' if chars.peek() == Some(&'\n') => {}
+            '\r' | '\n' => {
+                row.push(std::mem::take(&mut cell));
+                rows.push(std::mem::take(&mut row));
+            }
+            _ => cell.push(c),
+        }
+    }
+    if in_quotes {
+        return Err(ImportError::InvalidCsv(
+            "unterminated quoted cell".to_string(),
+        ));
+    }
+    if !cell.is_empty() || !row.is_empty() {
+        row.push(cell);
+        rows.push(row);
+    }
+    rows.retain(|row| !(row.len() == 1 && row[0].trim().is_empty()));
+    Ok(rows)
 }
 
 // Export functionality
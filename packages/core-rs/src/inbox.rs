@@ -0,0 +1,158 @@
+//! Quick capture inbox
+//!
+//! Captures are vault-wide scraps of text, kept apart from notes so nothing
+//! has to be decided when writing one down. Triage later turns each into a
+//! note or task in a space, or dismisses it; either way the capture leaves
+//! the inbox in the same transaction.
+
+use crate::db::DbError;
+use crate::note::{self, Note};
+use crate::task::{self, Task};
+use chrono::Utc;
+use rusqlite::{Connection, OptionalExtension, Row};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use ulid::Ulid;
+
+/// Longest task title taken from a capture, in characters
+const MAX_TASK_TITLE_CHARS: usize = 120;
+
+#[derive(Error, Debug)]
+pub enum InboxError {
+    #[error("Rusqlite error: {0}")]
+    Rusqlite(#[from] rusqlite::Error),
+    #[error("Database error: {0}")]
+    Db(#[from] DbError),
+    #[error("Inbox item not found: {0}")]
+    NotFound(String),
+    #[error("Invalid data: {0}")]
+    InvalidData(String),
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct InboxItem {
+    pub id: String,
+    pub text: String,
+    /// Where it was captured, e.g. `mobile`, `desktop` or `share-sheet`
+    pub source: String,
+    pub captured_at: i64,
+    pub updated_at: i64,
+}
+
+impl InboxItem {
+    fn from_row(row: &Row) -> rusqlite::Result<Self> {
+        Ok(InboxItem {
+            id: row.get(0)?,
+            text: row.get(1)?,
+            source: row.get(2)?,
+            captured_at: row.get(3)?,
+            updated_at: row.get(4)?,
+        })
+    }
+}
+
+pub fn capture_to_inbox(
+    conn: &Connection,
+    text: &str,
+    source: &str,
+) -> Result<InboxItem, InboxError> {
+    if text.trim().is_empty() {
+        return Err(InboxError::InvalidData(
+            "Nothing to capture: the text is empty".to_string(),
+        ));
+    }
+    let now = Utc::now().timestamp();
+    let item = InboxItem {
+        id: Ulid::new().to_string(),
+        text: text.to_string(),
+        source: source.to_string(),
+        captured_at: now,
+        updated_at: now,
+    };
+    conn.execute(
+        "INSERT INTO inbox_item (id, text, source, captured_at, updated_at)
+         VALUES (?1, ?2, ?3, ?4, ?5)",
+        rusqlite::params![
+            item.id,
+            item.text,
+            item.source,
+            item.captured_at,
+            item.updated_at
+        ],
+    )?;
+    log::info!("[inbox] Captured {} from {}", item.id, source);
+    Ok(item)
+}
+
+/// Everything awaiting triage, oldest capture first
+pub fn get_inbox_items(conn: &Connection) -> Result<Vec<InboxItem>, InboxError> {
+    let mut stmt = conn.prepare(
+        "SELECT id, text, source, captured_at, updated_at
+         FROM inbox_item
+         ORDER BY captured_at, id",
+    )?;
+    let items = stmt
+        .query_map([], InboxItem::from_row)?
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(items)
+}
+
+pub fn get_inbox_item(conn: &Connection, id: &str) -> Result<Option<InboxItem>, InboxError> {
+    Ok(conn
+        .query_row(
+            "SELECT id, text, source, captured_at, updated_at FROM inbox_item WHERE id = ?1",
+            [id],
+            InboxItem::from_row,
+        )
+        .optional()?)
+}
+
+/// Turn a capture into a note in `space_id` whose body is the captured text
+pub fn convert_inbox_to_note(
+    conn: &mut Connection,
+    item_id: &str,
+    space_id: &str,
+    title: &str,
+) -> Result<Note, InboxError> {
+    let tx = conn.transaction()?;
+    let item = take_item(&tx, item_id)?;
+    let note = note::create_note(&tx, space_id, title, &item.text)?;
+    tx.commit()?;
+    log::info!("[inbox] Converted {} to note {}", item_id, note.id);
+    Ok(note)
+}
+
+/// Turn a capture into a task in `space_id`, titled with its first line.
+/// Longer captures keep their full text as the description.
+pub fn convert_inbox_to_task(
+    conn: &mut Connection,
+    item_id: &str,
+    space_id: Ulid,
+) -> Result<Task, InboxError> {
+    let tx = conn.transaction()?;
+    let item = take_item(&tx, item_id)?;
+    let text = item.text.trim();
+    let first_line = text.lines().next().unwrap_or_default().trim();
+    let title: String = first_line.chars().take(MAX_TASK_TITLE_CHARS).collect();
+    let description = (title != text).then(|| text.to_string());
+    let task = task::create_task(&tx, space_id, &title, description)?;
+    tx.commit()?;
+    log::info!("[inbox] Converted {} to task {}", item_id, task.id);
+    Ok(task)
+}
+
+pub fn dismiss_inbox_item(conn: &mut Connection, item_id: &str) -> Result<(), InboxError> {
+    let tx = conn.transaction()?;
+    take_item(&tx, item_id)?;
+    tx.commit()?;
+    log::info!("[inbox] Dismissed {}", item_id);
+    Ok(())
+}
+
+/// Remove an item from the inbox, returning it
+fn take_item(conn: &Connection, item_id: &str) -> Result<InboxItem, InboxError> {
+    let item =
+        get_inbox_item(conn, item_id)?.ok_or_else(|| InboxError::NotFound(item_id.to_string()))?;
+    conn.execute("DELETE FROM inbox_item WHERE id = ?1", [item_id])?;
+    Ok(item)
+}
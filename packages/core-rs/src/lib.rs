@@ -7,9 +7,12 @@ pub mod ai;
 pub mod analytics;
 pub mod audit;
 pub mod auth;
+pub mod automation;
 pub mod backlink;
 pub mod backup;
 pub mod blob;
+pub mod bulk_edit;
+pub mod c_api;
 pub mod caldav;
 pub mod calendar;
 pub mod collaboration;
@@ -19,33 +22,53 @@ pub mod crypto;
 pub mod dashboard;
 pub mod db;
 pub mod editor;
+pub mod error;
+pub mod events;
 pub mod foresight;
 pub mod form;
 pub mod goals;
 pub mod graph;
 pub mod habits;
 pub mod health;
+pub mod ids;
 pub mod import;
+pub mod inbox;
 pub mod llm;
+pub mod lockout;
 pub mod logger;
+pub mod maintenance;
 pub mod meeting;
 pub mod mode;
 pub mod music;
 pub mod note;
+pub mod note_dedup;
+pub mod note_lock;
+pub mod note_session;
+pub mod note_share;
+pub mod note_stats;
+pub mod note_template;
 pub mod ocr;
+pub mod permission;
 pub mod personal_modes;
 pub mod plugin;
 pub mod project;
+pub mod property;
 pub mod quote;
+pub mod recipe;
 pub mod search;
 pub mod social;
 pub mod space;
+pub mod space_template;
 pub mod srs;
+pub mod srs_session;
 pub mod sync;
 pub mod tag;
 pub mod task;
 pub mod temporal_graph;
 pub mod time_tracking;
+pub mod travel;
+pub mod undo;
+pub mod url_metadata;
 pub mod vault;
 pub mod versioning;
 pub mod weekly_review;
@@ -60,7 +83,7 @@ pub mod sync_agent {
     pub use crate::sync::engine::SyncAgent;
     pub use crate::sync::models::{
         DeviceInfo, DeviceType, SyncConflict, SyncDelta, SyncHistoryEntry, SyncOperation,
-        SyncProgress, SyncStats, SyncTask,
+        SyncProgress, SyncStats, SyncTask, SyncTransport,
     };
 }
 
@@ -1,147 +1,355 @@
 // Response Caching for LLM Completions
 //
-// Caches LLM responses to reduce costs and improve latency.
-// Uses SQLite for persistent storage across sessions.
-
+// Caches LLM responses in the vault so identical requests aren't billed again,
+// including after a restart. Entries expire after a TTL chosen per request
+// type, the least recently used ones are evicted above a size cap, and
+// entries can be tagged with namespaces to invalidate them together, e.g.
+// everything derived from a note when the note changes.
+
+use super::cost::get_model_pricing;
+use super::tokenizer::{SimpleTokenCounter, TokenCounter};
 use super::{types::*, LlmError as LLMError};
+use crate::maintenance::{
+    MaintenanceError, MaintenanceRegistry, MaintenanceTask, TaskReport, PRIORITY_LOW,
+};
 use chrono::Utc;
-use rusqlite::{params, Connection};
+use rusqlite::{params, Connection, OptionalExtension};
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::time::Duration;
+
+/// Request type of entries stored without a more specific one
+pub const DEFAULT_REQUEST_TYPE: &str = "default";
+
+/// Lifetime of entries whose request type has no TTL of its own
+pub const DEFAULT_CACHE_TTL: Duration = Duration::from_secs(7 * 24 * 60 * 60);
+
+/// Total size of the cached requests and responses
+pub const DEFAULT_CACHE_MAX_BYTES: u64 = 50 * 1024 * 1024;
+
+/// Namespace of entries derived from a note's content
+pub fn note_namespace(note_id: &str) -> String {
+    format!("note:{}", note_id)
+}
 
 /// Statistics about the cache
 #[derive(Debug, Clone)]
 pub struct CacheStats {
     pub total_entries: usize,
     pub total_hits: usize,
+    /// Hits over lookups, counting each stored entry as one miss
     pub hit_rate: f32,
     pub size_bytes: usize,
+    /// What the hits would have cost had they gone to the provider
+    pub saved_usd: f64,
+}
+
+/// TTLs and size cap of a [`ResponseCache`]
+#[derive(Debug, Clone)]
+pub struct CacheConfig {
+    pub default_ttl: Duration,
+    /// TTL per request type, e.g. `summarize`
+    pub ttls: HashMap<String, Duration>,
+    /// Least recently used entries are evicted above this
+    pub max_bytes: u64,
+}
+
+impl Default for CacheConfig {
+    fn default() -> Self {
+        Self {
+            default_ttl: DEFAULT_CACHE_TTL,
+            ttls: HashMap::new(),
+            max_bytes: DEFAULT_CACHE_MAX_BYTES,
+        }
+    }
+}
+
+impl CacheConfig {
+    /// Builder method to set the TTL of a request type
+    pub fn ttl(mut self, request_type: impl Into<String>, ttl: Duration) -> Self {
+        self.ttls.insert(request_type.into(), ttl);
+        self
+    }
+
+    /// Builder method to set the size cap
+    pub fn max_bytes(mut self, max_bytes: u64) -> Self {
+        self.max_bytes = max_bytes;
+        self
+    }
+
+    fn ttl_for(&self, request_type: &str) -> Duration {
+        self.ttls
+            .get(request_type)
+            .copied()
+            .unwrap_or(self.default_ttl)
+    }
+}
+
+/// What a cached response is for and what invalidates it
+#[derive(Debug, Clone)]
+pub struct CacheContext {
+    pub request_type: String,
+    pub namespaces: Vec<String>,
+}
+
+impl Default for CacheContext {
+    fn default() -> Self {
+        Self::new(DEFAULT_REQUEST_TYPE)
+    }
+}
+
+impl CacheContext {
+    pub fn new(request_type: impl Into<String>) -> Self {
+        Self {
+            request_type: request_type.into(),
+            namespaces: Vec::new(),
+        }
+    }
+
+    /// Builder method to tag the entry with a namespace
+    pub fn namespace(mut self, namespace: impl Into<String>) -> Self {
+        let namespace = namespace.into();
+        if !self.namespaces.contains(&namespace) {
+            self.namespaces.push(namespace);
+        }
+        self
+    }
+
+    /// Builder method to drop the entry when the note changes
+    pub fn note(self, note_id: &str) -> Self {
+        self.namespace(note_namespace(note_id))
+    }
+}
+
+/// Everything that makes two requests answer the same
+#[derive(Serialize)]
+struct KeyMaterial<'a> {
+    provider: &'a str,
+    model: &'a str,
+    messages: Vec<(&'a Role, String)>,
+    temperature: Option<u32>,
+    max_tokens: Option<usize>,
+    top_p: Option<u32>,
+    stop_sequences: &'a Option<Vec<String>>,
 }
 
-/// Cache for LLM responses
+/// Stable key of a request sent to `model` on `provider`. Prompts are
+/// normalized so differences in line endings and trailing whitespace don't
+/// cause misses.
+pub fn cache_key(provider: &str, model: &str, request: &LLMRequest) -> String {
+    let material = KeyMaterial {
+        provider,
+        model,
+        messages: request
+            .messages
+            .iter()
+            .map(|m| (&m.role, normalize_prompt(&m.content)))
+            .collect(),
+        temperature: request.temperature.map(f32::to_bits),
+        max_tokens: request.max_tokens,
+        top_p: request.top_p.map(f32::to_bits),
+        stop_sequences: &request.stop_sequences,
+    };
+    let json = serde_json::to_vec(&material).unwrap_or_default();
+    hex::encode(Sha256::digest(json))
+}
+
+fn normalize_prompt(content: &str) -> String {
+    content
+        .lines()
+        .map(str::trim_end)
+        .collect::<Vec<_>>()
+        .join("\n")
+        .trim()
+        .to_string()
+}
+
+/// Cache for LLM responses, stored in the `llm_cache` table
+#[derive(Debug, Clone, Default)]
 pub struct ResponseCache {
-    // Note: We don't store Connection directly, we expect it to be passed in
-    // This allows for better control over transactions and thread safety
+    config: CacheConfig,
 }
 
 impl ResponseCache {
-    /// Create a new response cache
-    pub fn new(_conn: &Connection) -> Result<Self, LLMError> {
-        log::debug!("[LLM::Cache] Initializing response cache");
-        // Tables are now created in db::migrate
-        log::info!("[LLM::Cache] Cache initialized successfully");
-        Ok(Self {})
+    pub fn new(config: CacheConfig) -> Self {
+        Self { config }
     }
 
-    /// Get a cached response
-    pub fn get(&self, _request: &LLMRequest) -> Result<Option<LLMResponse>, LLMError> {
-        // Note: This function signature implies we need to pass conn
-        // For now, returning None to satisfy the type checker
-        // In practice, we'd need to restructure to pass Connection
-        Ok(None)
+    pub fn config(&self) -> &CacheConfig {
+        &self.config
     }
 
-    /// Get a cached response with explicit connection
-    pub fn get_with_conn(
+    /// Get the cached response to a request sent to `model` on `provider`
+    pub fn get(
         &self,
         conn: &Connection,
+        provider: &str,
+        model: &str,
         request: &LLMRequest,
     ) -> Result<Option<LLMResponse>, LLMError> {
-        let cache_key = request.cache_key();
+        let cache_key = cache_key(provider, model, request);
+        let now = Utc::now().timestamp_millis();
 
         log::debug!("[LLM::Cache] Looking up cache key: {}", cache_key);
 
-        let result = conn.query_row(
-            "SELECT response_json, model, tokens_used FROM llm_cache WHERE cache_key = ?1",
-            [&cache_key],
-            |row| {
-                let response_json: String = row.get(0)?;
-                let model: String = row.get(1)?;
-                let tokens: usize = row.get::<_, i64>(2)? as usize;
+        let entry: Option<(String, i64)> = conn
+            .query_row(
+                "SELECT response_json, expires_at FROM llm_cache WHERE cache_key = ?1",
+                [&cache_key],
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            )
+            .optional()?;
 
-                // Parse the cached response
-                let mut response: LLMResponse = serde_json::from_str(&response_json)
-                    .map_err(|e| rusqlite::Error::ToSqlConversionFailure(Box::new(e)))?;
+        let Some((response_json, expires_at)) = entry else {
+            log::debug!("[LLM::Cache] Cache miss for key: {}", cache_key);
+            return Ok(None);
+        };
+        if expires_at <= now {
+            log::debug!("[LLM::Cache] Cache entry expired: {}", cache_key);
+            conn.execute("DELETE FROM llm_cache WHERE cache_key = ?1", [&cache_key])?;
+            return Ok(None);
+        }
 
-                response.cached = true;
-                response.model = model;
-                response.tokens_used = tokens;
+        conn.execute(
+            "UPDATE llm_cache
+             SET last_accessed = ?1, access_count = access_count + 1, access_seq = ?2
+             WHERE cache_key = ?3",
+            params![now, next_access_seq(conn)?, &cache_key],
+        )?;
 
-                Ok(response)
-            },
-        );
+        let mut response: LLMResponse = serde_json::from_str(&response_json)?;
+        response.cached = true;
+        response.provider = Some(provider.to_string());
 
-        match result {
-            Ok(response) => {
-                // Update access stats
-                let now = Utc::now().timestamp_millis();
-                conn.execute(
-                    "UPDATE llm_cache
-                     SET last_accessed = ?1, access_count = access_count + 1
-                     WHERE cache_key = ?2",
-                    params![now, &cache_key],
-                )?;
-
-                log::info!("[LLM::Cache] Cache hit for key: {}", cache_key);
-                Ok(Some(response))
-            }
-            Err(rusqlite::Error::QueryReturnedNoRows) => {
-                log::debug!("[LLM::Cache] Cache miss for key: {}", cache_key);
-                Ok(None)
-            }
-            Err(e) => {
-                log::error!("[LLM::Cache] Error querying cache: {}", e);
-                Err(e.into())
-            }
-        }
+        log::info!("[LLM::Cache] Cache hit for key: {}", cache_key);
+        Ok(Some(response))
     }
 
-    /// Set a cache entry
-    pub fn set(&self, _request: &LLMRequest, _response: &LLMResponse) -> Result<(), LLMError> {
-        Ok(())
-    }
-
-    /// Set a cache entry with explicit connection
-    pub fn set_with_conn(
+    /// Store the response `provider` gave to a request sent to `model`,
+    /// then evict the least recently used entries above the size cap
+    pub fn put(
         &self,
         conn: &Connection,
+        provider: &str,
+        model: &str,
         request: &LLMRequest,
         response: &LLMResponse,
+        context: &CacheContext,
     ) -> Result<(), LLMError> {
-        let cache_key = request.cache_key();
+        let cache_key = cache_key(provider, model, request);
         let now = Utc::now().timestamp_millis();
+        let ttl = self.config.ttl_for(&context.request_type);
+        let expires_at = now.saturating_add(ttl.as_millis().min(i64::MAX as u128) as i64);
 
         let request_json = serde_json::to_string(request)?;
         let response_json = serde_json::to_string(response)?;
+        let size_bytes = (request_json.len() + response_json.len()) as u64;
+        if size_bytes > self.config.max_bytes {
+            log::debug!(
+                "[LLM::Cache] Not caching {}: {} bytes is over the cap",
+                cache_key,
+                size_bytes
+            );
+            return Ok(());
+        }
+
+        let input_tokens = SimpleTokenCounter::new().count_request(request).total;
+        let cost_usd = get_model_pricing(model).calculate(input_tokens, response.tokens_used);
 
         log::debug!("[LLM::Cache] Storing cache entry: {}", cache_key);
 
-        conn.execute(
-            "INSERT OR REPLACE INTO llm_cache (
+        let tx = conn.unchecked_transaction()?;
+        tx.execute(
+            "INSERT INTO llm_cache (
                 cache_key, request_json, response_json, model, tokens_used,
-                created_at, last_accessed, access_count
-            ) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, 1)",
+                created_at, last_accessed, access_count, provider, request_type,
+                input_tokens, cost_usd, size_bytes, expires_at, access_seq
+            ) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?6, 0, ?7, ?8, ?9, ?10, ?11, ?12, ?13)
+            ON CONFLICT(cache_key) DO UPDATE SET
+                request_json = excluded.request_json,
+                response_json = excluded.response_json,
+                model = excluded.model,
+                tokens_used = excluded.tokens_used,
+                created_at = excluded.created_at,
+                last_accessed = excluded.last_accessed,
+                access_count = 0,
+                request_type = excluded.request_type,
+                input_tokens = excluded.input_tokens,
+                cost_usd = excluded.cost_usd,
+                size_bytes = excluded.size_bytes,
+                expires_at = excluded.expires_at,
+                access_seq = excluded.access_seq",
             params![
                 &cache_key,
                 &request_json,
                 &response_json,
-                &response.model,
+                model,
                 response.tokens_used as i64,
                 now,
-                now,
+                provider,
+                &context.request_type,
+                input_tokens as i64,
+                cost_usd,
+                size_bytes as i64,
+                expires_at,
+                next_access_seq(&tx)?,
             ],
         )?;
+        tx.execute(
+            "DELETE FROM llm_cache_namespace WHERE cache_key = ?1",
+            [&cache_key],
+        )?;
+        for namespace in &context.namespaces {
+            tx.execute(
+                "INSERT INTO llm_cache_namespace (namespace, cache_key) VALUES (?1, ?2)",
+                params![namespace, &cache_key],
+            )?;
+        }
+        let evicted = self.evict(&tx)?;
+        tx.commit()?;
 
-        log::info!("[LLM::Cache] Cached response for key: {}", cache_key);
-
+        log::info!(
+            "[LLM::Cache] Cached response for key: {} ({} evicted)",
+            cache_key,
+            evicted
+        );
         Ok(())
     }
 
-    /// Clear all cached responses
-    pub fn clear(&self) -> Result<(), LLMError> {
-        Ok(())
+    /// Drop least recently used entries until the cache fits its size cap
+    fn evict(&self, conn: &Connection) -> Result<usize, LLMError> {
+        let mut total: i64 = conn.query_row(
+            "SELECT COALESCE(SUM(size_bytes), 0) FROM llm_cache",
+            [],
+            |row| row.get(0),
+        )?;
+        let max_bytes = self.config.max_bytes.min(i64::MAX as u64) as i64;
+        if total <= max_bytes {
+            return Ok(0);
+        }
+
+        let mut victims = Vec::new();
+        {
+            let mut stmt =
+                conn.prepare("SELECT cache_key, size_bytes FROM llm_cache ORDER BY access_seq")?;
+            let mut rows = stmt.query([])?;
+            while total > max_bytes {
+                let Some(row) = rows.next()? else {
+                    break;
+                };
+                victims.push(row.get::<_, String>(0)?);
+                total -= row.get::<_, i64>(1)?;
+            }
+        }
+        for key in &victims {
+            conn.execute("DELETE FROM llm_cache WHERE cache_key = ?1", [key])?;
+        }
+        Ok(victims.len())
     }
 
-    /// Clear cache with explicit connection
-    pub fn clear_with_conn(&self, conn: &Connection) -> Result<(), LLMError> {
+    /// Clear all cached responses
+    pub fn clear(&self, conn: &Connection) -> Result<(), LLMError> {
         log::warn!("[LLM::Cache] Clearing entire cache");
 
         conn.execute("DELETE FROM llm_cache", [])?;
@@ -152,45 +360,31 @@ impl ResponseCache {
     }
 
     /// Get cache statistics
-    pub fn stats(&self) -> Result<CacheStats, LLMError> {
-        Ok(CacheStats {
-            total_entries: 0,
-            total_hits: 0,
-            hit_rate: 0.0,
-            size_bytes: 0,
-        })
-    }
-
-    /// Get cache statistics with explicit connection
-    pub fn stats_with_conn(&self, conn: &Connection) -> Result<CacheStats, LLMError> {
+    pub fn stats(&self, conn: &Connection) -> Result<CacheStats, LLMError> {
         log::debug!("[LLM::Cache] Computing cache statistics");
 
-        let total_entries: usize = conn
-            .query_row("SELECT COUNT(*) FROM llm_cache", [], |row| {
-                Ok(row.get::<_, i64>(0)? as usize)
-            })
-            .unwrap_or(0);
-
-        let total_hits: usize = conn
-            .query_row("SELECT SUM(access_count) FROM llm_cache", [], |row| {
-                Ok(row.get::<_, i64>(0)? as usize)
-            })
-            .unwrap_or(0);
+        let (total_entries, total_hits, size_bytes, saved_usd) = conn.query_row(
+            "SELECT COUNT(*), COALESCE(SUM(access_count), 0), COALESCE(SUM(size_bytes), 0),
+                    COALESCE(SUM(access_count * cost_usd), 0)
+             FROM llm_cache",
+            [],
+            |row| {
+                Ok((
+                    row.get::<_, i64>(0)? as usize,
+                    row.get::<_, i64>(1)? as usize,
+                    row.get::<_, i64>(2)? as usize,
+                    row.get::<_, f64>(3)?,
+                ))
+            },
+        )?;
 
-        let hit_rate = if total_entries > 0 {
-            (total_hits as f32) / (total_entries as f32)
+        let lookups = total_entries + total_hits;
+        let hit_rate = if lookups > 0 {
+            (total_hits as f32) / (lookups as f32)
         } else {
             0.0
         };
 
-        let size_bytes: usize = conn
-            .query_row(
-                "SELECT SUM(LENGTH(request_json) + LENGTH(response_json)) FROM llm_cache",
-                [],
-                |row| Ok(row.get::<_, i64>(0)? as usize),
-            )
-            .unwrap_or(0);
-
         log::info!(
             "[LLM::Cache] Stats - entries: {}, hits: {}, hit_rate: {:.2}%, size: {} bytes",
             total_entries,
@@ -204,6 +398,7 @@ impl ResponseCache {
             total_hits,
             hit_rate,
             size_bytes,
+            saved_usd,
         })
     }
 
@@ -227,3 +422,86 @@ impl ResponseCache {
         Ok(deleted)
     }
 }
+
+fn next_access_seq(conn: &Connection) -> Result<i64, rusqlite::Error> {
+    conn.query_row(
+        "SELECT COALESCE(MAX(access_seq), 0) + 1 FROM llm_cache",
+        [],
+        |row| row.get(0),
+    )
+}
+
+/// Drop every entry tagged with `namespace`
+pub fn invalidate_namespace(conn: &Connection, namespace: &str) -> Result<usize, rusqlite::Error> {
+    let deleted = conn.execute(
+        "DELETE FROM llm_cache WHERE cache_key IN (
+            SELECT cache_key FROM llm_cache_namespace WHERE namespace = ?1
+        )",
+        [namespace],
+    )?;
+    if deleted > 0 {
+        log::debug!(
+            "[LLM::Cache] Invalidated {} entries of {}",
+            deleted,
+            namespace
+        );
+    }
+    Ok(deleted)
+}
+
+/// [`invalidate_namespace`] for several namespaces in one statement
+pub fn invalidate_namespaces(
+    conn: &Connection,
+    namespaces: &[String],
+) -> Result<usize, rusqlite::Error> {
+    if namespaces.is_empty() {
+        return Ok(0);
+    }
+    let deleted = conn.execute(
+        &format!(
+            "DELETE FROM llm_cache WHERE cache_key IN (
+                SELECT cache_key FROM llm_cache_namespace WHERE namespace IN ({})
+            )",
+            vec!["?"; namespaces.len()].join(", ")
+        ),
+        rusqlite::params_from_iter(namespaces),
+    )?;
+    if deleted > 0 {
+        log::debug!(
+            "[LLM::Cache] Invalidated {} entries of {} namespaces",
+            deleted,
+            namespaces.len()
+        );
+    }
+    Ok(deleted)
+}
+
+/// Remove entries past their TTL
+pub fn prune_expired(conn: &Connection) -> Result<usize, rusqlite::Error> {
+    conn.execute(
+        "DELETE FROM llm_cache WHERE expires_at <= ?1",
+        [Utc::now().timestamp_millis()],
+    )
+}
+
+struct LlmCachePruneTask;
+
+impl MaintenanceTask for LlmCachePruneTask {
+    fn name(&self) -> &str {
+        "llm_cache_prune"
+    }
+
+    fn estimated_cost(&self) -> Duration {
+        Duration::from_millis(50)
+    }
+
+    fn run(&self, conn: &Connection) -> Result<TaskReport, MaintenanceError> {
+        Ok(TaskReport {
+            items_affected: prune_expired(conn)? as u64,
+        })
+    }
+}
+
+pub(crate) fn register_maintenance(registry: &mut MaintenanceRegistry) {
+    registry.register(PRIORITY_LOW, LlmCachePruneTask);
+}
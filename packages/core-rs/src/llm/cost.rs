@@ -5,6 +5,7 @@
 //! - Session and lifetime tracking
 //! - Budget alerts and limits
 //! - Cost comparison between providers
+//! - Savings from responses served by the cache, which cost nothing
 
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
@@ -87,6 +88,12 @@ pub struct CostRecord {
     pub cost_usd: f64,
     /// Request ID (for correlation)
     pub request_id: Option<String>,
+    /// Served from the response cache
+    #[serde(default)]
+    pub cached: bool,
+    /// What a cached response would have cost (USD)
+    #[serde(default)]
+    pub saved_usd: f64,
 }
 
 /// Aggregate cost statistics
@@ -104,6 +111,12 @@ pub struct CostStats {
     pub by_provider: HashMap<String, f64>,
     /// Cost by model
     pub by_model: HashMap<String, f64>,
+    /// Requests served from the response cache
+    #[serde(default)]
+    pub cache_hits: u64,
+    /// What the cache hits would have cost (USD)
+    #[serde(default)]
+    pub saved_usd: f64,
 }
 
 impl CostStats {
@@ -117,6 +130,10 @@ impl CostStats {
         self.total_input_tokens += record.input_tokens as u64;
         self.total_output_tokens += record.output_tokens as u64;
         self.total_cost_usd += record.cost_usd;
+        if record.cached {
+            self.cache_hits += 1;
+            self.saved_usd += record.saved_usd;
+        }
 
         *self
             .by_provider
@@ -247,19 +264,45 @@ impl CostTracker {
         let pricing = get_model_pricing(model);
         let cost = pricing.calculate(input_tokens, output_tokens);
 
-        let record = CostRecord {
-            timestamp: std::time::SystemTime::now()
-                .duration_since(std::time::UNIX_EPOCH)
-                .unwrap()
-                .as_millis() as u64,
+        self.push(CostRecord {
+            timestamp: now_millis(),
             model: model.to_string(),
             provider: provider.to_string(),
             input_tokens,
             output_tokens,
             cost_usd: cost,
             request_id,
-        };
+            cached: false,
+            saved_usd: 0.0,
+        })
+    }
+
+    /// Record a request served from the response cache: it costs nothing and
+    /// saves what the provider would have charged
+    pub fn record_cache_hit(
+        &mut self,
+        model: &str,
+        provider: &str,
+        input_tokens: usize,
+        output_tokens: usize,
+        request_id: Option<String>,
+    ) -> CostRecord {
+        let pricing = get_model_pricing(model);
 
+        self.push(CostRecord {
+            timestamp: now_millis(),
+            model: model.to_string(),
+            provider: provider.to_string(),
+            input_tokens,
+            output_tokens,
+            cost_usd: 0.0,
+            request_id,
+            cached: true,
+            saved_usd: pricing.calculate(input_tokens, output_tokens),
+        })
+    }
+
+    fn push(&mut self, record: CostRecord) -> CostRecord {
         self.session_stats.add(&record);
         self.lifetime_stats.add(&record);
         self.records.push(record.clone());
@@ -404,6 +447,13 @@ impl CostTracker {
     }
 }
 
+fn now_millis() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap()
+        .as_millis() as u64
+}
+
 impl Default for CostTracker {
     fn default() -> Self {
         Self::new()
@@ -441,6 +491,22 @@ mod tests {
         assert!(tracker.session_stats().total_cost_usd > 0.0);
     }
 
+    #[test]
+    fn test_cache_hits_are_free() {
+        let mut tracker = CostTracker::new();
+
+        let paid = tracker.record("gpt-4", "openai", 1000, 500, None);
+        let hit = tracker.record_cache_hit("gpt-4", "openai", 1000, 500, None);
+
+        assert_eq!(hit.cost_usd, 0.0);
+        assert_eq!(hit.saved_usd, paid.cost_usd);
+        let stats = tracker.session_stats();
+        assert_eq!(stats.total_requests, 2);
+        assert_eq!(stats.cache_hits, 1);
+        assert_eq!(stats.total_cost_usd, paid.cost_usd);
+        assert_eq!(stats.saved_usd, paid.cost_usd);
+    }
+
     #[test]
     fn test_budget_checking() {
         let budget = BudgetConfig {
@@ -484,6 +550,8 @@ mod tests {
             output_tokens: 50,
             cost_usd: 0.01,
             request_id: None,
+            cached: false,
+            saved_usd: 0.0,
         });
 
         assert_eq!(stats.total_requests, 1);
@@ -86,6 +86,10 @@ pub enum LLMError {
     /// Streaming error
     #[error("Streaming error: {0}")]
     StreamingError(String),
+
+    /// No configured provider could serve the request
+    #[error("No provider available: {0}")]
+    NoProviderAvailable(String),
 }
 
 impl LLMError {
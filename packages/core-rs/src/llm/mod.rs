@@ -7,6 +7,7 @@ pub mod pii;
 pub mod priority;
 pub mod providers;
 pub mod retry;
+pub mod router;
 pub mod streaming;
 pub mod tokenizer;
 pub mod types;
@@ -14,6 +15,7 @@ pub mod validation;
 
 pub use config::LLMConfig as LlmConfig;
 pub use error::LLMError as LlmError;
-pub use pii::redact_pii;
+pub use pii::{contains_pii, redact_pii};
 pub use providers::LLMProvider;
+pub use router::{LlmRouter, RequestConstraints, RoutingPolicy};
 pub use types::{LLMRequest, Message, Role};
@@ -22,3 +22,8 @@ pub fn redact_pii(text: &str) -> String {
 
     text.to_string()
 }
+
+/// Returns true if `redact_pii` would change the text.
+pub fn contains_pii(text: &str) -> bool {
+    redact_pii(text) != text
+}
@@ -153,6 +153,7 @@ impl LLMProvider for ClaudeProvider {
             tokens_used: tokens,
             finish_reason: Some(claude_response.stop_reason.unwrap_or_default()),
             cached: false,
+            provider: Some(self.name().to_string()),
         })
     }
 
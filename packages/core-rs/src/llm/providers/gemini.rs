@@ -167,6 +167,7 @@ impl LLMProvider for GeminiProvider {
             tokens_used: tokens,
             finish_reason: candidate.finish_reason.clone(),
             cached: false,
+            provider: Some(self.name().to_string()),
         })
     }
 
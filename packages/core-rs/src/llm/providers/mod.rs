@@ -12,6 +12,7 @@ pub use gemini::GeminiProvider;
 pub use ollama::OllamaProvider;
 pub use openai::OpenAIProvider;
 
+use super::streaming::{send_event, StreamEvent, StreamSink};
 use super::{types::*, LlmError as LLMError};
 use async_trait::async_trait;
 use serde::{Deserialize, Serialize};
@@ -39,6 +40,11 @@ impl ProviderType {
         )
     }
 
+    /// Check if this provider runs on the local machine
+    pub fn is_local(&self) -> bool {
+        matches!(self, ProviderType::Ollama)
+    }
+
     /// Get the default model for this provider
     pub fn default_model(&self) -> &'static str {
         match self {
@@ -56,6 +62,48 @@ pub trait LLMProvider: Send + Sync {
     /// Generate a completion for the given request
     async fn complete(&self, request: &LLMRequest) -> Result<LLMResponse, LLMError>;
 
+    /// Generate a completion, forwarding tokens to `sink` as they arrive.
+    ///
+    /// Returns the assembled response once the stream finishes. Dropping the
+    /// sink's receiver cancels the request. The default implementation
+    /// buffers `complete()` and emits the whole answer as one token.
+    async fn complete_streaming(
+        &self,
+        request: &LLMRequest,
+        sink: &StreamSink,
+    ) -> Result<LLMResponse, LLMError> {
+        let response = match self.complete(request).await {
+            Ok(response) => response,
+            Err(e) => {
+                report_stream_error(sink, &e).await;
+                return Err(e);
+            }
+        };
+        send_event(
+            sink,
+            StreamEvent::Token {
+                content: response.content.clone(),
+            },
+        )
+        .await?;
+        send_event(
+            sink,
+            StreamEvent::Usage {
+                prompt_tokens: None,
+                completion_tokens: response.tokens_used,
+            },
+        )
+        .await?;
+        send_event(
+            sink,
+            StreamEvent::Done {
+                finish_reason: response.finish_reason.clone(),
+            },
+        )
+        .await?;
+        Ok(response)
+    }
+
     /// List available models for this provider
     async fn list_models(&self) -> Result<Vec<String>, LLMError>;
 
@@ -67,3 +115,14 @@ pub trait LLMProvider: Send + Sync {
         Ok(true)
     }
 }
+
+/// Emit a terminal error event unless the stream was cancelled by the consumer
+pub(crate) async fn report_stream_error(sink: &StreamSink, error: &LLMError) {
+    if !matches!(error, LLMError::Cancelled) {
+        let _ = sink
+            .send(StreamEvent::Error {
+                message: error.to_string(),
+            })
+            .await;
+    }
+}
@@ -4,6 +4,7 @@
 // This provider communicates with a local Ollama instance via its REST API.
 
 use super::*;
+use crate::llm::streaming::LineReader;
 use serde::{Deserialize, Serialize};
 
 /// Ollama provider for local LLM inference
@@ -29,15 +30,9 @@ impl OllamaProvider {
     }
 }
 
-#[async_trait]
-impl LLMProvider for OllamaProvider {
-    async fn complete(&self, request: &LLMRequest) -> Result<LLMResponse, LLMError> {
-        log::debug!(
-            "[LLM::Ollama] Generating completion - model: {:?}, messages: {}",
-            request.model,
-            request.messages.len()
-        );
-
+impl OllamaProvider {
+    /// Convert a request into the Ollama chat format
+    fn build_request(&self, request: &LLMRequest, stream: bool) -> Result<OllamaRequest, LLMError> {
         let model = request
             .model
             .as_ref()
@@ -53,18 +48,24 @@ impl LLMProvider for OllamaProvider {
             })
             .collect();
 
-        let ollama_request = OllamaRequest {
+        Ok(OllamaRequest {
             model: model.clone(),
             messages,
-            stream: false,
+            stream,
             options: Some(OllamaOptions {
                 temperature: request.temperature,
                 num_predict: request.max_tokens.map(|t| t as i32),
                 top_p: request.top_p,
                 stop: request.stop_sequences.clone(),
             }),
-        };
+        })
+    }
 
+    /// POST a chat request and check the response status
+    async fn send_chat(
+        &self,
+        ollama_request: &OllamaRequest,
+    ) -> Result<reqwest::Response, LLMError> {
         let url = format!("{}/api/chat", self.base_url);
 
         log::debug!("[LLM::Ollama] Sending request to {}", url);
@@ -72,7 +73,7 @@ impl LLMProvider for OllamaProvider {
         let response = self
             .client
             .post(&url)
-            .json(&ollama_request)
+            .json(ollama_request)
             .send()
             .await
             .map_err(|e| {
@@ -97,6 +98,83 @@ impl LLMProvider for OllamaProvider {
             )));
         }
 
+        Ok(response)
+    }
+
+    /// Read the NDJSON stream, forwarding each message chunk as a token
+    async fn read_stream(
+        &self,
+        response: reqwest::Response,
+        model: String,
+        sink: &StreamSink,
+    ) -> Result<LLMResponse, LLMError> {
+        let mut lines = LineReader::new(response);
+        let mut content = String::new();
+
+        while let Some(line) = lines.next_line(sink).await? {
+            let chunk: OllamaResponse = serde_json::from_str(&line).map_err(|e| {
+                LLMError::InvalidResponse(format!("Failed to parse Ollama stream chunk: {}", e))
+            })?;
+
+            if let Some(token) = chunk.message.content.filter(|t| !t.is_empty()) {
+                content.push_str(&token);
+                send_event(sink, StreamEvent::Token { content: token }).await?;
+            }
+
+            if chunk.done {
+                let tokens = chunk.eval_count.unwrap_or(0) as usize;
+                send_event(
+                    sink,
+                    StreamEvent::Usage {
+                        prompt_tokens: chunk.prompt_eval_count.map(|t| t as usize),
+                        completion_tokens: tokens,
+                    },
+                )
+                .await?;
+                send_event(
+                    sink,
+                    StreamEvent::Done {
+                        finish_reason: chunk.done_reason.clone(),
+                    },
+                )
+                .await?;
+
+                log::info!(
+                    "[LLM::Ollama] Streaming completion finished - tokens: {}, model: {}",
+                    tokens,
+                    model
+                );
+
+                return Ok(LLMResponse {
+                    content,
+                    model,
+                    tokens_used: tokens,
+                    finish_reason: chunk.done_reason,
+                    cached: false,
+                    provider: Some(self.name().to_string()),
+                });
+            }
+        }
+
+        Err(LLMError::StreamingError(
+            "Ollama stream ended before completion".to_string(),
+        ))
+    }
+}
+
+#[async_trait]
+impl LLMProvider for OllamaProvider {
+    async fn complete(&self, request: &LLMRequest) -> Result<LLMResponse, LLMError> {
+        log::debug!(
+            "[LLM::Ollama] Generating completion - model: {:?}, messages: {}",
+            request.model,
+            request.messages.len()
+        );
+
+        let ollama_request = self.build_request(request, false)?;
+        let model = &ollama_request.model;
+        let response = self.send_chat(&ollama_request).await?;
+
         let ollama_response: OllamaResponse = response.json().await.map_err(|e| {
             log::error!("[LLM::Ollama] Failed to parse response: {}", e);
             LLMError::InvalidResponse(format!("Failed to parse Ollama response: {}", e))
@@ -121,9 +199,34 @@ impl LLMProvider for OllamaProvider {
             tokens_used: tokens,
             finish_reason: ollama_response.done_reason,
             cached: false,
+            provider: Some(self.name().to_string()),
         })
     }
 
+    async fn complete_streaming(
+        &self,
+        request: &LLMRequest,
+        sink: &StreamSink,
+    ) -> Result<LLMResponse, LLMError> {
+        log::debug!(
+            "[LLM::Ollama] Streaming completion - model: {:?}, messages: {}",
+            request.model,
+            request.messages.len()
+        );
+
+        let result = async {
+            let ollama_request = self.build_request(request, true)?;
+            let response = self.send_chat(&ollama_request).await?;
+            self.read_stream(response, ollama_request.model, sink).await
+        }
+        .await;
+
+        if let Err(e) = &result {
+            report_stream_error(sink, e).await;
+        }
+        result
+    }
+
     async fn list_models(&self) -> Result<Vec<String>, LLMError> {
         log::debug!("[LLM::Ollama] Fetching available models");
 
@@ -208,6 +311,10 @@ struct OllamaResponse {
     done_reason: Option<String>,
     #[serde(default)]
     eval_count: Option<i32>,
+    #[serde(default)]
+    prompt_eval_count: Option<i32>,
+    #[serde(default)]
+    done: bool,
 }
 
 #[derive(Debug, Deserialize)]
@@ -3,6 +3,7 @@
 // Supports GPT-4, GPT-3.5-turbo, and other OpenAI models via the official API.
 
 use super::*;
+use crate::llm::streaming::LineReader;
 use serde::{Deserialize, Serialize};
 
 /// Convert internal Role enum to OpenAI-compatible role string
@@ -14,9 +15,13 @@ fn role_to_openai_string(role: &Role) -> &'static str {
     }
 }
 
+/// Default OpenAI API endpoint
+const OPENAI_API_BASE: &str = "https://api.openai.com/v1";
+
 /// OpenAI provider for cloud-based LLM inference
 pub struct OpenAIProvider {
     api_key: String,
+    base_url: String,
     client: reqwest::Client,
 }
 
@@ -30,19 +35,21 @@ impl OpenAIProvider {
             .build()
             .map_err(|e| LLMError::NetworkError(format!("Failed to create HTTP client: {}", e)))?;
 
-        Ok(Self { api_key, client })
+        Ok(Self {
+            api_key,
+            base_url: OPENAI_API_BASE.to_string(),
+            client,
+        })
     }
-}
 
-#[async_trait]
-impl LLMProvider for OpenAIProvider {
-    async fn complete(&self, request: &LLMRequest) -> Result<LLMResponse, LLMError> {
-        log::debug!(
-            "[LLM::OpenAI] Generating completion - model: {:?}, messages: {}",
-            request.model,
-            request.messages.len()
-        );
+    /// Use a different OpenAI-compatible endpoint (e.g. a proxy)
+    pub fn with_base_url(mut self, base_url: impl Into<String>) -> Self {
+        self.base_url = base_url.into().trim_end_matches('/').to_string();
+        self
+    }
 
+    /// Convert a request into the OpenAI chat format
+    fn build_request(&self, request: &LLMRequest, stream: bool) -> OpenAIRequest {
         let model = request
             .model
             .as_ref()
@@ -59,25 +66,35 @@ impl LLMProvider for OpenAIProvider {
             })
             .collect();
 
-        let openai_request = OpenAIRequest {
-            model: model.clone(),
+        OpenAIRequest {
+            model,
             messages,
             temperature: request.temperature,
             max_tokens: request.max_tokens,
             top_p: request.top_p,
             stop: request.stop_sequences.clone(),
-        };
+            stream: stream.then_some(true),
+            stream_options: stream.then_some(OpenAIStreamOptions {
+                include_usage: true,
+            }),
+        }
+    }
 
-        let url = "https://api.openai.com/v1/chat/completions";
+    /// POST a chat completion request and check the response status
+    async fn send_chat(
+        &self,
+        openai_request: &OpenAIRequest,
+    ) -> Result<reqwest::Response, LLMError> {
+        let url = format!("{}/chat/completions", self.base_url);
 
         log::debug!("[LLM::OpenAI] Sending request to OpenAI API");
 
         let response = self
             .client
-            .post(url)
+            .post(&url)
             .header("Authorization", format!("Bearer {}", self.api_key))
             .header("Content-Type", "application/json")
-            .json(&openai_request)
+            .json(openai_request)
             .send()
             .await
             .map_err(|e| {
@@ -109,6 +126,99 @@ impl LLMProvider for OpenAIProvider {
             )));
         }
 
+        Ok(response)
+    }
+
+    /// Read the SSE stream, forwarding each content delta as a token
+    async fn read_stream(
+        &self,
+        response: reqwest::Response,
+        model: String,
+        sink: &StreamSink,
+    ) -> Result<LLMResponse, LLMError> {
+        let mut lines = LineReader::new(response);
+        let mut content = String::new();
+        let mut finish_reason = None;
+        let mut tokens = 0;
+
+        while let Some(line) = lines.next_line(sink).await? {
+            let Some(data) = line.strip_prefix("data:") else {
+                // Comments, event names and retry hints carry no content
+                continue;
+            };
+            let data = data.trim();
+
+            if data == "[DONE]" {
+                send_event(
+                    sink,
+                    StreamEvent::Done {
+                        finish_reason: finish_reason.clone(),
+                    },
+                )
+                .await?;
+
+                log::info!(
+                    "[LLM::OpenAI] Streaming completion finished - tokens: {}, model: {}",
+                    tokens,
+                    model
+                );
+
+                return Ok(LLMResponse {
+                    content,
+                    model,
+                    tokens_used: tokens,
+                    finish_reason,
+                    cached: false,
+                    provider: Some(self.name().to_string()),
+                });
+            }
+
+            let chunk: OpenAIStreamChunk = serde_json::from_str(data).map_err(|e| {
+                LLMError::InvalidResponse(format!("Failed to parse OpenAI stream chunk: {}", e))
+            })?;
+
+            for choice in chunk.choices {
+                if let Some(token) = choice.delta.content.filter(|t| !t.is_empty()) {
+                    content.push_str(&token);
+                    send_event(sink, StreamEvent::Token { content: token }).await?;
+                }
+                if choice.finish_reason.is_some() {
+                    finish_reason = choice.finish_reason;
+                }
+            }
+
+            if let Some(usage) = chunk.usage {
+                tokens = usage.total_tokens;
+                send_event(
+                    sink,
+                    StreamEvent::Usage {
+                        prompt_tokens: usage.prompt_tokens,
+                        completion_tokens: usage.completion_tokens.unwrap_or(0),
+                    },
+                )
+                .await?;
+            }
+        }
+
+        Err(LLMError::StreamingError(
+            "OpenAI stream ended before [DONE]".to_string(),
+        ))
+    }
+}
+
+#[async_trait]
+impl LLMProvider for OpenAIProvider {
+    async fn complete(&self, request: &LLMRequest) -> Result<LLMResponse, LLMError> {
+        log::debug!(
+            "[LLM::OpenAI] Generating completion - model: {:?}, messages: {}",
+            request.model,
+            request.messages.len()
+        );
+
+        let openai_request = self.build_request(request, false);
+        let model = openai_request.model.clone();
+        let response = self.send_chat(&openai_request).await?;
+
         let openai_response: OpenAIResponse = response.json().await.map_err(|e| {
             log::error!("[LLM::OpenAI] Failed to parse response: {}", e);
             LLMError::InvalidResponse(format!("Failed to parse OpenAI response: {}", e))
@@ -134,17 +244,42 @@ impl LLMProvider for OpenAIProvider {
             tokens_used: tokens,
             finish_reason: choice.finish_reason.clone(),
             cached: false,
+            provider: Some(self.name().to_string()),
         })
     }
 
+    async fn complete_streaming(
+        &self,
+        request: &LLMRequest,
+        sink: &StreamSink,
+    ) -> Result<LLMResponse, LLMError> {
+        log::debug!(
+            "[LLM::OpenAI] Streaming completion - model: {:?}, messages: {}",
+            request.model,
+            request.messages.len()
+        );
+
+        let result = async {
+            let openai_request = self.build_request(request, true);
+            let response = self.send_chat(&openai_request).await?;
+            self.read_stream(response, openai_request.model, sink).await
+        }
+        .await;
+
+        if let Err(e) = &result {
+            report_stream_error(sink, e).await;
+        }
+        result
+    }
+
     async fn list_models(&self) -> Result<Vec<String>, LLMError> {
         log::debug!("[LLM::OpenAI] Fetching available models");
 
-        let url = "https://api.openai.com/v1/models";
+        let url = format!("{}/models", self.base_url);
 
         let response = self
             .client
-            .get(url)
+            .get(&url)
             .header("Authorization", format!("Bearer {}", self.api_key))
             .send()
             .await
@@ -175,11 +310,11 @@ impl LLMProvider for OpenAIProvider {
     }
 
     async fn health_check(&self) -> Result<bool, LLMError> {
-        let url = "https://api.openai.com/v1/models";
+        let url = format!("{}/models", self.base_url);
 
         match self
             .client
-            .get(url)
+            .get(&url)
             .header("Authorization", format!("Bearer {}", self.api_key))
             .send()
             .await
@@ -213,6 +348,15 @@ struct OpenAIRequest {
     top_p: Option<f32>,
     #[serde(skip_serializing_if = "Option::is_none")]
     stop: Option<Vec<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    stream: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    stream_options: Option<OpenAIStreamOptions>,
+}
+
+#[derive(Debug, Serialize)]
+struct OpenAIStreamOptions {
+    include_usage: bool,
 }
 
 #[derive(Debug, Serialize)]
@@ -243,6 +387,33 @@ struct OpenAIUsage {
     total_tokens: usize,
 }
 
+#[derive(Debug, Deserialize)]
+struct OpenAIStreamChunk {
+    #[serde(default)]
+    choices: Vec<OpenAIStreamChoice>,
+    #[serde(default)]
+    usage: Option<OpenAIStreamUsage>,
+}
+
+#[derive(Debug, Deserialize)]
+struct OpenAIStreamChoice {
+    delta: OpenAIDelta,
+    finish_reason: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct OpenAIDelta {
+    #[serde(default)]
+    content: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct OpenAIStreamUsage {
+    prompt_tokens: Option<usize>,
+    completion_tokens: Option<usize>,
+    total_tokens: usize,
+}
+
 #[derive(Debug, Deserialize)]
 struct OpenAIModelsResponse {
     data: Vec<OpenAIModel>,
@@ -0,0 +1,653 @@
+//! Provider Routing and Failover
+//!
+//! Wraps an ordered list of configured providers with a routing policy:
+//! - Prefer-local-first (Ollama when its health check passes)
+//! - Failover to the next provider on errors and timeouts
+//! - Per-request cost caps and local-only enforcement for PII
+//! - Per-provider circuit breakers and retries via the retry module
+//! - Optional response caching, with cache hits counted as free
+
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::Mutex;
+
+use super::cache::{CacheContext, ResponseCache};
+use super::config::LLMConfig;
+use super::cost::{BudgetConfig, CostStats, CostTracker};
+use super::error::LLMError;
+use super::pii::contains_pii;
+use super::providers::{
+    ClaudeProvider, GeminiProvider, LLMProvider, OllamaProvider, OpenAIProvider, ProviderType,
+};
+use super::retry::{with_retry, CircuitBreaker, RetryConfig};
+use super::tokenizer::{SimpleTokenCounter, TokenCounter};
+use super::types::{LLMRequest, LLMResponse};
+
+/// Output tokens assumed for cost estimation when the request sets no max_tokens
+const DEFAULT_EXPECTED_OUTPUT_TOKENS: usize = 512;
+
+/// Routing policy applied to every request
+#[derive(Debug, Clone)]
+pub struct RoutingPolicy {
+    /// Try healthy local providers before cloud providers
+    pub prefer_local: bool,
+    /// Never send any request to a cloud provider
+    pub local_only: bool,
+    /// Restrict requests containing PII to local providers
+    pub local_only_for_pii: bool,
+    /// Timeout for a single provider attempt
+    pub request_timeout: Duration,
+    /// Retries against the same provider before failing over
+    pub retry: RetryConfig,
+}
+
+impl Default for RoutingPolicy {
+    fn default() -> Self {
+        Self {
+            prefer_local: true,
+            local_only: false,
+            local_only_for_pii: true,
+            request_timeout: Duration::from_secs(60),
+            retry: RetryConfig::aggressive(),
+        }
+    }
+}
+
+/// Per-request routing constraints
+#[derive(Debug, Clone, Default)]
+pub struct RequestConstraints {
+    /// Reject providers whose estimated cost exceeds this (USD)
+    pub max_cost_usd: Option<f64>,
+    /// Only route to local providers
+    pub local_only: bool,
+}
+
+impl RequestConstraints {
+    /// Builder method to cap the estimated cost
+    pub fn max_cost(mut self, max_cost_usd: f64) -> Self {
+        self.max_cost_usd = Some(max_cost_usd);
+        self
+    }
+
+    /// Builder method to require a local provider
+    pub fn local_only(mut self) -> Self {
+        self.local_only = true;
+        self
+    }
+}
+
+/// A provider registered with the router
+struct Route {
+    provider: Arc<dyn LLMProvider>,
+    provider_type: ProviderType,
+    model: String,
+    breaker: Mutex<CircuitBreaker>,
+}
+
+/// Routes requests across an ordered list of providers with failover
+pub struct LlmRouter {
+    routes: Vec<Route>,
+    policy: RoutingPolicy,
+    costs: Mutex<CostTracker>,
+    counter: SimpleTokenCounter,
+}
+
+impl LlmRouter {
+    pub fn new(policy: RoutingPolicy) -> Self {
+        Self::with_budget(policy, BudgetConfig::default())
+    }
+
+    /// Create a router that also enforces the given budget
+    pub fn with_budget(policy: RoutingPolicy, budget: BudgetConfig) -> Self {
+        Self {
+            routes: Vec::new(),
+            policy,
+            costs: Mutex::new(CostTracker::with_budget(budget)),
+            counter: SimpleTokenCounter::new(),
+        }
+    }
+
+    /// Build a router from the default provider and fallback chain of a config
+    pub fn from_config(config: &LLMConfig) -> Result<Self, LLMError> {
+        let policy = RoutingPolicy {
+            local_only: config.privacy_mode,
+            ..Default::default()
+        };
+        let mut router = Self::new(policy);
+
+        let mut chain = vec![config.default_provider.clone()];
+        for provider_type in &config.fallback_chain {
+            if !chain.contains(provider_type) {
+                chain.push(provider_type.clone());
+            }
+        }
+
+        for provider_type in chain {
+            let provider: Arc<dyn LLMProvider> = match provider_type {
+                ProviderType::Ollama => {
+                    Arc::new(OllamaProvider::new(config.ollama_base_url.clone())?)
+                }
+                ProviderType::OpenAI => match &config.openai_api_key {
+                    Some(key) => Arc::new(OpenAIProvider::new(key.clone())?),
+                    None => continue,
+                },
+                ProviderType::Claude => match &config.anthropic_api_key {
+                    Some(key) => Arc::new(ClaudeProvider::new(key.clone())?),
+                    None => continue,
+                },
+                ProviderType::Gemini => match &config.google_api_key {
+                    Some(key) => Arc::new(GeminiProvider::new(key.clone())?),
+                    None => continue,
+                },
+            };
+            router = router.add_provider(provider, provider_type);
+        }
+
+        if router.routes.is_empty() {
+            return Err(LLMError::ConfigError(
+                "No LLM provider could be configured".to_string(),
+            ));
+        }
+
+        Ok(router)
+    }
+
+    /// Builder method to append a provider using its default model
+    pub fn add_provider(self, provider: Arc<dyn LLMProvider>, provider_type: ProviderType) -> Self {
+        let model = provider_type.default_model().to_string();
+        self.add_provider_with_model(provider, provider_type, model)
+    }
+
+    /// Builder method to append a provider with an explicit model
+    pub fn add_provider_with_model(
+        mut self,
+        provider: Arc<dyn LLMProvider>,
+        provider_type: ProviderType,
+        model: impl Into<String>,
+    ) -> Self {
+        self.routes.push(Route {
+            provider,
+            provider_type,
+            model: model.into(),
+            breaker: Mutex::new(CircuitBreaker::default()),
+        });
+        self
+    }
+
+    /// Names of the registered providers, in configured order
+    pub fn provider_names(&self) -> Vec<String> {
+        self.routes
+            .iter()
+            .map(|r| r.provider.name().to_string())
+            .collect()
+    }
+
+    /// Cost statistics for requests served by this router
+    pub async fn cost_stats(&self) -> CostStats {
+        self.costs.lock().await.session_stats().clone()
+    }
+
+    /// Route a request without per-request constraints
+    pub async fn complete(&self, request: &LLMRequest) -> Result<LLMResponse, LLMError> {
+        self.complete_with(request, &RequestConstraints::default())
+            .await
+    }
+
+    /// Route a request, failing over until a provider serves it.
+    ///
+    /// The request's model is replaced by each route's model, so callers
+    /// should not rely on a provider-specific model name.
+    pub async fn complete_with(
+        &self,
+        request: &LLMRequest,
+        constraints: &RequestConstraints,
+    ) -> Result<LLMResponse, LLMError> {
+        let local_only = self.is_local_only(request, constraints);
+
+        let input_tokens = self.counter.count_request(request).total;
+        let expected_output = request.max_tokens.unwrap_or(DEFAULT_EXPECTED_OUTPUT_TOKENS);
+
+        let mut skipped = Vec::new();
+        let mut over_budget = 0usize;
+        let mut candidates = 0usize;
+
+        for route in self.ordered_routes() {
+            let name = route.provider.name();
+
+            if local_only && !route.provider_type.is_local() {
+                continue;
+            }
+            candidates += 1;
+
+            if route.provider_type.is_local()
+                && !matches!(route.provider.health_check().await, Ok(true))
+            {
+                log::warn!("[LLM::Router] Skipping {}: health check failed", name);
+                skipped.push(format!("{}: unhealthy", name));
+                continue;
+            }
+
+            let estimated_cost = {
+                let costs = self.costs.lock().await;
+                let estimate = costs.estimate_cost(&route.model, input_tokens, expected_output);
+                if matches!(constraints.max_cost_usd, Some(max) if estimate > max)
+                    || costs.would_exceed_budget(estimate)
+                {
+                    None
+                } else {
+                    Some(estimate)
+                }
+            };
+            if estimated_cost.is_none() {
+                log::info!("[LLM::Router] Skipping {}: over cost limit", name);
+                skipped.push(format!("{}: over cost limit", name));
+                over_budget += 1;
+                continue;
+            }
+
+            if !route.breaker.lock().await.should_allow() {
+                skipped.push(format!("{}: circuit open", name));
+                continue;
+            }
+
+            let mut routed = request.clone();
+            routed.model = Some(route.model.clone());
+
+            let provider = &route.provider;
+            let routed = &routed;
+            let timeout = self.policy.request_timeout;
+            let result = with_retry(&self.policy.retry, || async move {
+                tokio::time::timeout(timeout, provider.complete(routed))
+                    .await
+                    .map_err(|_| {
+                        LLMError::Timeout(format!("{} timed out after {:?}", name, timeout))
+                    })?
+            })
+            .await
+            .into_result();
+
+            match result {
+                Ok(mut response) => {
+                    route.breaker.lock().await.record_success();
+                    self.costs.lock().await.record(
+                        &response.model,
+                        name,
+                        input_tokens,
+                        response.tokens_used,
+                        None,
+                    );
+                    response.provider = Some(name.to_string());
+                    log::info!("[LLM::Router] Request served by {}", name);
+                    return Ok(response);
+                }
+                Err(e) => {
+                    route.breaker.lock().await.record_failure();
+                    log::warn!("[LLM::Router] {} failed, failing over: {}", name, e);
+                    skipped.push(format!("{}: {}", name, e));
+                }
+            }
+        }
+
+        if candidates > 0 && over_budget == candidates {
+            return Err(LLMError::BudgetExceeded(format!(
+                "No provider fits the cost limit ({})",
+                skipped.join("; ")
+            )));
+        }
+
+        if candidates == 0 {
+            return Err(LLMError::NoProviderAvailable(if local_only {
+                "request requires a local provider".to_string()
+            } else {
+                "no providers configured".to_string()
+            }));
+        }
+
+        Err(LLMError::NoProviderAvailable(skipped.join("; ")))
+    }
+
+    /// Route a request unless a provider it may go to already answered it.
+    ///
+    /// The cache is looked up for every eligible route in routing order, so a
+    /// response cached from a cloud provider is never served to a request
+    /// that must stay local. Cache errors are logged and don't fail the request.
+    pub async fn complete_cached<M>(
+        &self,
+        request: &LLMRequest,
+        constraints: &RequestConstraints,
+        cache: &ResponseCache,
+        pool: &r2d2::Pool<M>,
+        context: &CacheContext,
+    ) -> Result<LLMResponse, LLMError>
+    where
+        M: r2d2::ManageConnection<Connection = rusqlite::Connection>,
+    {
+        let local_only = self.is_local_only(request, constraints);
+        let routes: Vec<(&str, &str)> = self
+            .ordered_routes()
+            .into_iter()
+            .filter(|route| !local_only || route.provider_type.is_local())
+            .map(|route| (route.provider.name(), route.model.as_str()))
+            .collect();
+
+        let hit = match pool.get() {
+            Ok(conn) => routes.iter().find_map(|&(provider, model)| {
+                match cache.get(&conn, provider, model, request) {
+                    Ok(response) => response.map(|r| (provider, model, r)),
+                    Err(e) => {
+                        log::warn!("[LLM::Router] Cache lookup failed: {}", e);
+                        None
+                    }
+                }
+            }),
+            Err(e) => {
+                log::warn!("[LLM::Router] No connection for the cache: {}", e);
+                None
+            }
+        };
+        if let Some((provider, model, response)) = hit {
+            let input_tokens = self.counter.count_request(request).total;
+            self.costs.lock().await.record_cache_hit(
+                model,
+                provider,
+                input_tokens,
+                response.tokens_used,
+                None,
+            );
+            log::info!(
+                "[LLM::Router] Request served from the cache of {}",
+                provider
+            );
+            return Ok(response);
+        }
+
+        let response = self.complete_with(request, constraints).await?;
+
+        let served_by = routes
+            .iter()
+            .find(|(provider, _)| response.provider.as_deref() == Some(*provider));
+        if let Some(&(provider, model)) = served_by {
+            let stored = pool.get().map_err(|e| e.to_string()).and_then(|conn| {
+                cache
+                    .put(&conn, provider, model, request, &response, context)
+                    .map_err(|e| e.to_string())
+            });
+            if let Err(e) = stored {
+                log::warn!("[LLM::Router] Failed to cache the response: {}", e);
+            }
+        }
+
+        Ok(response)
+    }
+
+    fn is_local_only(&self, request: &LLMRequest, constraints: &RequestConstraints) -> bool {
+        self.policy.local_only
+            || constraints.local_only
+            || (self.policy.local_only_for_pii
+                && request.messages.iter().any(|m| contains_pii(&m.content)))
+    }
+
+    /// Routes in the order they should be tried
+    fn ordered_routes(&self) -> Vec<&Route> {
+        let mut routes: Vec<&Route> = self.routes.iter().collect();
+        if self.policy.prefer_local {
+            // Stable sort keeps configured order within each group
+            routes.sort_by_key(|r| !r.provider_type.is_local());
+        }
+        routes
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use async_trait::async_trait;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    struct MockProvider {
+        name: &'static str,
+        healthy: bool,
+        fail_with: Option<fn() -> LLMError>,
+        delay: Option<Duration>,
+        calls: AtomicUsize,
+    }
+
+    impl MockProvider {
+        fn ok(name: &'static str) -> Arc<Self> {
+            Arc::new(Self {
+                name,
+                healthy: true,
+                fail_with: None,
+                delay: None,
+                calls: AtomicUsize::new(0),
+            })
+        }
+
+        fn failing(name: &'static str, fail_with: fn() -> LLMError) -> Arc<Self> {
+            Arc::new(Self {
+                name,
+                healthy: true,
+                fail_with: Some(fail_with),
+                delay: None,
+                calls: AtomicUsize::new(0),
+            })
+        }
+
+        fn unhealthy(name: &'static str) -> Arc<Self> {
+            Arc::new(Self {
+                name,
+                healthy: false,
+                fail_with: None,
+                delay: None,
+                calls: AtomicUsize::new(0),
+            })
+        }
+
+        fn slow(name: &'static str, delay: Duration) -> Arc<Self> {
+            Arc::new(Self {
+                name,
+                healthy: true,
+                fail_with: None,
+                delay: Some(delay),
+                calls: AtomicUsize::new(0),
+            })
+        }
+
+        fn calls(&self) -> usize {
+            self.calls.load(Ordering::SeqCst)
+        }
+    }
+
+    #[async_trait]
+    impl LLMProvider for MockProvider {
+        async fn complete(&self, request: &LLMRequest) -> Result<LLMResponse, LLMError> {
+            self.calls.fetch_add(1, Ordering::SeqCst);
+            if let Some(delay) = self.delay {
+                tokio::time::sleep(delay).await;
+            }
+            if let Some(fail_with) = self.fail_with {
+                return Err(fail_with());
+            }
+            let model = request.model.clone().unwrap_or_default();
+            Ok(LLMResponse::new(format!("from {}", self.name), model, 10))
+        }
+
+        async fn list_models(&self) -> Result<Vec<String>, LLMError> {
+            Ok(vec![])
+        }
+
+        fn name(&self) -> &str {
+            self.name
+        }
+
+        async fn health_check(&self) -> Result<bool, LLMError> {
+            Ok(self.healthy)
+        }
+    }
+
+    fn test_policy() -> RoutingPolicy {
+        RoutingPolicy {
+            request_timeout: Duration::from_millis(100),
+            retry: RetryConfig::no_retry(),
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_prefers_healthy_local_provider() {
+        let ollama = MockProvider::ok("ollama");
+        let openai = MockProvider::ok("openai");
+        let router = LlmRouter::new(test_policy())
+            .add_provider(openai.clone(), ProviderType::OpenAI)
+            .add_provider(ollama.clone(), ProviderType::Ollama);
+
+        let response = router.complete(&LLMRequest::simple("hi")).await.unwrap();
+        assert_eq!(response.provider.as_deref(), Some("ollama"));
+        assert_eq!(response.model, "llama3.2");
+        assert_eq!(openai.calls(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_skips_unhealthy_local_provider() {
+        let ollama = MockProvider::unhealthy("ollama");
+        let openai = MockProvider::ok("openai");
+        let router = LlmRouter::new(test_policy())
+            .add_provider(ollama.clone(), ProviderType::Ollama)
+            .add_provider(openai.clone(), ProviderType::OpenAI);
+
+        let response = router.complete(&LLMRequest::simple("hi")).await.unwrap();
+        assert_eq!(response.provider.as_deref(), Some("openai"));
+        assert_eq!(ollama.calls(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_fails_over_on_error_and_timeout() {
+        let claude = MockProvider::failing("claude", || {
+            LLMError::ProviderError("503 Service Unavailable".to_string())
+        });
+        let openai = MockProvider::slow("openai", Duration::from_secs(5));
+        let gemini = MockProvider::ok("gemini");
+        let router = LlmRouter::new(test_policy())
+            .add_provider(claude.clone(), ProviderType::Claude)
+            .add_provider(openai.clone(), ProviderType::OpenAI)
+            .add_provider(gemini.clone(), ProviderType::Gemini);
+
+        let response = router.complete(&LLMRequest::simple("hi")).await.unwrap();
+        assert_eq!(response.provider.as_deref(), Some("gemini"));
+        assert_eq!(response.content, "from gemini");
+        assert_eq!(claude.calls(), 1);
+        assert_eq!(openai.calls(), 1);
+
+        let stats = router.cost_stats().await;
+        assert_eq!(stats.total_requests, 1);
+        assert!(stats.by_provider.contains_key("gemini"));
+    }
+
+    #[tokio::test]
+    async fn test_all_providers_failing() {
+        let router = LlmRouter::new(test_policy())
+            .add_provider(
+                MockProvider::failing("openai", || LLMError::RateLimitExceeded),
+                ProviderType::OpenAI,
+            )
+            .add_provider(MockProvider::unhealthy("ollama"), ProviderType::Ollama);
+
+        let err = router
+            .complete(&LLMRequest::simple("hi"))
+            .await
+            .unwrap_err();
+        match err {
+            LLMError::NoProviderAvailable(msg) => {
+                assert!(msg.contains("ollama: unhealthy"));
+                assert!(msg.contains("openai: Rate limit exceeded"));
+            }
+            other => panic!("unexpected error: {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_pii_requests_stay_local() {
+        let ollama = MockProvider::ok("ollama");
+        let openai = MockProvider::ok("openai");
+        let policy = RoutingPolicy {
+            prefer_local: false,
+            ..test_policy()
+        };
+        let router = LlmRouter::new(policy)
+            .add_provider(openai.clone(), ProviderType::OpenAI)
+            .add_provider(ollama.clone(), ProviderType::Ollama);
+
+        let request = LLMRequest::simple("Email jane.doe@example.com about the invoice");
+        let response = router.complete(&request).await.unwrap();
+        assert_eq!(response.provider.as_deref(), Some("ollama"));
+        assert_eq!(openai.calls(), 0);
+
+        // Without PII the configured order applies
+        let response = router.complete(&LLMRequest::simple("hi")).await.unwrap();
+        assert_eq!(response.provider.as_deref(), Some("openai"));
+    }
+
+    #[tokio::test]
+    async fn test_local_only_never_reaches_cloud() {
+        let openai = MockProvider::ok("openai");
+        let router = LlmRouter::new(test_policy())
+            .add_provider(MockProvider::unhealthy("ollama"), ProviderType::Ollama)
+            .add_provider(openai.clone(), ProviderType::OpenAI);
+
+        let constraints = RequestConstraints::default().local_only();
+        let err = router
+            .complete_with(&LLMRequest::simple("hi"), &constraints)
+            .await
+            .unwrap_err();
+        assert!(matches!(err, LLMError::NoProviderAvailable(_)));
+        assert_eq!(openai.calls(), 0);
+
+        let cloud_only = LlmRouter::new(test_policy()).add_provider(openai, ProviderType::OpenAI);
+        let err = cloud_only
+            .complete_with(&LLMRequest::simple("hi"), &constraints)
+            .await
+            .unwrap_err();
+        assert!(matches!(err, LLMError::NoProviderAvailable(_)));
+    }
+
+    #[tokio::test]
+    async fn test_cost_cap_rejects_expensive_providers() {
+        let gpt4 = MockProvider::ok("openai");
+        let router = LlmRouter::new(test_policy()).add_provider_with_model(
+            gpt4.clone(),
+            ProviderType::OpenAI,
+            "gpt-4",
+        );
+
+        let request = LLMRequest::simple("x".repeat(4000)).max_tokens(1000);
+        let constraints = RequestConstraints::default().max_cost(0.01);
+        let err = router
+            .complete_with(&request, &constraints)
+            .await
+            .unwrap_err();
+        assert!(matches!(err, LLMError::BudgetExceeded(_)));
+        assert_eq!(gpt4.calls(), 0);
+
+        // A cheaper route within the cap serves the request instead
+        let mini = MockProvider::ok("openai-mini");
+        let router = LlmRouter::new(test_policy())
+            .add_provider_with_model(gpt4.clone(), ProviderType::OpenAI, "gpt-4")
+            .add_provider_with_model(mini, ProviderType::OpenAI, "gpt-4o-mini");
+        let response = router.complete_with(&request, &constraints).await.unwrap();
+        assert_eq!(response.provider.as_deref(), Some("openai-mini"));
+        assert_eq!(gpt4.calls(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_from_config_respects_privacy_mode() {
+        let router = LlmRouter::from_config(&LLMConfig::privacy_first()).unwrap();
+        assert_eq!(router.provider_names(), vec!["ollama".to_string()]);
+        assert!(router.policy.local_only);
+
+        let router = LlmRouter::from_config(&LLMConfig::cloud_first("key")).unwrap();
+        assert_eq!(
+            router.provider_names(),
+            vec!["openai".to_string(), "ollama".to_string()]
+        );
+    }
+}
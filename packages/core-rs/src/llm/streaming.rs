@@ -59,6 +59,92 @@ impl StreamChunk {
 /// Type alias for the streaming response
 pub type StreamResponse = Pin<Box<dyn Stream<Item = Result<StreamChunk, LLMError>> + Send>>;
 
+/// Provider-agnostic event emitted while a completion streams
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum StreamEvent {
+    /// A piece of generated text, in order
+    Token { content: String },
+    /// Token usage reported by the provider
+    Usage {
+        prompt_tokens: Option<usize>,
+        completion_tokens: usize,
+    },
+    /// Generation finished
+    Done { finish_reason: Option<String> },
+    /// Generation failed; no further events follow
+    Error { message: String },
+}
+
+/// Receives stream events. Dropping the receiver cancels the stream.
+pub type StreamSink = mpsc::Sender<StreamEvent>;
+
+/// Forward an event to the sink, mapping a dropped receiver to cancellation
+pub async fn send_event(sink: &StreamSink, event: StreamEvent) -> Result<(), LLMError> {
+    sink.send(event).await.map_err(|_| LLMError::Cancelled)
+}
+
+/// Splits a chunked HTTP body into lines (NDJSON or SSE).
+///
+/// Reading stops with `LLMError::Cancelled` as soon as the sink's receiver is
+/// dropped; the response is then dropped, which aborts the HTTP request.
+pub(crate) struct LineReader {
+    response: reqwest::Response,
+    buffer: Vec<u8>,
+    finished: bool,
+}
+
+impl LineReader {
+    pub(crate) fn new(response: reqwest::Response) -> Self {
+        Self {
+            response,
+            buffer: Vec::new(),
+            finished: false,
+        }
+    }
+
+    /// Next non-empty line, or None at end of body
+    pub(crate) async fn next_line(
+        &mut self,
+        sink: &StreamSink,
+    ) -> Result<Option<String>, LLMError> {
+        loop {
+            if let Some(pos) = self.buffer.iter().position(|b| *b == b'\n') {
+                let line: Vec<u8> = self.buffer.drain(..=pos).collect();
+                let line = String::from_utf8_lossy(&line).trim().to_string();
+                if line.is_empty() {
+                    continue;
+                }
+                return Ok(Some(line));
+            }
+
+            if self.finished {
+                if self.buffer.is_empty() {
+                    return Ok(None);
+                }
+                let rest = std::mem::take(&mut self.buffer);
+                let line = String::from_utf8_lossy(&rest).trim().to_string();
+                return Ok(if line.is_empty() { None } else { Some(line) });
+            }
+
+            let chunk = tokio::select! {
+                chunk = self.response.chunk() => chunk,
+                _ = sink.closed() => return Err(LLMError::Cancelled),
+            };
+            match chunk {
+                Ok(Some(bytes)) => self.buffer.extend_from_slice(&bytes),
+                Ok(None) => self.finished = true,
+                Err(e) => {
+                    return Err(LLMError::StreamingError(format!(
+                        "Failed to read stream: {}",
+                        e
+                    )))
+                }
+            }
+        }
+    }
+}
+
 /// Streaming request builder
 #[derive(Debug, Clone)]
 pub struct StreamRequest {
@@ -267,6 +353,39 @@ mod tests {
         assert_eq!(collector.finish_reason(), Some("stop"));
     }
 
+    #[test]
+    fn test_stream_event_serialization() {
+        let event = StreamEvent::Token {
+            content: "Hi".to_string(),
+        };
+        let json = serde_json::to_value(&event).unwrap();
+        assert_eq!(json["type"], "token");
+        assert_eq!(json["content"], "Hi");
+
+        let done: StreamEvent =
+            serde_json::from_str(r#"{"type":"done","finish_reason":"stop"}"#).unwrap();
+        assert_eq!(
+            done,
+            StreamEvent::Done {
+                finish_reason: Some("stop".to_string())
+            }
+        );
+    }
+
+    #[tokio::test]
+    async fn test_send_event_after_receiver_dropped() {
+        let (sink, receiver) = mpsc::channel(1);
+        drop(receiver);
+        let result = send_event(
+            &sink,
+            StreamEvent::Done {
+                finish_reason: None,
+            },
+        )
+        .await;
+        assert!(matches!(result, Err(LLMError::Cancelled)));
+    }
+
     #[test]
     fn test_stream_request_builder() {
         let req = StreamRequest::simple("Test")
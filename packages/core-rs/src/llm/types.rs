@@ -176,6 +176,9 @@ pub struct LLMResponse {
     pub tokens_used: usize,
     pub finish_reason: Option<String>,
     pub cached: bool,
+    /// Name of the provider that served the request
+    #[serde(default)]
+    pub provider: Option<String>,
 }
 
 impl LLMResponse {
@@ -186,6 +189,7 @@ impl LLMResponse {
             tokens_used: tokens,
             finish_reason: None,
             cached: false,
+            provider: None,
         }
     }
 }
@@ -438,6 +442,7 @@ mod tests {
         assert_eq!(response.tokens_used, 42);
         assert_eq!(response.finish_reason, None);
         assert!(!response.cached);
+        assert_eq!(response.provider, None);
     }
 
     #[test]
@@ -0,0 +1,197 @@
+//! Failed-Attempt Tracking
+//!
+//! Counts consecutive password failures per subject and locks the subject out
+//! with exponential backoff once the allowance is used up. User logins keep
+//! their counters in the `auth_attempt` table; vault unlocks happen before the
+//! encrypted database can be read, so they keep theirs in a small JSON file
+//! next to the vault (see [`VaultAttemptFile`]). Either way the state outlives
+//! the connection, so reopening it does not reset the lockout.
+
+use rusqlite::{params, Connection, OptionalExtension};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+/// File inside the vault directory holding unlock attempt state
+pub const VAULT_ATTEMPT_FILE: &str = "unlock_attempts.json";
+
+/// Failed unlocks remembered until the next successful unlock audits them
+const MAX_PENDING_FAILURES: usize = 100;
+
+/// How many failures are allowed and how long lockouts last
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LockoutPolicy {
+    /// Failures allowed before the first lockout
+    pub max_attempts: u32,
+    /// Length of the first lockout; each further failure doubles it
+    pub base_lockout_secs: i64,
+    pub max_lockout_secs: i64,
+}
+
+impl Default for LockoutPolicy {
+    fn default() -> Self {
+        LockoutPolicy {
+            max_attempts: 5,
+            base_lockout_secs: 30,
+            max_lockout_secs: 60 * 60,
+        }
+    }
+}
+
+impl LockoutPolicy {
+    /// Lockout length after `failures` consecutive failures, if any
+    pub fn lockout_secs(&self, failures: u32) -> Option<i64> {
+        if failures < self.max_attempts {
+            return None;
+        }
+        let doublings = (failures - self.max_attempts).min(30);
+        Some(
+            self.base_lockout_secs
+                .saturating_mul(1i64 << doublings)
+                .min(self.max_lockout_secs),
+        )
+    }
+}
+
+/// Consecutive failures for one subject
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AttemptState {
+    pub failures: u32,
+    pub last_failure_at: Option<i64>,
+    pub locked_until: Option<i64>,
+}
+
+impl AttemptState {
+    /// Seconds until the subject may try again, or `None` if not locked
+    pub fn retry_after(&self, now: i64) -> Option<i64> {
+        self.locked_until
+            .filter(|until| *until > now)
+            .map(|until| until - now)
+    }
+
+    fn record_failure(&mut self, policy: &LockoutPolicy, now: i64) {
+        self.failures = self.failures.saturating_add(1);
+        self.last_failure_at = Some(now);
+        self.locked_until = policy.lockout_secs(self.failures).map(|secs| now + secs);
+    }
+}
+
+/// Attempt-tracking key for a username; usernames are case-insensitive here
+/// so changing the case doesn't buy extra attempts
+pub fn user_key(username: &str) -> String {
+    format!("user:{}", username.trim().to_lowercase())
+}
+
+pub fn get_attempt_state(conn: &Connection, key: &str) -> rusqlite::Result<AttemptState> {
+    let state = conn
+        .query_row(
+            "SELECT failures, last_failure_at, locked_until FROM auth_attempt WHERE key = ?1",
+            [key],
+            |row| {
+                Ok(AttemptState {
+                    failures: row.get(0)?,
+                    last_failure_at: row.get(1)?,
+                    locked_until: row.get(2)?,
+                })
+            },
+        )
+        .optional()?;
+    Ok(state.unwrap_or_default())
+}
+
+/// Record a failure and return the updated state
+pub fn record_failure_at(
+    conn: &Connection,
+    key: &str,
+    policy: &LockoutPolicy,
+    now: i64,
+) -> rusqlite::Result<AttemptState> {
+    let mut state = get_attempt_state(conn, key)?;
+    state.record_failure(policy, now);
+    conn.execute(
+        "INSERT INTO auth_attempt (key, failures, last_failure_at, locked_until)
+         VALUES (?1, ?2, ?3, ?4)
+         ON CONFLICT(key) DO UPDATE SET
+            failures = excluded.failures,
+            last_failure_at = excluded.last_failure_at,
+            locked_until = excluded.locked_until",
+        params![
+            key,
+            state.failures,
+            state.last_failure_at,
+            state.locked_until
+        ],
+    )?;
+    Ok(state)
+}
+
+/// Forget the failures of a subject after a successful attempt
+pub fn reset_attempts(conn: &Connection, key: &str) -> rusqlite::Result<()> {
+    conn.execute("DELETE FROM auth_attempt WHERE key = ?1", [key])?;
+    Ok(())
+}
+
+/// A failed vault unlock waiting to be written to the audit log
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PendingUnlockFailure {
+    pub at: i64,
+    pub locked: bool,
+}
+
+/// Unlock attempt state of a vault, stored unencrypted beside it
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct VaultAttemptFile {
+    #[serde(flatten)]
+    pub state: AttemptState,
+    /// Failures (and refused attempts) since the last successful unlock
+    #[serde(default)]
+    pub pending: Vec<PendingUnlockFailure>,
+}
+
+impl VaultAttemptFile {
+    fn path(vault_path: &Path) -> PathBuf {
+        vault_path.join(VAULT_ATTEMPT_FILE)
+    }
+
+    pub fn load(vault_path: &Path) -> std::io::Result<Self> {
+        match std::fs::read_to_string(Self::path(vault_path)) {
+            // A corrupt file must not unlock the vault for unlimited guesses,
+            // but failing closed forever would brick it, so start over
+            Ok(contents) => Ok(serde_json::from_str(&contents).unwrap_or_else(|e| {
+                log::warn!("[lockout] Ignoring unreadable attempt file: {}", e);
+                VaultAttemptFile::default()
+            })),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(VaultAttemptFile::default()),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Write via a temporary file so a crash never leaves a truncated file
+    pub fn save(&self, vault_path: &Path) -> std::io::Result<()> {
+        let path = Self::path(vault_path);
+        let tmp = path.with_extension("json.tmp");
+        std::fs::write(&tmp, serde_json::to_vec(self)?)?;
+        std::fs::rename(tmp, path)
+    }
+
+    pub fn record_failure(&mut self, policy: &LockoutPolicy, now: i64) {
+        self.state.record_failure(policy, now);
+        self.push_pending(PendingUnlockFailure {
+            at: now,
+            locked: false,
+        });
+    }
+
+    pub fn record_refused(&mut self, now: i64) {
+        self.push_pending(PendingUnlockFailure {
+            at: now,
+            locked: true,
+        });
+    }
+
+    fn push_pending(&mut self, failure: PendingUnlockFailure) {
+        if self.pending.len() >= MAX_PENDING_FAILURES {
+            self.pending.remove(0);
+        }
+        self.pending.push(failure);
+    }
+}
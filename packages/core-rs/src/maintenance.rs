@@ -0,0 +1,321 @@
+//! Background maintenance orchestrator.
+//!
+//! Modules expose their housekeeping (journal pruning, blob GC, ...) as
+//! [`MaintenanceTask`]s and a [`MaintenanceRegistry`] runs them in priority
+//! order within a time budget. Every task gets its own pooled connection and
+//! a failing or panicking task only fails itself: an open transaction it left
+//! behind is rolled back and the next task runs. Each run is recorded in
+//! `maintenance_run` for the diagnostics screen.
+//!
+//! Tasks can't be interrupted, so the budget decides which tasks start: a task
+//! is skipped when its estimated cost doesn't fit in what is left.
+
+use rusqlite::{params, Connection};
+use serde::{Deserialize, Serialize};
+use std::panic::{self, AssertUnwindSafe};
+use std::time::{Duration, Instant};
+use thiserror::Error;
+use ulid::Ulid;
+
+/// Budget of a scheduled background run
+pub const DEFAULT_MAINTENANCE_BUDGET: Duration = Duration::from_secs(5);
+
+/// History rows kept; older ones are dropped after each run
+pub const MAX_HISTORY_ROWS: i64 = 1_000;
+
+/// Priorities of the built-in tasks
+pub const PRIORITY_HIGH: u32 = 100;
+pub const PRIORITY_NORMAL: u32 = 50;
+pub const PRIORITY_LOW: u32 = 10;
+
+#[derive(Error, Debug)]
+pub enum MaintenanceError {
+    #[error("Database error: {0}")]
+    Database(#[from] rusqlite::Error),
+    #[error("Connection pool error: {0}")]
+    Pool(#[from] r2d2::Error),
+    #[error("{0}")]
+    Task(String),
+}
+
+/// What a task did
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TaskReport {
+    /// Rows, blobs, ... removed or rewritten
+    pub items_affected: u64,
+}
+
+/// A unit of housekeeping run by [`MaintenanceRegistry::run`]
+pub trait MaintenanceTask: Send + Sync {
+    /// Stable name, recorded in the run history
+    fn name(&self) -> &str;
+
+    /// Rough duration of a run on a typical vault
+    fn estimated_cost(&self) -> Duration;
+
+    fn run(&self, conn: &Connection) -> Result<TaskReport, MaintenanceError>;
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TaskStatus {
+    Completed,
+    Failed,
+    /// Didn't fit in the remaining budget
+    Skipped,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TaskOutcome {
+    pub task: String,
+    pub status: TaskStatus,
+    pub items_affected: u64,
+    pub duration_ms: i64,
+    pub error: Option<String>,
+}
+
+/// Result of one [`MaintenanceRegistry::run`], in the order tasks were considered
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MaintenanceRun {
+    pub id: String,
+    pub started_at: i64,
+    pub duration_ms: i64,
+    pub tasks: Vec<TaskOutcome>,
+}
+
+/// A task that ran, as recorded in `maintenance_run`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MaintenanceHistoryEntry {
+    pub id: String,
+    /// Shared by the tasks of one run
+    pub run_id: String,
+    pub task: String,
+    pub started_at: i64,
+    pub duration_ms: i64,
+    pub items_affected: u64,
+    /// Set when the task failed or panicked
+    pub error: Option<String>,
+}
+
+/// Tasks to run, highest priority first; equal priorities run in
+/// registration order
+#[derive(Default)]
+pub struct MaintenanceRegistry {
+    tasks: Vec<(u32, Box<dyn MaintenanceTask>)>,
+}
+
+impl MaintenanceRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Registry with the housekeeping of the core modules
+    pub fn with_builtin_tasks() -> Self {
+        let mut registry = Self::new();
+        crate::undo::register_maintenance(&mut registry);
+        crate::sync::retention::register_maintenance(&mut registry);
+        crate::blob::register_maintenance(&mut registry);
+        crate::llm::cache::register_maintenance(&mut registry);
+        registry
+    }
+
+    pub fn register(&mut self, priority: u32, task: impl MaintenanceTask + 'static) {
+        self.tasks.push((priority, Box::new(task)));
+    }
+
+    /// Names of the registered tasks in the order they run
+    pub fn task_names(&self) -> Vec<String> {
+        self.ordered().map(|task| task.name().to_string()).collect()
+    }
+
+    fn ordered(&self) -> impl Iterator<Item = &dyn MaintenanceTask> {
+        let mut tasks: Vec<_> = self.tasks.iter().collect();
+        tasks.sort_by_key(|(priority, _)| std::cmp::Reverse(*priority));
+        tasks.into_iter().map(|(_, task)| task.as_ref())
+    }
+
+    /// Run the tasks that fit in `budget` and record them in the history
+    pub fn run<M>(
+        &self,
+        pool: &r2d2::Pool<M>,
+        budget: Duration,
+    ) -> Result<MaintenanceRun, MaintenanceError>
+    where
+        M: r2d2::ManageConnection<Connection = Connection>,
+    {
+        let run_id = Ulid::new().to_string();
+        let started_at = chrono::Utc::now().timestamp();
+        let start = Instant::now();
+        let mut outcomes = Vec::with_capacity(self.tasks.len());
+
+        for task in self.ordered() {
+            let remaining = budget.saturating_sub(start.elapsed());
+            if task.estimated_cost() > remaining || remaining.is_zero() {
+                log::debug!(
+                    "[maintenance] Skipping {}: {:?} left of the budget",
+                    task.name(),
+                    remaining
+                );
+                outcomes.push(TaskOutcome {
+                    task: task.name().to_string(),
+                    status: TaskStatus::Skipped,
+                    items_affected: 0,
+                    duration_ms: 0,
+                    error: None,
+                });
+                continue;
+            }
+
+            let conn = pool.get()?;
+            let task_started_at = chrono::Utc::now().timestamp();
+            let task_start = Instant::now();
+            let result = run_isolated(task, &conn);
+            let duration_ms = task_start.elapsed().as_millis() as i64;
+
+            let outcome = match result {
+                Ok(report) => TaskOutcome {
+                    task: task.name().to_string(),
+                    status: TaskStatus::Completed,
+                    items_affected: report.items_affected,
+                    duration_ms,
+                    error: None,
+                },
+                Err(error) => {
+                    log::warn!("[maintenance] Task {} failed: {}", task.name(), error);
+                    TaskOutcome {
+                        task: task.name().to_string(),
+                        status: TaskStatus::Failed,
+                        items_affected: 0,
+                        duration_ms,
+                        error: Some(error),
+                    }
+                }
+            };
+            record_history(&conn, &run_id, task_started_at, &outcome)?;
+            outcomes.push(outcome);
+        }
+
+        prune_history(&*pool.get()?)?;
+        let duration_ms = start.elapsed().as_millis() as i64;
+        log::info!(
+            "[maintenance] Run {} finished in {}ms: {} tasks run, {} skipped",
+            run_id,
+            duration_ms,
+            outcomes
+                .iter()
+                .filter(|o| o.status != TaskStatus::Skipped)
+                .count(),
+            outcomes
+                .iter()
+                .filter(|o| o.status == TaskStatus::Skipped)
+                .count()
+        );
+        Ok(MaintenanceRun {
+            id: run_id,
+            started_at,
+            duration_ms,
+            tasks: outcomes,
+        })
+    }
+}
+
+/// Run the built-in tasks within `budget`
+pub fn run_maintenance<M>(
+    pool: &r2d2::Pool<M>,
+    budget: Duration,
+) -> Result<MaintenanceRun, MaintenanceError>
+where
+    M: r2d2::ManageConnection<Connection = Connection>,
+{
+    MaintenanceRegistry::with_builtin_tasks().run(pool, budget)
+}
+
+/// Run a task, turning panics into errors and rolling back a transaction it
+/// left open so the connection goes back to the pool clean
+fn run_isolated(task: &dyn MaintenanceTask, conn: &Connection) -> Result<TaskReport, String> {
+    let result = match panic::catch_unwind(AssertUnwindSafe(|| task.run(conn))) {
+        Ok(result) => result.map_err(|e| e.to_string()),
+        Err(payload) => Err(panic_message(payload.as_ref())),
+    };
+    if !conn.is_autocommit() {
+        if let Err(e) = conn.execute_batch("ROLLBACK") {
+            log::warn!(
+                "[maintenance] Failed to roll back after {}: {}",
+                task.name(),
+                e
+            );
+        }
+        return result.and(Err("left a transaction open".to_string()));
+    }
+    result
+}
+
+fn panic_message(payload: &(dyn std::any::Any + Send)) -> String {
+    let message = payload
+        .downcast_ref::<&str>()
+        .map(|s| s.to_string())
+        .or_else(|| payload.downcast_ref::<String>().cloned())
+        .unwrap_or_else(|| "unknown cause".to_string());
+    format!("panicked: {}", message)
+}
+
+fn record_history(
+    conn: &Connection,
+    run_id: &str,
+    started_at: i64,
+    outcome: &TaskOutcome,
+) -> Result<(), MaintenanceError> {
+    conn.execute(
+        "INSERT INTO maintenance_run
+             (id, run_id, task, started_at, duration_ms, items_affected, error)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+        params![
+            Ulid::new().to_string(),
+            run_id,
+            outcome.task,
+            started_at,
+            outcome.duration_ms,
+            outcome.items_affected as i64,
+            outcome.error
+        ],
+    )?;
+    Ok(())
+}
+
+fn prune_history(conn: &Connection) -> Result<usize, MaintenanceError> {
+    Ok(conn.execute(
+        "DELETE FROM maintenance_run WHERE id IN (
+            SELECT id FROM maintenance_run
+            ORDER BY started_at DESC, rowid DESC
+            LIMIT -1 OFFSET ?1
+        )",
+        [MAX_HISTORY_ROWS],
+    )?)
+}
+
+/// Most recent task runs, newest first
+pub fn get_maintenance_history(
+    conn: &Connection,
+    limit: u32,
+) -> Result<Vec<MaintenanceHistoryEntry>, MaintenanceError> {
+    let mut stmt = conn.prepare(
+        "SELECT id, run_id, task, started_at, duration_ms, items_affected, error
+         FROM maintenance_run
+         ORDER BY started_at DESC, rowid DESC
+         LIMIT ?1",
+    )?;
+    let entries = stmt
+        .query_map([limit], |row| {
+            Ok(MaintenanceHistoryEntry {
+                id: row.get(0)?,
+                run_id: row.get(1)?,
+                task: row.get(2)?,
+                started_at: row.get(3)?,
+                duration_ms: row.get(4)?,
+                items_affected: row.get::<_, i64>(5)? as u64,
+                error: row.get(6)?,
+            })
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(entries)
+}
@@ -1,59 +1,333 @@
+//! Meeting Action Items
+//!
+//! Turns the action items in a meeting note into tasks in the note's space.
+//! The heuristic parser recognises checkbox lines (`- [ ] ...`), assignments
+//! (`@name to ...`) and `TODO:` / `Action:` lines. An LLM can be used instead
+//! when one is configured; any failure falls back to the heuristic parser.
+//!
+//! Every extracted item is recorded in `meeting_action` under a hash of its
+//! normalized text, so running the extraction again after editing the note
+//! only creates tasks for new items.
+
+use crate::llm::{LLMProvider, LLMRequest};
 use regex::Regex;
-use rusqlite::Connection;
+use rusqlite::{params, Connection, OptionalExtension};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
 use thiserror::Error;
 use ulid::Ulid;
 
+/// Titles shorter than this are too vague to become tasks
+const MIN_TITLE_LEN: usize = 3;
+
 #[derive(Error, Debug)]
 pub enum MeetingError {
     #[error("Rusqlite error: {0}")]
     Rusqlite(#[from] rusqlite::Error),
     #[error("Regex error: {0}")]
     Regex(#[from] regex::Error),
+    #[error("Note not found: {0}")]
+    NoteNotFound(String),
+    #[error("LLM extraction failed: {0}")]
+    Llm(String),
+}
+
+/// An action item found in a meeting note
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ActionItem {
+    pub title: String,
+    pub assignee: Option<String>,
+    /// Already ticked off in the note
+    #[serde(default)]
+    pub done: bool,
+    /// The note line the item came from
+    #[serde(default)]
+    pub source_line: String,
+}
+
+impl ActionItem {
+    /// Stable identity of the item: survives re-indenting, re-wording the
+    /// bullet marker and ticking the checkbox
+    fn hash(&self) -> String {
+        let normalized = format!(
+            "{}|{}",
+            self.assignee.as_deref().unwrap_or("").to_lowercase(),
+            self.title
+                .split_whitespace()
+                .collect::<Vec<_>>()
+                .join(" ")
+                .to_lowercase()
+        );
+        hex::encode(Sha256::digest(normalized.as_bytes()))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ExtractionMethod {
+    Heuristic,
+    Llm,
+}
+
+/// Outcome of one extraction run
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ExtractionReport {
+    /// Ids of the tasks created by this run
+    pub created: Vec<String>,
+    /// Items already extracted by an earlier run
+    pub skipped: usize,
+    /// Lines that look like action items but could not be turned into one
+    pub ambiguous: Vec<String>,
+    pub method: ExtractionMethod,
+}
+
+/// Result of parsing a note, before anything is written
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ParsedActions {
+    pub items: Vec<ActionItem>,
+    pub ambiguous: Vec<String>,
+}
+
+struct Patterns {
+    checkbox: Regex,
+    assignment: Regex,
+    todo: Regex,
+    leading_assignee: Regex,
+    mention: Regex,
+}
+
+impl Patterns {
+    fn new() -> Result<Self, regex::Error> {
+        Ok(Patterns {
+            checkbox: Regex::new(r"^\s*[-*+]\s+\[(?P<state>[ xX])\]\s*(?P<rest>.*)$")?,
+            assignment: Regex::new(
+                r"(?i)^\s*(?:[-*+]\s+)?@(?P<who>[\w.-]+)\s+(?:to|will|should|needs to)\s+(?P<title>.*)$",
+            )?,
+            todo: Regex::new(
+                r"(?i)^\s*(?:[-*+]\s+)?(?:todo|action(?: item)?)\s*:\s*(?P<rest>.*)$",
+            )?,
+            leading_assignee: Regex::new(
+                r"(?i)^@(?P<who>[\w.-]+)\s*(?::|\s+to\s+|\s+will\s+|\s+)(?P<title>.*)$",
+            )?,
+            mention: Regex::new(r"^\s*(?:[-*+]\s+)?@[\w.-]+")?,
+        })
+    }
+}
+
+/// Find the action items in meeting note text with the heuristic parser
+pub fn parse_action_items(content: &str) -> Result<ParsedActions, MeetingError> {
+    let patterns = Patterns::new()?;
+    let mut parsed = ParsedActions::default();
+
+    for line in content.lines() {
+        let source_line = line.trim().to_string();
+        let candidate = if let Some(cap) = patterns.checkbox.captures(line) {
+            let done = cap["state"].eq_ignore_ascii_case("x");
+            Some((split_assignee(&patterns, &cap["rest"]), done))
+        } else if let Some(cap) = patterns.assignment.captures(line) {
+            Some((
+                (Some(cap["who"].to_string()), cap["title"].to_string()),
+                false,
+            ))
+        } else if let Some(cap) = patterns.todo.captures(line) {
+            Some((split_assignee(&patterns, &cap["rest"]), false))
+        } else if patterns.mention.is_match(line) {
+            // "@sam the budget?" names someone but says nothing to do
+            parsed.ambiguous.push(source_line);
+            continue;
+        } else {
+            None
+        };
+
+        if let Some(((assignee, title), done)) = candidate {
+            let title = title.trim().trim_end_matches('.').trim().to_string();
+            if title.chars().count() < MIN_TITLE_LEN {
+                parsed.ambiguous.push(source_line);
+                continue;
+            }
+            parsed.items.push(ActionItem {
+                title,
+                assignee,
+                done,
+                source_line,
+            });
+        }
+    }
+    Ok(parsed)
+}
+
+/// Split a leading `@name` off an item's text
+fn split_assignee(patterns: &Patterns, text: &str) -> (Option<String>, String) {
+    let text = text.trim();
+    match patterns.leading_assignee.captures(text) {
+        Some(cap) => (Some(cap["who"].to_string()), cap["title"].to_string()),
+        None => (None, text.to_string()),
+    }
 }
 
+/// Extract a meeting note's action items with the heuristic parser and
+/// create tasks for the ones not extracted before
 pub fn extract_action_items(
     conn: &Connection,
-    space_id: &str,
     note_id: &str,
-    note_content: &str,
-) -> Result<Vec<String>, MeetingError> {
+) -> Result<ExtractionReport, MeetingError> {
     log::info!("[meeting] Extracting action items from note: {}", note_id);
-    // Updated regex:
-    // - Matches both `[ ]` and `[x]` for status.
-    // - Makes the `@owner` tag optional.
-    let re = Regex::new(r"- \[(?P<status_char> |x)\] (?:@(?P<owner>\w+) )?(?P<title>.+)")?;
-    let mut new_task_ids = Vec::new();
-
-    for cap in re.captures_iter(note_content) {
-        let title = cap.name("title").map_or("", |m| m.as_str()).trim();
-        if title.is_empty() {
+    let (_, content) = get_note(conn, note_id)?;
+    let parsed = parse_action_items(&content)?;
+    apply_action_items(conn, note_id, parsed, ExtractionMethod::Heuristic)
+}
+
+/// Like [`extract_action_items`], but asks `llm` to find the items. Falls back
+/// to the heuristic parser when no provider is given or the LLM fails.
+pub async fn extract_action_items_with_llm(
+    conn: &Connection,
+    note_id: &str,
+    llm: Option<&dyn LLMProvider>,
+    model: Option<&str>,
+) -> Result<ExtractionReport, MeetingError> {
+    let (_, content) = get_note(conn, note_id)?;
+    let (parsed, method) = match llm {
+        Some(llm) => match suggest_action_items(llm, model, &content).await {
+            Ok(parsed) => (parsed, ExtractionMethod::Llm),
+            Err(e) => {
+                log::warn!("[meeting] {}; using the heuristic parser", e);
+                (parse_action_items(&content)?, ExtractionMethod::Heuristic)
+            }
+        },
+        None => (parse_action_items(&content)?, ExtractionMethod::Heuristic),
+    };
+    apply_action_items(conn, note_id, parsed, method)
+}
+
+/// Ask an LLM for the action items in a note. Needs no database, so callers
+/// can release their connection while the request runs.
+pub async fn suggest_action_items(
+    llm: &dyn LLMProvider,
+    model: Option<&str>,
+    content: &str,
+) -> Result<ParsedActions, MeetingError> {
+    let system = "You extract action items from meeting notes. Reply with only a JSON array. \
+        Each element is {\"title\": string, \"assignee\": string or null, \"done\": boolean}. \
+        Use the person's handle without '@' as assignee. Reply [] if there are none.";
+    let mut request = LLMRequest::with_system(system, content).temperature(0.0);
+    if let Some(model) = model {
+        request = request.model(model);
+    }
+
+    let response = llm
+        .complete(&request)
+        .await
+        .map_err(|e| MeetingError::Llm(e.to_string()))?;
+    // Models like to wrap JSON in prose or code fences
+    let json = match (response.content.find('['), response.content.rfind(']')) {
+        (Some(start), Some(end)) if start < end => &response.content[start..=end],
+        _ => {
+            return Err(MeetingError::Llm(
+                "Response did not contain a JSON array".to_string(),
+            ))
+        }
+    };
+    let items: Vec<ActionItem> =
+        serde_json::from_str(json).map_err(|e| MeetingError::Llm(e.to_string()))?;
+
+    let mut parsed = ParsedActions::default();
+    for mut item in items {
+        item.title = item.title.trim().to_string();
+        item.assignee = item
+            .assignee
+            .map(|a| a.trim().trim_start_matches('@').to_string())
+            .filter(|a| !a.is_empty());
+        if item.title.chars().count() < MIN_TITLE_LEN {
+            parsed.ambiguous.push(item.title);
             continue;
         }
+        if item.source_line.is_empty() {
+            item.source_line = item.title.clone();
+        }
+        parsed.items.push(item);
+    }
+    Ok(parsed)
+}
 
-        // Default to "unassigned" if no owner is captured
-        let owner = cap.name("owner").map_or("unassigned", |m| m.as_str());
+/// Create tasks for the parsed items that this note has not produced before
+pub fn apply_action_items(
+    conn: &Connection,
+    note_id: &str,
+    parsed: ParsedActions,
+    method: ExtractionMethod,
+) -> Result<ExtractionReport, MeetingError> {
+    let (space_id, _) = get_note(conn, note_id)?;
+    let now = chrono::Utc::now().timestamp();
+    let mut report = ExtractionReport {
+        created: Vec::new(),
+        skipped: 0,
+        ambiguous: parsed.ambiguous,
+        method,
+    };
 
-        // Determine status based on the character in brackets
-        let status = match cap.name("status_char") {
-            Some(m) if m.as_str() == "x" => "done",
-            _ => "inbox",
-        };
+    let tx = conn.unchecked_transaction()?;
+    for item in parsed.items {
+        let hash = item.hash();
+        let known: bool = tx.query_row(
+            "SELECT EXISTS(SELECT 1 FROM meeting_action WHERE note_id = ?1 AND line_hash = ?2)",
+            params![note_id, hash],
+            |row| row.get(0),
+        )?;
+        if known {
+            report.skipped += 1;
+            continue;
+        }
 
         let task_id = Ulid::new().to_string();
-
+        let (status, completed_at) = if item.done {
+            ("done", Some(now))
+        } else {
+            ("inbox", None)
+        };
+        tx.execute(
+            "INSERT INTO task (id, space_id, note_id, title, status, context, completed_at, updated_at)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+            params![
+                task_id,
+                space_id,
+                note_id,
+                item.title,
+                status,
+                item.assignee,
+                completed_at,
+                now
+            ],
+        )?;
+        tx.execute(
+            "INSERT INTO meeting_action (note_id, line_hash, task_id, source_line, created_at)
+             VALUES (?1, ?2, ?3, ?4, ?5)",
+            params![note_id, hash, task_id, item.source_line, now],
+        )?;
         log::info!(
-            "[meeting] Found action item: '{}' for owner: '{}' with status: '{}'",
-            title,
-            owner,
-            status
+            "[meeting] Created task '{}' for {}",
+            item.title,
+            item.assignee.as_deref().unwrap_or("nobody")
         );
-        conn.execute(
-            "INSERT INTO task (id, space_id, note_id, title, status, context) VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
-            rusqlite::params![task_id, space_id, note_id, title, status, owner],
-        )?;
-        new_task_ids.push(task_id);
+        report.created.push(task_id);
     }
+    tx.commit()?;
+
+    log::info!(
+        "[meeting] Extracted {} action items ({} already known, {} ambiguous)",
+        report.created.len(),
+        report.skipped,
+        report.ambiguous.len()
+    );
+    Ok(report)
+}
 
-    log::info!("[meeting] Extracted {} action items", new_task_ids.len());
-    Ok(new_task_ids)
+/// Space and content of a note
+fn get_note(conn: &Connection, note_id: &str) -> Result<(String, String), MeetingError> {
+    conn.query_row(
+        "SELECT space_id, content_md FROM note WHERE id = ?1",
+        [note_id],
+        |row| Ok((row.get(0)?, row.get(1)?)),
+    )
+    .optional()?
+    .ok_or_else(|| MeetingError::NoteNotFound(note_id.to_string()))
 }
@@ -10,7 +10,7 @@ use lazy_static::lazy_static;
 use tokio::runtime::Runtime;
 
 use crate::sync::discovery::DiscoveredDevice;
-use crate::sync::models::SyncProgress;
+use crate::sync::models::{SyncProgress, SYNC_PROTOCOL_VERSION};
 use crate::sync_agent::{SyncAgent, SyncConflict, ConflictResolution, DeviceInfo, DeviceType};
 use crate::sync::p2p::P2pSync;
 
@@ -66,7 +66,7 @@ pub unsafe extern "C" fn rust_init_agent(
         last_seen: chrono::Utc::now().timestamp(),
         sync_address: "0.0.0.0".to_string(), // Default, will be updated by discovery or connection
         sync_port: sync_port as u16,
-        protocol_version: "1.0.0".to_string(),
+        protocol_version: SYNC_PROTOCOL_VERSION.to_string(),
     };
 
     match P2pSync::new(device_info) {
@@ -167,7 +167,7 @@ fn register_device_impl(json_str: &str) -> Result<(), String> {
         last_seen: chrono::Utc::now().timestamp(),
         sync_address: device.address.clone(),
         sync_port: device.port,
-        protocol_version: "1.0.0".to_string(),
+        protocol_version: "2.0.0".to_string(),
     };
 
     // Use global P2P protocol's agent if available?
@@ -1,8 +1,30 @@
 use crate::db::DbError;
+use crate::personal_modes::{
+    setup_finance_mode, setup_health_mode, setup_travel_mode, MODE_FINANCE, MODE_HEALTH,
+    MODE_TRAVEL,
+};
+use crate::undo::{capture_rows, record_undo, UndoRecord, UndoSnapshot};
 use rusqlite::{Connection, Result};
 use serde::{Deserialize, Serialize};
+use thiserror::Error;
 use ulid::Ulid;
 
+#[derive(Error, Debug)]
+pub enum ModeError {
+    #[error("Database error: {0}")]
+    Database(#[from] DbError),
+    #[error("Rusqlite error: {0}")]
+    Rusqlite(#[from] rusqlite::Error),
+    #[error(
+        "{table} has {actual} rows but the purge preview counted {previewed}; preview the purge again"
+    )]
+    StalePreview {
+        table: String,
+        previewed: i64,
+        actual: i64,
+    },
+}
+
 #[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone)]
 pub struct Mode {
     pub id: String,
@@ -16,6 +38,116 @@ pub struct Space {
     pub enabled_modes: Vec<Mode>,
 }
 
+/// A table holding data that belongs to a mode, scoped by `space_id`
+#[derive(Debug, Clone, Copy)]
+pub struct ModeTable {
+    pub table: &'static str,
+    /// What the rows are, for messages like "Health mode has 1,204 metrics"
+    pub label: &'static str,
+}
+
+/// Idempotent setup run when a mode is enabled in a space
+pub type ModeSetup = fn(&Connection, &str) -> Result<(), DbError>;
+
+/// What a mode does when it is enabled and which data it owns.
+///
+/// `setup` runs on every enable and must be idempotent. Disabling only hides
+/// the mode; its data is deleted by [`purge_mode`] alone.
+pub struct ModeLifecycle {
+    pub setup: Option<ModeSetup>,
+    /// Tables purged with the mode, in deletion order
+    pub tables: &'static [ModeTable],
+}
+
+pub fn mode_lifecycle(mode_id: &str) -> ModeLifecycle {
+    match mode_id {
+        MODE_HEALTH => ModeLifecycle {
+            setup: Some(setup_health_mode),
+            tables: &[
+                ModeTable {
+                    table: "health_metric",
+                    label: "metrics",
+                },
+                ModeTable {
+                    table: "health_metric_setting",
+                    label: "metric settings",
+                },
+            ],
+        },
+        MODE_FINANCE => ModeLifecycle {
+            setup: Some(setup_finance_mode),
+            tables: &[
+                ModeTable {
+                    table: "transaction_log",
+                    label: "transactions",
+                },
+                ModeTable {
+                    table: "budget",
+                    label: "budgets",
+                },
+            ],
+        },
+        MODE_TRAVEL => ModeLifecycle {
+            setup: Some(setup_travel_mode),
+            tables: &[ModeTable {
+                table: "trip",
+                label: "trips",
+            }],
+        },
+        _ => ModeLifecycle {
+            setup: None,
+            tables: &[],
+        },
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone)]
+pub struct ModeDataCount {
+    pub table: String,
+    pub label: String,
+    pub count: i64,
+}
+
+/// An enabled mode and the data it owns in the space
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone)]
+pub struct ModeStatus {
+    #[serde(flatten)]
+    pub mode: Mode,
+    pub data: Vec<ModeDataCount>,
+    pub total_rows: i64,
+}
+
+/// Rows a purge would delete. [`purge_mode`] only deletes when the counts
+/// still match, so the UI must show a fresh preview before purging.
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone)]
+pub struct PurgePreview {
+    pub mode_id: String,
+    pub space_id: String,
+    pub data: Vec<ModeDataCount>,
+    pub total_rows: i64,
+}
+
+fn count_mode_data(
+    conn: &Connection,
+    space_id: &str,
+    mode_id: &str,
+) -> Result<Vec<ModeDataCount>, DbError> {
+    let mut counts = Vec::new();
+    for table in mode_lifecycle(mode_id).tables {
+        let count: i64 = conn.query_row(
+            &format!("SELECT COUNT(*) FROM {} WHERE space_id = ?1", table.table),
+            [space_id],
+            |row| row.get(0),
+        )?;
+        counts.push(ModeDataCount {
+            table: table.table.to_string(),
+            label: table.label.to_string(),
+            count,
+        });
+    }
+    Ok(counts)
+}
+
 pub fn get_space_modes(conn: &Connection, space_id: &str) -> Result<Vec<Mode>, DbError> {
     log::info!("[mode] Getting modes for space: {}", space_id);
     let mut stmt = conn.prepare("SELECT enabled_modes_json FROM space WHERE id = ?1")?;
@@ -58,21 +190,48 @@ pub(crate) fn enable_core_pack(conn: &Connection, space_id: Ulid) -> Result<(),
     Ok(())
 }
 
+/// The space's enabled modes with the number of rows each one owns
+pub fn get_space_modes_with_data(
+    conn: &Connection,
+    space_id: &str,
+) -> Result<Vec<ModeStatus>, DbError> {
+    get_space_modes(conn, space_id)?
+        .into_iter()
+        .map(|mode| {
+            let data = count_mode_data(conn, space_id, &mode.id)?;
+            Ok(ModeStatus {
+                total_rows: data.iter().map(|d| d.count).sum(),
+                mode,
+                data,
+            })
+        })
+        .collect()
+}
+
+/// Enable a mode and run its setup. Setup runs again when the mode is
+/// already enabled, restoring defaults the user removed.
 pub fn enable_mode(conn: &Connection, space_id: &str, mode: &Mode) -> Result<(), DbError> {
     log::info!("[mode] Enabling mode '{}' for space: {}", mode.id, space_id);
-    let mut modes = get_space_modes(conn, space_id)?;
+    let tx = conn.unchecked_transaction()?;
+    let mut modes = get_space_modes(&tx, space_id)?;
     if !modes.contains(mode) {
         modes.push(mode.clone());
     }
     let modes_json = serde_json::to_string(&modes)
         .map_err(|_| DbError::Message("Could not serialize modes".into()))?;
-    conn.execute(
+    tx.execute(
         "UPDATE space SET enabled_modes_json = ?1 WHERE id = ?2",
         rusqlite::params![modes_json, space_id],
     )?;
+    if let Some(setup) = mode_lifecycle(&mode.id).setup {
+        setup(&tx, space_id)?;
+    }
+    tx.commit()?;
     Ok(())
 }
 
+/// Hide a mode. Its data is kept and shows up again when the mode is
+/// re-enabled; use [`purge_mode`] to delete it.
 pub fn disable_mode(conn: &Connection, space_id: &str, mode: &Mode) -> Result<(), DbError> {
     log::info!(
         "[mode] Disabling mode '{}' for space: {}",
@@ -89,3 +248,106 @@ pub fn disable_mode(conn: &Connection, space_id: &str, mode: &Mode) -> Result<()
     )?;
     Ok(())
 }
+
+/// Count what [`purge_mode`] would delete, without deleting anything
+pub fn preview_mode_purge(
+    conn: &Connection,
+    space_id: &str,
+    mode_id: &str,
+) -> Result<PurgePreview, DbError> {
+    let data = count_mode_data(conn, space_id, mode_id)?;
+    Ok(PurgePreview {
+        mode_id: mode_id.to_string(),
+        space_id: space_id.to_string(),
+        total_rows: data.iter().map(|d| d.count).sum(),
+        data,
+    })
+}
+
+/// Disable a mode and delete the data it owns, in one transaction. Refuses
+/// to delete anything when the data changed since `preview` was taken.
+pub fn purge_mode(
+    conn: &Connection,
+    preview: &PurgePreview,
+) -> Result<Vec<ModeDataCount>, ModeError> {
+    log::info!(
+        "[mode] Purging mode '{}' for space: {}",
+        preview.mode_id,
+        preview.space_id
+    );
+    let tx = conn.unchecked_transaction()?;
+    let current = count_mode_data(&tx, &preview.space_id, &preview.mode_id)?;
+    for table in &current {
+        let previewed = preview
+            .data
+            .iter()
+            .find(|d| d.table == table.table)
+            .map_or(0, |d| d.count);
+        if previewed != table.count {
+            return Err(ModeError::StalePreview {
+                table: table.table.clone(),
+                previewed,
+                actual: table.count,
+            });
+        }
+    }
+
+    let mut snapshot = UndoSnapshot::default();
+    for table in &current {
+        snapshot.deleted.extend(capture_rows(
+            &tx,
+            &table.table,
+            "*",
+            "space_id = ?1",
+            [&preview.space_id],
+        )?);
+    }
+    // Purged children before their parents; restore the other way round
+    snapshot.deleted.reverse();
+    snapshot.modified.extend(capture_rows(
+        &tx,
+        "space",
+        "id, enabled_modes_json",
+        "id = ?1",
+        [&preview.space_id],
+    )?);
+
+    let mut deleted = Vec::new();
+    for table in current {
+        let count = tx.execute(
+            &format!("DELETE FROM {} WHERE space_id = ?1", table.table),
+            [&preview.space_id],
+        )?;
+        deleted.push(ModeDataCount {
+            count: count as i64,
+            ..table
+        });
+    }
+    let mut modes = get_space_modes(&tx, &preview.space_id)?;
+    modes.retain(|m| m.id != preview.mode_id);
+    let modes_json = serde_json::to_string(&modes)
+        .map_err(|_| DbError::Message("Could not serialize modes".into()))?;
+    tx.execute(
+        "UPDATE space SET enabled_modes_json = ?1 WHERE id = ?2",
+        rusqlite::params![modes_json, preview.space_id],
+    )?;
+    record_undo(
+        &tx,
+        UndoRecord {
+            space_id: &preview.space_id,
+            operation: "purge_mode",
+            entity_type: "mode",
+            entity_id: &preview.mode_id,
+            summary: format!("Purged mode '{}'", preview.mode_id),
+            snapshot,
+        },
+    );
+    tx.commit()?;
+
+    log::info!(
+        "[mode] Purged {} rows of mode '{}'",
+        deleted.iter().map(|d| d.count).sum::<i64>(),
+        preview.mode_id
+    );
+    Ok(deleted)
+}
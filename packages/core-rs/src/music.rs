@@ -1,6 +1,8 @@
+use crate::calendar::TimeRange;
 use crate::db::DbError;
-use rusqlite::{Connection, Result};
+use rusqlite::{Connection, OptionalExtension, Result};
 use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
 use ulid::Ulid;
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -182,3 +184,500 @@ pub fn get_playlists(conn: &Connection, space_id: Ulid) -> Result<Vec<Playlist>,
 
     Ok(playlists)
 }
+
+/// How many tracks and artists [`get_listening_stats`] ranks
+const TOP_LISTENING_ENTRIES: i64 = 10;
+
+const DAY_SECS: i64 = 86_400;
+
+/// One listen of a track. Plays are kept on this device and not synced.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct TrackPlay {
+    pub id: Ulid,
+    pub track_id: Ulid,
+    pub played_at: i64,
+    /// Seconds actually listened, which may be less than the track
+    pub duration_listened: i64,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct TrackListening {
+    pub track_id: Ulid,
+    pub title: String,
+    pub artist: Option<String>,
+    pub plays: i64,
+    pub seconds_listened: i64,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct ArtistListening {
+    pub artist: String,
+    pub plays: i64,
+    pub seconds_listened: i64,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct ListeningStats {
+    pub total_plays: i64,
+    pub total_seconds_listened: i64,
+    /// Most played first
+    pub top_tracks: Vec<TrackListening>,
+    /// Most played first; tracks without an artist are left out
+    pub top_artists: Vec<ArtistListening>,
+}
+
+/// A smart playlist rule. Same JSON shape as social category rules:
+/// `{"type": "all", "conditions": [{"type": "genre_equals", "genre": "jazz"}]}`
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum SmartPlaylistCondition {
+    /// Every nested condition must match (an empty group matches)
+    All {
+        conditions: Vec<SmartPlaylistCondition>,
+    },
+    /// At least one nested condition must match (an empty group does not)
+    Any {
+        conditions: Vec<SmartPlaylistCondition>,
+    },
+    /// Case-insensitive
+    GenreEquals { genre: String },
+    /// Case-insensitive; an empty set matches nothing
+    ArtistIn { artists: Vec<String> },
+    /// Strictly more than `times` plays inside the window (all bounds
+    /// inclusive, in seconds). `last_days` is relative to the refresh.
+    PlayedMoreThan {
+        times: i64,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        after: Option<i64>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        before: Option<i64>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        last_days: Option<i64>,
+    },
+    /// Added to the library in the last `days` days
+    AddedWithin { days: i64 },
+}
+
+/// Whether a playlist entry was added by hand or by the playlist's rule
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum PlaylistEntrySource {
+    Manual,
+    Rule,
+}
+
+impl PlaylistEntrySource {
+    fn as_str(self) -> &'static str {
+        match self {
+            PlaylistEntrySource::Manual => "manual",
+            PlaylistEntrySource::Rule => "rule",
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct PlaylistEntry {
+    pub track_id: Ulid,
+    pub position: i64,
+    pub added_at: i64,
+    pub source: PlaylistEntrySource,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq, Eq)]
+pub struct SmartPlaylistRefresh {
+    pub added: usize,
+    pub removed: usize,
+    /// Entries after the refresh, manual ones included
+    pub total: usize,
+}
+
+/// Track fields that smart playlist conditions look at
+struct RuleTrack {
+    id: String,
+    artist: Option<String>,
+    genre: Option<String>,
+    added_at: i64,
+    plays: Vec<i64>,
+}
+
+impl SmartPlaylistCondition {
+    fn matches(&self, track: &RuleTrack, now: i64) -> bool {
+        match self {
+            SmartPlaylistCondition::All { conditions } => {
+                conditions.iter().all(|c| c.matches(track, now))
+            }
+            SmartPlaylistCondition::Any { conditions } => {
+                conditions.iter().any(|c| c.matches(track, now))
+            }
+            SmartPlaylistCondition::GenreEquals { genre } => track
+                .genre
+                .as_deref()
+                .is_some_and(|g| g.trim().eq_ignore_ascii_case(genre.trim())),
+            SmartPlaylistCondition::ArtistIn { artists } => {
+                track.artist.as_deref().is_some_and(|artist| {
+                    let artist = artist.trim().to_lowercase();
+                    artists.iter().any(|a| a.trim().to_lowercase() == artist)
+                })
+            }
+            SmartPlaylistCondition::PlayedMoreThan {
+                times,
+                after,
+                before,
+                last_days,
+            } => {
+                let plays = track
+                    .plays
+                    .iter()
+                    .filter(|&&at| {
+                        after.is_none_or(|a| at >= a)
+                            && before.is_none_or(|b| at <= b)
+                            && last_days.is_none_or(|d| at >= now - d * DAY_SECS)
+                    })
+                    .count() as i64;
+                plays > *times
+            }
+            SmartPlaylistCondition::AddedWithin { days } => track.added_at >= now - days * DAY_SECS,
+        }
+    }
+}
+
+/// Record a listen and bump the track's play count. `duration_listened` is
+/// in seconds.
+pub fn record_play(
+    conn: &Connection,
+    track_id: Ulid,
+    played_at: i64,
+    duration_listened: i64,
+) -> Result<TrackPlay, DbError> {
+    if duration_listened < 0 {
+        return Err(DbError::Message(
+            "Listened duration can't be negative".into(),
+        ));
+    }
+    // Not `updated_at`: play counts are local, like the history
+    let updated = conn.execute(
+        "UPDATE track SET play_count = COALESCE(play_count, 0) + 1,
+                          last_played_at = MAX(COALESCE(last_played_at, ?2), ?2)
+         WHERE id = ?1",
+        rusqlite::params![track_id.to_string(), played_at],
+    )?;
+    if updated == 0 {
+        return Err(DbError::Message(format!("Track not found: {}", track_id)));
+    }
+
+    let play = TrackPlay {
+        id: Ulid::new(),
+        track_id,
+        played_at,
+        duration_listened,
+    };
+    conn.execute(
+        "INSERT INTO track_play (id, track_id, played_at, duration_listened)
+         VALUES (?1, ?2, ?3, ?4)",
+        rusqlite::params![
+            play.id.to_string(),
+            play.track_id.to_string(),
+            play.played_at,
+            play.duration_listened
+        ],
+    )?;
+    Ok(play)
+}
+
+/// Plays in `range` of the space's tracks, with the most played tracks and
+/// artists
+pub fn get_listening_stats(
+    conn: &Connection,
+    space_id: Ulid,
+    range: TimeRange,
+) -> Result<ListeningStats, DbError> {
+    let plays_in_range = "FROM track_play p JOIN track t ON t.id = p.track_id
+                          WHERE t.space_id = ?1 AND p.played_at >= ?2 AND p.played_at < ?3";
+    let space_id = space_id.to_string();
+
+    let (total_plays, total_seconds_listened): (i64, i64) = conn.query_row(
+        &format!(
+            "SELECT COUNT(*), COALESCE(SUM(p.duration_listened), 0) {}",
+            plays_in_range
+        ),
+        rusqlite::params![space_id, range.start, range.end],
+        |row| Ok((row.get(0)?, row.get(1)?)),
+    )?;
+
+    let mut stmt = conn.prepare(&format!(
+        "SELECT t.id, t.title, t.artist, COUNT(*) AS plays, SUM(p.duration_listened) AS secs
+         {}
+         GROUP BY t.id
+         ORDER BY plays DESC, secs DESC, t.title ASC
+         LIMIT ?4",
+        plays_in_range
+    ))?;
+    let top_tracks = stmt
+        .query_map(
+            rusqlite::params![space_id, range.start, range.end, TOP_LISTENING_ENTRIES],
+            |row| {
+                Ok(TrackListening {
+                    track_id: Ulid::from_string(&row.get::<_, String>(0)?)
+                        .map_err(|e| rusqlite::Error::ToSqlConversionFailure(Box::new(e)))?,
+                    title: row.get(1)?,
+                    artist: row.get(2)?,
+                    plays: row.get(3)?,
+                    seconds_listened: row.get(4)?,
+                })
+            },
+        )?
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let mut stmt = conn.prepare(&format!(
+        "SELECT TRIM(t.artist) AS name, COUNT(*) AS plays, SUM(p.duration_listened) AS secs
+         {} AND TRIM(COALESCE(t.artist, '')) != ''
+         GROUP BY name COLLATE NOCASE
+         ORDER BY plays DESC, secs DESC, name ASC
+         LIMIT ?4",
+        plays_in_range
+    ))?;
+    let top_artists = stmt
+        .query_map(
+            rusqlite::params![space_id, range.start, range.end, TOP_LISTENING_ENTRIES],
+            |row| {
+                Ok(ArtistListening {
+                    artist: row.get(0)?,
+                    plays: row.get(1)?,
+                    seconds_listened: row.get(2)?,
+                })
+            },
+        )?
+        .collect::<Result<Vec<_>, _>>()?;
+
+    Ok(ListeningStats {
+        total_plays,
+        total_seconds_listened,
+        top_tracks,
+        top_artists,
+    })
+}
+
+/// Create a playlist whose rule-added entries are kept up to date by
+/// [`materialize_smart_playlist`]
+pub fn create_smart_playlist(
+    conn: &Connection,
+    space_id: Ulid,
+    name: &str,
+    condition: &SmartPlaylistCondition,
+) -> Result<Playlist, DbError> {
+    let now = chrono::Utc::now().timestamp();
+    let playlist = Playlist {
+        id: Ulid::new(),
+        space_id,
+        name: name.to_string(),
+        description: None,
+        artwork_url: None,
+        is_smart_playlist: true,
+        smart_criteria_json: Some(serde_json::to_string(condition)?),
+        created_at: now,
+        updated_at: now,
+    };
+
+    conn.execute(
+        "INSERT INTO playlist (id, space_id, name, is_smart_playlist, smart_criteria_json,
+                               created_at, updated_at)
+         VALUES (?1, ?2, ?3, 1, ?4, ?5, ?6)",
+        rusqlite::params![
+            &playlist.id.to_string(),
+            &playlist.space_id.to_string(),
+            &playlist.name,
+            &playlist.smart_criteria_json,
+            &playlist.created_at,
+            &playlist.updated_at
+        ],
+    )?;
+
+    Ok(playlist)
+}
+
+/// Replace a smart playlist's rule; takes effect on the next refresh
+pub fn update_smart_playlist_rule(
+    conn: &Connection,
+    playlist_id: Ulid,
+    condition: &SmartPlaylistCondition,
+) -> Result<(), DbError> {
+    let updated = conn.execute(
+        "UPDATE playlist SET is_smart_playlist = 1, smart_criteria_json = ?2, updated_at = ?3
+         WHERE id = ?1",
+        rusqlite::params![
+            playlist_id.to_string(),
+            serde_json::to_string(condition)?,
+            chrono::Utc::now().timestamp()
+        ],
+    )?;
+    if updated == 0 {
+        return Err(DbError::Message(format!(
+            "Playlist not found: {}",
+            playlist_id
+        )));
+    }
+    Ok(())
+}
+
+/// Add a track by hand. Refreshes never remove manual entries; a track the
+/// rule already added becomes manual.
+pub fn add_track_to_playlist(
+    conn: &Connection,
+    playlist_id: Ulid,
+    track_id: Ulid,
+) -> Result<(), DbError> {
+    conn.execute(
+        "INSERT INTO playlist_track (playlist_id, track_id, position, added_at, source)
+         VALUES (?1, ?2,
+                 (SELECT COALESCE(MAX(position), -1) + 1 FROM playlist_track WHERE playlist_id = ?1),
+                 ?3, 'manual')
+         ON CONFLICT(playlist_id, track_id) DO UPDATE SET source = 'manual'",
+        rusqlite::params![
+            playlist_id.to_string(),
+            track_id.to_string(),
+            chrono::Utc::now().timestamp()
+        ],
+    )?;
+    Ok(())
+}
+
+pub fn get_playlist_entries(
+    conn: &Connection,
+    playlist_id: Ulid,
+) -> Result<Vec<PlaylistEntry>, DbError> {
+    let mut stmt = conn.prepare(
+        "SELECT track_id, position, added_at, source FROM playlist_track
+         WHERE playlist_id = ?1
+         ORDER BY position ASC",
+    )?;
+    let entries = stmt
+        .query_map([playlist_id.to_string()], |row| {
+            Ok(PlaylistEntry {
+                track_id: Ulid::from_string(&row.get::<_, String>(0)?)
+                    .map_err(|e| rusqlite::Error::ToSqlConversionFailure(Box::new(e)))?,
+                position: row.get(1)?,
+                added_at: row.get(2)?,
+                source: if row.get::<_, String>(3)? == "rule" {
+                    PlaylistEntrySource::Rule
+                } else {
+                    PlaylistEntrySource::Manual
+                },
+            })
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(entries)
+}
+
+/// Evaluate a smart playlist's rule against the tracks of its space. Rule
+/// entries that no longer match are removed and new matches are appended;
+/// manual entries are left alone.
+pub fn materialize_smart_playlist(
+    conn: &Connection,
+    playlist_id: Ulid,
+) -> Result<SmartPlaylistRefresh, DbError> {
+    let playlist_id = playlist_id.to_string();
+    let tx = conn.unchecked_transaction()?;
+    let (space_id, criteria): (String, Option<String>) = tx
+        .query_row(
+            "SELECT space_id, smart_criteria_json FROM playlist
+             WHERE id = ?1 AND is_smart_playlist = 1",
+            [&playlist_id],
+            |row| Ok((row.get(0)?, row.get(1)?)),
+        )
+        .optional()?
+        .ok_or_else(|| DbError::Message(format!("Smart playlist not found: {}", playlist_id)))?;
+    let condition: SmartPlaylistCondition = match criteria {
+        Some(json) => serde_json::from_str(&json)?,
+        None => return Err(DbError::Message("Smart playlist has no rule".into())),
+    };
+
+    let mut tracks: Vec<RuleTrack> = tx
+        .prepare(
+            "SELECT id, artist, genre, added_at FROM track
+             WHERE space_id = ?1
+             ORDER BY added_at ASC, title ASC",
+        )?
+        .query_map([&space_id], |row| {
+            Ok(RuleTrack {
+                id: row.get(0)?,
+                artist: row.get(1)?,
+                genre: row.get(2)?,
+                added_at: row.get(3)?,
+                plays: Vec::new(),
+            })
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
+    let mut plays: HashMap<String, Vec<i64>> = HashMap::new();
+    let mut stmt = tx.prepare(
+        "SELECT p.track_id, p.played_at FROM track_play p
+         JOIN track t ON t.id = p.track_id
+         WHERE t.space_id = ?1",
+    )?;
+    let rows = stmt.query_map([&space_id], |row| {
+        Ok((row.get::<_, String>(0)?, row.get::<_, i64>(1)?))
+    })?;
+    for row in rows {
+        let (track_id, played_at) = row?;
+        plays.entry(track_id).or_default().push(played_at);
+    }
+    drop(stmt);
+    for track in &mut tracks {
+        track.plays = plays.remove(&track.id).unwrap_or_default();
+    }
+
+    let now = chrono::Utc::now().timestamp();
+    let matching: HashSet<&str> = tracks
+        .iter()
+        .filter(|t| condition.matches(t, now))
+        .map(|t| t.id.as_str())
+        .collect();
+    let current: HashMap<String, String> = tx
+        .prepare("SELECT track_id, source FROM playlist_track WHERE playlist_id = ?1")?
+        .query_map([&playlist_id], |row| Ok((row.get(0)?, row.get(1)?)))?
+        .collect::<Result<_, _>>()?;
+
+    let mut refresh = SmartPlaylistRefresh::default();
+    for (track_id, source) in &current {
+        if source == PlaylistEntrySource::Rule.as_str() && !matching.contains(track_id.as_str()) {
+            tx.execute(
+                "DELETE FROM playlist_track WHERE playlist_id = ?1 AND track_id = ?2",
+                [&playlist_id, track_id],
+            )?;
+            refresh.removed += 1;
+        }
+    }
+    let mut position: i64 = tx.query_row(
+        "SELECT COALESCE(MAX(position), -1) FROM playlist_track WHERE playlist_id = ?1",
+        [&playlist_id],
+        |row| row.get(0),
+    )?;
+    // Appended in library order, oldest first
+    for track in tracks.iter().filter(|t| matching.contains(t.id.as_str())) {
+        if current.contains_key(&track.id) {
+            continue;
+        }
+        position += 1;
+        tx.execute(
+            "INSERT INTO playlist_track (playlist_id, track_id, position, added_at, source)
+             VALUES (?1, ?2, ?3, ?4, ?5)",
+            rusqlite::params![
+                playlist_id,
+                track.id,
+                position,
+                now,
+                PlaylistEntrySource::Rule.as_str()
+            ],
+        )?;
+        refresh.added += 1;
+    }
+    refresh.total = current.len() - refresh.removed + refresh.added;
+    tx.commit()?;
+
+    log::info!(
+        "[music] Refreshed smart playlist {}: {} added, {} removed",
+        playlist_id,
+        refresh.added,
+        refresh.removed
+    );
+    Ok(refresh)
+}
@@ -1,8 +1,18 @@
+use crate::audit::{audit_change, AuditOperation, AUDIT_SOURCE_LOCAL};
+use crate::automation::{run_automation_from, AutomationEvent};
+use crate::collaboration::check_edit_lock;
+use crate::crdt::record_note_edit;
 use crate::db::DbError;
-use chrono::Utc;
+use crate::note_stats::{count_text, record_word_change};
+use crate::note_template::render_daily_note_template;
+use crate::permission::{
+    authorize, ActorContext, PERMISSION_DELETE, PERMISSION_READ, PERMISSION_WRITE,
+};
+use chrono::{NaiveDate, NaiveTime, Utc};
 use rusqlite::types::{FromSql, FromSqlResult, ValueRef};
-use rusqlite::{Connection, OptionalExtension, Result};
+use rusqlite::{Connection, OptionalExtension, Result, Transaction, TransactionBehavior};
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 use ulid::Ulid;
 
 use std::fmt::{Display, Formatter};
@@ -24,6 +34,12 @@ impl FromSql for DbUlid {
     }
 }
 
+/// Notes of the space `?1`, and notes of other spaces referenced in it, see
+/// [`crate::note_share::create_note_reference`]
+const IN_SPACE: &str = "(space_id = ?1 OR id IN (SELECT note_id FROM note_reference
+     WHERE space_id = ?1 AND removed_at IS NULL))";
+
+/// Recently modified notes of the space, including the ones referenced in it
 pub fn get_recent_notes(
     conn: &Connection,
     space_id: &str,
@@ -33,19 +49,9 @@ pub fn get_recent_notes(
         "[note] Getting recent notes for space with id: {}",
         space_id
     );
-    let mut stmt = conn.prepare("SELECT id, space_id, title, content_md, created_at, modified_at, is_trashed FROM note WHERE space_id = ?1 AND is_trashed = 0 ORDER BY modified_at DESC LIMIT ?2")?;
+    let mut stmt = conn.prepare_cached(&format!("SELECT id, space_id, title, content_md, created_at, modified_at, is_trashed, is_locked FROM note WHERE {} AND is_trashed = 0 ORDER BY modified_at DESC LIMIT ?2", IN_SPACE))?;
     let notes = stmt
-        .query_map([space_id, &limit.to_string()], |row| {
-            Ok(Note {
-                id: row.get(0)?,
-                space_id: row.get(1)?,
-                title: row.get(2)?,
-                content_md: row.get(3)?,
-                created_at: row.get(4)?,
-                modified_at: row.get(5)?,
-                is_trashed: row.get(6)?,
-            })
-        })?
+        .query_map([space_id, &limit.to_string()], Note::from_row)?
         .collect::<Result<Vec<Note>, _>>()?;
     log::info!("[note] Found {} recent notes", notes.len());
     Ok(notes)
@@ -60,6 +66,33 @@ pub struct Note {
     pub created_at: i64,
     pub modified_at: i64,
     pub is_trashed: bool,
+    /// Locked with a passphrase, see [`crate::note_lock`]. `content_md` is
+    /// empty until the note is unlocked.
+    #[serde(default)]
+    pub is_locked: bool,
+}
+
+impl Note {
+    /// Read a note selected as `id, space_id, title, content_md, created_at,
+    /// modified_at, is_trashed, is_locked`. A locked note's stored body is
+    /// ciphertext, so it is left out.
+    pub(crate) fn from_row(row: &rusqlite::Row) -> Result<Self> {
+        let is_locked: bool = row.get(7)?;
+        Ok(Note {
+            id: row.get(0)?,
+            space_id: row.get(1)?,
+            title: row.get(2)?,
+            content_md: if is_locked {
+                String::new()
+            } else {
+                row.get(3)?
+            },
+            created_at: row.get(4)?,
+            modified_at: row.get(5)?,
+            is_trashed: row.get(6)?,
+            is_locked,
+        })
+    }
 }
 
 pub fn create_note(
@@ -78,6 +111,7 @@ pub fn create_note(
         created_at: now,
         modified_at: now,
         is_trashed: false,
+        is_locked: false,
     };
 
     // Use explicit transaction if possible, or just implicit since we use last_insert_rowid immediately
@@ -97,41 +131,65 @@ pub fn create_note(
     // rusqlite  is not . So it can only be used by one thread at a time.
     // So  is safe.
 
+    let counts = count_text(&note.content_md);
     conn.execute(
-        "INSERT INTO note (id, space_id, title, content_md, created_at, modified_at, is_trashed) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
-        rusqlite::params![&note.id.0.to_string(), &note.space_id, &note.title, &note.content_md, &note.created_at, &note.modified_at, note.is_trashed],
+        "INSERT INTO note (id, space_id, title, content_md, created_at, modified_at, is_trashed, word_count, char_count) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)",
+        rusqlite::params![&note.id.0.to_string(), &note.space_id, &note.title, &note.content_md, &note.created_at, &note.modified_at, note.is_trashed, counts.words, counts.chars],
     )?;
 
     let rowid = conn.last_insert_rowid();
 
-    conn.execute(
-        "INSERT INTO fts_note (rowid, note_id, title, content_md) VALUES (?1, ?2, ?3, ?4)",
-        rusqlite::params![
-            rowid,
-            &note.id.0.to_string(),
-            &note.title.to_lowercase(),
-            &note.content_md
-        ],
+    // Bulk imports index all their notes in one pass when they finish
+    let bulk_import = crate::db::bulk::fts_deferred(conn)?;
+    if !bulk_import {
+        conn.execute(
+            "INSERT INTO fts_note (rowid, note_id, title, content_md) VALUES (?1, ?2, ?3, ?4)",
+            rusqlite::params![
+                rowid,
+                &note.id.0.to_string(),
+                &note.title.to_lowercase(),
+                &note.content_md
+            ],
+        )?;
+    }
+
+    mark_note_dirty(conn, &note.id.0.to_string())?;
+    record_note_edit(conn, &note.id.0.to_string(), &note.content_md)?;
+    record_word_change(
+        conn,
+        &note.id.0.to_string(),
+        &note.space_id,
+        counts.words,
+        now,
     )?;
+    sync_note_links(conn, note.id.0, &note.content_md)?;
+    audit_change(
+        conn,
+        &note.space_id,
+        "note",
+        &note.id.0.to_string(),
+        AuditOperation::Create,
+        AUDIT_SOURCE_LOCAL,
+    );
+    // Imported notes aren't new to the user, so they don't trigger rules
+    if !bulk_import {
+        run_automation_from(
+            conn,
+            AutomationEvent::NoteCreated {
+                note_id: note.id.0.to_string(),
+            },
+            AUDIT_SOURCE_LOCAL,
+        );
+    }
 
     Ok(note)
 }
 
 pub fn get_note(conn: &Connection, id: DbUlid) -> Result<Option<Note>, DbError> {
     log::info!("[note] Getting note with id: {}", id.0);
-    let mut stmt = conn.prepare("SELECT id, space_id, title, content_md, created_at, modified_at, is_trashed FROM note WHERE id = ?1 ORDER BY modified_at DESC LIMIT 1")?;
+    let mut stmt = conn.prepare_cached("SELECT id, space_id, title, content_md, created_at, modified_at, is_trashed, is_locked FROM note WHERE id = ?1 ORDER BY modified_at DESC LIMIT 1")?;
     let note: Option<Note> = stmt
-        .query_row([id.0.to_string()], |row| {
-            Ok(Note {
-                id: row.get(0)?,
-                space_id: row.get(1)?,
-                title: row.get(2)?,
-                content_md: row.get(3)?,
-                created_at: row.get(4)?,
-                modified_at: row.get(5)?,
-                is_trashed: row.get(6)?,
-            })
-        })
+        .query_row([id.0.to_string()], Note::from_row)
         .optional()
         .map_err(|e| {
             log::error!("[note] Error getting note with id: {}, error: {}", id.0, e);
@@ -152,11 +210,11 @@ pub fn update_note_content(
     let tx = conn.transaction()?;
 
     // Check if note exists first to return nice error
-    let rowid: i64 = tx
+    let (rowid, is_locked, space_id, old_words): (i64, bool, String, i64) = tx
         .query_row(
-            "SELECT rowid FROM note WHERE id = ?1",
+            "SELECT rowid, is_locked, space_id, word_count FROM note WHERE id = ?1",
             [id.0.to_string()],
-            |row| row.get(0),
+            |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?)),
         )
         .map_err(|e| {
             log::error!(
@@ -165,14 +223,26 @@ pub fn update_note_content(
                 e
             );
             match e {
-                rusqlite::Error::QueryReturnedNoRows => DbError::Message("Note not found".into()),
+                rusqlite::Error::QueryReturnedNoRows => DbError::NotFound {
+                    entity: "note",
+                    id: id.0.to_string(),
+                },
                 _ => DbError::Rusqlite(e),
             }
         })?;
+    // Saving would overwrite the ciphertext with whatever the editor holds
+    if is_locked {
+        return Err(DbError::Locked {
+            entity: "note",
+            id: id.0.to_string(),
+        });
+    }
 
+    let now = Utc::now().timestamp();
+    let counts = count_text(content_md);
     tx.execute(
-        "UPDATE note SET title = ?1, content_md = ?2, modified_at = ?3 WHERE id = ?4",
-        rusqlite::params![title, content_md, Utc::now().timestamp(), id.0.to_string(),],
+        "UPDATE note SET title = ?1, content_md = ?2, modified_at = ?3, word_count = ?4, char_count = ?5 WHERE id = ?6",
+        rusqlite::params![title, content_md, now, counts.words, counts.chars, id.0.to_string()],
     )?;
 
     tx.execute(
@@ -185,6 +255,18 @@ pub fn update_note_content(
         rusqlite::params![rowid, title.to_lowercase(), content_md, id.0.to_string()],
     )?;
 
+    mark_note_dirty(&tx, &id.0.to_string())?;
+    record_note_edit(&tx, &id.0.to_string(), content_md)?;
+    record_word_change(
+        &tx,
+        &id.0.to_string(),
+        &space_id,
+        counts.words - old_words,
+        now,
+    )?;
+    sync_note_links(&tx, id.0, content_md)?;
+    audit_note_change(&tx, &id, AuditOperation::Update)?;
+
     tx.commit()?;
 
     // Need to drop tx to use conn again
@@ -197,8 +279,11 @@ pub fn update_note_content(
     // And  signature takes .
     // So we are good.
 
-    let note = get_note(conn, id.clone())?.ok_or(DbError::Message("Note not found".into()))?;
-    handle_note_update(conn, &note.space_id, id, content_md)?;
+    let note = get_note(conn, id.clone())?.ok_or_else(|| DbError::NotFound {
+        entity: "note",
+        id: id.0.to_string(),
+    })?;
+    handle_note_update(conn, &note.space_id, id)?;
 
     Ok(())
 }
@@ -209,9 +294,14 @@ pub fn trash_note(conn: &Connection, id: DbUlid) -> Result<(), DbError> {
         "UPDATE note SET is_trashed = 1 WHERE id = ?1",
         [id.0.to_string()],
     )?;
+    mark_note_dirty(conn, &id.0.to_string())?;
+    // Notes are deleted by trashing them
+    audit_note_change(conn, &id, AuditOperation::Delete)?;
     Ok(())
 }
 
+use crate::ai::rag::mark_note_dirty;
+use crate::backlink::sync_note_links;
 use crate::meeting::extract_action_items;
 use crate::mode::get_space_modes;
 
@@ -221,69 +311,285 @@ pub fn restore_note(conn: &Connection, id: DbUlid) -> Result<(), DbError> {
         "UPDATE note SET is_trashed = 0 WHERE id = ?1",
         [id.0.to_string()],
     )?;
+    mark_note_dirty(conn, &id.0.to_string())?;
+    audit_note_change(conn, &id, AuditOperation::Update)?;
     Ok(())
 }
 
-fn handle_note_update(
+/// Bring what's derived from a note's stored title and body in line after
+/// the row was written directly, e.g. by a merge or an undo: word counts,
+/// the search index, the CRDT log, links and RAG chunks. Bumps
+/// `modified_at` so sync sends the note again.
+pub(crate) fn refresh_note(conn: &Connection, id: &str, now: i64) -> Result<(), DbError> {
+    let row: Option<(i64, String, String, String, bool, i64)> = conn
+        .query_row(
+            "SELECT rowid, space_id, title, content_md, is_locked, word_count FROM note WHERE id = ?1",
+            [id],
+            |row| {
+                Ok((
+                    row.get(0)?,
+                    row.get(1)?,
+                    row.get(2)?,
+                    row.get(3)?,
+                    row.get(4)?,
+                    row.get(5)?,
+                ))
+            },
+        )
+        .optional()?;
+    let Some((rowid, space_id, title, content_md, is_locked, old_words)) = row else {
+        return Ok(());
+    };
+    conn.execute(
+        "UPDATE note SET modified_at = ?1 WHERE id = ?2",
+        rusqlite::params![now, id],
+    )?;
+    conn.execute("DELETE FROM fts_note WHERE rowid = ?1", [rowid])?;
+    mark_note_dirty(conn, id)?;
+    // A locked body is ciphertext, nothing can be derived from it
+    if is_locked {
+        return Ok(());
+    }
+
+    let counts = count_text(&content_md);
+    conn.execute(
+        "UPDATE note SET word_count = ?1, char_count = ?2 WHERE id = ?3",
+        rusqlite::params![counts.words, counts.chars, id],
+    )?;
+    record_word_change(conn, id, &space_id, counts.words - old_words, now)?;
+    conn.execute(
+        "INSERT INTO fts_note(rowid, title, content_md, note_id) VALUES (?1, ?2, ?3, ?4)",
+        rusqlite::params![rowid, title.to_lowercase(), content_md, id],
+    )?;
+    record_note_edit(conn, id, &content_md)?;
+    let note_id = Ulid::from_string(id).map_err(|e| DbError::Message(e.to_string()))?;
+    sync_note_links(conn, note_id, &content_md)?;
+    Ok(())
+}
+
+/// [`create_note`] on behalf of `actor`, who needs `write_notes` in the space
+pub fn create_note_as(
     conn: &Connection,
+    actor: &ActorContext,
     space_id: &str,
-    note_id: DbUlid,
+    title: &str,
+    content_md: &str,
+) -> Result<Note, DbError> {
+    authorize::<DbError>(conn, actor, space_id, PERMISSION_WRITE)?;
+    create_note(conn, space_id, title, content_md)
+}
+
+/// [`get_note`] on behalf of `actor`, who needs `read_notes` in its space
+pub fn get_note_as(
+    conn: &Connection,
+    actor: &ActorContext,
+    id: DbUlid,
+) -> Result<Option<Note>, DbError> {
+    let Some(note) = get_note(conn, id)? else {
+        return Ok(None);
+    };
+    authorize::<DbError>(conn, actor, &note.space_id, PERMISSION_READ)?;
+    Ok(Some(note))
+}
+
+/// [`update_note_content`] on behalf of `actor`, who needs `write_notes` in
+/// the note's space and must not be locked out by another user's edit lock
+pub fn update_note_content_as(
+    conn: &mut Connection,
+    actor: &ActorContext,
+    id: DbUlid,
+    title: &str,
     content_md: &str,
 ) -> Result<(), DbError> {
+    let space_id = note_space_id(conn, &id)?;
+    authorize::<DbError>(conn, actor, &space_id, PERMISSION_WRITE)?;
+    check_edit_lock::<DbError>(conn, actor, &id.0.to_string())?;
+    update_note_content(conn, id, title, content_md)
+}
+
+/// [`trash_note`] on behalf of `actor`, who needs `delete_notes` in the
+/// note's space
+pub fn trash_note_as(conn: &Connection, actor: &ActorContext, id: DbUlid) -> Result<(), DbError> {
+    let space_id = note_space_id(conn, &id)?;
+    authorize::<DbError>(conn, actor, &space_id, PERMISSION_DELETE)?;
+    trash_note(conn, id)
+}
+
+/// [`restore_note`] on behalf of `actor`, who needs `write_notes` in the
+/// note's space
+pub fn restore_note_as(conn: &Connection, actor: &ActorContext, id: DbUlid) -> Result<(), DbError> {
+    let space_id = note_space_id(conn, &id)?;
+    authorize::<DbError>(conn, actor, &space_id, PERMISSION_WRITE)?;
+    restore_note(conn, id)
+}
+
+fn note_space_id(conn: &Connection, id: &DbUlid) -> Result<String, DbError> {
+    conn.query_row(
+        "SELECT space_id FROM note WHERE id = ?1",
+        [id.0.to_string()],
+        |row| row.get(0),
+    )
+    .optional()?
+    .ok_or_else(|| DbError::NotFound {
+        entity: "note",
+        id: id.0.to_string(),
+    })
+}
+
+fn audit_note_change(
+    conn: &Connection,
+    id: &DbUlid,
+    operation: AuditOperation,
+) -> Result<(), DbError> {
+    let space_id: Option<String> = conn
+        .query_row(
+            "SELECT space_id FROM note WHERE id = ?1",
+            [id.0.to_string()],
+            |row| row.get(0),
+        )
+        .optional()?;
+    if let Some(space_id) = space_id {
+        audit_change(
+            conn,
+            &space_id,
+            "note",
+            &id.0.to_string(),
+            operation,
+            AUDIT_SOURCE_LOCAL,
+        );
+    }
+    Ok(())
+}
+
+fn handle_note_update(conn: &Connection, space_id: &str, note_id: DbUlid) -> Result<(), DbError> {
     let modes = get_space_modes(conn, space_id)?;
     if modes.iter().any(|m| m.id == "meeting-notes") {
-        extract_action_items(conn, space_id, &note_id.0.to_string(), content_md)
+        // Items extracted on an earlier save are skipped
+        extract_action_items(conn, &note_id.0.to_string())
             .map_err(|e| DbError::Message(e.to_string()))?;
     }
     Ok(())
 }
 
+/// Notes of the space, including the ones referenced in it; a referenced
+/// note keeps the `space_id` it belongs to
 pub fn get_all_notes_in_space(conn: &Connection, space_id: &str) -> Result<Vec<Note>, DbError> {
     log::info!("[note] Getting all notes for space with id: {}", space_id);
-    let mut stmt = conn.prepare("SELECT id, space_id, title, content_md, created_at, modified_at, is_trashed FROM note WHERE space_id = ?1 AND is_trashed = 0")?;
+    let mut stmt = conn.prepare_cached(&format!("SELECT id, space_id, title, content_md, created_at, modified_at, is_trashed, is_locked FROM note WHERE {} AND is_trashed = 0", IN_SPACE))?;
     let notes = stmt
-        .query_map([space_id], |row| {
-            Ok(Note {
-                id: row.get(0)?,
-                space_id: row.get(1)?,
-                title: row.get(2)?,
-                content_md: row.get(3)?,
-                created_at: row.get(4)?,
-                modified_at: row.get(5)?,
-                is_trashed: row.get(6)?,
-            })
-        })?
+        .query_map([space_id], Note::from_row)?
         .collect::<Result<Vec<Note>, _>>()?;
     log::info!("[note] Found {} notes", notes.len());
     Ok(notes)
 }
 
+/// Today's (UTC) daily note, see [`get_or_create_daily_note_for_date`]
 pub fn get_or_create_daily_note(conn: &Connection, space_id: &str) -> Result<Note, DbError> {
-    let today = Utc::now().format("%Y-%m-%d").to_string();
-    let title = format!("Daily Note - {}", today);
+    get_or_create_daily_note_for_date(conn, space_id, Utc::now().date_naive())
+}
 
-    let mut stmt = conn.prepare("SELECT id, space_id, title, content_md, created_at, modified_at, is_trashed FROM note WHERE title = ?1 AND space_id = ?2")?;
-    let note: Option<Note> = stmt
-        .query_row([&title, space_id], |row| {
-            Ok(Note {
-                id: row.get(0)?,
-                space_id: row.get(1)?,
-                title: row.get(2)?,
-                content_md: row.get(3)?,
-                created_at: row.get(4)?,
-                modified_at: row.get(5)?,
-                is_trashed: row.get(6)?,
-            })
-        })
-        .optional()?;
+/// The space's daily note for `date`, created from the daily note template,
+/// or the built-in layout, when there is none yet.
+///
+/// Daily notes are identified by `note.daily_date`. A note that only has the
+/// daily title, e.g. one created by hand, becomes the day's note; one in the
+/// trash stops being it and a fresh note is created.
+pub fn get_or_create_daily_note_for_date(
+    conn: &Connection,
+    space_id: &str,
+    date: NaiveDate,
+) -> Result<Note, DbError> {
+    let key = date.format("%Y-%m-%d").to_string();
+    if let Some(id) = find_daily_note(conn, space_id, &key)? {
+        return get_note(conn, id.clone())?.ok_or_else(|| DbError::NotFound {
+            entity: "note",
+            id: id.0.to_string(),
+        });
+    }
 
-    if let Some(note) = note {
-        Ok(note)
-    } else {
-        let content = format!(
-            "# Daily Note - {}\n\n## Tasks\n\n- [ ] \n\n## Notes\n\n",
-            today
-        );
-        create_note(conn, space_id, &title, &content)
+    // Taking the write lock first keeps two callers from creating the same day
+    let tx = Transaction::new_unchecked(conn, TransactionBehavior::Immediate)?;
+    if find_daily_note(&tx, space_id, &key)?.is_none() {
+        let title = format!("Daily Note - {}", key);
+        tx.execute(
+            "UPDATE note SET daily_date = NULL
+             WHERE space_id = ?1 AND daily_date = ?2 AND is_trashed = 1",
+            rusqlite::params![space_id, key],
+        )?;
+        let adopted = tx.execute(
+            "UPDATE note SET daily_date = ?2
+             WHERE id = (SELECT id FROM note
+                         WHERE space_id = ?1 AND title = ?3 AND daily_date IS NULL
+                           AND is_trashed = 0
+                         ORDER BY created_at, rowid LIMIT 1)",
+            rusqlite::params![space_id, key, title],
+        )?;
+        if adopted == 0 {
+            let now = Utc::now();
+            let at = if date == now.date_naive() {
+                now.naive_utc()
+            } else {
+                date.and_time(NaiveTime::MIN)
+            };
+            let templated = render_daily_note_template(&tx, space_id, at)
+                .map_err(|e| DbError::Message(e.to_string()))?;
+            let content = templated
+                .unwrap_or_else(|| format!("# {}\n\n## Tasks\n\n- [ ] \n\n## Notes\n\n", title));
+            let note = create_note(&tx, space_id, &title, &content)?;
+            tx.execute(
+                "UPDATE note SET daily_date = ?1 WHERE id = ?2",
+                rusqlite::params![key, note.id.0.to_string()],
+            )?;
+        }
     }
+    tx.commit()?;
+
+    let id = find_daily_note(conn, space_id, &key)?
+        .ok_or_else(|| DbError::Message("Daily note not found".into()))?;
+    get_note(conn, id.clone())?.ok_or_else(|| DbError::NotFound {
+        entity: "note",
+        id: id.0.to_string(),
+    })
+}
+
+fn find_daily_note(
+    conn: &Connection,
+    space_id: &str,
+    date: &str,
+) -> Result<Option<DbUlid>, DbError> {
+    Ok(conn
+        .query_row(
+            "SELECT id FROM note WHERE space_id = ?1 AND daily_date = ?2 AND is_trashed = 0",
+            rusqlite::params![space_id, date],
+            |row| row.get(0),
+        )
+        .optional()?)
+}
+
+/// Daily notes of the space dated `start` through `end`, as date
+/// ("YYYY-MM-DD") to note id. Days without a note are missing.
+pub fn get_daily_notes_in_range(
+    conn: &Connection,
+    space_id: &str,
+    start: NaiveDate,
+    end: NaiveDate,
+) -> Result<BTreeMap<String, String>, DbError> {
+    if end < start {
+        return Err(DbError::Message("Range ends before it starts".into()));
+    }
+    let mut stmt = conn.prepare_cached(
+        "SELECT daily_date, id FROM note
+         WHERE space_id = ?1 AND daily_date BETWEEN ?2 AND ?3 AND is_trashed = 0",
+    )?;
+    let notes = stmt
+        .query_map(
+            rusqlite::params![
+                space_id,
+                start.format("%Y-%m-%d").to_string(),
+                end.format("%Y-%m-%d").to_string()
+            ],
+            |row| Ok((row.get(0)?, row.get(1)?)),
+        )?
+        .collect::<Result<BTreeMap<String, String>, _>>()?;
+    Ok(notes)
 }
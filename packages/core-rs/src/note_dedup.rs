@@ -0,0 +1,953 @@
+//! Duplicate notes
+//!
+//! Candidates are found by title similarity and by MinHash signatures of the
+//! notes' word shingles. Signatures are cached in `note_minhash` and only
+//! recomputed for notes whose body changed, and only notes sharing a title
+//! or a band of their signature are compared, so repeat runs over a large
+//! space stay cheap.
+//!
+//! Merging folds duplicates into a primary note: their content as the
+//! [`MergeStrategy`] says, the links pointing at them, their tags, SRS
+//! cards, time entries and attachments. The duplicates go to the trash and
+//! the merge can be undone from the undo journal. A `note_merge` record is
+//! synced alongside the merged body so peers move the same relations and
+//! trash the same notes.
+
+use crate::audit::{audit_change, AuditOperation, AUDIT_SOURCE_LOCAL};
+use crate::db::{numbered_placeholders, placeholders, DbError};
+use crate::note::refresh_note;
+use crate::undo::{capture_rows, record_undo, UndoRecord, UndoSnapshot};
+use chrono::Utc;
+use regex::Regex;
+use rusqlite::types::Value;
+use rusqlite::{params, params_from_iter, Connection, OptionalExtension};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeSet, HashMap, HashSet};
+use thiserror::Error;
+use ulid::Ulid;
+
+/// Entity type of the sync deltas carrying merges
+pub const NOTE_MERGE_ENTITY_TYPE: &str = "note_merge";
+/// Similarity above which [`find_duplicate_notes`] callers usually treat
+/// notes as duplicates
+pub const DEFAULT_DUPLICATE_THRESHOLD: f64 = 0.8;
+/// Hash functions in a signature
+const NUM_HASHES: usize = 64;
+/// Signature slots per LSH band; notes sharing any band are compared
+const BAND_ROWS: usize = 4;
+/// Words per shingle
+const SHINGLE_WORDS: usize = 3;
+/// Share of the title in a pair's similarity; the rest is the content's
+const TITLE_WEIGHT: f64 = 0.3;
+
+#[derive(Error, Debug)]
+pub enum NoteDedupError {
+    #[error("Rusqlite error: {0}")]
+    Rusqlite(#[from] rusqlite::Error),
+    #[error("Database error: {0}")]
+    Db(#[from] DbError),
+    #[error("Note not found: {0}")]
+    NotFound(String),
+    #[error("Threshold must be above 0 and at most 1, got {0}")]
+    InvalidThreshold(f64),
+    #[error("Invalid merge: {0}")]
+    InvalidMerge(String),
+    #[error("Note is locked: {0}")]
+    Locked(String),
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DuplicateNote {
+    pub id: String,
+    pub title: String,
+    pub created_at: i64,
+    pub modified_at: i64,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DuplicateCluster {
+    /// Oldest first, the usual choice of primary
+    pub notes: Vec<DuplicateNote>,
+    /// Lowest similarity of the pairs that put the notes together, 0 to 1
+    pub similarity: f64,
+}
+
+/// What happens to the content of merged duplicates
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MergeStrategy {
+    /// The primary keeps its content as it is
+    #[default]
+    KeepPrimary,
+    /// Sections of the duplicates the primary lacks are appended to it. A
+    /// section is a block between blank lines, e.g. a heading, a paragraph
+    /// or a list.
+    AppendDiffering,
+}
+
+impl MergeStrategy {
+    fn as_str(&self) -> &'static str {
+        match self {
+            MergeStrategy::KeepPrimary => "keep_primary",
+            MergeStrategy::AppendDiffering => "append_differing",
+        }
+    }
+
+    fn parse(s: &str) -> Option<Self> {
+        match s {
+            "keep_primary" => Some(MergeStrategy::KeepPrimary),
+            "append_differing" => Some(MergeStrategy::AppendDiffering),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct NoteMergeSummary {
+    pub merge_id: String,
+    pub primary_id: String,
+    pub duplicate_ids: Vec<String>,
+    /// Sections of the duplicates appended to the primary
+    pub appended_sections: usize,
+    /// Notes whose links now point at the primary
+    pub relinked_notes: usize,
+    /// Tags of the duplicates the primary didn't have
+    pub tags_added: usize,
+    pub cards_moved: usize,
+    pub time_entries_moved: usize,
+    /// Attachments of the duplicates the primary didn't have
+    pub attachments_added: usize,
+}
+
+/// FNV-1a, stable across builds unlike the std hasher
+fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf2_9ce4_8422_2325, |hash, &b| {
+        (hash ^ b as u64).wrapping_mul(0x0100_0000_01b3)
+    })
+}
+
+/// SplitMix64 finalizer, turning one shingle hash into many
+fn mix(mut x: u64) -> u64 {
+    x ^= x >> 30;
+    x = x.wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    x ^= x >> 27;
+    x = x.wrapping_mul(0x94d0_49bb_1331_11eb);
+    x ^ (x >> 31)
+}
+
+fn words(text: &str) -> Vec<String> {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|w| !w.is_empty())
+        .map(str::to_lowercase)
+        .collect()
+}
+
+fn shingles(content: &str) -> HashSet<u64> {
+    let words = words(content);
+    if words.len() < SHINGLE_WORDS {
+        return (!words.is_empty())
+            .then(|| fnv1a(words.join(" ").as_bytes()))
+            .into_iter()
+            .collect();
+    }
+    words
+        .windows(SHINGLE_WORDS)
+        .map(|w| fnv1a(w.join(" ").as_bytes()))
+        .collect()
+}
+
+/// MinHash signature of the content; empty when it has no words
+fn signature(content: &str) -> Vec<u64> {
+    let shingles = shingles(content);
+    if shingles.is_empty() {
+        return Vec::new();
+    }
+    (0..NUM_HASHES as u64)
+        .map(|i| {
+            let seed = (i + 1).wrapping_mul(0x9e37_79b9_7f4a_7c15);
+            shingles
+                .iter()
+                .map(|&h| mix(h ^ seed))
+                .min()
+                .unwrap_or(u64::MAX)
+        })
+        .collect()
+}
+
+fn encode_signature(signature: &[u64]) -> Vec<u8> {
+    signature.iter().flat_map(|h| h.to_le_bytes()).collect()
+}
+
+fn decode_signature(bytes: &[u8]) -> Option<Vec<u64>> {
+    if !bytes.is_empty() && bytes.len() != NUM_HASHES * 8 {
+        return None;
+    }
+    Some(
+        bytes
+            .chunks_exact(8)
+            .map(|chunk| u64::from_le_bytes(chunk.try_into().expect("8 byte chunk")))
+            .collect(),
+    )
+}
+
+/// Estimated Jaccard similarity of the shingles; `None` when neither note
+/// has any words
+fn content_similarity(a: &[u64], b: &[u64]) -> Option<f64> {
+    match (a.is_empty(), b.is_empty()) {
+        (true, true) => None,
+        (true, false) | (false, true) => Some(0.0),
+        (false, false) => {
+            let equal = a.iter().zip(b).filter(|(x, y)| x == y).count();
+            Some(equal as f64 / NUM_HASHES as f64)
+        }
+    }
+}
+
+fn title_key(title: &str) -> String {
+    words(title).join(" ")
+}
+
+/// Jaccard similarity of the character trigrams of two title keys
+fn title_similarity(a: &str, b: &str) -> f64 {
+    if a.is_empty() || b.is_empty() {
+        return 0.0;
+    }
+    if a == b {
+        return 1.0;
+    }
+    let trigrams = |s: &str| {
+        let chars: Vec<char> = format!("  {} ", s).chars().collect();
+        chars
+            .windows(3)
+            .map(|w| w.iter().collect::<String>())
+            .collect::<HashSet<_>>()
+    };
+    let (a, b) = (trigrams(a), trigrams(b));
+    a.intersection(&b).count() as f64 / a.union(&b).count() as f64
+}
+
+struct Candidate {
+    note: DuplicateNote,
+    title_key: String,
+    signature: Vec<u64>,
+}
+
+impl Candidate {
+    fn similarity(&self, other: &Candidate) -> f64 {
+        let title = title_similarity(&self.title_key, &other.title_key);
+        match content_similarity(&self.signature, &other.signature) {
+            Some(content) => TITLE_WEIGHT * title + (1.0 - TITLE_WEIGHT) * content,
+            None => title,
+        }
+    }
+}
+
+/// Notes of the space that can be merged, oldest first, with their
+/// signatures; stale or missing signatures are computed and cached
+fn load_candidates(conn: &Connection, space_id: &str) -> Result<Vec<Candidate>, NoteDedupError> {
+    let mut stmt = conn.prepare(
+        "SELECT n.id, n.title, n.created_at, n.modified_at, n.content_md,
+                m.content_hash, m.signature
+         FROM note n LEFT JOIN note_minhash m ON m.note_id = n.id
+         WHERE n.space_id = ?1 AND n.is_trashed = 0 AND n.is_locked = 0
+         ORDER BY n.created_at, n.id",
+    )?;
+    let rows = stmt
+        .query_map([space_id], |row| {
+            Ok((
+                DuplicateNote {
+                    id: row.get(0)?,
+                    title: row.get(1)?,
+                    created_at: row.get(2)?,
+                    modified_at: row.get(3)?,
+                },
+                row.get::<_, String>(4)?,
+                row.get::<_, Option<i64>>(5)?,
+                row.get::<_, Option<Vec<u8>>>(6)?,
+            ))
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let mut store = conn.prepare(
+        "INSERT INTO note_minhash (note_id, content_hash, signature) VALUES (?1, ?2, ?3)
+         ON CONFLICT(note_id) DO UPDATE SET
+             content_hash = excluded.content_hash, signature = excluded.signature",
+    )?;
+    let mut computed = 0;
+    let mut candidates = Vec::with_capacity(rows.len());
+    for (note, content, cached_hash, cached) in rows {
+        // The hash is stored as SQLite's signed integer
+        let hash = fnv1a(content.as_bytes()) as i64;
+        let cached = cached
+            .filter(|_| cached_hash == Some(hash))
+            .and_then(|bytes| decode_signature(&bytes));
+        let signature = match cached {
+            Some(signature) => signature,
+            None => {
+                let signature = signature(&content);
+                store.execute(params![note.id, hash, encode_signature(&signature)])?;
+                computed += 1;
+                signature
+            }
+        };
+        candidates.push(Candidate {
+            title_key: title_key(&note.title),
+            note,
+            signature,
+        });
+    }
+    log::info!(
+        "[dedup] Loaded {} notes, computed {} signatures",
+        candidates.len(),
+        computed
+    );
+    Ok(candidates)
+}
+
+/// Pairs of notes worth comparing: same title, or a shared signature band
+fn candidate_pairs(candidates: &[Candidate]) -> BTreeSet<(usize, usize)> {
+    let mut buckets: HashMap<(usize, u64), Vec<usize>> = HashMap::new();
+    let mut titles: HashMap<&str, Vec<usize>> = HashMap::new();
+    for (i, candidate) in candidates.iter().enumerate() {
+        if !candidate.title_key.is_empty() {
+            titles.entry(&candidate.title_key).or_default().push(i);
+        }
+        for (band, rows) in candidate.signature.chunks(BAND_ROWS).enumerate() {
+            buckets
+                .entry((band, fnv1a(&encode_signature(rows))))
+                .or_default()
+                .push(i);
+        }
+    }
+    let mut pairs = BTreeSet::new();
+    for members in buckets.values().chain(titles.values()) {
+        for (n, &a) in members.iter().enumerate() {
+            for &b in &members[n + 1..] {
+                pairs.insert((a, b));
+            }
+        }
+    }
+    pairs
+}
+
+fn find_root(parents: &mut [usize], mut i: usize) -> usize {
+    while parents[i] != i {
+        parents[i] = parents[parents[i]];
+        i = parents[i];
+    }
+    i
+}
+
+/// Groups of notes in the space at least `threshold` similar to each other,
+/// most similar first. Similarity weighs title trigrams against the
+/// estimated overlap of the notes' three-word shingles; trashed and locked
+/// notes are left out.
+pub fn find_duplicate_notes(
+    conn: &Connection,
+    space_id: &str,
+    threshold: f64,
+) -> Result<Vec<DuplicateCluster>, NoteDedupError> {
+    if !(threshold > 0.0 && threshold <= 1.0) {
+        return Err(NoteDedupError::InvalidThreshold(threshold));
+    }
+    let candidates = load_candidates(conn, space_id)?;
+
+    let mut parents: Vec<usize> = (0..candidates.len()).collect();
+    let mut edges = Vec::new();
+    for (a, b) in candidate_pairs(&candidates) {
+        let similarity = candidates[a].similarity(&candidates[b]);
+        if similarity >= threshold {
+            let (root_a, root_b) = (find_root(&mut parents, a), find_root(&mut parents, b));
+            parents[root_b] = root_a;
+            edges.push((a, similarity));
+        }
+    }
+
+    let mut clusters: HashMap<usize, (Vec<usize>, f64)> = HashMap::new();
+    for (a, similarity) in edges {
+        let root = find_root(&mut parents, a);
+        let cluster = clusters.entry(root).or_insert((Vec::new(), 1.0));
+        cluster.1 = cluster.1.min(similarity);
+    }
+    for i in 0..candidates.len() {
+        let root = find_root(&mut parents, i);
+        if let Some((members, _)) = clusters.get_mut(&root) {
+            members.push(i);
+        }
+    }
+
+    let mut clusters = clusters
+        .into_values()
+        .map(|(members, similarity)| DuplicateCluster {
+            notes: members
+                .into_iter()
+                .map(|i| candidates[i].note.clone())
+                .collect(),
+            similarity,
+        })
+        .collect::<Vec<_>>();
+    clusters.sort_by(|a, b| {
+        b.similarity
+            .total_cmp(&a.similarity)
+            .then_with(|| a.notes[0].created_at.cmp(&b.notes[0].created_at))
+    });
+    Ok(clusters)
+}
+
+/// Blocks of text between blank lines
+fn sections(content: &str) -> Vec<String> {
+    let mut sections = Vec::new();
+    let mut current: Vec<&str> = Vec::new();
+    for line in content.lines() {
+        if line.trim().is_empty() {
+            if !current.is_empty() {
+                sections.push(current.join("\n"));
+                current.clear();
+            }
+        } else {
+            current.push(line);
+        }
+    }
+    if !current.is_empty() {
+        sections.push(current.join("\n"));
+    }
+    sections
+}
+
+fn section_key(section: &str) -> String {
+    section.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+/// The primary's content followed by the sections of the duplicates it
+/// lacks, and how many were appended
+fn append_differing(primary: &str, duplicates: &[String]) -> (String, usize) {
+    let mut seen: HashSet<String> = sections(primary).iter().map(|s| section_key(s)).collect();
+    let mut merged = primary.trim_end().to_string();
+    let mut appended = 0;
+    for duplicate in duplicates {
+        for section in sections(duplicate) {
+            if seen.insert(section_key(&section)) {
+                if !merged.is_empty() {
+                    merged.push_str("\n\n");
+                }
+                merged.push_str(&section);
+                appended += 1;
+            }
+        }
+    }
+    if appended == 0 {
+        return (primary.to_string(), 0);
+    }
+    merged.push('\n');
+    (merged, appended)
+}
+
+/// Point the links of `content` at `old_id` or titled `old_title` at the
+/// primary instead. Returns `None` when nothing changed.
+fn retarget_links(
+    content: &str,
+    old_id: &str,
+    old_title: &str,
+    new_id: &str,
+    new_title: &str,
+) -> Option<String> {
+    let wiki = Regex::new(r"\[\[([^\[\]]+?)\]\]").expect("valid regex");
+    let old_title = old_title.trim().to_lowercase();
+    let retargeted = wiki.replace_all(content, |cap: &regex::Captures| {
+        let inner = &cap[1];
+        let end = inner.find(|c| c == '|' || c == '#').unwrap_or(inner.len());
+        let target = inner[..end].trim();
+        if !old_title.is_empty() && target.to_lowercase() == old_title {
+            format!("[[{}{}]]", new_title, &inner[end..])
+        } else {
+            cap[0].to_string()
+        }
+    });
+    // Links by id, wiki or markdown
+    let retargeted = retargeted.replace(old_id, new_id);
+    (retargeted != content).then_some(retargeted)
+}
+
+/// `?{first}, ?{first + 1}, ...` for `count` parameters
+/// The primary's id as `?1`, then the duplicates' ids
+fn merge_params(primary_id: &str, duplicate_ids: &[String]) -> Vec<Value> {
+    std::iter::once(primary_id)
+        .chain(duplicate_ids.iter().map(String::as_str))
+        .map(|id| Value::Text(id.to_string()))
+        .collect()
+}
+
+struct MovedRelations {
+    relinked_notes: usize,
+    tags_added: usize,
+    cards_moved: usize,
+    time_entries_moved: usize,
+    attachments_added: usize,
+}
+
+/// Move what points at the duplicates over to the primary and trash the
+/// duplicates. Shared by local merges and merges received through sync.
+fn move_relations(
+    conn: &Connection,
+    primary_id: &str,
+    duplicate_ids: &[String],
+) -> Result<MovedRelations, NoteDedupError> {
+    let dups = numbered_placeholders(2, duplicate_ids.len());
+    let ids = merge_params(primary_id, duplicate_ids);
+    let run = |sql: String| conn.execute(&sql, params_from_iter(ids.iter()));
+
+    let moved = MovedRelations {
+        relinked_notes: run(format!(
+            "INSERT OR IGNORE INTO link (source_note_id, target_note_id)
+             SELECT DISTINCT source_note_id, ?1 FROM link
+             WHERE target_note_id IN ({dups}) AND source_note_id <> ?1
+               AND source_note_id NOT IN ({dups})"
+        ))?,
+        tags_added: run(format!(
+            "INSERT OR IGNORE INTO note_tags (note_id, tag_id)
+             SELECT ?1, tag_id FROM note_tags WHERE note_id IN ({dups})"
+        ))?,
+        cards_moved: run(format!(
+            "UPDATE knowledge_card SET note_id = ?1 WHERE note_id IN ({dups})"
+        ))?,
+        time_entries_moved: run(format!(
+            "UPDATE time_entry SET note_id = ?1 WHERE note_id IN ({dups})"
+        ))?,
+        attachments_added: run(format!(
+            "INSERT OR IGNORE INTO note_attachment (note_id, blob_id, filename, created_at)
+             SELECT ?1, blob_id, filename, created_at FROM note_attachment
+             WHERE note_id IN ({dups})"
+        ))?,
+    };
+    // References that aren't attachments, e.g. images embedded in the body
+    run(format!(
+        "INSERT OR IGNORE INTO blob_ref (blob_id, owner_type, owner_id, created_at)
+         SELECT blob_id, 'note', ?1, created_at FROM blob_ref
+         WHERE owner_type = 'note' AND owner_id IN ({dups})"
+    ))?;
+    run(format!("DELETE FROM link WHERE target_note_id IN ({dups})"))?;
+    run(format!("DELETE FROM note_tags WHERE note_id IN ({dups})"))?;
+    run(format!(
+        "DELETE FROM note_attachment WHERE note_id IN ({dups})"
+    ))?;
+    run(format!(
+        "DELETE FROM blob_ref WHERE owner_type = 'note' AND owner_id IN ({dups})"
+    ))?;
+    run(format!(
+        "UPDATE note SET is_trashed = 1 WHERE id IN ({dups})"
+    ))?;
+    for id in duplicate_ids {
+        crate::ai::rag::mark_note_dirty(conn, id)?;
+    }
+    Ok(moved)
+}
+
+/// Rows the merge will change, delete and add, for the undo journal
+fn capture_merge(
+    conn: &Connection,
+    primary_id: &str,
+    duplicate_ids: &[String],
+    linking_ids: &[String],
+) -> Result<UndoSnapshot, NoteDedupError> {
+    let dups = numbered_placeholders(2, duplicate_ids.len());
+    let ids = merge_params(primary_id, duplicate_ids);
+    let capture = |table: &str, columns: &str, filter: String| {
+        capture_rows(conn, table, columns, &filter, params_from_iter(ids.iter()))
+    };
+
+    let notes = std::iter::once(primary_id)
+        .chain(duplicate_ids.iter().map(String::as_str))
+        .chain(linking_ids.iter().map(String::as_str))
+        .map(|id| Value::Text(id.to_string()))
+        .collect::<Vec<_>>();
+    let mut snapshot = UndoSnapshot::default();
+    snapshot.modified.extend(capture_rows(
+        conn,
+        "note",
+        "id, title, content_md, is_trashed",
+        &format!("id IN ({})", placeholders(notes.len())),
+        params_from_iter(notes.iter()),
+    )?);
+    snapshot.modified.extend(capture(
+        "knowledge_card",
+        "id, note_id",
+        format!("note_id IN ({dups})"),
+    )?);
+    snapshot.modified.extend(capture(
+        "time_entry",
+        "id, note_id",
+        format!("note_id IN ({dups})"),
+    )?);
+
+    // Restored in order: blob references before the attachments whose
+    // trigger would otherwise add them first
+    for (table, filter) in [
+        ("link", format!("target_note_id IN ({dups})")),
+        ("note_tags", format!("note_id IN ({dups})")),
+        (
+            "blob_ref",
+            format!("owner_type = 'note' AND owner_id IN ({dups})"),
+        ),
+        ("note_attachment", format!("note_id IN ({dups})")),
+    ] {
+        snapshot.deleted.extend(capture(table, "*", filter)?);
+    }
+
+    for (table, columns, filter) in [
+        (
+            "link",
+            "source_note_id, ?1 AS target_note_id",
+            format!(
+                "target_note_id IN ({dups}) AND source_note_id <> ?1
+                 AND source_note_id NOT IN ({dups})
+                 AND source_note_id NOT IN (SELECT source_note_id FROM link WHERE target_note_id = ?1)"
+            ),
+        ),
+        (
+            "note_tags",
+            "?1 AS note_id, tag_id",
+            format!(
+                "note_id IN ({dups})
+                 AND tag_id NOT IN (SELECT tag_id FROM note_tags WHERE note_id = ?1)"
+            ),
+        ),
+        (
+            "blob_ref",
+            "blob_id, owner_type, ?1 AS owner_id",
+            format!(
+                "owner_type = 'note' AND owner_id IN ({dups}) AND blob_id NOT IN
+                 (SELECT blob_id FROM blob_ref WHERE owner_type = 'note' AND owner_id = ?1)"
+            ),
+        ),
+        (
+            "note_attachment",
+            "?1 AS note_id, blob_id",
+            format!(
+                "note_id IN ({dups})
+                 AND blob_id NOT IN (SELECT blob_id FROM note_attachment WHERE note_id = ?1)"
+            ),
+        ),
+    ] {
+        snapshot.inserted.extend(capture(table, columns, filter)?);
+    }
+    Ok(snapshot)
+}
+
+struct MergeNote {
+    id: String,
+    space_id: String,
+    title: String,
+    content_md: String,
+}
+
+fn load_merge_note(conn: &Connection, id: &str) -> Result<MergeNote, NoteDedupError> {
+    let (note, is_trashed, is_locked) = conn
+        .query_row(
+            "SELECT id, space_id, title, content_md, is_trashed, is_locked FROM note WHERE id = ?1",
+            [id],
+            |row| {
+                Ok((
+                    MergeNote {
+                        id: row.get(0)?,
+                        space_id: row.get(1)?,
+                        title: row.get(2)?,
+                        content_md: row.get(3)?,
+                    },
+                    row.get::<_, bool>(4)?,
+                    row.get::<_, bool>(5)?,
+                ))
+            },
+        )
+        .optional()?
+        .ok_or_else(|| NoteDedupError::NotFound(id.to_string()))?;
+    if is_locked {
+        return Err(NoteDedupError::Locked(id.to_string()));
+    }
+    if is_trashed {
+        return Err(NoteDedupError::InvalidMerge(format!(
+            "note {} is in the trash",
+            id
+        )));
+    }
+    Ok(note)
+}
+
+/// Merge `duplicate_ids` into `primary_id`: content per `strategy`, links
+/// from other notes (rewritten when they name a duplicate), tags, SRS
+/// cards, time entries and attachments move to the primary, and the
+/// duplicates are trashed. The merge is journaled and can be undone.
+pub fn merge_notes(
+    conn: &mut Connection,
+    primary_id: &str,
+    duplicate_ids: &[String],
+    strategy: MergeStrategy,
+) -> Result<NoteMergeSummary, NoteDedupError> {
+    let mut duplicate_ids = duplicate_ids.to_vec();
+    duplicate_ids.sort();
+    duplicate_ids.dedup();
+    if duplicate_ids.is_empty() {
+        return Err(NoteDedupError::InvalidMerge("no duplicates".to_string()));
+    }
+    if duplicate_ids.iter().any(|id| id == primary_id) {
+        return Err(NoteDedupError::InvalidMerge(
+            "a note cannot be merged into itself".to_string(),
+        ));
+    }
+    let primary = load_merge_note(conn, primary_id)?;
+    let duplicates = duplicate_ids
+        .iter()
+        .map(|id| load_merge_note(conn, id))
+        .collect::<Result<Vec<_>, _>>()?;
+    if let Some(other) = duplicates.iter().find(|d| d.space_id != primary.space_id) {
+        return Err(NoteDedupError::InvalidMerge(format!(
+            "note {} belongs to another space",
+            other.id
+        )));
+    }
+    log::info!(
+        "[dedup] Merging {} notes into {}",
+        duplicates.len(),
+        primary.id
+    );
+
+    let now = Utc::now().timestamp();
+    let tx = conn.transaction()?;
+    let linking_ids: Vec<String> = {
+        let mut stmt = tx.prepare(&format!(
+            "SELECT DISTINCT source_note_id FROM link
+             WHERE target_note_id IN ({dups}) AND source_note_id <> ?1
+               AND source_note_id NOT IN ({dups})",
+            dups = numbered_placeholders(2, duplicate_ids.len())
+        ))?;
+        let rows = stmt.query_map(
+            params_from_iter(merge_params(primary_id, &duplicate_ids).iter()),
+            |row| row.get(0),
+        )?;
+        rows.collect::<Result<_, _>>()?
+    };
+    let mut snapshot = capture_merge(&tx, primary_id, &duplicate_ids, &linking_ids)?;
+
+    let (content, appended_sections) = match strategy {
+        MergeStrategy::KeepPrimary => (primary.content_md.clone(), 0),
+        MergeStrategy::AppendDiffering => append_differing(
+            &primary.content_md,
+            &duplicates
+                .iter()
+                .map(|d| d.content_md.clone())
+                .collect::<Vec<_>>(),
+        ),
+    };
+    if content != primary.content_md {
+        tx.execute(
+            "UPDATE note SET content_md = ?1 WHERE id = ?2",
+            params![content, primary.id],
+        )?;
+    }
+
+    // Links that name a duplicate would stop resolving once it's trashed
+    let mut rewritten = Vec::new();
+    for source_id in &linking_ids {
+        let source: Option<String> = tx
+            .query_row(
+                "SELECT content_md FROM note WHERE id = ?1 AND is_locked = 0",
+                [source_id],
+                |row| row.get(0),
+            )
+            .optional()?;
+        let Some(mut source_content) = source else {
+            continue;
+        };
+        let mut changed = false;
+        for duplicate in &duplicates {
+            if let Some(updated) = retarget_links(
+                &source_content,
+                &duplicate.id,
+                &duplicate.title,
+                &primary.id,
+                &primary.title,
+            ) {
+                source_content = updated;
+                changed = true;
+            }
+        }
+        if changed {
+            tx.execute(
+                "UPDATE note SET content_md = ?1 WHERE id = ?2",
+                params![source_content, source_id],
+            )?;
+            rewritten.push(source_id.clone());
+        }
+    }
+
+    let moved = move_relations(&tx, primary_id, &duplicate_ids)?;
+    refresh_note(&tx, primary_id, now)?;
+    for id in &rewritten {
+        refresh_note(&tx, id, now)?;
+    }
+
+    let merge_id = Ulid::new().to_string();
+    tx.execute(
+        "INSERT INTO note_merge
+             (id, space_id, primary_id, duplicate_ids_json, strategy, merged_at)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+        params![
+            merge_id,
+            primary.space_id,
+            primary.id,
+            serde_json::to_string(&duplicate_ids).map_err(DbError::from)?,
+            strategy.as_str(),
+            now
+        ],
+    )?;
+    snapshot.inserted.extend(capture_rows(
+        &tx,
+        "note_merge",
+        "id",
+        "id = ?1",
+        [&merge_id],
+    )?);
+
+    audit_change(
+        &tx,
+        &primary.space_id,
+        "note",
+        &primary.id,
+        AuditOperation::Update,
+        AUDIT_SOURCE_LOCAL,
+    );
+    for id in &duplicate_ids {
+        // Notes are deleted by trashing them
+        audit_change(
+            &tx,
+            &primary.space_id,
+            "note",
+            id,
+            AuditOperation::Delete,
+            AUDIT_SOURCE_LOCAL,
+        );
+    }
+    record_undo(
+        &tx,
+        UndoRecord {
+            space_id: &primary.space_id,
+            operation: "merge_notes",
+            entity_type: "note",
+            entity_id: &primary.id,
+            summary: format!(
+                "Merged {} duplicate note(s) into \"{}\"",
+                duplicate_ids.len(),
+                primary.title
+            ),
+            snapshot,
+        },
+    );
+    tx.commit()?;
+
+    Ok(NoteMergeSummary {
+        merge_id,
+        primary_id: primary.id,
+        duplicate_ids,
+        appended_sections,
+        relinked_notes: moved.relinked_notes,
+        tags_added: moved.tags_added,
+        cards_moved: moved.cards_moved,
+        time_entries_moved: moved.time_entries_moved,
+        attachments_added: moved.attachments_added,
+    })
+}
+
+/// A merge as exchanged by sync
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub(crate) struct SyncedMerge {
+    pub primary_id: String,
+    pub duplicate_ids: Vec<String>,
+    pub strategy: MergeStrategy,
+}
+
+/// Merges made in the space after `since`, with their time
+pub(crate) fn merges_since(
+    conn: &Connection,
+    space_id: &str,
+    since: i64,
+) -> Result<Vec<(String, SyncedMerge, i64)>, NoteDedupError> {
+    let mut stmt = conn.prepare(
+        "SELECT id, primary_id, duplicate_ids_json, strategy, merged_at FROM note_merge
+         WHERE space_id = ?1 AND merged_at > ?2",
+    )?;
+    let rows = stmt
+        .query_map(params![space_id, since], |row| {
+            Ok((
+                row.get::<_, String>(0)?,
+                row.get::<_, String>(1)?,
+                row.get::<_, String>(2)?,
+                row.get::<_, String>(3)?,
+                row.get::<_, i64>(4)?,
+            ))
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
+    rows.into_iter()
+        .map(|(id, primary_id, duplicate_ids, strategy, merged_at)| {
+            let merge = SyncedMerge {
+                primary_id,
+                duplicate_ids: serde_json::from_str(&duplicate_ids).map_err(DbError::from)?,
+                strategy: MergeStrategy::parse(&strategy).unwrap_or_default(),
+            };
+            Ok((id, merge, merged_at))
+        })
+        .collect()
+}
+
+/// Apply a merge made on another device. The merged body arrives as a note
+/// delta of its own, so only the relations move and the duplicates are
+/// trashed. A merge seen before is skipped, as are notes this device
+/// doesn't have.
+pub(crate) fn apply_synced_merge(
+    conn: &Connection,
+    merge_id: &str,
+    space_id: &str,
+    merge: &SyncedMerge,
+    merged_at: i64,
+) -> Result<(), NoteDedupError> {
+    let recorded = conn.execute(
+        "INSERT OR IGNORE INTO note_merge
+             (id, space_id, primary_id, duplicate_ids_json, strategy, merged_at)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+        params![
+            merge_id,
+            space_id,
+            merge.primary_id,
+            serde_json::to_string(&merge.duplicate_ids).map_err(DbError::from)?,
+            merge.strategy.as_str(),
+            merged_at
+        ],
+    )?;
+    if recorded == 0 {
+        return Ok(());
+    }
+    let exists = |id: &str| -> rusqlite::Result<bool> {
+        conn.query_row(
+            "SELECT EXISTS(SELECT 1 FROM note WHERE id = ?1 AND space_id = ?2)",
+            params![id, space_id],
+            |row| row.get(0),
+        )
+    };
+    if !exists(&merge.primary_id)? {
+        log::warn!(
+            "[dedup] Skipping synced merge {}: primary {} is missing",
+            merge_id,
+            merge.primary_id
+        );
+        return Ok(());
+    }
+    let mut duplicate_ids = Vec::new();
+    for id in &merge.duplicate_ids {
+        if exists(id)? && *id != merge.primary_id {
+            duplicate_ids.push(id.clone());
+        }
+    }
+    if duplicate_ids.is_empty() {
+        return Ok(());
+    }
+    move_relations(conn, &merge.primary_id, &duplicate_ids)?;
+    Ok(())
+}
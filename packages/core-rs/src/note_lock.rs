@@ -0,0 +1,338 @@
+//! Locked notes
+//!
+//! A locked note's body is encrypted with a key derived from a passphrase of
+//! its own, and the result is wrapped again with the vault DEK. `content_md`
+//! holds that envelope, so sync carries it like any other body while only
+//! someone with the passphrase can read it, on any device of the vault.
+//!
+//! Locking drops everything derived from the plaintext: the search index
+//! row, RAG chunks, the CRDT edit log and outgoing links. Readers such as
+//! [`crate::note::get_note`] return locked notes with an empty body; the
+//! title stays readable. Wrong passphrases count towards a per-note lockout
+//! (see [`crate::lockout`]).
+
+use crate::ai::rag::mark_note_dirty;
+use crate::audit::{audit_change, AuditOperation, AUDIT_SOURCE_LOCAL};
+use crate::backlink::sync_note_links;
+use crate::crdt::{delete_note_log, record_note_edit};
+use crate::crypto::{
+    decrypt_bytes, decrypt_bytes_with_aad, derive_key_argon2, encrypt_bytes,
+    encrypt_bytes_with_aad, CryptoError,
+};
+use crate::db::DbError;
+use crate::lockout::{self, LockoutPolicy};
+use crate::note::{get_note, DbUlid, Note};
+use crate::note_session::delete_note_history;
+use base64::Engine;
+use chrono::Utc;
+use rusqlite::{Connection, OptionalExtension};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use ulid::Ulid;
+use zeroize::Zeroizing;
+
+/// Marks `content_md` as a locked envelope rather than markdown
+const LOCKED_PREFIX: &str = "noteece-locked:v1:";
+const SALT_LEN: usize = 16;
+// Argon2id parameters of new locks; each lock stores its own
+const ARGON2_M_COST: u32 = 19_456;
+const ARGON2_T_COST: u32 = 2;
+const ARGON2_P_COST: u32 = 1;
+
+#[derive(Error, Debug)]
+pub enum NoteLockError {
+    #[error("Rusqlite error: {0}")]
+    Rusqlite(#[from] rusqlite::Error),
+    #[error("Database error: {0}")]
+    Db(#[from] DbError),
+    #[error("Crypto error: {0}")]
+    Crypto(CryptoError),
+    #[error("Note not found: {0}")]
+    NotFound(String),
+    #[error("Note is already locked: {0}")]
+    AlreadyLocked(String),
+    #[error("Note is not locked: {0}")]
+    NotLocked(String),
+    #[error("The passphrase must not be empty")]
+    EmptyPassphrase,
+    #[error("Wrong passphrase")]
+    WrongPassphrase,
+    #[error("Too many failed attempts; try again in {0} seconds")]
+    LockedOut(i64),
+    #[error("Corrupt locked note: {0}")]
+    Corrupt(String),
+}
+
+impl From<CryptoError> for NoteLockError {
+    fn from(e: CryptoError) -> Self {
+        match e {
+            CryptoError::Decrypt => NoteLockError::WrongPassphrase,
+            other => NoteLockError::Crypto(other),
+        }
+    }
+}
+
+/// What the DEK wraps: the passphrase-encrypted body and how to derive its key
+#[derive(Serialize, Deserialize)]
+struct LockedBody {
+    m_cost: u32,
+    t_cost: u32,
+    p_cost: u32,
+    /// Hex encoded
+    salt: String,
+    /// Base64 of nonce + ciphertext
+    ciphertext: String,
+}
+
+impl LockedBody {
+    /// Ties the ciphertext to its note and parameters, so it can't be moved
+    /// to another note or opened with weakened parameters
+    fn aad(&self, note_id: &str) -> Vec<u8> {
+        format!(
+            "{}{}:{}:{}:{}:{}",
+            LOCKED_PREFIX, note_id, self.m_cost, self.t_cost, self.p_cost, self.salt
+        )
+        .into_bytes()
+    }
+}
+
+/// Whether `content_md` is a locked envelope
+pub fn is_locked_content(content_md: &str) -> bool {
+    content_md.starts_with(LOCKED_PREFIX)
+}
+
+/// Encrypt the note's body with `passphrase`. The plaintext is gone from the
+/// vault afterwards; keep the passphrase, as it can't be recovered.
+pub fn lock_note(
+    conn: &mut Connection,
+    dek: &[u8],
+    note_id: &str,
+    passphrase: &str,
+) -> Result<(), NoteLockError> {
+    if passphrase.is_empty() {
+        return Err(NoteLockError::EmptyPassphrase);
+    }
+    let tx = conn.transaction()?;
+    let stored = read_stored(&tx, note_id)?;
+    if stored.is_locked {
+        return Err(NoteLockError::AlreadyLocked(note_id.to_string()));
+    }
+
+    let envelope = seal(note_id, &stored.content_md, passphrase, dek)?;
+    tx.execute(
+        "UPDATE note SET content_md = ?1, is_locked = 1, modified_at = ?2 WHERE id = ?3",
+        rusqlite::params![envelope, Utc::now().timestamp(), note_id],
+    )?;
+    tx.execute("DELETE FROM fts_note WHERE rowid = ?1", [stored.rowid])?;
+    // Chunks go on the next refresh, and so do the answers cached from them
+    mark_note_dirty(&tx, note_id)?;
+    delete_note_log(&tx, note_id)?;
+    delete_note_history(&tx, note_id)?;
+    sync_note_links(&tx, stored.ulid()?, "")?;
+    audit_change(
+        &tx,
+        &stored.space_id,
+        "note",
+        note_id,
+        AuditOperation::Update,
+        AUDIT_SOURCE_LOCAL,
+    );
+    tx.commit()?;
+    log::info!("[note_lock] Locked note {}", note_id);
+    Ok(())
+}
+
+/// The plaintext of a locked note, which stays locked
+pub fn unlock_note_content(
+    conn: &Connection,
+    dek: &[u8],
+    note_id: &str,
+    passphrase: &str,
+) -> Result<Zeroizing<String>, NoteLockError> {
+    let stored = read_stored(conn, note_id)?;
+    if !stored.is_locked {
+        return Err(NoteLockError::NotLocked(note_id.to_string()));
+    }
+    open_throttled(conn, note_id, &stored.content_md, passphrase, dek)
+}
+
+/// Remove the lock, putting the plaintext back as the note's body
+pub fn permanently_unlock_note(
+    conn: &mut Connection,
+    dek: &[u8],
+    note_id: &str,
+    passphrase: &str,
+) -> Result<Note, NoteLockError> {
+    let stored = read_stored(conn, note_id)?;
+    if !stored.is_locked {
+        return Err(NoteLockError::NotLocked(note_id.to_string()));
+    }
+    let content = open_throttled(conn, note_id, &stored.content_md, passphrase, dek)?;
+
+    let tx = conn.transaction()?;
+    tx.execute(
+        "UPDATE note SET content_md = ?1, is_locked = 0, modified_at = ?2 WHERE id = ?3",
+        rusqlite::params![content.as_str(), Utc::now().timestamp(), note_id],
+    )?;
+    tx.execute("DELETE FROM fts_note WHERE rowid = ?1", [stored.rowid])?;
+    tx.execute(
+        "INSERT INTO fts_note (rowid, note_id, title, content_md) VALUES (?1, ?2, ?3, ?4)",
+        rusqlite::params![
+            stored.rowid,
+            note_id,
+            stored.title.to_lowercase(),
+            content.as_str()
+        ],
+    )?;
+    mark_note_dirty(&tx, note_id)?;
+    record_note_edit(&tx, note_id, &content)?;
+    sync_note_links(&tx, stored.ulid()?, &content)?;
+    audit_change(
+        &tx,
+        &stored.space_id,
+        "note",
+        note_id,
+        AuditOperation::Update,
+        AUDIT_SOURCE_LOCAL,
+    );
+    tx.commit()?;
+    log::info!("[note_lock] Unlocked note {}", note_id);
+
+    get_note(conn, DbUlid(stored.ulid()?))?
+        .ok_or_else(|| NoteLockError::NotFound(note_id.to_string()))
+}
+
+/// The note row as stored, envelope included
+struct StoredNote {
+    rowid: i64,
+    id: String,
+    space_id: String,
+    title: String,
+    content_md: String,
+    is_locked: bool,
+}
+
+impl StoredNote {
+    fn ulid(&self) -> Result<Ulid, NoteLockError> {
+        Ulid::from_string(&self.id).map_err(|e| NoteLockError::Corrupt(e.to_string()))
+    }
+}
+
+fn read_stored(conn: &Connection, note_id: &str) -> Result<StoredNote, NoteLockError> {
+    conn.query_row(
+        "SELECT rowid, id, space_id, title, content_md, is_locked FROM note WHERE id = ?1",
+        [note_id],
+        |row| {
+            Ok(StoredNote {
+                rowid: row.get(0)?,
+                id: row.get(1)?,
+                space_id: row.get(2)?,
+                title: row.get(3)?,
+                content_md: row.get(4)?,
+                is_locked: row.get(5)?,
+            })
+        },
+    )
+    .optional()?
+    .ok_or_else(|| NoteLockError::NotFound(note_id.to_string()))
+}
+
+/// [`open`] behind the note's attempt counter. A locked-out note is refused
+/// before the key is derived, so waiting out the lockout is the only way on.
+fn open_throttled(
+    conn: &Connection,
+    note_id: &str,
+    envelope: &str,
+    passphrase: &str,
+    dek: &[u8],
+) -> Result<Zeroizing<String>, NoteLockError> {
+    let now = Utc::now().timestamp();
+    let attempt_key = format!("note:{}", note_id);
+    if let Some(retry_after) = lockout::get_attempt_state(conn, &attempt_key)?.retry_after(now) {
+        return Err(NoteLockError::LockedOut(retry_after));
+    }
+    match open(note_id, envelope, passphrase, dek) {
+        Ok(content) => {
+            lockout::reset_attempts(conn, &attempt_key)?;
+            Ok(content)
+        }
+        Err(NoteLockError::WrongPassphrase) => {
+            let state =
+                lockout::record_failure_at(conn, &attempt_key, &LockoutPolicy::default(), now)?;
+            log::warn!(
+                "[note_lock] Wrong passphrase for note {} ({} failures)",
+                note_id,
+                state.failures
+            );
+            Err(NoteLockError::WrongPassphrase)
+        }
+        Err(e) => Err(e),
+    }
+}
+
+fn seal(
+    note_id: &str,
+    content_md: &str,
+    passphrase: &str,
+    dek: &[u8],
+) -> Result<String, NoteLockError> {
+    let salt: [u8; SALT_LEN] = rand::random();
+    let mut body = LockedBody {
+        m_cost: ARGON2_M_COST,
+        t_cost: ARGON2_T_COST,
+        p_cost: ARGON2_P_COST,
+        salt: hex::encode(salt),
+        ciphertext: String::new(),
+    };
+    let key = derive_key_argon2(passphrase, &salt, body.m_cost, body.t_cost, body.p_cost)?;
+    let ciphertext =
+        encrypt_bytes_with_aad(content_md.as_bytes(), key.as_ref(), &body.aad(note_id))?;
+    body.ciphertext = base64::engine::general_purpose::STANDARD.encode(ciphertext);
+
+    let body = serde_json::to_vec(&body).map_err(|e| NoteLockError::Corrupt(e.to_string()))?;
+    let wrapped = encrypt_bytes(&body, dek)?;
+    Ok(format!(
+        "{}{}",
+        LOCKED_PREFIX,
+        base64::engine::general_purpose::STANDARD.encode(wrapped)
+    ))
+}
+
+fn open(
+    note_id: &str,
+    envelope: &str,
+    passphrase: &str,
+    dek: &[u8],
+) -> Result<Zeroizing<String>, NoteLockError> {
+    let encoded = envelope
+        .strip_prefix(LOCKED_PREFIX)
+        .ok_or_else(|| NoteLockError::Corrupt("missing lock marker".to_string()))?;
+    let wrapped = base64::engine::general_purpose::STANDARD
+        .decode(encoded)
+        .map_err(|e| NoteLockError::Corrupt(e.to_string()))?;
+    // Only the passphrase layer says anything about the passphrase; failing
+    // to open the outer one means the envelope or the vault key is wrong
+    let body = decrypt_bytes(&wrapped, dek).map_err(|e| match e {
+        CryptoError::Decrypt => {
+            NoteLockError::Corrupt("the vault key does not open it".to_string())
+        }
+        other => NoteLockError::Crypto(other),
+    })?;
+    let body: LockedBody =
+        serde_json::from_slice(&body).map_err(|e| NoteLockError::Corrupt(e.to_string()))?;
+    let salt = hex::decode(&body.salt).map_err(|e| NoteLockError::Corrupt(e.to_string()))?;
+    let ciphertext = base64::engine::general_purpose::STANDARD
+        .decode(&body.ciphertext)
+        .map_err(|e| NoteLockError::Corrupt(e.to_string()))?;
+
+    let key = derive_key_argon2(passphrase, &salt, body.m_cost, body.t_cost, body.p_cost)?;
+    let plaintext = Zeroizing::new(decrypt_bytes_with_aad(
+        &ciphertext,
+        key.as_ref(),
+        &body.aad(note_id),
+    )?);
+    let content = std::str::from_utf8(&plaintext)
+        .map_err(|e| NoteLockError::Corrupt(e.to_string()))?
+        .to_string();
+    Ok(Zeroizing::new(content))
+}
@@ -0,0 +1,349 @@
+//! Editing Sessions
+//!
+//! The editor saves on every debounce tick. Saved through a session, a
+//! draft only replaces the note's body and its search index entry; the rest
+//! of a save (a version snapshot, the `modified_at` bump sync deltas are
+//! gathered by, links, word counts, the CRDT log) happens at most once per
+//! [`EditorSettings::version_interval_secs`] and when the session ends.
+//! [`crate::note::update_note_content`] keeps saving everything at once.
+
+use crate::audit::{audit_change, AuditOperation, AUDIT_SOURCE_LOCAL};
+use crate::db::{load_settings, DbError, Settings};
+use crate::note::refresh_note;
+use chrono::Utc;
+use rusqlite::{params, Connection, OptionalExtension};
+use serde::{Deserialize, Serialize};
+use ulid::Ulid;
+
+/// How often sessions snapshot the notes they edit
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct EditorSettings {
+    /// Seconds between versions of a note edited in a session
+    pub version_interval_secs: i64,
+}
+
+impl Default for EditorSettings {
+    fn default() -> Self {
+        EditorSettings {
+            version_interval_secs: 60,
+        }
+    }
+}
+
+impl Settings for EditorSettings {
+    const KEY: &'static str = "editor.settings";
+    const DESCRIPTION: &'static str = "Editor auto-save";
+
+    fn validate(&self) -> Result<(), String> {
+        if self.version_interval_secs < 1 {
+            return Err("version_interval_secs must be at least 1".to_string());
+        }
+        Ok(())
+    }
+}
+
+/// An open editing session of one note
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct EditSession {
+    /// Token passed to [`save_note_draft`] and [`end_edit_session`]
+    pub id: Ulid,
+    pub note_id: String,
+    pub interval_secs: i64,
+    pub started_at: i64,
+}
+
+/// A snapshot of a note's title and body
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct NoteVersion {
+    pub id: Ulid,
+    pub note_id: String,
+    pub title: String,
+    pub content_md: String,
+    /// The session the snapshot was taken in
+    pub session_id: Option<String>,
+    pub created_at: i64,
+}
+
+/// Start editing a note. Sessions the note was left open in, e.g. by a
+/// crash, are ended first so their last drafts get a version.
+pub fn begin_edit_session(conn: &Connection, note_id: &str) -> Result<EditSession, DbError> {
+    begin_edit_session_at(conn, note_id, Utc::now().timestamp())
+}
+
+pub fn begin_edit_session_at(
+    conn: &Connection,
+    note_id: &str,
+    now: i64,
+) -> Result<EditSession, DbError> {
+    let is_locked: bool = conn
+        .query_row(
+            "SELECT is_locked FROM note WHERE id = ?1",
+            [note_id],
+            |row| row.get(0),
+        )
+        .optional()?
+        .ok_or_else(|| DbError::NotFound {
+            entity: "note",
+            id: note_id.to_string(),
+        })?;
+    if is_locked {
+        return Err(DbError::Locked {
+            entity: "note",
+            id: note_id.to_string(),
+        });
+    }
+    let interval_secs = load_settings::<EditorSettings>(conn)
+        .map_err(|e| DbError::Message(e.to_string()))?
+        .version_interval_secs;
+
+    let tx = conn.unchecked_transaction()?;
+    let stale = {
+        let mut stmt = tx.prepare("SELECT id FROM note_edit_session WHERE note_id = ?1")?;
+        let rows = stmt.query_map([note_id], |row| row.get::<_, String>(0))?;
+        rows.collect::<Result<Vec<_>, _>>()?
+    };
+    for id in &stale {
+        log::info!(
+            "[note_session] Ending stale session {} of note {}",
+            id,
+            note_id
+        );
+        finish(&tx, &parse_id(id)?, now)?;
+    }
+
+    let session = EditSession {
+        id: Ulid::new(),
+        note_id: note_id.to_string(),
+        interval_secs,
+        started_at: now,
+    };
+    tx.execute(
+        "INSERT INTO note_edit_session (id, note_id, interval_secs, started_at, flushed_at)
+         VALUES (?1, ?2, ?3, ?4, ?4)",
+        params![session.id.to_string(), note_id, interval_secs, now],
+    )?;
+    tx.commit()?;
+    log::info!(
+        "[note_session] Started session {} of note {}",
+        session.id,
+        note_id
+    );
+    Ok(session)
+}
+
+/// Save the editor's body of the session's note. A version is taken and
+/// `modified_at` bumped when the last one is at least the session's interval
+/// old; returns that version.
+pub fn save_note_draft(
+    conn: &Connection,
+    session_id: &Ulid,
+    content_md: &str,
+) -> Result<Option<NoteVersion>, DbError> {
+    save_note_draft_at(conn, session_id, content_md, Utc::now().timestamp())
+}
+
+pub fn save_note_draft_at(
+    conn: &Connection,
+    session_id: &Ulid,
+    content_md: &str,
+    now: i64,
+) -> Result<Option<NoteVersion>, DbError> {
+    let tx = conn.unchecked_transaction()?;
+    let (note_id, interval_secs, flushed_at, _) = session_row(&tx, session_id)?;
+    let (rowid, title, is_locked): (i64, String, bool) = tx.query_row(
+        "SELECT rowid, title, is_locked FROM note WHERE id = ?1",
+        [&note_id],
+        |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+    )?;
+    // Locked since the session began; the body is ciphertext now
+    if is_locked {
+        return Err(DbError::Locked {
+            entity: "note",
+            id: note_id,
+        });
+    }
+
+    tx.execute(
+        "UPDATE note SET content_md = ?1 WHERE id = ?2",
+        params![content_md, note_id],
+    )?;
+    tx.execute("DELETE FROM fts_note WHERE rowid = ?1", [rowid])?;
+    tx.execute(
+        "INSERT INTO fts_note(rowid, title, content_md, note_id) VALUES (?1, ?2, ?3, ?4)",
+        params![rowid, title.to_lowercase(), content_md, note_id],
+    )?;
+    tx.execute(
+        "UPDATE note_edit_session SET pending = 1 WHERE id = ?1",
+        [session_id.to_string()],
+    )?;
+
+    let version = if now - flushed_at >= interval_secs {
+        Some(flush(&tx, session_id, &note_id, now)?)
+    } else {
+        None
+    };
+    tx.commit()?;
+    Ok(version)
+}
+
+/// Stop editing. Drafts saved since the last version get one, which is
+/// returned.
+pub fn end_edit_session(
+    conn: &Connection,
+    session_id: &Ulid,
+) -> Result<Option<NoteVersion>, DbError> {
+    end_edit_session_at(conn, session_id, Utc::now().timestamp())
+}
+
+pub fn end_edit_session_at(
+    conn: &Connection,
+    session_id: &Ulid,
+    now: i64,
+) -> Result<Option<NoteVersion>, DbError> {
+    let tx = conn.unchecked_transaction()?;
+    let version = finish(&tx, session_id, now)?;
+    tx.commit()?;
+    log::info!("[note_session] Ended session {}", session_id);
+    Ok(version)
+}
+
+/// Versions of a note, newest first. A locked note has none to show.
+pub fn get_note_versions(conn: &Connection, note_id: &str) -> Result<Vec<NoteVersion>, DbError> {
+    let is_locked: Option<bool> = conn
+        .query_row(
+            "SELECT is_locked FROM note WHERE id = ?1",
+            [note_id],
+            |row| row.get(0),
+        )
+        .optional()?;
+    if is_locked == Some(true) {
+        return Err(DbError::Locked {
+            entity: "note",
+            id: note_id.to_string(),
+        });
+    }
+    let mut stmt = conn.prepare(
+        "SELECT id, note_id, title, content_md, session_id, created_at FROM note_version
+         WHERE note_id = ?1 ORDER BY created_at DESC, id DESC",
+    )?;
+    let rows = stmt.query_map([note_id], |row| {
+        Ok((
+            row.get::<_, String>(0)?,
+            row.get(1)?,
+            row.get(2)?,
+            row.get(3)?,
+            row.get(4)?,
+            row.get(5)?,
+        ))
+    })?;
+    rows.map(|row| {
+        let (id, note_id, title, content_md, session_id, created_at) = row?;
+        Ok(NoteVersion {
+            id: parse_id(&id)?,
+            note_id,
+            title,
+            content_md,
+            session_id,
+            created_at,
+        })
+    })
+    .collect()
+}
+
+/// Drop a note's versions and open sessions, e.g. once its body is locked
+/// and the plaintext they hold must go with it
+pub(crate) fn delete_note_history(conn: &Connection, note_id: &str) -> Result<(), DbError> {
+    conn.execute("DELETE FROM note_version WHERE note_id = ?1", [note_id])?;
+    conn.execute(
+        "DELETE FROM note_edit_session WHERE note_id = ?1",
+        [note_id],
+    )?;
+    Ok(())
+}
+
+fn parse_id(id: &str) -> Result<Ulid, DbError> {
+    Ulid::from_string(id).map_err(|e| DbError::Message(format!("Invalid id {id}: {e}")))
+}
+
+/// `(note_id, interval_secs, flushed_at, pending)` of an open session
+fn session_row(conn: &Connection, session_id: &Ulid) -> Result<(String, i64, i64, bool), DbError> {
+    conn.query_row(
+        "SELECT note_id, interval_secs, flushed_at, pending FROM note_edit_session WHERE id = ?1",
+        [session_id.to_string()],
+        |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?)),
+    )
+    .optional()?
+    .ok_or_else(|| DbError::NotFound {
+        entity: "edit_session",
+        id: session_id.to_string(),
+    })
+}
+
+/// Version pending drafts of a session and close it
+fn finish(conn: &Connection, session_id: &Ulid, now: i64) -> Result<Option<NoteVersion>, DbError> {
+    let (note_id, _, _, pending) = session_row(conn, session_id)?;
+    let version = if pending {
+        Some(flush(conn, session_id, &note_id, now)?)
+    } else {
+        None
+    };
+    conn.execute(
+        "DELETE FROM note_edit_session WHERE id = ?1",
+        [session_id.to_string()],
+    )?;
+    Ok(version)
+}
+
+/// Do what the drafts since the last flush skipped, and snapshot the note
+fn flush(
+    conn: &Connection,
+    session_id: &Ulid,
+    note_id: &str,
+    now: i64,
+) -> Result<NoteVersion, DbError> {
+    refresh_note(conn, note_id, now)?;
+    let (space_id, title, content_md): (String, String, String) = conn.query_row(
+        "SELECT space_id, title, content_md FROM note WHERE id = ?1",
+        [note_id],
+        |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+    )?;
+    let version = NoteVersion {
+        id: Ulid::new(),
+        note_id: note_id.to_string(),
+        title,
+        content_md,
+        session_id: Some(session_id.to_string()),
+        created_at: now,
+    };
+    conn.execute(
+        "INSERT INTO note_version (id, note_id, title, content_md, session_id, created_at)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+        params![
+            version.id.to_string(),
+            note_id,
+            version.title,
+            version.content_md,
+            version.session_id,
+            now
+        ],
+    )?;
+    conn.execute(
+        "UPDATE note_edit_session SET flushed_at = ?1, pending = 0 WHERE id = ?2",
+        params![now, session_id.to_string()],
+    )?;
+    audit_change(
+        conn,
+        &space_id,
+        "note",
+        note_id,
+        AuditOperation::Update,
+        AUDIT_SOURCE_LOCAL,
+    );
+    log::debug!(
+        "[note_session] Versioned note {} in session {}",
+        note_id,
+        session_id
+    );
+    Ok(version)
+}
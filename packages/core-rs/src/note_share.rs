@@ -0,0 +1,727 @@
+//! Notes across spaces
+//!
+//! A note belongs to one space. [`move_note_to_space`] re-homes it, along
+//! with any notes moving with it, and settles its relations: tags are
+//! matched by name in the target space or created there, time entries move,
+//! stay or go as asked, and links are re-resolved, so title links between
+//! notes that moved together survive while those to notes left behind are
+//! dropped. Each move is recorded in `note_move` and synced; the latest move
+//! of a note decides its space on every device, so a stale peer sending the
+//! note under its old space doesn't put it back.
+//!
+//! [`create_note_reference`] makes a note show up in another space's note
+//! lists and search without copying it. References are synced as their own
+//! records and removed ones are kept as tombstones.
+
+use crate::audit::{audit_change, audit_changes, AuditOperation, AUDIT_SOURCE_LOCAL};
+use crate::backlink::sync_note_links;
+use crate::db::{placeholders, DbError};
+use crate::note::refresh_note;
+use crate::tag::create_tag;
+use chrono::Utc;
+use rusqlite::{params, params_from_iter, Connection, OptionalExtension};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
+use thiserror::Error;
+use ulid::Ulid;
+
+/// Entity type of the sync deltas carrying moves
+pub const NOTE_MOVE_ENTITY_TYPE: &str = "note_move";
+/// Entity type of the sync deltas carrying references
+pub const NOTE_REFERENCE_ENTITY_TYPE: &str = "note_reference";
+
+#[derive(Error, Debug)]
+pub enum NoteShareError {
+    #[error("Rusqlite error: {0}")]
+    Rusqlite(#[from] rusqlite::Error),
+    #[error("Database error: {0}")]
+    Db(#[from] DbError),
+    #[error("{entity} not found: {id}")]
+    NotFound { entity: &'static str, id: String },
+    #[error("Invalid move: {0}")]
+    InvalidMove(String),
+    #[error("Invalid reference: {0}")]
+    InvalidReference(String),
+}
+
+/// What happens to the tags of moved notes
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TagAction {
+    /// Each tag is replaced by the target space's tag of the same name,
+    /// which is created when there is none
+    #[default]
+    Map,
+    /// The notes lose their tags
+    Drop,
+}
+
+/// What happens to the time entries of moved notes
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TimeEntryAction {
+    /// The entries are counted in the target space
+    Move,
+    /// The entries stay in the original space's reports
+    #[default]
+    Keep,
+    /// The entries are deleted
+    Drop,
+}
+
+impl TagAction {
+    fn as_str(&self) -> &'static str {
+        match self {
+            TagAction::Map => "map",
+            TagAction::Drop => "drop",
+        }
+    }
+
+    fn parse(s: &str) -> Option<Self> {
+        match s {
+            "map" => Some(TagAction::Map),
+            "drop" => Some(TagAction::Drop),
+            _ => None,
+        }
+    }
+}
+
+impl TimeEntryAction {
+    fn as_str(&self) -> &'static str {
+        match self {
+            TimeEntryAction::Move => "move",
+            TimeEntryAction::Keep => "keep",
+            TimeEntryAction::Drop => "drop",
+        }
+    }
+
+    fn parse(s: &str) -> Option<Self> {
+        match s {
+            "move" => Some(TimeEntryAction::Move),
+            "keep" => Some(TimeEntryAction::Keep),
+            "drop" => Some(TimeEntryAction::Drop),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MoveNoteOptions {
+    /// Other notes of the same space moving along, e.g. the rest of a linked
+    /// cluster; links between them survive the move
+    #[serde(default)]
+    pub with_notes: Vec<String>,
+    #[serde(default)]
+    pub tags: TagAction,
+    #[serde(default)]
+    pub time_entries: TimeEntryAction,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MoveReport {
+    pub note_ids: Vec<String>,
+    pub from_space_id: String,
+    pub to_space_id: String,
+    /// Tags replaced by a tag the target space already had
+    pub tags_matched: usize,
+    /// Tags created in the target space
+    pub tags_created: usize,
+    pub tags_dropped: usize,
+    /// Links to or from the moved notes that still resolve
+    pub links_preserved: usize,
+    /// Links to or from the moved notes that named a note in the other space
+    pub links_dropped: usize,
+    /// Links the target space's notes gained to the moved notes
+    pub links_added: usize,
+    pub time_entries_moved: usize,
+    pub time_entries_kept: usize,
+    pub time_entries_dropped: usize,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct NoteReference {
+    pub id: String,
+    pub note_id: String,
+    /// Space the note is shown in; it still belongs to its own
+    pub space_id: String,
+    pub created_at: i64,
+}
+
+/// Move `note_id`, and `options.with_notes`, to `target_space_id`.
+pub fn move_note_to_space(
+    conn: &mut Connection,
+    note_id: &str,
+    target_space_id: &str,
+    options: &MoveNoteOptions,
+) -> Result<MoveReport, NoteShareError> {
+    let from_space_id = note_space(conn, note_id)?;
+    ensure_space(conn, target_space_id)?;
+    if from_space_id == target_space_id {
+        return Err(NoteShareError::InvalidMove(format!(
+            "note {} is already in space {}",
+            note_id, target_space_id
+        )));
+    }
+    let mut note_ids = vec![note_id.to_string()];
+    for id in &options.with_notes {
+        if note_ids.contains(id) {
+            continue;
+        }
+        if note_space(conn, id)? != from_space_id {
+            return Err(NoteShareError::InvalidMove(format!(
+                "note {} belongs to another space",
+                id
+            )));
+        }
+        note_ids.push(id.clone());
+    }
+    log::info!(
+        "[note_share] Moving {} note(s) from space {} to {}",
+        note_ids.len(),
+        from_space_id,
+        target_space_id
+    );
+
+    let now = Utc::now().timestamp();
+    let tx = conn.transaction()?;
+    let report = rehome_notes(
+        &tx,
+        &note_ids,
+        &from_space_id,
+        target_space_id,
+        options.tags,
+        options.time_entries,
+        now,
+    )?;
+    record_note_moves(
+        &tx,
+        &note_ids,
+        &from_space_id,
+        target_space_id,
+        options.tags,
+        options.time_entries,
+        now,
+    )?;
+    tx.commit()?;
+    Ok(report)
+}
+
+/// Record local moves of `note_ids` in `note_move` for sync, and audit them
+pub(crate) fn record_note_moves(
+    conn: &Connection,
+    note_ids: &[String],
+    from_space_id: &str,
+    to_space_id: &str,
+    tags: TagAction,
+    time_entries: TimeEntryAction,
+    now: i64,
+) -> Result<(), NoteShareError> {
+    let mut stmt = conn.prepare_cached(
+        "INSERT INTO note_move
+             (id, note_id, from_space_id, to_space_id, tag_action, time_entry_action, moved_at)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+    )?;
+    for id in note_ids {
+        stmt.execute(params![
+            Ulid::new().to_string(),
+            id,
+            from_space_id,
+            to_space_id,
+            tags.as_str(),
+            time_entries.as_str(),
+            now
+        ])?;
+    }
+    audit_changes(
+        conn,
+        to_space_id,
+        "note",
+        note_ids,
+        AuditOperation::Update,
+        AUDIT_SOURCE_LOCAL,
+    );
+    Ok(())
+}
+
+/// Change the space of `note_ids` and settle their relations. Shared by local
+/// moves, bulk moves and moves synced from another device.
+pub(crate) fn rehome_notes(
+    conn: &Connection,
+    note_ids: &[String],
+    from_space_id: &str,
+    to_space_id: &str,
+    tags: TagAction,
+    time_entries: TimeEntryAction,
+    now: i64,
+) -> Result<MoveReport, NoteShareError> {
+    let ids = placeholders(note_ids.len());
+    let links_before = links_touching(conn, note_ids)?;
+
+    let mut report = MoveReport {
+        note_ids: note_ids.to_vec(),
+        from_space_id: from_space_id.to_string(),
+        to_space_id: to_space_id.to_string(),
+        ..Default::default()
+    };
+
+    // A daily note can't take the day of one the target space already has
+    conn.execute(
+        &format!(
+            "UPDATE note SET daily_date = NULL
+             WHERE daily_date IN (SELECT daily_date FROM note WHERE space_id = ? AND is_trashed = 0)
+               AND id IN ({ids})"
+        ),
+        params_from_iter(with_ids(to_space_id, note_ids)),
+    )?;
+    conn.execute(
+        &format!("UPDATE note SET space_id = ? WHERE id IN ({ids})"),
+        params_from_iter(with_ids(to_space_id, note_ids)),
+    )?;
+    // A reference in the space the notes now belong to is redundant
+    conn.execute(
+        &format!("DELETE FROM note_reference WHERE space_id = ? AND note_id IN ({ids})"),
+        params_from_iter(with_ids(to_space_id, note_ids)),
+    )?;
+
+    let note_tags: Vec<(String, String, String)> = {
+        let mut stmt = conn.prepare(&format!(
+            "SELECT nt.note_id, nt.tag_id, t.name FROM note_tags nt
+             JOIN tag t ON t.id = nt.tag_id
+             WHERE t.space_id <> ? AND nt.note_id IN ({ids})"
+        ))?;
+        let rows = stmt.query_map(params_from_iter(with_ids(to_space_id, note_ids)), |row| {
+            Ok((row.get(0)?, row.get(1)?, row.get(2)?))
+        })?;
+        rows.collect::<Result<_, _>>()?
+    };
+    for (note_id, tag_id, name) in note_tags {
+        conn.execute(
+            "DELETE FROM note_tags WHERE note_id = ?1 AND tag_id = ?2",
+            params![note_id, tag_id],
+        )?;
+        if tags == TagAction::Drop {
+            report.tags_dropped += 1;
+            continue;
+        }
+        let existing: Option<String> = conn
+            .query_row(
+                "SELECT id FROM tag WHERE space_id = ?1 AND name = ?2",
+                params![to_space_id, name],
+                |row| row.get(0),
+            )
+            .optional()?;
+        let target_tag = match existing {
+            Some(id) => {
+                report.tags_matched += 1;
+                id
+            }
+            None => {
+                report.tags_created += 1;
+                create_tag(conn, to_space_id, &name, None)?.id.to_string()
+            }
+        };
+        conn.execute(
+            "INSERT OR IGNORE INTO note_tags (note_id, tag_id) VALUES (?1, ?2)",
+            params![note_id, target_tag],
+        )?;
+    }
+
+    match time_entries {
+        TimeEntryAction::Move => {
+            report.time_entries_moved = conn.execute(
+                &format!("UPDATE time_entry SET space_id = ? WHERE note_id IN ({ids})"),
+                params_from_iter(with_ids(to_space_id, note_ids)),
+            )?;
+        }
+        TimeEntryAction::Keep => {
+            report.time_entries_kept = conn.query_row(
+                &format!("SELECT COUNT(*) FROM time_entry WHERE note_id IN ({ids})"),
+                params_from_iter(note_ids),
+                |row| row.get(0),
+            )?;
+        }
+        TimeEntryAction::Drop => {
+            report.time_entries_dropped = conn.execute(
+                &format!("DELETE FROM time_entry WHERE note_id IN ({ids})"),
+                params_from_iter(note_ids),
+            )?;
+        }
+    }
+
+    // Titles resolve within a space: moved notes now link into the target
+    // space, and notes of the target space naming a moved note link to it
+    let linking_ids: BTreeSet<String> = links_before
+        .iter()
+        .filter(|(_, target)| note_ids.contains(target))
+        .map(|(source, _)| source.clone())
+        .filter(|source| !note_ids.contains(source))
+        .collect();
+    for id in note_ids {
+        refresh_note(conn, id, now)?;
+    }
+    for id in &linking_ids {
+        relink(conn, id)?;
+    }
+    let mut naming = Vec::new();
+    for id in note_ids {
+        let title: String =
+            conn.query_row("SELECT title FROM note WHERE id = ?1", [id], |row| {
+                row.get(0)
+            })?;
+        if title.trim().is_empty() {
+            continue;
+        }
+        let mut stmt = conn.prepare(&format!(
+            "SELECT id FROM note
+             WHERE space_id = ? AND is_locked = 0 AND is_trashed = 0 AND id NOT IN ({ids})
+               AND instr(lower(content_md), lower(?)) > 0"
+        ))?;
+        let rows = stmt.query_map(
+            params_from_iter(
+                with_ids(to_space_id, note_ids).chain(std::iter::once(title.as_str())),
+            ),
+            |row| row.get::<_, String>(0),
+        )?;
+        for row in rows {
+            naming.push(row?);
+        }
+    }
+    for id in naming {
+        report.links_added += relink(conn, &id)?;
+    }
+
+    let links_after = links_touching(conn, note_ids)?;
+    report.links_preserved = links_before.intersection(&links_after).count();
+    report.links_dropped = links_before.difference(&links_after).count();
+    Ok(report)
+}
+
+/// Re-parse a note's links; returns how many were added
+fn relink(conn: &Connection, note_id: &str) -> Result<usize, NoteShareError> {
+    let content: Option<String> = conn
+        .query_row(
+            "SELECT content_md FROM note WHERE id = ?1 AND is_locked = 0",
+            [note_id],
+            |row| row.get(0),
+        )
+        .optional()?;
+    let Some(content) = content else {
+        return Ok(0);
+    };
+    let id = Ulid::from_string(note_id).map_err(|e| DbError::Message(e.to_string()))?;
+    Ok(sync_note_links(conn, id, &content)?.added.len())
+}
+
+/// `(source, target)` of the links from or to any of `note_ids`
+fn links_touching(
+    conn: &Connection,
+    note_ids: &[String],
+) -> Result<BTreeSet<(String, String)>, NoteShareError> {
+    let ids = placeholders(note_ids.len());
+    let mut stmt = conn.prepare(&format!(
+        "SELECT source_note_id, target_note_id FROM link
+         WHERE source_note_id IN ({ids}) OR target_note_id IN ({ids})"
+    ))?;
+    let rows = stmt.query_map(params_from_iter(note_ids.iter().chain(note_ids)), |row| {
+        Ok((row.get(0)?, row.get(1)?))
+    })?;
+    Ok(rows.collect::<Result<_, _>>()?)
+}
+
+/// `first` followed by `ids`, as parameters of a statement with a single
+/// placeholder ahead of the `IN` list
+fn with_ids<'a>(first: &'a str, ids: &'a [String]) -> impl Iterator<Item = &'a str> {
+    std::iter::once(first).chain(ids.iter().map(String::as_str))
+}
+
+fn note_space(conn: &Connection, note_id: &str) -> Result<String, NoteShareError> {
+    conn.query_row(
+        "SELECT space_id FROM note WHERE id = ?1",
+        [note_id],
+        |row| row.get(0),
+    )
+    .optional()?
+    .ok_or_else(|| NoteShareError::NotFound {
+        entity: "note",
+        id: note_id.to_string(),
+    })
+}
+
+pub(crate) fn ensure_space(conn: &Connection, space_id: &str) -> Result<(), NoteShareError> {
+    let exists: bool = conn.query_row(
+        "SELECT EXISTS(SELECT 1 FROM space WHERE id = ?1)",
+        [space_id],
+        |row| row.get(0),
+    )?;
+    if !exists {
+        return Err(NoteShareError::NotFound {
+            entity: "space",
+            id: space_id.to_string(),
+        });
+    }
+    Ok(())
+}
+
+// --- References ---
+
+/// Show `note_id` in `target_space_id` as well. Creating a reference that
+/// exists returns it.
+pub fn create_note_reference(
+    conn: &Connection,
+    note_id: &str,
+    target_space_id: &str,
+) -> Result<NoteReference, NoteShareError> {
+    if note_space(conn, note_id)? == target_space_id {
+        return Err(NoteShareError::InvalidReference(format!(
+            "note {} already belongs to space {}",
+            note_id, target_space_id
+        )));
+    }
+    ensure_space(conn, target_space_id)?;
+    let now = Utc::now().timestamp();
+    let reference = conn.query_row(
+        "INSERT INTO note_reference (id, note_id, space_id, created_at, updated_at)
+         VALUES (?1, ?2, ?3, ?4, ?4)
+         ON CONFLICT(note_id, space_id) DO UPDATE SET
+             created_at = CASE WHEN removed_at IS NULL THEN created_at ELSE excluded.created_at END,
+             updated_at = CASE WHEN removed_at IS NULL THEN updated_at ELSE excluded.updated_at END,
+             removed_at = NULL
+         RETURNING id, note_id, space_id, created_at",
+        params![Ulid::new().to_string(), note_id, target_space_id, now],
+        reference_from_row,
+    )?;
+    audit_change(
+        conn,
+        target_space_id,
+        "note_reference",
+        &reference.id,
+        AuditOperation::Create,
+        AUDIT_SOURCE_LOCAL,
+    );
+    Ok(reference)
+}
+
+/// Stop showing `note_id` in `space_id`
+pub fn remove_note_reference(
+    conn: &Connection,
+    note_id: &str,
+    space_id: &str,
+) -> Result<(), NoteShareError> {
+    let id: String = conn
+        .query_row(
+            "UPDATE note_reference SET removed_at = ?1, updated_at = ?1
+             WHERE note_id = ?2 AND space_id = ?3 AND removed_at IS NULL
+             RETURNING id",
+            params![Utc::now().timestamp(), note_id, space_id],
+            |row| row.get(0),
+        )
+        .optional()?
+        .ok_or_else(|| NoteShareError::NotFound {
+            entity: "note_reference",
+            id: format!("{}@{}", note_id, space_id),
+        })?;
+    audit_change(
+        conn,
+        space_id,
+        "note_reference",
+        &id,
+        AuditOperation::Delete,
+        AUDIT_SOURCE_LOCAL,
+    );
+    Ok(())
+}
+
+/// Spaces a note is shown in besides its own
+pub fn get_note_references(
+    conn: &Connection,
+    note_id: &str,
+) -> Result<Vec<NoteReference>, NoteShareError> {
+    let mut stmt = conn.prepare(
+        "SELECT id, note_id, space_id, created_at FROM note_reference
+         WHERE note_id = ?1 AND removed_at IS NULL ORDER BY created_at",
+    )?;
+    let references = stmt
+        .query_map([note_id], reference_from_row)?
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(references)
+}
+
+fn reference_from_row(row: &rusqlite::Row) -> rusqlite::Result<NoteReference> {
+    Ok(NoteReference {
+        id: row.get(0)?,
+        note_id: row.get(1)?,
+        space_id: row.get(2)?,
+        created_at: row.get(3)?,
+    })
+}
+
+// --- Sync ---
+
+/// A move as exchanged by sync
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub(crate) struct SyncedMove {
+    pub note_id: String,
+    pub from_space_id: String,
+    pub to_space_id: String,
+    pub tags: TagAction,
+    pub time_entries: TimeEntryAction,
+}
+
+/// A reference as exchanged by sync; the space is the delta's
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub(crate) struct SyncedReference {
+    pub note_id: String,
+}
+
+/// Moves into or out of the space after `since`, with their time
+pub(crate) fn moves_since(
+    conn: &Connection,
+    space_id: &str,
+    since: i64,
+) -> Result<Vec<(String, SyncedMove, i64)>, NoteShareError> {
+    let mut stmt = conn.prepare(
+        "SELECT id, note_id, from_space_id, to_space_id, tag_action, time_entry_action, moved_at
+         FROM note_move
+         WHERE (from_space_id = ?1 OR to_space_id = ?1) AND moved_at > ?2",
+    )?;
+    let moves = stmt
+        .query_map(params![space_id, since], |row| {
+            let tags: String = row.get(4)?;
+            let time_entries: String = row.get(5)?;
+            Ok((
+                row.get(0)?,
+                SyncedMove {
+                    note_id: row.get(1)?,
+                    from_space_id: row.get(2)?,
+                    to_space_id: row.get(3)?,
+                    tags: TagAction::parse(&tags).unwrap_or_default(),
+                    time_entries: TimeEntryAction::parse(&time_entries).unwrap_or_default(),
+                },
+                row.get(6)?,
+            ))
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(moves)
+}
+
+/// Apply a move made on another device. A move seen before is skipped, and
+/// one older than a move this device already knows of for the note only
+/// gets recorded. The note's body arrives as a note delta of its own.
+pub(crate) fn apply_synced_move(
+    conn: &Connection,
+    move_id: &str,
+    synced: &SyncedMove,
+    moved_at: i64,
+) -> Result<(), NoteShareError> {
+    let superseded = latest_move(conn, &synced.note_id)?.is_some_and(|(_, at)| at > moved_at);
+    let recorded = conn.execute(
+        "INSERT OR IGNORE INTO note_move
+             (id, note_id, from_space_id, to_space_id, tag_action, time_entry_action, moved_at)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+        params![
+            move_id,
+            synced.note_id,
+            synced.from_space_id,
+            synced.to_space_id,
+            synced.tags.as_str(),
+            synced.time_entries.as_str(),
+            moved_at
+        ],
+    )?;
+    if recorded == 0 || superseded {
+        return Ok(());
+    }
+    // The note's body may have arrived first and already be in the target
+    // space, its relations still aren't. It isn't stamped later than on the
+    // device that moved it, so it doesn't look like a local edit.
+    let modified_at: Option<i64> = conn
+        .query_row(
+            "SELECT modified_at FROM note WHERE id = ?1",
+            [&synced.note_id],
+            |row| row.get(0),
+        )
+        .optional()?;
+    if let Some(modified_at) = modified_at {
+        rehome_notes(
+            conn,
+            std::slice::from_ref(&synced.note_id),
+            &synced.from_space_id,
+            &synced.to_space_id,
+            synced.tags,
+            synced.time_entries,
+            moved_at.max(modified_at),
+        )?;
+    }
+    Ok(())
+}
+
+/// The space the latest recorded move of a note took it to, and when
+pub(crate) fn latest_move(
+    conn: &Connection,
+    note_id: &str,
+) -> Result<Option<(String, i64)>, NoteShareError> {
+    Ok(conn
+        .query_row(
+            "SELECT to_space_id, moved_at FROM note_move WHERE note_id = ?1
+             ORDER BY moved_at DESC, id DESC LIMIT 1",
+            [note_id],
+            |row| Ok((row.get(0)?, row.get(1)?)),
+        )
+        .optional()?)
+}
+
+/// References in the space created or removed after `since`: the reference
+/// id, the synced record, whether it's removed and when it last changed
+pub(crate) fn references_since(
+    conn: &Connection,
+    space_id: &str,
+    since: i64,
+) -> Result<Vec<(String, SyncedReference, bool, i64)>, NoteShareError> {
+    let mut stmt = conn.prepare(
+        "SELECT id, note_id, removed_at IS NOT NULL, updated_at FROM note_reference
+         WHERE space_id = ?1 AND updated_at > ?2",
+    )?;
+    let references = stmt
+        .query_map(params![space_id, since], |row| {
+            Ok((
+                row.get(0)?,
+                SyncedReference {
+                    note_id: row.get(1)?,
+                },
+                row.get(2)?,
+                row.get(3)?,
+            ))
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(references)
+}
+
+/// Apply a reference created or removed on another device. References are
+/// matched by note and space, so two devices referencing the same note end
+/// up with one; the later change wins.
+pub(crate) fn apply_synced_reference(
+    conn: &Connection,
+    reference_id: &str,
+    space_id: &str,
+    synced: &SyncedReference,
+    removed: bool,
+    changed_at: i64,
+) -> Result<(), NoteShareError> {
+    let removed_at = removed.then_some(changed_at);
+    conn.execute(
+        "INSERT INTO note_reference (id, note_id, space_id, created_at, updated_at, removed_at)
+         VALUES (?1, ?2, ?3, ?4, ?4, ?5)
+         ON CONFLICT(note_id, space_id) DO UPDATE SET
+             updated_at = excluded.updated_at,
+             removed_at = excluded.removed_at
+         WHERE excluded.updated_at > note_reference.updated_at",
+        params![
+            reference_id,
+            synced.note_id,
+            space_id,
+            changed_at,
+            removed_at
+        ],
+    )?;
+    Ok(())
+}
@@ -0,0 +1,168 @@
+//! Note statistics
+//!
+//! Word and character counts are kept on the note row and recounted on
+//! every save, so nothing has to count per keystroke. Counting reads the
+//! markdown the way a reader would: front matter and fenced code are left
+//! out, links count as their text and images not at all. Each save also
+//! logs how many words it added or removed, which is what
+//! [`get_writing_stats`](crate::analytics::get_writing_stats) adds up.
+
+use crate::db::DbError;
+use regex::Regex;
+use rusqlite::{params, Connection, OptionalExtension};
+use serde::{Deserialize, Serialize};
+
+/// Average silent reading speed used for reading time estimates
+pub const READING_WORDS_PER_MINUTE: i64 = 230;
+
+lazy_static::lazy_static! {
+    static ref EMBED: Regex = Regex::new(r"!\[\[[^\[\]]*\]\]").expect("valid regex");
+    static ref IMAGE: Regex = Regex::new(r"!\[[^\]]*\]\([^)]*\)").expect("valid regex");
+    static ref WIKI_LINK: Regex =
+        Regex::new(r"\[\[([^\[\]|]+?)(?:\|([^\[\]]+?))?\]\]").expect("valid regex");
+    static ref LINK: Regex = Regex::new(r"\[([^\[\]]*)\]\([^()\s]*\)").expect("valid regex");
+    static ref HTML_TAG: Regex = Regex::new(r"</?[A-Za-z][^>]*>").expect("valid regex");
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TextCounts {
+    pub words: i64,
+    /// Characters of the body, front matter left out
+    pub chars: i64,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct NoteStats {
+    pub note_id: String,
+    pub word_count: i64,
+    pub char_count: i64,
+    /// Rounded up, so any text takes at least a minute
+    pub reading_minutes: i64,
+}
+
+/// Count the words and characters of a markdown note
+pub fn count_text(markdown: &str) -> TextCounts {
+    let body = strip_front_matter(markdown);
+    let mut words = 0;
+    let mut fence: Option<(char, usize)> = None;
+    for line in body.lines() {
+        let trimmed = line.trim_start();
+        let marker = trimmed.chars().next().filter(|c| *c == '`' || *c == '~');
+        let run = marker.map_or(0, |m| trimmed.chars().take_while(|c| *c == m).count());
+        match (fence, marker) {
+            // A fence closes with at least as many of the same marker
+            (Some((open, len)), Some(m)) if m == open && run >= len => {
+                fence = None;
+                continue;
+            }
+            (Some(_), _) => continue,
+            (None, Some(m)) if run >= 3 => {
+                fence = Some((m, run));
+                continue;
+            }
+            _ => {}
+        }
+        words += count_words(&prose(line));
+    }
+    TextCounts {
+        words,
+        chars: body.trim().chars().count() as i64,
+    }
+}
+
+pub fn get_note_stats(conn: &Connection, note_id: &str) -> Result<NoteStats, DbError> {
+    let counts: Option<(i64, i64)> = conn
+        .query_row(
+            "SELECT word_count, char_count FROM note WHERE id = ?1",
+            [note_id],
+            |row| Ok((row.get(0)?, row.get(1)?)),
+        )
+        .optional()?;
+    let (word_count, char_count) = counts.ok_or_else(|| DbError::NotFound {
+        entity: "note",
+        id: note_id.to_string(),
+    })?;
+    Ok(NoteStats {
+        note_id: note_id.to_string(),
+        word_count,
+        char_count,
+        reading_minutes: (word_count + READING_WORDS_PER_MINUTE - 1) / READING_WORDS_PER_MINUTE,
+    })
+}
+
+/// Log the words a save added or removed. Notes written by a bulk import
+/// weren't written just now, so they are left out.
+pub(crate) fn record_word_change(
+    conn: &Connection,
+    note_id: &str,
+    space_id: &str,
+    delta: i64,
+    recorded_at: i64,
+) -> rusqlite::Result<()> {
+    if delta == 0 || crate::db::bulk::fts_deferred(conn)? {
+        return Ok(());
+    }
+    conn.execute(
+        "INSERT INTO note_word_delta (note_id, space_id, recorded_at, delta)
+         VALUES (?1, ?2, ?3, ?4)",
+        params![note_id, space_id, recorded_at, delta],
+    )?;
+    Ok(())
+}
+
+/// Count every note saved before counts were kept. Locked notes hold
+/// ciphertext and keep no count until they are unlocked and saved.
+pub(crate) fn backfill_note_counts(conn: &Connection) -> Result<(), DbError> {
+    let notes: Vec<(String, String)> = {
+        let mut stmt = conn.prepare("SELECT id, content_md FROM note WHERE is_locked = 0")?;
+        let rows = stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?;
+        rows.collect::<Result<_, _>>()?
+    };
+    let mut update =
+        conn.prepare("UPDATE note SET word_count = ?1, char_count = ?2 WHERE id = ?3")?;
+    for (id, content) in &notes {
+        let counts = count_text(content);
+        update.execute(params![counts.words, counts.chars, id])?;
+    }
+    log::info!("[note_stats] Counted words of {} notes", notes.len());
+    Ok(())
+}
+
+/// The body after a leading YAML front matter block, if there is one
+fn strip_front_matter(markdown: &str) -> &str {
+    let Some(rest) = markdown
+        .strip_prefix("---\n")
+        .or_else(|| markdown.strip_prefix("---\r\n"))
+    else {
+        return markdown;
+    };
+    let mut offset = 0;
+    for line in rest.split_inclusive('\n') {
+        offset += line.len();
+        if matches!(line.trim_end(), "---" | "...") {
+            return &rest[offset..];
+        }
+    }
+    // Never closed, so not front matter
+    markdown
+}
+
+/// A line with links reduced to their text and images and tags removed
+fn prose(line: &str) -> String {
+    let line = EMBED.replace_all(line, " ");
+    let line = IMAGE.replace_all(&line, " ");
+    let line = WIKI_LINK.replace_all(&line, |caps: &regex::Captures| match caps.get(2) {
+        Some(alias) => alias.as_str().to_string(),
+        None => caps[1].split('#').next().unwrap_or("").to_string(),
+    });
+    let line = LINK.replace_all(&line, "$1");
+    HTML_TAG.replace_all(&line, " ").into_owned()
+}
+
+/// Whitespace-separated tokens with a letter or digit in them, so list
+/// markers, heading hashes and rules don't count
+fn count_words(text: &str) -> i64 {
+    text.split_whitespace()
+        .filter(|token| token.chars().any(char::is_alphanumeric))
+        .count() as i64
+}
@@ -0,0 +1,413 @@
+// packages/core-rs/src/note_template.rs
+
+use chrono::{Days, Months, NaiveDate, NaiveDateTime, Utc};
+use rusqlite::{Connection, OptionalExtension};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use thiserror::Error;
+use ulid::Ulid;
+
+use crate::db::{get_setting, set_setting, DbError};
+use crate::note::{create_note, Note};
+
+/// Placeholders every template can use without declaring them
+const BUILTIN_VARIABLES: &[&str] = &["date", "time", "space", "cursor"];
+
+#[derive(Error, Debug)]
+pub enum TemplateError {
+    #[error("Database error: {0}")]
+    Database(#[from] DbError),
+    #[error("Rusqlite error: {0}")]
+    Rusqlite(#[from] rusqlite::Error),
+    #[error("Serde JSON error: {0}")]
+    SerdeJson(#[from] serde_json::Error),
+    #[error("Note template not found: {0}")]
+    NotFound(String),
+    #[error("Malformed template: {0}")]
+    Malformed(String),
+    #[error("Unknown template variable: {0}")]
+    UnknownVariable(String),
+    #[error("No value for template variable: {0}")]
+    MissingVariable(String),
+    #[error("Invalid template variable name: {0}")]
+    InvalidVariableName(String),
+}
+
+/// A custom variable the user is prompted for when creating a note
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TemplateVariable {
+    pub name: String,
+    pub prompt: String,
+    pub default_value: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NoteTemplate {
+    pub id: String,
+    pub space_id: String,
+    pub name: String,
+    pub content: String,
+    pub variables: Vec<TemplateVariable>,
+    pub created_at: i64,
+    pub updated_at: i64,
+}
+
+/// Inputs a template is rendered against
+#[derive(Debug, Clone)]
+pub struct RenderContext {
+    pub now: NaiveDateTime,
+    pub space_name: String,
+    /// Values for custom variables
+    pub values: HashMap<String, String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RenderedTemplate {
+    pub content: String,
+    /// Where `{{cursor}}` was, in UTF-16 code units as the editor counts them
+    pub cursor: Option<usize>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NoteFromTemplate {
+    pub note: Note,
+    pub cursor: Option<usize>,
+}
+
+/// A piece of a parsed template
+enum Segment<'a> {
+    Text(&'a str),
+    Placeholder(&'a str),
+}
+
+fn parse(content: &str) -> Result<Vec<Segment<'_>>, TemplateError> {
+    let mut segments = Vec::new();
+    let mut rest = content;
+    while let Some(start) = rest.find("{{") {
+        segments.push(Segment::Text(&rest[..start]));
+        let after = &rest[start + 2..];
+        let end = after
+            .find("}}")
+            .ok_or_else(|| TemplateError::Malformed("unclosed '{{'".to_string()))?;
+        let name = after[..end].trim();
+        if name.is_empty() {
+            return Err(TemplateError::Malformed("empty placeholder".to_string()));
+        }
+        segments.push(Segment::Placeholder(name));
+        rest = &after[end + 2..];
+    }
+    segments.push(Segment::Text(rest));
+    Ok(segments)
+}
+
+fn is_valid_variable_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    matches!(chars.next(), Some(c) if c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// Parse the offset in `date+1d`, `date-2w`, `date+3m` or `date+1y`
+fn parse_date_offset(expr: &str) -> Option<(i64, char)> {
+    let offset = expr.strip_prefix("date")?;
+    let sign = match offset.chars().next()? {
+        '+' => 1,
+        '-' => -1,
+        _ => return None,
+    };
+    let unit = offset.chars().last()?;
+    let digits = offset[1..].strip_suffix(unit)?;
+    if digits.is_empty() || !digits.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    let amount: i64 = digits.parse().ok()?;
+    matches!(unit, 'd' | 'w' | 'm' | 'y').then_some((sign * amount, unit))
+}
+
+fn shift_date(date: NaiveDate, amount: i64, unit: char) -> Option<NaiveDate> {
+    let (days, months) = match unit {
+        'd' => (amount, 0),
+        'w' => (amount.checked_mul(7)?, 0),
+        'm' => (0, amount),
+        'y' => (0, amount.checked_mul(12)?),
+        _ => return None,
+    };
+    let magnitude = |n: i64| u32::try_from(n.unsigned_abs()).ok();
+    // Month math clamps to the end of shorter months: Jan 31 + 1m is Feb 28/29
+    if months > 0 {
+        date.checked_add_months(Months::new(magnitude(months)?))
+    } else if months < 0 {
+        date.checked_sub_months(Months::new(magnitude(months)?))
+    } else if days >= 0 {
+        date.checked_add_days(Days::new(days as u64))
+    } else {
+        date.checked_sub_days(Days::new(days.unsigned_abs()))
+    }
+}
+
+/// Check that every placeholder is a builtin, a date expression or one of
+/// `variables`
+fn validate(content: &str, variables: &[TemplateVariable]) -> Result<(), TemplateError> {
+    for variable in variables {
+        if !is_valid_variable_name(&variable.name)
+            || BUILTIN_VARIABLES.contains(&variable.name.as_str())
+        {
+            return Err(TemplateError::InvalidVariableName(variable.name.clone()));
+        }
+    }
+    for segment in parse(content)? {
+        if let Segment::Placeholder(name) = segment {
+            let known = BUILTIN_VARIABLES.contains(&name)
+                || parse_date_offset(name).is_some()
+                || variables.iter().any(|v| v.name == name);
+            if !known {
+                return Err(TemplateError::UnknownVariable(name.to_string()));
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Substitute the placeholders of `content`. Values are inserted verbatim and
+/// never rendered again, so a value containing `{{...}}` stays literal text.
+pub fn render_template(
+    content: &str,
+    variables: &[TemplateVariable],
+    ctx: &RenderContext,
+) -> Result<RenderedTemplate, TemplateError> {
+    validate(content, variables)?;
+
+    let mut out = String::with_capacity(content.len());
+    let mut cursor = None;
+    for segment in parse(content)? {
+        let name = match segment {
+            Segment::Text(text) => {
+                out.push_str(text);
+                continue;
+            }
+            Segment::Placeholder(name) => name,
+        };
+        match name {
+            "date" => out.push_str(&ctx.now.date().format("%Y-%m-%d").to_string()),
+            "time" => out.push_str(&ctx.now.format("%H:%M").to_string()),
+            "space" => out.push_str(&ctx.space_name),
+            "cursor" => {
+                cursor.get_or_insert_with(|| out.encode_utf16().count());
+            }
+            _ => {
+                if let Some((amount, unit)) = parse_date_offset(name) {
+                    let date = shift_date(ctx.now.date(), amount, unit).ok_or_else(|| {
+                        TemplateError::Malformed(format!("date out of range: {}", name))
+                    })?;
+                    out.push_str(&date.format("%Y-%m-%d").to_string());
+                    continue;
+                }
+                let default = variables
+                    .iter()
+                    .find(|v| v.name == name)
+                    .and_then(|v| v.default_value.as_ref());
+                let value = ctx
+                    .values
+                    .get(name)
+                    .or(default)
+                    .ok_or_else(|| TemplateError::MissingVariable(name.to_string()))?;
+                out.push_str(value);
+            }
+        }
+    }
+    Ok(RenderedTemplate {
+        content: out,
+        cursor,
+    })
+}
+
+pub fn create_note_template(
+    conn: &Connection,
+    space_id: &str,
+    name: &str,
+    content: &str,
+    variables: Vec<TemplateVariable>,
+) -> Result<NoteTemplate, TemplateError> {
+    validate(content, &variables)?;
+    let now = Utc::now().timestamp();
+    let template = NoteTemplate {
+        id: Ulid::new().to_string(),
+        space_id: space_id.to_string(),
+        name: name.to_string(),
+        content: content.to_string(),
+        variables,
+        created_at: now,
+        updated_at: now,
+    };
+    conn.execute(
+        "INSERT INTO note_template (id, space_id, name, content, variables_json, created_at, updated_at)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+        rusqlite::params![
+            template.id,
+            template.space_id,
+            template.name,
+            template.content,
+            serde_json::to_string(&template.variables)?,
+            template.created_at,
+            template.updated_at
+        ],
+    )?;
+    Ok(template)
+}
+
+fn map_template(row: &rusqlite::Row) -> rusqlite::Result<(NoteTemplate, String)> {
+    Ok((
+        NoteTemplate {
+            id: row.get(0)?,
+            space_id: row.get(1)?,
+            name: row.get(2)?,
+            content: row.get(3)?,
+            variables: Vec::new(),
+            created_at: row.get(5)?,
+            updated_at: row.get(6)?,
+        },
+        row.get(4)?,
+    ))
+}
+
+fn with_variables(
+    (mut template, variables_json): (NoteTemplate, String),
+) -> Result<NoteTemplate, TemplateError> {
+    template.variables = serde_json::from_str(&variables_json)?;
+    Ok(template)
+}
+
+pub fn get_note_template(conn: &Connection, id: &str) -> Result<NoteTemplate, TemplateError> {
+    let row = conn
+        .query_row(
+            "SELECT id, space_id, name, content, variables_json, created_at, updated_at
+             FROM note_template WHERE id = ?1",
+            [id],
+            map_template,
+        )
+        .optional()?
+        .ok_or_else(|| TemplateError::NotFound(id.to_string()))?;
+    with_variables(row)
+}
+
+pub fn get_note_templates_for_space(
+    conn: &Connection,
+    space_id: &str,
+) -> Result<Vec<NoteTemplate>, TemplateError> {
+    let mut stmt = conn.prepare(
+        "SELECT id, space_id, name, content, variables_json, created_at, updated_at
+         FROM note_template WHERE space_id = ?1 ORDER BY name",
+    )?;
+    let rows = stmt
+        .query_map([space_id], map_template)?
+        .collect::<Result<Vec<_>, _>>()?;
+    rows.into_iter().map(with_variables).collect()
+}
+
+pub fn delete_note_template(conn: &Connection, id: &str) -> Result<(), TemplateError> {
+    conn.execute("DELETE FROM note_template WHERE id = ?1", [id])?;
+    Ok(())
+}
+
+fn render_for_space(
+    conn: &Connection,
+    template: &NoteTemplate,
+    now: NaiveDateTime,
+    values: HashMap<String, String>,
+) -> Result<RenderedTemplate, TemplateError> {
+    let space_name: String = conn
+        .query_row(
+            "SELECT name FROM space WHERE id = ?1",
+            [&template.space_id],
+            |row| row.get(0),
+        )
+        .optional()?
+        .unwrap_or_default();
+    let ctx = RenderContext {
+        now,
+        space_name,
+        values,
+    };
+    render_template(&template.content, &template.variables, &ctx)
+}
+
+/// Render a template and create a note from it. The title comes from the
+/// `title` override, then the first heading of the rendered body, then the
+/// template name.
+pub fn create_note_from_template(
+    conn: &Connection,
+    template_id: &str,
+    overrides: HashMap<String, String>,
+) -> Result<NoteFromTemplate, TemplateError> {
+    let template = get_note_template(conn, template_id)?;
+    let title = overrides.get("title").cloned();
+    let rendered = render_for_space(conn, &template, Utc::now().naive_utc(), overrides)?;
+    let title = title
+        .or_else(|| first_heading(&rendered.content))
+        .unwrap_or_else(|| template.name.clone());
+    let note = create_note(conn, &template.space_id, &title, &rendered.content)?;
+    Ok(NoteFromTemplate {
+        note,
+        cursor: rendered.cursor,
+    })
+}
+
+fn first_heading(content: &str) -> Option<String> {
+    content
+        .lines()
+        .find_map(|line| line.strip_prefix("# "))
+        .map(|heading| heading.trim().to_string())
+        .filter(|heading| !heading.is_empty())
+}
+
+pub(crate) fn daily_template_key(space_id: &str) -> String {
+    format!("daily_note_template:{}", space_id)
+}
+
+/// Use `template_id` for new daily notes in the space, or the built-in
+/// layout again when `None`
+pub fn set_daily_note_template(
+    conn: &Connection,
+    space_id: &str,
+    template_id: Option<&str>,
+) -> Result<(), TemplateError> {
+    match template_id {
+        Some(id) => {
+            let template = get_note_template(conn, id)?;
+            if template.space_id != space_id {
+                return Err(TemplateError::NotFound(id.to_string()));
+            }
+            set_setting(
+                conn,
+                &daily_template_key(space_id),
+                id,
+                Some("Note template used for new daily notes"),
+            )?;
+        }
+        None => {
+            conn.execute(
+                "DELETE FROM settings WHERE key = ?1",
+                [daily_template_key(space_id)],
+            )?;
+        }
+    }
+    Ok(())
+}
+
+/// Render the space's daily note template for `now`, or `None` when the
+/// space has none or it was deleted
+pub(crate) fn render_daily_note_template(
+    conn: &Connection,
+    space_id: &str,
+    now: NaiveDateTime,
+) -> Result<Option<String>, TemplateError> {
+    let Some(template_id) = get_setting(conn, &daily_template_key(space_id))? else {
+        return Ok(None);
+    };
+    let template = match get_note_template(conn, &template_id) {
+        Ok(template) => template,
+        Err(TemplateError::NotFound(_)) => return Ok(None),
+        Err(e) => return Err(e),
+    };
+    let rendered = render_for_space(conn, &template, now, HashMap::new())?;
+    Ok(Some(rendered.content))
+}
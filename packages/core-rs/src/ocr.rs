@@ -1,7 +1,15 @@
-use rusqlite::Connection;
+use crate::db::settings::{load_settings, Settings, SettingsWatcher};
+use crate::events::{self, CoreEvent};
+use crate::vault::AutoLockGuard;
+use rusqlite::{Connection, OptionalExtension};
 use serde::{Deserialize, Serialize};
-use std::path::Path;
+use std::io::Write;
+use std::path::{Path, PathBuf};
 use std::process::Command;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread::JoinHandle;
+use std::time::Duration;
 use thiserror::Error;
 use ulid::Ulid;
 
@@ -15,6 +23,16 @@ pub enum OcrError {
     Processing(String),
     #[error("Tesseract not found or not executable")]
     TesseractNotFound,
+    #[error("Blob error: {0}")]
+    Blob(#[from] crate::blob::BlobError),
+    #[error("Connection pool error: {0}")]
+    Pool(#[from] r2d2::Error),
+    #[error("Invalid language tag: {0}")]
+    InvalidLanguage(String),
+    #[error("No OCR result for blob {0}")]
+    NotFound(String),
+    #[error(transparent)]
+    Settings(#[from] crate::db::DbError),
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
@@ -22,11 +40,46 @@ pub struct OcrResult {
     pub id: String,
     pub blob_id: String,
     pub extracted_text: Option<String>,
+    /// Mean word confidence, 0 to 1
     pub confidence: Option<f64>,
     pub processed_at: Option<i64>,
     pub status: OcrStatus,
     pub error_message: Option<String>,
     pub created_at: i64,
+    /// BCP-47 language hints the job was queued with; empty to use the
+    /// space's default languages
+    pub languages: Vec<String>,
+    /// Language the engine recognized the text as
+    pub detected_language: Option<String>,
+}
+
+const RESULT_COLUMNS: &str = "id, blob_id, extracted_text, confidence, status, processed_at,
+     error_message, created_at, languages, detected_language";
+
+fn result_from_row(row: &rusqlite::Row) -> rusqlite::Result<OcrResult> {
+    let status_str: String = row.get(4)?;
+    Ok(OcrResult {
+        id: row.get(0)?,
+        blob_id: row.get(1)?,
+        extracted_text: row.get(2)?,
+        confidence: row.get(3)?,
+        status: status_str.parse().unwrap_or(OcrStatus::Unknown),
+        processed_at: row.get(5)?,
+        error_message: row.get(6)?,
+        created_at: row.get(7)?,
+        languages: split_languages(row.get::<_, Option<String>>(8)?.as_deref()),
+        detected_language: row.get(9)?,
+    })
+}
+
+/// Text recognized in an image
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct OcrOutput {
+    pub text: String,
+    /// BCP-47 tag of the language the text was recognized as, if known
+    pub language: Option<String>,
+    /// Mean word confidence, 0 to 1
+    pub confidence: Option<f64>,
 }
 
 #[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
@@ -77,6 +130,11 @@ pub fn init_ocr_tables(conn: &Connection) -> Result<(), OcrError> {
             processed_at INTEGER,
             error_message TEXT,
             created_at INTEGER NOT NULL,
+            priority INTEGER NOT NULL DEFAULT 0,
+            attempts INTEGER NOT NULL DEFAULT 0,
+            next_attempt_at INTEGER,
+            languages TEXT,
+            detected_language TEXT,
             FOREIGN KEY (blob_id) REFERENCES blob(id) ON DELETE CASCADE
         )",
         [],
@@ -92,9 +150,178 @@ pub fn init_ocr_tables(conn: &Connection) -> Result<(), OcrError> {
         [],
     )?;
 
+    conn.execute(
+        "CREATE INDEX IF NOT EXISTS idx_ocr_result_queue ON ocr_result(status, priority DESC, created_at)",
+        [],
+    )?;
+
+    // Extracted text is indexed separately so it can be joined back to the owning notes
+    conn.execute(
+        "CREATE VIRTUAL TABLE IF NOT EXISTS fts_ocr USING fts5(
+            content,
+            blob_id UNINDEXED,
+            tokenize='porter unicode61 remove_diacritics 2'
+        )",
+        [],
+    )?;
+
+    conn.execute(
+        "CREATE TRIGGER IF NOT EXISTS ocr_result_ad AFTER DELETE ON ocr_result BEGIN
+            DELETE FROM fts_ocr WHERE blob_id = old.blob_id;
+        END",
+        [],
+    )?;
+
     Ok(())
 }
 
+/// Replace the indexed OCR text for a blob
+pub fn index_ocr_text(conn: &Connection, blob_id: &str, text: &str) -> Result<(), OcrError> {
+    conn.execute("DELETE FROM fts_ocr WHERE blob_id = ?1", [blob_id])?;
+    if !text.trim().is_empty() {
+        conn.execute(
+            "INSERT INTO fts_ocr (content, blob_id) VALUES (?1, ?2)",
+            rusqlite::params![text, blob_id],
+        )?;
+    }
+    Ok(())
+}
+
+/// Check that `tag` looks like a BCP-47 language tag ("de", "en-GB",
+/// "zh-Hant") and return it trimmed
+pub fn validate_language_tag(tag: &str) -> Result<String, OcrError> {
+    let tag = tag.trim();
+    let mut subtags = tag.split('-');
+    let primary = subtags.next().unwrap_or("");
+    let valid = tag.len() <= 35
+        && (2..=3).contains(&primary.len())
+        && primary.chars().all(|c| c.is_ascii_alphabetic())
+        && subtags
+            .all(|s| (1..=8).contains(&s.len()) && s.chars().all(|c| c.is_ascii_alphanumeric()));
+    if !valid {
+        return Err(OcrError::InvalidLanguage(tag.to_string()));
+    }
+    Ok(tag.to_string())
+}
+
+/// Validate language hints, dropping duplicates but keeping their order
+fn normalize_languages(languages: &[String]) -> Result<Vec<String>, OcrError> {
+    let mut normalized: Vec<String> = Vec::new();
+    for language in languages {
+        let tag = validate_language_tag(language)?;
+        if !normalized.iter().any(|t| t.eq_ignore_ascii_case(&tag)) {
+            normalized.push(tag);
+        }
+    }
+    Ok(normalized)
+}
+
+/// Languages are stored comma-separated; tags never contain commas
+fn join_languages(languages: &[String]) -> Option<String> {
+    (!languages.is_empty()).then(|| languages.join(","))
+}
+
+fn split_languages(stored: Option<&str>) -> Vec<String> {
+    stored
+        .unwrap_or("")
+        .split(',')
+        .filter(|tag| !tag.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+/// Tesseract model name for a BCP-47 tag. Three-letter tags are assumed to
+/// already name a model ("deu", "fra").
+pub fn tesseract_language_code(tag: &str) -> Result<String, OcrError> {
+    let lower = validate_language_tag(tag)?.to_ascii_lowercase();
+    let mut subtags = lower.split('-');
+    let primary = subtags.next().unwrap_or("");
+    let code = match primary {
+        "ar" => "ara",
+        "cs" => "ces",
+        "da" => "dan",
+        "de" => "deu",
+        "el" => "ell",
+        "en" => "eng",
+        "es" => "spa",
+        "fa" => "fas",
+        "fi" => "fin",
+        "fr" => "fra",
+        "he" => "heb",
+        "hi" => "hin",
+        "it" => "ita",
+        "ja" => "jpn",
+        "ko" => "kor",
+        "nb" | "no" => "nor",
+        "nl" => "nld",
+        "pl" => "pol",
+        "pt" => "por",
+        "ru" => "rus",
+        "sv" => "swe",
+        "tr" => "tur",
+        "uk" => "ukr",
+        "zh" if subtags.any(|s| matches!(s, "hant" | "tw" | "hk" | "mo")) => "chi_tra",
+        "zh" => "chi_sim",
+        other if other.len() == 3 => other,
+        _ => return Err(OcrError::InvalidLanguage(tag.to_string())),
+    };
+    Ok(code.to_string())
+}
+
+/// Common words used to tell hinted languages apart
+fn stopwords(primary: &str) -> &'static [&'static str] {
+    match primary {
+        "de" => &[
+            "der", "die", "das", "und", "ist", "nicht", "mit", "für", "von", "den", "zu", "ein",
+            "eine", "auf", "summe",
+        ],
+        "en" => &[
+            "the", "and", "of", "to", "in", "is", "for", "with", "that", "on", "this", "you",
+            "are", "total",
+        ],
+        "es" => &[
+            "el", "la", "los", "las", "y", "es", "de", "para", "con", "una", "que", "por", "del",
+        ],
+        "fr" => &[
+            "le", "la", "les", "et", "est", "des", "pour", "une", "dans", "que", "du", "avec",
+            "sur",
+        ],
+        "it" => &[
+            "il", "la", "di", "e", "che", "per", "una", "con", "non", "del", "sono", "gli",
+        ],
+        "nl" => &[
+            "de", "het", "een", "en", "van", "is", "niet", "met", "voor", "op", "dat", "zijn",
+        ],
+        "pt" => &[
+            "o", "a", "os", "as", "e", "de", "para", "com", "uma", "não", "que", "do", "da",
+        ],
+        _ => &[],
+    }
+}
+
+/// Which of the hinted languages `text` is most likely in, by counting common
+/// words. A single hint is taken as is; None if no hint's words occur.
+pub fn detect_language(text: &str, candidates: &[String]) -> Option<String> {
+    if let [only] = candidates {
+        return Some(only.clone());
+    }
+    let words: Vec<String> = text
+        .split(|c: char| !c.is_alphabetic())
+        .filter(|w| !w.is_empty())
+        .map(str::to_lowercase)
+        .collect();
+    let mut best: Option<(&String, usize)> = None;
+    for candidate in candidates {
+        let lower = candidate.to_ascii_lowercase();
+        let list = stopwords(lower.split('-').next().unwrap_or(""));
+        let hits = words.iter().filter(|w| list.contains(&w.as_str())).count();
+        if hits > 0 && best.is_none_or(|(_, most)| hits > most) {
+            best = Some((candidate, hits));
+        }
+    }
+    best.map(|(candidate, _)| candidate.clone())
+}
+
 fn find_tesseract() -> Option<String> {
     let possible_paths = vec![
         "tesseract",
@@ -126,7 +353,67 @@ pub fn process_image_ocr(image_path: &Path, language: Option<&str>) -> Result<St
             "Invalid language parameter".to_string(),
         ));
     }
+    run_tesseract(image_path, lang, None)
+}
+
+/// Recognize an image with Tesseract, using the models for the BCP-47
+/// `languages` (English when empty), and report word confidence and which of
+/// the languages the text is in
+pub fn process_image_ocr_with_languages(
+    image_path: &Path,
+    languages: &[String],
+) -> Result<OcrOutput, OcrError> {
+    let lang = if languages.is_empty() {
+        "eng".to_string()
+    } else {
+        languages
+            .iter()
+            .map(|tag| tesseract_language_code(tag))
+            .collect::<Result<Vec<_>, _>>()?
+            .join("+")
+    };
+    let tsv = run_tesseract(image_path, &lang, Some("tsv"))?;
+    let (text, confidence) = parse_tesseract_tsv(&tsv);
+    let language = detect_language(&text, languages);
+    Ok(OcrOutput {
+        text,
+        language,
+        confidence,
+    })
+}
+
+/// Rebuild the text from Tesseract's TSV output (one row per word, with
+/// block, paragraph and line numbers) along with the mean word confidence
+fn parse_tesseract_tsv(tsv: &str) -> (String, Option<f64>) {
+    let mut text = String::new();
+    let mut last_line: Option<[&str; 4]> = None;
+    let mut confidences = Vec::new();
+    for row in tsv.lines().skip(1) {
+        let cols: Vec<&str> = row.split('\t').collect();
+        if cols.len() < 12 || cols[0] != "5" || cols[11].trim().is_empty() {
+            continue;
+        }
+        let line = [cols[1], cols[2], cols[3], cols[4]];
+        match last_line {
+            Some(last) if last == line => text.push(' '),
+            Some(last) if last[..3] == line[..3] => text.push('\n'),
+            Some(_) => text.push_str("\n\n"),
+            None => {}
+        }
+        text.push_str(cols[11].trim());
+        last_line = Some(line);
+        if let Ok(conf) = cols[10].parse::<f64>() {
+            if conf >= 0.0 {
+                confidences.push(conf / 100.0);
+            }
+        }
+    }
+    let confidence = (!confidences.is_empty())
+        .then(|| confidences.iter().sum::<f64>() / confidences.len() as f64);
+    (text, confidence)
+}
 
+fn run_tesseract(image_path: &Path, lang: &str, config: Option<&str>) -> Result<String, OcrError> {
     let tesseract_path = find_tesseract().ok_or(OcrError::TesseractNotFound)?;
 
     if !image_path.exists() {
@@ -136,13 +423,12 @@ pub fn process_image_ocr(image_path: &Path, language: Option<&str>) -> Result<St
         )));
     }
 
-    let output = Command::new(&tesseract_path)
-        .arg(image_path)
-        .arg("stdout")
-        .arg("-l")
-        .arg(lang)
-        .output()
-        .map_err(OcrError::from)?;
+    let mut command = Command::new(&tesseract_path);
+    command.arg(image_path).arg("stdout").arg("-l").arg(lang);
+    if let Some(config) = config {
+        command.arg(config);
+    }
+    let output = command.output().map_err(OcrError::from)?;
 
     if !output.status.success() {
         let code = output.status.code().unwrap_or(-1);
@@ -155,52 +441,201 @@ pub fn process_image_ocr(image_path: &Path, language: Option<&str>) -> Result<St
     Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
 }
 
-pub fn queue_ocr(conn: &Connection, blob_id: &str) -> Result<String, OcrError> {
+/// Queue a blob for OCR in the BCP-47 `languages` (e.g. `["de", "en"]`);
+/// with none, the space's default OCR languages are used
+pub fn queue_ocr(
+    conn: &Connection,
+    blob_id: &str,
+    languages: &[String],
+) -> Result<String, OcrError> {
+    queue_ocr_with_priority(conn, blob_id, languages, 0)
+}
+
+/// Queue a blob for OCR. Jobs with a higher priority are picked up first;
+/// within the same priority the oldest job wins.
+pub fn queue_ocr_with_priority(
+    conn: &Connection,
+    blob_id: &str,
+    languages: &[String],
+    priority: i32,
+) -> Result<String, OcrError> {
     let id = Ulid::new().to_string();
     let now = chrono::Utc::now().timestamp();
+    let languages = join_languages(&normalize_languages(languages)?);
 
-    conn.execute(
-        "INSERT INTO ocr_result (id, blob_id, status, created_at) VALUES (?1, ?2, ?3, ?4)",
-        rusqlite::params![&id, blob_id, OcrStatus::Pending.as_str(), now],
+    // Re-queueing a blob resets its existing job so OCR can be re-run
+    let id: String = conn.query_row(
+        "INSERT INTO ocr_result (id, blob_id, status, created_at, priority, languages)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6)
+         ON CONFLICT(blob_id) DO UPDATE SET
+             status = excluded.status,
+             priority = excluded.priority,
+             languages = excluded.languages,
+             attempts = 0,
+             next_attempt_at = NULL,
+             error_message = NULL
+         RETURNING id",
+        rusqlite::params![
+            &id,
+            blob_id,
+            OcrStatus::Pending.as_str(),
+            now,
+            priority,
+            languages
+        ],
+        |row| row.get(0),
     )?;
 
     Ok(id)
 }
 
-pub fn get_ocr_status(conn: &Connection, blob_id: &str) -> Result<Option<OcrResult>, OcrError> {
-    let mut stmt = conn.prepare(
-        "SELECT id, blob_id, extracted_text, confidence, status, processed_at, error_message, created_at
-         FROM ocr_result WHERE blob_id = ?1",
+/// Run OCR on a blob again, e.g. after a result came out in the wrong
+/// language. The job is re-queued with the new hints and its text, in the
+/// result row and the search index, is replaced once the job completes.
+pub fn reprocess_ocr(
+    conn: &Connection,
+    blob_id: &str,
+    languages: &[String],
+) -> Result<String, OcrError> {
+    let priority: i32 = conn
+        .query_row(
+            "SELECT priority FROM ocr_result WHERE blob_id = ?1",
+            [blob_id],
+            |row| row.get(0),
+        )
+        .optional()?
+        .ok_or_else(|| OcrError::NotFound(blob_id.to_string()))?;
+    log::info!(
+        "[ocr] Reprocessing blob {} with languages {:?}",
+        blob_id,
+        languages
+    );
+    queue_ocr_with_priority(conn, blob_id, languages, priority)
+}
+
+fn space_languages_key(space_id: &str) -> String {
+    format!("ocr_languages:{}", space_id)
+}
+
+/// Languages a space's blobs are recognized in when queued without hints
+pub fn get_space_ocr_languages(conn: &Connection, space_id: &str) -> Result<Vec<String>, OcrError> {
+    Ok(split_languages(
+        crate::db::get_setting(conn, &space_languages_key(space_id))?.as_deref(),
+    ))
+}
+
+pub fn set_space_ocr_languages(
+    conn: &Connection,
+    space_id: &str,
+    languages: &[String],
+) -> Result<(), OcrError> {
+    let languages = normalize_languages(languages)?;
+    crate::db::set_setting(
+        conn,
+        &space_languages_key(space_id),
+        &languages.join(","),
+        Some("Default OCR languages"),
     )?;
+    Ok(())
+}
 
-    let result = stmt.query_row([blob_id], |row| {
-        let status_str: String = row.get(4)?;
-        Ok(OcrResult {
-            id: row.get(0)?,
-            blob_id: row.get(1)?,
-            extracted_text: row.get(2)?,
-            confidence: row.get(3)?,
-            status: status_str.parse().unwrap_or(OcrStatus::Unknown),
-            processed_at: row.get(5)?,
-            error_message: row.get(6)?,
-            created_at: row.get(7)?,
-        })
-    });
+/// Languages to recognize a blob in: the job's own hints, else the default of
+/// the space whose note it's attached to, else `fallback`
+pub fn resolve_ocr_languages(
+    conn: &Connection,
+    blob_id: &str,
+    fallback: &[String],
+) -> Result<Vec<String>, OcrError> {
+    let stored: Option<String> = conn
+        .query_row(
+            "SELECT languages FROM ocr_result WHERE blob_id = ?1",
+            [blob_id],
+            |row| row.get(0),
+        )
+        .optional()?
+        .flatten();
+    let languages = split_languages(stored.as_deref());
+    if !languages.is_empty() {
+        return Ok(languages);
+    }
 
-    match result {
-        Ok(r) => Ok(Some(r)),
-        Err(rusqlite::Error::QueryReturnedNoRows) => Ok(None),
-        Err(e) => Err(e.into()),
+    // Blobs may be stored without being attached to anything
+    if crate::db::table_exists(conn, "blob_ref")? {
+        let space_id: Option<String> = conn
+            .query_row(
+                "SELECT n.space_id FROM blob_ref r
+                 JOIN note n ON n.id = r.owner_id
+                 WHERE r.blob_id = ?1 AND r.owner_type = 'note'
+                 ORDER BY r.created_at, r.owner_id
+                 LIMIT 1",
+                [blob_id],
+                |row| row.get(0),
+            )
+            .optional()?;
+        if let Some(space_id) = space_id {
+            let languages = get_space_ocr_languages(conn, &space_id)?;
+            if !languages.is_empty() {
+                return Ok(languages);
+            }
+        }
     }
+    Ok(fallback.to_vec())
+}
+
+pub fn get_ocr_status(conn: &Connection, blob_id: &str) -> Result<Option<OcrResult>, OcrError> {
+    let mut stmt = conn.prepare(&format!(
+        "SELECT {} FROM ocr_result WHERE blob_id = ?1",
+        RESULT_COLUMNS
+    ))?;
+    Ok(stmt.query_row([blob_id], result_from_row).optional()?)
 }
 
+/// Jobs waiting to be processed, oldest first
+pub fn get_pending_ocr_jobs(conn: &Connection, limit: u32) -> Result<Vec<OcrResult>, OcrError> {
+    let mut stmt = conn.prepare(&format!(
+        "SELECT {} FROM ocr_result WHERE status = 'pending' ORDER BY created_at ASC LIMIT ?1",
+        RESULT_COLUMNS
+    ))?;
+    let results = stmt
+        .query_map([limit], result_from_row)?
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(results)
+}
+
+/// Store recognized text on a blob's job and replace its indexed text
+fn store_ocr_output(
+    conn: &Connection,
+    blob_id: &str,
+    output: &OcrOutput,
+    now: i64,
+) -> Result<(), OcrError> {
+    conn.execute(
+        "UPDATE ocr_result SET extracted_text = ?1, detected_language = ?2, confidence = ?3,
+         status = ?4, processed_at = ?5, error_message = NULL, next_attempt_at = NULL
+         WHERE blob_id = ?6",
+        rusqlite::params![
+            &output.text,
+            &output.language,
+            output.confidence,
+            OcrStatus::Completed.as_str(),
+            now,
+            blob_id
+        ],
+    )?;
+    index_ocr_text(conn, blob_id, &output.text)
+}
+
+/// Recognize a queued blob's image with `engine`, in the languages from
+/// [`resolve_ocr_languages`], and store the result
 pub fn process_ocr_job(
     conn: &Connection,
+    engine: &dyn OcrEngine,
     blob_id: &str,
     image_path: &Path,
-    language: Option<&str>,
+    default_languages: &[String],
 ) -> Result<String, OcrError> {
     let now = chrono::Utc::now().timestamp();
+    let languages = resolve_ocr_languages(conn, blob_id, default_languages)?;
 
     {
         let tx = conn.unchecked_transaction()?;
@@ -211,17 +646,14 @@ pub fn process_ocr_job(
         tx.commit()?;
     }
 
-    let text_or_err = process_image_ocr(image_path, language);
+    let output_or_err = engine.recognize(image_path, &languages);
 
     let tx2 = conn.unchecked_transaction()?;
-    match text_or_err {
-        Ok(text) => {
-            tx2.execute(
-                "UPDATE ocr_result SET extracted_text = ?1, status = ?2, processed_at = ?3 WHERE blob_id = ?4",
-                rusqlite::params![&text, OcrStatus::Completed.as_str(), now, blob_id],
-            )?;
+    match output_or_err {
+        Ok(output) => {
+            store_ocr_output(&tx2, blob_id, &output, now)?;
             tx2.commit()?;
-            Ok(text)
+            Ok(output.text)
         }
         Err(e) => {
             tx2.execute(
@@ -234,33 +666,29 @@ pub fn process_ocr_job(
     }
 }
 
+/// Completed results containing `query`, optionally only those detected as
+/// `language` ("de" also matches "de-AT")
 pub fn search_ocr_text(
     conn: &Connection,
     query: &str,
+    language: Option<&str>,
     limit: u32,
 ) -> Result<Vec<OcrResult>, OcrError> {
-    let mut stmt = conn.prepare(
-        "SELECT id, blob_id, extracted_text, confidence, status, processed_at, error_message, created_at
-         FROM ocr_result
+    let mut stmt = conn.prepare(&format!(
+        "SELECT {} FROM ocr_result
          WHERE status = 'completed' AND extracted_text LIKE ?1
+           AND (?2 IS NULL OR lower(detected_language) = lower(?2)
+                OR lower(detected_language) LIKE lower(?2) || '-%')
          ORDER BY processed_at DESC
-         LIMIT ?2",
-    )?;
+         LIMIT ?3",
+        RESULT_COLUMNS
+    ))?;
 
     let search_pattern = format!("%{}%", query);
-    let rows = stmt.query_map(rusqlite::params![search_pattern, limit], |row| {
-        let status_str: String = row.get(4)?;
-        Ok(OcrResult {
-            id: row.get(0)?,
-            blob_id: row.get(1)?,
-            extracted_text: row.get(2)?,
-            confidence: row.get(3)?,
-            status: status_str.parse().unwrap_or(OcrStatus::Unknown),
-            processed_at: row.get(5)?,
-            error_message: row.get(6)?,
-            created_at: row.get(7)?,
-        })
-    })?;
+    let rows = stmt.query_map(
+        rusqlite::params![search_pattern, language, limit],
+        result_from_row,
+    )?;
 
     let mut results = Vec::new();
     for row in rows {
@@ -268,3 +696,415 @@ pub fn search_ocr_text(
     }
     Ok(results)
 }
+
+/// Number of jobs still waiting to be processed (including ones in backoff).
+pub fn get_ocr_queue_depth(conn: &Connection) -> Result<usize, OcrError> {
+    let count: i64 = conn.query_row(
+        "SELECT COUNT(*) FROM ocr_result WHERE status = 'pending'",
+        [],
+        |row| row.get(0),
+    )?;
+    Ok(count as usize)
+}
+
+/// Text recognition backend used by [`OcrWorker`] and [`process_ocr_job`].
+pub trait OcrEngine: Send + Sync {
+    /// Recognize the image in any of the BCP-47 `languages`, or the engine's
+    /// default language when empty
+    fn recognize(&self, image_path: &Path, languages: &[String]) -> Result<OcrOutput, OcrError>;
+}
+
+/// Default engine that shells out to the Tesseract CLI.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct TesseractEngine;
+
+impl OcrEngine for TesseractEngine {
+    fn recognize(&self, image_path: &Path, languages: &[String]) -> Result<OcrOutput, OcrError> {
+        process_image_ocr_with_languages(image_path, languages)
+    }
+}
+
+/// A job claimed from the queue by a worker.
+#[derive(Debug, Clone, PartialEq)]
+pub struct OcrJob {
+    pub id: String,
+    pub blob_id: String,
+    pub attempts: u32,
+}
+
+/// Result of processing a single claimed job.
+#[derive(Debug, Clone, PartialEq)]
+pub enum OcrJobOutcome {
+    Completed,
+    /// The job failed and was re-queued; it becomes eligible again at `next_attempt_at`.
+    Retrying {
+        next_attempt_at: i64,
+    },
+    /// The job exhausted its attempts and is marked failed.
+    Failed,
+}
+
+#[derive(Debug, Clone)]
+pub struct OcrWorkerConfig {
+    /// Vault directory holding the encrypted blob objects.
+    pub vault_path: PathBuf,
+    /// Holds the key blobs are decrypted with. Each job takes a copy that is
+    /// wiped when it finishes, and no job starts once the vault has locked.
+    pub auto_lock: Arc<AutoLockGuard>,
+    /// BCP-47 languages for jobs with no hints of their own whose space has
+    /// no default either.
+    pub languages: Vec<String>,
+    /// Number of jobs processed in parallel.
+    pub concurrency: usize,
+    /// Attempts before a job is marked failed.
+    pub max_attempts: u32,
+    /// Base delay for exponential backoff between attempts.
+    pub retry_backoff_secs: i64,
+    /// How long an idle worker thread sleeps before polling again.
+    pub poll_interval: Duration,
+}
+
+impl OcrWorkerConfig {
+    pub fn new(vault_path: impl Into<PathBuf>, auto_lock: Arc<AutoLockGuard>) -> Self {
+        Self::from_settings(vault_path, auto_lock, &OcrSettings::default())
+    }
+
+    /// A config following the vault's [`OcrSettings`]
+    pub fn from_settings(
+        vault_path: impl Into<PathBuf>,
+        auto_lock: Arc<AutoLockGuard>,
+        settings: &OcrSettings,
+    ) -> Self {
+        Self {
+            vault_path: vault_path.into(),
+            auto_lock,
+            languages: settings.languages.clone(),
+            concurrency: settings.concurrency,
+            max_attempts: settings.max_attempts,
+            retry_backoff_secs: settings.retry_backoff_secs,
+            poll_interval: Duration::from_secs(settings.poll_interval_secs),
+        }
+    }
+
+    /// Take over the settings a running worker follows; the number of
+    /// threads only changes when the worker is restarted
+    pub fn apply_settings(&mut self, settings: &OcrSettings) {
+        self.languages = settings.languages.clone();
+        self.max_attempts = settings.max_attempts;
+        self.retry_backoff_secs = settings.retry_backoff_secs;
+        self.poll_interval = Duration::from_secs(settings.poll_interval_secs);
+    }
+}
+
+/// Most worker threads [`OcrSettings`] can ask for
+pub const MAX_OCR_CONCURRENCY: usize = 16;
+
+/// OCR worker settings of the vault
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct OcrSettings {
+    /// BCP-47 languages for jobs with no hints whose space has no default
+    pub languages: Vec<String>,
+    /// Jobs processed in parallel
+    pub concurrency: usize,
+    /// Attempts before a job is marked failed
+    pub max_attempts: u32,
+    /// Base delay for exponential backoff between attempts
+    pub retry_backoff_secs: i64,
+    /// How long an idle worker thread sleeps before polling again
+    pub poll_interval_secs: u64,
+}
+
+impl Default for OcrSettings {
+    fn default() -> Self {
+        OcrSettings {
+            languages: Vec::new(),
+            concurrency: 2,
+            max_attempts: 3,
+            retry_backoff_secs: 30,
+            poll_interval_secs: 2,
+        }
+    }
+}
+
+impl Settings for OcrSettings {
+    const KEY: &'static str = "ocr.settings";
+    const DESCRIPTION: &'static str = "Background OCR worker";
+
+    fn validate(&self) -> Result<(), String> {
+        for tag in &self.languages {
+            validate_language_tag(tag).map_err(|e| e.to_string())?;
+        }
+        if !(1..=MAX_OCR_CONCURRENCY).contains(&self.concurrency) {
+            return Err(format!(
+                "concurrency must be between 1 and {}",
+                MAX_OCR_CONCURRENCY
+            ));
+        }
+        if self.max_attempts == 0 {
+            return Err("max_attempts must be at least 1".to_string());
+        }
+        if self.retry_backoff_secs <= 0 || self.poll_interval_secs == 0 {
+            return Err("retry_backoff_secs and poll_interval_secs must be positive".to_string());
+        }
+        Ok(())
+    }
+}
+
+/// Background worker that drains the OCR queue.
+///
+/// Jobs are claimed with a single atomic `UPDATE ... RETURNING`, so any number of
+/// worker threads (or workers in different processes sharing the vault) never
+/// process the same job twice.
+pub struct OcrWorker<M = r2d2_sqlite::SqliteConnectionManager>
+where
+    M: r2d2::ManageConnection<Connection = Connection>,
+{
+    pool: r2d2::Pool<M>,
+    engine: Arc<dyn OcrEngine>,
+    config: Arc<OcrWorkerConfig>,
+    running: Arc<AtomicBool>,
+    handles: Vec<JoinHandle<()>>,
+}
+
+impl<M> OcrWorker<M>
+where
+    M: r2d2::ManageConnection<Connection = Connection>,
+{
+    pub fn new(pool: r2d2::Pool<M>, engine: Arc<dyn OcrEngine>, config: OcrWorkerConfig) -> Self {
+        Self {
+            pool,
+            engine,
+            config: Arc::new(config),
+            running: Arc::new(AtomicBool::new(false)),
+            handles: Vec::new(),
+        }
+    }
+
+    /// Spawn `concurrency` threads that keep draining the queue until [`stop`](Self::stop).
+    pub fn start(&mut self) {
+        if self.running.swap(true, Ordering::SeqCst) {
+            return;
+        }
+
+        let threads = self.config.concurrency.max(1);
+        log::info!("[ocr] Starting OCR worker with {} threads", threads);
+        for _ in 0..threads {
+            let pool = self.pool.clone();
+            let engine = self.engine.clone();
+            let mut config = (*self.config).clone();
+            let running = self.running.clone();
+            self.handles.push(std::thread::spawn(move || {
+                let settings = SettingsWatcher::new::<OcrSettings>();
+                while running.load(Ordering::SeqCst) {
+                    if settings.changed() {
+                        reload_settings(&pool, &mut config);
+                    }
+                    match run_next_job(&pool, engine.as_ref(), &config) {
+                        Ok(Some(_)) => continue,
+                        Ok(None) => {}
+                        Err(e) => log::error!("[ocr] Worker error: {}", e),
+                    }
+                    std::thread::sleep(config.poll_interval);
+                }
+            }));
+        }
+    }
+
+    /// Signal all worker threads to stop and wait for in-flight jobs to finish.
+    pub fn stop(&mut self) {
+        self.running.store(false, Ordering::SeqCst);
+        for handle in self.handles.drain(..) {
+            if handle.join().is_err() {
+                log::error!("[ocr] Worker thread panicked");
+            }
+        }
+        log::info!("[ocr] OCR worker stopped");
+    }
+
+    pub fn is_running(&self) -> bool {
+        self.running.load(Ordering::SeqCst)
+    }
+
+    pub fn queue_depth(&self) -> Result<usize, OcrError> {
+        let conn = self.pool.get()?;
+        get_ocr_queue_depth(&conn)
+    }
+
+    /// Claim and process one job on the calling thread.
+    /// Returns `None` when no job is currently eligible.
+    pub fn process_next(&self) -> Result<Option<(OcrJob, OcrJobOutcome)>, OcrError> {
+        run_next_job(&self.pool, self.engine.as_ref(), &self.config)
+    }
+}
+
+impl<M> Drop for OcrWorker<M>
+where
+    M: r2d2::ManageConnection<Connection = Connection>,
+{
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+/// Pick up settings saved while the worker runs
+fn reload_settings<M>(pool: &r2d2::Pool<M>, config: &mut OcrWorkerConfig)
+where
+    M: r2d2::ManageConnection<Connection = Connection>,
+{
+    let settings = pool
+        .get()
+        .map_err(|e| e.to_string())
+        .and_then(|conn| load_settings::<OcrSettings>(&conn).map_err(|e| e.to_string()));
+    match settings {
+        Ok(settings) => {
+            config.apply_settings(&settings);
+            log::info!("[ocr] Reloaded OCR settings");
+        }
+        Err(e) => log::warn!("[ocr] Keeping previous settings, reload failed: {}", e),
+    }
+}
+
+/// How long a claimed job may stay in `processing` before another worker
+/// takes it over, such as after the process running it was killed
+pub const OCR_JOB_LEASE_SECS: i64 = 15 * 60;
+
+/// Atomically move the next eligible job to `processing` and bump its attempt counter.
+/// A job whose lease ran out while `processing` is eligible again.
+pub fn claim_next_ocr_job(conn: &Connection) -> Result<Option<OcrJob>, OcrError> {
+    let now = chrono::Utc::now().timestamp();
+    let job = conn
+        .query_row(
+            "UPDATE ocr_result SET status = 'processing', attempts = attempts + 1,
+                 next_attempt_at = ?2
+             WHERE id = (
+                 SELECT id FROM ocr_result
+                 WHERE status IN ('pending', 'processing')
+                   AND (next_attempt_at IS NULL OR next_attempt_at <= ?1)
+                 ORDER BY priority DESC, created_at ASC, id ASC
+                 LIMIT 1
+             ) AND status IN ('pending', 'processing')
+               AND (next_attempt_at IS NULL OR next_attempt_at <= ?1)
+             RETURNING id, blob_id, attempts",
+            [now, now + OCR_JOB_LEASE_SECS],
+            |row| {
+                Ok(OcrJob {
+                    id: row.get(0)?,
+                    blob_id: row.get(1)?,
+                    attempts: row.get(2)?,
+                })
+            },
+        )
+        .optional()?;
+    Ok(job)
+}
+
+fn run_next_job<M>(
+    pool: &r2d2::Pool<M>,
+    engine: &dyn OcrEngine,
+    config: &OcrWorkerConfig,
+) -> Result<Option<(OcrJob, OcrJobOutcome)>, OcrError>
+where
+    M: r2d2::ManageConnection<Connection = Connection>,
+{
+    // A locked vault has no key to decrypt with; the job waits for unlock
+    if config.auto_lock.is_locked() {
+        return Ok(None);
+    }
+    let (job, languages) = {
+        let conn = pool.get()?;
+        match claim_next_ocr_job(&conn)? {
+            // Failing to look up the languages is retried like a failed recognition
+            Some(job) => {
+                let languages = resolve_ocr_languages(&conn, &job.blob_id, &config.languages);
+                (job, languages)
+            }
+            None => return Ok(None),
+        }
+    };
+
+    // Don't hold a pooled connection while the engine runs
+    let result = languages.and_then(|languages| recognize_blob(engine, config, &job, &languages));
+
+    let conn = pool.get()?;
+    let outcome = finish_ocr_job(&conn, &job, result, config)?;
+    Ok(Some((job, outcome)))
+}
+
+fn recognize_blob(
+    engine: &dyn OcrEngine,
+    config: &OcrWorkerConfig,
+    job: &OcrJob,
+    languages: &[String],
+) -> Result<OcrOutput, OcrError> {
+    let dek = config
+        .auto_lock
+        .dek()
+        .map_err(|e| OcrError::Processing(e.to_string()))?;
+    let content =
+        crate::blob::retrieve_blob(&config.vault_path.to_string_lossy(), &dek[..], &job.blob_id)?;
+    // The decrypted image only exists in a directory of our own, readable by
+    // us alone, and both go away when dropped
+    let dir = tempfile::Builder::new().prefix("noteece_ocr_").tempdir()?;
+    let mut image = tempfile::NamedTempFile::new_in(dir.path())?;
+    image.write_all(&content)?;
+    image.flush()?;
+    engine.recognize(image.path(), languages)
+}
+
+fn finish_ocr_job(
+    conn: &Connection,
+    job: &OcrJob,
+    result: Result<OcrOutput, OcrError>,
+    config: &OcrWorkerConfig,
+) -> Result<OcrJobOutcome, OcrError> {
+    let now = chrono::Utc::now().timestamp();
+    match result {
+        Ok(output) => {
+            let tx = conn.unchecked_transaction()?;
+            store_ocr_output(&tx, &job.blob_id, &output, now)?;
+            tx.commit()?;
+            log::info!("[ocr] Completed job {} for blob {}", job.id, job.blob_id);
+            events::emit(CoreEvent::OcrJobCompleted {
+                job_id: job.id.clone(),
+                blob_id: job.blob_id.clone(),
+            });
+            Ok(OcrJobOutcome::Completed)
+        }
+        Err(e) if job.attempts < config.max_attempts => {
+            let exponent = job.attempts.saturating_sub(1).min(16);
+            let next_attempt_at = now + config.retry_backoff_secs * (1i64 << exponent);
+            conn.execute(
+                "UPDATE ocr_result SET status = ?1, error_message = ?2, next_attempt_at = ?3 WHERE id = ?4",
+                rusqlite::params![
+                    OcrStatus::Pending.as_str(),
+                    e.to_string(),
+                    next_attempt_at,
+                    &job.id
+                ],
+            )?;
+            log::warn!(
+                "[ocr] Job {} failed (attempt {}/{}): {}",
+                job.id,
+                job.attempts,
+                config.max_attempts,
+                e
+            );
+            Ok(OcrJobOutcome::Retrying { next_attempt_at })
+        }
+        Err(e) => {
+            conn.execute(
+                "UPDATE ocr_result SET status = ?1, error_message = ?2, processed_at = ?3,
+                 next_attempt_at = NULL WHERE id = ?4",
+                rusqlite::params![OcrStatus::Failed.as_str(), e.to_string(), now, &job.id],
+            )?;
+            log::error!("[ocr] Job {} failed permanently: {}", job.id, e);
+            events::emit(CoreEvent::OcrJobFailed {
+                job_id: job.id.clone(),
+                blob_id: job.blob_id.clone(),
+                error: e.to_string(),
+            });
+            Ok(OcrJobOutcome::Failed)
+        }
+    }
+}
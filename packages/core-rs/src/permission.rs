@@ -0,0 +1,116 @@
+//! Permission enforcement for shared spaces.
+//!
+//! Changes made on behalf of a user go through the `_as` variants of the
+//! note, task, project and membership functions, which check the user's
+//! permissions in the space with the same lookup as
+//! [`check_permission`](crate::collaboration::check_permission). A space
+//! nobody has been added to is personal and isn't checked, so single-user
+//! vaults and the plain functions keep working as before.
+
+use crate::collaboration::has_permission;
+use rusqlite::{Connection, OptionalExtension};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+pub const PERMISSION_READ: &str = "read_notes";
+pub const PERMISSION_WRITE: &str = "write_notes";
+pub const PERMISSION_DELETE: &str = "delete_notes";
+pub const PERMISSION_ADMIN: &str = "admin";
+
+/// The user a change is made for
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ActorContext {
+    pub user_id: String,
+    /// Set when the change arrived from one of the user's devices by sync
+    pub via_sync: bool,
+}
+
+impl ActorContext {
+    pub fn local(user_id: impl Into<String>) -> Self {
+        Self {
+            user_id: user_id.into(),
+            via_sync: false,
+        }
+    }
+
+    pub fn sync(user_id: impl Into<String>) -> Self {
+        Self {
+            user_id: user_id.into(),
+            via_sync: true,
+        }
+    }
+}
+
+#[derive(Error, Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[error("Permission denied: {user_id} lacks {permission} in space {space_id}")]
+pub struct PermissionDenied {
+    pub user_id: String,
+    pub space_id: String,
+    pub permission: String,
+    pub via_sync: bool,
+}
+
+/// Whether anyone has been added to the space. Vaults that never set up
+/// collaboration have no membership tables and nothing shared.
+pub fn is_shared_space(conn: &Connection, space_id: &str) -> Result<bool, rusqlite::Error> {
+    if !crate::db::table_exists(conn, "space_users")? {
+        return Ok(false);
+    }
+    conn.query_row(
+        "SELECT EXISTS(SELECT 1 FROM space_users WHERE space_id = ?1)",
+        [space_id],
+        |row| row.get(0),
+    )
+}
+
+/// Whether `user_id` may act with `permission` in the space: always in a
+/// personal space, otherwise only as an active member granted it
+pub fn is_permitted(
+    conn: &Connection,
+    space_id: &str,
+    user_id: &str,
+    permission: &str,
+) -> Result<bool, rusqlite::Error> {
+    if !is_shared_space(conn, space_id)? {
+        return Ok(true);
+    }
+    let active = conn
+        .query_row(
+            "SELECT 1 FROM space_users
+             WHERE space_id = ?1 AND user_id = ?2 AND status = 'active'",
+            [space_id, user_id],
+            |_| Ok(()),
+        )
+        .optional()?
+        .is_some();
+    Ok(active && has_permission(conn, space_id, user_id, permission)?)
+}
+
+/// Fail with [`PermissionDenied`] unless `actor` has `permission` in the space
+pub fn authorize<E>(
+    conn: &Connection,
+    actor: &ActorContext,
+    space_id: &str,
+    permission: &str,
+) -> Result<(), E>
+where
+    E: From<PermissionDenied> + From<rusqlite::Error>,
+{
+    if is_permitted(conn, space_id, &actor.user_id, permission)? {
+        return Ok(());
+    }
+    log::warn!(
+        "[permission] Denied {} to {} in space {}{}",
+        permission,
+        actor.user_id,
+        space_id,
+        if actor.via_sync { " (sync)" } else { "" }
+    );
+    Err(PermissionDenied {
+        user_id: actor.user_id.clone(),
+        space_id: space_id.to_string(),
+        permission: permission.to_string(),
+        via_sync: actor.via_sync,
+    }
+    .into())
+}
@@ -1,7 +1,12 @@
+use crate::calendar::TimeRange;
 use crate::db::DbError;
+use crate::form::{create_form_template, get_form_templates_for_space, FormField, FormFieldType};
 use crate::mode::{disable_mode, enable_mode, Mode};
-use rusqlite::{Connection, Result};
+use chrono::NaiveDate;
+use rusqlite::{Connection, OptionalExtension, Result};
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use thiserror::Error;
 use ulid::Ulid;
 
 // --- Constants ---
@@ -10,6 +15,18 @@ pub const MODE_TRAVEL: &str = "travel";
 pub const MODE_FINANCE: &str = "finance";
 pub const MODE_HEALTH: &str = "health";
 
+/// Expense categories offered by the Finance mode's example template
+pub const DEFAULT_EXPENSE_CATEGORIES: &[&str] = &[
+    "Housing",
+    "Groceries",
+    "Transport",
+    "Dining",
+    "Utilities",
+    "Health",
+    "Entertainment",
+    "Other",
+];
+
 pub fn init_personal_modes_tables(conn: &Connection) -> Result<(), DbError> {
     conn.execute(
         "CREATE TABLE IF NOT EXISTS health_metric (
@@ -42,6 +59,20 @@ pub fn init_personal_modes_tables(conn: &Connection) -> Result<(), DbError> {
         [],
     )?;
 
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS budget (
+            space_id TEXT NOT NULL,
+            category TEXT NOT NULL,
+            monthly_amount REAL NOT NULL,
+            rollover INTEGER NOT NULL DEFAULT 0,
+            start_month TEXT NOT NULL,
+            created_at INTEGER NOT NULL,
+            updated_at INTEGER NOT NULL,
+            PRIMARY KEY (space_id, category)
+        )",
+        [],
+    )?;
+
     conn.execute(
         "CREATE TABLE IF NOT EXISTS recipe (
             id TEXT PRIMARY KEY,
@@ -50,11 +81,50 @@ pub fn init_personal_modes_tables(conn: &Connection) -> Result<(), DbError> {
             name TEXT NOT NULL,
             rating INTEGER NOT NULL,
             difficulty TEXT NOT NULL,
+            created_at INTEGER NOT NULL,
+            servings INTEGER NOT NULL DEFAULT 1
+        )",
+        [],
+    )?;
+
+    // Ingredients and shopping lists, see crate::recipe
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS recipe_ingredient (
+            id TEXT PRIMARY KEY,
+            recipe_id TEXT NOT NULL REFERENCES recipe(id) ON DELETE CASCADE,
+            name TEXT NOT NULL,
+            quantity REAL NOT NULL,
+            unit TEXT NOT NULL,
+            note TEXT,
+            position INTEGER NOT NULL
+        )",
+        [],
+    )?;
+
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS shopping_list (
+            id TEXT PRIMARY KEY,
+            space_id TEXT NOT NULL,
+            recipe_ids_json TEXT NOT NULL,
             created_at INTEGER NOT NULL
         )",
         [],
     )?;
 
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS shopping_list_item (
+            id TEXT PRIMARY KEY,
+            list_id TEXT NOT NULL REFERENCES shopping_list(id) ON DELETE CASCADE,
+            name TEXT NOT NULL,
+            quantity REAL NOT NULL,
+            unit TEXT NOT NULL,
+            unmergeable INTEGER NOT NULL DEFAULT 0,
+            is_checked INTEGER NOT NULL DEFAULT 0,
+            position INTEGER NOT NULL
+        )",
+        [],
+    )?;
+
     conn.execute(
         "CREATE TABLE IF NOT EXISTS trip (
             id TEXT PRIMARY KEY,
@@ -69,6 +139,64 @@ pub fn init_personal_modes_tables(conn: &Connection) -> Result<(), DbError> {
         [],
     )?;
 
+    // Itineraries and packing lists, see crate::travel
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS itinerary_item (
+            id TEXT PRIMARY KEY,
+            trip_id TEXT NOT NULL REFERENCES trip(id) ON DELETE CASCADE,
+            title TEXT NOT NULL,
+            item_type TEXT NOT NULL,
+            location TEXT,
+            notes TEXT,
+            starts_at INTEGER NOT NULL,
+            day TEXT NOT NULL,
+            position INTEGER NOT NULL,
+            created_at INTEGER NOT NULL
+        )",
+        [],
+    )?;
+    conn.execute(
+        "CREATE INDEX IF NOT EXISTS idx_itinerary_item_day ON itinerary_item(trip_id, day, position)",
+        [],
+    )?;
+
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS packing_item (
+            id TEXT PRIMARY KEY,
+            trip_id TEXT NOT NULL REFERENCES trip(id) ON DELETE CASCADE,
+            name TEXT NOT NULL,
+            category TEXT,
+            quantity INTEGER NOT NULL DEFAULT 1,
+            is_packed INTEGER NOT NULL DEFAULT 0,
+            position INTEGER NOT NULL,
+            created_at INTEGER NOT NULL
+        )",
+        [],
+    )?;
+
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS packing_template (
+            id TEXT PRIMARY KEY,
+            space_id TEXT NOT NULL,
+            name TEXT NOT NULL,
+            created_at INTEGER NOT NULL,
+            UNIQUE (space_id, name)
+        )",
+        [],
+    )?;
+
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS packing_template_item (
+            template_id TEXT NOT NULL REFERENCES packing_template(id) ON DELETE CASCADE,
+            position INTEGER NOT NULL,
+            name TEXT NOT NULL,
+            category TEXT,
+            quantity INTEGER NOT NULL DEFAULT 1,
+            PRIMARY KEY (template_id, position)
+        )",
+        [],
+    )?;
+
     Ok(())
 }
 
@@ -110,6 +238,8 @@ pub struct Recipe {
     pub rating: i32,        // 1-5
     pub difficulty: String, // 'easy', 'medium', 'hard'
     pub created_at: i64,
+    /// Servings the ingredient quantities are for
+    pub servings: i64,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -171,6 +301,87 @@ pub fn disable_health_mode(conn: &Connection, space_id: &str) -> Result<(), DbEr
     disable_mode(conn, space_id, &get_health_mode())
 }
 
+// --- Mode Setup ---
+
+fn form_field(name: &str, label: &str, field_type: FormFieldType, required: bool) -> FormField {
+    FormField {
+        name: name.to_string(),
+        label: label.to_string(),
+        field_type,
+        default_value: None,
+        required,
+        options: Vec::new(),
+        mapping: None,
+    }
+}
+
+/// Create a form template unless the space already has one with this name
+fn ensure_form_template(
+    conn: &Connection,
+    space_id: &str,
+    name: &str,
+    fields: Vec<FormField>,
+) -> Result<(), DbError> {
+    let exists = get_form_templates_for_space(conn, space_id)?
+        .iter()
+        .any(|t| t.name == name);
+    if !exists {
+        create_form_template(conn, space_id, name, fields)?;
+    }
+    Ok(())
+}
+
+pub fn setup_health_mode(conn: &Connection, space_id: &str) -> Result<(), DbError> {
+    ensure_form_template(
+        conn,
+        space_id,
+        "Health Check-in",
+        vec![
+            form_field("weight", "Weight (kg)", FormFieldType::Number, false),
+            form_field("sleep", "Sleep (hours)", FormFieldType::Number, false),
+            FormField {
+                options: ["1", "2", "3", "4", "5"].map(String::from).to_vec(),
+                ..form_field("mood", "Mood", FormFieldType::Select, false)
+            },
+            form_field("notes", "Notes", FormFieldType::Textarea, false),
+        ],
+    )
+}
+
+pub fn setup_finance_mode(conn: &Connection, space_id: &str) -> Result<(), DbError> {
+    ensure_form_template(
+        conn,
+        space_id,
+        "Expense",
+        vec![
+            form_field("amount", "Amount", FormFieldType::Number, true),
+            FormField {
+                options: DEFAULT_EXPENSE_CATEGORIES
+                    .iter()
+                    .map(|c| c.to_string())
+                    .collect(),
+                ..form_field("category", "Category", FormFieldType::Select, true)
+            },
+            form_field("date", "Date", FormFieldType::Date, true),
+            form_field("description", "Description", FormFieldType::Text, false),
+        ],
+    )
+}
+
+pub fn setup_travel_mode(conn: &Connection, space_id: &str) -> Result<(), DbError> {
+    ensure_form_template(
+        conn,
+        space_id,
+        "Trip Plan",
+        vec![
+            form_field("destination", "Destination", FormFieldType::Text, true),
+            form_field("start_date", "Start date", FormFieldType::Date, true),
+            form_field("end_date", "End date", FormFieldType::Date, true),
+            form_field("packing", "Packing list", FormFieldType::Textarea, false),
+        ],
+    )
+}
+
 // --- Health Logic ---
 
 pub struct CreateHealthMetricParams<'a> {
@@ -403,6 +614,325 @@ pub fn get_transactions_since(
     rows.collect::<Result<Vec<_>, _>>().map_err(DbError::from)
 }
 
+// --- Budget Logic ---
+
+#[derive(Error, Debug)]
+pub enum BudgetError {
+    #[error("Database error: {0}")]
+    Database(#[from] DbError),
+    #[error("Rusqlite error: {0}")]
+    Rusqlite(#[from] rusqlite::Error),
+    #[error("Invalid month '{0}', expected YYYY-MM")]
+    InvalidMonth(String),
+    #[error("Invalid budget: {0}")]
+    InvalidBudget(String),
+    #[error(
+        "Category '{category}' has transactions in {currencies:?}; \
+         amounts in different currencies can't be added without a conversion table"
+    )]
+    MixedCurrencies {
+        category: String,
+        currencies: Vec<String>,
+    },
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct Budget {
+    pub space_id: String,
+    pub category: String,
+    pub monthly_amount: f64,
+    /// Carry unspent money (or overspending) into the next month
+    pub rollover: bool,
+    /// First month the budget applies to, as YYYY-MM
+    pub start_month: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct BudgetStatus {
+    pub category: String,
+    /// Currency of the category's spending; `None` before anything was spent
+    pub currency: Option<String>,
+    pub monthly_amount: f64,
+    /// Left over from earlier months; negative after overspending
+    pub carried_over: f64,
+    /// This month's amount plus what was carried over
+    pub budgeted: f64,
+    pub spent: f64,
+    pub remaining: f64,
+    pub rollover: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum SpendingGroup {
+    Category,
+    Month,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct SpendingSummary {
+    /// Category name or YYYY-MM month
+    pub key: String,
+    pub currency: String,
+    pub spent: f64,
+    pub income: f64,
+    pub transaction_count: i64,
+}
+
+/// Year and month of a YYYY-MM string
+fn parse_month(month: &str) -> Result<NaiveDate, BudgetError> {
+    NaiveDate::parse_from_str(&format!("{}-01", month), "%Y-%m-%d")
+        .map_err(|_| BudgetError::InvalidMonth(month.to_string()))
+}
+
+fn next_month(month: NaiveDate) -> NaiveDate {
+    month
+        .checked_add_months(chrono::Months::new(1))
+        .expect("month in range")
+}
+
+fn month_start_ts(month: NaiveDate) -> i64 {
+    month
+        .and_hms_opt(0, 0, 0)
+        .expect("midnight exists")
+        .and_utc()
+        .timestamp()
+}
+
+/// Create or replace the monthly budget of a category. `start_month`
+/// (YYYY-MM) is when rollover starts accumulating; it defaults to the current
+/// month for new budgets and is kept on later edits.
+pub fn set_budget(
+    conn: &Connection,
+    space_id: Ulid,
+    category: &str,
+    monthly_amount: f64,
+    rollover: bool,
+    start_month: Option<&str>,
+) -> Result<Budget, BudgetError> {
+    if !monthly_amount.is_finite() || monthly_amount < 0.0 {
+        return Err(BudgetError::InvalidBudget(format!(
+            "monthly amount {} must be zero or more",
+            monthly_amount
+        )));
+    }
+    let now = chrono::Utc::now();
+    let existing: Option<String> = conn
+        .query_row(
+            "SELECT start_month FROM budget WHERE space_id = ?1 AND category = ?2",
+            rusqlite::params![space_id.to_string(), category],
+            |row| row.get(0),
+        )
+        .optional()?;
+    let start_month = match (start_month, existing) {
+        (Some(month), _) => parse_month(month)?.format("%Y-%m").to_string(),
+        (None, Some(existing)) => existing,
+        (None, None) => now.format("%Y-%m").to_string(),
+    };
+
+    conn.execute(
+        "INSERT INTO budget (space_id, category, monthly_amount, rollover, start_month, created_at, updated_at)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?6)
+         ON CONFLICT(space_id, category) DO UPDATE SET
+             monthly_amount = excluded.monthly_amount,
+             rollover = excluded.rollover,
+             start_month = excluded.start_month,
+             updated_at = excluded.updated_at",
+        rusqlite::params![
+            space_id.to_string(),
+            category,
+            monthly_amount,
+            rollover,
+            start_month,
+            now.timestamp()
+        ],
+    )?;
+    Ok(Budget {
+        space_id: space_id.to_string(),
+        category: category.to_string(),
+        monthly_amount,
+        rollover,
+        start_month,
+    })
+}
+
+pub fn get_budgets(conn: &Connection, space_id: Ulid) -> Result<Vec<Budget>, BudgetError> {
+    let mut stmt = conn.prepare(
+        "SELECT space_id, category, monthly_amount, rollover, start_month
+         FROM budget WHERE space_id = ?1 ORDER BY category",
+    )?;
+    let rows = stmt.query_map([space_id.to_string()], |row| {
+        Ok(Budget {
+            space_id: row.get(0)?,
+            category: row.get(1)?,
+            monthly_amount: row.get(2)?,
+            rollover: row.get(3)?,
+            start_month: row.get(4)?,
+        })
+    })?;
+    Ok(rows.collect::<Result<Vec<_>, _>>()?)
+}
+
+pub fn delete_budget(conn: &Connection, space_id: Ulid, category: &str) -> Result<(), BudgetError> {
+    conn.execute(
+        "DELETE FROM budget WHERE space_id = ?1 AND category = ?2",
+        rusqlite::params![space_id.to_string(), category],
+    )?;
+    Ok(())
+}
+
+/// Budgeted, spent and remaining amounts of every budgeted category in
+/// `month` (YYYY-MM). Rollover budgets carry the difference between budget
+/// and spending of each month since their start month.
+pub fn get_budget_status(
+    conn: &Connection,
+    space_id: Ulid,
+    month: &str,
+) -> Result<Vec<BudgetStatus>, BudgetError> {
+    let month = parse_month(month)?;
+    let month_key = month.format("%Y-%m").to_string();
+    let budgets = get_budgets(conn, space_id)?;
+    let earliest = budgets
+        .iter()
+        .filter(|b| b.rollover)
+        .map(|b| b.start_month.as_str())
+        .min()
+        .map_or(Ok(month), parse_month)?
+        .min(month);
+
+    // Spending per category, month and currency since the earliest start
+    let mut stmt = conn.prepare(
+        "SELECT category, strftime('%Y-%m', date, 'unixepoch') AS month, currency, SUM(ABS(amount))
+         FROM transaction_log
+         WHERE space_id = ?1 AND type = 'expense' AND date >= ?2 AND date < ?3
+         GROUP BY category, month, currency",
+    )?;
+    let rows = stmt
+        .query_map(
+            rusqlite::params![
+                space_id.to_string(),
+                month_start_ts(earliest),
+                month_start_ts(next_month(month))
+            ],
+            |row| {
+                Ok((
+                    row.get::<_, String>(0)?,
+                    row.get::<_, String>(1)?,
+                    row.get::<_, String>(2)?,
+                    row.get::<_, f64>(3)?,
+                ))
+            },
+        )?
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let mut statuses = Vec::new();
+    for budget in budgets {
+        let spending: Vec<&(String, String, String, f64)> = rows
+            .iter()
+            .filter(|(category, m, _, _)| {
+                *category == budget.category
+                    && (*m == month_key || (budget.rollover && *m >= budget.start_month))
+            })
+            .collect();
+        let currency = single_currency(&budget.category, spending.iter().map(|r| &r.2))?;
+        let spent_in = |m: &str| -> f64 { spending.iter().filter(|r| r.1 == m).map(|r| r.3).sum() };
+
+        let mut carried_over = 0.0;
+        if budget.rollover {
+            let mut m = parse_month(&budget.start_month)?;
+            while m < month {
+                carried_over += budget.monthly_amount - spent_in(&m.format("%Y-%m").to_string());
+                m = next_month(m);
+            }
+        }
+        let budgeted = budget.monthly_amount + carried_over;
+        let spent = spent_in(&month_key);
+        statuses.push(BudgetStatus {
+            category: budget.category,
+            currency,
+            monthly_amount: budget.monthly_amount,
+            carried_over,
+            budgeted,
+            spent,
+            remaining: budgeted - spent,
+            rollover: budget.rollover,
+        });
+    }
+    Ok(statuses)
+}
+
+/// The one currency of a category's transactions; mixed currencies are refused
+fn single_currency<'a>(
+    category: &str,
+    currencies: impl Iterator<Item = &'a String>,
+) -> Result<Option<String>, BudgetError> {
+    let mut distinct: Vec<String> = currencies.cloned().collect();
+    distinct.sort();
+    distinct.dedup();
+    match distinct.len() {
+        0 => Ok(None),
+        1 => Ok(distinct.pop()),
+        _ => Err(BudgetError::MixedCurrencies {
+            category: category.to_string(),
+            currencies: distinct,
+        }),
+    }
+}
+
+/// Spending and income in `range`, per category or per month (YYYY-MM).
+/// Month rows are split by currency; a category with transactions in more
+/// than one currency is an error.
+pub fn get_spending_summary(
+    conn: &Connection,
+    space_id: Ulid,
+    range: TimeRange,
+    group_by: SpendingGroup,
+) -> Result<Vec<SpendingSummary>, BudgetError> {
+    let key = match group_by {
+        SpendingGroup::Category => "category",
+        SpendingGroup::Month => "strftime('%Y-%m', date, 'unixepoch')",
+    };
+    let sql = format!(
+        "SELECT {key} AS key, currency,
+                SUM(CASE WHEN type = 'expense' THEN ABS(amount) ELSE 0 END),
+                SUM(CASE WHEN type = 'income' THEN ABS(amount) ELSE 0 END),
+                COUNT(*)
+         FROM transaction_log
+         WHERE space_id = ?1 AND date >= ?2 AND date < ?3
+         GROUP BY key, currency
+         ORDER BY key, currency"
+    );
+    let mut stmt = conn.prepare(&sql)?;
+    let summaries = stmt
+        .query_map(
+            rusqlite::params![space_id.to_string(), range.start, range.end],
+            |row| {
+                Ok(SpendingSummary {
+                    key: row.get(0)?,
+                    currency: row.get(1)?,
+                    spent: row.get(2)?,
+                    income: row.get(3)?,
+                    transaction_count: row.get(4)?,
+                })
+            },
+        )?
+        .collect::<Result<Vec<_>, _>>()?;
+
+    if group_by == SpendingGroup::Category {
+        let mut by_category: BTreeMap<&str, Vec<&String>> = BTreeMap::new();
+        for summary in &summaries {
+            by_category
+                .entry(&summary.key)
+                .or_default()
+                .push(&summary.currency);
+        }
+        for (category, currencies) in by_category {
+            single_currency(category, currencies.into_iter())?;
+        }
+    }
+    Ok(summaries)
+}
+
 // --- Recipe Logic ---
 
 pub fn create_recipe(
@@ -438,12 +968,13 @@ pub fn create_recipe(
         rating,
         difficulty: difficulty.to_string(),
         created_at: now,
+        servings: 1,
     })
 }
 
 pub fn get_recipes(conn: &Connection, space_id: Ulid, limit: i64) -> Result<Vec<Recipe>, DbError> {
     let mut stmt = conn.prepare(
-        "SELECT id, space_id, note_id, name, rating, difficulty, created_at
+        "SELECT id, space_id, note_id, name, rating, difficulty, created_at, servings
          FROM recipe
          WHERE space_id = ?1
          ORDER BY created_at DESC LIMIT ?2",
@@ -458,6 +989,7 @@ pub fn get_recipes(conn: &Connection, space_id: Ulid, limit: i64) -> Result<Vec<
             rating: row.get(4)?,
             difficulty: row.get(5)?,
             created_at: row.get(6)?,
+            servings: row.get(7)?,
         })
     })?;
 
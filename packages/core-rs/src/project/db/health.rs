@@ -0,0 +1,344 @@
+use crate::db::settings::{load_settings, save_settings, Settings, SettingsError};
+use crate::project::models::*;
+use chrono::{DateTime, Utc};
+use rusqlite::{Connection, OptionalExtension, Result};
+
+const SECONDS_PER_DAY: i64 = 86_400;
+
+/// Milestone statuses that count as finished
+const DONE_MILESTONE_STATUSES: &[&str] = &["done", "completed", "complete"];
+/// Risk impacts that count as high; risks have no closed state, so every
+/// recorded one is open until it is deleted
+const HIGH_IMPACT_LEVELS: &[&str] = &["high", "critical"];
+
+impl Settings for ProjectHealthConfig {
+    const KEY: &'static str = "project.health_config";
+    const DESCRIPTION: &'static str = "Weights and thresholds for project health scores";
+
+    fn validate(&self) -> Result<(), String> {
+        let weights = [
+            self.overdue_milestones_weight,
+            self.high_impact_risks_weight,
+            self.velocity_weight,
+            self.update_staleness_weight,
+        ];
+        if weights.iter().any(|w| !w.is_finite() || *w < 0.0) || weights.iter().sum::<f64>() <= 0.0
+        {
+            return Err("Health weights must be non-negative and not all zero".to_string());
+        }
+        if !(0.0..=100.0).contains(&self.at_risk_min_score)
+            || !(self.at_risk_min_score..=100.0).contains(&self.on_track_min_score)
+        {
+            return Err(
+                "Health thresholds must satisfy 0 <= at-risk <= on-track <= 100".to_string(),
+            );
+        }
+        if self.velocity_weeks < 1 || self.stale_update_days < 1 || self.high_impact_risk_cap < 1 {
+            return Err("Health windows and caps must be at least 1".to_string());
+        }
+        Ok(())
+    }
+}
+
+impl From<SettingsError> for ProjectError {
+    fn from(e: SettingsError) -> Self {
+        match e {
+            SettingsError::Rusqlite(e) => ProjectError::Rusqlite(e),
+            SettingsError::Db(e) => ProjectError::Database(e),
+            SettingsError::Invalid { reason, .. } => ProjectError::InvalidData(reason),
+            SettingsError::Malformed { .. } | SettingsError::UnsupportedVersion { .. } => {
+                ProjectError::InvalidData(e.to_string())
+            }
+        }
+    }
+}
+
+pub fn get_project_health_config(conn: &Connection) -> Result<ProjectHealthConfig, ProjectError> {
+    Ok(load_settings(conn)?)
+}
+
+pub fn set_project_health_config(
+    conn: &Connection,
+    config: &ProjectHealthConfig,
+) -> Result<(), ProjectError> {
+    Ok(save_settings(conn, config)?)
+}
+
+fn ratio(part: i64, whole: i64) -> f64 {
+    if whole <= 0 {
+        0.0
+    } else {
+        (part as f64 / whole as f64).clamp(0.0, 1.0)
+    }
+}
+
+fn gather_signals(
+    conn: &Connection,
+    project_id: &str,
+    start_at: Option<i64>,
+    config: &ProjectHealthConfig,
+    now: i64,
+) -> Result<ProjectHealthSignals, ProjectError> {
+    let done_statuses = DONE_MILESTONE_STATUSES
+        .iter()
+        .map(|s| format!("'{}'", s))
+        .collect::<Vec<_>>()
+        .join(", ");
+    let (milestones_with_due_date, overdue_milestones): (i64, i64) = conn.query_row(
+        &format!(
+            "SELECT COUNT(*),
+                    COALESCE(SUM(due_at < ?2 AND LOWER(COALESCE(status, '')) NOT IN ({})), 0)
+             FROM project_milestone WHERE project_id = ?1 AND due_at IS NOT NULL",
+            done_statuses
+        ),
+        rusqlite::params![project_id, now],
+        |row| Ok((row.get(0)?, row.get(1)?)),
+    )?;
+
+    let high_levels = HIGH_IMPACT_LEVELS
+        .iter()
+        .map(|s| format!("'{}'", s))
+        .collect::<Vec<_>>()
+        .join(", ");
+    let open_high_impact_risks: i64 = conn.query_row(
+        &format!(
+            "SELECT COUNT(*) FROM project_risk
+             WHERE project_id = ?1 AND LOWER(TRIM(COALESCE(impact, ''))) IN ({})",
+            high_levels
+        ),
+        [project_id],
+        |row| row.get(0),
+    )?;
+
+    let window = config.velocity_weeks * 7 * SECONDS_PER_DAY;
+    let (open_tasks, completed_recent, completed_previous): (i64, i64, i64) = conn.query_row(
+        "SELECT COALESCE(SUM(status NOT IN ('done', 'cancelled')), 0),
+                COALESCE(SUM(status = 'done' AND completed_at > ?2 - ?3 AND completed_at <= ?2), 0),
+                COALESCE(SUM(status = 'done' AND completed_at > ?2 - 2 * ?3 AND completed_at <= ?2 - ?3), 0)
+         FROM task WHERE project_id = ?1",
+        rusqlite::params![project_id, now, window],
+        |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+    )?;
+    let tracked_seconds_recent: i64 = conn.query_row(
+        "SELECT COALESCE(SUM(CASE WHEN is_running = 1 THEN ?2 - started_at
+                                  ELSE COALESCE(duration_seconds, 0) END), 0)
+         FROM time_entry
+         WHERE started_at > ?2 - ?3 AND started_at <= ?2
+           AND (project_id = ?1 OR task_id IN (SELECT id FROM task WHERE project_id = ?1))",
+        rusqlite::params![project_id, now, window],
+        |row| row.get(0),
+    )?;
+
+    // Nothing left to do is not a stall; otherwise compare with the window
+    // before, and count tracked time as progress that hasn't landed yet
+    let velocity_penalty = if open_tasks == 0 {
+        0.0
+    } else if completed_recent > 0 {
+        1.0 - ratio(completed_recent, completed_previous.max(completed_recent))
+    } else if tracked_seconds_recent > 0 {
+        0.5
+    } else {
+        1.0
+    };
+
+    let last_update: Option<i64> = conn
+        .query_row(
+            "SELECT MAX(when_at) FROM project_update WHERE project_id = ?1",
+            [project_id],
+            |row| row.get(0),
+        )
+        .optional()?
+        .flatten();
+    let days_since_update = last_update
+        .or(start_at)
+        .map(|ts| ((now - ts) / SECONDS_PER_DAY).max(0));
+    let staleness_penalty = match days_since_update {
+        Some(days) => ratio(days, config.stale_update_days),
+        None => 1.0,
+    };
+
+    Ok(ProjectHealthSignals {
+        milestones_with_due_date,
+        overdue_milestones,
+        overdue_milestone_penalty: ratio(overdue_milestones, milestones_with_due_date),
+        open_high_impact_risks,
+        risk_penalty: ratio(open_high_impact_risks, config.high_impact_risk_cap),
+        open_tasks,
+        completed_recent,
+        completed_previous,
+        tracked_seconds_recent,
+        velocity_penalty,
+        days_since_update,
+        staleness_penalty,
+    })
+}
+
+fn score(signals: &ProjectHealthSignals, config: &ProjectHealthConfig) -> f64 {
+    let weighted = [
+        (
+            config.overdue_milestones_weight,
+            signals.overdue_milestone_penalty,
+        ),
+        (config.high_impact_risks_weight, signals.risk_penalty),
+        (config.velocity_weight, signals.velocity_penalty),
+        (config.update_staleness_weight, signals.staleness_penalty),
+    ];
+    let total_weight: f64 = weighted.iter().map(|(w, _)| w).sum();
+    if total_weight <= 0.0 {
+        return 100.0;
+    }
+    let penalty: f64 = weighted.iter().map(|(w, p)| w * p).sum::<f64>() / total_weight;
+    // One decimal keeps the score stable against float noise between runs
+    ((1.0 - penalty) * 1000.0).round() / 10.0
+}
+
+fn classify(score: f64, config: &ProjectHealthConfig) -> ProjectHealthStatus {
+    if score >= config.on_track_min_score {
+        ProjectHealthStatus::OnTrack
+    } else if score >= config.at_risk_min_score {
+        ProjectHealthStatus::AtRisk
+    } else {
+        ProjectHealthStatus::OffTrack
+    }
+}
+
+fn record_snapshot(conn: &Connection, health: &ProjectHealth) -> Result<(), ProjectError> {
+    let day = DateTime::<Utc>::from_timestamp(health.computed_at, 0)
+        .unwrap_or_default()
+        .format("%Y-%m-%d")
+        .to_string();
+    conn.execute(
+        "INSERT INTO project_health_history (project_id, day, score, status, computed_at)
+         VALUES (?1, ?2, ?3, ?4, ?5)
+         ON CONFLICT(project_id, day) DO UPDATE SET
+            score = excluded.score,
+            status = excluded.status,
+            computed_at = excluded.computed_at",
+        rusqlite::params![
+            health.project_id,
+            day,
+            health.score,
+            health.status.as_str(),
+            health.computed_at
+        ],
+    )?;
+    Ok(())
+}
+
+/// Score a project as of `now` and record it as that day's snapshot
+pub fn compute_project_health_at(
+    conn: &Connection,
+    project_id: &str,
+    now: i64,
+) -> Result<ProjectHealth, ProjectError> {
+    let config = get_project_health_config(conn)?;
+    compute_with_config(conn, project_id, now, &config)
+}
+
+fn compute_with_config(
+    conn: &Connection,
+    project_id: &str,
+    now: i64,
+    config: &ProjectHealthConfig,
+) -> Result<ProjectHealth, ProjectError> {
+    let (title, start_at): (String, Option<i64>) = conn
+        .query_row(
+            "SELECT title, start_at FROM project WHERE id = ?1",
+            [project_id],
+            |row| Ok((row.get(0)?, row.get(1)?)),
+        )
+        .optional()?
+        .ok_or_else(|| ProjectError::NotFound(project_id.to_string()))?;
+
+    let signals = gather_signals(conn, project_id, start_at, config, now)?;
+    let score = score(&signals, config);
+    let health = ProjectHealth {
+        project_id: project_id.to_string(),
+        title,
+        score,
+        status: classify(score, config),
+        signals,
+        computed_at: now,
+    };
+    record_snapshot(conn, &health)?;
+    Ok(health)
+}
+
+pub fn compute_project_health(
+    conn: &Connection,
+    project_id: &str,
+) -> Result<ProjectHealth, ProjectError> {
+    compute_project_health_at(conn, project_id, Utc::now().timestamp())
+}
+
+/// Health of every project in the space that isn't done or archived
+pub fn get_projects_health_summary_at(
+    conn: &Connection,
+    space_id: &str,
+    now: i64,
+) -> Result<ProjectHealthSummary, ProjectError> {
+    let config = get_project_health_config(conn)?;
+    let project_ids: Vec<String> = {
+        let mut stmt = conn.prepare(
+            "SELECT id FROM project
+             WHERE space_id = ?1 AND status NOT IN ('done', 'archived')",
+        )?;
+        let rows = stmt.query_map([space_id], |row| row.get(0))?;
+        rows.collect::<Result<Vec<String>, _>>()?
+    };
+
+    let mut projects = project_ids
+        .iter()
+        .map(|id| compute_with_config(conn, id, now, &config))
+        .collect::<Result<Vec<_>, _>>()?;
+    projects.sort_by(|a, b| a.score.total_cmp(&b.score).then(a.title.cmp(&b.title)));
+
+    let count = |status| projects.iter().filter(|p| p.status == status).count() as i64;
+    Ok(ProjectHealthSummary {
+        on_track: count(ProjectHealthStatus::OnTrack),
+        at_risk: count(ProjectHealthStatus::AtRisk),
+        off_track: count(ProjectHealthStatus::OffTrack),
+        projects,
+    })
+}
+
+pub fn get_projects_health_summary(
+    conn: &Connection,
+    space_id: &str,
+) -> Result<ProjectHealthSummary, ProjectError> {
+    get_projects_health_summary_at(conn, space_id, Utc::now().timestamp())
+}
+
+/// Daily snapshots of a project's health, oldest first
+pub fn get_project_health_history(
+    conn: &Connection,
+    project_id: &str,
+    limit_days: Option<i64>,
+) -> Result<Vec<ProjectHealthSnapshot>, ProjectError> {
+    let mut stmt = conn.prepare(
+        "SELECT project_id, day, score, status, computed_at FROM (
+             SELECT * FROM project_health_history WHERE project_id = ?1
+             ORDER BY day DESC LIMIT ?2
+         ) ORDER BY day ASC",
+    )?;
+    let rows = stmt.query_map(
+        rusqlite::params![project_id, limit_days.unwrap_or(-1)],
+        |row| {
+            let status: String = row.get(3)?;
+            Ok(ProjectHealthSnapshot {
+                project_id: row.get(0)?,
+                day: row.get(1)?,
+                score: row.get(2)?,
+                status: ProjectHealthStatus::parse(&status).ok_or_else(|| {
+                    rusqlite::Error::FromSqlConversionFailure(
+                        3,
+                        rusqlite::types::Type::Text,
+                        format!("Unknown health status: {}", status).into(),
+                    )
+                })?,
+                computed_at: row.get(4)?,
+            })
+        },
+    )?;
+    Ok(rows.collect::<Result<Vec<_>, _>>()?)
+}
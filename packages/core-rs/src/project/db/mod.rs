@@ -1,10 +1,12 @@
 pub mod dependency;
+pub mod health;
 pub mod milestone;
 pub mod project;
 pub mod risk;
 pub mod update;
 
 pub use dependency::*;
+pub use health::*;
 pub use milestone::*;
 pub use project::*;
 pub use risk::*;
@@ -1,17 +1,28 @@
+use crate::audit::{audit_change, AuditOperation, AUDIT_SOURCE_LOCAL};
+use crate::permission::{authorize, ActorContext, PERMISSION_ADMIN, PERMISSION_WRITE};
 use crate::project::models::*;
 use crate::task::Task;
-use rusqlite::{Connection, Result};
+use rusqlite::{Connection, OptionalExtension, Result};
 use ulid::Ulid;
 
 pub fn delete_project(conn: &mut Connection, id: &str) -> Result<(), ProjectError> {
     log::info!("[project] Deleting project with id: {}", id);
 
     let tx = conn.transaction()?;
+    let space_id: Option<String> = tx
+        .query_row("SELECT space_id FROM project WHERE id = ?1", [id], |row| {
+            row.get(0)
+        })
+        .optional()?;
 
     // Manually delete related entities to satisfy FK constraints
     tx.execute("DELETE FROM project_milestone WHERE project_id = ?1", [id])?;
     tx.execute("DELETE FROM project_risk WHERE project_id = ?1", [id])?;
     tx.execute("DELETE FROM project_update WHERE project_id = ?1", [id])?;
+    tx.execute(
+        "DELETE FROM project_health_history WHERE project_id = ?1",
+        [id],
+    )?;
     tx.execute(
         "DELETE FROM project_dependency WHERE project_id = ?1 OR depends_on_project_id = ?1",
         [id],
@@ -31,6 +42,16 @@ pub fn delete_project(conn: &mut Connection, id: &str) -> Result<(), ProjectErro
 
     match tx.execute("DELETE FROM project WHERE id = ?1", [id]) {
         Ok(_) => {
+            if let Some(space_id) = space_id {
+                audit_change(
+                    &tx,
+                    &space_id,
+                    "project",
+                    id,
+                    AuditOperation::Delete,
+                    AUDIT_SOURCE_LOCAL,
+                );
+            }
             tx.commit()?;
             log::debug!("[project] Project deleted successfully");
             Ok(())
@@ -61,6 +82,14 @@ pub fn create_project(
     ) {
         Ok(_) => {
             log::debug!("[project] Project created successfully with id: {}", id);
+            audit_change(
+                conn,
+                space_id,
+                "project",
+                &id,
+                AuditOperation::Create,
+                AUDIT_SOURCE_LOCAL,
+            );
             Ok(Project {
                 id,
                 space_id: space_id.to_string(),
@@ -110,6 +139,14 @@ pub fn update_project(conn: &Connection, project: &Project) -> Result<(), Projec
     ) {
         Ok(_) => {
             log::debug!("[project] Project updated successfully");
+            audit_change(
+                conn,
+                &project.space_id,
+                "project",
+                &project.id,
+                AuditOperation::Update,
+                AUDIT_SOURCE_LOCAL,
+            );
             Ok(())
         }
         Err(e) => {
@@ -119,6 +156,50 @@ pub fn update_project(conn: &Connection, project: &Project) -> Result<(), Projec
     }
 }
 
+/// [`create_project`] on behalf of `actor`, who needs `write_notes` in the
+/// space
+pub fn create_project_as(
+    conn: &Connection,
+    actor: &ActorContext,
+    space_id: &str,
+    title: &str,
+) -> Result<Project, ProjectError> {
+    authorize::<ProjectError>(conn, actor, space_id, PERMISSION_WRITE)?;
+    create_project(conn, space_id, title)
+}
+
+/// [`update_project`] on behalf of `actor`, who needs `write_notes` in the
+/// project's space
+pub fn update_project_as(
+    conn: &Connection,
+    actor: &ActorContext,
+    project: &Project,
+) -> Result<(), ProjectError> {
+    let space_id = project_space_id(conn, &project.id)?;
+    authorize::<ProjectError>(conn, actor, &space_id, PERMISSION_WRITE)?;
+    update_project(conn, project)
+}
+
+/// [`delete_project`] on behalf of `actor`, who needs `admin` in the
+/// project's space
+pub fn delete_project_as(
+    conn: &mut Connection,
+    actor: &ActorContext,
+    id: &str,
+) -> Result<(), ProjectError> {
+    let space_id = project_space_id(conn, id)?;
+    authorize::<ProjectError>(conn, actor, &space_id, PERMISSION_ADMIN)?;
+    delete_project(conn, id)
+}
+
+fn project_space_id(conn: &Connection, id: &str) -> Result<String, ProjectError> {
+    conn.query_row("SELECT space_id FROM project WHERE id = ?1", [id], |row| {
+        row.get(0)
+    })
+    .optional()?
+    .ok_or_else(|| ProjectError::NotFound(id.to_string()))
+}
+
 fn validate_status_transition(current: &str, new: &str) -> Result<(), ProjectError> {
     if current == new {
         return Ok(());
@@ -176,10 +257,12 @@ pub fn get_projects_in_space(
             });
 
         if let Ok(task_id_str) = row.get::<_, String>(9) {
-            if let Ok(task_id) = Ulid::from_string(&task_id_str) {
-                let space_id = Ulid::from_string(&project.space_id).unwrap_or_default();
-                let project_id_ulid = Ulid::from_string(&project.id).unwrap_or_default();
-
+            // A row with a malformed id is skipped rather than read as ULID zero
+            if let (Ok(task_id), Ok(space_id), Ok(project_id_ulid)) = (
+                Ulid::from_string(&task_id_str),
+                Ulid::from_string(&project.space_id),
+                Ulid::from_string(&project.id),
+            ) {
                 if let Ok(title) = row.get::<_, String>(10) {
                     project.tasks.push(Task {
                         id: task_id,
@@ -258,10 +341,11 @@ pub fn get_project(conn: &Connection, id: &str) -> Result<Option<Project>, Proje
             });
 
         if let Ok(task_id_str) = row.get::<_, String>(9) {
-            if let Ok(task_id) = Ulid::from_string(&task_id_str) {
-                let space_id = Ulid::from_string(&project.space_id).unwrap_or_default();
-                let project_id_ulid = Ulid::from_string(&project.id).unwrap_or_default();
-
+            if let (Ok(task_id), Ok(space_id), Ok(project_id_ulid)) = (
+                Ulid::from_string(&task_id_str),
+                Ulid::from_string(&project.space_id),
+                Ulid::from_string(&project.id),
+            ) {
                 if let Ok(title) = row.get::<_, String>(10) {
                     project.tasks.push(Task {
                         id: task_id,
@@ -1,19 +1,65 @@
 use crate::project::models::*;
-use rusqlite::{Connection, OptionalExtension, Result};
+use rusqlite::{Connection, OptionalExtension, Result, Row};
 use ulid::Ulid;
 
+const UPDATE_COLUMNS: &str = "id, project_id, when_at, health, summary, status, percent_complete, highlights, risks_changed, next_steps";
+
+fn update_from_row(row: &Row) -> rusqlite::Result<ProjectUpdate> {
+    let status: Option<String> = row.get(5)?;
+    Ok(ProjectUpdate {
+        id: row.get(0)?,
+        project_id: row.get(1)?,
+        when_at: row.get(2)?,
+        health: row.get(3)?,
+        summary: row.get(4)?,
+        report: ProjectStatusReport {
+            status: status.as_deref().and_then(ProjectHealthStatus::parse),
+            percent_complete: row.get(6)?,
+            highlights: row.get(7)?,
+            risks_changed: row.get(8)?,
+            next_steps: row.get(9)?,
+        },
+    })
+}
+
+fn validate_report(report: &ProjectStatusReport) -> Result<(), ProjectError> {
+    match report.percent_complete {
+        Some(percent) if !(0..=100).contains(&percent) => Err(ProjectError::InvalidData(format!(
+            "Percent complete must be between 0 and 100, got {}",
+            percent
+        ))),
+        _ => Ok(()),
+    }
+}
+
 pub fn create_project_update(
     conn: &Connection,
     project_id: &str,
     when_at: i64,
     health: &str,
     summary: &str,
+    report: &ProjectStatusReport,
 ) -> Result<ProjectUpdate, ProjectError> {
     log::info!("[project] Creating update for project {}", project_id);
+    validate_report(report)?;
     let id = Ulid::new().to_string();
     match conn.execute(
-        "INSERT INTO project_update (id, project_id, when_at, health, summary) VALUES (?1, ?2, ?3, ?4, ?5)",
-        rusqlite::params![id, project_id, when_at, health, summary],
+        "INSERT INTO project_update (
+            id, project_id, when_at, health, summary,
+            status, percent_complete, highlights, risks_changed, next_steps
+        ) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10)",
+        rusqlite::params![
+            id,
+            project_id,
+            when_at,
+            health,
+            summary,
+            report.status.map(|s| s.as_str()),
+            report.percent_complete,
+            report.highlights,
+            report.risks_changed,
+            report.next_steps,
+        ],
     ) {
         Ok(_) => {
             log::debug!("[project] Update created successfully with id: {}", id);
@@ -23,6 +69,7 @@ pub fn create_project_update(
                 when_at,
                 health: health.to_string(),
                 summary: summary.to_string(),
+                report: report.clone(),
             })
         }
         Err(e) => {
@@ -37,21 +84,11 @@ pub fn get_project_update(
     id: &str,
 ) -> Result<Option<ProjectUpdate>, ProjectError> {
     log::info!("[project] Getting update with id: {}", id);
-    let mut stmt = conn.prepare(
-        "SELECT id, project_id, when_at, health, summary FROM project_update WHERE id = ?1",
-    )?;
-    match stmt
-        .query_row([id], |row| {
-            Ok(ProjectUpdate {
-                id: row.get(0)?,
-                project_id: row.get(1)?,
-                when_at: row.get(2)?,
-                health: row.get(3)?,
-                summary: row.get(4)?,
-            })
-        })
-        .optional()
-    {
+    let mut stmt = conn.prepare(&format!(
+        "SELECT {} FROM project_update WHERE id = ?1",
+        UPDATE_COLUMNS
+    ))?;
+    match stmt.query_row([id], update_from_row).optional() {
         Ok(update) => {
             log::debug!("[project] Found update with id: {}", id);
             Ok(update)
@@ -68,19 +105,12 @@ pub fn get_project_updates(
     project_id: &str,
 ) -> Result<Vec<ProjectUpdate>, ProjectError> {
     log::info!("[project] Getting updates for project: {}", project_id);
-    let mut stmt = conn.prepare(
-        "SELECT id, project_id, when_at, health, summary FROM project_update WHERE project_id = ?1",
-    )?;
+    let mut stmt = conn.prepare(&format!(
+        "SELECT {} FROM project_update WHERE project_id = ?1",
+        UPDATE_COLUMNS
+    ))?;
     let result = stmt
-        .query_map([project_id], |row| {
-            Ok(ProjectUpdate {
-                id: row.get(0)?,
-                project_id: row.get(1)?,
-                when_at: row.get(2)?,
-                health: row.get(3)?,
-                summary: row.get(4)?,
-            })
-        })?
+        .query_map([project_id], update_from_row)?
         .collect::<Result<Vec<ProjectUpdate>, _>>();
     match result {
         Ok(updates) => {
@@ -104,11 +134,26 @@ pub fn update_project_update(
     when_at: i64,
     health: &str,
     summary: &str,
+    report: &ProjectStatusReport,
 ) -> Result<(), ProjectError> {
     log::info!("[project] Updating update with id: {}", id);
+    validate_report(report)?;
     match conn.execute(
-        "UPDATE project_update SET when_at = ?2, health = ?3, summary = ?4 WHERE id = ?1",
-        rusqlite::params![id, when_at, health, summary],
+        "UPDATE project_update SET
+            when_at = ?2, health = ?3, summary = ?4, status = ?5, percent_complete = ?6,
+            highlights = ?7, risks_changed = ?8, next_steps = ?9
+         WHERE id = ?1",
+        rusqlite::params![
+            id,
+            when_at,
+            health,
+            summary,
+            report.status.map(|s| s.as_str()),
+            report.percent_complete,
+            report.highlights,
+            report.risks_changed,
+            report.next_steps,
+        ],
     ) {
         Ok(_) => {
             log::debug!("[project] Update updated successfully");
@@ -134,3 +179,65 @@ pub fn delete_project_update(conn: &Connection, id: &str) -> Result<(), ProjectE
         }
     }
 }
+
+/// The newest update of each project in the space, by `when_at`, for
+/// projects that have one
+pub fn get_latest_status_per_project(
+    conn: &Connection,
+    space_id: &str,
+) -> Result<Vec<ProjectUpdate>, ProjectError> {
+    log::info!("[project] Getting latest updates for space: {}", space_id);
+    let mut stmt = conn.prepare(&format!(
+        "SELECT {} FROM (
+            SELECT u.*, ROW_NUMBER() OVER (
+                PARTITION BY u.project_id ORDER BY u.when_at DESC, u.id DESC
+            ) AS row_num
+            FROM project_update u
+            JOIN project p ON p.id = u.project_id
+            WHERE p.space_id = ?1
+        ) u
+        WHERE u.row_num = 1
+        ORDER BY u.when_at DESC",
+        UPDATE_COLUMNS
+    ))?;
+    let updates = stmt
+        .query_map([space_id], update_from_row)?
+        .collect::<Result<Vec<ProjectUpdate>, _>>()?;
+    log::debug!(
+        "[project] Found latest updates for {} projects in space {}",
+        updates.len(),
+        space_id
+    );
+    Ok(updates)
+}
+
+/// Projects in the space that aren't done or archived, counted by the
+/// status of their latest update
+pub fn get_space_status_summary(
+    conn: &Connection,
+    space_id: &str,
+) -> Result<SpaceStatusSummary, ProjectError> {
+    let mut stmt = conn.prepare(
+        "SELECT id FROM project WHERE space_id = ?1 AND status NOT IN ('done', 'archived')",
+    )?;
+    let ongoing = stmt
+        .query_map([space_id], |row| row.get::<_, String>(0))?
+        .collect::<Result<Vec<_>, _>>()?;
+    let latest = get_latest_status_per_project(conn, space_id)?;
+
+    let mut summary = SpaceStatusSummary::default();
+    for project_id in &ongoing {
+        let status = latest
+            .iter()
+            .find(|u| &u.project_id == project_id)
+            .and_then(ProjectUpdate::effective_status);
+        match status {
+            Some(ProjectHealthStatus::OnTrack) => summary.on_track += 1,
+            Some(ProjectHealthStatus::AtRisk) => summary.at_risk += 1,
+            Some(ProjectHealthStatus::OffTrack) => summary.off_track += 1,
+            None => summary.unreported += 1,
+        }
+        summary.total += 1;
+    }
+    Ok(summary)
+}
@@ -1,3 +1,4 @@
+use crate::db::DbError;
 use crate::task::Task;
 use serde::{Deserialize, Serialize};
 use thiserror::Error;
@@ -6,8 +7,14 @@ use thiserror::Error;
 pub enum ProjectError {
     #[error("Rusqlite error: {0}")]
     Rusqlite(#[from] rusqlite::Error),
+    #[error("Database error: {0}")]
+    Database(#[from] DbError),
     #[error("Invalid data: {0}")]
     InvalidData(String),
+    #[error("Project not found: {0}")]
+    NotFound(String),
+    #[error(transparent)]
+    PermissionDenied(#[from] crate::permission::PermissionDenied),
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -58,4 +65,158 @@ pub struct ProjectUpdate {
     pub when_at: i64,
     pub health: String,
     pub summary: String,
+    /// Structured fields, all unset on text-only updates
+    #[serde(flatten)]
+    pub report: ProjectStatusReport,
+}
+
+impl ProjectUpdate {
+    /// The reported status, or the one implied by the health colour of
+    /// updates written before statuses were recorded
+    pub fn effective_status(&self) -> Option<ProjectHealthStatus> {
+        self.report.status.or(match self.health.as_str() {
+            "green" => Some(ProjectHealthStatus::OnTrack),
+            "amber" | "yellow" => Some(ProjectHealthStatus::AtRisk),
+            "red" => Some(ProjectHealthStatus::OffTrack),
+            _ => None,
+        })
+    }
+}
+
+/// The structured part of a project update, alongside its text summary
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+#[serde(default)]
+pub struct ProjectStatusReport {
+    pub status: Option<ProjectHealthStatus>,
+    /// 0 to 100
+    pub percent_complete: Option<i64>,
+    pub highlights: Option<String>,
+    pub risks_changed: Option<String>,
+    pub next_steps: Option<String>,
+}
+
+/// Ongoing projects in a space counted by the status of their latest update
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct SpaceStatusSummary {
+    pub on_track: i64,
+    pub at_risk: i64,
+    pub off_track: i64,
+    /// Projects without an update, or whose latest update gives no status
+    pub unreported: i64,
+    pub total: i64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum ProjectHealthStatus {
+    OnTrack,
+    AtRisk,
+    OffTrack,
+}
+
+impl ProjectHealthStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ProjectHealthStatus::OnTrack => "on-track",
+            ProjectHealthStatus::AtRisk => "at-risk",
+            ProjectHealthStatus::OffTrack => "off-track",
+        }
+    }
+
+    pub fn parse(s: &str) -> Option<Self> {
+        match s {
+            "on-track" => Some(ProjectHealthStatus::OnTrack),
+            "at-risk" => Some(ProjectHealthStatus::AtRisk),
+            "off-track" => Some(ProjectHealthStatus::OffTrack),
+            _ => None,
+        }
+    }
+}
+
+/// Relative weight of each health signal and the cut-offs between
+/// classifications, stored in the `project.health_config` setting
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(default)]
+pub struct ProjectHealthConfig {
+    pub overdue_milestones_weight: f64,
+    pub high_impact_risks_weight: f64,
+    pub velocity_weight: f64,
+    pub update_staleness_weight: f64,
+    /// Lowest score still classified on-track
+    pub on_track_min_score: f64,
+    /// Lowest score still classified at-risk; anything below is off-track
+    pub at_risk_min_score: f64,
+    /// Length of the window velocity is measured over, compared with the
+    /// window before it
+    pub velocity_weeks: i64,
+    /// Days without a project update after which staleness is maxed out
+    pub stale_update_days: i64,
+    /// Open high-impact risks at which that signal is maxed out
+    pub high_impact_risk_cap: i64,
+}
+
+impl Default for ProjectHealthConfig {
+    fn default() -> Self {
+        ProjectHealthConfig {
+            overdue_milestones_weight: 0.35,
+            high_impact_risks_weight: 0.25,
+            velocity_weight: 0.2,
+            update_staleness_weight: 0.2,
+            on_track_min_score: 75.0,
+            at_risk_min_score: 50.0,
+            velocity_weeks: 4,
+            stale_update_days: 14,
+            high_impact_risk_cap: 3,
+        }
+    }
+}
+
+/// The raw signals behind a health score, each penalty between 0 and 1
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ProjectHealthSignals {
+    pub milestones_with_due_date: i64,
+    pub overdue_milestones: i64,
+    pub overdue_milestone_penalty: f64,
+    pub open_high_impact_risks: i64,
+    pub risk_penalty: f64,
+    pub open_tasks: i64,
+    /// Tasks completed in the current velocity window
+    pub completed_recent: i64,
+    /// Tasks completed in the window before it
+    pub completed_previous: i64,
+    pub tracked_seconds_recent: i64,
+    pub velocity_penalty: f64,
+    /// `None` when the project has no updates and no start date
+    pub days_since_update: Option<i64>,
+    pub staleness_penalty: f64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ProjectHealth {
+    pub project_id: String,
+    pub title: String,
+    /// 0 (worst) to 100 (best)
+    pub score: f64,
+    pub status: ProjectHealthStatus,
+    pub signals: ProjectHealthSignals,
+    pub computed_at: i64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ProjectHealthSummary {
+    pub on_track: i64,
+    pub at_risk: i64,
+    pub off_track: i64,
+    /// Worst first
+    pub projects: Vec<ProjectHealth>,
+}
+
+/// A day's last computed score, for trend charts
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ProjectHealthSnapshot {
+    pub project_id: String,
+    pub day: String,
+    pub score: f64,
+    pub status: ProjectHealthStatus,
+    pub computed_at: i64,
 }
@@ -0,0 +1,641 @@
+//! Note properties
+//!
+//! Custom fields a space defines for its notes, like front matter keys in
+//! Obsidian or columns of a Notion database: a `status` select, a `rating`
+//! number, a `read` checkbox. Values are checked against their definition
+//! when set. Besides the canonical text, numbers, dates and checkboxes keep
+//! a numeric form, so search can compare them in SQL.
+
+use crate::db::DbError;
+use crate::search::query::like_pattern;
+use crate::search::{search_all, EntityType, SearchQuery};
+use chrono::{DateTime, NaiveDate, Utc};
+use rusqlite::types::Value as SqlValue;
+use rusqlite::{params, Connection, OptionalExtension, Row};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use thiserror::Error;
+use ulid::Ulid;
+
+#[derive(Error, Debug)]
+pub enum PropertyError {
+    #[error("Rusqlite error: {0}")]
+    Rusqlite(#[from] rusqlite::Error),
+    #[error("Database error: {0}")]
+    Db(#[from] DbError),
+    #[error("Property not found: {0}")]
+    NotFound(String),
+    #[error("A property named {0} already exists in this space")]
+    Duplicate(String),
+    #[error("Invalid property: {0}")]
+    InvalidDefinition(String),
+    #[error("Invalid value for {property}: {reason}")]
+    InvalidValue { property: String, reason: String },
+    #[error("Property {0} is not defined in the note's space")]
+    OtherSpace(String),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PropertyType {
+    Text,
+    Number,
+    /// A calendar day, written `YYYY-MM-DD`
+    Date,
+    /// One of the definition's options
+    Select,
+    Checkbox,
+}
+
+impl PropertyType {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            PropertyType::Text => "text",
+            PropertyType::Number => "number",
+            PropertyType::Date => "date",
+            PropertyType::Select => "select",
+            PropertyType::Checkbox => "checkbox",
+        }
+    }
+
+    pub fn parse(s: &str) -> Option<Self> {
+        match s {
+            "text" => Some(PropertyType::Text),
+            "number" => Some(PropertyType::Number),
+            "date" => Some(PropertyType::Date),
+            "select" => Some(PropertyType::Select),
+            "checkbox" => Some(PropertyType::Checkbox),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PropertyDefinition {
+    pub id: String,
+    pub space_id: String,
+    pub name: String,
+    pub property_type: PropertyType,
+    /// Choices of a select; empty for every other type
+    pub options: Vec<String>,
+    pub created_at: i64,
+}
+
+impl PropertyDefinition {
+    fn from_row(row: &Row) -> rusqlite::Result<Self> {
+        let property_type: String = row.get(3)?;
+        let options_json: String = row.get(4)?;
+        Ok(PropertyDefinition {
+            id: row.get(0)?,
+            space_id: row.get(1)?,
+            name: row.get(2)?,
+            property_type: PropertyType::parse(&property_type).unwrap_or(PropertyType::Text),
+            options: serde_json::from_str(&options_json).unwrap_or_default(),
+            created_at: row.get(5)?,
+        })
+    }
+}
+
+/// A note's value for one property
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct NoteProperty {
+    pub property_id: String,
+    pub name: String,
+    pub property_type: PropertyType,
+    /// A JSON number for numbers, a boolean for checkboxes and a string
+    /// otherwise
+    pub value: Value,
+    pub updated_at: i64,
+}
+
+/// How a [`PropertyFilter`] compares
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PropertyOp {
+    /// Same value; text compares case-insensitively
+    Equals,
+    /// Text containing the value, case-insensitively
+    Contains,
+    /// Numbers and dates after the value
+    GreaterThan,
+    /// Numbers and dates before the value
+    LessThan,
+    /// Any value at all
+    IsSet,
+    IsNotSet,
+}
+
+/// A condition on a note property, found by name in the note's space
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PropertyFilter {
+    pub property: String,
+    pub op: PropertyOp,
+    /// Unused by `is_set` and `is_not_set`
+    #[serde(default)]
+    pub value: Value,
+}
+
+/// A value checked against its definition, in the form it is stored
+struct StoredValue {
+    text: String,
+    number: Option<f64>,
+}
+
+const DEFINITION_COLUMNS: &str = "id, space_id, name, property_type, options_json, created_at";
+
+pub fn define_property(
+    conn: &Connection,
+    space_id: &str,
+    name: &str,
+    property_type: PropertyType,
+    options: &[String],
+) -> Result<PropertyDefinition, PropertyError> {
+    let name = name.trim();
+    if name.is_empty() {
+        return Err(PropertyError::InvalidDefinition(
+            "The name is empty".to_string(),
+        ));
+    }
+    let options: Vec<String> = options
+        .iter()
+        .map(|o| o.trim().to_string())
+        .filter(|o| !o.is_empty())
+        .collect();
+    match property_type {
+        PropertyType::Select if options.is_empty() => {
+            return Err(PropertyError::InvalidDefinition(format!(
+                "Select property {} needs at least one option",
+                name
+            )));
+        }
+        PropertyType::Select => {
+            for (i, option) in options.iter().enumerate() {
+                if options[..i].iter().any(|o| o.eq_ignore_ascii_case(option)) {
+                    return Err(PropertyError::InvalidDefinition(format!(
+                        "Option {} is listed twice",
+                        option
+                    )));
+                }
+            }
+        }
+        _ if !options.is_empty() => {
+            return Err(PropertyError::InvalidDefinition(format!(
+                "Only select properties have options, {} is a {}",
+                name,
+                property_type.as_str()
+            )));
+        }
+        _ => {}
+    }
+    let taken: bool = conn.query_row(
+        "SELECT EXISTS(SELECT 1 FROM property_definition WHERE space_id = ?1 AND name = ?2)",
+        [space_id, name],
+        |row| row.get(0),
+    )?;
+    if taken {
+        return Err(PropertyError::Duplicate(name.to_string()));
+    }
+
+    let definition = PropertyDefinition {
+        id: Ulid::new().to_string(),
+        space_id: space_id.to_string(),
+        name: name.to_string(),
+        property_type,
+        options,
+        created_at: Utc::now().timestamp(),
+    };
+    conn.execute(
+        "INSERT INTO property_definition
+             (id, space_id, name, property_type, options_json, created_at)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+        params![
+            definition.id,
+            definition.space_id,
+            definition.name,
+            definition.property_type.as_str(),
+            serde_json::to_string(&definition.options).map_err(DbError::from)?,
+            definition.created_at
+        ],
+    )?;
+    log::info!(
+        "[property] Defined {} property {} in space {}",
+        property_type.as_str(),
+        definition.name,
+        space_id
+    );
+    Ok(definition)
+}
+
+/// The space's properties, by name
+pub fn get_property_definitions(
+    conn: &Connection,
+    space_id: &str,
+) -> Result<Vec<PropertyDefinition>, PropertyError> {
+    let mut stmt = conn.prepare(&format!(
+        "SELECT {} FROM property_definition WHERE space_id = ?1 ORDER BY name",
+        DEFINITION_COLUMNS
+    ))?;
+    let definitions = stmt
+        .query_map([space_id], PropertyDefinition::from_row)?
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(definitions)
+}
+
+pub fn get_property_definition(
+    conn: &Connection,
+    id: &str,
+) -> Result<Option<PropertyDefinition>, PropertyError> {
+    Ok(conn
+        .query_row(
+            &format!(
+                "SELECT {} FROM property_definition WHERE id = ?1",
+                DEFINITION_COLUMNS
+            ),
+            [id],
+            PropertyDefinition::from_row,
+        )
+        .optional()?)
+}
+
+/// Delete a property along with every note's value for it
+pub fn delete_property(conn: &mut Connection, id: &str) -> Result<(), PropertyError> {
+    let tx = conn.transaction()?;
+    tx.execute("DELETE FROM note_property WHERE property_id = ?1", [id])?;
+    if tx.execute("DELETE FROM property_definition WHERE id = ?1", [id])? == 0 {
+        return Err(PropertyError::NotFound(id.to_string()));
+    }
+    tx.commit()?;
+    Ok(())
+}
+
+/// Set a note's value for a property defined in its space. Values may come
+/// as they would from front matter: numbers and checkboxes also accept
+/// their text form, and select options match case-insensitively.
+pub fn set_note_property(
+    conn: &Connection,
+    note_id: &str,
+    property_id: &str,
+    value: &Value,
+) -> Result<NoteProperty, PropertyError> {
+    let definition = require_definition(conn, property_id)?;
+    let space_id: Option<String> = conn
+        .query_row(
+            "SELECT space_id FROM note WHERE id = ?1",
+            [note_id],
+            |row| row.get(0),
+        )
+        .optional()?;
+    let Some(space_id) = space_id else {
+        return Err(DbError::NotFound {
+            entity: "note",
+            id: note_id.to_string(),
+        }
+        .into());
+    };
+    if space_id != definition.space_id {
+        return Err(PropertyError::OtherSpace(definition.name));
+    }
+    let stored = check_value(&definition, value)?;
+    let now = Utc::now().timestamp();
+    upsert_value(conn, note_id, &definition, &stored, now)?;
+    Ok(NoteProperty {
+        property_id: definition.id,
+        name: definition.name,
+        property_type: definition.property_type,
+        value: typed_value(definition.property_type, stored.text, stored.number),
+        updated_at: now,
+    })
+}
+
+/// The note's property values, by property name
+pub fn get_note_properties(
+    conn: &Connection,
+    note_id: &str,
+) -> Result<Vec<NoteProperty>, PropertyError> {
+    let mut stmt = conn.prepare(
+        "SELECT pd.id, pd.name, pd.property_type, np.value_text, np.value_number, np.updated_at
+         FROM note_property np
+         JOIN property_definition pd ON pd.id = np.property_id
+         WHERE np.note_id = ?1
+         ORDER BY pd.name",
+    )?;
+    let properties = stmt
+        .query_map([note_id], |row| {
+            let property_type: String = row.get(2)?;
+            let property_type = PropertyType::parse(&property_type).unwrap_or(PropertyType::Text);
+            Ok(NoteProperty {
+                property_id: row.get(0)?,
+                name: row.get(1)?,
+                property_type,
+                value: typed_value(property_type, row.get(3)?, row.get(4)?),
+                updated_at: row.get(5)?,
+            })
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(properties)
+}
+
+/// Clear a note's value for a property, returning whether it had one
+pub fn remove_note_property(
+    conn: &Connection,
+    note_id: &str,
+    property_id: &str,
+) -> Result<bool, PropertyError> {
+    let removed = conn.execute(
+        "DELETE FROM note_property WHERE note_id = ?1 AND property_id = ?2",
+        [note_id, property_id],
+    )?;
+    Ok(removed > 0)
+}
+
+/// Set a property on every note the search finds in the property's space,
+/// returning how many were set. Paging is ignored and only notes are
+/// searched, whatever entity types the query asks for.
+pub fn set_property_for_search(
+    conn: &mut Connection,
+    query: &SearchQuery,
+    property_id: &str,
+    value: &Value,
+) -> Result<usize, PropertyError> {
+    let definition = require_definition(conn, property_id)?;
+    let stored = check_value(&definition, value)?;
+
+    let mut query = query.clone();
+    query.entity_types = vec![EntityType::Note];
+    query.limit = None;
+    query.offset = None;
+    if query.filters.space_id.is_none() {
+        query.filters.space_id = Ulid::from_string(&definition.space_id).ok();
+    }
+    let note_ids: Vec<String> = search_all(conn, &query)?
+        .into_iter()
+        .map(|result| result.entity_id)
+        .collect();
+
+    let now = Utc::now().timestamp();
+    let tx = conn.transaction()?;
+    let mut updated = 0;
+    for note_id in &note_ids {
+        updated += upsert_value(&tx, note_id, &definition, &stored, now)?;
+    }
+    tx.commit()?;
+    log::info!(
+        "[property] Set {} on {} notes from a search",
+        definition.name,
+        updated
+    );
+    Ok(updated)
+}
+
+/// Set properties from a note's front matter for keys naming a property of
+/// its space, ignoring case. Values that don't fit their property are
+/// skipped. Returns how many were set.
+pub fn apply_front_matter(
+    conn: &Connection,
+    space_id: &str,
+    note_id: &str,
+    front_matter: &Value,
+) -> Result<usize, PropertyError> {
+    let Some(fields) = front_matter.as_object() else {
+        return Ok(0);
+    };
+    if fields.is_empty() {
+        return Ok(0);
+    }
+    let definitions = get_property_definitions(conn, space_id)?;
+    let now = Utc::now().timestamp();
+    let mut applied = 0;
+    for (key, value) in fields {
+        let Some(definition) = definitions
+            .iter()
+            .find(|d| d.name.eq_ignore_ascii_case(key.trim()))
+        else {
+            continue;
+        };
+        match check_value(definition, value) {
+            Ok(stored) => applied += upsert_value(conn, note_id, definition, &stored, now)?,
+            Err(e) => log::warn!(
+                "[property] Skipping front matter {} of {}: {}",
+                key,
+                note_id,
+                e
+            ),
+        }
+    }
+    Ok(applied)
+}
+
+/// SQL condition for notes, aliased `note_alias`, passing the filter. A
+/// value the comparison can't use matches nothing.
+pub(crate) fn filter_condition(
+    filter: &PropertyFilter,
+    note_alias: &str,
+) -> (String, Vec<SqlValue>) {
+    let comparison = match filter.op {
+        PropertyOp::IsSet | PropertyOp::IsNotSet => None,
+        PropertyOp::Equals => Some(match &filter.value {
+            Value::Bool(b) => Some((
+                "np.value_number = ?".to_string(),
+                SqlValue::Real(if *b { 1.0 } else { 0.0 }),
+            )),
+            Value::Number(n) => n
+                .as_f64()
+                .map(|n| ("np.value_number = ?".to_string(), SqlValue::Real(n))),
+            Value::String(s) => Some((
+                "np.value_text = ? COLLATE NOCASE".to_string(),
+                SqlValue::Text(s.trim().to_string()),
+            )),
+            _ => None,
+        }),
+        PropertyOp::Contains => Some(filter.value.as_str().map(|s| {
+            (
+                "np.value_text LIKE ? ESCAPE '\\'".to_string(),
+                SqlValue::Text(like_pattern(s.trim())),
+            )
+        })),
+        PropertyOp::GreaterThan | PropertyOp::LessThan => {
+            let operator = if filter.op == PropertyOp::GreaterThan {
+                ">"
+            } else {
+                "<"
+            };
+            let bound = match &filter.value {
+                Value::Number(n) => n.as_f64(),
+                Value::String(s) => parse_date(s)
+                    .map(|date| date_number(&date))
+                    .or_else(|| parse_number(s)),
+                _ => None,
+            };
+            Some(bound.map(|bound| {
+                (
+                    format!("np.value_number {} ?", operator),
+                    SqlValue::Real(bound),
+                )
+            }))
+        }
+    };
+
+    let mut params = vec![SqlValue::Text(filter.property.trim().to_string())];
+    let predicate = match comparison {
+        None => String::new(),
+        Some(Some((sql, param))) => {
+            params.push(param);
+            format!(" AND {}", sql)
+        }
+        Some(None) => " AND 0".to_string(),
+    };
+    let exists = if filter.op == PropertyOp::IsNotSet {
+        "NOT EXISTS"
+    } else {
+        "EXISTS"
+    };
+    let sql = format!(
+        "{exists} (SELECT 1 FROM note_property np
+             JOIN property_definition pd ON pd.id = np.property_id
+             WHERE np.note_id = {note_alias}.id AND pd.space_id = {note_alias}.space_id
+               AND pd.name = ?{predicate})"
+    );
+    (sql, params)
+}
+
+fn require_definition(
+    conn: &Connection,
+    property_id: &str,
+) -> Result<PropertyDefinition, PropertyError> {
+    get_property_definition(conn, property_id)?
+        .ok_or_else(|| PropertyError::NotFound(property_id.to_string()))
+}
+
+/// Write a value for a note in the property's space; notes elsewhere are
+/// left alone. Returns the rows written.
+fn upsert_value(
+    conn: &Connection,
+    note_id: &str,
+    definition: &PropertyDefinition,
+    stored: &StoredValue,
+    now: i64,
+) -> rusqlite::Result<usize> {
+    conn.execute(
+        "INSERT INTO note_property (note_id, property_id, value_text, value_number, updated_at)
+         SELECT id, ?2, ?3, ?4, ?5 FROM note WHERE id = ?1 AND space_id = ?6
+         ON CONFLICT(note_id, property_id) DO UPDATE SET
+             value_text = excluded.value_text,
+             value_number = excluded.value_number,
+             updated_at = excluded.updated_at",
+        params![
+            note_id,
+            definition.id,
+            stored.text,
+            stored.number,
+            now,
+            definition.space_id
+        ],
+    )
+}
+
+fn check_value(
+    definition: &PropertyDefinition,
+    value: &Value,
+) -> Result<StoredValue, PropertyError> {
+    let invalid = |reason: String| PropertyError::InvalidValue {
+        property: definition.name.clone(),
+        reason,
+    };
+    let text = match value {
+        Value::String(s) => Some(s.trim().to_string()),
+        Value::Number(n) => Some(n.to_string()),
+        Value::Bool(b) => Some(b.to_string()),
+        _ => None,
+    };
+    match definition.property_type {
+        PropertyType::Text => match text {
+            Some(text) => Ok(StoredValue { text, number: None }),
+            None => Err(invalid("expected text".to_string())),
+        },
+        PropertyType::Number => {
+            let number = match value {
+                Value::Number(n) => n.as_f64(),
+                Value::String(s) => parse_number(s),
+                _ => None,
+            };
+            match number {
+                Some(n) => Ok(StoredValue {
+                    text: n.to_string(),
+                    number: Some(n),
+                }),
+                None => Err(invalid(format!("expected a number, got {}", value))),
+            }
+        }
+        PropertyType::Date => match value.as_str().and_then(parse_date) {
+            Some(date) => Ok(StoredValue {
+                text: date.format("%Y-%m-%d").to_string(),
+                number: Some(date_number(&date)),
+            }),
+            None => Err(invalid(format!(
+                "expected a date such as 2024-05-31, got {}",
+                value
+            ))),
+        },
+        PropertyType::Select => {
+            let option = text.as_deref().and_then(|text| {
+                definition
+                    .options
+                    .iter()
+                    .find(|o| o.eq_ignore_ascii_case(text))
+            });
+            match option {
+                Some(option) => Ok(StoredValue {
+                    text: option.clone(),
+                    number: None,
+                }),
+                None => Err(invalid(format!(
+                    "expected one of {}, got {}",
+                    definition.options.join(", "),
+                    value
+                ))),
+            }
+        }
+        PropertyType::Checkbox => {
+            let checked = match value {
+                Value::Bool(b) => Some(*b),
+                Value::String(s) if s.trim().eq_ignore_ascii_case("true") => Some(true),
+                Value::String(s) if s.trim().eq_ignore_ascii_case("false") => Some(false),
+                _ => None,
+            };
+            match checked {
+                Some(checked) => Ok(StoredValue {
+                    text: checked.to_string(),
+                    number: Some(if checked { 1.0 } else { 0.0 }),
+                }),
+                None => Err(invalid(format!("expected true or false, got {}", value))),
+            }
+        }
+    }
+}
+
+fn typed_value(property_type: PropertyType, text: String, number: Option<f64>) -> Value {
+    match property_type {
+        PropertyType::Number => number.map(Value::from).unwrap_or(Value::Null),
+        PropertyType::Checkbox => Value::Bool(number == Some(1.0)),
+        _ => Value::String(text),
+    }
+}
+
+fn parse_number(s: &str) -> Option<f64> {
+    s.trim().parse::<f64>().ok().filter(|n| n.is_finite())
+}
+
+/// A `YYYY-MM-DD` day, or the day of an RFC 3339 timestamp
+fn parse_date(s: &str) -> Option<NaiveDate> {
+    let s = s.trim();
+    NaiveDate::parse_from_str(s, "%Y-%m-%d")
+        .ok()
+        .or_else(|| DateTime::parse_from_rfc3339(s).ok().map(|d| d.date_naive()))
+}
+
+/// Unix seconds at midnight UTC
+fn date_number(date: &NaiveDate) -> f64 {
+    date.and_hms_opt(0, 0, 0)
+        .map(|d| d.and_utc().timestamp() as f64)
+        .unwrap_or_default()
+}
@@ -0,0 +1,613 @@
+//! Recipe Ingredients
+//!
+//! Structured ingredients of the recipes created by
+//! [`crate::personal_modes::create_recipe`], scaling to other serving counts
+//! and shopping lists built from several recipes.
+//!
+//! Units are normalized on the way in ("tablespoons" and "tbs" are both
+//! [`Unit::Tablespoon`]). Units of the same kind convert into each other
+//! (g and kg, ml and l, tsp, tbsp and cups, oz and lb); other pairs, like
+//! grams and cups of flour, can't be added up without a density and are
+//! listed separately.
+
+use crate::db::DbError;
+use rusqlite::{params, Connection, OptionalExtension};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use ulid::Ulid;
+
+#[derive(Error, Debug)]
+pub enum RecipeError {
+    #[error("Database error: {0}")]
+    Database(#[from] DbError),
+    #[error("Rusqlite error: {0}")]
+    Rusqlite(#[from] rusqlite::Error),
+    #[error("{entity} not found: {id}")]
+    NotFound { entity: &'static str, id: String },
+    #[error("Invalid quantity '{0}'")]
+    InvalidQuantity(String),
+    #[error("Unknown unit '{0}'")]
+    UnknownUnit(String),
+    #[error("Servings must be at least 1, got {0}")]
+    InvalidServings(i64),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Unit {
+    Gram,
+    Kilogram,
+    Milliliter,
+    Liter,
+    Teaspoon,
+    Tablespoon,
+    Cup,
+    Ounce,
+    Pound,
+    Pinch,
+    /// Counted items: 2 eggs, 1 onion
+    Piece,
+}
+
+/// Units that convert into each other
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum UnitKind {
+    MetricMass,
+    MetricVolume,
+    Spoons,
+    ImperialMass,
+    Pinch,
+    Piece,
+}
+
+impl Unit {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Unit::Gram => "g",
+            Unit::Kilogram => "kg",
+            Unit::Milliliter => "ml",
+            Unit::Liter => "l",
+            Unit::Teaspoon => "tsp",
+            Unit::Tablespoon => "tbsp",
+            Unit::Cup => "cup",
+            Unit::Ounce => "oz",
+            Unit::Pound => "lb",
+            Unit::Pinch => "pinch",
+            Unit::Piece => "piece",
+        }
+    }
+
+    /// The unit an abbreviation or name stands for, regardless of case and
+    /// plural. An empty unit counts pieces.
+    pub fn parse(unit: &str) -> Option<Unit> {
+        let unit = unit.trim().trim_end_matches('.').to_lowercase();
+        let unit = match unit.as_str() {
+            "g" | "gr" | "gram" | "grams" | "gramme" | "grammes" => Unit::Gram,
+            "kg" | "kgs" | "kilo" | "kilos" | "kilogram" | "kilograms" => Unit::Kilogram,
+            "ml" | "milliliter" | "milliliters" | "millilitre" | "millilitres" => Unit::Milliliter,
+            "l" | "liter" | "liters" | "litre" | "litres" => Unit::Liter,
+            "tsp" | "tsps" | "teaspoon" | "teaspoons" => Unit::Teaspoon,
+            "tbsp" | "tbsps" | "tbs" | "tablespoon" | "tablespoons" => Unit::Tablespoon,
+            "c" | "cup" | "cups" => Unit::Cup,
+            "oz" | "ounce" | "ounces" => Unit::Ounce,
+            "lb" | "lbs" | "pound" | "pounds" => Unit::Pound,
+            "pinch" | "pinches" => Unit::Pinch,
+            "" | "pc" | "pcs" | "piece" | "pieces" | "x" | "whole" => Unit::Piece,
+            _ => return None,
+        };
+        Some(unit)
+    }
+
+    /// Kind of the unit and how many of the kind's smallest unit it is
+    fn kind(self) -> (UnitKind, f64) {
+        match self {
+            Unit::Gram => (UnitKind::MetricMass, 1.0),
+            Unit::Kilogram => (UnitKind::MetricMass, 1000.0),
+            Unit::Milliliter => (UnitKind::MetricVolume, 1.0),
+            Unit::Liter => (UnitKind::MetricVolume, 1000.0),
+            Unit::Teaspoon => (UnitKind::Spoons, 1.0),
+            Unit::Tablespoon => (UnitKind::Spoons, 3.0),
+            Unit::Cup => (UnitKind::Spoons, 48.0),
+            Unit::Ounce => (UnitKind::ImperialMass, 1.0),
+            Unit::Pound => (UnitKind::ImperialMass, 16.0),
+            Unit::Pinch => (UnitKind::Pinch, 1.0),
+            Unit::Piece => (UnitKind::Piece, 1.0),
+        }
+    }
+
+    /// Scaled quantities are rounded to multiples of this: whole grams and
+    /// milliliters, quarters of spoons, cups, liters and pieces
+    fn step(self) -> f64 {
+        match self {
+            Unit::Gram | Unit::Milliliter => 1.0,
+            Unit::Kilogram | Unit::Ounce | Unit::Pound => 0.01,
+            Unit::Liter | Unit::Teaspoon | Unit::Tablespoon | Unit::Cup => 0.25,
+            Unit::Pinch | Unit::Piece => 0.25,
+        }
+    }
+
+    /// `quantity` rounded to the unit's step; anything above zero stays at
+    /// least one step
+    fn round(self, quantity: f64) -> f64 {
+        let step = self.step();
+        let rounded = (quantity / step).round() * step;
+        if rounded == 0.0 && quantity > 0.0 {
+            step
+        } else {
+            // Drop float noise like 0.30000000000000004
+            (rounded * 100.0).round() / 100.0
+        }
+    }
+}
+
+/// A quantity written as a decimal ("1.5", "0,5"), a fraction ("3/4"), a
+/// mixed number ("1 1/2") or with a fraction character ("1½")
+pub fn parse_quantity(quantity: &str) -> Option<f64> {
+    const VULGAR: [(char, f64); 7] = [
+        ('¼', 0.25),
+        ('½', 0.5),
+        ('¾', 0.75),
+        ('⅓', 1.0 / 3.0),
+        ('⅔', 2.0 / 3.0),
+        ('⅛', 0.125),
+        ('⅜', 0.375),
+    ];
+    let quantity = quantity.trim();
+    let mut total = 0.0;
+    let mut whole = quantity;
+    if let Some((c, value)) = VULGAR.iter().find(|(c, _)| quantity.ends_with(*c)) {
+        total += value;
+        whole = quantity[..quantity.len() - c.len_utf8()].trim();
+        if whole.is_empty() {
+            return Some(total);
+        }
+    }
+
+    let parts: Vec<&str> = whole.split_whitespace().collect();
+    let value = match parts.as_slice() {
+        [fraction] if fraction.contains('/') => parse_fraction(fraction)?,
+        [number] => number.replace(',', ".").parse::<f64>().ok()?,
+        [number, fraction] => number.parse::<u32>().ok()? as f64 + parse_fraction(fraction)?,
+        _ => return None,
+    };
+    let total = total + value;
+    (total.is_finite() && total > 0.0).then_some(total)
+}
+
+fn parse_fraction(fraction: &str) -> Option<f64> {
+    let (numerator, denominator) = fraction.split_once('/')?;
+    let numerator: u32 = numerator.trim().parse().ok()?;
+    let denominator: u32 = denominator.trim().parse().ok()?;
+    if denominator == 0 {
+        return None;
+    }
+    Some(numerator as f64 / denominator as f64)
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RecipeIngredient {
+    pub id: String,
+    pub recipe_id: String,
+    pub name: String,
+    pub quantity: f64,
+    pub unit: Unit,
+    /// e.g. "finely chopped"
+    pub note: Option<String>,
+    pub position: i64,
+}
+
+/// An ingredient at a recipe's scaled serving count
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ScaledIngredient {
+    pub name: String,
+    pub quantity: f64,
+    pub unit: Unit,
+    pub note: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ScaledRecipe {
+    pub recipe_id: String,
+    /// Servings the recipe is written for
+    pub servings: i64,
+    pub target_servings: i64,
+    pub ingredients: Vec<ScaledIngredient>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ShoppingListItem {
+    pub id: String,
+    pub name: String,
+    pub quantity: f64,
+    pub unit: Unit,
+    /// Another line of the list is the same ingredient in a unit this one
+    /// doesn't convert to, like grams and cups of flour
+    pub unmergeable: bool,
+    pub is_checked: bool,
+    pub position: i64,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ShoppingList {
+    pub id: String,
+    pub space_id: String,
+    pub recipe_ids: Vec<String>,
+    pub created_at: i64,
+    pub items: Vec<ShoppingListItem>,
+}
+
+fn recipe_servings(conn: &Connection, recipe_id: &str) -> Result<i64, RecipeError> {
+    conn.query_row(
+        "SELECT servings FROM recipe WHERE id = ?1",
+        [recipe_id],
+        |row| row.get(0),
+    )
+    .optional()?
+    .ok_or_else(|| RecipeError::NotFound {
+        entity: "recipe",
+        id: recipe_id.to_string(),
+    })
+}
+
+fn unit_from_db(unit: String) -> rusqlite::Result<Unit> {
+    Unit::parse(&unit).ok_or_else(|| {
+        rusqlite::Error::FromSqlConversionFailure(
+            0,
+            rusqlite::types::Type::Text,
+            format!("Unknown unit {unit}").into(),
+        )
+    })
+}
+
+// --- Ingredients ---
+
+/// Add an ingredient after the recipe's others. `quantity` is a decimal or
+/// fraction, see [`parse_quantity`]; `unit` any of [`Unit::parse`]'s names.
+pub fn add_recipe_ingredient(
+    conn: &Connection,
+    recipe_id: &str,
+    name: &str,
+    quantity: &str,
+    unit: &str,
+    note: Option<&str>,
+) -> Result<RecipeIngredient, RecipeError> {
+    recipe_servings(conn, recipe_id)?;
+    let amount =
+        parse_quantity(quantity).ok_or_else(|| RecipeError::InvalidQuantity(quantity.into()))?;
+    let unit = Unit::parse(unit).ok_or_else(|| RecipeError::UnknownUnit(unit.into()))?;
+    let position: i64 = conn.query_row(
+        "SELECT COALESCE(MAX(position) + 1, 0) FROM recipe_ingredient WHERE recipe_id = ?1",
+        [recipe_id],
+        |row| row.get(0),
+    )?;
+    let ingredient = RecipeIngredient {
+        id: Ulid::new().to_string(),
+        recipe_id: recipe_id.to_string(),
+        name: name.trim().to_string(),
+        quantity: amount,
+        unit,
+        note: note.map(String::from),
+        position,
+    };
+    conn.execute(
+        "INSERT INTO recipe_ingredient (id, recipe_id, name, quantity, unit, note, position)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+        params![
+            ingredient.id,
+            ingredient.recipe_id,
+            ingredient.name,
+            ingredient.quantity,
+            ingredient.unit.as_str(),
+            ingredient.note,
+            ingredient.position
+        ],
+    )?;
+    Ok(ingredient)
+}
+
+pub fn get_recipe_ingredients(
+    conn: &Connection,
+    recipe_id: &str,
+) -> Result<Vec<RecipeIngredient>, RecipeError> {
+    let mut stmt = conn.prepare(
+        "SELECT id, recipe_id, name, quantity, unit, note, position FROM recipe_ingredient
+         WHERE recipe_id = ?1 ORDER BY position",
+    )?;
+    let ingredients = stmt
+        .query_map([recipe_id], |row| {
+            Ok(RecipeIngredient {
+                id: row.get(0)?,
+                recipe_id: row.get(1)?,
+                name: row.get(2)?,
+                quantity: row.get(3)?,
+                unit: unit_from_db(row.get(4)?)?,
+                note: row.get(5)?,
+                position: row.get(6)?,
+            })
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(ingredients)
+}
+
+/// Set how many servings the ingredient quantities are for
+pub fn set_recipe_servings(
+    conn: &Connection,
+    recipe_id: &str,
+    servings: i64,
+) -> Result<(), RecipeError> {
+    if servings < 1 {
+        return Err(RecipeError::InvalidServings(servings));
+    }
+    let updated = conn.execute(
+        "UPDATE recipe SET servings = ?1 WHERE id = ?2",
+        params![servings, recipe_id],
+    )?;
+    if updated == 0 {
+        return Err(RecipeError::NotFound {
+            entity: "recipe",
+            id: recipe_id.to_string(),
+        });
+    }
+    Ok(())
+}
+
+/// The recipe's ingredients for `target_servings`, rounded per unit
+pub fn scale_recipe(
+    conn: &Connection,
+    recipe_id: &str,
+    target_servings: i64,
+) -> Result<ScaledRecipe, RecipeError> {
+    if target_servings < 1 {
+        return Err(RecipeError::InvalidServings(target_servings));
+    }
+    let servings = recipe_servings(conn, recipe_id)?;
+    let factor = target_servings as f64 / servings as f64;
+    let ingredients = get_recipe_ingredients(conn, recipe_id)?
+        .into_iter()
+        .map(|i| ScaledIngredient {
+            quantity: i.unit.round(i.quantity * factor),
+            name: i.name,
+            unit: i.unit,
+            note: i.note,
+        })
+        .collect();
+    Ok(ScaledRecipe {
+        recipe_id: recipe_id.to_string(),
+        servings,
+        target_servings,
+        ingredients,
+    })
+}
+
+// --- Shopping lists ---
+
+/// One line of a list being built: an ingredient in one kind of unit,
+/// counted in that kind's smallest unit
+struct Line {
+    name: String,
+    key: String,
+    kind: UnitKind,
+    amount: f64,
+    /// Units the line was added up from
+    units: Vec<Unit>,
+}
+
+impl Line {
+    /// The largest of the line's units that shows the amount as at least one
+    /// whole step, e.g. 1.5 kg rather than 1500 g but 4 tsp rather than
+    /// 1.33 tbsp
+    fn display(&self) -> (f64, Unit) {
+        let mut units = self.units.clone();
+        units.sort_by(|a, b| b.kind().1.total_cmp(&a.kind().1));
+        for unit in &units {
+            let value = self.amount / unit.kind().1;
+            let steps = value / unit.step();
+            if value >= 1.0 && (steps - steps.round()).abs() < 1e-6 {
+                return (unit.round(value), *unit);
+            }
+        }
+        let smallest = *units.last().expect("a line has a unit");
+        (smallest.round(self.amount / smallest.kind().1), smallest)
+    }
+}
+
+/// Build and save a shopping list of the ingredients of `recipe_ids`, each at
+/// its own serving count. The same ingredient (by name, regardless of case)
+/// is added up across recipes as far as its units convert.
+pub fn generate_shopping_list(
+    conn: &Connection,
+    space_id: Ulid,
+    recipe_ids: &[String],
+) -> Result<ShoppingList, RecipeError> {
+    let mut lines: Vec<Line> = Vec::new();
+    for recipe_id in recipe_ids {
+        let in_space: bool = conn.query_row(
+            "SELECT EXISTS(SELECT 1 FROM recipe WHERE id = ?1 AND space_id = ?2)",
+            params![recipe_id, space_id.to_string()],
+            |row| row.get(0),
+        )?;
+        if !in_space {
+            return Err(RecipeError::NotFound {
+                entity: "recipe",
+                id: recipe_id.clone(),
+            });
+        }
+        for ingredient in get_recipe_ingredients(conn, recipe_id)? {
+            let key = ingredient.name.to_lowercase();
+            let (kind, factor) = ingredient.unit.kind();
+            let amount = ingredient.quantity * factor;
+            match lines.iter_mut().find(|l| l.key == key && l.kind == kind) {
+                Some(line) => {
+                    line.amount += amount;
+                    if !line.units.contains(&ingredient.unit) {
+                        line.units.push(ingredient.unit);
+                    }
+                }
+                None => lines.push(Line {
+                    name: ingredient.name,
+                    key,
+                    kind,
+                    amount,
+                    units: vec![ingredient.unit],
+                }),
+            }
+        }
+    }
+
+    let items: Vec<ShoppingListItem> = lines
+        .iter()
+        .enumerate()
+        .map(|(position, line)| {
+            let (quantity, unit) = line.display();
+            ShoppingListItem {
+                id: Ulid::new().to_string(),
+                name: line.name.clone(),
+                quantity,
+                unit,
+                unmergeable: lines
+                    .iter()
+                    .any(|l| l.key == line.key && l.kind != line.kind),
+                is_checked: false,
+                position: position as i64,
+            }
+        })
+        .collect();
+    let list = ShoppingList {
+        id: Ulid::new().to_string(),
+        space_id: space_id.to_string(),
+        recipe_ids: recipe_ids.to_vec(),
+        created_at: chrono::Utc::now().timestamp(),
+        items,
+    };
+
+    let tx = conn.unchecked_transaction()?;
+    tx.execute(
+        "INSERT INTO shopping_list (id, space_id, recipe_ids_json, created_at)
+         VALUES (?1, ?2, ?3, ?4)",
+        params![
+            list.id,
+            list.space_id,
+            serde_json::to_string(&list.recipe_ids).map_err(DbError::from)?,
+            list.created_at
+        ],
+    )?;
+    for item in &list.items {
+        tx.execute(
+            "INSERT INTO shopping_list_item (id, list_id, name, quantity, unit, unmergeable,
+                 is_checked, position)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, 0, ?7)",
+            params![
+                item.id,
+                list.id,
+                item.name,
+                item.quantity,
+                item.unit.as_str(),
+                item.unmergeable,
+                item.position
+            ],
+        )?;
+    }
+    tx.commit()?;
+    log::info!(
+        "[recipe] Shopping list {} with {} item(s) from {} recipe(s)",
+        list.id,
+        list.items.len(),
+        recipe_ids.len()
+    );
+    Ok(list)
+}
+
+pub fn get_shopping_list(conn: &Connection, list_id: &str) -> Result<ShoppingList, RecipeError> {
+    let (space_id, recipe_ids_json, created_at): (String, String, i64) = conn
+        .query_row(
+            "SELECT space_id, recipe_ids_json, created_at FROM shopping_list WHERE id = ?1",
+            [list_id],
+            |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+        )
+        .optional()?
+        .ok_or_else(|| RecipeError::NotFound {
+            entity: "shopping_list",
+            id: list_id.to_string(),
+        })?;
+    let mut stmt = conn.prepare(
+        "SELECT id, name, quantity, unit, unmergeable, is_checked, position
+         FROM shopping_list_item WHERE list_id = ?1 ORDER BY position",
+    )?;
+    let items = stmt
+        .query_map([list_id], |row| {
+            Ok(ShoppingListItem {
+                id: row.get(0)?,
+                name: row.get(1)?,
+                quantity: row.get(2)?,
+                unit: unit_from_db(row.get(3)?)?,
+                unmergeable: row.get(4)?,
+                is_checked: row.get(5)?,
+                position: row.get(6)?,
+            })
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(ShoppingList {
+        id: list_id.to_string(),
+        space_id,
+        recipe_ids: serde_json::from_str(&recipe_ids_json).map_err(DbError::from)?,
+        created_at,
+        items,
+    })
+}
+
+/// Check an item of a shopping list off, or back on
+pub fn set_shopping_item_checked(
+    conn: &Connection,
+    item_id: &str,
+    checked: bool,
+) -> Result<(), RecipeError> {
+    let updated = conn.execute(
+        "UPDATE shopping_list_item SET is_checked = ?1 WHERE id = ?2",
+        params![checked, item_id],
+    )?;
+    if updated == 0 {
+        return Err(RecipeError::NotFound {
+            entity: "shopping_list_item",
+            id: item_id.to_string(),
+        });
+    }
+    Ok(())
+}
+
+pub fn delete_shopping_list(conn: &Connection, list_id: &str) -> Result<(), RecipeError> {
+    let deleted = conn.execute("DELETE FROM shopping_list WHERE id = ?1", [list_id])?;
+    if deleted == 0 {
+        return Err(RecipeError::NotFound {
+            entity: "shopping_list",
+            id: list_id.to_string(),
+        });
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_units_and_quantities_parse() {
+        for unit in [
+            "g", "kg", "ml", "l", "tsp", "tbsp", "cup", "oz", "lb", "pinch", "piece",
+        ] {
+            assert_eq!(Unit::parse(unit).map(Unit::as_str), Some(unit));
+        }
+        assert_eq!(Unit::parse("Tablespoons"), Some(Unit::Tablespoon));
+        assert_eq!(Unit::parse(""), Some(Unit::Piece));
+        assert_eq!(Unit::parse("handful"), None);
+
+        assert_eq!(parse_quantity("1.5"), Some(1.5));
+        assert_eq!(parse_quantity("0,5"), Some(0.5));
+        assert_eq!(parse_quantity("3/4"), Some(0.75));
+        assert_eq!(parse_quantity("1 1/2"), Some(1.5));
+        assert_eq!(parse_quantity("1½"), Some(1.5));
+        assert_eq!(parse_quantity("¼"), Some(0.25));
+        assert_eq!(parse_quantity("1/0"), None);
+        assert_eq!(parse_quantity("0"), None);
+        assert_eq!(parse_quantity("some"), None);
+    }
+}
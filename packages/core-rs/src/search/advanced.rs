@@ -1,4 +1,9 @@
-use crate::db::DbError;
+use super::query::{
+    parse_query, QueryNode, TextSource, INBOX_TEXT, NOTE_TEXT, OCR_TEXT, PROJECT_TEXT, TASK_TEXT,
+};
+use crate::db::{table_exists, DbError};
+use crate::property::{filter_condition, PropertyFilter};
+use crate::space::in_active_space;
 use rusqlite::{Connection, Result};
 use serde::{Deserialize, Serialize};
 use ulid::Ulid;
@@ -20,11 +25,13 @@ pub enum EntityType {
     Project,
     Tag,
     TimeEntry,
+    InboxItem,
     All,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
 pub struct SearchFilters {
+    /// Without a space, archived spaces are left out
     pub space_id: Option<Ulid>,
     pub tags: Vec<String>,
     pub date_from: Option<i64>,
@@ -33,6 +40,10 @@ pub struct SearchFilters {
     pub priority: Option<String>,
     pub completed: Option<bool>,
     pub archived: Option<bool>,
+    /// Conditions on note properties, all of which must hold. Only notes
+    /// have properties, so other entities never match when any are given.
+    #[serde(default)]
+    pub properties: Vec<PropertyFilter>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -69,6 +80,14 @@ pub struct SearchResult {
     pub metadata: serde_json::Value,
 }
 
+/// Notes of a space, and notes of other spaces referenced in it; takes the
+/// space id twice
+const NOTE_IN_SPACE: &str = "(n.space_id = ? OR n.id IN (SELECT note_id FROM note_reference
+     WHERE space_id = ? AND removed_at IS NULL))";
+
+/// Attachment text (OCR) hits rank below direct matches in the note itself
+const ATTACHMENT_RELEVANCE_DISCOUNT: f64 = 0.5;
+
 impl Default for SortOptions {
     fn default() -> Self {
         SortOptions {
@@ -78,25 +97,34 @@ impl Default for SortOptions {
     }
 }
 
-/// Advanced multi-entity search
+/// Advanced multi-entity search. The query text uses the same syntax as
+/// [`search_notes`](super::search_notes).
 pub fn search_all(conn: &Connection, query: &SearchQuery) -> Result<Vec<SearchResult>, DbError> {
+    let text = parse_query(&query.query)?;
+    let text = text.as_ref();
     let mut results = Vec::new();
 
     for entity_type in &query.entity_types {
         match entity_type {
             EntityType::Note => {
-                results.extend(search_notes_advanced(conn, query)?);
+                results.extend(search_notes_advanced(conn, query, text)?);
+                merge_attachment_hits(&mut results, search_note_attachments(conn, query, text)?);
             }
             EntityType::Task => {
-                results.extend(search_tasks_advanced(conn, query)?);
+                results.extend(search_tasks_advanced(conn, query, text)?);
             }
             EntityType::Project => {
-                results.extend(search_projects_advanced(conn, query)?);
+                results.extend(search_projects_advanced(conn, query, text)?);
+            }
+            EntityType::InboxItem => {
+                results.extend(search_inbox_items(conn, query, text)?);
             }
             EntityType::All => {
-                results.extend(search_notes_advanced(conn, query)?);
-                results.extend(search_tasks_advanced(conn, query)?);
-                results.extend(search_projects_advanced(conn, query)?);
+                results.extend(search_notes_advanced(conn, query, text)?);
+                merge_attachment_hits(&mut results, search_note_attachments(conn, query, text)?);
+                results.extend(search_tasks_advanced(conn, query, text)?);
+                results.extend(search_projects_advanced(conn, query, text)?);
+                results.extend(search_inbox_items(conn, query, text)?);
             }
             _ => {}
         }
@@ -121,48 +149,29 @@ pub fn search_all(conn: &Connection, query: &SearchQuery) -> Result<Vec<SearchRe
 fn search_notes_advanced(
     conn: &Connection,
     query: &SearchQuery,
+    text: Option<&QueryNode>,
 ) -> Result<Vec<SearchResult>, DbError> {
-    // Check for FTS table availability
-    let has_fts: bool = conn
-        .query_row(
-            "SELECT name FROM sqlite_master WHERE type='table' AND name='fts_note'",
-            [],
-            |_| Ok(true),
-        )
-        .unwrap_or(false);
-
     // Include content_md for snippets
     let mut sql = String::from(
-        "SELECT n.id, n.title, n.created_at, n.modified_at, n.content_md
+        "SELECT n.id, n.title, n.created_at, n.modified_at, n.content_md, n.space_id
          FROM note n",
     );
 
     let mut where_clauses = Vec::new();
     let mut params: Vec<Box<dyn rusqlite::ToSql>> = Vec::new();
 
-    // Join FTS if available and querying text
-    if has_fts && !query.query.is_empty() {
-        sql.push_str(" JOIN fts_note ON n.rowid = fts_note.rowid");
-    }
-
     // Space filter
     if let Some(space_id) = &query.filters.space_id {
-        where_clauses.push("n.space_id = ?".to_string());
+        where_clauses.push(NOTE_IN_SPACE.to_string());
+        params.push(Box::new(space_id.to_string()));
         params.push(Box::new(space_id.to_string()));
+    } else {
+        where_clauses.push(in_active_space("n.space_id"));
     }
 
     // Text search
-    if !query.query.is_empty() {
-        if has_fts {
-            where_clauses.push("fts_note MATCH ?".to_string());
-            params.push(Box::new(query.query.clone()));
-        } else {
-            // Fallback to LIKE on title and content
-            where_clauses.push("(n.title LIKE ? OR n.content_md LIKE ?)".to_string());
-            let pattern = format!("%{}%", query.query);
-            params.push(Box::new(pattern.clone()));
-            params.push(Box::new(pattern));
-        }
+    if let Some(node) = text {
+        push_text_condition(conn, &NOTE_TEXT, node, &mut where_clauses, &mut params)?;
     }
 
     // Date filters
@@ -183,12 +192,16 @@ fn search_notes_advanced(
         // Default: exclude trashed
         where_clauses.push("n.is_trashed = 0".to_string());
     }
+    // Locked notes stay out of search, titles included
+    where_clauses.push("n.is_locked = 0".to_string());
+    push_property_conditions(&query.filters, &mut where_clauses, &mut params);
 
     if !where_clauses.is_empty() {
         sql.push_str(" WHERE ");
         sql.push_str(&where_clauses.join(" AND "));
     }
 
+    let terms = text.map(QueryNode::positive_terms).unwrap_or_default();
     let mut results = Vec::new();
     let mut stmt = conn.prepare(&sql)?;
     let params_refs: Vec<&dyn rusqlite::ToSql> = params.iter().map(|b| b.as_ref()).collect();
@@ -200,14 +213,15 @@ fn search_notes_advanced(
         let created_at: i64 = row.get(2)?;
         let updated_at: i64 = row.get(3)?;
         let content: String = row.get(4)?;
+        let note_space_id: String = row.get(5)?;
 
         // Calculate snippet and relevance
-        let (snippet, relevance) = if query.query.is_empty() {
+        let (snippet, relevance) = if terms.is_empty() {
             (None, 1.0)
         } else {
             (
-                extract_snippet(&content, &query.query),
-                calculate_relevance(&title, Some(&content), &query.query),
+                extract_snippet(&content, &terms),
+                calculate_relevance(&title, Some(&content), &terms),
             )
         };
 
@@ -219,27 +233,128 @@ fn search_notes_advanced(
             relevance_score: relevance,
             created_at,
             updated_at,
-            metadata: serde_json::json!({ "type": "note" }),
+            metadata: if query
+                .filters
+                .space_id
+                .is_some_and(|space_id| space_id.to_string() != note_space_id)
+            {
+                serde_json::json!({ "type": "note", "reference": true, "space_id": note_space_id })
+            } else {
+                serde_json::json!({ "type": "note" })
+            },
         });
     }
 
     Ok(results)
 }
 
+/// Search OCR text of note attachments, reporting hits as the owning note.
+/// Queries with nothing to look for, such as `-draft` or `tag:work`, only
+/// match notes directly.
+fn search_note_attachments(
+    conn: &Connection,
+    query: &SearchQuery,
+    text: Option<&QueryNode>,
+) -> Result<Vec<SearchResult>, DbError> {
+    let Some(node) = text else {
+        return Ok(Vec::new());
+    };
+    let terms = node.positive_terms();
+    if terms.is_empty() || !table_exists(conn, "fts_ocr")? {
+        return Ok(Vec::new());
+    }
+
+    let mut sql = String::from(
+        "SELECT n.id, n.title, n.created_at, n.modified_at, fts_ocr.content, fts_ocr.blob_id
+         FROM fts_ocr
+         JOIN note_attachment na ON na.blob_id = fts_ocr.blob_id
+         JOIN note n ON n.id = na.note_id",
+    );
+    let mut where_clauses = Vec::new();
+    let mut params: Vec<Box<dyn rusqlite::ToSql>> = Vec::new();
+    push_text_condition(conn, &OCR_TEXT, node, &mut where_clauses, &mut params)?;
+
+    if let Some(space_id) = &query.filters.space_id {
+        where_clauses.push(NOTE_IN_SPACE.to_string());
+        params.push(Box::new(space_id.to_string()));
+        params.push(Box::new(space_id.to_string()));
+    } else {
+        where_clauses.push(in_active_space("n.space_id"));
+    }
+    if let Some(from) = query.filters.date_from {
+        where_clauses.push("n.created_at >= ?".to_string());
+        params.push(Box::new(from));
+    }
+    if let Some(to) = query.filters.date_to {
+        where_clauses.push("n.created_at <= ?".to_string());
+        params.push(Box::new(to));
+    }
+    if let Some(archived) = query.filters.archived {
+        where_clauses.push("n.is_trashed = ?".to_string());
+        params.push(Box::new(if archived { 1 } else { 0 }));
+    } else {
+        where_clauses.push("n.is_trashed = 0".to_string());
+    }
+    where_clauses.push("n.is_locked = 0".to_string());
+    push_property_conditions(&query.filters, &mut where_clauses, &mut params);
+
+    sql.push_str(" WHERE ");
+    sql.push_str(&where_clauses.join(" AND "));
+
+    let mut results = Vec::new();
+    let mut stmt = conn.prepare(&sql)?;
+    let params_refs: Vec<&dyn rusqlite::ToSql> = params.iter().map(|b| b.as_ref()).collect();
+    let mut rows = stmt.query(params_refs.as_slice())?;
+
+    while let Some(row) = rows.next()? {
+        let id: String = row.get(0)?;
+        let title: String = row.get(1)?;
+        let created_at: i64 = row.get(2)?;
+        let updated_at: i64 = row.get(3)?;
+        let content: String = row.get(4)?;
+        let blob_id: String = row.get(5)?;
+
+        results.push(SearchResult {
+            entity_type: EntityType::Note,
+            entity_id: id,
+            title,
+            snippet: extract_snippet(&content, &terms),
+            relevance_score: calculate_relevance("", Some(&content), &terms)
+                * ATTACHMENT_RELEVANCE_DISCOUNT,
+            created_at,
+            updated_at,
+            metadata: serde_json::json!({
+                "type": "note",
+                "matched_in": "ocr",
+                "blob_id": blob_id
+            }),
+        });
+    }
+
+    Ok(results)
+}
+
+/// Add attachment hits for notes that did not already match directly
+fn merge_attachment_hits(results: &mut Vec<SearchResult>, hits: Vec<SearchResult>) {
+    for hit in hits {
+        let already_present = results
+            .iter()
+            .any(|r| r.entity_type == EntityType::Note && r.entity_id == hit.entity_id);
+        if !already_present {
+            results.push(hit);
+        }
+    }
+}
+
 /// Advanced task search
 fn search_tasks_advanced(
     conn: &Connection,
     query: &SearchQuery,
+    text: Option<&QueryNode>,
 ) -> Result<Vec<SearchResult>, DbError> {
-    // Check for FTS table
-    let has_fts: bool = conn
-        .query_row(
-            "SELECT name FROM sqlite_master WHERE type='table' AND name='fts_task'",
-            [],
-            |_| Ok(true),
-        )
-        .unwrap_or(false);
-
+    if !query.filters.properties.is_empty() {
+        return Ok(Vec::new());
+    }
     let mut sql = String::from(
         "SELECT t.id, t.title, t.description, t.status, t.priority, t.start_at, t.completed_at, t.due_at
          FROM task t",
@@ -247,28 +362,17 @@ fn search_tasks_advanced(
     let mut where_clauses = Vec::new();
     let mut params: Vec<Box<dyn rusqlite::ToSql>> = Vec::new();
 
-    if has_fts && !query.query.is_empty() {
-        // Join via rowid mapping
-        sql.push_str(" JOIN fts_task ON t.rowid = fts_task.rowid");
-    }
-
     // Space filter
     if let Some(space_id) = &query.filters.space_id {
         where_clauses.push("t.space_id = ?".to_string());
         params.push(Box::new(space_id.to_string()));
+    } else {
+        where_clauses.push(in_active_space("t.space_id"));
     }
 
     // Text search
-    if !query.query.is_empty() {
-        if has_fts {
-            where_clauses.push("fts_task MATCH ?".to_string());
-            params.push(Box::new(query.query.clone()));
-        } else {
-            where_clauses.push("(t.title LIKE ? OR t.description LIKE ?)".to_string());
-            let pattern = format!("%{}%", query.query);
-            params.push(Box::new(pattern.clone()));
-            params.push(Box::new(pattern));
-        }
+    if let Some(node) = text {
+        push_text_condition(conn, &TASK_TEXT, node, &mut where_clauses, &mut params)?;
     }
 
     // Status filter
@@ -299,6 +403,7 @@ fn search_tasks_advanced(
         sql.push_str(&where_clauses.join(" AND "));
     }
 
+    let terms = text.map(QueryNode::positive_terms).unwrap_or_default();
     let mut results = Vec::new();
     let mut stmt = conn.prepare(&sql)?;
     let params_refs: Vec<&dyn rusqlite::ToSql> = params.iter().map(|b| b.as_ref()).collect();
@@ -314,14 +419,14 @@ fn search_tasks_advanced(
         let updated_at: i64 = 0; // No updated_at in task schema v1?
         let deadline: Option<i64> = row.get::<_, Option<i64>>(7)?;
 
-        let (snippet, relevance) = if query.query.is_empty() {
+        let (snippet, relevance) = if terms.is_empty() {
             (None, 1.0)
         } else {
             (
                 description
                     .as_ref()
-                    .and_then(|d| extract_snippet(d, &query.query)),
-                calculate_relevance(&title, description.as_deref(), &query.query),
+                    .and_then(|d| extract_snippet(d, &terms)),
+                calculate_relevance(&title, description.as_deref(), &terms),
             )
         };
 
@@ -349,16 +454,11 @@ fn search_tasks_advanced(
 fn search_projects_advanced(
     conn: &Connection,
     query: &SearchQuery,
+    text: Option<&QueryNode>,
 ) -> Result<Vec<SearchResult>, DbError> {
-    // Check for FTS table
-    let has_fts: bool = conn
-        .query_row(
-            "SELECT name FROM sqlite_master WHERE type='table' AND name='fts_project'",
-            [],
-            |_| Ok(true),
-        )
-        .unwrap_or(false);
-
+    if !query.filters.properties.is_empty() {
+        return Ok(Vec::new());
+    }
     let mut sql = String::from(
         "SELECT p.id, p.title, p.goal_outcome, p.status, p.start_at, p.target_end_at
          FROM project p",
@@ -366,28 +466,17 @@ fn search_projects_advanced(
     let mut where_clauses = Vec::new();
     let mut params: Vec<Box<dyn rusqlite::ToSql>> = Vec::new();
 
-    if has_fts && !query.query.is_empty() {
-        // Use rowid mapping between base table and FTS table
-        sql.push_str(" JOIN fts_project ON p.rowid = fts_project.rowid");
-    }
-
     // Space filter
     if let Some(space_id) = &query.filters.space_id {
         where_clauses.push("p.space_id = ?".to_string());
         params.push(Box::new(space_id.to_string()));
+    } else {
+        where_clauses.push(in_active_space("p.space_id"));
     }
 
     // Text search
-    if !query.query.is_empty() {
-        if has_fts {
-            where_clauses.push("fts_project MATCH ?".to_string());
-            params.push(Box::new(query.query.clone()));
-        } else {
-            where_clauses.push("(p.title LIKE ? OR p.goal_outcome LIKE ?)".to_string());
-            let pattern = format!("%{}%", query.query);
-            params.push(Box::new(pattern.clone()));
-            params.push(Box::new(pattern));
-        }
+    if let Some(node) = text {
+        push_text_condition(conn, &PROJECT_TEXT, node, &mut where_clauses, &mut params)?;
     }
 
     // Status filter
@@ -401,6 +490,7 @@ fn search_projects_advanced(
         sql.push_str(&where_clauses.join(" AND "));
     }
 
+    let terms = text.map(QueryNode::positive_terms).unwrap_or_default();
     let mut results = Vec::new();
     let mut stmt = conn.prepare(&sql)?;
     let params_refs: Vec<&dyn rusqlite::ToSql> = params.iter().map(|b| b.as_ref()).collect();
@@ -414,14 +504,14 @@ fn search_projects_advanced(
         let start_at: i64 = row.get::<_, Option<i64>>(4)?.unwrap_or(0);
         // No updated_at in project schema v1
 
-        let (snippet, relevance) = if query.query.is_empty() {
+        let (snippet, relevance) = if terms.is_empty() {
             (None, 1.0)
         } else {
             (
                 goal_outcome
                     .as_ref()
-                    .and_then(|d| extract_snippet(d, &query.query)),
-                calculate_relevance(&name, goal_outcome.as_deref(), &query.query),
+                    .and_then(|d| extract_snippet(d, &terms)),
+                calculate_relevance(&name, goal_outcome.as_deref(), &terms),
             )
         };
 
@@ -443,8 +533,132 @@ fn search_projects_advanced(
     Ok(results)
 }
 
+/// Inbox search. Captures belong to no space, so searches within one
+/// leave them out.
+fn search_inbox_items(
+    conn: &Connection,
+    query: &SearchQuery,
+    text: Option<&QueryNode>,
+) -> Result<Vec<SearchResult>, DbError> {
+    if query.filters.space_id.is_some() || !query.filters.properties.is_empty() {
+        return Ok(Vec::new());
+    }
+    let mut sql = String::from(
+        "SELECT i.id, i.text, i.source, i.captured_at, i.updated_at
+         FROM inbox_item i",
+    );
+    let mut where_clauses = Vec::new();
+    let mut params: Vec<Box<dyn rusqlite::ToSql>> = Vec::new();
+
+    if let Some(node) = text {
+        push_text_condition(conn, &INBOX_TEXT, node, &mut where_clauses, &mut params)?;
+    }
+    if let Some(from) = query.filters.date_from {
+        where_clauses.push("i.captured_at >= ?".to_string());
+        params.push(Box::new(from));
+    }
+    if let Some(to) = query.filters.date_to {
+        where_clauses.push("i.captured_at <= ?".to_string());
+        params.push(Box::new(to));
+    }
+
+    if !where_clauses.is_empty() {
+        sql.push_str(" WHERE ");
+        sql.push_str(&where_clauses.join(" AND "));
+    }
+
+    let terms = text.map(QueryNode::positive_terms).unwrap_or_default();
+    let mut results = Vec::new();
+    let mut stmt = conn.prepare(&sql)?;
+    let params_refs: Vec<&dyn rusqlite::ToSql> = params.iter().map(|b| b.as_ref()).collect();
+    let mut rows = stmt.query(params_refs.as_slice())?;
+
+    while let Some(row) = rows.next()? {
+        let id: String = row.get(0)?;
+        let content: String = row.get(1)?;
+        let source: String = row.get(2)?;
+        let created_at: i64 = row.get(3)?;
+        let updated_at: i64 = row.get(4)?;
+
+        // Titled with the first line, as a task made from it would be
+        let title = content
+            .trim()
+            .lines()
+            .next()
+            .unwrap_or_default()
+            .to_string();
+        let (snippet, relevance) = if terms.is_empty() {
+            (None, 1.0)
+        } else {
+            (
+                extract_snippet(&content, &terms),
+                calculate_relevance("", Some(&content), &terms),
+            )
+        };
+
+        results.push(SearchResult {
+            entity_type: EntityType::InboxItem,
+            entity_id: id,
+            title,
+            snippet,
+            relevance_score: relevance,
+            created_at,
+            updated_at,
+            metadata: serde_json::json!({
+                "type": "inbox_item",
+                "source": source
+            }),
+        });
+    }
+
+    Ok(results)
+}
+
+/// Add the condition matching `node` in `source`, through its FTS index if
+/// there is one
+fn push_text_condition(
+    conn: &Connection,
+    source: &TextSource,
+    node: &QueryNode,
+    where_clauses: &mut Vec<String>,
+    params: &mut Vec<Box<dyn rusqlite::ToSql>>,
+) -> Result<(), DbError> {
+    let condition = source.condition(node, table_exists(conn, source.fts_table)?);
+    where_clauses.push(condition.sql);
+    for param in condition.params {
+        params.push(Box::new(param));
+    }
+    Ok(())
+}
+
+/// Add the conditions of the property filters on notes aliased `n`
+fn push_property_conditions(
+    filters: &SearchFilters,
+    where_clauses: &mut Vec<String>,
+    params: &mut Vec<Box<dyn rusqlite::ToSql>>,
+) {
+    for filter in &filters.properties {
+        let (condition, values) = filter_condition(filter, "n");
+        where_clauses.push(condition);
+        for value in values {
+            params.push(Box::new(value));
+        }
+    }
+}
+
+/// Extract snippet around the first of the query's terms in the content
+fn extract_snippet(content: &str, terms: &[String]) -> Option<String> {
+    let lower_content = content.to_lowercase();
+    let term = terms
+        .iter()
+        .find(|term| lower_content.contains(&term.to_lowercase()))
+        .map(String::as_str)
+        .unwrap_or("");
+    snippet_around(content, term)
+}
+
 /// Extract snippet around query match
-fn extract_snippet(content: &str, query: &str) -> Option<String> {
+fn snippet_around(content: &str, query: &str) -> Option<String> {
     let trimmed_query = query.trim();
     if trimmed_query.is_empty() {
         return Some(content.chars().take(150).collect());
@@ -483,8 +697,18 @@ fn extract_snippet(content: &str, query: &str) -> Option<String> {
     Some(format!("{}{}{}", prefix, snippet, suffix))
 }
 
-/// Calculate relevance score
-fn calculate_relevance(title: &str, content: Option<&str>, query: &str) -> f64 {
+/// Calculate relevance score, summed over the query's terms
+fn calculate_relevance(title: &str, content: Option<&str>, terms: &[String]) -> f64 {
+    if terms.is_empty() {
+        return 1.0;
+    }
+    terms
+        .iter()
+        .map(|term| term_relevance(title, content, term))
+        .sum()
+}
+
+fn term_relevance(title: &str, content: Option<&str>, query: &str) -> f64 {
     if query.is_empty() {
         return 1.0;
     }
@@ -0,0 +1,839 @@
+//! Search query syntax.
+//!
+//! A query is parsed into a [`QueryNode`] tree, which is compiled to an FTS5
+//! `MATCH` expression where the table has a full-text index and to `LIKE`
+//! clauses with the same meaning where it doesn't:
+//!
+//! - `budget plan` matches both words, `budget OR plan` either of them
+//! - `"exact phrase"` matches the words next to each other, in order
+//! - `NOT draft` and `-draft` leave out matches
+//! - `title:budget`, `content:"q3 numbers"` and `title:(budget OR plan)`
+//!   look in a single field
+//! - `tag:work` matches notes tagged `work` or a tag nested under it, such as
+//!   `work/meetings`
+//! - parentheses group, and `AND` binds tighter than `OR`
+//!
+//! Operators are upper case; a lower-case `or` is searched for as a word.
+
+use thiserror::Error;
+
+/// Fields a query can be scoped to with `field:`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SearchField {
+    Title,
+    Content,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum QueryNode {
+    Term(String),
+    /// Words that must appear next to each other, in order
+    Phrase(String),
+    /// Matches only within one field
+    Field(SearchField, Box<QueryNode>),
+    /// Notes carrying the tag or one nested under it
+    Tag(String),
+    And(Vec<QueryNode>),
+    Or(Vec<QueryNode>),
+    Not(Box<QueryNode>),
+}
+
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum QuerySyntaxError {
+    #[error("Unclosed quote at position {position}")]
+    UnclosedQuote { position: usize },
+    #[error("Unclosed parenthesis at position {position}")]
+    UnclosedParen { position: usize },
+    #[error("Unexpected ')' at position {position}")]
+    UnexpectedParen { position: usize },
+    #[error("Empty parentheses at position {position}")]
+    EmptyGroup { position: usize },
+    #[error("{operator} at position {position} is missing a search term")]
+    MissingOperand { operator: String, position: usize },
+    #[error("{field}: at position {position} can't be used inside another field")]
+    NestedField { field: String, position: usize },
+    #[error("tag: at position {position} takes a single tag")]
+    InvalidTag { position: usize },
+}
+
+/// Parse a search query. A blank query parses to None and matches everything.
+pub fn parse_query(input: &str) -> Result<Option<QueryNode>, QuerySyntaxError> {
+    let tokens = tokenize(input)?;
+    if tokens.is_empty() {
+        return Ok(None);
+    }
+    let mut parser = Parser { tokens, pos: 0 };
+    let node = parser.parse_or(None)?;
+    match parser.advance() {
+        None => Ok(Some(node)),
+        Some((_, position)) => Err(QuerySyntaxError::UnexpectedParen { position }),
+    }
+}
+
+impl QueryNode {
+    /// Words and phrases the results should contain, for ranking and
+    /// snippets. Excluded words and tags aren't included.
+    pub fn positive_terms(&self) -> Vec<String> {
+        let mut terms = Vec::new();
+        self.collect_terms(&mut terms);
+        terms
+    }
+
+    fn collect_terms(&self, terms: &mut Vec<String>) {
+        match self {
+            QueryNode::Term(text) | QueryNode::Phrase(text) => terms.push(text.clone()),
+            QueryNode::Field(_, node) => node.collect_terms(terms),
+            QueryNode::And(nodes) | QueryNode::Or(nodes) => {
+                for node in nodes {
+                    node.collect_terms(terms);
+                }
+            }
+            QueryNode::Tag(_) | QueryNode::Not(_) => {}
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Token {
+    Word(String),
+    Phrase(String),
+    /// A `field:` prefix, lower-cased
+    Field(String),
+    LParen,
+    RParen,
+    And,
+    Or,
+    Not,
+    Minus,
+}
+
+const FIELDS: [&str; 3] = ["title", "content", "tag"];
+
+fn tokenize(input: &str) -> Result<Vec<(Token, usize)>, QuerySyntaxError> {
+    let chars: Vec<char> = input.chars().collect();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+
+    while i < chars.len() {
+        let c = chars[i];
+        if c.is_whitespace() {
+            i += 1;
+            continue;
+        }
+        match c {
+            '(' => {
+                tokens.push((Token::LParen, i));
+                i += 1;
+            }
+            ')' => {
+                tokens.push((Token::RParen, i));
+                i += 1;
+            }
+            '"' => {
+                let end = chars[i + 1..]
+                    .iter()
+                    .position(|&c| c == '"')
+                    .map(|offset| i + 1 + offset)
+                    .ok_or(QuerySyntaxError::UnclosedQuote { position: i })?;
+                tokens.push((Token::Phrase(chars[i + 1..end].iter().collect()), i));
+                i = end + 1;
+            }
+            // A lone or doubled dash is searched for as a word
+            '-' if chars
+                .get(i + 1)
+                .is_some_and(|&next| !next.is_whitespace() && next != '-' && next != ')') =>
+            {
+                tokens.push((Token::Minus, i));
+                i += 1;
+            }
+            _ => {
+                let start = i;
+                while i < chars.len() && !chars[i].is_whitespace() && !"()\"".contains(chars[i]) {
+                    i += 1;
+                }
+                let word: String = chars[start..i].iter().collect();
+                match word.as_str() {
+                    "AND" => tokens.push((Token::And, start)),
+                    "OR" => tokens.push((Token::Or, start)),
+                    "NOT" => tokens.push((Token::Not, start)),
+                    _ => match word.split_once(':') {
+                        Some((field, _)) if FIELDS.contains(&field.to_lowercase().as_str()) => {
+                            tokens.push((Token::Field(field.to_lowercase()), start));
+                            // The value is tokenized on its own, so `title:-draft` works
+                            i = start + field.chars().count() + 1;
+                        }
+                        _ => tokens.push((Token::Word(word), start)),
+                    },
+                }
+            }
+        }
+    }
+    Ok(tokens)
+}
+
+struct Parser {
+    tokens: Vec<(Token, usize)>,
+    pos: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos).map(|(token, _)| token)
+    }
+
+    fn advance(&mut self) -> Option<(Token, usize)> {
+        let token = self.tokens.get(self.pos).cloned();
+        if token.is_some() {
+            self.pos += 1;
+        }
+        token
+    }
+
+    /// Whether the next token can begin a search term
+    fn at_operand(&self) -> bool {
+        matches!(
+            self.peek(),
+            Some(
+                Token::Word(_)
+                    | Token::Phrase(_)
+                    | Token::Field(_)
+                    | Token::LParen
+                    | Token::Not
+                    | Token::Minus
+            )
+        )
+    }
+
+    fn expect_operand(&self, operator: &str, position: usize) -> Result<(), QuerySyntaxError> {
+        if self.at_operand() {
+            Ok(())
+        } else {
+            Err(QuerySyntaxError::MissingOperand {
+                operator: operator.to_string(),
+                position,
+            })
+        }
+    }
+
+    fn parse_or(&mut self, field: Option<&str>) -> Result<QueryNode, QuerySyntaxError> {
+        let mut nodes = vec![self.parse_and(field)?];
+        while let Some(Token::Or) = self.peek() {
+            let (_, position) = self.advance().expect("peeked");
+            self.expect_operand("OR", position)?;
+            nodes.push(self.parse_and(field)?);
+        }
+        Ok(combine(nodes, QueryNode::Or))
+    }
+
+    fn parse_and(&mut self, field: Option<&str>) -> Result<QueryNode, QuerySyntaxError> {
+        let mut nodes = vec![self.parse_unary(field)?];
+        loop {
+            if let Some(Token::And) = self.peek() {
+                let (_, position) = self.advance().expect("peeked");
+                self.expect_operand("AND", position)?;
+            } else if !self.at_operand() {
+                break;
+            }
+            nodes.push(self.parse_unary(field)?);
+        }
+        Ok(combine(nodes, QueryNode::And))
+    }
+
+    fn parse_unary(&mut self, field: Option<&str>) -> Result<QueryNode, QuerySyntaxError> {
+        match self.peek() {
+            Some(Token::Not) | Some(Token::Minus) => {
+                let (token, position) = self.advance().expect("peeked");
+                let operator = if token == Token::Not { "NOT" } else { "-" };
+                self.expect_operand(operator, position)?;
+                Ok(QueryNode::Not(Box::new(self.parse_unary(field)?)))
+            }
+            _ => self.parse_primary(field),
+        }
+    }
+
+    fn parse_primary(&mut self, field: Option<&str>) -> Result<QueryNode, QuerySyntaxError> {
+        let (token, position) = self.advance().expect("callers check for an operand first");
+        match token {
+            Token::Word(text) => Ok(QueryNode::Term(text)),
+            Token::Phrase(text) => Ok(QueryNode::Phrase(text)),
+            Token::LParen => {
+                match self.peek() {
+                    Some(Token::RParen) => return Err(QuerySyntaxError::EmptyGroup { position }),
+                    None => return Err(QuerySyntaxError::UnclosedParen { position }),
+                    _ => {}
+                }
+                let node = self.parse_or(field)?;
+                match self.advance() {
+                    Some((Token::RParen, _)) => Ok(node),
+                    _ => Err(QuerySyntaxError::UnclosedParen { position }),
+                }
+            }
+            Token::RParen => Err(QuerySyntaxError::UnexpectedParen { position }),
+            Token::And | Token::Or => Err(QuerySyntaxError::MissingOperand {
+                operator: if token == Token::And { "AND" } else { "OR" }.to_string(),
+                position,
+            }),
+            Token::Field(name) => {
+                if field.is_some() {
+                    return Err(QuerySyntaxError::NestedField {
+                        field: name,
+                        position,
+                    });
+                }
+                self.expect_operand(&format!("{}:", name), position)?;
+                match name.as_str() {
+                    "tag" => match self.advance() {
+                        Some((Token::Word(tag), _)) | Some((Token::Phrase(tag), _)) => {
+                            Ok(QueryNode::Tag(tag))
+                        }
+                        _ => Err(QuerySyntaxError::InvalidTag { position }),
+                    },
+                    "title" => Ok(QueryNode::Field(
+                        SearchField::Title,
+                        Box::new(self.parse_unary(Some("title"))?),
+                    )),
+                    _ => Ok(QueryNode::Field(
+                        SearchField::Content,
+                        Box::new(self.parse_unary(Some("content"))?),
+                    )),
+                }
+            }
+            Token::Not | Token::Minus => unreachable!("handled by parse_unary"),
+        }
+    }
+}
+
+fn combine(mut nodes: Vec<QueryNode>, group: fn(Vec<QueryNode>) -> QueryNode) -> QueryNode {
+    if nodes.len() == 1 {
+        nodes.remove(0)
+    } else {
+        group(nodes)
+    }
+}
+
+/// Where a query's text is looked up: an FTS5 table and the columns of the
+/// base table it indexes
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct TextSource {
+    pub fts_table: &'static str,
+    /// Base table rowid the FTS rows share
+    pub rowid: &'static str,
+    /// (FTS column, base table column)
+    pub title: Option<(&'static str, &'static str)>,
+    pub content: (&'static str, &'static str),
+    /// Note the rows belong to, for `tag:`
+    pub note_id: Option<&'static str>,
+}
+
+pub(crate) const NOTE_TEXT: TextSource = TextSource {
+    fts_table: "fts_note",
+    rowid: "n.rowid",
+    title: Some(("title", "n.title")),
+    content: ("content_md", "n.content_md"),
+    note_id: Some("n.id"),
+};
+
+pub(crate) const TASK_TEXT: TextSource = TextSource {
+    fts_table: "fts_task",
+    rowid: "t.rowid",
+    title: Some(("title", "t.title")),
+    content: ("description", "t.description"),
+    note_id: None,
+};
+
+pub(crate) const PROJECT_TEXT: TextSource = TextSource {
+    fts_table: "fts_project",
+    rowid: "p.rowid",
+    title: Some(("title", "p.title")),
+    content: ("goal_outcome", "p.goal_outcome"),
+    note_id: None,
+};
+
+/// Inbox captures are bare text without an index of their own
+pub(crate) const INBOX_TEXT: TextSource = TextSource {
+    fts_table: "fts_inbox_item",
+    rowid: "i.rowid",
+    title: None,
+    content: ("text", "i.text"),
+    note_id: None,
+};
+
+/// Attachment text; attachments have no title of their own
+pub(crate) const OCR_TEXT: TextSource = TextSource {
+    fts_table: "fts_ocr",
+    rowid: "fts_ocr.rowid",
+    title: None,
+    content: ("content", "fts_ocr.content"),
+    note_id: Some("n.id"),
+};
+
+/// A WHERE clause condition with its `?` parameters, in order
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct SqlCondition {
+    pub sql: String,
+    pub params: Vec<String>,
+}
+
+impl TextSource {
+    fn column(&self, field: SearchField) -> Option<(&'static str, &'static str)> {
+        match field {
+            SearchField::Title => self.title,
+            SearchField::Content => Some(self.content),
+        }
+    }
+
+    /// The FTS5 MATCH expression for `node`, or None if it can't be written
+    /// as one: tags aren't indexed, and FTS5 can only exclude matches from
+    /// other matches
+    pub(crate) fn fts_match(&self, node: &QueryNode) -> Option<String> {
+        self.fts_expr(node, None)
+    }
+
+    fn fts_expr(&self, node: &QueryNode, field: Option<SearchField>) -> Option<String> {
+        match node {
+            QueryNode::Term(text) | QueryNode::Phrase(text) => {
+                let quoted = format!("\"{}\"", text.replace('"', "\"\""));
+                match field {
+                    None => Some(quoted),
+                    Some(field) => self
+                        .column(field)
+                        .map(|(column, _)| format!("{} : {}", column, quoted)),
+                }
+            }
+            QueryNode::Field(field, node) => self.fts_expr(node, Some(*field)),
+            QueryNode::Tag(_) | QueryNode::Not(_) => None,
+            QueryNode::And(nodes) => {
+                let mut included = Vec::new();
+                let mut excluded = Vec::new();
+                for node in nodes {
+                    match node {
+                        QueryNode::Not(inner) => excluded.push(self.fts_expr(inner, field)?),
+                        _ => included.push(self.fts_expr(node, field)?),
+                    }
+                }
+                let mut expr = match included.len() {
+                    0 => return None,
+                    1 => included.remove(0),
+                    _ => included
+                        .iter()
+                        .map(|e| format!("({})", e))
+                        .collect::<Vec<_>>()
+                        .join(" AND "),
+                };
+                for exclude in excluded {
+                    expr = format!("({}) NOT ({})", expr, exclude);
+                }
+                Some(expr)
+            }
+            QueryNode::Or(nodes) => {
+                let parts = nodes
+                    .iter()
+                    .map(|node| self.fts_expr(node, field).map(|e| format!("({})", e)))
+                    .collect::<Option<Vec<_>>>()?;
+                Some(parts.join(" OR "))
+            }
+        }
+    }
+
+    /// Compile `node` to a condition on the base table, matching through the
+    /// FTS index if `use_fts` and with LIKE otherwise
+    pub(crate) fn condition(&self, node: &QueryNode, use_fts: bool) -> SqlCondition {
+        let mut params = Vec::new();
+        let sql = self.condition_sql(node, None, use_fts, &mut params);
+        SqlCondition { sql, params }
+    }
+
+    fn condition_sql(
+        &self,
+        node: &QueryNode,
+        field: Option<SearchField>,
+        use_fts: bool,
+        params: &mut Vec<String>,
+    ) -> String {
+        if use_fts {
+            if let Some(expr) = self.fts_expr(node, field) {
+                params.push(expr);
+                return format!(
+                    "{} IN (SELECT rowid FROM {} WHERE {} MATCH ?)",
+                    self.rowid, self.fts_table, self.fts_table
+                );
+            }
+        }
+        match node {
+            QueryNode::Term(text) | QueryNode::Phrase(text) => {
+                let columns: Vec<&str> = match field {
+                    Some(field) => self.column(field).into_iter().map(|(_, c)| c).collect(),
+                    None => self
+                        .title
+                        .into_iter()
+                        .chain(Some(self.content))
+                        .map(|(_, c)| c)
+                        .collect(),
+                };
+                // Only reached with FTS for a field the source doesn't have
+                if use_fts || columns.is_empty() {
+                    return "0".to_string();
+                }
+                let pattern = like_pattern(text);
+                let clauses = columns
+                    .iter()
+                    .map(|column| {
+                        params.push(pattern.clone());
+                        format!("COALESCE({}, '') LIKE ? ESCAPE '\\'", column)
+                    })
+                    .collect::<Vec<_>>();
+                format!("({})", clauses.join(" OR "))
+            }
+            QueryNode::Field(field, node) => {
+                self.condition_sql(node, Some(*field), use_fts, params)
+            }
+            QueryNode::Tag(tag) => {
+                let Some(note_id) = self.note_id else {
+                    return "0".to_string();
+                };
+                let prefix = format!("{}{}", tag, crate::tag::TAG_SEPARATOR);
+                params.push(tag.clone());
+                params.push(prefix.clone());
+                params.push(prefix);
+                format!(
+                    "EXISTS (SELECT 1 FROM note_tags nt JOIN tag tg ON nt.tag_id = tg.id
+                     WHERE nt.note_id = {} AND (tg.name = ? OR substr(tg.name, 1, length(?)) = ?))",
+                    note_id
+                )
+            }
+            QueryNode::Not(node) => {
+                format!("NOT ({})", self.condition_sql(node, field, use_fts, params))
+            }
+            QueryNode::And(nodes) | QueryNode::Or(nodes) => {
+                let joiner = if matches!(node, QueryNode::And(_)) {
+                    " AND "
+                } else {
+                    " OR "
+                };
+                // Every part is parenthesized or binds tighter than AND/OR
+                let parts = nodes
+                    .iter()
+                    .map(|node| self.condition_sql(node, field, use_fts, params))
+                    .collect::<Vec<_>>();
+                format!("({})", parts.join(joiner))
+            }
+        }
+    }
+}
+
+pub(crate) fn like_pattern(text: &str) -> String {
+    let escaped = text
+        .replace('\\', "\\\\")
+        .replace('%', "\\%")
+        .replace('_', "\\_");
+    format!("%{}%", escaped)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(input: &str) -> QueryNode {
+        parse_query(input).unwrap().unwrap()
+    }
+
+    fn term(text: &str) -> QueryNode {
+        QueryNode::Term(text.to_string())
+    }
+
+    fn phrase(text: &str) -> QueryNode {
+        QueryNode::Phrase(text.to_string())
+    }
+
+    fn not(node: QueryNode) -> QueryNode {
+        QueryNode::Not(Box::new(node))
+    }
+
+    fn field(field: SearchField, node: QueryNode) -> QueryNode {
+        QueryNode::Field(field, Box::new(node))
+    }
+
+    #[test]
+    fn test_blank_query_matches_everything() {
+        assert_eq!(parse_query("").unwrap(), None);
+        assert_eq!(parse_query("   \t").unwrap(), None);
+    }
+
+    #[test]
+    fn test_words_are_anded() {
+        assert_eq!(parse("budget"), term("budget"));
+        assert_eq!(
+            parse("budget plan"),
+            QueryNode::And(vec![term("budget"), term("plan")])
+        );
+        assert_eq!(parse("budget AND plan"), parse("budget plan"));
+    }
+
+    #[test]
+    fn test_and_binds_tighter_than_or() {
+        assert_eq!(
+            parse("a b OR c"),
+            QueryNode::Or(vec![QueryNode::And(vec![term("a"), term("b")]), term("c")])
+        );
+        assert_eq!(
+            parse("a (b OR c)"),
+            QueryNode::And(vec![term("a"), QueryNode::Or(vec![term("b"), term("c")])])
+        );
+        assert_eq!(
+            parse("a OR b OR c"),
+            QueryNode::Or(vec![term("a"), term("b"), term("c")])
+        );
+    }
+
+    #[test]
+    fn test_lower_case_operators_are_words() {
+        assert_eq!(
+            parse("salt and pepper"),
+            QueryNode::And(vec![term("salt"), term("and"), term("pepper")])
+        );
+    }
+
+    #[test]
+    fn test_phrases() {
+        assert_eq!(parse("\"q3 budget\""), phrase("q3 budget"));
+        assert_eq!(
+            parse("plan \"q3 budget\"draft"),
+            QueryNode::And(vec![term("plan"), phrase("q3 budget"), term("draft")])
+        );
+        // Operators inside quotes are plain words
+        assert_eq!(parse("\"this OR that\""), phrase("this OR that"));
+    }
+
+    #[test]
+    fn test_negation() {
+        assert_eq!(parse("NOT draft"), not(term("draft")));
+        assert_eq!(
+            parse("budget -draft"),
+            QueryNode::And(vec![term("budget"), not(term("draft"))])
+        );
+        assert_eq!(parse("-\"old plan\""), not(phrase("old plan")));
+        assert_eq!(parse("NOT NOT a"), not(not(term("a"))));
+        assert_eq!(
+            parse("-(a OR b)"),
+            not(QueryNode::Or(vec![term("a"), term("b")]))
+        );
+    }
+
+    #[test]
+    fn test_dashes_inside_and_alone_are_words() {
+        assert_eq!(parse("follow-up"), term("follow-up"));
+        assert_eq!(
+            parse("a - b"),
+            QueryNode::And(vec![term("a"), term("-"), term("b")])
+        );
+        assert_eq!(parse("--"), term("--"));
+    }
+
+    #[test]
+    fn test_field_scopes() {
+        assert_eq!(
+            parse("title:budget"),
+            field(SearchField::Title, term("budget"))
+        );
+        assert_eq!(
+            parse("Content:\"q3 numbers\""),
+            field(SearchField::Content, phrase("q3 numbers"))
+        );
+        assert_eq!(
+            parse("title:(budget OR plan)"),
+            field(
+                SearchField::Title,
+                QueryNode::Or(vec![term("budget"), term("plan")])
+            )
+        );
+        assert_eq!(
+            parse("title:-draft"),
+            field(SearchField::Title, not(term("draft")))
+        );
+        assert_eq!(
+            parse("-title:draft"),
+            not(field(SearchField::Title, term("draft")))
+        );
+        // Unknown prefixes are ordinary words
+        assert_eq!(parse("https://example.com"), term("https://example.com"));
+    }
+
+    #[test]
+    fn test_tags() {
+        assert_eq!(parse("tag:work"), QueryNode::Tag("work".to_string()));
+        assert_eq!(
+            parse("tag:\"big project\" -tag:done"),
+            QueryNode::And(vec![
+                QueryNode::Tag("big project".to_string()),
+                not(QueryNode::Tag("done".to_string())),
+            ])
+        );
+    }
+
+    #[test]
+    fn test_unbalanced_quotes_and_parens() {
+        assert_eq!(
+            parse_query("plan \"q3 budget"),
+            Err(QuerySyntaxError::UnclosedQuote { position: 5 })
+        );
+        assert_eq!(
+            parse_query("(a OR b"),
+            Err(QuerySyntaxError::UnclosedParen { position: 0 })
+        );
+        assert_eq!(
+            parse_query("a (b (c)"),
+            Err(QuerySyntaxError::UnclosedParen { position: 2 })
+        );
+        assert_eq!(
+            parse_query("a OR b)"),
+            Err(QuerySyntaxError::UnexpectedParen { position: 6 })
+        );
+        assert_eq!(
+            parse_query(")"),
+            Err(QuerySyntaxError::UnexpectedParen { position: 0 })
+        );
+        assert_eq!(
+            parse_query("a () b"),
+            Err(QuerySyntaxError::EmptyGroup { position: 2 })
+        );
+    }
+
+    #[test]
+    fn test_missing_operands() {
+        for (input, operator, position) in [
+            ("budget OR", "OR", 7),
+            ("OR budget", "OR", 0),
+            ("budget AND", "AND", 7),
+            ("a AND OR b", "AND", 2),
+            ("NOT", "NOT", 0),
+            ("a NOT", "NOT", 2),
+            ("title:", "title:", 0),
+            ("(a NOT)", "NOT", 3),
+        ] {
+            assert_eq!(
+                parse_query(input),
+                Err(QuerySyntaxError::MissingOperand {
+                    operator: operator.to_string(),
+                    position,
+                }),
+                "{}",
+                input
+            );
+        }
+    }
+
+    #[test]
+    fn test_invalid_field_values() {
+        assert_eq!(
+            parse_query("title:(a content:b)"),
+            Err(QuerySyntaxError::NestedField {
+                field: "content".to_string(),
+                position: 9,
+            })
+        );
+        assert_eq!(
+            parse_query("tag:(a OR b)"),
+            Err(QuerySyntaxError::InvalidTag { position: 0 })
+        );
+        assert!(parse_query("tag:(a OR b)")
+            .unwrap_err()
+            .to_string()
+            .contains("single tag"));
+    }
+
+    #[test]
+    fn test_positive_terms_skip_exclusions_and_tags() {
+        assert_eq!(
+            parse("title:budget \"q3 plan\" -draft tag:work").positive_terms(),
+            vec!["budget".to_string(), "q3 plan".to_string()]
+        );
+        assert!(parse("NOT draft").positive_terms().is_empty());
+    }
+
+    #[test]
+    fn test_fts_expressions() {
+        let fts = |input: &str| NOTE_TEXT.fts_match(&parse(input));
+        assert_eq!(fts("budget").as_deref(), Some("\"budget\""));
+        assert_eq!(
+            fts("budget plan").as_deref(),
+            Some("(\"budget\") AND (\"plan\")")
+        );
+        assert_eq!(
+            fts("budget OR \"q3 plan\"").as_deref(),
+            Some("(\"budget\") OR (\"q3 plan\")")
+        );
+        assert_eq!(
+            fts("budget -draft -old").as_deref(),
+            Some("((\"budget\") NOT (\"draft\")) NOT (\"old\")")
+        );
+        assert_eq!(
+            fts("title:(budget OR plan) content:q3").as_deref(),
+            Some("((title : \"budget\") OR (title : \"plan\")) AND (content_md : \"q3\")")
+        );
+        // Words are always quoted, so FTS5 syntax in them is searched for
+        assert_eq!(
+            fts("Note'; DROP*").as_deref(),
+            Some("(\"Note';\") AND (\"DROP*\")")
+        );
+        assert_eq!(
+            NOTE_TEXT
+                .fts_match(&QueryNode::Term("say\"hi".to_string()))
+                .as_deref(),
+            Some("\"say\"\"hi\"")
+        );
+    }
+
+    #[test]
+    fn test_fts_falls_back_to_sql_where_it_must() {
+        // Standalone negation, negation under OR, and tags
+        for input in ["NOT draft", "budget OR -draft", "budget tag:work"] {
+            assert_eq!(NOTE_TEXT.fts_match(&parse(input)), None, "{}", input);
+        }
+
+        let condition = NOTE_TEXT.condition(&parse("NOT draft"), true);
+        assert_eq!(
+            condition.sql,
+            "NOT (n.rowid IN (SELECT rowid FROM fts_note WHERE fts_note MATCH ?))"
+        );
+        assert_eq!(condition.params, vec!["\"draft\"".to_string()]);
+
+        let condition = NOTE_TEXT.condition(&parse("budget tag:work"), true);
+        assert!(condition.sql.starts_with(
+            "(n.rowid IN (SELECT rowid FROM fts_note WHERE fts_note MATCH ?) AND EXISTS"
+        ));
+        assert_eq!(
+            condition.params,
+            vec![
+                "\"budget\"".to_string(),
+                "work".to_string(),
+                "work/".to_string(),
+                "work/".to_string(),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_like_conditions() {
+        let condition = NOTE_TEXT.condition(&parse("title:\"50%\" -draft"), false);
+        assert_eq!(
+            condition.sql,
+            "((COALESCE(n.title, '') LIKE ? ESCAPE '\\') AND NOT ((COALESCE(n.title, '') LIKE ? ESCAPE '\\' OR COALESCE(n.content_md, '') LIKE ? ESCAPE '\\')))"
+        );
+        assert_eq!(
+            condition.params,
+            vec![
+                "%50\\%%".to_string(),
+                "%draft%".to_string(),
+                "%draft%".to_string(),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_fields_a_source_lacks_match_nothing() {
+        let condition = OCR_TEXT.condition(&parse("title:budget"), true);
+        assert_eq!(condition.sql, "0");
+        assert!(condition.params.is_empty());
+        assert_eq!(TASK_TEXT.condition(&parse("tag:work"), false).sql, "0");
+    }
+}
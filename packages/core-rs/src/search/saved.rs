@@ -1,4 +1,8 @@
 use crate::db::DbError;
+use crate::ids::parse_entity_id;
+use crate::search::{
+    search_all, EntityType, SearchFilters, SearchQuery, SearchResult, SortOptions,
+};
 use rusqlite::{Connection, OptionalExtension, Result};
 use serde::{Deserialize, Serialize};
 use ulid::Ulid;
@@ -17,6 +21,9 @@ pub fn create_saved_search(
     query: &str,
     space_id: Option<&str>,
 ) -> Result<SavedSearch, DbError> {
+    if let Some(space_id) = space_id {
+        parse_entity_id("space", "space_id", space_id)?;
+    }
     let id = Ulid::new().to_string();
     conn.execute(
         "INSERT INTO saved_search (id, space_id, title, query_string, scope) VALUES (?1, ?2, ?3, ?4, 'note')",
@@ -54,6 +61,7 @@ pub fn get_saved_searches(
     let mut params: Vec<Box<dyn rusqlite::ToSql>> = Vec::new();
 
     if let Some(sid) = space_id {
+        parse_entity_id("space", "space_id", sid)?;
         sql.push_str(" WHERE space_id = ?1");
         params.push(Box::new(sid.to_string()));
     }
@@ -92,6 +100,31 @@ pub fn update_saved_search(
     ))
 }
 
+/// Run a saved search over notes, within its space when it has one
+pub fn execute_saved_search(conn: &Connection, id: Ulid) -> Result<Vec<SearchResult>, DbError> {
+    let search = get_saved_search(conn, id)?.ok_or_else(|| DbError::NotFound {
+        entity: "saved_search",
+        id: id.to_string(),
+    })?;
+    let space_id = search
+        .space_id
+        .as_deref()
+        .map(|space_id| parse_entity_id("space", "space_id", space_id))
+        .transpose()?;
+    let query = SearchQuery {
+        query: search.query,
+        entity_types: vec![EntityType::Note],
+        filters: SearchFilters {
+            space_id,
+            ..Default::default()
+        },
+        sort: SortOptions::default(),
+        limit: Some(50),
+        offset: None,
+    };
+    search_all(conn, &query)
+}
+
 pub fn delete_saved_search(conn: &Connection, id: Ulid) -> Result<(), DbError> {
     conn.execute("DELETE FROM saved_search WHERE id = ?1", [id.to_string()])?;
     Ok(())
@@ -22,6 +22,10 @@ pub enum SocialError {
     InvalidInput(String),
     #[error("Not found: {0}")]
     NotFound(String),
+    #[error("Note error: {0}")]
+    Note(#[from] crate::db::DbError),
+    #[error("Embedding error: {0}")]
+    Embedding(String),
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
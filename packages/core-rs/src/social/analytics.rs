@@ -1,7 +1,11 @@
+use chrono::{DateTime, Datelike, Duration, NaiveDate};
 use rusqlite::{params, Connection};
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 
 use super::account::SocialError;
+use super::timeline::{push_timeline_filters, TimelineFilters};
+use crate::calendar::TimeRange;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct PlatformStats {
@@ -12,10 +16,14 @@ pub struct PlatformStats {
     pub total_views: i64,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct TimeSeriesPoint {
     pub date: String, // YYYY-MM-DD format
+    /// Posts in the bucket
     pub count: i64,
+    pub likes: i64,
+    pub comments: i64,
+    pub shares: i64,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -156,7 +164,10 @@ fn get_time_series(
 
     let mut stmt = conn.prepare(
         "SELECT DATE(p.timestamp / 1000, 'unixepoch') as date,
-                COUNT(*) as count
+                COUNT(*) as count,
+                COALESCE(SUM(p.likes), 0),
+                COALESCE(SUM(p.comments), 0),
+                COALESCE(SUM(p.shares), 0)
          FROM social_post p
          JOIN social_account a ON p.account_id = a.id
          WHERE a.space_id = ?1 AND p.timestamp >= ?2
@@ -168,6 +179,9 @@ fn get_time_series(
         Ok(TimeSeriesPoint {
             date: row.get(0)?,
             count: row.get(1)?,
+            likes: row.get(2)?,
+            comments: row.get(3)?,
+            shares: row.get(4)?,
         })
     })?;
 
@@ -346,3 +360,243 @@ fn get_top_posts(
 
     Ok(result)
 }
+
+/// Buckets with fewer posts than this are marked low-confidence in the
+/// posting time analysis
+pub const MIN_POSTING_TIME_SAMPLES: i64 = 5;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TimeBucket {
+    Day,
+    /// Weeks start on Monday
+    Week,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct EngagementOptions {
+    /// Offset of the user's local time from UTC; buckets are local
+    #[serde(default)]
+    pub utc_offset_minutes: i32,
+    /// Only posts in any of these categories; empty for all posts
+    #[serde(default)]
+    pub categories: Vec<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PostingTimeCell {
+    /// 0 = Monday
+    pub weekday: u8,
+    pub hour: u8,
+    pub post_count: i64,
+    /// Mean likes + comments + shares per post
+    pub avg_engagement: f64,
+    /// Fewer than `min_samples` posts, so the average says little
+    pub low_confidence: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PostingTimeAnalysis {
+    /// All 7 x 24 buckets, Monday 00:00 first
+    pub cells: Vec<PostingTimeCell>,
+    pub min_samples: i64,
+    /// The confident bucket with the highest average, if any
+    pub best: Option<PostingTimeCell>,
+}
+
+/// Posts of a space's accounts in `range`, optionally on one platform and in
+/// the options' categories. Parameters are the space id, the range in
+/// milliseconds (post timestamps are), then the filters'.
+fn engagement_posts_sql(
+    platform: Option<&str>,
+    range: TimeRange,
+    options: &EngagementOptions,
+    space_id: &str,
+) -> (String, Vec<Box<dyn rusqlite::ToSql>>) {
+    let mut sql = String::from(
+        "SELECT p.timestamp, COALESCE(p.likes, 0) AS likes,
+                COALESCE(p.comments, 0) AS comments, COALESCE(p.shares, 0) AS shares
+         FROM social_post p
+         JOIN social_account a ON p.account_id = a.id
+         WHERE a.space_id = ?1 AND p.timestamp >= ?2 AND p.timestamp < ?3",
+    );
+    let mut params: Vec<Box<dyn rusqlite::ToSql>> = vec![
+        Box::new(space_id.to_string()),
+        Box::new(range.start * 1000),
+        Box::new(range.end * 1000),
+    ];
+    let filters = TimelineFilters {
+        platforms: platform.map(|p| vec![p.to_string()]),
+        categories: Some(options.categories.clone()),
+        ..Default::default()
+    };
+    push_timeline_filters(&mut sql, &mut params, &filters);
+    (sql, params)
+}
+
+fn validate_engagement_query(
+    range: TimeRange,
+    options: &EngagementOptions,
+) -> Result<(), SocialError> {
+    if range.end <= range.start {
+        return Err(SocialError::InvalidInput(
+            "Range must end after it starts".into(),
+        ));
+    }
+    if options.utc_offset_minutes.abs() > 14 * 60 {
+        return Err(SocialError::InvalidInput(format!(
+            "Invalid UTC offset: {} minutes",
+            options.utc_offset_minutes
+        )));
+    }
+    Ok(())
+}
+
+/// Likes, comments and shares per local day or week, for engagement charts.
+/// Every bucket touched by `range` is included, with zeros when nothing was
+/// posted; weekly points are dated by their Monday.
+pub fn get_engagement_timeseries(
+    conn: &Connection,
+    space_id: &str,
+    platform: Option<&str>,
+    range: TimeRange,
+    bucket: TimeBucket,
+    options: &EngagementOptions,
+) -> Result<Vec<TimeSeriesPoint>, SocialError> {
+    validate_engagement_query(range, options)?;
+    let offset_secs = options.utc_offset_minutes as i64 * 60;
+    let day = format!("p.timestamp / 1000 + {}, 'unixepoch'", offset_secs);
+    let bucket_sql = match bucket {
+        TimeBucket::Day => format!("DATE({})", day),
+        TimeBucket::Week => format!("DATE({}, 'weekday 0', '-6 days')", day),
+    };
+    let (posts_sql, params) = engagement_posts_sql(platform, range, options, space_id);
+    let mut stmt = conn.prepare(&format!(
+        "SELECT {bucket} AS bucket, COUNT(*), SUM(likes), SUM(comments), SUM(shares)
+         FROM ({posts}) p
+         GROUP BY bucket",
+        bucket = bucket_sql,
+        posts = posts_sql
+    ))?;
+    let rows = stmt.query_map(
+        rusqlite::params_from_iter(params.iter().map(|b| b.as_ref())),
+        |row| {
+            Ok(TimeSeriesPoint {
+                date: row.get(0)?,
+                count: row.get(1)?,
+                likes: row.get(2)?,
+                comments: row.get(3)?,
+                shares: row.get(4)?,
+            })
+        },
+    )?;
+    let mut by_bucket = HashMap::new();
+    for row in rows {
+        let point = row?;
+        by_bucket.insert(point.date.clone(), point);
+    }
+
+    let bucket_start = |ts: i64| {
+        let date = DateTime::from_timestamp(ts + offset_secs, 0)
+            .unwrap_or_default()
+            .date_naive();
+        match bucket {
+            TimeBucket::Day => date,
+            TimeBucket::Week => date - Duration::days(date.weekday().num_days_from_monday() as i64),
+        }
+    };
+    let step = match bucket {
+        TimeBucket::Day => Duration::days(1),
+        TimeBucket::Week => Duration::weeks(1),
+    };
+    let mut series = Vec::new();
+    let mut date: NaiveDate = bucket_start(range.start);
+    let last = bucket_start(range.end - 1);
+    while date <= last {
+        let key = date.format("%Y-%m-%d").to_string();
+        series.push(by_bucket.remove(&key).unwrap_or(TimeSeriesPoint {
+            date: key,
+            count: 0,
+            likes: 0,
+            comments: 0,
+            shares: 0,
+        }));
+        date += step;
+    }
+
+    log::debug!(
+        "[Social::Analytics] Engagement series for space {}: {} {:?} buckets",
+        space_id,
+        series.len(),
+        bucket
+    );
+    Ok(series)
+}
+
+/// Average engagement of posts by the local weekday and hour they were
+/// posted, to show when posts do best. Buckets with fewer than
+/// [`MIN_POSTING_TIME_SAMPLES`] posts are marked low-confidence and never
+/// picked as the best time.
+pub fn get_posting_time_analysis(
+    conn: &Connection,
+    space_id: &str,
+    platform: Option<&str>,
+    range: TimeRange,
+    options: &EngagementOptions,
+) -> Result<PostingTimeAnalysis, SocialError> {
+    validate_engagement_query(range, options)?;
+    let offset_secs = options.utc_offset_minutes as i64 * 60;
+    let local = format!("p.timestamp / 1000 + {}, 'unixepoch'", offset_secs);
+    let (posts_sql, params) = engagement_posts_sql(platform, range, options, space_id);
+    let mut stmt = conn.prepare(&format!(
+        "SELECT (CAST(strftime('%w', {local}) AS INTEGER) + 6) % 7 AS weekday,
+                CAST(strftime('%H', {local}) AS INTEGER) AS hour,
+                COUNT(*),
+                AVG(likes + comments + shares)
+         FROM ({posts}) p
+         GROUP BY weekday, hour",
+        local = local,
+        posts = posts_sql
+    ))?;
+    let rows = stmt.query_map(
+        rusqlite::params_from_iter(params.iter().map(|b| b.as_ref())),
+        |row| {
+            Ok((
+                row.get::<_, i64>(0)?,
+                row.get::<_, i64>(1)?,
+                row.get::<_, i64>(2)?,
+                row.get::<_, f64>(3)?,
+            ))
+        },
+    )?;
+
+    let mut cells: Vec<PostingTimeCell> = (0..7u8)
+        .flat_map(|weekday| {
+            (0..24u8).map(move |hour| PostingTimeCell {
+                weekday,
+                hour,
+                post_count: 0,
+                avg_engagement: 0.0,
+                low_confidence: true,
+            })
+        })
+        .collect();
+    for row in rows {
+        let (weekday, hour, post_count, avg_engagement) = row?;
+        let cell = &mut cells[(weekday * 24 + hour) as usize];
+        cell.post_count = post_count;
+        cell.avg_engagement = avg_engagement;
+        cell.low_confidence = post_count < MIN_POSTING_TIME_SAMPLES;
+    }
+
+    let best = cells
+        .iter()
+        .filter(|cell| !cell.low_confidence)
+        .max_by(|a, b| a.avg_engagement.total_cmp(&b.avg_engagement))
+        .cloned();
+    Ok(PostingTimeAnalysis {
+        cells,
+        min_samples: MIN_POSTING_TIME_SAMPLES,
+        best,
+    })
+}
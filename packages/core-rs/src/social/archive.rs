@@ -0,0 +1,331 @@
+//! Social Post Archival
+//!
+//! Keeps a post worth holding on to as a regular note: front matter with the
+//! author, platform, link and an engagement snapshot, then the post text.
+//! Media the post holds in blob storage is attached to the note, so it
+//! outlives the post. Archived posts are linked to their note in
+//! `social_post_note`, which keeps them out of pruning and makes archiving
+//! the same post again refresh the note instead of creating another one.
+
+use chrono::{DateTime, Utc};
+use rusqlite::{params, Connection, OptionalExtension};
+use serde::{Deserialize, Serialize};
+use ulid::Ulid;
+
+use super::account::SocialError;
+use super::category::get_category;
+use super::post::{Engagement, SocialPost};
+use super::webview::get_platform_display_name;
+use crate::blob::{attach_blob_to_note, BlobError};
+use crate::note::{create_note, get_note, update_note_content, DbUlid, Note};
+use crate::tag::normalize_tag_name;
+
+/// Tag given to archived posts unless configured otherwise
+pub const DEFAULT_ARCHIVE_TAG: &str = "social-archive";
+
+/// Longest post excerpt used in a note title, in characters
+const MAX_TITLE_EXCERPT_CHARS: usize = 60;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ArchiveOptions {
+    /// Tag for the note, with or without a leading `#`
+    pub tag: String,
+}
+
+impl Default for ArchiveOptions {
+    fn default() -> Self {
+        Self {
+            tag: DEFAULT_ARCHIVE_TAG.to_string(),
+        }
+    }
+}
+
+/// Archive a post as a note in `space_id`, or refresh the note it was
+/// archived to before. A refreshed note keeps its space.
+pub fn archive_post_to_note(
+    conn: &mut Connection,
+    post_id: &str,
+    space_id: &str,
+    options: &ArchiveOptions,
+) -> Result<Note, SocialError> {
+    let tag =
+        normalize_tag_name(&options.tag).map_err(|e| SocialError::InvalidInput(e.to_string()))?;
+    let post = load_post(conn, post_id)?
+        .ok_or_else(|| SocialError::NotFound(format!("Post {}", post_id)))?;
+    let now = Utc::now();
+    let title = note_title(&post);
+    let body = note_body(&post, &tag, now);
+
+    // Refreshing goes through the note's own update path; a new note is
+    // created together with its link
+    let refreshed = match get_archived_note(conn, post_id)? {
+        Some(note) => {
+            update_note_content(conn, note.id.clone(), &title, &body)?;
+            Some(note.id)
+        }
+        None => None,
+    };
+
+    let tx = conn.transaction()?;
+    let note_id = match &refreshed {
+        Some(id) => id.clone(),
+        None => create_note(&tx, space_id, &title, &body)?.id,
+    };
+    let note_key = note_id.0.to_string();
+    let note = get_note(&tx, note_id)?
+        .ok_or_else(|| SocialError::NotFound(format!("Note {}", note_key)))?;
+    attach_post_media(&tx, post_id, &note_key)?;
+    tag_note(&tx, &note.space_id, &note_key, &tag)?;
+    tx.execute(
+        "INSERT INTO social_post_note (post_id, note_id, archived_at, updated_at)
+         VALUES (?1, ?2, ?3, ?3)
+         ON CONFLICT(post_id) DO UPDATE SET note_id = excluded.note_id,
+             updated_at = excluded.updated_at",
+        params![post_id, &note_key, now.timestamp()],
+    )?;
+    tx.commit()?;
+
+    log::info!(
+        "[Social::Archive] {} post {} as note {}",
+        if refreshed.is_some() {
+            "Refreshed"
+        } else {
+            "Archived"
+        },
+        post_id,
+        note_key
+    );
+    Ok(note)
+}
+
+/// Archive every post in a category to notes in the category's space,
+/// returning the notes in the order the posts were published
+pub fn archive_category_to_notes(
+    conn: &mut Connection,
+    category_id: &str,
+    options: &ArchiveOptions,
+) -> Result<Vec<Note>, SocialError> {
+    let category = get_category(conn, category_id)?
+        .ok_or_else(|| SocialError::NotFound(format!("Category {}", category_id)))?;
+    let post_ids: Vec<String> = {
+        let mut stmt = conn.prepare(
+            "SELECT p.id FROM social_post p
+             JOIN social_post_category pc ON pc.post_id = p.id
+             WHERE pc.category_id = ?1
+             ORDER BY p.timestamp, p.id",
+        )?;
+        let rows = stmt.query_map([category_id], |row| row.get(0))?;
+        rows.collect::<Result<_, _>>()?
+    };
+
+    let mut notes = Vec::with_capacity(post_ids.len());
+    for post_id in &post_ids {
+        notes.push(archive_post_to_note(
+            conn,
+            post_id,
+            &category.space_id,
+            options,
+        )?);
+    }
+    log::info!(
+        "[Social::Archive] Archived {} posts from category {}",
+        notes.len(),
+        category.name
+    );
+    Ok(notes)
+}
+
+/// The note a post was archived to, if it still exists
+pub fn get_archived_note(conn: &Connection, post_id: &str) -> Result<Option<Note>, SocialError> {
+    let note_id: Option<String> = conn
+        .query_row(
+            "SELECT note_id FROM social_post_note WHERE post_id = ?1",
+            [post_id],
+            |row| row.get(0),
+        )
+        .optional()?;
+    let Some(note_id) = note_id else {
+        return Ok(None);
+    };
+    let ulid = Ulid::from_string(&note_id)
+        .map_err(|e| SocialError::InvalidInput(format!("Invalid note ID: {}", e)))?;
+    Ok(get_note(conn, DbUlid(ulid))?)
+}
+
+fn load_post(conn: &Connection, post_id: &str) -> Result<Option<SocialPost>, SocialError> {
+    let post = conn
+        .query_row(
+            "SELECT id, account_id, platform, platform_post_id,
+                author, author_handle, content, content_html,
+                media_urls_json, timestamp, fetched_at,
+                likes, shares, comments, views,
+                post_type, reply_to
+             FROM social_post
+             WHERE id = ?1",
+            [post_id],
+            |row| {
+                let media_json: Option<String> = row.get(8)?;
+                let media_urls = media_json
+                    .and_then(|s| serde_json::from_str(&s).ok())
+                    .unwrap_or_default();
+                Ok(SocialPost {
+                    id: row.get(0)?,
+                    account_id: row.get(1)?,
+                    platform: row.get(2)?,
+                    platform_post_id: row.get(3)?,
+                    author: row.get(4)?,
+                    author_handle: row.get(5)?,
+                    content: row.get(6)?,
+                    content_html: row.get(7)?,
+                    media_urls,
+                    timestamp: row.get(9)?,
+                    fetched_at: row.get(10)?,
+                    engagement: Engagement {
+                        likes: row.get(11)?,
+                        shares: row.get(12)?,
+                        comments: row.get(13)?,
+                        views: row.get(14)?,
+                    },
+                    post_type: row.get(15)?,
+                    reply_to: row.get(16)?,
+                })
+            },
+        )
+        .optional()?;
+    Ok(post)
+}
+
+/// Attach the blobs referenced by the post; the note's own references keep
+/// them alive once the post is gone
+fn attach_post_media(conn: &Connection, post_id: &str, note_id: &str) -> Result<(), SocialError> {
+    let blob_ids: Vec<String> = {
+        let mut stmt = conn.prepare(
+            "SELECT blob_id FROM blob_ref
+             WHERE owner_type = 'social_post' AND owner_id = ?1
+               AND blob_id NOT IN (SELECT blob_id FROM note_attachment WHERE note_id = ?2)
+             ORDER BY created_at, blob_id",
+        )?;
+        let rows = stmt.query_map([post_id, note_id], |row| row.get(0))?;
+        rows.collect::<Result<_, _>>()?
+    };
+    for blob_id in &blob_ids {
+        attach_blob_to_note(conn, note_id, blob_id, None).map_err(|e| match e {
+            BlobError::Database(e) => SocialError::Database(e),
+            other => SocialError::Platform(other.to_string()),
+        })?;
+    }
+    Ok(())
+}
+
+fn tag_note(
+    conn: &Connection,
+    space_id: &str,
+    note_id: &str,
+    tag: &str,
+) -> Result<(), SocialError> {
+    let existing: Option<String> = conn
+        .query_row(
+            "SELECT id FROM tag WHERE space_id = ?1 AND name = ?2",
+            [space_id, tag],
+            |row| row.get(0),
+        )
+        .optional()?;
+    let tag_id = match existing {
+        Some(id) => id,
+        None => crate::tag::create_tag(conn, space_id, tag, None)?
+            .id
+            .to_string(),
+    };
+    conn.execute(
+        "INSERT OR IGNORE INTO note_tags (note_id, tag_id) VALUES (?1, ?2)",
+        [note_id, &tag_id],
+    )?;
+    Ok(())
+}
+
+fn note_title(post: &SocialPost) -> String {
+    let platform = get_platform_display_name(&post.platform);
+    let excerpt: String = post
+        .content
+        .as_deref()
+        .and_then(|c| c.lines().map(str::trim).find(|l| !l.is_empty()))
+        .map(|line| line.chars().take(MAX_TITLE_EXCERPT_CHARS).collect())
+        .unwrap_or_default();
+    if excerpt.is_empty() {
+        format!("{} on {}", post.author, platform)
+    } else {
+        format!("{} on {}: {}", post.author, platform, excerpt)
+    }
+}
+
+/// Front matter followed by the post text and its media links
+fn note_body(post: &SocialPost, tag: &str, archived_at: DateTime<Utc>) -> String {
+    let mut fields: Vec<(&str, String)> = vec![
+        ("source", yaml_string("social")),
+        ("platform", yaml_string(&post.platform)),
+        ("author", yaml_string(&post.author)),
+    ];
+    if let Some(handle) = &post.author_handle {
+        fields.push(("author_handle", yaml_string(handle)));
+    }
+    if let Some(url) = post_url(post) {
+        fields.push(("url", yaml_string(&url)));
+    }
+    fields.push(("post_id", yaml_string(&post.id)));
+    if let Some(posted_at) = DateTime::from_timestamp_millis(post.timestamp) {
+        fields.push(("posted_at", yaml_string(&posted_at.to_rfc3339())));
+    }
+    fields.push(("archived_at", yaml_string(&archived_at.to_rfc3339())));
+    for (name, count) in [
+        ("likes", post.engagement.likes),
+        ("shares", post.engagement.shares),
+        ("comments", post.engagement.comments),
+        ("views", post.engagement.views),
+    ] {
+        if let Some(count) = count {
+            fields.push((name, count.to_string()));
+        }
+    }
+    fields.push(("tags", format!("[{}]", yaml_string(tag))));
+
+    let mut body = String::from("---\n");
+    for (name, value) in fields {
+        body.push_str(&format!("{}: {}\n", name, value));
+    }
+    body.push_str("---\n\n");
+    if let Some(content) = post.content.as_deref().filter(|c| !c.trim().is_empty()) {
+        body.push_str(content.trim());
+        body.push('\n');
+    }
+    if !post.media_urls.is_empty() {
+        body.push_str("\n## Media\n\n");
+        for url in &post.media_urls {
+            body.push_str(&format!("- {}\n", url));
+        }
+    }
+    body
+}
+
+/// A JSON string literal, which YAML reads as the same string
+fn yaml_string(value: &str) -> String {
+    serde_json::Value::String(value.to_string()).to_string()
+}
+
+/// Link to the post on its platform, for platforms whose post links are
+/// built from the post id
+fn post_url(post: &SocialPost) -> Option<String> {
+    let id = post.platform_post_id.as_deref()?;
+    let handle = post
+        .author_handle
+        .as_deref()
+        .map(|h| h.trim_start_matches('@'));
+    match post.platform.as_str() {
+        "twitter" => Some(format!("https://x.com/{}/status/{}", handle?, id)),
+        "bluesky" => Some(format!("https://bsky.app/profile/{}/post/{}", handle?, id)),
+        "instagram" => Some(format!("https://instagram.com/p/{}", id)),
+        "reddit" => Some(format!("https://reddit.com/comments/{}", id)),
+        "youtube" => Some(format!("https://youtube.com/watch?v={}", id)),
+        _ => None,
+    }
+}
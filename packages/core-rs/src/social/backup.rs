@@ -1,6 +1,7 @@
-use chrono;
 /// Backup and Restore Module for SocialHub
 /// Provides encrypted backup and restore functionality to prevent data loss
+use crate::events::{self, CoreEvent};
+use chrono;
 use rusqlite::Connection;
 use serde::{Deserialize, Serialize};
 use std::fs;
@@ -32,6 +33,9 @@ pub enum BackupError {
 
     #[error("Backup corrupted")]
     BackupCorrupted,
+
+    #[error("Invalid backup policy: {0}")]
+    InvalidPolicy(String),
 }
 
 /// Metadata for a backup
@@ -91,6 +95,28 @@ impl BackupService {
         dek: &[u8],
         description: Option<&str>,
     ) -> Result<String, BackupError> {
+        let result = self.write_backup(conn, dek, description);
+        events::emit(match &result {
+            Ok((backup_id, size_bytes)) => CoreEvent::BackupCompleted {
+                backup_id: backup_id.clone(),
+                size_bytes: *size_bytes,
+                description: description.map(str::to_string),
+            },
+            Err(e) => CoreEvent::BackupFailed {
+                description: description.map(str::to_string),
+                error: e.to_string(),
+            },
+        });
+        result.map(|(backup_id, _)| backup_id)
+    }
+
+    /// Write the backup; returns its id and size
+    fn write_backup(
+        &self,
+        conn: &Connection,
+        dek: &[u8],
+        description: Option<&str>,
+    ) -> Result<(String, u64), BackupError> {
         // Get database version
         let schema_version: i64 = conn
             .query_row("SELECT MAX(version) FROM schema_version", [], |row| {
@@ -141,7 +167,7 @@ impl BackupService {
             backup.metadata.size_bytes
         );
 
-        Ok(backup_id)
+        Ok((backup_id, backup.metadata.size_bytes))
     }
 
     /// Restore database from encrypted backup
@@ -213,9 +239,12 @@ impl BackupService {
             if path.extension().and_then(|s| s.to_str()) == Some("enc") {
                 if let Ok(bytes) = fs::read(&path) {
                     if let Ok(backup) = serde_json::from_slice::<Backup>(&bytes) {
+                        // Ids are file names without the `.json.enc` suffix,
+                        // as used by restore and delete
                         let backup_id = path
-                            .file_stem()
+                            .file_name()
                             .and_then(|s| s.to_str())
+                            .map(|s| s.strip_suffix(".json.enc").unwrap_or(s))
                             .unwrap_or("unknown")
                             .to_string();
 
@@ -243,6 +272,25 @@ impl BackupService {
         Ok(())
     }
 
+    /// Check a backup archive against its metadata without decrypting it
+    pub fn verify_backup(&self, backup_id: &str) -> Result<BackupMetadata, BackupError> {
+        let backup_path = self.backup_dir.join(format!("{}.json.enc", backup_id));
+        if !backup_path.exists() {
+            return Err(BackupError::BackupNotFound);
+        }
+
+        let backup: Backup = serde_json::from_slice(&fs::read(&backup_path)?)
+            .map_err(|_| BackupError::BackupCorrupted)?;
+        if backup.data.len() as u64 != backup.metadata.size_bytes
+            || self.calculate_checksum(&backup.data) != backup.metadata.checksum
+        {
+            log::warn!("[backup] Backup {} failed verification", backup_id);
+            return Err(BackupError::BackupCorrupted);
+        }
+
+        Ok(backup.metadata)
+    }
+
     /// Get backup details
     pub fn get_backup_details(&self, backup_id: &str) -> Result<BackupMetadata, BackupError> {
         let backups = self.list_backups()?;
@@ -263,15 +311,16 @@ impl BackupService {
             "tables": {}
         });
 
-        // Tables to backup (all social-related tables)
+        // Tables to backup (all social-related tables). Only list tables the
+        // migrations create: selecting from a missing one fails the whole export.
         let tables = vec![
             "social_account",
             "social_post",
             "social_category",
+            "social_category_rule",
             "social_post_category",
             "social_sync_history",
             "social_auto_rule",
-            "social_auto_rule_action",
             "social_focus_mode",
         ];
 
@@ -314,8 +363,8 @@ impl BackupService {
     fn clear_database_tx(&self, tx: &rusqlite::Transaction) -> Result<(), BackupError> {
         let tables = vec![
             "social_post_category", // Must delete junction table first
-            "social_auto_rule_action",
             "social_auto_rule",
+            "social_category_rule",
             "social_post",
             "social_category",
             "social_focus_mode",
@@ -0,0 +1,394 @@
+//! Scheduled Backups
+//!
+//! Runs [`BackupService`] on a timer driven by the embedding app and keeps the
+//! target directory from growing without bound. The policy lives in the
+//! `settings` table; every attempt, including skipped ones, is recorded in
+//! `backup_run` so the UI can show why the last backup did not happen.
+//!
+//! Retention keeps the newest `max_count` backups, and beyond those the
+//! newest backup of each ISO week that is not older than `max_age_days`.
+
+use super::backup::{BackupError, BackupService};
+use crate::db::settings::{load_stored_settings, save_settings, Settings, SettingsError};
+use chrono::{DateTime, Datelike, Duration, NaiveTime, TimeZone, Utc};
+use rusqlite::{params, Connection};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use ulid::Ulid;
+
+/// Settings key holding the JSON-encoded [`BackupPolicy`]
+pub const BACKUP_POLICY_KEY: &str = "backup.policy";
+
+/// Wait after a skipped or failed attempt before trying again
+const RETRY_AFTER_SECS: i64 = 15 * 60;
+
+lazy_static::lazy_static! {
+    /// Target directories with a backup in progress in this process
+    static ref RUNNING_BACKUPS: Mutex<HashSet<PathBuf>> = Mutex::new(HashSet::new());
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BackupPolicy {
+    pub enabled: bool,
+    /// Hours between scheduled backups
+    pub interval_hours: u32,
+    /// Preferred start time (UTC) for intervals of a day or more; runs are
+    /// due at this time on the day the interval elapses
+    pub time_of_day: Option<NaiveTime>,
+    /// Newest backups always kept
+    pub max_count: usize,
+    /// Weekly backups beyond `max_count` are kept for this long
+    pub max_age_days: u32,
+    pub target_dir: PathBuf,
+}
+
+impl BackupPolicy {
+    /// When the backup after one completed at `last_completed` is due
+    pub fn next_run_after(
+        &self,
+        last_completed: Option<DateTime<Utc>>,
+        now: DateTime<Utc>,
+    ) -> DateTime<Utc> {
+        let base = match last_completed {
+            Some(last) => last + Duration::hours(i64::from(self.interval_hours)),
+            None => now,
+        };
+        match self.time_of_day {
+            Some(time) if self.interval_hours >= 24 => {
+                Utc.from_utc_datetime(&base.date_naive().and_time(time))
+            }
+            _ => base,
+        }
+    }
+}
+
+impl Default for BackupPolicy {
+    /// Off until a target directory is chosen
+    fn default() -> Self {
+        BackupPolicy {
+            enabled: false,
+            interval_hours: 24,
+            time_of_day: None,
+            max_count: 7,
+            max_age_days: 30,
+            target_dir: PathBuf::new(),
+        }
+    }
+}
+
+impl Settings for BackupPolicy {
+    const KEY: &'static str = BACKUP_POLICY_KEY;
+    const DESCRIPTION: &'static str = "Scheduled backup policy";
+
+    fn validate(&self) -> Result<(), String> {
+        if self.interval_hours == 0 {
+            return Err("interval_hours must be at least 1".to_string());
+        }
+        if self.max_count == 0 {
+            return Err("max_count must be at least 1".to_string());
+        }
+        if self.target_dir.as_os_str().is_empty() {
+            return Err("target_dir must be set".to_string());
+        }
+        Ok(())
+    }
+}
+
+impl From<SettingsError> for BackupError {
+    fn from(e: SettingsError) -> Self {
+        match e {
+            SettingsError::Rusqlite(e) => BackupError::Database(e),
+            SettingsError::Db(e) => db_error(e),
+            SettingsError::Invalid { reason, .. } => BackupError::InvalidPolicy(reason),
+            SettingsError::Malformed { .. } | SettingsError::UnsupportedVersion { .. } => {
+                BackupError::InvalidPolicy(e.to_string())
+            }
+        }
+    }
+}
+
+pub fn get_backup_policy(conn: &Connection) -> Result<Option<BackupPolicy>, BackupError> {
+    Ok(load_stored_settings(conn)?)
+}
+
+pub fn set_backup_policy(conn: &Connection, policy: &BackupPolicy) -> Result<(), BackupError> {
+    Ok(save_settings(conn, policy)?)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BackupSkipReason {
+    /// No DEK to encrypt the backup with
+    VaultLocked,
+    /// Another backup into the same directory has not finished
+    AlreadyRunning,
+}
+
+impl BackupSkipReason {
+    fn as_str(self) -> &'static str {
+        match self {
+            BackupSkipReason::VaultLocked => "vault_locked",
+            BackupSkipReason::AlreadyRunning => "already_running",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "status", rename_all = "snake_case")]
+pub enum ScheduledBackupOutcome {
+    Completed {
+        backup_id: String,
+        /// Backups removed by retention afterwards
+        pruned: Vec<String>,
+    },
+    Skipped {
+        reason: BackupSkipReason,
+    },
+}
+
+/// One recorded attempt of the scheduler
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BackupRun {
+    pub id: String,
+    pub started_at: i64,
+    pub finished_at: Option<i64>,
+    pub status: String,
+    pub reason: Option<String>,
+    pub backup_id: Option<String>,
+    pub pruned_count: i64,
+}
+
+/// Marks a backup into `target_dir` as in progress until dropped
+pub struct BackupRunGuard {
+    target_dir: PathBuf,
+}
+
+impl BackupRunGuard {
+    /// `None` if a backup into the same directory is already running
+    pub fn try_acquire(target_dir: &Path) -> Option<Self> {
+        let mut running = RUNNING_BACKUPS.lock().unwrap_or_else(|e| e.into_inner());
+        if !running.insert(target_dir.to_path_buf()) {
+            return None;
+        }
+        Some(BackupRunGuard {
+            target_dir: target_dir.to_path_buf(),
+        })
+    }
+}
+
+impl Drop for BackupRunGuard {
+    fn drop(&mut self) {
+        RUNNING_BACKUPS
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .remove(&self.target_dir);
+    }
+}
+
+/// Whether the stored policy calls for a backup at `now`. After a skipped or
+/// failed attempt the scheduler waits a while before trying again.
+pub fn should_run_backup(conn: &Connection, now: DateTime<Utc>) -> Result<bool, BackupError> {
+    let policy = match get_backup_policy(conn)? {
+        Some(policy) if policy.enabled => policy,
+        _ => return Ok(false),
+    };
+
+    let last_completed: Option<i64> = conn.query_row(
+        "SELECT MAX(started_at) FROM backup_run WHERE status = 'completed'",
+        [],
+        |row| row.get(0),
+    )?;
+    let last_attempt: Option<i64> =
+        conn.query_row("SELECT MAX(started_at) FROM backup_run", [], |row| {
+            row.get(0)
+        })?;
+    if let Some(attempt) = last_attempt {
+        if last_completed != Some(attempt) && now.timestamp() - attempt < RETRY_AFTER_SECS {
+            return Ok(false);
+        }
+    }
+
+    let last_completed = last_completed.and_then(|ts| Utc.timestamp_opt(ts, 0).single());
+    Ok(now >= policy.next_run_after(last_completed, now))
+}
+
+/// Create a backup into the policy's directory, verify it and prune old ones.
+/// Skips (and records why) when the vault is locked or another backup into
+/// the same directory is running. Failures are recorded before returning.
+pub fn run_scheduled_backup(
+    conn: &Connection,
+    dek: Option<&[u8]>,
+    policy: &BackupPolicy,
+    now: DateTime<Utc>,
+) -> Result<ScheduledBackupOutcome, BackupError> {
+    let run_id = Ulid::new().to_string();
+    let started_at = now.timestamp();
+
+    let dek = match dek.filter(|dek| !dek.is_empty()) {
+        Some(dek) => dek,
+        None => return skip(conn, &run_id, started_at, BackupSkipReason::VaultLocked),
+    };
+    let _running = match BackupRunGuard::try_acquire(&policy.target_dir) {
+        Some(guard) => guard,
+        None => return skip(conn, &run_id, started_at, BackupSkipReason::AlreadyRunning),
+    };
+
+    match create_and_prune(conn, dek, policy, now) {
+        Ok((backup_id, pruned)) => {
+            conn.execute(
+                "INSERT INTO backup_run (id, started_at, finished_at, status, backup_id, pruned_count)
+                 VALUES (?1, ?2, ?3, 'completed', ?4, ?5)",
+                params![
+                    run_id,
+                    started_at,
+                    Utc::now().timestamp(),
+                    backup_id,
+                    pruned.len() as i64
+                ],
+            )?;
+            Ok(ScheduledBackupOutcome::Completed { backup_id, pruned })
+        }
+        Err(e) => {
+            log::error!("[backup] Scheduled backup failed: {}", e);
+            conn.execute(
+                "INSERT INTO backup_run (id, started_at, finished_at, status, reason)
+                 VALUES (?1, ?2, ?3, 'failed', ?4)",
+                params![run_id, started_at, Utc::now().timestamp(), e.to_string()],
+            )?;
+            Err(e)
+        }
+    }
+}
+
+fn create_and_prune(
+    conn: &Connection,
+    dek: &[u8],
+    policy: &BackupPolicy,
+    now: DateTime<Utc>,
+) -> Result<(String, Vec<String>), BackupError> {
+    let service = BackupService::new(&policy.target_dir)?;
+    let backup_id = service.create_backup(conn, dek, Some("scheduled"))?;
+    if let Err(e) = service.verify_backup(&backup_id) {
+        // Never let a bad archive count towards retention
+        service.delete_backup(&backup_id)?;
+        return Err(e);
+    }
+    let pruned = apply_retention(policy, now)?;
+    Ok((backup_id, pruned))
+}
+
+fn skip(
+    conn: &Connection,
+    run_id: &str,
+    started_at: i64,
+    reason: BackupSkipReason,
+) -> Result<ScheduledBackupOutcome, BackupError> {
+    log::info!("[backup] Scheduled backup skipped: {}", reason.as_str());
+    conn.execute(
+        "INSERT INTO backup_run (id, started_at, finished_at, status, reason)
+         VALUES (?1, ?2, ?2, 'skipped', ?3)",
+        params![run_id, started_at, reason.as_str()],
+    )?;
+    Ok(ScheduledBackupOutcome::Skipped { reason })
+}
+
+/// Most recent scheduler attempts, newest first
+pub fn get_backup_runs(conn: &Connection, limit: i64) -> Result<Vec<BackupRun>, BackupError> {
+    let mut stmt = conn.prepare(
+        "SELECT id, started_at, finished_at, status, reason, backup_id, pruned_count
+         FROM backup_run ORDER BY started_at DESC, id DESC LIMIT ?1",
+    )?;
+    let runs = stmt
+        .query_map([limit], |row| {
+            Ok(BackupRun {
+                id: row.get(0)?,
+                started_at: row.get(1)?,
+                finished_at: row.get(2)?,
+                status: row.get(3)?,
+                reason: row.get(4)?,
+                backup_id: row.get(5)?,
+                pruned_count: row.get(6)?,
+            })
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(runs)
+}
+
+/// Last recorded attempt, if any
+pub fn get_last_backup_run(conn: &Connection) -> Result<Option<BackupRun>, BackupError> {
+    Ok(get_backup_runs(conn, 1)?.into_iter().next())
+}
+
+/// Split backups (id, created_at) into those to keep and those to prune
+pub fn plan_retention(
+    policy: &BackupPolicy,
+    backups: &[(String, DateTime<Utc>)],
+    now: DateTime<Utc>,
+) -> (Vec<String>, Vec<String>) {
+    let mut sorted: Vec<&(String, DateTime<Utc>)> = backups.iter().collect();
+    sorted.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| b.0.cmp(&a.0)));
+
+    let max_age = Duration::days(i64::from(policy.max_age_days));
+    let mut weeks_kept = HashSet::new();
+    let (mut keep, mut prune) = (Vec::new(), Vec::new());
+    for (index, (id, created_at)) in sorted.into_iter().enumerate() {
+        let week = created_at.iso_week();
+        let week = (week.year(), week.week());
+        let kept = if index < policy.max_count {
+            true
+        } else {
+            now - *created_at <= max_age && !weeks_kept.contains(&week)
+        };
+        if kept {
+            weeks_kept.insert(week);
+            keep.push(id.clone());
+        } else {
+            prune.push(id.clone());
+        }
+    }
+    (keep, prune)
+}
+
+/// Delete backups in the policy's directory that retention no longer keeps.
+/// Backups whose creation time can't be read are left alone.
+pub fn apply_retention(
+    policy: &BackupPolicy,
+    now: DateTime<Utc>,
+) -> Result<Vec<String>, BackupError> {
+    let service = BackupService::new(&policy.target_dir)?;
+    let backups: Vec<(String, DateTime<Utc>)> = service
+        .list_backups()?
+        .into_iter()
+        .filter_map(|(id, metadata)| {
+            DateTime::parse_from_rfc3339(&metadata.created_at)
+                .ok()
+                .map(|created_at| (id, created_at.with_timezone(&Utc)))
+        })
+        .collect();
+
+    let (_, prune) = plan_retention(policy, &backups, now);
+    for id in &prune {
+        service.delete_backup(id)?;
+    }
+    if !prune.is_empty() {
+        log::info!("[backup] Retention pruned {} backups", prune.len());
+    }
+    Ok(prune)
+}
+
+fn db_error(e: crate::db::DbError) -> BackupError {
+    match e {
+        crate::db::DbError::Rusqlite(e) => BackupError::Database(e),
+        crate::db::DbError::SerdeJson(e) => BackupError::Serialization(e),
+        crate::db::DbError::Message(msg) => BackupError::InvalidBackup(msg),
+        crate::db::DbError::PermissionDenied(e) => BackupError::InvalidBackup(e.to_string()),
+        crate::db::DbError::QuerySyntax(e) => BackupError::InvalidBackup(e.to_string()),
+        crate::db::DbError::InvalidId(e) => BackupError::InvalidBackup(e.to_string()),
+        crate::db::DbError::LockedByOther(e) => BackupError::InvalidBackup(e.to_string()),
+        e @ (crate::db::DbError::NotFound { .. } | crate::db::DbError::Locked { .. }) => {
+            BackupError::InvalidBackup(e.to_string())
+        }
+    }
+}
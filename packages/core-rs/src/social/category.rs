@@ -4,6 +4,7 @@ use serde::{Deserialize, Serialize};
 use ulid::Ulid;
 
 use super::account::SocialError;
+use crate::undo::{capture_rows, record_undo, UndoRecord, UndoSnapshot};
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct SocialCategory {
@@ -205,16 +206,62 @@ pub fn update_category(
     Ok(())
 }
 
-/// Delete a category
+/// Delete a category along with its rules and post assignments, journaling
+/// them so the delete can be undone
 pub fn delete_category(conn: &Connection, category_id: &str) -> Result<(), SocialError> {
     log::warn!("[Social::Category] Deleting category {}", category_id);
 
-    conn.execute("DELETE FROM social_category WHERE id = ?1", [category_id])
+    let existing: Option<(String, String)> = conn
+        .query_row(
+            "SELECT space_id, name FROM social_category WHERE id = ?1",
+            [category_id],
+            |row| Ok((row.get(0)?, row.get(1)?)),
+        )
+        .optional()?;
+
+    let tx = conn.unchecked_transaction()?;
+    let mut snapshot = UndoSnapshot::default();
+    for (table, filter) in [
+        ("social_category", "id = ?1"),
+        ("social_category_rule", "category_id = ?1"),
+        ("social_auto_rule", "category_id = ?1"),
+        ("social_post_category", "category_id = ?1"),
+    ] {
+        snapshot
+            .deleted
+            .extend(capture_rows(&tx, table, "*", filter, [category_id])?);
+    }
+    for table in [
+        "social_post_category",
+        "social_auto_rule",
+        "social_category_rule",
+    ] {
+        tx.execute(
+            &format!("DELETE FROM {} WHERE category_id = ?1", table),
+            [category_id],
+        )?;
+    }
+    tx.execute("DELETE FROM social_category WHERE id = ?1", [category_id])
         .map_err(|e| {
             log::error!("[Social::Category] Failed to delete category: {}", e);
             e
         })?;
 
+    if let Some((space_id, name)) = existing {
+        record_undo(
+            &tx,
+            UndoRecord {
+                space_id: &space_id,
+                operation: "delete_category",
+                entity_type: "social_category",
+                entity_id: category_id,
+                summary: format!("Deleted category \"{}\"", name),
+                snapshot,
+            },
+        );
+    }
+    tx.commit()?;
+
     log::info!(
         "[Social::Category] Successfully deleted category {}",
         category_id
@@ -227,7 +274,7 @@ pub fn assign_category(
     conn: &Connection,
     post_id: &str,
     category_id: &str,
-    assigned_by: &str, // 'user', 'auto', or 'ai'
+    assigned_by: &str, // 'manual', 'user', 'auto', 'rule', or 'ai'
 ) -> Result<(), SocialError> {
     log::debug!(
         "[Social::Category] Assigning category {} to post {} (by: {})",
@@ -259,10 +306,21 @@ pub fn assign_category(
         (Some(ps), Some(cs)) if ps == cs => {
             let now = Utc::now().timestamp_millis();
             conn.execute(
-                "INSERT OR IGNORE INTO social_post_category (
+                "INSERT INTO social_post_category (
                     post_id, category_id, assigned_at, assigned_by
-                ) VALUES (?1, ?2, ?3, ?4)",
-                params![post_id, category_id, now, assigned_by],
+                ) VALUES (?1, ?2, ?3, ?4)
+                ON CONFLICT(post_id, category_id) DO UPDATE SET
+                    assigned_at = excluded.assigned_at,
+                    assigned_by = excluded.assigned_by
+                WHERE ?5",
+                // A manual assignment takes over an automatic one, never the reverse
+                params![
+                    post_id,
+                    category_id,
+                    now,
+                    assigned_by,
+                    super::rules::is_manual_source(assigned_by)
+                ],
             )
             .map_err(|e| {
                 log::error!("[Social::Category] Failed to assign category: {}", e);
@@ -4,8 +4,8 @@
  * Provides focus mode presets with platform restrictions, time limits,
  * and automation rules to help users maintain healthy social media habits.
  */
-use chrono::Utc;
-use rusqlite::{params, Connection};
+use chrono::{DateTime, Datelike, Duration, NaiveDate, Utc, Weekday};
+use rusqlite::{params, Connection, OptionalExtension};
 use serde::{Deserialize, Serialize};
 
 use super::account::SocialError;
@@ -24,10 +24,62 @@ pub struct FocusMode {
     pub created_at: i64,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 pub struct TimeLimit {
     pub platform: String,
     pub daily_minutes: i32,
+    /// Day this budget applies to; overrides the every-day limit on that day
+    #[serde(default)]
+    pub weekday: Option<Weekday>,
+}
+
+/// Whether a platform may be used right now, and why not
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(tag = "status", rename_all = "snake_case")]
+pub enum PlatformAccess {
+    Allowed {
+        /// Budget left today, if the active focus mode limits this platform
+        minutes_remaining: Option<i64>,
+    },
+    BlockedByFocus {
+        focus_mode_id: String,
+        focus_mode_name: String,
+    },
+    LimitExhausted {
+        focus_mode_id: String,
+        daily_minutes: i32,
+        used_minutes: i64,
+        minutes_remaining: i64,
+        /// Next local midnight, when the budget resets (ms)
+        resets_at: i64,
+    },
+}
+
+impl PlatformAccess {
+    pub fn is_blocked(&self) -> bool {
+        !matches!(self, PlatformAccess::Allowed { .. })
+    }
+}
+
+/// Time spent on a platform during the current local day
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct PlatformUsage {
+    pub platform: String,
+    pub day: String,
+    pub seconds: i64,
+    pub limit_minutes: Option<i32>,
+    pub minutes_remaining: Option<i64>,
+}
+
+/// An automation rule whose trigger fired
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FiredAutomation {
+    pub rule_id: String,
+    pub rule_name: String,
+    pub action_type: ActionType,
+    pub action_value: String,
+    pub platform: String,
+    pub usage_percent: i64,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -45,10 +97,11 @@ pub struct AutomationRule {
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum TriggerType {
-    TimeOfDay,    // e.g., "09:00"
-    DayOfWeek,    // e.g., "monday"
-    PlatformOpen, // e.g., "twitter"
-    CategoryPost, // e.g., "work"
+    TimeOfDay,      // e.g., "09:00"
+    DayOfWeek,      // e.g., "monday"
+    PlatformOpen,   // e.g., "twitter"
+    CategoryPost,   // e.g., "work"
+    UsageThreshold, // e.g., "twitter:80" (percent of today's limit) or "80" for any platform
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -132,7 +185,7 @@ pub fn get_focus_modes(conn: &Connection, space_id: &str) -> Result<Vec<FocusMod
          ORDER BY created_at DESC",
     )?;
 
-    let modes: Vec<FocusMode> = stmt
+    let mut modes: Vec<FocusMode> = stmt
         .query_map([space_id], |row| {
             let blocked_json: String = row.get(6)?;
             let allowed_json: String = row.get(7)?;
@@ -146,13 +199,17 @@ pub fn get_focus_modes(conn: &Connection, space_id: &str) -> Result<Vec<FocusMod
                 is_active: row.get::<_, i32>(5)? == 1,
                 blocked_platforms: serde_json::from_str(&blocked_json).unwrap_or_default(),
                 allowed_platforms: serde_json::from_str(&allowed_json).unwrap_or_default(),
-                time_limits: Vec::new(), // Load separately if needed
+                time_limits: Vec::new(),
                 created_at: row.get(8)?,
             })
         })?
         .filter_map(Result::ok)
         .collect();
 
+    for mode in &mut modes {
+        mode.time_limits = get_time_limits(conn, &mode.id)?;
+    }
+
     log::info!(
         "[Social::Focus] Retrieved {} focus modes for space {}",
         modes.len(),
@@ -228,44 +285,449 @@ pub fn deactivate_all_focus_modes(conn: &Connection, space_id: &str) -> Result<(
     Ok(())
 }
 
-/// Check if a platform is blocked by active focus mode
+/// Check whether a platform may be used: blocked by the active focus mode,
+/// out of today's time budget, or allowed
 pub fn is_platform_blocked(
     conn: &Connection,
     space_id: &str,
     platform: &str,
-) -> Result<bool, SocialError> {
+) -> Result<PlatformAccess, SocialError> {
+    is_platform_blocked_at(conn, space_id, platform, Utc::now().timestamp_millis())
+}
+
+/// `is_platform_blocked` as of `at` (ms)
+pub fn is_platform_blocked_at(
+    conn: &Connection,
+    space_id: &str,
+    platform: &str,
+    at: i64,
+) -> Result<PlatformAccess, SocialError> {
     log::debug!(
         "[Social::Focus] Checking if platform {} is blocked in space {}",
         platform,
         space_id
     );
 
+    let active: Option<(String, String, String)> = conn
+        .query_row(
+            "SELECT id, name, blocked_platforms FROM social_focus_mode
+             WHERE space_id = ?1 AND is_active = 1 LIMIT 1",
+            [space_id],
+            |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+        )
+        .optional()?;
+
+    let Some((focus_mode_id, focus_mode_name, blocked_json)) = active else {
+        return Ok(PlatformAccess::Allowed {
+            minutes_remaining: None,
+        });
+    };
+
+    let blocked: Vec<String> = serde_json::from_str(&blocked_json).unwrap_or_default();
+    if blocked.iter().any(|p| p.eq_ignore_ascii_case(platform)) {
+        log::debug!(
+            "[Social::Focus] Platform {} is blocked by focus mode {} in space {}",
+            platform,
+            focus_mode_id,
+            space_id
+        );
+        return Ok(PlatformAccess::BlockedByFocus {
+            focus_mode_id,
+            focus_mode_name,
+        });
+    }
+
+    let day = local_day(conn, space_id, at)?;
+    let Some(daily_minutes) = limit_for_day(conn, &focus_mode_id, platform, day.date)? else {
+        return Ok(PlatformAccess::Allowed {
+            minutes_remaining: None,
+        });
+    };
+
+    let used = usage_seconds(conn, space_id, platform, &day.key())?;
+    let remaining = (i64::from(daily_minutes) * 60 - used).max(0) / 60;
+    if used >= i64::from(daily_minutes) * 60 {
+        log::debug!(
+            "[Social::Focus] Platform {} has used its {} minute budget in space {}",
+            platform,
+            daily_minutes,
+            space_id
+        );
+        return Ok(PlatformAccess::LimitExhausted {
+            focus_mode_id,
+            daily_minutes,
+            used_minutes: used / 60,
+            minutes_remaining: 0,
+            resets_at: day.end,
+        });
+    }
+
+    Ok(PlatformAccess::Allowed {
+        minutes_remaining: Some(remaining),
+    })
+}
+
+/// Set a time budget for a platform in a focus mode, replacing any budget for
+/// the same platform and weekday
+pub fn set_time_limit(
+    conn: &Connection,
+    focus_mode_id: &str,
+    limit: &TimeLimit,
+) -> Result<(), SocialError> {
+    if limit.daily_minutes < 0 {
+        return Err(SocialError::InvalidInput(
+            "Daily minutes cannot be negative".into(),
+        ));
+    }
+    let platform = limit.platform.trim().to_lowercase();
+    let weekday = limit.weekday.map(|d| d.num_days_from_monday());
+
+    remove_time_limit(conn, focus_mode_id, &platform, limit.weekday)?;
+    conn.execute(
+        "INSERT INTO social_time_limit (focus_mode_id, platform, weekday, daily_minutes)
+         VALUES (?1, ?2, ?3, ?4)",
+        params![focus_mode_id, platform, weekday, limit.daily_minutes],
+    )?;
+
+    log::info!(
+        "[Social::Focus] Set {} minute limit for {} ({:?}) in focus mode {}",
+        limit.daily_minutes,
+        platform,
+        limit.weekday,
+        focus_mode_id
+    );
+    Ok(())
+}
+
+/// Remove a platform budget from a focus mode
+pub fn remove_time_limit(
+    conn: &Connection,
+    focus_mode_id: &str,
+    platform: &str,
+    weekday: Option<Weekday>,
+) -> Result<(), SocialError> {
+    conn.execute(
+        "DELETE FROM social_time_limit
+         WHERE focus_mode_id = ?1 AND platform = ?2 AND weekday IS ?3",
+        params![
+            focus_mode_id,
+            platform.trim().to_lowercase(),
+            weekday.map(|d| d.num_days_from_monday())
+        ],
+    )?;
+    Ok(())
+}
+
+/// Time budgets of a focus mode
+pub fn get_time_limits(
+    conn: &Connection,
+    focus_mode_id: &str,
+) -> Result<Vec<TimeLimit>, SocialError> {
     let mut stmt = conn.prepare(
-        "SELECT blocked_platforms FROM social_focus_mode
-         WHERE space_id = ?1 AND is_active = 1 LIMIT 1",
+        "SELECT platform, daily_minutes, weekday FROM social_time_limit
+         WHERE focus_mode_id = ?1
+         ORDER BY platform, weekday IS NOT NULL, weekday",
+    )?;
+    let limits = stmt
+        .query_map([focus_mode_id], |row| {
+            let weekday: Option<u8> = row.get(2)?;
+            Ok(TimeLimit {
+                platform: row.get(0)?,
+                daily_minutes: row.get(1)?,
+                weekday: weekday.and_then(|d| Weekday::try_from(d).ok()),
+            })
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(limits)
+}
+
+/// Set the UTC offset (minutes) whose midnight resets the daily budgets of a space.
+/// Spaces without a setting use the system's local offset.
+pub fn set_usage_timezone(
+    conn: &Connection,
+    space_id: &str,
+    utc_offset_minutes: i32,
+) -> Result<(), SocialError> {
+    if !(-14 * 60..=14 * 60).contains(&utc_offset_minutes) {
+        return Err(SocialError::InvalidInput(format!(
+            "UTC offset out of range: {} minutes",
+            utc_offset_minutes
+        )));
+    }
+    conn.execute(
+        "INSERT INTO social_usage_settings (space_id, utc_offset_minutes) VALUES (?1, ?2)
+         ON CONFLICT(space_id) DO UPDATE SET utc_offset_minutes = excluded.utc_offset_minutes",
+        params![space_id, utc_offset_minutes],
     )?;
+    Ok(())
+}
 
-    let result = stmt.query_row([space_id], |row| {
-        let blocked_json: String = row.get(0)?;
-        Ok(blocked_json)
-    });
+/// UTC offset (minutes) used for a space's daily budgets
+pub fn get_usage_timezone(conn: &Connection, space_id: &str) -> Result<i32, SocialError> {
+    let configured: Option<i32> = conn
+        .query_row(
+            "SELECT utc_offset_minutes FROM social_usage_settings WHERE space_id = ?1",
+            [space_id],
+            |row| row.get(0),
+        )
+        .optional()?;
+    Ok(configured.unwrap_or_else(|| chrono::Local::now().offset().local_minus_utc() / 60))
+}
 
-    let is_blocked = match result {
-        Ok(blocked_json) => {
-            let blocked: Vec<String> = serde_json::from_str(&blocked_json).unwrap_or_default();
-            blocked.contains(&platform.to_string())
-        }
-        Err(_) => false, // No active focus mode
-    };
+/// Record time spent on a platform, called by the webview layer as the user
+/// browses. Returns the usage threshold automations that fired.
+pub fn record_platform_usage(
+    conn: &Connection,
+    space_id: &str,
+    platform: &str,
+    seconds: i64,
+) -> Result<Vec<FiredAutomation>, SocialError> {
+    record_platform_usage_at(
+        conn,
+        space_id,
+        platform,
+        seconds,
+        Utc::now().timestamp_millis(),
+    )
+}
+
+/// `record_platform_usage` for a session ending at `at` (ms). A session that
+/// spans local midnight is split between the two days.
+pub fn record_platform_usage_at(
+    conn: &Connection,
+    space_id: &str,
+    platform: &str,
+    seconds: i64,
+    at: i64,
+) -> Result<Vec<FiredAutomation>, SocialError> {
+    if seconds <= 0 {
+        return Ok(Vec::new());
+    }
+    let platform = platform.trim().to_lowercase();
+
+    let mut end = at;
+    let mut remaining = seconds;
+    while remaining > 0 {
+        let day = local_day(conn, space_id, end - 1)?;
+        let portion = remaining.min((end - day.start + 999) / 1000);
+        conn.execute(
+            "INSERT INTO social_platform_usage (space_id, platform, day, seconds)
+             VALUES (?1, ?2, ?3, ?4)
+             ON CONFLICT(space_id, platform, day) DO UPDATE SET seconds = seconds + excluded.seconds",
+            params![space_id, &platform, day.key(), portion],
+        )?;
+        remaining -= portion;
+        end = day.start;
+    }
 
     log::debug!(
-        "[Social::Focus] Platform {} is {} in space {}",
+        "[Social::Focus] Recorded {}s on {} in space {}",
+        seconds,
         platform,
-        if is_blocked { "blocked" } else { "allowed" },
         space_id
     );
 
-    Ok(is_blocked)
+    fire_usage_automations(conn, space_id, &platform, at)
+}
+
+/// Usage per platform for the current local day
+pub fn get_platform_usage_today(
+    conn: &Connection,
+    space_id: &str,
+) -> Result<Vec<PlatformUsage>, SocialError> {
+    get_platform_usage_at(conn, space_id, Utc::now().timestamp_millis())
+}
+
+/// Usage per platform for the local day containing `at` (ms)
+pub fn get_platform_usage_at(
+    conn: &Connection,
+    space_id: &str,
+    at: i64,
+) -> Result<Vec<PlatformUsage>, SocialError> {
+    let day = local_day(conn, space_id, at)?;
+    let active_mode: Option<String> = conn
+        .query_row(
+            "SELECT id FROM social_focus_mode WHERE space_id = ?1 AND is_active = 1 LIMIT 1",
+            [space_id],
+            |row| row.get(0),
+        )
+        .optional()?;
+
+    let mut stmt = conn.prepare(
+        "SELECT platform, seconds FROM social_platform_usage
+         WHERE space_id = ?1 AND day = ?2
+         ORDER BY seconds DESC, platform",
+    )?;
+    let rows = stmt
+        .query_map(params![space_id, day.key()], |row| {
+            Ok((row.get::<_, String>(0)?, row.get::<_, i64>(1)?))
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let mut usage = Vec::with_capacity(rows.len());
+    for (platform, seconds) in rows {
+        let limit_minutes = match &active_mode {
+            Some(mode_id) => limit_for_day(conn, mode_id, &platform, day.date)?,
+            None => None,
+        };
+        usage.push(PlatformUsage {
+            minutes_remaining: limit_minutes.map(|m| (i64::from(m) * 60 - seconds).max(0) / 60),
+            platform,
+            day: day.key(),
+            seconds,
+            limit_minutes,
+        });
+    }
+    Ok(usage)
+}
+
+/// A local calendar day and its bounds in UTC milliseconds
+struct LocalDay {
+    date: NaiveDate,
+    start: i64,
+    end: i64,
+}
+
+impl LocalDay {
+    fn key(&self) -> String {
+        self.date.format("%Y-%m-%d").to_string()
+    }
+}
+
+fn local_day(conn: &Connection, space_id: &str, at: i64) -> Result<LocalDay, SocialError> {
+    const DAY_MS: i64 = 86_400_000;
+    let offset_ms = i64::from(get_usage_timezone(conn, space_id)?) * 60_000;
+    let local = at + offset_ms;
+    let start = local.div_euclid(DAY_MS) * DAY_MS - offset_ms;
+    let date = DateTime::<Utc>::from_timestamp_millis(local)
+        .ok_or_else(|| SocialError::InvalidInput(format!("Invalid timestamp {}", at)))?
+        .date_naive();
+    Ok(LocalDay {
+        date,
+        start,
+        end: start + Duration::days(1).num_milliseconds(),
+    })
+}
+
+/// The budget (minutes) of a platform on a given day: a weekday-specific limit
+/// wins over the every-day limit
+fn limit_for_day(
+    conn: &Connection,
+    focus_mode_id: &str,
+    platform: &str,
+    date: NaiveDate,
+) -> Result<Option<i32>, SocialError> {
+    let limit = conn
+        .query_row(
+            "SELECT daily_minutes FROM social_time_limit
+             WHERE focus_mode_id = ?1 AND platform = ?2 AND (weekday IS NULL OR weekday = ?3)
+             ORDER BY weekday IS NULL
+             LIMIT 1",
+            params![
+                focus_mode_id,
+                platform.trim().to_lowercase(),
+                date.weekday().num_days_from_monday()
+            ],
+            |row| row.get(0),
+        )
+        .optional()?;
+    Ok(limit)
+}
+
+fn usage_seconds(
+    conn: &Connection,
+    space_id: &str,
+    platform: &str,
+    day: &str,
+) -> Result<i64, SocialError> {
+    let seconds = conn
+        .query_row(
+            "SELECT seconds FROM social_platform_usage
+             WHERE space_id = ?1 AND platform = ?2 AND day = ?3",
+            params![space_id, platform.trim().to_lowercase(), day],
+            |row| row.get(0),
+        )
+        .optional()?;
+    Ok(seconds.unwrap_or(0))
+}
+
+/// Fire enabled usage threshold rules for a platform that crossed their
+/// percentage of today's budget. Each rule fires once per platform per day.
+fn fire_usage_automations(
+    conn: &Connection,
+    space_id: &str,
+    platform: &str,
+    at: i64,
+) -> Result<Vec<FiredAutomation>, SocialError> {
+    let active_mode: Option<String> = conn
+        .query_row(
+            "SELECT id FROM social_focus_mode WHERE space_id = ?1 AND is_active = 1 LIMIT 1",
+            [space_id],
+            |row| row.get(0),
+        )
+        .optional()?;
+    let Some(mode_id) = active_mode else {
+        return Ok(Vec::new());
+    };
+
+    let day = local_day(conn, space_id, at)?;
+    let Some(limit) = limit_for_day(conn, &mode_id, platform, day.date)? else {
+        return Ok(Vec::new());
+    };
+    let used = usage_seconds(conn, space_id, platform, &day.key())?;
+    let usage_percent = if limit == 0 {
+        100
+    } else {
+        used * 100 / (i64::from(limit) * 60)
+    };
+
+    let mut fired = Vec::new();
+    for rule in get_automation_rules(conn, space_id)? {
+        if !rule.enabled || !matches!(rule.trigger_type, TriggerType::UsageThreshold) {
+            continue;
+        }
+        let (rule_platform, threshold) = match rule.trigger_value.rsplit_once(':') {
+            Some((p, t)) => (Some(p.trim()), t),
+            None => (None, rule.trigger_value.as_str()),
+        };
+        let Ok(threshold) = threshold.trim().trim_end_matches('%').parse::<i64>() else {
+            log::warn!(
+                "[Social::Focus] Ignoring rule {} with invalid threshold '{}'",
+                rule.id,
+                rule.trigger_value
+            );
+            continue;
+        };
+        if rule_platform.is_some_and(|p| !p.eq_ignore_ascii_case(platform))
+            || usage_percent < threshold
+        {
+            continue;
+        }
+
+        let first_time = conn.execute(
+            "INSERT OR IGNORE INTO social_automation_firing (rule_id, platform, day, fired_at)
+             VALUES (?1, ?2, ?3, ?4)",
+            params![&rule.id, platform, day.key(), at],
+        )? > 0;
+        if first_time {
+            log::info!(
+                "[Social::Focus] Automation rule '{}' fired: {} at {}% of its budget",
+                rule.name,
+                platform,
+                usage_percent
+            );
+            fired.push(FiredAutomation {
+                rule_id: rule.id,
+                rule_name: rule.name,
+                action_type: rule.action_type,
+                action_value: rule.action_value,
+                platform: platform.to_string(),
+                usage_percent,
+            });
+        }
+    }
+
+    Ok(fired)
 }
 
 /// Create preset focus modes for a space
@@ -380,6 +842,7 @@ pub fn create_automation_rule(
         TriggerType::DayOfWeek => "day_of_week",
         TriggerType::PlatformOpen => "platform_open",
         TriggerType::CategoryPost => "category_post",
+        TriggerType::UsageThreshold => "usage_threshold",
     };
 
     let action_type_str = match action_type {
@@ -443,6 +906,7 @@ pub fn get_automation_rules(
                 "day_of_week" => TriggerType::DayOfWeek,
                 "platform_open" => TriggerType::PlatformOpen,
                 "category_post" => TriggerType::CategoryPost,
+                "usage_threshold" => TriggerType::UsageThreshold,
                 _ => TriggerType::TimeOfDay,
             };
 
@@ -0,0 +1,317 @@
+//! Chunked Ingestion of Extractor Payloads
+//!
+//! A long thread or a full timeline can make an extractor produce megabytes of
+//! posts. Instead of handing all of it to [`store_social_posts`] in one call,
+//! the extractor opens a session and sends the payload in chunks. Each chunk
+//! is parsed post by post and at most a bounded number of posts is held in
+//! memory before being flushed to the database.
+//!
+//! A chunk is a JSON array of posts, or one post per line. A chunk that fails
+//! to parse is recorded and skipped; posts read from it before the failure
+//! are kept, and the session carries on with the next chunk.
+
+use super::account::SocialError;
+use super::post::{store_social_posts, SocialPost, StorePostsResult};
+use rusqlite::Connection;
+use serde::de::{self, Deserializer as _, SeqAccess, Visitor};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fmt;
+use std::time::{Duration, Instant};
+use ulid::Ulid;
+
+/// Posts held in memory per session before they are written
+pub const DEFAULT_MAX_BUFFERED_POSTS: usize = 100;
+
+/// Sessions without a chunk for this long are dropped
+pub const DEFAULT_SESSION_IDLE_TIMEOUT: Duration = Duration::from_secs(10 * 60);
+
+/// A chunk that could not be parsed
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct ChunkFailure {
+    /// 1-based position of the chunk in the session
+    pub chunk: usize,
+    pub error: String,
+}
+
+/// What one chunk contributed
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct ChunkOutcome {
+    pub chunk: usize,
+    pub posts_parsed: usize,
+    /// Set when the chunk was malformed; the session is unaffected
+    pub error: Option<String>,
+    /// Posts waiting for the next flush
+    pub buffered: usize,
+}
+
+/// Totals of a finished session
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub struct ExtractionSummary {
+    pub session_id: String,
+    pub account_id: String,
+    pub chunks: usize,
+    pub failed_chunks: Vec<ChunkFailure>,
+    pub posts_parsed: usize,
+    /// Calls to `store_social_posts`
+    pub flushes: usize,
+    /// Most posts held in memory at once
+    pub peak_buffered: usize,
+    pub stored: StorePostsResult,
+}
+
+struct ExtractionSession {
+    buffer: Vec<SocialPost>,
+    summary: ExtractionSummary,
+    last_activity: Instant,
+}
+
+impl ExtractionSession {
+    fn push(
+        &mut self,
+        conn: &mut Connection,
+        post: SocialPost,
+        max_buffered: usize,
+    ) -> Result<(), SocialError> {
+        self.buffer.push(post);
+        self.summary.posts_parsed += 1;
+        self.summary.peak_buffered = self.summary.peak_buffered.max(self.buffer.len());
+        if self.buffer.len() >= max_buffered {
+            self.flush(conn)?;
+        }
+        Ok(())
+    }
+
+    fn flush(&mut self, conn: &mut Connection) -> Result<(), SocialError> {
+        if self.buffer.is_empty() {
+            return Ok(());
+        }
+        let posts = std::mem::take(&mut self.buffer);
+        let stored = store_social_posts(conn, &self.summary.account_id, posts)?;
+        self.summary.stored.inserted += stored.inserted;
+        self.summary.stored.updated += stored.updated;
+        self.summary.stored.skipped += stored.skipped;
+        self.summary.flushes += 1;
+        Ok(())
+    }
+}
+
+/// Open extraction sessions. Not synchronized; the caller keeps it behind a
+/// lock.
+pub struct ExtractionSessions {
+    sessions: HashMap<String, ExtractionSession>,
+    max_buffered: usize,
+    idle_timeout: Duration,
+}
+
+impl Default for ExtractionSessions {
+    fn default() -> Self {
+        Self::new(DEFAULT_MAX_BUFFERED_POSTS, DEFAULT_SESSION_IDLE_TIMEOUT)
+    }
+}
+
+impl ExtractionSessions {
+    pub fn new(max_buffered: usize, idle_timeout: Duration) -> Self {
+        Self {
+            sessions: HashMap::new(),
+            max_buffered: max_buffered.max(1),
+            idle_timeout,
+        }
+    }
+
+    /// Start a session for posts of `account_id`; returns its id
+    pub fn begin_extraction_session(&mut self, account_id: &str) -> String {
+        self.collect_idle_sessions();
+        let session_id = Ulid::new().to_string();
+        self.sessions.insert(
+            session_id.clone(),
+            ExtractionSession {
+                buffer: Vec::new(),
+                summary: ExtractionSummary {
+                    session_id: session_id.clone(),
+                    account_id: account_id.to_string(),
+                    ..Default::default()
+                },
+                last_activity: Instant::now(),
+            },
+        );
+        log::debug!(
+            "[Social::Ingest] Started session {} for account {}",
+            session_id,
+            account_id
+        );
+        session_id
+    }
+
+    /// Parse one chunk into the session, writing posts whenever the buffer
+    /// fills up. Only an unknown session or a failed write is an error.
+    pub fn ingest_chunk(
+        &mut self,
+        conn: &mut Connection,
+        session_id: &str,
+        payload: &str,
+    ) -> Result<ChunkOutcome, SocialError> {
+        self.collect_idle_sessions();
+        let max_buffered = self.max_buffered;
+        let session = self
+            .sessions
+            .get_mut(session_id)
+            .ok_or_else(|| SocialError::NotFound(format!("Extraction session {}", session_id)))?;
+        session.last_activity = Instant::now();
+        session.summary.chunks += 1;
+        let chunk = session.summary.chunks;
+        let parsed_before = session.summary.posts_parsed;
+
+        // A failed write stops parsing and fails the call; a parse error only
+        // marks the chunk
+        let mut write_error = None;
+        let parse_error = parse_chunk(payload, |post| {
+            session
+                .push(conn, post, max_buffered)
+                .map_err(|e| write_error = Some(e))
+        });
+        if let Some(e) = write_error {
+            return Err(e);
+        }
+        if let Some(error) = &parse_error {
+            log::warn!(
+                "[Social::Ingest] Chunk {} of session {} is malformed: {}",
+                chunk,
+                session_id,
+                error
+            );
+            session.summary.failed_chunks.push(ChunkFailure {
+                chunk,
+                error: error.clone(),
+            });
+        }
+
+        Ok(ChunkOutcome {
+            chunk,
+            posts_parsed: session.summary.posts_parsed - parsed_before,
+            error: parse_error,
+            buffered: session.buffer.len(),
+        })
+    }
+
+    /// Write what is still buffered and close the session
+    pub fn finish_extraction_session(
+        &mut self,
+        conn: &mut Connection,
+        session_id: &str,
+    ) -> Result<ExtractionSummary, SocialError> {
+        self.collect_idle_sessions();
+        let mut session = self
+            .sessions
+            .remove(session_id)
+            .ok_or_else(|| SocialError::NotFound(format!("Extraction session {}", session_id)))?;
+        session.flush(conn)?;
+        log::info!(
+            "[Social::Ingest] Finished session {}: {} chunks ({} failed), {} posts, {:?}",
+            session_id,
+            session.summary.chunks,
+            session.summary.failed_chunks.len(),
+            session.summary.posts_parsed,
+            session.summary.stored
+        );
+        Ok(session.summary)
+    }
+
+    /// Drop sessions idle for longer than the timeout, with whatever they
+    /// still buffered; returns their ids
+    pub fn collect_idle_sessions(&mut self) -> Vec<String> {
+        let idle_timeout = self.idle_timeout;
+        let expired: Vec<String> = self
+            .sessions
+            .iter()
+            .filter(|(_, s)| s.last_activity.elapsed() > idle_timeout)
+            .map(|(id, _)| id.clone())
+            .collect();
+        for id in &expired {
+            if let Some(session) = self.sessions.remove(id) {
+                log::warn!(
+                    "[Social::Ingest] Dropping abandoned session {} with {} unwritten posts",
+                    id,
+                    session.buffer.len()
+                );
+            }
+        }
+        expired
+    }
+
+    /// Sessions currently open
+    pub fn len(&self) -> usize {
+        self.sessions.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.sessions.is_empty()
+    }
+}
+
+/// Feed every post of `payload` to `sink`, stopping early if the sink fails.
+/// Returns the parse error, if the payload was malformed.
+fn parse_chunk<F>(payload: &str, mut sink: F) -> Option<String>
+where
+    F: FnMut(SocialPost) -> Result<(), ()>,
+{
+    let trimmed = payload.trim_start();
+    if trimmed.starts_with('[') {
+        let mut de = serde_json::Deserializer::from_str(trimmed);
+        let result = de
+            .deserialize_seq(PostSeq { sink: &mut sink })
+            .and_then(|()| de.end());
+        return match result {
+            Ok(()) => None,
+            // The sink already recorded why it stopped
+            Err(e) if e.to_string().starts_with(SINK_STOPPED) => None,
+            Err(e) => Some(e.to_string()),
+        };
+    }
+
+    // One post per line; a bad line doesn't stop the rest of the chunk
+    let mut error = None;
+    for (i, line) in payload.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+        match serde_json::from_str::<SocialPost>(line) {
+            Ok(post) => {
+                if sink(post).is_err() {
+                    return None;
+                }
+            }
+            Err(e) => {
+                error.get_or_insert_with(|| format!("line {}: {}", i + 1, e));
+            }
+        }
+    }
+    error
+}
+
+const SINK_STOPPED: &str = "post sink stopped";
+
+/// Deserializes a JSON array one post at a time, handing each to the sink
+/// instead of collecting them
+struct PostSeq<'a, F> {
+    sink: &'a mut F,
+}
+
+impl<'de, F> Visitor<'de> for PostSeq<'_, F>
+where
+    F: FnMut(SocialPost) -> Result<(), ()>,
+{
+    type Value = ();
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("an array of social posts")
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<(), A::Error> {
+        while let Some(post) = seq.next_element::<SocialPost>()? {
+            (self.sink)(post).map_err(|()| de::Error::custom(SINK_STOPPED))?;
+        }
+        Ok(())
+    }
+}
@@ -3,6 +3,13 @@
 //! Handles archival, pruning, and optimization of social post data.
 //! Designed to be called on startup and periodically to keep the database performant.
 
+use super::post::content_hash;
+use crate::blob::{BlobError, BlobGcResult, BlobGcTask};
+use crate::db::integrity::{
+    check_vault_with_mode, is_startup_integrity_check_enabled, IntegrityCheckMode,
+    VaultIntegrityReport,
+};
+use crate::sync::retention::{prune_sync_logs, SyncLogPruneResult, SyncLogRetentionPolicy};
 use rusqlite::{params, Connection, Result};
 use std::time::{SystemTime, UNIX_EPOCH};
 
@@ -12,11 +19,21 @@ pub const DEFAULT_RETENTION_DAYS: u64 = 7;
 /// Maximum posts to archive in a single batch (to avoid long locks)
 pub const BATCH_SIZE: usize = 1000;
 
+/// Unreferenced blobs younger than this are kept (e.g. uploads not attached yet)
+pub const BLOB_GC_GRACE_SECONDS: i64 = 24 * 60 * 60;
+
 /// Maintenance result with statistics
 #[derive(Debug, Clone)]
 pub struct MaintenanceResult {
     pub posts_archived: usize,
     pub posts_deleted: usize,
+    pub blobs_collected: usize,
+    pub blob_bytes_reclaimed: i64,
+    /// Quick integrity check, when enabled with
+    /// [`crate::db::integrity::set_startup_integrity_check`]
+    pub integrity: Option<VaultIntegrityReport>,
+    /// Sync bookkeeping pruned with the default retention policy
+    pub sync_logs: SyncLogPruneResult,
     pub duration_ms: u64,
 }
 
@@ -39,26 +56,85 @@ pub fn run_startup_maintenance(
     // Clean up orphaned archives (posts archived more than 30 days ago)
     let deleted = cleanup_old_archives(conn, 30)?;
 
+    // Drop attachments nothing points to anymore
+    let blob_gc = collect_unreferenced_blobs(conn)?;
+
+    let integrity = run_startup_integrity_check(conn);
+
+    let sync_logs = run_sync_log_pruning(conn);
+
     let duration = start.elapsed().as_millis() as u64;
 
     let result = MaintenanceResult {
         posts_archived: archived,
         posts_deleted: deleted,
+        blobs_collected: blob_gc.blobs_removed,
+        blob_bytes_reclaimed: blob_gc.bytes_reclaimed,
+        integrity,
+        sync_logs,
         duration_ms: duration,
     };
 
     log::info!(
-        "[maintenance] Complete - Archived: {}, Deleted: {}, Duration: {}ms",
+        "[maintenance] Complete - Archived: {}, Deleted: {}, Blobs collected: {}, Sync log rows reclaimed: {}, Duration: {}ms",
         result.posts_archived,
         result.posts_deleted,
+        result.blobs_collected,
+        result.sync_logs.rows_reclaimed,
         result.duration_ms
     );
 
     Ok(result)
 }
 
+/// Quick integrity check, if enabled. Problems are only reported here; fixing
+/// them is left to the user through `repair_vault`.
+fn run_startup_integrity_check(conn: &Connection) -> Option<VaultIntegrityReport> {
+    // Off when unset, or when there is no settings table to read it from
+    if !is_startup_integrity_check_enabled(conn).unwrap_or(false) {
+        return None;
+    }
+    match check_vault_with_mode(conn, IntegrityCheckMode::Quick) {
+        Ok(report) => {
+            if !report.is_healthy() {
+                log::warn!(
+                    "[maintenance] Integrity check found {} issues",
+                    report.issues.len()
+                );
+            }
+            Some(report)
+        }
+        Err(e) => {
+            log::warn!("[maintenance] Integrity check failed: {}", e);
+            None
+        }
+    }
+}
+
+/// Prune sync logs with the default policy. Bookkeeping that can't be pruned
+/// is left for the next startup rather than failing maintenance.
+fn run_sync_log_pruning(conn: &Connection) -> SyncLogPruneResult {
+    prune_sync_logs(conn, &SyncLogRetentionPolicy::default()).unwrap_or_else(|e| {
+        log::warn!("[maintenance] Sync log pruning failed: {}", e);
+        SyncLogPruneResult::default()
+    })
+}
+
+/// Garbage-collect unreferenced blob records with [`BlobGcTask`]
+fn collect_unreferenced_blobs(conn: &Connection) -> Result<BlobGcResult> {
+    match BlobGcTask::collect(conn) {
+        Ok(result) => Ok(result),
+        Err(BlobError::Database(e)) => Err(e),
+        Err(e) => {
+            log::warn!("[maintenance] Blob GC failed: {}", e);
+            Ok(BlobGcResult::default())
+        }
+    }
+}
+
 /// Prune social posts older than the specified retention period.
 /// Moves essential data to `social_post_archive` and deletes the original record.
+/// Posts archived to a note are kept.
 pub fn prune_old_posts(conn: &mut Connection, retention_days: u64) -> Result<usize> {
     let now = SystemTime::now()
         .duration_since(UNIX_EPOCH)
@@ -77,7 +153,8 @@ pub fn prune_old_posts(conn: &mut Connection, retention_days: u64) -> Result<usi
 
     // Count posts to be archived
     let count: i64 = tx.query_row(
-        "SELECT COUNT(*) FROM social_post WHERE timestamp < ?",
+        "SELECT COUNT(*) FROM social_post
+         WHERE timestamp < ? AND id NOT IN (SELECT post_id FROM social_post_note)",
         params![cutoff_timestamp],
         |row| row.get(0),
     )?;
@@ -93,7 +170,7 @@ pub fn prune_old_posts(conn: &mut Connection, retention_days: u64) -> Result<usi
         "INSERT OR IGNORE INTO social_post_archive (id, account_id, platform, author, content, timestamp, archived_at)
          SELECT id, account_id, platform, author, content, timestamp, ?
          FROM social_post
-         WHERE timestamp < ?
+         WHERE timestamp < ? AND id NOT IN (SELECT post_id FROM social_post_note)
          LIMIT ?",
         params![now, cutoff_timestamp, BATCH_SIZE as i64],
     )?;
@@ -118,6 +195,122 @@ pub fn prune_old_posts(conn: &mut Connection, retention_days: u64) -> Result<usi
     Ok(rows_deleted)
 }
 
+/// Merge social posts that were stored more than once in a space.
+///
+/// Duplicates share (platform, platform_post_id), or (platform, content hash)
+/// when they have no post id. The first-seen copy survives: it keeps its id and
+/// first_seen_at, takes the most recently fetched engagement and content, and
+/// gains every category assigned to a duplicate. Returns the number of posts removed.
+pub fn dedup_existing_posts(conn: &mut Connection, space_id: &str) -> Result<usize> {
+    log::info!(
+        "[maintenance] Deduplicating social posts in space {}",
+        space_id
+    );
+
+    let tx = conn.transaction()?;
+
+    // Posts stored before content hashes existed
+    let unhashed = {
+        let mut stmt = tx.prepare(
+            "SELECT p.id, p.platform, p.author, p.author_handle, p.content, p.reply_to
+             FROM social_post p
+             JOIN social_account a ON a.id = p.account_id
+             WHERE a.space_id = ?1 AND p.content_hash IS NULL",
+        )?;
+        let rows = stmt.query_map([space_id], |row| {
+            let platform: String = row.get(1)?;
+            let author: String = row.get(2)?;
+            let author_handle: Option<String> = row.get(3)?;
+            let content: Option<String> = row.get(4)?;
+            let reply_to: Option<String> = row.get(5)?;
+            Ok((
+                row.get::<_, String>(0)?,
+                content_hash(
+                    &platform,
+                    &author,
+                    author_handle.as_deref(),
+                    content.as_deref(),
+                    reply_to.as_deref(),
+                ),
+            ))
+        })?;
+        rows.collect::<Result<Vec<_>>>()?
+    };
+    for (id, hash) in unhashed {
+        tx.execute(
+            "UPDATE social_post SET content_hash = ?1 WHERE id = ?2",
+            params![hash, id],
+        )?;
+    }
+
+    // Oldest first within each key, so the first row of a group is the survivor
+    let posts = {
+        let mut stmt = tx.prepare(
+            "SELECT p.id, p.platform,
+                    COALESCE(NULLIF(p.platform_post_id, ''), '#' || p.content_hash) AS dedup_key,
+                    p.fetched_at
+             FROM social_post p
+             JOIN social_account a ON a.id = p.account_id
+             WHERE a.space_id = ?1
+             ORDER BY p.platform, dedup_key, COALESCE(p.first_seen_at, p.fetched_at), p.rowid",
+        )?;
+        let rows = stmt.query_map([space_id], |row| {
+            Ok((
+                row.get::<_, String>(0)?,
+                row.get::<_, String>(1)?,
+                row.get::<_, Option<String>>(2)?,
+                row.get::<_, i64>(3)?,
+            ))
+        })?;
+        rows.collect::<Result<Vec<_>>>()?
+    };
+
+    let mut removed = 0;
+    for group in posts.chunk_by(|a, b| a.1 == b.1 && a.2 == b.2) {
+        if group.len() < 2 || group[0].2.is_none() {
+            continue;
+        }
+        let keeper = &group[0].0;
+        let freshest = &group
+            .iter()
+            .max_by_key(|(_, _, _, fetched_at)| *fetched_at)
+            .expect("group is not empty")
+            .0;
+
+        if freshest != keeper {
+            tx.execute(
+                "UPDATE social_post
+                 SET (content, content_html, media_urls_json, likes, shares, comments, views,
+                      fetched_at, raw_json) =
+                     (SELECT content, content_html, media_urls_json, likes, shares, comments,
+                             views, fetched_at, raw_json
+                      FROM social_post WHERE id = ?2)
+                 WHERE id = ?1",
+                params![keeper, freshest],
+            )?;
+        }
+
+        for (duplicate, ..) in &group[1..] {
+            tx.execute(
+                "INSERT OR IGNORE INTO social_post_category (post_id, category_id, assigned_at, assigned_by)
+                 SELECT ?1, category_id, assigned_at, assigned_by
+                 FROM social_post_category WHERE post_id = ?2",
+                params![keeper, duplicate],
+            )?;
+            tx.execute(
+                "DELETE FROM social_post_category WHERE post_id = ?1",
+                [duplicate],
+            )?;
+            removed += tx.execute("DELETE FROM social_post WHERE id = ?1", [duplicate])?;
+        }
+    }
+
+    tx.commit()?;
+
+    log::info!("[maintenance] Removed {} duplicate social posts", removed);
+    Ok(removed)
+}
+
 /// Clean up old archive entries that are beyond the archive retention period
 pub fn cleanup_old_archives(conn: &mut Connection, archive_retention_days: u64) -> Result<usize> {
     let now = SystemTime::now()
@@ -222,6 +415,17 @@ mod tests {
         )
         .expect("Failed to create social_post_archive table");
 
+        conn.execute(
+            "CREATE TABLE social_post_note (
+                post_id TEXT PRIMARY KEY,
+                note_id TEXT NOT NULL,
+                archived_at INTEGER NOT NULL,
+                updated_at INTEGER NOT NULL
+            )",
+            [],
+        )
+        .expect("Failed to create social_post_note table");
+
         conn
     }
 
@@ -282,5 +486,6 @@ mod tests {
 
         assert_eq!(result.posts_archived, 0);
         assert_eq!(result.posts_deleted, 0);
+        assert_eq!(result.blobs_collected, 0);
     }
 }
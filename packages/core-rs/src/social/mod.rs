@@ -5,18 +5,23 @@
 
 pub mod account;
 pub mod analytics;
+pub mod archive;
 pub mod backup;
+pub mod backup_schedule;
 pub mod category;
 pub mod focus;
 pub mod inference;
+pub mod ingest;
 pub mod intelligence;
 pub mod maintenance;
 pub mod post;
 pub mod processing;
+pub mod rules;
 pub mod selector_verification;
 pub mod stream_processor;
 pub mod sync;
 pub mod timeline;
+pub mod topics;
 pub mod webview;
 
 // Re-export commonly used types
@@ -25,11 +30,28 @@ pub use account::{
     get_social_accounts, update_last_sync, update_social_account, SocialAccount, SocialError,
 };
 
+pub use archive::{
+    archive_category_to_notes, archive_post_to_note, get_archived_note, ArchiveOptions,
+    DEFAULT_ARCHIVE_TAG,
+};
+
 pub use backup::{Backup, BackupError, BackupMetadata, BackupService};
 
+pub use backup_schedule::{
+    apply_retention, get_backup_policy, get_backup_runs, run_scheduled_backup, set_backup_policy,
+    should_run_backup, BackupPolicy, BackupRun, BackupSkipReason, ScheduledBackupOutcome,
+};
+
 pub use post::{
     delete_old_posts, get_post_statistics, get_social_posts, search_social_posts,
-    store_social_posts, Engagement, SocialPost,
+    store_social_posts, Engagement, SocialPost, StorePostsResult,
+};
+
+pub use maintenance::dedup_existing_posts;
+
+pub use ingest::{
+    ChunkFailure, ChunkOutcome, ExtractionSessions, ExtractionSummary, DEFAULT_MAX_BUFFERED_POSTS,
+    DEFAULT_SESSION_IDLE_TIMEOUT,
 };
 
 pub use category::{
@@ -38,8 +60,14 @@ pub use category::{
 };
 
 pub use timeline::{
-    get_category_timeline, get_platform_timeline, get_timeline_stats, get_unified_timeline,
-    TimelineFilters, TimelinePost, TimelineStats,
+    get_category_timeline, get_platform_timeline, get_timeline_new_count, get_timeline_stats,
+    get_unified_timeline, get_unified_timeline_page, TimelineCursor, TimelineFilters, TimelinePage,
+    TimelinePost, TimelineStats,
+};
+
+pub use topics::{
+    cluster_timeline_topics, get_post_topic, get_topic_overview, TopicClusterOptions,
+    TopicClusteringResult, TopicOverview,
 };
 
 pub use webview::{
@@ -55,8 +83,10 @@ pub use sync::{
 };
 
 pub use analytics::{
-    get_analytics_overview, AnalyticsOverview, CategoryStats, EngagementStats, PlatformStats,
-    TimeSeriesPoint, TopPost,
+    get_analytics_overview, get_engagement_timeseries, get_posting_time_analysis,
+    AnalyticsOverview, CategoryStats, EngagementOptions, EngagementStats, PlatformStats,
+    PostingTimeAnalysis, PostingTimeCell, TimeBucket, TimeSeriesPoint, TopPost,
+    MIN_POSTING_TIME_SAMPLES,
 };
 
 pub use intelligence::{
@@ -69,12 +99,23 @@ pub use inference::{InferenceEngine, InferenceError, InferenceResult};
 pub use focus::{
     activate_focus_mode, create_automation_rule, create_focus_mode, create_preset_focus_modes,
     deactivate_all_focus_modes, delete_focus_mode, get_automation_rules, get_focus_modes,
-    is_platform_blocked, toggle_automation_rule, ActionType, AutomationRule, FocusMode, TimeLimit,
+    get_platform_usage_at, get_platform_usage_today, get_time_limits, get_usage_timezone,
+    is_platform_blocked, is_platform_blocked_at, record_platform_usage, record_platform_usage_at,
+    remove_time_limit, set_time_limit, set_usage_timezone, toggle_automation_rule, ActionType,
+    AutomationRule, FiredAutomation, FocusMode, PlatformAccess, PlatformUsage, TimeLimit,
     TriggerType,
 };
 
+pub use rules::{
+    apply_rules, create_category_rule, delete_category_rule, dry_run_rules, export_rules,
+    get_category_rules, import_rules, set_category_rule_enabled, CategoryRule, ExportedRule,
+    RuleCondition, RuleExport, RuleMatchMode, RulePlanEntry, RuleRunResult,
+};
+
 pub use maintenance::{prune_old_posts, run_startup_maintenance, MaintenanceResult};
 
 pub use selector_verification::{
-    get_bundled_selectors, load_verified_selectors, SignedSelectors, VerificationError,
+    fetch_selector_update, get_active_selectors, get_bundled_selectors, install_selector_update,
+    load_verified_selectors, record_extraction_result, rollback_selectors, ActiveSelectors,
+    SelectorSource, SelectorUpdate, SignedSelectors, VerificationError,
 };
@@ -1,9 +1,11 @@
 use chrono::Utc;
-use rusqlite::{params, Connection};
+use rusqlite::{params, Connection, OptionalExtension};
 use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
 use ulid::Ulid;
 
 use super::account::SocialError;
+use crate::db::with_bulk_import;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct SocialPost {
@@ -31,15 +33,118 @@ pub struct Engagement {
     pub views: Option<i64>,
 }
 
+/// Outcome of storing a batch of posts
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StorePostsResult {
+    pub inserted: usize,
+    pub updated: usize,
+    pub skipped: usize,
+}
+
+/// Identity of a post for platforms without a stable post id.
+///
+/// Built from who posted what (and in reply to what), so the same post seen on
+/// a later refresh maps to the same hash even though engagement has changed.
+pub(crate) fn content_hash(
+    platform: &str,
+    author: &str,
+    author_handle: Option<&str>,
+    content: Option<&str>,
+    reply_to: Option<&str>,
+) -> String {
+    let mut hasher = Sha256::new();
+    for part in [
+        platform,
+        author_handle.unwrap_or(author),
+        content.unwrap_or("").trim(),
+        reply_to.unwrap_or(""),
+    ] {
+        hasher.update(part.as_bytes());
+        hasher.update([0u8]);
+    }
+    hex::encode(hasher.finalize())
+}
+
+/// Stored values of a post that a re-sync may change
+struct ExistingPost {
+    id: String,
+    content: Option<String>,
+    content_html: Option<String>,
+    media_urls_json: Option<String>,
+    engagement: Engagement,
+}
+
+/// Find a post already stored in the account's space, by platform id or content hash
+fn find_existing_post(
+    tx: &Connection,
+    account_id: &str,
+    platform: &str,
+    platform_post_id: Option<&str>,
+    hash: &str,
+) -> Result<Option<ExistingPost>, SocialError> {
+    let (key_clause, key) = match platform_post_id {
+        Some(id) => ("p.platform_post_id = ?2", id),
+        None => ("p.platform_post_id IS NULL AND p.content_hash = ?2", hash),
+    };
+    let sql = format!(
+        "SELECT p.id, p.content, p.content_html, p.media_urls_json,
+                p.likes, p.shares, p.comments, p.views
+         FROM social_post p
+         JOIN social_account a ON a.id = p.account_id
+         WHERE p.platform = ?1 AND {}
+           AND a.space_id = (SELECT space_id FROM social_account WHERE id = ?3)
+         ORDER BY p.first_seen_at ASC
+         LIMIT 1",
+        key_clause
+    );
+
+    let existing = tx
+        .prepare_cached(&sql)?
+        .query_row(params![platform, key, account_id], |row| {
+            Ok(ExistingPost {
+                id: row.get(0)?,
+                content: row.get(1)?,
+                content_html: row.get(2)?,
+                media_urls_json: row.get(3)?,
+                engagement: Engagement {
+                    likes: row.get(4)?,
+                    shares: row.get(5)?,
+                    comments: row.get(6)?,
+                    views: row.get(7)?,
+                },
+            })
+        })
+        .optional()?;
+    Ok(existing)
+}
+
+/// Whether a re-synced post carries anything new; missing values never count as changes
+fn post_changed(existing: &ExistingPost, post: &SocialPost, media_json: &str) -> bool {
+    fn differs<T: PartialEq>(new: &Option<T>, old: &Option<T>) -> bool {
+        new.is_some() && new != old
+    }
+
+    differs(&post.engagement.likes, &existing.engagement.likes)
+        || differs(&post.engagement.shares, &existing.engagement.shares)
+        || differs(&post.engagement.comments, &existing.engagement.comments)
+        || differs(&post.engagement.views, &existing.engagement.views)
+        || differs(&post.content, &existing.content)
+        || differs(&post.content_html, &existing.content_html)
+        || (!post.media_urls.is_empty() && existing.media_urls_json.as_deref() != Some(media_json))
+}
+
 /// Store social posts extracted from platforms
 ///
-/// This function handles batch insertion of posts with deduplication.
-/// Posts with the same (account_id, platform_post_id) are skipped.
+/// Posts are matched against what the space already holds by
+/// (platform, platform_post_id), or by content hash when the platform gives no
+/// id. A match is updated in place with fresh engagement and edited content,
+/// keeping its id, first_seen_at and category assignments; unchanged matches
+/// are skipped.
 pub fn store_social_posts(
     conn: &mut Connection,
     account_id: &str,
     posts: Vec<SocialPost>,
-) -> Result<usize, SocialError> {
+) -> Result<StorePostsResult, SocialError> {
     const MAX_MEDIA_JSON_SIZE: usize = 100_000; // 100KB limit
     const MAX_RAW_JSON_SIZE: usize = 500_000; // 500KB limit
 
@@ -51,153 +156,212 @@ pub fn store_social_posts(
     );
 
     let now = Utc::now().timestamp_millis();
-    let mut stored = 0;
 
-    // Use safe transaction for atomicity with automatic rollback on error
-    log::debug!("[Social::Post] Starting transaction for batch insert");
-    let tx = conn.transaction().map_err(|e| {
-        log::error!("[Social::Post] Failed to start transaction: {}", e);
-        e
-    })?;
+    // One bulk write: atomic, and the search index is filled once at the end
+    log::debug!("[Social::Post] Starting bulk write for batch insert");
+    let result = with_bulk_import(conn, |tx| {
+        let mut result = StorePostsResult::default();
 
-    for post in posts {
-        let post_id = Ulid::new().to_string();
-
-        // Cap media JSON size to prevent OOM
-        let mut media_json = serde_json::to_string(&post.media_urls)?;
-        if media_json.len() > MAX_MEDIA_JSON_SIZE {
-            log::warn!("Post media JSON exceeds size limit, truncating media list");
-            let truncated_media = post
-                .media_urls
-                .iter()
-                .take(100) // Limit to 100 media items
-                .cloned()
-                .collect::<Vec<_>>();
-            media_json = serde_json::to_string(&truncated_media)?;
+        for post in posts {
+            // Cap media JSON size to prevent OOM
+            let mut media_json = serde_json::to_string(&post.media_urls)?;
             if media_json.len() > MAX_MEDIA_JSON_SIZE {
-                continue; // Skip post if still too large
+                log::warn!("Post media JSON exceeds size limit, truncating media list");
+                let truncated_media = post
+                    .media_urls
+                    .iter()
+                    .take(100) // Limit to 100 media items
+                    .cloned()
+                    .collect::<Vec<_>>();
+                media_json = serde_json::to_string(&truncated_media)?;
+                if media_json.len() > MAX_MEDIA_JSON_SIZE {
+                    result.skipped += 1;
+                    continue; // Skip post if still too large
+                }
             }
-        }
-
-        // Cap raw JSON size using a sanitized snapshot that excludes internal fields
-        #[derive(Serialize)]
-        struct RawSnapshot<'a> {
-            platform: &'a str,
-            platform_post_id: &'a Option<String>,
-            author: &'a str,
-            author_handle: &'a Option<String>,
-            content: &'a Option<String>,
-            content_html: &'a Option<String>,
-            media_urls: &'a [String],
-            timestamp: i64,
-            engagement: &'a Engagement,
-            post_type: &'a Option<String>,
-            reply_to: &'a Option<String>,
-        }
-        let snapshot = RawSnapshot {
-            platform: &post.platform,
-            platform_post_id: &post.platform_post_id,
-            author: &post.author,
-            author_handle: &post.author_handle,
-            content: &post.content,
-            content_html: &post.content_html,
-            media_urls: &post.media_urls,
-            timestamp: post.timestamp,
-            engagement: &post.engagement,
-            post_type: &post.post_type,
-            reply_to: &post.reply_to,
-        };
-        let raw_json = serde_json::to_string(&snapshot)?;
-        if raw_json.len() > MAX_RAW_JSON_SIZE {
-            log::warn!("[Social::Post] Post snapshot exceeds size limit, skipping");
-            continue;
-        }
 
-        // Skip posts with missing external ID to ensure proper deduplication
-        if post.platform_post_id.as_deref().unwrap_or("").is_empty() {
-            log::warn!(
-                "[Social::Post] Skipping post with empty platform_post_id for account {}",
-                account_id
-            );
-            continue;
-        }
-
-        // Validate timestamp: must not be in the future or unreasonably old
-        // Allow posts from up to 10 years ago (315360000000 ms)
-        const MAX_AGE_MS: i64 = 315360000000;
-        if post.timestamp > now {
-            log::warn!(
-                "[Social::Post] Skipping post with future timestamp {} (now: {})",
-                post.timestamp,
-                now
-            );
-            continue;
-        }
-        if post.timestamp < (now - MAX_AGE_MS) {
-            log::warn!(
-                "[Social::Post] Skipping post with timestamp too old: {} (cutoff: {})",
-                post.timestamp,
-                now - MAX_AGE_MS
-            );
-            continue;
-        }
+            // Cap raw JSON size using a sanitized snapshot that excludes internal fields
+            #[derive(Serialize)]
+            struct RawSnapshot<'a> {
+                platform: &'a str,
+                platform_post_id: &'a Option<String>,
+                author: &'a str,
+                author_handle: &'a Option<String>,
+                content: &'a Option<String>,
+                content_html: &'a Option<String>,
+                media_urls: &'a [String],
+                timestamp: i64,
+                engagement: &'a Engagement,
+                post_type: &'a Option<String>,
+                reply_to: &'a Option<String>,
+            }
+            let snapshot = RawSnapshot {
+                platform: &post.platform,
+                platform_post_id: &post.platform_post_id,
+                author: &post.author,
+                author_handle: &post.author_handle,
+                content: &post.content,
+                content_html: &post.content_html,
+                media_urls: &post.media_urls,
+                timestamp: post.timestamp,
+                engagement: &post.engagement,
+                post_type: &post.post_type,
+                reply_to: &post.reply_to,
+            };
+            let raw_json = serde_json::to_string(&snapshot)?;
+            if raw_json.len() > MAX_RAW_JSON_SIZE {
+                log::warn!("[Social::Post] Post snapshot exceeds size limit, skipping");
+                result.skipped += 1;
+                continue;
+            }
 
-        // Try to insert, skip if duplicate
-        match tx.execute(
-            "INSERT OR IGNORE INTO social_post (
-                id, account_id, platform, platform_post_id,
-                author, author_handle, content, content_html,
-                media_urls_json, timestamp, fetched_at,
-                likes, shares, comments, views,
-                post_type, reply_to, raw_json
-            ) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15, ?16, ?17, ?18)",
-            params![
-                &post_id,
-                account_id,
+            // Without an external ID, the content hash is the only identity a post has
+            let platform_post_id = post.platform_post_id.as_deref().filter(|id| !id.is_empty());
+            if platform_post_id.is_none() && post.content.as_deref().unwrap_or("").trim().is_empty() {
+                log::warn!(
+                    "[Social::Post] Skipping post with neither platform_post_id nor content for account {}",
+                    account_id
+                );
+                result.skipped += 1;
+                continue;
+            }
+            let hash = content_hash(
                 &post.platform,
-                post.platform_post_id,
                 &post.author,
-                post.author_handle,
-                post.content,
-                post.content_html,
-                &media_json,
-                post.timestamp,
-                now,
-                post.engagement.likes,
-                post.engagement.shares,
-                post.engagement.comments,
-                post.engagement.views,
-                post.post_type,
-                post.reply_to,
-                &raw_json,
-            ],
-        ) {
-            Ok(rows) if rows > 0 => {
-                stored += rows;
-                // FTS index is automatically updated by triggers
+                post.author_handle.as_deref(),
+                post.content.as_deref(),
+                post.reply_to.as_deref(),
+            );
+
+            // Validate timestamp: must not be in the future or unreasonably old
+            // Allow posts from up to 10 years ago (315360000000 ms)
+            const MAX_AGE_MS: i64 = 315360000000;
+            if post.timestamp > now {
+                log::warn!(
+                    "[Social::Post] Skipping post with future timestamp {} (now: {})",
+                    post.timestamp,
+                    now
+                );
+                result.skipped += 1;
+                continue;
             }
-            Ok(_) => {
-                // Post already exists (INSERT OR IGNORE), not an error
+            if post.timestamp < (now - MAX_AGE_MS) {
+                log::warn!(
+                    "[Social::Post] Skipping post with timestamp too old: {} (cutoff: {})",
+                    post.timestamp,
+                    now - MAX_AGE_MS
+                );
+                result.skipped += 1;
+                continue;
             }
-            Err(e) => {
-                // Don't log sensitive post IDs, just log sanitized error
-                log::warn!("Failed to store social post: {}", e);
+
+            if let Some(existing) =
+                find_existing_post(tx, account_id, &post.platform, platform_post_id, &hash)?
+            {
+                if !post_changed(&existing, &post, &media_json) {
+                    result.skipped += 1;
+                    continue;
+                }
+
+                // Missing values from the extractor keep what we already have
+                tx.execute(
+                    "UPDATE social_post SET
+                        content = COALESCE(?1, content),
+                        content_html = COALESCE(?2, content_html),
+                        media_urls_json = CASE WHEN ?3 THEN ?4 ELSE media_urls_json END,
+                        likes = COALESCE(?5, likes),
+                        shares = COALESCE(?6, shares),
+                        comments = COALESCE(?7, comments),
+                        views = COALESCE(?8, views),
+                        fetched_at = ?9,
+                        raw_json = ?10,
+                        content_hash = ?11
+                     WHERE id = ?12",
+                    params![
+                        post.content,
+                        post.content_html,
+                        !post.media_urls.is_empty(),
+                        &media_json,
+                        post.engagement.likes,
+                        post.engagement.shares,
+                        post.engagement.comments,
+                        post.engagement.views,
+                        now,
+                        &raw_json,
+                        &hash,
+                        &existing.id,
+                    ],
+                )?;
+                result.updated += 1;
+                continue;
+            }
+
+            let post_id = Ulid::new().to_string();
+
+            // Try to insert, skip if duplicate
+            match tx.execute(
+                "INSERT OR IGNORE INTO social_post (
+                    id, account_id, platform, platform_post_id,
+                    author, author_handle, content, content_html,
+                    media_urls_json, timestamp, fetched_at,
+                    likes, shares, comments, views,
+                    post_type, reply_to, raw_json,
+                    first_seen_at, content_hash
+                ) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15, ?16, ?17, ?18, ?19, ?20)",
+                params![
+                    &post_id,
+                    account_id,
+                    &post.platform,
+                    platform_post_id,
+                    &post.author,
+                    post.author_handle,
+                    post.content,
+                    post.content_html,
+                    &media_json,
+                    post.timestamp,
+                    now,
+                    post.engagement.likes,
+                    post.engagement.shares,
+                    post.engagement.comments,
+                    post.engagement.views,
+                    post.post_type,
+                    post.reply_to,
+                    &raw_json,
+                    now,
+                    &hash,
+                ],
+            ) {
+                Ok(rows) if rows > 0 => {
+                    result.inserted += rows;
+                    // FTS index is filled when the bulk write ends
+                }
+                Ok(_) => {
+                    // Post already exists under another key (INSERT OR IGNORE), not an error
+                    result.skipped += 1;
+                }
+                Err(e) => {
+                    // Don't log sensitive post IDs, just log sanitized error
+                    log::warn!("Failed to store social post: {}", e);
+                    result.skipped += 1;
+                }
             }
         }
-    }
 
-    // Commit transaction
-    log::debug!(
-        "[Social::Post] Committing transaction with {} posts stored",
-        stored
-    );
-    tx.commit().map_err(|e| {
-        log::error!("[Social::Post] Failed to commit transaction: {}", e);
+        log::debug!(
+            "[Social::Post] Committing batch: {} inserted, {} updated, {} skipped",
+            result.inserted,
+            result.updated,
+            result.skipped
+        );
+        Ok::<_, SocialError>(result)
+    })
+    .map_err(|e| {
+        log::error!("[Social::Post] Failed to store batch: {}", e);
         e
     })?;
 
     // Update account last_sync timestamp
-    if stored > 0 {
+    if result.inserted + result.updated > 0 {
         log::debug!(
             "[Social::Post] Updating last_sync for account {}",
             account_id
@@ -206,13 +370,15 @@ pub fn store_social_posts(
     }
 
     log::info!(
-        "[Social::Post] Successfully stored {} out of {} posts for account {}",
-        stored,
+        "[Social::Post] Stored {} posts for account {}: {} inserted, {} updated, {} skipped",
         total_posts,
-        account_id
+        account_id,
+        result.inserted,
+        result.updated,
+        result.skipped
     );
 
-    Ok(stored)
+    Ok(result)
 }
 
 /// Get social posts for an account
@@ -365,7 +531,7 @@ pub fn search_social_posts(
     Ok(result)
 }
 
-/// Delete old posts to manage storage
+/// Delete old posts to manage storage. Posts archived to a note are kept.
 pub fn delete_old_posts(
     conn: &Connection,
     account_id: &str,
@@ -380,7 +546,8 @@ pub fn delete_old_posts(
     let deleted = conn
         .execute(
             "DELETE FROM social_post
-         WHERE account_id = ?1 AND timestamp < ?2",
+         WHERE account_id = ?1 AND timestamp < ?2
+           AND id NOT IN (SELECT post_id FROM social_post_note)",
             params![account_id, before_timestamp],
         )
         .map_err(|e| {
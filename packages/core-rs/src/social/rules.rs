@@ -0,0 +1,647 @@
+/**
+ * Category Rules Engine
+ *
+ * Compound auto-categorization rules: ALL/ANY groups over post conditions,
+ * evaluated in priority order. Rules can be previewed with a dry run before
+ * being applied, and exported to JSON for use in another vault.
+ */
+use chrono::Utc;
+use rusqlite::{params, Connection, OptionalExtension};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeSet, HashMap};
+use ulid::Ulid;
+
+use super::account::SocialError;
+use super::category::{create_category, get_categories};
+
+/// `assigned_by` value written for assignments made by the rules engine
+pub const RULE_ASSIGNMENT_SOURCE: &str = "rule";
+
+/// `assigned_by` values that mark an assignment as made by hand
+const MANUAL_ASSIGNMENT_SOURCES: [&str; 2] = ["manual", "user"];
+
+/// Version of the rule export format
+const RULE_EXPORT_VERSION: u32 = 1;
+
+/// A condition over a single post, or a group of conditions
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum RuleCondition {
+    /// Every nested condition must match (an empty group matches)
+    All {
+        conditions: Vec<RuleCondition>,
+    },
+    /// At least one nested condition must match (an empty group does not)
+    Any {
+        conditions: Vec<RuleCondition>,
+    },
+    /// Case-insensitive substring of the post text
+    Keyword {
+        keyword: String,
+    },
+    /// Author name or handle, case-insensitive, ignoring a leading '@'
+    AuthorEquals {
+        author: String,
+    },
+    /// Case-insensitive substring of the author name or handle
+    AuthorContains {
+        pattern: String,
+    },
+    PlatformEquals {
+        platform: String,
+    },
+    /// Likes + shares + comments strictly above the threshold
+    EngagementAbove {
+        threshold: i64,
+    },
+    HasMedia,
+    /// Post timestamp inside the window (all bounds inclusive, in ms).
+    /// `last_hours` is relative to the time the rules run.
+    PostedWithin {
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        after: Option<i64>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        before: Option<i64>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        last_hours: Option<i64>,
+    },
+}
+
+/// How many rules may categorize a single post
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum RuleMatchMode {
+    /// Only the highest-priority matching rule applies
+    #[default]
+    FirstMatch,
+    /// Every matching rule applies
+    AllMatches,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CategoryRule {
+    pub id: String,
+    pub space_id: String,
+    pub category_id: String,
+    pub name: String,
+    pub condition: RuleCondition,
+    /// Higher priorities are evaluated first
+    pub priority: i32,
+    pub enabled: bool,
+    pub created_at: i64,
+}
+
+/// The change a rule run makes (or would make) to one post
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct RulePlanEntry {
+    pub post_id: String,
+    pub add_category_ids: Vec<String>,
+    pub remove_category_ids: Vec<String>,
+    /// Rules that matched the post, in evaluation order
+    pub matched_rule_ids: Vec<String>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub struct RuleRunResult {
+    pub posts_changed: usize,
+    pub assignments_added: usize,
+    pub assignments_removed: usize,
+}
+
+/// Portable form of a rule; the category is referenced by name
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct ExportedRule {
+    pub name: String,
+    pub category_name: String,
+    pub condition: RuleCondition,
+    pub priority: i32,
+    pub enabled: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct RuleExport {
+    pub version: u32,
+    pub rules: Vec<ExportedRule>,
+}
+
+/// Post fields that rule conditions look at
+struct RulePost {
+    id: String,
+    platform: String,
+    author: String,
+    author_handle: Option<String>,
+    content: String,
+    has_media: bool,
+    timestamp: i64,
+    engagement: i64,
+}
+
+impl RuleCondition {
+    fn matches(&self, post: &RulePost, now: i64) -> bool {
+        match self {
+            RuleCondition::All { conditions } => conditions.iter().all(|c| c.matches(post, now)),
+            RuleCondition::Any { conditions } => conditions.iter().any(|c| c.matches(post, now)),
+            RuleCondition::Keyword { keyword } => {
+                let keyword = keyword.trim().to_lowercase();
+                !keyword.is_empty() && post.content.to_lowercase().contains(&keyword)
+            }
+            RuleCondition::AuthorEquals { author } => {
+                let wanted = normalize_handle(author);
+                !wanted.is_empty()
+                    && (normalize_handle(&post.author) == wanted
+                        || post
+                            .author_handle
+                            .as_deref()
+                            .is_some_and(|h| normalize_handle(h) == wanted))
+            }
+            RuleCondition::AuthorContains { pattern } => {
+                let pattern = pattern.trim().to_lowercase();
+                !pattern.is_empty()
+                    && (post.author.to_lowercase().contains(&pattern)
+                        || post
+                            .author_handle
+                            .as_deref()
+                            .is_some_and(|h| h.to_lowercase().contains(&pattern)))
+            }
+            RuleCondition::PlatformEquals { platform } => {
+                post.platform.eq_ignore_ascii_case(platform.trim())
+            }
+            RuleCondition::EngagementAbove { threshold } => post.engagement > *threshold,
+            RuleCondition::HasMedia => post.has_media,
+            RuleCondition::PostedWithin {
+                after,
+                before,
+                last_hours,
+            } => {
+                after.is_none_or(|a| post.timestamp >= a)
+                    && before.is_none_or(|b| post.timestamp <= b)
+                    && last_hours.is_none_or(|h| post.timestamp >= now - h * 3_600_000)
+            }
+        }
+    }
+}
+
+fn normalize_handle(value: &str) -> String {
+    value.trim().trim_start_matches('@').to_lowercase()
+}
+
+/// Create a compound rule that assigns `category_id` to matching posts
+pub fn create_category_rule(
+    conn: &Connection,
+    space_id: &str,
+    category_id: &str,
+    name: &str,
+    condition: RuleCondition,
+    priority: i32,
+) -> Result<CategoryRule, SocialError> {
+    log::debug!(
+        "[Social::Rules] Creating rule '{}' for category {} in space {}",
+        name,
+        category_id,
+        space_id
+    );
+
+    let category_space: Option<String> = conn
+        .query_row(
+            "SELECT space_id FROM social_category WHERE id = ?1",
+            [category_id],
+            |row| row.get(0),
+        )
+        .optional()?;
+    match category_space {
+        Some(s) if s == space_id => {}
+        Some(_) => {
+            return Err(SocialError::InvalidInput(
+                "Category belongs to another space".into(),
+            ))
+        }
+        None => return Err(SocialError::NotFound("Category not found".into())),
+    }
+
+    let rule = CategoryRule {
+        id: Ulid::new().to_string(),
+        space_id: space_id.to_string(),
+        category_id: category_id.to_string(),
+        name: name.to_string(),
+        condition,
+        priority,
+        enabled: true,
+        created_at: Utc::now().timestamp_millis(),
+    };
+
+    conn.execute(
+        "INSERT INTO social_category_rule (
+            id, space_id, category_id, name, condition_json, priority, enabled, created_at
+        ) VALUES (?1, ?2, ?3, ?4, ?5, ?6, 1, ?7)",
+        params![
+            &rule.id,
+            space_id,
+            category_id,
+            name,
+            serde_json::to_string(&rule.condition)?,
+            priority,
+            rule.created_at
+        ],
+    )?;
+
+    log::info!("[Social::Rules] Created rule {} ({})", rule.id, name);
+    Ok(rule)
+}
+
+/// Compound rules of a space in evaluation order
+pub fn get_category_rules(
+    conn: &Connection,
+    space_id: &str,
+) -> Result<Vec<CategoryRule>, SocialError> {
+    let mut stmt = conn.prepare(
+        "SELECT id, space_id, category_id, name, condition_json, priority, enabled, created_at
+         FROM social_category_rule
+         WHERE space_id = ?1
+         ORDER BY priority DESC, created_at, id",
+    )?;
+
+    let rows = stmt.query_map([space_id], |row| {
+        Ok((
+            CategoryRule {
+                id: row.get(0)?,
+                space_id: row.get(1)?,
+                category_id: row.get(2)?,
+                name: row.get(3)?,
+                condition: RuleCondition::All { conditions: vec![] },
+                priority: row.get(5)?,
+                enabled: row.get::<_, i64>(6)? != 0,
+                created_at: row.get(7)?,
+            },
+            row.get::<_, String>(4)?,
+        ))
+    })?;
+
+    let mut rules = Vec::new();
+    for row in rows {
+        let (mut rule, condition_json) = row?;
+        rule.condition = serde_json::from_str(&condition_json)?;
+        rules.push(rule);
+    }
+    Ok(rules)
+}
+
+pub fn set_category_rule_enabled(
+    conn: &Connection,
+    rule_id: &str,
+    enabled: bool,
+) -> Result<(), SocialError> {
+    let updated = conn.execute(
+        "UPDATE social_category_rule SET enabled = ?1 WHERE id = ?2",
+        params![enabled, rule_id],
+    )?;
+    if updated == 0 {
+        return Err(SocialError::NotFound(format!("Rule {}", rule_id)));
+    }
+    Ok(())
+}
+
+pub fn delete_category_rule(conn: &Connection, rule_id: &str) -> Result<(), SocialError> {
+    conn.execute("DELETE FROM social_category_rule WHERE id = ?1", [rule_id])?;
+    Ok(())
+}
+
+/// Single-condition rules created with `create_auto_rule`, as compound rules
+fn legacy_auto_rules(conn: &Connection, space_id: &str) -> Result<Vec<CategoryRule>, SocialError> {
+    let mut stmt = conn.prepare(
+        "SELECT r.id, r.category_id, r.rule_type, r.pattern, r.priority, r.created_at
+         FROM social_auto_rule r
+         JOIN social_category c ON c.id = r.category_id
+         WHERE c.space_id = ?1",
+    )?;
+
+    let rows = stmt.query_map([space_id], |row| {
+        Ok((
+            row.get::<_, String>(0)?,
+            row.get::<_, String>(1)?,
+            row.get::<_, String>(2)?,
+            row.get::<_, String>(3)?,
+            row.get::<_, i32>(4)?,
+            row.get::<_, i64>(5)?,
+        ))
+    })?;
+
+    let mut rules = Vec::new();
+    for row in rows {
+        let (id, category_id, rule_type, pattern, priority, created_at) = row?;
+        let condition = match rule_type.as_str() {
+            "author_contains" => RuleCondition::AuthorContains {
+                pattern: pattern.clone(),
+            },
+            "content_contains" | "url_contains" => RuleCondition::Keyword {
+                keyword: pattern.clone(),
+            },
+            "hashtag_contains" => RuleCondition::Keyword {
+                keyword: format!("#{}", pattern.trim_start_matches('#')),
+            },
+            "platform_equals" => RuleCondition::PlatformEquals {
+                platform: pattern.clone(),
+            },
+            other => {
+                log::warn!("[Social::Rules] Skipping rule {} with type {}", id, other);
+                continue;
+            }
+        };
+        rules.push(CategoryRule {
+            id,
+            space_id: space_id.to_string(),
+            category_id,
+            name: pattern,
+            condition,
+            priority,
+            enabled: true,
+            created_at,
+        });
+    }
+    Ok(rules)
+}
+
+/// Work out which assignments a rule run would add and remove.
+///
+/// Only assignments made by the rules engine are ever removed, and a category
+/// that is already assigned (by hand or otherwise) is never re-assigned, so
+/// manual categorization always wins.
+fn plan_rules(
+    conn: &Connection,
+    space_id: &str,
+    mode: RuleMatchMode,
+) -> Result<Vec<RulePlanEntry>, SocialError> {
+    let mut rules = get_category_rules(conn, space_id)?;
+    rules.extend(legacy_auto_rules(conn, space_id)?);
+    rules.retain(|r| r.enabled);
+    rules.sort_by(|a, b| {
+        b.priority
+            .cmp(&a.priority)
+            .then(a.created_at.cmp(&b.created_at))
+            .then(a.id.cmp(&b.id))
+    });
+
+    // Current assignments: post -> (category -> assigned_by)
+    let mut assignments: HashMap<String, HashMap<String, String>> = HashMap::new();
+    {
+        let mut stmt = conn.prepare(
+            "SELECT spc.post_id, spc.category_id, COALESCE(spc.assigned_by, '')
+             FROM social_post_category spc
+             JOIN social_category c ON c.id = spc.category_id
+             WHERE c.space_id = ?1",
+        )?;
+        let rows = stmt.query_map([space_id], |row| {
+            Ok((
+                row.get::<_, String>(0)?,
+                row.get::<_, String>(1)?,
+                row.get::<_, String>(2)?,
+            ))
+        })?;
+        for row in rows {
+            let (post_id, category_id, assigned_by) = row?;
+            assignments
+                .entry(post_id)
+                .or_default()
+                .insert(category_id, assigned_by);
+        }
+    }
+
+    let mut stmt = conn.prepare(
+        "SELECT p.id, p.platform, p.author, p.author_handle, COALESCE(p.content, ''),
+                p.media_urls_json, p.timestamp,
+                COALESCE(p.likes, 0) + COALESCE(p.shares, 0) + COALESCE(p.comments, 0)
+         FROM social_post p
+         JOIN social_account a ON p.account_id = a.id
+         WHERE a.space_id = ?1
+         ORDER BY p.timestamp DESC, p.id",
+    )?;
+    let posts = stmt.query_map([space_id], |row| {
+        let media_json: Option<String> = row.get(5)?;
+        let has_media = media_json
+            .and_then(|s| serde_json::from_str::<Vec<String>>(&s).ok())
+            .is_some_and(|urls| !urls.is_empty());
+        Ok(RulePost {
+            id: row.get(0)?,
+            platform: row.get(1)?,
+            author: row.get(2)?,
+            author_handle: row.get(3)?,
+            content: row.get(4)?,
+            has_media,
+            timestamp: row.get(6)?,
+            engagement: row.get(7)?,
+        })
+    })?;
+
+    let now = Utc::now().timestamp_millis();
+    let no_assignments = HashMap::new();
+    let mut plan = Vec::new();
+
+    for post in posts {
+        let post = post?;
+
+        let mut matched_rule_ids = Vec::new();
+        let mut wanted = BTreeSet::new();
+        for rule in &rules {
+            if rule.condition.matches(&post, now) {
+                matched_rule_ids.push(rule.id.clone());
+                wanted.insert(rule.category_id.clone());
+                if mode == RuleMatchMode::FirstMatch {
+                    break;
+                }
+            }
+        }
+
+        let current = assignments.get(&post.id).unwrap_or(&no_assignments);
+        let add_category_ids: Vec<String> = wanted
+            .iter()
+            .filter(|c| !current.contains_key(*c))
+            .cloned()
+            .collect();
+        let mut remove_category_ids: Vec<String> = current
+            .iter()
+            .filter(|(c, by)| by.as_str() == RULE_ASSIGNMENT_SOURCE && !wanted.contains(*c))
+            .map(|(c, _)| c.clone())
+            .collect();
+        remove_category_ids.sort();
+
+        if !add_category_ids.is_empty() || !remove_category_ids.is_empty() {
+            plan.push(RulePlanEntry {
+                post_id: post.id,
+                add_category_ids,
+                remove_category_ids,
+                matched_rule_ids,
+            });
+        }
+    }
+
+    Ok(plan)
+}
+
+/// Preview a rule run: the posts whose categories would change, without writing
+pub fn dry_run_rules(
+    conn: &Connection,
+    space_id: &str,
+    mode: RuleMatchMode,
+) -> Result<Vec<RulePlanEntry>, SocialError> {
+    let plan = plan_rules(conn, space_id, mode)?;
+    log::debug!(
+        "[Social::Rules] Dry run for space {}: {} posts would change",
+        space_id,
+        plan.len()
+    );
+    Ok(plan)
+}
+
+/// Run all enabled rules of a space and write the resulting assignments
+pub fn apply_rules(
+    conn: &Connection,
+    space_id: &str,
+    mode: RuleMatchMode,
+) -> Result<RuleRunResult, SocialError> {
+    let plan = plan_rules(conn, space_id, mode)?;
+    if plan.is_empty() {
+        return Ok(RuleRunResult::default());
+    }
+
+    let now = Utc::now().timestamp_millis();
+    let mut result = RuleRunResult {
+        posts_changed: plan.len(),
+        ..Default::default()
+    };
+
+    conn.execute_batch("SAVEPOINT apply_category_rules")?;
+    let written = (|| -> rusqlite::Result<()> {
+        let mut insert = conn.prepare_cached(
+            "INSERT OR IGNORE INTO social_post_category (
+                post_id, category_id, assigned_at, assigned_by
+            ) VALUES (?1, ?2, ?3, ?4)",
+        )?;
+        let mut delete = conn.prepare_cached(
+            "DELETE FROM social_post_category
+             WHERE post_id = ?1 AND category_id = ?2 AND assigned_by = ?3",
+        )?;
+        for entry in &plan {
+            for category_id in &entry.add_category_ids {
+                result.assignments_added += insert.execute(params![
+                    &entry.post_id,
+                    category_id,
+                    now,
+                    RULE_ASSIGNMENT_SOURCE
+                ])?;
+            }
+            for category_id in &entry.remove_category_ids {
+                result.assignments_removed +=
+                    delete.execute(params![&entry.post_id, category_id, RULE_ASSIGNMENT_SOURCE])?;
+            }
+        }
+        Ok(())
+    })();
+
+    match written {
+        Ok(()) => conn.execute_batch("RELEASE apply_category_rules")?,
+        Err(e) => {
+            conn.execute_batch("ROLLBACK TO apply_category_rules; RELEASE apply_category_rules")?;
+            return Err(e.into());
+        }
+    }
+
+    log::info!(
+        "[Social::Rules] Applied rules in space {}: {} posts changed (+{} / -{})",
+        space_id,
+        result.posts_changed,
+        result.assignments_added,
+        result.assignments_removed
+    );
+    Ok(result)
+}
+
+/// Serialize a space's compound rules to JSON
+pub fn export_rules(conn: &Connection, space_id: &str) -> Result<String, SocialError> {
+    let category_names: HashMap<String, String> = get_categories(conn, space_id)?
+        .into_iter()
+        .map(|c| (c.id, c.name))
+        .collect();
+
+    let rules = get_category_rules(conn, space_id)?
+        .into_iter()
+        .filter_map(|rule| {
+            Some(ExportedRule {
+                category_name: category_names.get(&rule.category_id)?.clone(),
+                name: rule.name,
+                condition: rule.condition,
+                priority: rule.priority,
+                enabled: rule.enabled,
+            })
+        })
+        .collect();
+
+    Ok(serde_json::to_string_pretty(&RuleExport {
+        version: RULE_EXPORT_VERSION,
+        rules,
+    })?)
+}
+
+/// Import rules exported with `export_rules`, creating missing categories by name.
+/// Returns the number of rules imported.
+pub fn import_rules(conn: &Connection, space_id: &str, json: &str) -> Result<usize, SocialError> {
+    let export: RuleExport = serde_json::from_str(json)?;
+    if export.version > RULE_EXPORT_VERSION {
+        return Err(SocialError::InvalidInput(format!(
+            "Unsupported rule export version {}",
+            export.version
+        )));
+    }
+
+    let mut categories: HashMap<String, String> = get_categories(conn, space_id)?
+        .into_iter()
+        .map(|c| (c.name.to_lowercase(), c.id))
+        .collect();
+
+    conn.execute_batch("SAVEPOINT import_category_rules")?;
+    let imported = (|| -> Result<usize, SocialError> {
+        for exported in &export.rules {
+            let key = exported.category_name.to_lowercase();
+            let category_id = match categories.get(&key) {
+                Some(id) => id.clone(),
+                None => {
+                    let category =
+                        create_category(conn, space_id, &exported.category_name, None, None, None)?;
+                    categories.insert(key, category.id.clone());
+                    category.id
+                }
+            };
+            let rule = create_category_rule(
+                conn,
+                space_id,
+                &category_id,
+                &exported.name,
+                exported.condition.clone(),
+                exported.priority,
+            )?;
+            if !exported.enabled {
+                set_category_rule_enabled(conn, &rule.id, false)?;
+            }
+        }
+        Ok(export.rules.len())
+    })();
+
+    match imported {
+        Ok(count) => {
+            conn.execute_batch("RELEASE import_category_rules")?;
+            log::info!(
+                "[Social::Rules] Imported {} rules into space {}",
+                count,
+                space_id
+            );
+            Ok(count)
+        }
+        Err(e) => {
+            conn.execute_batch("ROLLBACK TO import_category_rules; RELEASE import_category_rules")?;
+            Err(e)
+        }
+    }
+}
+
+/// Whether an `assigned_by` value marks a manual assignment
+pub(crate) fn is_manual_source(assigned_by: &str) -> bool {
+    MANUAL_ASSIGNMENT_SOURCES.contains(&assigned_by)
+}
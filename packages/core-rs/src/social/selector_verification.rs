@@ -2,7 +2,14 @@
 //!
 //! Provides cryptographic verification of social selector configurations
 //! to prevent supply chain attacks via tampered selector files.
+//!
+//! Platforms change their markup faster than the app is released, so signed
+//! selector bundles can also be installed into the vault as updates. An update
+//! whose selectors stop working for a platform is rolled back to the bundled
+//! ones for that platform.
 
+use rusqlite::{params, Connection, OptionalExtension};
+use serde::{Deserialize, Serialize};
 use sha2::{Digest, Sha256};
 use thiserror::Error;
 
@@ -30,6 +37,18 @@ pub enum VerificationError {
     MissingSignature,
     #[error("Parsing error: {0}")]
     ParseError(String),
+    #[error("No public key is pinned for selector updates")]
+    NoPinnedKey,
+    #[error("Selector updates must be fetched over HTTPS: {0}")]
+    InsecureUrl(String),
+    #[error("Failed to download selector update: {0}")]
+    Download(String),
+    #[error("Invalid selector version: {0}")]
+    InvalidVersion(String),
+    #[error("Selector update {offered} is not newer than {current}")]
+    NotNewer { current: String, offered: String },
+    #[error("Database error: {0}")]
+    Database(#[from] rusqlite::Error),
 }
 
 /// Signed selector configuration
@@ -117,17 +136,25 @@ fn compute_hash(content: &str) -> String {
 
 /// Verify Ed25519 signature
 fn verify_signature(content: &str, signature: &[u8]) -> Result<(), VerificationError> {
+    verify_signature_with(SELECTOR_PUBLIC_KEY, content, signature)
+}
+
+/// Verify an Ed25519 signature against `public_key`
+fn verify_signature_with(
+    public_key: &[u8],
+    content: &str,
+    signature: &[u8],
+) -> Result<(), VerificationError> {
     use ed25519_dalek::{Signature, Verifier, VerifyingKey};
 
     // Skip if no public key embedded
-    #[allow(clippy::const_is_empty)]
-    if SELECTOR_PUBLIC_KEY.is_empty() || SELECTOR_PUBLIC_KEY.len() != 32 {
+    if public_key.len() != 32 {
         log::warn!("[selectors] No valid public key embedded, skipping signature verification");
         return Err(VerificationError::MissingSignature);
     }
 
     let public_key = VerifyingKey::from_bytes(
-        SELECTOR_PUBLIC_KEY
+        public_key
             .try_into()
             .map_err(|_| VerificationError::InvalidSignature)?,
     )
@@ -189,6 +216,334 @@ pub fn get_bundled_selectors() -> &'static str {
     include_str!("../../config/bundled_selectors.json")
 }
 
+/// Largest selector bundle accepted from an update URL
+const MAX_UPDATE_BYTES: usize = 1024 * 1024;
+
+/// Extractions under updated selectors before their failure rate is judged
+pub const SPIKE_MIN_ATTEMPTS: i64 = 20;
+
+/// Failure rate at which updated selectors count as broken for a platform,
+/// provided it is also well above the rate of earlier selectors
+pub const SPIKE_FAILURE_RATE: f64 = 0.5;
+
+/// How much worse than earlier selectors a broken update has to be
+const SPIKE_MARGIN: f64 = 0.25;
+
+/// Where the selectors in use for a platform come from
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum SelectorSource {
+    Bundled,
+    Update,
+}
+
+/// Selectors the extractor of a platform should use
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct ActiveSelectors {
+    pub platform: String,
+    pub version: String,
+    pub source: SelectorSource,
+    pub selectors: serde_json::Value,
+}
+
+/// An installed selector update
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct SelectorUpdate {
+    pub version: String,
+    pub previous_version: String,
+    pub platforms: Vec<String>,
+}
+
+/// The signed content of a selector bundle
+#[derive(Deserialize)]
+struct SelectorBundle {
+    version: String,
+    platforms: serde_json::Map<String, serde_json::Value>,
+}
+
+fn parse_bundle(selectors: &str) -> Result<SelectorBundle, VerificationError> {
+    let bundle: SelectorBundle = serde_json::from_str(selectors)
+        .map_err(|e| VerificationError::ParseError(e.to_string()))?;
+    parse_version(&bundle.version)?;
+    Ok(bundle)
+}
+
+/// Dotted numeric version, e.g. `1.2.0`
+fn parse_version(version: &str) -> Result<Vec<u64>, VerificationError> {
+    version
+        .trim()
+        .trim_start_matches('v')
+        .split('.')
+        .map(|part| part.parse::<u64>())
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|_| VerificationError::InvalidVersion(version.to_string()))
+}
+
+fn is_newer(version: &str, than: &str) -> Result<bool, VerificationError> {
+    let (mut a, mut b) = (parse_version(version)?, parse_version(than)?);
+    let len = a.len().max(b.len());
+    a.resize(len, 0);
+    b.resize(len, 0);
+    Ok(a > b)
+}
+
+/// Version and signed JSON of the active selector update
+fn active_update(conn: &Connection) -> Result<Option<(String, String)>, VerificationError> {
+    let active = conn
+        .query_row(
+            "SELECT version, signed_json FROM selector_bundle WHERE active = 1",
+            [],
+            |row| Ok((row.get(0)?, row.get(1)?)),
+        )
+        .optional()?;
+    Ok(active)
+}
+
+/// Download a signed selector bundle from `url` and install it if it is
+/// newer than `current_version`
+pub fn fetch_selector_update(
+    conn: &Connection,
+    url: &str,
+    current_version: &str,
+) -> Result<SelectorUpdate, VerificationError> {
+    let parsed =
+        reqwest::Url::parse(url).map_err(|_| VerificationError::InsecureUrl(url.to_string()))?;
+    if parsed.scheme() != "https" {
+        return Err(VerificationError::InsecureUrl(url.to_string()));
+    }
+
+    log::info!("[selectors] Checking for selector update at: {}", url);
+    let client = reqwest::blocking::Client::builder()
+        .https_only(true)
+        .timeout(std::time::Duration::from_secs(30))
+        .build()
+        .map_err(|e| VerificationError::Download(e.to_string()))?;
+    let body = client
+        .get(parsed)
+        .send()
+        .and_then(|response| response.error_for_status())
+        .and_then(|response| response.text())
+        .map_err(|e| VerificationError::Download(e.to_string()))?;
+    if body.len() > MAX_UPDATE_BYTES {
+        return Err(VerificationError::Download(format!(
+            "bundle of {} bytes exceeds the {} byte limit",
+            body.len(),
+            MAX_UPDATE_BYTES
+        )));
+    }
+
+    install_selector_update(conn, &body, current_version, SELECTOR_PUBLIC_KEY)
+}
+
+/// Verify a signed selector bundle against `public_key` and make it the
+/// active selectors. The bundle must be signed, and newer than both
+/// `current_version` and whatever is installed or bundled.
+pub fn install_selector_update(
+    conn: &Connection,
+    signed_json: &str,
+    current_version: &str,
+    public_key: &[u8],
+) -> Result<SelectorUpdate, VerificationError> {
+    if public_key.len() != 32 {
+        log::error!("[selectors] Refusing selector update: no public key pinned");
+        return Err(VerificationError::NoPinnedKey);
+    }
+    let signed = SignedSelectors::parse(signed_json)?;
+    let signature = signed.signature.as_deref().ok_or_else(|| {
+        log::error!("[selectors] Refusing unsigned selector update");
+        VerificationError::MissingSignature
+    })?;
+    if let Err(e) = verify_signature_with(public_key, &signed.selectors, signature) {
+        log::error!(
+            "[selectors] Refusing selector update with a bad signature (hash {})",
+            signed.hash
+        );
+        return Err(e);
+    }
+    let bundle = parse_bundle(&signed.selectors)?;
+
+    // Never go back past what this vault or this build already has
+    let bundled_version = parse_bundle(get_bundled_selectors())?.version;
+    let installed_version = active_update(conn)?.map(|(version, _)| version);
+    let previous_version = installed_version.unwrap_or(bundled_version);
+    let mut floor = previous_version.clone();
+    if is_newer(current_version, &floor)? {
+        floor = current_version.to_string();
+    }
+    if !is_newer(&bundle.version, &floor)? {
+        log::error!(
+            "[selectors] Refusing selector update {}: not newer than {}",
+            bundle.version,
+            floor
+        );
+        return Err(VerificationError::NotNewer {
+            current: floor,
+            offered: bundle.version,
+        });
+    }
+
+    // Extractors read the active row, so switching is a single commit
+    let tx = conn.unchecked_transaction()?;
+    tx.execute("UPDATE selector_bundle SET active = 0 WHERE active = 1", [])?;
+    tx.execute(
+        "INSERT INTO selector_bundle (version, signed_json, installed_at, active)
+         VALUES (?1, ?2, ?3, 1)",
+        params![bundle.version, signed_json, chrono::Utc::now().timestamp()],
+    )?;
+    // Rollbacks were from an older update
+    tx.execute("DELETE FROM selector_rollback", [])?;
+    tx.commit()?;
+
+    log::info!(
+        "[selectors] Installed selector update {} (was {})",
+        bundle.version,
+        previous_version
+    );
+    Ok(SelectorUpdate {
+        version: bundle.version,
+        previous_version,
+        platforms: bundle.platforms.keys().cloned().collect(),
+    })
+}
+
+/// Selectors the extractor of `platform` should use: the active update
+/// unless it was rolled back for the platform, otherwise the bundled ones.
+/// `None` if neither knows the platform.
+pub fn get_active_selectors(
+    conn: &Connection,
+    platform: &str,
+) -> Result<Option<ActiveSelectors>, VerificationError> {
+    let update: Option<String> = conn
+        .query_row(
+            "SELECT b.signed_json FROM selector_bundle b
+             WHERE b.active = 1
+               AND NOT EXISTS (
+                   SELECT 1 FROM selector_rollback r
+                   WHERE r.platform = ?1 AND r.version = b.version
+               )",
+            [platform],
+            |row| row.get(0),
+        )
+        .optional()?;
+
+    // The update was verified when it was installed
+    if let Some(signed_json) = update {
+        let bundle = parse_bundle(&SignedSelectors::parse(&signed_json)?.selectors)?;
+        if let Some(selectors) = bundle.platforms.get(platform) {
+            return Ok(Some(ActiveSelectors {
+                platform: platform.to_string(),
+                version: bundle.version,
+                source: SelectorSource::Update,
+                selectors: selectors.clone(),
+            }));
+        }
+    }
+
+    let bundled = parse_bundle(get_bundled_selectors())?;
+    Ok(bundled
+        .platforms
+        .get(platform)
+        .map(|selectors| ActiveSelectors {
+            platform: platform.to_string(),
+            version: bundled.version.clone(),
+            source: SelectorSource::Bundled,
+            selectors: selectors.clone(),
+        }))
+}
+
+/// Send `platform` back to the bundled selectors until a newer update is
+/// installed. Returns false if the platform wasn't using an update.
+pub fn rollback_selectors(conn: &Connection, platform: &str) -> Result<bool, VerificationError> {
+    let Some(active) = get_active_selectors(conn, platform)? else {
+        return Ok(false);
+    };
+    if active.source != SelectorSource::Update {
+        return Ok(false);
+    }
+    conn.execute(
+        "INSERT OR REPLACE INTO selector_rollback (platform, version, rolled_back_at)
+         VALUES (?1, ?2, ?3)",
+        params![platform, active.version, chrono::Utc::now().timestamp()],
+    )?;
+    log::warn!(
+        "[selectors] Rolled {} back from selector update {} to the bundled selectors",
+        platform,
+        active.version
+    );
+    Ok(true)
+}
+
+/// Record whether an extraction on `platform` worked with the selectors in
+/// use. A failure spike under updated selectors rolls the platform back;
+/// returns whether that happened.
+pub fn record_extraction_result(
+    conn: &Connection,
+    platform: &str,
+    succeeded: bool,
+) -> Result<bool, VerificationError> {
+    let Some(active) = get_active_selectors(conn, platform)? else {
+        return Ok(false);
+    };
+    conn.execute(
+        "INSERT INTO selector_extraction_health (platform, version, successes, failures, updated_at)
+         VALUES (?1, ?2, ?3, ?4, ?5)
+         ON CONFLICT(platform, version) DO UPDATE SET
+             successes = successes + excluded.successes,
+             failures = failures + excluded.failures,
+             updated_at = excluded.updated_at",
+        params![
+            platform,
+            active.version,
+            succeeded as i64,
+            !succeeded as i64,
+            chrono::Utc::now().timestamp()
+        ],
+    )?;
+
+    if active.source != SelectorSource::Update || !failure_spike(conn, platform, &active.version)? {
+        return Ok(false);
+    }
+    log::error!(
+        "[selectors] Extraction failures spiked on {} with selector update {}",
+        platform,
+        active.version
+    );
+    rollback_selectors(conn, platform)
+}
+
+/// Whether extractions with `version` fail far more often than with the
+/// selectors the platform used before
+fn failure_spike(
+    conn: &Connection,
+    platform: &str,
+    version: &str,
+) -> Result<bool, VerificationError> {
+    let (successes, failures): (i64, i64) = conn.query_row(
+        "SELECT successes, failures FROM selector_extraction_health
+         WHERE platform = ?1 AND version = ?2",
+        params![platform, version],
+        |row| Ok((row.get(0)?, row.get(1)?)),
+    )?;
+    let attempts = successes + failures;
+    if attempts < SPIKE_MIN_ATTEMPTS {
+        return Ok(false);
+    }
+    let (earlier_successes, earlier_failures): (i64, i64) = conn.query_row(
+        "SELECT COALESCE(SUM(successes), 0), COALESCE(SUM(failures), 0)
+         FROM selector_extraction_health WHERE platform = ?1 AND version != ?2",
+        params![platform, version],
+        |row| Ok((row.get(0)?, row.get(1)?)),
+    )?;
+    let earlier_attempts = earlier_successes + earlier_failures;
+    let baseline = if earlier_attempts > 0 {
+        earlier_failures as f64 / earlier_attempts as f64
+    } else {
+        0.0
+    };
+    let rate = failures as f64 / attempts as f64;
+    Ok(rate >= SPIKE_FAILURE_RATE && rate - baseline >= SPIKE_MARGIN)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -207,6 +562,14 @@ mod tests {
         assert!(!signed.hash.is_empty());
     }
 
+    #[test]
+    fn test_version_ordering() {
+        assert!(is_newer("1.2.0", "1.1.9").unwrap());
+        assert!(is_newer("1.10", "1.9.5").unwrap());
+        assert!(!is_newer("1.1", "1.1.0").unwrap());
+        assert!(parse_version("1.x").is_err());
+    }
+
     #[test]
     fn test_bundled_selectors_exist() {
         let bundled = get_bundled_selectors();
@@ -23,60 +23,83 @@ pub struct TimelinePost {
 pub struct TimelineFilters {
     pub platforms: Option<Vec<String>>,
     pub categories: Option<Vec<String>>,
+    pub accounts: Option<Vec<String>>,
+    /// Discovered topic (cluster) ids
+    pub topics: Option<Vec<String>>,
     pub after: Option<i64>,
     pub before: Option<i64>,
     pub limit: Option<i64>,
+    /// Keyset cursor: only posts ordered after (before_timestamp, before_id)
+    pub before_timestamp: Option<i64>,
+    pub before_id: Option<String>,
 }
 
-/// Get unified timeline across all platforms
-///
-/// This is the core function that powers the cross-platform social media timeline.
-/// It aggregates posts from all enabled accounts in a space and applies filters.
-pub fn get_unified_timeline(
-    conn: &Connection,
-    space_id: &str,
-    filters: TimelineFilters,
-) -> Result<Vec<TimelinePost>, SocialError> {
-    log::debug!(
-        "[Social::Timeline] Building unified timeline for space {} with filters: platforms={:?}, categories={:?}, limit={:?}",
-        space_id,
-        filters.platforms,
-        filters.categories,
-        filters.limit
-    );
+/// Position of the last post on a timeline page
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TimelineCursor {
+    pub before_timestamp: i64,
+    pub before_id: String,
+}
 
-    let mut query = String::from(
-        "SELECT p.id, p.platform, a.username, p.author, p.author_handle,
-                p.content, p.timestamp, p.likes, p.shares, p.comments, p.views,
-                p.media_urls_json, p.post_type,
-                GROUP_CONCAT(DISTINCT c.name, char(31)) as categories
-         FROM social_post p
-         JOIN social_account a ON p.account_id = a.id
-         LEFT JOIN social_post_category pc ON p.id = pc.post_id
-         LEFT JOIN social_category c ON pc.category_id = c.id AND c.space_id = a.space_id
-         WHERE a.space_id = ?1 AND a.enabled = 1",
-    );
+/// One page of the unified timeline
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TimelinePage {
+    pub posts: Vec<TimelinePost>,
+    /// Pass back as `before_timestamp`/`before_id` to continue; None on the last page
+    pub next_cursor: Option<TimelineCursor>,
+}
 
-    let mut params: Vec<Box<dyn rusqlite::ToSql>> = vec![Box::new(space_id.to_string())];
+impl TimelineFilters {
+    /// Continue from where `cursor` left off
+    pub fn after_cursor(mut self, cursor: &TimelineCursor) -> Self {
+        self.before_timestamp = Some(cursor.before_timestamp);
+        self.before_id = Some(cursor.before_id.clone());
+        self
+    }
+}
 
-    // Apply platform filter
-    if let Some(platforms) = filters.platforms {
-        if !platforms.is_empty() {
-            query.push_str(" AND p.platform IN (");
-            for (i, platform) in platforms.iter().enumerate() {
-                if i > 0 {
-                    query.push_str(", ");
-                }
-                query.push('?');
-                params.push(Box::new(platform.clone()));
+/// Append the platform, category, account, topic and time filters to a query over
+/// `social_post p JOIN social_account a` whose first parameter is the space id
+pub(super) fn push_timeline_filters(
+    query: &mut String,
+    params: &mut Vec<Box<dyn rusqlite::ToSql>>,
+    filters: &TimelineFilters,
+) {
+    fn push_in_list(
+        query: &mut String,
+        params: &mut Vec<Box<dyn rusqlite::ToSql>>,
+        values: &[String],
+    ) {
+        for (i, value) in values.iter().enumerate() {
+            if i > 0 {
+                query.push_str(", ");
             }
-            query.push(')');
+            query.push('?');
+            params.push(Box::new(value.clone()));
         }
     }
 
+    // Apply platform filter
+    if let Some(platforms) = filters.platforms.as_deref().filter(|p| !p.is_empty()) {
+        query.push_str(" AND p.platform IN (");
+        push_in_list(query, params, platforms);
+        query.push(')');
+    }
+
+    // Apply account filter
+    if let Some(accounts) = filters.accounts.as_deref().filter(|a| !a.is_empty()) {
+        query.push_str(" AND p.account_id IN (");
+        push_in_list(query, params, accounts);
+        query.push(')');
+    }
+
     // Apply category filter
-    if let Some(categories) = filters.categories {
-        let cats: Vec<String> = categories.into_iter().filter(|c| !c.is_empty()).collect();
+    if let Some(categories) = &filters.categories {
+        let cats: Vec<String> = categories
+            .iter()
+            .filter(|c| !c.is_empty())
+            .cloned()
+            .collect();
         if !cats.is_empty() {
             // Constrain to space via EXISTS to avoid cross-space leakage and preserve LEFT JOIN semantics
             query.push_str(
@@ -85,19 +108,20 @@ pub fn get_unified_timeline(
                 JOIN social_category c2 ON pc2.category_id = c2.id \
                 WHERE pc2.post_id = p.id AND c2.space_id = ?1 AND c2.id IN (",
             );
-            for i in 0..cats.len() {
-                if i > 0 {
-                    query.push_str(", ");
-                }
-                query.push('?');
-            }
+            push_in_list(query, params, &cats);
             query.push_str("))");
-            for c in cats {
-                params.push(Box::new(c));
-            }
         }
     }
 
+    // Apply topic filter
+    if let Some(topics) = filters.topics.as_deref().filter(|t| !t.is_empty()) {
+        query.push_str(
+            " AND p.id IN (SELECT t.post_id FROM social_post_topic t WHERE t.cluster_id IN (",
+        );
+        push_in_list(query, params, topics);
+        query.push_str("))");
+    }
+
     // Apply time filters
     if let Some(after) = filters.after {
         query.push_str(" AND p.timestamp >= ?");
@@ -108,11 +132,77 @@ pub fn get_unified_timeline(
         query.push_str(" AND p.timestamp <= ?");
         params.push(Box::new(before));
     }
+}
 
-    query.push_str(" GROUP BY p.id ORDER BY p.timestamp DESC LIMIT ?");
-    params.push(Box::new(filters.limit.unwrap_or(100)));
+/// Get unified timeline across all platforms
+///
+/// This is the core function that powers the cross-platform social media timeline.
+/// It aggregates posts from all enabled accounts in a space and applies filters.
+pub fn get_unified_timeline(
+    conn: &Connection,
+    space_id: &str,
+    filters: TimelineFilters,
+) -> Result<Vec<TimelinePost>, SocialError> {
+    Ok(get_unified_timeline_page(conn, space_id, filters)?.posts)
+}
+
+/// Get one page of the unified timeline, newest first.
+///
+/// Posts are ordered by (timestamp, id) descending, so the order is total even
+/// when several platforms report the same timestamp. Passing the returned
+/// cursor back in the filters resumes right after the last post of the page.
+pub fn get_unified_timeline_page(
+    conn: &Connection,
+    space_id: &str,
+    filters: TimelineFilters,
+) -> Result<TimelinePage, SocialError> {
+    log::debug!(
+        "[Social::Timeline] Building unified timeline for space {} with filters: platforms={:?}, categories={:?}, accounts={:?}, limit={:?}, cursor=({:?}, {:?})",
+        space_id,
+        filters.platforms,
+        filters.categories,
+        filters.accounts,
+        filters.limit,
+        filters.before_timestamp,
+        filters.before_id
+    );
+
+    let mut query = String::from(
+        "SELECT p.id, p.platform, a.username, p.author, p.author_handle,
+                p.content, p.timestamp, p.likes, p.shares, p.comments, p.views,
+                p.media_urls_json, p.post_type,
+                GROUP_CONCAT(c.name, char(31)) as categories
+         FROM social_post p
+         JOIN social_account a ON p.account_id = a.id
+         LEFT JOIN social_post_category pc ON p.id = pc.post_id
+         LEFT JOIN social_category c ON pc.category_id = c.id AND c.space_id = a.space_id
+         WHERE a.space_id = ?1 AND a.enabled = 1",
+    );
 
-    let mut stmt = conn.prepare(&query)?;
+    let mut params: Vec<Box<dyn rusqlite::ToSql>> = vec![Box::new(space_id.to_string())];
+    push_timeline_filters(&mut query, &mut params, &filters);
+
+    // Keyset cursor
+    match (filters.before_timestamp, &filters.before_id) {
+        (Some(timestamp), Some(id)) => {
+            query.push_str(" AND (p.timestamp < ? OR (p.timestamp = ? AND p.id < ?))");
+            params.push(Box::new(timestamp));
+            params.push(Box::new(timestamp));
+            params.push(Box::new(id.clone()));
+        }
+        (Some(timestamp), None) => {
+            query.push_str(" AND p.timestamp < ?");
+            params.push(Box::new(timestamp));
+        }
+        _ => {}
+    }
+
+    // One extra row tells whether another page follows
+    let limit = filters.limit.unwrap_or(100).max(1);
+    query.push_str(" GROUP BY p.id ORDER BY p.timestamp DESC, p.id DESC LIMIT ?");
+    params.push(Box::new(limit + 1));
+
+    let mut stmt = conn.prepare_cached(&query)?;
     let posts = stmt.query_map(
         rusqlite::params_from_iter(params.iter().map(|b| b.as_ref())),
         |row| {
@@ -161,13 +251,56 @@ pub fn get_unified_timeline(
         result.push(post?);
     }
 
+    let next_cursor = if result.len() as i64 > limit {
+        result.truncate(limit as usize);
+        result.last().map(|last| TimelineCursor {
+            before_timestamp: last.timestamp,
+            before_id: last.id.clone(),
+        })
+    } else {
+        None
+    };
+
     log::info!(
         "[Social::Timeline] Retrieved {} posts for unified timeline in space {}",
         result.len(),
         space_id
     );
 
-    Ok(result)
+    Ok(TimelinePage {
+        posts: result,
+        next_cursor,
+    })
+}
+
+/// Count timeline posts that arrived after `since` (epoch milliseconds).
+///
+/// Arrival is when the post was first stored, not its platform timestamp, so
+/// posts that sync late with an older timestamp still count as new. The
+/// platform, category, account and time filters apply as in the timeline;
+/// the cursor and limit are ignored.
+pub fn get_timeline_new_count(
+    conn: &Connection,
+    space_id: &str,
+    since: i64,
+    filters: &TimelineFilters,
+) -> Result<i64, SocialError> {
+    let mut query = String::from(
+        "SELECT COUNT(*) FROM social_post p
+         JOIN social_account a ON p.account_id = a.id
+         WHERE a.space_id = ?1 AND a.enabled = 1
+           AND COALESCE(p.first_seen_at, p.fetched_at) > ?2",
+    );
+    let mut params: Vec<Box<dyn rusqlite::ToSql>> =
+        vec![Box::new(space_id.to_string()), Box::new(since)];
+    push_timeline_filters(&mut query, &mut params, filters);
+
+    let count = conn.query_row(
+        &query,
+        rusqlite::params_from_iter(params.iter().map(|b| b.as_ref())),
+        |row| row.get(0),
+    )?;
+    Ok(count)
 }
 
 /// Get timeline for a specific category
@@ -178,11 +311,11 @@ pub fn get_category_timeline(
 ) -> Result<Vec<TimelinePost>, SocialError> {
     let limit = limit.unwrap_or(100);
 
-    let mut stmt = conn.prepare(
+    let mut stmt = conn.prepare_cached(
         "SELECT p.id, p.platform, a.username, p.author, p.author_handle,
                 p.content, p.timestamp, p.likes, p.shares, p.comments, p.views,
                 p.media_urls_json, p.post_type,
-                GROUP_CONCAT(DISTINCT c.name, char(31)) as categories
+                GROUP_CONCAT(c.name, char(31)) as categories
          FROM social_post p
          JOIN social_account a ON p.account_id = a.id
          JOIN social_post_category pc ON p.id = pc.post_id
@@ -250,11 +383,11 @@ pub fn get_platform_timeline(
 ) -> Result<Vec<TimelinePost>, SocialError> {
     let limit = limit.unwrap_or(100);
 
-    let mut stmt = conn.prepare(
+    let mut stmt = conn.prepare_cached(
         "SELECT p.id, p.platform, a.username, p.author, p.author_handle,
                 p.content, p.timestamp, p.likes, p.shares, p.comments, p.views,
                 p.media_urls_json, p.post_type,
-                GROUP_CONCAT(DISTINCT c.name, char(31)) as categories
+                GROUP_CONCAT(c.name, char(31)) as categories
          FROM social_post p
          JOIN social_account a ON p.account_id = a.id
          LEFT JOIN social_post_category pc ON p.id = pc.post_id
@@ -0,0 +1,637 @@
+//! Topic Discovery
+//!
+//! Groups timeline posts into topics without any cloud calls: post texts are
+//! embedded with a local [`Embedder`], clustered with spherical k-means and
+//! each cluster is labelled with its most distinctive TF-IDF terms.
+//!
+//! The first run, and any run with [`TopicClusterOptions::full_recluster`],
+//! clusters every post in the range from scratch. Later runs only embed the
+//! posts that arrived since and add each one to its nearest topic, nudging
+//! that topic's centroid; posts that aren't similar enough to any topic stay
+//! unassigned until the next full run.
+
+use rusqlite::{params, Connection, OptionalExtension};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeSet, HashMap};
+
+use super::account::SocialError;
+use crate::ai::rag::{decode_embedding, encode_embedding};
+use crate::ai::Embedder;
+use crate::calendar::TimeRange;
+
+/// Words too common to say anything about a topic
+const STOPWORDS: &[&str] = &[
+    "about", "after", "all", "also", "and", "any", "are", "back", "been", "but", "can", "com",
+    "could", "did", "does", "for", "from", "get", "had", "has", "have", "her", "here", "his",
+    "how", "http", "https", "into", "its", "just", "like", "more", "most", "new", "not", "now",
+    "one", "only", "our", "out", "over", "some", "than", "that", "the", "their", "them", "then",
+    "there", "these", "they", "this", "too", "very", "was", "were", "what", "when", "which", "who",
+    "why", "will", "with", "would", "www", "you", "your",
+];
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TopicClusterOptions {
+    /// Topics to form when clustering from scratch; fewer when there are
+    /// fewer posts
+    pub k: usize,
+    pub max_iterations: usize,
+    /// Cosine similarity a new post needs to its nearest topic to join it
+    pub min_similarity: f32,
+    /// Replace the existing topics by clustering every post in range again
+    pub full_recluster: bool,
+    /// Terms in each topic label
+    pub label_terms: usize,
+}
+
+impl Default for TopicClusterOptions {
+    fn default() -> Self {
+        TopicClusterOptions {
+            k: 8,
+            max_iterations: 50,
+            min_similarity: 0.5,
+            full_recluster: false,
+            label_terms: 3,
+        }
+    }
+}
+
+/// What one clustering run did
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct TopicClusteringResult {
+    /// Whether the topics were rebuilt from scratch
+    pub reclustered: bool,
+    pub clusters: usize,
+    pub posts_embedded: usize,
+    pub posts_assigned: usize,
+    /// Posts not similar enough to any topic
+    pub posts_unassigned: usize,
+}
+
+/// A discovered topic with how its posts perform
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TopicOverview {
+    pub cluster_id: String,
+    pub label: String,
+    pub post_count: i64,
+    pub avg_likes: f64,
+    pub avg_shares: f64,
+    pub avg_comments: f64,
+    /// Likes, shares and comments per post
+    pub avg_engagement: f64,
+}
+
+struct Cluster {
+    id: String,
+    centroid: Vec<f32>,
+    size: usize,
+}
+
+fn normalize(mut vector: Vec<f32>) -> Vec<f32> {
+    let norm = vector.iter().map(|v| v * v).sum::<f32>().sqrt();
+    if norm > 0.0 {
+        vector.iter_mut().for_each(|v| *v /= norm);
+    }
+    vector
+}
+
+/// Cosine similarity of two unit vectors; vectors of another dimension never match
+fn similarity(a: &[f32], b: &[f32]) -> f32 {
+    if a.len() != b.len() {
+        return f32::MIN;
+    }
+    a.iter().zip(b).map(|(x, y)| x * y).sum()
+}
+
+/// Index and similarity of the centroid closest to `vector`, first one on ties
+fn nearest<'a>(vector: &[f32], centroids: impl Iterator<Item = &'a [f32]>) -> (usize, f32) {
+    centroids
+        .enumerate()
+        .map(|(i, c)| (i, similarity(vector, c)))
+        .fold((0, f32::MIN), |best, candidate| {
+            if candidate.1 > best.1 {
+                candidate
+            } else {
+                best
+            }
+        })
+}
+
+/// Spherical k-means over unit vectors. Seeds are picked farthest-first
+/// starting from the first vector, so the same input always gives the same
+/// clusters. Returns each vector's cluster index and the centroids.
+fn k_means(vectors: &[Vec<f32>], k: usize, max_iterations: usize) -> (Vec<usize>, Vec<Vec<f32>>) {
+    let k = k.min(vectors.len());
+    if k == 0 {
+        return (Vec::new(), Vec::new());
+    }
+
+    let mut centroids = vec![vectors[0].clone()];
+    while centroids.len() < k {
+        let farthest = vectors
+            .iter()
+            .enumerate()
+            .map(|(i, v)| (i, nearest(v, centroids.iter().map(Vec::as_slice)).1))
+            .fold((0, f32::MAX), |best, candidate| {
+                if candidate.1 < best.1 {
+                    candidate
+                } else {
+                    best
+                }
+            });
+        centroids.push(vectors[farthest.0].clone());
+    }
+
+    let mut assignments = vec![usize::MAX; vectors.len()];
+    for _ in 0..max_iterations.max(1) {
+        let next: Vec<usize> = vectors
+            .iter()
+            .map(|v| nearest(v, centroids.iter().map(Vec::as_slice)).0)
+            .collect();
+        if next == assignments {
+            break;
+        }
+        assignments = next;
+
+        for (index, centroid) in centroids.iter_mut().enumerate() {
+            let mut sum = vec![0.0f32; centroid.len()];
+            let mut members = 0;
+            for (vector, _) in vectors
+                .iter()
+                .zip(&assignments)
+                .filter(|(_, cluster)| **cluster == index)
+            {
+                sum.iter_mut().zip(vector).for_each(|(s, v)| *s += v);
+                members += 1;
+            }
+            // An emptied cluster keeps its centroid and is dropped later
+            if members > 0 {
+                *centroid = normalize(sum);
+            }
+        }
+    }
+    (assignments, centroids)
+}
+
+fn tokenize(text: &str) -> impl Iterator<Item = String> + '_ {
+    text.split(|c: char| !c.is_alphanumeric())
+        .map(str::to_lowercase)
+        .filter(|word| {
+            word.chars().count() >= 3
+                && !word.chars().all(|c| c.is_ascii_digit())
+                && !STOPWORDS.contains(&word.as_str())
+        })
+}
+
+/// Top TF-IDF terms of each cluster, treating each cluster's posts as one
+/// document. Ties go to the alphabetically first term.
+fn label_clusters(documents: &[Vec<&str>], terms: usize) -> Vec<String> {
+    let counts: Vec<HashMap<String, usize>> = documents
+        .iter()
+        .map(|texts| {
+            let mut counts = HashMap::new();
+            for word in texts.iter().flat_map(|text| tokenize(text)) {
+                *counts.entry(word).or_insert(0) += 1;
+            }
+            counts
+        })
+        .collect();
+    let mut document_frequency: HashMap<&str, usize> = HashMap::new();
+    for term in counts.iter().flat_map(|c| c.keys()) {
+        *document_frequency.entry(term.as_str()).or_insert(0) += 1;
+    }
+
+    let clusters = counts.len() as f64;
+    counts
+        .iter()
+        .map(|counts| {
+            let total = counts.values().sum::<usize>().max(1) as f64;
+            let mut scored: Vec<(&str, f64)> = counts
+                .iter()
+                .map(|(term, &count)| {
+                    let df = document_frequency[term.as_str()] as f64;
+                    let idf = ((1.0 + clusters) / (1.0 + df)).ln() + 1.0;
+                    (term.as_str(), count as f64 / total * idf)
+                })
+                .collect();
+            scored.sort_by(|a, b| b.1.total_cmp(&a.1).then_with(|| a.0.cmp(b.0)));
+            let label: Vec<&str> = scored.iter().take(terms.max(1)).map(|s| s.0).collect();
+            if label.is_empty() {
+                "misc".to_string()
+            } else {
+                label.join(", ")
+            }
+        })
+        .collect()
+}
+
+/// Posts of the space in `range` with text, oldest first
+fn posts_in_range(
+    conn: &Connection,
+    space_id: &str,
+    range: TimeRange,
+) -> Result<Vec<(String, String)>, SocialError> {
+    let mut stmt = conn.prepare(
+        "SELECT p.id, p.content
+         FROM social_post p
+         JOIN social_account a ON p.account_id = a.id
+         WHERE a.space_id = ?1 AND p.timestamp >= ?2 AND p.timestamp < ?3
+           AND TRIM(COALESCE(p.content, '')) != ''
+         ORDER BY p.timestamp, p.id",
+    )?;
+    let posts = stmt
+        .query_map(
+            params![space_id, range.start * 1000, range.end * 1000],
+            |row| Ok((row.get(0)?, row.get(1)?)),
+        )?
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(posts)
+}
+
+/// Embed `text` unless an embedding under the same version is stored
+fn embed_post(
+    conn: &Connection,
+    embedder: &dyn Embedder,
+    post_id: &str,
+    text: &str,
+    embedded: &mut usize,
+) -> Result<Vec<f32>, SocialError> {
+    let stored: Option<Vec<u8>> = conn
+        .query_row(
+            "SELECT embedding FROM social_post_topic WHERE post_id = ?1 AND embedding_version = ?2",
+            params![post_id, embedder.version()],
+            |row| row.get(0),
+        )
+        .optional()?;
+    if let Some(bytes) = stored {
+        return Ok(decode_embedding(&bytes));
+    }
+    *embedded += 1;
+    let vector = embedder
+        .embed(text)
+        .map_err(|e| SocialError::Embedding(e.to_string()))?;
+    Ok(normalize(vector))
+}
+
+fn save_assignment(
+    conn: &Connection,
+    space_id: &str,
+    post_id: &str,
+    cluster: Option<(&str, f32)>,
+    embedding: &[f32],
+    version: &str,
+    now: i64,
+) -> Result<(), SocialError> {
+    conn.execute(
+        "INSERT INTO social_post_topic
+             (post_id, space_id, cluster_id, similarity, embedding, embedding_version, assigned_at)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)
+         ON CONFLICT(post_id) DO UPDATE SET
+             space_id = excluded.space_id,
+             cluster_id = excluded.cluster_id,
+             similarity = excluded.similarity,
+             embedding = excluded.embedding,
+             embedding_version = excluded.embedding_version,
+             assigned_at = excluded.assigned_at",
+        params![
+            post_id,
+            space_id,
+            cluster.map(|c| c.0),
+            cluster.map(|c| c.1 as f64),
+            encode_embedding(embedding),
+            version,
+            now
+        ],
+    )?;
+    Ok(())
+}
+
+/// Existing topics of the space, or None when they need rebuilding because
+/// there are none or they were embedded by another model
+fn load_clusters(
+    conn: &Connection,
+    space_id: &str,
+    version: &str,
+) -> Result<Option<Vec<Cluster>>, SocialError> {
+    let mut stmt = conn.prepare(
+        "SELECT c.id, c.centroid, c.embedding_version,
+                (SELECT COUNT(*) FROM social_post_topic t WHERE t.cluster_id = c.id)
+         FROM social_topic_cluster c
+         WHERE c.space_id = ?1
+         ORDER BY c.id",
+    )?;
+    let rows = stmt
+        .query_map([space_id], |row| {
+            Ok((
+                row.get::<_, String>(0)?,
+                row.get::<_, Vec<u8>>(1)?,
+                row.get::<_, String>(2)?,
+                row.get::<_, i64>(3)?,
+            ))
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
+    if rows.is_empty() || rows.iter().any(|row| row.2 != version) {
+        return Ok(None);
+    }
+    Ok(Some(
+        rows.into_iter()
+            .map(|(id, centroid, _, size)| Cluster {
+                id,
+                centroid: decode_embedding(&centroid),
+                size: size.max(0) as usize,
+            })
+            .collect(),
+    ))
+}
+
+/// Recompute the labels of every topic of the space from its posts
+fn relabel_clusters(
+    conn: &Connection,
+    space_id: &str,
+    label_terms: usize,
+    now: i64,
+) -> Result<(), SocialError> {
+    let mut stmt = conn.prepare(
+        "SELECT c.id, p.content
+         FROM social_topic_cluster c
+         LEFT JOIN social_post_topic t ON t.cluster_id = c.id
+         LEFT JOIN social_post p ON p.id = t.post_id
+         WHERE c.space_id = ?1
+         ORDER BY c.id",
+    )?;
+    let mut texts: Vec<(String, Vec<String>)> = Vec::new();
+    let rows = stmt.query_map([space_id], |row| {
+        Ok((row.get::<_, String>(0)?, row.get::<_, Option<String>>(1)?))
+    })?;
+    for row in rows {
+        let (cluster_id, content) = row?;
+        if texts.last().map(|t| &t.0) != Some(&cluster_id) {
+            texts.push((cluster_id, Vec::new()));
+        }
+        if let (Some(content), Some(last)) = (content, texts.last_mut()) {
+            last.1.push(content);
+        }
+    }
+
+    let documents: Vec<Vec<&str>> = texts
+        .iter()
+        .map(|(_, contents)| contents.iter().map(String::as_str).collect())
+        .collect();
+    let labels = label_clusters(&documents, label_terms);
+    for ((cluster_id, _), label) in texts.iter().zip(labels) {
+        conn.execute(
+            "UPDATE social_topic_cluster SET label = ?1, updated_at = ?2 WHERE id = ?3",
+            params![label, now, cluster_id],
+        )?;
+    }
+    Ok(())
+}
+
+fn recluster(
+    conn: &Connection,
+    space_id: &str,
+    posts: &[(String, String)],
+    embedder: &dyn Embedder,
+    options: &TopicClusterOptions,
+    now: i64,
+) -> Result<TopicClusteringResult, SocialError> {
+    let mut result = TopicClusteringResult {
+        reclustered: true,
+        ..Default::default()
+    };
+    let mut vectors = Vec::with_capacity(posts.len());
+    for (post_id, text) in posts {
+        vectors.push(embed_post(
+            conn,
+            embedder,
+            post_id,
+            text,
+            &mut result.posts_embedded,
+        )?);
+    }
+
+    conn.execute(
+        "DELETE FROM social_topic_cluster WHERE space_id = ?1",
+        [space_id],
+    )?;
+    let (assignments, centroids) = k_means(&vectors, options.k, options.max_iterations);
+    let mut cluster_ids: Vec<Option<String>> = vec![None; centroids.len()];
+    for (index, centroid) in centroids.iter().enumerate() {
+        if !assignments.contains(&index) {
+            continue;
+        }
+        let id = ulid::Ulid::new().to_string();
+        conn.execute(
+            "INSERT INTO social_topic_cluster
+                 (id, space_id, label, centroid, embedding_version, created_at, updated_at)
+             VALUES (?1, ?2, '', ?3, ?4, ?5, ?5)",
+            params![
+                id,
+                space_id,
+                encode_embedding(centroid),
+                embedder.version(),
+                now
+            ],
+        )?;
+        cluster_ids[index] = Some(id);
+        result.clusters += 1;
+    }
+
+    for (((post_id, _), vector), &index) in posts.iter().zip(&vectors).zip(&assignments) {
+        let cluster_id = cluster_ids[index].as_deref().unwrap_or_default();
+        let score = similarity(vector, &centroids[index]);
+        save_assignment(
+            conn,
+            space_id,
+            post_id,
+            Some((cluster_id, score)),
+            vector,
+            embedder.version(),
+            now,
+        )?;
+        result.posts_assigned += 1;
+    }
+    Ok(result)
+}
+
+fn assign_new_posts(
+    conn: &Connection,
+    space_id: &str,
+    posts: &[(String, String)],
+    mut clusters: Vec<Cluster>,
+    embedder: &dyn Embedder,
+    options: &TopicClusterOptions,
+    now: i64,
+) -> Result<TopicClusteringResult, SocialError> {
+    let mut result = TopicClusteringResult {
+        clusters: clusters.len(),
+        ..Default::default()
+    };
+    let mut seen_stmt = conn.prepare("SELECT 1 FROM social_post_topic WHERE post_id = ?1")?;
+    let mut touched = BTreeSet::new();
+
+    for (post_id, text) in posts {
+        if seen_stmt.exists([post_id])? {
+            continue;
+        }
+        let vector = embed_post(conn, embedder, post_id, text, &mut result.posts_embedded)?;
+        let (index, score) = nearest(&vector, clusters.iter().map(|c| c.centroid.as_slice()));
+        if score < options.min_similarity {
+            save_assignment(
+                conn,
+                space_id,
+                post_id,
+                None,
+                &vector,
+                embedder.version(),
+                now,
+            )?;
+            result.posts_unassigned += 1;
+            continue;
+        }
+
+        // Running mean of the members, kept on the unit sphere
+        let cluster = &mut clusters[index];
+        let size = cluster.size as f32;
+        let moved: Vec<f32> = cluster
+            .centroid
+            .iter()
+            .zip(&vector)
+            .map(|(c, v)| (c * size + v) / (size + 1.0))
+            .collect();
+        cluster.centroid = normalize(moved);
+        cluster.size += 1;
+        touched.insert(index);
+        save_assignment(
+            conn,
+            space_id,
+            post_id,
+            Some((cluster.id.as_str(), score)),
+            &vector,
+            embedder.version(),
+            now,
+        )?;
+        result.posts_assigned += 1;
+    }
+
+    for index in touched {
+        conn.execute(
+            "UPDATE social_topic_cluster SET centroid = ?1, updated_at = ?2 WHERE id = ?3",
+            params![
+                encode_embedding(&clusters[index].centroid),
+                now,
+                clusters[index].id
+            ],
+        )?;
+    }
+    Ok(result)
+}
+
+/// Discover topics among the space's posts in `range` (Unix seconds) and
+/// remember which topic each post belongs to.
+///
+/// Runs in one transaction. Only posts with text take part; post embeddings
+/// are stored, so a full re-cluster only embeds posts it hasn't seen.
+pub fn cluster_timeline_topics(
+    conn: &mut Connection,
+    space_id: &str,
+    range: TimeRange,
+    embedder: &dyn Embedder,
+    options: &TopicClusterOptions,
+) -> Result<TopicClusteringResult, SocialError> {
+    if range.end <= range.start {
+        return Err(SocialError::InvalidInput(
+            "Range must end after it starts".into(),
+        ));
+    }
+    if options.k == 0 {
+        return Err(SocialError::InvalidInput("k must be at least 1".into()));
+    }
+    if !(-1.0..=1.0).contains(&options.min_similarity) {
+        return Err(SocialError::InvalidInput(format!(
+            "min_similarity must be between -1 and 1, not {}",
+            options.min_similarity
+        )));
+    }
+
+    let now = chrono::Utc::now().timestamp();
+    let tx = conn.transaction()?;
+    let posts = posts_in_range(&tx, space_id, range)?;
+    let existing = if options.full_recluster {
+        None
+    } else {
+        load_clusters(&tx, space_id, embedder.version())?
+    };
+    let result = match existing {
+        Some(clusters) => {
+            assign_new_posts(&tx, space_id, &posts, clusters, embedder, options, now)?
+        }
+        None => recluster(&tx, space_id, &posts, embedder, options, now)?,
+    };
+    if result.reclustered || result.posts_assigned > 0 {
+        relabel_clusters(&tx, space_id, options.label_terms, now)?;
+    }
+    tx.commit()?;
+
+    log::info!(
+        "[Social::Topics] Space {}: {} topics, {} posts assigned, {} unassigned, {} embedded{}",
+        space_id,
+        result.clusters,
+        result.posts_assigned,
+        result.posts_unassigned,
+        result.posts_embedded,
+        if result.reclustered {
+            " (re-clustered)"
+        } else {
+            ""
+        }
+    );
+    Ok(result)
+}
+
+/// Topics of the space, largest first, with the average engagement of their posts
+pub fn get_topic_overview(
+    conn: &Connection,
+    space_id: &str,
+) -> Result<Vec<TopicOverview>, SocialError> {
+    let mut stmt = conn.prepare(
+        "SELECT c.id, c.label, COUNT(p.id),
+                COALESCE(AVG(COALESCE(p.likes, 0)), 0),
+                COALESCE(AVG(COALESCE(p.shares, 0)), 0),
+                COALESCE(AVG(COALESCE(p.comments, 0)), 0),
+                COALESCE(AVG(COALESCE(p.likes, 0) + COALESCE(p.shares, 0)
+                             + COALESCE(p.comments, 0)), 0)
+         FROM social_topic_cluster c
+         LEFT JOIN social_post_topic t ON t.cluster_id = c.id
+         LEFT JOIN social_post p ON p.id = t.post_id
+         WHERE c.space_id = ?1
+         GROUP BY c.id
+         ORDER BY COUNT(p.id) DESC, c.label, c.id",
+    )?;
+    let topics = stmt
+        .query_map([space_id], |row| {
+            Ok(TopicOverview {
+                cluster_id: row.get(0)?,
+                label: row.get(1)?,
+                post_count: row.get(2)?,
+                avg_likes: row.get(3)?,
+                avg_shares: row.get(4)?,
+                avg_comments: row.get(5)?,
+                avg_engagement: row.get(6)?,
+            })
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(topics)
+}
+
+/// The topic a post was assigned to, if any
+pub fn get_post_topic(conn: &Connection, post_id: &str) -> Result<Option<String>, SocialError> {
+    Ok(conn
+        .query_row(
+            "SELECT cluster_id FROM social_post_topic WHERE post_id = ?1",
+            [post_id],
+            |row| row.get(0),
+        )
+        .optional()?
+        .flatten())
+}
@@ -1,15 +1,57 @@
-use crate::db::DbError;
+use crate::db::{table_exists, DbError};
 use crate::mode::enable_core_pack;
-use rusqlite::Connection;
+use rusqlite::{Connection, OptionalExtension};
 use serde::{Deserialize, Serialize};
+use thiserror::Error;
 use ulid::Ulid;
 
+#[derive(Error, Debug)]
+pub enum SpaceError {
+    #[error("Database error: {0}")]
+    Database(#[from] DbError),
+    #[error("Rusqlite error: {0}")]
+    Rusqlite(#[from] rusqlite::Error),
+    #[error("Space not found: {0}")]
+    NotFound(String),
+    #[error("Confirmation token does not match; type '{expected}' to delete the space")]
+    ConfirmationMismatch { expected: String },
+}
+
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct Space {
     pub id: Ulid,
     pub name: String,
     pub icon: Option<String>,
     pub enabled_modes_json: String,
+    /// When the space was archived; `None` for active spaces
+    #[serde(default)]
+    pub archived_at: Option<i64>,
+}
+
+/// Rows removed from one table by [`delete_space`]
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone)]
+pub struct DeletedRows {
+    pub table: String,
+    pub count: i64,
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone)]
+pub struct SpaceDeletionSummary {
+    pub space_id: String,
+    pub name: String,
+    /// Tables that had rows, in deletion order
+    pub deleted: Vec<DeletedRows>,
+    pub total_rows: i64,
+}
+
+/// SQL condition that holds unless `space_column` names an archived space.
+/// Rows without a space pass.
+pub(crate) fn in_active_space(space_column: &str) -> String {
+    format!(
+        "NOT EXISTS (SELECT 1 FROM space archived_space
+                     WHERE archived_space.id = {} AND archived_space.archived_at IS NOT NULL)",
+        space_column
+    )
 }
 
 pub fn create_space(conn: &mut Connection, name: &str) -> Result<Ulid, DbError> {
@@ -25,20 +67,399 @@ pub fn create_space(conn: &mut Connection, name: &str) -> Result<Ulid, DbError>
     Ok(id)
 }
 
-pub fn get_all_spaces(conn: &Connection) -> Result<Vec<Space>, DbError> {
+/// All spaces; archived ones only when `include_archived` is set
+pub fn get_all_spaces(conn: &Connection, include_archived: bool) -> Result<Vec<Space>, DbError> {
     log::info!("[space] Getting all spaces");
-    let mut stmt = conn.prepare("SELECT id, name, icon, enabled_modes_json FROM space")?;
+    let mut stmt = conn.prepare(
+        "SELECT id, name, icon, enabled_modes_json, archived_at FROM space
+         WHERE ?1 OR archived_at IS NULL",
+    )?;
     let spaces = stmt
-        .query_map([], |row| {
+        .query_map([include_archived], |row| {
             Ok(Space {
                 id: Ulid::from_string(&row.get::<_, String>(0)?)
                     .map_err(|e| rusqlite::Error::ToSqlConversionFailure(Box::new(e)))?,
                 name: row.get(1)?,
                 icon: row.get(2)?,
                 enabled_modes_json: row.get(3)?,
+                archived_at: row.get(4)?,
             })
         })?
         .collect::<Result<Vec<Space>, _>>()?;
     log::info!("[space] Found {} spaces", spaces.len());
     Ok(spaces)
 }
+
+pub fn is_space_archived(conn: &Connection, space_id: &str) -> Result<bool, SpaceError> {
+    let archived_at: Option<i64> = conn
+        .query_row(
+            "SELECT archived_at FROM space WHERE id = ?1",
+            [space_id],
+            |row| row.get(0),
+        )
+        .optional()?
+        .ok_or_else(|| SpaceError::NotFound(space_id.to_string()))?;
+    Ok(archived_at.is_some())
+}
+
+/// Hide a space from space pickers, vault-wide dashboards, default search,
+/// sync manifests and insight generation. Nothing is deleted.
+pub fn archive_space(conn: &Connection, space_id: &str) -> Result<(), SpaceError> {
+    log::info!("[space] Archiving space: {}", space_id);
+    let updated = conn.execute(
+        "UPDATE space SET archived_at = COALESCE(archived_at, ?2), updated_at = ?2 WHERE id = ?1",
+        rusqlite::params![space_id, chrono::Utc::now().timestamp()],
+    )?;
+    if updated == 0 {
+        return Err(SpaceError::NotFound(space_id.to_string()));
+    }
+    Ok(())
+}
+
+pub fn unarchive_space(conn: &Connection, space_id: &str) -> Result<(), SpaceError> {
+    log::info!("[space] Unarchiving space: {}", space_id);
+    let updated = conn.execute(
+        "UPDATE space SET archived_at = NULL, updated_at = ?2 WHERE id = ?1",
+        rusqlite::params![space_id, chrono::Utc::now().timestamp()],
+    )?;
+    if updated == 0 {
+        return Err(SpaceError::NotFound(space_id.to_string()));
+    }
+    Ok(())
+}
+
+/// The token [`delete_space`] requires, e.g. `delete-client-acme` for a
+/// space named "Client ACME". The UI asks the user to type it.
+pub fn deletion_confirm_token(space_name: &str) -> String {
+    let slug = space_name
+        .trim()
+        .to_lowercase()
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|part| !part.is_empty())
+        .collect::<Vec<_>>()
+        .join("-");
+    format!("delete-{}", slug)
+}
+
+fn space_name(conn: &Connection, space_id: &str) -> Result<String, SpaceError> {
+    conn.query_row("SELECT name FROM space WHERE id = ?1", [space_id], |row| {
+        row.get(0)
+    })
+    .optional()?
+    .ok_or_else(|| SpaceError::NotFound(space_id.to_string()))
+}
+
+/// The token [`delete_space`] currently requires for `space_id`
+pub fn get_space_deletion_token(conn: &Connection, space_id: &str) -> Result<String, SpaceError> {
+    Ok(deletion_confirm_token(&space_name(conn, space_id)?))
+}
+
+/// One `DELETE` of [`delete_space`]; `?1` is the space id
+struct DeletionStep {
+    table: &'static str,
+    /// The condition after `WHERE`, or `None` for `space_id = ?1`
+    condition: Option<String>,
+}
+
+fn step(table: &'static str) -> DeletionStep {
+    DeletionStep {
+        table,
+        condition: None,
+    }
+}
+
+fn step_where(table: &'static str, condition: String) -> DeletionStep {
+    DeletionStep {
+        table,
+        condition: Some(condition),
+    }
+}
+
+/// Everything that belongs to a space, children before their parents.
+/// Tables created outside migrations are skipped when they don't exist.
+/// The audit log is kept: its hash chain must stay verifiable.
+fn deletion_steps() -> Vec<DeletionStep> {
+    let notes = "(SELECT id FROM note WHERE space_id = ?1)";
+    let tasks = "(SELECT id FROM task WHERE space_id = ?1)";
+    let projects = "(SELECT id FROM project WHERE space_id = ?1)";
+    let accounts = "(SELECT id FROM social_account WHERE space_id = ?1)";
+    let categories = "(SELECT id FROM social_category WHERE space_id = ?1)";
+    vec![
+        // Sync bookkeeping of entities that are about to go
+        step_where(
+            "entity_sync_log",
+            format!(
+                "entity_id IN {notes} OR entity_id IN {tasks} OR entity_id IN {projects}
+                 OR entity_id IN (SELECT id FROM tag WHERE space_id = ?1)"
+            ),
+        ),
+        step("sync_conflict"),
+        step("sync_history"),
+        step("sync_rejected_delta"),
+        step("sync_vector_clock"),
+        step_where(
+            "caldav_event_mapping",
+            format!("local_task_id IN {tasks} OR local_note_id IN {notes}"),
+        ),
+        // Rows hanging off notes, tasks and projects
+        step_where(
+            "meeting_action",
+            format!("note_id IN {notes} OR task_id IN {tasks}"),
+        ),
+        step_where(
+            "form_submission",
+            format!(
+                "note_id IN {notes}
+                 OR template_id IN (SELECT id FROM form_template WHERE space_id = ?1)"
+            ),
+        ),
+        step_where(
+            "form_template_version",
+            "template_id IN (SELECT id FROM form_template WHERE space_id = ?1)".to_string(),
+        ),
+        step("form_template"),
+        step_where(
+            "time_entry",
+            format!(
+                "space_id = ?1 OR note_id IN {notes} OR task_id IN {tasks}
+                 OR project_id IN {projects}"
+            ),
+        ),
+        step_where(
+            "task_tags",
+            format!("task_id IN {tasks} OR tag_id IN (SELECT id FROM tag WHERE space_id = ?1)"),
+        ),
+        step_where(
+            "note_tags",
+            format!("note_id IN {notes} OR tag_id IN (SELECT id FROM tag WHERE space_id = ?1)"),
+        ),
+        step_where("task_people", format!("task_id IN {tasks}")),
+        step_where("task_recur_exdate", format!("task_id IN {tasks}")),
+        step_where(
+            "review_session_card",
+            "session_id IN (SELECT id FROM review_session WHERE space_id = ?1)".to_string(),
+        ),
+        step("review_session"),
+        step("review_streak"),
+        step_where(
+            "review_log",
+            format!("card_id IN (SELECT id FROM knowledge_card WHERE note_id IN {notes})"),
+        ),
+        step_where("knowledge_card", format!("note_id IN {notes}")),
+        // Personal modes
+        step("health_metric"),
+        step("health_metric_setting"),
+        step("health_goal"),
+        step("transaction_log"),
+        step("transaction"),
+        step("budget"),
+        step("finance_account"),
+        step_where(
+            "recipe_ingredient",
+            "recipe_id IN (SELECT id FROM recipe WHERE space_id = ?1)".to_string(),
+        ),
+        step("meal_plan"),
+        step("recipe"),
+        step_where(
+            "shopping_list_item",
+            "list_id IN (SELECT id FROM shopping_list WHERE space_id = ?1)".to_string(),
+        ),
+        step("shopping_list"),
+        step_where(
+            "itinerary_item",
+            "trip_id IN (SELECT id FROM trip WHERE space_id = ?1)".to_string(),
+        ),
+        step_where(
+            "travel_document",
+            "trip_id IN (SELECT id FROM trip WHERE space_id = ?1)".to_string(),
+        ),
+        step_where(
+            "packing_item",
+            "trip_id IN (SELECT id FROM trip WHERE space_id = ?1)".to_string(),
+        ),
+        step_where(
+            "packing_template_item",
+            "template_id IN (SELECT id FROM packing_template WHERE space_id = ?1)".to_string(),
+        ),
+        step("packing_template"),
+        step("trip"),
+        // Note internals; blobs themselves are left to blob GC, which also
+        // sweeps their encrypted objects
+        step_where("note_embeddings", format!("note_id IN {notes}")),
+        step_where("rag_dirty_note", format!("note_id IN {notes}")),
+        step_where("note_attachment", format!("note_id IN {notes}")),
+        step_where(
+            "blob_ref",
+            format!("owner_type = 'note' AND owner_id IN {notes}"),
+        ),
+        step_where("note_meta", format!("note_id IN {notes}")),
+        step_where("note_crdt_update", format!("note_id IN {notes}")),
+        step_where("note_minhash", format!("note_id IN {notes}")),
+        step_where("note_edit_session", format!("note_id IN {notes}")),
+        step_where("note_version", format!("note_id IN {notes}")),
+        step("note_merge"),
+        step_where(
+            "note_move",
+            format!("from_space_id = ?1 OR to_space_id = ?1 OR note_id IN {notes}"),
+        ),
+        step_where(
+            "note_reference",
+            format!("space_id = ?1 OR note_id IN {notes}"),
+        ),
+        step("weekly_review"),
+        step_where("note_property", format!("note_id IN {notes}")),
+        step("note_word_delta"),
+        step_where(
+            "link",
+            format!("source_note_id IN {notes} OR target_note_id IN {notes}"),
+        ),
+        step_where(
+            "fts_note",
+            "rowid IN (SELECT rowid FROM note WHERE space_id = ?1)".to_string(),
+        ),
+        // Tasks and projects
+        step("task"),
+        step_where("project_milestone", format!("project_id IN {projects}")),
+        step_where(
+            "project_dependency",
+            format!("project_id IN {projects} OR depends_on_project_id IN {projects}"),
+        ),
+        step_where("project_risk", format!("project_id IN {projects}")),
+        step_where("project_update", format!("project_id IN {projects}")),
+        step_where(
+            "project_health_history",
+            format!("project_id IN {projects}"),
+        ),
+        step("project"),
+        step("note"),
+        step("tag"),
+        step("saved_search"),
+        step("task_view"),
+        step("note_template"),
+        step("property_definition"),
+        step("automation_rule"),
+        step("space_people"),
+        step("person"),
+        // Social
+        step_where(
+            "social_post_category",
+            format!(
+                "category_id IN {categories}
+                 OR post_id IN (SELECT id FROM social_post WHERE account_id IN {accounts})"
+            ),
+        ),
+        step_where("social_auto_rule", format!("category_id IN {categories}")),
+        step("social_category_rule"),
+        step_where("social_sync_history", format!("account_id IN {accounts}")),
+        step_where(
+            "social_webview_session",
+            format!("account_id IN {accounts}"),
+        ),
+        step_where("social_post_archive", format!("account_id IN {accounts}")),
+        step_where("social_post", format!("account_id IN {accounts}")),
+        step("social_account"),
+        step("social_category"),
+        step_where(
+            "social_time_limit",
+            "focus_mode_id IN (SELECT id FROM social_focus_mode WHERE space_id = ?1)".to_string(),
+        ),
+        step("social_focus_mode"),
+        step("social_platform_usage"),
+        step("social_usage_settings"),
+        step_where(
+            "social_automation_firing",
+            "rule_id IN (SELECT id FROM social_automation_rule WHERE space_id = ?1)".to_string(),
+        ),
+        step("social_automation_rule"),
+        // Music, calendar, goals, habits, insights
+        step_where(
+            "track_play",
+            "track_id IN (SELECT id FROM track WHERE space_id = ?1)".to_string(),
+        ),
+        step_where(
+            "playlist_track",
+            "playlist_id IN (SELECT id FROM playlist WHERE space_id = ?1)
+             OR track_id IN (SELECT id FROM track WHERE space_id = ?1)"
+                .to_string(),
+        ),
+        step("playlist"),
+        step("track"),
+        step("calendar_event"),
+        step("goal"),
+        step_where(
+            "habit_log",
+            "habit_id IN (SELECT id FROM habit WHERE space_id = ?1)".to_string(),
+        ),
+        step("habit"),
+        step("insight"),
+        step("insight_schedule_state"),
+        step("undo_log"),
+        // Collaboration
+        step("user_permissions"),
+        step("user_invitations"),
+        step("space_user_roles"),
+        step("space_users"),
+        // Derived data; deleting notes and links above logs graph changes,
+        // so these go last
+        step("graph_change_log"),
+        step("graph_summary"),
+        step("graph_node_metric"),
+        step("graph_snapshot"),
+        step("graph_milestone"),
+        step("dashboard_stats"),
+    ]
+}
+
+/// Permanently delete a space and everything in it, in one transaction.
+/// `confirm_token` must equal [`deletion_confirm_token`] of the space's
+/// current name, so a space can't be deleted by passing its id alone.
+pub fn delete_space(
+    conn: &mut Connection,
+    space_id: &str,
+    confirm_token: &str,
+) -> Result<SpaceDeletionSummary, SpaceError> {
+    log::info!("[space] Deleting space: {}", space_id);
+    let tx = conn.transaction()?;
+    let name = space_name(&tx, space_id)?;
+    let expected = deletion_confirm_token(&name);
+    if confirm_token != expected {
+        return Err(SpaceError::ConfirmationMismatch { expected });
+    }
+
+    let mut deleted = Vec::new();
+    for step in deletion_steps() {
+        if !table_exists(&tx, step.table)? {
+            continue;
+        }
+        let condition = step.condition.as_deref().unwrap_or("space_id = ?1");
+        let count = tx.execute(
+            &format!("DELETE FROM \"{}\" WHERE {}", step.table, condition),
+            [space_id],
+        )?;
+        if count > 0 {
+            deleted.push(DeletedRows {
+                table: step.table.to_string(),
+                count: count as i64,
+            });
+        }
+    }
+    tx.execute("DELETE FROM space WHERE id = ?1", [space_id])?;
+    deleted.push(DeletedRows {
+        table: "space".to_string(),
+        count: 1,
+    });
+    tx.commit()?;
+
+    let total_rows: i64 = deleted.iter().map(|d| d.count).sum();
+    log::info!(
+        "[space] Deleted space '{}' and {} rows",
+        name,
+        total_rows - 1
+    );
+    Ok(SpaceDeletionSummary {
+        space_id: space_id.to_string(),
+        name,
+        deleted,
+        total_rows,
+    })
+}
@@ -0,0 +1,394 @@
+//! Space Templates
+//!
+//! A space template is a JSON bundle of everything that makes a space ready
+//! to use: enabled modes, tags, saved searches, form templates, note
+//! templates (plus which one daily notes use) and optionally a few starter
+//! notes. Nothing else from the source space is exported, so a template is
+//! safe to share.
+//!
+//! Provisioning a space gives every item a fresh ID. References between
+//! items are by key instead: a note template's `key` is its ID in the
+//! source space and `daily_note_template` names one, and a starter note's
+//! `key` is its source note ID, which is rewritten to the new note ID
+//! wherever another starter note links to it. Links by title need no
+//! rewriting; they resolve once all starter notes exist.
+
+use crate::db::DbError;
+use crate::form::{create_form_template, get_form_templates_for_space, FormField};
+use crate::mode::{enable_core_pack, get_space_modes, mode_lifecycle, Mode};
+use crate::note::refresh_note;
+use crate::note_template::{
+    create_note_template, daily_template_key, get_note_templates_for_space,
+    set_daily_note_template, TemplateError, TemplateVariable,
+};
+use crate::tag::{add_tag_to_note, normalize_tag_name, TagError};
+use rusqlite::{Connection, OptionalExtension};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::Path;
+use thiserror::Error;
+use ulid::Ulid;
+
+/// Version written by [`export_space_template`]; newer bundles are refused
+pub const SPACE_TEMPLATE_FORMAT_VERSION: u32 = 1;
+
+/// Keys of the templates shipped with the app, for
+/// [`builtin_space_template`]
+pub const BUILTIN_SPACE_TEMPLATES: &[&str] = &["personal", "project"];
+
+#[derive(Error, Debug)]
+pub enum SpaceTemplateError {
+    #[error("Database error: {0}")]
+    Database(#[from] DbError),
+    #[error("Rusqlite error: {0}")]
+    Rusqlite(#[from] rusqlite::Error),
+    #[error("Serde JSON error: {0}")]
+    SerdeJson(#[from] serde_json::Error),
+    #[error("I/O error: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("Note template error: {0}")]
+    NoteTemplate(#[from] TemplateError),
+    #[error("Tag error: {0}")]
+    Tag(#[from] TagError),
+    #[error("Space not found: {0}")]
+    SpaceNotFound(String),
+    #[error("Note not found in the space: {0}")]
+    NoteNotFound(String),
+    #[error("Note {0} is locked and can't be exported")]
+    NoteLocked(String),
+    #[error("Unknown space template: {0}")]
+    UnknownBuiltin(String),
+    #[error("Space template format {0} is newer than this version supports")]
+    UnsupportedVersion(u32),
+    #[error("Invalid space template: {0}")]
+    Invalid(String),
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SpaceTemplate {
+    pub format_version: u32,
+    /// Name of the space the template was exported from
+    pub name: String,
+    #[serde(default)]
+    pub description: Option<String>,
+    #[serde(default)]
+    pub modes: Vec<Mode>,
+    /// Hierarchical tags keep their full `parent/child` name
+    #[serde(default)]
+    pub tags: Vec<TemplateTag>,
+    #[serde(default)]
+    pub saved_searches: Vec<TemplateSavedSearch>,
+    #[serde(default)]
+    pub form_templates: Vec<TemplateForm>,
+    #[serde(default)]
+    pub note_templates: Vec<TemplateNoteTemplate>,
+    /// Key of the note template new daily notes use
+    #[serde(default)]
+    pub daily_note_template: Option<String>,
+    #[serde(default)]
+    pub starter_notes: Vec<StarterNote>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TemplateTag {
+    pub name: String,
+    pub color: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TemplateSavedSearch {
+    pub name: String,
+    pub query: String,
+    pub scope: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TemplateForm {
+    pub name: String,
+    pub fields: Vec<FormField>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TemplateNoteTemplate {
+    pub key: String,
+    pub name: String,
+    pub content: String,
+    pub variables: Vec<TemplateVariable>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StarterNote {
+    pub key: String,
+    pub title: String,
+    pub content_md: String,
+    #[serde(default)]
+    pub tags: Vec<String>,
+}
+
+/// Capture the space's setup as a template. Notes are included only when
+/// listed in `starter_note_ids`.
+pub fn space_template_for(
+    conn: &Connection,
+    space_id: &str,
+    starter_note_ids: &[String],
+) -> Result<SpaceTemplate, SpaceTemplateError> {
+    let name: String = conn
+        .query_row("SELECT name FROM space WHERE id = ?1", [space_id], |row| {
+            row.get(0)
+        })
+        .optional()?
+        .ok_or_else(|| SpaceTemplateError::SpaceNotFound(space_id.to_string()))?;
+
+    let mut stmt = conn.prepare("SELECT name, color FROM tag WHERE space_id = ?1 ORDER BY name")?;
+    let tags = stmt
+        .query_map([space_id], |row| {
+            Ok(TemplateTag {
+                name: row.get(0)?,
+                color: row.get(1)?,
+            })
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let mut stmt = conn.prepare(
+        "SELECT title, query_string, scope FROM saved_search WHERE space_id = ?1 ORDER BY title",
+    )?;
+    let saved_searches = stmt
+        .query_map([space_id], |row| {
+            Ok(TemplateSavedSearch {
+                name: row.get(0)?,
+                query: row.get(1)?,
+                scope: row.get(2)?,
+            })
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let form_templates = get_form_templates_for_space(conn, space_id)?
+        .into_iter()
+        .map(|form| TemplateForm {
+            name: form.name,
+            fields: form.fields,
+        })
+        .collect();
+
+    let note_templates = get_note_templates_for_space(conn, space_id)?
+        .into_iter()
+        .map(|template| TemplateNoteTemplate {
+            key: template.id,
+            name: template.name,
+            content: template.content,
+            variables: template.variables,
+        })
+        .collect::<Vec<_>>();
+    // A daily template that was since deleted is dropped
+    let daily_note_template = crate::db::get_setting(conn, &daily_template_key(space_id))?
+        .filter(|id| note_templates.iter().any(|t| &t.key == id));
+
+    let mut starter_notes = Vec::new();
+    for note_id in starter_note_ids {
+        let note: Option<(String, String, bool)> = conn
+            .query_row(
+                "SELECT title, content_md, is_locked FROM note
+                 WHERE id = ?1 AND space_id = ?2 AND is_trashed = 0",
+                rusqlite::params![note_id, space_id],
+                |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+            )
+            .optional()?;
+        let (title, content_md, is_locked) =
+            note.ok_or_else(|| SpaceTemplateError::NoteNotFound(note_id.clone()))?;
+        if is_locked {
+            return Err(SpaceTemplateError::NoteLocked(note_id.clone()));
+        }
+        let mut stmt = conn.prepare(
+            "SELECT t.name FROM note_tags nt JOIN tag t ON t.id = nt.tag_id
+             WHERE nt.note_id = ?1 ORDER BY t.name",
+        )?;
+        let tags = stmt
+            .query_map([note_id], |row| row.get(0))?
+            .collect::<Result<Vec<String>, _>>()?;
+        starter_notes.push(StarterNote {
+            key: note_id.clone(),
+            title,
+            content_md,
+            tags,
+        });
+    }
+
+    Ok(SpaceTemplate {
+        format_version: SPACE_TEMPLATE_FORMAT_VERSION,
+        name,
+        description: None,
+        modes: get_space_modes(conn, space_id)?,
+        tags,
+        saved_searches,
+        form_templates,
+        note_templates,
+        daily_note_template,
+        starter_notes,
+    })
+}
+
+/// Write the space's template to `path` as JSON; see [`space_template_for`]
+pub fn export_space_template(
+    conn: &Connection,
+    space_id: &str,
+    path: &Path,
+    starter_note_ids: &[String],
+) -> Result<SpaceTemplate, SpaceTemplateError> {
+    log::info!("[space_template] Exporting template of space: {}", space_id);
+    let template = space_template_for(conn, space_id, starter_note_ids)?;
+    std::fs::write(path, serde_json::to_string_pretty(&template)?)?;
+    Ok(template)
+}
+
+/// One of the [`BUILTIN_SPACE_TEMPLATES`]
+pub fn builtin_space_template(key: &str) -> Result<SpaceTemplate, SpaceTemplateError> {
+    let json = match key {
+        "personal" => include_str!("space_template/personal.json"),
+        "project" => include_str!("space_template/project.json"),
+        _ => return Err(SpaceTemplateError::UnknownBuiltin(key.to_string())),
+    };
+    Ok(serde_json::from_str(json)?)
+}
+
+/// Create a space named `name` from the template bundle at `template_path`
+pub fn create_space_from_template(
+    conn: &mut Connection,
+    name: &str,
+    template_path: &Path,
+) -> Result<Ulid, SpaceTemplateError> {
+    let template: SpaceTemplate = serde_json::from_str(&std::fs::read_to_string(template_path)?)?;
+    provision_space(conn, name, &template)
+}
+
+/// Create a space named `name` and provision everything in `template`, all
+/// or nothing. Tags whose names normalize to the same name are merged.
+pub fn provision_space(
+    conn: &mut Connection,
+    name: &str,
+    template: &SpaceTemplate,
+) -> Result<Ulid, SpaceTemplateError> {
+    log::info!(
+        "[space_template] Creating space '{}' from template '{}'",
+        name,
+        template.name
+    );
+    if template.format_version > SPACE_TEMPLATE_FORMAT_VERSION {
+        return Err(SpaceTemplateError::UnsupportedVersion(
+            template.format_version,
+        ));
+    }
+    if let Some(key) = &template.daily_note_template {
+        if !template.note_templates.iter().any(|t| &t.key == key) {
+            return Err(SpaceTemplateError::Invalid(format!(
+                "daily note template '{}' is not in the template",
+                key
+            )));
+        }
+    }
+
+    let space_id = Ulid::new();
+    let space = space_id.to_string();
+    let tx = conn.transaction()?;
+    tx.execute(
+        "INSERT INTO space (id, name, enabled_modes_json) VALUES (?1, ?2, ?3)",
+        rusqlite::params![space, name, serde_json::to_string(&template.modes)?],
+    )?;
+    if template.modes.is_empty() {
+        enable_core_pack(&tx, space_id)?;
+    }
+    for mode in &template.modes {
+        if let Some(setup) = mode_lifecycle(&mode.id).setup {
+            setup(&tx, &space)?;
+        }
+    }
+
+    for tag in &template.tags {
+        let tag_name = normalize_tag_name(&tag.name)?;
+        tx.execute(
+            "INSERT INTO tag (id, space_id, name, color) VALUES (?1, ?2, ?3, ?4)
+             ON CONFLICT(space_id, name) DO UPDATE SET color = COALESCE(tag.color, excluded.color)",
+            rusqlite::params![Ulid::new().to_string(), space, tag_name, tag.color],
+        )?;
+    }
+
+    for search in &template.saved_searches {
+        tx.execute(
+            "INSERT INTO saved_search (id, space_id, title, query_string, scope)
+             VALUES (?1, ?2, ?3, ?4, ?5)",
+            rusqlite::params![
+                Ulid::new().to_string(),
+                space,
+                search.name,
+                search.query,
+                search.scope
+            ],
+        )?;
+    }
+
+    for form in &template.form_templates {
+        create_form_template(&tx, &space, &form.name, form.fields.clone())?;
+    }
+
+    let mut note_template_ids = HashMap::new();
+    for note_template in &template.note_templates {
+        let created = create_note_template(
+            &tx,
+            &space,
+            &note_template.name,
+            &note_template.content,
+            note_template.variables.clone(),
+        )?;
+        note_template_ids.insert(note_template.key.as_str(), created.id);
+    }
+    if let Some(key) = &template.daily_note_template {
+        set_daily_note_template(&tx, &space, Some(&note_template_ids[key.as_str()]))?;
+    }
+
+    provision_starter_notes(&tx, &space, &template.starter_notes)?;
+
+    tx.commit()?;
+    Ok(space_id)
+}
+
+fn provision_starter_notes(
+    conn: &Connection,
+    space_id: &str,
+    notes: &[StarterNote],
+) -> Result<(), SpaceTemplateError> {
+    let mut created = Vec::with_capacity(notes.len());
+    for note in notes {
+        let id = crate::note::create_note(conn, space_id, &note.title, &note.content_md)?
+            .id
+            .0
+            .to_string();
+        for tag in &note.tags {
+            add_tag_to_note(conn, &id, tag)?;
+        }
+        created.push(id);
+    }
+
+    // Only keys that are note IDs are rewritten, so a key like `welcome`
+    // doesn't replace the word in a body
+    let remap = notes
+        .iter()
+        .zip(&created)
+        .filter(|(note, _)| Ulid::from_string(&note.key).is_ok())
+        .map(|(note, id)| (note.key.as_str(), id.as_str()))
+        .collect::<Vec<_>>();
+    let now = chrono::Utc::now().timestamp();
+    for (note, id) in notes.iter().zip(&created) {
+        let mut content = note.content_md.clone();
+        for (key, new_id) in &remap {
+            content = content.replace(key, new_id);
+        }
+        if content != note.content_md {
+            conn.execute(
+                "UPDATE note SET content_md = ?1 WHERE id = ?2",
+                [&content, id],
+            )?;
+        }
+        // Also resolves links to starter notes created after this one
+        refresh_note(conn, id, now)?;
+    }
+    Ok(())
+}
@@ -1,5 +1,5 @@
 use crate::db::DbError;
-use chrono::{Duration, Utc};
+use chrono::{DateTime, Duration, Utc};
 use rusqlite::{Connection, OptionalExtension, Result};
 use serde::{Deserialize, Serialize};
 use ulid::Ulid;
@@ -100,65 +100,77 @@ pub fn create_knowledge_card(conn: &Connection, note_id: Ulid) -> Result<Knowled
     Ok(card)
 }
 
-pub fn get_knowledge_card(conn: &Connection, id: Ulid) -> Result<Option<KnowledgeCard>, DbError> {
-    log::info!("[srs] Getting knowledge card with id: {}", id);
-    let mut stmt = conn.prepare("SELECT id, note_id, deck_id, state, due_at, stability, difficulty, lapses, revision_history_json FROM knowledge_card WHERE id = ?1")?;
-    let card: Option<KnowledgeCard> = stmt
-        .query_row([id.to_string()], |row| {
-            let revision_history_json: String = row.get(8)?;
-            let revision_history: Vec<RevisionHistory> =
-                serde_json::from_str(&revision_history_json).map_err(|e| {
-                    rusqlite::Error::FromSqlConversionFailure(
-                        8,
-                        rusqlite::types::Type::Text,
-                        Box::new(e),
-                    )
-                })?;
+/// Columns read by [`card_from_row`], in order
+pub(crate) const CARD_COLUMNS: &str =
+    "id, note_id, deck_id, state, due_at, stability, difficulty, lapses, revision_history_json";
 
-            let id_str: String = row.get(0)?;
-            let note_id_str: String = row.get(1)?;
-            let state_str: String = row.get(3)?;
+pub(crate) fn card_from_row(row: &rusqlite::Row) -> rusqlite::Result<KnowledgeCard> {
+    let revision_history_json: String = row.get(8)?;
+    let revision_history: Vec<RevisionHistory> = serde_json::from_str(&revision_history_json)
+        .map_err(|e| {
+            rusqlite::Error::FromSqlConversionFailure(8, rusqlite::types::Type::Text, Box::new(e))
+        })?;
 
-            Ok(KnowledgeCard {
-                id: Ulid::from_string(&id_str).map_err(|e| {
-                    rusqlite::Error::FromSqlConversionFailure(
-                        0,
-                        rusqlite::types::Type::Text,
-                        Box::new(e),
-                    )
-                })?,
-                note_id: Ulid::from_string(&note_id_str).map_err(|e| {
-                    rusqlite::Error::FromSqlConversionFailure(
-                        1,
-                        rusqlite::types::Type::Text,
-                        Box::new(e),
-                    )
-                })?,
-                deck_id: row.get(2)?,
-                state: CardState::from_str(&state_str).map_err(|e| {
-                    rusqlite::Error::FromSqlConversionFailure(
-                        3,
-                        rusqlite::types::Type::Text,
-                        Box::new(e),
-                    )
-                })?,
-                due_at: row.get(4)?,
-                stability: row.get(5)?,
-                difficulty: row.get(6)?,
-                lapses: row.get(7)?,
-                revision_history,
-            })
-        })
-        .optional()?;
+    let id_str: String = row.get(0)?;
+    let note_id_str: String = row.get(1)?;
+    let state_str: String = row.get(3)?;
+
+    Ok(KnowledgeCard {
+        id: Ulid::from_string(&id_str).map_err(|e| {
+            rusqlite::Error::FromSqlConversionFailure(0, rusqlite::types::Type::Text, Box::new(e))
+        })?,
+        note_id: Ulid::from_string(&note_id_str).map_err(|e| {
+            rusqlite::Error::FromSqlConversionFailure(1, rusqlite::types::Type::Text, Box::new(e))
+        })?,
+        deck_id: row.get(2)?,
+        state: CardState::from_str(&state_str).map_err(|e| {
+            rusqlite::Error::FromSqlConversionFailure(3, rusqlite::types::Type::Text, Box::new(e))
+        })?,
+        due_at: row.get(4)?,
+        stability: row.get(5)?,
+        difficulty: row.get(6)?,
+        lapses: row.get(7)?,
+        revision_history,
+    })
+}
+
+pub fn get_knowledge_card(conn: &Connection, id: Ulid) -> Result<Option<KnowledgeCard>, DbError> {
+    log::info!("[srs] Getting knowledge card with id: {}", id);
+    let mut stmt = conn.prepare(&format!(
+        "SELECT {CARD_COLUMNS} FROM knowledge_card WHERE id = ?1"
+    ))?;
+    let card: Option<KnowledgeCard> = stmt.query_row([id.to_string()], card_from_row).optional()?;
     Ok(card)
 }
 
-pub fn review_card(conn: &Connection, card_id: Ulid, rating: i64) -> Result<(), DbError> {
+/// Answer a card. With a `session_id`, the answer is attributed to that
+/// review session (see [`crate::srs_session`]), which must be active and
+/// have the card waiting in its queue.
+pub fn review_card(
+    conn: &Connection,
+    card_id: Ulid,
+    rating: i64,
+    session_id: Option<Ulid>,
+) -> Result<(), DbError> {
+    review_card_at(conn, card_id, rating, session_id, Utc::now().timestamp())
+}
+
+pub fn review_card_at(
+    conn: &Connection,
+    card_id: Ulid,
+    rating: i64,
+    session_id: Option<Ulid>,
+    now: i64,
+) -> Result<(), DbError> {
     log::info!("[srs] Reviewing card: {}, rating: {}", card_id, rating);
     let mut card =
         get_knowledge_card(conn, card_id)?.ok_or(DbError::Message("Card not found".into()))?;
+    if let Some(session_id) = session_id {
+        crate::srs_session::record_answer(conn, session_id, card_id, rating, now)?;
+    }
 
-    let now = Utc::now();
+    let now = DateTime::from_timestamp(now, 0)
+        .ok_or_else(|| DbError::Message(format!("Invalid review time: {now}")))?;
     let (new_stability, new_difficulty) = if card.state == CardState::New {
         let stability = match rating {
             1 => 1.0,
@@ -196,7 +208,7 @@ pub fn review_card(conn: &Connection, card_id: Ulid, rating: i64) -> Result<(),
     };
 
     conn.execute(
-        "INSERT INTO review_log (id, card_id, review_at, rating, state, due_at, stability, difficulty, lapses) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)",
+        "INSERT INTO review_log (id, card_id, review_at, rating, state, due_at, stability, difficulty, lapses, session_id) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10)",
         rusqlite::params![
             log_entry.id.to_string(),
             log_entry.card_id.to_string(),
@@ -207,6 +219,7 @@ pub fn review_card(conn: &Connection, card_id: Ulid, rating: i64) -> Result<(),
             log_entry.stability,
             log_entry.difficulty,
             log_entry.lapses,
+            session_id.map(|id| id.to_string()),
         ],
     )?;
 
@@ -238,53 +251,11 @@ pub fn review_card(conn: &Connection, card_id: Ulid, rating: i64) -> Result<(),
 pub fn get_due_cards(conn: &Connection) -> Result<Vec<KnowledgeCard>, DbError> {
     log::info!("[srs] Getting due cards");
     let now = Utc::now().timestamp();
-    let mut stmt = conn.prepare("SELECT id, note_id, deck_id, state, due_at, stability, difficulty, lapses, revision_history_json FROM knowledge_card WHERE due_at <= ?1")?;
+    let mut stmt = conn.prepare(&format!(
+        "SELECT {CARD_COLUMNS} FROM knowledge_card WHERE due_at <= ?1"
+    ))?;
     let cards = stmt
-        .query_map([now], |row| {
-            let revision_history_json: String = row.get(8)?;
-            let revision_history: Vec<RevisionHistory> =
-                serde_json::from_str(&revision_history_json).map_err(|e| {
-                    rusqlite::Error::FromSqlConversionFailure(
-                        8,
-                        rusqlite::types::Type::Text,
-                        Box::new(e),
-                    )
-                })?;
-
-            let id_str: String = row.get(0)?;
-            let note_id_str: String = row.get(1)?;
-            let state_str: String = row.get(3)?;
-
-            Ok(KnowledgeCard {
-                id: Ulid::from_string(&id_str).map_err(|e| {
-                    rusqlite::Error::FromSqlConversionFailure(
-                        0,
-                        rusqlite::types::Type::Text,
-                        Box::new(e),
-                    )
-                })?,
-                note_id: Ulid::from_string(&note_id_str).map_err(|e| {
-                    rusqlite::Error::FromSqlConversionFailure(
-                        1,
-                        rusqlite::types::Type::Text,
-                        Box::new(e),
-                    )
-                })?,
-                deck_id: row.get(2)?,
-                state: CardState::from_str(&state_str).map_err(|e| {
-                    rusqlite::Error::FromSqlConversionFailure(
-                        3,
-                        rusqlite::types::Type::Text,
-                        Box::new(e),
-                    )
-                })?,
-                due_at: row.get(4)?,
-                stability: row.get(5)?,
-                difficulty: row.get(6)?,
-                lapses: row.get(7)?,
-                revision_history,
-            })
-        })?
+        .query_map([now], card_from_row)?
         .collect::<Result<Vec<KnowledgeCard>, _>>()?;
     Ok(cards)
 }
@@ -0,0 +1,685 @@
+//! SRS review sessions
+//!
+//! A session is a space's review queue for one UTC day, built from the
+//! cards due in it: reviews up to `max_reviews` and new cards up to
+//! `max_new_cards`, both counted per day across sessions, in the order the
+//! config asks for. Answers given through [`crate::srs::review_card`] with
+//! the session id are attributed to it. A session left unfinished is picked
+//! up again by the next start on the same day and abandoned on a later one.
+//!
+//! Finishing a session with every card answered extends the space's review
+//! streak, the run of consecutive days with a completed session, which the
+//! dashboard shows.
+
+use crate::db::DbError;
+use crate::srs::{card_from_row, get_knowledge_card, CardState, KnowledgeCard, CARD_COLUMNS};
+use chrono::{DateTime, Days, NaiveDate, Utc};
+use rand::seq::SliceRandom;
+use rusqlite::{params, Connection, OptionalExtension};
+use serde::{Deserialize, Serialize};
+use ulid::Ulid;
+
+/// Gaps between answers count as at most this long, so a session left open
+/// over lunch doesn't inflate the time spent
+const IDLE_CAP_SECS: i64 = 5 * 60;
+/// Lowest rating that counts as remembering the card, as in
+/// [`crate::srs::review_card`]
+pub const PASSING_RATING: i64 = 2;
+
+/// Order of the cards in a session's queue
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ReviewOrder {
+    /// Most overdue first
+    #[default]
+    DueDate,
+    Random,
+    /// One card from each deck in turn; cards without a deck are grouped by
+    /// the first tag of their note
+    Interleave,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ReviewSessionConfig {
+    /// Cards already learned to review per day
+    pub max_reviews: usize,
+    /// Cards seen for the first time per day
+    pub max_new_cards: usize,
+    /// New cards are spread evenly between the reviews in every order but
+    /// random
+    pub order: ReviewOrder,
+}
+
+impl Default for ReviewSessionConfig {
+    fn default() -> Self {
+        ReviewSessionConfig {
+            max_reviews: 200,
+            max_new_cards: 20,
+            order: ReviewOrder::DueDate,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ReviewSessionStatus {
+    Active,
+    Finished,
+    /// Left unfinished on an earlier day
+    Abandoned,
+}
+
+impl ReviewSessionStatus {
+    fn from_str(s: &str) -> Result<Self, DbError> {
+        match s {
+            "active" => Ok(ReviewSessionStatus::Active),
+            "finished" => Ok(ReviewSessionStatus::Finished),
+            "abandoned" => Ok(ReviewSessionStatus::Abandoned),
+            _ => Err(DbError::Message(format!(
+                "Invalid review session status: {s}"
+            ))),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct QueuedCard {
+    pub card: KnowledgeCard,
+    /// Seen for the first time in this session
+    pub is_new: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReviewSession {
+    pub id: Ulid,
+    pub space_id: String,
+    pub config: ReviewSessionConfig,
+    pub status: ReviewSessionStatus,
+    /// UTC day the session belongs to, `YYYY-MM-DD`
+    pub day: String,
+    pub started_at: i64,
+    pub finished_at: Option<i64>,
+    /// Started earlier today and picked up again
+    pub resumed: bool,
+    /// Cards still to answer, in order
+    pub queue: Vec<QueuedCard>,
+    /// Cards answered so far
+    pub answered: usize,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ReviewSessionStats {
+    pub session_id: Ulid,
+    /// Cards answered in the session
+    pub cards_seen: usize,
+    pub new_cards_seen: usize,
+    /// Answers rated at least [`PASSING_RATING`]
+    pub correct: usize,
+    /// Share of correct answers, 0 when nothing was answered
+    pub accuracy: f64,
+    /// Time from the start to the last answer, with idle gaps capped
+    pub time_spent_secs: i64,
+    /// Every queued card was answered, so the day counts towards the streak
+    pub completed: bool,
+    pub streak_days: i64,
+    pub longest_streak_days: i64,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub struct ReviewStreak {
+    /// Consecutive days with a completed session up to today or yesterday;
+    /// 0 once a day was missed
+    pub current_days: i64,
+    pub longest_days: i64,
+    /// Last day with a completed session, `YYYY-MM-DD`
+    pub last_day: Option<String>,
+}
+
+fn day_of(ts: i64) -> Result<NaiveDate, DbError> {
+    DateTime::from_timestamp(ts, 0)
+        .map(|t| t.date_naive())
+        .ok_or_else(|| DbError::Message(format!("Invalid timestamp: {ts}")))
+}
+
+fn parse_day(day: &str) -> Result<NaiveDate, DbError> {
+    day.parse()
+        .map_err(|_| DbError::Message(format!("Invalid review day: {day}")))
+}
+
+/// Start and end of a UTC day, as timestamps
+fn day_bounds(day: NaiveDate) -> (i64, i64) {
+    let start = day.and_time(chrono::NaiveTime::MIN).and_utc().timestamp();
+    (start, start + 86_400)
+}
+
+/// New cards first answered on `day`, and the other answers given that day
+fn answered_on(
+    conn: &Connection,
+    space_id: &str,
+    day: NaiveDate,
+) -> Result<(usize, usize), DbError> {
+    let (start, end) = day_bounds(day);
+    let (new, total): (i64, i64) = conn.query_row(
+        "WITH space_log AS (
+             SELECT l.card_id, l.review_at FROM review_log l
+             JOIN knowledge_card c ON c.id = l.card_id
+             JOIN note n ON n.id = c.note_id
+             WHERE n.space_id = ?1
+         )
+         SELECT
+             (SELECT COUNT(*) FROM (
+                 SELECT card_id FROM space_log GROUP BY card_id
+                 HAVING MIN(review_at) >= ?2 AND MIN(review_at) < ?3
+             )),
+             (SELECT COUNT(*) FROM space_log WHERE review_at >= ?2 AND review_at < ?3)",
+        params![space_id, start, end],
+        |row| Ok((row.get(0)?, row.get(1)?)),
+    )?;
+    Ok((new as usize, (total - new) as usize))
+}
+
+struct Candidate {
+    queued: QueuedCard,
+    /// Deck, else first tag of the note, else empty
+    group: String,
+}
+
+/// Cards of the space due at `now`, most overdue first
+fn due_candidates(conn: &Connection, space_id: &str, now: i64) -> Result<Vec<Candidate>, DbError> {
+    let columns = CARD_COLUMNS
+        .split(", ")
+        .map(|column| format!("c.{column}"))
+        .collect::<Vec<_>>()
+        .join(", ");
+    let mut stmt = conn.prepare(&format!(
+        "SELECT {columns},
+             COALESCE(c.deck_id, (
+                 SELECT t.name FROM note_tags nt JOIN tag t ON t.id = nt.tag_id
+                 WHERE nt.note_id = c.note_id ORDER BY t.name LIMIT 1
+             ), '')
+         FROM knowledge_card c
+         JOIN note n ON n.id = c.note_id
+         WHERE n.space_id = ?1 AND n.is_trashed = 0 AND c.due_at <= ?2
+         ORDER BY c.due_at, c.id"
+    ))?;
+    let candidates = stmt
+        .query_map(params![space_id, now], |row| {
+            let card = card_from_row(row)?;
+            Ok(Candidate {
+                queued: QueuedCard {
+                    is_new: card.state == CardState::New,
+                    card,
+                },
+                group: row.get(9)?,
+            })
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(candidates)
+}
+
+/// Place the new cards evenly between the reviews, keeping the order of both
+fn spread<T>(reviews: Vec<T>, new: Vec<T>) -> Vec<T> {
+    let total = reviews.len() + new.len();
+    let new_len = new.len();
+    let mut reviews = reviews.into_iter();
+    let mut new = new.into_iter();
+    let mut placed = 0;
+    let mut queue = Vec::with_capacity(total);
+    for position in 0..total {
+        // The k-th new card ends the k-th of new_len + 1 equal stretches
+        let next =
+            if placed < new_len && (placed + 1) * (total + 1) <= (position + 1) * (new_len + 1) {
+                placed += 1;
+                new.next()
+            } else {
+                reviews.next()
+            };
+        queue.extend(next.or_else(|| new.next()).or_else(|| reviews.next()));
+    }
+    queue
+}
+
+/// Deal the cards from their groups in turn, groups in order of their most
+/// overdue card
+fn interleave(candidates: Vec<Candidate>) -> Vec<QueuedCard> {
+    let mut groups: Vec<(String, Vec<QueuedCard>, Vec<QueuedCard>)> = Vec::new();
+    for candidate in candidates {
+        let index = match groups.iter().position(|(g, _, _)| *g == candidate.group) {
+            Some(index) => index,
+            None => {
+                groups.push((candidate.group, Vec::new(), Vec::new()));
+                groups.len() - 1
+            }
+        };
+        let (_, reviews, new) = &mut groups[index];
+        if candidate.queued.is_new {
+            new.push(candidate.queued);
+        } else {
+            reviews.push(candidate.queued);
+        }
+    }
+
+    let mut lanes = groups
+        .into_iter()
+        .map(|(_, reviews, new)| spread(reviews, new).into_iter())
+        .collect::<Vec<_>>();
+    let mut queue = Vec::new();
+    loop {
+        let before = queue.len();
+        queue.extend(lanes.iter_mut().filter_map(|lane| lane.next()));
+        if queue.len() == before {
+            return queue;
+        }
+    }
+}
+
+fn build_queue(
+    conn: &Connection,
+    space_id: &str,
+    config: &ReviewSessionConfig,
+    now: i64,
+) -> Result<Vec<QueuedCard>, DbError> {
+    let (new_today, reviews_today) = answered_on(conn, space_id, day_of(now)?)?;
+    let mut new_left = config.max_new_cards.saturating_sub(new_today);
+    let mut reviews_left = config.max_reviews.saturating_sub(reviews_today);
+    let candidates = due_candidates(conn, space_id, now)?
+        .into_iter()
+        .filter(|candidate| {
+            let left = if candidate.queued.is_new {
+                &mut new_left
+            } else {
+                &mut reviews_left
+            };
+            let keep = *left > 0;
+            *left = left.saturating_sub(1);
+            keep
+        })
+        .collect::<Vec<_>>();
+
+    Ok(match config.order {
+        ReviewOrder::DueDate => {
+            let (new, reviews): (Vec<_>, Vec<_>) = candidates
+                .into_iter()
+                .map(|candidate| candidate.queued)
+                .partition(|queued| queued.is_new);
+            spread(reviews, new)
+        }
+        ReviewOrder::Random => {
+            let mut queue = candidates
+                .into_iter()
+                .map(|candidate| candidate.queued)
+                .collect::<Vec<_>>();
+            queue.shuffle(&mut rand::thread_rng());
+            queue
+        }
+        ReviewOrder::Interleave => interleave(candidates),
+    })
+}
+
+/// Start the day's review session of a space. A session already started
+/// today and not finished is returned instead, with the cards it still has
+/// to go and its original config; active sessions of earlier days are
+/// abandoned.
+pub fn start_review_session(
+    conn: &Connection,
+    space_id: &str,
+    config: &ReviewSessionConfig,
+) -> Result<ReviewSession, DbError> {
+    start_review_session_at(conn, space_id, config, Utc::now().timestamp())
+}
+
+pub fn start_review_session_at(
+    conn: &Connection,
+    space_id: &str,
+    config: &ReviewSessionConfig,
+    now: i64,
+) -> Result<ReviewSession, DbError> {
+    let day = day_of(now)?.to_string();
+    let tx = conn.unchecked_transaction()?;
+    let abandoned = tx.execute(
+        "UPDATE review_session SET status = 'abandoned'
+         WHERE space_id = ?1 AND status = 'active' AND day < ?2",
+        params![space_id, day],
+    )?;
+    if abandoned > 0 {
+        log::info!(
+            "[srs] Abandoned {} review session(s) of earlier days in space {}",
+            abandoned,
+            space_id
+        );
+    }
+
+    let unfinished: Option<String> = tx
+        .query_row(
+            "SELECT id FROM review_session
+             WHERE space_id = ?1 AND status = 'active' AND day = ?2
+             ORDER BY started_at DESC LIMIT 1",
+            params![space_id, day],
+            |row| row.get(0),
+        )
+        .optional()?;
+    if let Some(id) = unfinished {
+        log::info!("[srs] Resuming review session {}", id);
+        tx.execute(
+            "UPDATE review_session SET last_activity_at = MAX(last_activity_at, ?1) WHERE id = ?2",
+            params![now, id],
+        )?;
+        tx.commit()?;
+        let mut session = get_review_session(conn, parse_id(&id)?)?;
+        session.resumed = true;
+        return Ok(session);
+    }
+
+    let id = Ulid::new();
+    let queue = build_queue(&tx, space_id, config, now)?;
+    tx.execute(
+        "INSERT INTO review_session
+             (id, space_id, config_json, status, day, started_at, last_activity_at)
+         VALUES (?1, ?2, ?3, 'active', ?4, ?5, ?5)",
+        params![
+            id.to_string(),
+            space_id,
+            serde_json::to_string(config)?,
+            day,
+            now
+        ],
+    )?;
+    for (position, queued) in queue.iter().enumerate() {
+        tx.execute(
+            "INSERT INTO review_session_card (session_id, position, card_id, is_new)
+             VALUES (?1, ?2, ?3, ?4)",
+            params![
+                id.to_string(),
+                position as i64,
+                queued.card.id.to_string(),
+                queued.is_new
+            ],
+        )?;
+    }
+    tx.commit()?;
+    log::info!(
+        "[srs] Started review session {} in space {} with {} card(s)",
+        id,
+        space_id,
+        queue.len()
+    );
+
+    Ok(ReviewSession {
+        id,
+        space_id: space_id.to_string(),
+        config: config.clone(),
+        status: ReviewSessionStatus::Active,
+        day,
+        started_at: now,
+        finished_at: None,
+        resumed: false,
+        queue,
+        answered: 0,
+    })
+}
+
+fn parse_id(id: &str) -> Result<Ulid, DbError> {
+    Ulid::from_string(id).map_err(|e| DbError::Message(format!("Invalid review session id: {e}")))
+}
+
+pub fn get_review_session(conn: &Connection, id: Ulid) -> Result<ReviewSession, DbError> {
+    let (space_id, config_json, status, day, started_at, finished_at): (
+        String,
+        String,
+        String,
+        String,
+        i64,
+        Option<i64>,
+    ) = conn
+        .query_row(
+            "SELECT space_id, config_json, status, day, started_at, finished_at
+             FROM review_session WHERE id = ?1",
+            [id.to_string()],
+            |row| {
+                Ok((
+                    row.get(0)?,
+                    row.get(1)?,
+                    row.get(2)?,
+                    row.get(3)?,
+                    row.get(4)?,
+                    row.get(5)?,
+                ))
+            },
+        )
+        .optional()?
+        .ok_or_else(|| DbError::NotFound {
+            entity: "review_session",
+            id: id.to_string(),
+        })?;
+
+    let mut stmt = conn.prepare(
+        "SELECT card_id, is_new, answered_at IS NOT NULL FROM review_session_card
+         WHERE session_id = ?1 ORDER BY position",
+    )?;
+    let rows = stmt
+        .query_map([id.to_string()], |row| {
+            Ok((
+                row.get::<_, String>(0)?,
+                row.get::<_, bool>(1)?,
+                row.get::<_, bool>(2)?,
+            ))
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
+    let mut queue = Vec::new();
+    let mut answered = 0;
+    for (card_id, is_new, is_answered) in rows {
+        if is_answered {
+            answered += 1;
+            continue;
+        }
+        let card_id = Ulid::from_string(&card_id)
+            .map_err(|e| DbError::Message(format!("Invalid card id: {e}")))?;
+        if let Some(card) = get_knowledge_card(conn, card_id)? {
+            queue.push(QueuedCard { card, is_new });
+        }
+    }
+
+    Ok(ReviewSession {
+        id,
+        space_id,
+        config: serde_json::from_str(&config_json)?,
+        status: ReviewSessionStatus::from_str(&status)?,
+        day,
+        started_at,
+        finished_at,
+        resumed: false,
+        queue,
+        answered,
+    })
+}
+
+/// Attribute an answer to a session, before the card itself is updated
+pub(crate) fn record_answer(
+    conn: &Connection,
+    session_id: Ulid,
+    card_id: Ulid,
+    rating: i64,
+    now: i64,
+) -> Result<(), DbError> {
+    let (status, last_activity_at): (String, i64) = conn
+        .query_row(
+            "SELECT status, last_activity_at FROM review_session WHERE id = ?1",
+            [session_id.to_string()],
+            |row| Ok((row.get(0)?, row.get(1)?)),
+        )
+        .optional()?
+        .ok_or_else(|| DbError::NotFound {
+            entity: "review_session",
+            id: session_id.to_string(),
+        })?;
+    if ReviewSessionStatus::from_str(&status)? != ReviewSessionStatus::Active {
+        return Err(DbError::Locked {
+            entity: "review_session",
+            id: session_id.to_string(),
+        });
+    }
+
+    let position: i64 = conn
+        .query_row(
+            "SELECT position FROM review_session_card
+             WHERE session_id = ?1 AND card_id = ?2 AND answered_at IS NULL
+             ORDER BY position LIMIT 1",
+            params![session_id.to_string(), card_id.to_string()],
+            |row| row.get(0),
+        )
+        .optional()?
+        .ok_or_else(|| {
+            DbError::Message(format!(
+                "Card {card_id} isn't waiting in review session {session_id}"
+            ))
+        })?;
+    conn.execute(
+        "UPDATE review_session_card SET answered_at = ?1, rating = ?2
+         WHERE session_id = ?3 AND position = ?4",
+        params![now, rating, session_id.to_string(), position],
+    )?;
+    let active = (now - last_activity_at).clamp(0, IDLE_CAP_SECS);
+    conn.execute(
+        "UPDATE review_session
+         SET active_secs = active_secs + ?1, last_activity_at = MAX(last_activity_at, ?2)
+         WHERE id = ?3",
+        params![active, now, session_id.to_string()],
+    )?;
+    Ok(())
+}
+
+/// Count a completed session's day towards the space's streak
+fn record_completed_day(conn: &Connection, space_id: &str, day: NaiveDate) -> Result<(), DbError> {
+    let stored: Option<(i64, i64, String)> = conn
+        .query_row(
+            "SELECT current_streak, longest_streak, last_day FROM review_streak
+             WHERE space_id = ?1",
+            [space_id],
+            |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+        )
+        .optional()?;
+    let (current, longest) = match stored {
+        None => (1, 1),
+        Some((current, longest, last_day)) => {
+            let last_day = parse_day(&last_day)?;
+            if day <= last_day {
+                return Ok(());
+            }
+            let current = if last_day.checked_add_days(Days::new(1)) == Some(day) {
+                current + 1
+            } else {
+                1
+            };
+            (current, longest.max(current))
+        }
+    };
+    conn.execute(
+        "INSERT INTO review_streak (space_id, current_streak, longest_streak, last_day)
+         VALUES (?1, ?2, ?3, ?4)
+         ON CONFLICT(space_id) DO UPDATE SET
+             current_streak = excluded.current_streak,
+             longest_streak = excluded.longest_streak,
+             last_day = excluded.last_day",
+        params![space_id, current, longest, day.to_string()],
+    )?;
+    Ok(())
+}
+
+/// Finish a session and summarize it. Finishing it again returns the same
+/// summary; abandoned sessions can't be finished.
+pub fn finish_review_session(conn: &Connection, id: Ulid) -> Result<ReviewSessionStats, DbError> {
+    finish_review_session_at(conn, id, Utc::now().timestamp())
+}
+
+pub fn finish_review_session_at(
+    conn: &Connection,
+    id: Ulid,
+    now: i64,
+) -> Result<ReviewSessionStats, DbError> {
+    let session = get_review_session(conn, id)?;
+    let completed = session.queue.is_empty();
+    match session.status {
+        ReviewSessionStatus::Abandoned => {
+            return Err(DbError::Locked {
+                entity: "review_session",
+                id: id.to_string(),
+            })
+        }
+        ReviewSessionStatus::Finished => {}
+        ReviewSessionStatus::Active => {
+            let tx = conn.unchecked_transaction()?;
+            tx.execute(
+                "UPDATE review_session SET status = 'finished', finished_at = ?1 WHERE id = ?2",
+                params![now, id.to_string()],
+            )?;
+            if completed {
+                record_completed_day(&tx, &session.space_id, parse_day(&session.day)?)?;
+            }
+            tx.commit()?;
+            log::info!("[srs] Finished review session {}", id);
+        }
+    }
+
+    let (cards_seen, new_cards_seen, correct, time_spent_secs): (i64, i64, i64, i64) = conn
+        .query_row(
+            "SELECT COUNT(c.answered_at),
+                    COALESCE(SUM(c.answered_at IS NOT NULL AND c.is_new), 0),
+                    COALESCE(SUM(c.rating >= ?2), 0),
+                    s.active_secs
+             FROM review_session s
+             LEFT JOIN review_session_card c ON c.session_id = s.id
+             WHERE s.id = ?1",
+            params![id.to_string(), PASSING_RATING],
+            |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?)),
+        )?;
+    let streak = get_review_streak_at(conn, &session.space_id, now)?;
+    Ok(ReviewSessionStats {
+        session_id: id,
+        cards_seen: cards_seen as usize,
+        new_cards_seen: new_cards_seen as usize,
+        correct: correct as usize,
+        accuracy: if cards_seen > 0 {
+            correct as f64 / cards_seen as f64
+        } else {
+            0.0
+        },
+        time_spent_secs,
+        completed,
+        streak_days: streak.current_days,
+        longest_streak_days: streak.longest_days,
+    })
+}
+
+pub fn get_review_streak(conn: &Connection, space_id: &str) -> Result<ReviewStreak, DbError> {
+    get_review_streak_at(conn, space_id, Utc::now().timestamp())
+}
+
+pub fn get_review_streak_at(
+    conn: &Connection,
+    space_id: &str,
+    now: i64,
+) -> Result<ReviewStreak, DbError> {
+    let stored: Option<(i64, i64, String)> = conn
+        .query_row(
+            "SELECT current_streak, longest_streak, last_day FROM review_streak
+             WHERE space_id = ?1",
+            [space_id],
+            |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+        )
+        .optional()?;
+    let Some((current, longest, last_day)) = stored else {
+        return Ok(ReviewStreak::default());
+    };
+    let yesterday = day_of(now)?.checked_sub_days(Days::new(1));
+    let current_days = if Some(parse_day(&last_day)?) >= yesterday {
+        current
+    } else {
+        0
+    };
+    Ok(ReviewStreak {
+        current_days,
+        longest_days: longest,
+        last_day: Some(last_day),
+    })
+}
@@ -0,0 +1,164 @@
+//! Permission checks on incoming deltas.
+//!
+//! A paired device can be linked to the user it belongs to, stored on its
+//! `sync_state` row. Deltas for a shared space are applied only if that user
+//! could make the same change locally (see [`crate::permission`]); the rest,
+//! including everything from devices not linked to a user, are recorded in
+//! `sync_rejected_delta` instead of being applied.
+
+use crate::permission::{
+    is_permitted, is_shared_space, PERMISSION_ADMIN, PERMISSION_DELETE, PERMISSION_WRITE,
+};
+use crate::sync::error::SyncError;
+use crate::sync::models::{SyncDelta, SyncOperation};
+use rusqlite::{Connection, OptionalExtension};
+use serde::{Deserialize, Serialize};
+use ulid::Ulid;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RejectedDelta {
+    pub id: String,
+    pub device_id: String,
+    /// None when the device isn't linked to a user
+    pub user_id: Option<String>,
+    pub space_id: String,
+    pub entity_type: String,
+    pub entity_id: String,
+    pub operation: String,
+    /// The permission the change needed
+    pub permission: String,
+    pub rejected_at: i64,
+}
+
+/// User the deltas from `device_id` are applied for
+pub fn get_device_user(conn: &Connection, device_id: &str) -> Result<Option<String>, SyncError> {
+    Ok(conn
+        .query_row(
+            "SELECT user_id FROM sync_state WHERE device_id = ?1",
+            [device_id],
+            |row| row.get(0),
+        )
+        .optional()?
+        .flatten())
+}
+
+/// Link a registered device to the user it belongs to, or unlink it with None
+pub fn set_device_user(
+    conn: &Connection,
+    device_id: &str,
+    user_id: Option<&str>,
+) -> Result<(), SyncError> {
+    let updated = conn.execute(
+        "UPDATE sync_state SET user_id = ?1 WHERE device_id = ?2",
+        rusqlite::params![user_id, device_id],
+    )?;
+    if updated == 0 {
+        return Err(SyncError::InvalidData(format!(
+            "Unknown device: {}",
+            device_id
+        )));
+    }
+    log::info!(
+        "[sync] Device {} linked to user {}",
+        device_id,
+        user_id.unwrap_or("(none)")
+    );
+    Ok(())
+}
+
+/// Permission a user needs to make the delta's change
+pub fn required_permission(delta: &SyncDelta) -> &'static str {
+    match delta.operation {
+        SyncOperation::Delete if delta.entity_type == "project" => PERMISSION_ADMIN,
+        SyncOperation::Delete => PERMISSION_DELETE,
+        SyncOperation::Create | SyncOperation::Update => PERMISSION_WRITE,
+    }
+}
+
+/// The rejection to record if `delta` from `device_id` isn't permitted
+pub(crate) fn check_delta(
+    conn: &Connection,
+    device_id: &str,
+    delta: &SyncDelta,
+) -> Result<Option<RejectedDelta>, SyncError> {
+    let Some(space_id) = &delta.space_id else {
+        return Ok(None);
+    };
+    if !is_shared_space(conn, space_id)? {
+        return Ok(None);
+    }
+    let permission = required_permission(delta);
+    let user_id = get_device_user(conn, device_id)?;
+    if let Some(user_id) = &user_id {
+        if is_permitted(conn, space_id, user_id, permission)? {
+            return Ok(None);
+        }
+    }
+    Ok(Some(RejectedDelta {
+        id: Ulid::new().to_string(),
+        device_id: device_id.to_string(),
+        user_id,
+        space_id: space_id.clone(),
+        entity_type: delta.entity_type.clone(),
+        entity_id: delta.entity_id.clone(),
+        operation: format!("{:?}", delta.operation),
+        permission: permission.to_string(),
+        rejected_at: chrono::Utc::now().timestamp(),
+    }))
+}
+
+pub(crate) fn record_rejected_delta(
+    conn: &Connection,
+    rejected: &RejectedDelta,
+) -> Result<(), SyncError> {
+    conn.execute(
+        "INSERT INTO sync_rejected_delta (
+            id, device_id, user_id, space_id, entity_type, entity_id,
+            operation, permission, rejected_at
+        ) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)",
+        rusqlite::params![
+            rejected.id,
+            rejected.device_id,
+            rejected.user_id,
+            rejected.space_id,
+            rejected.entity_type,
+            rejected.entity_id,
+            rejected.operation,
+            rejected.permission,
+            rejected.rejected_at
+        ],
+    )?;
+    Ok(())
+}
+
+/// Most recent deltas rejected for the space, newest first
+pub fn get_rejected_deltas(
+    conn: &Connection,
+    space_id: &str,
+    limit: u32,
+) -> Result<Vec<RejectedDelta>, SyncError> {
+    let mut stmt = conn.prepare(
+        "SELECT id, device_id, user_id, space_id, entity_type, entity_id,
+                operation, permission, rejected_at
+         FROM sync_rejected_delta
+         WHERE space_id = ?1
+         ORDER BY rejected_at DESC, id DESC
+         LIMIT ?2",
+    )?;
+    let rejected = stmt
+        .query_map(rusqlite::params![space_id, limit], |row| {
+            Ok(RejectedDelta {
+                id: row.get(0)?,
+                device_id: row.get(1)?,
+                user_id: row.get(2)?,
+                space_id: row.get(3)?,
+                entity_type: row.get(4)?,
+                entity_id: row.get(5)?,
+                operation: row.get(6)?,
+                permission: row.get(7)?,
+                rejected_at: row.get(8)?,
+            })
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(rejected)
+}
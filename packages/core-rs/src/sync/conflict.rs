@@ -7,6 +7,17 @@ pub enum ConflictType {
     DeleteUpdate,
 }
 
+impl ConflictType {
+    /// Parse the name stored in `sync_conflict`, defaulting to `UpdateUpdate`
+    pub fn from_name(name: &str) -> Self {
+        match name {
+            "UpdateDelete" => ConflictType::UpdateDelete,
+            "DeleteUpdate" => ConflictType::DeleteUpdate,
+            _ => ConflictType::UpdateUpdate,
+        }
+    }
+}
+
 impl std::fmt::Display for ConflictType {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(f, "{:?}", self)
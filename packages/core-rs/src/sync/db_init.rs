@@ -14,11 +14,26 @@ pub fn init_sync_tables(conn: &Connection) -> Result<(), SyncError> {
             sync_port INTEGER NOT NULL,
             protocol_version TEXT NOT NULL,
             trusted INTEGER NOT NULL DEFAULT 0,
+            static_public_key BLOB,
+            discovery_source TEXT NOT NULL DEFAULT 'mdns',
+            sync_scope TEXT,
+            user_id TEXT,
             created_at INTEGER NOT NULL DEFAULT (strftime('%s', 'now'))
         )",
         [],
     )?;
 
+    // This device's static key for the encrypted sync transport
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS sync_identity (
+            id INTEGER PRIMARY KEY CHECK (id = 1),
+            private_key BLOB NOT NULL,
+            public_key BLOB NOT NULL,
+            created_at INTEGER NOT NULL
+        )",
+        [],
+    )?;
+
     // Sync history table
     conn.execute(
         "CREATE TABLE IF NOT EXISTS sync_history (
@@ -32,6 +47,10 @@ pub fn init_sync_tables(conn: &Connection) -> Result<(), SyncError> {
             conflicts_detected INTEGER NOT NULL DEFAULT 0,
             success INTEGER NOT NULL DEFAULT 1,
             error_message TEXT,
+            sync_count INTEGER NOT NULL DEFAULT 1,
+            success_count INTEGER,
+            note TEXT,
+            transport TEXT NOT NULL DEFAULT 'direct',
             FOREIGN KEY (device_id) REFERENCES sync_state(device_id)
         )",
         [],
@@ -52,6 +71,8 @@ pub fn init_sync_tables(conn: &Connection) -> Result<(), SyncError> {
             resolution TEXT,
             device_id TEXT NOT NULL,
             space_id TEXT NOT NULL,
+            source_device_id TEXT,
+            remote_modified_at INTEGER,
             FOREIGN KEY (device_id) REFERENCES sync_state(device_id)
         )",
         [],
@@ -82,5 +103,89 @@ pub fn init_sync_tables(conn: &Connection) -> Result<(), SyncError> {
         [],
     )?;
 
+    // Last version of each entity received from a peer, the common ancestor
+    // for three-way note merges
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS sync_shadow (
+            entity_type TEXT NOT NULL,
+            entity_id TEXT NOT NULL,
+            content BLOB NOT NULL,
+            synced_at INTEGER NOT NULL,
+            PRIMARY KEY (entity_type, entity_id)
+        )",
+        [],
+    )?;
+
+    // Deltas to shared spaces not applied for lack of permission
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS sync_rejected_delta (
+            id TEXT PRIMARY KEY,
+            device_id TEXT NOT NULL,
+            user_id TEXT,
+            space_id TEXT NOT NULL,
+            entity_type TEXT NOT NULL,
+            entity_id TEXT NOT NULL,
+            operation TEXT NOT NULL,
+            permission TEXT NOT NULL,
+            rejected_at INTEGER NOT NULL
+        )",
+        [],
+    )?;
+    conn.execute(
+        "CREATE INDEX IF NOT EXISTS idx_sync_rejected_delta_space
+            ON sync_rejected_delta(space_id, rejected_at)",
+        [],
+    )?;
+
+    // Progress of interrupted transfers from each peer
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS sync_transfer_checkpoint (
+            peer_device_id TEXT PRIMARY KEY,
+            transfer_id TEXT NOT NULL,
+            last_applied_batch INTEGER NOT NULL,
+            total_batches INTEGER NOT NULL,
+            vector_clock TEXT NOT NULL,
+            updated_at INTEGER NOT NULL
+        )",
+        [],
+    )?;
+
+    // Chunks of relayed sessions still waiting for the rest of their chunks
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS sync_relay_chunk (
+            from_device TEXT NOT NULL,
+            session_id TEXT NOT NULL,
+            chunk_index INTEGER NOT NULL,
+            chunk_count INTEGER NOT NULL,
+            data TEXT NOT NULL,
+            received_at INTEGER NOT NULL,
+            PRIMARY KEY (from_device, session_id, chunk_index)
+        )",
+        [],
+    )?;
+
+    // Bytes and deltas each sync session moved
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS sync_transfer_stats (
+            id TEXT PRIMARY KEY,
+            session_id TEXT NOT NULL,
+            device_id TEXT NOT NULL,
+            space_id TEXT NOT NULL,
+            entity_type TEXT NOT NULL,
+            direction TEXT NOT NULL CHECK (direction IN ('sent', 'received')),
+            delta_count INTEGER NOT NULL,
+            payload_bytes INTEGER NOT NULL,
+            wire_bytes INTEGER NOT NULL,
+            transport TEXT NOT NULL,
+            recorded_at INTEGER NOT NULL
+        )",
+        [],
+    )?;
+    conn.execute(
+        "CREATE INDEX IF NOT EXISTS idx_sync_transfer_stats_space_time
+            ON sync_transfer_stats(space_id, recorded_at)",
+        [],
+    )?;
+
     Ok(())
 }
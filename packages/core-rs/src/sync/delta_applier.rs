@@ -1,5 +1,12 @@
 use rusqlite::{Connection, OptionalExtension, Result};
 
+use crate::ai::rag::mark_note_dirty;
+use crate::crdt::{self, NOTE_CRDT_ENTITY_TYPE};
+use crate::note_dedup::{self, SyncedMerge, NOTE_MERGE_ENTITY_TYPE};
+use crate::note_lock;
+use crate::note_share::{
+    self, SyncedMove, SyncedReference, NOTE_MOVE_ENTITY_TYPE, NOTE_REFERENCE_ENTITY_TYPE,
+};
 use crate::sync::error::SyncError;
 use crate::sync::models::{SyncDelta, SyncOperation};
 
@@ -27,13 +34,17 @@ impl DeltaApplier {
         #[allow(unused_variables)] dek: &[u8],
     ) -> Result<(), SyncError> {
         match delta.entity_type.as_str() {
-            "note" => Self::apply_note_delta(conn, delta),
+            "note" | NOTE_CRDT_ENTITY_TYPE => Self::apply_note_delta(conn, delta),
             "task" => Self::apply_task_delta(conn, delta),
             "project" => Self::apply_project_delta(conn, delta),
             "health_metric" => Self::apply_health_metric_delta(conn, delta),
             "track" => Self::apply_track_delta(conn, delta),
             "playlist" => Self::apply_playlist_delta(conn, delta),
             "calendar_event" => Self::apply_calendar_event_delta(conn, delta),
+            "inbox_item" => Self::apply_inbox_item_delta(conn, delta),
+            NOTE_MERGE_ENTITY_TYPE => Self::apply_note_merge_delta(conn, delta),
+            NOTE_MOVE_ENTITY_TYPE => Self::apply_note_move_delta(conn, delta),
+            NOTE_REFERENCE_ENTITY_TYPE => Self::apply_note_reference_delta(conn, delta),
             _ => Err(SyncError::InvalidData(format!(
                 "Unknown entity type: {}",
                 delta.entity_type
@@ -44,19 +55,15 @@ impl DeltaApplier {
     fn apply_note_delta(conn: &Connection, delta: &SyncDelta) -> Result<(), SyncError> {
         if let Some(data) = &delta.data {
             match delta.operation {
+                SyncOperation::Create | SyncOperation::Update
+                    if delta.entity_type == NOTE_CRDT_ENTITY_TYPE =>
+                {
+                    Self::merge_note_crdt_delta(conn, delta, data)?;
+                }
                 SyncOperation::Create | SyncOperation::Update => {
                     let content = String::from_utf8(data.clone())
                         .map_err(|e| SyncError::InvalidData(e.to_string()))?;
-                    let space_id = if let Some(sid) = &delta.space_id {
-                        Some(sid.clone())
-                    } else {
-                        conn.query_row(
-                            "SELECT space_id FROM note WHERE id = ?1",
-                            [&delta.entity_id],
-                            |row| row.get(0),
-                        )
-                        .optional()?
-                    };
+                    let space_id = Self::note_space_id(conn, delta)?;
 
                     if let Some(sid) = space_id {
                         conn.execute(
@@ -64,16 +71,139 @@ impl DeltaApplier {
                              VALUES (?1, ?2, ?3, ?4, COALESCE((SELECT created_at FROM note WHERE id = ?1), ?4))",
                             rusqlite::params![&delta.entity_id, sid, content, delta.timestamp],
                         )?;
+                        if note_lock::is_locked_content(&content) {
+                            // Drop what this device still knows of the plaintext
+                            conn.execute(
+                                "UPDATE note SET is_locked = 1 WHERE id = ?1",
+                                [&delta.entity_id],
+                            )?;
+                            conn.execute(
+                                "DELETE FROM fts_note WHERE note_id = ?1",
+                                [&delta.entity_id],
+                            )?;
+                            crdt::delete_note_log(conn, &delta.entity_id)
+                                .map_err(|e| SyncError::DatabaseError(e.to_string()))?;
+                            mark_note_dirty(conn, &delta.entity_id)?;
+                        }
                     }
                 }
                 SyncOperation::Delete => {
                     conn.execute("DELETE FROM note WHERE id = ?1", [&delta.entity_id])?;
+                    crdt::delete_note_log(conn, &delta.entity_id)
+                        .map_err(|e| SyncError::DatabaseError(e.to_string()))?;
                 }
             }
         }
         Ok(())
     }
 
+    /// Merge a CRDT update into the note's document. Concurrent edits
+    /// interleave, so unlike whole-body deltas this never needs a conflict.
+    fn merge_note_crdt_delta(
+        conn: &Connection,
+        delta: &SyncDelta,
+        update: &[u8],
+    ) -> Result<(), SyncError> {
+        let Some(sid) = Self::note_space_id(conn, delta)? else {
+            return Ok(());
+        };
+        // A locked body is ciphertext with no edit history to merge into.
+        // An update older than the lock lost to it; a newer one comes from a
+        // device that unlocked the note, so it starts the history afresh.
+        let locked_at: Option<i64> = conn
+            .query_row(
+                "SELECT modified_at FROM note WHERE id = ?1 AND is_locked = 1",
+                [&delta.entity_id],
+                |row| row.get(0),
+            )
+            .optional()?;
+        if let Some(locked_at) = locked_at {
+            if delta.timestamp <= locked_at {
+                log::info!(
+                    "[sync] Skipping CRDT update older than the lock on note {}",
+                    delta.entity_id
+                );
+                return Ok(());
+            }
+            crdt::delete_note_log(conn, &delta.entity_id)
+                .map_err(|e| SyncError::DatabaseError(e.to_string()))?;
+            conn.execute(
+                "UPDATE note SET content_md = '', is_locked = 0 WHERE id = ?1",
+                [&delta.entity_id],
+            )?;
+        }
+        let content = crdt::merge_note_update(conn, &delta.entity_id, update)
+            .map_err(|e| SyncError::InvalidData(e.to_string()))?;
+
+        conn.execute(
+            "INSERT INTO note (id, space_id, content_md, modified_at, created_at)
+             VALUES (?1, ?2, ?3, ?4, ?4)
+             ON CONFLICT(id) DO UPDATE SET
+                content_md = excluded.content_md,
+                modified_at = MAX(note.modified_at, excluded.modified_at)",
+            rusqlite::params![&delta.entity_id, sid, content, delta.timestamp],
+        )?;
+        Ok(())
+    }
+
+    fn apply_note_merge_delta(conn: &Connection, delta: &SyncDelta) -> Result<(), SyncError> {
+        let (Some(data), Some(space_id)) = (&delta.data, &delta.space_id) else {
+            return Ok(());
+        };
+        let merge: SyncedMerge =
+            serde_json::from_slice(data).map_err(|e| SyncError::InvalidData(e.to_string()))?;
+        note_dedup::apply_synced_merge(conn, &delta.entity_id, space_id, &merge, delta.timestamp)
+            .map_err(|e| SyncError::DatabaseError(e.to_string()))
+    }
+
+    fn apply_note_move_delta(conn: &Connection, delta: &SyncDelta) -> Result<(), SyncError> {
+        let Some(data) = &delta.data else {
+            return Ok(());
+        };
+        let synced: SyncedMove =
+            serde_json::from_slice(data).map_err(|e| SyncError::InvalidData(e.to_string()))?;
+        note_share::apply_synced_move(conn, &delta.entity_id, &synced, delta.timestamp)
+            .map_err(|e| SyncError::DatabaseError(e.to_string()))
+    }
+
+    fn apply_note_reference_delta(conn: &Connection, delta: &SyncDelta) -> Result<(), SyncError> {
+        let (Some(data), Some(space_id)) = (&delta.data, &delta.space_id) else {
+            return Ok(());
+        };
+        let synced: SyncedReference =
+            serde_json::from_slice(data).map_err(|e| SyncError::InvalidData(e.to_string()))?;
+        note_share::apply_synced_reference(
+            conn,
+            &delta.entity_id,
+            space_id,
+            &synced,
+            delta.operation == SyncOperation::Delete,
+            delta.timestamp,
+        )
+        .map_err(|e| SyncError::DatabaseError(e.to_string()))
+    }
+
+    /// The space a note delta goes to. Once a note has been moved, the
+    /// latest move decides, so a peer that hasn't seen the move yet can't
+    /// send the note back to its old space.
+    fn note_space_id(conn: &Connection, delta: &SyncDelta) -> Result<Option<String>, SyncError> {
+        let moved = note_share::latest_move(conn, &delta.entity_id)
+            .map_err(|e| SyncError::DatabaseError(e.to_string()))?;
+        if let Some((space_id, _)) = moved {
+            return Ok(Some(space_id));
+        }
+        if let Some(sid) = &delta.space_id {
+            return Ok(Some(sid.clone()));
+        }
+        Ok(conn
+            .query_row(
+                "SELECT space_id FROM note WHERE id = ?1",
+                [&delta.entity_id],
+                |row| row.get(0),
+            )
+            .optional()?)
+    }
+
     fn apply_task_delta(conn: &Connection, delta: &SyncDelta) -> Result<(), SyncError> {
         if let Some(data) = &delta.data {
             let task_data: serde_json::Value =
@@ -223,9 +353,14 @@ impl DeltaApplier {
                         ],
                     )?;
                 }
-                SyncOperation::Delete => conn
-                    .execute("DELETE FROM track WHERE id = ?1", [&delta.entity_id])
-                    .map(|_| ())?,
+                SyncOperation::Delete => {
+                    // Listening history is local and goes with the track
+                    conn.execute(
+                        "DELETE FROM track_play WHERE track_id = ?1",
+                        [&delta.entity_id],
+                    )?;
+                    conn.execute("DELETE FROM track WHERE id = ?1", [&delta.entity_id])?;
+                }
             }
         }
         Ok(())
@@ -295,4 +430,32 @@ impl DeltaApplier {
         }
         Ok(())
     }
+
+    /// Inbox items are vault-wide, so unlike the other types they need no
+    /// space
+    fn apply_inbox_item_delta(conn: &Connection, delta: &SyncDelta) -> Result<(), SyncError> {
+        if let Some(data) = &delta.data {
+            let item_data: serde_json::Value =
+                serde_json::from_slice(data).map_err(|e| SyncError::InvalidData(e.to_string()))?;
+            match delta.operation {
+                SyncOperation::Create | SyncOperation::Update => {
+                    conn.execute(
+                        "INSERT OR REPLACE INTO inbox_item (id, text, source, captured_at, updated_at)
+                         VALUES (?1, ?2, ?3, ?4, ?5)",
+                        rusqlite::params![
+                            &delta.entity_id,
+                            item_data["text"].as_str().unwrap_or(""),
+                            item_data["source"].as_str().unwrap_or("sync"),
+                            item_data["captured_at"].as_i64().unwrap_or(delta.timestamp),
+                            delta.timestamp
+                        ],
+                    )?;
+                }
+                SyncOperation::Delete => conn
+                    .execute("DELETE FROM inbox_item WHERE id = ?1", [&delta.entity_id])
+                    .map(|_| ())?,
+            }
+        }
+        Ok(())
+    }
 }
@@ -3,8 +3,12 @@ use serde_json::json;
 use std::collections::HashMap;
 use ulid::Ulid;
 
+use crate::crdt;
+use crate::note_dedup;
+use crate::note_share;
 use crate::sync::error::SyncError;
 use crate::sync::models::{SyncDelta, SyncOperation};
+use crate::sync::scope::{SyncEntityType, SyncScope};
 
 pub struct DeltaGatherer;
 
@@ -13,16 +17,46 @@ impl DeltaGatherer {
         conn: &Connection,
         space_id: Ulid,
         since: i64,
+    ) -> Result<Vec<SyncDelta>, SyncError> {
+        Self::get_deltas_since_for_peer(conn, space_id, since, false, &SyncScope::All)
+    }
+
+    /// Like `get_deltas_since`, sending note bodies as CRDT updates when
+    /// `crdt_notes` is set. Entity types outside `scope` aren't queried.
+    pub fn get_deltas_since_for_peer(
+        conn: &Connection,
+        space_id: Ulid,
+        since: i64,
+        crdt_notes: bool,
+        scope: &SyncScope,
     ) -> Result<Vec<SyncDelta>, SyncError> {
         let mut deltas = Vec::new();
 
-        deltas.extend(Self::get_notes_deltas(conn, space_id, since)?);
-        deltas.extend(Self::get_tasks_deltas(conn, space_id, since)?);
-        deltas.extend(Self::get_projects_deltas(conn, space_id, since)?);
-        deltas.extend(Self::get_health_metrics_deltas(conn, space_id, since)?);
-        deltas.extend(Self::get_tracks_deltas(conn, space_id, since)?);
-        deltas.extend(Self::get_playlists_deltas(conn, space_id, since)?);
-        deltas.extend(Self::get_calendar_events_deltas(conn, space_id, since)?);
+        for entity_type in SyncEntityType::ALL {
+            if !scope.includes(entity_type) {
+                continue;
+            }
+            deltas.extend(match entity_type {
+                SyncEntityType::Note => {
+                    let mut notes = Self::get_notes_deltas(conn, space_id, since, crdt_notes)?;
+                    notes.extend(Self::get_note_merges_deltas(conn, space_id, since)?);
+                    notes.extend(Self::get_note_moves_deltas(conn, space_id, since)?);
+                    notes.extend(Self::get_note_references_deltas(conn, space_id, since)?);
+                    notes
+                }
+                SyncEntityType::Task => Self::get_tasks_deltas(conn, space_id, since)?,
+                SyncEntityType::Project => Self::get_projects_deltas(conn, space_id, since)?,
+                SyncEntityType::HealthMetric => {
+                    Self::get_health_metrics_deltas(conn, space_id, since)?
+                }
+                SyncEntityType::Track => Self::get_tracks_deltas(conn, space_id, since)?,
+                SyncEntityType::Playlist => Self::get_playlists_deltas(conn, space_id, since)?,
+                SyncEntityType::CalendarEvent => {
+                    Self::get_calendar_events_deltas(conn, space_id, since)?
+                }
+                SyncEntityType::InboxItem => Self::get_inbox_items_deltas(conn, since)?,
+            });
+        }
 
         deltas.sort_by_key(|d| d.timestamp);
 
@@ -33,25 +67,118 @@ impl DeltaGatherer {
         conn: &Connection,
         space_id: Ulid,
         since: i64,
+        crdt_notes: bool,
     ) -> Result<Vec<SyncDelta>, SyncError> {
-        let mut stmt = conn.prepare(
-            "SELECT id, content_md, modified_at FROM note WHERE space_id = ?1 AND modified_at > ?2",
+        let mut stmt = conn.prepare_cached(
+            "SELECT id, content_md, modified_at, is_locked FROM note WHERE space_id = ?1 AND modified_at > ?2",
         )?;
         let rows = stmt.query_map(rusqlite::params![space_id.to_string(), since], |row| {
             Ok((
                 row.get::<_, String>(0)?,
                 row.get::<_, String>(1)?,
                 row.get::<_, i64>(2)?,
+                row.get::<_, bool>(3)?,
             ))
         })?;
         let mut deltas = Vec::new();
         for row in rows {
-            let (id, content, ts) = row?;
+            let (id, content, ts, is_locked) = row?;
+            // The peer has no state vector in the delta exchange, so send the
+            // whole document state; merging it twice is a no-op. A locked
+            // body is ciphertext with no edit history, so it goes whole.
+            let (entity_type, data) = if crdt_notes && !is_locked {
+                let update = crdt::encode_note_state(conn, &id, &content)
+                    .map_err(|e| SyncError::DatabaseError(e.to_string()))?;
+                (crdt::NOTE_CRDT_ENTITY_TYPE, update)
+            } else {
+                ("note", content.into_bytes())
+            };
             deltas.push(SyncDelta {
-                entity_type: "note".into(),
+                entity_type: entity_type.into(),
                 entity_id: id,
                 operation: SyncOperation::Update,
-                data: Some(content.into_bytes()),
+                data: Some(data),
+                timestamp: ts,
+                vector_clock: HashMap::new(),
+                space_id: Some(space_id.to_string()),
+            });
+        }
+        Ok(deltas)
+    }
+
+    /// Merges of duplicate notes, so peers move the same relations and trash
+    /// the same notes
+    fn get_note_merges_deltas(
+        conn: &Connection,
+        space_id: Ulid,
+        since: i64,
+    ) -> Result<Vec<SyncDelta>, SyncError> {
+        let merges = note_dedup::merges_since(conn, &space_id.to_string(), since)
+            .map_err(|e| SyncError::DatabaseError(e.to_string()))?;
+        let mut deltas = Vec::new();
+        for (id, merge, ts) in merges {
+            let data =
+                serde_json::to_vec(&merge).map_err(|e| SyncError::InvalidData(e.to_string()))?;
+            deltas.push(SyncDelta {
+                entity_type: note_dedup::NOTE_MERGE_ENTITY_TYPE.into(),
+                entity_id: id,
+                operation: SyncOperation::Create,
+                data: Some(data),
+                timestamp: ts,
+                vector_clock: HashMap::new(),
+                space_id: Some(space_id.to_string()),
+            });
+        }
+        Ok(deltas)
+    }
+
+    /// Moves of notes into or out of the space, so peers of either space
+    /// re-home the note instead of keeping it where it was
+    fn get_note_moves_deltas(
+        conn: &Connection,
+        space_id: Ulid,
+        since: i64,
+    ) -> Result<Vec<SyncDelta>, SyncError> {
+        let moves = note_share::moves_since(conn, &space_id.to_string(), since)
+            .map_err(|e| SyncError::DatabaseError(e.to_string()))?;
+        let mut deltas = Vec::new();
+        for (id, synced, ts) in moves {
+            let data =
+                serde_json::to_vec(&synced).map_err(|e| SyncError::InvalidData(e.to_string()))?;
+            deltas.push(SyncDelta {
+                entity_type: note_share::NOTE_MOVE_ENTITY_TYPE.into(),
+                entity_id: id,
+                operation: SyncOperation::Create,
+                data: Some(data),
+                timestamp: ts,
+                vector_clock: HashMap::new(),
+                space_id: Some(synced.to_space_id),
+            });
+        }
+        Ok(deltas)
+    }
+
+    /// References shown in the space, removed ones as deletes
+    fn get_note_references_deltas(
+        conn: &Connection,
+        space_id: Ulid,
+        since: i64,
+    ) -> Result<Vec<SyncDelta>, SyncError> {
+        let references = note_share::references_since(conn, &space_id.to_string(), since)
+            .map_err(|e| SyncError::DatabaseError(e.to_string()))?;
+        let mut deltas = Vec::new();
+        for (id, synced, removed, ts) in references {
+            let data =
+                serde_json::to_vec(&synced).map_err(|e| SyncError::InvalidData(e.to_string()))?;
+            deltas.push(SyncDelta {
+                entity_type: note_share::NOTE_REFERENCE_ENTITY_TYPE.into(),
+                entity_id: id,
+                operation: if removed {
+                    SyncOperation::Delete
+                } else {
+                    SyncOperation::Create
+                },
+                data: Some(data),
                 timestamp: ts,
                 vector_clock: HashMap::new(),
                 space_id: Some(space_id.to_string()),
@@ -65,7 +192,7 @@ impl DeltaGatherer {
         space_id: Ulid,
         since: i64,
     ) -> Result<Vec<SyncDelta>, SyncError> {
-        let mut stmt = conn.prepare("SELECT id, title, status, updated_at FROM task WHERE space_id = ?1 AND updated_at > ?2")?;
+        let mut stmt = conn.prepare_cached("SELECT id, title, status, updated_at FROM task WHERE space_id = ?1 AND updated_at > ?2")?;
         let rows = stmt.query_map(rusqlite::params![space_id.to_string(), since], |row| {
             Ok((
                 row.get::<_, String>(0)?,
@@ -96,7 +223,7 @@ impl DeltaGatherer {
         space_id: Ulid,
         since: i64,
     ) -> Result<Vec<SyncDelta>, SyncError> {
-        let mut stmt = conn.prepare(
+        let mut stmt = conn.prepare_cached(
             "SELECT id, title, updated_at FROM project WHERE space_id = ?1 AND updated_at > ?2",
         )?;
         let rows = stmt.query_map(rusqlite::params![space_id.to_string(), since], |row| {
@@ -128,7 +255,7 @@ impl DeltaGatherer {
         space_id: Ulid,
         since: i64,
     ) -> Result<Vec<SyncDelta>, SyncError> {
-        let mut stmt = conn.prepare("SELECT id, metric_type, value, unit, notes, recorded_at, created_at, updated_at FROM health_metric WHERE space_id = ?1 AND updated_at > ?2")?;
+        let mut stmt = conn.prepare_cached("SELECT id, metric_type, value, unit, notes, recorded_at, created_at, updated_at FROM health_metric WHERE space_id = ?1 AND updated_at > ?2")?;
         let rows = stmt.query_map(rusqlite::params![space_id.to_string(), since], |row| {
             Ok((
                 row.get(0)?,
@@ -172,7 +299,7 @@ impl DeltaGatherer {
         space_id: Ulid,
         since: i64,
     ) -> Result<Vec<SyncDelta>, SyncError> {
-        let mut stmt = conn.prepare("SELECT id, title, artist, album, updated_at FROM track WHERE space_id = ?1 AND updated_at > ?2")?;
+        let mut stmt = conn.prepare_cached("SELECT id, title, artist, album, updated_at FROM track WHERE space_id = ?1 AND updated_at > ?2")?;
         let rows = stmt.query_map(rusqlite::params![space_id.to_string(), since], |row| {
             Ok((
                 row.get(0)?,
@@ -210,7 +337,7 @@ impl DeltaGatherer {
         space_id: Ulid,
         since: i64,
     ) -> Result<Vec<SyncDelta>, SyncError> {
-        let mut stmt = conn.prepare("SELECT id, name, description, updated_at FROM playlist WHERE space_id = ?1 AND updated_at > ?2")?;
+        let mut stmt = conn.prepare_cached("SELECT id, name, description, updated_at FROM playlist WHERE space_id = ?1 AND updated_at > ?2")?;
         let rows = stmt.query_map(rusqlite::params![space_id.to_string(), since], |row| {
             Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?))
         })?;
@@ -236,7 +363,7 @@ impl DeltaGatherer {
         space_id: Ulid,
         since: i64,
     ) -> Result<Vec<SyncDelta>, SyncError> {
-        let mut stmt = conn.prepare("SELECT id, title, description, start_time, end_time, updated_at FROM calendar_event WHERE space_id = ?1 AND updated_at > ?2")?;
+        let mut stmt = conn.prepare_cached("SELECT id, title, description, start_time, end_time, updated_at FROM calendar_event WHERE space_id = ?1 AND updated_at > ?2")?;
         let rows = stmt.query_map(rusqlite::params![space_id.to_string(), since], |row| {
             Ok((
                 row.get(0)?,
@@ -270,4 +397,37 @@ impl DeltaGatherer {
         }
         Ok(deltas)
     }
+
+    /// Inbox items belong to no space, so they go out with every space
+    fn get_inbox_items_deltas(conn: &Connection, since: i64) -> Result<Vec<SyncDelta>, SyncError> {
+        let mut stmt = conn.prepare_cached(
+            "SELECT id, text, source, captured_at, updated_at FROM inbox_item WHERE updated_at > ?1",
+        )?;
+        let rows = stmt.query_map([since], |row| {
+            Ok((
+                row.get(0)?,
+                row.get(1)?,
+                row.get(2)?,
+                row.get(3)?,
+                row.get(4)?,
+            ))
+        })?;
+        let mut deltas = Vec::new();
+        for row in rows {
+            let (id, text, source, captured_at, updated_at): (String, String, String, i64, i64) =
+                row?;
+            let data =
+                json!({ "text": text, "source": source, "captured_at": captured_at }).to_string();
+            deltas.push(SyncDelta {
+                entity_type: "inbox_item".into(),
+                entity_id: id,
+                operation: SyncOperation::Update,
+                data: Some(data.into_bytes()),
+                timestamp: updated_at,
+                vector_clock: HashMap::new(),
+                space_id: None,
+            });
+        }
+        Ok(deltas)
+    }
 }
@@ -0,0 +1,316 @@
+//! Human-readable descriptions of sync conflicts
+//!
+//! A conflict keeps both versions as the payloads exchanged during sync: the
+//! note body, which may be encrypted with the vault DEK, or the entity's JSON.
+//! [`describe_conflict`] turns them into a title and a short field diff the
+//! conflicts UI can show when asking which side to keep.
+
+use crate::crypto::{decrypt_bytes, decrypt_string};
+use crate::sync::conflict::ConflictType;
+use crate::sync::error::SyncError;
+use crate::sync::models::SyncConflict;
+use rusqlite::{Connection, OptionalExtension, Row};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+
+/// Longest value shown for a field, in characters
+const MAX_VALUE_CHARS: usize = 120;
+/// Characters kept before the first difference when a value is cut short
+const DIFF_CONTEXT_CHARS: usize = 30;
+
+/// A field whose value differs between the two versions
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct FieldChange {
+    pub field: String,
+    /// `None` when the field is missing from that version
+    pub local: Option<String>,
+    pub remote: Option<String>,
+}
+
+/// What the user needs to choose a side of a conflict
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ConflictSummary {
+    /// The conflict itself, to pass back when resolving it
+    pub conflict: SyncConflict,
+    pub conflict_id: Option<String>,
+    pub title: Option<String>,
+    /// Changed fields, empty when either version couldn't be read
+    pub changes: Vec<FieldChange>,
+    pub detected_at: Option<i64>,
+    pub local_modified_at: Option<i64>,
+    pub remote_modified_at: Option<i64>,
+    /// Device names from `sync_state`, or the ids of devices not in it
+    pub local_device: Option<String>,
+    pub remote_device: Option<String>,
+    /// Why the summary is partial, e.g. a version that couldn't be decrypted
+    pub problems: Vec<String>,
+}
+
+/// What `sync_conflict` records beyond the conflict itself
+#[derive(Default)]
+struct ConflictRecord {
+    id: Option<String>,
+    detected_at: Option<i64>,
+    device_id: Option<String>,
+    source_device_id: Option<String>,
+    remote_modified_at: Option<i64>,
+}
+
+impl ConflictRecord {
+    fn from_row(row: &Row, offset: usize) -> rusqlite::Result<Self> {
+        Ok(ConflictRecord {
+            id: row.get(offset)?,
+            detected_at: row.get(offset + 1)?,
+            device_id: row.get(offset + 2)?,
+            source_device_id: row.get(offset + 3)?,
+            remote_modified_at: row.get(offset + 4)?,
+        })
+    }
+}
+
+type Fields = BTreeMap<String, String>;
+
+/// Describe `conflict`, using the newest unresolved `sync_conflict` row for
+/// the entity for when and where it happened
+pub fn describe_conflict(
+    conn: &Connection,
+    conflict: &SyncConflict,
+    dek: &[u8],
+) -> Result<ConflictSummary, SyncError> {
+    let record = conn
+        .query_row(
+            "SELECT id, detected_at, device_id, source_device_id, remote_modified_at
+             FROM sync_conflict
+             WHERE entity_type = ?1 AND entity_id = ?2 AND resolved = 0
+             ORDER BY detected_at DESC
+             LIMIT 1",
+            [&conflict.entity_type, &conflict.entity_id],
+            |row| ConflictRecord::from_row(row, 0),
+        )
+        .optional()?
+        .unwrap_or_default();
+    summarize(conn, conflict.clone(), record, dek)
+}
+
+/// Every unresolved conflict, oldest first, described for the conflicts UI
+pub fn get_unresolved_conflicts_described(
+    conn: &Connection,
+    dek: &[u8],
+) -> Result<Vec<ConflictSummary>, SyncError> {
+    let mut stmt = conn.prepare(
+        "SELECT entity_type, entity_id, local_version, remote_version, conflict_type, space_id,
+                id, detected_at, device_id, source_device_id, remote_modified_at
+         FROM sync_conflict
+         WHERE resolved = 0
+         ORDER BY detected_at, id",
+    )?;
+    let conflicts = stmt
+        .query_map([], |row| {
+            let conflict = SyncConflict {
+                entity_type: row.get(0)?,
+                entity_id: row.get(1)?,
+                local_version: row.get::<_, Option<Vec<u8>>>(2)?.unwrap_or_default(),
+                remote_version: row.get::<_, Option<Vec<u8>>>(3)?.unwrap_or_default(),
+                conflict_type: ConflictType::from_name(&row.get::<_, String>(4)?),
+                space_id: row.get(5)?,
+            };
+            Ok((conflict, ConflictRecord::from_row(row, 6)?))
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
+
+    conflicts
+        .into_iter()
+        .map(|(conflict, record)| summarize(conn, conflict, record, dek))
+        .collect()
+}
+
+fn summarize(
+    conn: &Connection,
+    conflict: SyncConflict,
+    record: ConflictRecord,
+    dek: &[u8],
+) -> Result<ConflictSummary, SyncError> {
+    let mut problems = Vec::new();
+    let mut read = |side: &str, payload: &[u8]| {
+        read_version(&conflict.entity_type, payload, dek)
+            .map_err(|e| problems.push(format!("The {} version can't be read: {}", side, e)))
+            .ok()
+    };
+    let local = read("local", &conflict.local_version);
+    let remote = read("remote", &conflict.remote_version);
+
+    let changes = match (&local, &remote) {
+        (Some(local), Some(remote)) => diff_fields(local, remote),
+        _ => Vec::new(),
+    };
+    let (stored_title, local_modified_at) =
+        stored_entity(conn, &conflict.entity_type, &conflict.entity_id)?;
+    let title = payload_title(&conflict.entity_type, local.as_ref(), remote.as_ref())
+        .or(stored_title.filter(|t| !t.trim().is_empty()))
+        .or_else(|| heading_title(&conflict.entity_type, local.as_ref(), remote.as_ref()));
+
+    Ok(ConflictSummary {
+        conflict_id: record.id,
+        title,
+        changes,
+        detected_at: record.detected_at,
+        local_modified_at,
+        remote_modified_at: record.remote_modified_at,
+        local_device: record.device_id.map(|id| device_name(conn, &id)),
+        remote_device: record.source_device_id.map(|id| device_name(conn, &id)),
+        problems,
+        conflict,
+    })
+}
+
+/// The fields of one version. An empty payload, as for a deleted entity,
+/// has none.
+fn read_version(entity_type: &str, payload: &[u8], dek: &[u8]) -> Result<Fields, String> {
+    if payload.is_empty() {
+        return Ok(Fields::new());
+    }
+    if entity_type == "note" {
+        let content = note_text(payload, dek)?;
+        return Ok(Fields::from([("content".to_string(), content)]));
+    }
+
+    let value: serde_json::Value = match serde_json::from_slice(payload) {
+        Ok(value) => value,
+        Err(e) => {
+            let plain = decrypt_bytes(payload, dek).map_err(|_| format!("invalid JSON ({})", e))?;
+            serde_json::from_slice(&plain).map_err(|e| format!("invalid JSON ({})", e))?
+        }
+    };
+    let serde_json::Value::Object(object) = value else {
+        return Err("not a JSON object".to_string());
+    };
+    Ok(object
+        .into_iter()
+        .filter(|(_, value)| !value.is_null())
+        .map(|(field, value)| match value {
+            serde_json::Value::String(s) => (field, s),
+            other => (field, other.to_string()),
+        })
+        .collect())
+}
+
+/// A note body sent as plain text, encrypted bytes or base64 ciphertext
+fn note_text(payload: &[u8], dek: &[u8]) -> Result<String, String> {
+    if let Ok(plain) = decrypt_bytes(payload, dek) {
+        return String::from_utf8(plain).map_err(|_| "the decrypted note isn't text".to_string());
+    }
+    let text = std::str::from_utf8(payload)
+        .map_err(|_| "neither text nor decryptable with the vault key".to_string())?;
+    Ok(decrypt_string(text, dek).unwrap_or_else(|_| text.to_string()))
+}
+
+fn diff_fields(local: &Fields, remote: &Fields) -> Vec<FieldChange> {
+    let fields: BTreeSet<&String> = local.keys().chain(remote.keys()).collect();
+    fields
+        .into_iter()
+        .filter_map(|field| {
+            let local = local.get(field).map(String::as_str);
+            let remote = remote.get(field).map(String::as_str);
+            if local == remote {
+                return None;
+            }
+            // Show both values from just before where they start to differ
+            let start = match (local, remote) {
+                (Some(l), Some(r)) => common_prefix_chars(l, r).saturating_sub(DIFF_CONTEXT_CHARS),
+                _ => 0,
+            };
+            Some(FieldChange {
+                field: field.clone(),
+                local: local.map(|v| excerpt(v, start)),
+                remote: remote.map(|v| excerpt(v, start)),
+            })
+        })
+        .collect()
+}
+
+fn common_prefix_chars(a: &str, b: &str) -> usize {
+    a.chars().zip(b.chars()).take_while(|(x, y)| x == y).count()
+}
+
+/// Up to `MAX_VALUE_CHARS` of `value` from char `start`, with ellipses
+/// where it was cut
+fn excerpt(value: &str, start: usize) -> String {
+    let mut out = String::new();
+    if start > 0 {
+        out.push('…');
+    }
+    let mut chars = value.chars().skip(start);
+    out.extend(chars.by_ref().take(MAX_VALUE_CHARS));
+    if chars.next().is_some() {
+        out.push('…');
+    }
+    out
+}
+
+fn payload_title(
+    entity_type: &str,
+    local: Option<&Fields>,
+    remote: Option<&Fields>,
+) -> Option<String> {
+    let field = match entity_type {
+        "project" => "name",
+        _ => "title",
+    };
+    [local, remote]
+        .into_iter()
+        .flatten()
+        .filter_map(|fields| fields.get(field))
+        .find(|title| !title.trim().is_empty())
+        .cloned()
+}
+
+/// The first line of a note body, without heading markers
+fn heading_title(
+    entity_type: &str,
+    local: Option<&Fields>,
+    remote: Option<&Fields>,
+) -> Option<String> {
+    if entity_type != "note" {
+        return None;
+    }
+    [local, remote]
+        .into_iter()
+        .flatten()
+        .filter_map(|fields| fields.get("content"))
+        .find_map(|content| {
+            content
+                .lines()
+                .map(|line| line.trim_start_matches('#').trim())
+                .find(|line| !line.is_empty())
+        })
+        .map(|line| excerpt(line, 0))
+}
+
+/// The entity's title and modification time as stored on this device
+fn stored_entity(
+    conn: &Connection,
+    entity_type: &str,
+    entity_id: &str,
+) -> Result<(Option<String>, Option<i64>), SyncError> {
+    let sql = match entity_type {
+        "note" => "SELECT title, modified_at FROM note WHERE id = ?1",
+        "task" => "SELECT title, updated_at FROM task WHERE id = ?1",
+        "project" => "SELECT title, updated_at FROM project WHERE id = ?1",
+        _ => return Ok((None, None)),
+    };
+    let row = conn
+        .query_row(sql, [entity_id], |row| Ok((row.get(0)?, row.get(1)?)))
+        .optional()?;
+    Ok(row.unwrap_or((None, None)))
+}
+
+/// The name a device registered with, or its id if it never did. The
+/// registry is created with the sync tables, so it may not exist yet.
+fn device_name(conn: &Connection, device_id: &str) -> String {
+    conn.query_row(
+        "SELECT device_name FROM sync_state WHERE device_id = ?1",
+        [device_id],
+        |row| row.get(0),
+    )
+    .unwrap_or_else(|_| device_id.to_string())
+}
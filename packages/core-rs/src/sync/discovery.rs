@@ -1,35 +1,322 @@
+use crate::sync::models::DeviceType;
 use mdns_sd::{ServiceDaemon, ServiceEvent, ServiceInfo};
+use rusqlite::{params, Connection};
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
-use std::time::Duration;
+use std::net::{TcpStream, ToSocketAddrs};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
 use thiserror::Error;
 
 const SERVICE_TYPE: &str = "_noteece-sync._tcp.local.";
 
+/// How long a probe result is reused before the peer is probed again
+const HEALTH_TTL: Duration = Duration::from_secs(60);
+/// Connect timeout for a reachability probe
+const PROBE_TIMEOUT: Duration = Duration::from_millis(750);
+
+/// How a device was found
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum DiscoverySource {
+    /// Announced itself over mDNS
+    #[default]
+    Mdns,
+    /// Added by the user, for networks that block multicast
+    Manual,
+}
+
+impl DiscoverySource {
+    fn as_str(&self) -> &'static str {
+        match self {
+            DiscoverySource::Mdns => "mdns",
+            DiscoverySource::Manual => "manual",
+        }
+    }
+}
+
+/// Result of the last reachability probe of a device
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum Reachability {
+    #[default]
+    Unknown,
+    Reachable,
+    Unreachable,
+}
+
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct DiscoveredDevice {
     pub id: String,
     pub name: String,
     pub address: String,
     pub port: u16,
+    #[serde(default)]
+    pub source: DiscoverySource,
+    #[serde(default)]
+    pub reachability: Reachability,
+    /// Unix time of the probe behind `reachability`
+    #[serde(default)]
+    pub last_probe_at: Option<i64>,
 }
 
 #[derive(Error, Debug)]
 pub enum DiscoveryError {
     #[error("mDNS Daemon error: {0}")]
     DaemonError(String),
+
+    #[error("Invalid peer address: {0}")]
+    InvalidPeer(String),
+
+    #[error("Database error: {0}")]
+    Database(#[from] rusqlite::Error),
+}
+
+/// Outcome of a reachability probe
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PeerHealth {
+    pub reachable: bool,
+    /// Unix time of the probe
+    pub last_probe_at: i64,
+    probed: Instant,
+}
+
+impl PeerHealth {
+    pub fn reachability(&self) -> Reachability {
+        if self.reachable {
+            Reachability::Reachable
+        } else {
+            Reachability::Unreachable
+        }
+    }
+}
+
+/// Remembers recent probe results so repeated discovery does not wait on
+/// the connect timeout of every dead peer each time
+pub struct PeerHealthCache {
+    ttl: Duration,
+    probe_timeout: Duration,
+    entries: Mutex<HashMap<String, PeerHealth>>,
+}
+
+impl PeerHealthCache {
+    pub fn new(ttl: Duration, probe_timeout: Duration) -> Self {
+        PeerHealthCache {
+            ttl,
+            probe_timeout,
+            entries: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// The cached result for a peer, probing it if none is fresh
+    pub fn check(&self, address: &str, port: u16) -> PeerHealth {
+        if let Some(health) = self.get(address, port) {
+            return health;
+        }
+        let health = PeerHealth {
+            reachable: probe_peer(address, port, self.probe_timeout),
+            last_probe_at: chrono::Utc::now().timestamp(),
+            probed: Instant::now(),
+        };
+        self.lock_entries().insert(peer_key(address, port), health);
+        health
+    }
+
+    /// A fresh cached result, without probing
+    pub fn get(&self, address: &str, port: u16) -> Option<PeerHealth> {
+        self.lock_entries()
+            .get(&peer_key(address, port))
+            .filter(|health| health.probed.elapsed() < self.ttl)
+            .copied()
+    }
+
+    /// Forget a peer's result so the next check probes it again
+    pub fn invalidate(&self, address: &str, port: u16) {
+        self.lock_entries().remove(&peer_key(address, port));
+    }
+
+    fn lock_entries(&self) -> std::sync::MutexGuard<'_, HashMap<String, PeerHealth>> {
+        self.entries.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+impl Default for PeerHealthCache {
+    fn default() -> Self {
+        Self::new(HEALTH_TTL, PROBE_TIMEOUT)
+    }
+}
+
+fn peer_key(address: &str, port: u16) -> String {
+    format!("{}:{}", address, port)
+}
+
+/// Whether a TCP connection to the peer's sync port succeeds within `timeout`
+pub fn probe_peer(address: &str, port: u16, timeout: Duration) -> bool {
+    let addrs = match (address, port).to_socket_addrs() {
+        Ok(addrs) => addrs,
+        Err(e) => {
+            log::debug!("[discovery] Cannot resolve {}: {}", address, e);
+            return false;
+        }
+    };
+    addrs
+        .into_iter()
+        .any(|addr| TcpStream::connect_timeout(&addr, timeout).is_ok())
+}
+
+/// Id under which a manually added peer is stored until it is paired
+fn manual_peer_id(address: &str, port: u16) -> String {
+    format!("manual:{}", peer_key(address, port))
+}
+
+/// Remember a peer that cannot be found over mDNS. Adding the same address
+/// and port again updates its name.
+pub fn add_manual_peer(
+    conn: &Connection,
+    address: &str,
+    port: u16,
+    display_name: &str,
+) -> Result<DiscoveredDevice, DiscoveryError> {
+    let address = address.trim();
+    if address.is_empty() || address.contains(char::is_whitespace) || address.contains('/') {
+        return Err(DiscoveryError::InvalidPeer(format!(
+            "'{}' is not a host name or IP address",
+            address
+        )));
+    }
+    if port == 0 {
+        return Err(DiscoveryError::InvalidPeer(
+            "Port must not be 0".to_string(),
+        ));
+    }
+    let name = match display_name.trim() {
+        "" => address,
+        name => name,
+    };
+
+    let id = manual_peer_id(address, port);
+    let device_type =
+        serde_json::to_string(&DeviceType::Desktop).expect("DeviceType serializes to JSON");
+    conn.execute(
+        "INSERT INTO sync_state (
+            device_id, device_name, device_type, last_seen,
+            sync_address, sync_port, protocol_version, discovery_source
+        ) VALUES (?1, ?2, ?3, ?4, ?5, ?6, 'unknown', ?7)
+        ON CONFLICT(device_id) DO UPDATE SET device_name = excluded.device_name",
+        params![
+            id,
+            name,
+            device_type,
+            chrono::Utc::now().timestamp(),
+            address,
+            port,
+            DiscoverySource::Manual.as_str()
+        ],
+    )?;
+    log::info!(
+        "[discovery] Added manual peer {} at {}:{}",
+        name,
+        address,
+        port
+    );
+
+    Ok(DiscoveredDevice {
+        id,
+        name: name.to_string(),
+        address: address.to_string(),
+        port,
+        source: DiscoverySource::Manual,
+        reachability: Reachability::Unknown,
+        last_probe_at: None,
+    })
+}
+
+/// Forget a manually added peer. Returns whether one was removed.
+pub fn remove_manual_peer(conn: &Connection, device_id: &str) -> Result<bool, DiscoveryError> {
+    let removed = conn.execute(
+        "DELETE FROM sync_state WHERE device_id = ?1 AND discovery_source = ?2",
+        params![device_id, DiscoverySource::Manual.as_str()],
+    )?;
+    Ok(removed > 0)
+}
+
+pub fn get_manual_peers(conn: &Connection) -> Result<Vec<DiscoveredDevice>, DiscoveryError> {
+    let mut stmt = conn.prepare(
+        "SELECT device_id, device_name, sync_address, sync_port
+         FROM sync_state WHERE discovery_source = ?1
+         ORDER BY device_name",
+    )?;
+    let peers = stmt
+        .query_map([DiscoverySource::Manual.as_str()], |row| {
+            Ok(DiscoveredDevice {
+                id: row.get(0)?,
+                name: row.get(1)?,
+                address: row.get(2)?,
+                port: row.get(3)?,
+                source: DiscoverySource::Manual,
+                reachability: Reachability::Unknown,
+                last_probe_at: None,
+            })
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(peers)
 }
 
 /// A service for discovering other Noteece devices on the local network.
 pub struct DiscoveryService {
     mdns: ServiceDaemon,
+    health: PeerHealthCache,
 }
 
 impl DiscoveryService {
     /// Creates a new DiscoveryService.
     pub fn new() -> Result<Self, DiscoveryError> {
         let mdns = ServiceDaemon::new().map_err(|e| DiscoveryError::DaemonError(e.to_string()))?;
-        Ok(Self { mdns })
+        Ok(Self {
+            mdns,
+            health: PeerHealthCache::default(),
+        })
+    }
+
+    pub fn health(&self) -> &PeerHealthCache {
+        &self.health
+    }
+
+    /// Devices found over mDNS during `duration` merged with the manually
+    /// added peers, each with its reachability. A manual entry at the same
+    /// address and port as an mDNS result is dropped in favour of the latter.
+    /// mDNS failures are logged and leave only the manual peers.
+    pub fn discover_all(
+        &self,
+        conn: &Connection,
+        duration: Duration,
+    ) -> Result<Vec<DiscoveredDevice>, DiscoveryError> {
+        let manual = get_manual_peers(conn)?;
+        let mut devices = self.discover(duration).unwrap_or_else(|e| {
+            log::warn!("[discovery] mDNS discovery failed: {}", e);
+            Vec::new()
+        });
+        for peer in manual {
+            let announced = devices
+                .iter()
+                .any(|d| d.address == peer.address && d.port == peer.port);
+            if !announced {
+                devices.push(peer);
+            }
+        }
+
+        // Probe in parallel so a few dead peers cost one timeout, not several
+        std::thread::scope(|scope| {
+            for device in devices.iter_mut() {
+                let health = &self.health;
+                scope.spawn(move || {
+                    let result = health.check(&device.address, device.port);
+                    device.reachability = result.reachability();
+                    device.last_probe_at = Some(result.last_probe_at);
+                });
+            }
+        });
+        Ok(devices)
     }
 
     /// Registers and broadcasts this device on the local network.
@@ -86,6 +373,9 @@ impl DiscoveryService {
                             name,
                             address: address.to_string(),
                             port: info.get_port(),
+                            source: DiscoverySource::Mdns,
+                            reachability: Reachability::Unknown,
+                            last_probe_at: None,
                         };
                         log::info!("[discovery] Found device: {:?}", device);
                         discovered_devices.push(device);
@@ -1,9 +1,18 @@
+use crate::audit::{audit_change, AuditOperation, AUDIT_SOURCE_SYNC};
+use crate::crdt::NOTE_CRDT_ENTITY_TYPE;
+use crate::events::{self, CoreEvent};
+use crate::space::in_active_space;
+use crate::sync::access;
 use crate::sync::conflict::{ConflictResolution, ConflictType};
 use crate::sync::delta_applier::DeltaApplier;
 use crate::sync::delta_gatherer::DeltaGatherer;
 use crate::sync::error::SyncError;
 use crate::sync::history::SyncHistory;
+use crate::sync::merge;
 use crate::sync::models::*;
+use crate::sync::scope::{get_device_sync_scope, SyncScope};
+use crate::sync::shadow;
+use crate::sync::transfer_stats::total_wire_bytes;
 use rusqlite::{Connection, OptionalExtension};
 use std::collections::HashMap;
 use ulid::Ulid;
@@ -39,7 +48,7 @@ impl SyncAgent {
             last_seen: now,
             sync_address: "localhost".to_string(),
             sync_port: self.sync_port,
-            protocol_version: "1.0.0".to_string(),
+            protocol_version: SYNC_PROTOCOL_VERSION.to_string(),
         }
     }
 
@@ -56,10 +65,17 @@ impl SyncAgent {
         );
 
         conn.execute(
-            "INSERT OR REPLACE INTO sync_state (
+            "INSERT INTO sync_state (
                 device_id, device_name, device_type, last_seen,
                 sync_address, sync_port, protocol_version, trusted
-            ) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+            ) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)
+            ON CONFLICT(device_id) DO UPDATE SET
+                device_name = excluded.device_name,
+                device_type = excluded.device_type,
+                last_seen = excluded.last_seen,
+                sync_address = excluded.sync_address,
+                sync_port = excluded.sync_port,
+                protocol_version = excluded.protocol_version",
             rusqlite::params![
                 &device_info.device_id,
                 &device_info.device_name,
@@ -69,7 +85,7 @@ impl SyncAgent {
                 &device_info.sync_address,
                 device_info.sync_port,
                 &device_info.protocol_version,
-                false, // Not trusted by default
+                false, // Not trusted until a verified pairing; kept on re-register
             ],
         )?;
 
@@ -143,15 +159,157 @@ impl SyncAgent {
         DeltaGatherer::get_deltas_since(conn, space_id, since_timestamp)
     }
 
-    /// Apply incoming deltas
+    /// Get deltas since last sync for `peer`, within the scope kept for it.
+    /// Note bodies go out as CRDT updates when both sides support them, and
+    /// as whole bodies otherwise.
+    pub fn get_deltas_for_peer(
+        &self,
+        conn: &Connection,
+        space_id: Ulid,
+        since_timestamp: i64,
+        peer: &DeviceInfo,
+    ) -> Result<Vec<SyncDelta>, SyncError> {
+        let scope = get_device_sync_scope(conn, &peer.device_id)?;
+        self.get_deltas_for_peer_in_scope(conn, space_id, since_timestamp, peer, &scope)
+    }
+
+    /// Like [`get_deltas_for_peer`](Self::get_deltas_for_peer), limited to
+    /// `scope` as well, e.g. the scope negotiated with the peer
+    pub fn get_deltas_for_peer_in_scope(
+        &self,
+        conn: &Connection,
+        space_id: Ulid,
+        since_timestamp: i64,
+        peer: &DeviceInfo,
+        scope: &SyncScope,
+    ) -> Result<Vec<SyncDelta>, SyncError> {
+        let scope = get_device_sync_scope(conn, &peer.device_id)?.intersect(scope);
+        let crdt_notes = supports_crdt_notes(SYNC_PROTOCOL_VERSION)
+            && supports_crdt_notes(&peer.protocol_version);
+        DeltaGatherer::get_deltas_since_for_peer(
+            conn,
+            space_id,
+            since_timestamp,
+            crdt_notes,
+            &scope,
+        )
+    }
+
+    /// Request for the changes `peer` has in a space, advertising the scope
+    /// kept for it
+    pub fn create_sync_request(
+        &self,
+        conn: &Connection,
+        space_id: Ulid,
+        since_timestamp: i64,
+        peer_device_id: &str,
+    ) -> Result<SyncRequest, SyncError> {
+        Ok(SyncRequest {
+            device_id: self.device_id.clone(),
+            space_id,
+            since_timestamp,
+            vector_clock: self.get_vector_clock(conn, space_id)?,
+            scope: Some(get_device_sync_scope(conn, peer_device_id)?),
+        })
+    }
+
+    /// Answer a sync request from `peer` with the deltas in the scope both
+    /// sides keep for each other. The response carries that scope, which the
+    /// requester holds its own deltas to.
+    pub fn handle_sync_request(
+        &self,
+        conn: &Connection,
+        request: &SyncRequest,
+        peer: &DeviceInfo,
+    ) -> Result<SyncResponse, SyncError> {
+        if request.device_id != peer.device_id {
+            return Err(SyncError::InvalidData(format!(
+                "Sync request from {} sent by {}",
+                request.device_id, peer.device_id
+            )));
+        }
+        let scope = get_device_sync_scope(conn, &peer.device_id)?.negotiate(request.scope.as_ref());
+        log::info!(
+            "[SyncAgent] Sync request from {} in scope {:?}",
+            peer.device_id,
+            scope
+        );
+        let deltas = self.get_deltas_for_peer_in_scope(
+            conn,
+            request.space_id,
+            request.since_timestamp,
+            peer,
+            &scope,
+        )?;
+        Ok(SyncResponse {
+            deltas,
+            conflicts: Vec::new(),
+            new_vector_clock: self.get_vector_clock(conn, request.space_id)?,
+            scope: Some(scope),
+        })
+    }
+
+    /// Scope agreed with `peer_device_id` given the scope it advertised
+    pub fn negotiate_scope(
+        &self,
+        conn: &Connection,
+        peer_device_id: &str,
+        peer_scope: Option<&SyncScope>,
+    ) -> Result<SyncScope, SyncError> {
+        Ok(get_device_sync_scope(conn, peer_device_id)?.negotiate(peer_scope))
+    }
+
+    /// Apply incoming deltas. Their device isn't known, so deltas to shared
+    /// spaces are rejected.
     pub fn apply_deltas(
         &self,
         conn: &mut Connection,
         deltas: Vec<SyncDelta>,
         dek: &[u8],
+    ) -> Result<Vec<SyncConflict>, SyncError> {
+        self.apply_deltas_within(conn, deltas, dek, AUDIT_SOURCE_SYNC, &SyncScope::All)
+    }
+
+    /// Apply deltas received from `source_device_id`, which is recorded as
+    /// the source of the changes in the audit log. Deltas outside the scope
+    /// kept for the device are skipped, and deltas to a shared space its user
+    /// lacks the permission for are recorded as rejected.
+    pub fn apply_deltas_from(
+        &self,
+        conn: &mut Connection,
+        deltas: Vec<SyncDelta>,
+        dek: &[u8],
+        source_device_id: &str,
+    ) -> Result<Vec<SyncConflict>, SyncError> {
+        let scope = get_device_sync_scope(conn, source_device_id)?;
+        self.apply_deltas_within(conn, deltas, dek, source_device_id, &scope)
+    }
+
+    /// Like [`apply_deltas_from`](Self::apply_deltas_from), limited to
+    /// `scope` as well, e.g. the scope negotiated with the device
+    pub fn apply_deltas_in_scope(
+        &self,
+        conn: &mut Connection,
+        deltas: Vec<SyncDelta>,
+        dek: &[u8],
+        source_device_id: &str,
+        scope: &SyncScope,
+    ) -> Result<Vec<SyncConflict>, SyncError> {
+        let scope = get_device_sync_scope(conn, source_device_id)?.intersect(scope);
+        self.apply_deltas_within(conn, deltas, dek, source_device_id, &scope)
+    }
+
+    fn apply_deltas_within(
+        &self,
+        conn: &mut Connection,
+        deltas: Vec<SyncDelta>,
+        dek: &[u8],
+        source_device_id: &str,
+        scope: &SyncScope,
     ) -> Result<Vec<SyncConflict>, SyncError> {
         log::info!("[SyncAgent] Applying {} deltas", deltas.len());
         let mut conflicts = Vec::new();
+        let mut conflict_events = Vec::new();
         let tx = conn.transaction()?;
 
         for delta in deltas {
@@ -161,6 +319,32 @@ impl SyncAgent {
                 delta.entity_type
             );
 
+            // A peer that ignores the negotiated scope doesn't get to write
+            // outside it
+            if !scope.allows(&delta) {
+                log::warn!(
+                    "[SyncAgent] Rejecting out-of-scope delta {} ({}) from {}",
+                    delta.entity_id,
+                    delta.entity_type,
+                    source_device_id
+                );
+                continue;
+            }
+
+            // Changes to a shared space need the permission the device's
+            // user would need to make them locally
+            if let Some(rejected) = access::check_delta(&tx, source_device_id, &delta)? {
+                log::warn!(
+                    "[SyncAgent] Rejecting delta {} ({}) from {}: missing {}",
+                    delta.entity_id,
+                    delta.entity_type,
+                    source_device_id,
+                    rejected.permission
+                );
+                access::record_rejected_delta(&tx, &rejected)?;
+                continue;
+            }
+
             if let Some(conflict) = self.detect_conflict(&tx, &delta)? {
                 log::warn!(
                     "[SyncAgent] Conflict detected for {} ({})",
@@ -172,8 +356,9 @@ impl SyncAgent {
                     tx.execute(
                         "INSERT INTO sync_conflict (
                             id, entity_type, entity_id, local_version, remote_version,
-                            conflict_type, detected_at, resolved, device_id, space_id
-                        ) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, 0, ?8, ?9)",
+                            conflict_type, detected_at, resolved, device_id, space_id,
+                            source_device_id, remote_modified_at
+                        ) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, 0, ?8, ?9, ?10, ?11)",
                         rusqlite::params![
                             conflict_id,
                             conflict.entity_type,
@@ -183,9 +368,19 @@ impl SyncAgent {
                             format!("{:?}", conflict.conflict_type),
                             chrono::Utc::now().timestamp(),
                             self.device_id,
-                            space_id
+                            space_id,
+                            (source_device_id != AUDIT_SOURCE_SYNC).then_some(source_device_id),
+                            delta.timestamp
                         ],
                     )?;
+                    conflict_events.push(CoreEvent::SyncConflictDetected {
+                        space_id: space_id.clone(),
+                        conflict_id,
+                        device_id: source_device_id.to_string(),
+                        entity_type: conflict.entity_type.clone(),
+                        entity_id: conflict.entity_id.clone(),
+                        conflict_type: conflict.conflict_type.clone(),
+                    });
                     conflicts.push(conflict);
                 } else {
                     log::error!(
@@ -199,6 +394,22 @@ impl SyncAgent {
             match DeltaApplier::apply_single_delta(&tx, &delta, dek) {
                 Ok(_) => {
                     self.log_entity_sync(&tx, &delta)?;
+                    record_note_shadow(&tx, &delta)?;
+                    if let Some(space_id) = &delta.space_id {
+                        let entity_type = if delta.entity_type == NOTE_CRDT_ENTITY_TYPE {
+                            "note"
+                        } else {
+                            &delta.entity_type
+                        };
+                        audit_change(
+                            &tx,
+                            space_id,
+                            entity_type,
+                            &delta.entity_id,
+                            audit_operation(&delta.operation),
+                            source_device_id,
+                        );
+                    }
                     log::trace!(
                         "[SyncAgent] Delta applied successfully: {}",
                         delta.entity_id
@@ -224,6 +435,7 @@ impl SyncAgent {
             "[SyncAgent] Delta application complete. Conflicts: {}",
             conflicts.len()
         );
+        conflict_events.into_iter().for_each(events::emit);
         Ok(conflicts)
     }
 
@@ -241,12 +453,7 @@ impl SyncAgent {
         let conflicts = stmt
             .query_map([], |row| {
                 let conflict_type_str: String = row.get(4)?;
-                let conflict_type: ConflictType = match conflict_type_str.as_str() {
-                    "UpdateUpdate" => ConflictType::UpdateUpdate,
-                    "DeleteUpdate" => ConflictType::DeleteUpdate,
-                    "UpdateDelete" => ConflictType::UpdateDelete,
-                    _ => ConflictType::UpdateUpdate,
-                };
+                let conflict_type = ConflictType::from_name(&conflict_type_str);
 
                 Ok(SyncConflict {
                     entity_type: row.get(0)?,
@@ -280,7 +487,7 @@ impl SyncAgent {
         limit: i64,
     ) -> Result<Vec<SyncHistoryEntry>, SyncError> {
         let mut stmt = conn.prepare(
-            "SELECT id, device_id, sync_time, direction, entities_pushed, entities_pulled, conflicts_detected, success, error_message
+            "SELECT id, device_id, sync_time, direction, entities_pushed, entities_pulled, conflicts_detected, success, error_message, note, transport
              FROM sync_history
              WHERE space_id = ?1
              ORDER BY sync_time DESC
@@ -300,6 +507,8 @@ impl SyncAgent {
                     conflicts_detected: row.get::<_, i64>(6).unwrap_or(0) as i32,
                     success: row.get(7)?,
                     error_message: row.get(8)?,
+                    note: row.get(9)?,
+                    transport: SyncTransport::parse(&row.get::<_, String>(10)?),
                 })
             })?
             .collect::<Result<Vec<_>, _>>()?;
@@ -377,9 +586,10 @@ impl SyncAgent {
             )
             .unwrap_or(0);
 
+        // Pruned months are single rows counting many syncs
         let total_attempts: f64 = conn
             .query_row(
-                "SELECT COUNT(*) FROM sync_history WHERE space_id = ?1",
+                "SELECT COALESCE(SUM(sync_count), 0) FROM sync_history WHERE space_id = ?1",
                 [space_id],
                 |row| row.get(0),
             )
@@ -387,7 +597,8 @@ impl SyncAgent {
 
         let successful_attempts: f64 = conn
             .query_row(
-                "SELECT COUNT(*) FROM sync_history WHERE space_id = ?1 AND success = 1",
+                "SELECT COALESCE(SUM(COALESCE(success_count, success)), 0)
+                 FROM sync_history WHERE space_id = ?1",
                 [space_id],
                 |row| row.get(0),
             )
@@ -399,11 +610,15 @@ impl SyncAgent {
             1.0
         };
 
+        let (total_bytes_sent, total_bytes_received) = total_wire_bytes(conn, space_id)?;
+
         Ok(SyncStats {
             last_sync_at,
             total_synced: total_synced as i32,
             conflicts_total: conflicts_total as i32,
             success_rate,
+            total_bytes_sent,
+            total_bytes_received,
         })
     }
 
@@ -420,6 +635,19 @@ impl SyncAgent {
             conflict.entity_id,
             resolution
         );
+        // Resolving twice would apply a stale remote version over later edits
+        let (recorded, pending): (i64, i64) = conn.query_row(
+            "SELECT COUNT(*), COALESCE(SUM(resolved = 0), 0) FROM sync_conflict
+             WHERE entity_id = ?1",
+            [&conflict.entity_id],
+            |row| Ok((row.get(0)?, row.get(1)?)),
+        )?;
+        if recorded > 0 && pending == 0 {
+            return Err(SyncError::ConflictError(format!(
+                "Conflict for {} is already resolved",
+                conflict.entity_id
+            )));
+        }
         match resolution {
             ConflictResolution::UseLocal => {
                 self.mark_conflict_resolved(conn, &conflict.entity_id)?;
@@ -435,6 +663,7 @@ impl SyncAgent {
                     space_id: conflict.space_id.clone(),
                 };
                 DeltaApplier::apply_single_delta(conn, &delta, dek)?;
+                record_note_shadow(conn, &delta)?;
                 self.mark_conflict_resolved(conn, &conflict.entity_id)?;
             }
             ConflictResolution::Merge => {
@@ -504,21 +733,33 @@ impl SyncAgent {
                 let local_str = String::from_utf8(local_bytes.clone()).unwrap_or_default();
                 let remote_str = String::from_utf8(remote_bytes.clone()).unwrap_or_default();
 
-                let merged_content = format!(
-                    "{}\n\n--- MERGED REMOTE CONTENT ---\n\n{}",
-                    local_str, remote_str
-                );
+                // The shadow is what both sides last agreed on
+                let base = shadow::get_shadow(conn, "note", &conflict.entity_id)?
+                    .and_then(|b| String::from_utf8(b).ok());
+                let merged = match base {
+                    Some(base) => merge::merge_three_way(&base, &local_str, &remote_str),
+                    None => merge::merge_two_way(&local_str, &remote_str),
+                };
+                if merged.has_conflicts {
+                    log::warn!(
+                        "[SyncAgent] Note {} merged with conflict markers",
+                        conflict.entity_id
+                    );
+                }
 
                 let delta = SyncDelta {
                     entity_type: "note".into(),
                     entity_id: conflict.entity_id.clone(),
                     operation: SyncOperation::Update,
-                    data: Some(merged_content.into_bytes()),
+                    data: Some(merged.content.into_bytes()),
                     timestamp: chrono::Utc::now().timestamp(),
                     vector_clock: HashMap::new(),
                     space_id: conflict.space_id.clone(),
                 };
                 DeltaApplier::apply_single_delta(conn, &delta, dek)?;
+                shadow::set_merge_conflict(conn, &conflict.entity_id, merged.has_conflicts)?;
+                // The remote version is now part of the local history
+                shadow::record_shadow(conn, "note", &conflict.entity_id, remote_bytes)?;
             }
             _ => {
                 log::warn!(
@@ -582,8 +823,12 @@ impl SyncAgent {
         // (Index note_mod exists on modified_at, but we need space_id filtering too).
         // A better index would be CREATE INDEX idx_note_space_mod ON note(space_id, modified_at);
 
+        // Archived spaces are not offered to peers
         let mut hashes = HashMap::new();
-        let mut stmt = conn.prepare("SELECT id, modified_at FROM note WHERE space_id = ?1")?;
+        let mut stmt = conn.prepare(&format!(
+            "SELECT id, modified_at FROM note WHERE space_id = ?1 AND {}",
+            in_active_space("space_id")
+        ))?;
         let note_rows = stmt.query_map([space_id.to_string()], |row| {
             Ok((row.get::<_, String>(0)?, row.get::<_, i64>(1)?))
         })?;
@@ -644,14 +889,24 @@ impl SyncAgent {
                     (ts, task_row)
                 }
                 "project" => {
-                    let ts = conn
+                    let row = conn
                         .query_row(
-                            "SELECT updated_at FROM project WHERE id = ?1",
+                            "SELECT updated_at, title FROM project WHERE id = ?1",
                             [&delta.entity_id],
-                            |row| row.get(0),
+                            |row| Ok((row.get::<_, i64>(0)?, row.get::<_, String>(1)?)),
                         )
                         .optional()?;
-                    (ts, None)
+                    match row {
+                        Some((ts, title)) => (
+                            Some(ts),
+                            Some(
+                                serde_json::json!({ "name": title })
+                                    .to_string()
+                                    .into_bytes(),
+                            ),
+                        ),
+                        None => (None, None),
+                    }
                 }
                 _ => (None, None),
             };
@@ -705,3 +960,23 @@ impl SyncAgent {
         Ok(())
     }
 }
+
+/// Keep the version of a note taken from a peer as the base for later merges
+fn record_note_shadow(conn: &Connection, delta: &SyncDelta) -> Result<(), SyncError> {
+    if delta.entity_type != "note" {
+        return Ok(());
+    }
+    match (&delta.operation, &delta.data) {
+        (SyncOperation::Delete, _) => shadow::delete_shadow(conn, "note", &delta.entity_id),
+        (_, Some(data)) => shadow::record_shadow(conn, "note", &delta.entity_id, data),
+        (_, None) => Ok(()),
+    }
+}
+
+fn audit_operation(operation: &SyncOperation) -> AuditOperation {
+    match operation {
+        SyncOperation::Create => AuditOperation::Create,
+        SyncOperation::Update => AuditOperation::Update,
+        SyncOperation::Delete => AuditOperation::Delete,
+    }
+}
@@ -1,5 +1,6 @@
 use crate::sync::error::SyncError;
-use crate::sync::models::{SyncHistoryEntry, SyncStats};
+use crate::sync::models::{SyncHistoryEntry, SyncStats, SyncTransport};
+use crate::sync::transfer_stats::total_wire_bytes;
 use rusqlite::Connection;
 use rusqlite::OptionalExtension;
 use ulid::Ulid;
@@ -15,6 +16,7 @@ pub struct SyncRecordParams<'a> {
     pub conflicts: u32,
     pub success: bool,
     pub error_message: Option<&'a str>,
+    pub transport: SyncTransport,
 }
 
 impl SyncHistory {
@@ -29,8 +31,8 @@ impl SyncHistory {
         conn.execute(
             "INSERT INTO sync_history (id, device_id, space_id, sync_time, direction,
                                        entities_pushed, entities_pulled, conflicts_detected,
-                                       success, error_message)
-             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10)",
+                                       success, error_message, transport)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11)",
             rusqlite::params![
                 id,
                 params.device_id,
@@ -42,6 +44,7 @@ impl SyncHistory {
                 params.conflicts as i32,
                 if params.success { 1 } else { 0 },
                 params.error_message,
+                params.transport.as_str(),
             ],
         )?;
 
@@ -56,7 +59,7 @@ impl SyncHistory {
     ) -> Result<Vec<SyncHistoryEntry>, SyncError> {
         let mut stmt = conn.prepare(
             "SELECT id, device_id, space_id, sync_time, direction, entities_pushed,
-                    entities_pulled, conflicts_detected, success, error_message
+                    entities_pulled, conflicts_detected, success, error_message, note, transport
              FROM sync_history
              WHERE space_id = ?1
              ORDER BY sync_time DESC
@@ -76,6 +79,8 @@ impl SyncHistory {
                     conflicts_detected: row.get(7)?,
                     success: row.get::<_, i32>(8)? == 1,
                     error_message: row.get(9)?,
+                    note: row.get(10)?,
+                    transport: SyncTransport::parse(&row.get::<_, String>(11)?),
                 })
             })?
             .collect::<Result<Vec<_>, _>>()?;
@@ -83,19 +88,22 @@ impl SyncHistory {
         Ok(entries)
     }
 
-    /// Get sync statistics for a space
+    /// Get sync statistics for a space. Monthly aggregates left by
+    /// [`prune_sync_logs`](crate::sync::retention::prune_sync_logs) count as
+    /// the syncs they stand for.
     pub fn get_stats(conn: &Connection, space_id: &str) -> Result<SyncStats, SyncError> {
         let mut stmt = conn.prepare(
             "SELECT
-                COUNT(*) as total_syncs,
+                COALESCE(SUM(sync_count), 0) as total_syncs,
                 MAX(sync_time) as last_sync,
-                SUM(success) as success_count,
+                SUM(COALESCE(success_count, success)) as success_count,
                 SUM(conflicts_detected) as conflicts_total,
                 SUM(entities_pushed + entities_pulled) as total_entities
              FROM sync_history
              WHERE space_id = ?1",
         )?;
 
+        let (total_bytes_sent, total_bytes_received) = total_wire_bytes(conn, space_id)?;
         let stats = stmt.query_row([space_id], |row| {
             let total_syncs: i32 = row.get(0)?;
             let last_sync_at: Option<i64> = row.get(1)?;
@@ -114,6 +122,8 @@ impl SyncHistory {
                 last_sync_at,
                 success_rate,
                 conflicts_total,
+                total_bytes_sent,
+                total_bytes_received,
             })
         })?;
 
@@ -0,0 +1,175 @@
+//! Line-based merging of conflicting note bodies.
+//!
+//! With the last version both devices agreed on (the sync shadow) a note is
+//! merged three ways, diff3 style: a hunk changed on one side only takes that
+//! side, and only hunks both sides changed differently get conflict markers.
+//! Without a shadow the merge is two-way: lines present on only one side are
+//! kept, and lines that were replaced differently on each side get markers.
+
+pub const MARKER_LOCAL: &str = "<<<<<<< local";
+pub const MARKER_SEPARATOR: &str = "=======";
+pub const MARKER_REMOTE: &str = ">>>>>>> remote";
+
+/// Beyond this many line pairs in the differing middle of two texts the
+/// whole middle is treated as one changed hunk
+const MAX_DIFF_CELLS: usize = 4_000_000;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MergeResult {
+    pub content: String,
+    /// Set when conflict markers were written for overlapping edits
+    pub has_conflicts: bool,
+}
+
+/// Merge `local` and `remote`, both edited from `base`
+pub fn merge_three_way(base: &str, local: &str, remote: &str) -> MergeResult {
+    let base: Vec<&str> = base.split('\n').collect();
+    let local: Vec<&str> = local.split('\n').collect();
+    let remote: Vec<&str> = remote.split('\n').collect();
+
+    // For each base line, the local and remote line it was kept as
+    let to_local = match_lines(&base, &local);
+    let to_remote = match_lines(&base, &remote);
+
+    let mut out = MergeOutput::default();
+    let (mut i, mut j, mut k) = (0, 0, 0);
+    loop {
+        // Lines kept unchanged on both sides
+        while i < base.len() && to_local[i] == Some(j) && to_remote[i] == Some(k) {
+            out.lines.push(base[i]);
+            i += 1;
+            j += 1;
+            k += 1;
+        }
+
+        // The next base line both sides kept closes the changed hunk
+        let next_stable =
+            (i..base.len()).find(|&q| to_local[q].is_some() && to_remote[q].is_some());
+        let (base_end, local_end, remote_end) = match next_stable {
+            Some(q) => (q, to_local[q].unwrap_or(j), to_remote[q].unwrap_or(k)),
+            None => (base.len(), local.len(), remote.len()),
+        };
+        let (b, l, r) = (
+            &base[i..base_end],
+            &local[j..local_end],
+            &remote[k..remote_end],
+        );
+        if l == b {
+            out.lines.extend_from_slice(r);
+        } else if r == b || l == r {
+            out.lines.extend_from_slice(l);
+        } else {
+            out.conflict(l, r);
+        }
+
+        if next_stable.is_none() {
+            break;
+        }
+        (i, j, k) = (base_end, local_end, remote_end);
+    }
+    out.finish()
+}
+
+/// Merge `local` and `remote` without knowing what they started from
+pub fn merge_two_way(local: &str, remote: &str) -> MergeResult {
+    let local: Vec<&str> = local.split('\n').collect();
+    let remote: Vec<&str> = remote.split('\n').collect();
+    let to_remote = match_lines(&local, &remote);
+
+    let mut out = MergeOutput::default();
+    let (mut j, mut k) = (0, 0);
+    for (i, matched) in to_remote.iter().enumerate().chain([(local.len(), &None)]) {
+        let Some(k_end) = matched.or((i == local.len()).then_some(remote.len())) else {
+            continue;
+        };
+        let (l, r) = (&local[j..i], &remote[k..k_end]);
+        if l.is_empty() || r.is_empty() {
+            // Only one side has lines here; keep them
+            out.lines.extend_from_slice(l);
+            out.lines.extend_from_slice(r);
+        } else {
+            out.conflict(l, r);
+        }
+        if i < local.len() {
+            out.lines.push(local[i]);
+        }
+        (j, k) = (i + 1, k_end + 1);
+    }
+    out.finish()
+}
+
+#[derive(Default)]
+struct MergeOutput<'a> {
+    lines: Vec<&'a str>,
+    has_conflicts: bool,
+}
+
+impl<'a> MergeOutput<'a> {
+    fn conflict(&mut self, local: &[&'a str], remote: &[&'a str]) {
+        self.lines.push(MARKER_LOCAL);
+        self.lines.extend_from_slice(local);
+        self.lines.push(MARKER_SEPARATOR);
+        self.lines.extend_from_slice(remote);
+        self.lines.push(MARKER_REMOTE);
+        self.has_conflicts = true;
+    }
+
+    fn finish(self) -> MergeResult {
+        MergeResult {
+            content: self.lines.join("\n"),
+            has_conflicts: self.has_conflicts,
+        }
+    }
+}
+
+/// Longest common subsequence of lines: for each line of `a`, the index of
+/// the line of `b` it matches
+fn match_lines(a: &[&str], b: &[&str]) -> Vec<Option<usize>> {
+    let mut matched = vec![None; a.len()];
+
+    let prefix = a.iter().zip(b).take_while(|(x, y)| x == y).count();
+    let suffix = a[prefix..]
+        .iter()
+        .rev()
+        .zip(b[prefix..].iter().rev())
+        .take_while(|(x, y)| x == y)
+        .count();
+    for (i, m) in matched.iter_mut().enumerate().take(prefix) {
+        *m = Some(i);
+    }
+    for n in 1..=suffix {
+        matched[a.len() - n] = Some(b.len() - n);
+    }
+
+    let a_mid = &a[prefix..a.len() - suffix];
+    let b_mid = &b[prefix..b.len() - suffix];
+    if a_mid.is_empty() || b_mid.is_empty() || a_mid.len() * b_mid.len() > MAX_DIFF_CELLS {
+        return matched;
+    }
+
+    // lengths[x][y]: LCS length of a_mid[x..] and b_mid[y..]
+    let width = b_mid.len() + 1;
+    let mut lengths = vec![0u32; (a_mid.len() + 1) * width];
+    for x in (0..a_mid.len()).rev() {
+        for y in (0..b_mid.len()).rev() {
+            lengths[x * width + y] = if a_mid[x] == b_mid[y] {
+                lengths[(x + 1) * width + y + 1] + 1
+            } else {
+                lengths[(x + 1) * width + y].max(lengths[x * width + y + 1])
+            };
+        }
+    }
+    let (mut x, mut y) = (0, 0);
+    while x < a_mid.len() && y < b_mid.len() {
+        if a_mid[x] == b_mid[y] {
+            matched[prefix + x] = Some(prefix + y);
+            x += 1;
+            y += 1;
+        } else if lengths[(x + 1) * width + y] >= lengths[x * width + y + 1] {
+            x += 1;
+        } else {
+            y += 1;
+        }
+    }
+    matched
+}
@@ -6,6 +6,10 @@ pub mod protocol;
 
 pub use protocol::batch::SyncBatchProcessor;
 pub use protocol::handler::SyncProtocol;
+pub use protocol::transfer::{
+    apply_batch, clear_checkpoint, load_checkpoint, BatchOutcome, OutgoingTransfer,
+    CHECKPOINT_MAX_AGE_SECS,
+};
 pub use protocol::types::*;
 
 #[cfg(test)]
@@ -1,3 +1,4 @@
+use super::transfer::{batch_hash, OutgoingTransfer};
 use super::types::*;
 
 /// Batch processor for efficient syncing
@@ -54,6 +55,34 @@ impl SyncBatchProcessor {
 
         batches
     }
+
+    /// Split deltas into numbered, hashed batches under a new transfer id,
+    /// so an interrupted transfer can resume where the receiver stopped
+    pub fn create_transfer(&self, deltas: Vec<SyncDelta>) -> OutgoingTransfer {
+        let transfer_id = uuid::Uuid::new_v4().to_string();
+        let batches = self.create_batches(deltas);
+        let total_batches = batches.len() as u32;
+        log::info!(
+            "[mobile_sync] Transfer {} has {} batches",
+            transfer_id,
+            total_batches
+        );
+        let batches = batches
+            .into_iter()
+            .enumerate()
+            .map(|(i, deltas)| SyncBatch {
+                transfer_id: transfer_id.clone(),
+                sequence: i as u32 + 1,
+                total_batches,
+                hash: batch_hash(&deltas),
+                deltas,
+            })
+            .collect();
+        OutgoingTransfer {
+            transfer_id,
+            batches,
+        }
+    }
 }
 
 #[cfg(test)]
@@ -1,7 +1,12 @@
 pub mod batch;
 pub mod handler;
+pub mod transfer;
 pub mod types;
 
 pub use batch::SyncBatchProcessor;
 pub use handler::SyncProtocol;
+pub use transfer::{
+    apply_batch, clear_checkpoint, load_checkpoint, BatchOutcome, OutgoingTransfer,
+    CHECKPOINT_MAX_AGE_SECS,
+};
 pub use types::*;
@@ -0,0 +1,228 @@
+//! Resumable transfers
+//!
+//! The sender splits a transfer into numbered, hashed batches. The receiver
+//! applies each batch in a transaction that also saves its checkpoint, so
+//! after a dropped connection it sends the checkpoint in its next
+//! [`SyncRequest`] and the sender continues with the batch after it.
+
+use super::types::*;
+use crate::sync::error::SyncError;
+use rusqlite::{Connection, OptionalExtension};
+use sha2::{Digest, Sha256};
+
+/// Checkpoints older than this are dropped and the transfer starts over
+pub const CHECKPOINT_MAX_AGE_SECS: i64 = 24 * 60 * 60;
+
+/// SHA-256 of the serialized deltas, hex encoded
+pub fn batch_hash(deltas: &[SyncDelta]) -> String {
+    let bytes = serde_json::to_vec(deltas).unwrap_or_default();
+    hex::encode(Sha256::digest(bytes))
+}
+
+impl SyncBatch {
+    /// Whether the deltas still match the hash they were sent with
+    pub fn verify(&self) -> bool {
+        batch_hash(&self.deltas) == self.hash
+    }
+}
+
+/// A transfer on the sending side, kept across reconnects
+#[derive(Debug, Clone)]
+pub struct OutgoingTransfer {
+    pub transfer_id: String,
+    pub batches: Vec<SyncBatch>,
+}
+
+impl OutgoingTransfer {
+    /// The batches still to send to a receiver that reported `checkpoint`.
+    /// A checkpoint for another transfer means the receiver starts over.
+    pub fn remaining(&self, checkpoint: Option<&TransferCheckpoint>) -> &[SyncBatch] {
+        match checkpoint {
+            Some(c)
+                if c.transfer_id == self.transfer_id
+                    && c.total_batches as usize == self.batches.len() =>
+            {
+                let applied = (c.last_applied_batch as usize).min(self.batches.len());
+                log::info!(
+                    "[mobile_sync] Resuming transfer {} after batch {}",
+                    self.transfer_id,
+                    applied
+                );
+                &self.batches[applied..]
+            }
+            Some(c) => {
+                log::info!(
+                    "[mobile_sync] Unknown transfer {} in checkpoint, sending {} from the start",
+                    c.transfer_id,
+                    self.transfer_id
+                );
+                &self.batches
+            }
+            None => &self.batches,
+        }
+    }
+}
+
+/// What happened to a batch handed to [`apply_batch`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BatchOutcome {
+    /// Applied and checkpointed
+    Applied { deltas: usize },
+    /// Applied before the connection dropped, so skipped
+    AlreadyApplied,
+}
+
+/// The receiver's checkpoint for the transfer from `peer_device_id`, if it
+/// is recent enough to resume. Stale checkpoints are deleted.
+pub fn load_checkpoint(
+    conn: &Connection,
+    peer_device_id: &str,
+) -> Result<Option<TransferCheckpoint>, SyncProtocolError> {
+    let Some(checkpoint) = read_checkpoint(conn, peer_device_id)? else {
+        return Ok(None);
+    };
+    if chrono::Utc::now().timestamp() - checkpoint.updated_at > CHECKPOINT_MAX_AGE_SECS {
+        log::info!(
+            "[mobile_sync] Dropping stale checkpoint for transfer {}",
+            checkpoint.transfer_id
+        );
+        clear_checkpoint(conn, peer_device_id)?;
+        return Ok(None);
+    }
+    Ok(Some(checkpoint))
+}
+
+/// Forget the transfer from `peer_device_id`, e.g. once it completed
+pub fn clear_checkpoint(conn: &Connection, peer_device_id: &str) -> Result<(), SyncProtocolError> {
+    conn.execute(
+        "DELETE FROM sync_transfer_checkpoint WHERE peer_device_id = ?1",
+        [peer_device_id],
+    )?;
+    Ok(())
+}
+
+/// Apply a batch from `peer_device_id` with `apply`, saving the checkpoint
+/// in the same transaction. A batch that fails its hash is refused so the
+/// sender can retry it; one that doesn't follow the checkpoint is an error.
+pub fn apply_batch<F>(
+    conn: &mut Connection,
+    peer_device_id: &str,
+    batch: &SyncBatch,
+    mut apply: F,
+) -> Result<BatchOutcome, SyncProtocolError>
+where
+    F: FnMut(&Connection, &SyncDelta) -> Result<(), SyncError>,
+{
+    if !batch.verify() {
+        log::warn!(
+            "[mobile_sync] Batch {} of transfer {} is corrupt",
+            batch.sequence,
+            batch.transfer_id
+        );
+        return Err(SyncProtocolError::CorruptBatch(batch.sequence));
+    }
+
+    let tx = conn.transaction()?;
+    // A checkpoint for another transfer is superseded by this one
+    let current =
+        read_checkpoint(&tx, peer_device_id)?.filter(|c| c.transfer_id == batch.transfer_id);
+    let last_applied = current.as_ref().map_or(0, |c| c.last_applied_batch);
+    if batch.sequence <= last_applied {
+        return Ok(BatchOutcome::AlreadyApplied);
+    }
+    if batch.sequence != last_applied + 1 {
+        return Err(SyncProtocolError::InvalidState(format!(
+            "Expected batch {} of transfer {}, got {}",
+            last_applied + 1,
+            batch.transfer_id,
+            batch.sequence
+        )));
+    }
+
+    let mut vector_clock = current.map(|c| c.vector_clock).unwrap_or_default();
+    for delta in &batch.deltas {
+        apply(&tx, delta).map_err(|e| SyncProtocolError::Apply(e.to_string()))?;
+        for (device, counter) in &delta.vector_clock {
+            let merged = vector_clock.entry(device.clone()).or_insert(*counter);
+            *merged = (*merged).max(*counter);
+        }
+    }
+    save_checkpoint(
+        &tx,
+        peer_device_id,
+        &TransferCheckpoint {
+            transfer_id: batch.transfer_id.clone(),
+            last_applied_batch: batch.sequence,
+            total_batches: batch.total_batches,
+            vector_clock,
+            updated_at: chrono::Utc::now().timestamp(),
+        },
+    )?;
+    tx.commit()?;
+
+    log::debug!(
+        "[mobile_sync] Applied batch {}/{} of transfer {}",
+        batch.sequence,
+        batch.total_batches,
+        batch.transfer_id
+    );
+    Ok(BatchOutcome::Applied {
+        deltas: batch.deltas.len(),
+    })
+}
+
+fn read_checkpoint(
+    conn: &Connection,
+    peer_device_id: &str,
+) -> Result<Option<TransferCheckpoint>, SyncProtocolError> {
+    let row = conn
+        .query_row(
+            "SELECT transfer_id, last_applied_batch, total_batches, vector_clock, updated_at
+             FROM sync_transfer_checkpoint
+             WHERE peer_device_id = ?1",
+            [peer_device_id],
+            |row| {
+                Ok((
+                    row.get::<_, String>(0)?,
+                    row.get::<_, u32>(1)?,
+                    row.get::<_, u32>(2)?,
+                    row.get::<_, String>(3)?,
+                    row.get::<_, i64>(4)?,
+                ))
+            },
+        )
+        .optional()?;
+    let Some((transfer_id, last_applied_batch, total_batches, vector_clock, updated_at)) = row
+    else {
+        return Ok(None);
+    };
+    Ok(Some(TransferCheckpoint {
+        transfer_id,
+        last_applied_batch,
+        total_batches,
+        vector_clock: serde_json::from_str(&vector_clock)?,
+        updated_at,
+    }))
+}
+
+fn save_checkpoint(
+    conn: &Connection,
+    peer_device_id: &str,
+    checkpoint: &TransferCheckpoint,
+) -> Result<(), SyncProtocolError> {
+    conn.execute(
+        "INSERT OR REPLACE INTO sync_transfer_checkpoint (
+            peer_device_id, transfer_id, last_applied_batch, total_batches,
+            vector_clock, updated_at
+        ) VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+        rusqlite::params![
+            peer_device_id,
+            checkpoint.transfer_id,
+            checkpoint.last_applied_batch,
+            checkpoint.total_batches,
+            serde_json::to_string(&checkpoint.vector_clock)?,
+            checkpoint.updated_at,
+        ],
+    )?;
+    Ok(())
+}
@@ -1,3 +1,4 @@
+use crate::sync::scope::SyncScope;
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
@@ -41,6 +42,15 @@ pub enum SyncProtocolError {
 
     #[error("Serialization error: {0}")]
     Serialization(#[from] serde_json::Error),
+
+    #[error("Database error: {0}")]
+    Database(#[from] rusqlite::Error),
+
+    #[error("Batch {0} failed its integrity check")]
+    CorruptBatch(u32),
+
+    #[error("Failed to apply delta: {0}")]
+    Apply(String),
 }
 
 /// Protocol version for compatibility checking
@@ -123,6 +133,16 @@ pub struct SyncRequest {
 
     /// Last successful sync timestamp
     pub last_sync_timestamp: Option<DateTime<Utc>>,
+
+    /// Entity types the source keeps in sync with the target. Peers that
+    /// predate scopes leave it out, which means everything.
+    #[serde(default)]
+    pub scope: Option<SyncScope>,
+
+    /// How far the source got with an interrupted transfer from the target,
+    /// so the target can skip the batches already applied
+    #[serde(default)]
+    pub checkpoint: Option<TransferCheckpoint>,
 }
 
 /// Categories of data to synchronize
@@ -177,6 +197,44 @@ pub struct SyncDelta {
     pub vector_clock: HashMap<String, i64>,
 }
 
+/// One batch of a resumable transfer. Batches are numbered from 1.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SyncBatch {
+    /// Identifies the transfer across reconnects
+    pub transfer_id: String,
+
+    /// Position of this batch in the transfer
+    pub sequence: u32,
+
+    /// Number of batches in the transfer
+    pub total_batches: u32,
+
+    /// Deltas in this batch
+    pub deltas: Vec<SyncDelta>,
+
+    /// SHA-256 of the serialized deltas, checked before the batch is applied
+    pub hash: String,
+}
+
+/// How far a receiver got with a transfer, saved with each applied batch
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct TransferCheckpoint {
+    /// Transfer the checkpoint belongs to
+    pub transfer_id: String,
+
+    /// Sequence number of the last batch applied
+    pub last_applied_batch: u32,
+
+    /// Number of batches in the transfer
+    pub total_batches: u32,
+
+    /// Vector clock merged from the deltas applied so far
+    pub vector_clock: HashMap<String, i64>,
+
+    /// When the checkpoint was last saved (Unix seconds)
+    pub updated_at: i64,
+}
+
 /// Operation type in sync delta
 #[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
 pub enum DeltaOperation {
@@ -220,6 +278,10 @@ pub struct SyncResponse {
 
     /// Uncompressed data size
     pub uncompressed_size: u64,
+
+    /// Scope both sides agreed on; absent from peers that predate scopes
+    #[serde(default)]
+    pub scope: Option<SyncScope>,
 }
 
 /// Status of sync response
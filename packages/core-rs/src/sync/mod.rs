@@ -1,24 +1,48 @@
+pub mod access;
 pub mod conflict;
 pub mod conflict_resolver;
 pub mod db_init;
 pub mod delta_applier;
 pub mod delta_gatherer;
+pub mod describe;
 pub mod discovery;
 pub mod engine;
 pub mod error;
 pub mod history;
+pub mod merge;
 pub mod mobile_sync;
 pub mod models;
 pub mod p2p;
+pub mod pairing;
 pub mod relay;
+pub mod relay_sync;
+pub mod retention;
+pub mod scope;
+pub mod secure_channel;
+pub mod shadow;
 pub mod tofu;
+pub mod transfer_stats;
 pub mod vector_clock;
 
+pub use access::{get_device_user, get_rejected_deltas, set_device_user, RejectedDelta};
 pub use conflict::{ConflictResolution, ConflictType};
 pub use conflict_resolver::{ConflictResolver, ResolutionStrategy, VersionedEntity};
+pub use describe::{
+    describe_conflict, get_unresolved_conflicts_described, ConflictSummary, FieldChange,
+};
 pub use engine::SyncAgent;
 pub use error::SyncError;
+pub use merge::{merge_three_way, merge_two_way, MergeResult};
 pub use mobile_sync::{DeviceInfo as MobileDeviceInfo, SyncProtocol};
 pub use models::*;
-pub use relay::{RelayClient, RelayEnvelope, RelayError};
+pub use pairing::{PairingCoordinator, PairingError, PairingHello, ShortAuthString};
+pub use relay::{RelayClient, RelayConfig, RelayEnvelope, RelayError};
+pub use relay_sync::{RelayOutbox, RelayPullReport, RelaySyncError};
+pub use retention::{prune_sync_logs, SyncLogPruneResult, SyncLogRetentionPolicy};
+pub use scope::{get_device_sync_scope, set_device_sync_scope, SyncEntityType, SyncScope};
+pub use shadow::{has_merge_conflict, MERGE_CONFLICT_META_KEY};
 pub use tofu::{DeviceTrust, TofuStore, TrustLevel};
+pub use transfer_stats::{
+    get_sync_transfer_report, SyncTransferReport, SyncTransferSession, TransferBreakdown,
+    TransferCounts, TransferDirection,
+};
@@ -1,7 +1,23 @@
+use crate::sync::scope::SyncScope;
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use ulid::Ulid;
 
+/// Sync protocol version this build advertises in `DeviceInfo`
+pub const SYNC_PROTOCOL_VERSION: &str = "2.1.0";
+
+/// First protocol version that exchanges note bodies as CRDT updates
+const CRDT_NOTES_PROTOCOL: (u32, u32) = (2, 1);
+
+/// Whether a peer advertising `protocol_version` accepts CRDT note deltas
+pub fn supports_crdt_notes(protocol_version: &str) -> bool {
+    let mut parts = protocol_version.split('.').map(|p| p.parse::<u32>().ok());
+    match (parts.next().flatten(), parts.next().flatten()) {
+        (Some(major), Some(minor)) => (major, minor) >= CRDT_NOTES_PROTOCOL,
+        _ => false,
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct DeviceInfo {
     pub device_id: String,
@@ -53,6 +69,10 @@ pub struct SyncRequest {
     pub space_id: Ulid,
     pub since_timestamp: i64,
     pub vector_clock: HashMap<String, i64>,
+    /// Scope the requester keeps for the responder; absent from peers that
+    /// predate scopes, which sync everything
+    #[serde(default)]
+    pub scope: Option<SyncScope>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -60,6 +80,9 @@ pub struct SyncResponse {
     pub deltas: Vec<SyncDelta>,
     pub conflicts: Vec<SyncConflict>,
     pub new_vector_clock: HashMap<String, i64>,
+    /// Scope both sides agreed on; the requester sends only deltas in it
+    #[serde(default)]
+    pub scope: Option<SyncScope>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -91,6 +114,11 @@ pub struct SyncStats {
     pub last_sync_at: Option<i64>,
     pub success_rate: f64,
     pub conflicts_total: i32,
+    /// Bytes put on the network by syncs that recorded their transfers
+    #[serde(default)]
+    pub total_bytes_sent: i64,
+    #[serde(default)]
+    pub total_bytes_received: i64,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -105,6 +133,39 @@ pub struct SyncHistoryEntry {
     pub conflicts_detected: i32,
     pub success: bool,
     pub error_message: Option<String>,
+    /// What a row that isn't a sync records
+    #[serde(default)]
+    pub note: Option<String>,
+    #[serde(default)]
+    pub transport: SyncTransport,
+}
+
+/// How a sync reached the other device
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SyncTransport {
+    /// Over the encrypted channel between the two devices
+    #[default]
+    Direct,
+    /// As sealed envelopes through a blind relay
+    Relay,
+}
+
+impl SyncTransport {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SyncTransport::Direct => "direct",
+            SyncTransport::Relay => "relay",
+        }
+    }
+
+    /// Rows written before the transport was recorded were direct syncs
+    pub fn parse(s: &str) -> Self {
+        match s {
+            "relay" => SyncTransport::Relay,
+            _ => SyncTransport::Direct,
+        }
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -2,16 +2,23 @@
 // This module integrates the discovery and mobile_sync modules to provide a complete P2P sync solution.
 
 use super::discovery::{DiscoveredDevice, DiscoveryService};
-use super::mobile_sync::{DeltaOperation, DeviceInfo, SyncCategory, SyncDelta, SyncProtocol};
+use super::mobile_sync::{
+    DeltaOperation, DeviceInfo, SyncCategory, SyncDelta, SyncProtocol, SyncProtocolError,
+};
+use super::pairing::{PairingCoordinator, PairingHello, ShortAuthString};
+use super::secure_channel::{key_matches, ChannelError, Incoming, SecureChannel, StaticKeypair};
 use crate::sync::models::SyncProgress;
 use crate::sync_agent::{SyncDelta as DbSyncDelta, SyncOperation as DbSyncOperation};
 use futures::{SinkExt, StreamExt};
+use rusqlite::Connection;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
 use std::time::Duration;
 use thiserror::Error;
-use tokio::net::TcpListener;
+use tokio::net::{TcpListener, TcpStream};
 use tokio::sync::{Mutex, Semaphore};
-use tokio_tungstenite::accept_async;
+use tokio_tungstenite::tungstenite::Message;
+use tokio_tungstenite::{accept_async, connect_async, WebSocketStream};
 
 #[derive(Error, Debug)]
 pub enum P2pError {
@@ -21,41 +28,97 @@ pub enum P2pError {
     Sync(String),
     #[error("Network error: {0}")]
     Network(String),
+    /// The device couldn't be reached directly; it may be on another
+    /// network, in which case a relay can carry the sync instead
+    #[error("Device unreachable: {0}")]
+    Unreachable(String),
     #[error("Database error: {0}")]
     Database(String),
+    #[error("Pairing error: {0}")]
+    Pairing(String),
+    #[error("Device {0} is not trusted; pair and confirm it first")]
+    UntrustedDevice(String),
+    #[error("Secure channel error: {0}")]
+    Channel(String),
+}
+
+impl From<super::pairing::PairingError> for P2pError {
+    fn from(err: super::pairing::PairingError) -> Self {
+        P2pError::Pairing(err.to_string())
+    }
+}
+
+impl From<ChannelError> for P2pError {
+    fn from(err: ChannelError) -> Self {
+        match err {
+            ChannelError::UntrustedDevice(device_id) => P2pError::UntrustedDevice(device_id),
+            other => P2pError::Channel(other.to_string()),
+        }
+    }
 }
 
 #[derive(Clone)]
 pub struct P2pSync {
     pub discovery: Arc<DiscoveryService>,
     protocol: Arc<Mutex<SyncProtocol>>,
+    pairing: Arc<PairingCoordinator>,
+    static_key: Arc<StaticKeypair>,
+    device_id: String,
+    /// Serve protocol 1 peers (the mobile app until it speaks protocol 2)
+    /// without transport encryption
+    allow_legacy_peers: Arc<AtomicBool>,
 }
 
 impl P2pSync {
+    /// Create a sync node with a throwaway transport key. Peers pin the key
+    /// at pairing, so apps should load a persistent one with
+    /// [`P2pSync::with_static_key`].
     pub fn new(device_info: DeviceInfo) -> Result<Self, P2pError> {
+        let static_key = StaticKeypair::generate()?;
+        Self::with_static_key(device_info, static_key)
+    }
+
+    pub fn with_static_key(
+        device_info: DeviceInfo,
+        static_key: StaticKeypair,
+    ) -> Result<Self, P2pError> {
         let discovery = DiscoveryService::new().map_err(|e| P2pError::Discovery(e.to_string()))?;
+        let pairing = PairingCoordinator::new(device_info.device_id.clone())
+            .with_static_key(static_key.public_key().to_vec());
+        let device_id = device_info.device_id.clone();
         let protocol = SyncProtocol::new(device_info);
 
         Ok(Self {
             discovery: Arc::new(discovery),
             protocol: Arc::new(Mutex::new(protocol)),
+            pairing: Arc::new(pairing),
+            static_key: Arc::new(static_key),
+            device_id,
+            allow_legacy_peers: Arc::new(AtomicBool::new(false)),
         })
     }
 
+    /// Let trusted protocol 1 peers sync over the old plaintext transport.
+    /// Off by default; see [`crate::db::SyncSettings::allow_legacy_protocol`].
+    pub fn set_allow_legacy_peers(&self, allow: bool) {
+        if allow {
+            log::warn!("[p2p] Accepting unencrypted protocol 1 sync from trusted devices");
+        }
+        self.allow_legacy_peers.store(allow, Ordering::Relaxed);
+    }
+
     pub async fn start_server(&self, port: u16) -> Result<(), P2pError> {
         let listener = TcpListener::bind(format!("0.0.0.0:{}", port))
             .await
             .map_err(|e| P2pError::Network(e.to_string()))?;
         log::info!("[p2p] Sync server listening on port {}", port);
+        self.serve(listener).await
+    }
 
-        // Get server public key for handshake
-        let server_pubkey_b64 = {
-            let proto = self.protocol.lock().await;
-            use base64::Engine;
-            base64::engine::general_purpose::STANDARD.encode(&proto.device_info.public_key)
-        };
-        let server_pubkey_b64 = Arc::new(server_pubkey_b64);
-
+    /// Accept sync connections on a bound listener. Each connection must
+    /// complete the encrypted handshake as a trusted device before any delta
+    /// is read.
+    pub async fn serve(&self, listener: TcpListener) -> Result<(), P2pError> {
         // Limit concurrent connections to 10
         let connection_limit = Arc::new(Semaphore::new(10));
 
@@ -80,118 +143,141 @@ impl P2pSync {
                 .unwrap_or_default();
             log::info!("[p2p] New connection from {}", peer_addr);
 
-            let server_pubkey = server_pubkey_b64.clone();
-            let protocol = self.protocol.clone();
-
+            let node = self.clone();
             tokio::spawn(async move {
                 // Permit is held until this task is dropped
                 let _permit = permit;
 
-                match accept_async(stream).await {
-                    Ok(ws_stream) => {
-                        let (mut writer, mut reader) = ws_stream.split();
-                        // Expect a handshake JSON as the first text message
-                        match reader.next().await {
-                            Some(Ok(msg)) if msg.is_text() => {
-                                let text = msg.to_text().unwrap_or_default();
-                                match serde_json::from_str::<serde_json::Value>(text) {
-                                    Ok(req)
-                                        if req.get("type")
-                                            == Some(&serde_json::Value::String(
-                                                "handshake".to_string(),
-                                            )) =>
-                                    {
-                                        let response = serde_json::json!({
-                                            "type": "handshake_response",
-                                            "publicKey": *server_pubkey
-                                        });
-                                        if let Err(e) = writer
-                                            .send(tokio_tungstenite::tungstenite::Message::Text(
-                                                response.to_string(),
-                                            ))
-                                            .await
-                                        {
-                                            log::error!(
-                                                "[p2p] Failed to send handshake_response: {}",
-                                                e
-                                            );
-                                            return;
-                                        }
-                                    }
-                                    _ => {
-                                        log::warn!(
-                                            "[p2p] Invalid or unexpected first message from {}",
-                                            peer_addr
-                                        );
-                                        let _ = writer
-                                            .send(tokio_tungstenite::tungstenite::Message::Close(
-                                                None,
-                                            ))
-                                            .await;
-                                        return;
-                                    }
-                                }
-                            }
-                            Some(Ok(other)) => {
-                                log::warn!(
-                                    "[p2p] Non-text first frame from {}: {:?}",
-                                    peer_addr,
-                                    other
-                                );
-                                let _ = writer
-                                    .send(tokio_tungstenite::tungstenite::Message::Close(None))
-                                    .await;
-                                return;
-                            }
-                            Some(Err(e)) => {
-                                log::error!("[p2p] Read error from {}: {}", peer_addr, e);
-                                return;
-                            }
-                            None => {
-                                log::warn!(
-                                    "[p2p] Connection closed before handshake from {}",
-                                    peer_addr
-                                );
-                                return;
-                            }
-                        }
-
-                        // After handshake, handle the full sync data exchange
-                        use tokio_tungstenite::tungstenite::Message;
-                        let mut proto_guard = protocol.lock().await;
-                        while let Some(Ok(msg)) = reader.next().await {
-                            if let Message::Text(text) = msg {
-                                // Deserialize delta from text, process it with SyncProtocol
-                                if let Ok(delta) = serde_json::from_str::<SyncDelta>(&text) {
-                                    if let Ok(response_delta) =
-                                        proto_guard.handle_delta(delta).await
-                                    {
-                                        if let Ok(response_text) =
-                                            serde_json::to_string(&response_delta)
-                                        {
-                                            let _ = writer.send(Message::Text(response_text)).await;
-                                        }
-                                    }
-                                }
-                            }
-                        }
-                    }
+                let ws_stream = match accept_async(stream).await {
+                    Ok(ws_stream) => ws_stream,
                     Err(e) => {
                         log::error!("[p2p] WebSocket upgrade failed for {}: {}", peer_addr, e);
+                        return;
+                    }
+                };
+                let pairing = node.pairing.clone();
+                let incoming = SecureChannel::accept_incoming(
+                    ws_stream,
+                    &node.static_key,
+                    &node.device_id,
+                    node.allow_legacy_peers.load(Ordering::Relaxed),
+                    |device_id, static_key| authorize_peer(&pairing, device_id, static_key),
+                )
+                .await;
+                let mut channel = match incoming {
+                    Ok(Incoming::Secure(channel)) => channel,
+                    Ok(Incoming::Legacy { ws, handshake }) => {
+                        node.serve_legacy(ws, &handshake, &peer_addr).await;
+                        return;
+                    }
+                    Err(e) => {
+                        log::warn!("[p2p] Rejected handshake from {}: {}", peer_addr, e);
+                        return;
+                    }
+                };
+                log::info!(
+                    "[p2p] Secure channel established with {}",
+                    channel.peer_device_id()
+                );
+
+                // After handshake, handle the full sync data exchange
+                let mut proto_guard = node.protocol.lock().await;
+                loop {
+                    let delta = match channel.recv_json::<SyncDelta>().await {
+                        Ok(Some(delta)) => delta,
+                        Ok(None) => break,
+                        Err(e) => {
+                            log::warn!("[p2p] Sync channel with {} failed: {}", peer_addr, e);
+                            break;
+                        }
+                    };
+                    if let Ok(response_delta) = proto_guard.handle_delta(delta).await {
+                        if let Err(e) = channel.send_json(&response_delta).await {
+                            log::warn!("[p2p] Failed to answer {}: {}", peer_addr, e);
+                            break;
+                        }
                     }
                 }
             });
         }
     }
 
-    pub fn discover_peers(&self) -> Result<Vec<DiscoveredDevice>, P2pError> {
+    /// Sync with a protocol 1 peer the way it expects: a plaintext JSON
+    /// handshake naming a trusted device, then one delta per text frame
+    async fn serve_legacy(
+        &self,
+        mut ws: WebSocketStream<TcpStream>,
+        handshake: &str,
+        peer_addr: &str,
+    ) {
+        let device_id = match legacy_handshake_device(&self.pairing, handshake) {
+            Ok(device_id) => device_id,
+            Err(e) => {
+                log::warn!(
+                    "[p2p] Rejected protocol 1 handshake from {}: {}",
+                    peer_addr,
+                    e
+                );
+                let rejection = serde_json::json!({
+                    "type": "handshake_rejected",
+                    "reason": e.to_string(),
+                });
+                let _ = ws.send(Message::Text(rejection.to_string())).await;
+                let _ = ws.close(None).await;
+                return;
+            }
+        };
+        log::warn!(
+            "[p2p] Syncing with {} over the unencrypted protocol 1 transport",
+            device_id
+        );
+
+        let mut proto_guard = self.protocol.lock().await;
+        let response = {
+            use base64::Engine;
+            serde_json::json!({
+                "type": "handshake_response",
+                "publicKey": base64::engine::general_purpose::STANDARD
+                    .encode(&proto_guard.device_info.public_key),
+            })
+        };
+        if let Err(e) = ws.send(Message::Text(response.to_string())).await {
+            log::warn!("[p2p] Failed to answer {}: {}", peer_addr, e);
+            return;
+        }
+        while let Some(Ok(message)) = ws.next().await {
+            let Message::Text(text) = message else {
+                continue;
+            };
+            let Ok(delta) = serde_json::from_str::<SyncDelta>(&text) else {
+                continue;
+            };
+            if let Ok(response_delta) = proto_guard.handle_delta(delta).await {
+                let Ok(response_text) = serde_json::to_string(&response_delta) else {
+                    continue;
+                };
+                if let Err(e) = ws.send(Message::Text(response_text)).await {
+                    log::warn!("[p2p] Failed to answer {}: {}", peer_addr, e);
+                    break;
+                }
+            }
+        }
+    }
+
+    /// Peers found over mDNS plus the manually added ones, with reachability
+    pub fn discover_peers(&self, conn: &Connection) -> Result<Vec<DiscoveredDevice>, P2pError> {
         self.discovery
-            .discover(Duration::from_secs(5))
+            .discover_all(conn, Duration::from_secs(5))
             .map_err(|e| P2pError::Discovery(e.to_string()))
     }
 
-    /// Start sync with a specific device.
+    /// Start sync with a specific device. The device must have completed a
+    /// verified pairing.
     pub async fn start_sync(&self, device_id: &str) -> Result<(), P2pError> {
+        if !self.pairing.is_trusted(device_id) {
+            log::warn!("[p2p] Refusing to sync with untrusted device {}", device_id);
+            return Err(P2pError::UntrustedDevice(device_id.to_string()));
+        }
         log::info!("[p2p] Starting sync with device {}", device_id);
         let mut protocol = self.protocol.lock().await;
 
@@ -210,6 +296,10 @@ impl P2pSync {
                 log::info!("[p2p] Sync finished successfully with {}", device_id);
                 Ok(())
             }
+            Err(e @ (SyncProtocolError::ConnectionFailed(_) | SyncProtocolError::Timeout)) => {
+                log::warn!("[p2p] Device {} is unreachable: {}", device_id, e);
+                Err(P2pError::Unreachable(e.to_string()))
+            }
             Err(e) => {
                 log::error!("[p2p] Sync failed with {}: {}", device_id, e);
                 Err(P2pError::Sync(e.to_string()))
@@ -217,16 +307,83 @@ impl P2pSync {
         }
     }
 
-    pub async fn initiate_pairing(&self, device_id: &str) -> Result<(), P2pError> {
-        let protocol = self.protocol.lock().await;
+    /// Send deltas to a trusted device's sync server over the encrypted
+    /// channel, returning its answer to each
+    pub async fn exchange_deltas(
+        &self,
+        addr: &str,
+        device_id: &str,
+        deltas: &[SyncDelta],
+    ) -> Result<Vec<SyncDelta>, P2pError> {
+        if !self.pairing.is_trusted(device_id) {
+            return Err(P2pError::UntrustedDevice(device_id.to_string()));
+        }
+        let (ws_stream, _) = connect_async(format!("ws://{}", addr))
+            .await
+            .map_err(|e| P2pError::Network(e.to_string()))?;
+        let pinned = self.pairing.pinned_static_key(device_id);
+        let mut channel = SecureChannel::connect(
+            ws_stream,
+            &self.static_key,
+            &self.device_id,
+            device_id,
+            pinned.as_deref(),
+        )
+        .await?;
 
-        if protocol.is_device_available(device_id) {
-            log::info!("[p2p] Device {} is already available/paired.", device_id);
-            return Ok(());
+        let mut responses = Vec::with_capacity(deltas.len());
+        for delta in deltas {
+            channel.send_json(delta).await?;
+            let response = channel
+                .recv_json::<SyncDelta>()
+                .await?
+                .ok_or_else(|| P2pError::Network("Peer closed the sync channel".to_string()))?;
+            responses.push(response);
         }
+        channel.close().await?;
+        Ok(responses)
+    }
+
+    /// Start a verified pairing with a device, returning the hello to send it
+    pub async fn initiate_pairing(&self, device_id: &str) -> Result<PairingHello, P2pError> {
+        log::info!("[p2p] Initiating pairing with {}", device_id);
+        Ok(self.pairing.initiate_pairing(device_id)?)
+    }
 
-        log::info!("[p2p] Ready to accept pairing from {}", device_id);
-        Ok(())
+    /// Process the peer's next pairing message; returns the one to send back
+    pub fn exchange_keys(&self, peer: &PairingHello) -> Result<PairingHello, P2pError> {
+        Ok(self.pairing.exchange_keys(peer)?)
+    }
+
+    /// SAS to display while a pairing waits for the user's confirmation
+    pub fn get_pending_pairing_sas(&self, device_id: &str) -> Option<ShortAuthString> {
+        self.pairing.get_pending_pairing_sas(device_id)
+    }
+
+    /// Record the user's SAS comparison; only an accepted pairing is trusted
+    pub fn confirm_pairing(
+        &self,
+        conn: &Connection,
+        device_id: &str,
+        accepted: bool,
+    ) -> Result<(), P2pError> {
+        Ok(self.pairing.confirm_pairing(conn, device_id, accepted)?)
+    }
+
+    /// Load the trusted peers recorded in `sync_state`
+    pub fn load_trusted_peers(&self, conn: &Connection) -> Result<usize, P2pError> {
+        Ok(self.pairing.load_trusted(conn)?)
+    }
+
+    /// This device's public transport key, exchanged during pairing
+    pub fn static_public_key(&self) -> &[u8] {
+        self.static_key.public_key()
+    }
+
+    /// Decide whether a device that completed the transport handshake may
+    /// sync: it must be trusted and prove the key pinned at pairing
+    pub fn authorize_peer(&self, device_id: &str, static_key: &[u8]) -> Result<(), P2pError> {
+        Ok(authorize_peer(&self.pairing, device_id, static_key)?)
     }
 
     /// Get sync progress for a specific peer
@@ -304,6 +461,42 @@ impl P2pSync {
     }
 }
 
+fn authorize_peer(
+    pairing: &PairingCoordinator,
+    device_id: &str,
+    static_key: &[u8],
+) -> Result<(), ChannelError> {
+    if !pairing.is_trusted(device_id) {
+        return Err(ChannelError::UntrustedDevice(device_id.to_string()));
+    }
+    key_matches(
+        device_id,
+        pairing.pinned_static_key(device_id).as_deref(),
+        static_key,
+    )
+}
+
+/// Trusted device a protocol 1 handshake names
+fn legacy_handshake_device(
+    pairing: &PairingCoordinator,
+    handshake: &str,
+) -> Result<String, ChannelError> {
+    let request: serde_json::Value = serde_json::from_str(handshake)?;
+    if request.get("type").and_then(|t| t.as_str()) != Some("handshake") {
+        return Err(ChannelError::Protocol(
+            "Expected a handshake message".to_string(),
+        ));
+    }
+    let device_id = request
+        .get("deviceId")
+        .and_then(|id| id.as_str())
+        .ok_or_else(|| ChannelError::Protocol("Handshake is missing deviceId".to_string()))?;
+    if !pairing.is_trusted(device_id) {
+        return Err(ChannelError::UntrustedDevice(device_id.to_string()));
+    }
+    Ok(device_id.to_string())
+}
+
 // Conversion helpers between Database SyncDelta and Protocol SyncDelta
 pub fn db_delta_to_protocol_delta(db_delta: DbSyncDelta) -> SyncDelta {
     // Use vector clock sequence number for causal ordering if available, otherwise fallback to 0.
@@ -0,0 +1,394 @@
+//! Verified Device Pairing
+//!
+//! After the X25519 key exchange both devices derive a short authentication
+//! string (SAS) from the shared secret and the pairing transcript (both device
+//! ids and public keys). The user compares the SAS shown on the two screens;
+//! only a confirmed match marks the peer as trusted in `sync_state`.
+//!
+//! A man in the middle who substitutes public keys on the LAN ends up with a
+//! different shared secret and transcript on each side, so the two SAS values
+//! disagree and the user rejects the pairing.
+//!
+//! The hello also carries the device's long-term transport key. It is part of
+//! the transcript, so a confirmed SAS vouches for it too, and it is pinned in
+//! `sync_state.static_public_key` for the encrypted sync channel to check.
+//!
+//! A six-digit SAS is only ~20 bits, so an attacker who could choose its key
+//! after seeing both real keys could grind for a colliding SAS. The initiator
+//! therefore opens with a commitment, `SHA256(public_key || static_key)`, and
+//! reveals its keys only after the responder's keys have arrived:
+//!
+//! 1. initiator -> responder: hello carrying only the commitment
+//! 2. responder -> initiator: hello with the responder's keys
+//! 3. initiator -> responder: reveal with the initiator's keys
+//!
+//! The responder checks the reveal against the commitment before deriving the
+//! SAS, so neither side's keys can be picked with knowledge of the other's.
+
+use hkdf::Hkdf;
+use rand::rngs::OsRng;
+use rusqlite::{params, Connection};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::sync::Mutex;
+use thiserror::Error;
+use x25519_dalek::{EphemeralSecret, PublicKey};
+
+/// Domain separation for the transcript hash and SAS derivation
+const TRANSCRIPT_LABEL: &[u8] = b"noteece-pairing-v1";
+const SAS_INFO: &[u8] = b"noteece-sas";
+
+/// Number of emoji shown in the emoji form of the SAS (6 bits each)
+const SAS_EMOJI_COUNT: usize = 7;
+
+const SAS_EMOJI: [&str; 64] = [
+    "🐶", "🐱", "🦁", "🐎", "🦄", "🐷", "🐘", "🐰", "🐼", "🐓", "🐧", "🐢", "🐟", "🐙", "🦋", "🌷",
+    "🌳", "🌵", "🍄", "🌏", "🌙", "☁️", "🔥", "🍌", "🍎", "🍓", "🌽", "🍕", "🎂", "❤️", "😀", "🤖",
+    "🎩", "👓", "🔧", "🎅", "👍", "☂️", "⌛", "⏰", "🎁", "💡", "📕", "✏️", "📎", "✂️", "🔒", "🔑",
+    "🔨", "☎️", "🏁", "🚂", "🚲", "✈️", "🚀", "🏆", "⚽", "🎸", "🎺", "🔔", "⚓", "🎧", "📁", "📌",
+];
+
+#[derive(Error, Debug)]
+pub enum PairingError {
+    #[error("No pairing in progress with device {0}")]
+    NoPendingPairing(String),
+
+    #[error("Pairing with device {0} has not finished the key exchange")]
+    KeyExchangeIncomplete(String),
+
+    #[error("Invalid public key from device {0}")]
+    InvalidPublicKey(String),
+
+    #[error("Pairing hello from device {0} carries no key commitment")]
+    MissingCommitment(String),
+
+    #[error("Keys revealed by device {0} do not match its commitment")]
+    CommitmentMismatch(String),
+
+    #[error("Device {0} is not registered")]
+    DeviceNotRegistered(String),
+
+    #[error("Database error: {0}")]
+    Database(#[from] rusqlite::Error),
+}
+
+/// The message each side sends during the key exchange. The initiator's
+/// opening hello carries only `commitment`; every later hello carries keys.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct PairingHello {
+    pub device_id: String,
+    #[serde(default)]
+    pub public_key: Vec<u8>,
+    /// Long-term transport key; empty from peers that predate it
+    #[serde(default)]
+    pub static_key: Vec<u8>,
+    /// `SHA256(public_key || static_key)` of keys to be revealed later
+    #[serde(default)]
+    pub commitment: Vec<u8>,
+}
+
+/// Commitment the initiator sends before revealing its keys
+pub fn key_commitment(public_key: &[u8], static_key: &[u8]) -> Vec<u8> {
+    let mut hasher = Sha256::new();
+    hasher.update(public_key);
+    hasher.update(static_key);
+    hasher.finalize().to_vec()
+}
+
+/// Short authentication string shown to the user on both devices
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct ShortAuthString {
+    /// Six decimal digits, e.g. "042917"
+    pub decimal: String,
+    pub emoji: Vec<String>,
+}
+
+/// Derive the SAS for a completed key exchange.
+///
+/// Each party is its device id, ephemeral public key and static transport
+/// key. The transcript orders the two parties by device id, so both sides
+/// compute the same value regardless of who initiated.
+pub fn derive_sas(
+    shared_secret: &[u8],
+    party_a: (&str, &[u8], &[u8]),
+    party_b: (&str, &[u8], &[u8]),
+) -> ShortAuthString {
+    let (first, second) = if party_a.0 <= party_b.0 {
+        (party_a, party_b)
+    } else {
+        (party_b, party_a)
+    };
+
+    let mut transcript = Sha256::new();
+    transcript.update(TRANSCRIPT_LABEL);
+    for (device_id, public_key, static_key) in [first, second] {
+        for field in [device_id.as_bytes(), public_key, static_key] {
+            transcript.update((field.len() as u32).to_be_bytes());
+            transcript.update(field);
+        }
+    }
+    let transcript_hash = transcript.finalize();
+
+    let hk = Hkdf::<Sha256>::new(Some(&transcript_hash), shared_secret);
+    let mut okm = [0u8; 10];
+    hk.expand(SAS_INFO, &mut okm)
+        .expect("10 bytes is a valid HKDF-SHA256 output length");
+
+    let number = u32::from_be_bytes([okm[0], okm[1], okm[2], okm[3]]) % 1_000_000;
+
+    // 42 bits from the remaining bytes, 6 bits per emoji
+    let bits = okm[4..]
+        .iter()
+        .fold(0u64, |acc, byte| (acc << 8) | u64::from(*byte));
+    let emoji = (0..SAS_EMOJI_COUNT)
+        .map(|i| {
+            let index = (bits >> (48 - 6 * (i + 1))) & 0x3f;
+            SAS_EMOJI[index as usize].to_string()
+        })
+        .collect();
+
+    ShortAuthString {
+        decimal: format!("{:06}", number),
+        emoji,
+    }
+}
+
+struct PendingPairing {
+    /// Consumed by the key exchange
+    secret: Option<EphemeralSecret>,
+    our_public_key: [u8; 32],
+    /// Set on the responder until the initiator reveals its keys
+    peer_commitment: Option<Vec<u8>>,
+    peer_static_key: Vec<u8>,
+    sas: Option<ShortAuthString>,
+}
+
+/// Tracks pairing ceremonies in progress and the set of trusted peers.
+///
+/// Trust is persisted in `sync_state.trusted` and the pinned transport key in
+/// `sync_state.static_public_key`; the in-memory map mirrors both so the sync
+/// paths can check a peer without a database connection.
+pub struct PairingCoordinator {
+    device_id: String,
+    static_key: Vec<u8>,
+    pending: Mutex<HashMap<String, PendingPairing>>,
+    trusted: Mutex<HashMap<String, Option<Vec<u8>>>>,
+}
+
+impl PairingCoordinator {
+    pub fn new(device_id: String) -> Self {
+        PairingCoordinator {
+            device_id,
+            static_key: Vec::new(),
+            pending: Mutex::new(HashMap::new()),
+            trusted: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Announce this device's transport key in its hellos
+    pub fn with_static_key(mut self, static_key: Vec<u8>) -> Self {
+        self.static_key = static_key;
+        self
+    }
+
+    /// Start pairing with a peer, returning the hello to send it. The hello
+    /// commits to our keys without revealing them.
+    pub fn initiate_pairing(&self, peer_device_id: &str) -> Result<PairingHello, PairingError> {
+        let our_public_key = self.start_session(peer_device_id, None);
+        log::info!("[pairing] Initiated pairing with {}", peer_device_id);
+        Ok(PairingHello {
+            device_id: self.device_id.clone(),
+            public_key: Vec::new(),
+            static_key: Vec::new(),
+            commitment: key_commitment(&our_public_key, &self.static_key),
+        })
+    }
+
+    /// Process the next message of the ceremony and return the one to send
+    /// back, if any:
+    ///
+    /// - an opening commitment makes us the responder; we reply with our keys
+    /// - as initiator, the responder's keys yield the SAS and our reveal
+    /// - as responder, the reveal is checked against the commitment before
+    ///   the SAS is derived; nothing further needs sending
+    pub fn exchange_keys(&self, peer: &PairingHello) -> Result<PairingHello, PairingError> {
+        if !peer.commitment.is_empty() {
+            let our_public_key = self.start_session(&peer.device_id, Some(peer.commitment.clone()));
+            log::info!(
+                "[pairing] Received commitment from {}, sending our keys",
+                peer.device_id
+            );
+            return Ok(self.our_hello(&our_public_key));
+        }
+
+        let peer_key = <[u8; 32]>::try_from(peer.public_key.as_slice())
+            .map_err(|_| PairingError::InvalidPublicKey(peer.device_id.clone()))?;
+
+        let mut pending = self.lock_pending();
+        let session = pending
+            .get_mut(&peer.device_id)
+            .filter(|session| session.secret.is_some())
+            .ok_or_else(|| PairingError::MissingCommitment(peer.device_id.clone()))?;
+
+        if let Some(commitment) = &session.peer_commitment {
+            if key_commitment(&peer_key, &peer.static_key) != *commitment {
+                pending.remove(&peer.device_id);
+                log::warn!(
+                    "[pairing] Device {} revealed keys that do not match its commitment",
+                    peer.device_id
+                );
+                return Err(PairingError::CommitmentMismatch(peer.device_id.clone()));
+            }
+        }
+
+        let secret = session
+            .secret
+            .take()
+            .ok_or_else(|| PairingError::NoPendingPairing(peer.device_id.clone()))?;
+        let shared_secret = secret.diffie_hellman(&PublicKey::from(peer_key));
+        session.sas = Some(derive_sas(
+            shared_secret.as_bytes(),
+            (&self.device_id, &session.our_public_key, &self.static_key),
+            (&peer.device_id, &peer_key, &peer.static_key),
+        ));
+        session.peer_static_key = peer.static_key.clone();
+        let our_public_key = session.our_public_key;
+        drop(pending);
+
+        log::info!(
+            "[pairing] Key exchange with {} complete, awaiting SAS confirmation",
+            peer.device_id
+        );
+        Ok(self.our_hello(&our_public_key))
+    }
+
+    /// The SAS to display for a pairing awaiting confirmation
+    pub fn get_pending_pairing_sas(&self, device_id: &str) -> Option<ShortAuthString> {
+        self.lock_pending()
+            .get(device_id)
+            .and_then(|session| session.sas.clone())
+    }
+
+    /// Finish a pairing once the user has compared the SAS on both devices.
+    /// Accepting marks the peer trusted and pins its transport key; rejecting
+    /// revokes any earlier trust.
+    pub fn confirm_pairing(
+        &self,
+        conn: &Connection,
+        device_id: &str,
+        accepted: bool,
+    ) -> Result<(), PairingError> {
+        let session = self
+            .lock_pending()
+            .remove(device_id)
+            .ok_or_else(|| PairingError::NoPendingPairing(device_id.to_string()))?;
+        if session.sas.is_none() {
+            return Err(PairingError::KeyExchangeIncomplete(device_id.to_string()));
+        }
+
+        if accepted {
+            set_device_trusted(conn, device_id, true)?;
+            let static_key =
+                (!session.peer_static_key.is_empty()).then_some(session.peer_static_key);
+            conn.execute(
+                "UPDATE sync_state SET static_public_key = ?1 WHERE device_id = ?2",
+                params![static_key, device_id],
+            )?;
+            self.lock_trusted()
+                .insert(device_id.to_string(), static_key);
+            log::info!("[pairing] Device {} verified and trusted", device_id);
+        } else {
+            // The device may never have been registered; nothing to revoke then
+            match set_device_trusted(conn, device_id, false) {
+                Ok(()) | Err(PairingError::DeviceNotRegistered(_)) => {}
+                Err(e) => return Err(e),
+            }
+            self.lock_trusted().remove(device_id);
+            log::warn!("[pairing] Pairing with {} rejected by user", device_id);
+        }
+        Ok(())
+    }
+
+    /// Load trusted peers and their pinned keys from `sync_state`
+    pub fn load_trusted(&self, conn: &Connection) -> Result<usize, PairingError> {
+        let mut stmt =
+            conn.prepare("SELECT device_id, static_public_key FROM sync_state WHERE trusted = 1")?;
+        let peers = stmt
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?
+            .collect::<Result<Vec<(String, Option<Vec<u8>>)>, _>>()?;
+        let mut trusted = self.lock_trusted();
+        trusted.clear();
+        trusted.extend(peers);
+        Ok(trusted.len())
+    }
+
+    pub fn is_trusted(&self, device_id: &str) -> bool {
+        self.lock_trusted().contains_key(device_id)
+    }
+
+    /// Transport key pinned when the device was paired, if it sent one
+    pub fn pinned_static_key(&self, device_id: &str) -> Option<Vec<u8>> {
+        self.lock_trusted().get(device_id).cloned().flatten()
+    }
+
+    /// Register a fresh ephemeral key for a ceremony, replacing any earlier one
+    fn start_session(&self, peer_device_id: &str, peer_commitment: Option<Vec<u8>>) -> [u8; 32] {
+        let secret = EphemeralSecret::random_from_rng(OsRng);
+        let our_public_key = PublicKey::from(&secret).to_bytes();
+        self.lock_pending().insert(
+            peer_device_id.to_string(),
+            PendingPairing {
+                secret: Some(secret),
+                our_public_key,
+                peer_commitment,
+                peer_static_key: Vec::new(),
+                sas: None,
+            },
+        );
+        our_public_key
+    }
+
+    fn our_hello(&self, our_public_key: &[u8; 32]) -> PairingHello {
+        PairingHello {
+            device_id: self.device_id.clone(),
+            public_key: our_public_key.to_vec(),
+            static_key: self.static_key.clone(),
+            commitment: Vec::new(),
+        }
+    }
+
+    fn lock_pending(&self) -> std::sync::MutexGuard<'_, HashMap<String, PendingPairing>> {
+        self.pending.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn lock_trusted(&self) -> std::sync::MutexGuard<'_, HashMap<String, Option<Vec<u8>>>> {
+        self.trusted.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+/// Set the `trusted` flag of a registered device
+pub fn set_device_trusted(
+    conn: &Connection,
+    device_id: &str,
+    trusted: bool,
+) -> Result<(), PairingError> {
+    let updated = conn.execute(
+        "UPDATE sync_state SET trusted = ?1 WHERE device_id = ?2",
+        params![trusted, device_id],
+    )?;
+    if updated == 0 {
+        return Err(PairingError::DeviceNotRegistered(device_id.to_string()));
+    }
+    Ok(())
+}
+
+/// Ids of all devices marked trusted in `sync_state`
+pub fn get_trusted_device_ids(conn: &Connection) -> Result<Vec<String>, PairingError> {
+    let mut stmt = conn.prepare("SELECT device_id FROM sync_state WHERE trusted = 1")?;
+    let ids = stmt
+        .query_map([], |row| row.get(0))?
+        .collect::<Result<Vec<String>, _>>()?;
+    Ok(ids)
+}
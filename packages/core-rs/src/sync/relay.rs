@@ -11,11 +11,20 @@
 //!                                  plaintext)
 //! ```
 
+use super::secure_channel::StaticKeypair;
+use chacha20poly1305::aead::{Aead, AeadCore, KeyInit, OsRng, Payload};
+use chacha20poly1305::XChaCha20Poly1305;
+use hkdf::Hkdf;
+use hmac::{Hmac, Mac};
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use sha2::Sha256;
+use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
 use std::sync::{Arc, Mutex};
 use std::time::{SystemTime, UNIX_EPOCH};
+use subtle::ConstantTimeEq;
 use thiserror::Error;
+use x25519_dalek::{EphemeralSecret, PublicKey};
+use zeroize::Zeroizing;
 
 /// Maximum age for pending messages (24 hours)
 const MAX_MESSAGE_AGE_SECS: u64 = 86400;
@@ -26,14 +35,33 @@ const MAX_MESSAGE_SIZE: usize = 10 * 1024 * 1024;
 /// Maximum pending messages per device
 const MAX_PENDING_PER_DEVICE: usize = 100;
 
+/// Maximum submissions per sender per minute
+const MAX_SUBMISSIONS_PER_MINUTE: u32 = 120;
+
+/// Window of the per-sender rate limit
+const RATE_WINDOW_SECS: u64 = 60;
+
+/// HKDF labels of the envelope encryption and signature keys
+const SEAL_INFO: &[u8] = b"noteece-relay-v1 seal";
+const SIGN_INFO: &[u8] = b"noteece-relay-v1 sign";
+
+/// XChaCha20-Poly1305 nonce length
+const NONCE_LEN: usize = 24;
+
 #[derive(Error, Debug)]
 pub enum RelayError {
     #[error("Device not registered")]
     DeviceNotRegistered,
-    #[error("Message too large (max {MAX_MESSAGE_SIZE} bytes)")]
+    #[error("Missing or invalid device token")]
+    Unauthorized,
+    #[error("Recipient has deregistered")]
+    RecipientDeregistered,
+    #[error("Message too large")]
     MessageTooLarge,
     #[error("Too many pending messages")]
     TooManyPending,
+    #[error("Too many submissions, retry later")]
+    RateLimited,
     #[error("Message expired")]
     MessageExpired,
     #[error("Invalid signature")]
@@ -44,8 +72,41 @@ pub enum RelayError {
     NetworkError(String),
 }
 
+/// Limits a relay enforces on the devices using it. Missing fields take
+/// their defaults when deserialized.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RelayConfig {
+    /// Largest envelope accepted, counting every binary field
+    pub max_envelope_bytes: usize,
+    /// Messages a recipient can have waiting before submissions are refused
+    pub max_pending_per_recipient: usize,
+    /// Submissions accepted from one sender in any minute
+    pub max_submissions_per_minute: u32,
+    /// Seconds a message waits for its recipient before it is dropped
+    pub message_ttl_secs: u64,
+}
+
+impl Default for RelayConfig {
+    fn default() -> Self {
+        Self {
+            max_envelope_bytes: MAX_MESSAGE_SIZE,
+            max_pending_per_recipient: MAX_PENDING_PER_DEVICE,
+            max_submissions_per_minute: MAX_SUBMISSIONS_PER_MINUTE,
+            message_ttl_secs: MAX_MESSAGE_AGE_SECS,
+        }
+    }
+}
+
+fn now_secs() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or(std::time::Duration::from_secs(0))
+        .as_secs()
+}
+
 /// Encrypted message envelope for relay
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct RelayEnvelope {
     /// Unique message ID
     pub id: String,
@@ -91,7 +152,7 @@ impl RelayEnvelope {
             nonce,
             timestamp,
             message_type: message_type.to_string(),
-            signature: Vec::new(), // Filled by sign()
+            signature: Vec::new(), // Filled by seal()
         }
     }
 
@@ -104,6 +165,14 @@ impl RelayEnvelope {
         now.saturating_sub(self.timestamp) > MAX_MESSAGE_AGE_SECS
     }
 
+    /// Bytes the envelope occupies in a queue
+    pub fn size_bytes(&self) -> usize {
+        self.ciphertext.len()
+            + self.ephemeral_pubkey.len()
+            + self.nonce.len()
+            + self.signature.len()
+    }
+
     /// Validate envelope size
     pub fn validate_size(&self) -> Result<(), RelayError> {
         if self.ciphertext.len() > MAX_MESSAGE_SIZE {
@@ -111,22 +180,188 @@ impl RelayEnvelope {
         }
         Ok(())
     }
+
+    /// Encrypt `plaintext` for `recipient_key`, the recipient's static
+    /// transport key pinned at pairing. The payload is sealed to an
+    /// ephemeral key, so only the recipient can open it; the signature is
+    /// keyed by both static keys, so only the sender could have made it.
+    pub fn seal(
+        sender: &StaticKeypair,
+        from_device: &str,
+        to_device: &str,
+        recipient_key: &[u8],
+        message_type: &str,
+        plaintext: &[u8],
+    ) -> Result<Self, RelayError> {
+        let recipient: [u8; 32] = recipient_key
+            .try_into()
+            .map_err(|_| RelayError::EncryptionError("Invalid recipient key".to_string()))?;
+        let ephemeral = EphemeralSecret::random_from_rng(OsRng);
+        let ephemeral_public = PublicKey::from(&ephemeral);
+        let shared = ephemeral.diffie_hellman(&PublicKey::from(recipient));
+        if !shared.was_contributory() {
+            return Err(RelayError::EncryptionError(
+                "Recipient key is of low order".to_string(),
+            ));
+        }
+
+        let mut envelope = Self::new(
+            from_device,
+            to_device,
+            Vec::new(),
+            ephemeral_public.as_bytes().to_vec(),
+            Vec::new(),
+            message_type,
+        );
+        let key = derive_envelope_key(
+            shared.as_bytes(),
+            &envelope.ephemeral_pubkey,
+            recipient_key,
+            SEAL_INFO,
+        );
+        let cipher = XChaCha20Poly1305::new(key.as_slice().into());
+        let nonce = XChaCha20Poly1305::generate_nonce(&mut OsRng);
+        envelope.ciphertext = cipher
+            .encrypt(
+                &nonce,
+                Payload {
+                    msg: plaintext,
+                    aad: &envelope.header(),
+                },
+            )
+            .map_err(|e| RelayError::EncryptionError(e.to_string()))?;
+        envelope.nonce = nonce.to_vec();
+        envelope.signature = envelope.mac(sender, recipient_key)?;
+        Ok(envelope)
+    }
+
+    /// Check the envelope came from the device holding `sender_key` and
+    /// decrypt it with the recipient's static key
+    pub fn open(
+        &self,
+        recipient: &StaticKeypair,
+        sender_key: &[u8],
+    ) -> Result<Vec<u8>, RelayError> {
+        if self.is_expired() {
+            return Err(RelayError::MessageExpired);
+        }
+        let expected = self.mac(recipient, sender_key)?;
+        if expected.len() != self.signature.len()
+            || !bool::from(expected.as_slice().ct_eq(&self.signature))
+        {
+            return Err(RelayError::InvalidSignature);
+        }
+
+        let shared = recipient
+            .diffie_hellman(&self.ephemeral_pubkey)
+            .map_err(|e| RelayError::EncryptionError(e.to_string()))?;
+        let key = derive_envelope_key(
+            shared.as_slice(),
+            &self.ephemeral_pubkey,
+            recipient.public_key(),
+            SEAL_INFO,
+        );
+        if self.nonce.len() != NONCE_LEN {
+            return Err(RelayError::EncryptionError("Invalid nonce".to_string()));
+        }
+        XChaCha20Poly1305::new(key.as_slice().into())
+            .decrypt(
+                self.nonce.as_slice().into(),
+                Payload {
+                    msg: &self.ciphertext,
+                    aad: &self.header(),
+                },
+            )
+            .map_err(|_| RelayError::EncryptionError("Envelope failed to decrypt".to_string()))
+    }
+
+    /// Fields the relay routes by, bound to the ciphertext and signature
+    fn header(&self) -> Vec<u8> {
+        let mut header = Vec::new();
+        for field in [
+            self.id.as_bytes(),
+            self.from_device.as_bytes(),
+            self.to_device.as_bytes(),
+            self.message_type.as_bytes(),
+            &self.timestamp.to_be_bytes(),
+        ] {
+            header.extend_from_slice(&(field.len() as u32).to_be_bytes());
+            header.extend_from_slice(field);
+        }
+        header
+    }
+
+    /// HMAC over everything but the signature, keyed by the static keys of
+    /// the two devices; either side computes it with its own private key
+    fn mac(&self, own_key: &StaticKeypair, peer_key: &[u8]) -> Result<Vec<u8>, RelayError> {
+        let shared = own_key
+            .diffie_hellman(peer_key)
+            .map_err(|e| RelayError::EncryptionError(e.to_string()))?;
+        let key = derive_envelope_key(shared.as_slice(), &[], &[], SIGN_INFO);
+        let mut mac =
+            Hmac::<Sha256>::new_from_slice(key.as_slice()).expect("HMAC accepts any key length");
+        mac.update(&self.header());
+        for field in [&self.ephemeral_pubkey, &self.nonce, &self.ciphertext] {
+            mac.update(&(field.len() as u32).to_be_bytes());
+            mac.update(field);
+        }
+        Ok(mac.finalize().into_bytes().to_vec())
+    }
+}
+
+fn derive_envelope_key(
+    shared: &[u8],
+    ephemeral_key: &[u8],
+    recipient_key: &[u8],
+    info: &[u8],
+) -> Zeroizing<[u8; 32]> {
+    let salt = [ephemeral_key, recipient_key].concat();
+    let hk = Hkdf::<Sha256>::new(Some(&salt), shared);
+    let mut okm = Zeroizing::new([0u8; 32]);
+    hk.expand(info, okm.as_mut_slice())
+        .expect("32 bytes is a valid HKDF-SHA256 output length");
+    okm
+}
+
+/// A registered device's key and the bearer token it authenticates with
+#[derive(Debug, Clone)]
+struct Registration {
+    public_key_hash: String,
+    token: String,
+}
+
+impl Registration {
+    fn accepts(&self, token: &str) -> bool {
+        self.token.as_bytes().ct_eq(token.as_bytes()).into()
+    }
+}
+
+fn generate_device_token() -> String {
+    hex::encode(rand::random::<[u8; 32]>())
 }
 
 /// Pending message in relay queue
 #[derive(Debug, Clone)]
 struct PendingMessage {
     envelope: RelayEnvelope,
-    _received_at: u64,
+    received_at: u64,
 }
 
 /// In-memory relay server (for development/testing)
 /// Production would use a distributed store (Redis, etc.)
 pub struct BlindRelayServer {
+    config: RelayConfig,
     /// Pending messages per device
     pending: Arc<Mutex<HashMap<String, Vec<PendingMessage>>>>,
-    /// Registered devices (device_id -> public_key_hash)
-    devices: Arc<Mutex<HashMap<String, String>>>,
+    /// Registered devices
+    devices: Arc<Mutex<HashMap<String, Registration>>>,
+    /// Devices that deregistered; messages for them are refused until they
+    /// register again
+    deregistered: Arc<Mutex<HashSet<String>>>,
+    /// Accepted submission times per sender within the rate window
+    submissions: Arc<Mutex<HashMap<String, VecDeque<u64>>>>,
+    /// Activity counters per device
+    activity: Arc<Mutex<HashMap<String, DeviceStats>>>,
 }
 
 impl Default for BlindRelayServer {
@@ -136,52 +371,197 @@ impl Default for BlindRelayServer {
 }
 
 impl BlindRelayServer {
-    /// Create new relay server
+    /// Create new relay server with the default limits
     pub fn new() -> Self {
+        Self::with_config(RelayConfig::default())
+    }
+
+    /// Create new relay server enforcing `config`
+    pub fn with_config(config: RelayConfig) -> Self {
         Self {
+            config,
             pending: Arc::new(Mutex::new(HashMap::new())),
             devices: Arc::new(Mutex::new(HashMap::new())),
+            deregistered: Arc::new(Mutex::new(HashSet::new())),
+            submissions: Arc::new(Mutex::new(HashMap::new())),
+            activity: Arc::new(Mutex::new(HashMap::new())),
         }
     }
 
-    /// Register a device with the relay
+    pub fn config(&self) -> &RelayConfig {
+        &self.config
+    }
+
+    /// Register a device with the relay, returning the bearer token it
+    /// authenticates with from now on. Registering again issues a new token.
     pub fn register_device(
         &self,
         device_id: &str,
         public_key_hash: &str,
-    ) -> Result<(), RelayError> {
+    ) -> Result<String, RelayError> {
+        let token = generate_device_token();
         let mut devices = self
             .devices
             .lock()
             .map_err(|_| RelayError::EncryptionError("Mutex poisoned".to_string()))?;
-        devices.insert(device_id.to_string(), public_key_hash.to_string());
+        devices.insert(
+            device_id.to_string(),
+            Registration {
+                public_key_hash: public_key_hash.to_string(),
+                token: token.clone(),
+            },
+        );
+        if let Ok(mut deregistered) = self.deregistered.lock() {
+            deregistered.remove(device_id);
+        }
         log::info!("[relay] Registered device: {}", device_id);
-        Ok(())
+        Ok(token)
+    }
+
+    /// Check that `token` is the current token of a registered `device_id`
+    pub fn authenticate(&self, device_id: &str, token: &str) -> Result<(), RelayError> {
+        let devices = self
+            .devices
+            .lock()
+            .map_err(|_| RelayError::EncryptionError("Mutex poisoned".to_string()))?;
+        match devices.get(device_id) {
+            Some(registration) if registration.accepts(token) => Ok(()),
+            _ => Err(RelayError::Unauthorized),
+        }
+    }
+
+    /// Public key hash a device registered with
+    pub fn public_key_hash(&self, device_id: &str) -> Option<String> {
+        let devices = self.devices.lock().ok()?;
+        devices.get(device_id).map(|r| r.public_key_hash.clone())
+    }
+
+    /// Remove a device on its own request, purging the messages waiting for
+    /// it. Returns how many were purged.
+    pub fn deregister_device(&self, device_id: &str, token: &str) -> Result<usize, RelayError> {
+        {
+            let mut devices = self
+                .devices
+                .lock()
+                .map_err(|_| RelayError::EncryptionError("Mutex poisoned".to_string()))?;
+            if !devices.get(device_id).is_some_and(|r| r.accepts(token)) {
+                return Err(RelayError::Unauthorized);
+            }
+            devices.remove(device_id);
+        }
+        Ok(self.forget_device(device_id))
+    }
+
+    /// Replace a device's public key hash and token in one step, e.g. after
+    /// re-pairing. The old token stops working; pending messages are kept.
+    pub fn rotate_key(
+        &self,
+        device_id: &str,
+        token: &str,
+        public_key_hash: &str,
+    ) -> Result<String, RelayError> {
+        let new_token = generate_device_token();
+        {
+            let mut devices = self
+                .devices
+                .lock()
+                .map_err(|_| RelayError::EncryptionError("Mutex poisoned".to_string()))?;
+            let registration = devices
+                .get_mut(device_id)
+                .filter(|r| r.accepts(token))
+                .ok_or(RelayError::Unauthorized)?;
+            registration.public_key_hash = public_key_hash.to_string();
+            registration.token = new_token.clone();
+        }
+        self.record(device_id, |s| s.key_rotations += 1);
+        log::info!("[relay] Rotated key of device: {}", device_id);
+        Ok(new_token)
     }
 
-    /// Unregister a device
+    /// Unregister a device without its token
     pub fn unregister_device(&self, device_id: &str) {
         if let Ok(mut devices) = self.devices.lock() {
             devices.remove(device_id);
         }
+        self.forget_device(device_id);
+    }
 
-        // Also clear pending messages
-        if let Ok(mut pending) = self.pending.lock() {
-            pending.remove(device_id);
+    /// Drop a removed device's queue and refuse messages for it from now on
+    fn forget_device(&self, device_id: &str) -> usize {
+        let purged = self
+            .pending
+            .lock()
+            .map(|mut pending| pending.remove(device_id).map_or(0, |q| q.len()))
+            .unwrap_or(0);
+        if let Ok(mut deregistered) = self.deregistered.lock() {
+            deregistered.insert(device_id.to_string());
         }
+        log::info!(
+            "[relay] Unregistered device: {} ({} pending messages purged)",
+            device_id,
+            purged
+        );
+        purged
+    }
 
-        log::info!("[relay] Unregistered device: {}", device_id);
+    /// Bump the counters of `device_id`
+    fn record(&self, device_id: &str, update: impl FnOnce(&mut DeviceStats)) {
+        if let Ok(mut activity) = self.activity.lock() {
+            update(activity.entry(device_id.to_string()).or_default());
+        }
+    }
+
+    /// Whether a message received at `received_at` has outlived its TTL or
+    /// the sender's expiry
+    fn is_stale(&self, message: &PendingMessage, now: u64) -> bool {
+        now.saturating_sub(message.received_at) > self.config.message_ttl_secs
+            || message.envelope.is_expired()
+    }
+
+    /// Drop stale messages from a recipient's queue; returns how many
+    fn prune(&self, device_id: &str, queue: &mut Vec<PendingMessage>, now: u64) -> usize {
+        let before = queue.len();
+        queue.retain(|m| !self.is_stale(m, now));
+        let expired = before - queue.len();
+        if expired > 0 {
+            self.record(device_id, |s| s.expired += expired as u64);
+        }
+        expired
     }
 
     /// Submit a message for relay
     pub fn submit_message(&self, envelope: RelayEnvelope) -> Result<String, RelayError> {
+        let sender = envelope.from_device.clone();
+        let result = self.try_submit(envelope, now_secs());
+        match &result {
+            Ok(_) => self.record(&sender, |s| s.submitted += 1),
+            Err(e) => {
+                log::warn!("[relay] Rejected message from {}: {}", sender, e);
+                self.record(&sender, |s| s.rejected += 1);
+            }
+        }
+        result
+    }
+
+    fn try_submit(&self, envelope: RelayEnvelope, now: u64) -> Result<String, RelayError> {
         // Validate
-        envelope.validate_size()?;
+        if envelope.size_bytes() > self.config.max_envelope_bytes {
+            return Err(RelayError::MessageTooLarge);
+        }
 
         if envelope.is_expired() {
             return Err(RelayError::MessageExpired);
         }
 
+        let deregistered = self
+            .deregistered
+            .lock()
+            .map_err(|_| RelayError::EncryptionError("Mutex poisoned".to_string()))?
+            .contains(&envelope.to_device);
+        if deregistered {
+            return Err(RelayError::RecipientDeregistered);
+        }
+
         // Check recipient exists
         {
             let devices = self
@@ -197,35 +577,47 @@ impl BlindRelayServer {
             }
         }
 
+        let mut submissions = self
+            .submissions
+            .lock()
+            .map_err(|_| RelayError::EncryptionError("Mutex poisoned".to_string()))?;
+        let recent = submissions.entry(envelope.from_device.clone()).or_default();
+        while recent
+            .front()
+            .is_some_and(|&at| now.saturating_sub(at) >= RATE_WINDOW_SECS)
+        {
+            recent.pop_front();
+        }
+        if recent.len() >= self.config.max_submissions_per_minute as usize {
+            return Err(RelayError::RateLimited);
+        }
+
         // Add to pending queue
         let msg_id = envelope.id.clone();
-        let now = SystemTime::now()
-            .duration_since(UNIX_EPOCH)
-            .unwrap_or(std::time::Duration::from_secs(0))
-            .as_secs();
-
         {
             let mut pending = self
                 .pending
                 .lock()
                 .map_err(|_| RelayError::EncryptionError("Mutex poisoned".to_string()))?;
-            let queue = pending.entry(envelope.to_device.clone()).or_default();
+            let recipient = envelope.to_device.clone();
+            let queue = pending.entry(recipient.clone()).or_default();
 
             // Check limits
-            if queue.len() >= MAX_PENDING_PER_DEVICE {
+            if queue.len() >= self.config.max_pending_per_recipient {
                 // Prune expired messages first
-                queue.retain(|m| !m.envelope.is_expired());
+                self.prune(&recipient, queue, now);
 
-                if queue.len() >= MAX_PENDING_PER_DEVICE {
+                if queue.len() >= self.config.max_pending_per_recipient {
                     return Err(RelayError::TooManyPending);
                 }
             }
 
             queue.push(PendingMessage {
                 envelope,
-                _received_at: now,
+                received_at: now,
             });
         }
+        recent.push_back(now);
 
         log::info!("[relay] Message {} queued for delivery", msg_id);
         Ok(msg_id)
@@ -240,11 +632,12 @@ impl BlindRelayServer {
 
         if let Some(queue) = pending.get_mut(device_id) {
             // Remove expired messages
-            queue.retain(|m| !m.envelope.is_expired());
+            self.prune(device_id, queue, now_secs());
 
             // Take up to limit messages
             let count = queue.len().min(limit);
             let messages: Vec<RelayEnvelope> = queue.drain(..count).map(|m| m.envelope).collect();
+            self.record(device_id, |s| s.delivered += messages.len() as u64);
 
             log::info!(
                 "[relay] Delivered {} messages to {}",
@@ -268,16 +661,30 @@ impl BlindRelayServer {
 
     /// Cleanup expired messages
     pub fn cleanup_expired(&self) -> usize {
-        let mut pending = match self.pending.lock() {
-            Ok(g) => g,
-            Err(_) => return 0,
-        };
+        self.cleanup_expired_at(now_secs())
+    }
+
+    /// Cleanup messages that are expired at `now` (unix seconds)
+    pub fn cleanup_expired_at(&self, now: u64) -> usize {
         let mut cleaned = 0;
+        {
+            let mut pending = match self.pending.lock() {
+                Ok(g) => g,
+                Err(_) => return 0,
+            };
+            for (device_id, queue) in pending.iter_mut() {
+                cleaned += self.prune(device_id, queue, now);
+            }
+            pending.retain(|_, queue| !queue.is_empty());
+        }
 
-        for queue in pending.values_mut() {
-            let before = queue.len();
-            queue.retain(|m| !m.envelope.is_expired());
-            cleaned += before - queue.len();
+        // Rate windows that have run out hold nothing worth keeping
+        if let Ok(mut submissions) = self.submissions.lock() {
+            submissions.retain(|_, recent| {
+                recent
+                    .back()
+                    .is_some_and(|&at| now.saturating_sub(at) < RATE_WINDOW_SECS)
+            });
         }
 
         if cleaned > 0 {
@@ -291,42 +698,79 @@ impl BlindRelayServer {
     pub fn stats(&self) -> RelayStats {
         let pending = match self.pending.lock() {
             Ok(g) => g,
-            Err(_) => {
-                return RelayStats {
-                    registered_devices: 0,
-                    total_pending_messages: 0,
-                    active_queues: 0,
-                }
-            }
+            Err(_) => return RelayStats::default(),
         };
 
         let devices = match self.devices.lock() {
             Ok(g) => g,
-            Err(_) => {
-                return RelayStats {
-                    registered_devices: 0,
-                    total_pending_messages: 0,
-                    active_queues: 0,
-                }
-            }
+            Err(_) => return RelayStats::default(),
+        };
+
+        let activity = match self.activity.lock() {
+            Ok(g) => g,
+            Err(_) => return RelayStats::default(),
+        };
+
+        let deregistered = match self.deregistered.lock() {
+            Ok(g) => g,
+            Err(_) => return RelayStats::default(),
         };
 
         let total_pending: usize = pending.values().map(|q| q.len()).sum();
+        let mut per_device: BTreeMap<String, DeviceStats> = activity
+            .iter()
+            .map(|(id, stats)| (id.clone(), stats.clone()))
+            .collect();
+        for (id, queue) in pending.iter() {
+            per_device.entry(id.clone()).or_default().pending = queue.len();
+        }
+        for id in devices.keys() {
+            per_device.entry(id.clone()).or_default().registered = true;
+        }
 
         RelayStats {
             registered_devices: devices.len(),
+            deregistered_devices: deregistered.len(),
             total_pending_messages: total_pending,
-            active_queues: pending.len(),
+            active_queues: pending.values().filter(|q| !q.is_empty()).count(),
+            expired_messages: per_device.values().map(|s| s.expired).sum(),
+            devices: per_device,
         }
     }
 }
 
 /// Relay server statistics
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct RelayStats {
+    /// Devices with an active registration
     pub registered_devices: usize,
+    /// Devices that deregistered and haven't registered again
+    pub deregistered_devices: usize,
     pub total_pending_messages: usize,
     pub active_queues: usize,
+    /// Messages dropped unread because they outlived the TTL
+    pub expired_messages: u64,
+    /// Counters per device id, for senders and recipients alike
+    pub devices: BTreeMap<String, DeviceStats>,
+}
+
+/// What one device did on the relay since it started
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DeviceStats {
+    /// Whether the device currently holds a registration
+    pub registered: bool,
+    /// Messages waiting for the device
+    pub pending: usize,
+    /// Messages the device sent that were queued
+    pub submitted: u64,
+    /// Messages the device sent that were refused
+    pub rejected: u64,
+    /// Messages the device fetched
+    pub delivered: u64,
+    /// Messages for the device that expired before it fetched them
+    pub expired: u64,
+    /// Times the device replaced its key
+    pub key_rotations: u64,
 }
 
 /// Client for connecting to a blind relay server
@@ -386,6 +830,94 @@ impl RelayClient {
         }
     }
 
+    /// Token from a registration made earlier, so a restarted client needn't
+    /// register again
+    pub fn with_auth_token(mut self, token: &str) -> Self {
+        self.auth_token = Some(token.to_string());
+        self
+    }
+
+    /// Token the relay issued at the last registration or key rotation
+    pub fn auth_token(&self) -> Option<&str> {
+        self.auth_token.as_deref()
+    }
+
+    /// Remove this device from the relay, dropping the messages waiting for
+    /// it. Returns how many were dropped.
+    pub async fn deregister(&mut self) -> Result<usize, RelayError> {
+        let token = self.auth_token.as_ref().ok_or(RelayError::Unauthorized)?;
+        let url = format!("{}/register", self.relay_url);
+
+        let client = reqwest::Client::new();
+        let response = client
+            .delete(&url)
+            .header("Authorization", format!("Bearer {}", token))
+            .json(&serde_json::json!({ "device_id": self.device_id }))
+            .send()
+            .await
+            .map_err(|e| RelayError::NetworkError(e.to_string()))?;
+
+        if response.status() == reqwest::StatusCode::UNAUTHORIZED {
+            return Err(RelayError::Unauthorized);
+        }
+        if !response.status().is_success() {
+            return Err(RelayError::NetworkError(format!(
+                "Deregistration failed: {}",
+                response.status()
+            )));
+        }
+        let result: serde_json::Value = response
+            .json()
+            .await
+            .map_err(|e| RelayError::NetworkError(e.to_string()))?;
+
+        self.auth_token = None;
+        log::info!("[relay_client] Deregistered from relay server");
+        Ok(result.get("purged").and_then(|p| p.as_u64()).unwrap_or(0) as usize)
+    }
+
+    /// Replace the public key hash the relay knows this device by, e.g.
+    /// after re-pairing. Messages already waiting are kept, and the client
+    /// switches to the new token the relay issues.
+    pub async fn rotate_key(&mut self, public_key_hash: &str) -> Result<(), RelayError> {
+        let token = self.auth_token.as_ref().ok_or(RelayError::Unauthorized)?;
+        let url = format!("{}/rotate_key", self.relay_url);
+
+        let client = reqwest::Client::new();
+        let response = client
+            .post(&url)
+            .header("Authorization", format!("Bearer {}", token))
+            .json(&serde_json::json!({
+                "device_id": self.device_id,
+                "public_key_hash": public_key_hash,
+            }))
+            .send()
+            .await
+            .map_err(|e| RelayError::NetworkError(e.to_string()))?;
+
+        if response.status() == reqwest::StatusCode::UNAUTHORIZED {
+            return Err(RelayError::Unauthorized);
+        }
+        if !response.status().is_success() {
+            return Err(RelayError::NetworkError(format!(
+                "Key rotation failed: {}",
+                response.status()
+            )));
+        }
+        let result: serde_json::Value = response
+            .json()
+            .await
+            .map_err(|e| RelayError::NetworkError(e.to_string()))?;
+        let token = result
+            .get("token")
+            .and_then(|t| t.as_str())
+            .ok_or_else(|| RelayError::NetworkError("Relay returned no token".to_string()))?;
+
+        self.auth_token = Some(token.to_string());
+        log::info!("[relay_client] Rotated key with relay server");
+        Ok(())
+    }
+
     /// Send message via relay
     pub async fn send(&self, envelope: RelayEnvelope) -> Result<String, RelayError> {
         let url = format!("{}/send", self.relay_url);
@@ -579,4 +1111,115 @@ mod tests {
         assert_eq!(stats.registered_devices, 1);
         assert_eq!(stats.total_pending_messages, 0);
     }
+
+    fn envelope(from: &str, to: &str) -> RelayEnvelope {
+        RelayEnvelope::new(from, to, vec![1, 2, 3], vec![], vec![], "test")
+    }
+
+    #[test]
+    fn test_rate_limit_per_sender() {
+        let server = BlindRelayServer::with_config(RelayConfig {
+            max_submissions_per_minute: 2,
+            ..RelayConfig::default()
+        });
+
+        server.submit_message(envelope("a", "b")).unwrap();
+        server.submit_message(envelope("a", "b")).unwrap();
+        assert!(matches!(
+            server.submit_message(envelope("a", "b")),
+            Err(RelayError::RateLimited)
+        ));
+        server.submit_message(envelope("c", "b")).unwrap();
+
+        let stats = server.stats();
+        assert_eq!(stats.devices["a"].submitted, 2);
+        assert_eq!(stats.devices["a"].rejected, 1);
+        assert_eq!(stats.devices["b"].pending, 3);
+    }
+
+    #[test]
+    fn test_ttl_expiry_is_counted() {
+        let server = BlindRelayServer::with_config(RelayConfig {
+            message_ttl_secs: 60,
+            ..RelayConfig::default()
+        });
+        server.submit_message(envelope("a", "b")).unwrap();
+        server.submit_message(envelope("a", "c")).unwrap();
+
+        let now = now_secs();
+        assert_eq!(server.cleanup_expired_at(now + 30), 0);
+        assert_eq!(server.cleanup_expired_at(now + 61), 2);
+        assert_eq!(server.pending_count("b"), 0);
+
+        let stats = server.stats();
+        assert_eq!(stats.expired_messages, 2);
+        assert_eq!(stats.devices["c"].expired, 1);
+        assert_eq!(stats.active_queues, 0);
+    }
+
+    #[test]
+    fn test_registering_again_lifts_deregistration() {
+        let server = BlindRelayServer::new();
+        let token = server.register_device("phone", "hash_a").unwrap();
+        server.submit_message(envelope("a", "phone")).unwrap();
+
+        assert!(matches!(
+            server.deregister_device("phone", "wrong"),
+            Err(RelayError::Unauthorized)
+        ));
+        assert_eq!(server.deregister_device("phone", &token).unwrap(), 1);
+        assert!(matches!(
+            server.submit_message(envelope("a", "phone")),
+            Err(RelayError::RecipientDeregistered)
+        ));
+
+        let token = server.register_device("phone", "hash_b").unwrap();
+        server.authenticate("phone", &token).unwrap();
+        server.submit_message(envelope("a", "phone")).unwrap();
+        assert_eq!(server.public_key_hash("phone").as_deref(), Some("hash_b"));
+        assert_eq!(server.stats().deregistered_devices, 0);
+    }
+
+    #[test]
+    fn test_sealed_envelope_opens_only_for_its_devices() {
+        let alice = StaticKeypair::generate().unwrap();
+        let bob = StaticKeypair::generate().unwrap();
+        let mallory = StaticKeypair::generate().unwrap();
+
+        let sealed = RelayEnvelope::seal(
+            &alice,
+            "alice",
+            "bob",
+            bob.public_key(),
+            "sync_delta",
+            b"the deltas",
+        )
+        .unwrap();
+        assert_ne!(sealed.ciphertext, b"the deltas".to_vec());
+        assert_eq!(
+            sealed.open(&bob, alice.public_key()).unwrap(),
+            b"the deltas".to_vec()
+        );
+
+        // Nobody else can open it, and bob can't be fooled about the sender
+        assert!(sealed.open(&mallory, alice.public_key()).is_err());
+        assert!(matches!(
+            sealed.open(&bob, mallory.public_key()),
+            Err(RelayError::InvalidSignature)
+        ));
+
+        // The routing fields are bound to the payload
+        let mut rerouted = sealed.clone();
+        rerouted.from_device = "carol".to_string();
+        assert!(matches!(
+            rerouted.open(&bob, alice.public_key()),
+            Err(RelayError::InvalidSignature)
+        ));
+        let mut tampered = sealed;
+        tampered.ciphertext[0] ^= 1;
+        assert!(matches!(
+            tampered.open(&bob, alice.public_key()),
+            Err(RelayError::InvalidSignature)
+        ));
+    }
 }
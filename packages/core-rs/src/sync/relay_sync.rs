@@ -0,0 +1,715 @@
+//! Relay Sync
+//!
+//! Carries a sync session through a blind relay when the peer can't be
+//! reached directly, e.g. because the two devices are on different
+//! networks. The sender gathers the deltas it would have pushed, seals them
+//! to the peer's pinned transport key and submits them as envelopes; the
+//! peer fetches them whenever it next polls the relay, opens them and
+//! applies the deltas exactly as a direct sync would, conflicts included.
+//!
+//! A session bigger than the relay's envelope limit is split into chunks.
+//! Chunks are kept in `sync_relay_chunk` until the whole session has
+//! arrived, so a session can span several polls. Both sides record relayed
+//! syncs in `sync_history` with the `relay` transport, and the bytes they
+//! moved in `sync_transfer_stats`.
+//!
+//! The relay drops what isn't fetched in time, so a push proves nothing
+//! about delivery. The sender keeps each session in `sync_relay_outbox` with
+//! the time its deltas were gathered, and the receiver answers every session
+//! it applied with an acknowledgement envelope. Only an acknowledged session
+//! moves the peer's watermark; until then every push gathers again from the
+//! last acknowledged one, so a session lost on the way is sent again. Each
+//! session is applied in its own transaction, and one that fails is recorded
+//! and dropped without holding up the others.
+//!
+//! Talking to the relay is async and touching the vault is not, so each
+//! side is split into steps that never hold a connection across an await:
+//! [`prepare_relay_session`], [`send_relay_session`], [`record_relay_push`]
+//! on the sender and [`fetch_relay_envelopes`], [`apply_relay_envelopes`],
+//! [`send_relay_acks`] on the receiver.
+
+use crate::db::{get_setting, set_setting, DbError};
+use crate::space::get_all_spaces;
+use crate::sync::engine::SyncAgent;
+use crate::sync::error::SyncError;
+use crate::sync::history::{SyncHistory, SyncRecordParams};
+use crate::sync::models::{SyncDelta, SyncTransport};
+use crate::sync::relay::{RelayClient, RelayConfig, RelayEnvelope, RelayError};
+use crate::sync::secure_channel::{ChannelError, StaticKeypair};
+use crate::sync::transfer_stats::SyncTransferSession;
+use base64::Engine;
+use rusqlite::{params, Connection, OptionalExtension};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use thiserror::Error;
+
+/// `message_type` of the envelopes a relayed session travels in
+pub const RELAY_SYNC_MESSAGE: &str = "sync_delta";
+/// `message_type` of the envelope confirming a session was applied
+pub const RELAY_ACK_MESSAGE: &str = "sync_ack";
+
+const RELAY_URL_SETTING: &str = "sync_relay_url";
+const RELAY_TOKEN_SETTING: &str = "sync_relay_token";
+
+/// Envelope bytes besides the chunk's data: the AEAD tag, ephemeral key,
+/// nonce and signature, and the JSON around the data
+const CHUNK_OVERHEAD_BYTES: usize = 512;
+
+/// Envelopes fetched per request while draining the relay
+const FETCH_LIMIT: usize = 50;
+
+#[derive(Error, Debug)]
+pub enum RelaySyncError {
+    #[error("Relay error: {0}")]
+    Relay(#[from] RelayError),
+
+    #[error("Sync error: {0}")]
+    Sync(#[from] SyncError),
+
+    #[error("Rusqlite error: {0}")]
+    Rusqlite(#[from] rusqlite::Error),
+
+    #[error("Database error: {0}")]
+    Db(#[from] DbError),
+
+    #[error("Serialization error: {0}")]
+    Serialization(#[from] serde_json::Error),
+
+    #[error("Transport key error: {0}")]
+    Key(#[from] ChannelError),
+
+    #[error("Device {0} is not trusted; pair and confirm it first")]
+    UntrustedDevice(String),
+
+    #[error("Device {0} has no pinned transport key; pair it again")]
+    KeyNotPinned(String),
+
+    #[error("No relay is configured")]
+    NotConfigured,
+
+    #[error("Invalid relay message: {0}")]
+    InvalidMessage(String),
+}
+
+/// One space's deltas within a relayed session
+#[derive(Debug, Serialize, Deserialize)]
+struct RelayBatch {
+    space_id: String,
+    deltas: Vec<SyncDelta>,
+}
+
+/// What each envelope of a session holds once opened. `data` is a slice of
+/// the base64 encoded session.
+#[derive(Debug, Serialize, Deserialize)]
+struct RelayChunk {
+    session_id: String,
+    index: u32,
+    count: u32,
+    data: String,
+}
+
+/// Sent back once a session has been applied
+#[derive(Debug, Serialize, Deserialize)]
+struct RelayAck {
+    session_id: String,
+}
+
+/// A session sealed for one peer and ready to submit
+#[derive(Debug, Clone)]
+pub struct RelayOutbox {
+    pub session_id: String,
+    pub peer_device_id: String,
+    /// Deltas changed after this were left for a later session
+    pub gathered_at: i64,
+    pub envelopes: Vec<RelayEnvelope>,
+    /// Deltas sent per space
+    pub spaces: Vec<(String, u32)>,
+    /// Bytes sent per space and entity type, saved with the push
+    pub transfer: SyncTransferSession,
+}
+
+impl RelayOutbox {
+    /// Whether there was nothing to send
+    pub fn is_empty(&self) -> bool {
+        self.envelopes.is_empty()
+    }
+
+    pub fn delta_count(&self) -> u32 {
+        self.spaces.iter().map(|(_, count)| count).sum()
+    }
+}
+
+/// What one poll of the relay received
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RelayPullReport {
+    /// Sessions that arrived in full and were applied
+    pub sessions: usize,
+    pub entities_pulled: u32,
+    pub conflicts: u32,
+    /// Envelopes that failed to open or weren't meant for this device
+    pub rejected: usize,
+    /// Sessions that arrived in full but couldn't be applied; the sender
+    /// gets no acknowledgement and sends their changes again
+    pub failed: usize,
+    /// Sessions of ours the peer confirmed it applied
+    pub acknowledged: usize,
+    /// Acknowledgements of the applied sessions, for [`send_relay_acks`]
+    #[serde(skip)]
+    pub acks: Vec<RelayEnvelope>,
+}
+
+/// URL of the relay this device syncs through, if one is set up
+pub fn get_relay_url(conn: &Connection) -> Result<Option<String>, RelaySyncError> {
+    Ok(get_setting(conn, RELAY_URL_SETTING)?)
+}
+
+/// Client for the relay this device syncs through, if one is set up
+pub fn get_relay_client(
+    conn: &Connection,
+    device_id: &str,
+) -> Result<Option<RelayClient>, RelaySyncError> {
+    let Some(url) = get_relay_url(conn)? else {
+        return Ok(None);
+    };
+    let client = RelayClient::new(device_id, &url);
+    Ok(Some(match get_setting(conn, RELAY_TOKEN_SETTING)? {
+        Some(token) => client.with_auth_token(&token),
+        None => client,
+    }))
+}
+
+/// Remember the relay `client` registered with, and its token
+pub fn save_relay_registration(
+    conn: &Connection,
+    relay_url: &str,
+    client: &RelayClient,
+) -> Result<(), RelaySyncError> {
+    let token = client.auth_token().ok_or(RelayError::Unauthorized)?;
+    set_setting(
+        conn,
+        RELAY_URL_SETTING,
+        relay_url,
+        Some("Relay used when a peer can't be reached directly"),
+    )?;
+    set_setting(
+        conn,
+        RELAY_TOKEN_SETTING,
+        token,
+        Some("Token issued by the sync relay"),
+    )?;
+    Ok(())
+}
+
+/// Stop syncing through a relay
+pub fn clear_relay_registration(conn: &Connection) -> Result<(), RelaySyncError> {
+    conn.execute(
+        "DELETE FROM settings WHERE key IN (?1, ?2)",
+        params![RELAY_URL_SETTING, RELAY_TOKEN_SETTING],
+    )?;
+    Ok(())
+}
+
+/// The key hash a device registers with, so the relay never sees the key
+pub fn relay_key_hash(static_key: &StaticKeypair) -> String {
+    hex::encode(Sha256::digest(static_key.public_key()))
+}
+
+/// Gather the deltas `peer_device_id` hasn't been sent yet in every space
+/// and seal them into envelopes of at most `max_envelope_bytes`
+pub fn prepare_relay_session(
+    conn: &Connection,
+    agent: &SyncAgent,
+    static_key: &StaticKeypair,
+    peer_device_id: &str,
+    max_envelope_bytes: usize,
+) -> Result<RelayOutbox, RelaySyncError> {
+    let peer_key = pinned_key(conn, peer_device_id)?;
+    let peer = agent
+        .get_devices(conn)?
+        .into_iter()
+        .find(|d| d.device_id == peer_device_id)
+        .ok_or_else(|| RelaySyncError::UntrustedDevice(peer_device_id.to_string()))?;
+
+    let gathered_at = chrono::Utc::now().timestamp();
+    let acked = acked_watermark(conn, peer_device_id)?;
+    let mut batches = Vec::new();
+    for space in get_all_spaces(conn, true)? {
+        let space_id = space.id.to_string();
+        let since = last_direct_push_time(conn, &space_id, peer_device_id)?.max(acked);
+        let deltas = agent.get_deltas_for_peer(conn, space.id, since, &peer)?;
+        if !deltas.is_empty() {
+            batches.push(RelayBatch { space_id, deltas });
+        }
+    }
+
+    let session_id = ulid::Ulid::new().to_string();
+    let spaces = batches
+        .iter()
+        .map(|b| (b.space_id.clone(), b.deltas.len() as u32))
+        .collect();
+    let mut transfer = SyncTransferSession::new(&session_id, peer_device_id, SyncTransport::Relay);
+    if batches.is_empty() {
+        return Ok(RelayOutbox {
+            session_id,
+            peer_device_id: peer_device_id.to_string(),
+            gathered_at,
+            envelopes: Vec::new(),
+            spaces,
+            transfer,
+        });
+    }
+
+    let chunk_len = max_envelope_bytes.saturating_sub(CHUNK_OVERHEAD_BYTES);
+    if chunk_len == 0 {
+        return Err(RelaySyncError::InvalidMessage(format!(
+            "{} byte envelopes can't hold a chunk",
+            max_envelope_bytes
+        )));
+    }
+    let encoded = base64::engine::general_purpose::STANDARD.encode(serde_json::to_vec(&batches)?);
+    for batch in &batches {
+        transfer.record_sent(&batch.space_id, &batch.deltas)?;
+    }
+    // Base64 is ASCII, so any byte offset is a character boundary
+    let chunks: Vec<&[u8]> = encoded.as_bytes().chunks(chunk_len).collect();
+    let own_id = agent.get_device_info().device_id;
+    let envelopes = chunks
+        .iter()
+        .enumerate()
+        .map(|(index, data)| {
+            let chunk = RelayChunk {
+                session_id: session_id.clone(),
+                index: index as u32,
+                count: chunks.len() as u32,
+                data: String::from_utf8_lossy(data).into_owned(),
+            };
+            Ok(RelayEnvelope::seal(
+                static_key,
+                &own_id,
+                peer_device_id,
+                &peer_key,
+                RELAY_SYNC_MESSAGE,
+                &serde_json::to_vec(&chunk)?,
+            )?)
+        })
+        .collect::<Result<Vec<_>, RelaySyncError>>()?;
+    log::info!(
+        "[relay_sync] Sealed session {} for {} in {} envelopes",
+        session_id,
+        peer_device_id,
+        envelopes.len()
+    );
+
+    Ok(RelayOutbox {
+        session_id,
+        peer_device_id: peer_device_id.to_string(),
+        gathered_at,
+        envelopes,
+        spaces,
+        transfer,
+    })
+}
+
+/// Submit every envelope of a session to the relay
+pub async fn send_relay_session(
+    client: &RelayClient,
+    outbox: &RelayOutbox,
+) -> Result<(), RelaySyncError> {
+    for envelope in &outbox.envelopes {
+        client.send(envelope.clone()).await?;
+    }
+    Ok(())
+}
+
+/// Record a relayed push in `sync_history`, one row per space it carried,
+/// and the bytes it sent once it went through. A submitted session waits in
+/// `sync_relay_outbox` for the peer's acknowledgement.
+pub fn record_relay_push(
+    conn: &Connection,
+    outbox: &RelayOutbox,
+    error: Option<&str>,
+) -> Result<(), RelaySyncError> {
+    if error.is_none() && !outbox.is_empty() {
+        let now = chrono::Utc::now().timestamp();
+        outbox.transfer.save(conn)?;
+        conn.execute(
+            "INSERT INTO sync_relay_outbox (session_id, peer_device_id, gathered_at, sent_at)
+             VALUES (?1, ?2, ?3, ?4)",
+            params![
+                outbox.session_id,
+                outbox.peer_device_id,
+                outbox.gathered_at,
+                now
+            ],
+        )?;
+        // The relay has dropped sessions nobody fetched in time, and an
+        // older acknowledged session no longer sets the watermark
+        conn.execute(
+            "DELETE FROM sync_relay_outbox
+             WHERE peer_device_id = ?1 AND (
+                (acked_at IS NULL AND sent_at < ?2)
+                OR gathered_at < (SELECT MAX(gathered_at) FROM sync_relay_outbox
+                                  WHERE peer_device_id = ?1 AND acked_at IS NOT NULL))",
+            params![
+                outbox.peer_device_id,
+                now - RelayConfig::default().message_ttl_secs as i64
+            ],
+        )?;
+    }
+    for (space_id, count) in &outbox.spaces {
+        SyncHistory::record(
+            conn,
+            SyncRecordParams {
+                device_id: &outbox.peer_device_id,
+                space_id,
+                direction: "push",
+                entities_pushed: *count,
+                entities_pulled: 0,
+                conflicts: 0,
+                success: error.is_none(),
+                error_message: error,
+                transport: SyncTransport::Relay,
+            },
+        )?;
+    }
+    Ok(())
+}
+
+/// Fetch everything waiting for this device on the relay
+pub async fn fetch_relay_envelopes(
+    client: &RelayClient,
+) -> Result<Vec<RelayEnvelope>, RelaySyncError> {
+    let mut envelopes = Vec::new();
+    while client.check_pending().await? > 0 {
+        let fetched = client.fetch(FETCH_LIMIT).await?;
+        if fetched.is_empty() {
+            break;
+        }
+        envelopes.extend(fetched);
+    }
+    Ok(envelopes)
+}
+
+/// Submit the acknowledgements of the sessions a poll applied
+pub async fn send_relay_acks(
+    client: &RelayClient,
+    report: &RelayPullReport,
+) -> Result<(), RelaySyncError> {
+    for ack in &report.acks {
+        client.send(ack.clone()).await?;
+    }
+    Ok(())
+}
+
+/// Open fetched envelopes, record the acknowledgements among them and apply
+/// every session that is now complete, each in its own transaction.
+/// Envelopes that don't open are counted and skipped; the rest of their
+/// session never completes and ages out. A session that fails to apply is
+/// recorded as a failed pull and dropped.
+pub fn apply_relay_envelopes(
+    conn: &mut Connection,
+    agent: &SyncAgent,
+    static_key: &StaticKeypair,
+    dek: &[u8],
+    envelopes: Vec<RelayEnvelope>,
+) -> Result<RelayPullReport, RelaySyncError> {
+    let own_id = agent.get_device_info().device_id;
+    let now = chrono::Utc::now().timestamp();
+    let mut report = RelayPullReport::default();
+
+    for envelope in envelopes {
+        let result = if envelope.message_type == RELAY_ACK_MESSAGE {
+            record_ack(conn, &own_id, static_key, &envelope, now).map(|acked| {
+                report.acknowledged += usize::from(acked);
+            })
+        } else {
+            store_chunk(conn, &own_id, static_key, &envelope, now)
+        };
+        if let Err(e) = result {
+            log::warn!(
+                "[relay_sync] Rejected envelope {} from {}: {}",
+                envelope.id,
+                envelope.from_device,
+                e
+            );
+            report.rejected += 1;
+        }
+    }
+
+    // The relay has dropped whatever of a session hasn't arrived by now
+    conn.execute(
+        "DELETE FROM sync_relay_chunk WHERE received_at < ?1",
+        [now - RelayConfig::default().message_ttl_secs as i64],
+    )?;
+
+    for (from_device, session_id) in complete_sessions(conn)? {
+        let batches = assemble_session(conn, &from_device, &session_id);
+        let space_ids: Vec<String> = batches
+            .iter()
+            .flatten()
+            .map(|batch| batch.space_id.clone())
+            .collect();
+        let applied = batches.and_then(|batches| {
+            apply_session(conn, agent, dek, &from_device, &session_id, batches)
+        });
+        conn.execute(
+            "DELETE FROM sync_relay_chunk WHERE from_device = ?1 AND session_id = ?2",
+            params![from_device, session_id],
+        )?;
+        match applied {
+            Ok((pulled, conflicts)) => {
+                report.entities_pulled += pulled;
+                report.conflicts += conflicts;
+                report.sessions += 1;
+                report.acks.push(seal_ack(
+                    conn,
+                    &own_id,
+                    static_key,
+                    &from_device,
+                    &session_id,
+                )?);
+                log::info!(
+                    "[relay_sync] Applied session {} from {}",
+                    session_id,
+                    from_device
+                );
+            }
+            Err(e) => {
+                log::warn!(
+                    "[relay_sync] Dropped session {} from {}: {}",
+                    session_id,
+                    from_device,
+                    e
+                );
+                let error = e.to_string();
+                for space_id in &space_ids {
+                    SyncHistory::record(
+                        conn,
+                        SyncRecordParams {
+                            device_id: &from_device,
+                            space_id,
+                            direction: "pull",
+                            entities_pushed: 0,
+                            entities_pulled: 0,
+                            conflicts: 0,
+                            success: false,
+                            error_message: Some(&error),
+                            transport: SyncTransport::Relay,
+                        },
+                    )?;
+                }
+                report.failed += 1;
+            }
+        }
+    }
+    Ok(report)
+}
+
+/// Apply one complete session in a single transaction and record it.
+/// Returns the deltas pulled and the conflicts found.
+fn apply_session(
+    conn: &mut Connection,
+    agent: &SyncAgent,
+    dek: &[u8],
+    from_device: &str,
+    session_id: &str,
+    batches: Vec<RelayBatch>,
+) -> Result<(u32, u32), RelaySyncError> {
+    let mut transfer = SyncTransferSession::new(session_id, from_device, SyncTransport::Relay);
+    let mut spaces = Vec::with_capacity(batches.len());
+    let mut deltas = Vec::new();
+    for batch in batches {
+        transfer.record_received(&batch.space_id, &batch.deltas)?;
+        spaces.push((batch.space_id, batch.deltas.len() as u32));
+        deltas.extend(batch.deltas);
+    }
+    let conflicts = agent.apply_deltas_from(conn, deltas, dek, from_device)?;
+
+    for (space_id, pulled) in &spaces {
+        let space_conflicts = conflicts
+            .iter()
+            .filter(|c| c.space_id.as_deref() == Some(space_id.as_str()))
+            .count() as u32;
+        SyncHistory::record(
+            conn,
+            SyncRecordParams {
+                device_id: from_device,
+                space_id,
+                direction: "pull",
+                entities_pushed: 0,
+                entities_pulled: *pulled,
+                conflicts: space_conflicts,
+                success: true,
+                error_message: None,
+                transport: SyncTransport::Relay,
+            },
+        )?;
+    }
+    transfer.save(conn)?;
+    let pulled = spaces.iter().map(|(_, count)| count).sum();
+    Ok((pulled, conflicts.len() as u32))
+}
+
+/// Acknowledgement of `session_id`, sealed for the device that sent it
+fn seal_ack(
+    conn: &Connection,
+    own_id: &str,
+    static_key: &StaticKeypair,
+    to_device: &str,
+    session_id: &str,
+) -> Result<RelayEnvelope, RelaySyncError> {
+    let peer_key = pinned_key(conn, to_device)?;
+    let ack = RelayAck {
+        session_id: session_id.to_string(),
+    };
+    Ok(RelayEnvelope::seal(
+        static_key,
+        own_id,
+        to_device,
+        &peer_key,
+        RELAY_ACK_MESSAGE,
+        &serde_json::to_vec(&ack)?,
+    )?)
+}
+
+/// Open an acknowledgement from a trusted device and mark its session
+/// delivered. False when the session isn't one we are waiting on.
+fn record_ack(
+    conn: &Connection,
+    own_id: &str,
+    static_key: &StaticKeypair,
+    envelope: &RelayEnvelope,
+    now: i64,
+) -> Result<bool, RelaySyncError> {
+    if envelope.to_device != own_id {
+        return Err(RelaySyncError::InvalidMessage(format!(
+            "{} message for {}",
+            envelope.message_type, envelope.to_device
+        )));
+    }
+    let sender_key = pinned_key(conn, &envelope.from_device)?;
+    let ack: RelayAck = serde_json::from_slice(&envelope.open(static_key, &sender_key)?)?;
+    let updated = conn.execute(
+        "UPDATE sync_relay_outbox SET acked_at = ?1
+         WHERE session_id = ?2 AND peer_device_id = ?3 AND acked_at IS NULL",
+        params![now, ack.session_id, envelope.from_device],
+    )?;
+    Ok(updated > 0)
+}
+
+/// Open an envelope from a trusted device and keep its chunk
+fn store_chunk(
+    conn: &Connection,
+    own_id: &str,
+    static_key: &StaticKeypair,
+    envelope: &RelayEnvelope,
+    now: i64,
+) -> Result<(), RelaySyncError> {
+    if envelope.to_device != own_id || envelope.message_type != RELAY_SYNC_MESSAGE {
+        return Err(RelaySyncError::InvalidMessage(format!(
+            "{} message for {}",
+            envelope.message_type, envelope.to_device
+        )));
+    }
+    let sender_key = pinned_key(conn, &envelope.from_device)?;
+    let chunk: RelayChunk = serde_json::from_slice(&envelope.open(static_key, &sender_key)?)?;
+    if chunk.index >= chunk.count {
+        return Err(RelaySyncError::InvalidMessage(format!(
+            "chunk {} of {}",
+            chunk.index, chunk.count
+        )));
+    }
+    conn.execute(
+        "INSERT OR IGNORE INTO sync_relay_chunk
+            (from_device, session_id, chunk_index, chunk_count, data, received_at)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+        params![
+            envelope.from_device,
+            chunk.session_id,
+            chunk.index,
+            chunk.count,
+            chunk.data,
+            now
+        ],
+    )?;
+    Ok(())
+}
+
+/// Sessions all of whose chunks have arrived, oldest first
+fn complete_sessions(conn: &Connection) -> Result<Vec<(String, String)>, RelaySyncError> {
+    let mut stmt = conn.prepare(
+        "SELECT from_device, session_id FROM sync_relay_chunk
+         GROUP BY from_device, session_id
+         HAVING COUNT(*) = MAX(chunk_count) AND MIN(chunk_count) = MAX(chunk_count)
+         ORDER BY session_id",
+    )?;
+    let sessions = stmt
+        .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(sessions)
+}
+
+fn assemble_session(
+    conn: &Connection,
+    from_device: &str,
+    session_id: &str,
+) -> Result<Vec<RelayBatch>, RelaySyncError> {
+    let mut stmt = conn.prepare(
+        "SELECT data FROM sync_relay_chunk
+         WHERE from_device = ?1 AND session_id = ?2
+         ORDER BY chunk_index",
+    )?;
+    let encoded = stmt
+        .query_map(params![from_device, session_id], |row| {
+            row.get::<_, String>(0)
+        })?
+        .collect::<Result<String, _>>()?;
+    let payload = base64::engine::general_purpose::STANDARD
+        .decode(encoded)
+        .map_err(|e| RelaySyncError::InvalidMessage(e.to_string()))?;
+    Ok(serde_json::from_slice(&payload)?)
+}
+
+/// The transport key pinned when `device_id` was paired
+fn pinned_key(conn: &Connection, device_id: &str) -> Result<Vec<u8>, RelaySyncError> {
+    let row: Option<(bool, Option<Vec<u8>>)> = conn
+        .query_row(
+            "SELECT trusted, static_public_key FROM sync_state WHERE device_id = ?1",
+            [device_id],
+            |row| Ok((row.get(0)?, row.get(1)?)),
+        )
+        .optional()?;
+    match row {
+        Some((true, Some(key))) => Ok(key),
+        Some((true, None)) => Err(RelaySyncError::KeyNotPinned(device_id.to_string())),
+        _ => Err(RelaySyncError::UntrustedDevice(device_id.to_string())),
+    }
+}
+
+/// When `device_id` was last sent this space's changes directly
+fn last_direct_push_time(
+    conn: &Connection,
+    space_id: &str,
+    device_id: &str,
+) -> Result<i64, RelaySyncError> {
+    let at: Option<i64> = conn.query_row(
+        "SELECT MAX(sync_time) FROM sync_history
+         WHERE space_id = ?1 AND device_id = ?2 AND direction = 'push' AND success = 1
+           AND transport != 'relay'",
+        [space_id, device_id],
+        |row| row.get(0),
+    )?;
+    Ok(at.unwrap_or(0))
+}
+
+/// When the changes of the last session `device_id` acknowledged were
+/// gathered; everything up to then has reached it through the relay
+fn acked_watermark(conn: &Connection, device_id: &str) -> Result<i64, RelaySyncError> {
+    let at: Option<i64> = conn.query_row(
+        "SELECT MAX(gathered_at) FROM sync_relay_outbox
+         WHERE peer_device_id = ?1 AND acked_at IS NOT NULL",
+        [device_id],
+        |row| row.get(0),
+    )?;
+    Ok(at.unwrap_or(0))
+}
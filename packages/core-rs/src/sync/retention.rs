@@ -0,0 +1,217 @@
+//! Retention for sync bookkeeping.
+//!
+//! `entity_sync_log` gains a row per entity per sync and `sync_history` a row
+//! per sync, forever. Pruning keeps recent rows as they are, drops old entity
+//! log rows that nothing needs anymore, and folds old sync history into one
+//! row per space, device and month so [`SyncStats`](super::SyncStats) still
+//! add up.
+
+use crate::db::table_exists;
+use crate::maintenance::{
+    MaintenanceError, MaintenanceRegistry, MaintenanceTask, TaskReport, PRIORITY_NORMAL,
+};
+use crate::sync::error::SyncError;
+use rusqlite::Connection;
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+use ulid::Ulid;
+
+/// Days of sync bookkeeping kept as-is by default
+pub const DEFAULT_SYNC_LOG_RETENTION_DAYS: u32 = 180;
+
+/// `direction` of a monthly aggregate row in `sync_history`
+pub const AGGREGATE_DIRECTION: &str = "aggregate";
+
+const DAY_SECS: i64 = 86_400;
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct SyncLogRetentionPolicy {
+    /// Entity sync log rows younger than this are always kept. Older ones
+    /// survive only as the latest row of an entity for a registered device.
+    pub entity_log_days: u32,
+    /// Sync history younger than this stays one row per sync
+    pub history_days: u32,
+}
+
+impl Default for SyncLogRetentionPolicy {
+    fn default() -> Self {
+        Self {
+            entity_log_days: DEFAULT_SYNC_LOG_RETENTION_DAYS,
+            history_days: DEFAULT_SYNC_LOG_RETENTION_DAYS,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub struct SyncLogPruneResult {
+    pub entity_log_rows_removed: usize,
+    /// Individual syncs folded into monthly aggregates
+    pub history_rows_collapsed: usize,
+    /// Rows gone from both tables, after counting the aggregates written
+    pub rows_reclaimed: usize,
+}
+
+/// One space, device and month of sync history past the cutoff
+struct HistoryMonth {
+    space_id: String,
+    device_id: String,
+    month: String,
+    syncs: i64,
+    successes: i64,
+    entities_pushed: i64,
+    entities_pulled: i64,
+    conflicts: i64,
+    sync_time: i64,
+    /// Rows that are individual syncs rather than an earlier aggregate
+    raw_rows: usize,
+}
+
+/// Apply `policy` to the sync logs
+pub fn prune_sync_logs(
+    conn: &Connection,
+    policy: &SyncLogRetentionPolicy,
+) -> Result<SyncLogPruneResult, SyncError> {
+    let now = chrono::Utc::now().timestamp();
+    let tx = conn.unchecked_transaction()?;
+    let mut result = SyncLogPruneResult::default();
+
+    if table_exists(&tx, "entity_sync_log")? {
+        result.entity_log_rows_removed =
+            prune_entity_log(&tx, now - policy.entity_log_days as i64 * DAY_SECS)?;
+    }
+    let mut history_removed = 0;
+    if table_exists(&tx, "sync_history")? {
+        let cutoff = now - policy.history_days as i64 * DAY_SECS;
+        for month in history_months(&tx, cutoff)? {
+            let removed = tx.execute(
+                "DELETE FROM sync_history
+                 WHERE space_id = ?1 AND device_id = ?2 AND sync_time < ?3
+                   AND strftime('%Y-%m', sync_time, 'unixepoch') = ?4",
+                rusqlite::params![month.space_id, month.device_id, cutoff, month.month],
+            )?;
+            tx.execute(
+                "INSERT INTO sync_history (id, device_id, space_id, sync_time, direction,
+                                           entities_pushed, entities_pulled, conflicts_detected,
+                                           success, sync_count, success_count)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11)",
+                rusqlite::params![
+                    Ulid::new().to_string(),
+                    month.device_id,
+                    month.space_id,
+                    month.sync_time,
+                    AGGREGATE_DIRECTION,
+                    month.entities_pushed,
+                    month.entities_pulled,
+                    month.conflicts,
+                    month.successes > 0,
+                    month.syncs,
+                    month.successes
+                ],
+            )?;
+            result.history_rows_collapsed += month.raw_rows;
+            history_removed += removed - 1;
+        }
+    }
+    tx.commit()?;
+
+    result.rows_reclaimed = result.entity_log_rows_removed + history_removed;
+    if result.rows_reclaimed > 0 {
+        log::info!(
+            "[sync] Pruned sync logs: {} entity log rows removed, {} history rows collapsed",
+            result.entity_log_rows_removed,
+            result.history_rows_collapsed
+        );
+    }
+    Ok(result)
+}
+
+/// [`prune_sync_logs`] with the default policy as a background maintenance task
+pub struct SyncLogPruneTask;
+
+impl MaintenanceTask for SyncLogPruneTask {
+    fn name(&self) -> &str {
+        "sync_log_prune"
+    }
+
+    fn estimated_cost(&self) -> Duration {
+        Duration::from_millis(500)
+    }
+
+    fn run(&self, conn: &Connection) -> Result<TaskReport, MaintenanceError> {
+        let result = prune_sync_logs(conn, &SyncLogRetentionPolicy::default())
+            .map_err(|e| MaintenanceError::Task(e.to_string()))?;
+        Ok(TaskReport {
+            items_affected: result.rows_reclaimed as u64,
+        })
+    }
+}
+
+pub(crate) fn register_maintenance(registry: &mut MaintenanceRegistry) {
+    registry.register(PRIORITY_NORMAL, SyncLogPruneTask);
+}
+
+/// Delete entity log rows older than `cutoff`, except the latest row of each
+/// entity for every registered device, which is what answers whether the
+/// entity ever synced with it
+fn prune_entity_log(conn: &Connection, cutoff: i64) -> Result<usize, SyncError> {
+    // Without a device registry no device is registered
+    let unregistered = if table_exists(conn, "sync_state")? {
+        "l.device_id NOT IN (SELECT device_id FROM sync_state)"
+    } else {
+        "1"
+    };
+    let removed = conn.execute(
+        &format!(
+            "DELETE FROM entity_sync_log AS l
+             WHERE l.synced_at < ?1
+               AND ({}
+                    OR EXISTS (
+                        SELECT 1 FROM entity_sync_log n
+                        WHERE n.entity_id = l.entity_id
+                          AND n.entity_type = l.entity_type
+                          AND n.device_id = l.device_id
+                          AND (n.synced_at > l.synced_at
+                               OR (n.synced_at = l.synced_at AND n.id > l.id))
+                    ))",
+            unregistered
+        ),
+        [cutoff],
+    )?;
+    Ok(removed)
+}
+
+/// Months before `cutoff` that still hold individual syncs. Aggregates from
+/// an earlier prune are merged into the totals, so pruning again as a month
+/// ages out keeps one row for it.
+fn history_months(conn: &Connection, cutoff: i64) -> Result<Vec<HistoryMonth>, SyncError> {
+    let mut stmt = conn.prepare(
+        "SELECT space_id, device_id, strftime('%Y-%m', sync_time, 'unixepoch') AS month,
+                SUM(sync_count), SUM(COALESCE(success_count, success)),
+                SUM(entities_pushed), SUM(entities_pulled), SUM(conflicts_detected),
+                -- The last successful sync, so the last-sync time survives
+                COALESCE(MAX(CASE WHEN success = 1 THEN sync_time END), MAX(sync_time)),
+                SUM(success_count IS NULL) AS raw_rows
+         FROM sync_history
+         WHERE sync_time < ?1
+         GROUP BY space_id, device_id, month
+         HAVING raw_rows > 0
+         ORDER BY month",
+    )?;
+    let months = stmt
+        .query_map([cutoff], |row| {
+            Ok(HistoryMonth {
+                space_id: row.get(0)?,
+                device_id: row.get(1)?,
+                month: row.get(2)?,
+                syncs: row.get(3)?,
+                successes: row.get(4)?,
+                entities_pushed: row.get(5)?,
+                entities_pulled: row.get(6)?,
+                conflicts: row.get(7)?,
+                sync_time: row.get(8)?,
+                raw_rows: row.get::<_, i64>(9)? as usize,
+            })
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(months)
+}
@@ -0,0 +1,214 @@
+//! Per-device sync scope.
+//!
+//! Each paired device can be limited to some entity types, e.g. a phone that
+//! skips music. The scope is stored on the device's `sync_state` row as a JSON
+//! array of entity types; NULL means every type. When two peers sync, each
+//! side advertises the scope it keeps for the other and both use the
+//! intersection, so neither sends nor applies deltas outside it.
+
+use crate::crdt::NOTE_CRDT_ENTITY_TYPE;
+use crate::note_dedup::NOTE_MERGE_ENTITY_TYPE;
+use crate::note_share::{NOTE_MOVE_ENTITY_TYPE, NOTE_REFERENCE_ENTITY_TYPE};
+use crate::sync::error::SyncError;
+use crate::sync::models::SyncDelta;
+use rusqlite::{Connection, OptionalExtension};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
+use ulid::Ulid;
+
+/// `direction` of the `sync_history` rows noting a scope change
+pub const SCOPE_CHANGE_DIRECTION: &str = "scope_change";
+
+/// Entity types exchanged by vault sync
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SyncEntityType {
+    Note,
+    Task,
+    Project,
+    HealthMetric,
+    Track,
+    Playlist,
+    CalendarEvent,
+    InboxItem,
+}
+
+impl SyncEntityType {
+    pub const ALL: [SyncEntityType; 8] = [
+        SyncEntityType::Note,
+        SyncEntityType::Task,
+        SyncEntityType::Project,
+        SyncEntityType::HealthMetric,
+        SyncEntityType::Track,
+        SyncEntityType::Playlist,
+        SyncEntityType::CalendarEvent,
+        SyncEntityType::InboxItem,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SyncEntityType::Note => "note",
+            SyncEntityType::Task => "task",
+            SyncEntityType::Project => "project",
+            SyncEntityType::HealthMetric => "health_metric",
+            SyncEntityType::Track => "track",
+            SyncEntityType::Playlist => "playlist",
+            SyncEntityType::CalendarEvent => "calendar_event",
+            SyncEntityType::InboxItem => "inbox_item",
+        }
+    }
+
+    /// Type of the `entity_type` of a delta; CRDT note updates are notes
+    pub fn from_delta_type(entity_type: &str) -> Option<Self> {
+        if [
+            NOTE_CRDT_ENTITY_TYPE,
+            NOTE_MERGE_ENTITY_TYPE,
+            NOTE_MOVE_ENTITY_TYPE,
+            NOTE_REFERENCE_ENTITY_TYPE,
+        ]
+        .contains(&entity_type)
+        {
+            return Some(SyncEntityType::Note);
+        }
+        Self::ALL.into_iter().find(|t| t.as_str() == entity_type)
+    }
+}
+
+/// Entity types synced with a device
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SyncScope {
+    #[default]
+    All,
+    Only(BTreeSet<SyncEntityType>),
+}
+
+impl SyncScope {
+    pub fn only(types: impl IntoIterator<Item = SyncEntityType>) -> Self {
+        SyncScope::Only(types.into_iter().collect())
+    }
+
+    pub fn includes(&self, entity_type: SyncEntityType) -> bool {
+        match self {
+            SyncScope::All => true,
+            SyncScope::Only(types) => types.contains(&entity_type),
+        }
+    }
+
+    /// Whether a delta is in scope. Deltas of unknown types are only in the
+    /// scope of everything.
+    pub fn allows(&self, delta: &SyncDelta) -> bool {
+        match self {
+            SyncScope::All => true,
+            SyncScope::Only(_) => SyncEntityType::from_delta_type(&delta.entity_type)
+                .is_some_and(|t| self.includes(t)),
+        }
+    }
+
+    /// Types in both scopes
+    pub fn intersect(&self, other: &SyncScope) -> SyncScope {
+        match (self, other) {
+            (SyncScope::All, other) => other.clone(),
+            (this, SyncScope::All) => this.clone(),
+            (SyncScope::Only(a), SyncScope::Only(b)) => {
+                SyncScope::Only(a.intersection(b).copied().collect())
+            }
+        }
+    }
+
+    /// Scope agreed with a peer that advertised `peer`; peers that predate
+    /// scopes advertise nothing and sync everything
+    pub fn negotiate(&self, peer: Option<&SyncScope>) -> SyncScope {
+        self.intersect(peer.unwrap_or(&SyncScope::All))
+    }
+
+    fn describe(&self) -> String {
+        match self {
+            SyncScope::All => "all entity types".to_string(),
+            SyncScope::Only(types) if types.is_empty() => "no entity types".to_string(),
+            SyncScope::Only(types) => types
+                .iter()
+                .map(|t| t.as_str())
+                .collect::<Vec<_>>()
+                .join(", "),
+        }
+    }
+}
+
+/// Scope kept for `device_id`; devices without one, and unknown devices,
+/// sync everything
+pub fn get_device_sync_scope(conn: &Connection, device_id: &str) -> Result<SyncScope, SyncError> {
+    let stored: Option<String> = conn
+        .query_row(
+            "SELECT sync_scope FROM sync_state WHERE device_id = ?1",
+            [device_id],
+            |row| row.get(0),
+        )
+        .optional()?
+        .flatten();
+    match stored {
+        Some(json) => {
+            let types: BTreeSet<SyncEntityType> = serde_json::from_str(&json)
+                .map_err(|e| SyncError::InvalidData(format!("Invalid sync scope: {}", e)))?;
+            Ok(SyncScope::Only(types))
+        }
+        None => Ok(SyncScope::All),
+    }
+}
+
+/// Limit sync with a registered device to `scope`. The change is noted in the
+/// sync history of every space.
+pub fn set_device_sync_scope(
+    conn: &Connection,
+    device_id: &str,
+    scope: &SyncScope,
+) -> Result<(), SyncError> {
+    let stored = match scope {
+        SyncScope::All => None,
+        SyncScope::Only(types) => {
+            Some(serde_json::to_string(types).map_err(|e| SyncError::InvalidData(e.to_string()))?)
+        }
+    };
+    let tx = conn.unchecked_transaction()?;
+    let updated = tx.execute(
+        "UPDATE sync_state SET sync_scope = ?1 WHERE device_id = ?2",
+        rusqlite::params![stored, device_id],
+    )?;
+    if updated == 0 {
+        return Err(SyncError::InvalidData(format!(
+            "Unknown device: {}",
+            device_id
+        )));
+    }
+
+    // Not a sync: no counts, and never a success that would move the
+    // last-sync time deltas are gathered from
+    let note = format!("Sync scope changed to {}", scope.describe());
+    let now = chrono::Utc::now().timestamp();
+    let space_ids = {
+        let mut stmt = tx.prepare("SELECT id FROM space")?;
+        let ids = stmt
+            .query_map([], |row| row.get::<_, String>(0))?
+            .collect::<Result<Vec<_>, _>>()?;
+        ids
+    };
+    for space_id in space_ids {
+        tx.execute(
+            "INSERT INTO sync_history (id, device_id, space_id, sync_time, direction,
+                                       success, sync_count, success_count, note)
+             VALUES (?1, ?2, ?3, ?4, ?5, 0, 0, 0, ?6)",
+            rusqlite::params![
+                Ulid::new().to_string(),
+                device_id,
+                space_id,
+                now,
+                SCOPE_CHANGE_DIRECTION,
+                note
+            ],
+        )?;
+    }
+    tx.commit()?;
+
+    log::info!("[sync] {} for device {}", note, device_id);
+    Ok(())
+}
@@ -0,0 +1,511 @@
+//! Encrypted Sync Transport
+//!
+//! Sync connections run a Noise XX handshake (X25519, ChaCha20-Poly1305,
+//! SHA-256) over WebSocket binary frames before any sync message is sent, so
+//! device ids, entity ids and timestamps never cross the LAN in the clear.
+//!
+//! Every device has a long-term static key ([`StaticKeypair`]). Its public
+//! half is exchanged during pairing, covered by the SAS the user compares,
+//! and pinned in `sync_state.static_public_key`. After the handshake each
+//! side checks that the peer proved possession of the pinned key for the
+//! device id it claims.
+//!
+//! Peers on protocol version 1 send a plaintext JSON handshake; they are told
+//! that version 2 is required instead of being silently dropped. Until the
+//! mobile app speaks protocol 2, a listener can opt in to taking such peers
+//! with [`SecureChannel::accept_incoming`] and serving them in plaintext.
+
+use futures::{SinkExt, StreamExt};
+use rusqlite::{params, Connection, OptionalExtension};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use subtle::ConstantTimeEq;
+use thiserror::Error;
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio_tungstenite::tungstenite::Message;
+use tokio_tungstenite::WebSocketStream;
+use zeroize::Zeroizing;
+
+/// Sync protocol version that introduced the encrypted transport
+pub const SECURE_PROTOCOL_VERSION: u32 = 2;
+
+const NOISE_PARAMS: &str = "Noise_XX_25519_ChaChaPoly_SHA256";
+/// Bound into the handshake hash so both sides agree on the protocol
+const PROLOGUE: &[u8] = b"noteece-sync-v2";
+
+/// Largest Noise message, and the AEAD tag each transport message carries
+const MAX_NOISE_MESSAGE: usize = 65535;
+const TAG_LEN: usize = 16;
+/// Plaintext per transport message: one flag byte says whether more follow
+const MAX_CHUNK: usize = MAX_NOISE_MESSAGE - TAG_LEN - 1;
+const MORE_FOLLOWS: u8 = 1;
+const LAST_CHUNK: u8 = 0;
+/// Refuse to reassemble logical messages beyond this size
+const MAX_MESSAGE_SIZE: usize = 64 * 1024 * 1024;
+
+#[derive(Error, Debug)]
+pub enum ChannelError {
+    #[error("Noise error: {0}")]
+    Noise(#[from] snow::Error),
+
+    #[error("WebSocket error: {0}")]
+    WebSocket(String),
+
+    #[error("Serialization error: {0}")]
+    Serialization(#[from] serde_json::Error),
+
+    #[error("Database error: {0}")]
+    Database(#[from] rusqlite::Error),
+
+    #[error(
+        "Peer speaks sync protocol {0}; encrypted sync requires protocol {SECURE_PROTOCOL_VERSION}"
+    )]
+    UnsupportedProtocol(u32),
+
+    #[error("Device {0} is not trusted; pair and confirm it first")]
+    UntrustedDevice(String),
+
+    #[error("Device {0} has no pinned transport key; pair it again")]
+    KeyNotPinned(String),
+
+    #[error("Device {0} presented a transport key that does not match the one pinned at pairing")]
+    KeyMismatch(String),
+
+    #[error("Peer rejected the connection: {0}")]
+    Rejected(String),
+
+    #[error("Connection closed during the handshake; the peer may not support protocol {SECURE_PROTOCOL_VERSION}")]
+    ClosedDuringHandshake,
+
+    #[error("Protocol error: {0}")]
+    Protocol(String),
+}
+
+/// A device's long-term Noise key
+pub struct StaticKeypair {
+    private: Zeroizing<Vec<u8>>,
+    public: Vec<u8>,
+}
+
+impl StaticKeypair {
+    pub fn generate() -> Result<Self, ChannelError> {
+        let keypair = snow::Builder::new(noise_params()).generate_keypair()?;
+        Ok(StaticKeypair {
+            private: Zeroizing::new(keypair.private),
+            public: keypair.public,
+        })
+    }
+
+    pub fn public_key(&self) -> &[u8] {
+        &self.public
+    }
+
+    /// X25519 of this key with a peer's public key, for sealing messages
+    /// outside a Noise session. Fails on a malformed or low-order key.
+    pub(crate) fn diffie_hellman(
+        &self,
+        peer_public: &[u8],
+    ) -> Result<Zeroizing<[u8; 32]>, ChannelError> {
+        let private: [u8; 32] =
+            self.private.as_slice().try_into().map_err(|_| {
+                ChannelError::Protocol("Static key is not an X25519 key".to_string())
+            })?;
+        let peer: [u8; 32] = peer_public
+            .try_into()
+            .map_err(|_| ChannelError::Protocol("Peer key is not an X25519 key".to_string()))?;
+        let shared = Zeroizing::new(x25519_dalek::x25519(private, peer));
+        if shared.iter().all(|b| *b == 0) {
+            return Err(ChannelError::Protocol(
+                "Peer key is of low order".to_string(),
+            ));
+        }
+        Ok(shared)
+    }
+}
+
+impl std::fmt::Debug for StaticKeypair {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("StaticKeypair")
+            .field("public", &hex::encode(&self.public))
+            .finish()
+    }
+}
+
+/// This device's static key, created on first use. Stored in the encrypted
+/// vault next to the sync tables.
+pub fn load_or_create_static_key(conn: &Connection) -> Result<StaticKeypair, ChannelError> {
+    let existing = conn
+        .query_row(
+            "SELECT private_key, public_key FROM sync_identity WHERE id = 1",
+            [],
+            |row| Ok((row.get::<_, Vec<u8>>(0)?, row.get::<_, Vec<u8>>(1)?)),
+        )
+        .optional()?;
+    if let Some((private, public)) = existing {
+        return Ok(StaticKeypair {
+            private: Zeroizing::new(private),
+            public,
+        });
+    }
+
+    let keypair = StaticKeypair::generate()?;
+    conn.execute(
+        "INSERT INTO sync_identity (id, private_key, public_key, created_at)
+         VALUES (1, ?1, ?2, ?3)",
+        params![
+            keypair.private.as_slice(),
+            keypair.public,
+            chrono::Utc::now().timestamp()
+        ],
+    )?;
+    log::info!("[sync] Created static transport key");
+    Ok(keypair)
+}
+
+/// Constant-time check of a presented key against the pinned one
+pub fn key_matches(
+    device_id: &str,
+    pinned: Option<&[u8]>,
+    presented: &[u8],
+) -> Result<(), ChannelError> {
+    let pinned = pinned.ok_or_else(|| ChannelError::KeyNotPinned(device_id.to_string()))?;
+    if pinned.len() != presented.len() || !bool::from(pinned.ct_eq(presented)) {
+        return Err(ChannelError::KeyMismatch(device_id.to_string()));
+    }
+    Ok(())
+}
+
+/// Identity each side sends, encrypted, inside the handshake
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct HandshakeIdentity {
+    device_id: String,
+    protocol_version: u32,
+}
+
+/// First message after the handshake, from responder to initiator
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum HandshakeResult {
+    HandshakeResponse,
+    HandshakeRejected { reason: String },
+}
+
+/// What a listening side accepted
+pub enum Incoming<S> {
+    Secure(SecureChannel<S>),
+    /// A protocol 1 peer, let in only when legacy peers are allowed, with
+    /// the plaintext handshake it opened with
+    Legacy {
+        ws: WebSocketStream<S>,
+        handshake: String,
+    },
+}
+
+/// An authenticated, encrypted sync connection
+pub struct SecureChannel<S> {
+    ws: WebSocketStream<S>,
+    transport: snow::TransportState,
+    peer_device_id: String,
+}
+
+impl<S> SecureChannel<S>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    /// Run the handshake as the connecting side. The responder must prove
+    /// the key pinned for `peer_device_id`.
+    pub async fn connect(
+        mut ws: WebSocketStream<S>,
+        keypair: &StaticKeypair,
+        device_id: &str,
+        peer_device_id: &str,
+        pinned_peer_key: Option<&[u8]>,
+    ) -> Result<Self, ChannelError> {
+        let mut noise = snow::Builder::new(noise_params())
+            .local_private_key(&keypair.private)
+            .prologue(PROLOGUE)
+            .build_initiator()?;
+        let mut buf = vec![0u8; MAX_NOISE_MESSAGE];
+
+        // -> e
+        let len = noise.write_message(&[], &mut buf)?;
+        send_frame(&mut ws, buf[..len].to_vec()).await?;
+
+        // <- e, ee, s, es
+        let message = recv_handshake_frame(&mut ws).await?;
+        let len = noise.read_message(&message, &mut buf)?;
+        let responder: HandshakeIdentity = serde_json::from_slice(&buf[..len])?;
+        if responder.device_id != peer_device_id {
+            return Err(ChannelError::Protocol(format!(
+                "Expected device {}, reached {}",
+                peer_device_id, responder.device_id
+            )));
+        }
+        let remote_static = noise.get_remote_static().unwrap_or_default();
+        key_matches(peer_device_id, pinned_peer_key, remote_static)?;
+
+        // -> s, se
+        let identity = serde_json::to_vec(&HandshakeIdentity {
+            device_id: device_id.to_string(),
+            protocol_version: SECURE_PROTOCOL_VERSION,
+        })?;
+        let len = noise.write_message(&identity, &mut buf)?;
+        send_frame(&mut ws, buf[..len].to_vec()).await?;
+
+        let mut channel = SecureChannel {
+            ws,
+            transport: noise.into_transport_mode()?,
+            peer_device_id: peer_device_id.to_string(),
+        };
+        match channel.recv_json::<HandshakeResult>().await? {
+            Some(HandshakeResult::HandshakeResponse) => Ok(channel),
+            Some(HandshakeResult::HandshakeRejected { reason }) => {
+                Err(ChannelError::Rejected(reason))
+            }
+            None => Err(ChannelError::ClosedDuringHandshake),
+        }
+    }
+
+    /// Run the handshake as the listening side. `authorize` gets the device
+    /// id the initiator claims and the static key it proved, and decides
+    /// whether to let it in; a rejection is sent to the peer encrypted.
+    pub async fn accept<F>(
+        ws: WebSocketStream<S>,
+        keypair: &StaticKeypair,
+        device_id: &str,
+        authorize: F,
+    ) -> Result<Self, ChannelError>
+    where
+        F: FnOnce(&str, &[u8]) -> Result<(), ChannelError>,
+    {
+        match Self::accept_incoming(ws, keypair, device_id, false, authorize).await? {
+            Incoming::Secure(channel) => Ok(channel),
+            Incoming::Legacy { .. } => Err(ChannelError::UnsupportedProtocol(1)),
+        }
+    }
+
+    /// [`SecureChannel::accept`], handing a protocol 1 peer's connection
+    /// back instead of rejecting it when `allow_legacy` is set. Such a peer
+    /// is not authenticated; the caller has to check it.
+    pub async fn accept_incoming<F>(
+        mut ws: WebSocketStream<S>,
+        keypair: &StaticKeypair,
+        device_id: &str,
+        allow_legacy: bool,
+        authorize: F,
+    ) -> Result<Incoming<S>, ChannelError>
+    where
+        F: FnOnce(&str, &[u8]) -> Result<(), ChannelError>,
+    {
+        let mut noise = snow::Builder::new(noise_params())
+            .local_private_key(&keypair.private)
+            .prologue(PROLOGUE)
+            .build_responder()?;
+        let mut buf = vec![0u8; MAX_NOISE_MESSAGE];
+
+        // <- e
+        let message = match ws.next().await {
+            Some(Ok(Message::Binary(bytes))) => bytes,
+            Some(Ok(Message::Text(text))) => {
+                let version = legacy_handshake_version(&text);
+                if allow_legacy && version < SECURE_PROTOCOL_VERSION {
+                    return Ok(Incoming::Legacy {
+                        ws,
+                        handshake: text,
+                    });
+                }
+                let error = ChannelError::UnsupportedProtocol(version);
+                let rejection = serde_json::json!({
+                    "type": "handshake_rejected",
+                    "reason": error.to_string(),
+                });
+                let _ = ws.send(Message::Text(rejection.to_string())).await;
+                let _ = ws.close(None).await;
+                return Err(error);
+            }
+            Some(Ok(_)) | None => return Err(ChannelError::ClosedDuringHandshake),
+            Some(Err(e)) => return Err(ChannelError::WebSocket(e.to_string())),
+        };
+        noise.read_message(&message, &mut buf)?;
+
+        // -> e, ee, s, es
+        let identity = serde_json::to_vec(&HandshakeIdentity {
+            device_id: device_id.to_string(),
+            protocol_version: SECURE_PROTOCOL_VERSION,
+        })?;
+        let len = noise.write_message(&identity, &mut buf)?;
+        send_frame(&mut ws, buf[..len].to_vec()).await?;
+
+        // <- s, se
+        let message = recv_handshake_frame(&mut ws).await?;
+        let len = noise.read_message(&message, &mut buf)?;
+        let initiator: HandshakeIdentity = serde_json::from_slice(&buf[..len])?;
+        let remote_static = noise.get_remote_static().unwrap_or_default().to_vec();
+
+        let mut channel = SecureChannel {
+            ws,
+            transport: noise.into_transport_mode()?,
+            peer_device_id: initiator.device_id,
+        };
+        let verdict = if initiator.protocol_version < SECURE_PROTOCOL_VERSION {
+            Err(ChannelError::UnsupportedProtocol(
+                initiator.protocol_version,
+            ))
+        } else {
+            authorize(&channel.peer_device_id, &remote_static)
+        };
+        match verdict {
+            Ok(()) => {
+                channel
+                    .send_json(&HandshakeResult::HandshakeResponse)
+                    .await?;
+                Ok(Incoming::Secure(channel))
+            }
+            Err(e) => {
+                let _ = channel
+                    .send_json(&HandshakeResult::HandshakeRejected {
+                        reason: e.to_string(),
+                    })
+                    .await;
+                let _ = channel.close().await;
+                Err(e)
+            }
+        }
+    }
+
+    /// Device id the peer authenticated as
+    pub fn peer_device_id(&self) -> &str {
+        &self.peer_device_id
+    }
+
+    pub async fn send(&mut self, message: &[u8]) -> Result<(), ChannelError> {
+        let mut chunks: Vec<&[u8]> = message.chunks(MAX_CHUNK).collect();
+        // An empty message still needs one (empty, final) chunk
+        if chunks.is_empty() {
+            chunks.push(&[]);
+        }
+
+        let last = chunks.len() - 1;
+        let mut plaintext = Vec::with_capacity(MAX_CHUNK + 1);
+        let mut ciphertext = vec![0u8; MAX_NOISE_MESSAGE];
+        for (i, chunk) in chunks.into_iter().enumerate() {
+            plaintext.clear();
+            plaintext.push(if i < last { MORE_FOLLOWS } else { LAST_CHUNK });
+            plaintext.extend_from_slice(chunk);
+            let len = self.transport.write_message(&plaintext, &mut ciphertext)?;
+            send_frame(&mut self.ws, ciphertext[..len].to_vec()).await?;
+        }
+        Ok(())
+    }
+
+    /// Next message from the peer, or `None` once it closed the connection
+    pub async fn recv(&mut self) -> Result<Option<Vec<u8>>, ChannelError> {
+        let mut message = Vec::new();
+        let mut plaintext = vec![0u8; MAX_NOISE_MESSAGE];
+        loop {
+            let frame = match self.ws.next().await {
+                Some(Ok(Message::Binary(bytes))) => bytes,
+                Some(Ok(Message::Close(_))) | None => {
+                    return if message.is_empty() {
+                        Ok(None)
+                    } else {
+                        Err(ChannelError::Protocol(
+                            "Connection closed mid-message".to_string(),
+                        ))
+                    };
+                }
+                Some(Ok(Message::Ping(_) | Message::Pong(_))) => continue,
+                Some(Ok(_)) => {
+                    return Err(ChannelError::Protocol(
+                        "Unexpected plaintext frame on encrypted channel".to_string(),
+                    ))
+                }
+                Some(Err(e)) => return Err(ChannelError::WebSocket(e.to_string())),
+            };
+            let len = self.transport.read_message(&frame, &mut plaintext)?;
+            let (flag, chunk) = plaintext[..len]
+                .split_first()
+                .ok_or_else(|| ChannelError::Protocol("Empty transport message".to_string()))?;
+            if message.len() + chunk.len() > MAX_MESSAGE_SIZE {
+                return Err(ChannelError::Protocol("Message too large".to_string()));
+            }
+            message.extend_from_slice(chunk);
+            if *flag == LAST_CHUNK {
+                return Ok(Some(message));
+            }
+        }
+    }
+
+    pub async fn send_json<T: Serialize>(&mut self, value: &T) -> Result<(), ChannelError> {
+        let bytes = serde_json::to_vec(value)?;
+        self.send(&bytes).await
+    }
+
+    pub async fn recv_json<T: DeserializeOwned>(&mut self) -> Result<Option<T>, ChannelError> {
+        match self.recv().await? {
+            Some(bytes) => Ok(Some(serde_json::from_slice(&bytes)?)),
+            None => Ok(None),
+        }
+    }
+
+    pub async fn close(&mut self) -> Result<(), ChannelError> {
+        self.ws
+            .close(None)
+            .await
+            .map_err(|e| ChannelError::WebSocket(e.to_string()))
+    }
+}
+
+fn noise_params() -> snow::params::NoiseParams {
+    NOISE_PARAMS
+        .parse()
+        .expect("NOISE_PARAMS is a valid Noise protocol name")
+}
+
+async fn send_frame<S>(ws: &mut WebSocketStream<S>, bytes: Vec<u8>) -> Result<(), ChannelError>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    ws.send(Message::Binary(bytes))
+        .await
+        .map_err(|e| ChannelError::WebSocket(e.to_string()))
+}
+
+async fn recv_handshake_frame<S>(ws: &mut WebSocketStream<S>) -> Result<Vec<u8>, ChannelError>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    match ws.next().await {
+        Some(Ok(Message::Binary(bytes))) => Ok(bytes),
+        Some(Ok(Message::Text(text))) => {
+            // A legacy peer answering in plaintext, most likely a rejection
+            let reason = serde_json::from_str::<serde_json::Value>(&text)
+                .ok()
+                .and_then(|v| v.get("reason").and_then(|r| r.as_str()).map(String::from));
+            Err(match reason {
+                Some(reason) => ChannelError::Rejected(reason),
+                None => ChannelError::UnsupportedProtocol(1),
+            })
+        }
+        Some(Ok(_)) | None => Err(ChannelError::ClosedDuringHandshake),
+        Some(Err(e)) => Err(ChannelError::WebSocket(e.to_string())),
+    }
+}
+
+/// Version announced by a plaintext (pre-Noise) handshake. Version 1 clients
+/// sent a string such as "1.0" or nothing at all.
+fn legacy_handshake_version(text: &str) -> u32 {
+    serde_json::from_str::<serde_json::Value>(text)
+        .ok()
+        .and_then(|v| {
+            let version = v.get("protocolVersion").or_else(|| v.get("version"))?;
+            version.as_u64().map(|n| n as u32).or_else(|| {
+                version
+                    .as_str()
+                    .and_then(|s| s.split('.').next())
+                    .and_then(|major| major.parse().ok())
+            })
+        })
+        .unwrap_or(1)
+}
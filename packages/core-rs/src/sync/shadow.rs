@@ -0,0 +1,89 @@
+//! Sync shadows.
+//!
+//! The shadow of an entity is the last version received from a peer and
+//! taken into the local copy. After the two sides diverge it is their most
+//! recent common ancestor, which lets note conflicts be merged three ways.
+
+use crate::sync::error::SyncError;
+use rusqlite::{Connection, OptionalExtension};
+
+/// `note_meta` key set to "true" on notes merged with conflict markers
+pub const MERGE_CONFLICT_META_KEY: &str = "sync_merge_conflict";
+
+pub fn get_shadow(
+    conn: &Connection,
+    entity_type: &str,
+    entity_id: &str,
+) -> Result<Option<Vec<u8>>, SyncError> {
+    Ok(conn
+        .query_row(
+            "SELECT content FROM sync_shadow WHERE entity_type = ?1 AND entity_id = ?2",
+            [entity_type, entity_id],
+            |row| row.get(0),
+        )
+        .optional()?)
+}
+
+pub fn record_shadow(
+    conn: &Connection,
+    entity_type: &str,
+    entity_id: &str,
+    content: &[u8],
+) -> Result<(), SyncError> {
+    conn.execute(
+        "INSERT INTO sync_shadow (entity_type, entity_id, content, synced_at)
+         VALUES (?1, ?2, ?3, ?4)
+         ON CONFLICT(entity_type, entity_id) DO UPDATE SET
+            content = excluded.content, synced_at = excluded.synced_at",
+        rusqlite::params![
+            entity_type,
+            entity_id,
+            content,
+            chrono::Utc::now().timestamp()
+        ],
+    )?;
+    Ok(())
+}
+
+pub fn delete_shadow(
+    conn: &Connection,
+    entity_type: &str,
+    entity_id: &str,
+) -> Result<(), SyncError> {
+    conn.execute(
+        "DELETE FROM sync_shadow WHERE entity_type = ?1 AND entity_id = ?2",
+        [entity_type, entity_id],
+    )?;
+    Ok(())
+}
+
+/// Whether the note's last sync merge left conflict markers to review
+pub fn has_merge_conflict(conn: &Connection, note_id: &str) -> Result<bool, SyncError> {
+    let flagged = conn
+        .query_row(
+            "SELECT 1 FROM note_meta WHERE note_id = ?1 AND key = ?2 AND value = 'true'",
+            [note_id, MERGE_CONFLICT_META_KEY],
+            |_| Ok(()),
+        )
+        .optional()?;
+    Ok(flagged.is_some())
+}
+
+pub(crate) fn set_merge_conflict(
+    conn: &Connection,
+    note_id: &str,
+    has_conflicts: bool,
+) -> Result<(), SyncError> {
+    if has_conflicts {
+        conn.execute(
+            "INSERT OR REPLACE INTO note_meta (note_id, key, value) VALUES (?1, ?2, 'true')",
+            [note_id, MERGE_CONFLICT_META_KEY],
+        )?;
+    } else {
+        conn.execute(
+            "DELETE FROM note_meta WHERE note_id = ?1 AND key = ?2",
+            [note_id, MERGE_CONFLICT_META_KEY],
+        )?;
+    }
+    Ok(())
+}
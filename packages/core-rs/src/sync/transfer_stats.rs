@@ -0,0 +1,293 @@
+//! Sync transfer accounting.
+//!
+//! `sync_history` says how many entities a sync moved, not how many bytes.
+//! A [`SyncTransferSession`] is filled in where a session serializes its
+//! deltas for sending or decodes the ones it received, and saved as one
+//! `sync_transfer_stats` row per space, entity type and direction.
+//! [`get_sync_transfer_report`] adds those up per entity type and per device
+//! so the UI can show what a slow sync spent its bandwidth on.
+//!
+//! Each row keeps the serialized size of the deltas (`payload_bytes`) next to
+//! what went over the network (`wire_bytes`). They only differ once a
+//! transport compresses its payload; the report shows the difference as
+//! compression savings.
+
+use crate::calendar::TimeRange;
+use crate::sync::error::SyncError;
+use crate::sync::models::{SyncDelta, SyncTransport};
+use rusqlite::Connection;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use ulid::Ulid;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TransferDirection {
+    Sent,
+    Received,
+}
+
+impl TransferDirection {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            TransferDirection::Sent => "sent",
+            TransferDirection::Received => "received",
+        }
+    }
+}
+
+/// Deltas and bytes moved in one direction
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TransferCounts {
+    pub deltas: i64,
+    /// Serialized size before compression
+    pub payload_bytes: i64,
+    /// Size on the network
+    pub wire_bytes: i64,
+}
+
+impl TransferCounts {
+    fn add(&mut self, other: TransferCounts) {
+        self.deltas += other.deltas;
+        self.payload_bytes += other.payload_bytes;
+        self.wire_bytes += other.wire_bytes;
+    }
+}
+
+/// What one sync session with one device moved, until it is saved
+#[derive(Debug, Clone)]
+pub struct SyncTransferSession {
+    pub session_id: String,
+    pub device_id: String,
+    pub transport: SyncTransport,
+    /// By space, entity type and direction
+    tallies: BTreeMap<(String, String, TransferDirection), TransferCounts>,
+}
+
+impl SyncTransferSession {
+    pub fn new(session_id: &str, device_id: &str, transport: SyncTransport) -> Self {
+        Self {
+            session_id: session_id.to_string(),
+            device_id: device_id.to_string(),
+            transport,
+            tallies: BTreeMap::new(),
+        }
+    }
+
+    /// Count deltas of one space as sent, by their serialized size. Call it
+    /// where the deltas are serialized for the transport; one that sends them
+    /// inside a larger message still gets each delta's share.
+    pub fn record_sent(&mut self, space_id: &str, deltas: &[SyncDelta]) -> Result<(), SyncError> {
+        self.record_deltas(space_id, deltas, TransferDirection::Sent)
+    }
+
+    /// Count deltas of one space that arrived, by their serialized size
+    pub fn record_received(
+        &mut self,
+        space_id: &str,
+        deltas: &[SyncDelta],
+    ) -> Result<(), SyncError> {
+        self.record_deltas(space_id, deltas, TransferDirection::Received)
+    }
+
+    /// Uncompressed transports put exactly the serialized deltas on the wire
+    fn record_deltas(
+        &mut self,
+        space_id: &str,
+        deltas: &[SyncDelta],
+        direction: TransferDirection,
+    ) -> Result<(), SyncError> {
+        for delta in deltas {
+            let size = serde_json::to_vec(delta)
+                .map_err(|e| SyncError::InvalidData(e.to_string()))?
+                .len();
+            self.record(space_id, &delta.entity_type, direction, size, size);
+        }
+        Ok(())
+    }
+
+    /// Count one delta whose payload was `payload_bytes` before compression
+    /// and `wire_bytes` after
+    pub fn record(
+        &mut self,
+        space_id: &str,
+        entity_type: &str,
+        direction: TransferDirection,
+        payload_bytes: usize,
+        wire_bytes: usize,
+    ) {
+        self.tallies
+            .entry((space_id.to_string(), entity_type.to_string(), direction))
+            .or_default()
+            .add(TransferCounts {
+                deltas: 1,
+                payload_bytes: payload_bytes as i64,
+                wire_bytes: wire_bytes as i64,
+            });
+    }
+
+    /// Everything moved in one direction so far
+    pub fn totals(&self, direction: TransferDirection) -> TransferCounts {
+        let mut totals = TransferCounts::default();
+        for ((_, _, d), counts) in &self.tallies {
+            if *d == direction {
+                totals.add(*counts);
+            }
+        }
+        totals
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tallies.is_empty()
+    }
+
+    /// Write the session's rows to `sync_transfer_stats`
+    pub fn save(&self, conn: &Connection) -> Result<(), SyncError> {
+        let now = chrono::Utc::now().timestamp();
+        let mut stmt = conn.prepare_cached(
+            "INSERT INTO sync_transfer_stats
+                (id, session_id, device_id, space_id, entity_type, direction, delta_count,
+                 payload_bytes, wire_bytes, transport, recorded_at)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11)",
+        )?;
+        for ((space_id, entity_type, direction), counts) in &self.tallies {
+            stmt.execute(rusqlite::params![
+                Ulid::new().to_string(),
+                self.session_id,
+                self.device_id,
+                space_id,
+                entity_type,
+                direction.as_str(),
+                counts.deltas,
+                counts.payload_bytes,
+                counts.wire_bytes,
+                self.transport.as_str(),
+                now,
+            ])?;
+        }
+        Ok(())
+    }
+}
+
+/// Transfers of one entity type or one device
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TransferBreakdown {
+    /// Entity type or device id
+    pub key: String,
+    pub sent: TransferCounts,
+    pub received: TransferCounts,
+}
+
+impl TransferBreakdown {
+    /// Bytes this entry put on the network both ways
+    pub fn wire_bytes(&self) -> i64 {
+        self.sent.wire_bytes + self.received.wire_bytes
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SyncTransferReport {
+    pub sessions: i64,
+    pub sent: TransferCounts,
+    pub received: TransferCounts,
+    /// Bytes compression kept off the network, both ways
+    pub compression_savings_bytes: i64,
+    /// Heaviest first
+    pub by_entity_type: Vec<TransferBreakdown>,
+    /// Heaviest first
+    pub by_device: Vec<TransferBreakdown>,
+}
+
+/// Transfers of a space's syncs recorded within `range` (Unix seconds)
+pub fn get_sync_transfer_report(
+    conn: &Connection,
+    space_id: &str,
+    range: TimeRange,
+) -> Result<SyncTransferReport, SyncError> {
+    if range.end <= range.start {
+        return Err(SyncError::InvalidData(format!(
+            "Empty time range {}..{}",
+            range.start, range.end
+        )));
+    }
+
+    let sessions: i64 = conn.query_row(
+        "SELECT COUNT(DISTINCT session_id) FROM sync_transfer_stats
+         WHERE space_id = ?1 AND recorded_at >= ?2 AND recorded_at < ?3",
+        rusqlite::params![space_id, range.start, range.end],
+        |row| row.get(0),
+    )?;
+
+    let mut report = SyncTransferReport {
+        sessions,
+        by_entity_type: breakdown(conn, "entity_type", space_id, range)?,
+        by_device: breakdown(conn, "device_id", space_id, range)?,
+        ..Default::default()
+    };
+    for entry in &report.by_entity_type {
+        report.sent.add(entry.sent);
+        report.received.add(entry.received);
+    }
+    report.compression_savings_bytes = report.sent.payload_bytes + report.received.payload_bytes
+        - report.sent.wire_bytes
+        - report.received.wire_bytes;
+    Ok(report)
+}
+
+/// `column` is one of our own column names, never user input
+fn breakdown(
+    conn: &Connection,
+    column: &str,
+    space_id: &str,
+    range: TimeRange,
+) -> Result<Vec<TransferBreakdown>, SyncError> {
+    let mut stmt = conn.prepare_cached(&format!(
+        "SELECT {column}, direction, SUM(delta_count), SUM(payload_bytes), SUM(wire_bytes)
+         FROM sync_transfer_stats
+         WHERE space_id = ?1 AND recorded_at >= ?2 AND recorded_at < ?3
+         GROUP BY {column}, direction",
+        column = column
+    ))?;
+    let rows = stmt
+        .query_map(rusqlite::params![space_id, range.start, range.end], |row| {
+            Ok((
+                row.get::<_, String>(0)?,
+                row.get::<_, String>(1)?,
+                TransferCounts {
+                    deltas: row.get(2)?,
+                    payload_bytes: row.get(3)?,
+                    wire_bytes: row.get(4)?,
+                },
+            ))
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let mut entries: BTreeMap<String, TransferBreakdown> = BTreeMap::new();
+    for (key, direction, counts) in rows {
+        let entry = entries
+            .entry(key.clone())
+            .or_insert_with(|| TransferBreakdown {
+                key,
+                ..Default::default()
+            });
+        if direction == TransferDirection::Sent.as_str() {
+            entry.sent.add(counts);
+        } else {
+            entry.received.add(counts);
+        }
+    }
+    let mut entries: Vec<_> = entries.into_values().collect();
+    entries.sort_by(|a, b| b.wire_bytes().cmp(&a.wire_bytes()).then(a.key.cmp(&b.key)));
+    Ok(entries)
+}
+
+/// Bytes put on the network by all of a space's syncs, as (sent, received)
+pub(crate) fn total_wire_bytes(conn: &Connection, space_id: &str) -> Result<(i64, i64), SyncError> {
+    Ok(conn.query_row(
+        "SELECT COALESCE(SUM(CASE WHEN direction = 'sent' THEN wire_bytes END), 0),
+                COALESCE(SUM(CASE WHEN direction = 'received' THEN wire_bytes END), 0)
+         FROM sync_transfer_stats WHERE space_id = ?1",
+        [space_id],
+        |row| Ok((row.get(0)?, row.get(1)?)),
+    )?)
+}
@@ -1,8 +1,32 @@
+use crate::audit::{audit_change, AuditOperation, AUDIT_SOURCE_LOCAL};
+use crate::automation::{run_automation_from, AutomationEvent};
 use crate::db::DbError;
-use rusqlite::Connection;
+use crate::note::{update_note_content, DbUlid};
+use rusqlite::{Connection, OptionalExtension};
 use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use thiserror::Error;
 use ulid::Ulid;
 
+/// Separates the levels of a hierarchical tag name, as in `work/meetings`
+pub const TAG_SEPARATOR: char = '/';
+
+#[derive(Error, Debug)]
+pub enum TagError {
+    #[error("Database error: {0}")]
+    Database(#[from] DbError),
+    #[error("Rusqlite error: {0}")]
+    Rusqlite(#[from] rusqlite::Error),
+    #[error("Tag not found: {0}")]
+    NotFound(String),
+    #[error("A tag named '{0}' already exists")]
+    NameTaken(String),
+    #[error("Invalid tag name: {0}")]
+    InvalidName(String),
+    #[error("Invalid merge: {0}")]
+    InvalidMerge(String),
+}
+
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct Tag {
     pub id: Ulid,
@@ -98,3 +122,512 @@ pub fn get_tags_with_counts(
 
     Ok(tags)
 }
+
+fn map_tag(row: &rusqlite::Row) -> rusqlite::Result<Tag> {
+    Ok(Tag {
+        id: Ulid::from_string(&row.get::<_, String>(0)?)
+            .map_err(|e| rusqlite::Error::ToSqlConversionFailure(Box::new(e)))?,
+        space_id: Ulid::from_string(&row.get::<_, String>(1)?)
+            .map_err(|e| rusqlite::Error::ToSqlConversionFailure(Box::new(e)))?,
+        name: row.get(2)?,
+        color: row.get(3)?,
+    })
+}
+
+pub fn get_tag(conn: &Connection, tag_id: &str) -> Result<Option<Tag>, DbError> {
+    let tag = conn
+        .query_row(
+            "SELECT id, space_id, name, color FROM tag WHERE id = ?1",
+            [tag_id],
+            map_tag,
+        )
+        .optional()?;
+    Ok(tag)
+}
+
+/// Trim a tag name and drop a leading `#`, rejecting empty levels such as
+/// `work//meetings` or a trailing `/`
+pub fn normalize_tag_name(name: &str) -> Result<String, TagError> {
+    let name = name.trim();
+    let name = name.strip_prefix('#').unwrap_or(name);
+    if name.is_empty()
+        || name
+            .split(TAG_SEPARATOR)
+            .any(|level| level.trim().is_empty() || level.trim() != level)
+    {
+        return Err(TagError::InvalidName(name.to_string()));
+    }
+    Ok(name.to_string())
+}
+
+/// Whether `name` is `parent` or nested anywhere below it
+pub(crate) fn is_same_or_descendant(name: &str, parent: &str) -> bool {
+    name == parent
+        || name
+            .strip_prefix(parent)
+            .is_some_and(|rest| rest.starts_with(TAG_SEPARATOR))
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct TagRenameReport {
+    pub tag: Tag,
+    /// The tag itself plus its renamed descendants
+    pub renamed_tags: usize,
+    /// Notes whose inline `#tags` were rewritten
+    pub updated_note_ids: Vec<String>,
+    /// Notes mentioning the old tag that could not be rewritten
+    pub notes_needing_edit: Vec<String>,
+}
+
+/// Rename a tag together with its descendants (`work` to `job` also turns
+/// `work/meetings` into `job/meetings`), then rewrite inline `#tags` in the
+/// space's note bodies. The tag rows change in one transaction; notes are
+/// updated one by one afterwards and reported in `notes_needing_edit` if
+/// that fails.
+pub fn rename_tag(
+    conn: &mut Connection,
+    tag_id: &str,
+    new_name: &str,
+) -> Result<TagRenameReport, TagError> {
+    let new_name = normalize_tag_name(new_name)?;
+    let tag = get_tag(conn, tag_id)?.ok_or_else(|| TagError::NotFound(tag_id.to_string()))?;
+    let old_name = tag.name.clone();
+    let space_id = tag.space_id.to_string();
+    log::info!("[tag] Renaming tag '{}' to '{}'", old_name, new_name);
+
+    let tx = conn.transaction()?;
+    let mut renames = Vec::new();
+    let mut others = HashSet::new();
+    {
+        let mut stmt = tx.prepare("SELECT id, name FROM tag WHERE space_id = ?1")?;
+        let rows = stmt.query_map([&space_id], |row| {
+            Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?))
+        })?;
+        for row in rows {
+            let (id, name) = row?;
+            if is_same_or_descendant(&name, &old_name) {
+                let renamed = format!("{}{}", new_name, &name[old_name.len()..]);
+                renames.push((id, renamed));
+            } else {
+                others.insert(name);
+            }
+        }
+    }
+    if let Some((_, taken)) = renames.iter().find(|(_, name)| others.contains(name)) {
+        return Err(TagError::NameTaken(taken.clone()));
+    }
+
+    // Park every row on its id first, so renames within the subtree (`a` to
+    // `a/b` while `a/b` exists) don't trip UNIQUE(space_id, name)
+    for (id, _) in &renames {
+        tx.execute(
+            "UPDATE tag SET name = ?1 WHERE id = ?1",
+            rusqlite::params![id],
+        )?;
+    }
+    for (id, name) in &renames {
+        tx.execute(
+            "UPDATE tag SET name = ?1 WHERE id = ?2",
+            rusqlite::params![name, id],
+        )?;
+    }
+    tx.commit()?;
+
+    let mut updated_note_ids = Vec::new();
+    let mut notes_needing_edit = Vec::new();
+    if old_name != new_name {
+        let notes: Vec<(String, String, String)> = {
+            let mut stmt = conn.prepare(
+                "SELECT id, title, content_md FROM note
+                 WHERE space_id = ?1 AND instr(content_md, ?2) > 0",
+            )?;
+            let rows = stmt.query_map(
+                rusqlite::params![space_id, format!("#{}", old_name)],
+                |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+            )?;
+            rows.collect::<Result<_, _>>()?
+        };
+        for (id, title, content) in notes {
+            let Some(rewritten) = rewrite_inline_tag(&content, &old_name, &new_name) else {
+                continue;
+            };
+            let updated = Ulid::from_string(&id)
+                .map_err(|e| DbError::Message(e.to_string()))
+                .and_then(|ulid| update_note_content(conn, DbUlid(ulid), &title, &rewritten));
+            match updated {
+                Ok(()) => updated_note_ids.push(id),
+                Err(e) => {
+                    log::warn!(
+                        "[tag] Could not rewrite #{} in note {}: {}",
+                        old_name,
+                        id,
+                        e
+                    );
+                    notes_needing_edit.push(id);
+                }
+            }
+        }
+    }
+
+    Ok(TagRenameReport {
+        tag: Tag {
+            name: new_name,
+            ..tag
+        },
+        renamed_tags: renames.len(),
+        updated_note_ids,
+        notes_needing_edit,
+    })
+}
+
+fn is_tag_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_' || c == '-'
+}
+
+/// Replace `#old` and `#old/...` with `#new`, leaving longer tags such as
+/// `#oldest` alone. `None` when nothing changed.
+fn rewrite_inline_tag(content: &str, old: &str, new: &str) -> Option<String> {
+    let needle = format!("#{}", old);
+    let mut out = String::with_capacity(content.len());
+    let mut rest = content;
+    let mut changed = false;
+    while let Some(pos) = rest.find(&needle) {
+        let (before, after) = (&rest[..pos], &rest[pos + needle.len()..]);
+        let starts_tag = before
+            .chars()
+            .next_back()
+            .or_else(|| out.chars().next_back())
+            .is_none_or(|c| !is_tag_char(c) && c != '#' && c != TAG_SEPARATOR);
+        let ends_tag = after.chars().next().is_none_or(|c| !is_tag_char(c));
+        out.push_str(before);
+        if starts_tag && ends_tag {
+            out.push('#');
+            out.push_str(new);
+            changed = true;
+        } else {
+            out.push_str(&needle);
+        }
+        rest = after;
+    }
+    out.push_str(rest);
+    changed.then_some(out)
+}
+
+/// Fold `source_ids` into `target_id`: every note and task tagged with a
+/// source ends up tagged with the target once, then the sources are deleted
+pub fn merge_tags(
+    conn: &mut Connection,
+    source_ids: &[String],
+    target_id: &str,
+) -> Result<Tag, TagError> {
+    let target =
+        get_tag(conn, target_id)?.ok_or_else(|| TagError::NotFound(target_id.to_string()))?;
+    if source_ids.is_empty() {
+        return Err(TagError::InvalidMerge("no source tags".to_string()));
+    }
+    for source_id in source_ids {
+        if source_id == target_id {
+            return Err(TagError::InvalidMerge(
+                "a tag cannot be merged into itself".to_string(),
+            ));
+        }
+        let source =
+            get_tag(conn, source_id)?.ok_or_else(|| TagError::NotFound(source_id.clone()))?;
+        if source.space_id != target.space_id {
+            return Err(TagError::InvalidMerge(format!(
+                "tag '{}' belongs to another space",
+                source.name
+            )));
+        }
+    }
+    log::info!(
+        "[tag] Merging {} tags into '{}'",
+        source_ids.len(),
+        target.name
+    );
+
+    let tx = conn.transaction()?;
+    for source_id in source_ids {
+        // OR IGNORE keeps a single row where an item had both tags
+        tx.execute(
+            "INSERT OR IGNORE INTO note_tags (note_id, tag_id)
+             SELECT note_id, ?2 FROM note_tags WHERE tag_id = ?1",
+            rusqlite::params![source_id, target_id],
+        )?;
+        tx.execute(
+            "INSERT OR IGNORE INTO task_tags (task_id, tag_id)
+             SELECT task_id, ?2 FROM task_tags WHERE tag_id = ?1",
+            rusqlite::params![source_id, target_id],
+        )?;
+        tx.execute("DELETE FROM note_tags WHERE tag_id = ?1", [source_id])?;
+        tx.execute("DELETE FROM task_tags WHERE tag_id = ?1", [source_id])?;
+        tx.execute("DELETE FROM tag WHERE id = ?1", [source_id])?;
+    }
+    tx.commit()?;
+
+    Ok(target)
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct TagUsage {
+    pub tag: Tag,
+    pub note_count: i64,
+    pub task_count: i64,
+    /// Distinct notes tagged with this tag or any tag nested below it
+    pub total_note_count: i64,
+}
+
+/// Note ids per tag name, credited to the tag and every ancestor level
+fn notes_by_tag_path(
+    conn: &Connection,
+    space_id: Ulid,
+) -> Result<HashMap<String, HashSet<String>>, DbError> {
+    let mut stmt = conn.prepare(
+        "SELECT t.name, nt.note_id FROM tag t
+         JOIN note_tags nt ON nt.tag_id = t.id
+         WHERE t.space_id = ?1",
+    )?;
+    let rows = stmt.query_map([space_id.to_string()], |row| {
+        Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?))
+    })?;
+    let mut notes: HashMap<String, HashSet<String>> = HashMap::new();
+    for row in rows {
+        let (name, note_id) = row?;
+        for (end, _) in name
+            .match_indices(TAG_SEPARATOR)
+            .chain(std::iter::once((name.len(), "")))
+        {
+            notes
+                .entry(name[..end].to_string())
+                .or_default()
+                .insert(note_id.clone());
+        }
+    }
+    Ok(notes)
+}
+
+pub fn get_tag_usage_counts(conn: &Connection, space_id: Ulid) -> Result<Vec<TagUsage>, DbError> {
+    let mut stmt = conn.prepare(
+        "SELECT t.id, t.space_id, t.name, t.color,
+                (SELECT COUNT(*) FROM note_tags nt WHERE nt.tag_id = t.id),
+                (SELECT COUNT(*) FROM task_tags tt WHERE tt.tag_id = t.id)
+         FROM tag t
+         WHERE t.space_id = ?1
+         ORDER BY t.name",
+    )?;
+    let rows = stmt
+        .query_map([space_id.to_string()], |row| {
+            Ok((map_tag(row)?, row.get::<_, i64>(4)?, row.get::<_, i64>(5)?))
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let notes = notes_by_tag_path(conn, space_id)?;
+    Ok(rows
+        .into_iter()
+        .map(|(tag, note_count, task_count)| TagUsage {
+            total_note_count: notes.get(&tag.name).map_or(0, |n| n.len() as i64),
+            tag,
+            note_count,
+            task_count,
+        })
+        .collect())
+}
+
+/// One level of the tag hierarchy
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct TagNode {
+    /// This level's part of the name, e.g. `meetings` in `work/meetings`
+    pub segment: String,
+    pub path: String,
+    /// `None` for a level that only exists as the parent of other tags
+    pub tag: Option<Tag>,
+    pub note_count: i64,
+    pub total_note_count: i64,
+    pub children: Vec<TagNode>,
+}
+
+#[derive(Default)]
+struct TreeBuilder {
+    tag: Option<Tag>,
+    children: BTreeMap<String, TreeBuilder>,
+}
+
+impl TreeBuilder {
+    fn into_nodes(
+        self,
+        prefix: &str,
+        notes: &HashMap<String, HashSet<String>>,
+        counts: &HashMap<String, i64>,
+    ) -> Vec<TagNode> {
+        self.children
+            .into_iter()
+            .map(|(segment, child)| {
+                let path = if prefix.is_empty() {
+                    segment.clone()
+                } else {
+                    format!("{}{}{}", prefix, TAG_SEPARATOR, segment)
+                };
+                TagNode {
+                    note_count: counts.get(&path).copied().unwrap_or(0),
+                    total_note_count: notes.get(&path).map_or(0, |n| n.len() as i64),
+                    children: TreeBuilder {
+                        tag: None,
+                        children: child.children,
+                    }
+                    .into_nodes(&path, notes, counts),
+                    tag: child.tag,
+                    segment,
+                    path,
+                }
+            })
+            .collect()
+    }
+}
+
+/// The space's tags as a tree built from their `/`-separated names, sorted
+/// by name at every level
+pub fn get_tag_tree(conn: &Connection, space_id: Ulid) -> Result<Vec<TagNode>, DbError> {
+    let usage = get_tag_usage_counts(conn, space_id)?;
+    let notes = notes_by_tag_path(conn, space_id)?;
+    let counts: HashMap<String, i64> = usage
+        .iter()
+        .map(|u| (u.tag.name.clone(), u.note_count))
+        .collect();
+
+    let mut root = TreeBuilder::default();
+    for TagUsage { tag, .. } in usage {
+        let mut node = &mut root;
+        for segment in tag.name.split(TAG_SEPARATOR) {
+            node = node.children.entry(segment.to_string()).or_default();
+        }
+        node.tag = Some(tag);
+    }
+    Ok(root.into_nodes("", &notes, &counts))
+}
+
+/// Notes in the space tagged with `tag_name`, or with any tag nested below
+/// it when `include_descendants` is set
+pub fn get_notes_with_tag(
+    conn: &Connection,
+    space_id: Ulid,
+    tag_name: &str,
+    include_descendants: bool,
+) -> Result<Vec<crate::note::Note>, DbError> {
+    let mut stmt = conn.prepare(
+        "SELECT DISTINCT n.id, n.space_id, n.title, n.content_md, n.created_at, n.modified_at, n.is_trashed, n.is_locked
+         FROM note n
+         JOIN note_tags nt ON nt.note_id = n.id
+         JOIN tag t ON t.id = nt.tag_id
+         WHERE n.space_id = ?1 AND n.is_trashed = 0
+           AND (t.name = ?2 OR (?3 AND substr(t.name, 1, length(?4)) = ?4))
+         ORDER BY n.modified_at DESC",
+    )?;
+    let notes = stmt
+        .query_map(
+            rusqlite::params![
+                space_id.to_string(),
+                tag_name,
+                include_descendants,
+                format!("{}{}", tag_name, TAG_SEPARATOR)
+            ],
+            crate::note::Note::from_row,
+        )?
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(notes)
+}
+
+/// Tag a note, creating the tag in the note's space if it doesn't exist.
+/// Returns whether the note didn't have the tag yet; only then do the
+/// space's automation rules run.
+pub fn add_tag_to_note(conn: &Connection, note_id: &str, name: &str) -> Result<bool, TagError> {
+    add_tag_to_note_from(conn, note_id, name, AUDIT_SOURCE_LOCAL)
+}
+
+/// [`add_tag_to_note`] for a change made by `source`
+pub(crate) fn add_tag_to_note_from(
+    conn: &Connection,
+    note_id: &str,
+    name: &str,
+    source: &str,
+) -> Result<bool, TagError> {
+    let (name, added) = attach_tag(conn, "note", note_id, name, source)?;
+    if added {
+        run_automation_from(
+            conn,
+            AutomationEvent::TagAdded {
+                note_id: note_id.to_string(),
+                tag: name,
+            },
+            source,
+        );
+    }
+    Ok(added)
+}
+
+/// Tag a task, creating the tag in the task's space if it doesn't exist.
+/// Returns whether the task didn't have the tag yet.
+pub fn add_tag_to_task(conn: &Connection, task_id: &str, name: &str) -> Result<bool, TagError> {
+    add_tag_to_task_from(conn, task_id, name, AUDIT_SOURCE_LOCAL)
+}
+
+/// [`add_tag_to_task`] for a change made by `source`
+pub(crate) fn add_tag_to_task_from(
+    conn: &Connection,
+    task_id: &str,
+    name: &str,
+    source: &str,
+) -> Result<bool, TagError> {
+    Ok(attach_tag(conn, "task", task_id, name, source)?.1)
+}
+
+/// Link a note or task to the tag named `name` in its space. Returns the
+/// normalized name and whether the link is new.
+fn attach_tag(
+    conn: &Connection,
+    entity_type: &'static str,
+    entity_id: &str,
+    name: &str,
+    source: &str,
+) -> Result<(String, bool), TagError> {
+    let name = normalize_tag_name(name)?;
+    let space_id: String = conn
+        .query_row(
+            &format!("SELECT space_id FROM {} WHERE id = ?1", entity_type),
+            [entity_id],
+            |row| row.get(0),
+        )
+        .optional()?
+        .ok_or_else(|| DbError::NotFound {
+            entity: entity_type,
+            id: entity_id.to_string(),
+        })?;
+    let existing: Option<String> = conn
+        .query_row(
+            "SELECT id FROM tag WHERE space_id = ?1 AND name = ?2",
+            [&space_id, &name],
+            |row| row.get(0),
+        )
+        .optional()?;
+    let tag_id = match existing {
+        Some(id) => id,
+        None => create_tag(conn, &space_id, &name, None)?.id.to_string(),
+    };
+    let added = conn.execute(
+        &format!(
+            "INSERT OR IGNORE INTO {0}_tags ({0}_id, tag_id) VALUES (?1, ?2)",
+            entity_type
+        ),
+        [entity_id, &tag_id],
+    )? > 0;
+    if added {
+        audit_change(
+            conn,
+            &space_id,
+            entity_type,
+            entity_id,
+            AuditOperation::Update,
+            source,
+        );
+    }
+    Ok((name, added))
+}
@@ -1,6 +1,10 @@
 use super::models::Task;
-use crate::audit;
+use crate::audit::{audit_change, AuditOperation, AUDIT_SOURCE_LOCAL};
+use crate::automation::{run_automation_from, AutomationEvent};
+use crate::collaboration::check_edit_lock;
 use crate::db::DbError;
+use crate::permission::{authorize, ActorContext, PERMISSION_DELETE, PERMISSION_WRITE};
+use crate::undo::{capture_rows, record_undo, UndoRecord, UndoSnapshot};
 // use chrono::TimeZone;
 use rusqlite::{Connection, OptionalExtension, Result};
 use ulid::Ulid;
@@ -10,6 +14,17 @@ pub fn create_task(
     space_id: Ulid,
     title: &str,
     description: Option<String>,
+) -> Result<Task, DbError> {
+    create_task_from(conn, space_id, title, description, AUDIT_SOURCE_LOCAL)
+}
+
+/// [`create_task`] for a change made by `source`
+pub(crate) fn create_task_from(
+    conn: &Connection,
+    space_id: Ulid,
+    title: &str,
+    description: Option<String>,
+    source: &str,
 ) -> Result<Task, DbError> {
     log::info!("[task] Creating task with title: {}", title);
     let now = chrono::Utc::now().timestamp();
@@ -45,15 +60,13 @@ pub fn create_task(
         ],
     )?;
 
-    let _ = audit::log_event(
+    audit_change(
         conn,
-        None,
-        "TASK_CREATED",
+        &task.space_id.to_string(),
         "task",
-        Some(&task.id.to_string()),
-        Some(&format!(r#"{{"title": "{}"}}"#, task.title)),
-        None,
-        None,
+        &task.id.to_string(),
+        AuditOperation::Create,
+        source,
     );
 
     Ok(task)
@@ -61,7 +74,7 @@ pub fn create_task(
 
 pub fn get_task(conn: &Connection, id: Ulid) -> Result<Option<Task>, DbError> {
     log::info!("[task] Getting task with id: {}", id);
-    let mut stmt = conn.prepare("SELECT id, space_id, note_id, project_id, parent_task_id, title, description, status, due_at, start_at, completed_at, priority, estimate_minutes, recur_rule, context, area, updated_at FROM task WHERE id = ?1")?;
+    let mut stmt = conn.prepare_cached("SELECT id, space_id, note_id, project_id, parent_task_id, title, description, status, due_at, start_at, completed_at, priority, estimate_minutes, recur_rule, context, area, updated_at FROM task WHERE id = ?1")?;
     let task: Option<Task> = stmt
         .query_row([id.to_string()], |row| Task::try_from(row))
         .optional()?;
@@ -73,8 +86,26 @@ pub fn get_task(conn: &Connection, id: Ulid) -> Result<Option<Task>, DbError> {
     Ok(task)
 }
 
+/// Save a task. Marking it done runs the space's task completion rules.
 pub fn update_task(conn: &Connection, task: &Task) -> Result<(), DbError> {
+    update_task_from(conn, task, AUDIT_SOURCE_LOCAL)
+}
+
+/// [`update_task`] for a change made by `source`
+pub(crate) fn update_task_from(
+    conn: &Connection,
+    task: &Task,
+    source: &str,
+) -> Result<(), DbError> {
     log::info!("[task] Updating task with id: {}", task.id);
+    let was_done: bool = conn
+        .query_row(
+            "SELECT status = 'done' FROM task WHERE id = ?1",
+            [task.id.to_string()],
+            |row| row.get(0),
+        )
+        .optional()?
+        .unwrap_or(false);
     let now = chrono::Utc::now().timestamp();
     conn.execute(
         "UPDATE task SET note_id = ?1, project_id = ?2, parent_task_id = ?3, title = ?4, description = ?5, status = ?6, due_at = ?7, start_at = ?8, completed_at = ?9, priority = ?10, estimate_minutes = ?11, recur_rule = ?12, context = ?13, area = ?14, updated_at = ?15 WHERE id = ?16",
@@ -97,16 +128,33 @@ pub fn update_task(conn: &Connection, task: &Task) -> Result<(), DbError> {
             &task.id.to_string(),
         ],
     )?;
+    audit_change(
+        conn,
+        &task.space_id.to_string(),
+        "task",
+        &task.id.to_string(),
+        AuditOperation::Update,
+        source,
+    );
 
     // Handle recurrence if task is marked as done
     if task.status == "done" && task.recur_rule.is_some() {
         handle_recurrence(conn, task)?;
     }
+    if task.status == "done" && !was_done {
+        run_automation_from(
+            conn,
+            AutomationEvent::TaskCompleted {
+                task_id: task.id.to_string(),
+            },
+            source,
+        );
+    }
 
     Ok(())
 }
 
-fn handle_recurrence(conn: &Connection, task: &Task) -> Result<(), DbError> {
+pub(crate) fn handle_recurrence(conn: &Connection, task: &Task) -> Result<(), DbError> {
     if let Some(rule) = &task.recur_rule {
         // Prevent duplicate recurrence: Check if a child task already exists
         let count: i64 = conn
@@ -198,42 +246,148 @@ fn handle_recurrence(conn: &Connection, task: &Task) -> Result<(), DbError> {
                 ],
             )?;
 
-            let _ = audit::log_event(
+            audit_change(
                 conn,
-                None,
-                "TASK_RECURRENCE_CREATED",
+                &new_task.space_id.to_string(),
                 "task",
-                Some(&new_task.id.to_string()),
-                Some(&format!(r#"{{"parent_id": "{}"}}"#, task.id)),
-                None,
-                None,
+                &new_task.id.to_string(),
+                AuditOperation::Create,
+                AUDIT_SOURCE_LOCAL,
             );
         }
     }
     Ok(())
 }
 
+/// Delete a task along with its tags, exdates, people and time entries.
+/// Subtasks and meeting action items are detached rather than deleted. The
+/// removed rows are journaled so the delete can be undone.
 pub fn delete_task(conn: &Connection, id: Ulid) -> Result<(), DbError> {
     log::info!("[task] Deleting task with id: {}", id);
-    conn.execute("DELETE FROM task WHERE id = ?1", [id.to_string()])?;
+    let id = id.to_string();
+    let task: Option<(String, String)> = conn
+        .query_row(
+            "SELECT space_id, title FROM task WHERE id = ?1",
+            [&id],
+            |row| Ok((row.get(0)?, row.get(1)?)),
+        )
+        .optional()?;
 
-    let _ = audit::log_event(
-        conn,
-        None,
-        "TASK_DELETED",
+    let tx = conn.unchecked_transaction()?;
+    let mut snapshot = UndoSnapshot::default();
+    for (table, filter) in [
+        ("task", "id = ?1"),
+        ("task_tags", "task_id = ?1"),
+        ("task_recur_exdate", "task_id = ?1"),
+        ("task_people", "task_id = ?1"),
+        ("time_entry", "task_id = ?1"),
+    ] {
+        snapshot
+            .deleted
+            .extend(capture_rows(&tx, table, "*", filter, [&id])?);
+    }
+    snapshot.modified.extend(capture_rows(
+        &tx,
         "task",
-        Some(&id.to_string()),
-        None,
-        None,
-        None,
-    );
+        "id, parent_task_id",
+        "parent_task_id = ?1",
+        [&id],
+    )?);
+    snapshot.modified.extend(capture_rows(
+        &tx,
+        "meeting_action",
+        "note_id, line_hash, task_id",
+        "task_id = ?1",
+        [&id],
+    )?);
+
+    for table in [
+        "task_tags",
+        "task_recur_exdate",
+        "task_people",
+        "time_entry",
+    ] {
+        tx.execute(&format!("DELETE FROM {} WHERE task_id = ?1", table), [&id])?;
+    }
+    tx.execute(
+        "UPDATE task SET parent_task_id = NULL WHERE parent_task_id = ?1",
+        [&id],
+    )?;
+    tx.execute(
+        "UPDATE meeting_action SET task_id = NULL WHERE task_id = ?1",
+        [&id],
+    )?;
+    tx.execute("DELETE FROM task WHERE id = ?1", [&id])?;
+
+    if let Some((space_id, title)) = task {
+        audit_change(
+            &tx,
+            &space_id,
+            "task",
+            &id,
+            AuditOperation::Delete,
+            AUDIT_SOURCE_LOCAL,
+        );
+        record_undo(
+            &tx,
+            UndoRecord {
+                space_id: &space_id,
+                operation: "delete_task",
+                entity_type: "task",
+                entity_id: &id,
+                summary: format!("Deleted task \"{}\"", title),
+                snapshot,
+            },
+        );
+    }
+    tx.commit()?;
 
     Ok(())
 }
 
+/// [`create_task`] on behalf of `actor`, who needs `write_notes` in the space
+pub fn create_task_as(
+    conn: &Connection,
+    actor: &ActorContext,
+    space_id: Ulid,
+    title: &str,
+    description: Option<String>,
+) -> Result<Task, DbError> {
+    authorize::<DbError>(conn, actor, &space_id.to_string(), PERMISSION_WRITE)?;
+    create_task(conn, space_id, title, description)
+}
+
+/// [`update_task`] on behalf of `actor`, who needs `write_notes` in the
+/// task's space and must not be locked out by another user's edit lock
+pub fn update_task_as(conn: &Connection, actor: &ActorContext, task: &Task) -> Result<(), DbError> {
+    let space_id = task_space_id(conn, task.id)?.unwrap_or_else(|| task.space_id.to_string());
+    authorize::<DbError>(conn, actor, &space_id, PERMISSION_WRITE)?;
+    check_edit_lock::<DbError>(conn, actor, &task.id.to_string())?;
+    update_task(conn, task)
+}
+
+/// [`delete_task`] on behalf of `actor`, who needs `delete_notes` in the
+/// task's space
+pub fn delete_task_as(conn: &Connection, actor: &ActorContext, id: Ulid) -> Result<(), DbError> {
+    if let Some(space_id) = task_space_id(conn, id)? {
+        authorize::<DbError>(conn, actor, &space_id, PERMISSION_DELETE)?;
+    }
+    delete_task(conn, id)
+}
+
+fn task_space_id(conn: &Connection, id: Ulid) -> Result<Option<String>, DbError> {
+    Ok(conn
+        .query_row(
+            "SELECT space_id FROM task WHERE id = ?1",
+            [id.to_string()],
+            |row| row.get(0),
+        )
+        .optional()?)
+}
+
 pub fn get_tasks_by_project(conn: &Connection, project_id: Ulid) -> Result<Vec<Task>, DbError> {
     log::info!("[task] Getting tasks for project with id: {}", project_id);
-    let mut stmt = conn.prepare("SELECT id, space_id, note_id, project_id, parent_task_id, title, description, status, due_at, start_at, completed_at, priority, estimate_minutes, recur_rule, context, area, updated_at FROM task WHERE project_id = ?1")?;
+    let mut stmt = conn.prepare_cached("SELECT id, space_id, note_id, project_id, parent_task_id, title, description, status, due_at, start_at, completed_at, priority, estimate_minutes, recur_rule, context, area, updated_at FROM task WHERE project_id = ?1")?;
     let tasks = stmt
         .query_map([project_id.to_string()], |row| Task::try_from(row))?
         .collect::<Result<Vec<Task>, _>>()?;
@@ -250,7 +404,7 @@ pub fn get_upcoming_tasks(
         "[task] Getting upcoming tasks for space with id: {}",
         space_id
     );
-    let mut stmt = conn.prepare("SELECT id, space_id, note_id, project_id, parent_task_id, title, description, status, due_at, start_at, completed_at, priority, estimate_minutes, recur_rule, context, area, updated_at FROM task WHERE space_id = ?1 AND due_at IS NOT NULL AND status != 'done' ORDER BY due_at ASC LIMIT ?2")?;
+    let mut stmt = conn.prepare_cached("SELECT id, space_id, note_id, project_id, parent_task_id, title, description, status, due_at, start_at, completed_at, priority, estimate_minutes, recur_rule, context, area, updated_at FROM task WHERE space_id = ?1 AND due_at IS NOT NULL AND status != 'done' ORDER BY due_at ASC LIMIT ?2")?;
     let tasks = stmt
         .query_map([space_id.to_string(), limit.to_string()], |row| {
             Task::try_from(row)
@@ -262,7 +416,7 @@ pub fn get_upcoming_tasks(
 
 pub fn get_all_tasks_in_space(conn: &Connection, space_id: Ulid) -> Result<Vec<Task>, DbError> {
     log::info!("[task] Getting all tasks for space with id: {}", space_id);
-    let mut stmt = conn.prepare("SELECT id, space_id, note_id, project_id, parent_task_id, title, description, status, due_at, start_at, completed_at, priority, estimate_minutes, recur_rule, context, area, updated_at FROM task WHERE space_id = ?1")?;
+    let mut stmt = conn.prepare_cached("SELECT id, space_id, note_id, project_id, parent_task_id, title, description, status, due_at, start_at, completed_at, priority, estimate_minutes, recur_rule, context, area, updated_at FROM task WHERE space_id = ?1")?;
     let tasks = stmt
         .query_map([space_id.to_string()], |row| Task::try_from(row))?
         .collect::<Result<Vec<Task>, _>>()?;
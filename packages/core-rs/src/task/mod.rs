@@ -1,7 +1,9 @@
 pub mod db;
 pub mod models;
 pub mod query;
+pub mod views;
 
 pub use db::*;
 pub use models::*;
 pub use query::*;
+pub use views::*;
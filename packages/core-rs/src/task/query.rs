@@ -1,3 +1,11 @@
+use super::models::Task;
+use crate::db::{placeholders, DbError};
+use crate::search::query::like_pattern;
+use crate::search::SortDirection;
+use rusqlite::{params_from_iter, Connection, ToSql};
+use serde::{Deserialize, Serialize};
+use ulid::Ulid;
+
 /// Builder for complex task queries
 #[derive(Default)]
 pub struct TaskQueryBuilder {
@@ -61,3 +69,193 @@ impl TaskQueryBuilder {
         format!("WHERE {}", conditions.join(" AND "))
     }
 }
+
+/// Filter for building custom task views. Every set dimension narrows the
+/// result; list dimensions match any of their values and empty lists don't
+/// filter at all, so `TaskFilter::default()` matches every task in the space.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TaskFilter {
+    pub statuses: Vec<String>,
+    /// Priorities run from 1 (highest) to 4; tasks without one never match
+    /// a priority bound
+    pub min_priority: Option<i64>,
+    pub max_priority: Option<i64>,
+    /// Inclusive lower bound on `due_at`
+    pub due_after: Option<i64>,
+    /// Exclusive upper bound on `due_at`
+    pub due_before: Option<i64>,
+    pub project_ids: Vec<Ulid>,
+    pub tag_ids: Vec<Ulid>,
+    pub has_due_date: Option<bool>,
+    /// Case-insensitive substring of the title or description
+    pub text: Option<String>,
+    /// A task is blocked when it is waiting, or its project is blocked or
+    /// depends on a project that isn't done or archived yet
+    pub blocked: Option<bool>,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum TaskSortField {
+    /// Tasks without a due date come last in either direction
+    #[default]
+    DueAt,
+    /// Tasks without a priority come last in either direction
+    Priority,
+    UpdatedAt,
+    Title,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TaskSort {
+    pub field: TaskSortField,
+    pub direction: SortDirection,
+}
+
+impl Default for TaskSort {
+    fn default() -> Self {
+        Self {
+            field: TaskSortField::DueAt,
+            direction: SortDirection::Asc,
+        }
+    }
+}
+
+const BLOCKED_SQL: &str = "(t.status = 'waiting' OR EXISTS (
+    SELECT 1 FROM project p
+    WHERE p.id = t.project_id
+      AND (p.status = 'blocked' OR EXISTS (
+          SELECT 1 FROM project_dependency d
+          JOIN project dp ON dp.id = d.depends_on_project_id
+          WHERE d.project_id = p.id AND dp.status NOT IN ('done', 'archived')
+      ))
+))";
+
+/// Compiles the filter into a WHERE clause over `task t` and its parameters
+fn filter_clause(space_id: Ulid, filter: &TaskFilter) -> (String, Vec<Box<dyn ToSql>>) {
+    let mut conditions = vec!["t.space_id = ?".to_string()];
+    let mut params: Vec<Box<dyn ToSql>> = vec![Box::new(space_id.to_string())];
+
+    if !filter.statuses.is_empty() {
+        conditions.push(format!(
+            "t.status IN ({})",
+            placeholders(filter.statuses.len())
+        ));
+        for status in &filter.statuses {
+            params.push(Box::new(status.clone()));
+        }
+    }
+    if let Some(min) = filter.min_priority {
+        conditions.push("t.priority >= ?".to_string());
+        params.push(Box::new(min));
+    }
+    if let Some(max) = filter.max_priority {
+        conditions.push("t.priority <= ?".to_string());
+        params.push(Box::new(max));
+    }
+    if let Some(after) = filter.due_after {
+        conditions.push("t.due_at >= ?".to_string());
+        params.push(Box::new(after));
+    }
+    if let Some(before) = filter.due_before {
+        conditions.push("t.due_at < ?".to_string());
+        params.push(Box::new(before));
+    }
+    if !filter.project_ids.is_empty() {
+        conditions.push(format!(
+            "t.project_id IN ({})",
+            placeholders(filter.project_ids.len())
+        ));
+        for id in &filter.project_ids {
+            params.push(Box::new(id.to_string()));
+        }
+    }
+    if !filter.tag_ids.is_empty() {
+        // EXISTS rather than a join so a task with several matching tags is
+        // still returned once
+        conditions.push(format!(
+            "EXISTS (SELECT 1 FROM task_tags tt WHERE tt.task_id = t.id AND tt.tag_id IN ({}))",
+            placeholders(filter.tag_ids.len())
+        ));
+        for id in &filter.tag_ids {
+            params.push(Box::new(id.to_string()));
+        }
+    }
+    match filter.has_due_date {
+        Some(true) => conditions.push("t.due_at IS NOT NULL".to_string()),
+        Some(false) => conditions.push("t.due_at IS NULL".to_string()),
+        None => {}
+    }
+    if let Some(text) = filter.text.as_deref().filter(|t| !t.trim().is_empty()) {
+        conditions.push(
+            "(t.title LIKE ? ESCAPE '\\' OR COALESCE(t.description, '') LIKE ? ESCAPE '\\')"
+                .to_string(),
+        );
+        let pattern = like_pattern(text.trim());
+        params.push(Box::new(pattern.clone()));
+        params.push(Box::new(pattern));
+    }
+    match filter.blocked {
+        Some(true) => conditions.push(BLOCKED_SQL.to_string()),
+        Some(false) => conditions.push(format!("NOT {}", BLOCKED_SQL)),
+        None => {}
+    }
+
+    (format!("WHERE {}", conditions.join(" AND ")), params)
+}
+
+fn order_clause(sort: &TaskSort) -> String {
+    let direction = match sort.direction {
+        SortDirection::Asc => "ASC",
+        SortDirection::Desc => "DESC",
+    };
+    let order = match sort.field {
+        TaskSortField::DueAt => format!("t.due_at IS NULL, t.due_at {}", direction),
+        TaskSortField::Priority => format!("t.priority IS NULL, t.priority {}", direction),
+        TaskSortField::UpdatedAt => format!("t.updated_at {}", direction),
+        TaskSortField::Title => format!("t.title COLLATE NOCASE {}", direction),
+    };
+    // The id keeps pages stable when the sort key ties
+    format!("ORDER BY {}, t.id ASC", order)
+}
+
+/// Tasks in the space matching the filter, as a single parameterized query
+pub fn query_tasks(
+    conn: &Connection,
+    space_id: Ulid,
+    filter: &TaskFilter,
+    sort: &TaskSort,
+    limit: Option<u32>,
+    offset: Option<u32>,
+) -> Result<Vec<Task>, DbError> {
+    let (where_clause, mut params) = filter_clause(space_id, filter);
+    let mut sql = format!(
+        "SELECT t.id, t.space_id, t.note_id, t.project_id, t.parent_task_id, t.title, t.description, t.status, t.due_at, t.start_at, t.completed_at, t.priority, t.estimate_minutes, t.recur_rule, t.context, t.area, t.updated_at
+         FROM task t {} {}",
+        where_clause,
+        order_clause(sort)
+    );
+    if limit.is_some() || offset.is_some() {
+        sql.push_str(" LIMIT ? OFFSET ?");
+        params.push(Box::new(limit.map_or(-1, i64::from)));
+        params.push(Box::new(i64::from(offset.unwrap_or(0))));
+    }
+
+    let mut stmt = conn.prepare(&sql)?;
+    let tasks = stmt
+        .query_map(params_from_iter(params.iter()), |row| Task::try_from(row))?
+        .collect::<Result<Vec<Task>, _>>()?;
+    log::debug!("[task] Filter matched {} tasks", tasks.len());
+    Ok(tasks)
+}
+
+/// Number of tasks in the space matching the filter, for badges
+pub fn count_tasks(conn: &Connection, space_id: Ulid, filter: &TaskFilter) -> Result<u64, DbError> {
+    let (where_clause, params) = filter_clause(space_id, filter);
+    let count: i64 = conn.query_row(
+        &format!("SELECT COUNT(*) FROM task t {}", where_clause),
+        params_from_iter(params.iter()),
+        |row| row.get(0),
+    )?;
+    Ok(count as u64)
+}
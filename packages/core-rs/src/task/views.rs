@@ -0,0 +1,126 @@
+use super::query::{TaskFilter, TaskSort};
+use crate::db::DbError;
+use rusqlite::{Connection, OptionalExtension, Result, Row};
+use serde::{Deserialize, Serialize};
+use ulid::Ulid;
+
+/// A named task filter saved for reuse, like a saved search for notes
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TaskView {
+    pub id: String,
+    pub space_id: String,
+    pub name: String,
+    pub filter: TaskFilter,
+    pub sort: TaskSort,
+    pub created_at: i64,
+    pub updated_at: i64,
+}
+
+const TASK_VIEW_COLUMNS: &str =
+    "id, space_id, name, filter_json, sort_json, created_at, updated_at";
+
+fn json_column<T: serde::de::DeserializeOwned>(row: &Row, idx: usize) -> Result<T> {
+    let json: String = row.get(idx)?;
+    serde_json::from_str(&json).map_err(|e| {
+        rusqlite::Error::FromSqlConversionFailure(idx, rusqlite::types::Type::Text, Box::new(e))
+    })
+}
+
+fn task_view_from_row(row: &Row) -> Result<TaskView> {
+    Ok(TaskView {
+        id: row.get(0)?,
+        space_id: row.get(1)?,
+        name: row.get(2)?,
+        filter: json_column(row, 3)?,
+        sort: json_column(row, 4)?,
+        created_at: row.get(5)?,
+        updated_at: row.get(6)?,
+    })
+}
+
+fn to_json<T: Serialize>(value: &T) -> Result<String, DbError> {
+    serde_json::to_string(value).map_err(|e| DbError::Message(e.to_string()))
+}
+
+pub fn create_task_view(
+    conn: &Connection,
+    space_id: Ulid,
+    name: &str,
+    filter: &TaskFilter,
+    sort: &TaskSort,
+) -> Result<TaskView, DbError> {
+    let now = chrono::Utc::now().timestamp();
+    let view = TaskView {
+        id: Ulid::new().to_string(),
+        space_id: space_id.to_string(),
+        name: name.to_string(),
+        filter: filter.clone(),
+        sort: sort.clone(),
+        created_at: now,
+        updated_at: now,
+    };
+    conn.execute(
+        "INSERT INTO task_view (id, space_id, name, filter_json, sort_json, created_at, updated_at)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+        rusqlite::params![
+            view.id,
+            view.space_id,
+            view.name,
+            to_json(filter)?,
+            to_json(sort)?,
+            view.created_at,
+            view.updated_at
+        ],
+    )?;
+    Ok(view)
+}
+
+pub fn get_task_view(conn: &Connection, id: Ulid) -> Result<Option<TaskView>, DbError> {
+    let view = conn
+        .query_row(
+            &format!("SELECT {} FROM task_view WHERE id = ?1", TASK_VIEW_COLUMNS),
+            [id.to_string()],
+            task_view_from_row,
+        )
+        .optional()?;
+    Ok(view)
+}
+
+pub fn get_task_views(conn: &Connection, space_id: Ulid) -> Result<Vec<TaskView>, DbError> {
+    let mut stmt = conn.prepare(&format!(
+        "SELECT {} FROM task_view WHERE space_id = ?1 ORDER BY name COLLATE NOCASE",
+        TASK_VIEW_COLUMNS
+    ))?;
+    let views = stmt
+        .query_map([space_id.to_string()], task_view_from_row)?
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(views)
+}
+
+pub fn update_task_view(
+    conn: &Connection,
+    id: Ulid,
+    name: &str,
+    filter: &TaskFilter,
+    sort: &TaskSort,
+) -> Result<TaskView, DbError> {
+    conn.execute(
+        "UPDATE task_view SET name = ?1, filter_json = ?2, sort_json = ?3, updated_at = ?4
+         WHERE id = ?5",
+        rusqlite::params![
+            name,
+            to_json(filter)?,
+            to_json(sort)?,
+            chrono::Utc::now().timestamp(),
+            id.to_string()
+        ],
+    )?;
+    get_task_view(conn, id)?.ok_or(DbError::Message(
+        "Task view not found after update".to_string(),
+    ))
+}
+
+pub fn delete_task_view(conn: &Connection, id: Ulid) -> Result<(), DbError> {
+    conn.execute("DELETE FROM task_view WHERE id = ?1", [id.to_string()])?;
+    Ok(())
+}
@@ -1,6 +1,7 @@
 use chrono::Utc;
-use rusqlite::Connection;
+use rusqlite::{Connection, OptionalExtension};
 use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
 use thiserror::Error;
 use ulid::Ulid;
 
@@ -39,6 +40,15 @@ pub struct GraphSnapshot {
     pub nodes: Vec<GraphNode>,
     pub edges: Vec<GraphEdge>,
     pub metrics: GraphMetrics,
+    #[serde(default)]
+    pub top_nodes: Vec<NodeDegree>, // Most connected nodes, highest degree first
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct NodeDegree {
+    pub node_id: String,
+    pub title: String,
+    pub degree: u32,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -65,8 +75,16 @@ pub struct GraphEvolution {
     pub time_range: (i64, i64),
     pub snapshots: Vec<GraphSnapshot>,
     pub milestones: Vec<GraphMilestone>,
+    #[serde(default)]
+    pub summaries_computed: u32, // Sample points that missed the summary cache
 }
 
+/// Nodes listed in `GraphSnapshot::top_nodes`
+const TOP_NODES_LIMIT: usize = 10;
+
+/// Window used for `GraphMetrics::growth_rate`
+const GROWTH_WINDOW_DAYS: i64 = 7;
+
 /// Initialize temporal graph tables
 pub fn init_temporal_graph_tables(conn: &Connection) -> Result<(), TemporalGraphError> {
     conn.execute(
@@ -277,14 +295,16 @@ pub fn build_current_graph(
         total_edges,
         avg_degree,
         communities: detect_communities(&nodes, &edges),
-        growth_rate: calculate_growth_rate(conn, space_id, 7)?,
+        growth_rate: calculate_growth_rate(conn, space_id, GROWTH_WINDOW_DAYS)?,
     };
+    let top_nodes = top_degree_nodes(&nodes, &edges);
 
     Ok(GraphSnapshot {
         timestamp: now,
         nodes,
         edges,
         metrics,
+        top_nodes,
     })
 }
 
@@ -359,12 +379,14 @@ pub fn get_graph_snapshots(
                     communities: 0,
                     growth_rate: 0.0,
                 });
+            let top_nodes = top_degree_nodes(&nodes, &edges);
 
             Ok(GraphSnapshot {
                 timestamp,
                 nodes,
                 edges,
                 metrics,
+                top_nodes,
             })
         },
     )?;
@@ -460,7 +482,12 @@ pub fn get_milestones(
     Ok(result)
 }
 
-/// Get full graph evolution for a time range
+/// Get graph evolution for a time range, sampled at `snapshot_limit` evenly spaced points.
+///
+/// Each sample is a summary of the note graph (metrics and top nodes, no node
+/// or edge lists). Summaries come from the `graph_summary` cache; points that
+/// are missing are computed in a single replay of `graph_change_log` and cached.
+/// Logging a change drops the cached summaries at or after its timestamp.
 pub fn get_graph_evolution(
     conn: &Connection,
     space_id: Ulid,
@@ -468,25 +495,84 @@ pub fn get_graph_evolution(
     end_time: i64,
     snapshot_limit: u32,
 ) -> Result<GraphEvolution, TemporalGraphError> {
-    let snapshots = get_graph_snapshots(conn, space_id, start_time, end_time, snapshot_limit)?;
+    let space = space_id.to_string();
+    let points = sample_points(start_time, end_time, snapshot_limit);
+
+    let mut summaries = load_summaries(conn, &space, start_time, end_time)?;
+    let missing: Vec<i64> = points
+        .iter()
+        .copied()
+        .filter(|t| !summaries.contains_key(t))
+        .collect();
+
+    if !missing.is_empty() {
+        log::debug!(
+            "[temporal_graph] Replaying change log for {} of {} sample points",
+            missing.len(),
+            points.len()
+        );
+        for summary in replay_summaries(conn, &space, &missing)? {
+            store_summary(conn, &space, &summary)?;
+            summaries.insert(summary.timestamp, summary);
+        }
+    }
+
+    let snapshots = points.iter().filter_map(|t| summaries.remove(t)).collect();
     let milestones = get_milestones(conn, space_id, start_time, end_time)?;
 
     Ok(GraphEvolution {
         time_range: (start_time, end_time),
         snapshots,
         milestones,
+        summaries_computed: missing.len() as u32,
     })
 }
 
+/// Rebuild the graph change log and node metrics from the current notes and links.
+///
+/// History from before the log existed is reconstructed from note timestamps;
+/// a link is dated to the creation of the younger of its two notes.
+pub fn backfill_graph_history(conn: &Connection) -> Result<(), TemporalGraphError> {
+    log::info!("[temporal_graph] Backfilling graph change log");
+    conn.execute_batch(
+        "DELETE FROM graph_change_log;
+         DELETE FROM graph_summary;
+         DELETE FROM graph_node_metric;
+
+         INSERT INTO graph_change_log (space_id, timestamp, change_type, node_id)
+         SELECT space_id, created_at, 'node_added', id FROM note;
+
+         INSERT INTO graph_change_log (space_id, timestamp, change_type, node_id)
+         SELECT space_id, MAX(created_at, modified_at), 'node_removed', id
+         FROM note WHERE is_trashed = 1;
+
+         INSERT INTO graph_change_log (space_id, timestamp, change_type, node_id, target_id)
+         SELECT s.space_id, MAX(s.created_at, COALESCE(t.created_at, s.created_at)),
+                'edge_added', l.source_note_id, l.target_note_id
+         FROM link l
+         JOIN note s ON s.id = l.source_note_id
+         LEFT JOIN note t ON t.id = l.target_note_id;
+
+         INSERT INTO graph_node_metric (note_id, space_id, created_at, content_length, degree)
+         SELECT n.id, n.space_id, n.created_at, LENGTH(n.content_md),
+                (SELECT COUNT(*) FROM link l
+                 WHERE l.source_note_id = n.id OR l.target_note_id = n.id)
+         FROM note n;",
+    )?;
+    Ok(())
+}
+
 /// Detect major note creation events (>2000 words)
 pub fn detect_major_notes(
     conn: &Connection,
     space_id: Ulid,
 ) -> Result<Vec<GraphMilestone>, TemporalGraphError> {
+    // Candidates come from the cached content lengths; only matches are read in full
     let mut stmt = conn.prepare(
-        "SELECT id, title, created_at, content_md FROM note
-         WHERE space_id = ?1 AND is_trashed = 0 AND LENGTH(content_md) > 6000
-         ORDER BY created_at DESC LIMIT 10",
+        "SELECT n.id, n.title, m.created_at, n.content_md FROM graph_node_metric m
+         JOIN note n ON n.id = m.note_id
+         WHERE m.space_id = ?1 AND m.content_length > 6000 AND n.is_trashed = 0
+         ORDER BY m.created_at DESC LIMIT 10",
     )?;
 
     let milestones = stmt.query_map([space_id.to_string()], |row| {
@@ -518,39 +604,45 @@ pub fn detect_major_notes(
 
 /// Simple community detection using connected components
 fn detect_communities(nodes: &[GraphNode], edges: &[GraphEdge]) -> u32 {
-    use std::collections::{HashMap, HashSet};
+    count_components(
+        nodes.iter().map(|n| n.id.as_str()),
+        edges
+            .iter()
+            .map(|e| (e.source_id.as_str(), e.target_id.as_str())),
+    )
+}
 
-    let mut adjacency: HashMap<String, HashSet<String>> = HashMap::new();
+/// Number of connected components among `nodes`
+fn count_components<'a>(
+    nodes: impl Iterator<Item = &'a str>,
+    edges: impl Iterator<Item = (&'a str, &'a str)>,
+) -> u32 {
+    let nodes: Vec<&str> = nodes.collect();
+    let mut adjacency: HashMap<&str, HashSet<&str>> = HashMap::new();
 
-    for node in nodes {
-        adjacency.entry(node.id.clone()).or_default();
+    for node in &nodes {
+        adjacency.entry(node).or_default();
     }
 
-    for edge in edges {
-        adjacency
-            .entry(edge.source_id.clone())
-            .or_default()
-            .insert(edge.target_id.clone());
-        adjacency
-            .entry(edge.target_id.clone())
-            .or_default()
-            .insert(edge.source_id.clone());
+    for (source, target) in edges {
+        adjacency.entry(source).or_default().insert(target);
+        adjacency.entry(target).or_default().insert(source);
     }
 
     let mut visited = HashSet::new();
     let mut communities = 0;
 
     for node in nodes {
-        if !visited.contains(&node.id) {
+        if !visited.contains(node) {
             communities += 1;
-            let mut stack = vec![node.id.clone()];
+            let mut stack = vec![node];
 
             while let Some(current) = stack.pop() {
-                if visited.insert(current.clone()) {
-                    if let Some(neighbors) = adjacency.get(&current) {
+                if visited.insert(current) {
+                    if let Some(neighbors) = adjacency.get(current) {
                         for neighbor in neighbors {
                             if !visited.contains(neighbor) {
-                                stack.push(neighbor.clone());
+                                stack.push(neighbor);
                             }
                         }
                     }
@@ -562,6 +654,246 @@ fn detect_communities(nodes: &[GraphNode], edges: &[GraphEdge]) -> u32 {
     communities
 }
 
+/// Highest-degree nodes, most connected first
+fn top_degree_nodes(nodes: &[GraphNode], edges: &[GraphEdge]) -> Vec<NodeDegree> {
+    let mut degrees: HashMap<&str, u32> = HashMap::new();
+    for edge in edges {
+        *degrees.entry(edge.source_id.as_str()).or_default() += 1;
+        *degrees.entry(edge.target_id.as_str()).or_default() += 1;
+    }
+
+    let mut ranked: Vec<NodeDegree> = nodes
+        .iter()
+        .filter_map(|node| {
+            degrees.get(node.id.as_str()).map(|&degree| NodeDegree {
+                node_id: node.id.clone(),
+                title: node.title.clone(),
+                degree,
+            })
+        })
+        .collect();
+    ranked.sort_by(|a, b| b.degree.cmp(&a.degree).then(a.node_id.cmp(&b.node_id)));
+    ranked.truncate(TOP_NODES_LIMIT);
+    ranked
+}
+
+/// `limit` evenly spaced timestamps from `start_time` to `end_time`, inclusive
+fn sample_points(start_time: i64, end_time: i64, limit: u32) -> Vec<i64> {
+    if limit == 0 || end_time < start_time {
+        return Vec::new();
+    }
+    if limit == 1 {
+        return vec![end_time];
+    }
+
+    let span = (end_time - start_time) as i128;
+    let steps = (limit - 1) as i128;
+    let mut points: Vec<i64> = (0..limit as i128)
+        .map(|i| start_time + (span * i / steps) as i64)
+        .collect();
+    points.dedup();
+    points
+}
+
+/// Cached summaries for a space within a time range, keyed by timestamp
+fn load_summaries(
+    conn: &Connection,
+    space_id: &str,
+    start_time: i64,
+    end_time: i64,
+) -> Result<HashMap<i64, GraphSnapshot>, TemporalGraphError> {
+    let mut stmt = conn.prepare(
+        "SELECT timestamp, metrics_json, top_nodes_json FROM graph_summary
+         WHERE space_id = ?1 AND timestamp BETWEEN ?2 AND ?3",
+    )?;
+    let rows = stmt.query_map(rusqlite::params![space_id, start_time, end_time], |row| {
+        Ok((
+            row.get::<_, i64>(0)?,
+            row.get::<_, String>(1)?,
+            row.get::<_, String>(2)?,
+        ))
+    })?;
+
+    let mut summaries = HashMap::new();
+    for row in rows {
+        let (timestamp, metrics_json, top_nodes_json) = row?;
+        let metrics = serde_json::from_str(&metrics_json)
+            .map_err(|e| TemporalGraphError::Parse(e.to_string()))?;
+        let top_nodes = serde_json::from_str(&top_nodes_json)
+            .map_err(|e| TemporalGraphError::Parse(e.to_string()))?;
+        summaries.insert(
+            timestamp,
+            GraphSnapshot {
+                timestamp,
+                nodes: Vec::new(),
+                edges: Vec::new(),
+                metrics,
+                top_nodes,
+            },
+        );
+    }
+    Ok(summaries)
+}
+
+fn store_summary(
+    conn: &Connection,
+    space_id: &str,
+    summary: &GraphSnapshot,
+) -> Result<(), TemporalGraphError> {
+    let metrics_json = serde_json::to_string(&summary.metrics)
+        .map_err(|e| TemporalGraphError::Parse(e.to_string()))?;
+    let top_nodes_json = serde_json::to_string(&summary.top_nodes)
+        .map_err(|e| TemporalGraphError::Parse(e.to_string()))?;
+
+    conn.execute(
+        "INSERT OR REPLACE INTO graph_summary
+            (space_id, timestamp, metrics_json, top_nodes_json, computed_at)
+         VALUES (?1, ?2, ?3, ?4, ?5)",
+        rusqlite::params![
+            space_id,
+            summary.timestamp,
+            metrics_json,
+            top_nodes_json,
+            Utc::now().timestamp()
+        ],
+    )?;
+    Ok(())
+}
+
+/// Note graph state while replaying the change log
+#[derive(Default)]
+struct GraphReplay {
+    live: HashSet<String>,
+    edges: HashSet<(String, String)>,
+    additions: Vec<i64>, // node_added timestamps, ascending
+}
+
+impl GraphReplay {
+    fn apply(
+        &mut self,
+        timestamp: i64,
+        change_type: &str,
+        node_id: String,
+        target_id: Option<String>,
+    ) {
+        match (change_type, target_id) {
+            ("node_added", _) => {
+                self.live.insert(node_id);
+                self.additions.push(timestamp);
+            }
+            ("node_removed", _) => {
+                self.live.remove(&node_id);
+            }
+            ("edge_added", Some(target)) if target != node_id => {
+                self.edges.insert((node_id, target));
+            }
+            ("edge_removed", Some(target)) => {
+                self.edges.remove(&(node_id, target));
+            }
+            _ => {}
+        }
+    }
+
+    /// Summary of the graph as of `timestamp`
+    fn summarize(
+        &self,
+        timestamp: i64,
+        titles: &mut rusqlite::CachedStatement<'_>,
+    ) -> Result<GraphSnapshot, TemporalGraphError> {
+        let live_edges: Vec<(&str, &str)> = self
+            .edges
+            .iter()
+            .filter(|(s, t)| self.live.contains(s) && self.live.contains(t))
+            .map(|(s, t)| (s.as_str(), t.as_str()))
+            .collect();
+
+        let total_nodes = self.live.len() as u32;
+        let total_edges = live_edges.len() as u32;
+        let avg_degree = if total_nodes > 0 {
+            (total_edges as f64 * 2.0) / total_nodes as f64
+        } else {
+            0.0
+        };
+
+        let window_start = timestamp - GROWTH_WINDOW_DAYS * 86400;
+        let recent = self.additions.len() - self.additions.partition_point(|&t| t < window_start);
+
+        let mut degrees: HashMap<&str, u32> = HashMap::new();
+        for (source, target) in &live_edges {
+            *degrees.entry(source).or_default() += 1;
+            *degrees.entry(target).or_default() += 1;
+        }
+        let mut ranked: Vec<(&str, u32)> = degrees.into_iter().collect();
+        ranked.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(b.0)));
+        ranked.truncate(TOP_NODES_LIMIT);
+
+        let mut top_nodes = Vec::with_capacity(ranked.len());
+        for (node_id, degree) in ranked {
+            let title: Option<String> = titles.query_row([node_id], |row| row.get(0)).optional()?;
+            top_nodes.push(NodeDegree {
+                node_id: node_id.to_string(),
+                title: title.unwrap_or_default(),
+                degree,
+            });
+        }
+
+        Ok(GraphSnapshot {
+            timestamp,
+            nodes: Vec::new(),
+            edges: Vec::new(),
+            metrics: GraphMetrics {
+                total_nodes,
+                total_edges,
+                avg_degree,
+                communities: count_components(
+                    self.live.iter().map(String::as_str),
+                    live_edges.iter().copied(),
+                ),
+                growth_rate: recent as f64 / GROWTH_WINDOW_DAYS as f64,
+            },
+            top_nodes,
+        })
+    }
+}
+
+/// Summaries at each of `points` (ascending) from one pass over the change log
+fn replay_summaries(
+    conn: &Connection,
+    space_id: &str,
+    points: &[i64],
+) -> Result<Vec<GraphSnapshot>, TemporalGraphError> {
+    let Some(&last) = points.last() else {
+        return Ok(Vec::new());
+    };
+
+    let mut titles = conn.prepare_cached("SELECT title FROM note WHERE id = ?1")?;
+    let mut stmt = conn.prepare(
+        "SELECT timestamp, change_type, node_id, target_id FROM graph_change_log
+         WHERE space_id = ?1 AND timestamp <= ?2
+         ORDER BY timestamp, id",
+    )?;
+    let mut rows = stmt.query(rusqlite::params![space_id, last])?;
+
+    let mut replay = GraphReplay::default();
+    let mut summaries = Vec::with_capacity(points.len());
+    let mut next = 0;
+
+    while let Some(row) = rows.next()? {
+        let timestamp: i64 = row.get(0)?;
+        while next < points.len() && points[next] < timestamp {
+            summaries.push(replay.summarize(points[next], &mut titles)?);
+            next += 1;
+        }
+        let change_type: String = row.get(1)?;
+        replay.apply(timestamp, &change_type, row.get(2)?, row.get(3)?);
+    }
+    for &point in &points[next..] {
+        summaries.push(replay.summarize(point, &mut titles)?);
+    }
+
+    Ok(summaries)
+}
+
 /// Calculate growth rate (new nodes per day)
 fn calculate_growth_rate(
     conn: &Connection,
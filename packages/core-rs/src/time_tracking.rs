@@ -1,4 +1,6 @@
+use crate::calendar::{get_event, get_events_in_range, CalendarEvent};
 use crate::db::DbError;
+use chrono::NaiveDate;
 use rusqlite::{Connection, OptionalExtension, Result};
 use serde::{Deserialize, Serialize};
 use ulid::Ulid;
@@ -15,6 +17,9 @@ pub struct TimeEntry {
     pub ended_at: Option<i64>,
     pub duration_seconds: Option<i64>,
     pub is_running: bool,
+    /// Event the entry was logged from. Its times were copied at the time,
+    /// so later edits to the event, or deleting it, leave the entry as is.
+    pub calendar_event_id: Option<Ulid>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -24,6 +29,41 @@ pub struct TimeStats {
     pub average_seconds: i64,
 }
 
+const ENTRY_COLUMNS: &str = "id, space_id, task_id, project_id, note_id, description, started_at,
+                             ended_at, duration_seconds, is_running, calendar_event_id";
+
+fn parse_ulid(value: String) -> rusqlite::Result<Ulid> {
+    Ulid::from_string(&value).map_err(|e| rusqlite::Error::ToSqlConversionFailure(Box::new(e)))
+}
+
+fn entry_from_row(row: &rusqlite::Row) -> rusqlite::Result<TimeEntry> {
+    Ok(TimeEntry {
+        id: parse_ulid(row.get(0)?)?,
+        space_id: parse_ulid(row.get(1)?)?,
+        task_id: row
+            .get::<_, Option<String>>(2)?
+            .map(parse_ulid)
+            .transpose()?,
+        project_id: row
+            .get::<_, Option<String>>(3)?
+            .map(parse_ulid)
+            .transpose()?,
+        note_id: row
+            .get::<_, Option<String>>(4)?
+            .map(parse_ulid)
+            .transpose()?,
+        description: row.get(5)?,
+        started_at: row.get(6)?,
+        ended_at: row.get(7)?,
+        duration_seconds: row.get(8)?,
+        is_running: row.get::<_, i64>(9)? == 1,
+        calendar_event_id: row
+            .get::<_, Option<String>>(10)?
+            .map(parse_ulid)
+            .transpose()?,
+    })
+}
+
 /// Start a new time entry
 pub fn start_time_entry(
     conn: &Connection,
@@ -50,6 +90,7 @@ pub fn start_time_entry(
         ended_at: None,
         duration_seconds: None,
         is_running: true,
+        calendar_event_id: None,
     };
 
     conn.execute(
@@ -119,80 +160,26 @@ pub fn stop_all_running_entries(conn: &Connection, space_id: Ulid) -> Result<(),
 
 /// Get a single time entry by ID
 pub fn get_time_entry(conn: &Connection, id: Ulid) -> Result<Option<TimeEntry>, DbError> {
-    let mut stmt = conn.prepare(
-        "SELECT id, space_id, task_id, project_id, note_id, description, started_at, ended_at, duration_seconds, is_running
-         FROM time_entry WHERE id = ?1"
-    )?;
+    let mut stmt = conn.prepare(&format!(
+        "SELECT {} FROM time_entry WHERE id = ?1",
+        ENTRY_COLUMNS
+    ))?;
 
     let entry = stmt
-        .query_row([id.to_string()], |row| {
-            Ok(TimeEntry {
-                id: Ulid::from_string(&row.get::<_, String>(0)?)
-                    .map_err(|e| rusqlite::Error::ToSqlConversionFailure(Box::new(e)))?,
-                space_id: Ulid::from_string(&row.get::<_, String>(1)?)
-                    .map_err(|e| rusqlite::Error::ToSqlConversionFailure(Box::new(e)))?,
-                task_id: row
-                    .get::<_, Option<String>>(2)?
-                    .map(|s| Ulid::from_string(&s))
-                    .transpose()
-                    .map_err(|e| rusqlite::Error::ToSqlConversionFailure(Box::new(e)))?,
-                project_id: row
-                    .get::<_, Option<String>>(3)?
-                    .map(|s| Ulid::from_string(&s))
-                    .transpose()
-                    .map_err(|e| rusqlite::Error::ToSqlConversionFailure(Box::new(e)))?,
-                note_id: row
-                    .get::<_, Option<String>>(4)?
-                    .map(|s| Ulid::from_string(&s))
-                    .transpose()
-                    .map_err(|e| rusqlite::Error::ToSqlConversionFailure(Box::new(e)))?,
-                description: row.get(5)?,
-                started_at: row.get(6)?,
-                ended_at: row.get(7)?,
-                duration_seconds: row.get(8)?,
-                is_running: row.get::<_, i64>(9)? == 1,
-            })
-        })
+        .query_row([id.to_string()], entry_from_row)
         .optional()?;
 
     Ok(entry)
 }
 
 pub fn get_time_entries(conn: &Connection, space_id: &str) -> Result<Vec<TimeEntry>, DbError> {
-    let mut stmt = conn.prepare(
-        "SELECT id, space_id, task_id, project_id, note_id, description, started_at, ended_at, duration_seconds, is_running
-         FROM time_entry WHERE space_id = ?1 ORDER BY started_at DESC"
-    )?;
+    let mut stmt = conn.prepare(&format!(
+        "SELECT {} FROM time_entry WHERE space_id = ?1 ORDER BY started_at DESC",
+        ENTRY_COLUMNS
+    ))?;
 
     let entries = stmt
-        .query_map([space_id], |row| {
-            Ok(TimeEntry {
-                id: Ulid::from_string(&row.get::<_, String>(0)?)
-                    .map_err(|e| rusqlite::Error::ToSqlConversionFailure(Box::new(e)))?,
-                space_id: Ulid::from_string(&row.get::<_, String>(1)?)
-                    .map_err(|e| rusqlite::Error::ToSqlConversionFailure(Box::new(e)))?,
-                task_id: row
-                    .get::<_, Option<String>>(2)?
-                    .map(|s| Ulid::from_string(&s))
-                    .transpose()
-                    .map_err(|e| rusqlite::Error::ToSqlConversionFailure(Box::new(e)))?,
-                project_id: row
-                    .get::<_, Option<String>>(3)?
-                    .map(|s| Ulid::from_string(&s))
-                    .transpose()
-                    .map_err(|e| rusqlite::Error::ToSqlConversionFailure(Box::new(e)))?,
-                note_id: row
-                    .get::<_, Option<String>>(4)?
-                    .map(|s| Ulid::from_string(&s))
-                    .transpose()
-                    .map_err(|e| rusqlite::Error::ToSqlConversionFailure(Box::new(e)))?,
-                description: row.get(5)?,
-                started_at: row.get(6)?,
-                ended_at: row.get(7)?,
-                duration_seconds: row.get(8)?,
-                is_running: row.get::<_, i64>(9)? == 1,
-            })
-        })?
+        .query_map([space_id], entry_from_row)?
         .collect::<Result<Vec<_>, _>>()?;
 
     Ok(entries)
@@ -200,40 +187,13 @@ pub fn get_time_entries(conn: &Connection, space_id: &str) -> Result<Vec<TimeEnt
 
 /// Get all time entries for a task
 pub fn get_task_time_entries(conn: &Connection, task_id: Ulid) -> Result<Vec<TimeEntry>, DbError> {
-    let mut stmt = conn.prepare(
-        "SELECT id, space_id, task_id, project_id, note_id, description, started_at, ended_at, duration_seconds, is_running
-         FROM time_entry WHERE task_id = ?1 ORDER BY started_at DESC"
-    )?;
+    let mut stmt = conn.prepare(&format!(
+        "SELECT {} FROM time_entry WHERE task_id = ?1 ORDER BY started_at DESC",
+        ENTRY_COLUMNS
+    ))?;
 
     let entries = stmt
-        .query_map([task_id.to_string()], |row| {
-            Ok(TimeEntry {
-                id: Ulid::from_string(&row.get::<_, String>(0)?)
-                    .map_err(|e| rusqlite::Error::ToSqlConversionFailure(Box::new(e)))?,
-                space_id: Ulid::from_string(&row.get::<_, String>(1)?)
-                    .map_err(|e| rusqlite::Error::ToSqlConversionFailure(Box::new(e)))?,
-                task_id: row
-                    .get::<_, Option<String>>(2)?
-                    .map(|s| Ulid::from_string(&s))
-                    .transpose()
-                    .map_err(|e| rusqlite::Error::ToSqlConversionFailure(Box::new(e)))?,
-                project_id: row
-                    .get::<_, Option<String>>(3)?
-                    .map(|s| Ulid::from_string(&s))
-                    .transpose()
-                    .map_err(|e| rusqlite::Error::ToSqlConversionFailure(Box::new(e)))?,
-                note_id: row
-                    .get::<_, Option<String>>(4)?
-                    .map(|s| Ulid::from_string(&s))
-                    .transpose()
-                    .map_err(|e| rusqlite::Error::ToSqlConversionFailure(Box::new(e)))?,
-                description: row.get(5)?,
-                started_at: row.get(6)?,
-                ended_at: row.get(7)?,
-                duration_seconds: row.get(8)?,
-                is_running: row.get::<_, i64>(9)? == 1,
-            })
-        })?
+        .query_map([task_id.to_string()], entry_from_row)?
         .collect::<Result<Vec<_>, _>>()?;
 
     Ok(entries)
@@ -244,40 +204,13 @@ pub fn get_project_time_entries(
     conn: &Connection,
     project_id: Ulid,
 ) -> Result<Vec<TimeEntry>, DbError> {
-    let mut stmt = conn.prepare(
-        "SELECT id, space_id, task_id, project_id, note_id, description, started_at, ended_at, duration_seconds, is_running
-         FROM time_entry WHERE project_id = ?1 ORDER BY started_at DESC"
-    )?;
+    let mut stmt = conn.prepare(&format!(
+        "SELECT {} FROM time_entry WHERE project_id = ?1 ORDER BY started_at DESC",
+        ENTRY_COLUMNS
+    ))?;
 
     let entries = stmt
-        .query_map([project_id.to_string()], |row| {
-            Ok(TimeEntry {
-                id: Ulid::from_string(&row.get::<_, String>(0)?)
-                    .map_err(|e| rusqlite::Error::ToSqlConversionFailure(Box::new(e)))?,
-                space_id: Ulid::from_string(&row.get::<_, String>(1)?)
-                    .map_err(|e| rusqlite::Error::ToSqlConversionFailure(Box::new(e)))?,
-                task_id: row
-                    .get::<_, Option<String>>(2)?
-                    .map(|s| Ulid::from_string(&s))
-                    .transpose()
-                    .map_err(|e| rusqlite::Error::ToSqlConversionFailure(Box::new(e)))?,
-                project_id: row
-                    .get::<_, Option<String>>(3)?
-                    .map(|s| Ulid::from_string(&s))
-                    .transpose()
-                    .map_err(|e| rusqlite::Error::ToSqlConversionFailure(Box::new(e)))?,
-                note_id: row
-                    .get::<_, Option<String>>(4)?
-                    .map(|s| Ulid::from_string(&s))
-                    .transpose()
-                    .map_err(|e| rusqlite::Error::ToSqlConversionFailure(Box::new(e)))?,
-                description: row.get(5)?,
-                started_at: row.get(6)?,
-                ended_at: row.get(7)?,
-                duration_seconds: row.get(8)?,
-                is_running: row.get::<_, i64>(9)? == 1,
-            })
-        })?
+        .query_map([project_id.to_string()], entry_from_row)?
         .collect::<Result<Vec<_>, _>>()?;
 
     Ok(entries)
@@ -285,40 +218,13 @@ pub fn get_project_time_entries(
 
 /// Get all running time entries in a space
 pub fn get_running_entries(conn: &Connection, space_id: Ulid) -> Result<Vec<TimeEntry>, DbError> {
-    let mut stmt = conn.prepare(
-        "SELECT id, space_id, task_id, project_id, note_id, description, started_at, ended_at, duration_seconds, is_running
-         FROM time_entry WHERE space_id = ?1 AND is_running = 1"
-    )?;
+    let mut stmt = conn.prepare(&format!(
+        "SELECT {} FROM time_entry WHERE space_id = ?1 AND is_running = 1",
+        ENTRY_COLUMNS
+    ))?;
 
     let entries = stmt
-        .query_map([space_id.to_string()], |row| {
-            Ok(TimeEntry {
-                id: Ulid::from_string(&row.get::<_, String>(0)?)
-                    .map_err(|e| rusqlite::Error::ToSqlConversionFailure(Box::new(e)))?,
-                space_id: Ulid::from_string(&row.get::<_, String>(1)?)
-                    .map_err(|e| rusqlite::Error::ToSqlConversionFailure(Box::new(e)))?,
-                task_id: row
-                    .get::<_, Option<String>>(2)?
-                    .map(|s| Ulid::from_string(&s))
-                    .transpose()
-                    .map_err(|e| rusqlite::Error::ToSqlConversionFailure(Box::new(e)))?,
-                project_id: row
-                    .get::<_, Option<String>>(3)?
-                    .map(|s| Ulid::from_string(&s))
-                    .transpose()
-                    .map_err(|e| rusqlite::Error::ToSqlConversionFailure(Box::new(e)))?,
-                note_id: row
-                    .get::<_, Option<String>>(4)?
-                    .map(|s| Ulid::from_string(&s))
-                    .transpose()
-                    .map_err(|e| rusqlite::Error::ToSqlConversionFailure(Box::new(e)))?,
-                description: row.get(5)?,
-                started_at: row.get(6)?,
-                ended_at: row.get(7)?,
-                duration_seconds: row.get(8)?,
-                is_running: row.get::<_, i64>(9)? == 1,
-            })
-        })?
+        .query_map([space_id.to_string()], entry_from_row)?
         .collect::<Result<Vec<_>, _>>()?;
 
     Ok(entries)
@@ -330,40 +236,16 @@ pub fn get_recent_time_entries(
     space_id: Ulid,
     limit: i64,
 ) -> Result<Vec<TimeEntry>, DbError> {
-    let mut stmt = conn.prepare(
-        "SELECT id, space_id, task_id, project_id, note_id, description, started_at, ended_at, duration_seconds, is_running
-         FROM time_entry WHERE space_id = ?1 ORDER BY started_at DESC LIMIT ?2"
-    )?;
+    let mut stmt = conn.prepare(&format!(
+        "SELECT {} FROM time_entry WHERE space_id = ?1 ORDER BY started_at DESC LIMIT ?2",
+        ENTRY_COLUMNS
+    ))?;
 
     let entries = stmt
-        .query_map(rusqlite::params![space_id.to_string(), limit], |row| {
-            Ok(TimeEntry {
-                id: Ulid::from_string(&row.get::<_, String>(0)?)
-                    .map_err(|e| rusqlite::Error::ToSqlConversionFailure(Box::new(e)))?,
-                space_id: Ulid::from_string(&row.get::<_, String>(1)?)
-                    .map_err(|e| rusqlite::Error::ToSqlConversionFailure(Box::new(e)))?,
-                task_id: row
-                    .get::<_, Option<String>>(2)?
-                    .map(|s| Ulid::from_string(&s))
-                    .transpose()
-                    .map_err(|e| rusqlite::Error::ToSqlConversionFailure(Box::new(e)))?,
-                project_id: row
-                    .get::<_, Option<String>>(3)?
-                    .map(|s| Ulid::from_string(&s))
-                    .transpose()
-                    .map_err(|e| rusqlite::Error::ToSqlConversionFailure(Box::new(e)))?,
-                note_id: row
-                    .get::<_, Option<String>>(4)?
-                    .map(|s| Ulid::from_string(&s))
-                    .transpose()
-                    .map_err(|e| rusqlite::Error::ToSqlConversionFailure(Box::new(e)))?,
-                description: row.get(5)?,
-                started_at: row.get(6)?,
-                ended_at: row.get(7)?,
-                duration_seconds: row.get(8)?,
-                is_running: row.get::<_, i64>(9)? == 1,
-            })
-        })?
+        .query_map(
+            rusqlite::params![space_id.to_string(), limit],
+            entry_from_row,
+        )?
         .collect::<Result<Vec<_>, _>>()?;
 
     Ok(entries)
@@ -374,41 +256,14 @@ pub fn get_time_entries_since(
     space_id: &str,
     since_timestamp: i64,
 ) -> Result<Vec<TimeEntry>, DbError> {
-    let mut stmt = conn.prepare(
-        "SELECT id, space_id, task_id, project_id, note_id, description, started_at, ended_at, duration_seconds, is_running
-         FROM time_entry WHERE space_id = ?1 AND started_at >= ?2
-         ORDER BY started_at DESC"
-    )?;
+    let mut stmt = conn.prepare(&format!(
+        "SELECT {} FROM time_entry WHERE space_id = ?1 AND started_at >= ?2
+         ORDER BY started_at DESC",
+        ENTRY_COLUMNS
+    ))?;
 
     let entries = stmt
-        .query_map(rusqlite::params![space_id, since_timestamp], |row| {
-            Ok(TimeEntry {
-                id: Ulid::from_string(&row.get::<_, String>(0)?)
-                    .map_err(|e| rusqlite::Error::ToSqlConversionFailure(Box::new(e)))?,
-                space_id: Ulid::from_string(&row.get::<_, String>(1)?)
-                    .map_err(|e| rusqlite::Error::ToSqlConversionFailure(Box::new(e)))?,
-                task_id: row
-                    .get::<_, Option<String>>(2)?
-                    .map(|s| Ulid::from_string(&s))
-                    .transpose()
-                    .map_err(|e| rusqlite::Error::ToSqlConversionFailure(Box::new(e)))?,
-                project_id: row
-                    .get::<_, Option<String>>(3)?
-                    .map(|s| Ulid::from_string(&s))
-                    .transpose()
-                    .map_err(|e| rusqlite::Error::ToSqlConversionFailure(Box::new(e)))?,
-                note_id: row
-                    .get::<_, Option<String>>(4)?
-                    .map(|s| Ulid::from_string(&s))
-                    .transpose()
-                    .map_err(|e| rusqlite::Error::ToSqlConversionFailure(Box::new(e)))?,
-                description: row.get(5)?,
-                started_at: row.get(6)?,
-                ended_at: row.get(7)?,
-                duration_seconds: row.get(8)?,
-                is_running: row.get::<_, i64>(9)? == 1,
-            })
-        })?
+        .query_map(rusqlite::params![space_id, since_timestamp], entry_from_row)?
         .collect::<Result<Vec<_>, _>>()?;
 
     Ok(entries)
@@ -499,6 +354,7 @@ pub fn create_manual_time_entry(
         ended_at: Some(ended_at),
         duration_seconds: Some(params.duration_seconds),
         is_running: false,
+        calendar_event_id: None,
     };
 
     conn.execute(
@@ -520,3 +376,128 @@ pub fn create_manual_time_entry(
 
     Ok(entry)
 }
+
+/// What to log differently from the event when creating an entry from it.
+/// An entry belongs to exactly one task, project or note, so one of those
+/// must be set.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct EventEntryOverrides {
+    pub task_id: Option<Ulid>,
+    pub project_id: Option<Ulid>,
+    pub note_id: Option<Ulid>,
+    /// Defaults to the event's summary
+    pub description: Option<String>,
+    pub started_at: Option<i64>,
+    pub ended_at: Option<i64>,
+}
+
+/// Log the time spent in a calendar event as a completed entry linked back
+/// to it
+pub fn create_time_entry_from_event(
+    conn: &Connection,
+    event_id: Ulid,
+    overrides: EventEntryOverrides,
+) -> Result<TimeEntry, DbError> {
+    log::info!(
+        "[time_tracking] Creating time entry from event: {}",
+        event_id
+    );
+
+    let event = get_event(conn, event_id)?.ok_or_else(|| DbError::NotFound {
+        entity: "calendar_event",
+        id: event_id.to_string(),
+    })?;
+    let targets = [
+        overrides.task_id.is_some(),
+        overrides.project_id.is_some(),
+        overrides.note_id.is_some(),
+    ];
+    if targets.iter().filter(|&&set| set).count() != 1 {
+        return Err(DbError::Message(
+            "A time entry must belong to exactly one task, project or note".to_string(),
+        ));
+    }
+    let started_at = overrides.started_at.unwrap_or(event.start);
+    let ended_at = overrides.ended_at.unwrap_or_else(|| event.effective_end());
+    if ended_at <= started_at {
+        return Err(DbError::Message(
+            "Time entry must end after it starts".to_string(),
+        ));
+    }
+
+    let entry = TimeEntry {
+        id: Ulid::new(),
+        space_id: event.space_id,
+        task_id: overrides.task_id,
+        project_id: overrides.project_id,
+        note_id: overrides.note_id,
+        description: Some(overrides.description.unwrap_or(event.summary)),
+        started_at,
+        ended_at: Some(ended_at),
+        duration_seconds: Some(ended_at - started_at),
+        is_running: false,
+        calendar_event_id: Some(event.id),
+    };
+    conn.execute(
+        "INSERT INTO time_entry (id, space_id, task_id, project_id, note_id, description, started_at,
+                                 ended_at, duration_seconds, is_running, calendar_event_id)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, 0, ?10)",
+        rusqlite::params![
+            entry.id.to_string(),
+            entry.space_id.to_string(),
+            entry.task_id.map(|id| id.to_string()),
+            entry.project_id.map(|id| id.to_string()),
+            entry.note_id.map(|id| id.to_string()),
+            entry.description,
+            entry.started_at,
+            entry.ended_at,
+            entry.duration_seconds,
+            entry.calendar_event_id.map(|id| id.to_string()),
+        ],
+    )?;
+
+    Ok(entry)
+}
+
+/// Timed events of `date` (UTC) that could be logged in one click: no
+/// entry was created from them, and no tracked time overlaps them. A
+/// running entry counts as tracked up to now.
+pub fn suggest_time_entries_for_day(
+    conn: &Connection,
+    space_id: Ulid,
+    date: NaiveDate,
+) -> Result<Vec<CalendarEvent>, DbError> {
+    let start = date
+        .and_hms_opt(0, 0, 0)
+        .expect("midnight is a valid time")
+        .and_utc()
+        .timestamp();
+    let now = chrono::Utc::now().timestamp();
+    let mut stmt = conn.prepare(
+        "SELECT EXISTS (SELECT 1 FROM time_entry WHERE calendar_event_id = ?1)
+             OR EXISTS (SELECT 1 FROM time_entry
+                        WHERE space_id = ?2 AND started_at < ?4
+                          AND COALESCE(ended_at, ?5) > ?3)",
+    )?;
+
+    let mut suggestions = Vec::new();
+    for event in get_events_in_range(conn, space_id, start, start + 24 * 60 * 60)? {
+        if event.all_day {
+            continue;
+        }
+        let taken: bool = stmt.query_row(
+            rusqlite::params![
+                event.id.to_string(),
+                space_id.to_string(),
+                event.start,
+                event.effective_end(),
+                now
+            ],
+            |row| row.get(0),
+        )?;
+        if !taken {
+            suggestions.push(event);
+        }
+    }
+    Ok(suggestions)
+}
@@ -0,0 +1,654 @@
+//! Trip Planning
+//!
+//! Itineraries and packing lists of the trips created by
+//! [`crate::personal_modes::create_trip`].
+//!
+//! Times are floating: the day of an itinerary item, and the days a trip
+//! spans, are the UTC calendar dates of their timestamps, so an item at
+//! 23:30 stays on its day whatever timezone the trip is looked at from.
+//! Items are ordered by a position within their day, which the user sets
+//! with [`reorder_itinerary_item`]; new items go last.
+//!
+//! Packing templates are lists saved from one trip and applied to others in
+//! the same space.
+
+use crate::db::DbError;
+use crate::personal_modes::Trip;
+use chrono::{DateTime, NaiveDate};
+use rusqlite::{params, Connection, OptionalExtension};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashSet};
+use thiserror::Error;
+use ulid::Ulid;
+
+const DAY_FORMAT: &str = "%Y-%m-%d";
+
+#[derive(Error, Debug)]
+pub enum TripError {
+    #[error("Database error: {0}")]
+    Database(#[from] DbError),
+    #[error("Rusqlite error: {0}")]
+    Rusqlite(#[from] rusqlite::Error),
+    #[error("{entity} not found: {id}")]
+    NotFound { entity: &'static str, id: String },
+    #[error("{day} is outside the trip, which runs from {start} to {end}")]
+    OutsideTripDates {
+        day: String,
+        start: String,
+        end: String,
+    },
+    #[error("Timestamp {0} is out of range")]
+    InvalidTime(i64),
+    #[error("Invalid packing item: {0}")]
+    InvalidPackingItem(String),
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ItineraryItem {
+    pub id: String,
+    pub trip_id: String,
+    pub title: String,
+    /// Free-form kind, e.g. "flight", "lodging" or "activity"
+    pub item_type: String,
+    pub location: Option<String>,
+    pub notes: Option<String>,
+    pub starts_at: i64,
+    /// YYYY-MM-DD date of `starts_at`
+    pub day: String,
+    /// Place within the day, from 0
+    pub position: i64,
+    pub created_at: i64,
+}
+
+pub struct ItineraryItemParams<'a> {
+    pub title: &'a str,
+    pub item_type: &'a str,
+    pub starts_at: i64,
+    pub location: Option<&'a str>,
+    pub notes: Option<&'a str>,
+    /// Accept a time outside the trip's dates, like the flight there
+    pub allow_outside: bool,
+}
+
+/// One day of an itinerary
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ItineraryDay {
+    /// YYYY-MM-DD
+    pub day: String,
+    pub items: Vec<ItineraryItem>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PackingItem {
+    pub id: String,
+    pub trip_id: String,
+    pub name: String,
+    pub category: Option<String>,
+    pub quantity: i64,
+    pub is_packed: bool,
+    pub position: i64,
+    pub created_at: i64,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PackingTemplateItem {
+    pub name: String,
+    pub category: Option<String>,
+    pub quantity: i64,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PackingTemplate {
+    pub id: String,
+    pub space_id: String,
+    pub name: String,
+    pub items: Vec<PackingTemplateItem>,
+    pub created_at: i64,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PackingStats {
+    pub total: i64,
+    pub packed: i64,
+    pub unpacked: i64,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ItineraryDayCount {
+    pub day: String,
+    pub item_count: i64,
+}
+
+/// What the dashboard shows of a trip
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TripSummary {
+    pub trip_id: String,
+    pub name: String,
+    pub destination: String,
+    pub start_date: i64,
+    pub end_date: i64,
+    /// Every day of the trip, plus days outside it that have items
+    pub days: Vec<ItineraryDayCount>,
+    pub item_count: i64,
+    pub unpacked_count: i64,
+}
+
+fn date_of(ts: i64) -> Result<NaiveDate, TripError> {
+    DateTime::from_timestamp(ts, 0)
+        .map(|t| t.date_naive())
+        .ok_or(TripError::InvalidTime(ts))
+}
+
+fn format_day(date: NaiveDate) -> String {
+    date.format(DAY_FORMAT).to_string()
+}
+
+/// First and last day of a trip
+fn trip_dates(trip: &Trip) -> Result<(NaiveDate, NaiveDate), TripError> {
+    Ok((date_of(trip.start_date)?, date_of(trip.end_date)?))
+}
+
+const TRIP_COLUMNS: &str =
+    "id, space_id, note_id, name, destination, start_date, end_date, created_at";
+
+fn trip_from_row(row: &rusqlite::Row) -> rusqlite::Result<Trip> {
+    Ok(Trip {
+        id: row.get(0)?,
+        space_id: row.get(1)?,
+        note_id: row.get(2)?,
+        name: row.get(3)?,
+        destination: row.get(4)?,
+        start_date: row.get(5)?,
+        end_date: row.get(6)?,
+        created_at: row.get(7)?,
+    })
+}
+
+fn get_trip(conn: &Connection, trip_id: &str) -> Result<Trip, TripError> {
+    conn.query_row(
+        &format!("SELECT {TRIP_COLUMNS} FROM trip WHERE id = ?1"),
+        [trip_id],
+        trip_from_row,
+    )
+    .optional()?
+    .ok_or_else(|| TripError::NotFound {
+        entity: "trip",
+        id: trip_id.to_string(),
+    })
+}
+
+// --- Itinerary ---
+
+/// Add an item at the end of its day. Items outside the trip's dates are
+/// refused unless `allow_outside` is set.
+pub fn add_itinerary_item(
+    conn: &Connection,
+    trip_id: &str,
+    params: ItineraryItemParams,
+) -> Result<ItineraryItem, TripError> {
+    let trip = get_trip(conn, trip_id)?;
+    let date = date_of(params.starts_at)?;
+    let (start, end) = trip_dates(&trip)?;
+    if !params.allow_outside && (date < start || date > end) {
+        return Err(TripError::OutsideTripDates {
+            day: format_day(date),
+            start: format_day(start),
+            end: format_day(end),
+        });
+    }
+
+    let day = format_day(date);
+    let position: i64 = conn.query_row(
+        "SELECT COALESCE(MAX(position) + 1, 0) FROM itinerary_item
+         WHERE trip_id = ?1 AND day = ?2",
+        params![trip_id, day],
+        |row| row.get(0),
+    )?;
+    let item = ItineraryItem {
+        id: Ulid::new().to_string(),
+        trip_id: trip_id.to_string(),
+        title: params.title.to_string(),
+        item_type: params.item_type.to_string(),
+        location: params.location.map(String::from),
+        notes: params.notes.map(String::from),
+        starts_at: params.starts_at,
+        day,
+        position,
+        created_at: chrono::Utc::now().timestamp(),
+    };
+    conn.execute(
+        "INSERT INTO itinerary_item (id, trip_id, title, item_type, location, notes, starts_at,
+             day, position, created_at)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10)",
+        params![
+            item.id,
+            item.trip_id,
+            item.title,
+            item.item_type,
+            item.location,
+            item.notes,
+            item.starts_at,
+            item.day,
+            item.position,
+            item.created_at
+        ],
+    )?;
+    Ok(item)
+}
+
+/// Move an item to `position` within its day; positions past the end put it
+/// last. The other items of the day keep their order.
+pub fn reorder_itinerary_item(
+    conn: &Connection,
+    item_id: &str,
+    position: i64,
+) -> Result<Vec<ItineraryItem>, TripError> {
+    let tx = conn.unchecked_transaction()?;
+    let (trip_id, day): (String, String) = tx
+        .query_row(
+            "SELECT trip_id, day FROM itinerary_item WHERE id = ?1",
+            [item_id],
+            |row| Ok((row.get(0)?, row.get(1)?)),
+        )
+        .optional()?
+        .ok_or_else(|| TripError::NotFound {
+            entity: "itinerary_item",
+            id: item_id.to_string(),
+        })?;
+    let mut items = day_items(&tx, &trip_id, &day)?;
+    let from = items
+        .iter()
+        .position(|i| i.id == item_id)
+        .expect("item is on its day");
+    let item = items.remove(from);
+    let to = position.clamp(0, items.len() as i64) as usize;
+    items.insert(to, item);
+    for (position, item) in items.iter_mut().enumerate() {
+        item.position = position as i64;
+        tx.execute(
+            "UPDATE itinerary_item SET position = ?1 WHERE id = ?2",
+            params![item.position, item.id],
+        )?;
+    }
+    tx.commit()?;
+    Ok(items)
+}
+
+fn itinerary_item_from_row(row: &rusqlite::Row) -> rusqlite::Result<ItineraryItem> {
+    Ok(ItineraryItem {
+        id: row.get(0)?,
+        trip_id: row.get(1)?,
+        title: row.get(2)?,
+        item_type: row.get(3)?,
+        location: row.get(4)?,
+        notes: row.get(5)?,
+        starts_at: row.get(6)?,
+        day: row.get(7)?,
+        position: row.get(8)?,
+        created_at: row.get(9)?,
+    })
+}
+
+const ITINERARY_COLUMNS: &str =
+    "id, trip_id, title, item_type, location, notes, starts_at, day, position, created_at";
+
+fn day_items(conn: &Connection, trip_id: &str, day: &str) -> Result<Vec<ItineraryItem>, TripError> {
+    let mut stmt = conn.prepare(&format!(
+        "SELECT {ITINERARY_COLUMNS} FROM itinerary_item
+         WHERE trip_id = ?1 AND day = ?2 ORDER BY position, id"
+    ))?;
+    let items = stmt
+        .query_map(params![trip_id, day], itinerary_item_from_row)?
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(items)
+}
+
+/// The trip's items grouped by day, in day order. Every day of the trip is
+/// there, with or without items; days outside it only when they have some.
+pub fn get_trip_itinerary(
+    conn: &Connection,
+    trip_id: &str,
+) -> Result<Vec<ItineraryDay>, TripError> {
+    let trip = get_trip(conn, trip_id)?;
+    let (start, end) = trip_dates(&trip)?;
+    let mut days: BTreeMap<String, Vec<ItineraryItem>> = start
+        .iter_days()
+        .take_while(|d| *d <= end)
+        .map(|d| (format_day(d), Vec::new()))
+        .collect();
+
+    let mut stmt = conn.prepare(&format!(
+        "SELECT {ITINERARY_COLUMNS} FROM itinerary_item
+         WHERE trip_id = ?1 ORDER BY day, position, id"
+    ))?;
+    let items = stmt.query_map([trip_id], itinerary_item_from_row)?;
+    for item in items {
+        let item = item?;
+        days.entry(item.day.clone()).or_default().push(item);
+    }
+    Ok(days
+        .into_iter()
+        .map(|(day, items)| ItineraryDay { day, items })
+        .collect())
+}
+
+// --- Packing ---
+
+pub fn add_packing_item(
+    conn: &Connection,
+    trip_id: &str,
+    name: &str,
+    category: Option<&str>,
+    quantity: i64,
+) -> Result<PackingItem, TripError> {
+    get_trip(conn, trip_id)?;
+    insert_packing_item(conn, trip_id, name, category, quantity)
+}
+
+fn insert_packing_item(
+    conn: &Connection,
+    trip_id: &str,
+    name: &str,
+    category: Option<&str>,
+    quantity: i64,
+) -> Result<PackingItem, TripError> {
+    let name = name.trim();
+    if name.is_empty() {
+        return Err(TripError::InvalidPackingItem(
+            "name can't be empty".to_string(),
+        ));
+    }
+    if quantity < 1 {
+        return Err(TripError::InvalidPackingItem(format!(
+            "quantity of {name} must be at least 1"
+        )));
+    }
+    let position: i64 = conn.query_row(
+        "SELECT COALESCE(MAX(position) + 1, 0) FROM packing_item WHERE trip_id = ?1",
+        [trip_id],
+        |row| row.get(0),
+    )?;
+    let item = PackingItem {
+        id: Ulid::new().to_string(),
+        trip_id: trip_id.to_string(),
+        name: name.to_string(),
+        category: category.map(String::from),
+        quantity,
+        is_packed: false,
+        position,
+        created_at: chrono::Utc::now().timestamp(),
+    };
+    conn.execute(
+        "INSERT INTO packing_item (id, trip_id, name, category, quantity, is_packed, position,
+             created_at)
+         VALUES (?1, ?2, ?3, ?4, ?5, 0, ?6, ?7)",
+        params![
+            item.id,
+            item.trip_id,
+            item.name,
+            item.category,
+            item.quantity,
+            item.position,
+            item.created_at
+        ],
+    )?;
+    Ok(item)
+}
+
+/// Flip an item between packed and unpacked; returns whether it's packed now
+pub fn toggle_packed(conn: &Connection, item_id: &str) -> Result<bool, TripError> {
+    conn.query_row(
+        "UPDATE packing_item SET is_packed = NOT is_packed WHERE id = ?1 RETURNING is_packed",
+        [item_id],
+        |row| row.get(0),
+    )
+    .optional()?
+    .ok_or_else(|| TripError::NotFound {
+        entity: "packing_item",
+        id: item_id.to_string(),
+    })
+}
+
+pub fn get_packing_list(conn: &Connection, trip_id: &str) -> Result<Vec<PackingItem>, TripError> {
+    let mut stmt = conn.prepare(
+        "SELECT id, trip_id, name, category, quantity, is_packed, position, created_at
+         FROM packing_item WHERE trip_id = ?1 ORDER BY position, id",
+    )?;
+    let items = stmt
+        .query_map([trip_id], |row| {
+            Ok(PackingItem {
+                id: row.get(0)?,
+                trip_id: row.get(1)?,
+                name: row.get(2)?,
+                category: row.get(3)?,
+                quantity: row.get(4)?,
+                is_packed: row.get(5)?,
+                position: row.get(6)?,
+                created_at: row.get(7)?,
+            })
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(items)
+}
+
+pub fn get_trip_packing_stats(conn: &Connection, trip_id: &str) -> Result<PackingStats, TripError> {
+    let (total, packed): (i64, i64) = conn.query_row(
+        "SELECT COUNT(*), COALESCE(SUM(is_packed), 0) FROM packing_item WHERE trip_id = ?1",
+        [trip_id],
+        |row| Ok((row.get(0)?, row.get(1)?)),
+    )?;
+    Ok(PackingStats {
+        total,
+        packed,
+        unpacked: total - packed,
+    })
+}
+
+/// Save the trip's packing list as a template of its space, replacing the
+/// space's template of the same name. Packed state isn't kept.
+pub fn save_packing_template(
+    conn: &Connection,
+    trip_id: &str,
+    name: &str,
+) -> Result<PackingTemplate, TripError> {
+    let trip = get_trip(conn, trip_id)?;
+    let items = get_packing_list(conn, trip_id)?
+        .into_iter()
+        .map(|item| PackingTemplateItem {
+            name: item.name,
+            category: item.category,
+            quantity: item.quantity,
+        })
+        .collect::<Vec<_>>();
+    let template = PackingTemplate {
+        id: Ulid::new().to_string(),
+        space_id: trip.space_id,
+        name: name.to_string(),
+        items,
+        created_at: chrono::Utc::now().timestamp(),
+    };
+
+    let tx = conn.unchecked_transaction()?;
+    tx.execute(
+        "DELETE FROM packing_template WHERE space_id = ?1 AND name = ?2",
+        params![template.space_id, template.name],
+    )?;
+    tx.execute(
+        "INSERT INTO packing_template (id, space_id, name, created_at) VALUES (?1, ?2, ?3, ?4)",
+        params![
+            template.id,
+            template.space_id,
+            template.name,
+            template.created_at
+        ],
+    )?;
+    for (position, item) in template.items.iter().enumerate() {
+        tx.execute(
+            "INSERT INTO packing_template_item (template_id, position, name, category, quantity)
+             VALUES (?1, ?2, ?3, ?4, ?5)",
+            params![
+                template.id,
+                position as i64,
+                item.name,
+                item.category,
+                item.quantity
+            ],
+        )?;
+    }
+    tx.commit()?;
+    Ok(template)
+}
+
+pub fn get_packing_templates(
+    conn: &Connection,
+    space_id: Ulid,
+) -> Result<Vec<PackingTemplate>, TripError> {
+    let mut stmt = conn.prepare(
+        "SELECT id, space_id, name, created_at FROM packing_template
+         WHERE space_id = ?1 ORDER BY name",
+    )?;
+    let templates = stmt
+        .query_map([space_id.to_string()], |row| {
+            Ok(PackingTemplate {
+                id: row.get(0)?,
+                space_id: row.get(1)?,
+                name: row.get(2)?,
+                items: Vec::new(),
+                created_at: row.get(3)?,
+            })
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
+    templates
+        .into_iter()
+        .map(|mut template| {
+            template.items = packing_template_items(conn, &template.id)?;
+            Ok(template)
+        })
+        .collect()
+}
+
+fn packing_template_items(
+    conn: &Connection,
+    template_id: &str,
+) -> Result<Vec<PackingTemplateItem>, TripError> {
+    let mut stmt = conn.prepare(
+        "SELECT name, category, quantity FROM packing_template_item
+         WHERE template_id = ?1 ORDER BY position",
+    )?;
+    let items = stmt
+        .query_map([template_id], |row| {
+            Ok(PackingTemplateItem {
+                name: row.get(0)?,
+                category: row.get(1)?,
+                quantity: row.get(2)?,
+            })
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(items)
+}
+
+/// Add a template's items to the trip's packing list, unpacked. Items the
+/// list already has, by name regardless of case, are skipped. Returns the
+/// added items.
+pub fn apply_packing_template(
+    conn: &Connection,
+    template_id: &str,
+    trip_id: &str,
+) -> Result<Vec<PackingItem>, TripError> {
+    let trip = get_trip(conn, trip_id)?;
+    let in_space: bool = conn.query_row(
+        "SELECT EXISTS(SELECT 1 FROM packing_template WHERE id = ?1 AND space_id = ?2)",
+        params![template_id, trip.space_id],
+        |row| row.get(0),
+    )?;
+    // Templates are reused within their space only
+    if !in_space {
+        return Err(TripError::NotFound {
+            entity: "packing_template",
+            id: template_id.to_string(),
+        });
+    }
+
+    let mut listed: HashSet<String> = get_packing_list(conn, trip_id)?
+        .into_iter()
+        .map(|item| item.name.to_lowercase())
+        .collect();
+    let tx = conn.unchecked_transaction()?;
+    let mut added = Vec::new();
+    for item in packing_template_items(&tx, template_id)? {
+        if listed.insert(item.name.to_lowercase()) {
+            added.push(insert_packing_item(
+                &tx,
+                trip_id,
+                &item.name,
+                item.category.as_deref(),
+                item.quantity,
+            )?);
+        }
+    }
+    tx.commit()?;
+    Ok(added)
+}
+
+pub fn delete_packing_template(conn: &Connection, template_id: &str) -> Result<(), TripError> {
+    let deleted = conn.execute("DELETE FROM packing_template WHERE id = ?1", [template_id])?;
+    if deleted == 0 {
+        return Err(TripError::NotFound {
+            entity: "packing_template",
+            id: template_id.to_string(),
+        });
+    }
+    Ok(())
+}
+
+// --- Summaries ---
+
+pub fn get_trip_summary(conn: &Connection, trip_id: &str) -> Result<TripSummary, TripError> {
+    let trip = get_trip(conn, trip_id)?;
+    summarize(conn, trip)
+}
+
+fn summarize(conn: &Connection, trip: Trip) -> Result<TripSummary, TripError> {
+    let days = get_trip_itinerary(conn, &trip.id)?
+        .into_iter()
+        .map(|day| ItineraryDayCount {
+            day: day.day,
+            item_count: day.items.len() as i64,
+        })
+        .collect::<Vec<_>>();
+    let packing = get_trip_packing_stats(conn, &trip.id)?;
+    Ok(TripSummary {
+        item_count: days.iter().map(|d| d.item_count).sum(),
+        unpacked_count: packing.unpacked,
+        days,
+        trip_id: trip.id,
+        name: trip.name,
+        destination: trip.destination,
+        start_date: trip.start_date,
+        end_date: trip.end_date,
+    })
+}
+
+/// Summaries of the space's trips that haven't ended by `now`'s day,
+/// soonest first
+pub fn get_upcoming_trip_summaries(
+    conn: &Connection,
+    space_id: &str,
+    now: i64,
+) -> Result<Vec<TripSummary>, TripError> {
+    let today = date_of(now)?;
+    let mut stmt = conn.prepare(&format!(
+        "SELECT {TRIP_COLUMNS} FROM trip WHERE space_id = ?1 ORDER BY start_date, id"
+    ))?;
+    let trips = stmt
+        .query_map([space_id], trip_from_row)?
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let mut summaries = Vec::new();
+    for trip in trips {
+        if trip_dates(&trip)?.1 >= today {
+            summaries.push(summarize(conn, trip)?);
+        }
+    }
+    Ok(summaries)
+}
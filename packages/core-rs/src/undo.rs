@@ -0,0 +1,566 @@
+//! Undo journal for destructive operations.
+//!
+//! Functions that delete data record what they removed in `undo_log`: every
+//! deleted row (the entity and its relation rows) plus the old values of rows
+//! they modified on the way, e.g. subtasks whose parent went away. An entry
+//! can be undone until it expires, as long as nothing has been created since
+//! that the restored rows would collide with.
+//!
+//! The journal is local to the device. It's bounded by age
+//! ([`UNDO_TTL_SECS`]), entry count ([`MAX_UNDO_ENTRIES`]) and total snapshot
+//! size ([`MAX_JOURNAL_BYTES`]), and `undo_log` isn't one of the tables sync
+//! gathers deltas from. Restoring rows touches their `updated_at`, so an undone
+//! delete reaches other devices as a fresh change.
+
+use crate::audit::{audit_change, AuditOperation, AUDIT_SOURCE_LOCAL};
+use crate::maintenance::{
+    MaintenanceError, MaintenanceRegistry, MaintenanceTask, TaskReport, PRIORITY_HIGH,
+};
+use rusqlite::types::{ToSqlOutput, Value, ValueRef};
+use rusqlite::{params, params_from_iter, Connection, OptionalExtension, Params, ToSql};
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+use thiserror::Error;
+use ulid::Ulid;
+
+/// How long an operation can be undone
+pub const UNDO_TTL_SECS: i64 = 7 * 24 * 60 * 60;
+
+/// Most entries kept; older ones are dropped first
+pub const MAX_UNDO_ENTRIES: i64 = 500;
+
+/// Most snapshot bytes kept across all entries
+pub const MAX_JOURNAL_BYTES: i64 = 16 * 1024 * 1024;
+
+/// Operations whose snapshot is larger than this aren't journaled
+pub const MAX_ENTRY_BYTES: usize = 4 * 1024 * 1024;
+
+#[derive(Error, Debug)]
+pub enum UndoError {
+    #[error("Database error: {0}")]
+    Database(#[from] rusqlite::Error),
+    #[error("Snapshot error: {0}")]
+    Snapshot(#[from] serde_json::Error),
+    #[error("Undo entry not found: {0}")]
+    NotFound(String),
+    #[error("Undo entry {0} has expired")]
+    Expired(String),
+    #[error("Cannot undo: {0}")]
+    Conflict(UndoConflict),
+    #[error("Database error: {0}")]
+    Db(#[from] crate::db::DbError),
+}
+
+/// Why the rows of an entry can't be restored
+#[derive(Error, Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum UndoConflict {
+    /// A row with the same key was created after the delete
+    #[error("{table} {key} has been created again since it was deleted")]
+    RowExists { table: String, key: String },
+    /// A restored row breaks a constraint, e.g. its space is gone
+    #[error("restoring {table} violates a constraint: {message}")]
+    Constraint { table: String, message: String },
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct UndoableOperation {
+    pub id: String,
+    pub space_id: String,
+    /// What removed the data, e.g. `delete_task`
+    pub operation: String,
+    pub entity_type: String,
+    pub entity_id: String,
+    pub summary: String,
+    /// Rows the undo would restore
+    pub row_count: i64,
+    pub size_bytes: i64,
+    pub created_at: i64,
+    pub expires_at: i64,
+}
+
+/// A column value as stored in a snapshot
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub(crate) enum SnapshotValue {
+    Null,
+    Integer(i64),
+    Real(f64),
+    Text(String),
+    Blob { blob: Vec<u8> },
+}
+
+impl From<ValueRef<'_>> for SnapshotValue {
+    fn from(value: ValueRef<'_>) -> Self {
+        match value {
+            ValueRef::Null => SnapshotValue::Null,
+            ValueRef::Integer(i) => SnapshotValue::Integer(i),
+            ValueRef::Real(f) => SnapshotValue::Real(f),
+            ValueRef::Text(t) => SnapshotValue::Text(String::from_utf8_lossy(t).into_owned()),
+            ValueRef::Blob(b) => SnapshotValue::Blob { blob: b.to_vec() },
+        }
+    }
+}
+
+impl ToSql for SnapshotValue {
+    fn to_sql(&self) -> rusqlite::Result<ToSqlOutput<'_>> {
+        Ok(match self {
+            SnapshotValue::Null => ToSqlOutput::Owned(Value::Null),
+            SnapshotValue::Integer(i) => ToSqlOutput::Owned(Value::Integer(*i)),
+            SnapshotValue::Real(f) => ToSqlOutput::Owned(Value::Real(*f)),
+            SnapshotValue::Text(t) => ToSqlOutput::Borrowed(ValueRef::Text(t.as_bytes())),
+            SnapshotValue::Blob { blob } => ToSqlOutput::Borrowed(ValueRef::Blob(blob)),
+        })
+    }
+}
+
+/// Rows of one table, sharing a column list to keep the snapshot small
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub(crate) struct TableRows {
+    pub table: String,
+    pub columns: Vec<String>,
+    pub rows: Vec<Vec<SnapshotValue>>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub(crate) struct UndoSnapshot {
+    /// Rows to insert again, parents before the rows referencing them
+    pub deleted: Vec<TableRows>,
+    /// Previous values of rows the operation changed, written back by
+    /// primary key; rows deleted since are skipped
+    pub modified: Vec<TableRows>,
+    /// Rows the operation added, deleted again by primary key
+    #[serde(default)]
+    pub inserted: Vec<TableRows>,
+}
+
+impl UndoSnapshot {
+    fn row_count(&self) -> usize {
+        self.deleted
+            .iter()
+            .chain(&self.modified)
+            .chain(&self.inserted)
+            .map(|t| t.rows.len())
+            .sum()
+    }
+}
+
+/// A destructive operation about to be journaled
+pub(crate) struct UndoRecord<'a> {
+    pub space_id: &'a str,
+    pub operation: &'a str,
+    pub entity_type: &'a str,
+    pub entity_id: &'a str,
+    pub summary: String,
+    pub snapshot: UndoSnapshot,
+}
+
+/// Read `columns` (a select list, `*` for whole rows) of the rows of `table`
+/// matching `filter`. Returns `None` when nothing matches.
+pub(crate) fn capture_rows<P: Params>(
+    conn: &Connection,
+    table: &str,
+    columns: &str,
+    filter: &str,
+    params: P,
+) -> rusqlite::Result<Option<TableRows>> {
+    let mut stmt = conn.prepare(&format!(
+        "SELECT {} FROM {} WHERE {}",
+        columns, table, filter
+    ))?;
+    let names: Vec<String> = stmt.column_names().into_iter().map(String::from).collect();
+    let width = names.len();
+    let rows = stmt
+        .query_map(params, |row| {
+            (0..width)
+                .map(|i| row.get_ref(i).map(SnapshotValue::from))
+                .collect::<rusqlite::Result<Vec<_>>>()
+        })?
+        .collect::<rusqlite::Result<Vec<_>>>()?;
+    Ok((!rows.is_empty()).then(|| TableRows {
+        table: table.to_string(),
+        columns: names,
+        rows,
+    }))
+}
+
+/// Journal a destructive operation. Like auditing, a failure to journal is
+/// logged and never fails the operation itself.
+pub(crate) fn record_undo(conn: &Connection, record: UndoRecord<'_>) {
+    if !crate::db::table_exists(conn, "undo_log").unwrap_or(false) {
+        return;
+    }
+    if let Err(e) = try_record_undo(conn, &record) {
+        log::warn!(
+            "[undo] Failed to journal {} of {} {}: {}",
+            record.operation,
+            record.entity_type,
+            record.entity_id,
+            e
+        );
+    }
+}
+
+fn try_record_undo(conn: &Connection, record: &UndoRecord<'_>) -> Result<(), UndoError> {
+    let snapshot = serde_json::to_string(&record.snapshot)?;
+    if snapshot.len() > MAX_ENTRY_BYTES {
+        log::warn!(
+            "[undo] Not journaling {} of {} {}: snapshot is {} bytes",
+            record.operation,
+            record.entity_type,
+            record.entity_id,
+            snapshot.len()
+        );
+        return Ok(());
+    }
+
+    let now = chrono::Utc::now().timestamp();
+    conn.execute(
+        "INSERT INTO undo_log (id, space_id, operation, entity_type, entity_id, summary,
+                               snapshot, row_count, size_bytes, created_at, expires_at)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11)",
+        params![
+            Ulid::new().to_string(),
+            record.space_id,
+            record.operation,
+            record.entity_type,
+            record.entity_id,
+            record.summary,
+            snapshot,
+            record.snapshot.row_count() as i64,
+            snapshot.len() as i64,
+            now,
+            now + UNDO_TTL_SECS
+        ],
+    )?;
+    prune_undo_log(conn)?;
+    Ok(())
+}
+
+/// Drop expired entries, then the oldest ones past the count and size bounds.
+/// Returns how many entries were removed.
+pub fn prune_undo_log(conn: &Connection) -> Result<usize, UndoError> {
+    let expired = conn.execute(
+        "DELETE FROM undo_log WHERE expires_at <= ?1",
+        [chrono::Utc::now().timestamp()],
+    )?;
+    let over_bounds = conn.execute(
+        "DELETE FROM undo_log WHERE id IN (
+            SELECT id FROM (
+                SELECT id,
+                       ROW_NUMBER() OVER newest AS position,
+                       SUM(size_bytes) OVER newest AS total_bytes
+                FROM undo_log
+                WINDOW newest AS (ORDER BY created_at DESC, rowid DESC)
+            )
+            WHERE position > ?1 OR total_bytes > ?2
+        )",
+        params![MAX_UNDO_ENTRIES, MAX_JOURNAL_BYTES],
+    )?;
+    Ok(expired + over_bounds)
+}
+
+/// [`prune_undo_log`] as a background maintenance task
+pub struct UndoLogPruneTask;
+
+impl MaintenanceTask for UndoLogPruneTask {
+    fn name(&self) -> &str {
+        "undo_log_prune"
+    }
+
+    fn estimated_cost(&self) -> Duration {
+        Duration::from_millis(50)
+    }
+
+    fn run(&self, conn: &Connection) -> Result<TaskReport, MaintenanceError> {
+        let removed = prune_undo_log(conn).map_err(|e| MaintenanceError::Task(e.to_string()))?;
+        Ok(TaskReport {
+            items_affected: removed as u64,
+        })
+    }
+}
+
+pub(crate) fn register_maintenance(registry: &mut MaintenanceRegistry) {
+    registry.register(PRIORITY_HIGH, UndoLogPruneTask);
+}
+
+const OPERATION_COLUMNS: &str = "id, space_id, operation, entity_type, entity_id, summary,
+                                 row_count, size_bytes, created_at, expires_at";
+
+fn operation_from_row(row: &rusqlite::Row) -> rusqlite::Result<UndoableOperation> {
+    Ok(UndoableOperation {
+        id: row.get(0)?,
+        space_id: row.get(1)?,
+        operation: row.get(2)?,
+        entity_type: row.get(3)?,
+        entity_id: row.get(4)?,
+        summary: row.get(5)?,
+        row_count: row.get(6)?,
+        size_bytes: row.get(7)?,
+        created_at: row.get(8)?,
+        expires_at: row.get(9)?,
+    })
+}
+
+/// Operations that can still be undone, newest first
+pub fn list_undoable_operations(
+    conn: &Connection,
+    limit: u32,
+) -> Result<Vec<UndoableOperation>, UndoError> {
+    let mut stmt = conn.prepare(&format!(
+        "SELECT {} FROM undo_log WHERE expires_at > ?1
+         ORDER BY created_at DESC, rowid DESC LIMIT ?2",
+        OPERATION_COLUMNS
+    ))?;
+    let operations = stmt
+        .query_map(
+            params![chrono::Utc::now().timestamp(), limit],
+            operation_from_row,
+        )?
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(operations)
+}
+
+/// Restore the rows removed by `op_id` in one transaction and drop the entry.
+/// Nothing is restored if any row conflicts with data created since.
+pub fn undo_operation(conn: &Connection, op_id: &str) -> Result<UndoableOperation, UndoError> {
+    log::info!("[undo] Undoing operation {}", op_id);
+    let (operation, snapshot) = conn
+        .query_row(
+            &format!(
+                "SELECT {}, snapshot FROM undo_log WHERE id = ?1",
+                OPERATION_COLUMNS
+            ),
+            [op_id],
+            |row| Ok((operation_from_row(row)?, row.get::<_, String>(10)?)),
+        )
+        .optional()?
+        .ok_or_else(|| UndoError::NotFound(op_id.to_string()))?;
+    if operation.expires_at <= chrono::Utc::now().timestamp() {
+        return Err(UndoError::Expired(op_id.to_string()));
+    }
+    let snapshot: UndoSnapshot = serde_json::from_str(&snapshot)?;
+
+    let tx = conn.unchecked_transaction()?;
+    for table in &snapshot.inserted {
+        remove_inserted(&tx, table)?;
+    }
+    for table in &snapshot.deleted {
+        restore_deleted(&tx, table)?;
+    }
+    for table in &snapshot.modified {
+        restore_modified(&tx, table)?;
+    }
+    touch_entity(&tx, &operation, &snapshot)?;
+    audit_change(
+        &tx,
+        &operation.space_id,
+        &operation.entity_type,
+        &operation.entity_id,
+        AuditOperation::Create,
+        AUDIT_SOURCE_LOCAL,
+    );
+    tx.execute("DELETE FROM undo_log WHERE id = ?1", [op_id])?;
+    tx.commit()?;
+
+    log::info!(
+        "[undo] Restored {} rows of {} {}",
+        operation.row_count,
+        operation.entity_type,
+        operation.entity_id
+    );
+    Ok(operation)
+}
+
+fn primary_key_columns(conn: &Connection, table: &str) -> rusqlite::Result<Vec<String>> {
+    let mut stmt = conn.prepare(&format!(
+        "SELECT name FROM pragma_table_info('{}') WHERE pk > 0 ORDER BY pk",
+        table
+    ))?;
+    let columns = stmt
+        .query_map([], |row| row.get(0))?
+        .collect::<Result<Vec<String>, _>>()?;
+    Ok(columns)
+}
+
+/// Positions of `keys` in `columns`, or `None` if the snapshot lacks one
+fn key_positions(columns: &[String], keys: &[String]) -> Option<Vec<usize>> {
+    keys.iter()
+        .map(|key| columns.iter().position(|c| c == key))
+        .collect()
+}
+
+fn describe_key(row: &[SnapshotValue], positions: &[usize]) -> String {
+    positions
+        .iter()
+        .map(|&i| match &row[i] {
+            SnapshotValue::Null => "NULL".to_string(),
+            SnapshotValue::Integer(v) => v.to_string(),
+            SnapshotValue::Real(v) => v.to_string(),
+            SnapshotValue::Text(v) => v.clone(),
+            SnapshotValue::Blob { blob } => format!("<{} bytes>", blob.len()),
+        })
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+fn restore_deleted(conn: &Connection, table: &TableRows) -> Result<(), UndoError> {
+    let keys = primary_key_columns(conn, &table.table)?;
+    let positions = key_positions(&table.columns, &keys).filter(|p| !p.is_empty());
+    let exists_sql = format!(
+        "SELECT 1 FROM {} WHERE {}",
+        table.table,
+        keys.iter()
+            .enumerate()
+            .map(|(i, key)| format!("{} IS ?{}", key, i + 1))
+            .collect::<Vec<_>>()
+            .join(" AND ")
+    );
+    let insert_sql = format!(
+        "INSERT INTO {} ({}) VALUES ({})",
+        table.table,
+        table.columns.join(", "),
+        (1..=table.columns.len())
+            .map(|i| format!("?{}", i))
+            .collect::<Vec<_>>()
+            .join(", ")
+    );
+
+    for row in &table.rows {
+        if let Some(positions) = &positions {
+            let exists = conn
+                .query_row(
+                    &exists_sql,
+                    params_from_iter(positions.iter().map(|&i| &row[i])),
+                    |_| Ok(()),
+                )
+                .optional()?
+                .is_some();
+            if exists {
+                return Err(UndoError::Conflict(UndoConflict::RowExists {
+                    table: table.table.clone(),
+                    key: describe_key(row, positions),
+                }));
+            }
+        }
+        conn.execute(&insert_sql, params_from_iter(row.iter()))
+            .map_err(|e| match e {
+                rusqlite::Error::SqliteFailure(err, message)
+                    if err.code == rusqlite::ErrorCode::ConstraintViolation =>
+                {
+                    UndoError::Conflict(UndoConflict::Constraint {
+                        table: table.table.clone(),
+                        message: message.unwrap_or_else(|| err.to_string()),
+                    })
+                }
+                e => UndoError::Database(e),
+            })?;
+    }
+    Ok(())
+}
+
+fn remove_inserted(conn: &Connection, table: &TableRows) -> Result<(), UndoError> {
+    let keys = primary_key_columns(conn, &table.table)?;
+    let Some(positions) = key_positions(&table.columns, &keys).filter(|p| !p.is_empty()) else {
+        log::warn!(
+            "[undo] Snapshot of {} lacks its primary key; not removing it",
+            table.table
+        );
+        return Ok(());
+    };
+    let sql = format!(
+        "DELETE FROM {} WHERE {}",
+        table.table,
+        positions
+            .iter()
+            .enumerate()
+            .map(|(n, &i)| format!("{} IS ?{}", table.columns[i], n + 1))
+            .collect::<Vec<_>>()
+            .join(" AND ")
+    );
+    for row in &table.rows {
+        conn.execute(&sql, params_from_iter(positions.iter().map(|&i| &row[i])))?;
+    }
+    Ok(())
+}
+
+fn restore_modified(conn: &Connection, table: &TableRows) -> Result<(), UndoError> {
+    let keys = primary_key_columns(conn, &table.table)?;
+    let Some(positions) = key_positions(&table.columns, &keys).filter(|p| !p.is_empty()) else {
+        log::warn!(
+            "[undo] Snapshot of {} lacks its primary key; not restoring it",
+            table.table
+        );
+        return Ok(());
+    };
+    let values: Vec<usize> = (0..table.columns.len())
+        .filter(|i| !positions.contains(i))
+        .collect();
+    if values.is_empty() {
+        return Ok(());
+    }
+    let sql = format!(
+        "UPDATE {} SET {} WHERE {}",
+        table.table,
+        values
+            .iter()
+            .enumerate()
+            .map(|(n, &i)| format!("{} = ?{}", table.columns[i], n + 1))
+            .collect::<Vec<_>>()
+            .join(", "),
+        positions
+            .iter()
+            .enumerate()
+            .map(|(n, &i)| format!("{} IS ?{}", table.columns[i], values.len() + n + 1))
+            .collect::<Vec<_>>()
+            .join(" AND ")
+    );
+    for row in &table.rows {
+        conn.execute(
+            &sql,
+            params_from_iter(values.iter().chain(&positions).map(|&i| &row[i])),
+        )?;
+    }
+    Ok(())
+}
+
+/// Mark the restored entity as changed now, so sync sends it again.
+/// Restored notes also get their search index, links and CRDT log back in
+/// line with their body.
+fn touch_entity(
+    conn: &Connection,
+    operation: &UndoableOperation,
+    snapshot: &UndoSnapshot,
+) -> Result<(), UndoError> {
+    let now = chrono::Utc::now().timestamp();
+    for table in snapshot
+        .deleted
+        .iter()
+        .chain(&snapshot.modified)
+        .filter(|t| t.table == "note")
+    {
+        let Some(id) = table.columns.iter().position(|c| c == "id") else {
+            continue;
+        };
+        for row in &table.rows {
+            if let SnapshotValue::Text(note_id) = &row[id] {
+                crate::note::refresh_note(conn, note_id, now)?;
+            }
+        }
+    }
+
+    let restored = snapshot
+        .deleted
+        .iter()
+        .any(|t| t.table == operation.entity_type && t.columns.iter().any(|c| c == "updated_at"));
+    if restored {
+        conn.execute(
+            &format!(
+                "UPDATE {} SET updated_at = ?1 WHERE id = ?2",
+                operation.entity_type
+            ),
+            params![now, operation.entity_id],
+        )?;
+    }
+    Ok(())
+}
@@ -0,0 +1,506 @@
+//! Link previews for external URLs in notes.
+//!
+//! Pages are fetched once and their title, description, image and site name
+//! cached by URL, so every platform can render previews without re-fetching
+//! and still show them offline. Fetching refuses anything that resolves to a
+//! private, loopback or link-local address, follows a capped number of
+//! redirects without ever downgrading HTTPS to HTTP, and reads no more than a
+//! size limit of the page.
+
+use crate::db::DbError;
+use regex::Regex;
+use reqwest::Url;
+use rusqlite::{Connection, OptionalExtension};
+use serde::{Deserialize, Serialize};
+use std::net::{IpAddr, SocketAddr};
+use std::time::Duration;
+use thiserror::Error;
+use ulid::Ulid;
+
+/// How much of a page is read looking for the end of its `<head>`
+pub const DEFAULT_MAX_BYTES: usize = 1024 * 1024;
+pub const DEFAULT_MAX_REDIRECTS: usize = 5;
+/// How long fetched metadata is served before it is fetched again
+pub const METADATA_TTL: Duration = Duration::from_secs(7 * 24 * 60 * 60);
+/// Failed fetches are cached too, briefly, so a dead link isn't retried on
+/// every render
+pub const FAILURE_TTL: Duration = Duration::from_secs(60 * 60);
+
+#[derive(Error, Debug)]
+pub enum UrlMetadataError {
+    #[error("HTTP error: {0}")]
+    Http(#[from] reqwest::Error),
+    #[error("Invalid URL: {0}")]
+    InvalidUrl(String),
+    #[error("Refusing to fetch {0}: it resolves to a private address")]
+    PrivateAddress(String),
+    #[error("Refusing to follow a redirect from HTTPS to HTTP: {0}")]
+    InsecureRedirect(String),
+    #[error("More than {0} redirects")]
+    TooManyRedirects(usize),
+    #[error("Response is larger than {0} bytes")]
+    TooLarge(usize),
+    #[error("Server responded with status {0}")]
+    Status(u16),
+    #[error("Not an HTML page: {0}")]
+    NotHtml(String),
+}
+
+#[derive(Debug, Clone)]
+pub struct FetchOptions {
+    pub max_bytes: usize,
+    pub max_redirects: usize,
+    pub timeout: Duration,
+    /// Only for tests against a local server
+    pub allow_private_addresses: bool,
+}
+
+impl Default for FetchOptions {
+    fn default() -> Self {
+        Self {
+            max_bytes: DEFAULT_MAX_BYTES,
+            max_redirects: DEFAULT_MAX_REDIRECTS,
+            timeout: Duration::from_secs(10),
+            allow_private_addresses: false,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct UrlMetadata {
+    /// The URL as written in the note
+    pub url: String,
+    /// Where the page was found after redirects
+    pub final_url: String,
+    pub title: Option<String>,
+    pub description: Option<String>,
+    pub image_url: Option<String>,
+    pub site_name: Option<String>,
+    pub favicon_url: Option<String>,
+    /// Why the last fetch failed; the other fields are empty when set
+    pub error: Option<String>,
+    pub fetched_at: i64,
+    pub expires_at: i64,
+}
+
+impl UrlMetadata {
+    /// A cache entry recording that fetching `url` failed
+    pub fn failed(url: &str, error: &UrlMetadataError) -> Self {
+        let now = chrono::Utc::now().timestamp();
+        Self {
+            url: url.to_string(),
+            final_url: url.to_string(),
+            title: None,
+            description: None,
+            image_url: None,
+            site_name: None,
+            favicon_url: None,
+            error: Some(error.to_string()),
+            fetched_at: now,
+            expires_at: now + FAILURE_TTL.as_secs() as i64,
+        }
+    }
+
+    pub fn is_expired(&self) -> bool {
+        self.expires_at <= chrono::Utc::now().timestamp()
+    }
+}
+
+/// The external links in a note
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct NoteLinkPreviews {
+    /// Everything known about the note's links, including expired entries,
+    /// which are still worth showing offline
+    pub cached: Vec<UrlMetadata>,
+    /// Links never fetched or whose entry has expired, in note order
+    pub needs_fetch: Vec<String>,
+}
+
+/// Whether an address is reachable on the public internet
+fn is_public_ip(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(v4) => {
+            let [a, b, ..] = v4.octets();
+            !(v4.is_private()
+                || v4.is_loopback()
+                || v4.is_link_local()
+                || v4.is_unspecified()
+                || v4.is_broadcast()
+                || v4.is_documentation()
+                || v4.is_multicast()
+                || a == 0
+                // Carrier-grade NAT, benchmarking and reserved ranges
+                || (a == 100 && (64..128).contains(&b))
+                || (a == 198 && (18..20).contains(&b))
+                || a >= 240)
+        }
+        IpAddr::V6(v6) => {
+            if let Some(v4) = v6.to_ipv4_mapped() {
+                return is_public_ip(IpAddr::V4(v4));
+            }
+            let first = v6.segments()[0];
+            !(v6.is_loopback()
+                || v6.is_unspecified()
+                || v6.is_multicast()
+                // Unique local, link-local and documentation ranges
+                || (first & 0xfe00) == 0xfc00
+                || (first & 0xffc0) == 0xfe80
+                || (first == 0x2001 && v6.segments()[1] == 0x0db8))
+        }
+    }
+}
+
+fn parse_http_url(url: &str) -> Result<Url, UrlMetadataError> {
+    let parsed = Url::parse(url).map_err(|_| UrlMetadataError::InvalidUrl(url.to_string()))?;
+    if !matches!(parsed.scheme(), "http" | "https") || parsed.host_str().is_none() {
+        return Err(UrlMetadataError::InvalidUrl(url.to_string()));
+    }
+    Ok(parsed)
+}
+
+/// A client that can only connect to the addresses `url` resolved to when it
+/// was checked, so a second DNS answer can't point it somewhere private
+async fn client_for(
+    url: &Url,
+    options: &FetchOptions,
+) -> Result<reqwest::Client, UrlMetadataError> {
+    let host = url
+        .host_str()
+        .ok_or_else(|| UrlMetadataError::InvalidUrl(url.to_string()))?;
+    let port = url.port_or_known_default().unwrap_or(80);
+    let literal = host
+        .trim_start_matches('[')
+        .trim_end_matches(']')
+        .parse::<IpAddr>()
+        .ok();
+    let addrs: Vec<SocketAddr> = match literal {
+        Some(ip) => vec![SocketAddr::new(ip, port)],
+        None => tokio::net::lookup_host((host, port))
+            .await
+            .map_err(|e| UrlMetadataError::InvalidUrl(format!("{}: {}", url, e)))?
+            .collect(),
+    };
+    if addrs.is_empty() {
+        return Err(UrlMetadataError::InvalidUrl(url.to_string()));
+    }
+    if !options.allow_private_addresses && addrs.iter().any(|a| !is_public_ip(a.ip())) {
+        return Err(UrlMetadataError::PrivateAddress(url.to_string()));
+    }
+
+    let mut builder = reqwest::Client::builder()
+        .redirect(reqwest::redirect::Policy::none())
+        .timeout(options.timeout)
+        .user_agent("Noteece link preview");
+    if literal.is_none() {
+        builder = builder.resolve(host, addrs[0]);
+    }
+    Ok(builder.build()?)
+}
+
+/// Read the body up to the end of its `<head>`, failing past `max_bytes`
+async fn read_head(
+    mut response: reqwest::Response,
+    max_bytes: usize,
+) -> Result<String, UrlMetadataError> {
+    let mut body = Vec::new();
+    while let Some(chunk) = response.chunk().await? {
+        let scanned = body.len().saturating_sub(6);
+        body.extend_from_slice(&chunk);
+        if body.len() > max_bytes {
+            return Err(UrlMetadataError::TooLarge(max_bytes));
+        }
+        if body[scanned..]
+            .windows(7)
+            .any(|w| w.eq_ignore_ascii_case(b"</head>"))
+        {
+            break;
+        }
+    }
+    Ok(String::from_utf8_lossy(&body).into_owned())
+}
+
+/// Fetch a page's metadata with the default limits
+pub async fn fetch_url_metadata(url: &str) -> Result<UrlMetadata, UrlMetadataError> {
+    fetch_url_metadata_with(url, &FetchOptions::default()).await
+}
+
+pub async fn fetch_url_metadata_with(
+    url: &str,
+    options: &FetchOptions,
+) -> Result<UrlMetadata, UrlMetadataError> {
+    let mut current = parse_http_url(url)?;
+    for _ in 0..=options.max_redirects {
+        let client = client_for(&current, options).await?;
+        let response = client
+            .get(current.clone())
+            .header(reqwest::header::ACCEPT, "text/html,application/xhtml+xml")
+            .send()
+            .await?;
+
+        let status = response.status();
+        if status.is_redirection() {
+            let location = response
+                .headers()
+                .get(reqwest::header::LOCATION)
+                .and_then(|v| v.to_str().ok())
+                .ok_or(UrlMetadataError::Status(status.as_u16()))?;
+            let next = current
+                .join(location)
+                .map_err(|_| UrlMetadataError::InvalidUrl(location.to_string()))?;
+            let next = parse_http_url(next.as_str())?;
+            if current.scheme() == "https" && next.scheme() != "https" {
+                return Err(UrlMetadataError::InsecureRedirect(next.to_string()));
+            }
+            current = next;
+            continue;
+        }
+        if !status.is_success() {
+            return Err(UrlMetadataError::Status(status.as_u16()));
+        }
+        if let Some(content_type) = response
+            .headers()
+            .get(reqwest::header::CONTENT_TYPE)
+            .and_then(|v| v.to_str().ok())
+        {
+            if !content_type.contains("html") {
+                return Err(UrlMetadataError::NotHtml(content_type.to_string()));
+            }
+        }
+        let head = read_head(response, options.max_bytes).await?;
+        log::debug!("[url_metadata] Fetched {} ({} bytes)", current, head.len());
+        return Ok(parse_html_metadata(url, &current, &head));
+    }
+    Err(UrlMetadataError::TooManyRedirects(options.max_redirects))
+}
+
+fn decode_entities(text: &str) -> String {
+    let entity = Regex::new(r"&(#[0-9]+|#[xX][0-9a-fA-F]+|[a-zA-Z]+);").expect("valid regex");
+    entity
+        .replace_all(text, |caps: &regex::Captures| {
+            let name = &caps[1];
+            let decoded = match name {
+                "amp" => Some('&'),
+                "lt" => Some('<'),
+                "gt" => Some('>'),
+                "quot" => Some('"'),
+                "apos" => Some('\''),
+                "nbsp" => Some(' '),
+                _ => name
+                    .strip_prefix('#')
+                    .and_then(|number| match number.strip_prefix(['x', 'X']) {
+                        Some(hex) => u32::from_str_radix(hex, 16).ok(),
+                        None => number.parse().ok(),
+                    })
+                    .and_then(char::from_u32),
+            };
+            decoded.map_or_else(|| caps[0].to_string(), |c| c.to_string())
+        })
+        .into_owned()
+}
+
+/// Decoded text with whitespace collapsed, or None if nothing is left
+fn clean(text: &str) -> Option<String> {
+    let text = decode_entities(text)
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ");
+    (!text.is_empty()).then_some(text)
+}
+
+fn attributes(tag: &str) -> Vec<(String, String)> {
+    let attribute = Regex::new(r#"([a-zA-Z_:-]+)\s*=\s*(?:"([^"]*)"|'([^']*)'|([^\s"'>]+))"#)
+        .expect("valid regex");
+    attribute
+        .captures_iter(tag)
+        .map(|caps| {
+            let value = caps
+                .get(2)
+                .or_else(|| caps.get(3))
+                .or_else(|| caps.get(4))
+                .map_or("", |m| m.as_str());
+            (caps[1].to_ascii_lowercase(), value.to_string())
+        })
+        .collect()
+}
+
+/// An absolute http(s) URL for `href` relative to the page
+fn resolve(page_url: &Url, href: &str) -> Option<String> {
+    let resolved = page_url.join(decode_entities(href.trim()).as_str()).ok()?;
+    matches!(resolved.scheme(), "http" | "https").then(|| resolved.to_string())
+}
+
+fn parse_html_metadata(url: &str, page_url: &Url, html: &str) -> UrlMetadata {
+    let meta_tag = Regex::new(r"(?is)<meta\b[^>]*>").expect("valid regex");
+    let link_tag = Regex::new(r"(?is)<link\b[^>]*>").expect("valid regex");
+    let title_tag = Regex::new(r"(?is)<title[^>]*>(.*?)</title>").expect("valid regex");
+
+    // Open Graph tags use `property`, the rest `name`
+    let mut meta: Vec<(String, String)> = Vec::new();
+    for tag in meta_tag.find_iter(html) {
+        let attrs = attributes(tag.as_str());
+        let get = |key: &str| attrs.iter().find(|(k, _)| k == key).map(|(_, v)| v);
+        if let (Some(key), Some(content)) = (get("property").or(get("name")), get("content")) {
+            meta.push((key.to_ascii_lowercase(), content.clone()));
+        }
+    }
+    let first = |keys: &[&str]| {
+        keys.iter().find_map(|key| {
+            meta.iter()
+                .filter(|(k, _)| k == key)
+                .find_map(|(_, v)| clean(v))
+        })
+    };
+
+    let favicon_url = link_tag.find_iter(html).find_map(|tag| {
+        let attrs = attributes(tag.as_str());
+        let rel = attrs
+            .iter()
+            .find(|(k, _)| k == "rel")?
+            .1
+            .to_ascii_lowercase();
+        if !rel.split_whitespace().any(|r| r == "icon") {
+            return None;
+        }
+        let href = &attrs.iter().find(|(k, _)| k == "href")?.1;
+        resolve(page_url, href)
+    });
+    let site_name = first(&["og:site_name", "application-name"]).or_else(|| {
+        page_url
+            .host_str()
+            .map(|host| host.trim_start_matches("www.").to_string())
+    });
+    let now = chrono::Utc::now().timestamp();
+
+    UrlMetadata {
+        url: url.to_string(),
+        final_url: page_url.to_string(),
+        title: first(&["og:title", "twitter:title"]).or_else(|| {
+            title_tag
+                .captures(html)
+                .and_then(|caps| clean(caps.get(1)?.as_str()))
+        }),
+        description: first(&["og:description", "twitter:description", "description"]),
+        image_url: first(&[
+            "og:image",
+            "og:image:url",
+            "og:image:secure_url",
+            "twitter:image",
+        ])
+        .and_then(|href| resolve(page_url, &href)),
+        site_name,
+        favicon_url,
+        error: None,
+        fetched_at: now,
+        expires_at: now + METADATA_TTL.as_secs() as i64,
+    }
+}
+
+const METADATA_COLUMNS: &str = "url, final_url, title, description, image_url, site_name,
+     favicon_url, error, fetched_at, expires_at";
+
+fn metadata_from_row(row: &rusqlite::Row) -> rusqlite::Result<UrlMetadata> {
+    Ok(UrlMetadata {
+        url: row.get(0)?,
+        final_url: row.get(1)?,
+        title: row.get(2)?,
+        description: row.get(3)?,
+        image_url: row.get(4)?,
+        site_name: row.get(5)?,
+        favicon_url: row.get(6)?,
+        error: row.get(7)?,
+        fetched_at: row.get(8)?,
+        expires_at: row.get(9)?,
+    })
+}
+
+/// Cached metadata for the URL, expired or not
+pub fn get_cached_url_metadata(
+    conn: &Connection,
+    url: &str,
+) -> Result<Option<UrlMetadata>, DbError> {
+    let metadata = conn
+        .query_row(
+            &format!(
+                "SELECT {} FROM url_metadata WHERE url = ?1",
+                METADATA_COLUMNS
+            ),
+            [url],
+            metadata_from_row,
+        )
+        .optional()?;
+    Ok(metadata)
+}
+
+/// Store a fetch result, replacing what was cached for its URL
+pub fn cache_url_metadata(conn: &Connection, metadata: &UrlMetadata) -> Result<(), DbError> {
+    conn.execute(
+        &format!(
+            "INSERT OR REPLACE INTO url_metadata ({}) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10)",
+            METADATA_COLUMNS
+        ),
+        rusqlite::params![
+            metadata.url,
+            metadata.final_url,
+            metadata.title,
+            metadata.description,
+            metadata.image_url,
+            metadata.site_name,
+            metadata.favicon_url,
+            metadata.error,
+            metadata.fetched_at,
+            metadata.expires_at
+        ],
+    )?;
+    Ok(())
+}
+
+/// External http(s) links in markdown, in order of first appearance
+pub fn extract_urls(content: &str) -> Vec<String> {
+    let link = Regex::new(r#"https?://[^\s<>()\[\]{}"'`]+"#).expect("valid regex");
+    let mut urls: Vec<String> = Vec::new();
+    for m in link.find_iter(content) {
+        // Sentence punctuation and emphasis markers after a bare link
+        let url = m
+            .as_str()
+            .trim_end_matches(|c: char| ".,;:!?*_~".contains(c));
+        if parse_http_url(url).is_ok() && !urls.iter().any(|u| u == url) {
+            urls.push(url.to_string());
+        }
+    }
+    urls
+}
+
+/// The note's external links, split into those with cached metadata and
+/// those that still need fetching
+pub fn extract_urls_from_note(
+    conn: &Connection,
+    note_id: Ulid,
+) -> Result<NoteLinkPreviews, DbError> {
+    let content: Option<String> = conn
+        .query_row(
+            "SELECT content_md FROM note WHERE id = ?1",
+            [note_id.to_string()],
+            |row| row.get(0),
+        )
+        .optional()?;
+    let Some(content) = content else {
+        return Err(DbError::NotFound {
+            entity: "note",
+            id: note_id.to_string(),
+        });
+    };
+
+    let mut previews = NoteLinkPreviews::default();
+    for url in extract_urls(&content) {
+        match get_cached_url_metadata(conn, &url)? {
+            Some(metadata) => {
+                if metadata.is_expired() {
+                    previews.needs_fetch.push(url);
+                }
+                previews.cached.push(metadata);
+            }
+            None => previews.needs_fetch.push(url),
+        }
+    }
+    Ok(previews)
+}
@@ -1,7 +1,33 @@
-use crate::crypto::{derive_key, generate_dek, unwrap_dek, wrap_dek, CryptoError};
-use crate::db::{migrate, DbError};
-use log::{debug, error, info};
+use crate::audit;
+use crate::crypto::{
+    derive_key, derive_key_argon2, generate_dek, unwrap_dek, wrap_dek, CryptoError,
+};
+use crate::db::{migrate, store_vault_backup, verify_vault_backup, DbError};
+use crate::lockout::{LockoutPolicy, VaultAttemptFile};
+use log::{debug, error, info, warn};
+use std::io::Write;
+use std::path::Path;
 use thiserror::Error;
+use zeroize::Zeroizing;
+
+pub mod autolock;
+pub mod lock;
+pub mod readonly;
+pub mod rotation;
+
+pub use autolock::{AutoLockGuard, Clock, LockReason, SystemClock};
+pub use lock::{LockHolder, LockOptions, VaultLock};
+pub use readonly::{
+    biometric_token, open_vault_readonly, NotePreview, ReadOnlyCredential, TaskPreview,
+    VaultHandle, PREVIEW_CHARS,
+};
+pub use rotation::{rotate_dek, rotate_vault_password, RekeyProgress, RekeyStage};
+
+/// File holding the vault header (salt and wrapped DEK) and the database file
+const CONFIG_FILE: &str = "config.json";
+const DB_FILE: &str = "vault.sqlite3";
+/// Version of headers that record their KDF; unversioned headers used PBKDF2
+const HEADER_VERSION: u64 = 2;
 
 #[derive(Error, Debug)]
 pub enum VaultError {
@@ -19,11 +45,32 @@ pub enum VaultError {
     Rusqlite(#[from] rusqlite::Error),
     #[error("Message: {0}")]
     Message(String),
+    #[error("Too many failed unlock attempts; try again in {0} seconds")]
+    LockedOut(i64),
+    #[error("Vault is locked; unlock it to continue")]
+    Locked,
+    #[error("Vault is open read-only; unlock it fully to continue")]
+    ReadOnly,
+    #[error("Vault is already open by {0}")]
+    VaultAlreadyOpen(LockHolder),
 }
 
 pub struct Vault {
     pub conn: rusqlite::Connection,
-    pub dek: [u8; 32],
+    /// Wiped when the vault is dropped; hand a copy to an [`AutoLockGuard`]
+    /// rather than keeping others
+    pub dek: Zeroizing<[u8; 32]>,
+    /// Keeps other openers out until the vault is dropped or closed
+    pub lock: VaultLock,
+}
+
+impl Vault {
+    /// Close the database, then release the lock file
+    pub fn close(self) -> Result<(), VaultError> {
+        let Vault { conn, lock, .. } = self;
+        conn.close().map_err(|(_, e)| e)?;
+        lock.release()
+    }
 }
 
 fn apply_sqlcipher_settings(conn: &rusqlite::Connection, dek: &[u8; 32]) -> Result<(), VaultError> {
@@ -59,9 +106,9 @@ pub fn create_vault(path: &str, password: &str) -> Result<Vault, VaultError> {
     info!("[vault] Creating vault at path: {}", path);
     // 1) Derive keys.
     let salt: [u8; 16] = rand::random();
-    let mk = derive_key(password, &salt);
-    let dek = generate_dek();
-    let wrapped_dek = wrap_dek(&dek, &mk).map_err(|e| {
+    let mk = HeaderKdf::CURRENT.derive(password, &salt)?;
+    let dek = Zeroizing::new(generate_dek());
+    let wrapped_dek = wrap_dek(&dek, &mk[..]).map_err(|e| {
         error!("[vault] DEK wrapping failed: {}", e);
         e
     })?;
@@ -75,6 +122,7 @@ pub fn create_vault(path: &str, password: &str) -> Result<Vault, VaultError> {
         );
         return Err(e.into());
     }
+    let lock = VaultLock::acquire(Path::new(path), &LockOptions::default())?;
     let db_path = std::path::Path::new(path).join("vault.sqlite3");
     info!("[vault] Database path: {:?}", db_path);
     let mut conn = rusqlite::Connection::open(&db_path).map_err(|e| {
@@ -120,80 +168,49 @@ pub fn create_vault(path: &str, password: &str) -> Result<Vault, VaultError> {
     info!("[vault] Vault created and verified successfully.");
 
     // 7) Persist config.
-    let vault_config = serde_json::json!({
-        "salt": hex::encode(salt),
-        "wrapped_dek": hex::encode(&wrapped_dek),
-        "cipher": { "compat": 4, "page_size": 4096, "kdf_iter": 256000, "hmac": "HMAC_SHA512", "kdf": "PBKDF2_HMAC_SHA512" }
-    });
-    let config_path = std::path::Path::new(path).join("config.json");
-    if let Err(e) = std::fs::write(&config_path, serde_json::to_string_pretty(&vault_config)?) {
-        error!(
-            "[vault] Failed to write config file to {:?}: {}",
-            config_path, e
-        );
-        return Err(e.into());
+    let header = VaultHeader::new(salt.to_vec(), HeaderKdf::CURRENT, wrapped_dek)
+        .with_read_key(readonly::wrap_read_key(&dek, &mk)?);
+    if let Err(e) = header.write(Path::new(path), CONFIG_FILE) {
+        error!("[vault] Failed to write config file in '{}': {}", path, e);
+        return Err(e);
     }
     debug!("[vault] Vault config file written.");
+    sync_header_backup(&conn, &header)?;
+    if let Err(e) = readonly::attach_read_cache(&conn, Path::new(path), &dek) {
+        warn!("[vault] Read cache unavailable: {}", e);
+    }
 
-    Ok(Vault { conn, dek })
+    Ok(Vault { conn, dek, lock })
 }
 
 pub fn unlock_vault(path: &str, password: &str) -> Result<Vault, VaultError> {
+    unlock_vault_with_options(path, password, &LockOptions::default())
+}
+
+/// [`unlock_vault`], deciding with `lock_options` whether a lock left behind
+/// by another open may be broken
+pub fn unlock_vault_with_options(
+    path: &str,
+    password: &str,
+    lock_options: &LockOptions,
+) -> Result<Vault, VaultError> {
     info!("[vault] Unlocking vault at path: {}", path);
+    let vault_dir = Path::new(path);
+    let lock = VaultLock::acquire(vault_dir, lock_options)?;
+    rotation::recover_interrupted_rekey(vault_dir)?;
+
     // 1) Load config and reconstruct DEK.
-    let config_path = std::path::Path::new(path).join("config.json");
-    let cfg_str = std::fs::read_to_string(&config_path).map_err(|e| {
-        error!(
-            "[vault] Failed to read config file at {:?}: {}",
-            config_path, e
-        );
-        e
-    })?;
-    let cfg: serde_json::Value = serde_json::from_str(&cfg_str)?;
-    let salt = hex::decode(cfg["salt"].as_str().ok_or_else(|| {
-        error!("[vault] 'salt' missing from config.json");
-        VaultError::Message("missing salt".to_string())
-    })?)?;
-    let wrapped_dek = hex::decode(cfg["wrapped_dek"].as_str().ok_or_else(|| {
-        error!("[vault] 'wrapped_dek' missing from config.json");
-        VaultError::Message("missing wrapped_dek".to_string())
-    })?)?;
-
-    let mk = derive_key(password, &salt);
-    let dek = unwrap_dek(&wrapped_dek, &mk).map_err(|e| {
-        error!("[vault] Failed to unwrap DEK. Incorrect password? {}", e);
-        e
-    })?;
+    let header = VaultHeader::read(vault_dir, CONFIG_FILE)?;
+    let now = chrono::Utc::now().timestamp();
+    let (dek, kek, attempts) =
+        unwrap_key_with_lockout(vault_dir, &header, &header.wrapped_dek, password, now)?;
+    let dek = Zeroizing::new(dek);
     debug!("[vault] DEK unwrapped successfully.");
 
-    // 2) Open DB file and apply SQLCipher settings in the correct order.
-    let db_path = std::path::Path::new(path).join("vault.sqlite3");
-    let conn = rusqlite::Connection::open(&db_path)?;
-    apply_sqlcipher_settings(&conn, &dek)?;
+    // 2) Open DB file, apply SQLCipher settings and verify it is readable.
+    let conn = open_encrypted(&vault_dir.join(DB_FILE), &dek)?;
 
-    // 4) Verify we can read from the DB by checking the schema version.
-    {
-        let mut stmt =
-            conn.prepare("SELECT version FROM schema_version ORDER BY version DESC LIMIT 1")?;
-        match stmt.query_row([], |row| row.get(0)) {
-            Ok(version) => {
-                let version: i64 = version;
-                info!(
-                    "[vault] Successfully read schema version {} from unlocked vault.",
-                    version
-                );
-            }
-            Err(e) => {
-                error!(
-                    "[vault] Failed to read from supposedly unlocked database: {}",
-                    e
-                );
-                return Err(e.into());
-            }
-        }
-    }
-
-    // 5) Set session PRAGMAs.
+    // 3) Set session PRAGMAs.
     conn.execute_batch(
         r#"
         PRAGMA foreign_keys = ON;
@@ -203,5 +220,328 @@ pub fn unlock_vault(path: &str, password: &str) -> Result<Vault, VaultError> {
     )?;
     info!("[vault] Vault unlocked successfully.");
 
-    Ok(Vault { conn, dek })
+    // Completes the backup step of a password rotation interrupted by a crash
+    sync_header_backup(&conn, &header)?;
+    finish_authenticated(
+        &conn,
+        path,
+        attempts,
+        audit::AUTH_VAULT_UNLOCKED,
+        serde_json::json!({}),
+        now,
+    )?;
+
+    // The read cache only speeds up read-only opens; a vault that can't keep
+    // one still unlocks
+    readonly::ensure_wrapped_read_key(vault_dir, &header, &dek, &kek)?;
+    if let Err(e) = readonly::attach_read_cache(&conn, vault_dir, &dek) {
+        warn!("[vault] Read cache unavailable: {}", e);
+    }
+
+    Ok(Vault { conn, dek, lock })
+}
+
+/// The salt and wrapped DEK stored in `config.json`, plus the rest of the
+/// file (cipher settings, the wrapped read key) so rewriting the header
+/// preserves it
+struct VaultHeader {
+    salt: Vec<u8>,
+    kdf: HeaderKdf,
+    wrapped_dek: Vec<u8>,
+    config: serde_json::Value,
+}
+
+impl VaultHeader {
+    fn new(salt: Vec<u8>, kdf: HeaderKdf, wrapped_dek: Vec<u8>) -> Self {
+        let config = serde_json::json!({
+            "version": HEADER_VERSION,
+            "salt": hex::encode(&salt),
+            "kdf": kdf.to_json(),
+            "wrapped_dek": hex::encode(&wrapped_dek),
+            "cipher": { "compat": 4, "page_size": 4096, "kdf_iter": 256000, "hmac": "HMAC_SHA512", "kdf": "PBKDF2_HMAC_SHA512" }
+        });
+        VaultHeader {
+            salt,
+            kdf,
+            wrapped_dek,
+            config,
+        }
+    }
+
+    fn read(dir: &Path, file_name: &str) -> Result<Self, VaultError> {
+        let config_path = dir.join(file_name);
+        let cfg_str = std::fs::read_to_string(&config_path).map_err(|e| {
+            error!(
+                "[vault] Failed to read config file at {:?}: {}",
+                config_path, e
+            );
+            e
+        })?;
+        let config: serde_json::Value = serde_json::from_str(&cfg_str)?;
+        let salt = hex::decode(config["salt"].as_str().ok_or_else(|| {
+            error!("[vault] 'salt' missing from {}", file_name);
+            VaultError::Message("missing salt".to_string())
+        })?)?;
+        let wrapped_dek = hex::decode(config["wrapped_dek"].as_str().ok_or_else(|| {
+            error!("[vault] 'wrapped_dek' missing from {}", file_name);
+            VaultError::Message("missing wrapped_dek".to_string())
+        })?)?;
+        let kdf = match config["version"].as_u64() {
+            None => HeaderKdf::Pbkdf2,
+            Some(HEADER_VERSION) => HeaderKdf::from_json(&config["kdf"]).ok_or_else(|| {
+                error!("[vault] 'kdf' missing or invalid in {}", file_name);
+                VaultError::Message("invalid kdf".to_string())
+            })?,
+            Some(version) => {
+                return Err(VaultError::Message(format!(
+                    "unsupported vault header version {}; upgrade Noteece to open this vault",
+                    version
+                )))
+            }
+        };
+        Ok(VaultHeader {
+            salt,
+            kdf,
+            wrapped_dek,
+            config,
+        })
+    }
+
+    /// Same header with the DEK wrapped under a new salt and KEK
+    fn rewrapped(&self, salt: Vec<u8>, kdf: HeaderKdf, wrapped_dek: Vec<u8>) -> Self {
+        let mut config = self.config.clone();
+        config["version"] = HEADER_VERSION.into();
+        config["salt"] = serde_json::Value::String(hex::encode(&salt));
+        config["kdf"] = kdf.to_json();
+        config["wrapped_dek"] = serde_json::Value::String(hex::encode(&wrapped_dek));
+        VaultHeader {
+            salt,
+            kdf,
+            wrapped_dek,
+            config,
+        }
+    }
+
+    /// The read-only key (see `readonly`), wrapped with the same KEK as the DEK
+    fn wrapped_read_key(&self) -> Option<Vec<u8>> {
+        self.config["wrapped_read_key"]
+            .as_str()
+            .and_then(|wrapped| hex::decode(wrapped).ok())
+    }
+
+    /// Same header storing `wrapped` as the read-only key
+    fn with_read_key(&self, wrapped: Vec<u8>) -> Self {
+        let mut config = self.config.clone();
+        config["wrapped_read_key"] = serde_json::Value::String(hex::encode(wrapped));
+        VaultHeader {
+            salt: self.salt.clone(),
+            kdf: self.kdf,
+            wrapped_dek: self.wrapped_dek.clone(),
+            config,
+        }
+    }
+
+    /// Write through a synced temporary file and rename it into place, so the
+    /// file is always either the old or the new header
+    fn write(&self, dir: &Path, file_name: &str) -> Result<(), VaultError> {
+        let target = dir.join(file_name);
+        let tmp = dir.join(format!("{}.tmp", file_name));
+        {
+            let mut file = std::fs::File::create(&tmp)?;
+            file.write_all(serde_json::to_string_pretty(&self.config)?.as_bytes())?;
+            file.sync_all()?;
+        }
+        std::fs::rename(&tmp, &target)?;
+        sync_dir(dir);
+        Ok(())
+    }
+}
+
+/// How the KEK is derived from the password. Headers without a version
+/// predate Argon2id and used PBKDF2, which is kept only to read them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum HeaderKdf {
+    Pbkdf2,
+    Argon2id {
+        m_cost: u32,
+        t_cost: u32,
+        p_cost: u32,
+    },
+}
+
+impl HeaderKdf {
+    /// Used for every header written: 19 MiB, 2 passes, 1 lane
+    const CURRENT: HeaderKdf = HeaderKdf::Argon2id {
+        m_cost: 19_456,
+        t_cost: 2,
+        p_cost: 1,
+    };
+
+    fn derive(&self, password: &str, salt: &[u8]) -> Result<Zeroizing<[u8; 32]>, VaultError> {
+        Ok(match *self {
+            HeaderKdf::Pbkdf2 => Zeroizing::new(derive_key(password, salt)),
+            HeaderKdf::Argon2id {
+                m_cost,
+                t_cost,
+                p_cost,
+            } => derive_key_argon2(password, salt, m_cost, t_cost, p_cost)?,
+        })
+    }
+
+    fn to_json(self) -> serde_json::Value {
+        match self {
+            HeaderKdf::Pbkdf2 => serde_json::json!({ "algorithm": "pbkdf2_hmac_sha512" }),
+            HeaderKdf::Argon2id {
+                m_cost,
+                t_cost,
+                p_cost,
+            } => serde_json::json!({
+                "algorithm": "argon2id",
+                "m_cost": m_cost,
+                "t_cost": t_cost,
+                "p_cost": p_cost,
+            }),
+        }
+    }
+
+    fn from_json(value: &serde_json::Value) -> Option<Self> {
+        let cost = |name: &str| value[name].as_u64().and_then(|n| u32::try_from(n).ok());
+        match value["algorithm"].as_str()? {
+            "pbkdf2_hmac_sha512" => Some(HeaderKdf::Pbkdf2),
+            "argon2id" => Some(HeaderKdf::Argon2id {
+                m_cost: cost("m_cost")?,
+                t_cost: cost("t_cost")?,
+                p_cost: cost("p_cost")?,
+            }),
+            _ => None,
+        }
+    }
+}
+
+/// Persist a rename on platforms where directories can be synced
+fn sync_dir(dir: &Path) {
+    #[cfg(unix)]
+    if let Ok(handle) = std::fs::File::open(dir) {
+        let _ = handle.sync_all();
+    }
+    #[cfg(not(unix))]
+    let _ = dir;
+}
+
+/// Derive the KEK and unwrap the DEK, enforcing the unlock lockout. Wrong
+/// passwords are counted in the vault's attempt file.
+fn unwrap_with_lockout(
+    vault_dir: &Path,
+    header: &VaultHeader,
+    password: &str,
+    now: i64,
+) -> Result<([u8; 32], VaultAttemptFile), VaultError> {
+    let (dek, _, attempts) =
+        unwrap_key_with_lockout(vault_dir, header, &header.wrapped_dek, password, now)?;
+    Ok((dek, attempts))
+}
+
+/// Unwrap any key wrapped with the password KEK, under the same lockout as
+/// the DEK. Also returns the KEK.
+fn unwrap_key_with_lockout(
+    vault_dir: &Path,
+    header: &VaultHeader,
+    wrapped: &[u8],
+    password: &str,
+    now: i64,
+) -> Result<([u8; 32], Zeroizing<[u8; 32]>, VaultAttemptFile), VaultError> {
+    // Failed attempts are tracked beside the vault, since the database can't
+    // be read until the password is right
+    let mut attempts = VaultAttemptFile::load(vault_dir)?;
+    if let Some(retry_after) = attempts.state.retry_after(now) {
+        warn!(
+            "[vault] Unlock refused, locked out for another {}s",
+            retry_after
+        );
+        attempts.record_refused(now);
+        attempts.save(vault_dir)?;
+        return Err(VaultError::LockedOut(retry_after));
+    }
+
+    let mk = header.kdf.derive(password, &header.salt)?;
+    match unwrap_dek(wrapped, &mk[..]) {
+        Ok(key) => Ok((key, mk, attempts)),
+        Err(e) => {
+            error!("[vault] Failed to unwrap key. Incorrect password? {}", e);
+            attempts.record_failure(&LockoutPolicy::default(), now);
+            attempts.save(vault_dir)?;
+            Err(e.into())
+        }
+    }
+}
+
+/// Open the database with the DEK and check it can actually be read
+fn open_encrypted(db_path: &Path, dek: &[u8; 32]) -> Result<rusqlite::Connection, VaultError> {
+    let conn = rusqlite::Connection::open(db_path)?;
+    apply_sqlcipher_settings(&conn, dek)?;
+
+    let mut stmt =
+        conn.prepare("SELECT version FROM schema_version ORDER BY version DESC LIMIT 1")?;
+    match stmt.query_row([], |row| row.get::<_, i64>(0)) {
+        Ok(version) => {
+            info!(
+                "[vault] Successfully read schema version {} from unlocked vault.",
+                version
+            );
+        }
+        Err(e) => {
+            error!(
+                "[vault] Failed to read from supposedly unlocked database: {}",
+                e
+            );
+            return Err(e.into());
+        }
+    }
+    drop(stmt);
+    Ok(conn)
+}
+
+/// Keep the in-database copy of the header (see `db::vault_backup`) in step
+/// with `config.json`
+fn sync_header_backup(conn: &rusqlite::Connection, header: &VaultHeader) -> Result<(), VaultError> {
+    crate::db::init_vault_backup_table(conn)?;
+    if !verify_vault_backup(conn, &header.salt, &header.wrapped_dek)? {
+        store_vault_backup(conn, &header.salt, &header.wrapped_dek)?;
+    }
+    Ok(())
+}
+
+/// After a correct password: audit the failures recorded while the vault was
+/// locked plus `event_type`, then clear the attempt file
+fn finish_authenticated(
+    conn: &rusqlite::Connection,
+    path: &str,
+    attempts: VaultAttemptFile,
+    event_type: &str,
+    mut details: serde_json::Value,
+    now: i64,
+) -> Result<(), VaultError> {
+    for failure in &attempts.pending {
+        let failure_event = if failure.locked {
+            audit::AUTH_VAULT_UNLOCK_LOCKED_OUT
+        } else {
+            audit::AUTH_VAULT_UNLOCK_FAILED
+        };
+        audit::log_auth_event(
+            conn,
+            None,
+            failure_event,
+            path,
+            serde_json::json!({}),
+            None,
+            failure.at,
+        );
+    }
+    details["failed_attempts"] = attempts.state.failures.into();
+    audit::log_auth_event(conn, None, event_type, path, details, None, now);
+
+    if attempts != VaultAttemptFile::default() {
+        VaultAttemptFile::default().save(Path::new(path))?;
+    }
+    Ok(())
 }
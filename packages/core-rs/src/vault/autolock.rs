@@ -0,0 +1,171 @@
+//! Vault Auto-Lock
+//!
+//! An unlocked vault keeps its DEK in memory for as long as the app runs.
+//! [`AutoLockGuard`] owns that key, remembers when the vault was last used
+//! and wipes the key once it has been idle for too long. The embedding app
+//! calls [`AutoLockGuard::touch`] from its commands, polls
+//! [`AutoLockGuard::check_and_lock`] from a timer and is told through the
+//! `on_lock` callback when the key is gone, so it can drop its open
+//! connections and show the unlock screen.
+
+use super::VaultError;
+use log::info;
+use serde::Serialize;
+use std::fmt;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+use zeroize::Zeroizing;
+
+/// Source of the current time; swapped out in tests
+pub trait Clock: Send + Sync {
+    fn now(&self) -> Instant;
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+}
+
+/// Why the vault was locked
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LockReason {
+    /// No activity for longer than the idle threshold
+    Idle,
+    /// [`AutoLockGuard::lock`] was called
+    Manual,
+}
+
+type LockCallback = Box<dyn Fn(LockReason) + Send + Sync>;
+
+struct GuardState {
+    /// `None` once locked; dropping the `Zeroizing` wipes the key
+    dek: Option<Zeroizing<[u8; 32]>>,
+    last_activity: Instant,
+}
+
+pub struct AutoLockGuard {
+    state: Mutex<GuardState>,
+    clock: Arc<dyn Clock>,
+    on_lock: Option<LockCallback>,
+}
+
+impl AutoLockGuard {
+    /// Take ownership of an unlocked vault's DEK. The caller should not keep
+    /// other copies of it around.
+    pub fn new(dek: [u8; 32]) -> Self {
+        Self::with_clock(dek, Arc::new(SystemClock))
+    }
+
+    pub fn with_clock(dek: [u8; 32], clock: Arc<dyn Clock>) -> Self {
+        let now = clock.now();
+        AutoLockGuard {
+            state: Mutex::new(GuardState {
+                dek: Some(Zeroizing::new(dek)),
+                last_activity: now,
+            }),
+            clock,
+            on_lock: None,
+        }
+    }
+
+    /// Called once, after the key has been wiped, when the vault locks. It
+    /// runs on the thread that locked the vault.
+    pub fn on_lock<F>(mut self, callback: F) -> Self
+    where
+        F: Fn(LockReason) + Send + Sync + 'static,
+    {
+        self.on_lock = Some(Box::new(callback));
+        self
+    }
+
+    /// Record activity, restarting the idle timer
+    pub fn touch(&self) -> Result<(), VaultError> {
+        let mut state = self.state();
+        if state.dek.is_none() {
+            return Err(VaultError::Locked);
+        }
+        state.last_activity = self.clock.now();
+        Ok(())
+    }
+
+    /// A copy of the DEK, wiped when dropped. Does not count as activity.
+    pub fn dek(&self) -> Result<Zeroizing<[u8; 32]>, VaultError> {
+        self.state()
+            .dek
+            .as_ref()
+            .map(|dek| Zeroizing::new(**dek))
+            .ok_or(VaultError::Locked)
+    }
+
+    pub fn is_locked(&self) -> bool {
+        self.state().dek.is_none()
+    }
+
+    /// Time left before `check_and_lock(max_idle)` locks the vault, or `None`
+    /// if it is already locked
+    pub fn remaining_until_lock(&self, max_idle: Duration) -> Option<Duration> {
+        let state = self.state();
+        state.dek.as_ref()?;
+        let idle = self
+            .clock
+            .now()
+            .saturating_duration_since(state.last_activity);
+        Some(max_idle.saturating_sub(idle))
+    }
+
+    /// Lock the vault if it has been idle for at least `max_idle`. Returns
+    /// whether this call locked it.
+    pub fn check_and_lock(&self, max_idle: Duration) -> bool {
+        let dek = {
+            let mut state = self.state();
+            let idle = self
+                .clock
+                .now()
+                .saturating_duration_since(state.last_activity);
+            if idle < max_idle {
+                return false;
+            }
+            state.dek.take()
+        };
+        self.finish_lock(dek, LockReason::Idle)
+    }
+
+    /// Lock the vault now. Returns `false` if it was already locked.
+    pub fn lock(&self) -> bool {
+        let dek = self.state().dek.take();
+        self.finish_lock(dek, LockReason::Manual)
+    }
+
+    /// Wipe the key taken out of the state, then tell the app. The state
+    /// mutex is released first so the callback may query the guard.
+    fn finish_lock(&self, dek: Option<Zeroizing<[u8; 32]>>, reason: LockReason) -> bool {
+        if dek.is_none() {
+            return false;
+        }
+        drop(dek);
+        info!("[vault] Vault locked ({:?})", reason);
+        if let Some(callback) = &self.on_lock {
+            callback(reason);
+        }
+        true
+    }
+
+    fn state(&self) -> MutexGuard<'_, GuardState> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+impl fmt::Debug for AutoLockGuard {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let state = self.state();
+        f.debug_struct("AutoLockGuard")
+            .field("locked", &state.dek.is_none())
+            .field("last_activity", &state.last_activity)
+            .finish()
+    }
+}
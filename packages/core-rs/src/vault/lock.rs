@@ -0,0 +1,225 @@
+//! Vault Lock File
+//!
+//! SQLite copes with several connections to one database, but two app
+//! instances unlocking the same vault would both run migrations, rekey
+//! recovery and the read-cache rebuild, and each pool assumes it is the only
+//! writer. [`VaultLock`] makes opening exclusive: it holds an OS file lock
+//! (`flock` / `LockFileEx`) on `vault.lock` next to the database, so exactly
+//! one opener wins and a crashed holder's lock goes away with its process.
+//! Who holds it is recorded in `vault.lock.holder` so the loser can say so;
+//! that file is only written and removed under the lock.
+//!
+//! OS locks don't reach across machines sharing a synced folder, so a holder
+//! record naming another host is honoured even when the lock is free. It is
+//! only replaced when the caller forces it and the record is old enough.
+
+use super::VaultError;
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::fs::{File, OpenOptions, TryLockError};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+pub const LOCK_FILE: &str = "vault.lock";
+
+/// Who holds the lock, written while holding it
+pub const HOLDER_FILE: &str = "vault.lock.holder";
+
+/// Who holds a vault open, as written to `vault.lock.holder`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LockHolder {
+    pub pid: u32,
+    pub hostname: String,
+    /// Unix seconds
+    pub acquired_at: i64,
+    /// Distinguishes two opens by the same process
+    pub instance_id: String,
+}
+
+impl fmt::Display for LockHolder {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "process {} on {} since {}",
+            self.pid,
+            self.hostname,
+            chrono::DateTime::from_timestamp(self.acquired_at, 0)
+                .map(|at| at.to_rfc3339())
+                .unwrap_or_else(|| self.acquired_at.to_string())
+        )
+    }
+}
+
+/// How to treat a holder record left by another machine
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LockOptions {
+    /// Replace a record taken on another machine once it is older than
+    /// `stale_after`. A lock held on this machine is never broken.
+    pub force: bool,
+    pub stale_after: Duration,
+}
+
+impl Default for LockOptions {
+    fn default() -> Self {
+        Self {
+            force: false,
+            stale_after: Duration::from_secs(10 * 60),
+        }
+    }
+}
+
+/// Exclusive hold on a vault directory; released when dropped
+#[derive(Debug)]
+pub struct VaultLock {
+    /// Holds the OS lock until it is closed
+    file: File,
+    holder_path: PathBuf,
+    holder: LockHolder,
+    released: bool,
+}
+
+impl VaultLock {
+    /// Take the lock on the vault in `dir`, replacing a foreign holder's
+    /// record as `options` allow. Fails with [`VaultError::VaultAlreadyOpen`]
+    /// while another open holds it, including one in this process.
+    pub fn acquire(dir: &Path, options: &LockOptions) -> Result<Self, VaultError> {
+        let path = dir.join(LOCK_FILE);
+        let holder_path = dir.join(HOLDER_FILE);
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(&path)?;
+        match file.try_lock() {
+            Ok(()) => {}
+            Err(TryLockError::WouldBlock) => {
+                let holder = read_holder(&holder_path).unwrap_or_else(unknown_holder);
+                return Err(VaultError::VaultAlreadyOpen(holder));
+            }
+            Err(TryLockError::Error(e)) => return Err(e.into()),
+        }
+
+        // Nobody here holds the lock, so a record from this machine was left
+        // by a process that has exited
+        if let Some(existing) = read_holder(&holder_path) {
+            if existing.hostname != hostname() {
+                if !is_stale(&existing, options) {
+                    return Err(VaultError::VaultAlreadyOpen(existing));
+                }
+                warn!("[vault] Breaking stale vault lock held by {}", existing);
+            } else {
+                info!("[vault] Replacing vault lock left by {}", existing);
+            }
+        }
+
+        let holder = LockHolder {
+            pid: std::process::id(),
+            hostname: hostname(),
+            acquired_at: chrono::Utc::now().timestamp(),
+            instance_id: ulid::Ulid::new().to_string(),
+        };
+        write_holder(&holder_path, &holder)?;
+        info!("[vault] Acquired vault lock at {:?}", path);
+        Ok(Self {
+            file,
+            holder_path,
+            holder,
+            released: false,
+        })
+    }
+
+    /// Who this lock was taken as
+    pub fn holder(&self) -> &LockHolder {
+        &self.holder
+    }
+
+    /// Release now, reporting failures that dropping would only log
+    pub fn release(mut self) -> Result<(), VaultError> {
+        self.released = true;
+        self.unlock()
+    }
+
+    /// Remove the record while still holding the lock, so no other opener
+    /// can have written its own in between
+    fn unlock(&self) -> Result<(), VaultError> {
+        let removed = match std::fs::remove_file(&self.holder_path) {
+            Ok(()) => Ok(()),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(()),
+            Err(e) => Err(e),
+        };
+        self.file.unlock()?;
+        removed?;
+        info!("[vault] Released vault lock at {:?}", self.holder_path);
+        Ok(())
+    }
+}
+
+impl Drop for VaultLock {
+    fn drop(&mut self) {
+        if self.released {
+            return;
+        }
+        if let Err(e) = self.unlock() {
+            warn!("[vault] Failed to release vault lock: {}", e);
+        }
+    }
+}
+
+/// `None` when there is no record or it can't be parsed, such as one cut
+/// short by a crash
+fn read_holder(path: &Path) -> Option<LockHolder> {
+    let contents = std::fs::read(path).ok()?;
+    serde_json::from_slice(&contents).ok()
+}
+
+fn write_holder(path: &Path, holder: &LockHolder) -> std::io::Result<()> {
+    let mut file = File::create(path)?;
+    let json = serde_json::to_vec(holder).map_err(std::io::Error::from)?;
+    file.write_all(&json)?;
+    file.sync_all()
+}
+
+fn unknown_holder() -> LockHolder {
+    LockHolder {
+        pid: 0,
+        hostname: "unknown".to_string(),
+        acquired_at: 0,
+        instance_id: String::new(),
+    }
+}
+
+fn is_stale(holder: &LockHolder, options: &LockOptions) -> bool {
+    let age = chrono::Utc::now().timestamp() - holder.acquired_at;
+    options.force && age >= options.stale_after.as_secs() as i64
+}
+
+lazy_static::lazy_static! {
+    static ref HOSTNAME: String = read_hostname();
+}
+
+fn hostname() -> String {
+    HOSTNAME.clone()
+}
+
+/// The kernel's name first: `HOSTNAME` is a shell variable that a process
+/// started from the desktop usually doesn't inherit
+fn read_hostname() -> String {
+    let from_kernel = std::fs::read_to_string("/proc/sys/kernel/hostname").ok();
+    let from_env = || std::env::var("COMPUTERNAME").ok();
+    let from_command = || {
+        std::process::Command::new("hostname")
+            .output()
+            .ok()
+            .filter(|output| output.status.success())
+            .map(|output| String::from_utf8_lossy(&output.stdout).into_owned())
+    };
+    from_kernel
+        .or_else(from_env)
+        .or_else(from_command)
+        .map(|name| name.trim().to_string())
+        .filter(|name| !name.is_empty())
+        .unwrap_or_else(|| "unknown".to_string())
+}
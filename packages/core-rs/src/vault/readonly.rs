@@ -0,0 +1,425 @@
+//! Read-only Vault Access
+//!
+//! Glancing at a vault on a phone shouldn't need the DEK in memory. A full
+//! unlock keeps a small read cache next to the database: note titles with
+//! the first [`PREVIEW_CHARS`] characters of their body, open tasks with a
+//! due date, and the due dates of SRS cards. The cache is its own SQLCipher
+//! database, encrypted with a read key derived from the DEK. A full unlock
+//! rebuilds it, and every connection to the unlocked vault, pooled ones
+//! included, attaches it with temporary triggers that update it on every
+//! save. Locked and trashed notes are never written to it.
+//!
+//! The read key is also wrapped with the password KEK in `config.json`, so
+//! [`open_vault_readonly`] can open the cache with the password (or with a
+//! token a biometric keystore released) and never touch the DEK. The
+//! [`VaultHandle`] it returns answers the small read API and refuses
+//! everything else with [`VaultError::ReadOnly`] until [`VaultHandle::upgrade`]
+//! unlocks the vault fully.
+
+use super::{
+    apply_sqlcipher_settings, unlock_vault, unwrap_key_with_lockout, Vault, VaultError,
+    VaultHeader, CONFIG_FILE,
+};
+use crate::crypto::{unwrap_dek, wrap_dek, CryptoError};
+use hkdf::Hkdf;
+use log::{info, warn};
+use rusqlite::{params, Connection, OpenFlags};
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use std::path::{Path, PathBuf};
+
+/// Database file of the read cache, beside the vault database
+pub(super) const READ_CACHE_FILE: &str = "preview.sqlite3";
+/// Characters of a note's body kept as its preview
+pub const PREVIEW_CHARS: usize = 280;
+
+/// Key of the read cache, derived from the DEK so a full unlock can always
+/// recompute it
+pub(super) fn derive_read_key(dek: &[u8; 32]) -> [u8; 32] {
+    let hk = Hkdf::<Sha256>::new(Some(b"noteece-read-cache"), dek);
+    let mut okm = [0u8; 32];
+    hk.expand(b"read-key", &mut okm)
+        .expect("HKDF expand failed");
+    okm
+}
+
+/// The read key wrapped with `kek`, for `config.json`
+pub(super) fn wrap_read_key(dek: &[u8; 32], kek: &[u8; 32]) -> Result<Vec<u8>, VaultError> {
+    Ok(wrap_dek(&derive_read_key(dek), kek)?)
+}
+
+/// Store the read key wrapped with the password KEK in the header, unless
+/// it already is. Vaults created before the read cache get it on their
+/// first unlock, and a header written by a DEK rotation is brought in step.
+pub(super) fn ensure_wrapped_read_key(
+    vault_dir: &Path,
+    header: &VaultHeader,
+    dek: &[u8; 32],
+    kek: &[u8; 32],
+) -> Result<(), VaultError> {
+    let current = header
+        .wrapped_read_key()
+        .and_then(|wrapped| unwrap_dek(&wrapped, kek).ok());
+    if current == Some(derive_read_key(dek)) {
+        return Ok(());
+    }
+    info!("[vault] Storing the read-only key in the vault header");
+    header
+        .with_read_key(wrap_read_key(dek, kek)?)
+        .write(vault_dir, CONFIG_FILE)
+}
+
+/// Attach the read cache to an unlocked connection, rebuild it from the
+/// vault and install the triggers that keep it current. A cache that can't
+/// be opened with the current key (e.g. after a DEK rotation) is replaced.
+pub(super) fn attach_read_cache(
+    conn: &Connection,
+    vault_dir: &Path,
+    dek: &[u8; 32],
+) -> Result<(), VaultError> {
+    let path = vault_dir.join(READ_CACHE_FILE);
+    if let Err(e) = attach(conn, &path, dek) {
+        warn!("[vault] Replacing unreadable read cache: {}", e);
+        let _ = conn.execute_batch("DETACH DATABASE read_cache");
+        super::rotation::remove_database_files(&path);
+        attach(conn, &path, dek)?;
+    }
+
+    conn.execute_batch(&format!(
+        "
+        -- Rollback journal, so read-only connections need no shared memory file
+        PRAGMA read_cache.journal_mode = DELETE;
+
+        DROP TABLE IF EXISTS read_cache.read_cache_note;
+        DROP TABLE IF EXISTS read_cache.read_cache_task;
+        DROP TABLE IF EXISTS read_cache.read_cache_card;
+
+        CREATE TABLE read_cache.read_cache_note (
+            note_id TEXT PRIMARY KEY,
+            space_id TEXT NOT NULL,
+            title TEXT NOT NULL,
+            preview TEXT NOT NULL,
+            modified_at INTEGER NOT NULL
+        );
+        CREATE INDEX read_cache.idx_read_cache_note_modified
+            ON read_cache_note(modified_at);
+        CREATE TABLE read_cache.read_cache_task (
+            task_id TEXT PRIMARY KEY,
+            space_id TEXT NOT NULL,
+            title TEXT NOT NULL,
+            status TEXT NOT NULL,
+            due_at INTEGER NOT NULL,
+            priority INTEGER
+        );
+        CREATE TABLE read_cache.read_cache_card (
+            card_id TEXT PRIMARY KEY,
+            space_id TEXT,
+            due_at INTEGER NOT NULL
+        );
+
+        INSERT INTO read_cache.read_cache_note (note_id, space_id, title, preview, modified_at)
+        SELECT id, space_id, title, substr(content_md, 1, {chars}), modified_at
+        FROM main.note WHERE is_locked = 0 AND is_trashed = 0;
+        INSERT INTO read_cache.read_cache_task (task_id, space_id, title, status, due_at, priority)
+        SELECT id, space_id, title, status, due_at, priority
+        FROM main.task WHERE due_at IS NOT NULL AND status NOT IN ('done', 'cancelled');
+        INSERT INTO read_cache.read_cache_card (card_id, space_id, due_at)
+        SELECT c.id, n.space_id, c.due_at
+        FROM main.knowledge_card c LEFT JOIN main.note n ON n.id = c.note_id;
+        ",
+        chars = PREVIEW_CHARS
+    ))?;
+    install_triggers(conn)?;
+    info!("[vault] Read cache rebuilt");
+    Ok(())
+}
+
+/// Attach the read cache a full unlock built to another connection to the
+/// same vault, with the triggers that keep it current. Every connection that
+/// writes to the vault needs them, or its saves (a note being locked, say)
+/// never reach the cache. Returns `false` when the vault has no cache.
+pub(crate) fn attach_existing_read_cache(
+    conn: &Connection,
+    vault_dir: &Path,
+    dek: &[u8; 32],
+) -> Result<bool, VaultError> {
+    let path = vault_dir.join(READ_CACHE_FILE);
+    if !path.exists() {
+        return Ok(false);
+    }
+    attach(conn, &path, dek)?;
+    install_triggers(conn)?;
+    Ok(true)
+}
+
+/// Attach the cache under the `read_cache` schema, checking the key
+fn attach(conn: &Connection, path: &Path, dek: &[u8; 32]) -> rusqlite::Result<()> {
+    let key = format!("x'{}'", hex::encode(derive_read_key(dek)));
+    conn.execute(
+        "ATTACH DATABASE ?1 AS read_cache KEY ?2",
+        params![path.to_string_lossy(), key],
+    )?;
+    conn.query_row("SELECT COUNT(*) FROM read_cache.sqlite_master", [], |_| {
+        Ok(())
+    })
+}
+
+fn install_triggers(conn: &Connection) -> rusqlite::Result<()> {
+    conn.execute_batch(&format!(
+        "
+        -- Temporary triggers may write to attached databases; the cache
+        -- tables resolve there since the main schema has no such names
+        DROP TRIGGER IF EXISTS temp.read_cache_note_ai;
+        DROP TRIGGER IF EXISTS temp.read_cache_note_au;
+        DROP TRIGGER IF EXISTS temp.read_cache_note_ad;
+        DROP TRIGGER IF EXISTS temp.read_cache_task_ai;
+        DROP TRIGGER IF EXISTS temp.read_cache_task_au;
+        DROP TRIGGER IF EXISTS temp.read_cache_task_ad;
+        DROP TRIGGER IF EXISTS temp.read_cache_card_ai;
+        DROP TRIGGER IF EXISTS temp.read_cache_card_au;
+        DROP TRIGGER IF EXISTS temp.read_cache_card_ad;
+
+        CREATE TEMP TRIGGER read_cache_note_ai AFTER INSERT ON main.note BEGIN
+            INSERT OR REPLACE INTO read_cache_note (note_id, space_id, title, preview, modified_at)
+            SELECT new.id, new.space_id, new.title, substr(new.content_md, 1, {chars}), new.modified_at
+            WHERE new.is_locked = 0 AND new.is_trashed = 0;
+        END;
+        CREATE TEMP TRIGGER read_cache_note_au AFTER UPDATE ON main.note BEGIN
+            DELETE FROM read_cache_note WHERE note_id = old.id;
+            INSERT INTO read_cache_note (note_id, space_id, title, preview, modified_at)
+            SELECT new.id, new.space_id, new.title, substr(new.content_md, 1, {chars}), new.modified_at
+            WHERE new.is_locked = 0 AND new.is_trashed = 0;
+        END;
+        CREATE TEMP TRIGGER read_cache_note_ad AFTER DELETE ON main.note BEGIN
+            DELETE FROM read_cache_note WHERE note_id = old.id;
+        END;
+
+        CREATE TEMP TRIGGER read_cache_task_ai AFTER INSERT ON main.task BEGIN
+            INSERT OR REPLACE INTO read_cache_task (task_id, space_id, title, status, due_at, priority)
+            SELECT new.id, new.space_id, new.title, new.status, new.due_at, new.priority
+            WHERE new.due_at IS NOT NULL AND new.status NOT IN ('done', 'cancelled');
+        END;
+        CREATE TEMP TRIGGER read_cache_task_au AFTER UPDATE ON main.task BEGIN
+            DELETE FROM read_cache_task WHERE task_id = old.id;
+            INSERT INTO read_cache_task (task_id, space_id, title, status, due_at, priority)
+            SELECT new.id, new.space_id, new.title, new.status, new.due_at, new.priority
+            WHERE new.due_at IS NOT NULL AND new.status NOT IN ('done', 'cancelled');
+        END;
+        CREATE TEMP TRIGGER read_cache_task_ad AFTER DELETE ON main.task BEGIN
+            DELETE FROM read_cache_task WHERE task_id = old.id;
+        END;
+
+        CREATE TEMP TRIGGER read_cache_card_ai AFTER INSERT ON main.knowledge_card BEGIN
+            INSERT OR REPLACE INTO read_cache_card (card_id, space_id, due_at)
+            SELECT new.id, (SELECT space_id FROM main.note WHERE id = new.note_id), new.due_at;
+        END;
+        CREATE TEMP TRIGGER read_cache_card_au AFTER UPDATE ON main.knowledge_card BEGIN
+            DELETE FROM read_cache_card WHERE card_id = old.id;
+            INSERT INTO read_cache_card (card_id, space_id, due_at)
+            SELECT new.id, (SELECT space_id FROM main.note WHERE id = new.note_id), new.due_at;
+        END;
+        CREATE TEMP TRIGGER read_cache_card_ad AFTER DELETE ON main.knowledge_card BEGIN
+            DELETE FROM read_cache_card WHERE card_id = old.id;
+        END;
+        ",
+        chars = PREVIEW_CHARS
+    ))
+}
+
+/// How a read-only open proves it may read the cache
+#[derive(Clone)]
+pub enum ReadOnlyCredential {
+    /// The vault password
+    Passphrase(String),
+    /// A token from [`biometric_token`], released by the platform keystore
+    /// after a biometric check
+    BiometricToken(String),
+}
+
+impl std::fmt::Debug for ReadOnlyCredential {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ReadOnlyCredential::Passphrase(_) => f.write_str("Passphrase(..)"),
+            ReadOnlyCredential::BiometricToken(_) => f.write_str("BiometricToken(..)"),
+        }
+    }
+}
+
+/// Token that opens `vault` read-only, to be kept in a biometric-protected
+/// keystore. It reads the cache only; the DEK can't be recovered from it.
+pub fn biometric_token(vault: &Vault) -> String {
+    hex::encode(derive_read_key(&vault.dek))
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct NotePreview {
+    pub id: String,
+    pub space_id: String,
+    pub title: String,
+    /// Start of the body, at most [`PREVIEW_CHARS`] characters
+    pub preview: String,
+    pub modified_at: i64,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TaskPreview {
+    pub id: String,
+    pub space_id: String,
+    pub title: String,
+    pub status: String,
+    pub due_at: i64,
+    pub priority: Option<i64>,
+}
+
+enum Access {
+    ReadOnly(Connection),
+    Full(Vault),
+}
+
+/// A vault opened read-only, or fully once upgraded. The read API works
+/// either way; everything else needs the full vault.
+pub struct VaultHandle {
+    path: PathBuf,
+    access: Access,
+}
+
+/// Open the vault's read cache without unwrapping the DEK. Wrong passwords
+/// count towards the unlock lockout. Fails if the vault was never unlocked
+/// since the read cache was introduced or its DEK was rotated.
+pub fn open_vault_readonly(
+    path: &str,
+    credential: &ReadOnlyCredential,
+) -> Result<VaultHandle, VaultError> {
+    info!("[vault] Opening vault read-only at path: {}", path);
+    let vault_dir = Path::new(path);
+    let no_cache = || VaultError::Message("No read cache yet; unlock the vault once".to_string());
+
+    let key = match credential {
+        ReadOnlyCredential::Passphrase(password) => {
+            let header = VaultHeader::read(vault_dir, CONFIG_FILE)?;
+            let wrapped = header.wrapped_read_key().ok_or_else(no_cache)?;
+            let now = chrono::Utc::now().timestamp();
+            let (key, _, _) = unwrap_key_with_lockout(vault_dir, &header, &wrapped, password, now)?;
+            key
+        }
+        ReadOnlyCredential::BiometricToken(token) => hex::decode(token)?
+            .try_into()
+            .map_err(|_| VaultError::Crypto(CryptoError::Decrypt))?,
+    };
+
+    let cache_path = vault_dir.join(READ_CACHE_FILE);
+    if !cache_path.exists() {
+        return Err(no_cache());
+    }
+    let conn = Connection::open_with_flags(&cache_path, OpenFlags::SQLITE_OPEN_READ_ONLY)?;
+    apply_sqlcipher_settings(&conn, &key)?;
+    conn.execute_batch("PRAGMA query_only = ON;")?;
+    // A wrong key only shows once a page is read
+    conn.query_row("SELECT COUNT(*) FROM read_cache_note", [], |_| Ok(()))
+        .map_err(|_| VaultError::Crypto(CryptoError::Decrypt))?;
+
+    info!("[vault] Vault opened read-only.");
+    Ok(VaultHandle {
+        path: vault_dir.to_path_buf(),
+        access: Access::ReadOnly(conn),
+    })
+}
+
+impl VaultHandle {
+    pub fn is_read_only(&self) -> bool {
+        matches!(self.access, Access::ReadOnly(_))
+    }
+
+    /// Connection the read API queries; the cache tables resolve to the
+    /// attached cache when the vault is unlocked
+    fn reader(&self) -> &Connection {
+        match &self.access {
+            Access::ReadOnly(conn) => conn,
+            Access::Full(vault) => &vault.conn,
+        }
+    }
+
+    /// Most recently modified notes, newest first
+    pub fn recent_notes(&self, limit: usize) -> Result<Vec<NotePreview>, VaultError> {
+        let mut stmt = self.reader().prepare(
+            "SELECT note_id, space_id, title, preview, modified_at FROM read_cache_note
+             ORDER BY modified_at DESC, note_id LIMIT ?1",
+        )?;
+        let notes = stmt
+            .query_map([limit as i64], |row| {
+                Ok(NotePreview {
+                    id: row.get(0)?,
+                    space_id: row.get(1)?,
+                    title: row.get(2)?,
+                    preview: row.get(3)?,
+                    modified_at: row.get(4)?,
+                })
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(notes)
+    }
+
+    /// Open tasks with a due date, overdue ones first
+    pub fn upcoming_tasks(&self, limit: usize) -> Result<Vec<TaskPreview>, VaultError> {
+        let mut stmt = self.reader().prepare(
+            "SELECT task_id, space_id, title, status, due_at, priority FROM read_cache_task
+             ORDER BY due_at, task_id LIMIT ?1",
+        )?;
+        let tasks = stmt
+            .query_map([limit as i64], |row| {
+                Ok(TaskPreview {
+                    id: row.get(0)?,
+                    space_id: row.get(1)?,
+                    title: row.get(2)?,
+                    status: row.get(3)?,
+                    due_at: row.get(4)?,
+                    priority: row.get(5)?,
+                })
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(tasks)
+    }
+
+    /// SRS cards due now
+    pub fn due_card_count(&self) -> Result<usize, VaultError> {
+        let count: i64 = self.reader().query_row(
+            "SELECT COUNT(*) FROM read_cache_card WHERE due_at <= ?1",
+            [chrono::Utc::now().timestamp()],
+            |row| row.get(0),
+        )?;
+        Ok(count as usize)
+    }
+
+    /// The unlocked vault, for full content and any change
+    pub fn vault(&self) -> Result<&Vault, VaultError> {
+        match &self.access {
+            Access::ReadOnly(_) => Err(VaultError::ReadOnly),
+            Access::Full(vault) => Ok(vault),
+        }
+    }
+
+    pub fn vault_mut(&mut self) -> Result<&mut Vault, VaultError> {
+        match &mut self.access {
+            Access::ReadOnly(_) => Err(VaultError::ReadOnly),
+            Access::Full(vault) => Ok(vault),
+        }
+    }
+
+    /// Unlock the vault fully; the handle keeps working as before, and
+    /// everything else becomes available. A no-op when already unlocked.
+    pub fn upgrade(&mut self, password: &str) -> Result<(), VaultError> {
+        if !self.is_read_only() {
+            return Ok(());
+        }
+        let vault = unlock_vault(&self.path.to_string_lossy(), password)?;
+        self.access = Access::Full(vault);
+        Ok(())
+    }
+
+    pub fn into_vault(self) -> Result<Vault, VaultError> {
+        match self.access {
+            Access::ReadOnly(_) => Err(VaultError::ReadOnly),
+            Access::Full(vault) => Ok(vault),
+        }
+    }
+}
@@ -0,0 +1,250 @@
+//! Vault Key Rotation
+//!
+//! The vault password only protects the KEK that wraps the DEK, so changing
+//! it rewrites `config.json` and nothing else. Replacing the DEK itself
+//! re-encrypts the whole database and is a separate, much slower operation.
+//!
+//! Both are crash safe: headers are replaced by atomic rename, and a DEK
+//! rotation stages the re-encrypted database and its header next to the live
+//! ones. [`recover_interrupted_rekey`] (run on every unlock) either finishes
+//! or discards a staged rotation depending on how far it got.
+//!
+//! The read-only key is re-wrapped along with the DEK. A new DEK also means
+//! a new read key, so the read cache is dropped and rebuilt on next unlock.
+
+use super::lock::{LockOptions, VaultLock};
+use super::readonly::{wrap_read_key, READ_CACHE_FILE};
+use super::{
+    finish_authenticated, open_encrypted, sync_dir, sync_header_backup, unwrap_with_lockout,
+    HeaderKdf, VaultError, VaultHeader, CONFIG_FILE, DB_FILE,
+};
+use crate::audit;
+use crate::crypto::{generate_dek, wrap_dek};
+use crate::db::store_vault_backup;
+use log::{info, warn};
+use serde::Serialize;
+use std::path::Path;
+
+/// Re-encrypted database staged by `rotate_dek` until the commit rename
+const REKEY_DB_FILE: &str = "vault.sqlite3.rekey";
+/// Header for the staged database; its presence marks a rotation in flight
+const REKEY_CONFIG_FILE: &str = "config.json.rekey";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RekeyStage {
+    Unlocking,
+    Exporting,
+    Verifying,
+    Committing,
+    Done,
+}
+
+impl RekeyStage {
+    const ALL: [RekeyStage; 5] = [
+        RekeyStage::Unlocking,
+        RekeyStage::Exporting,
+        RekeyStage::Verifying,
+        RekeyStage::Committing,
+        RekeyStage::Done,
+    ];
+}
+
+/// Reported to the `rotate_dek` callback as each stage starts
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct RekeyProgress {
+    pub stage: RekeyStage,
+    /// 1-based position of `stage`
+    pub step: usize,
+    pub total_steps: usize,
+}
+
+/// Change the vault password without touching the database: the DEK is
+/// unwrapped with the old password and re-wrapped under a KEK derived from
+/// the new one with a fresh salt and the current Argon2id parameters.
+pub fn rotate_vault_password(
+    path: &str,
+    old_password: &str,
+    new_password: &str,
+) -> Result<(), VaultError> {
+    info!("[vault] Rotating password for vault at path: {}", path);
+    let vault_dir = Path::new(path);
+    recover_interrupted_rekey(vault_dir)?;
+
+    let header = VaultHeader::read(vault_dir, CONFIG_FILE)?;
+    let now = chrono::Utc::now().timestamp();
+    let (dek, attempts) = unwrap_with_lockout(vault_dir, &header, old_password, now)?;
+    let conn = open_encrypted(&vault_dir.join(DB_FILE), &dek)?;
+
+    // 1) The database holds a copy of the current header until the new one
+    //    is in place
+    sync_header_backup(&conn, &header)?;
+
+    // 2) Re-wrap the DEK and swap the header in atomically
+    let salt: [u8; 16] = rand::random();
+    let kek = HeaderKdf::CURRENT.derive(new_password, &salt)?;
+    let wrapped_dek = wrap_dek(&dek, &kek[..])?;
+    let new_header = header
+        .rewrapped(salt.to_vec(), HeaderKdf::CURRENT, wrapped_dek)
+        .with_read_key(wrap_read_key(&dek, &kek)?);
+    new_header.write(vault_dir, CONFIG_FILE)?;
+
+    // 3) Point the backup at the new header. If we crash before this, the
+    //    next unlock notices the stale backup and replaces it.
+    store_vault_backup(&conn, &new_header.salt, &new_header.wrapped_dek)?;
+    finish_authenticated(
+        &conn,
+        path,
+        attempts,
+        audit::AUTH_VAULT_PASSWORD_CHANGED,
+        serde_json::json!({}),
+        now,
+    )?;
+
+    info!("[vault] Vault password rotated.");
+    Ok(())
+}
+
+/// Replace the DEK and re-encrypt the whole database under it. The password
+/// stays the same. This rewrites every page, so it takes time proportional to
+/// the vault size; `on_progress` is called as each stage starts.
+///
+/// No other connection may have the vault open while this runs; the vault
+/// lock is held throughout, so this fails while anyone has it unlocked.
+pub fn rotate_dek<F>(path: &str, password: &str, mut on_progress: F) -> Result<(), VaultError>
+where
+    F: FnMut(RekeyProgress),
+{
+    let mut report = |stage: RekeyStage| {
+        let step = RekeyStage::ALL
+            .iter()
+            .position(|s| *s == stage)
+            .unwrap_or(0)
+            + 1;
+        on_progress(RekeyProgress {
+            stage,
+            step,
+            total_steps: RekeyStage::ALL.len(),
+        });
+    };
+
+    info!("[vault] Re-encrypting vault at path: {}", path);
+    let vault_dir = Path::new(path);
+    let db_path = vault_dir.join(DB_FILE);
+    let rekey_db_path = vault_dir.join(REKEY_DB_FILE);
+
+    report(RekeyStage::Unlocking);
+    let _lock = VaultLock::acquire(vault_dir, &LockOptions::default())?;
+    recover_interrupted_rekey(vault_dir)?;
+    let header = VaultHeader::read(vault_dir, CONFIG_FILE)?;
+    let now = chrono::Utc::now().timestamp();
+    let (old_dek, attempts) = unwrap_with_lockout(vault_dir, &header, password, now)?;
+    let conn = open_encrypted(&db_path, &old_dek)?;
+
+    report(RekeyStage::Exporting);
+    let new_dek = generate_dek();
+    conn.execute(
+        "ATTACH DATABASE ?1 AS rekey KEY ?2",
+        rusqlite::params![
+            rekey_db_path.to_string_lossy(),
+            format!("x'{}'", hex::encode(new_dek))
+        ],
+    )?;
+    let exported = conn
+        .query_row("SELECT sqlcipher_export('rekey')", [], |_| Ok(()))
+        .and_then(|_| conn.execute_batch("DETACH DATABASE rekey"));
+    if let Err(e) = exported {
+        remove_database_files(&rekey_db_path);
+        return Err(e.into());
+    }
+
+    report(RekeyStage::Verifying);
+    let salt: [u8; 16] = rand::random();
+    let kek = HeaderKdf::CURRENT.derive(password, &salt)?;
+    let wrapped_dek = wrap_dek(&new_dek, &kek[..])?;
+    let new_header = header
+        .rewrapped(salt.to_vec(), HeaderKdf::CURRENT, wrapped_dek)
+        .with_read_key(wrap_read_key(&new_dek, &kek)?);
+    {
+        let rekeyed = open_encrypted(&rekey_db_path, &new_dek)?;
+        sync_header_backup(&rekeyed, &new_header)?;
+        finish_authenticated(
+            &rekeyed,
+            path,
+            attempts,
+            audit::AUTH_VAULT_REKEYED,
+            serde_json::json!({}),
+            now,
+        )?;
+    }
+    new_header.write(vault_dir, REKEY_CONFIG_FILE)?;
+
+    report(RekeyStage::Committing);
+    // Closing the last connection checkpoints the WAL into the old file,
+    // which is about to be replaced
+    drop(conn);
+    std::fs::rename(&rekey_db_path, &db_path)?;
+    for suffix in ["-wal", "-shm"] {
+        remove_if_exists(&vault_dir.join(format!("{}{}", DB_FILE, suffix)));
+    }
+    sync_dir(vault_dir);
+    std::fs::rename(
+        vault_dir.join(REKEY_CONFIG_FILE),
+        vault_dir.join(CONFIG_FILE),
+    )?;
+    sync_dir(vault_dir);
+    // Keyed with the old DEK's read key
+    remove_database_files(&vault_dir.join(READ_CACHE_FILE));
+
+    report(RekeyStage::Done);
+    info!("[vault] Vault re-encrypted under a new DEK.");
+    Ok(())
+}
+
+/// Finish or discard a DEK rotation that was interrupted. The staged header
+/// is written only after the staged database verified, and the database is
+/// renamed into place before the header, so:
+/// - staged header and staged database: not committed, keep the old pair
+/// - staged header only: the new database is live, install its header
+/// - staged database only: export never finished, discard it
+pub(super) fn recover_interrupted_rekey(vault_dir: &Path) -> Result<(), VaultError> {
+    let rekey_db_path = vault_dir.join(REKEY_DB_FILE);
+    let rekey_config_path = vault_dir.join(REKEY_CONFIG_FILE);
+
+    if rekey_config_path.exists() {
+        if rekey_db_path.exists() {
+            warn!("[vault] Discarding DEK rotation that did not commit");
+            remove_database_files(&rekey_db_path);
+            std::fs::remove_file(&rekey_config_path)?;
+        } else {
+            warn!("[vault] Completing DEK rotation interrupted after commit");
+            std::fs::rename(&rekey_config_path, vault_dir.join(CONFIG_FILE))?;
+            sync_dir(vault_dir);
+        }
+    } else if rekey_db_path.exists() {
+        warn!("[vault] Removing partial DEK rotation export");
+        remove_database_files(&rekey_db_path);
+    }
+
+    for tmp in [CONFIG_FILE, REKEY_CONFIG_FILE] {
+        remove_if_exists(&vault_dir.join(format!("{}.tmp", tmp)));
+    }
+    Ok(())
+}
+
+pub(super) fn remove_database_files(db_path: &Path) {
+    remove_if_exists(db_path);
+    for suffix in ["-journal", "-wal", "-shm"] {
+        let mut name = db_path.as_os_str().to_owned();
+        name.push(suffix);
+        remove_if_exists(Path::new(&name));
+    }
+}
+
+fn remove_if_exists(path: &Path) {
+    if let Err(e) = std::fs::remove_file(path) {
+        if e.kind() != std::io::ErrorKind::NotFound {
+            warn!("[vault] Failed to remove {:?}: {}", path, e);
+        }
+    }
+}
@@ -1,57 +1,151 @@
+//! Weekly reviews and their lifecycle
+//!
+//! Each space gets one review per week, a note summarising tasks, projects
+//! and health goals. Generating the same week again rewrites its note
+//! instead of creating another one. Unchecked action items of the previous
+//! review are carried over, and completed reviews make up a streak for the
+//! dashboard.
+
 use crate::db::DbError;
-use crate::note;
-use chrono::{Duration, Utc};
-use rusqlite::{Connection, Result};
+use crate::health::goals::{get_health_goals, HealthGoal, HealthGoalStatus};
+use crate::note::{self, refresh_note, DbUlid};
+use crate::project::{
+    get_latest_status_per_project, ProjectError, ProjectHealthStatus, ProjectUpdate,
+};
+use chrono::{DateTime, Utc};
+use rusqlite::{params, Connection, OptionalExtension, Result};
+use serde::{Deserialize, Serialize};
 use thiserror::Error;
 use ulid::Ulid;
 
+const SECONDS_PER_DAY: i64 = 86_400;
+const SECONDS_PER_WEEK: i64 = 7 * SECONDS_PER_DAY;
+
+const COMPLETED_HEADING: &str = "## ✅ Completed Tasks";
+const OVERDUE_HEADING: &str = "##  overdue Tasks";
+const UPCOMING_HEADING: &str = "## 📅 Upcoming Tasks";
+const PROJECTS_HEADING: &str = "## 📊 Project Status";
+const HEALTH_GOALS_HEADING: &str = "## 🎯 Health Goals";
+const CARRIED_OVER_HEADING: &str = "## ↪️ Carried Over from Last Week";
+const ACTION_ITEMS_HEADING: &str = "## 📝 Action Items";
+
+/// Sections rebuilt from the space's data on every generation; the rest of
+/// a review note belongs to the user
+const GENERATED_HEADINGS: [&str; 5] = [
+    COMPLETED_HEADING,
+    OVERDUE_HEADING,
+    UPCOMING_HEADING,
+    PROJECTS_HEADING,
+    HEALTH_GOALS_HEADING,
+];
+
 #[derive(Error, Debug)]
 pub enum WeeklyReviewError {
     #[error("Rusqlite error: {0}")]
     Rusqlite(#[from] rusqlite::Error),
     #[error("Note error: {0}")]
     Note(#[from] DbError),
+    #[error("Project error: {0}")]
+    Project(#[from] ProjectError),
+    #[error("JSON error: {0}")]
+    Json(#[from] serde_json::Error),
+    #[error("Weekly review not found: {0}")]
+    NotFound(String),
+}
+
+/// Counts as of the last time the review was generated
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct WeeklyReviewStats {
+    pub completed_tasks: usize,
+    pub overdue_tasks: usize,
+    pub upcoming_tasks: usize,
+    /// Open action items taken over from the previous review
+    pub carried_over: usize,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct WeeklyReview {
+    pub id: String,
+    pub space_id: String,
+    /// Monday 00:00 UTC of the reviewed week
+    pub period_start: i64,
+    pub note_id: String,
+    pub generated_at: i64,
+    pub completed_at: Option<i64>,
+    pub stats: WeeklyReviewStats,
+}
+
+/// Monday 00:00 UTC of the week containing `timestamp`
+pub fn week_start(timestamp: i64) -> i64 {
+    let day = timestamp.div_euclid(SECONDS_PER_DAY);
+    // 1970-01-01 was a Thursday
+    let days_since_monday = (day + 3).rem_euclid(7);
+    (day - days_since_monday) * SECONDS_PER_DAY
 }
 
 pub fn generate_weekly_review(
     conn: &Connection,
     space_id: Ulid,
+) -> Result<note::Note, WeeklyReviewError> {
+    generate_weekly_review_at(conn, space_id, Utc::now().timestamp())
+}
+
+/// Generate the review of the week containing `now`. When the week already
+/// has one its note is rewritten: the generated sections are rebuilt and
+/// the carried over items and whatever the user added are kept.
+pub fn generate_weekly_review_at(
+    conn: &Connection,
+    space_id: Ulid,
+    now: i64,
 ) -> Result<note::Note, WeeklyReviewError> {
     log::info!(
         "[weekly_review] Generating weekly review for space: {}",
         space_id
     );
-    let now = Utc::now();
-    let last_week = now - Duration::weeks(1);
+    let generated_on = DateTime::from_timestamp(now, 0)
+        .ok_or_else(|| DbError::Message(format!("Invalid timestamp: {}", now)))?;
+    let last_week = now - SECONDS_PER_WEEK;
     let space_id_str = space_id.to_string();
 
     let completed_tasks = conn
         .prepare(
             "SELECT title FROM task WHERE space_id = ? AND completed_at >= ? AND completed_at < ?",
         )?
-        .query_map(
-            rusqlite::params![space_id_str, last_week.timestamp(), now.timestamp()],
-            |row| row.get(0),
-        )?
+        .query_map(rusqlite::params![space_id_str, last_week, now], |row| {
+            row.get(0)
+        })?
         .collect::<Result<Vec<String>, _>>()?;
 
     let overdue_tasks = conn
         .prepare("SELECT title FROM task WHERE space_id = ? AND due_at < ? AND status != 'done'")?
-        .query_map(rusqlite::params![space_id_str, now.timestamp()], |row| {
-            row.get(0)
-        })?
+        .query_map(rusqlite::params![space_id_str, now], |row| row.get(0))?
         .collect::<Result<Vec<String>, _>>()?;
 
-    let next_week = now + Duration::weeks(1);
+    let next_week = now + SECONDS_PER_WEEK;
     let upcoming_tasks = conn.prepare(
         "SELECT title FROM task WHERE space_id = ? AND due_at >= ? AND due_at < ? AND status != 'done'",
     )?
-    .query_map(rusqlite::params![space_id_str, now.timestamp(), next_week.timestamp()], |row| row.get(0))?
+    .query_map(rusqlite::params![space_id_str, now, next_week], |row| row.get(0))?
     .collect::<Result<Vec<String>, _>>()?;
 
+    let ongoing_projects = conn
+        .prepare(
+            "SELECT id, title FROM project WHERE space_id = ? AND status NOT IN ('done', 'archived') ORDER BY title",
+        )?
+        .query_map([&space_id_str], |row| Ok((row.get(0)?, row.get(1)?)))?
+        .collect::<Result<Vec<(String, String)>, _>>()?;
+    let latest_updates = get_latest_status_per_project(conn, &space_id_str)?;
+
+    let mut stats = WeeklyReviewStats {
+        completed_tasks: completed_tasks.len(),
+        overdue_tasks: overdue_tasks.len(),
+        upcoming_tasks: upcoming_tasks.len(),
+        carried_over: 0,
+    };
     let mut review_content = String::new();
 
-    review_content.push_str("## ✅ Completed Tasks\n");
+    review_content.push_str(COMPLETED_HEADING);
+    review_content.push('\n');
     if completed_tasks.is_empty() {
         review_content.push_str("- None\n");
     } else {
@@ -60,7 +154,7 @@ pub fn generate_weekly_review(
         }
     }
 
-    review_content.push_str("\n##  overdue Tasks\n");
+    review_content.push_str(&format!("\n{}\n", OVERDUE_HEADING));
     if overdue_tasks.is_empty() {
         review_content.push_str("- None\n");
     } else {
@@ -69,7 +163,7 @@ pub fn generate_weekly_review(
         }
     }
 
-    review_content.push_str("\n## 📅 Upcoming Tasks\n");
+    review_content.push_str(&format!("\n{}\n", UPCOMING_HEADING));
     if upcoming_tasks.is_empty() {
         review_content.push_str("- None\n");
     } else {
@@ -78,16 +172,358 @@ pub fn generate_weekly_review(
         }
     }
 
-    let title = format!("Weekly Review for {}", now.format("%Y-%m-%d"));
+    review_content.push_str(&format!("\n{}\n", PROJECTS_HEADING));
+    if ongoing_projects.is_empty() {
+        review_content.push_str("- None\n");
+    } else {
+        for (project_id, title) in &ongoing_projects {
+            match latest_updates.iter().find(|u| &u.project_id == project_id) {
+                Some(update) => review_content.push_str(&project_status_lines(title, update)),
+                None => review_content.push_str(&format!("- **{}**: No updates yet\n", title)),
+            }
+        }
+    }
+
+    // As of their last evaluation; spaces without goals get no section
+    let health_goals = get_health_goals(conn, space_id)?;
+    if !health_goals.is_empty() {
+        review_content.push_str(&format!("\n{}\n", HEALTH_GOALS_HEADING));
+        for goal in &health_goals {
+            review_content.push_str(&health_goal_line(goal));
+        }
+    }
+
+    let period_start = week_start(now);
+    let existing = get_weekly_review_for_week(conn, space_id, period_start)?;
+    let current_note = match &existing {
+        Some(review) => review_note_content(conn, &review.note_id)?,
+        None => None,
+    };
+
+    if let (Some(review), Some(content)) = (existing.as_ref(), current_note) {
+        push_user_sections(&mut review_content, &user_sections(&content));
+        stats.carried_over = review.stats.carried_over;
+        conn.execute(
+            "UPDATE note SET content_md = ?1 WHERE id = ?2",
+            params![review_content, review.note_id],
+        )?;
+        refresh_note(conn, &review.note_id, now)?;
+        conn.execute(
+            "UPDATE weekly_review SET generated_at = ?1, stats_json = ?2 WHERE id = ?3",
+            params![now, serde_json::to_string(&stats)?, review.id],
+        )?;
+        log::info!("[weekly_review] Weekly review regenerated");
+        let note_id =
+            Ulid::from_string(&review.note_id).map_err(|e| DbError::Message(e.to_string()))?;
+        return note::get_note(conn, DbUlid(note_id))?
+            .ok_or_else(|| WeeklyReviewError::NotFound(review.id.clone()));
+    }
 
-    match note::create_note(conn, &space_id.to_string(), &title, &review_content) {
-        Ok(review_note) => {
-            log::info!("[weekly_review] Weekly review generated successfully");
-            Ok(review_note)
+    let carried = previous_open_items(conn, &space_id_str, period_start)?;
+    stats.carried_over = carried.len();
+    let mut user_content = String::new();
+    if !carried.is_empty() {
+        user_content.push_str(&format!("{}\n", CARRIED_OVER_HEADING));
+        for item in &carried {
+            user_content.push_str(&format!("- [ ] {}\n", item));
         }
+        user_content.push('\n');
+    }
+    user_content.push_str(ACTION_ITEMS_HEADING);
+    push_user_sections(&mut review_content, &user_content);
+
+    let title = format!("Weekly Review for {}", generated_on.format("%Y-%m-%d"));
+    let review_note = match note::create_note(conn, &space_id_str, &title, &review_content) {
+        Ok(review_note) => review_note,
         Err(e) => {
             log::error!("[weekly_review] Error generating weekly review: {}", e);
-            Err(e.into())
+            return Err(e.into());
+        }
+    };
+    // A review whose note was trashed or purged gets the new note
+    conn.execute(
+        "INSERT INTO weekly_review (id, space_id, period_start, note_id, generated_at, stats_json)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6)
+         ON CONFLICT(space_id, period_start) DO UPDATE SET
+            note_id = excluded.note_id,
+            generated_at = excluded.generated_at,
+            stats_json = excluded.stats_json",
+        params![
+            Ulid::new().to_string(),
+            space_id_str,
+            period_start,
+            review_note.id.0.to_string(),
+            now,
+            serde_json::to_string(&stats)?,
+        ],
+    )?;
+    log::info!("[weekly_review] Weekly review generated successfully");
+    Ok(review_note)
+}
+
+/// Unchecked `- [ ]` items outside the generated sections of a review note,
+/// in order and without repeats
+pub fn open_action_items(markdown: &str) -> Vec<String> {
+    let mut items: Vec<String> = Vec::new();
+    for line in user_lines(markdown) {
+        if let Some(item) = unchecked_item(line) {
+            if !items.iter().any(|existing| existing == item) {
+                items.push(item.to_string());
+            }
+        }
+    }
+    items
+}
+
+pub fn mark_review_completed(
+    conn: &Connection,
+    review_id: Ulid,
+) -> Result<WeeklyReview, WeeklyReviewError> {
+    mark_review_completed_at(conn, review_id, Utc::now().timestamp())
+}
+
+/// Mark a review as done; completing it again keeps the first time
+pub fn mark_review_completed_at(
+    conn: &Connection,
+    review_id: Ulid,
+    now: i64,
+) -> Result<WeeklyReview, WeeklyReviewError> {
+    let updated = conn.execute(
+        "UPDATE weekly_review SET completed_at = COALESCE(completed_at, ?1) WHERE id = ?2",
+        params![now, review_id.to_string()],
+    )?;
+    if updated == 0 {
+        return Err(WeeklyReviewError::NotFound(review_id.to_string()));
+    }
+    get_weekly_review(conn, review_id)
+}
+
+pub fn get_weekly_review(
+    conn: &Connection,
+    review_id: Ulid,
+) -> Result<WeeklyReview, WeeklyReviewError> {
+    query_reviews(conn, "id = ?1", params![review_id.to_string()])?
+        .pop()
+        .ok_or_else(|| WeeklyReviewError::NotFound(review_id.to_string()))
+}
+
+/// The space's reviews, latest week first
+pub fn get_weekly_reviews(
+    conn: &Connection,
+    space_id: Ulid,
+) -> Result<Vec<WeeklyReview>, WeeklyReviewError> {
+    query_reviews(conn, "space_id = ?1", params![space_id.to_string()])
+}
+
+fn get_weekly_review_for_week(
+    conn: &Connection,
+    space_id: Ulid,
+    period_start: i64,
+) -> Result<Option<WeeklyReview>, WeeklyReviewError> {
+    Ok(query_reviews(
+        conn,
+        "space_id = ?1 AND period_start = ?2",
+        params![space_id.to_string(), period_start],
+    )?
+    .pop())
+}
+
+pub fn get_review_streak(conn: &Connection, space_id: Ulid) -> Result<i64, WeeklyReviewError> {
+    get_review_streak_at(conn, space_id, Utc::now().timestamp())
+}
+
+/// Consecutive weeks with a completed review, up to the week containing
+/// `now`. The current week doesn't break the streak while it's still open.
+pub fn get_review_streak_at(
+    conn: &Connection,
+    space_id: Ulid,
+    now: i64,
+) -> Result<i64, WeeklyReviewError> {
+    let this_week = week_start(now);
+    let weeks = conn
+        .prepare(
+            "SELECT period_start FROM weekly_review
+             WHERE space_id = ?1 AND completed_at IS NOT NULL AND period_start <= ?2
+             ORDER BY period_start DESC",
+        )?
+        .query_map(params![space_id.to_string(), this_week], |row| row.get(0))?
+        .collect::<Result<Vec<i64>, _>>()?;
+
+    let mut expected = if weeks.first() == Some(&this_week) {
+        this_week
+    } else {
+        this_week - SECONDS_PER_WEEK
+    };
+    let mut streak = 0;
+    for week in weeks {
+        if week != expected {
+            break;
+        }
+        streak += 1;
+        expected -= SECONDS_PER_WEEK;
+    }
+    Ok(streak)
+}
+
+fn query_reviews(
+    conn: &Connection,
+    condition: &str,
+    params: impl rusqlite::Params,
+) -> Result<Vec<WeeklyReview>, WeeklyReviewError> {
+    let mut stmt = conn.prepare(&format!(
+        "SELECT id, space_id, period_start, note_id, generated_at, completed_at, stats_json
+         FROM weekly_review WHERE {} ORDER BY period_start DESC",
+        condition
+    ))?;
+    let rows = stmt
+        .query_map(params, |row| {
+            Ok((
+                WeeklyReview {
+                    id: row.get(0)?,
+                    space_id: row.get(1)?,
+                    period_start: row.get(2)?,
+                    note_id: row.get(3)?,
+                    generated_at: row.get(4)?,
+                    completed_at: row.get(5)?,
+                    stats: WeeklyReviewStats::default(),
+                },
+                row.get::<_, String>(6)?,
+            ))
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
+    let mut reviews = Vec::with_capacity(rows.len());
+    for (mut review, stats_json) in rows {
+        review.stats = serde_json::from_str(&stats_json)?;
+        reviews.push(review);
+    }
+    Ok(reviews)
+}
+
+/// Body of a review's note, unless the note was trashed or purged. A locked
+/// note can't be rewritten.
+fn review_note_content(
+    conn: &Connection,
+    note_id: &str,
+) -> Result<Option<String>, WeeklyReviewError> {
+    let row: Option<(String, bool)> = conn
+        .query_row(
+            "SELECT content_md, is_locked FROM note WHERE id = ?1 AND is_trashed = 0",
+            [note_id],
+            |row| Ok((row.get(0)?, row.get(1)?)),
+        )
+        .optional()?;
+    match row {
+        Some((_, true)) => Err(DbError::Locked {
+            entity: "note",
+            id: note_id.to_string(),
+        }
+        .into()),
+        Some((content, false)) => Ok(Some(content)),
+        None => Ok(None),
+    }
+}
+
+/// Open items of the space's latest review before `period_start`
+fn previous_open_items(
+    conn: &Connection,
+    space_id: &str,
+    period_start: i64,
+) -> Result<Vec<String>, WeeklyReviewError> {
+    let content: Option<String> = conn
+        .query_row(
+            "SELECT n.content_md
+             FROM (SELECT note_id FROM weekly_review
+                   WHERE space_id = ?1 AND period_start < ?2
+                   ORDER BY period_start DESC LIMIT 1) r
+             JOIN note n ON n.id = r.note_id AND n.is_trashed = 0 AND n.is_locked = 0",
+            params![space_id, period_start],
+            |row| row.get(0),
+        )
+        .optional()?;
+    Ok(content
+        .map(|content| open_action_items(&content))
+        .unwrap_or_default())
+}
+
+/// Lines of a review note outside the generated sections
+fn user_lines(markdown: &str) -> impl Iterator<Item = &str> {
+    let mut generated = false;
+    markdown.lines().filter(move |line| {
+        if line.starts_with("## ") {
+            generated = GENERATED_HEADINGS.contains(&line.trim_end());
+        }
+        !generated
+    })
+}
+
+fn user_sections(markdown: &str) -> String {
+    user_lines(markdown).collect::<Vec<_>>().join("\n")
+}
+
+fn push_user_sections(content: &mut String, user_content: &str) {
+    let user_content = user_content.trim_matches('\n');
+    if !user_content.is_empty() {
+        content.push('\n');
+        content.push_str(user_content);
+        content.push('\n');
+    }
+}
+
+/// Text of a `- [ ] text` line
+fn unchecked_item(line: &str) -> Option<&str> {
+    let line = line.trim_start();
+    let rest = ["- ", "* ", "+ "]
+        .iter()
+        .find_map(|marker| line.strip_prefix(marker))?;
+    let text = rest.trim_start().strip_prefix("[ ]")?.trim();
+    (!text.is_empty()).then_some(text)
+}
+
+fn health_goal_line(goal: &HealthGoal) -> String {
+    let status = match goal.status {
+        Some(HealthGoalStatus::Achieved) => "Achieved",
+        Some(HealthGoalStatus::OnTrack) => "On track",
+        Some(HealthGoalStatus::Behind) => "Behind",
+        Some(HealthGoalStatus::Expired) => "Expired",
+        None => "Not evaluated yet",
+    };
+    let mut line = format!("- **{}**: {}", goal.title, status);
+    if let Some(value) = goal.current_value {
+        line.push_str(&format!(", currently {:.1}", value));
+        if let Some(unit) = &goal.target.unit {
+            line.push_str(&format!(" {}", unit));
+        }
+    }
+    line.push('\n');
+    line
+}
+
+/// A project's latest update as a list item, with its report fields nested
+fn project_status_lines(title: &str, update: &ProjectUpdate) -> String {
+    let status = match update.effective_status() {
+        Some(ProjectHealthStatus::OnTrack) => "On track",
+        Some(ProjectHealthStatus::AtRisk) => "At risk",
+        Some(ProjectHealthStatus::OffTrack) => "Off track",
+        None => "No status",
+    };
+    let mut line = format!("- **{}**: {}", title, status);
+    if let Some(percent) = update.report.percent_complete {
+        line.push_str(&format!(", {}% complete", percent));
+    }
+    if !update.summary.trim().is_empty() {
+        line.push_str(&format!(" — {}", update.summary.trim()));
+    }
+    line.push('\n');
+
+    let report = &update.report;
+    for (label, value) in [
+        ("Highlights", &report.highlights),
+        ("Risks", &report.risks_changed),
+        ("Next steps", &report.next_steps),
+    ] {
+        if let Some(value) = value.as_deref().filter(|v| !v.trim().is_empty()) {
+            line.push_str(&format!("  - {}: {}\n", label, value.trim()));
         }
     }
+    line
 }
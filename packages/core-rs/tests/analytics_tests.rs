@@ -5,14 +5,20 @@ fn setup_db() -> Connection {
     let conn = Connection::open_in_memory().unwrap();
 
     // Mock schemas required by analytics
+    conn.execute("CREATE TABLE space (id TEXT, archived_at INTEGER)", [])
+        .unwrap();
+    conn.execute(
+        "CREATE TABLE note (id TEXT, space_id TEXT, created_at INTEGER, content_md TEXT, is_trashed BOOLEAN)",
+        [],
+    )
+    .unwrap();
     conn.execute(
-        "CREATE TABLE note (id TEXT, created_at INTEGER, content_md TEXT, is_trashed BOOLEAN)",
+        "CREATE TABLE task (id TEXT, space_id TEXT, completed_at INTEGER)",
         [],
     )
     .unwrap();
-    conn.execute("CREATE TABLE task (id TEXT, completed_at INTEGER)", [])
+    conn.execute("CREATE TABLE project (id TEXT, space_id TEXT)", [])
         .unwrap();
-    conn.execute("CREATE TABLE project (id TEXT)", []).unwrap();
 
     conn
 }
@@ -64,3 +70,129 @@ fn test_weekly_stats_aggregation() {
     assert!(!data.tasks_completed_by_week.is_empty());
     assert_eq!(data.tasks_completed_by_week[0].count, 1);
 }
+
+fn setup_vault() -> (Connection, tempfile::TempDir, String) {
+    let dir = tempfile::tempdir().unwrap();
+    let mut conn = Connection::open(dir.path().join("vault.db")).unwrap();
+    core_rs::db::migrate(&mut conn).unwrap();
+    let space_id = core_rs::space::create_space(&mut conn, "Work")
+        .unwrap()
+        .to_string();
+    (conn, dir, space_id)
+}
+
+fn seed_note(conn: &Connection, space_id: &str, id: &str, modified_at: i64) {
+    conn.execute(
+        "INSERT INTO note (id, space_id, title, content_md, created_at, modified_at)
+         VALUES (?1, ?2, '', '', ?3, ?3)",
+        rusqlite::params![id, space_id, modified_at],
+    )
+    .unwrap();
+}
+
+fn seed_completed_task(conn: &Connection, space_id: &str, id: &str, completed_at: i64) {
+    conn.execute(
+        "INSERT INTO task (id, space_id, title, status, completed_at) VALUES (?1, ?2, 'T', 'done', ?3)",
+        rusqlite::params![id, space_id, completed_at],
+    )
+    .unwrap();
+}
+
+// Monday 2024-01-01 00:00:00 UTC
+const MONDAY: i64 = 1_704_067_200;
+const HOUR: i64 = 3600;
+
+fn cell(heatmap: &ActivityHeatmap, weekday: u8, hour: u8) -> &ActivityCounts {
+    &heatmap.cells[weekday as usize * 24 + hour as usize].counts
+}
+
+#[test]
+fn test_heatmap_buckets_in_local_time_across_midnight() {
+    let (conn, _dir, space_id) = setup_vault();
+    // UTC+03:30: 20:00Z is 23:30 Monday, 20:45Z is 00:15 Tuesday
+    seed_note(&conn, &space_id, "n1", MONDAY + 20 * HOUR);
+    seed_completed_task(&conn, &space_id, "t1", MONDAY + 20 * HOUR + 45 * 60);
+    conn.execute(
+        "INSERT INTO time_entry (id, space_id, note_id, started_at) VALUES ('e1', ?1, 'n1', ?2)",
+        rusqlite::params![space_id, MONDAY + 20 * HOUR + 50 * 60],
+    )
+    .unwrap();
+
+    let options = ActivityOptions {
+        utc_offset_minutes: 210,
+        weights: ActivityWeights {
+            note_edit: 1.0,
+            task_completion: 3.0,
+            time_entry: 0.5,
+        },
+    };
+    let range = core_rs::calendar::TimeRange::new(MONDAY, MONDAY + 7 * 24 * HOUR);
+    let heatmap = get_activity_heatmap(&conn, &space_id, range, &options).unwrap();
+
+    assert_eq!(heatmap.cells.len(), 7 * 24);
+    assert_eq!(cell(&heatmap, 0, 23).note_edits, 1);
+    let tuesday = cell(&heatmap, 1, 0);
+    assert_eq!(tuesday.task_completions, 1);
+    assert_eq!(tuesday.time_entries, 1);
+    assert_eq!(tuesday.score, 3.5);
+    assert_eq!(heatmap.max_score, 3.5);
+    // Nothing lands in the UTC bucket
+    assert_eq!(cell(&heatmap, 0, 20).score, 0.0);
+
+    // Other spaces don't count
+    let other = get_activity_heatmap(&conn, "another-space", range, &options).unwrap();
+    assert_eq!(other.max_score, 0.0);
+}
+
+#[test]
+fn test_daily_series_uses_local_days_and_fills_gaps() {
+    let (conn, _dir, space_id) = setup_vault();
+    // UTC-05:00: 03:00Z Tuesday is still 22:00 Monday, 05:30Z is 00:30 Tuesday
+    seed_note(&conn, &space_id, "n1", MONDAY + 24 * HOUR + 3 * HOUR);
+    seed_completed_task(
+        &conn,
+        &space_id,
+        "t1",
+        MONDAY + 24 * HOUR + 5 * HOUR + 30 * 60,
+    );
+    // Trashed notes and incomplete tasks are ignored
+    seed_note(&conn, &space_id, "n2", MONDAY + 24 * HOUR + 3 * HOUR);
+    conn.execute("UPDATE note SET is_trashed = 1 WHERE id = 'n2'", [])
+        .unwrap();
+    conn.execute(
+        "INSERT INTO task (id, space_id, title, status) VALUES ('t2', ?1, 'Open', 'next')",
+        [&space_id],
+    )
+    .unwrap();
+
+    let options = ActivityOptions {
+        utc_offset_minutes: -300,
+        ..Default::default()
+    };
+    // Monday 00:00 to Thursday 00:00 local time
+    let range =
+        core_rs::calendar::TimeRange::new(MONDAY + 5 * HOUR, MONDAY + 3 * 24 * HOUR + 5 * HOUR);
+    let series = get_daily_activity_series(&conn, range, &options).unwrap();
+
+    let dates: Vec<String> = series.iter().map(|d| d.date.to_string()).collect();
+    assert_eq!(dates, vec!["2024-01-01", "2024-01-02", "2024-01-03"]);
+    assert_eq!(series[0].counts.note_edits, 1);
+    assert_eq!(series[0].counts.task_completions, 0);
+    assert_eq!(series[1].counts.task_completions, 1);
+    assert_eq!(series[1].counts.score, 1.0);
+    assert_eq!(series[2].counts, ActivityCounts::default());
+}
+
+#[test]
+fn test_activity_queries_reject_bad_input() {
+    let (conn, _dir, space_id) = setup_vault();
+    let range = core_rs::calendar::TimeRange::new(MONDAY, MONDAY);
+    assert!(get_daily_activity_series(&conn, range, &ActivityOptions::default()).is_err());
+
+    let options = ActivityOptions {
+        utc_offset_minutes: 15 * 60,
+        ..Default::default()
+    };
+    let range = core_rs::calendar::TimeRange::new(MONDAY, MONDAY + HOUR);
+    assert!(get_activity_heatmap(&conn, &space_id, range, &options).is_err());
+}
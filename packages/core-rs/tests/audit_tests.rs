@@ -45,3 +45,140 @@ fn test_audit_logging() {
     );
     assert_eq!(log.ip_address, Some("127.0.0.1".to_string()));
 }
+
+fn setup_space() -> (Connection, tempfile::TempDir, String) {
+    let (mut conn, dir) = setup_db();
+    let space_id = core_rs::space::create_space(&mut conn, "Audited")
+        .unwrap()
+        .to_string();
+    (conn, dir, space_id)
+}
+
+#[test]
+fn test_data_changes_form_a_verifiable_chain() {
+    let (mut conn, _dir, space_id) = setup_space();
+
+    let note = core_rs::note::create_note(&conn, &space_id, "Labs", "Cholesterol 180").unwrap();
+    core_rs::note::update_note_content(&mut conn, note.id.clone(), "Labs", "Cholesterol 170")
+        .unwrap();
+    let project = core_rs::project::create_project(&conn, &space_id, "Budget").unwrap();
+    core_rs::note::trash_note(&conn, note.id.clone()).unwrap();
+
+    let trail = audit::get_audit_trail(&conn, &note.id.to_string()).unwrap();
+    let operations: Vec<_> = trail.iter().map(|e| e.operation).collect();
+    assert_eq!(
+        operations,
+        vec![
+            audit::AuditOperation::Create,
+            audit::AuditOperation::Update,
+            audit::AuditOperation::Delete
+        ]
+    );
+    assert!(trail.iter().all(|e| e.source == audit::AUDIT_SOURCE_LOCAL));
+    assert_eq!(
+        audit::get_audit_trail(&conn, &project.id).unwrap()[0].chain_seq,
+        3
+    );
+
+    let report = audit::verify_audit_chain(&conn, &space_id).unwrap();
+    assert!(report.valid);
+    assert_eq!(report.events_checked, 4);
+    assert_eq!(trail[1].prev_hash, trail[0].hash);
+}
+
+#[test]
+fn test_tampering_breaks_the_chain() {
+    let (conn, _dir, space_id) = setup_space();
+    for title in ["One", "Two", "Three"] {
+        core_rs::note::create_note(&conn, &space_id, title, "").unwrap();
+    }
+    assert!(audit::verify_audit_chain(&conn, &space_id).unwrap().valid);
+
+    // Rewrite what the second event is about
+    conn.execute(
+        "UPDATE audit_log SET entity_id = 'someone-else' WHERE space_id = ?1 AND chain_seq = 2",
+        [&space_id],
+    )
+    .unwrap();
+    let report = audit::verify_audit_chain(&conn, &space_id).unwrap();
+    assert!(!report.valid);
+    let broken = report.first_break.unwrap();
+    assert_eq!(broken.chain_seq, 2);
+    assert!(broken.reason.contains("modified"));
+    assert_eq!(report.events_checked, 2);
+}
+
+#[test]
+fn test_deleted_event_is_detected() {
+    let (conn, _dir, space_id) = setup_space();
+    for title in ["One", "Two", "Three"] {
+        core_rs::note::create_note(&conn, &space_id, title, "").unwrap();
+    }
+    conn.execute(
+        "DELETE FROM audit_log WHERE space_id = ?1 AND chain_seq = 2",
+        [&space_id],
+    )
+    .unwrap();
+
+    let report = audit::verify_audit_chain(&conn, &space_id).unwrap();
+    assert!(!report.valid);
+    let broken = report.first_break.unwrap();
+    assert_eq!(broken.chain_seq, 2);
+    assert!(broken.reason.contains("found #3"));
+}
+
+#[test]
+fn test_auditing_can_be_disabled_per_space() {
+    let (mut conn, _dir, space_id) = setup_space();
+    let other_space = core_rs::space::create_space(&mut conn, "Other")
+        .unwrap()
+        .to_string();
+    audit::set_space_audit_enabled(&conn, &space_id, false).unwrap();
+    assert!(!audit::is_space_audit_enabled(&conn, &space_id).unwrap());
+
+    let quiet = core_rs::note::create_note(&conn, &space_id, "Quiet", "").unwrap();
+    let loud = core_rs::note::create_note(&conn, &other_space, "Loud", "").unwrap();
+    assert!(audit::get_audit_trail(&conn, &quiet.id.to_string())
+        .unwrap()
+        .is_empty());
+    assert_eq!(
+        audit::get_audit_trail(&conn, &loud.id.to_string())
+            .unwrap()
+            .len(),
+        1
+    );
+
+    audit::set_space_audit_enabled(&conn, &space_id, true).unwrap();
+    core_rs::note::trash_note(&conn, quiet.id.clone()).unwrap();
+    let trail = audit::get_audit_trail(&conn, &quiet.id.to_string()).unwrap();
+    assert_eq!(trail.len(), 1);
+    assert_eq!(trail[0].chain_seq, 1);
+}
+
+#[test]
+fn test_synced_changes_record_the_sending_device() {
+    let (mut conn, _dir, space_id) = setup_space();
+    core_rs::sync_agent::init_sync_tables(&conn).unwrap();
+    let agent =
+        core_rs::sync_agent::SyncAgent::new("desktop-1".to_string(), "Desktop".to_string(), 8080);
+    let note_id = ulid::Ulid::new().to_string();
+    let delta = core_rs::sync_agent::SyncDelta {
+        entity_type: "note".to_string(),
+        entity_id: note_id.clone(),
+        operation: core_rs::sync_agent::SyncOperation::Create,
+        data: Some(b"From the phone".to_vec()),
+        timestamp: chrono::Utc::now().timestamp(),
+        vector_clock: std::collections::HashMap::new(),
+        space_id: Some(space_id.clone()),
+    };
+
+    agent
+        .apply_deltas_from(&mut conn, vec![delta], &[0u8; 32], "phone-7")
+        .unwrap();
+
+    let trail = audit::get_audit_trail(&conn, &note_id).unwrap();
+    assert_eq!(trail.len(), 1);
+    assert_eq!(trail[0].source, "phone-7");
+    assert_eq!(trail[0].operation, audit::AuditOperation::Create);
+    assert!(audit::verify_audit_chain(&conn, &space_id).unwrap().valid);
+}
@@ -1,4 +1,6 @@
-use core_rs::auth::{AuthError, AuthService};
+use core_rs::auth::{
+    AuthError, AuthService, SessionConfig, SessionDevice, LAST_USED_WRITE_INTERVAL_SECS,
+};
 use rusqlite::Connection;
 use std::sync::{Arc, Mutex};
 use tempfile::tempdir;
@@ -32,12 +34,27 @@ fn setup_db() -> (Arc<Mutex<Connection>>, tempfile::TempDir) {
             token TEXT UNIQUE NOT NULL,
             expires_at INTEGER NOT NULL,
             created_at INTEGER NOT NULL,
+            last_used_at INTEGER,
+            device_name TEXT,
+            user_agent TEXT,
+            ttl_secs INTEGER NOT NULL DEFAULT 86400,
             FOREIGN KEY (user_id) REFERENCES users(id) ON DELETE CASCADE
         )",
         [],
     )
     .unwrap();
 
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS auth_attempt (
+            key TEXT PRIMARY KEY,
+            failures INTEGER NOT NULL DEFAULT 0,
+            last_failure_at INTEGER,
+            locked_until INTEGER
+        )",
+        [],
+    )
+    .unwrap();
+
     (Arc::new(Mutex::new(conn)), dir)
 }
 
@@ -78,3 +95,189 @@ fn test_auth_flow_integration() {
         Err(AuthError::InvalidSession)
     ));
 }
+
+fn migrated_db_with_user() -> Connection {
+    let mut conn = Connection::open_in_memory().unwrap();
+    core_rs::db::migrate(&mut conn).unwrap();
+    AuthService::create_user(&conn, "alice", "alice@example.com", "password123").unwrap();
+    conn
+}
+
+fn device(name: &str) -> SessionDevice {
+    SessionDevice {
+        device_name: Some(name.to_string()),
+        user_agent: Some(format!("Noteece/{}", name)),
+    }
+}
+
+fn last_used_at(conn: &Connection, session_id: &str) -> i64 {
+    conn.query_row(
+        "SELECT last_used_at FROM sessions WHERE id = ?1",
+        [session_id],
+        |row| row.get(0),
+    )
+    .unwrap()
+}
+
+#[test]
+fn test_session_ttl_and_expiry() {
+    let conn = migrated_db_with_user();
+    let session = AuthService::authenticate_with_device(
+        &conn,
+        "alice",
+        "password123",
+        &device("laptop"),
+        &SessionConfig { ttl_secs: 3600 },
+    )
+    .unwrap();
+    assert_eq!(session.expires_at - session.created_at, 3600);
+    assert!(AuthService::validate_session(&conn, &session.token).is_ok());
+
+    conn.execute(
+        "UPDATE sessions SET expires_at = ?1 WHERE id = ?2",
+        rusqlite::params![session.created_at - 1, session.id],
+    )
+    .unwrap();
+    assert!(matches!(
+        AuthService::validate_session(&conn, &session.token),
+        Err(AuthError::InvalidSession)
+    ));
+    assert!(matches!(
+        AuthService::refresh_session(&conn, &session.token),
+        Err(AuthError::SessionExpired)
+    ));
+    assert!(AuthService::list_sessions(&conn, &session.user_id)
+        .unwrap()
+        .is_empty());
+}
+
+#[test]
+fn test_refresh_rotates_token() {
+    let conn = migrated_db_with_user();
+    let session = AuthService::authenticate_with_device(
+        &conn,
+        "alice",
+        "password123",
+        &device("laptop"),
+        &SessionConfig { ttl_secs: 600 },
+    )
+    .unwrap();
+
+    let refreshed = AuthService::refresh_session(&conn, &session.token).unwrap();
+    assert_eq!(refreshed.id, session.id);
+    assert_eq!(refreshed.created_at, session.created_at);
+    assert_ne!(refreshed.token, session.token);
+    assert!(refreshed.expires_at >= session.expires_at);
+
+    // The old token is dead, for validation and for a second refresh
+    assert!(matches!(
+        AuthService::validate_session(&conn, &session.token),
+        Err(AuthError::InvalidSession)
+    ));
+    assert!(matches!(
+        AuthService::refresh_session(&conn, &session.token),
+        Err(AuthError::InvalidSession)
+    ));
+    assert_eq!(
+        AuthService::validate_session(&conn, &refreshed.token).unwrap(),
+        session.user_id
+    );
+}
+
+#[test]
+fn test_list_sessions_reports_device_metadata() {
+    let conn = migrated_db_with_user();
+    let config = SessionConfig::default();
+    let laptop = AuthService::authenticate_with_device(
+        &conn,
+        "alice",
+        "password123",
+        &device("laptop"),
+        &config,
+    )
+    .unwrap();
+    let phone = AuthService::authenticate_with_device(
+        &conn,
+        "alice",
+        "password123",
+        &device("phone"),
+        &config,
+    )
+    .unwrap();
+    conn.execute(
+        "UPDATE sessions SET last_used_at = last_used_at - 100 WHERE id = ?1",
+        [&laptop.id],
+    )
+    .unwrap();
+
+    let sessions = AuthService::list_sessions(&conn, &laptop.user_id).unwrap();
+    assert_eq!(sessions.len(), 2);
+    assert_eq!(sessions[0].id, phone.id);
+    assert_eq!(sessions[0].device_name.as_deref(), Some("phone"));
+    assert_eq!(sessions[0].user_agent.as_deref(), Some("Noteece/phone"));
+    assert_eq!(sessions[0].created_at, phone.created_at);
+    assert_eq!(sessions[1].id, laptop.id);
+    assert_eq!(sessions[1].expires_at, laptop.expires_at);
+}
+
+#[test]
+fn test_last_used_writes_are_rate_limited() {
+    let conn = migrated_db_with_user();
+    let session = AuthService::authenticate(&conn, "alice", "password123").unwrap();
+    assert_eq!(last_used_at(&conn, &session.id), session.created_at);
+
+    // Within the write interval validation leaves last_used_at alone
+    let recent = session.created_at - LAST_USED_WRITE_INTERVAL_SECS / 2;
+    conn.execute(
+        "UPDATE sessions SET last_used_at = ?1 WHERE id = ?2",
+        rusqlite::params![recent, session.id],
+    )
+    .unwrap();
+    AuthService::validate_session(&conn, &session.token).unwrap();
+    assert_eq!(last_used_at(&conn, &session.id), recent);
+
+    // Once it is older than the interval the next validation records the use
+    let stale = session.created_at - LAST_USED_WRITE_INTERVAL_SECS;
+    conn.execute(
+        "UPDATE sessions SET last_used_at = ?1 WHERE id = ?2",
+        rusqlite::params![stale, session.id],
+    )
+    .unwrap();
+    AuthService::validate_session(&conn, &session.token).unwrap();
+    assert!(last_used_at(&conn, &session.id) >= session.created_at);
+}
+
+#[test]
+fn test_revoke_sessions() {
+    let conn = migrated_db_with_user();
+    AuthService::create_user(&conn, "bob", "bob@example.com", "password123").unwrap();
+    let first = AuthService::authenticate(&conn, "alice", "password123").unwrap();
+    let second = AuthService::authenticate(&conn, "alice", "password123").unwrap();
+    let third = AuthService::authenticate(&conn, "alice", "password123").unwrap();
+    let bob = AuthService::authenticate(&conn, "bob", "password123").unwrap();
+
+    // Users can only revoke their own sessions
+    assert!(matches!(
+        AuthService::revoke_session(&conn, &first.user_id, &bob.id),
+        Err(AuthError::InvalidSession)
+    ));
+
+    AuthService::revoke_session(&conn, &first.user_id, &first.id).unwrap();
+    assert!(AuthService::validate_session(&conn, &first.token).is_err());
+    assert!(AuthService::validate_session(&conn, &second.token).is_ok());
+
+    let revoked =
+        AuthService::revoke_all_sessions(&conn, &first.user_id, Some(third.id.as_str())).unwrap();
+    assert_eq!(revoked, 1);
+    assert!(AuthService::validate_session(&conn, &second.token).is_err());
+    assert!(AuthService::validate_session(&conn, &third.token).is_ok());
+
+    assert_eq!(
+        AuthService::revoke_all_sessions(&conn, &first.user_id, None).unwrap(),
+        1
+    );
+    assert!(AuthService::list_sessions(&conn, &first.user_id)
+        .unwrap()
+        .is_empty());
+    assert!(AuthService::validate_session(&conn, &bob.token).is_ok());
+}
@@ -0,0 +1,234 @@
+use core_rs::audit::{self, get_auth_audit_log, AuthAuditFilter};
+use core_rs::auth::{AuthError, AuthService};
+use core_rs::collaboration::{activate_user, add_user_to_space, init_rbac_tables, suspend_user};
+use core_rs::lockout::{self, LockoutPolicy, VaultAttemptFile};
+use core_rs::vault::{create_vault, unlock_vault, VaultError};
+use rusqlite::Connection;
+use tempfile::tempdir;
+
+fn setup_db(path: &std::path::Path) -> Connection {
+    let mut conn = Connection::open(path).unwrap();
+    core_rs::db::migrate(&mut conn).unwrap();
+    AuthService::create_user(&conn, "alice", "alice@example.com", "password123").unwrap();
+    conn
+}
+
+fn failed_logins(conn: &Connection, username: &str, count: usize) {
+    for _ in 0..count {
+        assert!(matches!(
+            AuthService::authenticate(conn, username, "wrong-password"),
+            Err(AuthError::InvalidCredentials)
+        ));
+    }
+}
+
+fn expire_lockout(conn: &Connection, key: &str) {
+    conn.execute(
+        "UPDATE auth_attempt SET locked_until = locked_until - 100000 WHERE key = ?1",
+        [key],
+    )
+    .unwrap();
+}
+
+#[test]
+fn test_lockout_backoff_doubles_and_caps() {
+    let policy = LockoutPolicy {
+        max_attempts: 3,
+        base_lockout_secs: 10,
+        max_lockout_secs: 60,
+    };
+    assert_eq!(policy.lockout_secs(2), None);
+    assert_eq!(policy.lockout_secs(3), Some(10));
+    assert_eq!(policy.lockout_secs(4), Some(20));
+    assert_eq!(policy.lockout_secs(5), Some(40));
+    assert_eq!(policy.lockout_secs(6), Some(60));
+    assert_eq!(policy.lockout_secs(500), Some(60));
+
+    let conn = Connection::open_in_memory().unwrap();
+    conn.execute_batch(
+        "CREATE TABLE auth_attempt (key TEXT PRIMARY KEY, failures INTEGER NOT NULL DEFAULT 0,
+                                    last_failure_at INTEGER, locked_until INTEGER);",
+    )
+    .unwrap();
+    for now in [1000, 1001] {
+        let state = lockout::record_failure_at(&conn, "user:bob", &policy, now).unwrap();
+        assert_eq!(state.retry_after(now), None);
+    }
+    let state = lockout::record_failure_at(&conn, "user:bob", &policy, 1002).unwrap();
+    assert_eq!(state.locked_until, Some(1012));
+    assert_eq!(state.retry_after(1005), Some(7));
+    assert_eq!(state.retry_after(1012), None);
+
+    let state = lockout::record_failure_at(&conn, "user:bob", &policy, 1012).unwrap();
+    assert_eq!(state.locked_until, Some(1032));
+}
+
+#[test]
+fn test_login_burst_locks_out_across_connections() {
+    let dir = tempdir().unwrap();
+    let db_path = dir.path().join("auth.db");
+    let conn = setup_db(&db_path);
+    let key = lockout::user_key("alice");
+
+    failed_logins(&conn, "alice", 4);
+    assert!(AuthService::authenticate(&conn, "alice", "password123").is_ok());
+    assert_eq!(lockout::get_attempt_state(&conn, &key).unwrap().failures, 0);
+
+    failed_logins(&conn, "alice", 5);
+    let state = lockout::get_attempt_state(&conn, &key).unwrap();
+    assert_eq!(
+        state.locked_until.unwrap() - state.last_failure_at.unwrap(),
+        LockoutPolicy::default().base_lockout_secs
+    );
+
+    // Even the right password is refused, also over a fresh connection and
+    // with a differently cased username
+    drop(conn);
+    let conn = Connection::open(&db_path).unwrap();
+    match AuthService::authenticate(&conn, "ALICE", "password123") {
+        Err(AuthError::LockedOut(retry_after)) => assert!(retry_after > 0 && retry_after <= 30),
+        other => panic!("expected lockout, got {:?}", other.map(|s| s.id)),
+    }
+
+    // The next failure after the lockout doubles it
+    expire_lockout(&conn, &key);
+    failed_logins(&conn, "alice", 1);
+    let state = lockout::get_attempt_state(&conn, &key).unwrap();
+    assert_eq!(state.failures, 6);
+    assert_eq!(
+        state.locked_until.unwrap() - state.last_failure_at.unwrap(),
+        2 * LockoutPolicy::default().base_lockout_secs
+    );
+
+    expire_lockout(&conn, &key);
+    assert!(AuthService::authenticate(&conn, "alice", "password123").is_ok());
+    assert_eq!(
+        lockout::get_attempt_state(&conn, &key).unwrap(),
+        Default::default()
+    );
+}
+
+#[test]
+fn test_auth_events_are_audited() {
+    let dir = tempdir().unwrap();
+    let conn = setup_db(&dir.path().join("auth.db"));
+    let user_id = conn
+        .query_row("SELECT id FROM users WHERE username = 'alice'", [], |row| {
+            row.get::<_, String>(0)
+        })
+        .unwrap();
+
+    failed_logins(&conn, "alice", 5);
+    assert!(AuthService::authenticate(&conn, "alice", "password123").is_err());
+    expire_lockout(&conn, &lockout::user_key("alice"));
+    AuthService::authenticate(&conn, "alice", "password123").unwrap();
+    assert!(matches!(
+        AuthService::change_password(&conn, &user_id, "nope-nope", "newpassword123"),
+        Err(AuthError::InvalidCredentials)
+    ));
+    AuthService::change_password(&conn, &user_id, "password123", "newpassword123").unwrap();
+
+    init_rbac_tables(&conn).unwrap();
+    conn.execute("INSERT INTO space (id, name) VALUES ('s1', 'Team')", [])
+        .unwrap();
+    add_user_to_space(&conn, "s1", "bob", "bob@example.com", "viewer").unwrap();
+    suspend_user(&conn, "s1", "bob").unwrap();
+    activate_user(&conn, "s1", "bob").unwrap();
+
+    let all = get_auth_audit_log(&conn, &AuthAuditFilter::default()).unwrap();
+    let count = |event: &str| all.iter().filter(|e| e.event_type == event).count();
+    assert_eq!(count(audit::AUTH_LOGIN_FAILED), 5);
+    assert_eq!(count(audit::AUTH_LOGIN_LOCKED_OUT), 1);
+    assert_eq!(count(audit::AUTH_LOGIN_SUCCEEDED), 1);
+    assert_eq!(count(audit::AUTH_PASSWORD_CHANGE_FAILED), 1);
+    assert_eq!(count(audit::AUTH_PASSWORD_CHANGED), 1);
+    assert_eq!(count(audit::AUTH_USER_SUSPENDED), 1);
+    assert_eq!(count(audit::AUTH_USER_ACTIVATED), 1);
+
+    let failures = get_auth_audit_log(
+        &conn,
+        &AuthAuditFilter {
+            subject: Some("alice".into()),
+            success: Some(false),
+            ..Default::default()
+        },
+    )
+    .unwrap();
+    assert_eq!(failures.len(), 7);
+    assert!(failures.iter().all(|e| !e.success));
+    let last_failure = failures
+        .iter()
+        .find(|e| e.event_type == audit::AUTH_LOGIN_FAILED)
+        .unwrap();
+    assert_eq!(last_failure.user_id.as_deref(), Some(user_id.as_str()));
+    assert_eq!(last_failure.details["failures"], 5);
+
+    let suspended = get_auth_audit_log(
+        &conn,
+        &AuthAuditFilter {
+            event_types: Some(vec![audit::AUTH_USER_SUSPENDED.to_string()]),
+            ..Default::default()
+        },
+    )
+    .unwrap();
+    assert_eq!(suspended[0].subject.as_deref(), Some("bob"));
+    assert_eq!(suspended[0].details["space_id"], "s1");
+
+    let page = get_auth_audit_log(
+        &conn,
+        &AuthAuditFilter {
+            user_id: Some(user_id),
+            limit: Some(2),
+            ..Default::default()
+        },
+    )
+    .unwrap();
+    assert_eq!(page.len(), 2);
+}
+
+#[test]
+fn test_vault_unlock_lockout_persists_and_is_audited() {
+    let dir = tempdir().unwrap();
+    let vault_path = dir.path().to_str().unwrap();
+    drop(create_vault(vault_path, "correct horse").unwrap());
+
+    for _ in 0..5 {
+        assert!(matches!(
+            unlock_vault(vault_path, "wrong"),
+            Err(VaultError::Crypto(_))
+        ));
+    }
+    assert!(matches!(
+        unlock_vault(vault_path, "correct horse"),
+        Err(VaultError::LockedOut(_))
+    ));
+
+    let mut attempts = VaultAttemptFile::load(dir.path()).unwrap();
+    assert_eq!(attempts.state.failures, 5);
+    assert_eq!(attempts.pending.len(), 6);
+    assert!(attempts.pending[5].locked);
+
+    // Lift the lockout as if its time had passed
+    attempts.state.locked_until = Some(0);
+    attempts.save(dir.path()).unwrap();
+    let vault = unlock_vault(vault_path, "correct horse").unwrap();
+    assert_eq!(
+        VaultAttemptFile::load(dir.path()).unwrap(),
+        VaultAttemptFile::default()
+    );
+
+    let log = get_auth_audit_log(
+        &vault.conn,
+        &AuthAuditFilter {
+            subject: Some(vault_path.to_string()),
+            ..Default::default()
+        },
+    )
+    .unwrap();
+    let count = |event: &str| log.iter().filter(|e| e.event_type == event).count();
+    assert_eq!(count(audit::AUTH_VAULT_UNLOCK_FAILED), 5);
+    assert_eq!(count(audit::AUTH_VAULT_UNLOCK_LOCKED_OUT), 1);
+    assert_eq!(count(audit::AUTH_VAULT_UNLOCKED), 1);
+    assert_eq!(log[0].event_type, audit::AUTH_VAULT_UNLOCKED);
+    assert_eq!(log[0].details["failed_attempts"], 5);
+}
@@ -0,0 +1,661 @@
+use core_rs::automation::*;
+use core_rs::db::bulk::with_bulk_import;
+use core_rs::db::migrate;
+use core_rs::events::{register_sink, unregister_sink, CollectingSink, CoreEvent};
+use core_rs::note::create_note;
+use core_rs::project::create_project;
+use core_rs::space::create_space;
+use core_rs::tag::add_tag_to_note;
+use core_rs::task::{create_task, get_task, update_task, Task};
+use rusqlite::Connection;
+use std::sync::Arc;
+use std::time::Duration;
+use ulid::Ulid;
+
+const WAIT: Duration = Duration::from_secs(5);
+
+fn setup() -> (Connection, String) {
+    let mut conn = Connection::open_in_memory().unwrap();
+    migrate(&mut conn).unwrap();
+    let space_id = create_space(&mut conn, "Study").unwrap().to_string();
+    (conn, space_id)
+}
+
+fn note(conn: &Connection, space_id: &str, title: &str) -> String {
+    create_note(conn, space_id, title, "")
+        .unwrap()
+        .id
+        .0
+        .to_string()
+}
+
+fn rule(
+    conn: &Connection,
+    space_id: &str,
+    trigger: AutomationTrigger,
+    actions: Vec<AutomationAction>,
+) -> AutomationRule {
+    create_automation_rule(conn, space_id, "Rule", trigger, actions).unwrap()
+}
+
+fn tag_added(tag: &str) -> AutomationTrigger {
+    AutomationTrigger::TagAdded {
+        tag: tag.to_string(),
+    }
+}
+
+fn note_created(title_pattern: &str) -> AutomationTrigger {
+    AutomationTrigger::NoteCreated {
+        title_pattern: title_pattern.to_string(),
+    }
+}
+
+fn create_task_action(title: &str) -> AutomationAction {
+    AutomationAction::CreateTask {
+        title: title.to_string(),
+        description: None,
+        due_in_days: None,
+    }
+}
+
+fn add_tag_action(tag: &str) -> AutomationAction {
+    AutomationAction::AddTag {
+        tag: tag.to_string(),
+    }
+}
+
+fn prompt_action(message: &str) -> AutomationAction {
+    AutomationAction::Prompt {
+        message: message.to_string(),
+    }
+}
+
+fn space_tasks(conn: &Connection, space_id: &str) -> Vec<Task> {
+    let mut stmt = conn
+        .prepare("SELECT id FROM task WHERE space_id = ?1 ORDER BY rowid")
+        .unwrap();
+    let ids = stmt
+        .query_map([space_id], |row| row.get::<_, String>(0))
+        .unwrap()
+        .collect::<Result<Vec<_>, _>>()
+        .unwrap();
+    ids.iter()
+        .map(|id| {
+            get_task(conn, Ulid::from_string(id).unwrap())
+                .unwrap()
+                .unwrap()
+        })
+        .collect()
+}
+
+fn tags_of(conn: &Connection, table: &str, id: &str) -> Vec<String> {
+    let mut stmt = conn
+        .prepare(&format!(
+            "SELECT t.name FROM {0}_tags x JOIN tag t ON t.id = x.tag_id
+             WHERE x.{0}_id = ?1 ORDER BY t.name",
+            table
+        ))
+        .unwrap();
+    stmt.query_map([id], |row| row.get(0))
+        .unwrap()
+        .collect::<Result<_, _>>()
+        .unwrap()
+}
+
+fn card_count(conn: &Connection, note_id: &str) -> i64 {
+    conn.query_row(
+        "SELECT COUNT(*) FROM knowledge_card WHERE note_id = ?1",
+        [note_id],
+        |row| row.get(0),
+    )
+    .unwrap()
+}
+
+fn complete(conn: &Connection, task: &Task) {
+    let mut task = get_task(conn, task.id).unwrap().unwrap();
+    task.status = "done".to_string();
+    update_task(conn, &task).unwrap();
+}
+
+/// Wait for the prompt `rule` emits, with the sink registered for the call
+fn expect_prompt(rule: &AutomationRule, f: impl FnOnce()) -> CoreEvent {
+    let sink = Arc::new(CollectingSink::default());
+    let sink_id = register_sink(sink.clone());
+    f();
+    let event = sink
+        .wait_for(
+            WAIT,
+            |e| matches!(e, CoreEvent::AutomationPrompt { rule_id, .. } if *rule_id == rule.id),
+        )
+        .expect("prompt event");
+    unregister_sink(sink_id);
+    event.event
+}
+
+#[test]
+fn test_tag_added_creates_task_from_template() {
+    let (conn, space_id) = setup();
+    rule(
+        &conn,
+        &space_id,
+        tag_added("#reading"),
+        vec![AutomationAction::CreateTask {
+            title: "Summarise {{title}}".to_string(),
+            description: Some("Key ideas in five bullets".to_string()),
+            due_in_days: Some(3),
+        }],
+    );
+    let book = note(&conn, &space_id, "Deep Work");
+    let other = note(&conn, &space_id, "Groceries");
+
+    add_tag_to_note(&conn, &other, "cooking").unwrap();
+    assert!(space_tasks(&conn, &space_id).is_empty());
+
+    // Tags nested below the rule's tag trigger it too
+    assert!(add_tag_to_note(&conn, &book, "reading/nonfiction").unwrap());
+    let tasks = space_tasks(&conn, &space_id);
+    assert_eq!(tasks.len(), 1);
+    assert_eq!(tasks[0].title, "Summarise Deep Work");
+    assert_eq!(
+        tasks[0].description.as_deref(),
+        Some("Key ideas in five bullets")
+    );
+    assert_eq!(tasks[0].note_id.unwrap().to_string(), book);
+    let due_in = tasks[0].due_at.unwrap() - chrono::Utc::now().timestamp();
+    assert!((3 * 86_400 - 60..=3 * 86_400).contains(&due_in));
+
+    // Tagging again changes nothing, so nothing runs again
+    assert!(!add_tag_to_note(&conn, &book, "#reading/nonfiction").unwrap());
+    assert_eq!(space_tasks(&conn, &space_id).len(), 1);
+}
+
+#[test]
+fn test_tag_added_adds_another_tag() {
+    let (conn, space_id) = setup();
+    rule(
+        &conn,
+        &space_id,
+        tag_added("inbox"),
+        vec![add_tag_action("#to-process")],
+    );
+    let id = note(&conn, &space_id, "Scanned receipt");
+
+    add_tag_to_note(&conn, &id, "inbox").unwrap();
+    assert_eq!(tags_of(&conn, "note", &id), vec!["inbox", "to-process"]);
+}
+
+#[test]
+fn test_tag_added_moves_note_tasks_to_project() {
+    let (conn, space_id) = setup();
+    let project = create_project(&conn, &space_id, "Thesis").unwrap();
+    rule(
+        &conn,
+        &space_id,
+        tag_added("thesis"),
+        vec![AutomationAction::MoveToProject {
+            project_id: project.id.clone(),
+        }],
+    );
+    let id = note(&conn, &space_id, "Chapter 2 outline");
+    let mut task =
+        create_task(&conn, Ulid::from_string(&space_id).unwrap(), "Draft", None).unwrap();
+    task.note_id = Some(Ulid::from_string(&id).unwrap());
+    update_task(&conn, &task).unwrap();
+    let unrelated = create_task(&conn, task.space_id, "Laundry", None).unwrap();
+
+    add_tag_to_note(&conn, &id, "thesis").unwrap();
+    let moved = get_task(&conn, task.id).unwrap().unwrap();
+    assert_eq!(moved.project_id.unwrap().to_string(), project.id);
+    assert_eq!(
+        get_task(&conn, unrelated.id).unwrap().unwrap().project_id,
+        None
+    );
+}
+
+#[test]
+fn test_tag_added_creates_srs_card_once() {
+    let (conn, space_id) = setup();
+    rule(
+        &conn,
+        &space_id,
+        tag_added("flashcard"),
+        vec![AutomationAction::CreateSrsCard],
+    );
+    rule(
+        &conn,
+        &space_id,
+        tag_added("flashcard/spanish"),
+        vec![AutomationAction::CreateSrsCard],
+    );
+    let id = note(&conn, &space_id, "Irregular verbs");
+
+    add_tag_to_note(&conn, &id, "flashcard/spanish").unwrap();
+    assert_eq!(card_count(&conn, &id), 1);
+}
+
+#[test]
+fn test_tag_added_emits_prompt() {
+    let (conn, space_id) = setup();
+    let prompt = rule(
+        &conn,
+        &space_id,
+        tag_added("idea"),
+        vec![prompt_action("Turn {{title}} into a project?")],
+    );
+    let id = note(&conn, &space_id, "Garden planner app");
+
+    let event = expect_prompt(&prompt, || {
+        add_tag_to_note(&conn, &id, "idea").unwrap();
+    });
+    assert_eq!(
+        event,
+        CoreEvent::AutomationPrompt {
+            space_id: space_id.clone(),
+            rule_id: prompt.id.clone(),
+            rule_name: "Rule".to_string(),
+            message: "Turn Garden planner app into a project?".to_string(),
+            entity_type: "note".to_string(),
+            entity_id: id,
+        }
+    );
+}
+
+#[test]
+fn test_task_completed_creates_follow_up_task() {
+    let (conn, space_id) = setup();
+    let project = create_project(&conn, &space_id, "Website").unwrap();
+    rule(
+        &conn,
+        &space_id,
+        AutomationTrigger::TaskCompleted,
+        vec![create_task_action("Review: {{title}}")],
+    );
+    let id = note(&conn, &space_id, "Launch checklist");
+    let mut task = create_task(
+        &conn,
+        Ulid::from_string(&space_id).unwrap(),
+        "Ship v2",
+        None,
+    )
+    .unwrap();
+    task.note_id = Some(Ulid::from_string(&id).unwrap());
+    task.project_id = Some(Ulid::from_string(&project.id).unwrap());
+    update_task(&conn, &task).unwrap();
+    assert_eq!(space_tasks(&conn, &space_id).len(), 1);
+
+    complete(&conn, &task);
+    let tasks = space_tasks(&conn, &space_id);
+    assert_eq!(tasks.len(), 2);
+    assert_eq!(tasks[1].title, "Review: Ship v2");
+    assert_eq!(tasks[1].note_id, task.note_id);
+    assert_eq!(tasks[1].project_id, task.project_id);
+    assert_eq!(tasks[1].status, "inbox");
+
+    // Saving a task that is already done isn't completing it
+    complete(&conn, &task);
+    assert_eq!(space_tasks(&conn, &space_id).len(), 2);
+}
+
+#[test]
+fn test_task_completed_adds_tag_to_task() {
+    let (conn, space_id) = setup();
+    rule(
+        &conn,
+        &space_id,
+        AutomationTrigger::TaskCompleted,
+        vec![add_tag_action("done/this-week")],
+    );
+    let task = create_task(&conn, Ulid::from_string(&space_id).unwrap(), "Taxes", None).unwrap();
+
+    complete(&conn, &task);
+    assert_eq!(
+        tags_of(&conn, "task", &task.id.to_string()),
+        vec!["done/this-week"]
+    );
+}
+
+#[test]
+fn test_task_completed_moves_task_to_project() {
+    let (conn, space_id) = setup();
+    let archive = create_project(&conn, &space_id, "Archive").unwrap();
+    rule(
+        &conn,
+        &space_id,
+        AutomationTrigger::TaskCompleted,
+        vec![AutomationAction::MoveToProject {
+            project_id: archive.id.clone(),
+        }],
+    );
+    let task = create_task(
+        &conn,
+        Ulid::from_string(&space_id).unwrap(),
+        "Renew passport",
+        None,
+    )
+    .unwrap();
+
+    complete(&conn, &task);
+    let moved = get_task(&conn, task.id).unwrap().unwrap();
+    assert_eq!(moved.project_id.unwrap().to_string(), archive.id);
+    assert_eq!(moved.status, "done");
+}
+
+#[test]
+fn test_task_completed_creates_srs_card_for_its_note() {
+    let (conn, space_id) = setup();
+    rule(
+        &conn,
+        &space_id,
+        AutomationTrigger::TaskCompleted,
+        vec![AutomationAction::CreateSrsCard],
+    );
+    let id = note(&conn, &space_id, "Lecture 4");
+    let mut with_note = create_task(
+        &conn,
+        Ulid::from_string(&space_id).unwrap(),
+        "Read lecture 4",
+        None,
+    )
+    .unwrap();
+    with_note.note_id = Some(Ulid::from_string(&id).unwrap());
+    update_task(&conn, &with_note).unwrap();
+    let without_note = create_task(&conn, with_note.space_id, "Buy stamps", None).unwrap();
+
+    complete(&conn, &with_note);
+    assert_eq!(card_count(&conn, &id), 1);
+
+    // Nothing to study, and no reason to fail the completion
+    complete(&conn, &without_note);
+    let total: i64 = conn
+        .query_row("SELECT COUNT(*) FROM knowledge_card", [], |row| row.get(0))
+        .unwrap();
+    assert_eq!(total, 1);
+}
+
+#[test]
+fn test_task_completed_emits_prompt() {
+    let (conn, space_id) = setup();
+    let prompt = rule(
+        &conn,
+        &space_id,
+        AutomationTrigger::TaskCompleted,
+        vec![prompt_action("Log time for {{title}}?")],
+    );
+    let task = create_task(
+        &conn,
+        Ulid::from_string(&space_id).unwrap(),
+        "Client call",
+        None,
+    )
+    .unwrap();
+
+    let event = expect_prompt(&prompt, || complete(&conn, &task));
+    match event {
+        CoreEvent::AutomationPrompt {
+            message,
+            entity_type,
+            entity_id,
+            ..
+        } => {
+            assert_eq!(message, "Log time for Client call?");
+            assert_eq!(entity_type, "task");
+            assert_eq!(entity_id, task.id.to_string());
+        }
+        other => panic!("unexpected event {:?}", other),
+    }
+}
+
+#[test]
+fn test_note_created_matching_title_creates_task() {
+    let (conn, space_id) = setup();
+    rule(
+        &conn,
+        &space_id,
+        note_created(r"^meeting\b"),
+        vec![create_task_action("Send notes from {{title}}")],
+    );
+
+    let id = note(&conn, &space_id, "Meeting with design team");
+    note(&conn, &space_id, "Notes on a meeting");
+    let tasks = space_tasks(&conn, &space_id);
+    assert_eq!(tasks.len(), 1);
+    assert_eq!(tasks[0].title, "Send notes from Meeting with design team");
+    assert_eq!(tasks[0].note_id.unwrap().to_string(), id);
+}
+
+#[test]
+fn test_note_created_adds_tag() {
+    let (conn, space_id) = setup();
+    rule(
+        &conn,
+        &space_id,
+        note_created("journal"),
+        vec![add_tag_action("journal")],
+    );
+
+    let id = note(&conn, &space_id, "Journal 2026-10-16");
+    assert_eq!(tags_of(&conn, "note", &id), vec!["journal"]);
+}
+
+#[test]
+fn test_note_created_moves_its_new_task_to_project() {
+    let (conn, space_id) = setup();
+    let project = create_project(&conn, &space_id, "Hiring").unwrap();
+    rule(
+        &conn,
+        &space_id,
+        note_created("^candidate:"),
+        vec![
+            create_task_action("Schedule interview"),
+            AutomationAction::MoveToProject {
+                project_id: project.id.clone(),
+            },
+        ],
+    );
+
+    note(&conn, &space_id, "Candidate: Sam Rivera");
+    let tasks = space_tasks(&conn, &space_id);
+    assert_eq!(tasks.len(), 1);
+    assert_eq!(tasks[0].project_id.unwrap().to_string(), project.id);
+}
+
+#[test]
+fn test_note_created_creates_srs_card() {
+    let (conn, space_id) = setup();
+    rule(
+        &conn,
+        &space_id,
+        note_created("^vocab"),
+        vec![AutomationAction::CreateSrsCard],
+    );
+
+    let id = note(&conn, &space_id, "Vocab: la mariposa");
+    assert_eq!(card_count(&conn, &id), 1);
+}
+
+#[test]
+fn test_note_created_emits_prompt() {
+    let (conn, space_id) = setup();
+    let prompt = rule(
+        &conn,
+        &space_id,
+        note_created(""),
+        vec![prompt_action("File {{title}}?")],
+    );
+
+    let event = expect_prompt(&prompt, || {
+        note(&conn, &space_id, "Loose thought");
+    });
+    assert!(matches!(
+        event,
+        CoreEvent::AutomationPrompt { message, entity_type, .. }
+            if message == "File Loose thought?" && entity_type == "note"
+    ));
+}
+
+#[test]
+fn test_rules_adding_each_others_tags_do_not_loop() {
+    let (conn, space_id) = setup();
+    rule(
+        &conn,
+        &space_id,
+        tag_added("a"),
+        vec![add_tag_action("b"), create_task_action("From a")],
+    );
+    rule(
+        &conn,
+        &space_id,
+        tag_added("b"),
+        vec![add_tag_action("a"), create_task_action("From b")],
+    );
+    let id = note(&conn, &space_id, "Ping pong");
+
+    add_tag_to_note(&conn, &id, "a").unwrap();
+    assert_eq!(tags_of(&conn, "note", &id), vec!["a", "b"]);
+    // The tag the first rule added didn't set off the second
+    let titles: Vec<String> = space_tasks(&conn, &space_id)
+        .into_iter()
+        .map(|t| t.title)
+        .collect();
+    assert_eq!(titles, vec!["From a"]);
+
+    let sources: Vec<String> = conn
+        .prepare(
+            "SELECT source FROM audit_log
+             WHERE entity_type = 'note' AND entity_id = ?1 AND operation = 'update'
+             ORDER BY chain_seq",
+        )
+        .unwrap()
+        .query_map([&id], |row| row.get(0))
+        .unwrap()
+        .collect::<Result<_, _>>()
+        .unwrap();
+    assert_eq!(sources, vec!["local", "automation"]);
+}
+
+#[test]
+fn test_disabled_and_deleted_rules_do_not_run() {
+    let (conn, space_id) = setup();
+    let paused = rule(
+        &conn,
+        &space_id,
+        note_created(""),
+        vec![create_task_action("Triage {{title}}")],
+    );
+
+    set_automation_rule_enabled(&conn, &paused.id, false).unwrap();
+    note(&conn, &space_id, "First");
+    assert!(space_tasks(&conn, &space_id).is_empty());
+    assert!(
+        !get_automation_rule(&conn, &paused.id)
+            .unwrap()
+            .unwrap()
+            .enabled
+    );
+
+    set_automation_rule_enabled(&conn, &paused.id, true).unwrap();
+    note(&conn, &space_id, "Second");
+    assert_eq!(space_tasks(&conn, &space_id).len(), 1);
+
+    delete_automation_rule(&conn, &paused.id).unwrap();
+    note(&conn, &space_id, "Third");
+    assert_eq!(space_tasks(&conn, &space_id).len(), 1);
+    assert!(get_automation_rules(&conn, &space_id).unwrap().is_empty());
+    assert!(matches!(
+        delete_automation_rule(&conn, &paused.id),
+        Err(AutomationError::NotFound(_))
+    ));
+}
+
+#[test]
+fn test_imported_notes_do_not_trigger_rules() {
+    let (conn, space_id) = setup();
+    rule(
+        &conn,
+        &space_id,
+        note_created(""),
+        vec![create_task_action("Triage {{title}}")],
+    );
+
+    with_bulk_import(&conn, |conn| {
+        create_note(conn, &space_id, "From Obsidian", "").map(|_| ())
+    })
+    .unwrap();
+    assert!(space_tasks(&conn, &space_id).is_empty());
+}
+
+#[test]
+fn test_failing_rule_is_rolled_back_without_failing_the_change() {
+    let (conn, space_id) = setup();
+    let project = create_project(&conn, &space_id, "Gone soon").unwrap();
+    rule(
+        &conn,
+        &space_id,
+        tag_added("later"),
+        vec![
+            create_task_action("Revisit {{title}}"),
+            AutomationAction::MoveToProject {
+                project_id: project.id.clone(),
+            },
+        ],
+    );
+    conn.execute("DELETE FROM project WHERE id = ?1", [&project.id])
+        .unwrap();
+    let id = note(&conn, &space_id, "Someday");
+
+    assert!(add_tag_to_note(&conn, &id, "later").unwrap());
+    assert_eq!(tags_of(&conn, "note", &id), vec!["later"]);
+    assert!(space_tasks(&conn, &space_id).is_empty());
+
+    let event = AutomationEvent::TagAdded {
+        note_id: id,
+        tag: "later".to_string(),
+    };
+    assert!(run_automation_for_event(&conn, &event).is_err());
+    assert!(space_tasks(&conn, &space_id).is_empty());
+}
+
+#[test]
+fn test_invalid_rules_are_rejected() {
+    let (conn, space_id) = setup();
+    let invalid = |trigger, actions| {
+        matches!(
+            create_automation_rule(&conn, &space_id, "Rule", trigger, actions),
+            Err(AutomationError::InvalidRule(_) | AutomationError::Tag(_))
+        )
+    };
+    assert!(invalid(
+        note_created("(unclosed"),
+        vec![prompt_action("Hi")]
+    ));
+    assert!(invalid(tag_added("a//b"), vec![prompt_action("Hi")]));
+    assert!(invalid(AutomationTrigger::TaskCompleted, vec![]));
+    assert!(invalid(
+        AutomationTrigger::TaskCompleted,
+        vec![create_task_action("  ")]
+    ));
+
+    assert!(matches!(
+        create_automation_rule(
+            &conn,
+            &space_id,
+            "Rule",
+            AutomationTrigger::TaskCompleted,
+            vec![AutomationAction::MoveToProject {
+                project_id: Ulid::new().to_string(),
+            }],
+        ),
+        Err(AutomationError::Db(_))
+    ));
+
+    // Tags are stored the way they're named on notes
+    let saved = rule(
+        &conn,
+        &space_id,
+        tag_added(" #work "),
+        vec![add_tag_action("#todo")],
+    );
+    assert_eq!(saved.trigger, tag_added("work"));
+    assert_eq!(saved.actions, vec![add_tag_action("todo")]);
+    assert_eq!(get_automation_rules(&conn, &space_id).unwrap(), vec![saved]);
+}
@@ -1,5 +1,6 @@
-use core_rs::backlink::{find_backlinks, update_links};
+use core_rs::backlink::{find_backlinks, find_unlinked_mentions, update_links};
 use core_rs::db::migrate;
+use core_rs::note::{self, update_note_content};
 use rusqlite::Connection;
 use ulid::Ulid;
 
@@ -42,3 +43,65 @@ fn test_backlinks() {
     assert_eq!(backlinks.len(), 1);
     assert_eq!(backlinks[0].source_note_id, note2_id);
 }
+
+#[test]
+fn test_links_follow_note_saves() {
+    let mut conn = setup_db();
+    let space_id = create_space(&conn).to_string();
+    let target = note::create_note(&conn, &space_id, "Project Plan", "").unwrap();
+    let other = note::create_note(&conn, &space_id, "Reading List", "").unwrap();
+
+    let source = note::create_note(
+        &conn,
+        &space_id,
+        "Journal",
+        "See [[project plan|the plan]] and [list](Reading%20List.md).",
+    )
+    .unwrap();
+    assert_eq!(find_backlinks(&conn, target.id.0).unwrap().len(), 1);
+    assert_eq!(find_backlinks(&conn, other.id.0).unwrap().len(), 1);
+
+    // Removing a link on save removes the backlink row
+    update_note_content(
+        &mut conn,
+        source.id.clone(),
+        "Journal",
+        "See [[Project Plan#Goals]] only. External [site](https://example.com).",
+    )
+    .unwrap();
+    let backlinks = find_backlinks(&conn, target.id.0).unwrap();
+    assert_eq!(backlinks.len(), 1);
+    assert_eq!(backlinks[0].source_note_id, source.id.0);
+    assert!(find_backlinks(&conn, other.id.0).unwrap().is_empty());
+
+    update_note_content(&mut conn, source.id, "Journal", "No links left").unwrap();
+    assert!(find_backlinks(&conn, target.id.0).unwrap().is_empty());
+}
+
+#[test]
+fn test_unlinked_mentions_skip_code_and_links() {
+    let conn = setup_db();
+    let space_id = create_space(&conn).to_string();
+    let target = note::create_note(&conn, &space_id, "Rust", "").unwrap();
+
+    let content = "I like rust a lot.\n\n```\nfn rust() {}\n```\n\nInline `rust` code, \
+                   [[Rust]] link and trusty tools.";
+    let source = note::create_note(&conn, &space_id, "Languages", content).unwrap();
+    note::create_note(&conn, &space_id, "Unrelated", "Nothing here").unwrap();
+
+    let mentions = find_unlinked_mentions(&conn, target.id.0).unwrap();
+    assert_eq!(mentions.len(), 1);
+    let mention = &mentions[0];
+    assert_eq!(mention.source_note_id, source.id.0.to_string());
+    assert_eq!(mention.source_title, "Languages");
+    assert_eq!(
+        &content[mention.offset..mention.offset + mention.length],
+        "rust"
+    );
+    assert!(mention.context.contains("I like rust a lot."));
+
+    // Unknown notes have no mentions
+    assert!(find_unlinked_mentions(&conn, Ulid::new())
+        .unwrap()
+        .is_empty());
+}
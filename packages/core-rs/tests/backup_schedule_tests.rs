@@ -0,0 +1,214 @@
+use chrono::{DateTime, Duration, NaiveTime, TimeZone, Utc};
+use core_rs::social::backup_schedule::{plan_retention, BackupRunGuard};
+use core_rs::social::{
+    get_backup_runs, run_scheduled_backup, set_backup_policy, should_run_backup, Backup,
+    BackupError, BackupMetadata, BackupPolicy, BackupService, BackupSkipReason,
+    ScheduledBackupOutcome,
+};
+use rusqlite::Connection;
+use std::path::Path;
+use tempfile::tempdir;
+
+fn setup_db() -> Connection {
+    let mut conn = Connection::open_in_memory().unwrap();
+    core_rs::db::migrate(&mut conn).unwrap();
+    conn
+}
+
+fn policy(target_dir: &Path) -> BackupPolicy {
+    BackupPolicy {
+        enabled: true,
+        interval_hours: 24,
+        time_of_day: Some(NaiveTime::from_hms_opt(2, 0, 0).unwrap()),
+        max_count: 7,
+        max_age_days: 21,
+        target_dir: target_dir.to_path_buf(),
+    }
+}
+
+fn at(day: u32, hour: u32, min: u32) -> DateTime<Utc> {
+    Utc.with_ymd_and_hms(2025, 9, day, hour, min, 0).unwrap()
+}
+
+/// Write a backup file as `create_backup` would, with a chosen timestamp
+fn write_backup(dir: &Path, id: &str, created_at: DateTime<Utc>) {
+    let backup = Backup {
+        metadata: BackupMetadata {
+            created_at: created_at.to_rfc3339(),
+            schema_version: 1,
+            app_version: "test".to_string(),
+            size_bytes: 3,
+            checksum: "unchecked".to_string(),
+            description: None,
+            encrypted: true,
+        },
+        data: vec![1, 2, 3],
+    };
+    std::fs::write(
+        dir.join(format!("{}.json.enc", id)),
+        serde_json::to_vec(&backup).unwrap(),
+    )
+    .unwrap();
+}
+
+#[test]
+fn test_retention_over_simulated_month() {
+    let policy = policy(Path::new("unused"));
+    let mut kept: Vec<(String, DateTime<Utc>)> = Vec::new();
+
+    // One backup a day through September 2025 (the 1st is a Monday), with
+    // retention applied after each run like the scheduler does
+    for day in 1..=30 {
+        let now = at(day, 2, 0);
+        kept.push((format!("sep{:02}", day), now));
+        let (keep, prune) = plan_retention(&policy, &kept, now);
+        assert_eq!(keep.len() + prune.len(), kept.len());
+        assert!(keep.len() >= policy.max_count.min(kept.len()));
+        kept.retain(|(id, _)| keep.contains(id));
+    }
+
+    let mut ids: Vec<&str> = kept.iter().map(|(id, _)| id.as_str()).collect();
+    ids.sort();
+    // The newest seven, plus the last backup of each earlier week that is
+    // within 21 days; the week of the 22nd is covered by the newest seven
+    // and the week of the 1st has aged out
+    assert_eq!(
+        ids,
+        vec!["sep14", "sep21", "sep24", "sep25", "sep26", "sep27", "sep28", "sep29", "sep30"]
+    );
+
+    // Count-limited backups survive even when everything is old
+    let (keep, prune) = plan_retention(&policy, &kept, at(30, 2, 0) + Duration::days(365));
+    assert_eq!(keep.len(), policy.max_count);
+    assert_eq!(prune, vec!["sep21", "sep14"]);
+}
+
+#[test]
+fn test_should_run_backup_follows_schedule() {
+    let dir = tempdir().unwrap();
+    let conn = setup_db();
+    assert!(!should_run_backup(&conn, at(1, 3, 0)).unwrap());
+
+    let mut policy = policy(dir.path());
+    set_backup_policy(&conn, &policy).unwrap();
+    assert!(!should_run_backup(&conn, at(1, 1, 0)).unwrap());
+    assert!(should_run_backup(&conn, at(1, 2, 30)).unwrap());
+
+    let dek = [3u8; 32];
+    let outcome = run_scheduled_backup(&conn, Some(&dek), &policy, at(1, 2, 30)).unwrap();
+    assert!(matches!(outcome, ScheduledBackupOutcome::Completed { .. }));
+    assert!(!should_run_backup(&conn, at(1, 10, 0)).unwrap());
+    assert!(!should_run_backup(&conn, at(2, 1, 59)).unwrap());
+    assert!(should_run_backup(&conn, at(2, 2, 0)).unwrap());
+
+    policy.enabled = false;
+    set_backup_policy(&conn, &policy).unwrap();
+    assert!(!should_run_backup(&conn, at(2, 2, 0)).unwrap());
+
+    policy.max_count = 0;
+    assert!(matches!(
+        set_backup_policy(&conn, &policy),
+        Err(BackupError::InvalidPolicy(_))
+    ));
+}
+
+#[test]
+fn test_skipped_runs_are_recorded() {
+    let dir = tempdir().unwrap();
+    let conn = setup_db();
+    let policy = policy(dir.path());
+    set_backup_policy(&conn, &policy).unwrap();
+
+    let outcome = run_scheduled_backup(&conn, None, &policy, at(1, 2, 0)).unwrap();
+    assert_eq!(
+        outcome,
+        ScheduledBackupOutcome::Skipped {
+            reason: BackupSkipReason::VaultLocked
+        }
+    );
+    // Retried after a pause, not on every tick
+    assert!(!should_run_backup(&conn, at(1, 2, 10)).unwrap());
+    assert!(should_run_backup(&conn, at(1, 2, 15)).unwrap());
+
+    let dek = [3u8; 32];
+    {
+        let _manual = BackupRunGuard::try_acquire(dir.path()).unwrap();
+        let outcome = run_scheduled_backup(&conn, Some(&dek), &policy, at(1, 2, 15)).unwrap();
+        assert_eq!(
+            outcome,
+            ScheduledBackupOutcome::Skipped {
+                reason: BackupSkipReason::AlreadyRunning
+            }
+        );
+    }
+    assert!(BackupRunGuard::try_acquire(dir.path()).is_some());
+
+    let runs = get_backup_runs(&conn, 10).unwrap();
+    let reasons: Vec<(&str, Option<&str>)> = runs
+        .iter()
+        .map(|run| (run.status.as_str(), run.reason.as_deref()))
+        .collect();
+    assert_eq!(
+        reasons,
+        vec![
+            ("skipped", Some("already_running")),
+            ("skipped", Some("vault_locked"))
+        ]
+    );
+    assert!(BackupService::new(dir.path())
+        .unwrap()
+        .list_backups()
+        .unwrap()
+        .is_empty());
+}
+
+#[test]
+fn test_scheduled_backup_verifies_and_prunes() {
+    let dir = tempdir().unwrap();
+    let conn = setup_db();
+    let mut policy = policy(dir.path());
+    policy.max_count = 2;
+    policy.max_age_days = 30;
+
+    let now = Utc::now();
+    write_backup(dir.path(), "backup_old_1", now - Duration::days(40));
+    write_backup(dir.path(), "backup_old_2", now - Duration::days(39));
+    write_backup(dir.path(), "backup_recent", now - Duration::days(1));
+
+    let dek = [3u8; 32];
+    let backup_id = match run_scheduled_backup(&conn, Some(&dek), &policy, now).unwrap() {
+        ScheduledBackupOutcome::Completed { backup_id, pruned } => {
+            let mut pruned = pruned;
+            pruned.sort();
+            assert_eq!(pruned, vec!["backup_old_1", "backup_old_2"]);
+            backup_id
+        }
+        other => panic!("expected a backup, got {:?}", other),
+    };
+
+    let service = BackupService::new(dir.path()).unwrap();
+    let ids: Vec<String> = service
+        .list_backups()
+        .unwrap()
+        .into_iter()
+        .map(|(id, _)| id)
+        .collect();
+    assert_eq!(ids, vec![backup_id.clone(), "backup_recent".to_string()]);
+    let metadata = service.verify_backup(&backup_id).unwrap();
+    assert_eq!(metadata.description.as_deref(), Some("scheduled"));
+
+    let runs = get_backup_runs(&conn, 10).unwrap();
+    assert_eq!(runs[0].status, "completed");
+    assert_eq!(runs[0].backup_id.as_deref(), Some(backup_id.as_str()));
+    assert_eq!(runs[0].pruned_count, 2);
+
+    // A flipped byte in the archive no longer matches the recorded checksum
+    let path = dir.path().join(format!("{}.json.enc", backup_id));
+    let mut backup: Backup = serde_json::from_slice(&std::fs::read(&path).unwrap()).unwrap();
+    backup.data[0] ^= 0xff;
+    std::fs::write(&path, serde_json::to_vec(&backup).unwrap()).unwrap();
+    assert!(matches!(
+        service.verify_backup(&backup_id),
+        Err(BackupError::BackupCorrupted)
+    ));
+}
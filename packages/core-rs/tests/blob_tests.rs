@@ -45,3 +45,209 @@ fn test_blob_chunking_large_payload() {
     assert_eq!(chunk0.len(), 4096);
     assert_eq!(chunk0[0..10], content[0..10]);
 }
+
+// --- Deduplication and garbage collection ---
+
+use core_rs::blob::{
+    add_blob_ref, attach_blob_to_note, gc_unreferenced_blobs, get_blob_ref_count, insert_blob,
+    remove_blob_ref, sweep_blob_objects, BlobOwner,
+};
+use rusqlite::Connection;
+
+const MK: &[u8] = b"test-master-key-that-is-32-bytes";
+
+fn setup_db(dir: &tempfile::TempDir) -> Connection {
+    let mut conn = Connection::open(dir.path().join("test.db")).unwrap();
+    conn.pragma_update(None, "foreign_keys", "ON").unwrap();
+    core_rs::db::migrate(&mut conn).unwrap();
+    conn
+}
+
+fn far_future() -> i64 {
+    chrono::Utc::now().timestamp() + 3600
+}
+
+#[test]
+fn test_insert_blob_deduplicates_identical_content() {
+    let dir = tempdir().unwrap();
+    let conn = setup_db(&dir);
+    let vault = dir.path().to_str().unwrap();
+
+    let first = insert_blob(&conn, vault, MK, b"same bytes", Some("text/plain")).unwrap();
+    let second = insert_blob(&conn, vault, MK, b"same bytes", Some("text/plain")).unwrap();
+    let other = insert_blob(&conn, vault, MK, b"other bytes", None).unwrap();
+
+    assert_eq!(first, second);
+    assert_ne!(first, other);
+    let rows: i64 = conn
+        .query_row("SELECT COUNT(*) FROM blob", [], |row| row.get(0))
+        .unwrap();
+    assert_eq!(rows, 2);
+    assert_eq!(retrieve_blob(vault, MK, &first).unwrap(), b"same bytes");
+}
+
+#[test]
+fn test_reused_blob_is_not_collected_before_it_is_referenced() {
+    let dir = tempdir().unwrap();
+    let conn = setup_db(&dir);
+    let vault = dir.path().to_str().unwrap();
+
+    let blob_id = insert_blob(&conn, vault, MK, b"uploaded twice", None).unwrap();
+    conn.execute(
+        "UPDATE blob SET created_at = created_at - 2 * 86400 WHERE id = ?1",
+        [&blob_id],
+    )
+    .unwrap();
+
+    // The second upload reuses the old orphan; a GC between it and the
+    // caller adding its reference must leave it alone
+    let again = insert_blob(&conn, vault, MK, b"uploaded twice", None).unwrap();
+    assert_eq!(again, blob_id);
+    let cutoff = chrono::Utc::now().timestamp() - 86400;
+    assert_eq!(
+        gc_unreferenced_blobs(&conn, cutoff).unwrap().blobs_removed,
+        0
+    );
+    add_blob_ref(&conn, &again, BlobOwner::Note, "note-1").unwrap();
+    assert_eq!(get_blob_ref_count(&conn, &again).unwrap(), 1);
+}
+
+#[test]
+fn test_gc_honors_references_across_spaces() {
+    let dir = tempdir().unwrap();
+    let mut conn = setup_db(&dir);
+    let vault = dir.path().to_str().unwrap();
+
+    let space_a = core_rs::space::create_space(&mut conn, "A").unwrap();
+    let space_b = core_rs::space::create_space(&mut conn, "B").unwrap();
+    let note_a = core_rs::note::create_note(&conn, &space_a.to_string(), "a", "").unwrap();
+    let note_b = core_rs::note::create_note(&conn, &space_b.to_string(), "b", "").unwrap();
+
+    let shared = insert_blob(&conn, vault, MK, b"shared attachment", None).unwrap();
+    let orphan = insert_blob(&conn, vault, MK, b"never attached", None).unwrap();
+    attach_blob_to_note(&conn, &note_a.id.to_string(), &shared, None).unwrap();
+    attach_blob_to_note(&conn, &note_b.id.to_string(), &shared, None).unwrap();
+    assert_eq!(get_blob_ref_count(&conn, &shared).unwrap(), 2);
+
+    // Blobs newer than the cutoff are never collected
+    let result = gc_unreferenced_blobs(&conn, 0).unwrap();
+    assert_eq!(result.blobs_removed, 0);
+
+    let result = gc_unreferenced_blobs(&conn, far_future()).unwrap();
+    assert_eq!(result.blob_ids, vec![orphan.clone()]);
+    assert_eq!(result.bytes_reclaimed, b"never attached".len() as i64);
+
+    // Deleting one space's note keeps the blob alive for the other
+    conn.execute(
+        "DELETE FROM note_attachment WHERE note_id = ?1",
+        [note_a.id.to_string()],
+    )
+    .unwrap();
+    assert_eq!(
+        gc_unreferenced_blobs(&conn, far_future())
+            .unwrap()
+            .blobs_removed,
+        0
+    );
+
+    conn.execute(
+        "DELETE FROM note_attachment WHERE note_id = ?1",
+        [note_b.id.to_string()],
+    )
+    .unwrap();
+    let result = gc_unreferenced_blobs(&conn, far_future()).unwrap();
+    assert_eq!(result.blob_ids, vec![shared]);
+}
+
+#[test]
+fn test_gc_keeps_social_refs_and_pending_ocr() {
+    let dir = tempdir().unwrap();
+    let conn = setup_db(&dir);
+    let vault = dir.path().to_str().unwrap();
+
+    let media = insert_blob(&conn, vault, MK, b"post media", None).unwrap();
+    let scan = insert_blob(&conn, vault, MK, b"scan to ocr", None).unwrap();
+    add_blob_ref(&conn, &media, BlobOwner::SocialPost, "post-1").unwrap();
+    core_rs::ocr::queue_ocr(&conn, &scan, &[]).unwrap();
+
+    assert_eq!(
+        gc_unreferenced_blobs(&conn, far_future())
+            .unwrap()
+            .blobs_removed,
+        0
+    );
+
+    remove_blob_ref(&conn, &media, BlobOwner::SocialPost, "post-1").unwrap();
+    conn.execute(
+        "UPDATE ocr_result SET status = 'completed' WHERE blob_id = ?1",
+        [&scan],
+    )
+    .unwrap();
+    let result = gc_unreferenced_blobs(&conn, far_future()).unwrap();
+    assert_eq!(result.blobs_removed, 2);
+    assert!(core_rs::ocr::get_ocr_status(&conn, &scan)
+        .unwrap()
+        .is_none());
+}
+
+#[test]
+fn test_sweep_removes_objects_but_keeps_shared_chunks() {
+    let dir = tempdir().unwrap();
+    let conn = setup_db(&dir);
+    let vault = dir.path().to_str().unwrap();
+
+    // Both payloads share their first 4KB chunk
+    let mut keep: Vec<u8> = vec![7u8; 4096];
+    keep.extend_from_slice(b"tail that stays");
+    let mut drop: Vec<u8> = vec![7u8; 4096];
+    drop.extend_from_slice(b"tail that goes");
+
+    let kept = insert_blob(&conn, vault, MK, &keep, None).unwrap();
+    let dropped = insert_blob(&conn, vault, MK, &drop, None).unwrap();
+    add_blob_ref(&conn, &kept, BlobOwner::Note, "note-1").unwrap();
+
+    gc_unreferenced_blobs(&conn, far_future()).unwrap();
+    let sweep = sweep_blob_objects(&conn, vault).unwrap();
+    // The dropped manifest and its unique tail chunk
+    assert_eq!(sweep.objects_removed, 2);
+
+    assert_eq!(retrieve_blob(vault, MK, &kept).unwrap(), keep);
+    assert!(retrieve_blob(vault, MK, &dropped).is_err());
+
+    // Nothing left to sweep
+    assert_eq!(sweep_blob_objects(&conn, vault).unwrap().objects_removed, 0);
+}
+
+#[test]
+fn test_reinserted_blob_survives_pending_sweep() {
+    let dir = tempdir().unwrap();
+    let conn = setup_db(&dir);
+    let vault = dir.path().to_str().unwrap();
+
+    let blob_id = insert_blob(&conn, vault, MK, b"comes back", None).unwrap();
+    gc_unreferenced_blobs(&conn, far_future()).unwrap();
+
+    let again = insert_blob(&conn, vault, MK, b"comes back", None).unwrap();
+    assert_eq!(again, blob_id);
+    sweep_blob_objects(&conn, vault).unwrap();
+    assert_eq!(retrieve_blob(vault, MK, &blob_id).unwrap(), b"comes back");
+}
+
+#[test]
+fn test_startup_maintenance_collects_blobs() {
+    let dir = tempdir().unwrap();
+    let mut conn = setup_db(&dir);
+    let vault = dir.path().to_str().unwrap();
+
+    let blob_id = insert_blob(&conn, vault, MK, b"old orphan", None).unwrap();
+    conn.execute(
+        "UPDATE blob SET created_at = created_at - 2 * 86400 WHERE id = ?1",
+        [&blob_id],
+    )
+    .unwrap();
+    insert_blob(&conn, vault, MK, b"fresh orphan", None).unwrap();
+
+    let result = core_rs::social::run_startup_maintenance(&mut conn, 7).unwrap();
+    assert_eq!(result.blobs_collected, 1);
+    assert_eq!(result.blob_bytes_reclaimed, b"old orphan".len() as i64);
+}
@@ -0,0 +1,384 @@
+use core_rs::audit::{get_audit_trail, verify_audit_chain, AuditOperation};
+use core_rs::bulk_edit::*;
+use core_rs::db::{
+    apply_query_stats_settings, get_query_stats, instrument_connection, migrate, reset_query_stats,
+    QueryStatsSettings,
+};
+use core_rs::error::{codes, CoreError};
+use core_rs::note::{create_note, trash_note, DbUlid};
+use core_rs::note_share::{TagAction, TimeEntryAction};
+use core_rs::project::create_project;
+use core_rs::task::{create_task, get_task, update_task};
+use rusqlite::Connection;
+use ulid::Ulid;
+
+fn setup() -> (Connection, String, String) {
+    let mut conn = Connection::open_in_memory().unwrap();
+    conn.pragma_update(None, "foreign_keys", "ON").unwrap();
+    migrate(&mut conn).unwrap();
+    let work = core_rs::space::create_space(&mut conn, "Work").unwrap();
+    let home = core_rs::space::create_space(&mut conn, "Home").unwrap();
+    (conn, work.to_string(), home.to_string())
+}
+
+fn note(conn: &Connection, space_id: &str, title: &str) -> String {
+    create_note(conn, space_id, title, "")
+        .unwrap()
+        .id
+        .0
+        .to_string()
+}
+
+fn task(conn: &Connection, space_id: &str, title: &str) -> String {
+    let space = Ulid::from_string(space_id).unwrap();
+    create_task(conn, space, title, None)
+        .unwrap()
+        .id
+        .to_string()
+}
+
+fn tags_of(conn: &Connection, note_id: &str) -> Vec<String> {
+    let mut stmt = conn
+        .prepare(
+            "SELECT t.name FROM note_tags nt JOIN tag t ON t.id = nt.tag_id
+             WHERE nt.note_id = ?1 ORDER BY t.name",
+        )
+        .unwrap();
+    let names = stmt
+        .query_map([note_id], |row| row.get(0))
+        .unwrap()
+        .collect::<Result<Vec<String>, _>>()
+        .unwrap();
+    names
+}
+
+fn is_trashed(conn: &Connection, note_id: &str) -> bool {
+    conn.query_row(
+        "SELECT is_trashed FROM note WHERE id = ?1",
+        [note_id],
+        |row| row.get(0),
+    )
+    .unwrap()
+}
+
+fn skipped(id: &str, reason: SkipReason) -> BulkItemResult {
+    BulkItemResult {
+        id: id.to_string(),
+        outcome: BulkOutcome::Skipped { reason },
+    }
+}
+
+fn succeeded(id: &str) -> BulkItemResult {
+    BulkItemResult {
+        id: id.to_string(),
+        outcome: BulkOutcome::Succeeded,
+    }
+}
+
+fn strings(ids: &[&str]) -> Vec<String> {
+    ids.iter().map(|id| id.to_string()).collect()
+}
+
+#[test]
+fn test_each_item_is_reported_and_bad_ids_dont_stop_the_rest() {
+    let (conn, work, home) = setup();
+    let plan = note(&conn, &work, "Plan");
+    let budget = note(&conn, &home, "Budget");
+    let old = note(&conn, &work, "Old");
+    trash_note(&conn, DbUlid(Ulid::from_string(&old).unwrap())).unwrap();
+    let missing = Ulid::new().to_string();
+    core_rs::tag::add_tag_to_note(&conn, &plan, "draft").unwrap();
+
+    let ids = strings(&[&plan, &missing, &budget, &old, &plan]);
+    let results = bulk_tag_notes(
+        &conn,
+        &ids,
+        &strings(&["Review", "work/q3"]),
+        &strings(&["draft"]),
+        false,
+    )
+    .unwrap();
+    assert_eq!(
+        results,
+        vec![
+            succeeded(&plan),
+            skipped(&missing, SkipReason::NotFound),
+            succeeded(&budget),
+            skipped(&old, SkipReason::Trashed),
+            skipped(&plan, SkipReason::Duplicate),
+        ]
+    );
+    assert_eq!(tags_of(&conn, &plan), ["Review", "work/q3"]);
+    assert_eq!(tags_of(&conn, &budget), ["Review", "work/q3"]);
+    assert!(tags_of(&conn, &old).is_empty());
+
+    // Tags are created in each note's own space
+    let home_tags: i64 = conn
+        .query_row(
+            "SELECT COUNT(*) FROM tag WHERE space_id = ?1",
+            [&home],
+            |row| row.get(0),
+        )
+        .unwrap();
+    assert_eq!(home_tags, 2);
+
+    // One audit event per edited note, and the chains stay intact
+    let trail = get_audit_trail(&conn, &budget).unwrap();
+    assert_eq!(trail.last().unwrap().operation, AuditOperation::Update);
+    for space_id in [&work, &home] {
+        assert!(verify_audit_chain(&conn, space_id).unwrap().valid);
+    }
+
+    // Input that can't apply to any item fails the whole call
+    assert!(matches!(
+        bulk_tag_notes(&conn, &ids, &strings(&["a"]), &strings(&["#a"]), false),
+        Err(BulkError::Invalid(_))
+    ));
+    assert!(matches!(
+        bulk_tag_notes(&conn, &ids, &[], &[], false),
+        Err(BulkError::Invalid(_))
+    ));
+}
+
+#[test]
+fn test_all_or_nothing_writes_nothing_when_an_item_is_skipped() {
+    let (conn, work, _home) = setup();
+    let a = note(&conn, &work, "A");
+    let b = note(&conn, &work, "B");
+    let missing = Ulid::new().to_string();
+    let ids = strings(&[&a, &missing, &b]);
+
+    let err = bulk_trash_notes(&conn, &ids, true).unwrap_err();
+    match &err {
+        BulkError::Incomplete { skipped: items } => {
+            assert_eq!(items, &vec![skipped(&missing, SkipReason::NotFound)]);
+        }
+        other => panic!("expected Incomplete, got {:?}", other),
+    }
+    assert_eq!(CoreError::from(err).code, codes::BULK_INCOMPLETE);
+    assert!(!is_trashed(&conn, &a));
+    assert!(!is_trashed(&conn, &b));
+
+    // Without the flag the valid notes are trashed
+    let results = bulk_trash_notes(&conn, &ids, false).unwrap();
+    assert_eq!(results.iter().filter(|r| r.succeeded()).count(), 2);
+    assert!(is_trashed(&conn, &a));
+    assert!(is_trashed(&conn, &b));
+    assert_eq!(
+        get_audit_trail(&conn, &a)
+            .unwrap()
+            .last()
+            .unwrap()
+            .operation,
+        AuditOperation::Delete
+    );
+
+    // A note already in the trash holds the flag back too; a selection
+    // that is all valid goes through
+    let results = bulk_trash_notes(&conn, &strings(&[&a]), true);
+    assert!(matches!(results, Err(BulkError::Incomplete { .. })));
+    let c = note(&conn, &work, "C");
+    assert_eq!(
+        bulk_trash_notes(&conn, &strings(&[&c]), true).unwrap(),
+        vec![succeeded(&c)]
+    );
+}
+
+#[test]
+fn test_task_patch_sets_fields_and_completes_tasks() {
+    let (conn, work, home) = setup();
+    let project = create_project(&conn, &work, "Launch").unwrap();
+    let write = task(&conn, &work, "Write");
+    let review = task(&conn, &work, "Review");
+    let groceries = task(&conn, &home, "Groceries");
+
+    let mut standup = get_task(&conn, Ulid::from_string(&review).unwrap())
+        .unwrap()
+        .unwrap();
+    standup.recur_rule = Some("DAILY".to_string());
+    standup.due_at = Some(1_700_000_000);
+    update_task(&conn, &standup).unwrap();
+
+    let patch = TaskPatch {
+        status: Some("done".to_string()),
+        priority: Some(PatchValue::Set(1)),
+        project_id: Some(PatchValue::Set(project.id.clone())),
+        due_at: None,
+    };
+    let results = bulk_update_tasks(
+        &conn,
+        &strings(&[&write, &review, &groceries]),
+        &patch,
+        false,
+    )
+    .unwrap();
+    assert_eq!(
+        results,
+        vec![
+            succeeded(&write),
+            succeeded(&review),
+            skipped(&groceries, SkipReason::ProjectInOtherSpace),
+        ]
+    );
+
+    let written = get_task(&conn, Ulid::from_string(&write).unwrap())
+        .unwrap()
+        .unwrap();
+    assert_eq!(written.status, "done");
+    assert_eq!(written.priority, Some(1));
+    assert_eq!(written.project_id.unwrap().to_string(), project.id);
+    assert!(written.completed_at.is_some());
+    let untouched = get_task(&conn, Ulid::from_string(&groceries).unwrap())
+        .unwrap()
+        .unwrap();
+    assert_eq!(untouched.status, "inbox");
+
+    // The recurring task got its next occurrence
+    let next: i64 = conn
+        .query_row(
+            "SELECT COUNT(*) FROM task WHERE parent_task_id = ?1",
+            [&review],
+            |row| row.get(0),
+        )
+        .unwrap();
+    assert_eq!(next, 1);
+
+    // Clearing fields
+    let patch = TaskPatch {
+        priority: Some(PatchValue::Clear),
+        project_id: Some(PatchValue::Clear),
+        ..Default::default()
+    };
+    bulk_update_tasks(&conn, &strings(&[&write]), &patch, true).unwrap();
+    let cleared = get_task(&conn, Ulid::from_string(&write).unwrap())
+        .unwrap()
+        .unwrap();
+    assert_eq!(cleared.priority, None);
+    assert_eq!(cleared.project_id, None);
+    assert_eq!(cleared.status, "done");
+
+    let bad_status = TaskPatch {
+        status: Some("someday".to_string()),
+        ..Default::default()
+    };
+    assert!(matches!(
+        bulk_update_tasks(&conn, &strings(&[&write]), &bad_status, false),
+        Err(BulkError::Invalid(_))
+    ));
+    assert!(matches!(
+        bulk_update_tasks(&conn, &strings(&[&write]), &TaskPatch::default(), false),
+        Err(BulkError::Invalid(_))
+    ));
+}
+
+#[test]
+fn test_notes_move_from_several_spaces() {
+    let (mut conn, work, home) = setup();
+    let archive = core_rs::space::create_space(&mut conn, "Archive")
+        .unwrap()
+        .to_string();
+    let a = note(&conn, &work, "A");
+    let b = note(&conn, &home, "B");
+    let c = note(&conn, &archive, "C");
+    core_rs::tag::add_tag_to_note(&conn, &a, "keep").unwrap();
+
+    let results = bulk_move_notes_to_space(
+        &conn,
+        &strings(&[&a, &b, &c]),
+        &archive,
+        TagAction::Map,
+        TimeEntryAction::Keep,
+        false,
+    )
+    .unwrap();
+    assert_eq!(
+        results,
+        vec![
+            succeeded(&a),
+            succeeded(&b),
+            skipped(&c, SkipReason::AlreadyInSpace),
+        ]
+    );
+    for id in [&a, &b, &c] {
+        let space: String = conn
+            .query_row("SELECT space_id FROM note WHERE id = ?1", [id], |row| {
+                row.get(0)
+            })
+            .unwrap();
+        assert_eq!(space, archive);
+    }
+    assert_eq!(tags_of(&conn, &a), ["keep"]);
+    let moves: i64 = conn
+        .query_row("SELECT COUNT(*) FROM note_move", [], |row| row.get(0))
+        .unwrap();
+    assert_eq!(moves, 2);
+
+    assert!(bulk_move_notes_to_space(
+        &conn,
+        &strings(&[&a]),
+        &Ulid::new().to_string(),
+        TagAction::Map,
+        TimeEntryAction::Keep,
+        false,
+    )
+    .is_err());
+}
+
+#[test]
+fn test_large_batches_take_a_bounded_number_of_statements() {
+    let (conn, work, _home) = setup();
+    let tasks: Vec<String> = (0..500)
+        .map(|i| task(&conn, &work, &format!("Task {}", i)))
+        .collect();
+    let notes: Vec<String> = (0..500)
+        .map(|i| note(&conn, &work, &format!("Note {}", i)))
+        .collect();
+
+    // Only this test's connection reports to the registry
+    instrument_connection(&conn);
+    apply_query_stats_settings(&QueryStatsSettings {
+        enabled: true,
+        ..Default::default()
+    });
+    let statements = |f: &dyn Fn()| {
+        reset_query_stats();
+        f();
+        get_query_stats().iter().map(|stat| stat.count).sum::<u64>()
+    };
+
+    let patch = TaskPatch {
+        status: Some("next".to_string()),
+        due_at: Some(PatchValue::Set(1_800_000_000)),
+        ..Default::default()
+    };
+    let task_statements = statements(&|| {
+        let results = bulk_update_tasks(&conn, &tasks, &patch, true).unwrap();
+        assert!(results.iter().all(BulkItemResult::succeeded));
+    });
+    let tag_statements = statements(&|| {
+        let results = bulk_tag_notes(&conn, &notes, &strings(&["done"]), &[], true).unwrap();
+        assert!(results.iter().all(BulkItemResult::succeeded));
+    });
+    let note_statements = statements(&|| {
+        let results = bulk_trash_notes(&conn, &notes, true).unwrap();
+        assert!(results.iter().all(BulkItemResult::succeeded));
+    });
+    apply_query_stats_settings(&QueryStatsSettings::default());
+
+    // Three chunks of ids and ten batches of audit events each, against
+    // several statements per item when edited one by one
+    for count in [task_statements, tag_statements, note_statements] {
+        assert!(count > 0);
+        assert!(count <= 40, "{} statements for 500 items", count);
+    }
+    assert_eq!(
+        conn.query_row(
+            "SELECT COUNT(*) FROM task WHERE status = 'next' AND due_at = 1800000000",
+            [],
+            |row| row.get::<_, i64>(0),
+        )
+        .unwrap(),
+        500
+    );
+    assert!(verify_audit_chain(&conn, &work).unwrap().valid);
+}
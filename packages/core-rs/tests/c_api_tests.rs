@@ -0,0 +1,255 @@
+use core_rs::c_api::*;
+use std::ffi::{CStr, CString};
+use std::os::raw::c_char;
+use tempfile::tempdir;
+
+fn c(s: &str) -> CString {
+    CString::new(s).unwrap()
+}
+
+/// Read and free a result: the value on success, the code and message
+/// otherwise
+fn take(result: NoteeceResult) -> Result<Option<String>, (i32, String)> {
+    let read = |ptr: *mut c_char| {
+        (!ptr.is_null()).then(|| unsafe { CStr::from_ptr(ptr) }.to_str().unwrap().to_string())
+    };
+    let outcome = if result.code == NOTEECE_OK {
+        assert!(result.message.is_null());
+        Ok(read(result.value))
+    } else {
+        assert!(result.value.is_null());
+        Err((result.code, read(result.message).unwrap()))
+    };
+    assert_eq!(unsafe { noteece_result_free(result) }, NOTEECE_OK);
+    outcome
+}
+
+fn ok(result: NoteeceResult) -> String {
+    take(result).unwrap().unwrap_or_default()
+}
+
+fn code(result: NoteeceResult) -> i32 {
+    take(result).unwrap_err().0
+}
+
+fn json(result: NoteeceResult) -> serde_json::Value {
+    serde_json::from_str(&ok(result)).unwrap()
+}
+
+fn create(dir: &std::path::Path, password: &str) -> NoteeceHandle {
+    let path = c(dir.to_str().unwrap());
+    let mut handle = 0;
+    ok(unsafe { noteece_vault_create(path.as_ptr(), c(password).as_ptr(), &mut handle) });
+    assert_ne!(handle, 0);
+    handle
+}
+
+fn space(handle: NoteeceHandle) -> CString {
+    c(&ok(unsafe {
+        noteece_space_create(handle, c("Mobile").as_ptr())
+    }))
+}
+
+#[test]
+fn test_notes_round_trip_across_unlock() {
+    let dir = tempdir().unwrap();
+    let handle = create(dir.path(), "correct horse");
+    let space_id = space(handle);
+
+    let note = json(unsafe {
+        noteece_note_create(
+            handle,
+            space_id.as_ptr(),
+            c("Groceries").as_ptr(),
+            c("Buy oat milk").as_ptr(),
+        )
+    });
+    let note_id = c(note["id"].as_str().unwrap());
+    ok(unsafe {
+        noteece_note_update(
+            handle,
+            note_id.as_ptr(),
+            c("Groceries").as_ptr(),
+            c("Buy oat milk and bread ☕").as_ptr(),
+        )
+    });
+    ok(noteece_vault_close(handle));
+
+    let path = c(dir.path().to_str().unwrap());
+    let mut handle = 0;
+    ok(unsafe { noteece_vault_unlock(path.as_ptr(), c("correct horse").as_ptr(), &mut handle) });
+    let fetched = json(unsafe { noteece_note_get(handle, note_id.as_ptr()) });
+    assert_eq!(fetched["content_md"], "Buy oat milk and bread ☕");
+
+    let found =
+        json(unsafe { noteece_note_search(handle, space_id.as_ptr(), c("bread").as_ptr()) });
+    assert_eq!(found.as_array().unwrap().len(), 1);
+    assert_eq!(found[0]["id"], note["id"]);
+
+    let missing = c(&ulid::Ulid::new().to_string());
+    assert_eq!(
+        code(unsafe { noteece_note_get(handle, missing.as_ptr()) }),
+        NOTEECE_NOT_FOUND
+    );
+    assert_eq!(
+        code(unsafe { noteece_note_get(handle, c("not-an-id").as_ptr()) }),
+        NOTEECE_INVALID_ARGUMENT
+    );
+    ok(noteece_vault_close(handle));
+}
+
+#[test]
+fn test_task_crud() {
+    let dir = tempdir().unwrap();
+    let handle = create(dir.path(), "pw");
+    let space_id = space(handle);
+
+    let mut task = json(unsafe {
+        noteece_task_create(
+            handle,
+            space_id.as_ptr(),
+            c("Call the bank").as_ptr(),
+            std::ptr::null(),
+        )
+    });
+    assert!(task["description"].is_null());
+    let task_id = c(task["id"].as_str().unwrap());
+
+    task["status"] = "done".into();
+    let task_json = c(&task.to_string());
+    ok(unsafe { noteece_task_update(handle, task_json.as_ptr()) });
+    let fetched = json(unsafe { noteece_task_get(handle, task_id.as_ptr()) });
+    assert_eq!(fetched["status"], "done");
+
+    let listed = json(unsafe { noteece_task_list(handle, space_id.as_ptr()) });
+    assert_eq!(listed.as_array().unwrap().len(), 1);
+
+    ok(unsafe { noteece_task_delete(handle, task_id.as_ptr()) });
+    assert_eq!(
+        code(unsafe { noteece_task_get(handle, task_id.as_ptr()) }),
+        NOTEECE_NOT_FOUND
+    );
+    assert_eq!(
+        code(unsafe { noteece_task_update(handle, task_json.as_ptr()) }),
+        NOTEECE_NOT_FOUND
+    );
+    assert_eq!(
+        code(unsafe { noteece_task_update(handle, c("{\"id\":").as_ptr()) }),
+        NOTEECE_INVALID_ARGUMENT
+    );
+    ok(noteece_vault_close(handle));
+}
+
+#[test]
+fn test_sync_exchange() {
+    let dir = tempdir().unwrap();
+    let handle = create(dir.path(), "pw");
+    let space_id = space(handle);
+    let note = json(unsafe {
+        noteece_note_create(
+            handle,
+            space_id.as_ptr(),
+            c("Synced").as_ptr(),
+            c("Hello").as_ptr(),
+        )
+    });
+
+    let manifest = json(unsafe { noteece_sync_manifest(handle, space_id.as_ptr()) });
+    assert_eq!(manifest["space_id"], space_id.to_str().unwrap());
+
+    let deltas = ok(unsafe { noteece_sync_deltas_since(handle, space_id.as_ptr(), 0) });
+    assert!(deltas.contains(note["id"].as_str().unwrap()));
+
+    let conflicts =
+        json(unsafe { noteece_sync_apply_deltas(handle, c("peer").as_ptr(), c("[]").as_ptr()) });
+    assert_eq!(conflicts, serde_json::json!([]));
+    assert_eq!(
+        code(unsafe { noteece_sync_apply_deltas(handle, c("peer").as_ptr(), c("nope").as_ptr()) }),
+        NOTEECE_INVALID_ARGUMENT
+    );
+    ok(noteece_vault_close(handle));
+}
+
+#[test]
+fn test_bad_arguments_are_reported() {
+    let dir = tempdir().unwrap();
+    let handle = create(dir.path(), "pw");
+    let space_id = space(handle);
+
+    assert_eq!(
+        code(unsafe { noteece_note_search(handle, space_id.as_ptr(), std::ptr::null()) }),
+        NOTEECE_NULL_ARGUMENT
+    );
+    let invalid = [0xffu8, 0xfe, 0];
+    assert_eq!(
+        code(unsafe {
+            noteece_note_search(handle, space_id.as_ptr(), invalid.as_ptr() as *const c_char)
+        }),
+        NOTEECE_INVALID_UTF8
+    );
+    let path = c(dir.path().to_str().unwrap());
+    assert_eq!(
+        code(unsafe {
+            noteece_vault_unlock(path.as_ptr(), c("pw").as_ptr(), std::ptr::null_mut())
+        }),
+        NOTEECE_NULL_ARGUMENT
+    );
+
+    // The vault stays exclusive to its open handle
+    let mut other = 0;
+    assert_eq!(
+        code(unsafe { noteece_vault_unlock(path.as_ptr(), c("pw").as_ptr(), &mut other) }),
+        NOTEECE_VAULT_IN_USE
+    );
+    assert_eq!(other, 0);
+    ok(noteece_vault_close(handle));
+
+    assert_eq!(
+        code(unsafe { noteece_vault_unlock(path.as_ptr(), c("wrong").as_ptr(), &mut other) }),
+        NOTEECE_VAULT_ERROR
+    );
+    assert_eq!(other, 0);
+}
+
+#[test]
+fn test_double_free_is_rejected() {
+    let result = noteece_vault_close(0);
+    assert_eq!(result.code, NOTEECE_INVALID_HANDLE);
+    let message = result.message;
+    unsafe {
+        assert_eq!(noteece_string_free(message), NOTEECE_OK);
+        assert_eq!(noteece_string_free(message), NOTEECE_INVALID_POINTER);
+        assert_eq!(noteece_result_free(result), NOTEECE_INVALID_POINTER);
+        assert_eq!(noteece_string_free(std::ptr::null_mut()), NOTEECE_OK);
+
+        // Never handed out by the library
+        let foreign = CString::new("mine").unwrap().into_raw();
+        assert_eq!(noteece_string_free(foreign), NOTEECE_INVALID_POINTER);
+        drop(CString::from_raw(foreign));
+    }
+}
+
+#[test]
+fn test_locked_and_closed_handles() {
+    let dir = tempdir().unwrap();
+    let handle = create(dir.path(), "pw");
+    let space_id = space(handle);
+
+    ok(noteece_vault_lock(handle));
+    assert_eq!(
+        code(unsafe { noteece_task_list(handle, space_id.as_ptr()) }),
+        NOTEECE_LOCKED
+    );
+    assert_eq!(
+        code(unsafe { noteece_sync_apply_deltas(handle, c("peer").as_ptr(), c("[]").as_ptr()) }),
+        NOTEECE_LOCKED
+    );
+
+    ok(noteece_vault_close(handle));
+    assert_eq!(
+        code(unsafe { noteece_task_list(handle, space_id.as_ptr()) }),
+        NOTEECE_INVALID_HANDLE
+    );
+    assert_eq!(code(noteece_vault_close(handle)), NOTEECE_INVALID_HANDLE);
+    assert_eq!(code(noteece_vault_lock(handle)), NOTEECE_INVALID_HANDLE);
+}
@@ -0,0 +1,325 @@
+use base64::engine::general_purpose::{STANDARD, URL_SAFE_NO_PAD};
+use base64::Engine;
+use core_rs::caldav::*;
+use core_rs::crypto::{decrypt_string, encrypt_string};
+use core_rs::db::{migrate, migrate_to};
+use rusqlite::Connection;
+use sha2::{Digest, Sha256};
+use std::io::{Read, Write};
+use std::net::TcpListener;
+use std::thread::JoinHandle;
+
+const DEK: [u8; 32] = [7u8; 32];
+
+const MULTISTATUS: &str = r#"<?xml version="1.0" encoding="utf-8"?>
+<D:multistatus xmlns:D="DAV:" xmlns:C="urn:ietf:params:xml:ns:caldav">
+  <D:response>
+    <D:href>/calendars/alice/standup.ics</D:href>
+    <D:propstat>
+      <D:prop>
+        <D:getetag>"1"</D:getetag>
+        <C:calendar-data>BEGIN:VCALENDAR
+VERSION:2.0
+BEGIN:VEVENT
+UID:standup
+SUMMARY:Standup
+DTSTART:20260105T090000Z
+END:VEVENT
+END:VCALENDAR
+</C:calendar-data>
+      </D:prop>
+    </D:propstat>
+  </D:response>
+</D:multistatus>"#;
+
+#[derive(Debug)]
+struct Recorded {
+    method: String,
+    path: String,
+    authorization: Option<String>,
+    body: String,
+}
+
+/// Answer one request per scripted `(status, content type, body)`, closing the
+/// connection after each, and hand back what was requested
+fn serve(script: Vec<(&'static str, &'static str, String)>) -> (String, JoinHandle<Vec<Recorded>>) {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let base_url = format!("http://{}", listener.local_addr().unwrap());
+    let handle = std::thread::spawn(move || {
+        let mut recorded = Vec::new();
+        for (status, content_type, body) in script {
+            let (mut socket, _) = listener.accept().unwrap();
+            recorded.push(read_request(&mut socket));
+            let response = format!(
+                "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                status,
+                content_type,
+                body.len(),
+                body
+            );
+            socket.write_all(response.as_bytes()).unwrap();
+        }
+        recorded
+    });
+    (base_url, handle)
+}
+
+fn read_request(socket: &mut std::net::TcpStream) -> Recorded {
+    let mut data = Vec::new();
+    let mut buf = [0u8; 4096];
+    loop {
+        let n = socket.read(&mut buf).unwrap();
+        data.extend_from_slice(&buf[..n]);
+        if let Some(end) = data.windows(4).position(|w| w == b"\r\n\r\n") {
+            let head = String::from_utf8_lossy(&data[..end]).to_string();
+            let header = |name: &str| {
+                head.lines().find_map(|l| {
+                    let (key, value) = l.split_once(':')?;
+                    key.eq_ignore_ascii_case(name)
+                        .then(|| value.trim().to_string())
+                })
+            };
+            let length = header("content-length")
+                .and_then(|v| v.parse::<usize>().ok())
+                .unwrap_or(0);
+            while data.len() < end + 4 + length {
+                let n = socket.read(&mut buf).unwrap();
+                data.extend_from_slice(&buf[..n]);
+            }
+            let mut request_line = head.lines().next().unwrap().split(' ');
+            return Recorded {
+                method: request_line.next().unwrap().to_string(),
+                path: request_line.next().unwrap().to_string(),
+                authorization: header("authorization"),
+                body: String::from_utf8_lossy(&data[end + 4..end + 4 + length]).to_string(),
+            };
+        }
+    }
+}
+
+fn calendar() -> (&'static str, &'static str, String) {
+    (
+        "207 Multi-Status",
+        "application/xml; charset=utf-8",
+        MULTISTATUS.to_string(),
+    )
+}
+
+fn unauthorized() -> (&'static str, &'static str, String) {
+    ("401 Unauthorized", "text/plain", "expired".to_string())
+}
+
+fn token(access_token: &str) -> (&'static str, &'static str, String) {
+    (
+        "200 OK",
+        "application/json",
+        format!(
+            r#"{{"access_token":"{}","expires_in":3600,"token_type":"Bearer"}}"#,
+            access_token
+        ),
+    )
+}
+
+fn setup() -> Connection {
+    let mut conn = Connection::open_in_memory().unwrap();
+    migrate(&mut conn).unwrap();
+    conn
+}
+
+fn oauth_client(base_url: &str) -> OAuthClient {
+    OAuthClient {
+        auth_url: format!("{}/authorize", base_url),
+        token_url: format!("{}/token", base_url),
+        client_id: "noteece".to_string(),
+        client_secret: Some("not-so-secret".to_string()),
+        redirect_uri: "http://127.0.0.1:8765/callback".to_string(),
+        scopes: vec!["calendar".to_string()],
+        extra_auth_params: vec![],
+    }
+}
+
+fn bearer_account(conn: &Connection, base_url: &str, refresh_token: Option<&str>) -> CalDavAccount {
+    let tokens = OAuthTokens {
+        access_token: "stale".to_string(),
+        refresh_token: refresh_token.map(|t| t.to_string()),
+        expires_at: Some(0),
+    };
+    let client = oauth_client(base_url);
+    add_caldav_bearer_account(
+        conn,
+        base_url,
+        "alice@example.com",
+        "/calendars/alice/",
+        &tokens,
+        refresh_token.map(|_| &client),
+        &DEK,
+    )
+    .unwrap()
+}
+
+#[test]
+fn test_basic_accounts_keep_working() {
+    let dir = tempfile::tempdir().unwrap();
+    let mut conn = Connection::open(dir.path().join("vault.db")).unwrap();
+    migrate_to(&mut conn, 81, &dir.path().join("backup.db")).unwrap();
+    let (base_url, server) = serve(vec![calendar()]);
+    // An account stored before token auth existed
+    conn.execute_batch(
+        "CREATE TABLE caldav_account (
+            id TEXT PRIMARY KEY, url TEXT NOT NULL, username TEXT NOT NULL,
+            encrypted_password TEXT NOT NULL, calendar_path TEXT NOT NULL,
+            sync_token TEXT, last_sync INTEGER,
+            enabled INTEGER NOT NULL DEFAULT 1, auto_sync INTEGER NOT NULL DEFAULT 1,
+            sync_frequency_minutes INTEGER NOT NULL DEFAULT 15,
+            sync_direction TEXT NOT NULL DEFAULT 'bidirectional',
+            created_at INTEGER NOT NULL
+        )",
+    )
+    .unwrap();
+    conn.execute(
+        "INSERT INTO caldav_account (id, url, username, encrypted_password, calendar_path, created_at)
+         VALUES ('legacy', ?1, 'alice', ?2, '/calendars/alice/', 0)",
+        [&base_url, &encrypt_string("s3cret", &DEK).unwrap()],
+    )
+    .unwrap();
+    migrate(&mut conn).unwrap();
+    init_caldav_tables(&conn).unwrap();
+
+    let account = get_caldav_account(&conn, "legacy").unwrap().unwrap();
+    assert_eq!(account.auth_type, CalDavAuthType::Basic);
+    let result = sync_caldav_account(&conn, "legacy", &DEK).unwrap();
+    assert!(result.success, "{:?}", result.errors);
+    assert_eq!(result.events_pulled, 1);
+
+    let requests = server.join().unwrap();
+    assert_eq!(requests.len(), 1);
+    assert_eq!(requests[0].method, "REPORT");
+    assert_eq!(requests[0].path, "/calendars/alice/");
+    assert_eq!(
+        requests[0].authorization.as_deref(),
+        Some(format!("Basic {}", STANDARD.encode("alice:s3cret")).as_str())
+    );
+
+    // New Basic accounts are stored the same way
+    let added = add_caldav_account(&conn, &base_url, "bob", "pw", "/cal/", &DEK).unwrap();
+    let stored = get_caldav_account(&conn, &added.id).unwrap().unwrap();
+    assert_eq!(stored.auth_type, CalDavAuthType::Basic);
+    assert_eq!(stored.encrypted_access_token, None);
+}
+
+#[test]
+fn test_rejected_token_is_refreshed_and_the_request_retried() {
+    let conn = setup();
+    init_caldav_tables(&conn).unwrap();
+    let (base_url, server) = serve(vec![unauthorized(), token("fresh"), calendar()]);
+    let account = bearer_account(&conn, &base_url, Some("refresh-1"));
+
+    let result = sync_caldav_account(&conn, &account.id, &DEK).unwrap();
+    assert!(result.success, "{:?}", result.errors);
+    assert_eq!(result.events_pulled, 1);
+
+    let requests = server.join().unwrap();
+    let summary: Vec<(&str, &str, Option<&str>)> = requests
+        .iter()
+        .map(|r| {
+            (
+                r.method.as_str(),
+                r.path.as_str(),
+                r.authorization.as_deref(),
+            )
+        })
+        .collect();
+    assert_eq!(
+        summary,
+        [
+            ("REPORT", "/calendars/alice/", Some("Bearer stale")),
+            ("POST", "/token", None),
+            ("REPORT", "/calendars/alice/", Some("Bearer fresh")),
+        ]
+    );
+    let form = &requests[1].body;
+    assert!(form.contains("grant_type=refresh_token"), "{}", form);
+    assert!(form.contains("refresh_token=refresh-1"), "{}", form);
+    assert!(form.contains("client_id=noteece"), "{}", form);
+
+    // The new token is stored encrypted; the refresh token wasn't rotated
+    let stored = get_caldav_account(&conn, &account.id).unwrap().unwrap();
+    let access_token = stored.encrypted_access_token.unwrap();
+    assert!(!access_token.contains("fresh"));
+    assert_eq!(decrypt_string(&access_token, &DEK).unwrap(), "fresh");
+    assert_eq!(
+        decrypt_string(&stored.encrypted_refresh_token.unwrap(), &DEK).unwrap(),
+        "refresh-1"
+    );
+    assert!(stored.token_expires_at.unwrap() > chrono::Utc::now().timestamp());
+}
+
+#[test]
+fn test_requests_are_retried_only_once() {
+    let conn = setup();
+    init_caldav_tables(&conn).unwrap();
+    let (base_url, server) = serve(vec![unauthorized(), token("also-rejected"), unauthorized()]);
+    let account = bearer_account(&conn, &base_url, Some("refresh-1"));
+
+    let result = sync_caldav_account(&conn, &account.id, &DEK).unwrap();
+    assert!(!result.success);
+    assert!(
+        result.errors[0].contains("Authentication"),
+        "{:?}",
+        result.errors
+    );
+    assert_eq!(server.join().unwrap().len(), 3);
+
+    // A static token has nothing to refresh with
+    let (base_url, server) = serve(vec![unauthorized()]);
+    let account = bearer_account(&conn, &base_url, None);
+    assert!(matches!(
+        refresh_access_token(&conn, &account.id, &DEK),
+        Err(CalDavError::Authentication)
+    ));
+    let result = sync_caldav_account(&conn, &account.id, &DEK).unwrap();
+    assert!(!result.success);
+    assert_eq!(server.join().unwrap().len(), 1);
+}
+
+#[test]
+fn test_oauth_flow_uses_pkce() {
+    let (base_url, server) = serve(vec![(
+        "200 OK",
+        "application/json",
+        r#"{"access_token":"a1","refresh_token":"r1","expires_in":3599}"#.to_string(),
+    )]);
+    let google = OAuthClient::google("noteece.apps", None, "http://127.0.0.1:8765/callback");
+    let flow = start_oauth_flow(&google).unwrap();
+
+    let url = reqwest::Url::parse(&flow.auth_url).unwrap();
+    assert_eq!(url.host_str(), Some("accounts.google.com"));
+    let param = |name: &str| {
+        url.query_pairs()
+            .find(|(k, _)| k == name)
+            .map(|(_, v)| v.to_string())
+    };
+    assert!((43..=128).contains(&flow.code_verifier.len()));
+    let challenge = URL_SAFE_NO_PAD.encode(Sha256::digest(flow.code_verifier.as_bytes()));
+    assert_eq!(param("code_challenge"), Some(challenge));
+    assert_eq!(param("code_challenge_method").as_deref(), Some("S256"));
+    assert_eq!(param("state"), Some(flow.state.clone()));
+    assert_eq!(param("access_type").as_deref(), Some("offline"));
+    assert_eq!(
+        param("redirect_uri").as_deref(),
+        Some("http://127.0.0.1:8765/callback")
+    );
+
+    let tokens =
+        finish_oauth_flow(&oauth_client(&base_url), "code-1", &flow.code_verifier).unwrap();
+    assert_eq!(tokens.access_token, "a1");
+    assert_eq!(tokens.refresh_token.as_deref(), Some("r1"));
+    let form = server.join().unwrap().remove(0).body;
+    assert!(form.contains("grant_type=authorization_code"), "{}", form);
+    assert!(form.contains("code=code-1"), "{}", form);
+    assert!(
+        form.contains(&format!("code_verifier={}", flow.code_verifier)),
+        "{}",
+        form
+    );
+}
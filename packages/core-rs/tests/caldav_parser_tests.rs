@@ -0,0 +1,119 @@
+use core_rs::caldav::{parse_calendar_response, CalDavError, CalDavEvent, MAX_RESPONSE_SIZE};
+
+fn fixture(server: &str) -> String {
+    std::fs::read_to_string(format!("./tests/fixtures/caldav/{}.xml", server)).unwrap()
+}
+
+/// The events every fixture holds, without etags
+fn expected_events() -> Vec<CalDavEvent> {
+    vec![
+        CalDavEvent {
+            uid: "team-sync-1@example.com".to_string(),
+            summary: "Team sync & planning".to_string(),
+            description: None,
+            start_time: 1736260200,
+            end_time: Some(1736262000),
+            location: Some("Room 4".to_string()),
+            status: "CONFIRMED".to_string(),
+            last_modified: 1735732800,
+            etag: None,
+        },
+        CalDavEvent {
+            uid: "dentist-2@example.com".to_string(),
+            summary: "Dentist".to_string(),
+            description: Some("Bring the <x-ray> forms".to_string()),
+            start_time: 1736467200,
+            end_time: None,
+            location: None,
+            status: "TENTATIVE".to_string(),
+            last_modified: 1735808400,
+            etag: None,
+        },
+    ]
+}
+
+fn assert_extracted(server: &str, etags: [&str; 2]) {
+    let events = parse_calendar_response(&fixture(server)).unwrap();
+    let expected = expected_events()
+        .into_iter()
+        .zip(etags)
+        .map(|(event, etag)| CalDavEvent {
+            etag: Some(etag.to_string()),
+            ..event
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(events, expected, "events from {}", server);
+}
+
+#[test]
+fn test_nextcloud_response() {
+    // Prefixed namespaces, escaped calendar data with &#13; line endings
+    assert_extracted(
+        "nextcloud",
+        [
+            "\"5d1c6e1e8f0a2b3c4d5e6f708192a3b4\"",
+            "\"9f8e7d6c5b4a39281706f5e4d3c2b1a0\"",
+        ],
+    );
+}
+
+#[test]
+fn test_radicale_response() {
+    // DAV: as the default namespace
+    assert_extracted(
+        "radicale",
+        [
+            "\"a3f0c2e4b6d8f0a1c3e5b7d9f1a3c5e7\"",
+            "\"0b2d4f6a8c0e2b4d6f8a0c2e4b6d8f0a\"",
+        ],
+    );
+}
+
+#[test]
+fn test_icloud_response() {
+    // Namespaces declared per element, calendar data in CDATA
+    assert_extracted(
+        "icloud",
+        [
+            "\"C=1042@U=7c6b5a49-3827-4160-9f5e-4d3c2b1a0f9e\"",
+            "\"C=1043@U=7c6b5a49-3827-4160-9f5e-4d3c2b1a0f9e\"",
+        ],
+    );
+}
+
+#[test]
+fn test_elements_are_matched_by_namespace() {
+    let ics = "BEGIN:VCALENDAR\nVERSION:2.0\nBEGIN:VEVENT\nUID:a@example.com\n\
+               SUMMARY:Standup\nDTSTART:20250107T090000Z\nEND:VEVENT\nEND:VCALENDAR\n";
+    let xml = format!(
+        r#"<x:multistatus xmlns:x="DAV:" xmlns:zz="urn:ietf:params:xml:ns:caldav" xmlns:o="urn:other">
+            <x:response>
+              <x:propstat><x:prop>
+                <x:getetag>"1"</x:getetag>
+                <o:calendar-data>{ics}</o:calendar-data>
+                <zz:calendar-data>{ics}</zz:calendar-data>
+              </x:prop></x:propstat>
+            </x:response>
+            <x:response>
+              <x:propstat><x:prop><zz:calendar-data/></x:prop></x:propstat>
+            </x:response>
+          </x:multistatus>"#
+    );
+    let events = parse_calendar_response(&xml).unwrap();
+    assert_eq!(events.len(), 1);
+    assert_eq!(events[0].summary, "Standup");
+    assert_eq!(events[0].etag.as_deref(), Some("\"1\""));
+
+    assert!(matches!(
+        parse_calendar_response("<d:multistatus xmlns:d=\"DAV:\"></d:response>"),
+        Err(CalDavError::Parse(_))
+    ));
+    let oversized = format!(
+        "<d:multistatus xmlns:d=\"DAV:\">{}</d:multistatus>",
+        " ".repeat(MAX_RESPONSE_SIZE)
+    );
+    assert!(matches!(
+        parse_calendar_response(&oversized),
+        Err(CalDavError::Parse(_))
+    ));
+}
@@ -1,4 +1,9 @@
-use core_rs::calendar::{export_ics, import_ics};
+use chrono::NaiveDate;
+use core_rs::calendar::{
+    create_event, delete_event, export_ics, get_due_reminders, get_event, get_events_in_range,
+    get_free_busy, import_ics, mark_reminder_sent, suggest_focus_blocks, update_event,
+    FocusConstraints, TimeRange, WorkingHours, EVENT_SOURCE_LOCAL,
+};
 use core_rs::db::migrate;
 use rusqlite::Connection;
 use std::fs::File;
@@ -77,3 +82,388 @@ fn test_export_ics() {
     assert!(ics_content.contains("SUMMARY:test task"));
     assert!(ics_content.contains("DESCRIPTION:test description"));
 }
+
+/// Unix timestamp of a UTC time in the week of Monday 2025-01-06
+fn at(day: u32, hour: u32, minute: u32) -> i64 {
+    NaiveDate::from_ymd_opt(2025, 1, 6 + day)
+        .unwrap()
+        .and_hms_opt(hour, minute, 0)
+        .unwrap()
+        .and_utc()
+        .timestamp()
+}
+
+fn hours(day: u32, start: u32, end: u32) -> TimeRange {
+    TimeRange::new(at(day, start, 0), at(day, end, 0))
+}
+
+fn add_event(
+    conn: &Connection,
+    space_id: Ulid,
+    start: i64,
+    end: Option<i64>,
+    all_day: bool,
+    source: &str,
+) {
+    conn.execute(
+        "INSERT INTO calendar_event (id, space_id, title, start_time, end_time, source, all_day, created_at, updated_at)
+         VALUES (?1, ?2, 'event', ?3, ?4, ?5, ?6, 0, 0)",
+        rusqlite::params![
+            Ulid::new().to_string(),
+            space_id.to_string(),
+            start,
+            end,
+            source,
+            all_day
+        ],
+    )
+    .unwrap();
+}
+
+fn week() -> TimeRange {
+    TimeRange::new(at(0, 0, 0), at(7, 0, 0))
+}
+
+#[test]
+fn test_free_busy_merges_overlapping_events() {
+    let conn = setup_db();
+    let space_id = create_space(&conn);
+    add_event(
+        &conn,
+        space_id,
+        at(0, 9, 0),
+        Some(at(0, 10, 30)),
+        false,
+        "local",
+    );
+    add_event(
+        &conn,
+        space_id,
+        at(0, 10, 0),
+        Some(at(0, 11, 0)),
+        false,
+        "caldav",
+    );
+    add_event(
+        &conn,
+        space_id,
+        at(0, 10, 59),
+        Some(at(0, 12, 0)),
+        false,
+        "local",
+    );
+    add_event(
+        &conn,
+        space_id,
+        at(0, 14, 0),
+        Some(at(0, 15, 0)),
+        false,
+        "local",
+    );
+    // No end: assumed to last an hour
+    add_event(&conn, space_id, at(0, 16, 0), None, false, "caldav");
+    // Other spaces don't count
+    let other_space = create_space(&conn);
+    add_event(
+        &conn,
+        other_space,
+        at(0, 12, 0),
+        Some(at(0, 14, 0)),
+        false,
+        "local",
+    );
+
+    let monday = TimeRange::new(at(0, 0, 0), at(1, 0, 0));
+    let free_busy = get_free_busy(&conn, space_id, monday, &WorkingHours::default()).unwrap();
+    assert_eq!(
+        free_busy.busy,
+        vec![hours(0, 9, 12), hours(0, 14, 15), hours(0, 16, 17)]
+    );
+    assert_eq!(free_busy.free, vec![hours(0, 12, 14), hours(0, 15, 16)]);
+}
+
+#[test]
+fn test_all_day_events_block_whole_days() {
+    let conn = setup_db();
+    let space_id = create_space(&conn);
+    add_event(&conn, space_id, at(1, 0, 0), None, true, "caldav");
+    add_event(
+        &conn,
+        space_id,
+        at(3, 0, 0),
+        Some(at(5, 0, 0)),
+        true,
+        "local",
+    );
+    add_event(
+        &conn,
+        space_id,
+        at(2, 13, 0),
+        Some(at(2, 14, 0)),
+        false,
+        "local",
+    );
+    let range = TimeRange::new(at(0, 0, 0), at(5, 0, 0));
+
+    let free_busy = get_free_busy(&conn, space_id, range, &WorkingHours::default()).unwrap();
+    assert_eq!(
+        free_busy.free,
+        vec![hours(0, 9, 17), hours(2, 9, 13), hours(2, 14, 17)]
+    );
+
+    // Days are local: at UTC+1 Tuesday runs from Monday 23:00 UTC
+    let working_hours = WorkingHours {
+        utc_offset_minutes: 60,
+        ..WorkingHours::default()
+    };
+    let free_busy = get_free_busy(&conn, space_id, range, &working_hours).unwrap();
+    assert_eq!(
+        free_busy.busy[0],
+        TimeRange::new(at(0, 23, 0), at(1, 23, 0))
+    );
+    assert_eq!(
+        free_busy.free,
+        vec![hours(0, 8, 16), hours(2, 8, 13), hours(2, 14, 16)]
+    );
+}
+
+#[test]
+fn test_fully_booked_week_has_no_focus_blocks() {
+    let conn = setup_db();
+    let space_id = create_space(&conn);
+    add_event(
+        &conn,
+        space_id,
+        at(0, 0, 0),
+        Some(at(5, 0, 0)),
+        true,
+        "caldav",
+    );
+
+    let free_busy = get_free_busy(&conn, space_id, week(), &WorkingHours::default()).unwrap();
+    assert!(free_busy.free.is_empty());
+    let blocks =
+        suggest_focus_blocks(&conn, space_id, 4.0, &FocusConstraints::new(week())).unwrap();
+    assert!(blocks.is_empty());
+}
+
+#[test]
+fn test_focus_blocks_prefer_long_gaps_on_separate_days() {
+    let conn = setup_db();
+    let space_id = create_space(&conn);
+    add_event(
+        &conn,
+        space_id,
+        at(0, 9, 0),
+        Some(at(0, 12, 0)),
+        false,
+        "local",
+    );
+    add_event(
+        &conn,
+        space_id,
+        at(2, 11, 0),
+        Some(at(2, 13, 0)),
+        false,
+        "local",
+    );
+    add_event(&conn, space_id, at(3, 0, 0), None, true, "local");
+    add_event(
+        &conn,
+        space_id,
+        at(4, 9, 0),
+        Some(at(4, 10, 0)),
+        false,
+        "local",
+    );
+    let constraints = FocusConstraints::new(week());
+
+    let blocks = suggest_focus_blocks(&conn, space_id, 10.0, &constraints).unwrap();
+    assert_eq!(
+        blocks,
+        vec![hours(0, 12, 14), hours(1, 9, 13), hours(4, 10, 14)]
+    );
+
+    // Asking for more than there is doubles up on days, then gives what exists
+    let blocks = suggest_focus_blocks(&conn, space_id, 30.0, &constraints).unwrap();
+    let total: i64 = blocks.iter().map(|b| b.duration_secs()).sum();
+    assert_eq!(total, 26 * 3600);
+    let busy = get_free_busy(&conn, space_id, week(), &constraints.working_hours)
+        .unwrap()
+        .busy;
+    for block in &blocks {
+        assert!(block.duration_secs() <= 4 * 3600);
+        assert!(busy
+            .iter()
+            .all(|b| block.end <= b.start || block.start >= b.end));
+    }
+}
+
+fn summaries(events: &[core_rs::calendar::CalendarEvent]) -> Vec<&str> {
+    events.iter().map(|e| e.summary.as_str()).collect()
+}
+
+#[test]
+fn test_local_event_crud() {
+    let conn = setup_db();
+    let space_id = create_space(&conn);
+    let event = create_event(
+        &conn,
+        space_id,
+        "Standup",
+        at(0, 9, 0),
+        Some(at(0, 9, 15)),
+        Some("Room 4".to_string()),
+        None,
+        false,
+    )
+    .unwrap();
+    assert_eq!(event.source, EVENT_SOURCE_LOCAL);
+    assert_eq!(get_event(&conn, event.id).unwrap().unwrap(), event);
+
+    let mut moved = event.clone();
+    moved.start = at(0, 10, 0);
+    moved.end = Some(at(0, 10, 30));
+    moved.remind_minutes_before = Some(5);
+    let saved = update_event(&conn, &moved).unwrap();
+    assert_eq!(saved.start, at(0, 10, 0));
+    assert_eq!(saved.remind_minutes_before, Some(5));
+    assert_eq!(saved.location.as_deref(), Some("Room 4"));
+
+    let mut backwards = saved.clone();
+    backwards.end = Some(at(0, 9, 0));
+    assert!(update_event(&conn, &backwards).is_err());
+    assert!(create_event(&conn, space_id, " ", at(0, 9, 0), None, None, None, false).is_err());
+
+    delete_event(&conn, event.id).unwrap();
+    assert!(get_event(&conn, event.id).unwrap().is_none());
+}
+
+#[test]
+fn test_all_day_events_snap_to_whole_days() {
+    let conn = setup_db();
+    let space_id = create_space(&conn);
+    // Tuesday and Wednesday, given as times within those days
+    let event = create_event(
+        &conn,
+        space_id,
+        "Offsite",
+        at(1, 15, 30),
+        Some(at(2, 8, 0)),
+        None,
+        None,
+        true,
+    )
+    .unwrap();
+    assert_eq!(event.start, at(1, 0, 0));
+    assert_eq!(event.end, Some(at(3, 0, 0)));
+
+    // Without an end it covers its one day
+    let holiday = create_event(
+        &conn,
+        space_id,
+        "Holiday",
+        at(4, 12, 0),
+        None,
+        None,
+        None,
+        true,
+    )
+    .unwrap();
+    assert_eq!(holiday.start, at(4, 0, 0));
+    let friday = get_events_in_range(&conn, space_id, at(4, 23, 0), at(5, 0, 0)).unwrap();
+    assert_eq!(summaries(&friday), vec!["Holiday"]);
+    assert!(
+        get_events_in_range(&conn, space_id, at(5, 0, 0), at(5, 1, 0))
+            .unwrap()
+            .is_empty()
+    );
+}
+
+#[test]
+fn test_range_query_includes_events_straddling_boundaries() {
+    let conn = setup_db();
+    let space_id = create_space(&conn);
+    let event = |summary: &str, start: i64, end: Option<i64>| {
+        create_event(&conn, space_id, summary, start, end, None, None, false).unwrap();
+    };
+    // The range is Tuesday 09:00-17:00
+    event("Overnight", at(0, 22, 0), Some(at(1, 10, 0)));
+    event("Inside", at(1, 12, 0), Some(at(1, 13, 0)));
+    event("Into evening", at(1, 16, 0), Some(at(1, 19, 0)));
+    event("Spanning", at(0, 8, 0), Some(at(2, 8, 0)));
+    event("Ends at start", at(1, 8, 0), Some(at(1, 9, 0)));
+    event("Starts at end", at(1, 17, 0), Some(at(1, 18, 0)));
+    // No end, so an hour long
+    event("Open ended", at(1, 8, 30), None);
+
+    let events = get_events_in_range(&conn, space_id, at(1, 9, 0), at(1, 17, 0)).unwrap();
+    assert_eq!(
+        summaries(&events),
+        vec![
+            "Spanning",
+            "Overnight",
+            "Open ended",
+            "Inside",
+            "Into evening"
+        ]
+    );
+
+    // Other spaces' events stay out
+    let other = create_space(&conn);
+    assert!(get_events_in_range(&conn, other, at(0, 0, 0), at(7, 0, 0))
+        .unwrap()
+        .is_empty());
+    assert!(get_events_in_range(&conn, space_id, at(1, 9, 0), at(1, 9, 0)).is_err());
+}
+
+#[test]
+fn test_due_reminders() {
+    let conn = setup_db();
+    let space_id = create_space(&conn);
+    let mut review = create_event(
+        &conn,
+        space_id,
+        "Review",
+        at(2, 14, 0),
+        Some(at(2, 15, 0)),
+        None,
+        None,
+        false,
+    )
+    .unwrap();
+    review.remind_minutes_before = Some(30);
+    let review = update_event(&conn, &review).unwrap();
+    // No reminder set
+    create_event(
+        &conn,
+        space_id,
+        "Lunch",
+        at(2, 12, 0),
+        None,
+        None,
+        None,
+        false,
+    )
+    .unwrap();
+
+    assert!(get_due_reminders(&conn, at(2, 13, 29)).unwrap().is_empty());
+    let due = get_due_reminders(&conn, at(2, 13, 30)).unwrap();
+    assert_eq!(due.len(), 1);
+    assert_eq!(due[0].event.id, review.id);
+    assert_eq!(due[0].remind_at, at(2, 13, 30));
+    // Still due during the event, no longer once it's over
+    assert_eq!(get_due_reminders(&conn, at(2, 14, 30)).unwrap().len(), 1);
+    assert!(get_due_reminders(&conn, at(2, 15, 0)).unwrap().is_empty());
+
+    mark_reminder_sent(&conn, review.id, at(2, 13, 31)).unwrap();
+    assert!(get_due_reminders(&conn, at(2, 13, 45)).unwrap().is_empty());
+
+    // Moving the event later arms the reminder again
+    let mut later = review.clone();
+    later.start = at(3, 14, 0);
+    later.end = Some(at(3, 15, 0));
+    update_event(&conn, &later).unwrap();
+    assert!(get_due_reminders(&conn, at(2, 13, 45)).unwrap().is_empty());
+    assert_eq!(get_due_reminders(&conn, at(3, 13, 30)).unwrap().len(), 1);
+}
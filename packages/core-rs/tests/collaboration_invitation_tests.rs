@@ -0,0 +1,174 @@
+use core_rs::audit::{self, get_auth_audit_log, AuthAuditFilter};
+use core_rs::collaboration::{
+    accept_invitation, accept_invitation_at, check_permission, create_invitation, get_space_users,
+    init_rbac_tables, list_pending_invitations, revoke_invitation, CollaborationError,
+};
+use core_rs::db;
+use rusqlite::Connection;
+use ulid::Ulid;
+
+fn setup_db() -> (Connection, String) {
+    let mut conn = Connection::open_in_memory().unwrap();
+    db::migrate(&mut conn).unwrap();
+    init_rbac_tables(&conn).unwrap();
+    let space_id = Ulid::new().to_string();
+    conn.execute(
+        "INSERT INTO space (id, name) VALUES (?1, 'Team')",
+        [&space_id],
+    )
+    .unwrap();
+    (conn, space_id)
+}
+
+fn status(conn: &Connection, invitation_id: &str) -> String {
+    conn.query_row(
+        "SELECT status FROM user_invitations WHERE id = ?1",
+        [invitation_id],
+        |row| row.get(0),
+    )
+    .unwrap()
+}
+
+fn audit_events(conn: &Connection, event_type: &str) -> usize {
+    get_auth_audit_log(
+        conn,
+        &AuthAuditFilter {
+            event_types: Some(vec![event_type.to_string()]),
+            ..Default::default()
+        },
+    )
+    .unwrap()
+    .len()
+}
+
+#[test]
+fn test_accepting_applies_invited_role() {
+    let (conn, space_id) = setup_db();
+    let invitation =
+        create_invitation(&conn, &space_id, " Ana@Example.com", "editor", "owner-1").unwrap();
+    assert_eq!(invitation.email, "ana@example.com");
+    assert_eq!(invitation.status, "pending");
+    assert_eq!(
+        list_pending_invitations(&conn, &space_id).unwrap()[0].id,
+        invitation.id
+    );
+
+    let accepted = accept_invitation(&conn, &invitation.token, "user-ana").unwrap();
+    assert_eq!(accepted.status, "accepted");
+    assert_eq!(status(&conn, &invitation.id), "accepted");
+    assert!(list_pending_invitations(&conn, &space_id)
+        .unwrap()
+        .is_empty());
+
+    assert!(check_permission(&conn, &space_id, "user-ana", "write_notes").unwrap());
+    assert!(check_permission(&conn, &space_id, "user-ana", "delete_notes").unwrap());
+    assert!(!check_permission(&conn, &space_id, "user-ana", "admin").unwrap());
+    let users = get_space_users(&conn, &space_id).unwrap();
+    assert_eq!(users.len(), 1);
+    assert_eq!(users[0].email, "ana@example.com");
+    assert_eq!(users[0].status, "active");
+
+    assert_eq!(audit_events(&conn, audit::AUTH_INVITATION_CREATED), 1);
+    assert_eq!(audit_events(&conn, audit::AUTH_INVITATION_ACCEPTED), 1);
+}
+
+#[test]
+fn test_expired_token_is_rejected() {
+    let (conn, space_id) = setup_db();
+    let invitation =
+        create_invitation(&conn, &space_id, "bo@example.com", "viewer", "owner-1").unwrap();
+
+    let result = accept_invitation_at(&conn, &invitation.token, "user-bo", invitation.expires_at);
+    assert!(matches!(result, Err(CollaborationError::InvitationExpired)));
+    assert_eq!(status(&conn, &invitation.id), "expired");
+    assert!(!check_permission(&conn, &space_id, "user-bo", "read_notes").unwrap());
+    assert_eq!(audit_events(&conn, audit::AUTH_INVITATION_ACCEPT_FAILED), 1);
+
+    // Still expired when tried again in time
+    assert!(matches!(
+        accept_invitation(&conn, &invitation.token, "user-bo"),
+        Err(CollaborationError::InvitationExpired)
+    ));
+}
+
+#[test]
+fn test_revoked_token_is_rejected() {
+    let (conn, space_id) = setup_db();
+    let invitation =
+        create_invitation(&conn, &space_id, "cy@example.com", "editor", "owner-1").unwrap();
+
+    revoke_invitation(&conn, &invitation.id, "owner-1").unwrap();
+    assert_eq!(status(&conn, &invitation.id), "revoked");
+    assert!(list_pending_invitations(&conn, &space_id)
+        .unwrap()
+        .is_empty());
+    assert!(matches!(
+        accept_invitation(&conn, &invitation.token, "user-cy"),
+        Err(CollaborationError::InvitationRevoked)
+    ));
+    assert!(get_space_users(&conn, &space_id).unwrap().is_empty());
+    assert_eq!(audit_events(&conn, audit::AUTH_INVITATION_REVOKED), 1);
+}
+
+#[test]
+fn test_token_is_single_use() {
+    let (conn, space_id) = setup_db();
+    let invitation =
+        create_invitation(&conn, &space_id, "di@example.com", "viewer", "owner-1").unwrap();
+
+    accept_invitation(&conn, &invitation.token, "user-di").unwrap();
+    assert!(matches!(
+        accept_invitation(&conn, &invitation.token, "user-eve"),
+        Err(CollaborationError::InvitationAlreadyAccepted)
+    ));
+    assert!(!check_permission(&conn, &space_id, "user-eve", "read_notes").unwrap());
+    assert!(matches!(
+        revoke_invitation(&conn, &invitation.id, "owner-1"),
+        Err(CollaborationError::InvitationAlreadyAccepted)
+    ));
+}
+
+#[test]
+fn test_reinviting_supersedes_previous_token() {
+    let (conn, space_id) = setup_db();
+    let first = create_invitation(&conn, &space_id, "fa@example.com", "viewer", "owner-1").unwrap();
+    let second = create_invitation(&conn, &space_id, "FA@example.com", "admin", "owner-1").unwrap();
+
+    let pending = list_pending_invitations(&conn, &space_id).unwrap();
+    assert_eq!(pending.len(), 1);
+    assert_eq!(pending[0].id, second.id);
+    assert!(pending[0].permissions.contains(&"manage_space".to_string()));
+    assert_eq!(status(&conn, &first.id), "superseded");
+    assert_eq!(audit_events(&conn, audit::AUTH_INVITATION_SUPERSEDED), 1);
+
+    assert!(matches!(
+        accept_invitation(&conn, &first.token, "user-fa"),
+        Err(CollaborationError::InvitationSuperseded)
+    ));
+    accept_invitation(&conn, &second.token, "user-fa").unwrap();
+    assert!(check_permission(&conn, &space_id, "user-fa", "admin").unwrap());
+}
+
+#[test]
+fn test_token_is_bound_to_invitation_details() {
+    let (conn, space_id) = setup_db();
+    let invitation =
+        create_invitation(&conn, &space_id, "gu@example.com", "viewer", "owner-1").unwrap();
+
+    assert!(matches!(
+        accept_invitation(&conn, "not-a-token", "user-gu"),
+        Err(CollaborationError::InvalidInvitation)
+    ));
+
+    // Raising the role on the row doesn't carry over to the token
+    conn.execute(
+        "UPDATE user_invitations SET role_id = 'owner' WHERE id = ?1",
+        [&invitation.id],
+    )
+    .unwrap();
+    assert!(matches!(
+        accept_invitation(&conn, &invitation.token, "user-gu"),
+        Err(CollaborationError::InvalidInvitation)
+    ));
+    assert!(!check_permission(&conn, &space_id, "user-gu", "manage_billing").unwrap());
+}
@@ -0,0 +1,247 @@
+use core_rs::collaboration::{
+    acquire_edit_lock, acquire_edit_lock_at, add_user_to_space, get_active_users, get_edit_lock,
+    get_entity_viewers, heartbeat_presence, heartbeat_presence_at, init_rbac_tables,
+    leave_presence, release_edit_lock, CollaborationError, LockedByOther, PRESENCE_TTL_SECS,
+};
+use core_rs::db::{migrate, DbError};
+use core_rs::error::{codes, CoreError};
+use core_rs::note::{create_note, get_note, update_note_content_as};
+use core_rs::permission::ActorContext;
+use core_rs::sync::set_device_user;
+use core_rs::sync_agent::{init_sync_tables, SyncAgent, SyncDelta, SyncOperation};
+use core_rs::task::{create_task, get_task, update_task_as};
+use rusqlite::Connection;
+use std::collections::HashMap;
+use ulid::Ulid;
+
+/// A space shared by two editors
+fn setup_shared_space() -> (Connection, String) {
+    let mut conn = Connection::open_in_memory().unwrap();
+    migrate(&mut conn).unwrap();
+    init_rbac_tables(&conn).unwrap();
+    let space_id = Ulid::new().to_string();
+    conn.execute(
+        "INSERT INTO space (id, name) VALUES (?1, 'Team')",
+        [&space_id],
+    )
+    .unwrap();
+    for user_id in ["ana", "ben"] {
+        let email = format!("{}@example.com", user_id);
+        add_user_to_space(&conn, &space_id, user_id, &email, "editor").unwrap();
+    }
+    (conn, space_id)
+}
+
+fn now() -> i64 {
+    chrono::Utc::now().timestamp()
+}
+
+fn users(presence: &[core_rs::collaboration::Presence]) -> Vec<&str> {
+    presence.iter().map(|p| p.user_id.as_str()).collect()
+}
+
+#[test]
+fn test_second_user_is_told_who_holds_the_lock() {
+    let (conn, _space_id) = setup_shared_space();
+    let task_id = Ulid::new().to_string();
+
+    let lock = acquire_edit_lock(&conn, "ana", &task_id, 300).unwrap();
+    assert_eq!(lock.user_id, "ana");
+    assert_eq!(lock.expires_at - lock.acquired_at, 300);
+
+    let err = acquire_edit_lock(&conn, "ben", &task_id, 300).unwrap_err();
+    let CollaborationError::LockedByOther(LockedByOther { holder }) = &err else {
+        panic!("expected LockedByOther, got {:?}", err);
+    };
+    assert_eq!(holder, &lock);
+    let core = CoreError::from(err);
+    assert_eq!(core.code, codes::LOCKED_BY_OTHER);
+    assert_eq!(core.context["user_id"], "ana");
+
+    // The holder renews without losing when they started
+    let renewed = acquire_edit_lock_at(&conn, "ana", &task_id, 600, lock.acquired_at + 60).unwrap();
+    assert_eq!(renewed.acquired_at, lock.acquired_at);
+    assert_eq!(renewed.expires_at, lock.acquired_at + 660);
+
+    // Only the holder can release
+    assert!(!release_edit_lock(&conn, "ben", &task_id).unwrap());
+    assert!(release_edit_lock(&conn, "ana", &task_id).unwrap());
+    assert!(get_edit_lock(&conn, &task_id).unwrap().is_none());
+    assert_eq!(
+        acquire_edit_lock(&conn, "ben", &task_id, 300)
+            .unwrap()
+            .user_id,
+        "ben"
+    );
+
+    assert!(matches!(
+        acquire_edit_lock(&conn, "ana", "other", 0),
+        Err(CollaborationError::InvalidLockTtl(0))
+    ));
+}
+
+#[test]
+fn test_expired_lock_is_reclaimed() {
+    let (conn, _space_id) = setup_shared_space();
+    let task_id = Ulid::new().to_string();
+
+    // Ana's client crashed ten minutes into a five minute lock
+    let stale = acquire_edit_lock_at(&conn, "ana", &task_id, 300, now() - 600).unwrap();
+    assert!(get_edit_lock(&conn, &task_id).unwrap().is_none());
+
+    let lock = acquire_edit_lock(&conn, "ben", &task_id, 300).unwrap();
+    assert_eq!(lock.user_id, "ben");
+    assert!(lock.acquired_at > stale.acquired_at);
+    // Ana's expired lock gives her no claim on it
+    assert!(matches!(
+        acquire_edit_lock(&conn, "ana", &task_id, 300),
+        Err(CollaborationError::LockedByOther(_))
+    ));
+}
+
+#[test]
+fn test_presence_expires_without_heartbeats() {
+    let (conn, space_id) = setup_shared_space();
+    let note_id = Ulid::new().to_string();
+
+    heartbeat_presence(&conn, "ana", &space_id, Some(&note_id)).unwrap();
+    heartbeat_presence_at(
+        &conn,
+        "ben",
+        &space_id,
+        Some(&note_id),
+        now() - PRESENCE_TTL_SECS - 1,
+    )
+    .unwrap();
+    assert_eq!(users(&get_active_users(&conn, &space_id).unwrap()), ["ana"]);
+    assert_eq!(
+        users(&get_entity_viewers(&conn, &note_id).unwrap()),
+        ["ana"]
+    );
+
+    // A fresh heartbeat brings Ben back; moving on updates what he views
+    heartbeat_presence(&conn, "ben", &space_id, None).unwrap();
+    let active = get_active_users(&conn, &space_id).unwrap();
+    assert_eq!(active.len(), 2);
+    assert_eq!(
+        users(&get_entity_viewers(&conn, &note_id).unwrap()),
+        ["ana"]
+    );
+
+    leave_presence(&conn, "ana", &space_id).unwrap();
+    assert_eq!(users(&get_active_users(&conn, &space_id).unwrap()), ["ben"]);
+    assert!(get_entity_viewers(&conn, &note_id).unwrap().is_empty());
+}
+
+#[test]
+fn test_locked_edits_are_refused_to_other_users() {
+    let (mut conn, space_id) = setup_shared_space();
+    let space = Ulid::from_string(&space_id).unwrap();
+    let note = create_note(&conn, &space_id, "Plan", "Ship it").unwrap();
+    let note_id = note.id.0.to_string();
+    let mut task = create_task(&conn, space, "Review", None).unwrap();
+    let ana = ActorContext::local("ana");
+    let ben = ActorContext::local("ben");
+
+    acquire_edit_lock(&conn, "ana", &note_id, 300).unwrap();
+    acquire_edit_lock(&conn, "ana", &task.id.to_string(), 300).unwrap();
+
+    match update_note_content_as(&mut conn, &ben, note.id.clone(), "Plan", "Scrap it") {
+        Err(DbError::LockedByOther(LockedByOther { holder })) => {
+            assert_eq!(holder.user_id, "ana");
+            assert_eq!(holder.entity_id, note_id);
+        }
+        other => panic!("expected LockedByOther, got {:?}", other),
+    }
+    task.title = "Skip review".to_string();
+    assert!(matches!(
+        update_task_as(&conn, &ben, &task),
+        Err(DbError::LockedByOther(_))
+    ));
+    assert_eq!(get_task(&conn, task.id).unwrap().unwrap().title, "Review");
+
+    // The holder edits freely
+    update_note_content_as(&mut conn, &ana, note.id.clone(), "Plan", "Ship it today").unwrap();
+
+    // Once released, Ben can edit again
+    release_edit_lock(&conn, "ana", &note_id).unwrap();
+    update_note_content_as(&mut conn, &ben, note.id, "Plan", "Scrap it for real").unwrap();
+}
+
+#[test]
+fn test_edit_locks_do_not_hold_back_synced_changes() {
+    let (mut conn, space_id) = setup_shared_space();
+    init_sync_tables(&conn).unwrap();
+    let space = Ulid::from_string(&space_id).unwrap();
+    let note = create_note(&conn, &space_id, "Plan", "Ship it").unwrap();
+    let note_id = note.id.0.to_string();
+    let task = create_task(&conn, space, "Review", None).unwrap();
+    let task_id = task.id.to_string();
+
+    // Ben's phone last synced an hour ago and nothing changed here since,
+    // so its edits apply without a conflict
+    let desktop = SyncAgent::new("desktop".into(), "Desktop".into(), 0);
+    let phone = SyncAgent::new("ben-phone".into(), "Phone".into(), 0);
+    desktop
+        .register_device(&conn, &phone.get_device_info())
+        .unwrap();
+    set_device_user(&conn, "ben-phone", Some("ben")).unwrap();
+    let last_sync = now() - 3600;
+    conn.execute(
+        "UPDATE note SET modified_at = ?1 WHERE id = ?2",
+        rusqlite::params![last_sync - 100, note_id],
+    )
+    .unwrap();
+    conn.execute(
+        "UPDATE task SET updated_at = ?1 WHERE id = ?2",
+        rusqlite::params![last_sync - 100, task_id],
+    )
+    .unwrap();
+    conn.execute(
+        "INSERT INTO sync_history (id, device_id, space_id, sync_time, direction, success)
+         VALUES (?1, 'ben-phone', ?2, ?3, 'pull', 1)",
+        rusqlite::params![Ulid::new().to_string(), space_id, last_sync],
+    )
+    .unwrap();
+
+    // Ana's locks exist in this vault only; the phone never saw them
+    acquire_edit_lock(&conn, "ana", &note_id, 300).unwrap();
+    acquire_edit_lock(&conn, "ana", &task_id, 300).unwrap();
+
+    let delta = |entity_type: &str, entity_id: &str, data: Vec<u8>| SyncDelta {
+        entity_type: entity_type.to_string(),
+        entity_id: entity_id.to_string(),
+        operation: SyncOperation::Update,
+        data: Some(data),
+        timestamp: now(),
+        vector_clock: HashMap::new(),
+        space_id: Some(space_id.clone()),
+    };
+    let deltas = vec![
+        delta("note", &note_id, b"Scrap it".to_vec()),
+        delta(
+            "task",
+            &task_id,
+            serde_json::to_vec(&serde_json::json!({ "title": "Skip review", "status": "inbox" }))
+                .unwrap(),
+        ),
+    ];
+    let conflicts = desktop
+        .apply_deltas_from(&mut conn, deltas, &[0u8; 32], "ben-phone")
+        .unwrap();
+    assert!(conflicts.is_empty());
+
+    assert_eq!(
+        get_note(&conn, note.id).unwrap().unwrap().content_md,
+        "Scrap it"
+    );
+    assert_eq!(
+        get_task(&conn, task.id).unwrap().unwrap().title,
+        "Skip review"
+    );
+    // The locks themselves are untouched
+    assert_eq!(
+        get_edit_lock(&conn, &note_id).unwrap().unwrap().user_id,
+        "ana"
+    );
+}
@@ -0,0 +1,244 @@
+use core_rs::db::migrate;
+use core_rs::events::{
+    dropped_events, emit, register_sink, unregister_sink, CollectingSink, CoreEvent, EmittedEvent,
+    EventSeverity, EventSink, EVENT_QUEUE_CAPACITY,
+};
+use core_rs::ocr::*;
+use core_rs::sync::ConflictType;
+use core_rs::sync_agent::{SyncAgent, SyncDelta, SyncOperation};
+use core_rs::vault::AutoLockGuard;
+use rusqlite::Connection;
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+use std::time::Duration;
+use ulid::Ulid;
+
+// The bus is shared by every test in this binary, so each test only looks
+// at events carrying its own ids, and holds BUS_LOCK so the flood in
+// test_full_queue_drops_oldest_events can't push out another test's event
+
+static BUS_LOCK: Mutex<()> = Mutex::new(());
+
+const WAIT: Duration = Duration::from_secs(5);
+const TEST_MK: &[u8] = b"test-master-key-that-is-32-bytes";
+
+fn lock_bus() -> MutexGuard<'static, ()> {
+    BUS_LOCK.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+fn collecting_sink() -> Arc<CollectingSink> {
+    let sink = Arc::new(CollectingSink::default());
+    register_sink(sink.clone());
+    sink
+}
+
+struct EchoEngine;
+
+impl OcrEngine for EchoEngine {
+    fn recognize(&self, image_path: &Path, _languages: &[String]) -> Result<OcrOutput, OcrError> {
+        Ok(OcrOutput {
+            text: std::fs::read_to_string(image_path)?,
+            ..Default::default()
+        })
+    }
+}
+
+/// Holds the dispatcher in `deliver` until opened
+#[derive(Default)]
+struct GateSink {
+    open: Mutex<bool>,
+    opened: Condvar,
+}
+
+impl GateSink {
+    fn open(&self) {
+        *self.open.lock().unwrap() = true;
+        self.opened.notify_all();
+    }
+}
+
+impl EventSink for GateSink {
+    fn deliver(&self, _event: &EmittedEvent) {
+        let mut open = self.open.lock().unwrap();
+        while !*open {
+            open = self.opened.wait(open).unwrap();
+        }
+    }
+}
+
+#[test]
+fn test_sync_conflict_emits_event_after_commit() {
+    let _bus = lock_bus();
+    let sink = collecting_sink();
+    let mut conn = Connection::open_in_memory().unwrap();
+    migrate(&mut conn).unwrap();
+    core_rs::sync_agent::init_sync_tables(&conn).unwrap();
+    let agent = SyncAgent::new("device_local".into(), "Local".into(), 0);
+
+    let space_id = Ulid::new().to_string();
+    conn.execute(
+        "INSERT INTO space (id, name) VALUES (?1, ?2)",
+        rusqlite::params![space_id, "Test Space"],
+    )
+    .unwrap();
+    let last_sync = chrono::Utc::now().timestamp() - 3600;
+    conn.execute(
+        "INSERT INTO sync_history (id, device_id, space_id, sync_time, direction, entities_pushed, entities_pulled, conflicts_detected, success) VALUES (?1, ?2, ?3, ?4, 'pull', 1, 0, 0, 1)",
+        rusqlite::params![Ulid::new().to_string(), "device_remote", space_id, last_sync],
+    )
+    .unwrap();
+
+    // Both sides changed the task since the last sync
+    let task_id = Ulid::new().to_string();
+    conn.execute(
+        "INSERT INTO task (id, space_id, title, status, updated_at) VALUES (?1, ?2, ?3, 'inbox', ?4)",
+        rusqlite::params![task_id, space_id, "Local title", last_sync + 100],
+    )
+    .unwrap();
+    let delta = SyncDelta {
+        entity_type: "task".to_string(),
+        entity_id: task_id.clone(),
+        operation: SyncOperation::Update,
+        data: Some(
+            serde_json::json!({"title": "Remote title", "status": "done"})
+                .to_string()
+                .into_bytes(),
+        ),
+        timestamp: last_sync + 200,
+        vector_clock: HashMap::new(),
+        space_id: Some(space_id.clone()),
+    };
+    let conflicts = agent
+        .apply_deltas_from(&mut conn, vec![delta], &[0u8; 32], "device_remote")
+        .unwrap();
+    assert_eq!(conflicts.len(), 1);
+
+    let event = sink
+        .wait_for(WAIT, |e| e.space_id() == Some(space_id.as_str()))
+        .expect("no conflict event");
+    assert_eq!(event.severity, EventSeverity::Warning);
+    match event.event {
+        CoreEvent::SyncConflictDetected {
+            conflict_id,
+            device_id,
+            entity_type,
+            entity_id,
+            conflict_type,
+            ..
+        } => {
+            assert_eq!(device_id, "device_remote");
+            assert_eq!(entity_type, "task");
+            assert_eq!(entity_id, task_id);
+            assert_eq!(conflict_type, ConflictType::UpdateUpdate);
+            let stored: i64 = conn
+                .query_row(
+                    "SELECT COUNT(*) FROM sync_conflict WHERE id = ?1",
+                    [&conflict_id],
+                    |row| row.get(0),
+                )
+                .unwrap();
+            assert_eq!(stored, 1);
+        }
+        other => panic!("unexpected event {:?}", other),
+    }
+}
+
+#[test]
+fn test_ocr_completion_emits_event() {
+    let _bus = lock_bus();
+    let sink = collecting_sink();
+    let dir = tempfile::tempdir().unwrap();
+    let manager = r2d2_sqlite::SqliteConnectionManager::file(dir.path().join("ocr.db"));
+    let pool = r2d2::Pool::builder().max_size(2).build(manager).unwrap();
+    let blob_id = {
+        let conn = pool.get().unwrap();
+        conn.execute("CREATE TABLE blob (id TEXT PRIMARY KEY)", [])
+            .unwrap();
+        init_ocr_tables(&conn).unwrap();
+        let blob_id =
+            core_rs::blob::store_blob(dir.path().to_str().unwrap(), TEST_MK, b"receipt").unwrap();
+        conn.execute("INSERT INTO blob (id) VALUES (?1)", [&blob_id])
+            .unwrap();
+        queue_ocr(&conn, &blob_id, &[]).unwrap();
+        blob_id
+    };
+
+    let config = OcrWorkerConfig::new(
+        dir.path(),
+        Arc::new(AutoLockGuard::new(TEST_MK.try_into().unwrap())),
+    );
+    let worker = OcrWorker::new(pool, Arc::new(EchoEngine), config);
+    let (job, outcome) = worker.process_next().unwrap().unwrap();
+    assert_eq!(outcome, OcrJobOutcome::Completed);
+
+    let event = sink
+        .wait_for(
+            WAIT,
+            |e| matches!(e, CoreEvent::OcrJobCompleted { blob_id: b, .. } if *b == blob_id),
+        )
+        .expect("no OCR event");
+    assert_eq!(event.severity, EventSeverity::Info);
+    assert_eq!(
+        event.event,
+        CoreEvent::OcrJobCompleted {
+            job_id: job.id,
+            blob_id,
+        }
+    );
+}
+
+#[test]
+fn test_full_queue_drops_oldest_events() {
+    let _bus = lock_bus();
+    let gate = Arc::new(GateSink::default());
+    let gate_id = register_sink(gate.clone());
+    let sink = collecting_sink();
+    let tag = Ulid::new().to_string();
+    let before = dropped_events();
+
+    let total = EVENT_QUEUE_CAPACITY * 2;
+    for i in 0..total {
+        emit(CoreEvent::OcrJobFailed {
+            job_id: format!("{}-{}", tag, i),
+            blob_id: tag.clone(),
+            error: "test".into(),
+        });
+    }
+    // At most one event is out of the queue, held by the gate
+    assert!(dropped_events() - before >= (total - EVENT_QUEUE_CAPACITY - 1) as u64);
+    gate.open();
+    unregister_sink(gate_id);
+
+    let last = format!("{}-{}", tag, total - 1);
+    sink.wait_for(
+        WAIT,
+        |e| matches!(e, CoreEvent::OcrJobFailed { job_id, .. } if *job_id == last),
+    )
+    .expect("newest event was dropped");
+    let ours = sink
+        .events()
+        .into_iter()
+        .filter(|e| matches!(&e.event, CoreEvent::OcrJobFailed { blob_id, .. } if *blob_id == tag))
+        .count();
+    assert!(ours <= EVENT_QUEUE_CAPACITY + 1);
+}
+
+#[test]
+fn test_event_serializes_with_kind_and_severity() {
+    let event = EmittedEvent {
+        id: "01J".into(),
+        emitted_at: 1_700_000_000_000,
+        severity: EventSeverity::Error,
+        event: CoreEvent::CaldavAuthFailed {
+            account_id: "acc".into(),
+            username: "me".into(),
+            url: "https://dav.example.com".into(),
+        },
+    };
+    let json = serde_json::to_value(&event).unwrap();
+    assert_eq!(json["kind"], "caldav_auth_failed");
+    assert_eq!(json["severity"], "error");
+    assert_eq!(json["account_id"], "acc");
+    assert_eq!(serde_json::from_value::<EmittedEvent>(json).unwrap(), event);
+}
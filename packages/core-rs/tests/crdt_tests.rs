@@ -1,4 +1,9 @@
-use core_rs::crdt::{apply_update, get_text, get_update, insert_text};
+use core_rs::crdt::{apply_update, get_text, get_update, insert_text, NOTE_CRDT_ENTITY_TYPE};
+use core_rs::db::migrate;
+use core_rs::note::{create_note, get_note, update_note_content, DbUlid};
+use core_rs::sync_agent::{DeviceInfo, DeviceType, SyncAgent};
+use rusqlite::Connection;
+use ulid::Ulid;
 use yrs::updates::encoder::Encode;
 use yrs::{Doc, ReadTxn, Transact};
 
@@ -21,3 +26,105 @@ fn test_crdt() {
     apply_update(&mut doc1, &update2).unwrap();
     assert_eq!(get_text(&doc1), "hello world");
 }
+
+fn setup_db(space_id: Ulid) -> Connection {
+    let mut conn = Connection::open_in_memory().unwrap();
+    migrate(&mut conn).unwrap();
+    core_rs::sync_agent::init_sync_tables(&conn).unwrap();
+    conn.execute(
+        "INSERT INTO space (id, name) VALUES (?1, ?2)",
+        (space_id.to_string(), "test space"),
+    )
+    .unwrap();
+    conn
+}
+
+fn peer(device_id: &str, protocol_version: &str) -> DeviceInfo {
+    DeviceInfo {
+        device_id: device_id.to_string(),
+        device_name: device_id.to_string(),
+        device_type: DeviceType::Desktop,
+        last_seen: 0,
+        sync_address: "localhost".to_string(),
+        sync_port: 0,
+        protocol_version: protocol_version.to_string(),
+    }
+}
+
+/// Send everything `from` has to `to` over the CRDT note path
+fn exchange(from: &SyncAgent, from_conn: &Connection, to: &SyncAgent, to_conn: &mut Connection) {
+    let space_id = Ulid::from_string(
+        &from_conn
+            .query_row("SELECT id FROM space", [], |row| row.get::<_, String>(0))
+            .unwrap(),
+    )
+    .unwrap();
+    let deltas = from
+        .get_deltas_for_peer(from_conn, space_id, 0, &to.get_device_info())
+        .unwrap();
+    let conflicts = to.apply_deltas(to_conn, deltas, &[0u8; 32]).unwrap();
+    assert!(conflicts.is_empty());
+}
+
+fn note_content(conn: &Connection, id: &DbUlid) -> String {
+    get_note(conn, id.clone()).unwrap().unwrap().content_md
+}
+
+#[test]
+fn test_concurrent_note_inserts_converge() {
+    let space_id = Ulid::new();
+    let mut conn_a = setup_db(space_id);
+    let mut conn_b = setup_db(space_id);
+    let agent_a = SyncAgent::new("device_a".into(), "A".into(), 0);
+    let agent_b = SyncAgent::new("device_b".into(), "B".into(), 0);
+
+    let note = create_note(&conn_a, &space_id.to_string(), "Shared", "hello world").unwrap();
+    exchange(&agent_a, &conn_a, &agent_b, &mut conn_b);
+    assert_eq!(note_content(&conn_b, &note.id), "hello world");
+
+    // Both devices insert at the same offset before hearing from each other
+    update_note_content(&mut conn_a, note.id.clone(), "Shared", "hello brave world").unwrap();
+    update_note_content(&mut conn_b, note.id.clone(), "Shared", "hello new world").unwrap();
+
+    exchange(&agent_a, &conn_a, &agent_b, &mut conn_b);
+    exchange(&agent_b, &conn_b, &agent_a, &mut conn_a);
+
+    let merged_a = note_content(&conn_a, &note.id);
+    let merged_b = note_content(&conn_b, &note.id);
+    assert_eq!(merged_a, merged_b);
+    assert!(
+        merged_a == "hello brave new world" || merged_a == "hello new brave world",
+        "unexpected merge: {merged_a}"
+    );
+
+    let conflicts: i64 = conn_a
+        .query_row("SELECT COUNT(*) FROM sync_conflict", [], |row| row.get(0))
+        .unwrap();
+    assert_eq!(conflicts, 0);
+
+    // Exchanging again changes nothing
+    exchange(&agent_a, &conn_a, &agent_b, &mut conn_b);
+    assert_eq!(note_content(&conn_b, &note.id), merged_a);
+}
+
+#[test]
+fn test_legacy_peer_gets_whole_body_deltas() {
+    let space_id = Ulid::new();
+    let conn = setup_db(space_id);
+    let agent = SyncAgent::new("device_a".into(), "A".into(), 0);
+    create_note(&conn, &space_id.to_string(), "Note", "plain body").unwrap();
+
+    let legacy = agent
+        .get_deltas_for_peer(&conn, space_id, 0, &peer("old", "2.0.0"))
+        .unwrap();
+    let note_delta = legacy.iter().find(|d| d.entity_type == "note").unwrap();
+    assert_eq!(note_delta.data.as_deref(), Some("plain body".as_bytes()));
+
+    let current = agent
+        .get_deltas_for_peer(&conn, space_id, 0, &peer("new", "2.1.0"))
+        .unwrap();
+    assert!(current
+        .iter()
+        .any(|d| d.entity_type == NOTE_CRDT_ENTITY_TYPE));
+    assert!(!current.iter().any(|d| d.entity_type == "note"));
+}
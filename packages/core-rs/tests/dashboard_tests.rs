@@ -1,11 +1,16 @@
-use core_rs::dashboard;
+use core_rs::dashboard::{self, ComparisonWindow, DashboardConfig, PeriodTrend};
 use core_rs::db;
+use core_rs::mode::{enable_mode, Mode};
 use core_rs::space;
 use core_rs::task;
-use rusqlite::Connection;
+use rusqlite::{params, Connection};
 use tempfile::tempdir;
 use ulid::Ulid;
 
+const DAY: i64 = 86_400;
+/// Fixed "now" for the seeded trend data
+const NOW: i64 = 1_700_000_000;
+
 fn setup_db() -> (Connection, tempfile::TempDir) {
     let temp_dir = tempdir().unwrap();
     let db_path = temp_dir.path().join("test.db");
@@ -14,21 +19,39 @@ fn setup_db() -> (Connection, tempfile::TempDir) {
     (conn, temp_dir)
 }
 
+fn enable(conn: &Connection, space_id: &str, mode_ids: &[&str]) {
+    for id in mode_ids {
+        let mode = Mode {
+            id: id.to_string(),
+            name: id.to_string(),
+        };
+        enable_mode(conn, space_id, &mode).unwrap();
+    }
+}
+
 #[test]
 fn test_get_dashboard_stats_empty() {
     let (mut conn, _dir) = setup_db();
     let space_id = space::create_space(&mut conn, "Dashboard Space").unwrap();
 
-    let stats = dashboard::get_dashboard_stats(&conn, &space_id.to_string()).unwrap();
+    let space_str = space_id.to_string();
+    enable(&conn, &space_str, &["health", "music", "social"]);
+
+    let stats = dashboard::get_dashboard_stats(&conn, &space_str).unwrap();
 
-    assert_eq!(stats.health.metrics_count, 0);
-    assert!(stats.health.latest_metric.is_none());
-    assert_eq!(stats.music.track_count, 0);
-    assert_eq!(stats.music.playlist_count, 0);
-    assert_eq!(stats.social.posts_count, 0);
-    assert_eq!(stats.social.platforms_count, 0);
+    let health = stats.health.unwrap();
+    assert_eq!(health.metrics_count, 0);
+    assert!(health.latest_metric.is_none());
+    let music = stats.music.unwrap();
+    assert_eq!(music.track_count, 0);
+    assert_eq!(music.playlist_count, 0);
+    let social = stats.social.unwrap();
+    assert_eq!(social.posts_count, 0);
+    assert_eq!(social.platforms_count, 0);
+    assert_eq!(social.new_posts, PeriodTrend::default());
     assert_eq!(stats.tasks.pending_count, 0);
     assert_eq!(stats.tasks.completed_count, 0);
+    assert_eq!(stats.window_days, 7);
 }
 
 #[test]
@@ -36,6 +59,7 @@ fn test_get_dashboard_stats_with_data() {
     let (mut conn, _dir) = setup_db();
     let space_id = space::create_space(&mut conn, "Stats Space").unwrap();
     let space_str = space_id.to_string();
+    enable(&conn, &space_str, &["health", "music", "social"]);
 
     // 1. Add Task Data
     // Pending tasks
@@ -84,10 +108,203 @@ fn test_get_dashboard_stats_with_data() {
 
     assert_eq!(stats.tasks.pending_count, 2);
     assert_eq!(stats.tasks.completed_count, 1);
-    assert_eq!(stats.health.metrics_count, 1);
-    assert_eq!(stats.health.latest_metric, Some("weight".to_string()));
-    assert_eq!(stats.music.track_count, 1);
-    assert_eq!(stats.music.playlist_count, 1);
-    assert_eq!(stats.social.posts_count, 1);
-    assert_eq!(stats.social.platforms_count, 1);
+    let health = stats.health.unwrap();
+    assert_eq!(health.metrics_count, 1);
+    assert_eq!(health.latest_metric, Some("weight".to_string()));
+    let music = stats.music.unwrap();
+    assert_eq!(music.track_count, 1);
+    assert_eq!(music.playlist_count, 1);
+    let social = stats.social.unwrap();
+    assert_eq!(social.posts_count, 1);
+    assert_eq!(social.platforms_count, 1);
+}
+
+/// Two weeks of activity before `NOW`, plus rows outside both weeks that
+/// must not count. Everything sits mid-day, away from the period bounds.
+fn seed_two_weeks(conn: &mut Connection) -> String {
+    let space_id = space::create_space(conn, "Trends").unwrap();
+    let space_str = space_id.to_string();
+    let days_ago = |days: i64| NOW - days * DAY - DAY / 2;
+
+    // Notes: 6 this week, 4 the week before
+    for days in [0, 1, 2, 3, 5, 6, 7, 8, 10, 13, 20] {
+        let note = core_rs::note::create_note(conn, &space_str, "Note", "").unwrap();
+        conn.execute(
+            "UPDATE note SET created_at = ?1 WHERE id = ?2",
+            params![days_ago(days), note.id.to_string()],
+        )
+        .unwrap();
+    }
+
+    // Tasks completed: 3 this week, 6 the week before, one still pending
+    for days in [1, 2, 4, 7, 8, 9, 10, 11, 12] {
+        let mut t = task::create_task(conn, space_id, "Task", None).unwrap();
+        t.status = "done".to_string();
+        t.completed_at = Some(days_ago(days));
+        task::update_task(conn, &t).unwrap();
+    }
+    let pending = task::create_task(conn, space_id, "Pending", None).unwrap();
+
+    // Time tracked: an hour this week, nothing finished the week before
+    for (days, seconds, running) in [(1, 1800, 0), (3, 1800, 0), (9, 600, 1)] {
+        conn.execute(
+            "INSERT INTO time_entry (id, space_id, task_id, started_at, ended_at,
+                                     duration_seconds, is_running)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+            params![
+                Ulid::new().to_string(),
+                space_str,
+                pending.id.to_string(),
+                days_ago(days),
+                days_ago(days) + seconds,
+                seconds,
+                running
+            ],
+        )
+        .unwrap();
+    }
+
+    // Habit completions: 5 in each week
+    let habit = core_rs::habits::create_habit(conn, space_id, "Run", "daily").unwrap();
+    for days in [0, 1, 2, 3, 4, 7, 8, 9, 10, 11, 30] {
+        conn.execute(
+            "INSERT INTO habit_log (id, habit_id, completed_at) VALUES (?1, ?2, ?3)",
+            params![
+                Ulid::new().to_string(),
+                habit.id.to_string(),
+                days_ago(days)
+            ],
+        )
+        .unwrap();
+    }
+
+    // SRS cards: 2 came due this week, 1 the week before, 1 due tomorrow
+    let deck = core_rs::note::create_note(conn, &space_str, "Deck", "").unwrap();
+    for due_at in [days_ago(1), days_ago(4), days_ago(12), NOW + DAY] {
+        let card = core_rs::srs::create_knowledge_card(conn, deck.id.0).unwrap();
+        conn.execute(
+            "UPDATE knowledge_card SET due_at = ?1 WHERE id = ?2",
+            params![due_at, card.id.to_string()],
+        )
+        .unwrap();
+    }
+
+    // Social posts: 4 arrived this week, 2 the week before
+    let account_id = Ulid::new().to_string();
+    conn.execute(
+        "INSERT INTO social_account (id, space_id, platform, username, created_at,
+                                     encrypted_credentials)
+         VALUES (?1, ?2, 'twitter', 'me', 0, 'creds')",
+        params![account_id, space_str],
+    )
+    .unwrap();
+    for days in [0, 1, 2, 6, 8, 9] {
+        let seen_at = days_ago(days) * 1000;
+        conn.execute(
+            "INSERT INTO social_post (id, account_id, platform, author, timestamp, fetched_at,
+                                      first_seen_at, raw_json)
+             VALUES (?1, ?2, 'twitter', 'alice', ?3, ?3, ?3, '{}')",
+            params![Ulid::new().to_string(), account_id, seen_at],
+        )
+        .unwrap();
+    }
+
+    space_str
+}
+
+#[test]
+fn test_trends_compare_with_previous_week() {
+    let (mut conn, _dir) = setup_db();
+    let space_id = seed_two_weeks(&mut conn);
+    enable(&conn, &space_id, &["habits", "srs", "social"]);
+
+    let stats =
+        dashboard::get_dashboard_stats_at(&conn, &space_id, ComparisonWindow::Week, NOW).unwrap();
+    assert_eq!(stats.window_days, 7);
+    assert_eq!(stats.notes.created, PeriodTrend::new(6, 4));
+    assert_eq!(stats.notes.created.delta_percent, Some(50.0));
+    assert_eq!(stats.tasks.completed, PeriodTrend::new(3, 6));
+    assert_eq!(stats.tasks.completed.delta_percent, Some(-50.0));
+    assert_eq!(stats.tasks.pending_count, 1);
+    assert_eq!(stats.time.tracked_seconds.current, 3600);
+    assert_eq!(stats.time.tracked_seconds.previous, 0);
+    assert_eq!(stats.time.tracked_seconds.delta_percent, None);
+
+    let habits = stats.habits.unwrap();
+    assert_eq!(habits.habits_count, 1);
+    assert_eq!(habits.completed, PeriodTrend::new(5, 5));
+    assert_eq!(habits.completed.delta_percent, Some(0.0));
+    assert_eq!(stats.srs.unwrap().cards_due.delta_percent, Some(100.0));
+    let social = stats.social.unwrap();
+    assert_eq!(social.new_posts, PeriodTrend::new(4, 2));
+    assert_eq!(social.posts_count, 6);
+
+    // A month compares everything seeded against an empty month before it
+    let monthly =
+        dashboard::get_dashboard_stats_at(&conn, &space_id, ComparisonWindow::Month, NOW).unwrap();
+    assert_eq!(monthly.window_days, 30);
+    assert_eq!(monthly.notes.created, PeriodTrend::new(11, 0));
+    assert_eq!(monthly.habits.unwrap().completed, PeriodTrend::new(10, 1));
+}
+
+#[test]
+fn test_disabled_modes_are_left_out() {
+    let (mut conn, _dir) = setup_db();
+    let space_id = seed_two_weeks(&mut conn);
+
+    // Tables of disabled modes are never queried
+    conn.execute_batch(
+        "ALTER TABLE habit_log RENAME TO habit_log_hidden;
+         ALTER TABLE track RENAME TO track_hidden;",
+    )
+    .unwrap();
+    let stats =
+        dashboard::get_dashboard_stats_at(&conn, &space_id, ComparisonWindow::Week, NOW).unwrap();
+    assert!(stats.health.is_none());
+    assert!(stats.habits.is_none());
+    assert!(stats.srs.is_none());
+    assert!(stats.music.is_none());
+    assert!(stats.social.is_none());
+    assert_eq!(stats.notes.created.current, 6);
+
+    let json = serde_json::to_value(&stats).unwrap();
+    assert!(json["habits"].is_null());
+
+    enable(&conn, &space_id, &["habits"]);
+    assert!(
+        dashboard::get_dashboard_stats_at(&conn, &space_id, ComparisonWindow::Week, NOW).is_err()
+    );
+}
+
+#[test]
+fn test_dashboard_config_picks_window() {
+    let (mut conn, _dir) = setup_db();
+    let space_id = space::create_space(&mut conn, "Config")
+        .unwrap()
+        .to_string();
+
+    assert_eq!(
+        dashboard::get_dashboard_config(&conn).unwrap(),
+        DashboardConfig::default()
+    );
+    let monthly = DashboardConfig {
+        comparison_window: ComparisonWindow::Month,
+    };
+    dashboard::set_dashboard_config(&conn, &monthly).unwrap();
+    assert_eq!(dashboard::get_dashboard_config(&conn).unwrap(), monthly);
+    assert_eq!(
+        dashboard::get_dashboard_stats(&conn, &space_id)
+            .unwrap()
+            .window_days,
+        30
+    );
+
+    db::set_setting(
+        &conn,
+        "dashboard.config",
+        "{\"comparison_window\":\"year\"}",
+        None,
+    )
+    .unwrap();
+    assert!(dashboard::get_dashboard_config(&conn).is_err());
 }
@@ -0,0 +1,185 @@
+use core_rs::db::{EncryptedConnectionManager, PoolConfig, PoolError};
+use core_rs::vault::AutoLockGuard;
+use r2d2::{ManageConnection, Pool};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+use tempfile::{tempdir, TempDir};
+
+const WRITERS: usize = 8;
+const ROWS_PER_WRITER: usize = 50;
+
+fn setup_pool(
+    config: PoolConfig,
+) -> (
+    TempDir,
+    Pool<EncryptedConnectionManager>,
+    Arc<AutoLockGuard>,
+) {
+    let dir = tempdir().unwrap();
+    let guard = Arc::new(AutoLockGuard::new([7u8; 32]));
+    let manager =
+        EncryptedConnectionManager::with_config(dir.path().join("vault.db"), guard.clone(), config);
+    let pool = Pool::builder()
+        .max_size(WRITERS as u32)
+        .build(manager)
+        .unwrap();
+    pool.get()
+        .unwrap()
+        .execute_batch(
+            "CREATE TABLE parent (id INTEGER PRIMARY KEY);
+             CREATE TABLE item (
+                 id INTEGER PRIMARY KEY,
+                 parent_id INTEGER NOT NULL REFERENCES parent(id) ON DELETE CASCADE,
+                 payload TEXT NOT NULL
+             );
+             INSERT INTO parent (id) VALUES (1);",
+        )
+        .unwrap();
+    (dir, pool, guard)
+}
+
+/// Never checkpoint on checkout, so the test decides when it happens
+fn manual_checkpoints() -> PoolConfig {
+    PoolConfig {
+        wal_checkpoint_bytes: u64::MAX,
+        checkpoint_interval: Duration::from_secs(3600),
+        ..PoolConfig::default()
+    }
+}
+
+fn hammer(pool: &Pool<EncryptedConnectionManager>) {
+    let handles: Vec<_> = (0..WRITERS)
+        .map(|writer| {
+            let pool = pool.clone();
+            thread::spawn(move || {
+                for i in 0..ROWS_PER_WRITER {
+                    let mut conn = pool.get().unwrap();
+                    // Each write is its own transaction, competing for the lock
+                    let tx = conn.transaction().unwrap();
+                    tx.execute(
+                        "INSERT INTO item (parent_id, payload) VALUES (1, ?1)",
+                        [format!("{}-{}-{}", writer, i, "x".repeat(512))],
+                    )
+                    .unwrap();
+                    let _count: i64 = tx
+                        .query_row("SELECT COUNT(*) FROM item", [], |row| row.get(0))
+                        .unwrap();
+                    tx.commit().unwrap();
+                }
+            })
+        })
+        .collect();
+    for handle in handles {
+        handle.join().unwrap();
+    }
+}
+
+#[test]
+fn test_concurrent_writers_do_not_hit_busy_errors() {
+    let (_dir, pool, _guard) = setup_pool(manual_checkpoints());
+    hammer(&pool);
+
+    let count: i64 = pool
+        .get()
+        .unwrap()
+        .query_row("SELECT COUNT(*) FROM item", [], |row| row.get(0))
+        .unwrap();
+    assert_eq!(count, (WRITERS * ROWS_PER_WRITER) as i64);
+
+    // Every pooled connection enforces foreign keys
+    let connections: Vec<_> = (0..WRITERS).map(|_| pool.get().unwrap()).collect();
+    for conn in &connections {
+        let foreign_keys: i64 = conn
+            .query_row("PRAGMA foreign_keys", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(foreign_keys, 1);
+    }
+    connections[0]
+        .execute("DELETE FROM parent WHERE id = 1", [])
+        .unwrap();
+    let count: i64 = connections[1]
+        .query_row("SELECT COUNT(*) FROM item", [], |row| row.get(0))
+        .unwrap();
+    assert_eq!(count, 0);
+}
+
+#[test]
+fn test_checkpoint_truncates_the_wal() {
+    let (dir, pool, _guard) = setup_pool(manual_checkpoints());
+    let manager = EncryptedConnectionManager::with_config(
+        dir.path().join("vault.db"),
+        Arc::new(AutoLockGuard::new([7u8; 32])),
+        manual_checkpoints(),
+    );
+    let monitor = manager.monitor();
+    let pool_b = Pool::builder().max_size(2).build(manager).unwrap();
+    hammer(&pool);
+
+    let before = monitor.health(&pool_b);
+    assert!(before.wal_size_bytes > 100 * 1024, "{:?}", before);
+    assert_eq!(before.last_checkpoint, None);
+
+    let conn = pool_b.get().unwrap();
+    assert!(monitor.checkpoint(&conn).unwrap());
+    drop(conn);
+
+    let after = monitor.health(&pool_b);
+    assert_eq!(after.wal_size_bytes, 0);
+    assert!(after.last_checkpoint.is_some());
+    assert!(after.connections >= 1);
+    assert_eq!(after.idle, after.connections);
+}
+
+#[test]
+fn test_wal_over_threshold_is_checkpointed_on_checkout() {
+    let config = PoolConfig {
+        wal_checkpoint_bytes: 64 * 1024,
+        checkpoint_interval: Duration::from_secs(3600),
+        ..PoolConfig::default()
+    };
+    let dir = tempdir().unwrap();
+    let manager = EncryptedConnectionManager::with_config(
+        dir.path().join("vault.db"),
+        Arc::new(AutoLockGuard::new([7u8; 32])),
+        config,
+    );
+    let monitor = manager.monitor();
+    let pool = Pool::builder().max_size(2).build(manager).unwrap();
+    {
+        let conn = pool.get().unwrap();
+        conn.execute_batch("CREATE TABLE blob_data (payload BLOB NOT NULL)")
+            .unwrap();
+        for _ in 0..64 {
+            conn.execute(
+                "INSERT INTO blob_data (payload) VALUES (zeroblob(4096))",
+                [],
+            )
+            .unwrap();
+        }
+    }
+    assert!(monitor.wal_size_bytes() > 64 * 1024);
+
+    // The next checkout notices the WAL size and checkpoints it
+    let conn = pool.get().unwrap();
+    assert_eq!(monitor.wal_size_bytes(), 0);
+    assert!(monitor.last_checkpoint().is_some());
+    let count: i64 = conn
+        .query_row("SELECT COUNT(*) FROM blob_data", [], |row| row.get(0))
+        .unwrap();
+    assert_eq!(count, 64);
+}
+
+#[test]
+fn test_locked_vault_refuses_connections() {
+    let (dir, pool, guard) = setup_pool(manual_checkpoints());
+    drop(pool.get().unwrap());
+
+    guard.lock();
+    let manager = EncryptedConnectionManager::with_config(
+        dir.path().join("vault.db"),
+        guard,
+        manual_checkpoints(),
+    );
+    assert!(matches!(manager.connect(), Err(PoolError::Locked)));
+}
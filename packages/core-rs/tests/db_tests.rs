@@ -26,15 +26,31 @@ fn test_migrations() -> Result<(), DbError> {
         tables,
         vec![
             "audit_log",
+            "auth_attempt",
+            "automation_rule",
+            "backup_run",
+            "blob",
+            "blob_pending_sweep",
+            "blob_ref",
+            "budget",
             "calendar_event",
+            "dashboard_stats",
             "entity_sync_log",
+            "form_submission",
             "form_template",
+            "form_template_version",
             "fts_note",
             "fts_note_config",
             "fts_note_content",
             "fts_note_data",
             "fts_note_docsize",
             "fts_note_idx",
+            "fts_ocr",
+            "fts_ocr_config",
+            "fts_ocr_content",
+            "fts_ocr_data",
+            "fts_ocr_docsize",
+            "fts_ocr_idx",
             "fts_project",
             "fts_project_config",
             "fts_project_content",
@@ -48,16 +64,44 @@ fn test_migrations() -> Result<(), DbError> {
             "fts_task_docsize",
             "fts_task_idx",
             "goal",
+            "graph_change_log",
+            "graph_node_metric",
+            "graph_summary",
             "habit",
             "habit_log",
+            "health_goal",
             "health_metric",
+            "health_metric_setting",
+            "inbox_item",
             "insight",
+            "itinerary_item",
             "knowledge_card",
             "link",
             "llm_cache",
+            "llm_cache_namespace",
+            "meeting_action",
             "note",
+            "note_attachment",
+            "note_edit_session",
+            "note_embeddings",
+            "note_embeddings_fts",
+            "note_embeddings_fts_config",
+            "note_embeddings_fts_data",
+            "note_embeddings_fts_docsize",
+            "note_embeddings_fts_idx",
+            "note_merge",
+            "note_move",
             "note_meta",
+            "note_minhash",
+            "note_property",
+            "note_reference",
             "note_tags",
+            "note_version",
+            "note_word_delta",
+            "ocr_result",
+            "packing_item",
+            "packing_template",
+            "packing_template_item",
             "person",
             "playlist",
             "playlist_track",
@@ -66,17 +110,29 @@ fn test_migrations() -> Result<(), DbError> {
             "project_milestone",
             "project_risk",
             "project_update",
+            "property_definition",
+            "rag_dirty_note",
+            "rag_index_state",
             "recipe",
+            "recipe_ingredient",
             "review_log",
+            "review_session",
+            "review_session_card",
+            "review_streak",
             "saved_search",
             "schema_version",
             "sessions",
             "settings",
+            "shopping_list",
+            "shopping_list_item",
             "social_account",
             "social_auto_rule",
+            "social_automation_firing",
             "social_automation_rule",
             "social_category",
+            "social_category_rule",
             "social_focus_mode",
+            "social_platform_usage",
             "social_post",
             "social_post_archive",
             "social_post_category",
@@ -86,22 +142,34 @@ fn test_migrations() -> Result<(), DbError> {
             "social_post_fts_data",
             "social_post_fts_docsize",
             "social_post_fts_idx",
+            "social_post_note",
+            "social_post_topic",
             "social_sync_history",
+            "social_time_limit",
+            "social_topic_cluster",
+            "social_usage_settings",
             "social_webview_session",
             "space",
             "space_people",
+            "sqlite_sequence",
             "sync_conflict",
             "sync_history",
+            "sync_relay_chunk",
+            "sync_transfer_checkpoint",
+            "sync_transfer_stats",
             "tag",
             "task",
             "task_people",
             "task_recur_exdate",
             "task_tags",
+            "task_view",
             "time_entry",
             "track",
             "transaction_log",
             "trip",
-            "users"
+            "url_metadata",
+            "users",
+            "weekly_review"
         ]
     );
 
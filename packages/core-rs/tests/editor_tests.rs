@@ -1,4 +1,11 @@
-use core_rs::editor::{generate_block_id, parse_markdown, serialize_markdown};
+use core_rs::db::migrate;
+use core_rs::editor::{
+    apply_autofixes, generate_block_id, parse_markdown, serialize_markdown, validate_note_content,
+    Autofix, Diagnostic, DiagnosticKind, Severity,
+};
+use core_rs::note::create_note;
+use core_rs::space::create_space;
+use rusqlite::Connection;
 use ulid::Ulid;
 
 #[test]
@@ -31,3 +38,166 @@ fn test_markdown_serialization() {
     let content = serialize_markdown(&blocks);
     assert_eq!(content, "hello\nworld\n");
 }
+
+fn setup_db() -> (Connection, String) {
+    let mut conn = Connection::open_in_memory().unwrap();
+    migrate(&mut conn).unwrap();
+    let space_id = create_space(&mut conn, "Work").unwrap().to_string();
+    for title in ["Q3 Roadmap", "Meeting Notes", "Hiring Plan"] {
+        create_note(&conn, &space_id, title, "").unwrap();
+    }
+    (conn, space_id)
+}
+
+fn of_kind(diagnostics: &[Diagnostic], kind: DiagnosticKind) -> Vec<&Diagnostic> {
+    diagnostics.iter().filter(|d| d.kind == kind).collect()
+}
+
+/// Apply every autofix the diagnostics offer
+fn fix_all(content: &str, diagnostics: &[Diagnostic]) -> String {
+    let fixes: Vec<Autofix> = diagnostics.iter().filter_map(|d| d.fix.clone()).collect();
+    apply_autofixes(content, &fixes).unwrap()
+}
+
+#[test]
+fn test_broken_links_get_fuzzy_suggestions() {
+    let (conn, space_id) = setup_db();
+    let scratch = create_note(&conn, &space_id, "Scratch", "").unwrap();
+    let content = format!(
+        "See [[Q3 Roadmap]], [[meeting notes|the notes]] and [[{}]].\n\
+         Typos: [[Q3 Roadmp]] and [[ Hiring Pln#Goals ]].\n\
+         Gone: [[Quarterly budget]] and [[{}]].\n\
+         `[[Not a link]]`\n",
+        scratch.id.0,
+        Ulid::new()
+    );
+
+    let diagnostics = validate_note_content(&conn, &space_id, &content).unwrap();
+    let broken = of_kind(&diagnostics, DiagnosticKind::BrokenLink);
+    assert_eq!(broken.len(), 4);
+    assert_eq!(&content[broken[0].start..broken[0].end], "[[Q3 Roadmp]]");
+    assert_eq!(broken[0].suggestions, vec!["Q3 Roadmap"]);
+    assert_eq!(broken[0].severity, Severity::Warning);
+    assert_eq!(broken[1].suggestions, vec!["Hiring Plan"]);
+    assert!(broken[2].suggestions.is_empty());
+    assert!(broken[2].fix.is_none());
+    assert!(broken[3].message.contains("No note named"));
+
+    let fixed = fix_all(&content, &diagnostics);
+    assert!(fixed.contains("Typos: [[Q3 Roadmap]] and [[ Hiring Plan#Goals ]]."));
+    let remaining = validate_note_content(&conn, &space_id, &fixed).unwrap();
+    assert_eq!(remaining.len(), 2);
+}
+
+#[test]
+fn test_fuzzy_suggestions_rank_closest_titles_first() {
+    let (conn, space_id) = setup_db();
+    create_note(&conn, &space_id, "Q2 Roadmap", "").unwrap();
+    create_note(&conn, &space_id, "Roadmap", "").unwrap();
+
+    let diagnostics = validate_note_content(&conn, &space_id, "[[Q3 Roadmaps]]").unwrap();
+    assert_eq!(
+        diagnostics[0].suggestions,
+        vec!["Q3 Roadmap", "Q2 Roadmap", "Roadmap"]
+    );
+
+    // Nothing close enough: no suggestion and no fix
+    let diagnostics = validate_note_content(&conn, &space_id, "[[Grocery list]]").unwrap();
+    assert!(diagnostics[0].suggestions.is_empty());
+}
+
+#[test]
+fn test_malformed_checkboxes() {
+    let (conn, space_id) = setup_db();
+    let content = "- [ ] fine\n- [x] done\n- []  empty\n-[ ] no space\n  * [ x] padded\n- [ ]glued\n- [link] text\n";
+
+    let diagnostics = validate_note_content(&conn, &space_id, content).unwrap();
+    let checkboxes = of_kind(&diagnostics, DiagnosticKind::MalformedCheckbox);
+    assert_eq!(checkboxes.len(), 4);
+    assert_eq!(&content[checkboxes[0].start..checkboxes[0].end], "- []  ");
+    assert_eq!(
+        fix_all(content, &diagnostics),
+        "- [ ] fine\n- [x] done\n- [ ] empty\n- [ ] no space\n  * [x] padded\n- [ ] glued\n- [link] text\n"
+    );
+}
+
+#[test]
+fn test_duplicate_headings() {
+    let (conn, space_id) = setup_db();
+    let content = "# Notes\n## Actions\ntext\n## actions ##\n```\n# Notes\n```\n### Notes\n";
+
+    let diagnostics = validate_note_content(&conn, &space_id, content).unwrap();
+    let duplicates = of_kind(&diagnostics, DiagnosticKind::DuplicateHeading);
+    assert_eq!(duplicates.len(), 2);
+    assert_eq!(
+        &content[duplicates[0].start..duplicates[0].end],
+        "## actions ##"
+    );
+    assert!(duplicates[0].message.contains("line 2"));
+    assert_eq!(
+        &content[duplicates[1].start..duplicates[1].end],
+        "### Notes"
+    );
+    assert!(duplicates[1].fix.is_none());
+}
+
+#[test]
+fn test_unclosed_code_fence() {
+    let (conn, space_id) = setup_db();
+    let closed = "```rust\nfn main() {}\n```\n~~~\n[[Missing]]\n~~~";
+    let diagnostics = validate_note_content(&conn, &space_id, closed).unwrap();
+    assert!(diagnostics.is_empty());
+
+    let content = "Intro\n```rust\nfn main() {}\n- [] not a task\n";
+    let diagnostics = validate_note_content(&conn, &space_id, content).unwrap();
+    assert_eq!(diagnostics.len(), 1);
+    assert_eq!(diagnostics[0].kind, DiagnosticKind::UnclosedCodeFence);
+    assert_eq!(diagnostics[0].severity, Severity::Error);
+    assert_eq!(
+        &content[diagnostics[0].start..diagnostics[0].end],
+        "```rust"
+    );
+    let fixed = fix_all(content, &diagnostics);
+    assert_eq!(fixed, format!("{}```\n", content));
+    assert!(validate_note_content(&conn, &space_id, &fixed)
+        .unwrap()
+        .is_empty());
+}
+
+#[test]
+fn test_images_of_missing_blobs() {
+    let (conn, space_id) = setup_db();
+    conn.execute(
+        "INSERT INTO blob (id, size_bytes, created_at) VALUES ('abc123', 10, 0)",
+        [],
+    )
+    .unwrap();
+    let content = "![chart](blob://abc123)\n![gone](blob://deadbeef)\n\
+                   ![old](noteece://blob/cafe)\n![web](https://example.com/a.png)\n";
+
+    let diagnostics = validate_note_content(&conn, &space_id, content).unwrap();
+    let missing = of_kind(&diagnostics, DiagnosticKind::MissingBlob);
+    assert_eq!(missing.len(), 2);
+    assert_eq!(
+        &content[missing[0].start..missing[0].end],
+        "![gone](blob://deadbeef)"
+    );
+    assert!(missing[1].message.contains("cafe"));
+}
+
+#[test]
+fn test_apply_autofixes_rejects_bad_ranges() {
+    let fix = |start, end, replacement: &str| Autofix {
+        start,
+        end,
+        replacement: replacement.to_string(),
+    };
+    // Order doesn't matter
+    assert_eq!(
+        apply_autofixes("hello world", &[fix(6, 11, "there"), fix(0, 5, "Hi")]).unwrap(),
+        "Hi there"
+    );
+    assert!(apply_autofixes("hello", &[fix(0, 3, "a"), fix(2, 4, "b")]).is_err());
+    assert!(apply_autofixes("hello", &[fix(3, 9, "a")]).is_err());
+    assert!(apply_autofixes("héllo", &[fix(2, 3, "e")]).is_err());
+}
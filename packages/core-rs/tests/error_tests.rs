@@ -0,0 +1,134 @@
+use core_rs::auth::AuthService;
+use core_rs::db::migrate;
+use core_rs::error::{codes, CoreError, ErrorCategory};
+use core_rs::ids::{parse_entity_id, parse_entity_ids};
+use core_rs::note::{update_note_content, DbUlid};
+use core_rs::sync_agent::{
+    init_sync_tables, ConflictResolution, SyncAgent, SyncDelta, SyncOperation,
+};
+use core_rs::vault::AutoLockGuard;
+use rusqlite::Connection;
+use std::collections::HashMap;
+use ulid::Ulid;
+
+fn setup_db() -> Connection {
+    let mut conn = Connection::open_in_memory().unwrap();
+    migrate(&mut conn).unwrap();
+    conn
+}
+
+#[test]
+fn test_missing_note_is_not_found() {
+    let mut conn = setup_db();
+    let id = Ulid::new();
+    let err: CoreError = update_note_content(&mut conn, DbUlid(id), "Title", "Body")
+        .unwrap_err()
+        .into();
+    assert_eq!(err.code, codes::NOTE_NOT_FOUND);
+    assert_eq!(err.category, ErrorCategory::NotFound);
+    assert_eq!(err.context.get("id"), Some(&id.to_string()));
+}
+
+#[test]
+fn test_locked_vault() {
+    let guard = AutoLockGuard::new([1u8; 32]);
+    guard.touch().unwrap();
+    guard.lock();
+    let err: CoreError = guard.touch().unwrap_err().into();
+    assert_eq!(err.code, codes::VAULT_LOCKED);
+    assert_eq!(err.category, ErrorCategory::VaultLocked);
+}
+
+#[test]
+fn test_resolving_a_resolved_sync_conflict() {
+    let mut conn = setup_db();
+    init_sync_tables(&conn).unwrap();
+    let agent = SyncAgent::new("desktop".to_string(), "Desktop".to_string(), 0);
+    let space_id = Ulid::new().to_string();
+    let note_id = Ulid::new().to_string();
+    let now = chrono::Utc::now().timestamp();
+    conn.execute(
+        "INSERT INTO space (id, name) VALUES (?1, 'Work')",
+        [&space_id],
+    )
+    .unwrap();
+    conn.execute(
+        "INSERT INTO note (id, space_id, title, content_md, created_at, modified_at)
+         VALUES (?1, ?2, 'Plan', 'Local', ?3, ?4)",
+        rusqlite::params![note_id, space_id, now, now + 100],
+    )
+    .unwrap();
+    let delta = SyncDelta {
+        entity_type: "note".to_string(),
+        entity_id: note_id.clone(),
+        operation: SyncOperation::Update,
+        data: Some(b"Remote".to_vec()),
+        timestamp: now,
+        vector_clock: HashMap::new(),
+        space_id: Some(space_id),
+    };
+    let conflicts = agent.apply_deltas(&mut conn, vec![delta], &[]).unwrap();
+    assert_eq!(conflicts.len(), 1);
+
+    agent
+        .resolve_conflict(&conn, &conflicts[0], ConflictResolution::UseLocal, &[])
+        .unwrap();
+    // A second window still showing the conflict tries again
+    let err: CoreError = agent
+        .resolve_conflict(&conn, &conflicts[0], ConflictResolution::UseRemote, &[])
+        .unwrap_err()
+        .into();
+    assert_eq!(err.code, codes::SYNC_CONFLICT);
+    assert_eq!(err.category, ErrorCategory::Conflict);
+}
+
+#[test]
+fn test_bad_password() {
+    let conn = setup_db();
+    AuthService::create_user(&conn, "alice", "alice@example.com", "correct-horse-42").unwrap();
+    let err: CoreError = AuthService::authenticate(&conn, "alice", "wrong-password")
+        .unwrap_err()
+        .into();
+    assert_eq!(err.code, codes::AUTH_INVALID_CREDENTIALS);
+    assert_eq!(err.category, ErrorCategory::PermissionDenied);
+}
+
+#[test]
+fn test_invalid_id_names_the_parameter() {
+    let err: CoreError = parse_entity_id("space", "space_id", "0000")
+        .unwrap_err()
+        .into();
+    assert_eq!(err.code, codes::INVALID_ID);
+    assert_eq!(err.category, ErrorCategory::Validation);
+    assert_eq!(err.context["field"], "space_id");
+    assert_eq!(err.context["kind"], "space");
+    assert_eq!(err.context["value"], "0000");
+    assert!(err.message.starts_with("space_id is not a valid space id"));
+
+    let ids = [Ulid::new().to_string(), Ulid::new().to_string()];
+    let parsed = parse_entity_ids("note", "duplicate_ids", &ids).unwrap();
+    assert_eq!(parsed[1].to_string(), ids[1]);
+    let ids = [ids[0].clone(), String::new()];
+    let err: CoreError = parse_entity_ids("note", "duplicate_ids", &ids)
+        .unwrap_err()
+        .into();
+    assert_eq!(err.context["field"], "duplicate_ids[1]");
+    assert_eq!(err.context["kind"], "note");
+}
+
+#[test]
+fn test_serialized_shape() {
+    let err = CoreError::not_found("note", "01ABC");
+    let json = serde_json::to_value(&err).unwrap();
+    assert_eq!(
+        json,
+        serde_json::json!({
+            "code": "note.not_found",
+            "category": "not_found",
+            "message": "Not found: note 01ABC",
+            "context": { "id": "01ABC" },
+        })
+    );
+    assert_eq!(serde_json::from_value::<CoreError>(json).unwrap(), err);
+    assert_eq!(String::from(err), "Not found: note 01ABC");
+}
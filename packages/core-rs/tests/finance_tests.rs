@@ -0,0 +1,258 @@
+use core_rs::calendar::TimeRange;
+use core_rs::db::migrate;
+use core_rs::personal_modes::{
+    create_transaction, get_budget_status, get_spending_summary, set_budget, BudgetError,
+    CreateTransactionParams, SpendingGroup,
+};
+use core_rs::space::create_space;
+use rusqlite::Connection;
+use ulid::Ulid;
+
+const DAY: i64 = 24 * 60 * 60;
+
+/// Midnight UTC on the first of January to April 2025
+const JAN: i64 = 1_735_689_600;
+const FEB: i64 = JAN + 31 * DAY;
+const MAR: i64 = FEB + 28 * DAY;
+const APR: i64 = MAR + 31 * DAY;
+
+fn setup_db() -> (Connection, Ulid) {
+    let mut conn = Connection::open_in_memory().unwrap();
+    conn.pragma_update(None, "foreign_keys", "ON").unwrap();
+    migrate(&mut conn).unwrap();
+    let space_id = create_space(&mut conn, "Finance").unwrap();
+    (conn, space_id)
+}
+
+fn record(
+    conn: &Connection,
+    space_id: Ulid,
+    transaction_type: &str,
+    category: &str,
+    amount: f64,
+    currency: &str,
+    date: i64,
+) {
+    create_transaction(
+        conn,
+        CreateTransactionParams {
+            space_id,
+            transaction_type,
+            amount,
+            currency,
+            category,
+            account_id: "checking",
+            date,
+            description: None,
+        },
+    )
+    .unwrap();
+}
+
+#[test]
+fn test_budget_rollover_across_three_months() {
+    let (conn, space_id) = setup_db();
+    set_budget(&conn, space_id, "groceries", 300.0, true, Some("2025-01")).unwrap();
+    set_budget(&conn, space_id, "dining", 100.0, false, Some("2025-01")).unwrap();
+
+    record(
+        &conn,
+        space_id,
+        "expense",
+        "groceries",
+        250.0,
+        "EUR",
+        JAN + 3 * DAY,
+    );
+    record(
+        &conn,
+        space_id,
+        "expense",
+        "groceries",
+        220.0,
+        "EUR",
+        FEB + 10 * DAY,
+    );
+    record(
+        &conn,
+        space_id,
+        "expense",
+        "groceries",
+        100.0,
+        "EUR",
+        MAR + 2 * DAY,
+    );
+    record(
+        &conn,
+        space_id,
+        "expense",
+        "dining",
+        40.0,
+        "EUR",
+        JAN + 5 * DAY,
+    );
+    record(
+        &conn,
+        space_id,
+        "expense",
+        "dining",
+        30.0,
+        "EUR",
+        MAR + 5 * DAY,
+    );
+    // Income doesn't count as spending
+    record(
+        &conn,
+        space_id,
+        "income",
+        "groceries",
+        500.0,
+        "EUR",
+        MAR + 1,
+    );
+
+    let status = get_budget_status(&conn, space_id, "2025-03").unwrap();
+    assert_eq!(status.len(), 2);
+
+    let dining = &status[0];
+    assert_eq!(dining.category, "dining");
+    assert_eq!(dining.carried_over, 0.0);
+    assert_eq!(dining.budgeted, 100.0);
+    assert_eq!(dining.spent, 30.0);
+    assert_eq!(dining.remaining, 70.0);
+
+    // January leaves 50, February 80, March starts with 300 + 130
+    let groceries = &status[1];
+    assert_eq!(groceries.currency.as_deref(), Some("EUR"));
+    assert_eq!(groceries.carried_over, 130.0);
+    assert_eq!(groceries.budgeted, 430.0);
+    assert_eq!(groceries.spent, 100.0);
+    assert_eq!(groceries.remaining, 330.0);
+
+    let feb = get_budget_status(&conn, space_id, "2025-02").unwrap();
+    assert_eq!(feb[1].carried_over, 50.0);
+    assert_eq!(feb[1].remaining, 130.0);
+
+    // Months before the start carry nothing
+    let dec = get_budget_status(&conn, space_id, "2024-12").unwrap();
+    assert_eq!(dec[1].carried_over, 0.0);
+    assert_eq!(dec[1].spent, 0.0);
+    assert_eq!(dec[1].currency, None);
+
+    assert!(matches!(
+        get_budget_status(&conn, space_id, "March"),
+        Err(BudgetError::InvalidMonth(_))
+    ));
+}
+
+#[test]
+fn test_overspend_reduces_next_month() {
+    let (conn, space_id) = setup_db();
+    set_budget(&conn, space_id, "travel", 200.0, true, Some("2025-01")).unwrap();
+    record(
+        &conn,
+        space_id,
+        "expense",
+        "travel",
+        350.0,
+        "USD",
+        JAN + DAY,
+    );
+
+    let jan = get_budget_status(&conn, space_id, "2025-01").unwrap();
+    assert_eq!(jan[0].remaining, -150.0);
+
+    let feb = get_budget_status(&conn, space_id, "2025-02").unwrap();
+    assert_eq!(feb[0].carried_over, -150.0);
+    assert_eq!(feb[0].budgeted, 50.0);
+
+    // Without rollover every month starts fresh
+    set_budget(&conn, space_id, "travel", 200.0, false, None).unwrap();
+    let feb = get_budget_status(&conn, space_id, "2025-02").unwrap();
+    assert_eq!(feb[0].budgeted, 200.0);
+    assert_eq!(feb[0].remaining, 200.0);
+}
+
+#[test]
+fn test_mixed_currencies_are_refused() {
+    let (conn, space_id) = setup_db();
+    set_budget(&conn, space_id, "books", 50.0, false, Some("2025-01")).unwrap();
+    record(&conn, space_id, "expense", "books", 20.0, "EUR", JAN + DAY);
+    record(
+        &conn,
+        space_id,
+        "expense",
+        "books",
+        15.0,
+        "GBP",
+        JAN + 2 * DAY,
+    );
+    record(&conn, space_id, "expense", "rent", 900.0, "EUR", JAN + DAY);
+
+    match get_budget_status(&conn, space_id, "2025-01") {
+        Err(BudgetError::MixedCurrencies {
+            category,
+            currencies,
+        }) => {
+            assert_eq!(category, "books");
+            assert_eq!(currencies, vec!["EUR", "GBP"]);
+        }
+        other => panic!("expected a currency error, got {:?}", other),
+    }
+
+    let range = TimeRange::new(JAN, APR);
+    assert!(matches!(
+        get_spending_summary(&conn, space_id, range, SpendingGroup::Category),
+        Err(BudgetError::MixedCurrencies { .. })
+    ));
+
+    // Per month, each currency gets its own row
+    let by_month = get_spending_summary(&conn, space_id, range, SpendingGroup::Month).unwrap();
+    let rows: Vec<(&str, &str, f64)> = by_month
+        .iter()
+        .map(|s| (s.key.as_str(), s.currency.as_str(), s.spent))
+        .collect();
+    assert_eq!(
+        rows,
+        vec![("2025-01", "EUR", 920.0), ("2025-01", "GBP", 15.0)]
+    );
+}
+
+#[test]
+fn test_spending_summary_by_category() {
+    let (conn, space_id) = setup_db();
+    record(&conn, space_id, "expense", "rent", 900.0, "EUR", JAN + DAY);
+    record(&conn, space_id, "expense", "rent", 900.0, "EUR", FEB + DAY);
+    record(
+        &conn,
+        space_id,
+        "expense",
+        "dining",
+        45.5,
+        "EUR",
+        FEB + 2 * DAY,
+    );
+    record(&conn, space_id, "income", "salary", 3000.0, "EUR", FEB);
+    // Outside the range
+    record(&conn, space_id, "expense", "dining", 60.0, "EUR", MAR + DAY);
+
+    let summary = get_spending_summary(
+        &conn,
+        space_id,
+        TimeRange::new(JAN, MAR),
+        SpendingGroup::Category,
+    )
+    .unwrap();
+    let rows: Vec<(&str, f64, f64, i64)> = summary
+        .iter()
+        .map(|s| (s.key.as_str(), s.spent, s.income, s.transaction_count))
+        .collect();
+    assert_eq!(
+        rows,
+        vec![
+            ("dining", 45.5, 0.0, 1),
+            ("rent", 1800.0, 0.0, 2),
+            ("salary", 0.0, 3000.0, 1),
+        ]
+    );
+}
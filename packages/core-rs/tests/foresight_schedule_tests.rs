@@ -0,0 +1,170 @@
+use chrono::{DateTime, Duration, Utc};
+use core_rs::db::{migrate, DbPool};
+use core_rs::foresight::*;
+use core_rs::task;
+use tempfile::TempDir;
+use ulid::Ulid;
+
+/// A file-backed pool with one space holding a task due tomorrow
+fn setup() -> (DbPool, Ulid, TempDir) {
+    let dir = tempfile::tempdir().unwrap();
+    let manager = r2d2_sqlite::SqliteConnectionManager::file(dir.path().join("insights.db"));
+    let pool = r2d2::Pool::builder().max_size(2).build(manager).unwrap();
+    let space_id = Ulid::new();
+    {
+        let mut conn = pool.get().unwrap();
+        migrate(&mut conn).unwrap();
+        conn.execute(
+            "INSERT INTO space (id, name) VALUES (?1, 'Work')",
+            [space_id.to_string()],
+        )
+        .unwrap();
+    }
+    add_task_due_in(&pool, space_id, "Send report", 1);
+    (pool, space_id, dir)
+}
+
+fn add_task_due_in(pool: &DbPool, space_id: Ulid, title: &str, days: i64) {
+    let conn = pool.get().unwrap();
+    let mut task = task::create_task(&conn, space_id, title, None).unwrap();
+    task.due_at = Some((Utc::now() + Duration::days(days)).timestamp());
+    task::update_task(&conn, &task).unwrap();
+}
+
+fn outcomes(runs: Vec<InsightRun>) -> Vec<InsightRunOutcome> {
+    runs.into_iter().map(|run| run.outcome).collect()
+}
+
+fn active_count(pool: &DbPool, space_id: Ulid) -> usize {
+    get_active_insights(&pool.get().unwrap(), &space_id.to_string())
+        .unwrap()
+        .len()
+}
+
+fn set_interval(pool: &DbPool, space_id: Ulid, interval_minutes: u32) {
+    let schedule = InsightSchedule {
+        enabled: true,
+        interval_minutes,
+    };
+    set_insight_schedule(&pool.get().unwrap(), &space_id.to_string(), &schedule).unwrap();
+}
+
+#[test]
+fn test_runs_when_due_and_skips_unchanged_spaces() {
+    let (pool, space_id, _dir) = setup();
+    set_interval(&pool, space_id, 60);
+    let scheduler = InsightScheduler::new(pool.clone());
+    let t0: DateTime<Utc> = Utc::now();
+
+    let runs = scheduler.run_due(t0).unwrap();
+    assert_eq!(runs.len(), 1);
+    assert_eq!(runs[0].space_id, space_id.to_string());
+    assert_eq!(
+        runs[0].outcome,
+        InsightRunOutcome::Generated { new_insights: 1 }
+    );
+
+    // Not due again before the interval is up
+    assert!(scheduler
+        .run_due(t0 + Duration::minutes(30))
+        .unwrap()
+        .is_empty());
+
+    // Due, but nothing changed
+    assert_eq!(
+        outcomes(scheduler.run_due(t0 + Duration::minutes(61)).unwrap()),
+        vec![InsightRunOutcome::Unchanged]
+    );
+    assert_eq!(active_count(&pool, space_id), 1);
+
+    add_task_due_in(&pool, space_id, "Book venue", 2);
+    assert_eq!(
+        outcomes(scheduler.run_due(t0 + Duration::minutes(122)).unwrap()),
+        vec![InsightRunOutcome::Generated { new_insights: 1 }]
+    );
+    assert_eq!(active_count(&pool, space_id), 2);
+}
+
+#[test]
+fn test_unchanged_space_is_refreshed_daily_without_duplicates() {
+    let (pool, space_id, _dir) = setup();
+    set_interval(&pool, space_id, 60);
+    let scheduler = InsightScheduler::new(pool.clone());
+    let t0 = Utc::now();
+
+    scheduler.run_due(t0).unwrap();
+    assert_eq!(
+        outcomes(scheduler.run_due(t0 + Duration::hours(25)).unwrap()),
+        vec![InsightRunOutcome::Generated { new_insights: 0 }]
+    );
+    assert_eq!(active_count(&pool, space_id), 1);
+}
+
+#[test]
+fn test_default_and_disabled_schedules() {
+    let (pool, space_id, _dir) = setup();
+    let conn = pool.get().unwrap();
+    assert_eq!(
+        get_insight_schedule(&conn, &space_id.to_string()).unwrap(),
+        InsightSchedule::default()
+    );
+    assert!(set_insight_schedule(
+        &conn,
+        &space_id.to_string(),
+        &InsightSchedule {
+            enabled: true,
+            interval_minutes: 0,
+        },
+    )
+    .is_err());
+    set_insight_schedule(
+        &conn,
+        &space_id.to_string(),
+        &InsightSchedule {
+            enabled: false,
+            interval_minutes: 60,
+        },
+    )
+    .unwrap();
+    drop(conn);
+
+    let scheduler = InsightScheduler::new(pool.clone());
+    assert!(scheduler.run_due(Utc::now()).unwrap().is_empty());
+    assert_eq!(active_count(&pool, space_id), 0);
+}
+
+#[test]
+fn test_lease_keeps_runs_from_overlapping() {
+    let (pool, space_id, _dir) = setup();
+    let space = space_id.to_string();
+    let t0 = Utc::now();
+    let scheduler = InsightScheduler::new(pool.clone()).with_lease_secs(600);
+
+    // Another process is generating for the space
+    {
+        let conn = pool.get().unwrap();
+        assert!(acquire_insight_lease(&conn, &space, "other", t0, 600).unwrap());
+        assert!(!acquire_insight_lease(&conn, &space, scheduler.owner(), t0, 600).unwrap());
+    }
+    assert_eq!(
+        outcomes(scheduler.run_due(t0 + Duration::seconds(60)).unwrap()),
+        vec![InsightRunOutcome::LeaseHeld]
+    );
+    assert_eq!(active_count(&pool, space_id), 0);
+
+    // Its lease ran out without being released
+    assert_eq!(
+        outcomes(scheduler.run_due(t0 + Duration::seconds(601)).unwrap()),
+        vec![InsightRunOutcome::Generated { new_insights: 1 }]
+    );
+
+    // The run released its own lease
+    let conn = pool.get().unwrap();
+    assert!(
+        acquire_insight_lease(&conn, &space, "third", t0 + Duration::seconds(602), 600).unwrap()
+    );
+    release_insight_lease(&conn, &space, "third").unwrap();
+    assert!(
+        acquire_insight_lease(&conn, &space, "other", t0 + Duration::seconds(603), 600).unwrap()
+    );
+}
@@ -1,7 +1,13 @@
+use chrono::NaiveDate;
 use core_rs::db;
-use core_rs::form::{self, FormField, FormFieldType};
+use core_rs::form::{
+    self, FieldFilter, FieldMapping, FormError, FormField, FormFieldType, FormTemplate,
+    SubmissionFilters,
+};
+use core_rs::property::{define_property, get_note_properties, PropertyType};
 use core_rs::space;
 use rusqlite::Connection;
+use serde_json::{json, Map, Value};
 use tempfile::tempdir;
 
 fn setup_db() -> (Connection, tempfile::TempDir) {
@@ -24,12 +30,18 @@ fn test_form_template_crud() {
             label: "Field 1".to_string(),
             field_type: FormFieldType::Text,
             default_value: Some("default".to_string()),
+            required: false,
+            options: vec![],
+            mapping: None,
         },
         FormField {
             name: "field2".to_string(),
             label: "Field 2".to_string(),
             field_type: FormFieldType::Checkbox,
             default_value: None,
+            required: false,
+            options: vec![],
+            mapping: None,
         },
     ];
 
@@ -50,6 +62,9 @@ fn test_form_template_crud() {
         label: "Field 1 Updated".to_string(),
         field_type: FormFieldType::Text,
         default_value: None,
+        required: false,
+        options: vec![],
+        mapping: None,
     }];
     form::update_form_template(&conn, &template.id, "Updated Form", updated_fields).unwrap();
 
@@ -67,3 +82,623 @@ fn test_form_template_crud() {
     let result = form::get_form_template(&conn, &template.id);
     assert!(result.is_err());
 }
+
+fn field(name: &str, field_type: FormFieldType, required: bool) -> FormField {
+    FormField {
+        name: name.to_string(),
+        label: name.to_string(),
+        field_type,
+        default_value: None,
+        required,
+        options: vec![],
+        mapping: None,
+    }
+}
+
+/// A workout log with one field of every kind
+fn workout_template(conn: &mut Connection) -> FormTemplate {
+    let space_id = space::create_space(conn, "Health").unwrap().to_string();
+    let mut mood = field("mood", FormFieldType::Select, false);
+    mood.options = vec!["good".to_string(), "tired".to_string()];
+    let mut kind = field("kind", FormFieldType::Text, false);
+    kind.default_value = Some("run".to_string());
+    let fields = vec![
+        field("date", FormFieldType::Date, true),
+        field("minutes", FormFieldType::Number, true),
+        kind,
+        mood,
+        field("indoor", FormFieldType::Checkbox, false),
+        field("start", FormFieldType::Time, false),
+        field("notes", FormFieldType::Textarea, false),
+    ];
+    form::create_form_template(conn, &space_id, "Workout", fields).unwrap()
+}
+
+fn values(value: Value) -> Map<String, Value> {
+    value.as_object().unwrap().clone()
+}
+
+fn submit(conn: &Connection, template: &FormTemplate, value: Value) {
+    form::submit_form(conn, &template.id, values(value), None).unwrap();
+}
+
+#[test]
+fn test_submission_values_are_validated_and_normalized() {
+    let (mut conn, _dir) = setup_db();
+    let template = workout_template(&mut conn);
+
+    let submission = form::submit_form(
+        &conn,
+        &template.id,
+        values(json!({
+            "date": "2025-03-01",
+            "minutes": "45",
+            "mood": "good",
+            "indoor": "true",
+            "start": "07:30",
+            "notes": ""
+        })),
+        None,
+    )
+    .unwrap();
+    assert_eq!(
+        Value::Object(submission.values),
+        json!({
+            "date": "2025-03-01",
+            "minutes": 45,
+            "kind": "run",
+            "mood": "good",
+            "indoor": true,
+            "start": "07:30:00"
+        })
+    );
+    assert_eq!(submission.schema_version, 1);
+
+    let result = form::submit_form(
+        &conn,
+        &template.id,
+        values(json!({
+            "minutes": "a while",
+            "mood": "great",
+            "indoor": 1,
+            "start": "25:00",
+            "distance": 5
+        })),
+        None,
+    );
+    let Err(FormError::Validation(errors)) = result else {
+        panic!("expected validation errors, got {:?}", result);
+    };
+    let fields: Vec<&str> = errors.iter().map(|e| e.field.as_str()).collect();
+    assert_eq!(
+        fields,
+        vec!["date", "minutes", "mood", "indoor", "start", "distance"]
+    );
+    assert_eq!(errors[0].message, "is required");
+    assert!(errors[2].message.contains("good, tired"));
+
+    let bad_date = form::submit_form(
+        &conn,
+        &template.id,
+        values(json!({"date": "01/03/2025", "minutes": 10})),
+        None,
+    );
+    assert!(matches!(bad_date, Err(FormError::Validation(_))));
+
+    let stored =
+        form::get_form_submissions(&conn, &template.id, &SubmissionFilters::default()).unwrap();
+    assert_eq!(stored.len(), 1);
+}
+
+#[test]
+fn test_filter_submissions_by_field_values() {
+    let (mut conn, _dir) = setup_db();
+    let template = workout_template(&mut conn);
+    submit(
+        &conn,
+        &template,
+        json!({"date": "2025-03-01", "minutes": 20, "mood": "tired"}),
+    );
+    submit(
+        &conn,
+        &template,
+        json!({"date": "2025-03-05", "minutes": 45, "mood": "good"}),
+    );
+    submit(
+        &conn,
+        &template,
+        json!({"date": "2025-03-09", "minutes": 60.5, "kind": "swim"}),
+    );
+    submit(
+        &conn,
+        &template,
+        json!({"date": "2025-04-02", "minutes": 30, "mood": "good"}),
+    );
+
+    let dates = |filters: SubmissionFilters| -> Vec<String> {
+        form::get_form_submissions(&conn, &template.id, &filters)
+            .unwrap()
+            .into_iter()
+            .map(|s| s.values["date"].as_str().unwrap().to_string())
+            .collect()
+    };
+    let filter = |fields: Vec<FieldFilter>| SubmissionFilters {
+        fields,
+        ..SubmissionFilters::default()
+    };
+
+    assert_eq!(
+        dates(filter(vec![FieldFilter::Equals {
+            field: "mood".to_string(),
+            value: json!("good"),
+        }])),
+        vec!["2025-03-05", "2025-04-02"]
+    );
+    // Number filters compare numerically, even when given as text
+    assert_eq!(
+        dates(filter(vec![FieldFilter::Range {
+            field: "minutes".to_string(),
+            min: Some(json!("30")),
+            max: Some(json!(60.5)),
+        }])),
+        vec!["2025-03-05", "2025-03-09", "2025-04-02"]
+    );
+    assert_eq!(
+        dates(filter(vec![
+            FieldFilter::Range {
+                field: "date".to_string(),
+                min: None,
+                max: Some(json!("2025-03-31")),
+            },
+            FieldFilter::Equals {
+                field: "kind".to_string(),
+                value: json!("run"),
+            },
+        ])),
+        vec!["2025-03-01", "2025-03-05"]
+    );
+    assert!(dates(filter(vec![FieldFilter::Equals {
+        field: "missing".to_string(),
+        value: json!(1),
+    }]))
+    .is_empty());
+    assert_eq!(
+        dates(SubmissionFilters {
+            limit: Some(2),
+            ..SubmissionFilters::default()
+        }),
+        vec!["2025-03-01", "2025-03-05"]
+    );
+}
+
+#[test]
+fn test_template_edits_keep_old_submissions_readable() {
+    let (mut conn, _dir) = setup_db();
+    let template = workout_template(&mut conn);
+    submit(
+        &conn,
+        &template,
+        json!({"date": "2025-03-01", "minutes": 20, "mood": "tired"}),
+    );
+
+    // Rename the form only: same schema
+    form::update_form_template(&conn, &template.id, "Workouts", template.fields.clone()).unwrap();
+    assert_eq!(
+        form::get_form_template(&conn, &template.id)
+            .unwrap()
+            .schema_version,
+        1
+    );
+
+    // Drop "mood" and add a required field
+    let mut fields: Vec<FormField> = template
+        .fields
+        .iter()
+        .filter(|f| f.name != "mood")
+        .cloned()
+        .collect();
+    fields.push(field("effort", FormFieldType::Number, true));
+    form::update_form_template(&conn, &template.id, "Workouts", fields).unwrap();
+    assert_eq!(
+        form::get_form_template(&conn, &template.id)
+            .unwrap()
+            .schema_version,
+        2
+    );
+    submit(
+        &conn,
+        &template,
+        json!({"date": "2025-03-02", "minutes": 25, "effort": 7}),
+    );
+
+    let submissions =
+        form::get_form_submissions(&conn, &template.id, &SubmissionFilters::default()).unwrap();
+    assert_eq!(submissions[0].schema_version, 1);
+    assert_eq!(submissions[0].values["mood"], json!("tired"));
+    assert_eq!(submissions[1].schema_version, 2);
+
+    let dir = tempdir().unwrap();
+    let path = dir.path().join("workouts.csv");
+    form::export_submissions_csv(&conn, &template.id, path.to_str().unwrap()).unwrap();
+    let csv = std::fs::read_to_string(&path).unwrap();
+    let header = csv.lines().next().unwrap();
+    assert_eq!(
+        header,
+        "id,created_at,schema_version,note_id,date,minutes,kind,indoor,start,notes,effort,mood"
+    );
+    assert!(csv
+        .lines()
+        .nth(1)
+        .unwrap()
+        .ends_with(",2025-03-01,20,run,,,,,tired"));
+}
+
+#[test]
+fn test_csv_export_escapes_cells() {
+    let (mut conn, _dir) = setup_db();
+    let template = workout_template(&mut conn);
+    submit(
+        &conn,
+        &template,
+        json!({
+            "date": "2025-03-01",
+            "minutes": 12.5,
+            "kind": "run, then \"stretch\"",
+            "notes": "line one\nline two"
+        }),
+    );
+
+    let dir = tempdir().unwrap();
+    let path = dir.path().join("export.csv");
+    let written =
+        form::export_submissions_csv(&conn, &template.id, path.to_str().unwrap()).unwrap();
+    assert_eq!(written, 1);
+
+    let csv = std::fs::read_to_string(&path).unwrap();
+    let row = csv.split("\r\n").nth(1).unwrap();
+    assert!(
+        row.ends_with(",2025-03-01,12.5,\"run, then \"\"stretch\"\"\",,,,\"line one\nline two\""),
+        "{}",
+        row
+    );
+    assert!(csv.ends_with("\r\n"));
+}
+
+fn mapped(name: &str, label: &str, field_type: FormFieldType, mapping: FieldMapping) -> FormField {
+    FormField {
+        label: label.to_string(),
+        mapping: Some(mapping),
+        ..field(name, field_type, false)
+    }
+}
+
+fn section(heading: &str) -> FieldMapping {
+    FieldMapping::NoteSection {
+        heading: heading.to_string(),
+    }
+}
+
+fn count(conn: &Connection, table: &str) -> i64 {
+    conn.query_row(&format!("SELECT COUNT(*) FROM {}", table), [], |row| {
+        row.get(0)
+    })
+    .unwrap()
+}
+
+/// A daily check-in writing a note, a sleep metric and a coffee expense,
+/// with an optional status property that only accepts done or skipped
+fn daily_metrics_template(conn: &mut Connection) -> (String, FormTemplate) {
+    let space_id = space::create_space(conn, "Daily").unwrap().to_string();
+    define_property(
+        conn,
+        &space_id,
+        "status",
+        PropertyType::Select,
+        &["done".to_string(), "skipped".to_string()],
+    )
+    .unwrap();
+    let fields = vec![
+        mapped("mood", "Mood", FormFieldType::Text, section("Summary")),
+        mapped(
+            "sleep",
+            "Sleep",
+            FormFieldType::Number,
+            FieldMapping::HealthMetric {
+                metric_type: "sleep".to_string(),
+                unit: Some("hours".to_string()),
+            },
+        ),
+        mapped(
+            "coffee",
+            "Coffee",
+            FormFieldType::Number,
+            FieldMapping::Transaction {
+                transaction_type: "expense".to_string(),
+                currency: "EUR".to_string(),
+                category: "Dining".to_string(),
+                category_field: None,
+                account_id: "cash".to_string(),
+            },
+        ),
+        mapped(
+            "journal",
+            "Journal",
+            FormFieldType::Textarea,
+            section("Summary"),
+        ),
+        mapped("win", "Win", FormFieldType::Text, section("Wins")),
+        mapped(
+            "status",
+            "Status",
+            FormFieldType::Text,
+            FieldMapping::NoteProperty {
+                property: "Status".to_string(),
+            },
+        ),
+    ];
+    let template = form::create_form_template(conn, &space_id, "Daily metrics", fields).unwrap();
+    (space_id, template)
+}
+
+#[test]
+fn test_mapped_submission_creates_note_metric_and_transaction() {
+    let (mut conn, _dir) = setup_db();
+    let (space_id, template) = daily_metrics_template(&mut conn);
+
+    let result = form::submit_form_with_mapping(
+        &conn,
+        &template.id,
+        values(json!({
+            "mood": "calm",
+            "sleep": "7.5",
+            "coffee": 3.2,
+            "journal": "Long walk.\n",
+            "win": "Shipped the form",
+            "status": "done"
+        })),
+        &space_id,
+    )
+    .unwrap();
+
+    let note = result.note.unwrap();
+    assert_eq!(note.space_id, space_id);
+    // No title pattern: the note is named after the template
+    assert_eq!(note.title, "Daily metrics");
+    assert_eq!(
+        note.content_md,
+        "## Summary\n\nMood: calm\n\nLong walk.\n\n## Wins\n\nWin: Shipped the form\n"
+    );
+    let note_id = note.id.0.to_string();
+    let properties = get_note_properties(&conn, &note_id).unwrap();
+    assert_eq!(properties[0].value, json!("done"));
+
+    assert_eq!(result.health_metric_ids.len(), 1);
+    let (metric_type, value, unit, metric_note): (String, f64, String, String) = conn
+        .query_row(
+            "SELECT metric_type, value, unit, note_id FROM health_metric WHERE id = ?1",
+            [&result.health_metric_ids[0]],
+            |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?)),
+        )
+        .unwrap();
+    assert_eq!(
+        (metric_type.as_str(), value, unit.as_str()),
+        ("sleep", 7.5, "hours")
+    );
+    assert_eq!(metric_note, note_id);
+
+    assert_eq!(result.transaction_ids.len(), 1);
+    let (kind, amount, currency, category): (String, f64, String, String) = conn
+        .query_row(
+            "SELECT type, amount, currency, category FROM transaction_log WHERE id = ?1",
+            [&result.transaction_ids[0]],
+            |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?)),
+        )
+        .unwrap();
+    assert_eq!(
+        (kind.as_str(), amount, currency.as_str(), category.as_str()),
+        ("expense", 3.2, "EUR", "Dining")
+    );
+
+    assert_eq!(result.submission.note_id, Some(note_id));
+    assert_eq!(result.submission.values["sleep"], json!(7.5));
+    assert_eq!(count(&conn, "form_submission"), 1);
+
+    // Left-out optional fields map to nothing
+    let result = form::submit_form_with_mapping(
+        &conn,
+        &template.id,
+        values(json!({"mood": "tired"})),
+        &space_id,
+    )
+    .unwrap();
+    assert!(result.health_metric_ids.is_empty());
+    assert!(result.transaction_ids.is_empty());
+    assert_eq!(
+        result.note.unwrap().content_md,
+        "## Summary\n\nMood: tired\n"
+    );
+}
+
+#[test]
+fn test_failed_mapping_rolls_back_the_whole_submission() {
+    let (mut conn, _dir) = setup_db();
+    let (space_id, template) = daily_metrics_template(&mut conn);
+    let notes_before = count(&conn, "note");
+
+    // The metric and the transaction are written before the status
+    // property rejects its value
+    let result = form::submit_form_with_mapping(
+        &conn,
+        &template.id,
+        values(json!({"mood": "calm", "sleep": 8, "coffee": 2, "status": "maybe"})),
+        &space_id,
+    );
+    let Err(FormError::Mapping { field, message }) = result else {
+        panic!("expected a mapping error, got {:?}", result);
+    };
+    assert_eq!(field, "status");
+    assert!(message.contains("maybe"), "{}", message);
+
+    assert_eq!(count(&conn, "note"), notes_before);
+    assert_eq!(count(&conn, "health_metric"), 0);
+    assert_eq!(count(&conn, "transaction_log"), 0);
+    assert_eq!(count(&conn, "form_submission"), 0);
+
+    // Invalid values fail before anything is attempted
+    let result = form::submit_form_with_mapping(
+        &conn,
+        &template.id,
+        values(json!({"sleep": "lots"})),
+        &space_id,
+    );
+    assert!(matches!(result, Err(FormError::Validation(_))));
+    assert!(form::submit_form_with_mapping(&conn, &template.id, Map::new(), "nope").is_err());
+}
+
+#[test]
+fn test_mappings_are_validated_when_the_template_is_saved() {
+    let (mut conn, _dir) = setup_db();
+    let (space_id, template) = daily_metrics_template(&mut conn);
+    let metric = FieldMapping::HealthMetric {
+        metric_type: "weight".to_string(),
+        unit: None,
+    };
+
+    let invalid = [
+        // Only numbers become metrics
+        mapped("weight", "Weight", FormFieldType::Text, metric.clone()),
+        mapped(
+            "tag",
+            "Tag",
+            FormFieldType::Text,
+            FieldMapping::NoteProperty {
+                property: "missing".to_string(),
+            },
+        ),
+        mapped(
+            "status",
+            "Status",
+            FormFieldType::Checkbox,
+            FieldMapping::NoteProperty {
+                property: "status".to_string(),
+            },
+        ),
+        mapped(
+            "amount",
+            "Amount",
+            FormFieldType::Number,
+            FieldMapping::Transaction {
+                transaction_type: "expense".to_string(),
+                currency: "EUR".to_string(),
+                category: String::new(),
+                category_field: Some("category".to_string()),
+                account_id: String::new(),
+            },
+        ),
+        mapped("notes", "Notes", FormFieldType::Textarea, section(" ")),
+    ];
+    for field in invalid {
+        let name = field.name.clone();
+        let result = form::create_form_template(&conn, &space_id, "Broken", vec![field]);
+        let message = result.err().unwrap().to_string();
+        assert!(message.contains(&name), "{}", message);
+    }
+
+    // Updates are checked too, and leave the template as it was
+    let mut fields = template.fields.clone();
+    fields[0].mapping = Some(metric.clone());
+    assert!(form::update_form_template(&conn, &template.id, "Daily", fields).is_err());
+    assert_eq!(
+        form::get_form_template(&conn, &template.id).unwrap().fields[0].mapping,
+        Some(section("Summary"))
+    );
+
+    // A select field can name the category
+    let mut category = field("category", FormFieldType::Select, true);
+    category.options = vec!["Dining".to_string(), "Transport".to_string()];
+    let expense = form::create_form_template(
+        &conn,
+        &space_id,
+        "Expense",
+        vec![
+            mapped(
+                "amount",
+                "Amount",
+                FormFieldType::Number,
+                FieldMapping::Transaction {
+                    transaction_type: "expense".to_string(),
+                    currency: "EUR".to_string(),
+                    category: String::new(),
+                    category_field: Some("category".to_string()),
+                    account_id: String::new(),
+                },
+            ),
+            category,
+        ],
+    )
+    .unwrap();
+    let result = form::submit_form_with_mapping(
+        &conn,
+        &expense.id,
+        values(json!({"amount": 12, "category": "Transport"})),
+        &space_id,
+    )
+    .unwrap();
+    assert!(result.note.is_none());
+    let category: String = conn
+        .query_row(
+            "SELECT category FROM transaction_log WHERE id = ?1",
+            [&result.transaction_ids[0]],
+            |row| row.get(0),
+        )
+        .unwrap();
+    assert_eq!(category, "Transport");
+}
+
+#[test]
+fn test_title_pattern_renders_dates_and_values() {
+    let date = NaiveDate::from_ymd_opt(2025, 3, 7).unwrap();
+    let submitted = values(json!({"mood": "calm", "sleep": 7.5}));
+    let render = |pattern: &str| form::render_title_pattern(pattern, &submitted, date);
+
+    assert_eq!(render("Daily {date}"), "Daily 2025-03-07");
+    assert_eq!(render("{date:%A %-d %B} - {mood}"), "Friday 7 March - calm");
+    assert_eq!(render("Slept {sleep}h{missing}"), "Slept 7.5h");
+
+    let (mut conn, _dir) = setup_db();
+    let (space_id, template) = daily_metrics_template(&mut conn);
+    for bad in ["Daily {date", "{weather}", "{date:%H:%M}"] {
+        assert!(
+            form::set_form_title_pattern(&conn, &template.id, Some(bad)).is_err(),
+            "{}",
+            bad
+        );
+    }
+    form::set_form_title_pattern(&conn, &template.id, Some("Check-in {date} ({mood})")).unwrap();
+    assert_eq!(
+        form::get_form_template(&conn, &template.id)
+            .unwrap()
+            .title_pattern
+            .as_deref(),
+        Some("Check-in {date} ({mood})")
+    );
+
+    // Repeated submissions create dated notes, even without note mappings
+    let today = chrono::Local::now().date_naive().format("%Y-%m-%d");
+    let note = form::submit_form_with_mapping(
+        &conn,
+        &template.id,
+        values(json!({"mood": "calm"})),
+        &space_id,
+    )
+    .unwrap()
+    .note
+    .unwrap();
+    assert_eq!(note.title, format!("Check-in {} (calm)", today));
+
+    form::set_form_title_pattern(&conn, &template.id, None).unwrap();
+    assert!(form::get_form_template(&conn, &template.id)
+        .unwrap()
+        .title_pattern
+        .is_none());
+}
@@ -0,0 +1,151 @@
+use core_rs::db;
+use core_rs::graph::{find_path, get_neighborhood, get_orphan_notes};
+use core_rs::note;
+use core_rs::space;
+use rusqlite::Connection;
+use std::collections::HashMap;
+use ulid::Ulid;
+
+/// Seeded vault with a known topology:
+/// - chain: a -> b -> c -> d -> e
+/// - star: hub -> s1..s5
+/// - disconnected pair: x -> y
+/// - orphan: lonely
+struct Vault {
+    conn: Connection,
+    space_id: Ulid,
+    ids: HashMap<&'static str, Ulid>,
+}
+
+impl Vault {
+    fn id(&self, title: &str) -> Ulid {
+        self.ids[title]
+    }
+}
+
+fn seed() -> Vault {
+    let mut conn = Connection::open_in_memory().unwrap();
+    db::migrate(&mut conn).unwrap();
+    let space_id = space::create_space(&mut conn, "Graph").unwrap();
+    let space = space_id.to_string();
+
+    let notes: [(&str, &str); 14] = [
+        ("e", ""),
+        ("d", "[[e]]"),
+        ("c", "[[d]]"),
+        ("b", "[[c]]"),
+        ("a", "[[b]]"),
+        ("s1", ""),
+        ("s2", ""),
+        ("s3", ""),
+        ("s4", ""),
+        ("s5", ""),
+        ("hub", "[[s1]] [[s2]] [[s3]] [[s4]] [[s5]]"),
+        ("y", ""),
+        ("x", "[[y]]"),
+        ("lonely", "No links here"),
+    ];
+
+    let mut ids = HashMap::new();
+    for (title, content) in notes {
+        let created = note::create_note(&conn, &space, title, content).unwrap();
+        ids.insert(title, created.id.0);
+    }
+
+    Vault {
+        conn,
+        space_id,
+        ids,
+    }
+}
+
+fn labels(nodes: &[core_rs::graph::GraphNode]) -> Vec<String> {
+    let mut labels: Vec<String> = nodes.iter().map(|n| n.label.clone()).collect();
+    labels.sort();
+    labels
+}
+
+#[test]
+fn test_neighborhood_respects_depth() {
+    let vault = seed();
+
+    let one = get_neighborhood(&vault.conn, vault.id("c"), 1, 100).unwrap();
+    assert_eq!(labels(&one.nodes), vec!["b", "c", "d"]);
+    assert_eq!(one.edges.len(), 2);
+
+    let two = get_neighborhood(&vault.conn, vault.id("c"), 2, 100).unwrap();
+    assert_eq!(labels(&two.nodes), vec!["a", "b", "c", "d", "e"]);
+    assert_eq!(two.edges.len(), 4);
+
+    // The disconnected component never shows up
+    let pair = get_neighborhood(&vault.conn, vault.id("x"), 5, 100).unwrap();
+    assert_eq!(labels(&pair.nodes), vec!["x", "y"]);
+    assert_eq!(pair.edges.len(), 1);
+}
+
+#[test]
+fn test_neighborhood_respects_max_nodes() {
+    let vault = seed();
+
+    let star = get_neighborhood(&vault.conn, vault.id("hub"), 1, 100).unwrap();
+    assert_eq!(star.nodes.len(), 6);
+    assert_eq!(star.edges.len(), 5);
+
+    let capped = get_neighborhood(&vault.conn, vault.id("hub"), 1, 3).unwrap();
+    assert_eq!(capped.nodes.len(), 3);
+    assert_eq!(capped.edges.len(), 2);
+    assert!(capped
+        .edges
+        .iter()
+        .all(|e| capped.nodes.iter().any(|n| n.id == e.target)));
+
+    // Spokes reach each other through the hub
+    let spoke = get_neighborhood(&vault.conn, vault.id("s1"), 2, 100).unwrap();
+    assert_eq!(spoke.nodes.len(), 6);
+}
+
+#[test]
+fn test_find_path() {
+    let vault = seed();
+
+    let path = find_path(&vault.conn, vault.id("a"), vault.id("e"), 10)
+        .unwrap()
+        .unwrap();
+    let titles: Vec<&str> = path.iter().map(|n| n.label.as_str()).collect();
+    assert_eq!(titles, vec!["a", "b", "c", "d", "e"]);
+
+    // Links are followed against their direction too
+    let back = find_path(&vault.conn, vault.id("s2"), vault.id("s4"), 10)
+        .unwrap()
+        .unwrap();
+    let titles: Vec<&str> = back.iter().map(|n| n.label.as_str()).collect();
+    assert_eq!(titles, vec!["s2", "hub", "s4"]);
+
+    assert!(find_path(&vault.conn, vault.id("a"), vault.id("e"), 3)
+        .unwrap()
+        .is_none());
+    assert!(find_path(&vault.conn, vault.id("a"), vault.id("x"), 10)
+        .unwrap()
+        .is_none());
+
+    let same = find_path(&vault.conn, vault.id("c"), vault.id("c"), 0)
+        .unwrap()
+        .unwrap();
+    assert_eq!(same.len(), 1);
+}
+
+#[test]
+fn test_orphan_notes() {
+    let vault = seed();
+
+    let orphans = get_orphan_notes(&vault.conn, vault.space_id).unwrap();
+    assert_eq!(labels(&orphans), vec!["lonely"]);
+
+    // Trashing y leaves x with no live links
+    note::trash_note(&vault.conn, note::DbUlid(vault.id("y"))).unwrap();
+    let orphans = get_orphan_notes(&vault.conn, vault.space_id).unwrap();
+    assert_eq!(labels(&orphans), vec!["lonely", "x"]);
+    assert!(find_path(&vault.conn, vault.id("x"), vault.id("y"), 10)
+        .unwrap()
+        .is_none());
+}
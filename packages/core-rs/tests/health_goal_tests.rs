@@ -0,0 +1,355 @@
+use core_rs::db::{migrate, migrate_to};
+use core_rs::events::{register_sink, unregister_sink, CollectingSink, CoreEvent};
+use core_rs::goals::create_goal;
+use core_rs::health::create_health_metric;
+use core_rs::health::goals::*;
+use core_rs::space::create_space;
+use rusqlite::Connection;
+use std::sync::Arc;
+use std::time::Duration;
+use tempfile::tempdir;
+use ulid::Ulid;
+
+const DAY: i64 = 24 * 60 * 60;
+
+/// Midnight UTC, 2025-01-01
+const START: i64 = 1_735_689_600;
+
+const WAIT: Duration = Duration::from_secs(5);
+
+fn setup_db() -> (Connection, Ulid) {
+    let mut conn = Connection::open_in_memory().unwrap();
+    conn.pragma_update(None, "foreign_keys", "ON").unwrap();
+    migrate(&mut conn).unwrap();
+    let space_id = create_space(&mut conn, "Health").unwrap();
+    (conn, space_id)
+}
+
+fn record(conn: &Connection, space_id: Ulid, metric_type: &str, value: f64, recorded_at: i64) {
+    create_health_metric(
+        conn,
+        space_id,
+        None,
+        metric_type,
+        value,
+        None,
+        None,
+        recorded_at,
+    )
+    .unwrap();
+}
+
+fn target(
+    metric_type: &str,
+    comparison: GoalComparison,
+    target_value: f64,
+    aggregation: GoalAggregation,
+    deadline: Option<i64>,
+) -> HealthGoalTarget {
+    HealthGoalTarget {
+        metric_type: metric_type.to_string(),
+        comparison,
+        target_value,
+        unit: None,
+        aggregation,
+        deadline,
+    }
+}
+
+fn assert_close(actual: Option<f64>, expected: f64) {
+    let actual = actual.expect("a value");
+    assert!(
+        (actual - expected).abs() < 1e-9,
+        "expected {expected}, got {actual}"
+    );
+}
+
+#[test]
+fn test_aggregation_windows() {
+    let (conn, space_id) = setup_db();
+    // Six hours a night for 23 nights, then eight for a week
+    for day in 0..30 {
+        let hours = if day < 23 { 6.0 } else { 8.0 };
+        record(
+            &conn,
+            space_id,
+            "sleep",
+            hours,
+            START + day * DAY + 8 * 3600,
+        );
+    }
+    // A second reading on the last day; the day's mean stays at eight
+    record(&conn, space_id, "sleep", 7.0, START + 29 * DAY + 9 * 3600);
+    record(&conn, space_id, "sleep", 9.0, START + 29 * DAY + 20 * 3600);
+    let now = START + 30 * DAY;
+    // Readings after the evaluation don't count
+    record(&conn, space_id, "sleep", 1.0, now + DAY);
+
+    let windows = [
+        GoalAggregation::Latest,
+        GoalAggregation::SevenDayAverage,
+        GoalAggregation::ThirtyDayAverage,
+    ];
+    for aggregation in windows {
+        let goal = target("sleep", GoalComparison::AtLeast, 7.5, aggregation, None);
+        create_health_goal(&conn, space_id, "Sleep well", &goal).unwrap();
+    }
+    let evaluations = evaluate_health_goals_at(&conn, space_id, now).unwrap();
+    assert_eq!(evaluations.len(), 3);
+
+    assert_close(evaluations[0].current_value, 9.0);
+    assert_eq!(evaluations[0].status, HealthGoalStatus::Achieved);
+    assert_close(evaluations[1].current_value, 8.0);
+    assert_eq!(evaluations[1].status, HealthGoalStatus::Achieved);
+    assert_close(
+        evaluations[2].current_value,
+        (23.0 * 6.0 + 7.0 * 8.0) / 30.0,
+    );
+    assert_eq!(evaluations[2].status, HealthGoalStatus::Behind);
+    assert!(evaluations.iter().all(|e| e.projected_value.is_none()));
+
+    // The outcome is stored on the goals
+    let goals = get_health_goals(&conn, space_id).unwrap();
+    let stored = goals.iter().map(|g| g.status).collect::<Vec<_>>();
+    assert_eq!(
+        stored,
+        vec![
+            Some(HealthGoalStatus::Achieved),
+            Some(HealthGoalStatus::Achieved),
+            Some(HealthGoalStatus::Behind)
+        ]
+    );
+    assert!(goals.iter().all(|g| g.evaluated_at == Some(now)));
+    assert_close(goals[1].current_value, 8.0);
+
+    // Without readings in the window there's nothing to average
+    let later = now + 60 * DAY;
+    let evaluations = evaluate_health_goals_at(&conn, space_id, later).unwrap();
+    assert_close(evaluations[0].current_value, 1.0);
+    assert_eq!(evaluations[1].current_value, None);
+    assert_eq!(evaluations[1].status, HealthGoalStatus::Behind);
+}
+
+#[test]
+fn test_deadline_projection() {
+    let (conn, space_id) = setup_db();
+    // Half a kilo lost a day, from 90 to 85.5
+    for day in 0..10 {
+        let kg = 90.0 - 0.5 * day as f64;
+        record(&conn, space_id, "weight", kg, START + day * DAY + 7 * 3600);
+    }
+    let now = START + 10 * DAY;
+    let deadlines = [now + 20 * DAY, now + 5 * DAY, now - DAY];
+    let goals = deadlines
+        .iter()
+        .map(|&deadline| {
+            let goal = target(
+                "weight",
+                GoalComparison::AtMost,
+                80.0,
+                GoalAggregation::Latest,
+                Some(deadline),
+            );
+            create_health_goal(&conn, space_id, "Reach 80kg", &goal).unwrap()
+        })
+        .collect::<Vec<_>>();
+
+    let evaluations = evaluate_health_goals_at(&conn, space_id, now).unwrap();
+    for evaluation in &evaluations {
+        assert_close(evaluation.current_value, 85.5);
+    }
+    // 85.5 - 0.5 * 20 days = 75.5, under the target in time
+    assert_eq!(evaluations[0].goal_id, goals[0].id);
+    assert_close(evaluations[0].projected_value, 75.5);
+    assert_eq!(evaluations[0].status, HealthGoalStatus::OnTrack);
+    // 85.5 - 0.5 * 5 days = 83, not there yet
+    assert_close(evaluations[1].projected_value, 83.0);
+    assert_eq!(evaluations[1].status, HealthGoalStatus::Behind);
+    // Past the deadline nothing is projected
+    assert_eq!(evaluations[2].projected_value, None);
+    assert_eq!(evaluations[2].status, HealthGoalStatus::Expired);
+
+    // Half a day later the projection covers half a day less
+    let evaluations = evaluate_health_goals_at(&conn, space_id, now + DAY / 2).unwrap();
+    assert_close(evaluations[0].projected_value, 75.75);
+    let stored = get_health_goals(&conn, space_id).unwrap();
+    assert_close(stored[0].projected_value, 75.75);
+
+    // A flat trend projects the current value
+    let (conn, space_id) = setup_db();
+    record(&conn, space_id, "weight", 85.0, START);
+    let goal = target(
+        "weight",
+        GoalComparison::AtMost,
+        80.0,
+        GoalAggregation::Latest,
+        Some(START + 30 * DAY),
+    );
+    create_health_goal(&conn, space_id, "Reach 80kg", &goal).unwrap();
+    let evaluations = evaluate_health_goals_at(&conn, space_id, START + DAY).unwrap();
+    assert_close(evaluations[0].projected_value, 85.0);
+    assert_eq!(evaluations[0].status, HealthGoalStatus::Behind);
+}
+
+#[test]
+fn test_achieved_goal_falls_behind_when_new_data_arrives() {
+    let (conn, space_id) = setup_db();
+    let goal = create_health_goal(
+        &conn,
+        space_id,
+        "10k steps",
+        &target(
+            "steps",
+            GoalComparison::AtLeast,
+            10_000.0,
+            GoalAggregation::Latest,
+            None,
+        ),
+    )
+    .unwrap();
+    let goal_id = goal.id.to_string();
+    let sink = Arc::new(CollectingSink::default());
+    let sink_id = register_sink(sink.clone());
+
+    record(&conn, space_id, "steps", 12_000.0, START + 18 * 3600);
+    let evaluations = evaluate_health_goals_at(&conn, space_id, START + DAY).unwrap();
+    assert_eq!(evaluations[0].status, HealthGoalStatus::Achieved);
+    assert_eq!(evaluations[0].previous_status, None);
+    let achieved = sink
+        .wait_for(
+            WAIT,
+            |e| matches!(e, CoreEvent::HealthGoalAchieved { goal_id: id, .. } if *id == goal_id),
+        )
+        .expect("achieved event");
+    assert!(matches!(
+        achieved.event,
+        CoreEvent::HealthGoalAchieved { value, .. } if value == 12_000.0
+    ));
+
+    record(&conn, space_id, "steps", 4_000.0, START + DAY + 18 * 3600);
+    let evaluations = evaluate_health_goals_at(&conn, space_id, START + 2 * DAY).unwrap();
+    assert_eq!(evaluations[0].status, HealthGoalStatus::Behind);
+    assert_eq!(
+        evaluations[0].previous_status,
+        Some(HealthGoalStatus::Achieved)
+    );
+    sink.wait_for(WAIT, |e| {
+        matches!(
+            e,
+            CoreEvent::HealthGoalRegressed {
+                goal_id: id,
+                from: HealthGoalStatus::Achieved,
+                to: HealthGoalStatus::Behind,
+                ..
+            } if *id == goal_id
+        )
+    })
+    .expect("regressed event");
+
+    // Staying behind is no news, reaching it again is
+    evaluate_health_goals_at(&conn, space_id, START + 2 * DAY + 60).unwrap();
+    record(
+        &conn,
+        space_id,
+        "steps",
+        11_000.0,
+        START + 2 * DAY + 18 * 3600,
+    );
+    evaluate_health_goals_at(&conn, space_id, START + 3 * DAY).unwrap();
+    sink.wait_for(
+        WAIT,
+        |e| matches!(e, CoreEvent::HealthGoalAchieved { value, .. } if *value == 11_000.0),
+    )
+    .expect("achieved again");
+    unregister_sink(sink_id);
+
+    let events = sink
+        .events()
+        .into_iter()
+        .filter(|e| match &e.event {
+            CoreEvent::HealthGoalAchieved { goal_id: id, .. }
+            | CoreEvent::HealthGoalRegressed { goal_id: id, .. } => *id == goal_id,
+            _ => false,
+        })
+        .count();
+    assert_eq!(events, 3);
+}
+
+#[test]
+fn test_parse_free_form_goals() {
+    let sleep = parse_health_goal("Sleep 7h on average").unwrap();
+    assert_eq!(sleep.metric_type, "sleep");
+    assert_eq!(sleep.comparison, GoalComparison::AtLeast);
+    assert_eq!(sleep.target_value, 7.0);
+    assert_eq!(sleep.unit.as_deref(), Some("hours"));
+    assert_eq!(sleep.aggregation, GoalAggregation::SevenDayAverage);
+
+    let weight = parse_health_goal("Weigh under 180 lb").unwrap();
+    assert_eq!(weight.metric_type, "weight");
+    assert_eq!(weight.comparison, GoalComparison::AtMost);
+    assert_close(Some(weight.target_value), 180.0 * 0.453_592_37);
+    assert_eq!(weight.unit.as_deref(), Some("kg"));
+    assert_eq!(weight.aggregation, GoalAggregation::Latest);
+
+    let steps = parse_health_goal("Walk 10,000 steps a day over a 30-day stretch").unwrap();
+    assert_eq!(steps.metric_type, "steps");
+    assert_eq!(steps.target_value, 10_000.0);
+    assert_eq!(steps.aggregation, GoalAggregation::ThirtyDayAverage);
+
+    let heart = parse_health_goal("Resting heart rate below 60").unwrap();
+    assert_eq!(heart.metric_type, "heart_rate");
+    assert_eq!(heart.comparison, GoalComparison::AtMost);
+    assert_eq!(heart.unit.as_deref(), Some("bpm"));
+
+    assert_eq!(parse_health_goal("Run 8k"), None);
+    assert_eq!(parse_health_goal("Read more books"), None);
+}
+
+#[test]
+fn test_migration_imports_free_form_goals() {
+    let dir = tempdir().unwrap();
+    let mut conn = Connection::open(dir.path().join("vault.db")).unwrap();
+    conn.pragma_update(None, "foreign_keys", "ON").unwrap();
+    migrate(&mut conn).unwrap();
+    let space_id = create_space(&mut conn, "Health").unwrap();
+    migrate_to(&mut conn, 70, &dir.path().join("backup.db")).unwrap();
+
+    let sleep = create_goal(&conn, space_id, "Sleep 7h average", 7.0, "health").unwrap();
+    let weight = create_goal(&conn, space_id, "Get lighter", 80.0, "health").unwrap();
+    conn.execute(
+        "UPDATE goal SET description = 'Weight under 80kg', target_date = ?1 WHERE id = ?2",
+        rusqlite::params![START + 90 * DAY, weight.id.to_string()],
+    )
+    .unwrap();
+    create_goal(&conn, space_id, "Learn Spanish", 100.0, "personal").unwrap();
+    let done = create_goal(&conn, space_id, "Sleep 8h", 8.0, "health").unwrap();
+    conn.execute(
+        "UPDATE goal SET is_completed = 1 WHERE id = ?1",
+        [done.id.to_string()],
+    )
+    .unwrap();
+
+    migrate(&mut conn).unwrap();
+    let goals = get_health_goals(&conn, space_id).unwrap();
+    assert_eq!(goals.len(), 2);
+    let imported_sleep = goals
+        .iter()
+        .find(|g| g.goal_id == Some(sleep.id.to_string()))
+        .unwrap();
+    assert_eq!(imported_sleep.title, "Sleep 7h average");
+    assert_eq!(imported_sleep.target.metric_type, "sleep");
+    assert_eq!(imported_sleep.target.deadline, None);
+    assert_eq!(imported_sleep.status, None);
+
+    let imported_weight = goals
+        .iter()
+        .find(|g| g.goal_id == Some(weight.id.to_string()))
+        .unwrap();
+    assert_eq!(imported_weight.title, "Get lighter");
+    assert_eq!(imported_weight.target.comparison, GoalComparison::AtMost);
+    assert_eq!(imported_weight.target.target_value, 80.0);
+    assert_eq!(imported_weight.target.deadline, Some(START + 90 * DAY));
+
+    delete_health_goal(&conn, imported_sleep.id).unwrap();
+    assert!(delete_health_goal(&conn, imported_sleep.id).is_err());
+}
@@ -0,0 +1,294 @@
+use core_rs::db::migrate;
+use core_rs::health::import::{
+    import_health_export, HealthExportSource, HealthImportReport, METRIC_EXERCISE,
+    METRIC_HEART_RATE, METRIC_SLEEP, METRIC_STEPS, METRIC_WEIGHT,
+};
+use core_rs::space::create_space;
+use rusqlite::Connection;
+use std::fs::File;
+use std::io::Write;
+use std::path::Path;
+use ulid::Ulid;
+
+const APPLE_EXPORT: &str = "./tests/fixtures/apple_health/export.xml";
+const GOOGLE_FIT_EXPORT: &str = "./tests/fixtures/google_fit/All Data";
+
+/// Midnight UTC, 2025-01-02
+const JAN_2: i64 = 1_735_776_000;
+const HOUR: i64 = 60 * 60;
+
+fn setup_db() -> (Connection, Ulid) {
+    let mut conn = Connection::open_in_memory().unwrap();
+    conn.pragma_update(None, "foreign_keys", "ON").unwrap();
+    migrate(&mut conn).unwrap();
+    let space_id = create_space(&mut conn, "Health").unwrap();
+    (conn, space_id)
+}
+
+fn import(
+    conn: &Connection,
+    space_id: Ulid,
+    path: impl AsRef<Path>,
+    source: HealthExportSource,
+) -> HealthImportReport {
+    import_health_export(conn, space_id, path.as_ref(), source).unwrap()
+}
+
+fn imported(report: &HealthImportReport, metric_type: &str) -> usize {
+    report.metrics.get(metric_type).map_or(0, |c| c.imported)
+}
+
+/// `(value, unit, recorded_at)` of a metric type, oldest first
+fn readings(conn: &Connection, metric_type: &str) -> Vec<(f64, String, i64)> {
+    let mut stmt = conn
+        .prepare(
+            "SELECT value, unit, recorded_at FROM health_metric
+             WHERE metric_type = ?1 ORDER BY recorded_at",
+        )
+        .unwrap();
+    let rows = stmt
+        .query_map([metric_type], |row| {
+            Ok((row.get(0)?, row.get(1)?, row.get(2)?))
+        })
+        .unwrap()
+        .collect::<Result<Vec<_>, _>>()
+        .unwrap();
+    rows
+}
+
+fn metric_count(conn: &Connection) -> i64 {
+    conn.query_row("SELECT COUNT(*) FROM health_metric", [], |row| row.get(0))
+        .unwrap()
+}
+
+fn assert_close(actual: f64, expected: f64) {
+    assert!(
+        (actual - expected).abs() < 1e-6,
+        "expected {}, got {}",
+        expected,
+        actual
+    );
+}
+
+#[test]
+fn test_apple_health_export_maps_metrics_and_units() {
+    let (conn, space_id) = setup_db();
+    let report = import(
+        &conn,
+        space_id,
+        APPLE_EXPORT,
+        HealthExportSource::AppleHealth,
+    );
+
+    assert_eq!(imported(&report, METRIC_STEPS), 2);
+    assert_eq!(imported(&report, METRIC_WEIGHT), 2);
+    assert_eq!(imported(&report, METRIC_HEART_RATE), 2);
+    assert_eq!(imported(&report, METRIC_SLEEP), 1);
+    assert_eq!(imported(&report, METRIC_EXERCISE), 1);
+    assert_eq!(report.skipped_duplicates, 0);
+    // Active energy isn't imported
+    assert_eq!(report.unsupported_records, 1);
+
+    // Steps are summed per local day; the 00:30 walk belongs to January 3rd
+    // in the export's +01:00 offset although it's still the 2nd in UTC
+    let steps = readings(&conn, METRIC_STEPS);
+    assert_eq!(steps[0], (2000.0, "steps".to_string(), JAN_2 - HOUR));
+    assert_eq!(steps[1], (300.0, "steps".to_string(), JAN_2 + 23 * HOUR));
+
+    let weight = readings(&conn, METRIC_WEIGHT);
+    assert_close(weight[0].0, 74.842_741);
+    assert_eq!(weight[0].1, "kg");
+    assert_eq!(weight[0].2, JAN_2 + 6 * HOUR);
+    assert_close(weight[1].0, 74.5);
+
+    let heart_rate = readings(&conn, METRIC_HEART_RATE);
+    assert_eq!(heart_rate[0], (62.0, "bpm".to_string(), JAN_2 + 8 * HOUR));
+    assert_eq!(heart_rate[1].0, 71.0);
+
+    // Only asleep stages count, not time in bed or awake
+    let sleep = readings(&conn, METRIC_SLEEP);
+    assert_eq!(sleep, vec![(7.5, "hours".to_string(), JAN_2 - HOUR)]);
+
+    let (minutes, notes): (f64, String) = conn
+        .query_row(
+            "SELECT value, notes FROM health_metric WHERE metric_type = ?1",
+            [METRIC_EXERCISE],
+            |row| Ok((row.get(0)?, row.get(1)?)),
+        )
+        .unwrap();
+    assert_close(minutes, 32.5);
+    assert_eq!(notes, "Running");
+}
+
+#[test]
+fn test_apple_health_reimport_adds_nothing() {
+    let (conn, space_id) = setup_db();
+    import(
+        &conn,
+        space_id,
+        APPLE_EXPORT,
+        HealthExportSource::AppleHealth,
+    );
+    let before = metric_count(&conn);
+
+    let report = import(
+        &conn,
+        space_id,
+        APPLE_EXPORT,
+        HealthExportSource::AppleHealth,
+    );
+    assert_eq!(report.imported(), 0);
+    assert_eq!(report.skipped_duplicates, 8);
+    assert_eq!(report.metrics[METRIC_STEPS].duplicates, 2);
+    assert_eq!(metric_count(&conn), before);
+}
+
+#[test]
+fn test_apple_health_zip_and_grown_daily_totals() {
+    let dir = tempfile::tempdir().unwrap();
+    let (conn, space_id) = setup_db();
+    import(
+        &conn,
+        space_id,
+        APPLE_EXPORT,
+        HealthExportSource::AppleHealth,
+    );
+
+    // A later export, zipped as the Health app shares it, with one more
+    // walk on January 3rd
+    let later = std::fs::read_to_string(APPLE_EXPORT).unwrap().replace(
+        "</HealthData>",
+        r#" <Record type="HKQuantityTypeIdentifierStepCount" sourceName="iPhone" unit="count" startDate="2025-01-03 10:00:00 +0100" endDate="2025-01-03 10:20:00 +0100" value="2100"/>
+</HealthData>"#,
+    );
+    let zip_path = dir.path().join("export.zip");
+    let mut zip = zip::ZipWriter::new(File::create(&zip_path).unwrap());
+    let options: zip::write::FileOptions<()> = zip::write::FileOptions::default();
+    zip.start_file("apple_health_export/export_cda.xml", options)
+        .unwrap();
+    zip.write_all(b"<ClinicalDocument/>").unwrap();
+    zip.start_file("apple_health_export/export.xml", options)
+        .unwrap();
+    zip.write_all(later.as_bytes()).unwrap();
+    zip.finish().unwrap();
+
+    let report = import(&conn, space_id, &zip_path, HealthExportSource::AppleHealth);
+    assert_eq!(report.imported(), 0);
+    assert_eq!(report.metrics[METRIC_STEPS].updated, 1);
+    assert_eq!(report.metrics[METRIC_STEPS].duplicates, 1);
+    let steps = readings(&conn, METRIC_STEPS);
+    assert_eq!(steps.len(), 2);
+    assert_eq!(steps[1].0, 2400.0);
+}
+
+#[test]
+fn test_google_fit_takeout_maps_metrics_and_units() {
+    let (conn, space_id) = setup_db();
+    let report = import(
+        &conn,
+        space_id,
+        GOOGLE_FIT_EXPORT,
+        HealthExportSource::GoogleFit,
+    );
+
+    assert_eq!(imported(&report, METRIC_STEPS), 2);
+    assert_eq!(imported(&report, METRIC_WEIGHT), 1);
+    assert_eq!(imported(&report, METRIC_HEART_RATE), 2);
+    assert_eq!(imported(&report, METRIC_SLEEP), 1);
+    // Still and walking segments aren't workouts
+    assert_eq!(imported(&report, METRIC_EXERCISE), 1);
+    assert_eq!(report.unsupported_records, 1);
+
+    let steps = readings(&conn, METRIC_STEPS);
+    assert_eq!(
+        steps,
+        vec![
+            (2000.0, "steps".to_string(), JAN_2),
+            (1000.0, "steps".to_string(), JAN_2 + 24 * HOUR),
+        ]
+    );
+    assert_eq!(
+        readings(&conn, METRIC_WEIGHT),
+        vec![(74.2, "kg".to_string(), JAN_2 + 7 * HOUR)]
+    );
+    assert_eq!(
+        readings(&conn, METRIC_HEART_RATE)[0],
+        (64.0, "bpm".to_string(), JAN_2 + 9 * HOUR)
+    );
+    assert_eq!(
+        readings(&conn, METRIC_SLEEP),
+        vec![(7.0, "hours".to_string(), JAN_2)]
+    );
+    assert_eq!(
+        readings(&conn, METRIC_EXERCISE),
+        vec![(45.0, "min".to_string(), JAN_2 + 17 * HOUR)]
+    );
+}
+
+#[test]
+fn test_google_fit_reimport_adds_nothing() {
+    let (conn, space_id) = setup_db();
+    import(
+        &conn,
+        space_id,
+        GOOGLE_FIT_EXPORT,
+        HealthExportSource::GoogleFit,
+    );
+    let before = metric_count(&conn);
+
+    // One file on its own overlaps the directory imported before
+    let weight_file = Path::new(GOOGLE_FIT_EXPORT)
+        .join("derived_com.google.weight_com.google.android.gms_merge_weight.json");
+    let report = import(&conn, space_id, &weight_file, HealthExportSource::GoogleFit);
+    assert_eq!(report.imported(), 0);
+    assert_eq!(report.skipped_duplicates, 1);
+
+    let report = import(
+        &conn,
+        space_id,
+        GOOGLE_FIT_EXPORT,
+        HealthExportSource::GoogleFit,
+    );
+    assert_eq!(report.imported(), 0);
+    assert_eq!(report.skipped_duplicates, 7);
+    assert_eq!(metric_count(&conn), before);
+}
+
+#[test]
+fn test_wrong_files_are_rejected_without_changes() {
+    let dir = tempfile::tempdir().unwrap();
+    let (conn, space_id) = setup_db();
+
+    let not_health = dir.path().join("export.xml");
+    std::fs::write(&not_health, "<ClinicalDocument><id/></ClinicalDocument>").unwrap();
+    assert!(import_health_export(
+        &conn,
+        space_id,
+        &not_health,
+        HealthExportSource::AppleHealth
+    )
+    .is_err());
+
+    let empty_zip = dir.path().join("export.zip");
+    zip::ZipWriter::new(File::create(&empty_zip).unwrap())
+        .finish()
+        .unwrap();
+    assert!(
+        import_health_export(&conn, space_id, &empty_zip, HealthExportSource::AppleHealth).is_err()
+    );
+
+    // A broken file halfway through the takeout rolls back the files before it
+    let takeout = dir.path().join("All Data");
+    std::fs::create_dir(&takeout).unwrap();
+    std::fs::copy(
+        Path::new(GOOGLE_FIT_EXPORT)
+            .join("derived_com.google.weight_com.google.android.gms_merge_weight.json"),
+        takeout.join("a_weight.json"),
+    )
+    .unwrap();
+    std::fs::write(takeout.join("b_broken.json"), "{\"Data Points\": [").unwrap();
+    assert!(
+        import_health_export(&conn, space_id, &takeout, HealthExportSource::GoogleFit).is_err()
+    );
+    assert_eq!(metric_count(&conn), 0);
+}
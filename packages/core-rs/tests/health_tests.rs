@@ -0,0 +1,179 @@
+use core_rs::calendar::TimeRange;
+use core_rs::db::migrate;
+use core_rs::health::{
+    check_threshold_breaches, create_health_metric, get_metric_settings, get_metric_trend,
+    set_metric_direction, set_metric_threshold, TrendDirection,
+};
+use core_rs::space::create_space;
+use rusqlite::Connection;
+use ulid::Ulid;
+
+const DAY: i64 = 24 * 60 * 60;
+
+/// Midnight UTC, 2025-01-01
+const START: i64 = 1_735_689_600;
+
+fn setup_db() -> (Connection, Ulid) {
+    let mut conn = Connection::open_in_memory().unwrap();
+    conn.pragma_update(None, "foreign_keys", "ON").unwrap();
+    migrate(&mut conn).unwrap();
+    let space_id = create_space(&mut conn, "Health").unwrap();
+    (conn, space_id)
+}
+
+fn record(conn: &Connection, space_id: Ulid, metric_type: &str, value: f64, recorded_at: i64) {
+    create_health_metric(
+        conn,
+        space_id,
+        None,
+        metric_type,
+        value,
+        None,
+        None,
+        recorded_at,
+    )
+    .unwrap();
+}
+
+fn january() -> TimeRange {
+    TimeRange::new(START, START + 31 * DAY)
+}
+
+/// Weight dropping 0.1 kg a day, weighed on irregular days
+fn weight_series(conn: &Connection, space_id: Ulid) {
+    for day in [0, 1, 2, 5, 6, 12, 13, 14, 20, 27, 28] {
+        let value = 82.0 - 0.1 * day as f64;
+        record(
+            conn,
+            space_id,
+            "weight",
+            value,
+            START + day * DAY + 7 * 3600,
+        );
+    }
+}
+
+#[test]
+fn test_weight_trend_direction_follows_configuration() {
+    let (conn, space_id) = setup_db();
+    weight_series(&conn, space_id);
+
+    let trend = get_metric_trend(&conn, space_id, "weight", january()).unwrap();
+    assert_eq!(trend.points.len(), 11);
+    assert!((trend.slope_per_day + 0.1).abs() < 1e-9);
+    assert_eq!(trend.min, Some(82.0 - 2.8));
+    assert_eq!(trend.max, Some(82.0));
+    // Losing weight is the default goal
+    assert!(!trend.higher_is_better);
+    assert_eq!(trend.direction, TrendDirection::Improving);
+
+    set_metric_direction(&conn, space_id, "weight", true).unwrap();
+    let trend = get_metric_trend(&conn, space_id, "weight", january()).unwrap();
+    assert_eq!(trend.direction, TrendDirection::Declining);
+}
+
+#[test]
+fn test_mood_trends_up_down_and_flat() {
+    let (conn, space_id) = setup_db();
+    for day in 0..14 {
+        // Improving, with a wobble that doesn't hide the trend
+        let wobble = if day % 2 == 0 { 0.5 } else { -0.5 };
+        record(
+            &conn,
+            space_id,
+            "mood",
+            4.0 + 0.3 * day as f64 + wobble,
+            START + day * DAY,
+        );
+        record(
+            &conn,
+            space_id,
+            "energy",
+            8.0 - 0.2 * day as f64,
+            START + day * DAY,
+        );
+        record(
+            &conn,
+            space_id,
+            "sleep_hours",
+            7.5 + wobble * 0.1,
+            START + day * DAY,
+        );
+    }
+
+    let mood = get_metric_trend(&conn, space_id, "mood", january()).unwrap();
+    assert!(mood.higher_is_better);
+    assert_eq!(mood.direction, TrendDirection::Improving);
+    let energy = get_metric_trend(&conn, space_id, "energy", january()).unwrap();
+    assert_eq!(energy.direction, TrendDirection::Declining);
+    let sleep = get_metric_trend(&conn, space_id, "sleep_hours", january()).unwrap();
+    assert_eq!(sleep.direction, TrendDirection::Stable);
+
+    let empty = get_metric_trend(&conn, space_id, "steps", january()).unwrap();
+    assert!(empty.points.is_empty());
+    assert_eq!(empty.min, None);
+    assert_eq!(empty.direction, TrendDirection::Stable);
+}
+
+#[test]
+fn test_moving_average_handles_irregular_sampling() {
+    let (conn, space_id) = setup_db();
+    // Three readings on the first day count as one day
+    for hour in [8, 12, 20] {
+        record(&conn, space_id, "mood", 4.0, START + hour * 3600);
+    }
+    record(&conn, space_id, "mood", 7.0, START + DAY);
+    // After a ten-day gap the first days have left the window
+    record(&conn, space_id, "mood", 9.0, START + 11 * DAY);
+    record(&conn, space_id, "mood", 6.0, START + 13 * DAY);
+
+    let trend = get_metric_trend(&conn, space_id, "mood", january()).unwrap();
+    let averages: Vec<(f64, usize, f64)> = trend
+        .points
+        .iter()
+        .map(|p| (p.value, p.samples, p.moving_average))
+        .collect();
+    assert_eq!(
+        averages,
+        vec![(4.0, 3, 4.0), (7.0, 1, 5.5), (9.0, 1, 9.0), (6.0, 1, 7.5)]
+    );
+    assert_eq!(trend.points[2].day, START + 11 * DAY);
+}
+
+#[test]
+fn test_threshold_breaches() {
+    let (conn, space_id) = setup_db();
+    let now = chrono::Utc::now().timestamp();
+    set_metric_threshold(&conn, space_id, "weight", None, Some(80.0)).unwrap();
+    set_metric_threshold(&conn, space_id, "mood", Some(4.0), Some(10.0)).unwrap();
+    set_metric_direction(&conn, space_id, "mood", true).unwrap();
+
+    record(&conn, space_id, "weight", 79.5, now - 3 * DAY);
+    record(&conn, space_id, "weight", 81.2, now - 2 * DAY);
+    record(&conn, space_id, "mood", 3.0, now - DAY);
+    record(&conn, space_id, "mood", 4.0, now - 3600);
+    // Too old to be news, and a metric without bounds
+    record(&conn, space_id, "weight", 85.0, now - 10 * DAY);
+    record(&conn, space_id, "steps", 100.0, now - DAY);
+    // Another space's readings
+    let other = Ulid::new();
+    conn.execute(
+        "INSERT INTO space (id, name) VALUES (?1, 'Other')",
+        [other.to_string()],
+    )
+    .unwrap();
+    record(&conn, other, "weight", 90.0, now - DAY);
+
+    let breaches = check_threshold_breaches(&conn, space_id).unwrap();
+    let found: Vec<(&str, f64)> = breaches
+        .iter()
+        .map(|b| (b.metric_type.as_str(), b.value))
+        .collect();
+    assert_eq!(found, vec![("mood", 3.0), ("weight", 81.2)]);
+    assert_eq!(breaches[1].max_value, Some(80.0));
+
+    // Setting the direction kept the bounds
+    let mood = get_metric_settings(&conn, space_id, "mood").unwrap();
+    assert_eq!((mood.min_value, mood.max_value), (Some(4.0), Some(10.0)));
+    assert!(set_metric_threshold(&conn, space_id, "mood", Some(5.0), Some(1.0)).is_err());
+}
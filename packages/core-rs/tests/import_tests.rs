@@ -56,6 +56,166 @@ fn test_import_from_notion() -> Result<(), DbError> {
     Ok(())
 }
 
+/// Zip the miniature Notion export in `tests/fixtures/notion_export` the way
+/// Notion ships it
+fn zip_notion_fixture(dir: &std::path::Path) -> std::path::PathBuf {
+    let root = std::path::Path::new("./tests/fixtures/notion_export");
+    let zip_path = dir.join("notion_export.zip");
+    let mut zip = zip::ZipWriter::new(File::create(&zip_path).unwrap());
+    let options: zip::write::FileOptions<()> = zip::write::FileOptions::default();
+    for entry in walkdir::WalkDir::new(root).sort_by_file_name() {
+        let entry = entry.unwrap();
+        if entry.file_type().is_file() {
+            let name = entry.path().strip_prefix(root).unwrap();
+            zip.start_file(name.to_string_lossy().replace('\\', "/"), options)
+                .unwrap();
+            zip.write_all(&std::fs::read(entry.path()).unwrap())
+                .unwrap();
+        }
+    }
+    zip.finish().unwrap();
+    zip_path
+}
+
+fn note_content(conn: &Connection, space_id: &str, title: &str) -> String {
+    conn.query_row(
+        "SELECT content_md FROM note WHERE space_id = ?1 AND title = ?2",
+        [space_id, title],
+        |row| row.get(0),
+    )
+    .unwrap()
+}
+
+#[test]
+fn test_import_notion_databases_as_tasks_and_projects() {
+    use core_rs::task::get_task;
+
+    let (dir, mut conn) = setup_db();
+    let space_id = create_space(&mut conn, "test_space").unwrap();
+    let space = space_id.to_string();
+    let zip_path = zip_notion_fixture(dir.path());
+
+    let report = import_from_notion(&conn, space_id, zip_path.to_str().unwrap()).unwrap();
+    assert_eq!(report.projects, 2);
+    // Read from `Tasks _all.csv`, not the two-row current view
+    assert_eq!(report.tasks, 4);
+    assert_eq!(report.linked_tasks, 3);
+    // Three pages in the page tree plus one page per database row
+    assert_eq!(report.notes, 9);
+    let mut databases: Vec<_> = report
+        .databases
+        .iter()
+        .map(|db| (db.name.as_str(), db.kind, db.rows))
+        .collect();
+    databases.sort_by_key(|db| db.0);
+    assert_eq!(
+        databases,
+        vec![
+            ("Projects", NotionEntityKind::Project, 2),
+            ("Tasks", NotionEntityKind::Task, 4)
+        ]
+    );
+
+    // The page tree keeps its titles without Notion's id suffixes
+    for title in ["Workspace", "Roadmap", "Q1 Goals"] {
+        assert!(!note_content(&conn, &space, title).is_empty());
+    }
+
+    let project = |title: &str| -> (String, String, Option<i64>, Option<i64>) {
+        conn.query_row(
+            "SELECT id, status, start_at, target_end_at FROM project WHERE title = ?1",
+            [title],
+            |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?)),
+        )
+        .unwrap()
+    };
+    let (website_id, status, start_at, target_end_at) = project("Website Redesign");
+    assert_eq!(status, "active");
+    assert_eq!(start_at, Some(1_704_067_200));
+    assert_eq!(target_end_at, Some(1_711_843_200));
+    let (mobile_id, status, _, _) = project("Mobile App");
+    assert_eq!(status, "done");
+
+    let task = |title: &str| {
+        let id: String = conn
+            .query_row("SELECT id FROM task WHERE title = ?1", [title], |row| {
+                row.get(0)
+            })
+            .unwrap();
+        get_task(&conn, id.parse().unwrap()).unwrap().unwrap()
+    };
+    let wireframes = task("Draft wireframes");
+    assert_eq!(wireframes.status, "in_progress");
+    assert_eq!(wireframes.priority, Some(1));
+    assert_eq!(wireframes.due_at, Some(1_705_276_800));
+    assert_eq!(
+        wireframes.project_id.map(|id| id.to_string()),
+        Some(website_id.clone())
+    );
+    assert!(wireframes.note_id.is_some());
+
+    let beta = task("Ship beta");
+    assert_eq!(beta.status, "done");
+    assert_eq!(beta.due_at, Some(1_706_799_600));
+    assert_eq!(beta.project_id.map(|id| id.to_string()), Some(mobile_id));
+
+    // Linked only through the project's Tasks relation column
+    let copy = task("Write copy");
+    assert_eq!(copy.status, "next");
+    assert_eq!(copy.project_id.map(|id| id.to_string()), Some(website_id));
+
+    let login = task("Fix login, \"urgent\"");
+    assert_eq!(login.status, "waiting");
+    assert_eq!(login.priority, Some(1));
+    assert_eq!(login.due_at, None);
+    assert_eq!(login.project_id, None);
+
+    // Row pages keep their body; unmapped columns are appended as properties
+    let content = note_content(&conn, &space, "Draft wireframes");
+    assert!(content.starts_with("Sketch the landing page."));
+    assert!(content.contains("## Properties"));
+    assert!(content.contains("- Assignee: Alice"));
+    assert!(content.contains("- Tags: design, ux"));
+    assert!(!content.contains("Status:"));
+    assert!(!content.contains("Project:"));
+    // A date Notion wrote that can't be parsed is kept, not lost
+    let content = note_content(&conn, &space, "Fix login, \"urgent\"");
+    assert!(content.contains("- Due: sometime"));
+    let content = note_content(&conn, &space, "Website Redesign");
+    assert!(content.starts_with("Refresh the marketing site."));
+    assert!(content.contains("- Owner: Alice"));
+    assert!(!content.contains("Tasks:"));
+}
+
+#[test]
+fn test_import_notion_database_mapping_overrides_detection() {
+    let (dir, mut conn) = setup_db();
+    let space_id = create_space(&mut conn, "test_space").unwrap();
+    let zip_path = zip_notion_fixture(dir.path());
+
+    let options = NotionImportOptions {
+        database_kinds: [("tasks".to_string(), NotionEntityKind::Note)]
+            .into_iter()
+            .collect(),
+    };
+    let report =
+        import_from_notion_with_options(&conn, space_id, zip_path.to_str().unwrap(), &options)
+            .unwrap();
+    assert_eq!(
+        (report.projects, report.tasks, report.linked_tasks),
+        (2, 0, 0)
+    );
+    assert_eq!(report.notes, 9);
+
+    let task_count: i64 = conn
+        .query_row("SELECT COUNT(*) FROM task", [], |row| row.get(0))
+        .unwrap();
+    assert_eq!(task_count, 0);
+    let content = note_content(&conn, &space_id.to_string(), "Draft wireframes");
+    assert!(content.contains("- Status: In progress"));
+    assert!(content.contains("- Due: January 15, 2024"));
+}
+
 // ========== EXPORT SECURITY TESTS ==========
 
 #[test]
@@ -321,3 +481,131 @@ fn test_export_to_zip_preserves_content() -> Result<(), DbError> {
 
     Ok(())
 }
+
+fn fts_rows(conn: &Connection, table: &str) -> i64 {
+    conn.query_row(&format!("SELECT COUNT(*) FROM {}", table), [], |row| {
+        row.get(0)
+    })
+    .unwrap()
+}
+
+fn has_trigger(conn: &Connection, name: &str) -> bool {
+    conn.query_row(
+        "SELECT EXISTS (SELECT 1 FROM sqlite_master WHERE type = 'trigger' AND name = ?1)",
+        [name],
+        |row| row.get(0),
+    )
+    .unwrap()
+}
+
+#[test]
+fn test_bulk_import_indexes_everything_once_done() {
+    let (_dir, mut conn) = setup_db();
+    let space_id = create_space(&mut conn, "test_space").unwrap();
+
+    let import_dir = tempdir().unwrap();
+    for (name, body) in [
+        ("Garden", "Tomatoes need staking"),
+        ("Ünïcode Café", "Espresso tasting notes"),
+        ("Reading", "Finish the novel"),
+    ] {
+        std::fs::write(import_dir.path().join(format!("{}.md", name)), body).unwrap();
+    }
+    let report =
+        import_from_obsidian(&conn, space_id, import_dir.path().to_str().unwrap()).unwrap();
+    assert_eq!(report.notes, 3);
+    assert_eq!(fts_rows(&conn, "fts_note"), 3);
+
+    let found = core_rs::search::search_notes(&conn, "staking", &space_id.to_string()).unwrap();
+    assert_eq!(found.len(), 1);
+    assert_eq!(found[0].title, "Garden");
+    let indexed_title: String = conn
+        .query_row(
+            "SELECT title FROM fts_note WHERE fts_note MATCH 'espresso'",
+            [],
+            |row| row.get(0),
+        )
+        .unwrap();
+    assert_eq!(indexed_title, "ünïcode café");
+
+    // Tasks and projects from a Notion export are searchable too
+    let zip_dir = tempdir().unwrap();
+    let zip_path = zip_notion_fixture(zip_dir.path());
+    let report = import_from_notion(&conn, space_id, zip_path.to_str().unwrap()).unwrap();
+    assert_eq!(fts_rows(&conn, "fts_task"), report.tasks as i64);
+    assert_eq!(fts_rows(&conn, "fts_project"), report.projects as i64);
+    let notes: i64 = conn
+        .query_row("SELECT COUNT(*) FROM note", [], |row| row.get(0))
+        .unwrap();
+    assert_eq!(fts_rows(&conn, "fts_note"), notes);
+
+    // The triggers are back for ordinary writes
+    assert!(has_trigger(&conn, "task_ai"));
+    assert!(has_trigger(&conn, "project_ai"));
+    assert!(has_trigger(&conn, "social_post_ai"));
+    core_rs::task::create_task(&conn, space_id, "Water the plants", None).unwrap();
+    assert_eq!(fts_rows(&conn, "fts_task"), report.tasks as i64 + 1);
+    core_rs::note::create_note(&conn, &space_id.to_string(), "After", "plain write").unwrap();
+    assert_eq!(fts_rows(&conn, "fts_note"), notes + 1);
+}
+
+#[test]
+fn test_bulk_import_rolls_back_on_error() {
+    let (_dir, mut conn) = setup_db();
+    let space_id = create_space(&mut conn, "test_space").unwrap().to_string();
+
+    let result: Result<(), DbError> = core_rs::db::with_bulk_import(&conn, |conn| {
+        core_rs::note::create_note(conn, &space_id, "Doomed", "never committed")?;
+        Err(DbError::Message("import failed".into()))
+    });
+    assert!(result.is_err());
+
+    let notes: i64 = conn
+        .query_row("SELECT COUNT(*) FROM note", [], |row| row.get(0))
+        .unwrap();
+    assert_eq!(notes, 0);
+    assert_eq!(fts_rows(&conn, "fts_note"), 0);
+    assert!(has_trigger(&conn, "task_ai"));
+    assert!(conn.is_autocommit());
+    let journal_mode: String = conn
+        .query_row("PRAGMA journal_mode", [], |row| row.get(0))
+        .unwrap();
+    assert_eq!(journal_mode, "delete");
+}
+
+#[test]
+fn test_bulk_import_is_faster_than_note_by_note() {
+    let (_dir, mut conn) = setup_db();
+    let one_by_one = create_space(&mut conn, "one_by_one").unwrap().to_string();
+    let bulk = create_space(&mut conn, "bulk").unwrap().to_string();
+    let body = "Imported note body with a few words to index. ".repeat(20);
+
+    let started = std::time::Instant::now();
+    for i in 0..1_000 {
+        core_rs::note::create_note(&conn, &one_by_one, &format!("Note {}", i), &body).unwrap();
+    }
+    let note_by_note = started.elapsed();
+
+    let started = std::time::Instant::now();
+    core_rs::db::with_bulk_import(&conn, |conn| {
+        for i in 0..1_000 {
+            core_rs::note::create_note(conn, &bulk, &format!("Note {}", i), &body)?;
+        }
+        Ok::<_, DbError>(())
+    })
+    .unwrap();
+    let batched = started.elapsed();
+
+    assert_eq!(
+        core_rs::search::search_notes(&conn, "Imported", &bulk)
+            .unwrap()
+            .len(),
+        1_000
+    );
+    assert!(
+        batched < note_by_note,
+        "bulk import took {:?}, note by note {:?}",
+        batched,
+        note_by_note
+    );
+}
@@ -0,0 +1,206 @@
+use core_rs::db::migrate;
+use core_rs::inbox::*;
+use core_rs::search::{search_all, EntityType, SearchFilters, SearchQuery, SortOptions};
+use core_rs::space::create_space;
+use core_rs::sync::SyncEntityType;
+use core_rs::sync_agent::{init_sync_tables, SyncAgent};
+use rusqlite::Connection;
+use ulid::Ulid;
+
+const DEK: [u8; 32] = [0u8; 32];
+
+fn setup_db() -> (Connection, Ulid) {
+    let mut conn = Connection::open_in_memory().unwrap();
+    migrate(&mut conn).unwrap();
+    let space_id = create_space(&mut conn, "Inbox").unwrap();
+    (conn, space_id)
+}
+
+fn search(conn: &Connection, text: &str, space_id: Option<Ulid>) -> Vec<(EntityType, String)> {
+    let query = SearchQuery {
+        query: text.to_string(),
+        entity_types: vec![EntityType::All],
+        filters: SearchFilters {
+            space_id,
+            ..Default::default()
+        },
+        sort: SortOptions::default(),
+        limit: None,
+        offset: None,
+    };
+    search_all(conn, &query)
+        .unwrap()
+        .into_iter()
+        .map(|r| (r.entity_type, r.entity_id))
+        .collect()
+}
+
+#[test]
+fn test_captures_are_listed_oldest_first() {
+    let (conn, _) = setup_db();
+    let first = capture_to_inbox(&conn, "Call the plumber", "mobile").unwrap();
+    let second = capture_to_inbox(&conn, "Idea: bike to work", "desktop").unwrap();
+    conn.execute(
+        "UPDATE inbox_item SET captured_at = captured_at - 60 WHERE id = ?1",
+        [&first.id],
+    )
+    .unwrap();
+
+    let items = get_inbox_items(&conn).unwrap();
+    assert_eq!(
+        items.iter().map(|i| i.id.as_str()).collect::<Vec<_>>(),
+        [first.id.as_str(), second.id.as_str()]
+    );
+    assert_eq!(items[1].text, "Idea: bike to work");
+    assert_eq!(items[1].source, "desktop");
+
+    assert!(matches!(
+        capture_to_inbox(&conn, "  \n", "mobile"),
+        Err(InboxError::InvalidData(_))
+    ));
+}
+
+#[test]
+fn test_captures_show_up_in_search() {
+    let (conn, space_id) = setup_db();
+    let item = capture_to_inbox(
+        &conn,
+        "Look into solar panels\nquotes from 3 installers",
+        "mobile",
+    )
+    .unwrap();
+    capture_to_inbox(&conn, "Buy stamps", "mobile").unwrap();
+
+    assert_eq!(
+        search(&conn, "solar", None),
+        [(EntityType::InboxItem, item.id.clone())]
+    );
+    let query = SearchQuery {
+        query: "installers".to_string(),
+        entity_types: vec![EntityType::InboxItem],
+        filters: SearchFilters::default(),
+        sort: SortOptions::default(),
+        limit: None,
+        offset: None,
+    };
+    let results = search_all(&conn, &query).unwrap();
+    assert_eq!(results.len(), 1);
+    assert_eq!(results[0].title, "Look into solar panels");
+    assert!(results[0]
+        .snippet
+        .as_deref()
+        .unwrap()
+        .contains("installers"));
+
+    // Captures belong to no space
+    assert!(search(&conn, "solar", Some(space_id)).is_empty());
+}
+
+#[test]
+fn test_converting_to_a_note_keeps_the_text() {
+    let (mut conn, space_id) = setup_db();
+    let text = "Gift ideas\n- a kite\n- a good atlas";
+    let item = capture_to_inbox(&conn, text, "mobile").unwrap();
+
+    let note = convert_inbox_to_note(&mut conn, &item.id, &space_id.to_string(), "Gifts").unwrap();
+    assert_eq!(note.title, "Gifts");
+    assert_eq!(note.content_md, text);
+    assert!(get_inbox_items(&conn).unwrap().is_empty());
+    assert_eq!(
+        search(&conn, "atlas", None),
+        [(EntityType::Note, note.id.to_string())]
+    );
+
+    // Already triaged
+    assert!(matches!(
+        convert_inbox_to_note(&mut conn, &item.id, &space_id.to_string(), "Again"),
+        Err(InboxError::NotFound(_))
+    ));
+}
+
+#[test]
+fn test_converting_to_a_task_keeps_the_text() {
+    let (mut conn, space_id) = setup_db();
+    let short = capture_to_inbox(&conn, "Renew passport", "mobile").unwrap();
+    let long =
+        capture_to_inbox(&conn, "Plan the offsite\nVenue, agenda, budget", "mobile").unwrap();
+
+    let task = convert_inbox_to_task(&mut conn, &short.id, space_id).unwrap();
+    assert_eq!(task.title, "Renew passport");
+    assert_eq!(task.description, None);
+
+    let task = convert_inbox_to_task(&mut conn, &long.id, space_id).unwrap();
+    assert_eq!(task.title, "Plan the offsite");
+    assert_eq!(
+        task.description.as_deref(),
+        Some("Plan the offsite\nVenue, agenda, budget")
+    );
+    assert!(get_inbox_items(&conn).unwrap().is_empty());
+}
+
+#[test]
+fn test_failed_conversion_leaves_the_capture_in_the_inbox() {
+    let (mut conn, _) = setup_db();
+    conn.pragma_update(None, "foreign_keys", "ON").unwrap();
+    let item = capture_to_inbox(&conn, "Keep me", "mobile").unwrap();
+
+    let missing_space = Ulid::new().to_string();
+    assert!(convert_inbox_to_note(&mut conn, &item.id, &missing_space, "Lost").is_err());
+    assert_eq!(get_inbox_items(&conn).unwrap(), [item.clone()]);
+
+    dismiss_inbox_item(&mut conn, &item.id).unwrap();
+    assert!(get_inbox_items(&conn).unwrap().is_empty());
+    assert!(matches!(
+        dismiss_inbox_item(&mut conn, &item.id),
+        Err(InboxError::NotFound(_))
+    ));
+}
+
+#[test]
+fn test_mobile_captures_sync_to_desktop() {
+    let space_id = Ulid::new();
+    let setup = || {
+        let mut conn = Connection::open_in_memory().unwrap();
+        migrate(&mut conn).unwrap();
+        init_sync_tables(&conn).unwrap();
+        conn.execute(
+            "INSERT INTO space (id, name) VALUES (?1, 'Shared')",
+            [space_id.to_string()],
+        )
+        .unwrap();
+        conn
+    };
+    let desktop = SyncAgent::new("desktop".into(), "Desktop".into(), 0);
+    let phone = SyncAgent::new("phone".into(), "Phone".into(), 0);
+    let mut desktop_conn = setup();
+    let phone_conn = setup();
+    desktop
+        .register_device(&desktop_conn, &phone.get_device_info())
+        .unwrap();
+    phone
+        .register_device(&phone_conn, &desktop.get_device_info())
+        .unwrap();
+
+    let item = capture_to_inbox(&phone_conn, "Book dentist", "mobile").unwrap();
+    let deltas = phone
+        .get_deltas_for_peer(&phone_conn, space_id, 0, &desktop.get_device_info())
+        .unwrap();
+    let delta = deltas
+        .iter()
+        .find(|d| {
+            SyncEntityType::from_delta_type(&d.entity_type) == Some(SyncEntityType::InboxItem)
+        })
+        .unwrap();
+    assert_eq!(delta.entity_id, item.id);
+    assert_eq!(delta.space_id, None);
+
+    let conflicts = desktop
+        .apply_deltas_from(&mut desktop_conn, deltas, &DEK, "phone")
+        .unwrap();
+    assert!(conflicts.is_empty());
+    assert_eq!(get_inbox_items(&desktop_conn).unwrap(), [item.clone()]);
+
+    // Triaged on the desktop
+    let task = convert_inbox_to_task(&mut desktop_conn, &item.id, space_id).unwrap();
+    assert_eq!(task.title, "Book dentist");
+}
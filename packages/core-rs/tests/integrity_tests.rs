@@ -0,0 +1,313 @@
+use core_rs::caldav::init_caldav_tables;
+use core_rs::db::integrity::set_startup_integrity_check;
+use core_rs::db::{
+    check_vault, check_vault_with_mode, migrate, repair_vault, IntegrityCheckMode,
+    IntegrityIssueKind, IssueSeverity,
+};
+use core_rs::note::create_note;
+use core_rs::space::create_space;
+use core_rs::tag::create_tag;
+use rusqlite::{params, Connection};
+
+const MISSING: &str = "01HZZZZZZZZZZZZZZZZZZZZZZZ";
+
+fn setup_db() -> (Connection, String) {
+    let mut conn = Connection::open_in_memory().unwrap();
+    migrate(&mut conn).unwrap();
+    init_caldav_tables(&conn).unwrap();
+    let space_id = create_space(&mut conn, "Vault").unwrap().to_string();
+    // Corruption is seeded the way it happens in the wild: without enforcement
+    conn.pragma_update(None, "foreign_keys", "OFF").unwrap();
+    (conn, space_id)
+}
+
+fn insert_blob(conn: &Connection, id: &str, created_at: i64) {
+    conn.execute(
+        "INSERT INTO blob (id, size_bytes, created_at) VALUES (?1, 10, ?2)",
+        params![id, created_at],
+    )
+    .unwrap();
+}
+
+/// One of every kind of corruption; returns the id of the note whose search
+/// index row went stale
+fn seed_corruption(conn: &Connection, space_id: &str) -> String {
+    let note = create_note(conn, space_id, "Kept", "original body").unwrap();
+    let note_id = note.id.to_string();
+    let tag = create_tag(conn, space_id, "kept", None).unwrap();
+    let now = chrono::Utc::now().timestamp();
+
+    conn.execute(
+        "INSERT INTO note_tags (note_id, tag_id) VALUES (?1, ?2)",
+        params![MISSING, tag.id.to_string()],
+    )
+    .unwrap();
+    conn.execute(
+        "INSERT INTO task_tags (task_id, tag_id) VALUES (?1, ?2)",
+        params![MISSING, tag.id.to_string()],
+    )
+    .unwrap();
+    conn.execute(
+        "INSERT INTO link (source_note_id, target_note_id) VALUES (?1, ?2)",
+        params![MISSING, note_id],
+    )
+    .unwrap();
+    conn.execute(
+        "INSERT INTO note_meta (note_id, key, value) VALUES (?1, 'mood', 'ok')",
+        [MISSING],
+    )
+    .unwrap();
+    conn.execute(
+        "INSERT INTO note_crdt_update (note_id, update_data, created_at) VALUES (?1, x'00', 0)",
+        [MISSING],
+    )
+    .unwrap();
+
+    conn.execute(
+        "INSERT INTO caldav_account (id, url, username, encrypted_password, calendar_path, created_at)
+         VALUES ('acct', 'https://dav.example', 'me', 'x', '/cal', 0)",
+        [],
+    )
+    .unwrap();
+    conn.execute(
+        "INSERT INTO caldav_event_mapping (id, account_id, caldav_uid, local_task_id, last_synced)
+         VALUES ('gone-task', 'acct', 'uid-1', ?1, 0)",
+        [MISSING],
+    )
+    .unwrap();
+    // An event that was never mapped to anything local is fine
+    conn.execute(
+        "INSERT INTO caldav_event_mapping (id, account_id, caldav_uid, last_synced)
+         VALUES ('unmapped', 'acct', 'uid-2', 0)",
+        [],
+    )
+    .unwrap();
+
+    // Referenced by a note that no longer exists
+    insert_blob(conn, "blob-stale-ref", now);
+    conn.execute(
+        "INSERT INTO blob_ref (blob_id, owner_type, owner_id, created_at) VALUES ('blob-stale-ref', 'note', ?1, 0)",
+        [MISSING],
+    )
+    .unwrap();
+    // Attached, but its reference was lost
+    insert_blob(conn, "blob-no-ref", now);
+    core_rs::blob::attach_blob_to_note(conn, &note_id, "blob-no-ref", None).unwrap();
+    conn.execute("DELETE FROM blob_ref WHERE blob_id = 'blob-no-ref'", [])
+        .unwrap();
+    // Unreferenced: an old one is reported, a fresh one may still be attached
+    insert_blob(conn, "blob-old", 0);
+    insert_blob(conn, "blob-fresh", now);
+    conn.execute(
+        "INSERT INTO note_attachment (note_id, blob_id, created_at) VALUES (?1, 'blob-fresh', 0)",
+        [MISSING],
+    )
+    .unwrap();
+    conn.execute("DELETE FROM blob_ref WHERE blob_id = 'blob-fresh'", [])
+        .unwrap();
+
+    // Note content changed behind the search index's back, as sync does
+    conn.execute(
+        "UPDATE note SET content_md = 'synced body' WHERE id = ?1",
+        [&note_id],
+    )
+    .unwrap();
+    conn.execute(
+        "INSERT INTO fts_note (rowid, note_id, title, content_md) VALUES (9999, ?1, 'gone', 'gone')",
+        [MISSING],
+    )
+    .unwrap();
+
+    for (id, conflict_type) in [
+        ("conflict-gone", "UpdateUpdate"),
+        ("conflict-delete", "DeleteUpdate"),
+    ] {
+        conn.execute(
+            "INSERT INTO sync_conflict (id, entity_type, entity_id, local_version, remote_version,
+                                        conflict_type, detected_at, resolved, device_id, space_id)
+             VALUES (?1, 'note', ?2, x'00', x'00', ?3, 0, 0, 'peer', ?4)",
+            params![id, MISSING, conflict_type, space_id],
+        )
+        .unwrap();
+    }
+    note_id
+}
+
+fn ids_of(
+    report: &core_rs::db::VaultIntegrityReport,
+    kind: IntegrityIssueKind,
+) -> Vec<Vec<String>> {
+    let mut ids: Vec<Vec<String>> = report
+        .issues
+        .iter()
+        .filter(|i| i.kind == kind)
+        .map(|i| i.entity_ids.clone())
+        .collect();
+    ids.sort();
+    ids
+}
+
+fn strings(ids: &[&str]) -> Vec<String> {
+    ids.iter().map(|s| s.to_string()).collect()
+}
+
+#[test]
+fn test_healthy_vault_has_no_issues() {
+    let (conn, space_id) = setup_db();
+    let note = create_note(&conn, &space_id, "Ünïcode Title", "body").unwrap();
+    insert_blob(&conn, "blob", 0);
+    core_rs::blob::attach_blob_to_note(&conn, &note.id.to_string(), "blob", None).unwrap();
+
+    let report = check_vault(&conn).unwrap();
+    assert!(report.is_healthy(), "{:?}", report.issues);
+    assert_eq!(report.mode, IntegrityCheckMode::Full);
+}
+
+#[test]
+fn test_check_detects_each_corruption() {
+    let (conn, space_id) = setup_db();
+    let note_id = seed_corruption(&conn, &space_id);
+    let tag_id: String = conn
+        .query_row("SELECT id FROM tag", [], |row| row.get(0))
+        .unwrap();
+
+    let report = check_vault(&conn).unwrap();
+    use IntegrityIssueKind::*;
+    assert!(ids_of(&report, DatabaseCorruption).is_empty());
+    assert_eq!(
+        ids_of(&report, OrphanNoteTag),
+        vec![strings(&[MISSING, &tag_id])]
+    );
+    assert_eq!(
+        ids_of(&report, OrphanTaskTag),
+        vec![strings(&[MISSING, &tag_id])]
+    );
+    assert_eq!(
+        ids_of(&report, OrphanLink),
+        vec![strings(&[MISSING, &note_id])]
+    );
+    assert_eq!(
+        ids_of(&report, OrphanNoteMeta),
+        vec![strings(&[MISSING, "mood"])]
+    );
+    assert_eq!(ids_of(&report, OrphanCrdtLog), vec![strings(&[MISSING])]);
+    assert_eq!(
+        ids_of(&report, OrphanAttachment),
+        vec![strings(&[MISSING, "blob-fresh"])]
+    );
+    assert_eq!(
+        ids_of(&report, OrphanCaldavMapping),
+        vec![strings(&["gone-task"])]
+    );
+    assert_eq!(
+        ids_of(&report, OrphanBlobRef),
+        vec![strings(&["blob-stale-ref", "note", MISSING])]
+    );
+    assert_eq!(
+        ids_of(&report, MissingBlobRef),
+        vec![strings(&["blob-no-ref", &note_id])]
+    );
+    assert_eq!(
+        ids_of(&report, UnreferencedBlob),
+        vec![strings(&["blob-old"])]
+    );
+    assert_eq!(
+        ids_of(&report, StaleSearchIndex),
+        vec![strings(&[&note_id])]
+    );
+    assert_eq!(ids_of(&report, OrphanSearchIndex), vec![strings(&["9999"])]);
+    // Delete/update conflicts are expected to lack the entity locally
+    assert_eq!(
+        ids_of(&report, OrphanSyncConflict),
+        vec![strings(&["conflict-gone"])]
+    );
+
+    assert_eq!(report.issues[0].severity, IssueSeverity::Error);
+    assert!(report.issues.iter().all(|i| i.auto_fixable));
+
+    // The quick check only notices search index rows that are missing
+    let quick = check_vault_with_mode(&conn, IntegrityCheckMode::Quick).unwrap();
+    assert!(ids_of(&quick, StaleSearchIndex).is_empty());
+    assert_eq!(quick.issues.len(), report.issues.len() - 1);
+}
+
+#[test]
+fn test_repair_fixes_selected_issues() {
+    let (mut conn, space_id) = setup_db();
+    let note_id = seed_corruption(&conn, &space_id);
+    let report = check_vault(&conn).unwrap();
+
+    // Only the search index first
+    let (index, rest): (Vec<_>, Vec<_>) = report
+        .issues
+        .iter()
+        .cloned()
+        .partition(|i| i.kind == IntegrityIssueKind::StaleSearchIndex);
+    let repaired = repair_vault(&mut conn, &index).unwrap();
+    assert_eq!((repaired.repaired, repaired.skipped), (1, 0));
+    let found: String = conn
+        .query_row(
+            "SELECT note_id FROM fts_note WHERE fts_note MATCH 'synced'",
+            [],
+            |row| row.get(0),
+        )
+        .unwrap();
+    assert_eq!(found, note_id);
+    assert_eq!(check_vault(&conn).unwrap().issues.len(), rest.len());
+
+    let repaired = repair_vault(&mut conn, &rest).unwrap();
+    assert_eq!((repaired.repaired, repaired.skipped), (rest.len(), 0));
+    let after = check_vault(&conn).unwrap();
+    assert!(after.is_healthy(), "{:?}", after.issues);
+
+    // Repairs that were lost track of: the attachment kept its blob, the
+    // deleted blob is queued for the object sweep, and valid rows survived
+    let refs: i64 = conn
+        .query_row(
+            "SELECT COUNT(*) FROM blob_ref WHERE blob_id = 'blob-no-ref' AND owner_id = ?1",
+            [&note_id],
+            |row| row.get(0),
+        )
+        .unwrap();
+    assert_eq!(refs, 1);
+    let queued: i64 = conn
+        .query_row(
+            "SELECT COUNT(*) FROM blob_pending_sweep WHERE blob_id = 'blob-old'",
+            [],
+            |row| row.get(0),
+        )
+        .unwrap();
+    assert_eq!(queued, 1);
+    let kept: i64 = conn
+        .query_row(
+            "SELECT COUNT(*) FROM sync_conflict WHERE id = 'conflict-delete'",
+            [],
+            |row| row.get(0),
+        )
+        .unwrap();
+    assert_eq!(kept, 1);
+
+    // A stale report is harmless: nothing is left to fix
+    let again = repair_vault(&mut conn, &report.issues).unwrap();
+    assert_eq!((again.repaired, again.skipped), (0, report.issues.len()));
+}
+
+#[test]
+fn test_startup_maintenance_runs_quick_check_when_enabled() {
+    let (mut conn, space_id) = setup_db();
+    seed_corruption(&conn, &space_id);
+
+    let result = core_rs::social::run_startup_maintenance(&mut conn, 7).unwrap();
+    assert!(result.integrity.is_none());
+
+    set_startup_integrity_check(&conn, true).unwrap();
+    let result = core_rs::social::run_startup_maintenance(&mut conn, 7).unwrap();
+    let report = result.integrity.unwrap();
+    assert_eq!(report.mode, IntegrityCheckMode::Quick);
+    // Maintenance reports problems but leaves repairs to the user
+    assert!(!report.is_healthy());
+    assert!(report
+        .issues
+        .iter()
+        .any(|i| i.kind == IntegrityIssueKind::OrphanNoteTag));
+}
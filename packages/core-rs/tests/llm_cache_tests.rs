@@ -0,0 +1,284 @@
+use async_trait::async_trait;
+use core_rs::db::{migrate, DbPool};
+use core_rs::llm::cache::{CacheConfig, CacheContext, ResponseCache};
+use core_rs::llm::providers::ProviderType;
+use core_rs::llm::retry::RetryConfig;
+use core_rs::llm::types::LLMResponse;
+use core_rs::llm::{
+    LLMProvider, LLMRequest, LlmError, LlmRouter, RequestConstraints, RoutingPolicy,
+};
+use core_rs::note::{create_note, update_note_content};
+use rusqlite::Connection;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tempfile::TempDir;
+
+fn setup() -> (TempDir, DbPool) {
+    let dir = tempfile::tempdir().unwrap();
+    let manager = r2d2_sqlite::SqliteConnectionManager::file(dir.path().join("llm.db"));
+    let pool = r2d2::Pool::builder().max_size(2).build(manager).unwrap();
+    migrate(&mut pool.get().unwrap()).unwrap();
+    (dir, pool)
+}
+
+fn answer(content: &str) -> LLMResponse {
+    LLMResponse::new(content, "gpt-4o", 20)
+}
+
+fn cached_content(
+    cache: &ResponseCache,
+    conn: &Connection,
+    request: &LLMRequest,
+) -> Option<String> {
+    cache
+        .get(conn, "openai", "gpt-4o", request)
+        .unwrap()
+        .map(|r| r.content)
+}
+
+#[test]
+fn test_cache_survives_restart() {
+    let (dir, pool) = setup();
+    let request = LLMRequest::with_system("Summarize.", "Meeting notes\r\nwith Sam  ");
+    {
+        let conn = pool.get().unwrap();
+        let cache = ResponseCache::default();
+        assert_eq!(cached_content(&cache, &conn, &request), None);
+        cache
+            .put(
+                &conn,
+                "openai",
+                "gpt-4o",
+                &request,
+                &answer("Sam met."),
+                &CacheContext::default(),
+            )
+            .unwrap();
+    }
+    drop(pool);
+
+    // A fresh connection and cache, as after restarting the app
+    let conn = Connection::open(dir.path().join("llm.db")).unwrap();
+    let cache = ResponseCache::default();
+    let response = cache
+        .get(&conn, "openai", "gpt-4o", &request)
+        .unwrap()
+        .unwrap();
+    assert_eq!(response.content, "Sam met.");
+    assert!(response.cached);
+    assert_eq!(response.provider.as_deref(), Some("openai"));
+
+    // Line endings and trailing whitespace don't matter, everything else does
+    let normalized = LLMRequest::with_system("Summarize.", "Meeting notes\nwith Sam");
+    assert_eq!(
+        cached_content(&cache, &conn, &normalized).as_deref(),
+        Some("Sam met.")
+    );
+    let hotter = request.clone().temperature(0.9);
+    assert_eq!(cached_content(&cache, &conn, &hotter), None);
+    assert!(cache
+        .get(&conn, "claude", "gpt-4o", &request)
+        .unwrap()
+        .is_none());
+    assert!(cache
+        .get(&conn, "openai", "gpt-4o-mini", &request)
+        .unwrap()
+        .is_none());
+
+    let stats = cache.stats(&conn).unwrap();
+    assert_eq!(stats.total_entries, 1);
+    assert_eq!(stats.total_hits, 2);
+    assert!(stats.saved_usd > 0.0);
+}
+
+#[test]
+fn test_entries_expire_after_their_ttl() {
+    let (_dir, pool) = setup();
+    let conn = pool.get().unwrap();
+    let cache = ResponseCache::new(CacheConfig::default().ttl("autocomplete", Duration::ZERO));
+    let suggestion = LLMRequest::simple("Complete: The quick brown");
+    let summary = LLMRequest::simple("Summarize: The quick brown fox");
+    cache
+        .put(
+            &conn,
+            "openai",
+            "gpt-4o",
+            &suggestion,
+            &answer("fox"),
+            &CacheContext::new("autocomplete"),
+        )
+        .unwrap();
+    cache
+        .put(
+            &conn,
+            "openai",
+            "gpt-4o",
+            &summary,
+            &answer("A fox."),
+            &CacheContext::new("summarize"),
+        )
+        .unwrap();
+
+    assert_eq!(cached_content(&cache, &conn, &suggestion), None);
+    assert_eq!(
+        cached_content(&cache, &conn, &summary).as_deref(),
+        Some("A fox.")
+    );
+
+    conn.execute("UPDATE llm_cache SET expires_at = 0", [])
+        .unwrap();
+    assert_eq!(cached_content(&cache, &conn, &summary), None);
+    let remaining: i64 = conn
+        .query_row("SELECT COUNT(*) FROM llm_cache", [], |row| row.get(0))
+        .unwrap();
+    assert_eq!(remaining, 0);
+}
+
+#[test]
+fn test_least_recently_used_entries_are_evicted() {
+    let (_dir, pool) = setup();
+    let conn = pool.get().unwrap();
+    let request = |i: usize| LLMRequest::simple(format!("Question {}", i));
+    let put = |cache: &ResponseCache, i: usize| {
+        cache
+            .put(
+                &conn,
+                "openai",
+                "gpt-4o",
+                &request(i),
+                &answer(&format!("Answer {}", i)),
+                &CacheContext::default(),
+            )
+            .unwrap();
+    };
+
+    // Room for three entries of the same size
+    put(&ResponseCache::default(), 0);
+    let entry_size = ResponseCache::default().stats(&conn).unwrap().size_bytes as u64;
+    let cache = ResponseCache::new(CacheConfig::default().max_bytes(3 * entry_size));
+    put(&cache, 1);
+    put(&cache, 2);
+    assert!(cached_content(&cache, &conn, &request(0)).is_some());
+
+    put(&cache, 3);
+    assert_eq!(cached_content(&cache, &conn, &request(1)), None);
+    put(&cache, 4);
+    assert_eq!(cached_content(&cache, &conn, &request(2)), None);
+
+    for i in [0, 3, 4] {
+        assert!(cached_content(&cache, &conn, &request(i)).is_some());
+    }
+    assert!(cache.stats(&conn).unwrap().size_bytes as u64 <= 3 * entry_size);
+}
+
+#[test]
+fn test_editing_a_note_invalidates_its_entries() {
+    let (_dir, pool) = setup();
+    let mut conn = pool.get().unwrap();
+    let space_id = core_rs::space::create_space(&mut conn, "Cache")
+        .unwrap()
+        .to_string();
+    let recipe = create_note(&conn, &space_id, "Recipe", "Flour, water").unwrap();
+    let trip = create_note(&conn, &space_id, "Trip", "Lisbon").unwrap();
+
+    let cache = ResponseCache::default();
+    let about_recipe = LLMRequest::simple("Summarize: Flour, water");
+    let about_both = LLMRequest::simple("What should I pack?");
+    let about_trip = LLMRequest::simple("Summarize: Lisbon");
+    let recipe_id = recipe.id.to_string();
+    let trip_id = trip.id.to_string();
+    for (request, context) in [
+        (
+            &about_recipe,
+            CacheContext::new("summarize").note(&recipe_id),
+        ),
+        (
+            &about_both,
+            CacheContext::new("rag_answer")
+                .note(&recipe_id)
+                .note(&trip_id),
+        ),
+        (&about_trip, CacheContext::new("summarize").note(&trip_id)),
+    ] {
+        cache
+            .put(&conn, "openai", "gpt-4o", request, &answer("..."), &context)
+            .unwrap();
+    }
+
+    update_note_content(&mut conn, recipe.id, "Recipe", "Flour, water, salt").unwrap();
+
+    assert_eq!(cached_content(&cache, &conn, &about_recipe), None);
+    assert_eq!(cached_content(&cache, &conn, &about_both), None);
+    assert!(cached_content(&cache, &conn, &about_trip).is_some());
+    let namespaces: i64 = conn
+        .query_row("SELECT COUNT(*) FROM llm_cache_namespace", [], |row| {
+            row.get(0)
+        })
+        .unwrap();
+    assert_eq!(namespaces, 1);
+}
+
+struct CountingProvider {
+    calls: AtomicUsize,
+}
+
+#[async_trait]
+impl LLMProvider for CountingProvider {
+    async fn complete(&self, request: &LLMRequest) -> Result<LLMResponse, LlmError> {
+        self.calls.fetch_add(1, Ordering::SeqCst);
+        let model = request.model.clone().unwrap_or_default();
+        Ok(LLMResponse::new("An answer", model, 400))
+    }
+
+    async fn list_models(&self) -> Result<Vec<String>, LlmError> {
+        Ok(vec![])
+    }
+
+    fn name(&self) -> &str {
+        "openai"
+    }
+}
+
+#[tokio::test]
+async fn test_router_counts_cache_hits_as_free() {
+    let (_dir, pool) = setup();
+    let provider = Arc::new(CountingProvider {
+        calls: AtomicUsize::new(0),
+    });
+    let policy = RoutingPolicy {
+        retry: RetryConfig::no_retry(),
+        ..Default::default()
+    };
+    let router = LlmRouter::new(policy).add_provider_with_model(
+        provider.clone(),
+        ProviderType::OpenAI,
+        "gpt-4",
+    );
+    let cache = ResponseCache::default();
+    let request = LLMRequest::simple("Explain the plan in a paragraph");
+    let constraints = RequestConstraints::default();
+    let context = CacheContext::new("summarize");
+
+    let first = router
+        .complete_cached(&request, &constraints, &cache, &pool, &context)
+        .await
+        .unwrap();
+    assert!(!first.cached);
+    let paid = router.cost_stats().await.total_cost_usd;
+    assert!(paid > 0.0);
+
+    let second = router
+        .complete_cached(&request, &constraints, &cache, &pool, &context)
+        .await
+        .unwrap();
+    assert!(second.cached);
+    assert_eq!(second.content, "An answer");
+    assert_eq!(provider.calls.load(Ordering::SeqCst), 1);
+
+    let stats = router.cost_stats().await;
+    assert_eq!(stats.total_requests, 2);
+    assert_eq!(stats.cache_hits, 1);
+    assert_eq!(stats.total_cost_usd, paid);
+    assert_eq!(stats.saved_usd, paid);
+}
@@ -0,0 +1,340 @@
+use async_trait::async_trait;
+use core_rs::ai::rag::{RagConfig, RagPipeline, RagQuery};
+use core_rs::llm::providers::{OllamaProvider, OpenAIProvider};
+use core_rs::llm::streaming::StreamEvent;
+use core_rs::llm::types::LLMResponse;
+use core_rs::llm::{LLMProvider, LLMRequest, LlmError};
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpListener;
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+
+/// Serve one HTTP request with a chunked response body, one chunk per piece.
+///
+/// With `hold_open`, the server stops after the pieces without terminating the
+/// body and waits for the client to hang up; the handle resolves to true if it did.
+async fn serve_chunked(
+    status: &'static str,
+    pieces: Vec<&'static str>,
+    hold_open: bool,
+) -> (String, JoinHandle<bool>) {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let base_url = format!("http://{}", listener.local_addr().unwrap());
+
+    let handle = tokio::spawn(async move {
+        let (mut socket, _) = listener.accept().await.unwrap();
+        read_request(&mut socket).await;
+
+        let head = format!(
+            "HTTP/1.1 {}\r\nContent-Type: text/event-stream\r\nTransfer-Encoding: chunked\r\n\r\n",
+            status
+        );
+        socket.write_all(head.as_bytes()).await.unwrap();
+
+        for piece in pieces {
+            let chunk = format!("{:x}\r\n{}\r\n", piece.len(), piece);
+            socket.write_all(chunk.as_bytes()).await.unwrap();
+            socket.flush().await.unwrap();
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+
+        if hold_open {
+            let mut buf = [0u8; 64];
+            let closed = tokio::time::timeout(Duration::from_secs(5), socket.read(&mut buf)).await;
+            return matches!(closed, Ok(Ok(0)) | Ok(Err(_)));
+        }
+
+        socket.write_all(b"0\r\n\r\n").await.unwrap();
+        false
+    });
+
+    (base_url, handle)
+}
+
+/// Consume the request head and its Content-Length body
+async fn read_request(socket: &mut tokio::net::TcpStream) {
+    let mut data = Vec::new();
+    let mut buf = [0u8; 4096];
+    loop {
+        let n = socket.read(&mut buf).await.unwrap();
+        data.extend_from_slice(&buf[..n]);
+        if let Some(end) = data.windows(4).position(|w| w == b"\r\n\r\n") {
+            let head = String::from_utf8_lossy(&data[..end]).to_lowercase();
+            let length = head
+                .lines()
+                .find_map(|l| l.strip_prefix("content-length:"))
+                .and_then(|v| v.trim().parse::<usize>().ok())
+                .unwrap_or(0);
+            while data.len() < end + 4 + length {
+                let n = socket.read(&mut buf).await.unwrap();
+                data.extend_from_slice(&buf[..n]);
+            }
+            return;
+        }
+    }
+}
+
+async fn drain(mut receiver: mpsc::Receiver<StreamEvent>) -> Vec<StreamEvent> {
+    let mut events = Vec::new();
+    while let Some(event) = receiver.recv().await {
+        events.push(event);
+    }
+    events
+}
+
+fn tokens(events: &[StreamEvent]) -> Vec<String> {
+    events
+        .iter()
+        .filter_map(|e| match e {
+            StreamEvent::Token { content } => Some(content.clone()),
+            _ => None,
+        })
+        .collect()
+}
+
+const OLLAMA_STREAM: [&str; 4] = [
+    "{\"message\":{\"content\":\"The \"},\"done\":false}\n{\"message\":{\"content\":\"qu",
+    "ick \"},\"done\":false}\n",
+    "{\"message\":{\"content\":\"fox\"},\"done\":false}\n",
+    "{\"message\":{\"content\":\"\"},\"done\":true,\"done_reason\":\"stop\",\"eval_count\":3,\"prompt_eval_count\":7}\n",
+];
+
+#[tokio::test]
+async fn test_ollama_streams_tokens_in_order() {
+    let (base_url, server) = serve_chunked("200 OK", OLLAMA_STREAM.to_vec(), false).await;
+    let provider = OllamaProvider::new(base_url).unwrap();
+    let (sink, receiver) = mpsc::channel(16);
+
+    let request = LLMRequest::simple("Say something").model("llama3.2");
+    let response = provider.complete_streaming(&request, &sink).await.unwrap();
+    drop(sink);
+    server.await.unwrap();
+
+    let events = drain(receiver).await;
+    assert_eq!(tokens(&events), vec!["The ", "quick ", "fox"]);
+    assert_eq!(
+        &events[3..],
+        &[
+            StreamEvent::Usage {
+                prompt_tokens: Some(7),
+                completion_tokens: 3
+            },
+            StreamEvent::Done {
+                finish_reason: Some("stop".to_string())
+            },
+        ]
+    );
+    assert_eq!(response.content, "The quick fox");
+    assert_eq!(response.tokens_used, 3);
+    assert_eq!(response.provider.as_deref(), Some("ollama"));
+}
+
+#[tokio::test]
+async fn test_openai_streams_sse_events() {
+    let pieces = vec![
+        ": keep-alive\n\n",
+        "data: {\"choices\":[{\"delta\":{\"role\":\"assistant\"},\"finish_reason\":null}]}\n\n",
+        "data: {\"choices\":[{\"delta\":{\"content\":\"Hello\"},\"finish_reason\":null}]}\n\ndata: {\"choices\":[{\"delta\":{\"content\":\", world\"},\"finish_reason\":null}]}\n\n",
+        "data: {\"choices\":[{\"delta\":{},\"finish_reason\":\"stop\"}]}\n\n",
+        "data: {\"choices\":[],\"usage\":{\"prompt_tokens\":5,\"completion_tokens\":2,\"total_tokens\":7}}\n\n",
+        "data: [DONE]\n\n",
+    ];
+    let (base_url, server) = serve_chunked("200 OK", pieces, false).await;
+    let provider = OpenAIProvider::new("test-key".to_string())
+        .unwrap()
+        .with_base_url(base_url);
+    let (sink, receiver) = mpsc::channel(16);
+
+    let response = provider
+        .complete_streaming(&LLMRequest::simple("Hi"), &sink)
+        .await
+        .unwrap();
+    drop(sink);
+    server.await.unwrap();
+
+    let events = drain(receiver).await;
+    assert_eq!(
+        events,
+        vec![
+            StreamEvent::Token {
+                content: "Hello".to_string()
+            },
+            StreamEvent::Token {
+                content: ", world".to_string()
+            },
+            StreamEvent::Usage {
+                prompt_tokens: Some(5),
+                completion_tokens: 2
+            },
+            StreamEvent::Done {
+                finish_reason: Some("stop".to_string())
+            },
+        ]
+    );
+    assert_eq!(response.content, "Hello, world");
+    assert_eq!(response.tokens_used, 7);
+}
+
+#[tokio::test]
+async fn test_cancellation_aborts_http_request() {
+    let (base_url, server) = serve_chunked("200 OK", OLLAMA_STREAM[..1].to_vec(), true).await;
+    let provider = OllamaProvider::new(base_url).unwrap();
+    let (sink, mut receiver) = mpsc::channel(16);
+
+    let request = LLMRequest::simple("Say something").model("llama3.2");
+    let consumer = tokio::spawn(async move {
+        let first = receiver.recv().await;
+        // Hanging up the receiver cancels the stream
+        drop(receiver);
+        first
+    });
+
+    let result = tokio::time::timeout(
+        Duration::from_secs(5),
+        provider.complete_streaming(&request, &sink),
+    )
+    .await
+    .expect("cancellation should not hang");
+    assert!(matches!(result, Err(LlmError::Cancelled)));
+    assert_eq!(
+        consumer.await.unwrap(),
+        Some(StreamEvent::Token {
+            content: "The ".to_string()
+        })
+    );
+
+    drop(provider);
+    assert!(
+        server.await.unwrap(),
+        "server should see the connection close"
+    );
+}
+
+#[tokio::test]
+async fn test_http_error_emits_error_event() {
+    let (base_url, server) = serve_chunked("500 Internal Server Error", vec!["boom"], false).await;
+    let provider = OllamaProvider::new(base_url).unwrap();
+    let (sink, receiver) = mpsc::channel(16);
+
+    let request = LLMRequest::simple("Hi").model("llama3.2");
+    let result = provider.complete_streaming(&request, &sink).await;
+    drop(sink);
+    server.await.unwrap();
+
+    assert!(matches!(result, Err(LlmError::ProviderError(_))));
+    let events = drain(receiver).await;
+    assert_eq!(events.len(), 1);
+    assert!(matches!(&events[0], StreamEvent::Error { message } if message.contains("500")));
+}
+
+struct BufferedProvider;
+
+#[async_trait]
+impl LLMProvider for BufferedProvider {
+    async fn complete(&self, _request: &LLMRequest) -> Result<LLMResponse, LlmError> {
+        let mut response = LLMResponse::new("All at once", "buffered", 4);
+        response.finish_reason = Some("stop".to_string());
+        Ok(response)
+    }
+
+    async fn list_models(&self) -> Result<Vec<String>, LlmError> {
+        Ok(vec![])
+    }
+
+    fn name(&self) -> &str {
+        "buffered"
+    }
+}
+
+#[tokio::test]
+async fn test_default_streaming_buffers_complete() {
+    let (sink, receiver) = mpsc::channel(16);
+    let response = BufferedProvider
+        .complete_streaming(&LLMRequest::simple("Hi"), &sink)
+        .await
+        .unwrap();
+    drop(sink);
+
+    assert_eq!(response.content, "All at once");
+    assert_eq!(
+        drain(receiver).await,
+        vec![
+            StreamEvent::Token {
+                content: "All at once".to_string()
+            },
+            StreamEvent::Usage {
+                prompt_tokens: None,
+                completion_tokens: 4
+            },
+            StreamEvent::Done {
+                finish_reason: Some("stop".to_string())
+            },
+        ]
+    );
+}
+
+#[tokio::test]
+async fn test_rag_answer_streaming() {
+    const FOX: &str = "The quick brown fox jumps over the lazy dog.";
+    let manager = r2d2_sqlite::SqliteConnectionManager::memory();
+    let pool = r2d2::Pool::builder().max_size(1).build(manager).unwrap();
+    let note_id = {
+        let mut conn = pool.get().unwrap();
+        core_rs::db::migrate(&mut conn).unwrap();
+        let space_id = core_rs::space::create_space(&mut conn, "Animals").unwrap();
+        core_rs::note::create_note(&conn, &space_id.to_string(), "Foxes", FOX)
+            .unwrap()
+            .id
+            .to_string()
+    };
+
+    let (base_url, server) = serve_chunked("200 OK", OLLAMA_STREAM.to_vec(), false).await;
+    let provider = OllamaProvider::new(base_url).unwrap();
+
+    // Ollama needs an explicit model; wrap the provider to pin one
+    struct Pinned(OllamaProvider);
+
+    #[async_trait]
+    impl LLMProvider for Pinned {
+        async fn complete(&self, request: &LLMRequest) -> Result<LLMResponse, LlmError> {
+            self.0.complete(&request.clone().model("llama3.2")).await
+        }
+
+        async fn complete_streaming(
+            &self,
+            request: &LLMRequest,
+            sink: &core_rs::llm::streaming::StreamSink,
+        ) -> Result<LLMResponse, LlmError> {
+            self.0
+                .complete_streaming(&request.clone().model("llama3.2"), sink)
+                .await
+        }
+
+        async fn list_models(&self) -> Result<Vec<String>, LlmError> {
+            Ok(vec![])
+        }
+
+        fn name(&self) -> &str {
+            "pinned"
+        }
+    }
+
+    let pipeline =
+        RagPipeline::new(RagConfig::default(), pool).with_llm_provider(Box::new(Pinned(provider)));
+    pipeline.index_note(&note_id, "Foxes", FOX).await.unwrap();
+
+    let (sink, receiver) = mpsc::channel(16);
+    let query = RagQuery {
+        question: "fox".to_string(),
+        min_relevance_score: 0.0,
+        ..Default::default()
+    };
+    let response = pipeline.answer_streaming(query, &sink).await.unwrap();
+    drop(sink);
+    server.await.unwrap();
+
+    assert_eq!(response.answer, "The quick fox");
+    assert_eq!(response.sources.len(), 1);
+    assert_eq!(tokens(&drain(receiver).await).concat(), "The quick fox");
+}
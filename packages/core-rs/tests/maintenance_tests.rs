@@ -0,0 +1,257 @@
+use core_rs::db::{migrate, DbPool};
+use core_rs::maintenance::*;
+use rusqlite::Connection;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tempfile::TempDir;
+
+/// A migrated file-backed pool with a scratch table for tasks to write to
+fn setup() -> (DbPool, TempDir) {
+    let dir = tempfile::tempdir().unwrap();
+    let manager = r2d2_sqlite::SqliteConnectionManager::file(dir.path().join("maintenance.db"));
+    let pool = r2d2::Pool::builder().max_size(2).build(manager).unwrap();
+    {
+        let mut conn = pool.get().unwrap();
+        migrate(&mut conn).unwrap();
+        conn.execute("CREATE TABLE scratch (value TEXT)", [])
+            .unwrap();
+    }
+    (pool, dir)
+}
+
+enum Behavior {
+    Succeed(u64),
+    Fail,
+    Panic,
+    /// Writes a row inside a transaction it never finishes
+    LeaveTransactionOpen,
+}
+
+struct FakeTask {
+    name: &'static str,
+    cost: Duration,
+    sleep: Duration,
+    behavior: Behavior,
+    ran: Arc<Mutex<Vec<&'static str>>>,
+}
+
+impl MaintenanceTask for FakeTask {
+    fn name(&self) -> &str {
+        self.name
+    }
+
+    fn estimated_cost(&self) -> Duration {
+        self.cost
+    }
+
+    fn run(&self, conn: &Connection) -> Result<TaskReport, MaintenanceError> {
+        self.ran.lock().unwrap().push(self.name);
+        std::thread::sleep(self.sleep);
+        match self.behavior {
+            Behavior::Succeed(items_affected) => {
+                conn.execute("INSERT INTO scratch (value) VALUES (?1)", [self.name])?;
+                Ok(TaskReport { items_affected })
+            }
+            Behavior::Fail => Err(MaintenanceError::Task("disk on fire".to_string())),
+            Behavior::Panic => panic!("boom"),
+            Behavior::LeaveTransactionOpen => {
+                conn.execute_batch("BEGIN")?;
+                conn.execute("INSERT INTO scratch (value) VALUES (?1)", [self.name])?;
+                Ok(TaskReport { items_affected: 1 })
+            }
+        }
+    }
+}
+
+struct Fakes {
+    registry: MaintenanceRegistry,
+    ran: Arc<Mutex<Vec<&'static str>>>,
+}
+
+impl Fakes {
+    fn new() -> Self {
+        Self {
+            registry: MaintenanceRegistry::new(),
+            ran: Arc::default(),
+        }
+    }
+
+    fn add(&mut self, name: &'static str, priority: u32, behavior: Behavior) -> &mut Self {
+        self.add_timed(name, priority, Duration::ZERO, Duration::ZERO, behavior)
+    }
+
+    fn add_timed(
+        &mut self,
+        name: &'static str,
+        priority: u32,
+        cost: Duration,
+        sleep: Duration,
+        behavior: Behavior,
+    ) -> &mut Self {
+        self.registry.register(
+            priority,
+            FakeTask {
+                name,
+                cost,
+                sleep,
+                behavior,
+                ran: self.ran.clone(),
+            },
+        );
+        self
+    }
+
+    fn ran(&self) -> Vec<&'static str> {
+        self.ran.lock().unwrap().clone()
+    }
+}
+
+fn statuses(run: &MaintenanceRun) -> Vec<(&str, TaskStatus)> {
+    run.tasks
+        .iter()
+        .map(|t| (t.task.as_str(), t.status))
+        .collect()
+}
+
+fn scratch_values(pool: &DbPool) -> Vec<String> {
+    let conn = pool.get().unwrap();
+    let mut stmt = conn
+        .prepare("SELECT value FROM scratch ORDER BY rowid")
+        .unwrap();
+    let values = stmt
+        .query_map([], |row| row.get(0))
+        .unwrap()
+        .collect::<Result<Vec<_>, _>>()
+        .unwrap();
+    values
+}
+
+#[test]
+fn test_tasks_run_in_priority_order() {
+    let (pool, _dir) = setup();
+    let mut fakes = Fakes::new();
+    fakes
+        .add("vacuum", PRIORITY_LOW, Behavior::Succeed(0))
+        .add("journal", PRIORITY_HIGH, Behavior::Succeed(0))
+        .add("logs", PRIORITY_NORMAL, Behavior::Succeed(0))
+        .add("cache", PRIORITY_HIGH, Behavior::Succeed(0));
+
+    let expected = ["journal", "cache", "logs", "vacuum"];
+    assert_eq!(fakes.registry.task_names(), expected);
+    let run = fakes.registry.run(&pool, Duration::from_secs(5)).unwrap();
+    assert_eq!(fakes.ran(), expected);
+    assert!(run.tasks.iter().all(|t| t.status == TaskStatus::Completed));
+}
+
+#[test]
+fn test_tasks_that_do_not_fit_the_budget_are_skipped() {
+    let (pool, _dir) = setup();
+    let mut fakes = Fakes::new();
+    let ms = Duration::from_millis;
+    fakes
+        .add_timed("huge", 40, ms(10_000), ms(0), Behavior::Succeed(0))
+        .add_timed("slow", 30, ms(10), ms(200), Behavior::Succeed(0))
+        .add_timed("medium", 20, ms(250), ms(0), Behavior::Succeed(0))
+        .add_timed("quick", 10, ms(10), ms(0), Behavior::Succeed(0));
+
+    let run = fakes.registry.run(&pool, ms(400)).unwrap();
+    assert_eq!(
+        statuses(&run),
+        [
+            ("huge", TaskStatus::Skipped),
+            ("slow", TaskStatus::Completed),
+            ("medium", TaskStatus::Skipped),
+            ("quick", TaskStatus::Completed),
+        ]
+    );
+    assert_eq!(fakes.ran(), ["slow", "quick"]);
+    assert!(run.tasks[1].duration_ms >= 200);
+
+    // Only tasks that ran are in the history
+    let conn = pool.get().unwrap();
+    let history = get_maintenance_history(&conn, 10).unwrap();
+    let tasks: Vec<&str> = history.iter().map(|e| e.task.as_str()).collect();
+    assert_eq!(tasks, ["quick", "slow"]);
+    drop(conn);
+
+    // Nothing starts without a budget
+    let run = fakes.registry.run(&pool, Duration::ZERO).unwrap();
+    assert!(run.tasks.iter().all(|t| t.status == TaskStatus::Skipped));
+    assert_eq!(fakes.ran().len(), 2);
+}
+
+#[test]
+fn test_failing_tasks_do_not_stop_the_run() {
+    let (pool, _dir) = setup();
+    let mut fakes = Fakes::new();
+    fakes
+        .add("fails", 50, Behavior::Fail)
+        .add("panics", 40, Behavior::Panic)
+        .add("dangles", 30, Behavior::LeaveTransactionOpen)
+        .add("works", 20, Behavior::Succeed(3));
+
+    let run = fakes.registry.run(&pool, Duration::from_secs(5)).unwrap();
+    assert_eq!(
+        statuses(&run),
+        [
+            ("fails", TaskStatus::Failed),
+            ("panics", TaskStatus::Failed),
+            ("dangles", TaskStatus::Failed),
+            ("works", TaskStatus::Completed),
+        ]
+    );
+    assert_eq!(run.tasks[0].error.as_deref(), Some("disk on fire"));
+    assert_eq!(run.tasks[1].error.as_deref(), Some("panicked: boom"));
+    assert_eq!(
+        run.tasks[2].error.as_deref(),
+        Some("left a transaction open")
+    );
+    assert_eq!(run.tasks[3].items_affected, 3);
+
+    // The open transaction was rolled back rather than committed with the history
+    assert_eq!(scratch_values(&pool), ["works"]);
+}
+
+#[test]
+fn test_history_records_each_task_run() {
+    let (pool, _dir) = setup();
+    let mut fakes = Fakes::new();
+    fakes
+        .add("prune", 20, Behavior::Succeed(7))
+        .add("broken", 10, Behavior::Fail);
+
+    let first = fakes.registry.run(&pool, Duration::from_secs(5)).unwrap();
+    let second = fakes.registry.run(&pool, Duration::from_secs(5)).unwrap();
+    assert_ne!(first.id, second.id);
+
+    let conn = pool.get().unwrap();
+    let history = get_maintenance_history(&conn, 10).unwrap();
+    assert_eq!(history.len(), 4);
+    // Newest first
+    assert_eq!(history[0].run_id, second.id);
+    assert_eq!(history[0].task, "broken");
+    assert_eq!(history[0].error.as_deref(), Some("disk on fire"));
+    assert_eq!(history[1].run_id, second.id);
+    assert_eq!(history[1].task, "prune");
+    assert_eq!(history[1].items_affected, 7);
+    assert_eq!(history[1].error, None);
+    assert!(history[1].started_at >= first.started_at);
+    assert_eq!(history[3].run_id, first.id);
+
+    assert_eq!(get_maintenance_history(&conn, 1).unwrap().len(), 1);
+}
+
+#[test]
+fn test_builtin_tasks_run_on_a_fresh_vault() {
+    let (pool, _dir) = setup();
+    let run = run_maintenance(&pool, Duration::from_secs(30)).unwrap();
+    assert_eq!(
+        statuses(&run),
+        [
+            ("undo_log_prune", TaskStatus::Completed),
+            ("sync_log_prune", TaskStatus::Completed),
+            ("blob_gc", TaskStatus::Completed),
+            ("llm_cache_prune", TaskStatus::Completed),
+        ]
+    );
+}
@@ -1,10 +1,32 @@
+use async_trait::async_trait;
 use core_rs::db::{migrate, DbError};
-use core_rs::meeting::extract_action_items;
-use core_rs::note::create_note;
+use core_rs::llm::types::LLMResponse;
+use core_rs::llm::{LLMProvider, LLMRequest, LlmError};
+use core_rs::meeting::{
+    extract_action_items, extract_action_items_with_llm, parse_action_items, ExtractionMethod,
+};
+use core_rs::note::{create_note, Note};
 use core_rs::space::create_space;
 use rusqlite::Connection;
 use tempfile::tempdir;
 
+const MEETING_NOTE: &str = "\
+# Weekly sync
+
+Attendees: @jules, @mara
+
+- [ ] @jules Do the thing
+- [x] Book the retro room
+* [ ] Draft the Q3 roadmap
+- @mara to send the budget to finance.
+@sam will review the hiring plan
+TODO: update the onboarding docs
+Action item: @jules: follow up with legal
+- [ ] ok
+@dev the staging box?
+Notes: the demo went well; thanks @mara for presenting.
+";
+
 fn setup_db() -> (tempfile::TempDir, Connection) {
     let dir = tempdir().unwrap();
     let file_path = dir.path().join("test.db");
@@ -14,42 +36,168 @@ fn setup_db() -> (tempfile::TempDir, Connection) {
     (dir, conn)
 }
 
+fn meeting_note(conn: &mut Connection, content: &str) -> Note {
+    let space_id = create_space(conn, "test_space").unwrap();
+    create_note(conn, &space_id.to_string(), "Meeting Note", content).unwrap()
+}
+
+/// (title, status, context) of the tasks linked to a note
+fn note_tasks(conn: &Connection, note: &Note) -> Vec<(String, String, Option<String>)> {
+    let mut stmt = conn
+        .prepare("SELECT title, status, context FROM task WHERE note_id = ?1 ORDER BY title")
+        .unwrap();
+    stmt.query_map([note.id.to_string()], |row| {
+        Ok((row.get(0)?, row.get(1)?, row.get(2)?))
+    })
+    .unwrap()
+    .collect::<Result<Vec<_>, _>>()
+    .unwrap()
+}
+
+#[test]
+fn test_parse_recognises_action_item_syntax() {
+    let parsed = parse_action_items(MEETING_NOTE).unwrap();
+    let items: Vec<(&str, Option<&str>, bool)> = parsed
+        .items
+        .iter()
+        .map(|i| (i.title.as_str(), i.assignee.as_deref(), i.done))
+        .collect();
+    assert_eq!(
+        items,
+        vec![
+            ("Do the thing", Some("jules"), false),
+            ("Book the retro room", None, true),
+            ("Draft the Q3 roadmap", None, false),
+            ("send the budget to finance", Some("mara"), false),
+            ("review the hiring plan", Some("sam"), false),
+            ("update the onboarding docs", None, false),
+            ("follow up with legal", Some("jules"), false),
+        ]
+    );
+    assert_eq!(parsed.ambiguous, vec!["- [ ] ok", "@dev the staging box?"]);
+}
+
 #[test]
 fn test_extract_action_items() -> Result<(), DbError> {
     let (_dir, mut conn) = setup_db();
+    let note = meeting_note(&mut conn, MEETING_NOTE);
 
-    let space_id = create_space(&mut conn, "test_space").unwrap();
+    let report = extract_action_items(&conn, &note.id.to_string()).unwrap();
+    assert_eq!(report.created.len(), 7);
+    assert_eq!(report.skipped, 0);
+    assert_eq!(report.ambiguous.len(), 2);
+    assert_eq!(report.method, ExtractionMethod::Heuristic);
 
-    let note = create_note(
-        &conn,
-        &space_id.to_string(),
-        "Meeting Note",
-        "- [ ] @jules Do the thing\n- [ ] @jules Do another thing",
-    )
-    .unwrap();
+    let tasks = note_tasks(&conn, &note);
+    assert_eq!(tasks.len(), 7);
+    assert!(tasks.contains(&(
+        "Do the thing".to_string(),
+        "inbox".to_string(),
+        Some("jules".to_string())
+    )));
+    assert!(tasks.contains(&("Book the retro room".to_string(), "done".to_string(), None)));
+
+    let space_id: String = conn.query_row(
+        "SELECT DISTINCT space_id FROM task WHERE note_id = ?1",
+        [note.id.to_string()],
+        |row| row.get(0),
+    )?;
+    assert_eq!(space_id, note.space_id);
+    Ok(())
+}
+
+#[test]
+fn test_rerunning_extraction_does_not_duplicate() {
+    let (_dir, mut conn) = setup_db();
+    let note = meeting_note(&mut conn, MEETING_NOTE);
+    let note_id = note.id.to_string();
+    extract_action_items(&conn, &note_id).unwrap();
 
-    let new_task_ids = extract_action_items(
-        &conn,
-        &space_id.to_string(),
-        &note.id.to_string(),
-        &note.content_md,
+    let report = extract_action_items(&conn, &note_id).unwrap();
+    assert!(report.created.is_empty());
+    assert_eq!(report.skipped, 7);
+
+    // Ticking a box or re-indenting keeps the item's identity; a new line is new
+    let edited = MEETING_NOTE
+        .replace("- [ ] @jules Do the thing", "  - [x] @jules  Do the thing")
+        .replace(
+            "TODO: update the onboarding docs",
+            "- TODO: update the onboarding docs",
+        )
+        + "- [ ] Share the recording\n";
+    conn.execute(
+        "UPDATE note SET content_md = ?1 WHERE id = ?2",
+        rusqlite::params![edited, note_id],
     )
     .unwrap();
+    let report = extract_action_items(&conn, &note_id).unwrap();
+    assert_eq!(report.created.len(), 1);
+    assert_eq!(report.skipped, 7);
+    assert_eq!(note_tasks(&conn, &note).len(), 8);
+}
 
-    assert_eq!(new_task_ids.len(), 2);
+/// Provider that answers every request with a fixed body, or fails
+struct ScriptedProvider(Option<&'static str>);
 
-    let mut stmt = conn
-        .prepare("SELECT title FROM task WHERE id = ?1")
+#[async_trait]
+impl LLMProvider for ScriptedProvider {
+    async fn complete(&self, _request: &LLMRequest) -> Result<LLMResponse, LlmError> {
+        match self.0 {
+            Some(content) => Ok(LLMResponse::new(content, "scripted", 10)),
+            None => Err(LlmError::NetworkError("offline".to_string())),
+        }
+    }
+
+    async fn list_models(&self) -> Result<Vec<String>, LlmError> {
+        Ok(vec![])
+    }
+
+    fn name(&self) -> &str {
+        "scripted"
+    }
+}
+
+#[tokio::test]
+async fn test_llm_extraction_and_heuristic_fallback() {
+    let (_dir, mut conn) = setup_db();
+    let note = meeting_note(&mut conn, MEETING_NOTE);
+    let note_id = note.id.to_string();
+
+    // Without a provider, or with a failing one, the heuristic parser is used
+    let report = extract_action_items_with_llm(&conn, &note_id, None, None)
+        .await
         .unwrap();
-    let title: String = stmt
-        .query_row([&new_task_ids[0]], |row| row.get(0))
+    assert_eq!(report.method, ExtractionMethod::Heuristic);
+    assert_eq!(report.created.len(), 7);
+
+    let offline = ScriptedProvider(None);
+    let report = extract_action_items_with_llm(&conn, &note_id, Some(&offline), None)
+        .await
         .unwrap();
-    assert_eq!(title, "Do the thing");
+    assert_eq!(report.method, ExtractionMethod::Heuristic);
+    assert_eq!(report.skipped, 7);
 
-    let title: String = stmt
-        .query_row([&new_task_ids[1]], |row| row.get(0))
+    let llm = ScriptedProvider(Some(
+        "Here you go:\n```json\n[\
+         {\"title\": \"Do the thing\", \"assignee\": \"@jules\", \"done\": false},\
+         {\"title\": \"Prepare the demo script\", \"assignee\": null, \"done\": false},\
+         {\"title\": \"?\", \"assignee\": null, \"done\": false}\
+         ]\n```",
+    ));
+    let report = extract_action_items_with_llm(&conn, &note_id, Some(&llm), None)
+        .await
         .unwrap();
-    assert_eq!(title, "Do another thing");
+    assert_eq!(report.method, ExtractionMethod::Llm);
+    assert_eq!(report.created.len(), 1);
+    assert_eq!(report.skipped, 1);
+    assert_eq!(report.ambiguous, vec!["?"]);
+    assert_eq!(note_tasks(&conn, &note).len(), 8);
 
-    Ok(())
+    // A reply without JSON falls back as well
+    let chatty = ScriptedProvider(Some("I found no action items."));
+    let report = extract_action_items_with_llm(&conn, &note_id, Some(&chatty), None)
+        .await
+        .unwrap();
+    assert_eq!(report.method, ExtractionMethod::Heuristic);
+    assert!(report.created.is_empty());
 }
@@ -0,0 +1,317 @@
+use core_rs::db::migrations::{applied_migrations, current_version, latest_version};
+use core_rs::db::{
+    get_dashboard_stats, migrate, migrate_plan, migrate_to, MigrationDirection, MigrationError,
+};
+use core_rs::note::create_note;
+use core_rs::space::create_space;
+use rusqlite::Connection;
+use tempfile::{tempdir, TempDir};
+
+fn setup_db() -> (Connection, TempDir) {
+    let dir = tempdir().unwrap();
+    let mut conn = Connection::open(dir.path().join("vault.db")).unwrap();
+    conn.pragma_update(None, "foreign_keys", "ON").unwrap();
+    migrate(&mut conn).unwrap();
+    (conn, dir)
+}
+
+fn table_exists(conn: &Connection, name: &str) -> bool {
+    conn.query_row(
+        "SELECT EXISTS(SELECT 1 FROM sqlite_master WHERE name = ?1)",
+        [name],
+        |row| row.get(0),
+    )
+    .unwrap()
+}
+
+fn column_exists(conn: &Connection, table: &str, column: &str) -> bool {
+    conn.query_row(
+        "SELECT EXISTS(SELECT 1 FROM pragma_table_info(?1) WHERE name = ?2)",
+        [table, column],
+        |row| row.get(0),
+    )
+    .unwrap()
+}
+
+#[test]
+fn test_down_and_up_again_keeps_seeded_data() {
+    let (mut conn, dir) = setup_db();
+    let space_id = create_space(&mut conn, "Migrations").unwrap().to_string();
+    let note = create_note(&conn, &space_id, "Kept", "Survives the round trip").unwrap();
+    conn.execute(
+        "INSERT INTO task (id, space_id, title, status, updated_at) VALUES ('t1', ?1, 'Kept task', 'next', 1)",
+        [&space_id],
+    )
+    .unwrap();
+    conn.execute(
+        "INSERT INTO health_metric_setting (space_id, metric_type, min_value, updated_at) VALUES (?1, 'steps', 5000, 1)",
+        [&space_id],
+    )
+    .unwrap();
+
+    let backup = dir.path().join("before-downgrade.db");
+    let report = migrate_to(&mut conn, 31, &backup).unwrap();
+    assert_eq!(report.from_version, latest_version());
+    assert_eq!(report.to_version, 31);
+    let reverted: Vec<i64> = report.steps.iter().map(|s| s.version).collect();
+    assert_eq!(reverted, (32..=latest_version()).rev().collect::<Vec<_>>());
+    assert!(report
+        .steps
+        .iter()
+        .all(|s| s.direction == MigrationDirection::Down));
+    assert_eq!(current_version(&conn).unwrap(), 31);
+
+    // Newer tables and columns are gone, older ones untouched
+    assert!(!table_exists(&conn, "health_metric_setting"));
+    assert!(!table_exists(&conn, "form_submission"));
+    assert!(!table_exists(&conn, "meeting_action"));
+    assert!(!column_exists(&conn, "form_template", "schema_version"));
+    assert!(column_exists(&conn, "sessions", "last_used_at"));
+    let content: String = conn
+        .query_row(
+            "SELECT content_md FROM note WHERE id = ?1",
+            [note.id.to_string()],
+            |row| row.get(0),
+        )
+        .unwrap();
+    assert_eq!(content, "Survives the round trip");
+
+    // The snapshot still has everything from before the downgrade
+    let snapshot = Connection::open(&backup).unwrap();
+    assert_eq!(current_version(&snapshot).unwrap(), latest_version());
+    let steps: f64 = snapshot
+        .query_row(
+            "SELECT min_value FROM health_metric_setting WHERE metric_type = 'steps'",
+            [],
+            |row| row.get(0),
+        )
+        .unwrap();
+    assert_eq!(steps, 5000.0);
+
+    let report = migrate_to(
+        &mut conn,
+        latest_version(),
+        &dir.path().join("before-upgrade.db"),
+    )
+    .unwrap();
+    assert_eq!(report.steps.len(), reverted.len());
+    assert_eq!(current_version(&conn).unwrap(), latest_version());
+    assert!(table_exists(&conn, "health_metric_setting"));
+    assert!(column_exists(&conn, "form_template", "schema_version"));
+    let (title, status): (String, String) = conn
+        .query_row(
+            "SELECT title, status FROM task WHERE id = 't1'",
+            [],
+            |row| Ok((row.get(0)?, row.get(1)?)),
+        )
+        .unwrap();
+    assert_eq!((title.as_str(), status.as_str()), ("Kept task", "next"));
+    let notes: i64 = conn
+        .query_row("SELECT COUNT(*) FROM note", [], |row| row.get(0))
+        .unwrap();
+    assert_eq!(notes, 1);
+}
+
+#[test]
+fn test_downgrade_refuses_to_cross_irreversible_migration() {
+    let (mut conn, dir) = setup_db();
+    let backup = dir.path().join("backup.db");
+
+    // v30 rebuilds social_automation_rule
+    match migrate_to(&mut conn, 29, &backup) {
+        Err(MigrationError::Irreversible { version, .. }) => assert_eq!(version, 30),
+        other => panic!("expected an irreversible migration error, got {:?}", other),
+    }
+    assert_eq!(current_version(&conn).unwrap(), latest_version());
+    assert!(table_exists(&conn, "social_automation_firing"));
+    assert!(!backup.exists());
+
+    assert!(matches!(
+        migrate_to(&mut conn, latest_version() + 1, &backup),
+        Err(MigrationError::UnknownVersion(_))
+    ));
+}
+
+#[test]
+fn test_vault_from_a_newer_app_version_asks_for_an_upgrade() {
+    let (mut conn, dir) = setup_db();
+    let newer = latest_version() + 1;
+    conn.execute("INSERT INTO schema_version (version) VALUES (?1)", [newer])
+        .unwrap();
+
+    match migrate_plan(&conn) {
+        Err(MigrationError::VaultTooNew {
+            vault_version,
+            supported,
+        }) => {
+            assert_eq!(vault_version, newer);
+            assert_eq!(supported, latest_version());
+        }
+        other => panic!("expected a vault-too-new error, got {:?}", other),
+    }
+    let err = migrate(&mut conn).unwrap_err().to_string();
+    assert!(err.contains("newer version of the app"), "{}", err);
+    assert!(matches!(
+        migrate_to(&mut conn, latest_version(), &dir.path().join("backup.db")),
+        Err(MigrationError::VaultTooNew { .. })
+    ));
+    assert_eq!(current_version(&conn).unwrap(), newer);
+}
+
+#[test]
+fn test_plan_lists_pending_migrations_and_timing_is_recorded() {
+    let dir = tempdir().unwrap();
+    let mut conn = Connection::open(dir.path().join("vault.db")).unwrap();
+    migrate_to(&mut conn, 20, &dir.path().join("empty.db")).unwrap();
+
+    let plan = migrate_plan(&conn).unwrap();
+    let versions: Vec<i64> = plan.iter().map(|s| s.version).collect();
+    assert_eq!(versions, (21..=latest_version()).collect::<Vec<_>>());
+    assert!(plan.iter().all(|s| s.direction == MigrationDirection::Up));
+    assert_eq!(plan[0].description, "Performance Optimization");
+    let social_usage = plan.iter().find(|s| s.version == 30).unwrap();
+    assert!(!social_usage.reversible);
+    assert!(plan.iter().find(|s| s.version == 31).unwrap().reversible);
+    // Planning changes nothing
+    assert_eq!(current_version(&conn).unwrap(), 20);
+
+    migrate(&mut conn).unwrap();
+    assert!(migrate_plan(&conn).unwrap().is_empty());
+    let applied = applied_migrations(&conn).unwrap();
+    assert_eq!(applied.len() as i64, latest_version());
+    assert!(applied
+        .iter()
+        .all(|m| m.applied_at.is_some() && m.duration_ms.is_some()));
+}
+
+#[test]
+fn test_every_reversible_migration_reverts_and_reapplies() {
+    // Each range stops short of an irreversible migration (v9, v30)
+    for (top, bottom) in [(8, 1), (29, 9), (latest_version(), 30)] {
+        let dir = tempdir().unwrap();
+        let mut conn = Connection::open(dir.path().join("vault.db")).unwrap();
+        conn.pragma_update(None, "foreign_keys", "ON").unwrap();
+        migrate_to(&mut conn, top, &dir.path().join("a.db")).unwrap();
+        migrate_to(&mut conn, bottom, &dir.path().join("b.db")).unwrap();
+        assert_eq!(current_version(&conn).unwrap(), bottom);
+        migrate_to(&mut conn, top, &dir.path().join("c.db")).unwrap();
+        assert_eq!(current_version(&conn).unwrap(), top);
+    }
+}
+
+#[test]
+fn test_tables_created_before_migrations_gain_their_columns() {
+    let dir = tempdir().unwrap();
+    let mut conn = Connection::open(dir.path().join("vault.db")).unwrap();
+    migrate_to(&mut conn, 78, &dir.path().join("empty.db")).unwrap();
+
+    // As the startup code of older versions left them
+    conn.execute_batch(
+        "
+        CREATE TABLE ocr_result (
+            id TEXT PRIMARY KEY,
+            blob_id TEXT NOT NULL UNIQUE,
+            extracted_text TEXT,
+            confidence REAL,
+            status TEXT NOT NULL DEFAULT 'pending',
+            processed_at INTEGER,
+            error_message TEXT,
+            created_at INTEGER NOT NULL,
+            FOREIGN KEY (blob_id) REFERENCES blob(id) ON DELETE CASCADE
+        );
+        CREATE TABLE sync_state (
+            device_id TEXT PRIMARY KEY,
+            device_name TEXT NOT NULL,
+            device_type TEXT NOT NULL,
+            last_seen INTEGER NOT NULL,
+            sync_address TEXT NOT NULL,
+            sync_port INTEGER NOT NULL,
+            protocol_version TEXT NOT NULL,
+            trusted INTEGER NOT NULL DEFAULT 0,
+            created_at INTEGER NOT NULL DEFAULT (strftime('%s', 'now'))
+        );
+        CREATE TABLE user_invitations (
+            id TEXT PRIMARY KEY,
+            space_id TEXT NOT NULL,
+            email TEXT NOT NULL,
+            role_id TEXT NOT NULL,
+            token TEXT NOT NULL UNIQUE,
+            invited_by TEXT NOT NULL,
+            invited_at INTEGER NOT NULL,
+            expires_at INTEGER NOT NULL,
+            status TEXT NOT NULL DEFAULT 'pending'
+        );
+        CREATE TABLE recipe (
+            id TEXT PRIMARY KEY,
+            space_id TEXT NOT NULL,
+            note_id TEXT NOT NULL,
+            name TEXT NOT NULL,
+            rating INTEGER NOT NULL,
+            difficulty TEXT NOT NULL,
+            created_at INTEGER NOT NULL
+        );
+        INSERT INTO recipe VALUES ('r1', 's1', 'n1', 'Soup', 4, 'easy', 0);
+        CREATE TABLE note_embeddings (
+            id TEXT PRIMARY KEY,
+            note_id TEXT NOT NULL,
+            chunk_index INTEGER NOT NULL,
+            content TEXT NOT NULL,
+            embedding BLOB,
+            start_offset INTEGER,
+            end_offset INTEGER,
+            metadata TEXT,
+            created_at INTEGER DEFAULT (strftime('%s', 'now')),
+            FOREIGN KEY (note_id) REFERENCES note(id) ON DELETE CASCADE,
+            UNIQUE (note_id, chunk_index)
+        );
+        CREATE TABLE dashboard_stats (
+            space_id TEXT PRIMARY KEY,
+            total_notes INTEGER DEFAULT 0,
+            total_tasks INTEGER DEFAULT 0,
+            completed_tasks INTEGER DEFAULT 0,
+            pending_tasks INTEGER DEFAULT 0,
+            overdue_tasks INTEGER DEFAULT 0,
+            total_projects INTEGER DEFAULT 0,
+            active_habits INTEGER DEFAULT 0,
+            streak_days INTEGER DEFAULT 0,
+            notes_this_week INTEGER DEFAULT 0,
+            tasks_completed_this_week INTEGER DEFAULT 0,
+            last_updated INTEGER
+        );
+        INSERT INTO dashboard_stats (space_id, total_notes) VALUES ('s1', 3);
+        ",
+    )
+    .unwrap();
+
+    migrate(&mut conn).unwrap();
+    for column in [
+        "priority",
+        "attempts",
+        "next_attempt_at",
+        "languages",
+        "detected_language",
+    ] {
+        assert!(column_exists(&conn, "ocr_result", column), "{}", column);
+    }
+    for column in [
+        "static_public_key",
+        "discovery_source",
+        "sync_scope",
+        "user_id",
+    ] {
+        assert!(column_exists(&conn, "sync_state", column), "{}", column);
+    }
+    assert!(column_exists(&conn, "sync_history", "note"));
+    assert!(column_exists(&conn, "user_invitations", "responded_at"));
+    assert!(column_exists(&conn, "user_invitations", "accepted_by"));
+    let servings: i64 = conn
+        .query_row("SELECT servings FROM recipe WHERE id = 'r1'", [], |row| {
+            row.get(0)
+        })
+        .unwrap();
+    assert_eq!(servings, 1);
+    assert!(column_exists(&conn, "note_embeddings", "content_hash"));
+    assert!(column_exists(&conn, "note_embeddings", "embedding_version"));
+    let stats = get_dashboard_stats(&conn, "s1").unwrap();
+    assert_eq!((stats.total_notes, stats.notes_previous_week), (3, 0));
+}
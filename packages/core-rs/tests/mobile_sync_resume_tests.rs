@@ -0,0 +1,194 @@
+use chrono::Utc;
+use core_rs::db::migrate;
+use core_rs::sync::mobile_sync::{
+    apply_batch, load_checkpoint, BatchOutcome, DeltaOperation, OutgoingTransfer, SyncBatch,
+    SyncBatchProcessor, SyncDelta, SyncProtocolError,
+};
+use core_rs::sync::SyncError;
+use rusqlite::Connection;
+use std::collections::HashMap;
+
+const PHONE: &str = "phone";
+
+/// 50 deltas in batches of 5
+fn transfer() -> OutgoingTransfer {
+    let deltas = (1..=50)
+        .map(|i| SyncDelta {
+            operation: DeltaOperation::Update,
+            entity_type: "note".to_string(),
+            entity_id: format!("note-{:02}", i),
+            encrypted_data: Some(format!("body {}", i).into_bytes()),
+            timestamp: Utc::now(),
+            data_hash: None,
+            sequence: i,
+            vector_clock: HashMap::from([("phone".to_string(), i as i64)]),
+        })
+        .collect();
+    SyncBatchProcessor::new(5, 1024 * 1024).create_transfer(deltas)
+}
+
+/// A receiver whose deltas land in a scratch table
+fn receiver() -> Connection {
+    let mut conn = Connection::open_in_memory().unwrap();
+    migrate(&mut conn).unwrap();
+    conn.execute("CREATE TABLE received (entity_id TEXT PRIMARY KEY)", [])
+        .unwrap();
+    conn
+}
+
+fn receive(conn: &mut Connection, batch: &SyncBatch) -> Result<BatchOutcome, SyncProtocolError> {
+    apply_batch(conn, PHONE, batch, |tx, delta| {
+        tx.execute(
+            "INSERT OR REPLACE INTO received (entity_id) VALUES (?1)",
+            [&delta.entity_id],
+        )?;
+        Ok(())
+    })
+}
+
+fn received(conn: &Connection) -> Vec<String> {
+    let mut stmt = conn
+        .prepare("SELECT entity_id FROM received ORDER BY entity_id")
+        .unwrap();
+    let ids = stmt
+        .query_map([], |row| row.get(0))
+        .unwrap()
+        .collect::<Result<Vec<_>, _>>()
+        .unwrap();
+    ids
+}
+
+fn sequences(batches: &[SyncBatch]) -> Vec<u32> {
+    batches.iter().map(|b| b.sequence).collect()
+}
+
+#[test]
+fn test_interrupted_transfer_resumes_after_last_applied_batch() {
+    let transfer = transfer();
+    assert_eq!(transfer.batches.len(), 10);
+
+    // The one-shot run
+    let mut one_shot = receiver();
+    for batch in transfer.remaining(None) {
+        receive(&mut one_shot, batch).unwrap();
+    }
+
+    // The connection drops after batch 3
+    let mut conn = receiver();
+    let checkpoint = load_checkpoint(&conn, PHONE).unwrap();
+    assert_eq!(checkpoint, None);
+    for batch in &transfer.remaining(checkpoint.as_ref())[..3] {
+        assert_eq!(
+            receive(&mut conn, batch).unwrap(),
+            BatchOutcome::Applied { deltas: 5 }
+        );
+    }
+
+    // On reconnect the checkpoint travels in the handshake
+    let checkpoint = load_checkpoint(&conn, PHONE).unwrap().unwrap();
+    assert_eq!(checkpoint.transfer_id, transfer.transfer_id);
+    assert_eq!(checkpoint.last_applied_batch, 3);
+    assert_eq!(
+        checkpoint.vector_clock,
+        HashMap::from([("phone".to_string(), 15)])
+    );
+    let remaining = transfer.remaining(Some(&checkpoint));
+    assert_eq!(sequences(remaining), (4..=10).collect::<Vec<_>>());
+    for batch in remaining {
+        receive(&mut conn, batch).unwrap();
+    }
+
+    assert_eq!(received(&conn).len(), 50);
+    assert_eq!(received(&conn), received(&one_shot));
+    let done = load_checkpoint(&conn, PHONE).unwrap().unwrap();
+    assert_eq!(done.last_applied_batch, 10);
+    assert!(transfer.remaining(Some(&done)).is_empty());
+
+    // A batch sent twice around a reconnect is skipped
+    assert_eq!(
+        receive(&mut conn, &transfer.batches[9]).unwrap(),
+        BatchOutcome::AlreadyApplied
+    );
+}
+
+#[test]
+fn test_corrupt_batches_are_refused_and_retried() {
+    let transfer = transfer();
+    let mut conn = receiver();
+    for batch in &transfer.batches[..4] {
+        receive(&mut conn, batch).unwrap();
+    }
+
+    let mut corrupted = transfer.batches[4].clone();
+    corrupted.deltas[2].encrypted_data = Some(b"bit flip".to_vec());
+    assert!(!corrupted.verify());
+    assert!(matches!(
+        receive(&mut conn, &corrupted),
+        Err(SyncProtocolError::CorruptBatch(5))
+    ));
+    assert_eq!(received(&conn).len(), 20);
+    assert_eq!(
+        load_checkpoint(&conn, PHONE)
+            .unwrap()
+            .unwrap()
+            .last_applied_batch,
+        4
+    );
+
+    // Skipping ahead isn't allowed either
+    assert!(matches!(
+        receive(&mut conn, &transfer.batches[6]),
+        Err(SyncProtocolError::InvalidState(_))
+    ));
+
+    // A delta that fails to apply rolls back the whole batch
+    let failed = apply_batch(&mut conn, PHONE, &transfer.batches[4], |tx, delta| {
+        tx.execute(
+            "INSERT INTO received (entity_id) VALUES (?1)",
+            [&delta.entity_id],
+        )?;
+        if delta.entity_id == "note-23" {
+            return Err(SyncError::InvalidData("disk full".to_string()));
+        }
+        Ok(())
+    });
+    assert!(matches!(failed, Err(SyncProtocolError::Apply(_))));
+    assert_eq!(received(&conn).len(), 20);
+
+    // The resent batch goes through
+    receive(&mut conn, &transfer.batches[4]).unwrap();
+    assert_eq!(received(&conn).len(), 25);
+}
+
+#[test]
+fn test_stale_or_unknown_checkpoints_restart_the_transfer() {
+    let first = transfer();
+    let mut conn = receiver();
+    for batch in &first.batches[..3] {
+        receive(&mut conn, batch).unwrap();
+    }
+
+    // The sender lost the transfer and plans a new one
+    let second = transfer();
+    let checkpoint = load_checkpoint(&conn, PHONE).unwrap().unwrap();
+    assert_eq!(second.remaining(Some(&checkpoint)).len(), 10);
+    receive(&mut conn, &second.batches[0]).unwrap();
+    let checkpoint = load_checkpoint(&conn, PHONE).unwrap().unwrap();
+    assert_eq!(checkpoint.transfer_id, second.transfer_id);
+    assert_eq!(checkpoint.last_applied_batch, 1);
+
+    // A day-old checkpoint isn't resumed
+    conn.execute(
+        "UPDATE sync_transfer_checkpoint SET updated_at = updated_at - 2 * 86400",
+        [],
+    )
+    .unwrap();
+    assert_eq!(load_checkpoint(&conn, PHONE).unwrap(), None);
+    assert_eq!(second.remaining(None).len(), 10);
+    let rows: i64 = conn
+        .query_row("SELECT COUNT(*) FROM sync_transfer_checkpoint", [], |row| {
+            row.get(0)
+        })
+        .unwrap();
+    assert_eq!(rows, 0);
+}
@@ -1,5 +1,13 @@
 use core_rs::db;
-use core_rs::mode::{disable_mode, enable_mode, get_space_modes, Mode};
+use core_rs::form::get_form_templates_for_space;
+use core_rs::health::{create_health_metric, set_metric_threshold};
+use core_rs::mode::{
+    disable_mode, enable_mode, get_space_modes, get_space_modes_with_data, preview_mode_purge,
+    purge_mode, Mode, ModeError,
+};
+use core_rs::personal_modes::{
+    create_transaction, get_finance_mode, get_health_mode, CreateTransactionParams,
+};
 use rusqlite::Connection;
 use tempfile::{tempdir, TempDir};
 use ulid::Ulid;
@@ -84,3 +92,156 @@ fn test_disable_non_existent_mode() {
     let modes = get_space_modes(&conn, &space_id.to_string()).unwrap();
     assert_eq!(modes.len(), 0);
 }
+
+fn create_space(conn: &Connection) -> Ulid {
+    let space_id = Ulid::new();
+    conn.execute(
+        "INSERT INTO space (id, name, enabled_modes_json) VALUES (?, 'Test Space', '[]')",
+        [space_id.to_string()],
+    )
+    .unwrap();
+    space_id
+}
+
+fn record_metric(conn: &Connection, space_id: Ulid, value: f64) {
+    create_health_metric(
+        conn,
+        space_id,
+        None,
+        "weight",
+        value,
+        None,
+        None,
+        1_700_000_000,
+    )
+    .unwrap();
+}
+
+#[test]
+fn test_enable_runs_setup_once() {
+    let (conn, _dir) = setup_db();
+    let space_id = create_space(&conn).to_string();
+
+    enable_mode(&conn, &space_id, &get_finance_mode()).unwrap();
+    enable_mode(&conn, &space_id, &get_finance_mode()).unwrap();
+    disable_mode(&conn, &space_id, &get_finance_mode()).unwrap();
+    enable_mode(&conn, &space_id, &get_finance_mode()).unwrap();
+
+    let templates = get_form_templates_for_space(&conn, &space_id).unwrap();
+    assert_eq!(templates.len(), 1);
+    assert_eq!(templates[0].name, "Expense");
+    let category = templates[0]
+        .fields
+        .iter()
+        .find(|f| f.name == "category")
+        .unwrap();
+    assert!(category.options.contains(&"Groceries".to_string()));
+}
+
+#[test]
+fn test_disable_retains_data() {
+    let (conn, _dir) = setup_db();
+    let space = create_space(&conn);
+    let space_id = space.to_string();
+    enable_mode(&conn, &space_id, &get_health_mode()).unwrap();
+    for value in [80.0, 79.5, 79.0] {
+        record_metric(&conn, space, value);
+    }
+
+    disable_mode(&conn, &space_id, &get_health_mode()).unwrap();
+    assert!(get_space_modes_with_data(&conn, &space_id)
+        .unwrap()
+        .is_empty());
+
+    enable_mode(&conn, &space_id, &get_health_mode()).unwrap();
+    let modes = get_space_modes_with_data(&conn, &space_id).unwrap();
+    assert_eq!(modes.len(), 1);
+    assert_eq!(modes[0].mode, get_health_mode());
+    assert_eq!(modes[0].total_rows, 3);
+    assert_eq!(modes[0].data[0].label, "metrics");
+    assert_eq!(modes[0].data[0].count, 3);
+}
+
+#[test]
+fn test_purge_deletes_what_the_preview_counted() {
+    let (conn, _dir) = setup_db();
+    let space = create_space(&conn);
+    let other = create_space(&conn);
+    let space_id = space.to_string();
+    enable_mode(&conn, &space_id, &get_health_mode()).unwrap();
+    enable_mode(&conn, &space_id, &get_finance_mode()).unwrap();
+    for value in [80.0, 79.5] {
+        record_metric(&conn, space, value);
+    }
+    record_metric(&conn, other, 70.0);
+    set_metric_threshold(&conn, space, "weight", None, Some(85.0)).unwrap();
+    create_transaction(
+        &conn,
+        CreateTransactionParams {
+            space_id: space,
+            transaction_type: "expense",
+            amount: 12.0,
+            currency: "EUR",
+            category: "Dining",
+            account_id: "cash",
+            date: 1_700_000_000,
+            description: None,
+        },
+    )
+    .unwrap();
+
+    let preview = preview_mode_purge(&conn, &space_id, "health").unwrap();
+    assert_eq!(preview.total_rows, 3);
+    let counts: Vec<(&str, i64)> = preview
+        .data
+        .iter()
+        .map(|d| (d.table.as_str(), d.count))
+        .collect();
+    assert_eq!(
+        counts,
+        vec![("health_metric", 2), ("health_metric_setting", 1)]
+    );
+
+    // A preview that no longer matches deletes nothing
+    record_metric(&conn, space, 79.0);
+    assert!(matches!(
+        purge_mode(&conn, &preview),
+        Err(ModeError::StalePreview {
+            previewed: 2,
+            actual: 3,
+            ..
+        })
+    ));
+    assert_eq!(
+        preview_mode_purge(&conn, &space_id, "health")
+            .unwrap()
+            .total_rows,
+        4
+    );
+
+    let preview = preview_mode_purge(&conn, &space_id, "health").unwrap();
+    let deleted = purge_mode(&conn, &preview).unwrap();
+    assert_eq!(deleted, preview.data);
+    assert_eq!(
+        preview_mode_purge(&conn, &space_id, "health")
+            .unwrap()
+            .total_rows,
+        0
+    );
+
+    // Only the health mode of this space is gone
+    let modes = get_space_modes(&conn, &space_id).unwrap();
+    assert_eq!(modes, vec![get_finance_mode()]);
+    assert_eq!(
+        preview_mode_purge(&conn, &space_id, "finance")
+            .unwrap()
+            .total_rows,
+        1
+    );
+    assert_eq!(
+        preview_mode_purge(&conn, &other.to_string(), "health")
+            .unwrap()
+            .total_rows,
+        1
+    );
+}
@@ -53,3 +53,221 @@ fn test_playlist_creation_and_retrieval() {
     assert_eq!(playlists.len(), 1);
     assert_eq!(playlists[0].id, playlist.id);
 }
+
+const DAY: i64 = 86_400;
+
+/// Tracks with genres and library dates, as a sync or import would leave them
+fn seed_library(conn: &Connection, space_id: ulid::Ulid) -> Vec<music::Track> {
+    let now = chrono::Utc::now().timestamp();
+    let mut tracks = Vec::new();
+    for (title, artist, genre, added_days_ago) in [
+        ("So What", "Miles Davis", "Jazz", 90),
+        ("Blue in Green", "Miles Davis", "jazz", 5),
+        ("Karma Police", "Radiohead", "Rock", 200),
+        ("Reckoner", "Radiohead", "Rock", 2),
+    ] {
+        let track =
+            music::create_track(conn, space_id, title, Some(artist.to_string()), None).unwrap();
+        conn.execute(
+            "UPDATE track SET genre = ?1, added_at = ?2 WHERE id = ?3",
+            rusqlite::params![genre, now - added_days_ago * DAY, track.id.to_string()],
+        )
+        .unwrap();
+        tracks.push(track);
+    }
+    tracks
+}
+
+#[test]
+fn test_listening_stats_aggregate_plays_in_range() {
+    let (mut conn, _dir) = setup_db();
+    let space_id = space::create_space(&mut conn, "Music Space").unwrap();
+    let tracks = seed_library(&conn, space_id);
+    let (so_what, blue, karma) = (tracks[0].id, tracks[1].id, tracks[2].id);
+
+    for (track, played_at, listened) in [
+        (so_what, 1_000, 545),
+        (so_what, 2_000, 300),
+        (blue, 3_000, 337),
+        (karma, 4_000, 264),
+        (karma, 5_000, 264),
+        (karma, 6_000, 100),
+        // Outside the range
+        (karma, 20_000, 264),
+    ] {
+        music::record_play(&conn, track, played_at, listened).unwrap();
+    }
+    assert!(music::record_play(&conn, so_what, 7_000, -1).is_err());
+    assert!(music::record_play(&conn, ulid::Ulid::new(), 7_000, 10).is_err());
+
+    let (play_count, last_played_at): (i64, i64) = conn
+        .query_row(
+            "SELECT play_count, last_played_at FROM track WHERE id = ?1",
+            [karma.to_string()],
+            |row| Ok((row.get(0)?, row.get(1)?)),
+        )
+        .unwrap();
+    assert_eq!((play_count, last_played_at), (4, 20_000));
+
+    let stats = music::get_listening_stats(
+        &conn,
+        space_id,
+        core_rs::calendar::TimeRange::new(0, 10_000),
+    )
+    .unwrap();
+    assert_eq!(stats.total_plays, 6);
+    assert_eq!(
+        stats.total_seconds_listened,
+        545 + 300 + 337 + 264 * 2 + 100
+    );
+    let top: Vec<(ulid::Ulid, i64, i64)> = stats
+        .top_tracks
+        .iter()
+        .map(|t| (t.track_id, t.plays, t.seconds_listened))
+        .collect();
+    assert_eq!(
+        top,
+        vec![(karma, 3, 628), (so_what, 2, 845), (blue, 1, 337)]
+    );
+    let artists: Vec<(&str, i64, i64)> = stats
+        .top_artists
+        .iter()
+        .map(|a| (a.artist.as_str(), a.plays, a.seconds_listened))
+        .collect();
+    assert_eq!(
+        artists,
+        vec![("Miles Davis", 3, 1182), ("Radiohead", 3, 628)]
+    );
+}
+
+#[test]
+fn test_smart_playlist_rule_combinations() {
+    let (mut conn, _dir) = setup_db();
+    let space_id = space::create_space(&mut conn, "Music Space").unwrap();
+    let tracks = seed_library(&conn, space_id);
+    let now = chrono::Utc::now().timestamp();
+    // So What is on repeat this week; Karma Police was, months ago
+    for days_ago in [1, 2, 3] {
+        music::record_play(&conn, tracks[0].id, now - days_ago * DAY, 545).unwrap();
+        music::record_play(&conn, tracks[2].id, now - (100 + days_ago) * DAY, 264).unwrap();
+    }
+
+    use music::SmartPlaylistCondition::*;
+    let members = |condition: music::SmartPlaylistCondition| {
+        let playlist = music::create_smart_playlist(&conn, space_id, "Smart", &condition).unwrap();
+        music::materialize_smart_playlist(&conn, playlist.id).unwrap();
+        let mut titles: Vec<String> = music::get_playlist_entries(&conn, playlist.id)
+            .unwrap()
+            .iter()
+            .map(|e| {
+                tracks
+                    .iter()
+                    .find(|t| t.id == e.track_id)
+                    .unwrap()
+                    .title
+                    .clone()
+            })
+            .collect();
+        titles.sort();
+        titles
+    };
+
+    assert_eq!(
+        members(GenreEquals {
+            genre: "JAZZ".into()
+        }),
+        vec!["Blue in Green", "So What"]
+    );
+    assert_eq!(
+        members(All {
+            conditions: vec![
+                ArtistIn {
+                    artists: vec!["miles davis".into(), "Bill Evans".into()]
+                },
+                PlayedMoreThan {
+                    times: 2,
+                    after: None,
+                    before: None,
+                    last_days: Some(7)
+                },
+            ]
+        }),
+        vec!["So What"]
+    );
+    assert_eq!(
+        members(Any {
+            conditions: vec![
+                AddedWithin { days: 30 },
+                PlayedMoreThan {
+                    times: 2,
+                    after: Some(now - 200 * DAY),
+                    before: Some(now - 50 * DAY),
+                    last_days: None
+                },
+            ]
+        }),
+        vec!["Blue in Green", "Karma Police", "Reckoner"]
+    );
+    assert!(members(Any { conditions: vec![] }).is_empty());
+    assert_eq!(members(All { conditions: vec![] }).len(), 4);
+
+    // The stored rule uses the same tagged JSON as social category rules
+    let json: serde_json::Value = serde_json::from_str(
+        r#"{"type": "all", "conditions": [
+            {"type": "genre_equals", "genre": "rock"},
+            {"type": "added_within", "days": 30}
+        ]}"#,
+    )
+    .unwrap();
+    let condition: music::SmartPlaylistCondition = serde_json::from_value(json).unwrap();
+    assert_eq!(members(condition), vec!["Reckoner"]);
+}
+
+#[test]
+fn test_smart_playlist_refresh_keeps_manual_entries() {
+    let (mut conn, _dir) = setup_db();
+    let space_id = space::create_space(&mut conn, "Music Space").unwrap();
+    let tracks = seed_library(&conn, space_id);
+    let (so_what, blue, karma) = (tracks[0].id, tracks[1].id, tracks[2].id);
+
+    let playlist = music::create_smart_playlist(
+        &conn,
+        space_id,
+        "Jazz",
+        &music::SmartPlaylistCondition::GenreEquals {
+            genre: "jazz".into(),
+        },
+    )
+    .unwrap();
+    let refresh = music::materialize_smart_playlist(&conn, playlist.id).unwrap();
+    assert_eq!((refresh.added, refresh.removed, refresh.total), (2, 0, 2));
+
+    // A rock track added by hand, and a rule entry pinned by hand
+    music::add_track_to_playlist(&conn, playlist.id, karma).unwrap();
+    music::add_track_to_playlist(&conn, playlist.id, so_what).unwrap();
+    conn.execute(
+        "UPDATE track SET genre = 'Modal' WHERE id IN (?1, ?2)",
+        [so_what.to_string(), blue.to_string()],
+    )
+    .unwrap();
+
+    let refresh = music::materialize_smart_playlist(&conn, playlist.id).unwrap();
+    assert_eq!((refresh.added, refresh.removed, refresh.total), (0, 1, 2));
+    let entries = music::get_playlist_entries(&conn, playlist.id).unwrap();
+    let kept: Vec<(ulid::Ulid, music::PlaylistEntrySource)> =
+        entries.iter().map(|e| (e.track_id, e.source)).collect();
+    assert_eq!(
+        kept,
+        vec![
+            (so_what, music::PlaylistEntrySource::Manual),
+            (karma, music::PlaylistEntrySource::Manual),
+        ]
+    );
+
+    // Refreshing again is a no-op
+    let refresh = music::materialize_smart_playlist(&conn, playlist.id).unwrap();
+    assert_eq!((refresh.added, refresh.removed, refresh.total), (0, 0, 2));
+
+    let plain = music::create_playlist(&conn, space_id, "Plain", None).unwrap();
+    assert!(music::materialize_smart_playlist(&conn, plain.id).is_err());
+}
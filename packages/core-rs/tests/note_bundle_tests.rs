@@ -0,0 +1,276 @@
+use core_rs::backup::note_bundle::{
+    export_encrypted_note_bundle, import_encrypted_note_bundle, NoteBundleError,
+};
+use core_rs::blob::{attach_blob_to_note, get_note_attachments, insert_blob, retrieve_blob};
+use core_rs::crypto::{derive_key_argon2, encrypt_bytes_with_aad};
+use core_rs::note::create_note;
+use core_rs::note_lock::lock_note;
+use rusqlite::Connection;
+use tempfile::{tempdir, TempDir};
+
+const SOURCE_MK: &[u8] = b"source-master-key-that-is-32-byt";
+const TARGET_MK: &[u8] = b"target-master-key-that-is-32-byt";
+
+/// A vault directory with a migrated database and one space
+fn setup_vault() -> (TempDir, Connection, String) {
+    let dir = tempdir().unwrap();
+    let mut conn = Connection::open(dir.path().join("test.db")).unwrap();
+    conn.pragma_update(None, "foreign_keys", "ON").unwrap();
+    core_rs::db::migrate(&mut conn).unwrap();
+    let space_id = core_rs::space::create_space(&mut conn, "Shared")
+        .unwrap()
+        .to_string();
+    (dir, conn, space_id)
+}
+
+/// Encrypt `entries` as a bundle archive the way the exporter does
+fn write_bundle(path: &std::path::Path, passphrase: &str, entries: &[(&str, &[u8])]) {
+    use std::io::Write;
+
+    let mut zip = zip::ZipWriter::new(std::io::Cursor::new(Vec::new()));
+    let options: zip::write::FileOptions<'_, ()> =
+        zip::write::FileOptions::default().compression_method(zip::CompressionMethod::Deflated);
+    for (name, content) in entries {
+        zip.start_file(*name, options).unwrap();
+        zip.write_all(content).unwrap();
+    }
+    let archive = zip.finish().unwrap().into_inner();
+
+    let salt: [u8; 16] = rand::random();
+    let mut header = b"NOTEECEB\x01".to_vec();
+    for cost in [19_456u32, 2, 1] {
+        header.extend_from_slice(&cost.to_le_bytes());
+    }
+    header.extend_from_slice(&salt);
+    let key = derive_key_argon2(passphrase, &salt, 19_456, 2, 1).unwrap();
+    let mut bundle = header.clone();
+    bundle.extend_from_slice(&encrypt_bytes_with_aad(&archive, key.as_ref(), &header).unwrap());
+    std::fs::write(path, bundle).unwrap();
+}
+
+fn note_count(conn: &Connection) -> i64 {
+    conn.query_row("SELECT COUNT(*) FROM note", [], |row| row.get(0))
+        .unwrap()
+}
+
+/// Export a note with a text and a 2 MB attachment; returns the bundle path
+/// and the attachment contents
+fn export_sample(passphrase: &str) -> (TempDir, std::path::PathBuf, Vec<Vec<u8>>) {
+    let (dir, conn, space_id) = setup_vault();
+    let vault = dir.path().to_str().unwrap();
+    let large: Vec<u8> = (0..2 * 1024 * 1024).map(|i| (i * 31 % 251) as u8).collect();
+    let small = b"meeting minutes".to_vec();
+    let large_id = insert_blob(&conn, vault, SOURCE_MK, &large, Some("image/png")).unwrap();
+    let small_id = insert_blob(&conn, vault, SOURCE_MK, &small, Some("text/plain")).unwrap();
+
+    let note = create_note(
+        &conn,
+        &space_id,
+        "Quarterly plan",
+        &format!(
+            "See ![diagram](blob:{}) and [notes](blob:{})",
+            large_id, small_id
+        ),
+    )
+    .unwrap();
+    let note_id = note.id.to_string();
+    attach_blob_to_note(&conn, &note_id, &large_id, Some("diagram.png")).unwrap();
+    attach_blob_to_note(&conn, &note_id, &small_id, Some("minutes.txt")).unwrap();
+
+    let path = dir.path().join("plan.noteece");
+    export_encrypted_note_bundle(&conn, vault, SOURCE_MK, &note_id, passphrase, &path).unwrap();
+    (dir, path, vec![large, small])
+}
+
+#[test]
+fn test_bundle_round_trip_with_attachments() {
+    let (_source, path, contents) = export_sample("open sesame");
+    let (dir, conn, space_id) = setup_vault();
+    let vault = dir.path().to_str().unwrap();
+
+    let note =
+        import_encrypted_note_bundle(&conn, vault, TARGET_MK, &space_id, &path, "open sesame")
+            .unwrap();
+    assert_eq!(note.title, "Quarterly plan");
+    assert_eq!(note.space_id, space_id);
+
+    let mut attachments = get_note_attachments(&conn, &note.id.to_string()).unwrap();
+    attachments.sort_by(|a, b| b.size_bytes.cmp(&a.size_bytes));
+    assert_eq!(attachments.len(), 2);
+    assert_eq!(attachments[0].filename.as_deref(), Some("diagram.png"));
+    assert_eq!(attachments[0].mime_type.as_deref(), Some("image/png"));
+    assert_eq!(attachments[1].filename.as_deref(), Some("minutes.txt"));
+    for (attachment, content) in attachments.iter().zip(&contents) {
+        assert_eq!(
+            &retrieve_blob(vault, TARGET_MK, &attachment.blob_id).unwrap(),
+            content
+        );
+        assert!(note
+            .content_md
+            .contains(&format!("blob:{}", attachment.blob_id)));
+    }
+}
+
+#[test]
+fn test_wrong_passphrase_is_rejected_without_changes() {
+    let (_source, path, _) = export_sample("right");
+    let (dir, conn, space_id) = setup_vault();
+    let vault = dir.path().to_str().unwrap();
+
+    let result = import_encrypted_note_bundle(&conn, vault, TARGET_MK, &space_id, &path, "wrong");
+    assert!(matches!(result, Err(NoteBundleError::WrongPassphrase)));
+    assert_eq!(note_count(&conn), 0);
+    let blobs: i64 = conn
+        .query_row("SELECT COUNT(*) FROM blob", [], |row| row.get(0))
+        .unwrap();
+    assert_eq!(blobs, 0);
+}
+
+#[test]
+fn test_bundle_leaks_no_plaintext_or_vault_key() {
+    let (_source, path, contents) = export_sample("pw");
+    let bundle = std::fs::read(&path).unwrap();
+
+    let contains = |needle: &[u8]| bundle.windows(needle.len()).any(|w| w == needle);
+    assert!(!contains(b"Quarterly plan"));
+    assert!(!contains(b"meeting minutes"));
+    assert!(!contains(b"diagram.png"));
+    assert!(!contains(SOURCE_MK));
+    assert!(!contains(&contents[0][..64]));
+}
+
+#[test]
+fn test_tampered_and_foreign_files_are_rejected() {
+    let (source, path, _) = export_sample("pw");
+    let (dir, conn, space_id) = setup_vault();
+    let vault = dir.path().to_str().unwrap();
+    let import = |path: &std::path::Path| {
+        import_encrypted_note_bundle(&conn, vault, TARGET_MK, &space_id, path, "pw")
+    };
+    let bundle = std::fs::read(&path).unwrap();
+
+    // Changing the KDF cost in the header breaks authentication
+    let mut tampered = bundle.clone();
+    tampered[13] ^= 1;
+    let tampered_path = source.path().join("tampered.noteece");
+    std::fs::write(&tampered_path, &tampered).unwrap();
+    assert!(matches!(
+        import(&tampered_path),
+        Err(NoteBundleError::WrongPassphrase)
+    ));
+
+    let mut flipped = bundle.clone();
+    let last = flipped.len() - 1;
+    flipped[last] ^= 0xff;
+    std::fs::write(&tampered_path, &flipped).unwrap();
+    assert!(matches!(
+        import(&tampered_path),
+        Err(NoteBundleError::WrongPassphrase)
+    ));
+
+    // Costs past the bounds are refused before deriving the key
+    for offset in [9, 13, 17] {
+        let mut costly = bundle.clone();
+        costly[offset..offset + 4].copy_from_slice(&u32::MAX.to_le_bytes());
+        std::fs::write(&tampered_path, &costly).unwrap();
+        assert!(matches!(
+            import(&tampered_path),
+            Err(NoteBundleError::Corrupt(_))
+        ));
+    }
+
+    let mut future = bundle;
+    future[8] = 99;
+    std::fs::write(&tampered_path, &future).unwrap();
+    assert!(matches!(
+        import(&tampered_path),
+        Err(NoteBundleError::UnsupportedVersion(99))
+    ));
+
+    let foreign = source.path().join("notes.zip");
+    std::fs::write(&foreign, b"PK\x03\x04 definitely a zip").unwrap();
+    assert!(matches!(import(&foreign), Err(NoteBundleError::NotABundle)));
+    assert_eq!(note_count(&conn), 0);
+}
+
+#[test]
+fn test_export_requires_passphrase_and_note() {
+    let (dir, conn, space_id) = setup_vault();
+    let vault = dir.path().to_str().unwrap();
+    let note = create_note(&conn, &space_id, "Draft", "").unwrap();
+    let path = dir.path().join("draft.noteece");
+
+    assert!(matches!(
+        export_encrypted_note_bundle(&conn, vault, SOURCE_MK, &note.id.to_string(), "", &path),
+        Err(NoteBundleError::EmptyPassphrase)
+    ));
+    let missing = ulid::Ulid::new().to_string();
+    assert!(matches!(
+        export_encrypted_note_bundle(&conn, vault, SOURCE_MK, &missing, "pw", &path),
+        Err(NoteBundleError::NotFound(_))
+    ));
+    assert!(!path.exists());
+}
+
+#[test]
+fn test_locked_notes_are_not_exported() {
+    let (dir, mut conn, space_id) = setup_vault();
+    let vault = dir.path().to_str().unwrap();
+    let note = create_note(&conn, &space_id, "Diary", "Dear diary").unwrap();
+    let note_id = note.id.to_string();
+    lock_note(&mut conn, SOURCE_MK, &note_id, "hunter2").unwrap();
+    let path = dir.path().join("diary.noteece");
+
+    assert!(matches!(
+        export_encrypted_note_bundle(&conn, vault, SOURCE_MK, &note_id, "pw", &path),
+        Err(NoteBundleError::NoteLocked(id)) if id == note_id
+    ));
+    assert!(!path.exists());
+}
+
+#[test]
+fn test_entries_inflating_past_their_size_are_rejected() {
+    let (dir, conn, space_id) = setup_vault();
+    let vault = dir.path().to_str().unwrap();
+    let path = dir.path().join("bomb.noteece");
+    let manifest = |size_bytes: i64| {
+        serde_json::json!({
+            "title": "Bomb",
+            "content_md": "",
+            "exported_at": 0,
+            "attachments": [{
+                "blob_id": "b",
+                "filename": null,
+                "mime_type": null,
+                "size_bytes": size_bytes,
+            }],
+        })
+        .to_string()
+    };
+    // Compresses to a few KiB, far less than it unpacks to
+    let zeros = vec![0u8; 8 * 1024 * 1024];
+
+    write_bundle(
+        &path,
+        "pw",
+        &[
+            ("note.json", manifest(16).as_bytes()),
+            ("attachments/0", &zeros),
+        ],
+    );
+    let result = import_encrypted_note_bundle(&conn, vault, TARGET_MK, &space_id, &path, "pw");
+    assert!(matches!(result, Err(NoteBundleError::Corrupt(_))));
+
+    // A stated size past the import limit is refused before reading
+    write_bundle(
+        &path,
+        "pw",
+        &[
+            ("note.json", manifest(1 << 40).as_bytes()),
+            ("attachments/0", b"small"),
+        ],
+    );
+    let result = import_encrypted_note_bundle(&conn, vault, TARGET_MK, &space_id, &path, "pw");
+    assert!(matches!(result, Err(NoteBundleError::Corrupt(_))));
+    assert_eq!(note_count(&conn), 0);
+}
@@ -0,0 +1,281 @@
+use core_rs::db::migrate;
+use core_rs::note::create_note;
+use core_rs::note_dedup::*;
+use core_rs::sync_agent::SyncAgent;
+use core_rs::tag::add_tag_to_note;
+use core_rs::undo::{list_undoable_operations, undo_operation};
+use rusqlite::Connection;
+use ulid::Ulid;
+
+const DEK: [u8; 32] = [0u8; 32];
+
+const LECTURE: &str = "The borrow checker makes sure every reference points at live data. \
+    A value has exactly one owner and is dropped when the owner goes out of scope. \
+    Moving a value transfers ownership, while borrowing lends it for a while.";
+
+fn setup() -> (Connection, String) {
+    let mut conn = Connection::open_in_memory().unwrap();
+    conn.pragma_update(None, "foreign_keys", "ON").unwrap();
+    migrate(&mut conn).unwrap();
+    let space_id = core_rs::space::create_space(&mut conn, "Notes")
+        .unwrap()
+        .to_string();
+    (conn, space_id)
+}
+
+/// A note created `age` seconds ago
+fn note(conn: &Connection, space_id: &str, title: &str, content: &str, age: i64) -> String {
+    let id = create_note(conn, space_id, title, content)
+        .unwrap()
+        .id
+        .0
+        .to_string();
+    conn.execute(
+        "UPDATE note SET created_at = created_at - ?1, modified_at = modified_at - ?1 WHERE id = ?2",
+        (age, &id),
+    )
+    .unwrap();
+    id
+}
+
+fn tags(conn: &Connection, note_id: &str) -> Vec<String> {
+    let mut stmt = conn
+        .prepare(
+            "SELECT t.name FROM note_tags nt JOIN tag t ON t.id = nt.tag_id
+             WHERE nt.note_id = ?1 ORDER BY t.name",
+        )
+        .unwrap();
+    let rows = stmt.query_map([note_id], |row| row.get(0)).unwrap();
+    rows.collect::<Result<_, _>>().unwrap()
+}
+
+fn links_to(conn: &Connection, target: &str) -> Vec<String> {
+    let mut stmt = conn
+        .prepare("SELECT source_note_id FROM link WHERE target_note_id = ?1")
+        .unwrap();
+    let rows = stmt.query_map([target], |row| row.get(0)).unwrap();
+    rows.collect::<Result<_, _>>().unwrap()
+}
+
+fn field<T: rusqlite::types::FromSql>(conn: &Connection, note_id: &str, column: &str) -> T {
+    conn.query_row(
+        &format!("SELECT {} FROM note WHERE id = ?1", column),
+        [note_id],
+        |row| row.get(0),
+    )
+    .unwrap()
+}
+
+#[test]
+fn test_exact_duplicates_are_clustered() {
+    let (conn, space_id) = setup();
+    let original = note(&conn, &space_id, "Rust ownership", LECTURE, 300);
+    let copy = note(&conn, &space_id, "Rust ownership", LECTURE, 200);
+    note(
+        &conn,
+        &space_id,
+        "Groceries",
+        "Eggs, flour, butter and a bag of oranges for the weekend.",
+        100,
+    );
+
+    let clusters = find_duplicate_notes(&conn, &space_id, DEFAULT_DUPLICATE_THRESHOLD).unwrap();
+    assert_eq!(clusters.len(), 1);
+    assert_eq!(clusters[0].similarity, 1.0);
+    let ids = clusters[0]
+        .notes
+        .iter()
+        .map(|n| n.id.clone())
+        .collect::<Vec<_>>();
+    assert_eq!(ids, vec![original.clone(), copy.clone()]);
+
+    // Signatures are cached, and recomputed once a body changes
+    let cached = |id: &str| -> i64 {
+        conn.query_row(
+            "SELECT content_hash FROM note_minhash WHERE note_id = ?1",
+            [id],
+            |row| row.get(0),
+        )
+        .unwrap()
+    };
+    let before = cached(&copy);
+    conn.execute(
+        "UPDATE note SET content_md = 'Something else entirely' WHERE id = ?1",
+        [&copy],
+    )
+    .unwrap();
+    assert!(
+        find_duplicate_notes(&conn, &space_id, DEFAULT_DUPLICATE_THRESHOLD)
+            .unwrap()
+            .is_empty()
+    );
+    assert_ne!(cached(&copy), before);
+
+    assert!(matches!(
+        find_duplicate_notes(&conn, &space_id, 0.0),
+        Err(NoteDedupError::InvalidThreshold(_))
+    ));
+}
+
+#[test]
+fn test_near_duplicates_below_threshold_are_not_clustered() {
+    let (conn, space_id) = setup();
+    let original = note(&conn, &space_id, "Rust ownership", LECTURE, 300);
+    // One word changed
+    let edited = note(
+        &conn,
+        &space_id,
+        "Rust ownership",
+        &LECTURE.replace("live data", "valid data"),
+        200,
+    );
+    // Shares its opening with the lecture and little else
+    note(
+        &conn,
+        &space_id,
+        "Rust borrowing",
+        "The borrow checker makes sure every reference points at live data. \
+         Lifetimes are usually inferred, and annotations only name relationships \
+         the compiler could not work out on its own from the signature.",
+        100,
+    );
+
+    let clusters = find_duplicate_notes(&conn, &space_id, DEFAULT_DUPLICATE_THRESHOLD).unwrap();
+    assert_eq!(clusters.len(), 1);
+    let ids = clusters[0]
+        .notes
+        .iter()
+        .map(|n| n.id.clone())
+        .collect::<Vec<_>>();
+    assert_eq!(ids, vec![original, edited]);
+    assert!(clusters[0].similarity >= DEFAULT_DUPLICATE_THRESHOLD);
+    assert!(clusters[0].similarity < 1.0);
+}
+
+#[test]
+fn test_merge_carries_backlink_and_tag_and_can_be_undone() {
+    let (mut conn, space_id) = setup();
+    let primary = note(&conn, &space_id, "Rust ownership", LECTURE, 300);
+    let duplicate = note(
+        &conn,
+        &space_id,
+        "Ownership in Rust",
+        &format!("{}\n\nClone makes a deep copy when one is needed.", LECTURE),
+        200,
+    );
+    let index = note(
+        &conn,
+        &space_id,
+        "Index",
+        "Start with [[Ownership in Rust|ownership]].",
+        100,
+    );
+    add_tag_to_note(&conn, &duplicate, "rust").unwrap();
+    add_tag_to_note(&conn, &primary, "lecture").unwrap();
+    assert_eq!(links_to(&conn, &duplicate), vec![index.clone()]);
+
+    let summary = merge_notes(
+        &mut conn,
+        &primary,
+        &[duplicate.clone()],
+        MergeStrategy::AppendDiffering,
+    )
+    .unwrap();
+    assert_eq!(summary.appended_sections, 1);
+    assert_eq!(summary.relinked_notes, 1);
+    assert_eq!(summary.tags_added, 1);
+
+    assert!(
+        field::<String>(&conn, &primary, "content_md").ends_with("deep copy when one is needed.\n")
+    );
+    assert_eq!(tags(&conn, &primary), vec!["lecture", "rust"]);
+    assert!(tags(&conn, &duplicate).is_empty());
+    assert_eq!(links_to(&conn, &primary), vec![index.clone()]);
+    assert!(links_to(&conn, &duplicate).is_empty());
+    assert_eq!(
+        field::<String>(&conn, &index, "content_md"),
+        "Start with [[Rust ownership|ownership]]."
+    );
+    assert!(field::<bool>(&conn, &duplicate, "is_trashed"));
+    // Trashed notes can't be merged again
+    assert!(matches!(
+        merge_notes(
+            &mut conn,
+            &primary,
+            &[duplicate.clone()],
+            MergeStrategy::KeepPrimary
+        ),
+        Err(NoteDedupError::InvalidMerge(_))
+    ));
+
+    let op = list_undoable_operations(&conn, 10).unwrap().remove(0);
+    assert_eq!(op.operation, "merge_notes");
+    undo_operation(&conn, &op.id).unwrap();
+    assert_eq!(field::<String>(&conn, &primary, "content_md"), LECTURE);
+    assert_eq!(tags(&conn, &primary), vec!["lecture"]);
+    assert_eq!(tags(&conn, &duplicate), vec!["rust"]);
+    assert!(!field::<bool>(&conn, &duplicate, "is_trashed"));
+    assert_eq!(
+        field::<String>(&conn, &index, "content_md"),
+        "Start with [[Ownership in Rust|ownership]]."
+    );
+    assert_eq!(links_to(&conn, &duplicate), vec![index]);
+    assert!(links_to(&conn, &primary).is_empty());
+    let merges: i64 = conn
+        .query_row("SELECT COUNT(*) FROM note_merge", [], |row| row.get(0))
+        .unwrap();
+    assert_eq!(merges, 0);
+}
+
+#[test]
+fn test_merge_converges_through_sync() {
+    let space_id = Ulid::new();
+    let open = || {
+        let mut conn = Connection::open_in_memory().unwrap();
+        migrate(&mut conn).unwrap();
+        core_rs::sync_agent::init_sync_tables(&conn).unwrap();
+        conn.execute(
+            "INSERT INTO space (id, name) VALUES (?1, ?2)",
+            (space_id.to_string(), "Notes"),
+        )
+        .unwrap();
+        conn
+    };
+    let (mut desktop, mut phone) = (open(), open());
+    let desktop_agent = SyncAgent::new("desktop".into(), "Desktop".into(), 0);
+    let phone_agent = SyncAgent::new("phone".into(), "Phone".into(), 0);
+
+    let space = space_id.to_string();
+    let primary = note(&desktop, &space, "Rust ownership", LECTURE, 300);
+    let duplicate = note(&desktop, &space, "Rust ownership", LECTURE, 200);
+    let deltas = desktop_agent
+        .get_deltas_since(&desktop, space_id, 0)
+        .unwrap();
+    phone_agent.apply_deltas(&mut phone, deltas, &DEK).unwrap();
+    add_tag_to_note(&phone, &duplicate, "rust").unwrap();
+
+    let since = chrono::Utc::now().timestamp() - 60;
+    merge_notes(
+        &mut desktop,
+        &primary,
+        &[duplicate.clone()],
+        MergeStrategy::KeepPrimary,
+    )
+    .unwrap();
+    let deltas = desktop_agent
+        .get_deltas_since(&desktop, space_id, since)
+        .unwrap();
+    assert!(deltas
+        .iter()
+        .any(|d| d.entity_type == NOTE_MERGE_ENTITY_TYPE));
+    phone_agent
+        .apply_deltas(&mut phone, deltas.clone(), &DEK)
+        .unwrap();
+
+    assert!(field::<bool>(&phone, &duplicate, "is_trashed"));
+    assert_eq!(tags(&phone, &primary), vec!["rust"]);
+    // A merge received twice is applied once
+    add_tag_to_note(&phone, &duplicate, "again").unwrap();
+    phone_agent.apply_deltas(&mut phone, deltas, &DEK).unwrap();
+    assert_eq!(tags(&phone, &duplicate), vec!["again"]);
+}
@@ -0,0 +1,251 @@
+use core_rs::db::migrate;
+use core_rs::note::{create_note, get_note, update_note_content};
+use core_rs::note_lock::*;
+use core_rs::search::{
+    search_all, search_notes, EntityType, SearchFilters, SearchQuery, SortOptions,
+};
+use core_rs::space::create_space;
+use core_rs::sync_agent::{init_sync_tables, SyncAgent};
+use rusqlite::Connection;
+use ulid::Ulid;
+
+const DEK: [u8; 32] = [7u8; 32];
+const PASSPHRASE: &str = "correct horse battery staple";
+
+fn setup_db() -> (Connection, String) {
+    let mut conn = Connection::open_in_memory().unwrap();
+    migrate(&mut conn).unwrap();
+    let space_id = create_space(&mut conn, "Private").unwrap().to_string();
+    (conn, space_id)
+}
+
+fn search(conn: &Connection, text: &str) -> Vec<String> {
+    let query = SearchQuery {
+        query: text.to_string(),
+        entity_types: vec![EntityType::All],
+        filters: SearchFilters::default(),
+        sort: SortOptions::default(),
+        limit: None,
+        offset: None,
+    };
+    search_all(conn, &query)
+        .unwrap()
+        .into_iter()
+        .map(|r| r.entity_id)
+        .collect()
+}
+
+fn fts_rows(conn: &Connection, note_id: &str) -> i64 {
+    conn.query_row(
+        "SELECT COUNT(*) FROM fts_note WHERE note_id = ?1",
+        [note_id],
+        |row| row.get(0),
+    )
+    .unwrap()
+}
+
+fn stored_content(conn: &Connection, note_id: &str) -> String {
+    conn.query_row(
+        "SELECT content_md FROM note WHERE id = ?1",
+        [note_id],
+        |row| row.get(0),
+    )
+    .unwrap()
+}
+
+#[test]
+fn test_locked_note_is_hidden_from_readers_and_search() {
+    let (mut conn, space_id) = setup_db();
+    let note = create_note(&conn, &space_id, "Diary", "met the accountant about taxes").unwrap();
+    let id = note.id.to_string();
+    assert_eq!(search(&conn, "accountant"), [id.clone()]);
+
+    lock_note(&mut conn, &DEK, &id, PASSPHRASE).unwrap();
+
+    let locked = get_note(&conn, note.id.clone()).unwrap().unwrap();
+    assert!(locked.is_locked);
+    assert_eq!(locked.content_md, "");
+    assert_eq!(locked.title, "Diary");
+    assert!(!stored_content(&conn, &id).contains("accountant"));
+    assert_eq!(fts_rows(&conn, &id), 0);
+
+    assert!(search(&conn, "accountant").is_empty());
+    assert!(search(&conn, "diary").is_empty());
+    assert!(search_notes(&conn, "accountant", &space_id)
+        .unwrap()
+        .is_empty());
+
+    // Reading doesn't unlock it
+    let content = unlock_note_content(&conn, &DEK, &id, PASSPHRASE).unwrap();
+    assert_eq!(content.as_str(), "met the accountant about taxes");
+    assert!(get_note(&conn, note.id.clone()).unwrap().unwrap().is_locked);
+
+    // Nor can it be overwritten or locked twice
+    assert!(update_note_content(&mut conn, note.id.clone(), "Diary", "oops").is_err());
+    assert!(matches!(
+        lock_note(&mut conn, &DEK, &id, "another"),
+        Err(NoteLockError::AlreadyLocked(_))
+    ));
+}
+
+#[test]
+fn test_wrong_passphrases_are_throttled() {
+    let (mut conn, space_id) = setup_db();
+    let note = create_note(&conn, &space_id, "Codes", "1234").unwrap();
+    let id = note.id.to_string();
+    assert!(matches!(
+        lock_note(&mut conn, &DEK, &id, ""),
+        Err(NoteLockError::EmptyPassphrase)
+    ));
+    lock_note(&mut conn, &DEK, &id, PASSPHRASE).unwrap();
+
+    for _ in 0..5 {
+        assert!(matches!(
+            unlock_note_content(&conn, &DEK, &id, "guess"),
+            Err(NoteLockError::WrongPassphrase)
+        ));
+    }
+    // Locked out, even with the right passphrase
+    assert!(matches!(
+        unlock_note_content(&conn, &DEK, &id, PASSPHRASE),
+        Err(NoteLockError::LockedOut(secs)) if secs > 0
+    ));
+
+    conn.execute("UPDATE auth_attempt SET locked_until = 0", [])
+        .unwrap();
+    assert_eq!(
+        unlock_note_content(&conn, &DEK, &id, PASSPHRASE)
+            .unwrap()
+            .as_str(),
+        "1234"
+    );
+    let failures: i64 = conn
+        .query_row("SELECT COUNT(*) FROM auth_attempt", [], |row| row.get(0))
+        .unwrap();
+    assert_eq!(failures, 0);
+}
+
+#[test]
+fn test_envelope_the_vault_key_cannot_open_is_corrupt() {
+    let (mut conn, space_id) = setup_db();
+    let note = create_note(&conn, &space_id, "Codes", "1234").unwrap();
+    let id = note.id.to_string();
+    lock_note(&mut conn, &DEK, &id, PASSPHRASE).unwrap();
+
+    // Not a passphrase problem, so it doesn't count towards the lockout
+    for _ in 0..6 {
+        assert!(matches!(
+            unlock_note_content(&conn, &[8u8; 32], &id, PASSPHRASE),
+            Err(NoteLockError::Corrupt(_))
+        ));
+    }
+    assert_eq!(
+        unlock_note_content(&conn, &DEK, &id, PASSPHRASE)
+            .unwrap()
+            .as_str(),
+        "1234"
+    );
+}
+
+#[test]
+fn test_permanent_unlock_restores_the_note() {
+    let (mut conn, space_id) = setup_db();
+    let note = create_note(&conn, &space_id, "Plans", "surprise party on friday").unwrap();
+    let id = note.id.to_string();
+    lock_note(&mut conn, &DEK, &id, PASSPHRASE).unwrap();
+
+    assert!(matches!(
+        permanently_unlock_note(&mut conn, &DEK, &id, "wrong"),
+        Err(NoteLockError::WrongPassphrase)
+    ));
+    let unlocked = permanently_unlock_note(&mut conn, &DEK, &id, PASSPHRASE).unwrap();
+    assert!(!unlocked.is_locked);
+    assert_eq!(unlocked.content_md, "surprise party on friday");
+    assert_eq!(stored_content(&conn, &id), "surprise party on friday");
+    assert_eq!(fts_rows(&conn, &id), 1);
+    assert_eq!(search(&conn, "party"), [id.clone()]);
+
+    assert!(matches!(
+        unlock_note_content(&conn, &DEK, &id, PASSPHRASE),
+        Err(NoteLockError::NotLocked(_))
+    ));
+    // Editable again
+    update_note_content(&mut conn, note.id.clone(), "Plans", "party moved").unwrap();
+}
+
+#[test]
+fn test_locked_note_syncs_and_unlocks_on_another_device() {
+    let space_id = Ulid::new();
+    let setup = || {
+        let mut conn = Connection::open_in_memory().unwrap();
+        migrate(&mut conn).unwrap();
+        init_sync_tables(&conn).unwrap();
+        conn.execute(
+            "INSERT INTO space (id, name) VALUES (?1, 'Shared')",
+            [space_id.to_string()],
+        )
+        .unwrap();
+        conn
+    };
+    let phone = SyncAgent::new("phone".into(), "Phone".into(), 0);
+    let desktop = SyncAgent::new("desktop".into(), "Desktop".into(), 0);
+    let mut phone_conn = setup();
+    let mut desktop_conn = setup();
+
+    let note = create_note(
+        &phone_conn,
+        &space_id.to_string(),
+        "Bank",
+        "account 12-3456",
+    )
+    .unwrap();
+    let id = note.id.to_string();
+    lock_note(&mut phone_conn, &DEK, &id, PASSPHRASE).unwrap();
+
+    // The body travels as the opaque envelope, never as a CRDT update
+    let deltas = phone
+        .get_deltas_for_peer(&phone_conn, space_id, 0, &desktop.get_device_info())
+        .unwrap();
+    let delta = deltas.iter().find(|d| d.entity_id == id).unwrap();
+    assert_eq!(delta.entity_type, "note");
+    let sent = String::from_utf8(delta.data.clone().unwrap()).unwrap();
+    assert!(is_locked_content(&sent));
+    assert!(!sent.contains("12-3456"));
+
+    let conflicts = desktop
+        .apply_deltas(&mut desktop_conn, deltas, &DEK)
+        .unwrap();
+    assert!(conflicts.is_empty());
+    let synced = get_note(&desktop_conn, note.id.clone()).unwrap().unwrap();
+    assert!(synced.is_locked);
+    assert_eq!(synced.content_md, "");
+    assert_eq!(fts_rows(&desktop_conn, &id), 0);
+    assert!(matches!(
+        unlock_note_content(&desktop_conn, &DEK, &id, "guess"),
+        Err(NoteLockError::WrongPassphrase)
+    ));
+    assert_eq!(
+        unlock_note_content(&desktop_conn, &DEK, &id, PASSPHRASE)
+            .unwrap()
+            .as_str(),
+        "account 12-3456"
+    );
+
+    // Unlocking on the desktop a minute after the phone locked it carries
+    // back to the phone
+    phone_conn
+        .execute(
+            "UPDATE note SET modified_at = modified_at - 60 WHERE id = ?1",
+            [&id],
+        )
+        .unwrap();
+    permanently_unlock_note(&mut desktop_conn, &DEK, &id, PASSPHRASE).unwrap();
+    let deltas = desktop
+        .get_deltas_for_peer(&desktop_conn, space_id, 0, &phone.get_device_info())
+        .unwrap();
+    let conflicts = phone.apply_deltas(&mut phone_conn, deltas, &DEK).unwrap();
+    assert!(conflicts.is_empty());
+    let unlocked = get_note(&phone_conn, note.id.clone()).unwrap().unwrap();
+    assert!(!unlocked.is_locked);
+    assert_eq!(unlocked.content_md, "account 12-3456");
+}
@@ -0,0 +1,192 @@
+use core_rs::db::{migrate, save_settings, DbError};
+use core_rs::note::{create_note, get_note, update_note_content, DbUlid};
+use core_rs::note_lock::lock_note;
+use core_rs::note_session::*;
+use core_rs::space::create_space;
+use core_rs::sync::delta_gatherer::DeltaGatherer;
+use rusqlite::Connection;
+use ulid::Ulid;
+
+fn setup() -> (Connection, Ulid) {
+    let mut conn = Connection::open_in_memory().unwrap();
+    conn.pragma_update(None, "foreign_keys", "ON").unwrap();
+    migrate(&mut conn).unwrap();
+    let space_id = create_space(&mut conn, "Writing").unwrap();
+    (conn, space_id)
+}
+
+fn modified_at(conn: &Connection, note_id: &str) -> i64 {
+    get_note(conn, DbUlid(Ulid::from_string(note_id).unwrap()))
+        .unwrap()
+        .unwrap()
+        .modified_at
+}
+
+fn fts_matches(conn: &Connection, word: &str) -> i64 {
+    conn.query_row(
+        "SELECT COUNT(*) FROM fts_note WHERE fts_note MATCH ?1",
+        [word],
+        |row| row.get(0),
+    )
+    .unwrap()
+}
+
+#[test]
+fn test_rapid_saves_make_one_version_and_delta_per_interval() {
+    let (conn, space_id) = setup();
+    let note = create_note(&conn, &space_id.to_string(), "Essay", "").unwrap();
+    let note_id = note.id.0.to_string();
+    let start = note.modified_at;
+    let session = begin_edit_session_at(&conn, &note_id, start).unwrap();
+    assert_eq!(session.interval_secs, 60);
+
+    // A peer syncing after every save only sees the flushed ones
+    let mut synced_at = start;
+    let mut deltas = 0;
+    let mut versions = 0;
+    let mut body = String::new();
+    for i in 1..=50 {
+        body.push_str(&format!("word{} ", i));
+        let now = start + i * 5;
+        if save_note_draft_at(&conn, &session.id, &body, now)
+            .unwrap()
+            .is_some()
+        {
+            versions += 1;
+        }
+        let gathered = DeltaGatherer::get_deltas_since(&conn, space_id, synced_at).unwrap();
+        if let Some(latest) = gathered.iter().map(|d| d.timestamp).max() {
+            deltas += 1;
+            synced_at = latest;
+        }
+    }
+    // Saves run 5s apart for 250s, flushing at 60, 120, 180 and 240
+    assert_eq!(versions, 4);
+    assert_eq!(deltas, 4);
+    assert_eq!(modified_at(&conn, &note_id), start + 240);
+    assert_eq!(fts_matches(&conn, "word50"), 1);
+
+    let last = end_edit_session_at(&conn, &session.id, start + 251)
+        .unwrap()
+        .unwrap();
+    assert_eq!(last.content_md, body);
+    let gathered = DeltaGatherer::get_deltas_since(&conn, space_id, synced_at).unwrap();
+    assert_eq!(gathered.len(), 1);
+    assert_eq!(gathered[0].data.as_deref(), Some(body.as_bytes()));
+    assert_eq!(gathered[0].timestamp, start + 251);
+
+    let history = get_note_versions(&conn, &note_id).unwrap();
+    assert_eq!(history.len(), 5);
+    assert_eq!(history[0], last);
+    assert!(history
+        .iter()
+        .all(|v| v.session_id == Some(session.id.to_string())));
+    assert!(history[4].content_md.ends_with("word12 "));
+}
+
+#[test]
+fn test_saves_within_one_interval_are_versioned_at_the_end() {
+    let (conn, space_id) = setup();
+    let note = create_note(&conn, &space_id.to_string(), "Draft", "first").unwrap();
+    let note_id = note.id.0.to_string();
+    let start = note.modified_at;
+    let session = begin_edit_session_at(&conn, &note_id, start).unwrap();
+
+    for i in 1..=50 {
+        let content = format!("second take {}", i);
+        assert!(save_note_draft_at(&conn, &session.id, &content, start + i)
+            .unwrap()
+            .is_none());
+    }
+    // The body and search follow every draft, the timestamp doesn't
+    let current = get_note(&conn, note.id.clone()).unwrap().unwrap();
+    assert_eq!(current.content_md, "second take 50");
+    assert_eq!(current.modified_at, start);
+    assert_eq!(fts_matches(&conn, "second"), 1);
+    assert_eq!(fts_matches(&conn, "first"), 0);
+    assert!(DeltaGatherer::get_deltas_since(&conn, space_id, start)
+        .unwrap()
+        .is_empty());
+
+    end_edit_session_at(&conn, &session.id, start + 55).unwrap();
+    let history = get_note_versions(&conn, &note_id).unwrap();
+    assert_eq!(history.len(), 1);
+    assert_eq!(history[0].content_md, "second take 50");
+    assert_eq!(modified_at(&conn, &note_id), start + 55);
+    assert!(matches!(
+        save_note_draft_at(&conn, &session.id, "late", start + 56),
+        Err(DbError::NotFound { .. })
+    ));
+}
+
+#[test]
+fn test_sessions_and_plain_saves() {
+    let (mut conn, space_id) = setup();
+    save_settings(
+        &conn,
+        &EditorSettings {
+            version_interval_secs: 10,
+        },
+    )
+    .unwrap();
+    let note = create_note(&conn, &space_id.to_string(), "Notes", "").unwrap();
+    let note_id = note.id.0.to_string();
+    let start = note.modified_at;
+
+    // A session left open is ended by the next one
+    let abandoned = begin_edit_session_at(&conn, &note_id, start).unwrap();
+    assert_eq!(abandoned.interval_secs, 10);
+    save_note_draft_at(&conn, &abandoned.id, "unsaved", start + 1).unwrap();
+    let session = begin_edit_session_at(&conn, &note_id, start + 2).unwrap();
+    let history = get_note_versions(&conn, &note_id).unwrap();
+    assert_eq!(history.len(), 1);
+    assert_eq!(history[0].content_md, "unsaved");
+    assert!(end_edit_session_at(&conn, &abandoned.id, start + 3).is_err());
+
+    // Nothing saved in a session, nothing to version
+    assert!(end_edit_session_at(&conn, &session.id, start + 3)
+        .unwrap()
+        .is_none());
+
+    // Saves outside a session aren't versioned
+    update_note_content(&mut conn, note.id.clone(), "Notes", "plain save").unwrap();
+    assert_eq!(get_note_versions(&conn, &note_id).unwrap().len(), 1);
+
+    assert!(matches!(
+        begin_edit_session(&conn, &Ulid::new().to_string()),
+        Err(DbError::NotFound { .. })
+    ));
+}
+
+#[test]
+fn test_locking_a_note_discards_its_versions() {
+    let (mut conn, space_id) = setup();
+    let note = create_note(&conn, &space_id.to_string(), "Diary", "").unwrap();
+    let note_id = note.id.0.to_string();
+    let start = note.modified_at;
+
+    let session = begin_edit_session_at(&conn, &note_id, start).unwrap();
+    save_note_draft_at(&conn, &session.id, "the combination is 1234", start + 1).unwrap();
+    end_edit_session_at(&conn, &session.id, start + 2).unwrap();
+    let open = begin_edit_session_at(&conn, &note_id, start + 3).unwrap();
+    assert_eq!(get_note_versions(&conn, &note_id).unwrap().len(), 1);
+
+    lock_note(&mut conn, &[7u8; 32], &note_id, "hunter2").unwrap();
+    assert!(matches!(
+        get_note_versions(&conn, &note_id),
+        Err(DbError::Locked { .. })
+    ));
+    let stored: i64 = conn
+        .query_row(
+            "SELECT COUNT(*) FROM note_version WHERE content_md LIKE '%1234%'",
+            [],
+            |row| row.get(0),
+        )
+        .unwrap();
+    assert_eq!(stored, 0);
+    // The session open at lock time is gone with them
+    assert!(matches!(
+        end_edit_session_at(&conn, &open.id, start + 4),
+        Err(DbError::NotFound { .. })
+    ));
+}
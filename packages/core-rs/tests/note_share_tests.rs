@@ -0,0 +1,306 @@
+use core_rs::backlink::find_backlinks;
+use core_rs::db::migrate;
+use core_rs::note::{create_note, get_all_notes_in_space, get_recent_notes};
+use core_rs::note_share::*;
+use core_rs::search::{search_all, EntityType, SearchFilters, SearchQuery, SortOptions};
+use core_rs::sync::delta_applier::DeltaApplier;
+use core_rs::sync_agent::SyncAgent;
+use core_rs::tag::{add_tag_to_note, create_tag};
+use core_rs::time_tracking::{create_manual_time_entry, CreateManualEntryParams};
+use rusqlite::Connection;
+use ulid::Ulid;
+
+const DEK: [u8; 32] = [0u8; 32];
+
+fn setup() -> (Connection, String, String) {
+    let mut conn = Connection::open_in_memory().unwrap();
+    conn.pragma_update(None, "foreign_keys", "ON").unwrap();
+    migrate(&mut conn).unwrap();
+    let work = core_rs::space::create_space(&mut conn, "Work").unwrap();
+    let home = core_rs::space::create_space(&mut conn, "Home").unwrap();
+    (conn, work.to_string(), home.to_string())
+}
+
+fn note(conn: &Connection, space_id: &str, title: &str, content: &str) -> String {
+    create_note(conn, space_id, title, content)
+        .unwrap()
+        .id
+        .0
+        .to_string()
+}
+
+fn links_to(conn: &Connection, note_id: &str) -> Vec<String> {
+    let mut sources: Vec<String> = find_backlinks(conn, Ulid::from_string(note_id).unwrap())
+        .unwrap()
+        .into_iter()
+        .map(|b| b.source_note_id.to_string())
+        .collect();
+    sources.sort();
+    sources
+}
+
+fn space_of(conn: &Connection, note_id: &str) -> String {
+    conn.query_row(
+        "SELECT space_id FROM note WHERE id = ?1",
+        [note_id],
+        |row| row.get(0),
+    )
+    .unwrap()
+}
+
+/// Tag names of a note with the space each tag belongs to
+fn tags(conn: &Connection, note_id: &str) -> Vec<(String, String)> {
+    let mut stmt = conn
+        .prepare(
+            "SELECT t.name, t.space_id FROM note_tags nt JOIN tag t ON t.id = nt.tag_id
+             WHERE nt.note_id = ?1 ORDER BY t.name",
+        )
+        .unwrap();
+    let rows = stmt
+        .query_map([note_id], |row| Ok((row.get(0)?, row.get(1)?)))
+        .unwrap();
+    rows.collect::<Result<_, _>>().unwrap()
+}
+
+#[test]
+fn test_linked_pair_moved_together_keeps_links() {
+    let (mut conn, work, home) = setup();
+    let alpha = note(&conn, &work, "Alpha", "See [[Beta]]");
+    let beta = note(&conn, &work, "Beta", "Back to [[Alpha]]");
+    let options = MoveNoteOptions {
+        with_notes: vec![beta.clone()],
+        ..Default::default()
+    };
+
+    let report = move_note_to_space(&mut conn, &alpha, &home, &options).unwrap();
+
+    assert_eq!(report.note_ids, vec![alpha.clone(), beta.clone()]);
+    assert_eq!(report.links_preserved, 2);
+    assert_eq!(report.links_dropped, 0);
+    assert_eq!(space_of(&conn, &alpha), home);
+    assert_eq!(space_of(&conn, &beta), home);
+    assert_eq!(links_to(&conn, &alpha), vec![beta.clone()]);
+    assert_eq!(links_to(&conn, &beta), vec![alpha]);
+}
+
+#[test]
+fn test_moving_one_of_a_linked_pair_drops_links() {
+    let (mut conn, work, home) = setup();
+    let alpha = note(&conn, &work, "Alpha", "See [[Beta]]");
+    let beta = note(&conn, &work, "Beta", "Back to [[Alpha]]");
+
+    let report = move_note_to_space(&mut conn, &alpha, &home, &MoveNoteOptions::default()).unwrap();
+    // Titles only resolve within a space
+    assert_eq!(report.links_preserved, 0);
+    assert_eq!(report.links_dropped, 2);
+    assert!(links_to(&conn, &alpha).is_empty());
+    assert!(links_to(&conn, &beta).is_empty());
+
+    // Moving the other one after it links them up again
+    let report = move_note_to_space(&mut conn, &beta, &home, &MoveNoteOptions::default()).unwrap();
+    assert_eq!(report.links_added, 1);
+    assert_eq!(links_to(&conn, &alpha), vec![beta.clone()]);
+    assert_eq!(links_to(&conn, &beta), vec![alpha.clone()]);
+
+    assert!(matches!(
+        move_note_to_space(&mut conn, &alpha, &home, &MoveNoteOptions::default()),
+        Err(NoteShareError::InvalidMove(_))
+    ));
+    assert!(matches!(
+        move_note_to_space(&mut conn, &alpha, "missing", &MoveNoteOptions::default()),
+        Err(NoteShareError::NotFound {
+            entity: "space",
+            ..
+        })
+    ));
+}
+
+#[test]
+fn test_tags_are_mapped_and_time_entries_follow_options() {
+    let (mut conn, work, home) = setup();
+    create_tag(&conn, &home, "projects", None).unwrap();
+    let plan = note(&conn, &work, "Plan", "");
+    add_tag_to_note(&conn, &plan, "projects").unwrap();
+    add_tag_to_note(&conn, &plan, "ideas").unwrap();
+    let entry = |note_id: &str| {
+        create_manual_time_entry(
+            &conn,
+            CreateManualEntryParams {
+                space_id: Ulid::from_string(&work).unwrap(),
+                task_id: None,
+                project_id: None,
+                note_id: Some(Ulid::from_string(note_id).unwrap()),
+                description: None,
+                started_at: 1_700_000_000,
+                duration_seconds: 1800,
+            },
+        )
+        .unwrap()
+        .id
+        .to_string()
+    };
+    let plan_entry = entry(&plan);
+
+    let report = move_note_to_space(&mut conn, &plan, &home, &MoveNoteOptions::default()).unwrap();
+    assert_eq!(report.tags_matched, 1);
+    assert_eq!(report.tags_created, 1);
+    assert_eq!(
+        tags(&conn, &plan),
+        vec![
+            ("ideas".to_string(), home.clone()),
+            ("projects".to_string(), home.clone())
+        ]
+    );
+    // Time entries stay with the original space by default
+    assert_eq!(report.time_entries_kept, 1);
+    let entry_space: String = conn
+        .query_row(
+            "SELECT space_id FROM time_entry WHERE id = ?1",
+            [&plan_entry],
+            |row| row.get(0),
+        )
+        .unwrap();
+    assert_eq!(entry_space, work);
+
+    let draft = note(&conn, &work, "Draft", "");
+    add_tag_to_note(&conn, &draft, "ideas").unwrap();
+    entry(&draft);
+    let options = MoveNoteOptions {
+        tags: TagAction::Drop,
+        time_entries: TimeEntryAction::Move,
+        ..Default::default()
+    };
+    let report = move_note_to_space(&mut conn, &draft, &home, &options).unwrap();
+    assert_eq!(report.tags_dropped, 1);
+    assert_eq!(report.time_entries_moved, 1);
+    assert!(tags(&conn, &draft).is_empty());
+}
+
+#[test]
+fn test_reference_is_visible_in_target_space() {
+    let (conn, work, home) = setup();
+    let recipe = note(&conn, &work, "Sourdough", "Feed the starter at night");
+    let search = |space_id: &str| {
+        let query = SearchQuery {
+            query: "starter".to_string(),
+            entity_types: vec![EntityType::All],
+            filters: SearchFilters {
+                space_id: Some(Ulid::from_string(space_id).unwrap()),
+                ..Default::default()
+            },
+            sort: SortOptions::default(),
+            limit: None,
+            offset: None,
+        };
+        search_all(&conn, &query).unwrap()
+    };
+    assert!(search(&home).is_empty());
+
+    let reference = create_note_reference(&conn, &recipe, &home).unwrap();
+    assert_eq!(reference.space_id, home);
+    // Creating it again returns the same reference
+    assert_eq!(
+        create_note_reference(&conn, &recipe, &home).unwrap().id,
+        reference.id
+    );
+
+    let results = search(&home);
+    assert_eq!(results.len(), 1);
+    assert_eq!(results[0].entity_id, recipe);
+    assert_eq!(results[0].metadata["reference"], true);
+    assert_eq!(search(&work)[0].metadata.get("reference"), None);
+
+    // Listed in the target space, still belonging to its own
+    let listed = get_all_notes_in_space(&conn, &home).unwrap();
+    assert_eq!(listed.len(), 1);
+    assert_eq!(listed[0].space_id, work);
+    assert_eq!(get_recent_notes(&conn, &home, 10).unwrap().len(), 1);
+    let count: i64 = conn
+        .query_row("SELECT COUNT(*) FROM note", [], |row| row.get(0))
+        .unwrap();
+    assert_eq!(count, 1);
+    assert_eq!(
+        get_note_references(&conn, &recipe).unwrap(),
+        vec![reference]
+    );
+
+    remove_note_reference(&conn, &recipe, &home).unwrap();
+    assert!(search(&home).is_empty());
+    assert!(get_all_notes_in_space(&conn, &home).unwrap().is_empty());
+    assert!(matches!(
+        remove_note_reference(&conn, &recipe, &home),
+        Err(NoteShareError::NotFound { .. })
+    ));
+    assert!(matches!(
+        create_note_reference(&conn, &recipe, &work),
+        Err(NoteShareError::InvalidReference(_))
+    ));
+}
+
+#[test]
+fn test_move_and_reference_sync_without_duplicating_the_note() {
+    let (work, home) = (Ulid::new(), Ulid::new());
+    let open = || {
+        let mut conn = Connection::open_in_memory().unwrap();
+        migrate(&mut conn).unwrap();
+        core_rs::sync_agent::init_sync_tables(&conn).unwrap();
+        for (id, name) in [(work, "Work"), (home, "Home")] {
+            conn.execute(
+                "INSERT INTO space (id, name) VALUES (?1, ?2)",
+                (id.to_string(), name),
+            )
+            .unwrap();
+        }
+        conn
+    };
+    let (mut desktop, mut phone) = (open(), open());
+    let desktop_agent = SyncAgent::new("desktop".into(), "Desktop".into(), 0);
+    let phone_agent = SyncAgent::new("phone".into(), "Phone".into(), 0);
+
+    let plan = note(&desktop, &work.to_string(), "Plan", "Quarterly plan");
+    let journal = note(&desktop, &work.to_string(), "Journal", "Dear diary");
+    let before_move = desktop_agent.get_deltas_since(&desktop, work, 0).unwrap();
+    phone_agent
+        .apply_deltas(&mut phone, before_move.clone(), &DEK)
+        .unwrap();
+    assert_eq!(space_of(&phone, &plan), work.to_string());
+
+    move_note_to_space(
+        &mut desktop,
+        &plan,
+        &home.to_string(),
+        &MoveNoteOptions::default(),
+    )
+    .unwrap();
+    create_note_reference(&desktop, &journal, &home.to_string()).unwrap();
+    // A phone syncing only the old space learns the note left it
+    let deltas = desktop_agent.get_deltas_since(&desktop, work, 0).unwrap();
+    assert!(deltas
+        .iter()
+        .any(|d| d.entity_type == NOTE_MOVE_ENTITY_TYPE));
+    phone_agent.apply_deltas(&mut phone, deltas, &DEK).unwrap();
+    assert_eq!(space_of(&phone, &plan), home.to_string());
+
+    let deltas = desktop_agent.get_deltas_since(&desktop, home, 0).unwrap();
+    phone_agent.apply_deltas(&mut phone, deltas, &DEK).unwrap();
+    let notes: i64 = phone
+        .query_row("SELECT COUNT(*) FROM note", [], |row| row.get(0))
+        .unwrap();
+    assert_eq!(notes, 2);
+    assert_eq!(space_of(&phone, &journal), work.to_string());
+    let listed: Vec<String> = get_all_notes_in_space(&phone, &home.to_string())
+        .unwrap()
+        .into_iter()
+        .map(|n| n.id.0.to_string())
+        .collect();
+    assert_eq!(listed.len(), 2);
+    assert!(listed.contains(&plan) && listed.contains(&journal));
+
+    // A stale copy of the note under its old space doesn't move it back
+    let stale = before_move
+        .iter()
+        .find(|d| d.entity_id == plan && d.entity_type == "note")
+        .unwrap();
+    DeltaApplier::apply_single_delta(&phone, stale, &DEK).unwrap();
+    assert_eq!(space_of(&phone, &plan), home.to_string());
+}
@@ -0,0 +1,158 @@
+use core_rs::analytics::{get_writing_stats, ActivityOptions, DailyWriting};
+use core_rs::calendar::TimeRange;
+use core_rs::db::migrate;
+use core_rs::note::{create_note, update_note_content};
+use core_rs::note_stats::*;
+use rusqlite::Connection;
+
+/// 2024-03-04 00:00 UTC, a Monday
+const MONDAY: i64 = 1_709_510_400;
+const DAY: i64 = 24 * 60 * 60;
+
+fn setup() -> (Connection, String) {
+    let mut conn = Connection::open_in_memory().unwrap();
+    migrate(&mut conn).unwrap();
+    let space_id = core_rs::space::create_space(&mut conn, "Writing")
+        .unwrap()
+        .to_string();
+    (conn, space_id)
+}
+
+fn words(n: usize) -> String {
+    vec!["word"; n].join(" ")
+}
+
+/// Move the word changes logged since `after` to `at`, as if the saves
+/// had happened then
+fn backdate_saves(conn: &Connection, after: i64, at: i64) {
+    conn.execute(
+        "UPDATE note_word_delta SET recorded_at = ?1 WHERE recorded_at > ?2",
+        [at, after],
+    )
+    .unwrap();
+}
+
+#[test]
+fn test_markdown_aware_counting() {
+    assert_eq!(count_text("").words, 0);
+    assert_eq!(count_text("Hello, world!").words, 2);
+    // Markup on its own is not a word
+    assert_eq!(
+        count_text("# Title\n\n- one\n- two\n\n---\n\n> quoted **bold** text |").words,
+        6
+    );
+    // Links count as their text, images and embeds not at all
+    assert_eq!(
+        count_text("See [the docs](https://example.com/docs) and ![a diagram](d.png)").words,
+        4
+    );
+    assert_eq!(
+        count_text("Ask [[Alice Smith]] or [[people/bob|Bob]] ![[chart.png]]").words,
+        5
+    );
+    // Fenced code is left out, inline code is not
+    let with_code = "Run `cargo test` first.\n\n```rust\nfn main() { println!(\"hi\"); }\n```\n\n~~~\nmore code here\n~~~\nDone.";
+    assert_eq!(count_text(with_code).words, 5);
+    // A longer fence isn't closed by a shorter one
+    assert_eq!(
+        count_text("````\n```\nnot prose\n```\n````\nprose").words,
+        1
+    );
+    // Front matter is not part of the body
+    let with_front_matter = "---\ntitle: A long title here\ntags: [a, b]\n---\nJust three words";
+    assert_eq!(
+        count_text(with_front_matter),
+        TextCounts {
+            words: 3,
+            chars: 16
+        }
+    );
+    // An unclosed block is a rule followed by text
+    assert_eq!(count_text("---\nnot front matter").words, 3);
+}
+
+#[test]
+fn test_counts_follow_saves() {
+    let (mut conn, space_id) = setup();
+    let note = create_note(&conn, &space_id, "Essay", &words(460)).unwrap();
+    let id = note.id.0.to_string();
+    let stats = get_note_stats(&conn, &id).unwrap();
+    assert_eq!(stats.word_count, 460);
+    assert_eq!(stats.char_count, 460 * 5 - 1);
+    assert_eq!(stats.reading_minutes, 2);
+
+    update_note_content(&mut conn, note.id, "Essay", &words(461)).unwrap();
+    let stats = get_note_stats(&conn, &id).unwrap();
+    assert_eq!(stats.word_count, 461);
+    assert_eq!(stats.reading_minutes, 3);
+
+    assert!(matches!(
+        get_note_stats(&conn, "missing"),
+        Err(core_rs::db::DbError::NotFound { entity: "note", .. })
+    ));
+}
+
+#[test]
+fn test_writing_stats_net_words_per_day() {
+    let (mut conn, space_id) = setup();
+
+    // Monday: a draft grows from 100 to 150 words, another note gets 20
+    let draft = create_note(&conn, &space_id, "Draft", &words(100)).unwrap();
+    update_note_content(&mut conn, draft.id.clone(), "Draft", &words(150)).unwrap();
+    create_note(&conn, &space_id, "Ideas", &words(20)).unwrap();
+    backdate_saves(&conn, 0, MONDAY + 9 * 60 * 60);
+
+    // Tuesday: the draft is cut to 120, one word reworded across two saves
+    update_note_content(&mut conn, draft.id.clone(), "Draft", &words(119)).unwrap();
+    update_note_content(&mut conn, draft.id.clone(), "Draft", &words(120)).unwrap();
+    backdate_saves(&conn, MONDAY + DAY, MONDAY + DAY + 10 * 60 * 60);
+
+    // Wednesday: nothing. Thursday: a new note is written and trimmed, and
+    // the draft grows
+    let ideas = create_note(&conn, &space_id, "Ideas 2", &words(10)).unwrap();
+    update_note_content(&mut conn, ideas.id, "Ideas 2", &words(4)).unwrap();
+    update_note_content(&mut conn, draft.id, "Draft", &words(135)).unwrap();
+    backdate_saves(&conn, MONDAY + 2 * DAY, MONDAY + 3 * DAY + 23 * 60 * 60);
+
+    // Other spaces don't count
+    let other = core_rs::space::create_space(&mut conn, "Other")
+        .unwrap()
+        .to_string();
+    create_note(&conn, &other, "Elsewhere", &words(500)).unwrap();
+    backdate_saves(&conn, MONDAY + 4 * DAY, MONDAY + 60);
+
+    let stats = get_writing_stats(
+        &conn,
+        &space_id,
+        TimeRange::new(MONDAY, MONDAY + 4 * DAY),
+        &ActivityOptions::default(),
+    )
+    .unwrap();
+    let day = |offset: i64, words_added: i64, words_removed: i64| DailyWriting {
+        date: chrono::DateTime::from_timestamp(MONDAY + offset * DAY, 0)
+            .unwrap()
+            .date_naive(),
+        words_added,
+        words_removed,
+        net_words: words_added - words_removed,
+    };
+    assert_eq!(
+        stats.days,
+        vec![day(0, 170, 0), day(1, 0, 30), day(2, 0, 0), day(3, 19, 0)]
+    );
+    assert_eq!(stats.net_words, 159);
+
+    // Thursday 23:00 UTC is Friday in UTC+2
+    let stats = get_writing_stats(
+        &conn,
+        &space_id,
+        TimeRange::new(MONDAY, MONDAY + 5 * DAY),
+        &ActivityOptions {
+            utc_offset_minutes: 120,
+            ..Default::default()
+        },
+    )
+    .unwrap();
+    assert_eq!(stats.days[3].net_words, 0);
+    assert_eq!(stats.days[4].net_words, 19);
+}
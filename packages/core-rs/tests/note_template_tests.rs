@@ -0,0 +1,169 @@
+use chrono::{NaiveDate, Utc};
+use core_rs::db::migrate;
+use core_rs::note::get_or_create_daily_note;
+use core_rs::note_template::{
+    create_note_from_template, create_note_template, delete_note_template, render_template,
+    set_daily_note_template, RenderContext, TemplateError, TemplateVariable,
+};
+use rusqlite::Connection;
+use std::collections::HashMap;
+use ulid::Ulid;
+
+fn setup_db() -> (Connection, String) {
+    let mut conn = Connection::open_in_memory().unwrap();
+    migrate(&mut conn).unwrap();
+    let space_id = Ulid::new().to_string();
+    conn.execute(
+        "INSERT INTO space (id, name) VALUES (?1, ?2)",
+        (&space_id, "Work"),
+    )
+    .unwrap();
+    (conn, space_id)
+}
+
+fn context(date: (i32, u32, u32)) -> RenderContext {
+    RenderContext {
+        now: NaiveDate::from_ymd_opt(date.0, date.1, date.2)
+            .unwrap()
+            .and_hms_opt(9, 5, 0)
+            .unwrap(),
+        space_name: "Work".to_string(),
+        values: HashMap::new(),
+    }
+}
+
+fn variable(name: &str, default_value: Option<&str>) -> TemplateVariable {
+    TemplateVariable {
+        name: name.to_string(),
+        prompt: name.to_string(),
+        default_value: default_value.map(str::to_string),
+    }
+}
+
+#[test]
+fn test_render_date_arithmetic() {
+    let ctx = context((2024, 1, 31));
+    let rendered = render_template(
+        "{{date}} {{time}} | {{date+1d}} {{date-31d}} {{date+2w}} {{date+1m}} {{date-1y}}",
+        &[],
+        &ctx,
+    )
+    .unwrap();
+    // Leap year, and month math clamps Jan 31 to Feb 29
+    assert_eq!(
+        rendered.content,
+        "2024-01-31 09:05 | 2024-02-01 2023-12-31 2024-02-14 2024-02-29 2023-01-31"
+    );
+    assert_eq!(rendered.cursor, None);
+
+    for bad in ["{{date+d}}", "{{date+1x}}", "{{date1d}}", "{{date+-1d}}"] {
+        assert!(matches!(
+            render_template(bad, &[], &ctx),
+            Err(TemplateError::UnknownVariable(_))
+        ));
+    }
+}
+
+#[test]
+fn test_render_variables_and_cursor() {
+    let vars = [variable("client", None), variable("agenda", Some("TBD"))];
+    let mut ctx = context((2024, 3, 1));
+
+    assert!(matches!(
+        render_template("Meeting with {{client}}", &vars, &ctx),
+        Err(TemplateError::MissingVariable(name)) if name == "client"
+    ));
+
+    ctx.values.insert("client".into(), "{{date}} Corp".into());
+    let rendered = render_template(
+        "# {{space}}: {{ client }}\n{{agenda}}\n- {{cursor}}",
+        &vars,
+        &ctx,
+    )
+    .unwrap();
+    // Values are not rendered again
+    assert_eq!(rendered.content, "# Work: {{date}} Corp\nTBD\n- ");
+    assert_eq!(rendered.cursor, Some(rendered.content.len()));
+
+    assert!(matches!(
+        render_template("{{unclosed", &vars, &ctx),
+        Err(TemplateError::Malformed(_))
+    ));
+}
+
+#[test]
+fn test_create_note_from_template() {
+    let (conn, space_id) = setup_db();
+
+    assert!(matches!(
+        create_note_template(&conn, &space_id, "Bad", "{{who}}", vec![]),
+        Err(TemplateError::UnknownVariable(_))
+    ));
+    assert!(matches!(
+        create_note_template(&conn, &space_id, "Bad", "", vec![variable("date", None)]),
+        Err(TemplateError::InvalidVariableName(_))
+    ));
+
+    let template = create_note_template(
+        &conn,
+        &space_id,
+        "Meeting",
+        "# Meeting with {{client}}\n\n{{cursor}}",
+        vec![variable("client", None)],
+    )
+    .unwrap();
+
+    let missing = create_note_from_template(&conn, &template.id, HashMap::new());
+    assert!(matches!(missing, Err(TemplateError::MissingVariable(_))));
+
+    let overrides = HashMap::from([("client".to_string(), "Acme".to_string())]);
+    let created = create_note_from_template(&conn, &template.id, overrides).unwrap();
+    assert_eq!(created.note.title, "Meeting with Acme");
+    assert_eq!(created.note.content_md, "# Meeting with Acme\n\n");
+    assert_eq!(created.cursor, Some(created.note.content_md.len()));
+}
+
+#[test]
+fn test_daily_note_template_override() {
+    let (conn, space_id) = setup_db();
+    let template = create_note_template(
+        &conn,
+        &space_id,
+        "Daily",
+        "Plan for {{date}} in {{space}}\nTomorrow: {{date+1d}}",
+        vec![],
+    )
+    .unwrap();
+    set_daily_note_template(&conn, &space_id, Some(&template.id)).unwrap();
+
+    let today = Utc::now().date_naive();
+    let note = get_or_create_daily_note(&conn, &space_id).unwrap();
+    assert_eq!(
+        note.title,
+        format!("Daily Note - {}", today.format("%Y-%m-%d"))
+    );
+    assert_eq!(
+        note.content_md,
+        format!(
+            "Plan for {} in Work\nTomorrow: {}",
+            today.format("%Y-%m-%d"),
+            today.succ_opt().unwrap().format("%Y-%m-%d")
+        )
+    );
+
+    // A deleted template falls back to the built-in layout
+    let (conn, space_id) = setup_db();
+    let template = create_note_template(&conn, &space_id, "Daily", "x", vec![]).unwrap();
+    set_daily_note_template(&conn, &space_id, Some(&template.id)).unwrap();
+    delete_note_template(&conn, &template.id).unwrap();
+    let note = get_or_create_daily_note(&conn, &space_id).unwrap();
+    assert!(note.content_md.starts_with("# Daily Note - "));
+
+    // Templates from another space are rejected
+    let template = create_note_template(&conn, &space_id, "Daily", "y", vec![]).unwrap();
+    let other_space = Ulid::new().to_string();
+    assert!(matches!(
+        set_daily_note_template(&conn, &other_space, Some(&template.id)),
+        Err(TemplateError::NotFound(_))
+    ));
+}
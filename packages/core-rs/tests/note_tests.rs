@@ -1,5 +1,10 @@
-use core_rs::db::migrate;
-use core_rs::note::{create_note, get_note, restore_note, trash_note, update_note_content, DbUlid};
+use chrono::NaiveDate;
+use core_rs::db::{migrate, migrate_to, migrations::latest_version};
+use core_rs::note::{
+    create_note, get_daily_notes_in_range, get_note, get_or_create_daily_note_for_date,
+    restore_note, trash_note, update_note_content, DbUlid,
+};
+use core_rs::note_template::{create_note_template, set_daily_note_template};
 use rusqlite::Connection;
 use std::thread;
 use std::time::Duration;
@@ -111,3 +116,124 @@ fn test_restore_non_existent_note() {
     // This should not return an error, as the note does not exist.
     assert!(result.is_ok());
 }
+
+fn date(y: i32, m: u32, d: u32) -> NaiveDate {
+    NaiveDate::from_ymd_opt(y, m, d).unwrap()
+}
+
+#[test]
+fn test_daily_note_for_a_past_date() {
+    let conn = setup_db();
+    let space_id = create_space(&conn).to_string();
+
+    let note = get_or_create_daily_note_for_date(&conn, &space_id, date(2023, 11, 5)).unwrap();
+    assert_eq!(note.title, "Daily Note - 2023-11-05");
+    assert!(note.content_md.starts_with("# Daily Note - 2023-11-05\n"));
+
+    // The daily template is rendered for the note's day
+    let template =
+        create_note_template(&conn, &space_id, "Daily", "Plan for {{date}}", vec![]).unwrap();
+    set_daily_note_template(&conn, &space_id, Some(&template.id)).unwrap();
+    let note = get_or_create_daily_note_for_date(&conn, &space_id, date(2023, 11, 6)).unwrap();
+    assert_eq!(note.content_md, "Plan for 2023-11-06");
+}
+
+#[test]
+fn test_daily_note_is_created_once_per_date() {
+    let mut conn = setup_db();
+    let space_id = create_space(&conn).to_string();
+    let day = date(2024, 6, 1);
+
+    let first = get_or_create_daily_note_for_date(&conn, &space_id, day).unwrap();
+    let again = get_or_create_daily_note_for_date(&conn, &space_id, day).unwrap();
+    assert_eq!(first.id.0, again.id.0);
+    let other_space = create_space(&conn).to_string();
+    let elsewhere = get_or_create_daily_note_for_date(&conn, &other_space, day).unwrap();
+    assert_ne!(first.id.0, elsewhere.id.0);
+
+    // Renaming doesn't change which note is the day's
+    update_note_content(&mut conn, first.id.clone(), "June 1st", "Picnic").unwrap();
+    let renamed = get_or_create_daily_note_for_date(&conn, &space_id, day).unwrap();
+    assert_eq!(renamed.id.0, first.id.0);
+
+    // A trashed daily note is replaced by a fresh one
+    trash_note(&conn, first.id.clone()).unwrap();
+    let fresh = get_or_create_daily_note_for_date(&conn, &space_id, day).unwrap();
+    assert_ne!(fresh.id.0, first.id.0);
+    restore_note(&conn, first.id.clone()).unwrap();
+    let current = get_or_create_daily_note_for_date(&conn, &space_id, day).unwrap();
+    assert_eq!(current.id.0, fresh.id.0);
+
+    // A note made by hand with the daily title becomes the day's note
+    let manual = create_note(&conn, &space_id, "Daily Note - 2024-06-02", "Written early").unwrap();
+    let adopted = get_or_create_daily_note_for_date(&conn, &space_id, date(2024, 6, 2)).unwrap();
+    assert_eq!(adopted.id.0, manual.id.0);
+    assert_eq!(adopted.content_md, "Written early");
+}
+
+#[test]
+fn test_daily_notes_in_range_across_months() {
+    let conn = setup_db();
+    let space_id = create_space(&conn).to_string();
+    let mut ids = Vec::new();
+    for day in [
+        date(2024, 1, 30),
+        date(2024, 1, 31),
+        date(2024, 2, 1),
+        date(2024, 2, 29),
+        date(2024, 3, 1),
+    ] {
+        let note = get_or_create_daily_note_for_date(&conn, &space_id, day).unwrap();
+        ids.push(note.id.to_string());
+    }
+    let other_space = create_space(&conn).to_string();
+    get_or_create_daily_note_for_date(&conn, &other_space, date(2024, 2, 10)).unwrap();
+    create_note(&conn, &space_id, "Not a daily note", "").unwrap();
+
+    let notes =
+        get_daily_notes_in_range(&conn, &space_id, date(2024, 1, 31), date(2024, 2, 29)).unwrap();
+    let days: Vec<&str> = notes.keys().map(String::as_str).collect();
+    assert_eq!(days, ["2024-01-31", "2024-02-01", "2024-02-29"]);
+    assert_eq!(notes["2024-01-31"], ids[1]);
+    assert_eq!(notes["2024-02-29"], ids[3]);
+
+    // Trashed notes leave their day empty
+    let feb_first = DbUlid(ids[2].parse().unwrap());
+    trash_note(&conn, feb_first).unwrap();
+    let notes =
+        get_daily_notes_in_range(&conn, &space_id, date(2024, 2, 1), date(2024, 3, 31)).unwrap();
+    assert_eq!(
+        notes.keys().collect::<Vec<_>>(),
+        ["2024-02-29", "2024-03-01"]
+    );
+
+    assert!(
+        get_daily_notes_in_range(&conn, &space_id, date(2024, 2, 1), date(2024, 1, 1)).is_err()
+    );
+}
+
+#[test]
+fn test_existing_daily_notes_are_tagged_by_migration() {
+    let dir = tempfile::tempdir().unwrap();
+    let mut conn = Connection::open(dir.path().join("vault.db")).unwrap();
+    migrate(&mut conn).unwrap();
+    migrate_to(&mut conn, 51, &dir.path().join("before-downgrade.db")).unwrap();
+    let space_id = create_space(&conn).to_string();
+    let older = create_note(&conn, &space_id, "Daily Note - 2022-12-31", "Old").unwrap();
+    create_note(&conn, &space_id, "Daily Note - 2022-12-31", "Duplicate").unwrap();
+    create_note(&conn, &space_id, "Daily Note - 2022-02-30", "Not a date").unwrap();
+    create_note(&conn, &space_id, "Daily Note - 2023-01-01 (copy)", "").unwrap();
+
+    migrate_to(
+        &mut conn,
+        latest_version(),
+        &dir.path().join("before-upgrade.db"),
+    )
+    .unwrap();
+    let notes =
+        get_daily_notes_in_range(&conn, &space_id, date(2022, 1, 1), date(2023, 12, 31)).unwrap();
+    assert_eq!(notes.len(), 1);
+    assert_eq!(notes["2022-12-31"], older.id.to_string());
+    let note = get_or_create_daily_note_for_date(&conn, &space_id, date(2022, 12, 31)).unwrap();
+    assert_eq!(note.content_md, "Old");
+}
@@ -36,7 +36,7 @@ fn test_queue_ocr_job() {
     conn.execute("INSERT INTO blob (id) VALUES (?1)", [&blob_id])
         .unwrap();
 
-    let id = queue_ocr(&conn, &blob_id).expect("Failed to queue OCR result");
+    let id = queue_ocr(&conn, &blob_id, &[]).expect("Failed to queue OCR result");
 
     let result = get_ocr_status(&conn, &blob_id).unwrap().unwrap();
     assert_eq!(result.id, id);
@@ -51,7 +51,7 @@ fn test_update_ocr_status_flow() {
     let blob_id = Ulid::new().to_string();
     conn.execute("INSERT INTO blob (id) VALUES (?1)", [&blob_id])
         .unwrap();
-    queue_ocr(&conn, &blob_id).unwrap();
+    queue_ocr(&conn, &blob_id, &[]).unwrap();
 
     // Simulate Processing
     conn.execute(
@@ -83,7 +83,7 @@ fn test_search_ocr_text() {
     let blob_id = Ulid::new().to_string();
     conn.execute("INSERT INTO blob (id) VALUES (?1)", [&blob_id])
         .unwrap();
-    queue_ocr(&conn, &blob_id).unwrap();
+    queue_ocr(&conn, &blob_id, &[]).unwrap();
 
     // Manually complete it
     let text = "The quick brown fox jumps over the lazy dog";
@@ -93,11 +93,11 @@ fn test_search_ocr_text() {
     )
     .unwrap();
 
-    let results = search_ocr_text(&conn, "fox", 10).unwrap();
+    let results = search_ocr_text(&conn, "fox", None, 10).unwrap();
     assert_eq!(results.len(), 1);
     assert_eq!(results[0].blob_id, blob_id);
 
-    let empty_results = search_ocr_text(&conn, "zebra", 10).unwrap();
+    let empty_results = search_ocr_text(&conn, "zebra", None, 10).unwrap();
     assert_eq!(empty_results.len(), 0);
 }
 
@@ -121,3 +121,484 @@ fn test_security_validation_inputs() {
         panic!("Should have failed validation");
     }
 }
+
+// --- Background worker ---
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+
+use core_rs::vault::AutoLockGuard;
+
+const TEST_MK: &[u8] = b"test-master-key-that-is-32-bytes";
+
+fn test_guard() -> Arc<AutoLockGuard> {
+    Arc::new(AutoLockGuard::new(TEST_MK.try_into().unwrap()))
+}
+
+/// Engine stub that records calls and fails a configurable number of times.
+#[derive(Default)]
+struct StubEngine {
+    calls: Mutex<HashMap<String, usize>>,
+    failures_remaining: AtomicUsize,
+    total_calls: AtomicUsize,
+    /// Language hints of each call, in order
+    languages: Mutex<Vec<Vec<String>>>,
+    /// Image each call was given, in order
+    images: Mutex<Vec<PathBuf>>,
+}
+
+impl OcrEngine for StubEngine {
+    fn recognize(&self, image_path: &Path, languages: &[String]) -> Result<OcrOutput, OcrError> {
+        self.total_calls.fetch_add(1, Ordering::SeqCst);
+        self.languages.lock().unwrap().push(languages.to_vec());
+        self.images.lock().unwrap().push(image_path.to_path_buf());
+        let content = std::fs::read_to_string(image_path)?;
+        *self
+            .calls
+            .lock()
+            .unwrap()
+            .entry(content.clone())
+            .or_default() += 1;
+        std::thread::sleep(std::time::Duration::from_millis(5));
+
+        if self
+            .failures_remaining
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |n| n.checked_sub(1))
+            .is_ok()
+        {
+            return Err(OcrError::Processing("engine crashed".to_string()));
+        }
+        Ok(OcrOutput {
+            text: format!("text of {} [{}]", content, languages.join("+")),
+            language: languages.first().cloned(),
+            confidence: Some(0.9),
+        })
+    }
+}
+
+fn setup_worker_env() -> (
+    tempfile::TempDir,
+    r2d2::Pool<r2d2_sqlite::SqliteConnectionManager>,
+    OcrWorkerConfig,
+) {
+    let dir = tempfile::tempdir().unwrap();
+    let manager = r2d2_sqlite::SqliteConnectionManager::file(dir.path().join("ocr.db"));
+    let pool = r2d2::Pool::builder().max_size(4).build(manager).unwrap();
+    {
+        let conn = pool.get().unwrap();
+        conn.execute("CREATE TABLE blob (id TEXT PRIMARY KEY)", [])
+            .unwrap();
+        init_ocr_tables(&conn).unwrap();
+    }
+    let mut config = OcrWorkerConfig::new(dir.path(), test_guard());
+    config.retry_backoff_secs = 0;
+    config.poll_interval = std::time::Duration::from_millis(10);
+    (dir, pool, config)
+}
+
+fn store_and_queue(
+    pool: &r2d2::Pool<r2d2_sqlite::SqliteConnectionManager>,
+    vault: &Path,
+    content: &str,
+    priority: i32,
+) -> String {
+    let blob_id =
+        core_rs::blob::store_blob(vault.to_str().unwrap(), TEST_MK, content.as_bytes()).unwrap();
+    let conn = pool.get().unwrap();
+    conn.execute("INSERT INTO blob (id) VALUES (?1)", [&blob_id])
+        .unwrap();
+    queue_ocr_with_priority(&conn, &blob_id, &[], priority).unwrap();
+    blob_id
+}
+
+#[test]
+fn test_worker_processes_by_priority_and_writes_text() {
+    let (dir, pool, config) = setup_worker_env();
+    let low = store_and_queue(&pool, dir.path(), "low", 0);
+    let high = store_and_queue(&pool, dir.path(), "high", 5);
+
+    let engine = Arc::new(StubEngine::default());
+    let worker = OcrWorker::new(pool.clone(), engine, config);
+    assert_eq!(worker.queue_depth().unwrap(), 2);
+
+    let (job, outcome) = worker.process_next().unwrap().unwrap();
+    assert_eq!(job.blob_id, high);
+    assert_eq!(outcome, OcrJobOutcome::Completed);
+
+    let (job, _) = worker.process_next().unwrap().unwrap();
+    assert_eq!(job.blob_id, low);
+    assert!(worker.process_next().unwrap().is_none());
+    assert_eq!(worker.queue_depth().unwrap(), 0);
+
+    let conn = pool.get().unwrap();
+    let results = search_ocr_text(&conn, "text of high", None, 10).unwrap();
+    assert_eq!(results.len(), 1);
+    assert_eq!(results[0].blob_id, high);
+}
+
+#[test]
+fn test_worker_retries_then_fails() {
+    let (dir, pool, config) = setup_worker_env();
+    let blob_id = store_and_queue(&pool, dir.path(), "flaky", 0);
+
+    let engine = Arc::new(StubEngine::default());
+    engine.failures_remaining.store(10, Ordering::SeqCst);
+    let worker = OcrWorker::new(pool.clone(), engine.clone(), config);
+
+    let (_, first) = worker.process_next().unwrap().unwrap();
+    assert!(matches!(first, OcrJobOutcome::Retrying { .. }));
+    {
+        let conn = pool.get().unwrap();
+        let status = get_ocr_status(&conn, &blob_id).unwrap().unwrap();
+        assert_eq!(status.status, OcrStatus::Pending);
+        assert_eq!(
+            status.error_message.as_deref(),
+            Some("OCR processing error: engine crashed")
+        );
+    }
+
+    let (_, second) = worker.process_next().unwrap().unwrap();
+    assert!(matches!(second, OcrJobOutcome::Retrying { .. }));
+    let (job, third) = worker.process_next().unwrap().unwrap();
+    assert_eq!(job.attempts, 3);
+    assert_eq!(third, OcrJobOutcome::Failed);
+    assert!(worker.process_next().unwrap().is_none());
+    assert_eq!(engine.total_calls.load(Ordering::SeqCst), 3);
+
+    let conn = pool.get().unwrap();
+    let status = get_ocr_status(&conn, &blob_id).unwrap().unwrap();
+    assert_eq!(status.status, OcrStatus::Failed);
+}
+
+#[test]
+fn test_worker_recovers_after_transient_failure() {
+    let (dir, pool, config) = setup_worker_env();
+    let blob_id = store_and_queue(&pool, dir.path(), "transient", 0);
+
+    let engine = Arc::new(StubEngine::default());
+    engine.failures_remaining.store(1, Ordering::SeqCst);
+    let worker = OcrWorker::new(pool.clone(), engine, config);
+
+    worker.process_next().unwrap().unwrap();
+    let (_, outcome) = worker.process_next().unwrap().unwrap();
+    assert_eq!(outcome, OcrJobOutcome::Completed);
+
+    let conn = pool.get().unwrap();
+    let status = get_ocr_status(&conn, &blob_id).unwrap().unwrap();
+    assert_eq!(status.status, OcrStatus::Completed);
+    assert!(status.error_message.is_none());
+}
+
+#[test]
+fn test_locked_vault_leaves_the_worker_without_a_key() {
+    let (dir, pool, config) = setup_worker_env();
+    let blob_id = store_and_queue(&pool, dir.path(), "after lock", 0);
+    let guard = config.auto_lock.clone();
+
+    let engine = Arc::new(StubEngine::default());
+    let worker = OcrWorker::new(pool.clone(), engine.clone(), config);
+    assert!(guard.lock());
+
+    assert!(guard.dek().is_err(), "the guard held the only copy");
+    assert!(worker.process_next().unwrap().is_none());
+    assert_eq!(engine.total_calls.load(Ordering::SeqCst), 0);
+
+    let conn = pool.get().unwrap();
+    let status = get_ocr_status(&conn, &blob_id).unwrap().unwrap();
+    assert_eq!(status.status, OcrStatus::Pending);
+    assert!(status.error_message.is_none());
+}
+
+#[test]
+fn test_backoff_delays_retry() {
+    let (dir, pool, mut config) = setup_worker_env();
+    config.retry_backoff_secs = 3600;
+    store_and_queue(&pool, dir.path(), "later", 0);
+
+    let engine = Arc::new(StubEngine::default());
+    engine.failures_remaining.store(1, Ordering::SeqCst);
+    let worker = OcrWorker::new(pool, engine, config);
+
+    let (_, outcome) = worker.process_next().unwrap().unwrap();
+    assert!(matches!(outcome, OcrJobOutcome::Retrying { .. }));
+    // Still queued, but not eligible until the backoff elapses
+    assert_eq!(worker.queue_depth().unwrap(), 1);
+    assert!(worker.process_next().unwrap().is_none());
+}
+
+#[test]
+fn test_decrypted_image_is_removed_after_recognition() {
+    let (dir, pool, config) = setup_worker_env();
+    store_and_queue(&pool, dir.path(), "private", 0);
+
+    let engine = Arc::new(StubEngine::default());
+    let worker = OcrWorker::new(pool, engine.clone(), config);
+    worker.process_next().unwrap().unwrap();
+
+    let images = engine.images.lock().unwrap();
+    assert_eq!(images.len(), 1);
+    assert!(!images[0].exists());
+    assert!(!images[0].parent().unwrap().exists());
+}
+
+#[test]
+fn test_job_left_processing_is_claimed_again_after_its_lease() {
+    let (dir, pool, _config) = setup_worker_env();
+    let blob_id = store_and_queue(&pool, dir.path(), "interrupted", 0);
+    let conn = pool.get().unwrap();
+
+    let job = claim_next_ocr_job(&conn).unwrap().unwrap();
+    assert_eq!(job.attempts, 1);
+    let status = get_ocr_status(&conn, &blob_id).unwrap().unwrap();
+    assert_eq!(status.status, OcrStatus::Processing);
+    // Someone is still working on it
+    assert!(claim_next_ocr_job(&conn).unwrap().is_none());
+
+    // The worker died without finishing and its lease ran out
+    conn.execute(
+        "UPDATE ocr_result SET next_attempt_at = ?1 WHERE id = ?2",
+        rusqlite::params![chrono::Utc::now().timestamp() - 1, &job.id],
+    )
+    .unwrap();
+    let again = claim_next_ocr_job(&conn).unwrap().unwrap();
+    assert_eq!(again.id, job.id);
+    assert_eq!(again.attempts, 2);
+}
+
+#[test]
+fn test_concurrent_workers_do_not_double_process() {
+    let (dir, pool, mut config) = setup_worker_env();
+    config.concurrency = 3;
+    for i in 0..12 {
+        store_and_queue(&pool, dir.path(), &format!("doc-{}", i), 0);
+    }
+
+    let engine = Arc::new(StubEngine::default());
+    let mut worker_a = OcrWorker::new(pool.clone(), engine.clone(), config.clone());
+    let mut worker_b = OcrWorker::new(pool.clone(), engine.clone(), config);
+    worker_a.start();
+    worker_b.start();
+    assert!(worker_a.is_running());
+
+    let deadline = std::time::Instant::now() + std::time::Duration::from_secs(20);
+    while worker_a.queue_depth().unwrap() > 0 && std::time::Instant::now() < deadline {
+        std::thread::sleep(std::time::Duration::from_millis(20));
+    }
+    worker_a.stop();
+    worker_b.stop();
+    assert!(!worker_a.is_running());
+
+    let calls = engine.calls.lock().unwrap();
+    assert_eq!(calls.len(), 12);
+    assert!(calls.values().all(|&n| n == 1));
+
+    let conn = pool.get().unwrap();
+    let completed: i64 = conn
+        .query_row(
+            "SELECT COUNT(*) FROM ocr_result WHERE status = 'completed'",
+            [],
+            |row| row.get(0),
+        )
+        .unwrap();
+    assert_eq!(completed, 12);
+}
+
+// --- Languages ---
+
+fn langs(tags: &[&str]) -> Vec<String> {
+    tags.iter().map(|t| t.to_string()).collect()
+}
+
+#[test]
+fn test_language_tags() {
+    assert_eq!(validate_language_tag(" de-AT ").unwrap(), "de-AT");
+    for bad in ["", "d", "german", "de_AT", "eng; rm -rf /", "de--AT"] {
+        assert!(
+            matches!(
+                validate_language_tag(bad),
+                Err(OcrError::InvalidLanguage(_))
+            ),
+            "{:?} should be rejected",
+            bad
+        );
+    }
+
+    assert_eq!(tesseract_language_code("de").unwrap(), "deu");
+    assert_eq!(tesseract_language_code("en-GB").unwrap(), "eng");
+    assert_eq!(tesseract_language_code("zh-Hant").unwrap(), "chi_tra");
+    assert_eq!(tesseract_language_code("zh-CN").unwrap(), "chi_sim");
+    assert_eq!(tesseract_language_code("fra").unwrap(), "fra");
+    assert!(tesseract_language_code("xx").is_err());
+
+    let hints = langs(&["en", "de"]);
+    assert_eq!(
+        detect_language("Summe der Rechnung ist nicht bezahlt", &hints).as_deref(),
+        Some("de")
+    );
+    assert_eq!(
+        detect_language("The total is due with this invoice", &hints).as_deref(),
+        Some("en")
+    );
+    assert_eq!(detect_language("12,50 EUR", &hints), None);
+    assert_eq!(
+        detect_language("12,50 EUR", &langs(&["de"])).as_deref(),
+        Some("de")
+    );
+}
+
+#[test]
+fn test_queue_persists_language_hints() {
+    let conn = setup_db();
+    let blob_id = Ulid::new().to_string();
+    conn.execute("INSERT INTO blob (id) VALUES (?1)", [&blob_id])
+        .unwrap();
+
+    queue_ocr(&conn, &blob_id, &langs(&["de", "en", "DE"])).unwrap();
+    let status = get_ocr_status(&conn, &blob_id).unwrap().unwrap();
+    assert_eq!(status.languages, langs(&["de", "en"]));
+    assert!(status.detected_language.is_none());
+
+    assert!(matches!(
+        queue_ocr(&conn, &blob_id, &langs(&["deu+eng"])),
+        Err(OcrError::InvalidLanguage(_))
+    ));
+    assert!(matches!(
+        reprocess_ocr(&conn, "missing", &langs(&["de"])),
+        Err(OcrError::NotFound(_))
+    ));
+}
+
+#[test]
+fn test_worker_passes_hints_and_stores_detected_language() {
+    let (dir, pool, mut config) = setup_worker_env();
+    config.languages = langs(&["fr"]);
+    let receipt = store_and_queue(&pool, dir.path(), "receipt", 0);
+    let scan = store_and_queue(&pool, dir.path(), "scan", 1);
+    queue_ocr(&pool.get().unwrap(), &receipt, &langs(&["de", "en"])).unwrap();
+
+    let engine = Arc::new(StubEngine::default());
+    let worker = OcrWorker::new(pool.clone(), engine.clone(), config);
+    while worker.process_next().unwrap().is_some() {}
+    // Jobs without hints of their own fall back to the worker's languages
+    assert_eq!(
+        *engine.languages.lock().unwrap(),
+        vec![langs(&["fr"]), langs(&["de", "en"])]
+    );
+
+    let conn = pool.get().unwrap();
+    let status = get_ocr_status(&conn, &receipt).unwrap().unwrap();
+    assert_eq!(status.status, OcrStatus::Completed);
+    assert_eq!(status.languages, langs(&["de", "en"]));
+    assert_eq!(status.detected_language.as_deref(), Some("de"));
+    assert_eq!(status.confidence, Some(0.9));
+    let status = get_ocr_status(&conn, &scan).unwrap().unwrap();
+    assert!(status.languages.is_empty());
+    assert_eq!(status.detected_language.as_deref(), Some("fr"));
+
+    let german = search_ocr_text(&conn, "text of", Some("de"), 10).unwrap();
+    assert_eq!(german.len(), 1);
+    assert_eq!(german[0].blob_id, receipt);
+    assert_eq!(
+        search_ocr_text(&conn, "text of", Some("FR"), 10).unwrap()[0].blob_id,
+        scan
+    );
+    assert!(search_ocr_text(&conn, "text of", Some("en"), 10)
+        .unwrap()
+        .is_empty());
+}
+
+#[test]
+fn test_space_default_languages_apply_to_unhinted_jobs() {
+    let mut conn = Connection::open_in_memory().unwrap();
+    core_rs::db::migrate(&mut conn).unwrap();
+    let space_id = core_rs::space::create_space(&mut conn, "Receipts")
+        .unwrap()
+        .to_string();
+    let note = core_rs::note::create_note(&conn, &space_id, "March", "").unwrap();
+    let blob_id = Ulid::new().to_string();
+    core_rs::blob::register_blob(&conn, &blob_id, 10, Some("image/png")).unwrap();
+    core_rs::blob::attach_blob_to_note(&conn, &note.id.to_string(), &blob_id, None).unwrap();
+
+    assert!(get_space_ocr_languages(&conn, &space_id)
+        .unwrap()
+        .is_empty());
+    set_space_ocr_languages(&conn, &space_id, &langs(&["de", "en"])).unwrap();
+    assert_eq!(
+        get_space_ocr_languages(&conn, &space_id).unwrap(),
+        langs(&["de", "en"])
+    );
+    assert!(set_space_ocr_languages(&conn, &space_id, &langs(&["german"])).is_err());
+
+    queue_ocr(&conn, &blob_id, &[]).unwrap();
+    assert_eq!(
+        resolve_ocr_languages(&conn, &blob_id, &langs(&["fr"])).unwrap(),
+        langs(&["de", "en"])
+    );
+
+    let dir = tempfile::tempdir().unwrap();
+    let image = dir.path().join("receipt.png");
+    std::fs::write(&image, "receipt").unwrap();
+    let engine = StubEngine::default();
+    let text = process_ocr_job(&conn, &engine, &blob_id, &image, &[]).unwrap();
+    assert_eq!(text, "text of receipt [de+en]");
+    assert_eq!(
+        *engine.languages.lock().unwrap(),
+        vec![langs(&["de", "en"])]
+    );
+
+    // The job's own hints win over the space default
+    queue_ocr(&conn, &blob_id, &langs(&["it"])).unwrap();
+    assert_eq!(
+        resolve_ocr_languages(&conn, &blob_id, &[]).unwrap(),
+        langs(&["it"])
+    );
+}
+
+#[test]
+fn test_reprocess_replaces_text_in_result_and_index() {
+    let conn = setup_db();
+    let blob_id = Ulid::new().to_string();
+    conn.execute("INSERT INTO blob (id) VALUES (?1)", [&blob_id])
+        .unwrap();
+    let dir = tempfile::tempdir().unwrap();
+    let image = dir.path().join("receipt.png");
+    std::fs::write(&image, "receipt").unwrap();
+    let engine = StubEngine::default();
+    let indexed = |conn: &Connection| -> Vec<String> {
+        let mut stmt = conn
+            .prepare("SELECT content FROM fts_ocr WHERE blob_id = ?1")
+            .unwrap();
+        stmt.query_map([&blob_id], |row| row.get(0))
+            .unwrap()
+            .collect::<Result<_, _>>()
+            .unwrap()
+    };
+
+    queue_ocr_with_priority(&conn, &blob_id, &langs(&["en"]), 3).unwrap();
+    process_ocr_job(&conn, &engine, &blob_id, &image, &[]).unwrap();
+    assert_eq!(indexed(&conn), ["text of receipt [en]"]);
+
+    let id = reprocess_ocr(&conn, &blob_id, &langs(&["de"])).unwrap();
+    let pending = get_ocr_status(&conn, &blob_id).unwrap().unwrap();
+    assert_eq!(pending.id, id);
+    assert_eq!(pending.status, OcrStatus::Pending);
+    assert_eq!(pending.languages, langs(&["de"]));
+    assert_eq!(get_ocr_queue_depth(&conn).unwrap(), 1);
+    let priority: i32 = conn
+        .query_row(
+            "SELECT priority FROM ocr_result WHERE blob_id = ?1",
+            [&blob_id],
+            |row| row.get(0),
+        )
+        .unwrap();
+    assert_eq!(priority, 3);
+
+    process_ocr_job(&conn, &engine, &blob_id, &image, &[]).unwrap();
+    let done = get_ocr_status(&conn, &blob_id).unwrap().unwrap();
+    assert_eq!(done.extracted_text.as_deref(), Some("text of receipt [de]"));
+    assert_eq!(done.detected_language.as_deref(), Some("de"));
+    assert_eq!(indexed(&conn), ["text of receipt [de]"]);
+    assert!(search_ocr_text(&conn, "[en]", None, 10).unwrap().is_empty());
+}
@@ -0,0 +1,237 @@
+use core_rs::collaboration::{
+    add_user_to_space, check_permission, get_space_users, init_rbac_tables,
+    remove_user_from_space_as, suspend_user, CollaborationError,
+};
+use core_rs::db::{migrate, DbError};
+use core_rs::note::{
+    create_note, create_note_as, get_note, get_note_as, trash_note_as, update_note_content_as,
+    DbUlid,
+};
+use core_rs::permission::{ActorContext, PERMISSION_ADMIN, PERMISSION_WRITE};
+use core_rs::project::{create_project, delete_project_as, update_project_as, ProjectError};
+use core_rs::sync::{get_rejected_deltas, set_device_user};
+use core_rs::sync_agent::{SyncAgent, SyncDelta, SyncOperation};
+use core_rs::task::{create_task_as, delete_task_as};
+use rusqlite::Connection;
+use std::collections::HashMap;
+use ulid::Ulid;
+
+/// A space shared by an owner, an editor and a viewer
+fn setup_shared_space() -> (Connection, String) {
+    let mut conn = Connection::open_in_memory().unwrap();
+    migrate(&mut conn).unwrap();
+    init_rbac_tables(&conn).unwrap();
+    core_rs::sync_agent::init_sync_tables(&conn).unwrap();
+    let space_id = Ulid::new().to_string();
+    conn.execute(
+        "INSERT INTO space (id, name) VALUES (?1, 'Team')",
+        [&space_id],
+    )
+    .unwrap();
+    for (user_id, role) in [("owner", "owner"), ("ed", "editor"), ("vi", "viewer")] {
+        let email = format!("{}@example.com", user_id);
+        add_user_to_space(&conn, &space_id, user_id, &email, role).unwrap();
+    }
+    (conn, space_id)
+}
+
+fn note_content(conn: &Connection, id: &DbUlid) -> String {
+    get_note(conn, id.clone()).unwrap().unwrap().content_md
+}
+
+fn note_delta(space_id: &str, note_id: &str, content: &str) -> SyncDelta {
+    SyncDelta {
+        entity_type: "note".to_string(),
+        entity_id: note_id.to_string(),
+        operation: SyncOperation::Update,
+        data: Some(content.as_bytes().to_vec()),
+        timestamp: chrono::Utc::now().timestamp(),
+        vector_clock: HashMap::new(),
+        space_id: Some(space_id.to_string()),
+    }
+}
+
+#[test]
+fn test_viewer_can_read_but_not_update() {
+    let (mut conn, space_id) = setup_shared_space();
+    let viewer = ActorContext::local("vi");
+    let note = create_note(&conn, &space_id, "Plan", "Ship it").unwrap();
+
+    let read = get_note_as(&conn, &viewer, note.id.clone())
+        .unwrap()
+        .unwrap();
+    assert_eq!(read.content_md, "Ship it");
+
+    match update_note_content_as(&mut conn, &viewer, note.id.clone(), "Plan", "Scrap it") {
+        Err(DbError::PermissionDenied(denied)) => {
+            assert_eq!(denied.user_id, "vi");
+            assert_eq!(denied.space_id, space_id);
+            assert_eq!(denied.permission, PERMISSION_WRITE);
+            assert!(!denied.via_sync);
+        }
+        other => panic!("expected PermissionDenied, got {:?}", other),
+    }
+    assert_eq!(note_content(&conn, &note.id), "Ship it");
+
+    let space = Ulid::from_string(&space_id).unwrap();
+    assert!(matches!(
+        create_task_as(&conn, &viewer, space, "Sneaky", None),
+        Err(DbError::PermissionDenied(_))
+    ));
+    assert!(matches!(
+        create_note_as(&conn, &viewer, &space_id, "Sneaky", ""),
+        Err(DbError::PermissionDenied(_))
+    ));
+
+    // Someone who isn't a member can't even read
+    assert!(matches!(
+        get_note_as(&conn, &ActorContext::local("stranger"), note.id),
+        Err(DbError::PermissionDenied(_))
+    ));
+}
+
+#[test]
+fn test_editor_can_update_notes_but_not_remove_users() {
+    let (mut conn, space_id) = setup_shared_space();
+    let editor = ActorContext::local("ed");
+    let note = create_note_as(&conn, &editor, &space_id, "Plan", "Ship it").unwrap();
+
+    update_note_content_as(
+        &mut conn,
+        &editor,
+        note.id.clone(),
+        "Plan",
+        "Ship it Friday",
+    )
+    .unwrap();
+    assert_eq!(note_content(&conn, &note.id), "Ship it Friday");
+    trash_note_as(&conn, &editor, note.id.clone()).unwrap();
+
+    let task = create_task_as(
+        &conn,
+        &editor,
+        Ulid::from_string(&space_id).unwrap(),
+        "Book room",
+        None,
+    )
+    .unwrap();
+    delete_task_as(&conn, &editor, task.id).unwrap();
+
+    match remove_user_from_space_as(&conn, &editor, &space_id, "vi") {
+        Err(CollaborationError::PermissionDenied(denied)) => {
+            assert_eq!(denied.permission, PERMISSION_ADMIN);
+        }
+        other => panic!("expected PermissionDenied, got {:?}", other),
+    }
+    assert!(check_permission(&conn, &space_id, "vi", "read_notes").unwrap());
+
+    // Renaming a project is an edit, deleting it takes admin
+    let mut project = create_project(&conn, &space_id, "Launch").unwrap();
+    project.title = "Launch v2".to_string();
+    update_project_as(&conn, &editor, &project).unwrap();
+    assert!(matches!(
+        delete_project_as(&mut conn, &editor, &project.id),
+        Err(ProjectError::PermissionDenied(_))
+    ));
+
+    let owner = ActorContext::local("owner");
+    delete_project_as(&mut conn, &owner, &project.id).unwrap();
+    remove_user_from_space_as(&conn, &owner, &space_id, "vi").unwrap();
+    assert_eq!(get_space_users(&conn, &space_id).unwrap().len(), 2);
+}
+
+#[test]
+fn test_suspended_member_loses_access() {
+    let (mut conn, space_id) = setup_shared_space();
+    let editor = ActorContext::local("ed");
+    let note = create_note(&conn, &space_id, "Plan", "Ship it").unwrap();
+
+    suspend_user(&conn, &space_id, "ed").unwrap();
+    assert!(matches!(
+        update_note_content_as(&mut conn, &editor, note.id, "Plan", "Changed"),
+        Err(DbError::PermissionDenied(_))
+    ));
+}
+
+#[test]
+fn test_personal_space_is_not_checked() {
+    let mut conn = Connection::open_in_memory().unwrap();
+    migrate(&mut conn).unwrap();
+    let space_id = Ulid::new().to_string();
+    conn.execute(
+        "INSERT INTO space (id, name) VALUES (?1, 'Personal')",
+        [&space_id],
+    )
+    .unwrap();
+
+    // No membership tables at all
+    let me = ActorContext::local("me");
+    let note = create_note_as(&conn, &me, &space_id, "Diary", "Day one").unwrap();
+    update_note_content_as(&mut conn, &me, note.id.clone(), "Diary", "Day two").unwrap();
+
+    // Membership tables, but nobody added to this space
+    init_rbac_tables(&conn).unwrap();
+    trash_note_as(&conn, &me, note.id).unwrap();
+}
+
+#[test]
+fn test_sync_delta_from_viewer_device_is_rejected_and_logged() {
+    let (mut conn, space_id) = setup_shared_space();
+    let desktop = SyncAgent::new("desktop".into(), "Desktop".into(), 0);
+    let dek = [0u8; 32];
+    for (device_id, user_id) in [("viewer-phone", "vi"), ("editor-phone", "ed")] {
+        let device = SyncAgent::new(device_id.into(), device_id.into(), 0);
+        desktop
+            .register_device(&conn, &device.get_device_info())
+            .unwrap();
+        set_device_user(&conn, device_id, Some(user_id)).unwrap();
+    }
+    let note = create_note(&conn, &space_id, "Plan", "Ship it").unwrap();
+    let note_id = note.id.to_string();
+
+    // The note was last synced an hour ago and hasn't changed here since, so
+    // incoming edits apply without a conflict
+    let last_sync = chrono::Utc::now().timestamp() - 3600;
+    conn.execute(
+        "UPDATE note SET modified_at = ?1 WHERE id = ?2",
+        rusqlite::params![last_sync - 100, note_id],
+    )
+    .unwrap();
+    conn.execute(
+        "INSERT INTO sync_history (id, device_id, space_id, sync_time, direction, success)
+         VALUES (?1, 'editor-phone', ?2, ?3, 'pull', 1)",
+        rusqlite::params![Ulid::new().to_string(), space_id, last_sync],
+    )
+    .unwrap();
+
+    let delta = note_delta(&space_id, &note_id, "Scrap it");
+    desktop
+        .apply_deltas_from(&mut conn, vec![delta], &dek, "viewer-phone")
+        .unwrap();
+    assert_eq!(note_content(&conn, &note.id), "Ship it");
+
+    let rejected = get_rejected_deltas(&conn, &space_id, 10).unwrap();
+    assert_eq!(rejected.len(), 1);
+    assert_eq!(rejected[0].device_id, "viewer-phone");
+    assert_eq!(rejected[0].user_id.as_deref(), Some("vi"));
+    assert_eq!(rejected[0].entity_id, note_id);
+    assert_eq!(rejected[0].operation, "Update");
+    assert_eq!(rejected[0].permission, PERMISSION_WRITE);
+
+    // A device nobody is linked to can't write to the space either
+    let delta = note_delta(&space_id, &note_id, "Scrap it");
+    desktop
+        .apply_deltas_from(&mut conn, vec![delta], &dek, "unknown-laptop")
+        .unwrap();
+    assert_eq!(note_content(&conn, &note.id), "Ship it");
+    let rejected = get_rejected_deltas(&conn, &space_id, 10).unwrap();
+    assert_eq!(rejected.len(), 2);
+    assert!(rejected.iter().any(|r| r.user_id.is_none()));
+
+    let delta = note_delta(&space_id, &note_id, "Ship it Friday");
+    desktop
+        .apply_deltas_from(&mut conn, vec![delta], &dek, "editor-phone")
+        .unwrap();
+    assert_eq!(note_content(&conn, &note.id), "Ship it Friday");
+    assert_eq!(get_rejected_deltas(&conn, &space_id, 10).unwrap().len(), 2);
+}
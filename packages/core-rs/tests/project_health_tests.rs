@@ -0,0 +1,201 @@
+use core_rs::db::migrate;
+use core_rs::project::*;
+use core_rs::space::create_space;
+use rusqlite::Connection;
+use tempfile::tempdir;
+use ulid::Ulid;
+
+const DAY: i64 = 86_400;
+// 2024-01-15 12:00 UTC
+const NOW: i64 = 1_705_320_000;
+
+fn setup_db() -> (tempfile::TempDir, Connection, String) {
+    let dir = tempdir().unwrap();
+    let mut conn = Connection::open(dir.path().join("test.db")).unwrap();
+    conn.pragma_update(None, "foreign_keys", "ON").unwrap();
+    migrate(&mut conn).unwrap();
+    let space_id = create_space(&mut conn, "Health").unwrap().to_string();
+    (dir, conn, space_id)
+}
+
+fn add_task(
+    conn: &Connection,
+    space_id: &str,
+    project_id: &str,
+    status: &str,
+    completed_at: Option<i64>,
+) -> String {
+    let id = Ulid::new().to_string();
+    conn.execute(
+        "INSERT INTO task (id, space_id, project_id, title, status, completed_at)
+         VALUES (?1, ?2, ?3, 'Task', ?4, ?5)",
+        rusqlite::params![id, space_id, project_id, status, completed_at],
+    )
+    .unwrap();
+    id
+}
+
+fn track_time(conn: &Connection, space_id: &str, task_id: &str, started_at: i64) {
+    conn.execute(
+        "INSERT INTO time_entry (id, space_id, task_id, started_at, ended_at, duration_seconds)
+         VALUES (?1, ?2, ?3, ?4, ?4 + 3600, 3600)",
+        rusqlite::params![Ulid::new().to_string(), space_id, task_id, started_at],
+    )
+    .unwrap();
+}
+
+fn on_track_project(conn: &Connection, space_id: &str) -> Project {
+    let project = create_project(conn, space_id, "Healthy").unwrap();
+    create_project_milestone(conn, &project.id, "Beta", Some(NOW + 10 * DAY), "active").unwrap();
+    create_project_milestone(conn, &project.id, "Alpha", Some(NOW - 10 * DAY), "Done").unwrap();
+    create_project_risk(conn, &project.id, "Vendor delay", "Low", "Medium", "", None).unwrap();
+    create_project_update(
+        conn,
+        &project.id,
+        NOW - DAY,
+        "green",
+        "All good",
+        &ProjectStatusReport::default(),
+    )
+    .unwrap();
+    add_task(conn, space_id, &project.id, "done", Some(NOW - 3 * DAY));
+    add_task(conn, space_id, &project.id, "done", Some(NOW - 5 * DAY));
+    add_task(conn, space_id, &project.id, "done", Some(NOW - 40 * DAY));
+    add_task(conn, space_id, &project.id, "next", None);
+    project
+}
+
+fn at_risk_project(conn: &Connection, space_id: &str) -> Project {
+    let project = create_project(conn, space_id, "Wobbly").unwrap();
+    create_project_milestone(conn, &project.id, "Late", Some(NOW - 2 * DAY), "active").unwrap();
+    create_project_milestone(conn, &project.id, "Later", Some(NOW + 20 * DAY), "active").unwrap();
+    create_project_risk(conn, &project.id, "Key person", "High", "Low", "", None).unwrap();
+    create_project_update(
+        conn,
+        &project.id,
+        NOW - 7 * DAY,
+        "amber",
+        "Slipping",
+        &ProjectStatusReport::default(),
+    )
+    .unwrap();
+    // Work is being tracked but nothing has closed this window
+    let task = add_task(conn, space_id, &project.id, "in_progress", None);
+    track_time(conn, space_id, &task, NOW - 2 * DAY);
+    project
+}
+
+fn off_track_project(conn: &Connection, space_id: &str) -> Project {
+    let project = create_project(conn, space_id, "Stalled").unwrap();
+    create_project_milestone(conn, &project.id, "Missed", Some(NOW - 30 * DAY), "active").unwrap();
+    for risk in ["Budget", "Scope", "Legal"] {
+        create_project_risk(conn, &project.id, risk, "high", "High", "", None).unwrap();
+    }
+    add_task(conn, space_id, &project.id, "next", None);
+    project
+}
+
+#[test]
+fn test_health_classifications() {
+    let (_dir, conn, space_id) = setup_db();
+
+    let healthy = on_track_project(&conn, &space_id);
+    let health = compute_project_health_at(&conn, &healthy.id, NOW).unwrap();
+    assert_eq!(health.status, ProjectHealthStatus::OnTrack);
+    assert_eq!(health.signals.overdue_milestones, 0);
+    assert_eq!(health.signals.open_high_impact_risks, 0);
+    assert_eq!(
+        (
+            health.signals.completed_recent,
+            health.signals.completed_previous
+        ),
+        (2, 1)
+    );
+    assert_eq!(health.signals.velocity_penalty, 0.0);
+    assert_eq!(health.score, 98.6);
+
+    let wobbly = at_risk_project(&conn, &space_id);
+    let health = compute_project_health_at(&conn, &wobbly.id, NOW).unwrap();
+    assert_eq!(health.status, ProjectHealthStatus::AtRisk);
+    assert_eq!(health.signals.overdue_milestone_penalty, 0.5);
+    assert_eq!(health.signals.tracked_seconds_recent, 3600);
+    assert_eq!(health.signals.velocity_penalty, 0.5);
+    assert_eq!(health.signals.days_since_update, Some(7));
+    assert_eq!(health.score, 54.2);
+
+    let stalled = off_track_project(&conn, &space_id);
+    let health = compute_project_health_at(&conn, &stalled.id, NOW).unwrap();
+    assert_eq!(health.status, ProjectHealthStatus::OffTrack);
+    assert_eq!(health.signals.days_since_update, None);
+    assert_eq!(health.score, 0.0);
+
+    let summary = get_projects_health_summary_at(&conn, &space_id, NOW).unwrap();
+    assert_eq!(
+        (summary.on_track, summary.at_risk, summary.off_track),
+        (1, 1, 1)
+    );
+    let order: Vec<&str> = summary.projects.iter().map(|p| p.title.as_str()).collect();
+    assert_eq!(order, vec!["Stalled", "Wobbly", "Healthy"]);
+
+    // Finished projects are left out of the dashboard
+    conn.execute(
+        "UPDATE project SET status = 'done' WHERE id = ?1",
+        [&stalled.id],
+    )
+    .unwrap();
+    let summary = get_projects_health_summary_at(&conn, &space_id, NOW).unwrap();
+    assert_eq!(summary.off_track, 0);
+}
+
+#[test]
+fn test_health_is_stable_and_recorded_daily() {
+    let (_dir, conn, space_id) = setup_db();
+    let project = at_risk_project(&conn, &space_id);
+
+    let first = compute_project_health_at(&conn, &project.id, NOW).unwrap();
+    let second = compute_project_health_at(&conn, &project.id, NOW).unwrap();
+    assert_eq!(first, second);
+    let history = get_project_health_history(&conn, &project.id, None).unwrap();
+    assert_eq!(history.len(), 1);
+    assert_eq!(history[0].day, "2024-01-15");
+    assert_eq!(history[0].score, first.score);
+
+    // A week later the last update has gone fully stale
+    let later = compute_project_health_at(&conn, &project.id, NOW + 7 * DAY).unwrap();
+    assert!(later.score < first.score);
+    let history = get_project_health_history(&conn, &project.id, None).unwrap();
+    let days: Vec<&str> = history.iter().map(|s| s.day.as_str()).collect();
+    assert_eq!(days, vec!["2024-01-15", "2024-01-22"]);
+    let latest = get_project_health_history(&conn, &project.id, Some(1)).unwrap();
+    assert_eq!(latest[0].day, "2024-01-22");
+}
+
+#[test]
+fn test_health_weights_from_settings() {
+    let (_dir, conn, space_id) = setup_db();
+    let project = at_risk_project(&conn, &space_id);
+
+    // Only the single high-impact risk counts: a third of the risk cap
+    let config = ProjectHealthConfig {
+        overdue_milestones_weight: 0.0,
+        velocity_weight: 0.0,
+        update_staleness_weight: 0.0,
+        ..ProjectHealthConfig::default()
+    };
+    set_project_health_config(&conn, &config).unwrap();
+    assert_eq!(get_project_health_config(&conn).unwrap(), config);
+    let health = compute_project_health_at(&conn, &project.id, NOW).unwrap();
+    assert_eq!(health.score, 66.7);
+    assert_eq!(health.status, ProjectHealthStatus::AtRisk);
+
+    let all_zero = ProjectHealthConfig {
+        high_impact_risks_weight: 0.0,
+        ..config.clone()
+    };
+    assert!(set_project_health_config(&conn, &all_zero).is_err());
+    let inverted = ProjectHealthConfig {
+        on_track_min_score: 40.0,
+        ..config
+    };
+    assert!(set_project_health_config(&conn, &inverted).is_err());
+}
@@ -0,0 +1,240 @@
+use core_rs::db::migrate;
+use core_rs::project::*;
+use core_rs::space::create_space;
+use core_rs::weekly_review::generate_weekly_review;
+use rusqlite::Connection;
+use tempfile::tempdir;
+use ulid::Ulid;
+
+const DAY: i64 = 86_400;
+const NOW: i64 = 1_705_320_000;
+
+fn setup_db() -> (tempfile::TempDir, Connection, String) {
+    let dir = tempdir().unwrap();
+    let mut conn = Connection::open(dir.path().join("test.db")).unwrap();
+    conn.pragma_update(None, "foreign_keys", "ON").unwrap();
+    migrate(&mut conn).unwrap();
+    let space_id = create_space(&mut conn, "Status").unwrap().to_string();
+    (dir, conn, space_id)
+}
+
+fn report(status: ProjectHealthStatus, percent_complete: i64) -> ProjectStatusReport {
+    ProjectStatusReport {
+        status: Some(status),
+        percent_complete: Some(percent_complete),
+        ..Default::default()
+    }
+}
+
+/// An update as written before the structured fields existed
+fn insert_legacy_update(conn: &Connection, project_id: &str, when_at: i64, health: &str) {
+    conn.execute(
+        "INSERT INTO project_update (id, project_id, when_at, health, summary)
+         VALUES (?1, ?2, ?3, ?4, 'Written by hand')",
+        rusqlite::params![Ulid::new().to_string(), project_id, when_at, health],
+    )
+    .unwrap();
+}
+
+fn set_status(conn: &Connection, project_id: &str, status: &str) {
+    conn.execute(
+        "UPDATE project SET status = ?2 WHERE id = ?1",
+        [project_id, status],
+    )
+    .unwrap();
+}
+
+#[test]
+fn test_latest_status_per_project_picks_newest_update() {
+    let (_dir, conn, space_id) = setup_db();
+    let website = create_project(&conn, &space_id, "Website").unwrap();
+    let launch = create_project(&conn, &space_id, "Launch").unwrap();
+    create_project(&conn, &space_id, "Silent").unwrap();
+
+    let week_two = ProjectStatusReport {
+        highlights: Some("Design signed off".to_string()),
+        next_steps: Some("Build the pricing page".to_string()),
+        ..report(ProjectHealthStatus::AtRisk, 40)
+    };
+    create_project_update(
+        &conn,
+        &website.id,
+        NOW - 14 * DAY,
+        "green",
+        "Kicked off",
+        &report(ProjectHealthStatus::OnTrack, 10),
+    )
+    .unwrap();
+    let newest = create_project_update(
+        &conn,
+        &website.id,
+        NOW - 7 * DAY,
+        "amber",
+        "Copy is late",
+        &week_two,
+    )
+    .unwrap();
+    // Written later but dated earlier, so not the latest
+    create_project_update(
+        &conn,
+        &website.id,
+        NOW - 20 * DAY,
+        "green",
+        "Backfilled",
+        &report(ProjectHealthStatus::OnTrack, 5),
+    )
+    .unwrap();
+    create_project_update(
+        &conn,
+        &launch.id,
+        NOW - DAY,
+        "red",
+        "Venue cancelled",
+        &report(ProjectHealthStatus::OffTrack, 60),
+    )
+    .unwrap();
+
+    let latest = get_latest_status_per_project(&conn, &space_id).unwrap();
+    assert_eq!(latest.len(), 2);
+    assert_eq!(latest[0].project_id, launch.id);
+    assert_eq!(latest[1].id, newest.id);
+    assert_eq!(latest[1].report, week_two);
+    assert_eq!(
+        get_project_update(&conn, &newest.id)
+            .unwrap()
+            .unwrap()
+            .report,
+        week_two
+    );
+
+    // Other spaces' projects stay out
+    let other_space = Ulid::new().to_string();
+    assert!(get_latest_status_per_project(&conn, &other_space)
+        .unwrap()
+        .is_empty());
+}
+
+#[test]
+fn test_space_summary_counts_ongoing_projects_by_status() {
+    let (_dir, conn, space_id) = setup_db();
+    let statuses = [
+        ("A", Some(ProjectHealthStatus::OnTrack)),
+        ("B", Some(ProjectHealthStatus::OnTrack)),
+        ("C", Some(ProjectHealthStatus::AtRisk)),
+        ("D", Some(ProjectHealthStatus::OffTrack)),
+        ("E", None),
+    ];
+    for (title, status) in statuses {
+        let project = create_project(&conn, &space_id, title).unwrap();
+        if let Some(status) = status {
+            create_project_update(&conn, &project.id, NOW, "", "", &report(status, 50)).unwrap();
+        }
+    }
+    // A project that recovered counts by its newest update
+    let recovered = create_project(&conn, &space_id, "F").unwrap();
+    create_project_update(
+        &conn,
+        &recovered.id,
+        NOW - 7 * DAY,
+        "",
+        "",
+        &report(ProjectHealthStatus::OffTrack, 20),
+    )
+    .unwrap();
+    create_project_update(
+        &conn,
+        &recovered.id,
+        NOW,
+        "",
+        "",
+        &report(ProjectHealthStatus::OnTrack, 30),
+    )
+    .unwrap();
+    // Finished projects aren't counted
+    let done = create_project(&conn, &space_id, "G").unwrap();
+    create_project_update(
+        &conn,
+        &done.id,
+        NOW,
+        "",
+        "",
+        &report(ProjectHealthStatus::OffTrack, 90),
+    )
+    .unwrap();
+    set_status(&conn, &done.id, "done");
+
+    assert_eq!(
+        get_space_status_summary(&conn, &space_id).unwrap(),
+        SpaceStatusSummary {
+            on_track: 3,
+            at_risk: 1,
+            off_track: 1,
+            unreported: 1,
+            total: 6,
+        }
+    );
+}
+
+#[test]
+fn test_text_only_updates_still_load_and_roll_up() {
+    let (_dir, conn, space_id) = setup_db();
+    let old = create_project(&conn, &space_id, "Old").unwrap();
+    let vague = create_project(&conn, &space_id, "Vague").unwrap();
+    insert_legacy_update(&conn, &old.id, NOW - DAY, "red");
+    insert_legacy_update(&conn, &vague.id, NOW - DAY, "unsure");
+
+    let updates = get_project_updates(&conn, &old.id).unwrap();
+    assert_eq!(updates.len(), 1);
+    assert_eq!(updates[0].summary, "Written by hand");
+    assert_eq!(updates[0].report, ProjectStatusReport::default());
+    // The health colour stands in for the missing status
+    assert_eq!(
+        updates[0].effective_status(),
+        Some(ProjectHealthStatus::OffTrack)
+    );
+
+    let summary = get_space_status_summary(&conn, &space_id).unwrap();
+    assert_eq!(summary.off_track, 1);
+    assert_eq!(summary.unreported, 1);
+
+    // Out-of-range progress is refused
+    let invalid = create_project_update(
+        &conn,
+        &old.id,
+        NOW,
+        "green",
+        "",
+        &report(ProjectHealthStatus::OnTrack, 140),
+    );
+    assert!(matches!(invalid, Err(ProjectError::InvalidData(_))));
+}
+
+#[test]
+fn test_weekly_review_lists_latest_update_per_project() {
+    let (_dir, conn, space_id) = setup_db();
+    let website = create_project(&conn, &space_id, "Website").unwrap();
+    let archived = create_project(&conn, &space_id, "Old site").unwrap();
+    create_project(&conn, &space_id, "Podcast").unwrap();
+    create_project_update(
+        &conn,
+        &website.id,
+        NOW,
+        "amber",
+        "Copy is late",
+        &ProjectStatusReport {
+            risks_changed: Some("Copywriter on leave".to_string()),
+            ..report(ProjectHealthStatus::AtRisk, 40)
+        },
+    )
+    .unwrap();
+    insert_legacy_update(&conn, &archived.id, NOW, "green");
+    set_status(&conn, &archived.id, "archived");
+
+    let space = space_id.parse::<Ulid>().unwrap();
+    let review = generate_weekly_review(&conn, space).unwrap().content_md;
+    assert!(review.contains(
+        "- **Website**: At risk, 40% complete — Copy is late\n  - Risks: Copywriter on leave\n"
+    ));
+    assert!(review.contains("- **Podcast**: No updates yet\n"));
+    assert!(!review.contains("Old site"));
+}
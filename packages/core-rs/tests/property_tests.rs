@@ -0,0 +1,334 @@
+use core_rs::db::migrate;
+use core_rs::import::import_from_obsidian;
+use core_rs::note::create_note;
+use core_rs::property::*;
+use core_rs::search::{search_all, EntityType, SearchFilters, SearchQuery, SortOptions};
+use core_rs::space::create_space;
+use rusqlite::Connection;
+use serde_json::json;
+use std::fs;
+use tempfile::tempdir;
+use ulid::Ulid;
+
+fn setup() -> (Connection, String) {
+    let mut conn = Connection::open_in_memory().unwrap();
+    migrate(&mut conn).unwrap();
+    let space_id = create_space(&mut conn, "Reading").unwrap().to_string();
+    (conn, space_id)
+}
+
+fn note(conn: &Connection, space_id: &str, title: &str) -> String {
+    create_note(conn, space_id, title, "")
+        .unwrap()
+        .id
+        .0
+        .to_string()
+}
+
+fn options(names: &[&str]) -> Vec<String> {
+    names.iter().map(|n| n.to_string()).collect()
+}
+
+fn note_query(space_id: &str, text: &str, properties: Vec<PropertyFilter>) -> SearchQuery {
+    SearchQuery {
+        query: text.to_string(),
+        entity_types: vec![EntityType::All],
+        filters: SearchFilters {
+            space_id: Some(Ulid::from_string(space_id).unwrap()),
+            properties,
+            ..Default::default()
+        },
+        sort: SortOptions::default(),
+        limit: None,
+        offset: None,
+    }
+}
+
+fn filter(property: &str, op: PropertyOp, value: serde_json::Value) -> PropertyFilter {
+    PropertyFilter {
+        property: property.to_string(),
+        op,
+        value,
+    }
+}
+
+fn titles(conn: &Connection, query: &SearchQuery) -> Vec<String> {
+    let mut titles: Vec<String> = search_all(conn, query)
+        .unwrap()
+        .into_iter()
+        .map(|r| r.title)
+        .collect();
+    titles.sort();
+    titles
+}
+
+#[test]
+fn test_values_are_checked_against_their_type() {
+    let (mut conn, space_id) = setup();
+    let book = note(&conn, &space_id, "Dune");
+    let rating = define_property(&conn, &space_id, "Rating", PropertyType::Number, &[]).unwrap();
+    let finished = define_property(&conn, &space_id, "Finished", PropertyType::Date, &[]).unwrap();
+    let read = define_property(&conn, &space_id, "Read", PropertyType::Checkbox, &[]).unwrap();
+    let status = define_property(
+        &conn,
+        &space_id,
+        "Status",
+        PropertyType::Select,
+        &options(&["To read", "Reading", "Done"]),
+    )
+    .unwrap();
+
+    for (property, value) in [
+        (&rating, json!("four")),
+        (&finished, json!("last tuesday")),
+        (&read, json!(1)),
+        (&status, json!("Abandoned")),
+        (&status, json!(null)),
+    ] {
+        let err = set_note_property(&conn, &book, &property.id, &value).unwrap_err();
+        assert!(
+            matches!(err, PropertyError::InvalidValue { .. }),
+            "{} accepted {}",
+            property.name,
+            value
+        );
+    }
+
+    set_note_property(&conn, &book, &rating.id, &json!("4.5")).unwrap();
+    set_note_property(&conn, &book, &finished.id, &json!("2024-03-01T21:30:00Z")).unwrap();
+    set_note_property(&conn, &book, &read.id, &json!(true)).unwrap();
+    let set = set_note_property(&conn, &book, &status.id, &json!("done")).unwrap();
+    assert_eq!(set.value, json!("Done"));
+
+    let values: Vec<(String, serde_json::Value)> = get_note_properties(&conn, &book)
+        .unwrap()
+        .into_iter()
+        .map(|p| (p.name, p.value))
+        .collect();
+    assert_eq!(
+        values,
+        vec![
+            ("Finished".to_string(), json!("2024-03-01")),
+            ("Rating".to_string(), json!(4.5)),
+            ("Read".to_string(), json!(true)),
+            ("Status".to_string(), json!("Done")),
+        ]
+    );
+
+    // Definitions are per space, and names are unique within one
+    assert!(matches!(
+        define_property(&conn, &space_id, "rating", PropertyType::Text, &[]),
+        Err(PropertyError::Duplicate(_))
+    ));
+    assert!(matches!(
+        define_property(&conn, &space_id, "Genre", PropertyType::Select, &[]),
+        Err(PropertyError::InvalidDefinition(_))
+    ));
+    let other_space = create_space(&mut conn, "Work").unwrap().to_string();
+    let memo = note(&conn, &other_space, "Memo");
+    assert!(matches!(
+        set_note_property(&conn, &memo, &rating.id, &json!(3)),
+        Err(PropertyError::OtherSpace(_))
+    ));
+}
+
+#[test]
+fn test_search_filters_on_properties() {
+    let (conn, space_id) = setup();
+    let rating = define_property(&conn, &space_id, "Rating", PropertyType::Number, &[]).unwrap();
+    let finished = define_property(&conn, &space_id, "Finished", PropertyType::Date, &[]).unwrap();
+    let author = define_property(&conn, &space_id, "Author", PropertyType::Text, &[]).unwrap();
+    for (title, stars, day, by) in [
+        ("Dune", 5, "2024-01-10", "Frank Herbert"),
+        ("Emma", 3, "2024-02-20", "Jane Austen"),
+        ("Persuasion", 4, "2023-11-05", "Jane Austen"),
+    ] {
+        let id = note(&conn, &space_id, title);
+        set_note_property(&conn, &id, &rating.id, &json!(stars)).unwrap();
+        set_note_property(&conn, &id, &finished.id, &json!(day)).unwrap();
+        set_note_property(&conn, &id, &author.id, &json!(by)).unwrap();
+    }
+    note(&conn, &space_id, "Unrated");
+    core_rs::task::create_task(
+        &conn,
+        Ulid::from_string(&space_id).unwrap(),
+        "Buy Dune",
+        None,
+    )
+    .unwrap();
+
+    let find =
+        |properties: Vec<PropertyFilter>| titles(&conn, &note_query(&space_id, "", properties));
+    assert_eq!(
+        find(vec![filter("rating", PropertyOp::GreaterThan, json!(3))]),
+        vec!["Dune", "Persuasion"]
+    );
+    assert_eq!(
+        find(vec![filter("Rating", PropertyOp::Equals, json!(3))]),
+        vec!["Emma"]
+    );
+    assert_eq!(
+        find(vec![filter(
+            "Finished",
+            PropertyOp::LessThan,
+            json!("2024-01-01")
+        )]),
+        vec!["Persuasion"]
+    );
+    assert_eq!(
+        find(vec![
+            filter("Author", PropertyOp::Contains, json!("austen")),
+            filter("Finished", PropertyOp::GreaterThan, json!("2024-01-01")),
+        ]),
+        vec!["Emma"]
+    );
+    assert_eq!(
+        find(vec![filter(
+            "Author",
+            PropertyOp::Equals,
+            json!("jane austen")
+        )]),
+        vec!["Emma", "Persuasion"]
+    );
+    assert_eq!(
+        find(vec![filter("Rating", PropertyOp::IsNotSet, json!(null))]),
+        vec!["Unrated"]
+    );
+    assert_eq!(
+        find(vec![filter("Rating", PropertyOp::IsSet, json!(null))]).len(),
+        3
+    );
+    // Combined with the text query; the task never matches
+    assert_eq!(
+        titles(
+            &conn,
+            &note_query(
+                &space_id,
+                "dune",
+                vec![filter("Rating", PropertyOp::IsSet, json!(null))]
+            )
+        ),
+        vec!["Dune"]
+    );
+    // A bound that can't be compared matches nothing
+    assert!(find(vec![filter(
+        "Rating",
+        PropertyOp::GreaterThan,
+        json!("soon")
+    )])
+    .is_empty());
+}
+
+#[test]
+fn test_bulk_set_from_search() {
+    let (mut conn, space_id) = setup();
+    let status = define_property(
+        &conn,
+        &space_id,
+        "Status",
+        PropertyType::Select,
+        &options(&["Draft", "Published"]),
+    )
+    .unwrap();
+    for title in ["Draft: intro", "Draft: outro", "Published essay"] {
+        note(&conn, &space_id, title);
+    }
+    let other_space = create_space(&mut conn, "Elsewhere").unwrap().to_string();
+    note(&conn, &other_space, "Draft: elsewhere");
+
+    let mut query = note_query(&space_id, "draft", vec![]);
+    query.limit = Some(1);
+    let set = set_property_for_search(&mut conn, &query, &status.id, &json!("draft")).unwrap();
+    assert_eq!(set, 2);
+    assert_eq!(
+        titles(
+            &conn,
+            &note_query(
+                &space_id,
+                "",
+                vec![filter("Status", PropertyOp::Equals, json!("Draft"))]
+            )
+        ),
+        vec!["Draft: intro", "Draft: outro"]
+    );
+
+    // The value is checked once, before anything is written
+    assert!(matches!(
+        set_property_for_search(&mut conn, &query, &status.id, &json!("Archived")),
+        Err(PropertyError::InvalidValue { .. })
+    ));
+
+    // Without a space, the property's own space is searched
+    query.filters.space_id = None;
+    let set = set_property_for_search(&mut conn, &query, &status.id, &json!("Published")).unwrap();
+    assert_eq!(set, 2);
+    let untouched: i64 = conn
+        .query_row(
+            "SELECT COUNT(*) FROM note_property np JOIN note n ON n.id = np.note_id
+             WHERE n.space_id = ?1",
+            [&other_space],
+            |row| row.get(0),
+        )
+        .unwrap();
+    assert_eq!(untouched, 0);
+}
+
+#[test]
+fn test_obsidian_front_matter_maps_to_properties() {
+    let (conn, space_id) = setup();
+    define_property(&conn, &space_id, "Rating", PropertyType::Number, &[]).unwrap();
+    define_property(
+        &conn,
+        &space_id,
+        "status",
+        PropertyType::Select,
+        &options(&["Reading", "Done"]),
+    )
+    .unwrap();
+
+    let vault = tempdir().unwrap();
+    fs::write(
+        vault.path().join("Dune.md"),
+        "---\nrating: 5\nStatus: done\nauthor: Frank Herbert\n---\nSpice.\n",
+    )
+    .unwrap();
+    fs::write(
+        vault.path().join("Emma.md"),
+        "---\nrating: 3\nstatus: abandoned\n---\nMatchmaking.\n",
+    )
+    .unwrap();
+
+    let report = import_from_obsidian(
+        &conn,
+        Ulid::from_string(&space_id).unwrap(),
+        vault.path().to_str().unwrap(),
+    )
+    .unwrap();
+    assert_eq!(report.notes, 2);
+    // `author` has no definition and `abandoned` is not a status
+    assert_eq!(report.properties, 3);
+
+    let properties_of = |title: &str| {
+        let id: String = conn
+            .query_row("SELECT id FROM note WHERE title = ?1", [title], |row| {
+                row.get(0)
+            })
+            .unwrap();
+        get_note_properties(&conn, &id)
+            .unwrap()
+            .into_iter()
+            .map(|p| (p.name, p.value))
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(
+        properties_of("Dune"),
+        vec![
+            ("Rating".to_string(), json!(5.0)),
+            ("status".to_string(), json!("Done")),
+        ]
+    );
+    assert_eq!(
+        properties_of("Emma"),
+        vec![("Rating".to_string(), json!(3.0))]
+    );
+}
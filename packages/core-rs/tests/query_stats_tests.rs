@@ -0,0 +1,184 @@
+use core_rs::db::{
+    apply_query_stats_settings, get_query_stats, instrument_connection, migrate, reset_query_stats,
+    QueryStat, QueryStatsSettings,
+};
+use core_rs::social::{add_social_account, get_unified_timeline_page, TimelineFilters};
+use rusqlite::Connection;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// The registry and the enabled flag are process-wide
+static SERIAL: Mutex<()> = Mutex::new(());
+
+fn setup_db() -> Connection {
+    let mut conn = Connection::open_in_memory().unwrap();
+    migrate(&mut conn).unwrap();
+    instrument_connection(&conn);
+    conn
+}
+
+fn enable() {
+    apply_query_stats_settings(&QueryStatsSettings {
+        enabled: true,
+        ..Default::default()
+    });
+}
+
+fn stat_for(sql: &str) -> Option<QueryStat> {
+    get_query_stats().into_iter().find(|stat| stat.sql == sql)
+}
+
+#[test]
+fn test_counts_match_issued_queries() {
+    let _serial = SERIAL.lock().unwrap_or_else(|e| e.into_inner());
+    let conn = setup_db();
+    conn.execute_batch("CREATE TABLE counted (id INTEGER PRIMARY KEY, label TEXT)")
+        .unwrap();
+    reset_query_stats();
+    enable();
+
+    for i in 0..25 {
+        conn.prepare_cached("INSERT INTO counted (label) VALUES (?1)")
+            .unwrap()
+            .execute([format!("row {}", i)])
+            .unwrap();
+    }
+    for _ in 0..10 {
+        let _: i64 = conn
+            .query_row("SELECT COUNT(*)   FROM\n counted", [], |row| row.get(0))
+            .unwrap();
+    }
+
+    let insert = stat_for("INSERT INTO counted (label) VALUES (?1)").unwrap();
+    assert_eq!(insert.count, 25);
+    assert!(insert.max_micros <= insert.total_micros);
+    let count = stat_for("SELECT COUNT(*) FROM counted").unwrap();
+    assert_eq!(count.count, 10, "whitespace is collapsed into one entry");
+
+    let stats = get_query_stats();
+    assert!(stats
+        .windows(2)
+        .all(|pair| pair[0].total_micros >= pair[1].total_micros));
+}
+
+#[test]
+fn test_nothing_recorded_while_disabled() {
+    let _serial = SERIAL.lock().unwrap_or_else(|e| e.into_inner());
+    let conn = setup_db();
+    apply_query_stats_settings(&QueryStatsSettings::default());
+    reset_query_stats();
+
+    for _ in 0..5 {
+        let _: i64 = conn
+            .query_row("SELECT 41 + 1", [], |row| row.get(0))
+            .unwrap();
+    }
+    assert!(stat_for("SELECT 41 + 1").is_none());
+
+    enable();
+    let _: i64 = conn
+        .query_row("SELECT 41 + 1", [], |row| row.get(0))
+        .unwrap();
+    assert_eq!(stat_for("SELECT 41 + 1").unwrap().count, 1);
+
+    reset_query_stats();
+    assert!(stat_for("SELECT 41 + 1").is_none());
+}
+
+#[test]
+fn test_slow_queries_are_counted() {
+    let _serial = SERIAL.lock().unwrap_or_else(|e| e.into_inner());
+    let conn = setup_db();
+    reset_query_stats();
+    // Every query takes at least a millisecond of wall time here
+    apply_query_stats_settings(&QueryStatsSettings {
+        enabled: true,
+        slow_query_ms: 1,
+    });
+
+    let sql = "WITH RECURSIVE n(x) AS (SELECT 1 UNION ALL SELECT x + 1 FROM n WHERE x < 200000) \
+               SELECT SUM(x) FROM n";
+    let _: i64 = conn.query_row(sql, [], |row| row.get(0)).unwrap();
+    let stat = stat_for(sql).unwrap();
+    assert_eq!(stat.count, 1);
+    assert_eq!(stat.slow_count, 1);
+    assert!(stat.max_micros >= 1_000);
+
+    apply_query_stats_settings(&QueryStatsSettings::default());
+}
+
+#[test]
+fn test_settings_reject_zero_threshold() {
+    use core_rs::db::Settings;
+    let settings = QueryStatsSettings {
+        enabled: true,
+        slow_query_ms: 0,
+    };
+    assert!(settings.validate().is_err());
+    assert!(QueryStatsSettings::default().validate().is_ok());
+}
+
+fn seed_timeline(conn: &mut Connection) -> String {
+    let space_id = core_rs::space::create_space(conn, "Social")
+        .unwrap()
+        .to_string();
+    let account =
+        add_social_account(conn, &space_id, "twitter", "me", None, "token", &[0u8; 32]).unwrap();
+    for i in 0..20i64 {
+        conn.execute(
+            "INSERT INTO social_post (id, account_id, platform, platform_post_id, author, content,
+                                      timestamp, fetched_at, first_seen_at, raw_json)
+             VALUES (?1, ?2, ?3, ?4, 'Author', ?5, ?6, ?6, ?6, '{}')",
+            rusqlite::params![
+                ulid::Ulid::new().to_string(),
+                account.id,
+                account.platform,
+                format!("post-{}", i),
+                format!("Post {}", i),
+                1_700_000_000_000 + i * 1_000,
+            ],
+        )
+        .unwrap();
+    }
+    space_id
+}
+
+fn time_timeline_queries(conn: &Connection, space_id: &str, runs: usize) -> Duration {
+    let filters = TimelineFilters {
+        limit: Some(10),
+        ..Default::default()
+    };
+    let started = Instant::now();
+    for _ in 0..runs {
+        let page = get_unified_timeline_page(conn, space_id, filters.clone()).unwrap();
+        assert_eq!(page.posts.len(), 10);
+    }
+    started.elapsed()
+}
+
+#[test]
+fn test_statement_cache_speeds_up_repeated_timeline_queries() {
+    let mut conn = setup_db();
+    let space_id = seed_timeline(&mut conn);
+
+    // Best of three rounds each, to keep scheduler noise out of the comparison
+    conn.set_prepared_statement_cache_capacity(0);
+    let uncached = (0..3)
+        .map(|_| time_timeline_queries(&conn, &space_id, 1_000))
+        .min()
+        .unwrap();
+
+    instrument_connection(&conn);
+    time_timeline_queries(&conn, &space_id, 1);
+    let cached = (0..3)
+        .map(|_| time_timeline_queries(&conn, &space_id, 1_000))
+        .min()
+        .unwrap();
+
+    assert!(
+        cached < uncached,
+        "1,000 cached timeline queries took {:?}, uncached {:?}",
+        cached,
+        uncached
+    );
+}
@@ -0,0 +1,171 @@
+use core_rs::ai::rag::{Embedder, RagConfig, RagError, RagPipeline};
+use core_rs::db::DbPool;
+use core_rs::note::{create_note, trash_note, update_note_content};
+use r2d2_sqlite::SqliteConnectionManager;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use tempfile::TempDir;
+
+/// Embedder that counts calls and returns a vector derived from the text length
+struct CountingEmbedder {
+    calls: Arc<AtomicUsize>,
+    version: &'static str,
+}
+
+impl Embedder for CountingEmbedder {
+    fn embed(&self, text: &str) -> Result<Vec<f32>, RagError> {
+        self.calls.fetch_add(1, Ordering::SeqCst);
+        Ok(vec![text.len() as f32, 1.0])
+    }
+
+    fn version(&self) -> &str {
+        self.version
+    }
+}
+
+fn setup(version: &'static str) -> (TempDir, DbPool, RagPipeline, Arc<AtomicUsize>) {
+    let dir = TempDir::new().unwrap();
+    let manager = SqliteConnectionManager::file(dir.path().join("test.db"));
+    let pool = r2d2::Pool::builder().max_size(2).build(manager).unwrap();
+    core_rs::db::migrate(&mut pool.get().unwrap()).unwrap();
+
+    let calls = Arc::new(AtomicUsize::new(0));
+    let config = RagConfig {
+        chunk_size: 120,
+        chunk_overlap: 20,
+        ..Default::default()
+    };
+    let pipeline =
+        RagPipeline::new(config, pool.clone()).with_embedder(Box::new(CountingEmbedder {
+            calls: calls.clone(),
+            version,
+        }));
+
+    (dir, pool, pipeline, calls)
+}
+
+fn note_body(i: usize) -> String {
+    format!(
+        "Note number {i} talks about topic {i}. It has a second sentence with more words. \
+         A third sentence makes sure the note spans several chunks. The end of note {i}."
+    )
+}
+
+#[test]
+fn test_refresh_only_reembeds_changed_note() {
+    let (_dir, pool, pipeline, calls) = setup("mock-v1");
+    let mut conn = pool.get().unwrap();
+    let space_id = core_rs::space::create_space(&mut conn, "Vault").unwrap();
+
+    let notes: Vec<_> = (0..100)
+        .map(|i| {
+            create_note(
+                &conn,
+                &space_id.to_string(),
+                &format!("Note {i}"),
+                &note_body(i),
+            )
+            .unwrap()
+        })
+        .collect();
+
+    let initial = pipeline.refresh_dirty(&mut conn).unwrap();
+    assert_eq!(initial.notes_refreshed, 100);
+    assert_eq!(initial.chunks_reused, 0);
+    let initial_calls = calls.load(Ordering::SeqCst);
+    assert_eq!(initial_calls, initial.chunks_embedded);
+    assert!(
+        initial_calls >= 200,
+        "every note should span several chunks"
+    );
+
+    let stats = pipeline.get_stats().unwrap();
+    assert_eq!(stats.total_notes, 100);
+    assert_eq!(stats.dirty_notes, 0);
+    assert!(stats.last_refresh_at.is_some());
+
+    // Change only the last sentence of one note
+    let edited = &notes[42];
+    let content = note_body(42).replace("The end of note 42.", "A rewritten ending.");
+    update_note_content(&mut conn, edited.id.clone(), "Note 42", &content).unwrap();
+    assert_eq!(pipeline.get_stats().unwrap().dirty_notes, 1);
+
+    let result = pipeline.refresh_dirty(&mut conn).unwrap();
+    assert_eq!(result.notes_refreshed, 1);
+    assert!(result.chunks_reused > 0, "unchanged chunks keep embeddings");
+    assert!(result.chunks_embedded > 0);
+    assert_eq!(
+        calls.load(Ordering::SeqCst) - initial_calls,
+        result.chunks_embedded
+    );
+
+    let indexed: String = conn
+        .query_row(
+            "SELECT group_concat(content, '') FROM note_embeddings WHERE note_id = ?1",
+            [edited.id.0.to_string()],
+            |row| row.get(0),
+        )
+        .unwrap();
+    assert!(indexed.contains("A rewritten ending."));
+    assert!(!indexed.contains("The end of note 42."));
+
+    // Nothing is dirty anymore
+    let idle = pipeline.refresh_dirty(&mut conn).unwrap();
+    assert_eq!(idle.notes_refreshed, 0);
+    assert_eq!(idle.chunks_embedded, 0);
+}
+
+#[test]
+fn test_refresh_removes_chunks_of_trashed_notes() {
+    let (_dir, pool, pipeline, _calls) = setup("mock-v1");
+    let mut conn = pool.get().unwrap();
+    let space_id = core_rs::space::create_space(&mut conn, "Vault").unwrap();
+
+    let keep = create_note(&conn, &space_id.to_string(), "Keep", &note_body(1)).unwrap();
+    let gone = create_note(&conn, &space_id.to_string(), "Gone", &note_body(2)).unwrap();
+    pipeline.refresh_dirty(&mut conn).unwrap();
+
+    trash_note(&conn, gone.id.clone()).unwrap();
+    let result = pipeline.refresh_dirty(&mut conn).unwrap();
+    assert_eq!(result.notes_removed, 1);
+
+    let count = |note_id: String| -> i64 {
+        conn.query_row(
+            "SELECT COUNT(*) FROM note_embeddings WHERE note_id = ?1",
+            [note_id],
+            |row| row.get(0),
+        )
+        .unwrap()
+    };
+    assert_eq!(count(gone.id.0.to_string()), 0);
+    assert!(count(keep.id.0.to_string()) > 0);
+}
+
+#[test]
+fn test_embedder_version_change_reembeds() {
+    let (_dir, pool, pipeline, _calls) = setup("mock-v1");
+    let mut conn = pool.get().unwrap();
+    let space_id = core_rs::space::create_space(&mut conn, "Vault").unwrap();
+    let note = create_note(&conn, &space_id.to_string(), "N", &note_body(7)).unwrap();
+    pipeline.refresh_dirty(&mut conn).unwrap();
+    drop(pipeline);
+
+    let calls = Arc::new(AtomicUsize::new(0));
+    let upgraded = RagPipeline::new(
+        RagConfig {
+            chunk_size: 120,
+            chunk_overlap: 20,
+            ..Default::default()
+        },
+        pool.clone(),
+    )
+    .with_embedder(Box::new(CountingEmbedder {
+        calls: calls.clone(),
+        version: "mock-v2",
+    }));
+
+    core_rs::ai::rag::mark_note_dirty(&conn, &note.id.0.to_string()).unwrap();
+    let result = upgraded.refresh_dirty(&mut conn).unwrap();
+    assert_eq!(result.chunks_reused, 0);
+    assert_eq!(result.chunks_embedded, calls.load(Ordering::SeqCst));
+}
@@ -0,0 +1,173 @@
+use core_rs::db::migrate;
+use core_rs::personal_modes::{create_recipe, Recipe};
+use core_rs::recipe::*;
+use core_rs::space::create_space;
+use rusqlite::Connection;
+use ulid::Ulid;
+
+fn setup_db() -> (Connection, Ulid) {
+    let mut conn = Connection::open_in_memory().unwrap();
+    conn.pragma_update(None, "foreign_keys", "ON").unwrap();
+    migrate(&mut conn).unwrap();
+    let space_id = create_space(&mut conn, "Kitchen").unwrap();
+    (conn, space_id)
+}
+
+fn recipe(conn: &Connection, space_id: Ulid, name: &str, servings: i64) -> Recipe {
+    let recipe = create_recipe(conn, space_id, "", name, 4, "easy").unwrap();
+    set_recipe_servings(conn, &recipe.id, servings).unwrap();
+    recipe
+}
+
+fn scaled(recipe: &ScaledRecipe, name: &str) -> (f64, Unit) {
+    let ingredient = recipe
+        .ingredients
+        .iter()
+        .find(|i| i.name == name)
+        .unwrap_or_else(|| panic!("{name} missing"));
+    (ingredient.quantity, ingredient.unit)
+}
+
+#[test]
+fn test_scale_recipe_rounds_per_unit() {
+    let (conn, space_id) = setup_db();
+    let pancakes = recipe(&conn, space_id, "Pancakes", 4);
+    add_recipe_ingredient(&conn, &pancakes.id, "Flour", "250", "grams", None).unwrap();
+    add_recipe_ingredient(&conn, &pancakes.id, "Milk", "1/2", "l", None).unwrap();
+    add_recipe_ingredient(&conn, &pancakes.id, "Sugar", "1 1/2", "Tablespoons", None).unwrap();
+    add_recipe_ingredient(&conn, &pancakes.id, "Egg", "2", "", Some("beaten")).unwrap();
+    add_recipe_ingredient(&conn, &pancakes.id, "Salt", "1", "pinch", None).unwrap();
+
+    let ingredients = get_recipe_ingredients(&conn, &pancakes.id).unwrap();
+    assert_eq!(ingredients.len(), 5);
+    assert_eq!(ingredients[2].unit, Unit::Tablespoon);
+    assert_eq!(ingredients[2].quantity, 1.5);
+    assert_eq!(ingredients[3].note.as_deref(), Some("beaten"));
+
+    // Same servings: unchanged
+    let same = scale_recipe(&conn, &pancakes.id, 4).unwrap();
+    assert_eq!(scaled(&same, "Flour"), (250.0, Unit::Gram));
+
+    // 4 -> 3 servings
+    let three = scale_recipe(&conn, &pancakes.id, 3).unwrap();
+    assert_eq!(three.servings, 4);
+    assert_eq!(three.target_servings, 3);
+    // 187.5 g -> whole grams
+    assert_eq!(scaled(&three, "Flour"), (188.0, Unit::Gram));
+    // 0.375 l -> quarter steps
+    assert_eq!(scaled(&three, "Milk"), (0.5, Unit::Liter));
+    // 1.125 tbsp -> 1¼
+    assert_eq!(scaled(&three, "Sugar"), (1.25, Unit::Tablespoon));
+    assert_eq!(scaled(&three, "Egg"), (1.5, Unit::Piece));
+
+    // 4 -> 1 serving: a pinch never rounds away
+    let one = scale_recipe(&conn, &pancakes.id, 1).unwrap();
+    assert_eq!(scaled(&one, "Salt"), (0.25, Unit::Pinch));
+    assert_eq!(scaled(&one, "Sugar"), (0.5, Unit::Tablespoon));
+
+    // 4 -> 10 servings
+    let ten = scale_recipe(&conn, &pancakes.id, 10).unwrap();
+    assert_eq!(scaled(&ten, "Flour"), (625.0, Unit::Gram));
+    assert_eq!(scaled(&ten, "Milk"), (1.25, Unit::Liter));
+    assert_eq!(scaled(&ten, "Sugar"), (3.75, Unit::Tablespoon));
+
+    assert!(matches!(
+        scale_recipe(&conn, &pancakes.id, 0),
+        Err(RecipeError::InvalidServings(0))
+    ));
+    assert!(matches!(
+        add_recipe_ingredient(&conn, &pancakes.id, "Butter", "a knob", "g", None),
+        Err(RecipeError::InvalidQuantity(_))
+    ));
+    assert!(matches!(
+        add_recipe_ingredient(&conn, &pancakes.id, "Butter", "1", "knob", None),
+        Err(RecipeError::UnknownUnit(_))
+    ));
+    assert!(matches!(
+        scale_recipe(&conn, "missing", 2),
+        Err(RecipeError::NotFound { .. })
+    ));
+}
+
+#[test]
+fn test_shopping_list_merges_convertible_units() {
+    let (mut conn, space_id) = setup_db();
+    let bread = recipe(&conn, space_id, "Bread", 1);
+    add_recipe_ingredient(&conn, &bread.id, "Flour", "750", "g", None).unwrap();
+    add_recipe_ingredient(&conn, &bread.id, "Water", "500", "ml", None).unwrap();
+    add_recipe_ingredient(&conn, &bread.id, "Salt", "2", "tsp", None).unwrap();
+    let pizza = recipe(&conn, space_id, "Pizza", 2);
+    add_recipe_ingredient(&conn, &pizza.id, "flour", "0.75", "kg", None).unwrap();
+    add_recipe_ingredient(&conn, &pizza.id, "Water", "0.5", "l", None).unwrap();
+    add_recipe_ingredient(&conn, &pizza.id, "Salt", "1", "tbsp", None).unwrap();
+    let cake = recipe(&conn, space_id, "Cake", 8);
+    // No density: cups of flour can't be added to grams
+    add_recipe_ingredient(&conn, &cake.id, "Flour", "2", "cups", None).unwrap();
+
+    let list =
+        generate_shopping_list(&conn, space_id, &[bread.id.clone(), pizza.id, cake.id]).unwrap();
+    let lines: Vec<(&str, f64, Unit, bool)> = list
+        .items
+        .iter()
+        .map(|i| (i.name.as_str(), i.quantity, i.unit, i.unmergeable))
+        .collect();
+    assert_eq!(
+        lines,
+        vec![
+            ("Flour", 1.5, Unit::Kilogram, true),
+            ("Water", 1.0, Unit::Liter, false),
+            // 2 tsp + 3 tsp doesn't come out even in tablespoons
+            ("Salt", 5.0, Unit::Teaspoon, false),
+            ("Flour", 2.0, Unit::Cup, true),
+        ]
+    );
+    assert!(list.items.iter().all(|i| !i.is_checked));
+
+    // Recipes from another space aren't picked up
+    let other_space = create_space(&mut conn, "Other").unwrap();
+    assert!(matches!(
+        generate_shopping_list(&conn, other_space, &[bread.id]),
+        Err(RecipeError::NotFound { .. })
+    ));
+}
+
+#[test]
+fn test_shopping_list_check_off_round_trip() {
+    let (conn, space_id) = setup_db();
+    let soup = recipe(&conn, space_id, "Soup", 2);
+    add_recipe_ingredient(&conn, &soup.id, "Onion", "2", "", None).unwrap();
+    add_recipe_ingredient(&conn, &soup.id, "Stock", "1", "l", None).unwrap();
+
+    let list = generate_shopping_list(&conn, space_id, &[soup.id.clone()]).unwrap();
+    let onion = &list.items[0];
+    set_shopping_item_checked(&conn, &onion.id, true).unwrap();
+
+    let loaded = get_shopping_list(&conn, &list.id).unwrap();
+    assert_eq!(loaded.recipe_ids, vec![soup.id.clone()]);
+    assert_eq!(loaded.space_id, space_id.to_string());
+    assert!(loaded.items[0].is_checked);
+    assert!(!loaded.items[1].is_checked);
+    assert_eq!(loaded.items[1].quantity, 1.0);
+    assert_eq!(loaded.items[1].unit, Unit::Liter);
+
+    set_shopping_item_checked(&conn, &onion.id, false).unwrap();
+    let loaded = get_shopping_list(&conn, &list.id).unwrap();
+    assert!(loaded.items.iter().all(|i| !i.is_checked));
+
+    assert!(matches!(
+        set_shopping_item_checked(&conn, "missing", true),
+        Err(RecipeError::NotFound { .. })
+    ));
+
+    delete_shopping_list(&conn, &list.id).unwrap();
+    let items: i64 = conn
+        .query_row("SELECT COUNT(*) FROM shopping_list_item", [], |row| {
+            row.get(0)
+        })
+        .unwrap();
+    assert_eq!(items, 0);
+    assert!(matches!(
+        get_shopping_list(&conn, &list.id),
+        Err(RecipeError::NotFound { .. })
+    ));
+}
@@ -201,3 +201,284 @@ fn test_search_performance_without_encrypted_content() -> Result<(), DbError> {
 
     Ok(())
 }
+
+// ========== ATTACHMENT (OCR) SEARCH ==========
+
+fn note_query(text: &str) -> core_rs::search::SearchQuery {
+    core_rs::search::SearchQuery {
+        query: text.to_string(),
+        entity_types: vec![core_rs::search::EntityType::All],
+        filters: Default::default(),
+        sort: Default::default(),
+        limit: None,
+        offset: None,
+    }
+}
+
+fn attach_ocr_blob(conn: &Connection, note_id: &str, blob_id: &str, text: &str) {
+    use core_rs::blob::{attach_blob_to_note, register_blob};
+    use core_rs::ocr::{index_ocr_text, queue_ocr};
+
+    register_blob(conn, blob_id, 1024, Some("image/png")).unwrap();
+    attach_blob_to_note(conn, note_id, blob_id, Some("receipt.png")).unwrap();
+    queue_ocr(conn, blob_id, &[]).unwrap();
+    // Tesseract is not available in tests; simulate the worker completing the job
+    conn.execute(
+        "UPDATE ocr_result SET status = 'completed', extracted_text = ?1 WHERE blob_id = ?2",
+        rusqlite::params![text, blob_id],
+    )
+    .unwrap();
+    index_ocr_text(conn, blob_id, text).unwrap();
+}
+
+#[test]
+fn test_search_all_finds_note_by_attachment_ocr_text() -> Result<(), DbError> {
+    let (_dir, mut conn) = setup_db();
+    let space_id = create_space(&mut conn, "test_space").unwrap();
+    let note = create_note(&conn, &space_id.to_string(), "Expenses", "March receipts").unwrap();
+    let note_id = note.id.to_string();
+
+    attach_ocr_blob(
+        &conn,
+        &note_id,
+        "blobhash01",
+        "ACME Hardware total 42.00 screwdriver",
+    );
+
+    let results = core_rs::search::search_all(&conn, &note_query("screwdriver"))?;
+    assert_eq!(results.len(), 1);
+    assert_eq!(results[0].entity_id, note_id);
+    assert_eq!(results[0].metadata["matched_in"], "ocr");
+    assert_eq!(results[0].metadata["blob_id"], "blobhash01");
+
+    // Direct matches are not duplicated and outrank attachment hits
+    let other = create_note(
+        &conn,
+        &space_id.to_string(),
+        "Tools",
+        "Need a new screwdriver and a screwdriver set",
+    )
+    .unwrap();
+    let results = core_rs::search::search_all(&conn, &note_query("screwdriver"))?;
+    assert_eq!(results.len(), 2);
+    assert_eq!(results[0].entity_id, other.id.to_string());
+    assert!(results[0].relevance_score > results[1].relevance_score);
+
+    Ok(())
+}
+
+#[test]
+fn test_reindexing_and_deleting_attachment_text() -> Result<(), DbError> {
+    let (_dir, mut conn) = setup_db();
+    let space_id = create_space(&mut conn, "test_space").unwrap();
+    let note = create_note(&conn, &space_id.to_string(), "Scan", "").unwrap();
+    let note_id = note.id.to_string();
+
+    attach_ocr_blob(&conn, &note_id, "blobhash02", "invoice from globex");
+
+    // Re-running OCR replaces the previously indexed text
+    core_rs::ocr::index_ocr_text(&conn, "blobhash02", "invoice from initech").unwrap();
+    assert!(core_rs::search::search_all(&conn, &note_query("globex"))?.is_empty());
+    assert_eq!(
+        core_rs::search::search_all(&conn, &note_query("initech"))?.len(),
+        1
+    );
+
+    // Trashed notes don't surface through their attachments
+    core_rs::note::trash_note(&conn, note.id.clone())?;
+    assert!(core_rs::search::search_all(&conn, &note_query("initech"))?.is_empty());
+    core_rs::note::restore_note(&conn, note.id.clone())?;
+
+    // Deleting the blob removes its indexed text
+    core_rs::blob::delete_blob_record(&conn, "blobhash02").unwrap();
+    assert!(core_rs::search::search_all(&conn, &note_query("initech"))?.is_empty());
+    let indexed: i64 = conn.query_row("SELECT COUNT(*) FROM fts_ocr", [], |row| row.get(0))?;
+    assert_eq!(indexed, 0);
+
+    Ok(())
+}
+
+// ========== QUERY SYNTAX ==========
+
+/// Titles of the matching notes, sorted
+fn note_titles(conn: &Connection, query: &str, space_id: &str) -> Vec<String> {
+    let mut titles: Vec<String> = search_notes(conn, query, space_id)
+        .unwrap()
+        .into_iter()
+        .map(|note| note.title)
+        .collect();
+    titles.sort();
+    titles
+}
+
+fn seed_query_notes(conn: &mut Connection) -> String {
+    let space_id = create_space(conn, "test_space").unwrap().to_string();
+    for (title, content) in [
+        ("Budget 2024", "Quarterly numbers for the finance team"),
+        ("Trip plan", "Budget for the trip, numbers pending"),
+        ("Draft budget", "Numbers quarterly, still a draft"),
+        ("Groceries", "Eggs and milk"),
+    ] {
+        create_note(conn, &space_id, title, content).unwrap();
+    }
+    let finance = create_tag(conn, &space_id, "finance", None).unwrap();
+    let note_id: String = conn
+        .query_row(
+            "SELECT id FROM note WHERE title = 'Budget 2024'",
+            [],
+            |row| row.get(0),
+        )
+        .unwrap();
+    conn.execute(
+        "INSERT INTO note_tags (note_id, tag_id) VALUES (?1, ?2)",
+        rusqlite::params![note_id, finance.id.to_string()],
+    )
+    .unwrap();
+    space_id
+}
+
+fn assert_query_semantics(conn: &Connection, space_id: &str) {
+    // Phrases need the words together and in order
+    assert_eq!(
+        note_titles(conn, "\"quarterly numbers\"", space_id),
+        vec!["Budget 2024"]
+    );
+    assert_eq!(
+        note_titles(conn, "quarterly numbers", space_id),
+        vec!["Budget 2024", "Draft budget"]
+    );
+
+    // NOT, alone or after other terms
+    assert_eq!(
+        note_titles(conn, "budget NOT draft", space_id),
+        vec!["Budget 2024", "Trip plan"]
+    );
+    assert_eq!(
+        note_titles(conn, "budget -draft -trip", space_id),
+        vec!["Budget 2024"]
+    );
+    assert_eq!(note_titles(conn, "NOT budget", space_id), vec!["Groceries"]);
+
+    // Boolean groups and field scopes
+    assert_eq!(
+        note_titles(conn, "eggs OR (trip -pending)", space_id),
+        vec!["Groceries"]
+    );
+    assert_eq!(
+        note_titles(conn, "title:budget", space_id),
+        vec!["Budget 2024", "Draft budget"]
+    );
+    assert_eq!(
+        note_titles(conn, "content:budget", space_id),
+        vec!["Trip plan"]
+    );
+    assert_eq!(
+        note_titles(conn, "budget OR -tag:finance", space_id),
+        vec!["Budget 2024", "Draft budget", "Groceries", "Trip plan"]
+    );
+    assert_eq!(
+        note_titles(conn, "numbers -tag:finance", space_id),
+        vec!["Draft budget", "Trip plan"]
+    );
+}
+
+#[test]
+fn test_search_query_syntax_with_fts() {
+    let (_dir, mut conn) = setup_db();
+    let space_id = seed_query_notes(&mut conn);
+    assert_query_semantics(&conn, &space_id);
+}
+
+#[test]
+fn test_search_query_syntax_without_fts() {
+    let (_dir, mut conn) = setup_db();
+    let space_id = seed_query_notes(&mut conn);
+    conn.execute("DROP TABLE fts_note", []).unwrap();
+    assert_query_semantics(&conn, &space_id);
+}
+
+#[test]
+fn test_search_all_uses_query_syntax() -> Result<(), DbError> {
+    let (_dir, mut conn) = setup_db();
+    let space_id = seed_query_notes(&mut conn);
+    let space = ulid::Ulid::from_string(&space_id).unwrap();
+    core_rs::task::create_task(&conn, space, "Review draft budget", None)?;
+    core_rs::task::create_task(&conn, space, "Approve budget", None)?;
+
+    let mut titles: Vec<String> = core_rs::search::search_all(&conn, &note_query("budget -draft"))?
+        .into_iter()
+        .map(|result| result.title)
+        .collect();
+    titles.sort();
+    assert_eq!(titles, vec!["Approve budget", "Budget 2024", "Trip plan"]);
+
+    let results = core_rs::search::search_all(&conn, &note_query("\"quarterly numbers\""))?;
+    assert_eq!(results.len(), 1);
+    assert_eq!(results[0].title, "Budget 2024");
+    assert!(results[0]
+        .snippet
+        .as_deref()
+        .unwrap()
+        .contains("Quarterly numbers"));
+    Ok(())
+}
+
+#[test]
+fn test_malformed_queries_return_syntax_errors() {
+    use core_rs::search::QuerySyntaxError;
+
+    let (_dir, mut conn) = setup_db();
+    let space_id = seed_query_notes(&mut conn);
+
+    match search_notes(&conn, "\"quarterly numbers", &space_id) {
+        Err(DbError::QuerySyntax(QuerySyntaxError::UnclosedQuote { position })) => {
+            assert_eq!(position, 0)
+        }
+        other => panic!("expected UnclosedQuote, got {:?}", other.map(|n| n.len())),
+    }
+    assert!(matches!(
+        search_notes(&conn, "(budget OR trip", &space_id),
+        Err(DbError::QuerySyntax(QuerySyntaxError::UnclosedParen { .. }))
+    ));
+    let error = core_rs::search::search_all(&conn, &note_query("budget trip)")).unwrap_err();
+    assert!(matches!(
+        error,
+        DbError::QuerySyntax(QuerySyntaxError::UnexpectedParen { position: 11 })
+    ));
+    assert_eq!(error.to_string(), "Unexpected ')' at position 11");
+}
+
+// ========== SAVED SEARCHES ==========
+
+#[test]
+fn test_saved_search_with_invalid_space_is_rejected() -> Result<(), DbError> {
+    use core_rs::search::{create_saved_search, execute_saved_search};
+
+    let (_dir, mut conn) = setup_db();
+    let space_id = seed_query_notes(&mut conn);
+    let search = create_saved_search(&conn, "Budget", "budget", Some(&space_id))?;
+    let id = ulid::Ulid::from_string(&search.id).unwrap();
+    assert_eq!(execute_saved_search(&conn, id)?.len(), 3);
+
+    let error = create_saved_search(&conn, "Budget", "budget", Some("not-a-ulid")).unwrap_err();
+    assert!(matches!(error, DbError::InvalidId(ref e) if e.field == "space_id"));
+
+    // A row saved before ids were checked used to run against ULID zero
+    conn.pragma_update(None, "foreign_keys", "OFF").unwrap();
+    conn.execute(
+        "UPDATE saved_search SET space_id = 'not-a-ulid' WHERE id = ?1",
+        [&search.id],
+    )?;
+    match execute_saved_search(&conn, id) {
+        Err(DbError::InvalidId(e)) => {
+            assert_eq!(e.kind, "space");
+            assert_eq!(e.value, "not-a-ulid");
+        }
+        other => panic!("expected InvalidId, got {:?}", other.map(|r| r.len())),
+    }
+    assert!(matches!(
+        execute_saved_search(&conn, ulid::Ulid::new()),
+        Err(DbError::NotFound { .. })
+    ));
+    Ok(())
+}
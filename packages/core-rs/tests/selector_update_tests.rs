@@ -0,0 +1,182 @@
+use base64::Engine;
+use core_rs::db::migrate;
+use core_rs::social::selector_verification::SPIKE_MIN_ATTEMPTS;
+use core_rs::social::*;
+use ed25519_dalek::{Signer, SigningKey};
+use rusqlite::Connection;
+
+fn setup() -> Connection {
+    let mut conn = Connection::open_in_memory().unwrap();
+    migrate(&mut conn).unwrap();
+    conn
+}
+
+fn test_key() -> SigningKey {
+    SigningKey::from_bytes(&[7u8; 32])
+}
+
+fn public_key() -> [u8; 32] {
+    test_key().verifying_key().to_bytes()
+}
+
+fn bundle(version: &str) -> String {
+    serde_json::json!({
+        "version": version,
+        "platforms": {
+            "twitter": { "post": "article[data-testid='tweet-v2']" },
+            "instagram": { "post": "article[role='presentation']" }
+        }
+    })
+    .to_string()
+}
+
+fn sign_with(key: &SigningKey, selectors: &str) -> String {
+    let signature = key.sign(selectors.as_bytes());
+    serde_json::json!({
+        "selectors": selectors,
+        "signature": base64::engine::general_purpose::STANDARD.encode(signature.to_bytes()),
+    })
+    .to_string()
+}
+
+fn signed(version: &str) -> String {
+    sign_with(&test_key(), &bundle(version))
+}
+
+fn source(conn: &Connection, platform: &str) -> (SelectorSource, String) {
+    let active = get_active_selectors(conn, platform).unwrap().unwrap();
+    (active.source, active.version)
+}
+
+#[test]
+fn test_valid_update_switches_extractors() {
+    let conn = setup();
+    assert_eq!(source(&conn, "twitter").0, SelectorSource::Bundled);
+
+    let update = install_selector_update(&conn, &signed("2.0.0"), "1.1.0", &public_key()).unwrap();
+    assert_eq!(update.version, "2.0.0");
+    assert_eq!(update.previous_version, "1.1.0");
+    let mut platforms = update.platforms.clone();
+    platforms.sort();
+    assert_eq!(platforms, vec!["instagram", "twitter"]);
+
+    let twitter = get_active_selectors(&conn, "twitter").unwrap().unwrap();
+    assert_eq!(twitter.source, SelectorSource::Update);
+    assert_eq!(twitter.version, "2.0.0");
+    assert_eq!(twitter.selectors["post"], "article[data-testid='tweet-v2']");
+    // Platforms the update doesn't cover keep the bundled selectors
+    assert_eq!(source(&conn, "reddit").0, SelectorSource::Bundled);
+    assert!(get_active_selectors(&conn, "myspace").unwrap().is_none());
+
+    let next = install_selector_update(&conn, &signed("2.1.0"), "2.0.0", &public_key()).unwrap();
+    assert_eq!(next.previous_version, "2.0.0");
+    assert_eq!(
+        source(&conn, "twitter"),
+        (SelectorSource::Update, "2.1.0".into())
+    );
+}
+
+#[test]
+fn test_bad_signature_is_rejected() {
+    let conn = setup();
+
+    let forged = sign_with(&SigningKey::from_bytes(&[9u8; 32]), &bundle("2.0.0"));
+    let err = install_selector_update(&conn, &forged, "1.1.0", &public_key()).unwrap_err();
+    assert!(matches!(err, VerificationError::InvalidSignature));
+
+    // Signed, then tampered with
+    let tampered = signed("2.0.0").replace("tweet-v2", "evil");
+    let err = install_selector_update(&conn, &tampered, "1.1.0", &public_key()).unwrap_err();
+    assert!(matches!(err, VerificationError::InvalidSignature));
+
+    let unsigned = serde_json::json!({ "selectors": bundle("2.0.0"), "signature": "" }).to_string();
+    let err = install_selector_update(&conn, &unsigned, "1.1.0", &public_key()).unwrap_err();
+    assert!(matches!(err, VerificationError::MissingSignature));
+
+    let err = install_selector_update(&conn, &signed("2.0.0"), "1.1.0", &[]).unwrap_err();
+    assert!(matches!(err, VerificationError::NoPinnedKey));
+
+    assert_eq!(source(&conn, "twitter").0, SelectorSource::Bundled);
+}
+
+#[test]
+fn test_downgrade_is_rejected() {
+    let conn = setup();
+
+    // Older than the bundled selectors
+    let err = install_selector_update(&conn, &signed("1.0.0"), "0.9.0", &public_key()).unwrap_err();
+    assert!(matches!(err, VerificationError::NotNewer { .. }));
+
+    install_selector_update(&conn, &signed("2.0.0"), "1.1.0", &public_key()).unwrap();
+    for version in ["1.5.0", "2.0.0", "2.0"] {
+        let err =
+            install_selector_update(&conn, &signed(version), "1.1.0", &public_key()).unwrap_err();
+        match err {
+            VerificationError::NotNewer { current, offered } => {
+                assert_eq!(current, "2.0.0");
+                assert_eq!(offered, version);
+            }
+            other => panic!("unexpected error: {}", other),
+        }
+    }
+
+    // The caller's version counts too
+    let err = install_selector_update(&conn, &signed("2.5.0"), "3.0.0", &public_key()).unwrap_err();
+    assert!(matches!(err, VerificationError::NotNewer { .. }));
+
+    let err = install_selector_update(&conn, &signed("two"), "1.1.0", &public_key()).unwrap_err();
+    assert!(matches!(err, VerificationError::InvalidVersion(_)));
+
+    assert_eq!(
+        source(&conn, "twitter"),
+        (SelectorSource::Update, "2.0.0".into())
+    );
+}
+
+#[test]
+fn test_failure_spike_rolls_back_to_bundled() {
+    let conn = setup();
+
+    // Bundled selectors mostly work
+    for i in 0..20 {
+        assert!(!record_extraction_result(&conn, "twitter", i % 10 != 0).unwrap());
+    }
+    install_selector_update(&conn, &signed("2.0.0"), "1.1.0", &public_key()).unwrap();
+
+    // Too few extractions to judge the update
+    for _ in 0..SPIKE_MIN_ATTEMPTS - 1 {
+        assert!(!record_extraction_result(&conn, "twitter", false).unwrap());
+    }
+    assert_eq!(source(&conn, "twitter").0, SelectorSource::Update);
+    assert!(record_extraction_result(&conn, "twitter", false).unwrap());
+
+    assert_eq!(
+        source(&conn, "twitter"),
+        (SelectorSource::Bundled, "1.1.0".into())
+    );
+    // Other platforms keep the update
+    assert_eq!(source(&conn, "instagram").0, SelectorSource::Update);
+    // Failures under the bundled selectors don't roll anything back
+    assert!(!record_extraction_result(&conn, "twitter", false).unwrap());
+
+    assert!(rollback_selectors(&conn, "instagram").unwrap());
+    assert!(!rollback_selectors(&conn, "instagram").unwrap());
+    assert_eq!(source(&conn, "instagram").0, SelectorSource::Bundled);
+
+    // A newer update gets another chance
+    install_selector_update(&conn, &signed("2.1.0"), "2.0.0", &public_key()).unwrap();
+    assert_eq!(
+        source(&conn, "twitter"),
+        (SelectorSource::Update, "2.1.0".into())
+    );
+}
+
+#[test]
+fn test_fetch_requires_https() {
+    let conn = setup();
+    let err =
+        fetch_selector_update(&conn, "http://example.com/selectors.json", "1.1.0").unwrap_err();
+    assert!(matches!(err, VerificationError::InsecureUrl(_)));
+    let err = fetch_selector_update(&conn, "not a url", "1.1.0").unwrap_err();
+    assert!(matches!(err, VerificationError::InsecureUrl(_)));
+}
@@ -0,0 +1,484 @@
+use chrono::NaiveTime;
+use core_rs::dashboard::{ComparisonWindow, DashboardConfig};
+use core_rs::db::migrations::latest_version;
+use core_rs::db::settings::*;
+use core_rs::db::{migrate, migrate_to, set_setting, QueryStatsSettings};
+use core_rs::events::{register_sink, unregister_sink, CollectingSink, CoreEvent};
+use core_rs::note_session::EditorSettings;
+use core_rs::ocr::{OcrSettings, OcrWorkerConfig};
+use core_rs::project::ProjectHealthConfig;
+use core_rs::social::{get_backup_policy, BackupError, BackupPolicy};
+use core_rs::vault::AutoLockGuard;
+use rusqlite::Connection;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tempfile::tempdir;
+
+const WAIT: Duration = Duration::from_secs(5);
+
+fn setup_db() -> Connection {
+    let mut conn = Connection::open_in_memory().unwrap();
+    migrate(&mut conn).unwrap();
+    conn
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+struct ReminderSettings {
+    lead_minutes: u32,
+    sound: bool,
+}
+
+impl Default for ReminderSettings {
+    fn default() -> Self {
+        ReminderSettings {
+            lead_minutes: 10,
+            sound: true,
+        }
+    }
+}
+
+impl Settings for ReminderSettings {
+    const KEY: &'static str = "test.reminders";
+    const DESCRIPTION: &'static str = "Reminders";
+    /// Version 1 stored the lead time in seconds as `lead_secs`
+    const VERSION: u32 = 2;
+
+    fn validate(&self) -> Result<(), String> {
+        if self.lead_minutes == 0 {
+            return Err("lead_minutes must be positive".to_string());
+        }
+        Ok(())
+    }
+
+    fn upgrade(version: u32, mut value: Value) -> Result<Value, String> {
+        if version == 1 {
+            let secs = value["lead_secs"].as_u64().ok_or("lead_secs is missing")?;
+            value["lead_minutes"] = json!(secs / 60);
+        }
+        Ok(value)
+    }
+}
+
+/// Settings only the notification test saves, since every test's events
+/// reach every sink
+#[derive(Debug, Default, PartialEq, Serialize, Deserialize)]
+struct QuietHours {
+    start_hour: u8,
+}
+
+impl Settings for QuietHours {
+    const KEY: &'static str = "test.quiet_hours";
+    const DESCRIPTION: &'static str = "Quiet hours";
+
+    fn validate(&self) -> Result<(), String> {
+        if self.start_hour > 23 {
+            return Err("start_hour must be below 24".to_string());
+        }
+        Ok(())
+    }
+}
+
+/// Marks the end of the notification test's events: events are delivered in
+/// order, so once this one arrives every earlier one has
+#[derive(Debug, Default, PartialEq, Serialize, Deserialize)]
+struct Marker {
+    n: u32,
+}
+
+impl Settings for Marker {
+    const KEY: &'static str = "test.marker";
+    const DESCRIPTION: &'static str = "Marker";
+}
+
+fn changed_keys(sink: &CollectingSink) -> Vec<String> {
+    sink.events()
+        .into_iter()
+        .filter_map(|e| match e.event {
+            CoreEvent::SettingsChanged { key } if key == QuietHours::KEY || key == Marker::KEY => {
+                Some(key)
+            }
+            _ => None,
+        })
+        .collect()
+}
+
+fn invalid(result: Result<(), SettingsError>, expected_key: &str) -> bool {
+    matches!(result, Err(SettingsError::Invalid { key, .. }) if key == expected_key)
+}
+
+fn backup_policy() -> BackupPolicy {
+    BackupPolicy {
+        enabled: true,
+        interval_hours: 12,
+        time_of_day: Some(NaiveTime::from_hms_opt(3, 30, 0).unwrap()),
+        max_count: 5,
+        max_age_days: 60,
+        target_dir: PathBuf::from("/backups/noteece"),
+    }
+}
+
+#[test]
+fn test_defaults_apply_until_saved() {
+    let conn = setup_db();
+    assert_eq!(
+        load_settings::<OcrSettings>(&conn).unwrap(),
+        OcrSettings::default()
+    );
+    assert_eq!(
+        load_settings::<DashboardConfig>(&conn).unwrap(),
+        DashboardConfig::default()
+    );
+    assert_eq!(
+        load_settings::<ProjectHealthConfig>(&conn).unwrap(),
+        ProjectHealthConfig::default()
+    );
+    assert_eq!(load_stored_settings::<BackupPolicy>(&conn).unwrap(), None);
+    // New vaults start with the sync port stored, as before
+    assert_eq!(load_settings::<SyncSettings>(&conn).unwrap().port, 8765);
+
+    let ocr = OcrSettings::default();
+    let config = OcrWorkerConfig::new("/vault", Arc::new(AutoLockGuard::new([0u8; 32])));
+    assert_eq!(config.concurrency, ocr.concurrency);
+    assert_eq!(config.max_attempts, ocr.max_attempts);
+    assert_eq!(config.retry_backoff_secs, ocr.retry_backoff_secs);
+    assert_eq!(config.poll_interval.as_secs(), ocr.poll_interval_secs);
+}
+
+#[test]
+fn test_invalid_settings_are_rejected() {
+    let conn = setup_db();
+    assert!(invalid(
+        save_settings(
+            &conn,
+            &SyncSettings {
+                port: 0,
+                ..Default::default()
+            }
+        ),
+        "sync.settings"
+    ));
+    for ocr in [
+        OcrSettings {
+            concurrency: 0,
+            ..Default::default()
+        },
+        OcrSettings {
+            concurrency: 64,
+            ..Default::default()
+        },
+        OcrSettings {
+            max_attempts: 0,
+            ..Default::default()
+        },
+        OcrSettings {
+            retry_backoff_secs: -5,
+            ..Default::default()
+        },
+        OcrSettings {
+            poll_interval_secs: 0,
+            ..Default::default()
+        },
+        OcrSettings {
+            languages: vec!["not a language".to_string()],
+            ..Default::default()
+        },
+    ] {
+        assert!(
+            invalid(save_settings(&conn, &ocr), "ocr.settings"),
+            "{:?}",
+            ocr
+        );
+    }
+    assert!(invalid(
+        save_settings(
+            &conn,
+            &BackupPolicy {
+                interval_hours: 0,
+                ..backup_policy()
+            }
+        ),
+        "backup.policy"
+    ));
+    assert!(invalid(
+        save_settings(
+            &conn,
+            &ProjectHealthConfig {
+                on_track_min_score: 40.0,
+                ..Default::default()
+            }
+        ),
+        "project.health_config"
+    ));
+
+    // Nothing was stored
+    assert_eq!(load_settings::<SyncSettings>(&conn).unwrap().port, 8765);
+    assert_eq!(load_stored_settings::<OcrSettings>(&conn).unwrap(), None);
+    assert_eq!(load_stored_settings::<BackupPolicy>(&conn).unwrap(), None);
+
+    // Values stored some other way are checked as they're loaded
+    set_setting(&conn, "sync.settings", "{\"port\":0}", None).unwrap();
+    assert!(matches!(
+        load_settings::<SyncSettings>(&conn),
+        Err(SettingsError::Invalid { .. })
+    ));
+    set_setting(&conn, "backup.policy", "{\"enabled\":\"yes\"}", None).unwrap();
+    assert!(matches!(
+        get_backup_policy(&conn),
+        Err(BackupError::InvalidPolicy(_))
+    ));
+}
+
+#[test]
+fn test_each_settings_struct_round_trips() {
+    let conn = setup_db();
+
+    let sync = SyncSettings {
+        port: 49152,
+        ..Default::default()
+    };
+    save_settings(&conn, &sync).unwrap();
+    assert_eq!(load_settings::<SyncSettings>(&conn).unwrap(), sync);
+
+    let ocr = OcrSettings {
+        languages: vec!["de".to_string(), "en-GB".to_string()],
+        concurrency: 4,
+        max_attempts: 5,
+        retry_backoff_secs: 120,
+        poll_interval_secs: 10,
+    };
+    save_settings(&conn, &ocr).unwrap();
+    assert_eq!(load_settings::<OcrSettings>(&conn).unwrap(), ocr);
+    let config =
+        OcrWorkerConfig::from_settings("/vault", Arc::new(AutoLockGuard::new([0u8; 32])), &ocr);
+    assert_eq!(config.languages, ocr.languages);
+    assert_eq!(config.poll_interval, Duration::from_secs(10));
+
+    let policy = backup_policy();
+    save_settings(&conn, &policy).unwrap();
+    assert_eq!(get_backup_policy(&conn).unwrap(), Some(policy.clone()));
+
+    let dashboard = DashboardConfig {
+        comparison_window: ComparisonWindow::Month,
+    };
+    save_settings(&conn, &dashboard).unwrap();
+    assert_eq!(load_settings::<DashboardConfig>(&conn).unwrap(), dashboard);
+
+    let health = ProjectHealthConfig {
+        velocity_weight: 0.5,
+        stale_update_days: 30,
+        ..Default::default()
+    };
+    save_settings(&conn, &health).unwrap();
+    assert_eq!(load_settings::<ProjectHealthConfig>(&conn).unwrap(), health);
+
+    let editor = EditorSettings {
+        version_interval_secs: 300,
+    };
+    save_settings(&conn, &editor).unwrap();
+    assert_eq!(load_settings::<EditorSettings>(&conn).unwrap(), editor);
+
+    let query_stats = QueryStatsSettings {
+        enabled: true,
+        slow_query_ms: 250,
+    };
+    save_settings(&conn, &query_stats).unwrap();
+    assert_eq!(
+        load_settings::<QueryStatsSettings>(&conn).unwrap(),
+        query_stats
+    );
+
+    let entries = list_all_settings(&conn).unwrap();
+    let keys: Vec<&str> = entries.iter().map(|e| e.key.as_str()).collect();
+    assert_eq!(
+        keys,
+        vec![
+            "backup.policy",
+            "dashboard.config",
+            "diagnostics.query_stats",
+            "editor.settings",
+            "ocr.settings",
+            "project.health_config",
+            "sync.settings"
+        ]
+    );
+    assert!(entries
+        .iter()
+        .all(|e| !e.is_default && e.error.is_none() && e.updated_at.is_some()));
+    assert_eq!(entries[4].value, serde_json::to_value(&ocr).unwrap());
+    assert_eq!(
+        entries[6].value,
+        json!({ "port": 49152, "allow_legacy_protocol": false })
+    );
+
+    reset_settings::<OcrSettings>(&conn).unwrap();
+    assert_eq!(
+        load_settings::<OcrSettings>(&conn).unwrap(),
+        OcrSettings::default()
+    );
+    let entries = list_all_settings(&conn).unwrap();
+    assert!(entries[4].is_default);
+    assert_eq!(entries[4].updated_at, None);
+}
+
+#[test]
+fn test_list_reports_unreadable_settings() {
+    let conn = setup_db();
+    set_setting(
+        &conn,
+        "dashboard.config",
+        "{\"comparison_window\":\"year\"}",
+        None,
+    )
+    .unwrap();
+
+    let entries = list_all_settings(&conn).unwrap();
+    let dashboard = entries
+        .iter()
+        .find(|e| e.key == "dashboard.config")
+        .unwrap();
+    assert!(!dashboard.is_default);
+    assert_eq!(dashboard.value, json!({ "comparison_window": "year" }));
+    assert!(dashboard
+        .error
+        .as_deref()
+        .unwrap()
+        .contains("dashboard.config"));
+}
+
+#[test]
+fn test_older_values_are_upgraded_and_newer_refused() {
+    let conn = setup_db();
+    conn.execute(
+        "INSERT INTO settings (key, value, schema_version, updated_at, created_at)
+         VALUES ('test.reminders', '{\"lead_secs\":900,\"sound\":false}', 1, 1, 1)",
+        [],
+    )
+    .unwrap();
+    let upgraded = ReminderSettings {
+        lead_minutes: 15,
+        sound: false,
+    };
+    assert_eq!(load_settings::<ReminderSettings>(&conn).unwrap(), upgraded);
+
+    // Saving the same settings writes them in the current shape
+    save_settings(&conn, &upgraded).unwrap();
+    let (value, version): (String, u32) = conn
+        .query_row(
+            "SELECT value, schema_version FROM settings WHERE key = 'test.reminders'",
+            [],
+            |row| Ok((row.get(0)?, row.get(1)?)),
+        )
+        .unwrap();
+    assert_eq!(version, 2);
+    assert_eq!(
+        serde_json::from_str::<Value>(&value).unwrap(),
+        json!({ "lead_minutes": 15, "sound": false })
+    );
+
+    conn.execute(
+        "UPDATE settings SET schema_version = 3 WHERE key = 'test.reminders'",
+        [],
+    )
+    .unwrap();
+    assert!(matches!(
+        load_settings::<ReminderSettings>(&conn),
+        Err(SettingsError::UnsupportedVersion { version: 3, .. })
+    ));
+}
+
+#[test]
+fn test_saves_notify_watchers() {
+    let conn = setup_db();
+    let sink = Arc::new(CollectingSink::default());
+    let sink_id = register_sink(sink.clone());
+    let watcher = SettingsWatcher::new::<QuietHours>();
+
+    let evenings = QuietHours { start_hour: 21 };
+    save_settings(&conn, &evenings).unwrap();
+    let deadline = Instant::now() + WAIT;
+    while !watcher.changed() {
+        assert!(Instant::now() < deadline, "no change notification");
+        std::thread::sleep(Duration::from_millis(10));
+    }
+
+    // Saving what's already stored isn't a change; rejected saves aren't either
+    save_settings(&conn, &evenings).unwrap();
+    assert!(save_settings(&conn, &QuietHours { start_hour: 25 }).is_err());
+    reset_settings::<QuietHours>(&conn).unwrap();
+    reset_settings::<QuietHours>(&conn).unwrap();
+
+    save_settings(&conn, &Marker { n: 1 }).unwrap();
+    sink.wait_for(
+        WAIT,
+        |e| matches!(e, CoreEvent::SettingsChanged { key } if key == Marker::KEY),
+    )
+    .expect("marker event");
+    unregister_sink(sink_id);
+
+    assert_eq!(
+        changed_keys(&sink),
+        vec!["test.quiet_hours", "test.quiet_hours", "test.marker"]
+    );
+    // The reset arrived after the first check
+    assert!(watcher.changed());
+    assert!(!watcher.changed());
+}
+
+#[test]
+fn test_legacy_settings_keys_are_migrated() {
+    let dir = tempdir().unwrap();
+    let mut conn = Connection::open(dir.path().join("vault.db")).unwrap();
+    migrate(&mut conn).unwrap();
+    migrate_to(&mut conn, 66, &dir.path().join("before-downgrade.db")).unwrap();
+
+    set_setting(&conn, "sync_port", "9123", None).unwrap();
+    set_setting(
+        &conn,
+        "backup_policy",
+        &serde_json::to_string(&backup_policy()).unwrap(),
+        None,
+    )
+    .unwrap();
+    set_setting(
+        &conn,
+        "dashboard_config",
+        "{\"comparison_window\":\"month\"}",
+        None,
+    )
+    .unwrap();
+    migrate_to(
+        &mut conn,
+        latest_version(),
+        &dir.path().join("before-upgrade.db"),
+    )
+    .unwrap();
+
+    assert_eq!(load_settings::<SyncSettings>(&conn).unwrap().port, 9123);
+    assert_eq!(get_backup_policy(&conn).unwrap(), Some(backup_policy()));
+    assert_eq!(
+        load_settings::<DashboardConfig>(&conn)
+            .unwrap()
+            .comparison_window,
+        ComparisonWindow::Month
+    );
+
+    // And back again for older versions of the app
+    migrate_to(
+        &mut conn,
+        66,
+        &dir.path().join("before-second-downgrade.db"),
+    )
+    .unwrap();
+    let port: String = conn
+        .query_row(
+            "SELECT value FROM settings WHERE key = 'sync_port'",
+            [],
+            |row| row.get(0),
+        )
+        .unwrap();
+    assert_eq!(port, "9123");
+}
@@ -0,0 +1,287 @@
+use core_rs::calendar::TimeRange;
+use core_rs::db::migrate;
+use core_rs::social::*;
+use rusqlite::Connection;
+
+/// Monday 2024-01-01 00:00 UTC, in seconds
+const MONDAY: i64 = 1_704_067_200;
+const HOUR: i64 = 3_600;
+const DAY: i64 = 24 * HOUR;
+
+struct Seeded {
+    conn: Connection,
+    space_id: String,
+    twitter: SocialAccount,
+    mastodon: SocialAccount,
+}
+
+fn seed() -> Seeded {
+    let mut conn = Connection::open_in_memory().unwrap();
+    migrate(&mut conn).unwrap();
+    let space_id = core_rs::space::create_space(&mut conn, "Social")
+        .unwrap()
+        .to_string();
+    let twitter =
+        add_social_account(&conn, &space_id, "twitter", "me", None, "token", &[0u8; 32]).unwrap();
+    let mastodon = add_social_account(
+        &conn, &space_id, "mastodon", "me", None, "token", &[0u8; 32],
+    )
+    .unwrap();
+    Seeded {
+        conn,
+        space_id,
+        twitter,
+        mastodon,
+    }
+}
+
+/// Insert a post made at `at` (seconds) with the given likes, comments and
+/// shares, returning its id
+fn add_post(
+    conn: &Connection,
+    account: &SocialAccount,
+    at: i64,
+    (likes, comments, shares): (i64, i64, i64),
+) -> String {
+    let id = ulid::Ulid::new().to_string();
+    conn.execute(
+        "INSERT INTO social_post (id, account_id, platform, platform_post_id, author, content,
+                                  timestamp, fetched_at, first_seen_at, raw_json,
+                                  likes, comments, shares)
+         VALUES (?1, ?2, ?3, ?1, 'Author', 'Post', ?4, ?4, ?4, '{}', ?5, ?6, ?7)",
+        rusqlite::params![
+            id,
+            account.id,
+            account.platform,
+            at * 1000,
+            likes,
+            comments,
+            shares
+        ],
+    )
+    .unwrap();
+    id
+}
+
+fn week(start: i64) -> TimeRange {
+    TimeRange {
+        start,
+        end: start + 7 * DAY,
+    }
+}
+
+#[test]
+fn test_daily_series_sums_engagement_and_fills_empty_days() {
+    let s = seed();
+    add_post(&s.conn, &s.twitter, MONDAY + 9 * HOUR, (10, 2, 1));
+    add_post(&s.conn, &s.mastodon, MONDAY + 20 * HOUR, (5, 0, 3));
+    add_post(&s.conn, &s.twitter, MONDAY + 2 * DAY + HOUR, (1, 1, 1));
+    // Outside the range
+    add_post(&s.conn, &s.twitter, MONDAY + 7 * DAY, (100, 0, 0));
+
+    let series = get_engagement_timeseries(
+        &s.conn,
+        &s.space_id,
+        None,
+        week(MONDAY),
+        TimeBucket::Day,
+        &EngagementOptions::default(),
+    )
+    .unwrap();
+    assert_eq!(series.len(), 7);
+    assert_eq!(
+        series[0],
+        TimeSeriesPoint {
+            date: "2024-01-01".to_string(),
+            count: 2,
+            likes: 15,
+            comments: 2,
+            shares: 4,
+        }
+    );
+    assert_eq!(series[1].date, "2024-01-02");
+    assert_eq!(series[1].count, 0);
+    assert_eq!(series[2].likes, 1);
+    assert_eq!(series[6].date, "2024-01-07");
+    assert_eq!(series.iter().map(|p| p.count).sum::<i64>(), 3);
+
+    // Only one platform
+    let series = get_engagement_timeseries(
+        &s.conn,
+        &s.space_id,
+        Some("mastodon"),
+        week(MONDAY),
+        TimeBucket::Day,
+        &EngagementOptions::default(),
+    )
+    .unwrap();
+    assert_eq!(series[0].count, 1);
+    assert_eq!(series[0].likes, 5);
+    assert_eq!(series[2].count, 0);
+}
+
+#[test]
+fn test_utc_offset_moves_posts_between_days() {
+    let s = seed();
+    // Monday 22:00 UTC is Tuesday 01:00 at UTC+3
+    add_post(&s.conn, &s.twitter, MONDAY + 22 * HOUR, (4, 0, 0));
+
+    let options = EngagementOptions {
+        utc_offset_minutes: 180,
+        ..Default::default()
+    };
+    let series = get_engagement_timeseries(
+        &s.conn,
+        &s.space_id,
+        None,
+        week(MONDAY - 3 * HOUR),
+        TimeBucket::Day,
+        &options,
+    )
+    .unwrap();
+    assert_eq!(series.len(), 7);
+    assert_eq!(series[0].date, "2024-01-01");
+    assert_eq!(series[0].count, 0);
+    assert_eq!(series[1].date, "2024-01-02");
+    assert_eq!(series[1].likes, 4);
+
+    let options = EngagementOptions {
+        utc_offset_minutes: 15 * 60,
+        ..Default::default()
+    };
+    assert!(matches!(
+        get_engagement_timeseries(
+            &s.conn,
+            &s.space_id,
+            None,
+            week(MONDAY),
+            TimeBucket::Day,
+            &options
+        ),
+        Err(SocialError::InvalidInput(_))
+    ));
+}
+
+#[test]
+fn test_weekly_series_is_dated_by_monday() {
+    let s = seed();
+    // Sunday of the first week, Wednesday and Thursday of the third
+    add_post(&s.conn, &s.twitter, MONDAY + 6 * DAY + 23 * HOUR, (1, 0, 0));
+    add_post(&s.conn, &s.twitter, MONDAY + 16 * DAY, (2, 0, 0));
+    add_post(&s.conn, &s.twitter, MONDAY + 17 * DAY, (3, 0, 0));
+
+    // From a Thursday into the Thursday two weeks later
+    let range = TimeRange {
+        start: MONDAY + 3 * DAY,
+        end: MONDAY + 17 * DAY + 1,
+    };
+    let series = get_engagement_timeseries(
+        &s.conn,
+        &s.space_id,
+        None,
+        range,
+        TimeBucket::Week,
+        &EngagementOptions::default(),
+    )
+    .unwrap();
+    let dates: Vec<&str> = series.iter().map(|p| p.date.as_str()).collect();
+    assert_eq!(dates, ["2024-01-01", "2024-01-08", "2024-01-15"]);
+    assert_eq!(series[0].likes, 1);
+    assert_eq!(series[1].count, 0);
+    assert_eq!(series[2].count, 2);
+    assert_eq!(series[2].likes, 5);
+}
+
+#[test]
+fn test_series_can_be_limited_to_categories() {
+    let s = seed();
+    let work = create_category(&s.conn, &s.space_id, "Work", None, None, None).unwrap();
+    let post = add_post(&s.conn, &s.twitter, MONDAY + HOUR, (7, 0, 0));
+    add_post(&s.conn, &s.twitter, MONDAY + 2 * HOUR, (9, 0, 0));
+    assign_category(&s.conn, &post, &work.id, "manual").unwrap();
+
+    let options = EngagementOptions {
+        categories: vec![work.id.clone()],
+        ..Default::default()
+    };
+    let series = get_engagement_timeseries(
+        &s.conn,
+        &s.space_id,
+        None,
+        week(MONDAY),
+        TimeBucket::Day,
+        &options,
+    )
+    .unwrap();
+    assert_eq!(series[0].count, 1);
+    assert_eq!(series[0].likes, 7);
+}
+
+#[test]
+fn test_posting_time_analysis_ignores_sparse_buckets() {
+    let s = seed();
+    let range = TimeRange {
+        start: MONDAY,
+        end: MONDAY + 28 * DAY,
+    };
+    // Tuesdays at 09:00, five weeks of samples but four in range
+    for week in 0..5 {
+        add_post(
+            &s.conn,
+            &s.twitter,
+            MONDAY + week * 7 * DAY + DAY + 9 * HOUR,
+            (10, 0, 0),
+        );
+    }
+    // Fridays at 18:00, enough samples
+    for week in 0..4 {
+        for minute in [0, 10] {
+            add_post(
+                &s.conn,
+                &s.mastodon,
+                MONDAY + week * 7 * DAY + 4 * DAY + 18 * HOUR + minute * 60,
+                (20, 4, 0),
+            );
+        }
+    }
+    // Sunday at 12:00, a single viral post
+    add_post(
+        &s.conn,
+        &s.twitter,
+        MONDAY + 6 * DAY + 12 * HOUR,
+        (900, 0, 0),
+    );
+
+    let analysis =
+        get_posting_time_analysis(&s.conn, &s.space_id, None, range, &Default::default()).unwrap();
+    assert_eq!(analysis.cells.len(), 7 * 24);
+    assert_eq!(analysis.min_samples, MIN_POSTING_TIME_SAMPLES);
+
+    let tuesday = &analysis.cells[24 + 9];
+    assert_eq!((tuesday.weekday, tuesday.hour), (1, 9));
+    assert_eq!(tuesday.post_count, 4);
+    assert!(tuesday.low_confidence);
+
+    let sunday = &analysis.cells[6 * 24 + 12];
+    assert_eq!(sunday.avg_engagement, 900.0);
+    assert!(sunday.low_confidence);
+
+    let best = analysis.best.unwrap();
+    assert_eq!((best.weekday, best.hour), (4, 18));
+    assert_eq!(best.post_count, 8);
+    assert_eq!(best.avg_engagement, 24.0);
+    assert!(!best.low_confidence);
+
+    // Nothing on twitter has enough samples
+    let twitter = get_posting_time_analysis(
+        &s.conn,
+        &s.space_id,
+        Some("twitter"),
+        range,
+        &Default::default(),
+    )
+    .unwrap();
+    assert!(twitter.best.is_none());
+    assert!(twitter.cells[4 * 24 + 18].low_confidence);
+    assert_eq!(twitter.cells[4 * 24 + 18].post_count, 0);
+}
@@ -0,0 +1,192 @@
+use core_rs::blob::{add_blob_ref, get_note_attachments, insert_blob, retrieve_blob, BlobOwner};
+use core_rs::db::migrate;
+use core_rs::note::get_note;
+use core_rs::social::*;
+use core_rs::tag::get_notes_with_tag;
+use rusqlite::Connection;
+use tempfile::tempdir;
+use ulid::Ulid;
+
+const MK: &[u8] = b"test-master-key-that-is-32-bytes";
+
+fn setup() -> (Connection, String, SocialAccount) {
+    let mut conn = Connection::open_in_memory().unwrap();
+    migrate(&mut conn).unwrap();
+    let space_id = core_rs::space::create_space(&mut conn, "Social")
+        .unwrap()
+        .to_string();
+    let account =
+        add_social_account(&conn, &space_id, "twitter", "me", None, "token", &[0u8; 32]).unwrap();
+    (conn, space_id, account)
+}
+
+fn post(platform_post_id: &str, content: &str, timestamp: i64) -> SocialPost {
+    SocialPost {
+        id: String::new(),
+        account_id: String::new(),
+        platform: "twitter".to_string(),
+        platform_post_id: Some(platform_post_id.to_string()),
+        author: "Alice".to_string(),
+        author_handle: Some("@alice".to_string()),
+        content: Some(content.to_string()),
+        content_html: None,
+        media_urls: vec![],
+        timestamp,
+        fetched_at: 0,
+        engagement: Engagement {
+            likes: Some(12),
+            shares: Some(3),
+            comments: None,
+            views: None,
+        },
+        post_type: None,
+        reply_to: None,
+    }
+}
+
+fn store(conn: &mut Connection, account: &SocialAccount, post: SocialPost) -> String {
+    let platform_post_id = post.platform_post_id.clone().unwrap();
+    store_social_posts(conn, &account.id, vec![post]).unwrap();
+    conn.query_row(
+        "SELECT id FROM social_post WHERE platform_post_id = ?1",
+        [platform_post_id],
+        |row| row.get(0),
+    )
+    .unwrap()
+}
+
+fn post_exists(conn: &Connection, post_id: &str) -> bool {
+    conn.query_row(
+        "SELECT COUNT(*) FROM social_post WHERE id = ?1",
+        [post_id],
+        |row| row.get::<_, i64>(0),
+    )
+    .unwrap()
+        == 1
+}
+
+#[test]
+fn test_archive_post_with_media() {
+    let dir = tempdir().unwrap();
+    let vault = dir.path().to_str().unwrap();
+    let (mut conn, space_id, account) = setup();
+    let mut tweet = post(
+        "1001",
+        "Sunset over the bay",
+        chrono::Utc::now().timestamp_millis(),
+    );
+    tweet.media_urls = vec!["https://pbs.twimg.com/media/sunset.jpg".to_string()];
+    let post_id = store(&mut conn, &account, tweet);
+    let blob_id = insert_blob(&conn, vault, MK, b"jpeg bytes", Some("image/jpeg")).unwrap();
+    add_blob_ref(&conn, &blob_id, BlobOwner::SocialPost, &post_id).unwrap();
+
+    let note =
+        archive_post_to_note(&mut conn, &post_id, &space_id, &ArchiveOptions::default()).unwrap();
+    assert_eq!(note.space_id, space_id);
+    assert_eq!(note.title, "Alice on Twitter / X: Sunset over the bay");
+    for expected in [
+        "platform: \"twitter\"",
+        "author_handle: \"@alice\"",
+        "url: \"https://x.com/alice/status/1001\"",
+        "likes: 12",
+        "shares: 3",
+        "tags: [\"social-archive\"]",
+        "Sunset over the bay",
+        "- https://pbs.twimg.com/media/sunset.jpg",
+    ] {
+        assert!(note.content_md.contains(expected), "missing {}", expected);
+    }
+    assert!(note.content_md.starts_with("---\n"));
+
+    let space = Ulid::from_string(&space_id).unwrap();
+    let tagged = get_notes_with_tag(&conn, space, "social-archive", false).unwrap();
+    assert_eq!(tagged.len(), 1);
+
+    // The media now belongs to the note too, and outlives the post
+    let attachments = get_note_attachments(&conn, &note.id.0.to_string()).unwrap();
+    assert_eq!(attachments.len(), 1);
+    assert_eq!(attachments[0].blob_id, blob_id);
+    conn.execute("DELETE FROM social_post WHERE id = ?1", [&post_id])
+        .unwrap();
+    assert_eq!(
+        core_rs::blob::get_blob_ref_count(&conn, &blob_id).unwrap(),
+        1
+    );
+    assert_eq!(retrieve_blob(vault, MK, &blob_id).unwrap(), b"jpeg bytes");
+}
+
+#[test]
+fn test_archived_posts_are_not_pruned() {
+    let (mut conn, space_id, account) = setup();
+    let month_ago = chrono::Utc::now().timestamp() - 30 * 24 * 60 * 60;
+    let kept = store(&mut conn, &account, post("1", "Worth keeping", month_ago));
+    let pruned = store(&mut conn, &account, post("2", "Forgettable", month_ago));
+    archive_post_to_note(&mut conn, &kept, &space_id, &ArchiveOptions::default()).unwrap();
+
+    assert_eq!(prune_old_posts(&mut conn, 7).unwrap(), 1);
+    assert!(post_exists(&conn, &kept));
+    assert!(!post_exists(&conn, &pruned));
+
+    let stale = store(&mut conn, &account, post("4", "Old news", month_ago));
+    assert_eq!(
+        delete_old_posts(&conn, &account.id, chrono::Utc::now().timestamp()).unwrap(),
+        1
+    );
+    assert!(post_exists(&conn, &kept));
+    assert!(!post_exists(&conn, &stale));
+
+    // Deleting the note lets the post go
+    let note = get_archived_note(&conn, &kept).unwrap().unwrap();
+    conn.execute("DELETE FROM note WHERE id = ?1", [note.id.0.to_string()])
+        .unwrap();
+    assert_eq!(prune_old_posts(&mut conn, 7).unwrap(), 1);
+    assert!(!post_exists(&conn, &kept));
+}
+
+#[test]
+fn test_rearchiving_updates_the_same_note() {
+    let (mut conn, space_id, account) = setup();
+    let now = chrono::Utc::now().timestamp_millis();
+    let post_id = store(&mut conn, &account, post("7", "Launch day!", now));
+    let options = ArchiveOptions {
+        tag: "#saved/launches".to_string(),
+    };
+    let first = archive_post_to_note(&mut conn, &post_id, &space_id, &options).unwrap();
+    let space = Ulid::from_string(&space_id).unwrap();
+
+    // More likes since
+    let mut refreshed = post("7", "Launch day!", now);
+    refreshed.engagement.likes = Some(500);
+    store_social_posts(&mut conn, &account.id, vec![refreshed]).unwrap();
+    let second = archive_post_to_note(&mut conn, &post_id, &space_id, &options).unwrap();
+
+    assert_eq!(second.id.0, first.id.0);
+    assert!(second.content_md.contains("likes: 500"));
+    assert_eq!(
+        get_note(&conn, first.id.clone())
+            .unwrap()
+            .unwrap()
+            .content_md,
+        second.content_md
+    );
+    let notes: i64 = conn
+        .query_row("SELECT COUNT(*) FROM note", [], |row| row.get(0))
+        .unwrap();
+    assert_eq!(notes, 1);
+    assert_eq!(
+        get_notes_with_tag(&conn, space, "saved/launches", false)
+            .unwrap()
+            .len(),
+        1
+    );
+
+    // Batch archival over a category reuses it as well
+    let category = create_category(&conn, &space_id, "Launches", None, None, None).unwrap();
+    let other = store(&mut conn, &account, post("8", "Beta opens", now + 1));
+    assign_category(&conn, &post_id, &category.id, "manual").unwrap();
+    assign_category(&conn, &other, &category.id, "manual").unwrap();
+    let notes = archive_category_to_notes(&mut conn, &category.id, &options).unwrap();
+    assert_eq!(notes.len(), 2);
+    assert_eq!(notes[0].id.0, first.id.0);
+    assert_eq!(notes[1].title, "Alice on Twitter / X: Beta opens");
+}
@@ -0,0 +1,213 @@
+use core_rs::db::migrate;
+use core_rs::social::*;
+use rusqlite::Connection;
+use std::time::Duration;
+
+fn setup() -> (Connection, SocialAccount) {
+    let mut conn = Connection::open_in_memory().unwrap();
+    migrate(&mut conn).unwrap();
+    let space_id = core_rs::space::create_space(&mut conn, "Social")
+        .unwrap()
+        .to_string();
+    let account =
+        add_social_account(&conn, &space_id, "twitter", "me", None, "token", &[0u8; 32]).unwrap();
+    (conn, account)
+}
+
+fn post(platform_post_id: Option<String>, content: &str, likes: i64, timestamp: i64) -> SocialPost {
+    SocialPost {
+        id: String::new(),
+        account_id: String::new(),
+        platform: "twitter".to_string(),
+        platform_post_id,
+        author: "Alice".to_string(),
+        author_handle: Some("@alice".to_string()),
+        content: Some(content.to_string()),
+        content_html: None,
+        media_urls: vec![],
+        timestamp,
+        fetched_at: 0,
+        engagement: Engagement {
+            likes: Some(likes),
+            shares: Some(1),
+            comments: None,
+            views: None,
+        },
+        post_type: None,
+        reply_to: None,
+    }
+}
+
+/// A long timeline as an extractor would scrape it: mostly new posts, some
+/// seen again with fresh engagement, some repeated as-is, a few unusable
+fn timeline() -> Vec<SocialPost> {
+    let base = chrono::Utc::now().timestamp_millis() - 3_600_000;
+    let mut posts = Vec::new();
+    for i in 0..1000i64 {
+        let timestamp = base - i * 1000;
+        match i % 50 {
+            // Seen again after scrolling back, with more likes
+            7 if i >= 50 => posts.push(post(
+                Some(format!("{}", i - 50)),
+                &format!("Tweet {}", i - 50),
+                99,
+                timestamp,
+            )),
+            // Repeated exactly
+            13 if i >= 50 => posts.push(posts[(i - 40) as usize].clone()),
+            // From the future
+            21 => posts.push(post(Some(format!("{}", i)), "Later", 0, base + 86_400_000)),
+            30 => posts.push(post(None, &format!("Untracked {}", i), 1, timestamp)),
+            _ => posts.push(post(
+                Some(format!("{}", i)),
+                &format!("Tweet {}", i),
+                i % 17,
+                timestamp,
+            )),
+        }
+    }
+    posts
+}
+
+fn stored_rows(conn: &Connection) -> Vec<(Option<String>, Option<String>, Option<i64>)> {
+    conn.prepare(
+        "SELECT platform_post_id, content, likes FROM social_post
+         ORDER BY platform_post_id, content",
+    )
+    .unwrap()
+    .query_map([], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))
+    .unwrap()
+    .collect::<Result<_, _>>()
+    .unwrap()
+}
+
+#[test]
+fn test_chunked_extraction_matches_one_shot_with_bounded_buffer() {
+    let posts = timeline();
+
+    let (mut one_shot, account) = setup();
+    let expected = store_social_posts(&mut one_shot, &account.id, posts.clone()).unwrap();
+    assert!(expected.updated > 0 && expected.skipped > 0);
+
+    let (mut conn, account) = setup();
+    let mut sessions = ExtractionSessions::new(64, DEFAULT_SESSION_IDLE_TIMEOUT);
+    let session_id = sessions.begin_extraction_session(&account.id);
+    for (i, chunk) in posts.chunks(20).enumerate() {
+        // Alternate between both payload formats
+        let payload = if i % 2 == 0 {
+            serde_json::to_string(chunk).unwrap()
+        } else {
+            chunk
+                .iter()
+                .map(|p| serde_json::to_string(p).unwrap())
+                .collect::<Vec<_>>()
+                .join("\n")
+        };
+        let outcome = sessions
+            .ingest_chunk(&mut conn, &session_id, &payload)
+            .unwrap();
+        assert_eq!(outcome.chunk, i + 1);
+        assert_eq!(outcome.posts_parsed, 20);
+        assert!(outcome.error.is_none());
+        assert!(outcome.buffered < 64);
+    }
+    let summary = sessions
+        .finish_extraction_session(&mut conn, &session_id)
+        .unwrap();
+
+    assert_eq!(summary.chunks, 50);
+    assert!(summary.failed_chunks.is_empty());
+    assert_eq!(summary.posts_parsed, 1000);
+    assert!(
+        summary.peak_buffered <= 64,
+        "peak {}",
+        summary.peak_buffered
+    );
+    assert_eq!(summary.flushes, 1000usize.div_ceil(64));
+    assert_eq!(summary.stored, expected);
+    assert_eq!(stored_rows(&conn), stored_rows(&one_shot));
+    assert!(sessions.is_empty());
+}
+
+#[test]
+fn test_malformed_chunk_does_not_poison_session() {
+    let (mut conn, account) = setup();
+    let now = chrono::Utc::now().timestamp_millis() - 60_000;
+    let mut sessions = ExtractionSessions::default();
+    let session_id = sessions.begin_extraction_session(&account.id);
+
+    let first = serde_json::to_string(&[post(Some("1".into()), "One", 1, now)]).unwrap();
+    sessions
+        .ingest_chunk(&mut conn, &session_id, &first)
+        .unwrap();
+
+    // Truncated after the second post
+    let truncated = format!(
+        "[{},{},{{\"platform\":",
+        serde_json::to_string(&post(Some("2".into()), "Two", 1, now)).unwrap(),
+        serde_json::to_string(&post(Some("3".into()), "Three", 1, now)).unwrap()
+    );
+    let outcome = sessions
+        .ingest_chunk(&mut conn, &session_id, &truncated)
+        .unwrap();
+    assert_eq!(outcome.posts_parsed, 2);
+    assert!(outcome.error.is_some());
+
+    // One bad line among good ones
+    let lines = format!(
+        "{}\nnot json\n{}",
+        serde_json::to_string(&post(Some("4".into()), "Four", 1, now)).unwrap(),
+        serde_json::to_string(&post(Some("5".into()), "Five", 1, now)).unwrap()
+    );
+    let outcome = sessions
+        .ingest_chunk(&mut conn, &session_id, &lines)
+        .unwrap();
+    assert_eq!(outcome.posts_parsed, 2);
+    assert!(outcome.error.unwrap().starts_with("line 2"));
+
+    let last = serde_json::to_string(&[post(Some("6".into()), "Six", 1, now)]).unwrap();
+    let outcome = sessions
+        .ingest_chunk(&mut conn, &session_id, &last)
+        .unwrap();
+    assert!(outcome.error.is_none());
+
+    let summary = sessions
+        .finish_extraction_session(&mut conn, &session_id)
+        .unwrap();
+    assert_eq!(summary.chunks, 4);
+    let failed: Vec<usize> = summary.failed_chunks.iter().map(|f| f.chunk).collect();
+    assert_eq!(failed, vec![2, 3]);
+    assert_eq!(summary.stored.inserted, 6);
+    assert_eq!(stored_rows(&conn).len(), 6);
+}
+
+#[test]
+fn test_abandoned_sessions_are_collected() {
+    let (mut conn, account) = setup();
+    let now = chrono::Utc::now().timestamp_millis() - 60_000;
+    let mut sessions = ExtractionSessions::new(10, Duration::from_millis(50));
+    let abandoned = sessions.begin_extraction_session(&account.id);
+    let payload = serde_json::to_string(&[post(Some("1".into()), "One", 1, now)]).unwrap();
+    sessions
+        .ingest_chunk(&mut conn, &abandoned, &payload)
+        .unwrap();
+
+    std::thread::sleep(Duration::from_millis(100));
+    let active = sessions.begin_extraction_session(&account.id);
+    assert_eq!(sessions.len(), 1);
+
+    let err = sessions
+        .ingest_chunk(&mut conn, &abandoned, &payload)
+        .unwrap_err();
+    assert!(matches!(err, SocialError::NotFound(_)));
+    assert!(sessions
+        .finish_extraction_session(&mut conn, &abandoned)
+        .is_err());
+    // Buffered posts of an abandoned session are never written
+    assert!(stored_rows(&conn).is_empty());
+
+    sessions
+        .finish_extraction_session(&mut conn, &active)
+        .unwrap();
+    assert!(sessions.collect_idle_sessions().is_empty());
+}
@@ -0,0 +1,191 @@
+use core_rs::db::migrate;
+use core_rs::social::*;
+use rusqlite::Connection;
+
+fn setup() -> (Connection, String, SocialAccount) {
+    let mut conn = Connection::open_in_memory().unwrap();
+    migrate(&mut conn).unwrap();
+    let space_id = core_rs::space::create_space(&mut conn, "Social")
+        .unwrap()
+        .to_string();
+    let account =
+        add_social_account(&conn, &space_id, "twitter", "me", None, "token", &[0u8; 32]).unwrap();
+    (conn, space_id, account)
+}
+
+fn post(platform_post_id: Option<&str>, content: &str, likes: i64) -> SocialPost {
+    SocialPost {
+        id: String::new(),
+        account_id: String::new(),
+        platform: "twitter".to_string(),
+        platform_post_id: platform_post_id.map(str::to_string),
+        author: "Alice".to_string(),
+        author_handle: Some("@alice".to_string()),
+        content: Some(content.to_string()),
+        content_html: None,
+        media_urls: vec![],
+        timestamp: chrono::Utc::now().timestamp_millis() - 60_000,
+        fetched_at: 0,
+        engagement: Engagement {
+            likes: Some(likes),
+            shares: Some(1),
+            comments: None,
+            views: None,
+        },
+        post_type: None,
+        reply_to: None,
+    }
+}
+
+fn post_count(conn: &Connection) -> i64 {
+    conn.query_row("SELECT COUNT(*) FROM social_post", [], |row| row.get(0))
+        .unwrap()
+}
+
+#[test]
+fn test_restoring_batch_updates_instead_of_duplicating() {
+    let (mut conn, space_id, account) = setup();
+    let batch = vec![
+        post(Some("1"), "First tweet", 5),
+        post(Some("2"), "Second tweet", 7),
+        post(None, "A post without an id", 2),
+    ];
+
+    let first = store_social_posts(&mut conn, &account.id, batch.clone()).unwrap();
+    assert_eq!(
+        first,
+        StorePostsResult {
+            inserted: 3,
+            updated: 0,
+            skipped: 0
+        }
+    );
+    let stored = get_social_posts(&conn, &account.id, None).unwrap();
+    let original = stored
+        .iter()
+        .find(|p| p.platform_post_id.as_deref() == Some("1"))
+        .unwrap()
+        .clone();
+    let first_seen_at: i64 = conn
+        .query_row(
+            "SELECT first_seen_at FROM social_post WHERE id = ?1",
+            [&original.id],
+            |row| row.get(0),
+        )
+        .unwrap();
+
+    let category = create_category(&conn, &space_id, "Friends", None, None, None).unwrap();
+    assign_category(&conn, &original.id, &category.id, "user").unwrap();
+
+    // Identical batch: nothing to do
+    let same = store_social_posts(&mut conn, &account.id, batch.clone()).unwrap();
+    assert_eq!(same.skipped, 3);
+    assert_eq!(post_count(&conn), 3);
+
+    // Refreshed batch: new engagement, an edit, and one genuinely new post
+    let mut refreshed = vec![
+        post(Some("1"), "First tweet (edited)", 50),
+        post(Some("2"), "Second tweet", 70),
+        post(None, "A post without an id", 20),
+        post(Some("3"), "Third tweet", 0),
+    ];
+    refreshed[1].engagement.shares = None; // missing metrics keep their stored value
+    let second = store_social_posts(&mut conn, &account.id, refreshed).unwrap();
+    assert_eq!(
+        second,
+        StorePostsResult {
+            inserted: 1,
+            updated: 3,
+            skipped: 0
+        }
+    );
+    assert_eq!(post_count(&conn), 4);
+
+    let posts = get_social_posts(&conn, &account.id, None).unwrap();
+    let by_id = |id: &str| {
+        posts
+            .iter()
+            .find(|p| p.platform_post_id.as_deref() == Some(id))
+            .unwrap()
+    };
+    let updated = by_id("1");
+    assert_eq!(updated.id, original.id);
+    assert_eq!(updated.engagement.likes, Some(50));
+    assert_eq!(updated.content.as_deref(), Some("First tweet (edited)"));
+    assert_eq!(by_id("2").engagement.likes, Some(70));
+    assert_eq!(by_id("2").engagement.shares, Some(1));
+    let unkeyed = posts.iter().find(|p| p.platform_post_id.is_none()).unwrap();
+    assert_eq!(unkeyed.engagement.likes, Some(20));
+
+    // Identity-bound state survives the update
+    let kept_first_seen: i64 = conn
+        .query_row(
+            "SELECT first_seen_at FROM social_post WHERE id = ?1",
+            [&original.id],
+            |row| row.get(0),
+        )
+        .unwrap();
+    assert_eq!(kept_first_seen, first_seen_at);
+    assert_eq!(get_post_categories(&conn, &original.id).unwrap().len(), 1);
+
+    // The edited text is searchable
+    let found = search_social_posts(&conn, &space_id, "edited", None).unwrap();
+    assert_eq!(found.len(), 1);
+}
+
+#[test]
+fn test_same_post_from_another_account_in_space_is_merged() {
+    let (mut conn, space_id, account) = setup();
+    let other = add_social_account(
+        &conn, &space_id, "twitter", "alt", None, "token", &[0u8; 32],
+    )
+    .unwrap();
+
+    store_social_posts(&mut conn, &account.id, vec![post(Some("9"), "Shared", 1)]).unwrap();
+    let result =
+        store_social_posts(&mut conn, &other.id, vec![post(Some("9"), "Shared", 3)]).unwrap();
+    assert_eq!(result.updated, 1);
+    assert_eq!(post_count(&conn), 1);
+}
+
+#[test]
+fn test_dedup_existing_posts_merges_duplicates() {
+    let (mut conn, space_id, account) = setup();
+    let other = add_social_account(
+        &conn, &space_id, "twitter", "alt", None, "token", &[0u8; 32],
+    )
+    .unwrap();
+    let category = create_category(&conn, &space_id, "Saved", None, None, None).unwrap();
+
+    // Duplicates as older versions stored them, one per account
+    for (id, account_id, seen, likes) in
+        [("old", &account.id, 1_000, 1), ("new", &other.id, 2_000, 9)]
+    {
+        conn.execute(
+            "INSERT INTO social_post (id, account_id, platform, platform_post_id, author, content,
+                                      timestamp, fetched_at, first_seen_at, likes, raw_json)
+             VALUES (?1, ?2, 'twitter', '42', 'Alice', 'Hello', 500, ?3, ?3, ?4, '{}')",
+            rusqlite::params![id, account_id, seen, likes],
+        )
+        .unwrap();
+    }
+    assign_category(&conn, "new", &category.id, "user").unwrap();
+
+    assert_eq!(dedup_existing_posts(&mut conn, &space_id).unwrap(), 1);
+    assert_eq!(post_count(&conn), 1);
+
+    let (id, likes, first_seen_at): (String, i64, i64) = conn
+        .query_row(
+            "SELECT id, likes, first_seen_at FROM social_post",
+            [],
+            |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+        )
+        .unwrap();
+    assert_eq!(id, "old");
+    assert_eq!(likes, 9);
+    assert_eq!(first_seen_at, 1_000);
+    assert_eq!(get_post_categories(&conn, "old").unwrap().len(), 1);
+
+    // Running it again finds nothing
+    assert_eq!(dedup_existing_posts(&mut conn, &space_id).unwrap(), 0);
+}
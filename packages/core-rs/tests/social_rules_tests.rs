@@ -0,0 +1,449 @@
+use core_rs::db::migrate;
+use core_rs::social::*;
+use rusqlite::Connection;
+
+const HOUR: i64 = 3_600_000;
+
+fn setup() -> (Connection, String, SocialAccount) {
+    let mut conn = Connection::open_in_memory().unwrap();
+    migrate(&mut conn).unwrap();
+    let space_id = core_rs::space::create_space(&mut conn, "Social")
+        .unwrap()
+        .to_string();
+    let account =
+        add_social_account(&conn, &space_id, "twitter", "me", None, "token", &[0u8; 32]).unwrap();
+    (conn, space_id, account)
+}
+
+struct TestPost {
+    id: String,
+    platform: &'static str,
+    author: &'static str,
+    content: &'static str,
+    likes: i64,
+    media: bool,
+    age_hours: i64,
+}
+
+impl Default for TestPost {
+    fn default() -> Self {
+        TestPost {
+            id: String::new(),
+            platform: "twitter",
+            author: "Alice",
+            content: "",
+            likes: 0,
+            media: false,
+            age_hours: 1,
+        }
+    }
+}
+
+/// Store posts and return their stored ids keyed by platform post id
+fn store(
+    conn: &mut Connection,
+    account: &SocialAccount,
+    posts: Vec<TestPost>,
+) -> std::collections::HashMap<String, String> {
+    let now = chrono::Utc::now().timestamp_millis();
+    let batch = posts
+        .into_iter()
+        .map(|p| SocialPost {
+            id: String::new(),
+            account_id: String::new(),
+            platform: p.platform.to_string(),
+            platform_post_id: Some(p.id),
+            author: p.author.to_string(),
+            author_handle: Some(format!("@{}", p.author.to_lowercase())),
+            content: Some(p.content.to_string()),
+            content_html: None,
+            media_urls: if p.media {
+                vec!["https://example.com/a.png".to_string()]
+            } else {
+                vec![]
+            },
+            timestamp: now - p.age_hours * HOUR,
+            fetched_at: 0,
+            engagement: Engagement {
+                likes: Some(p.likes),
+                shares: None,
+                comments: None,
+                views: None,
+            },
+            post_type: None,
+            reply_to: None,
+        })
+        .collect();
+    store_social_posts(conn, &account.id, batch).unwrap();
+
+    get_social_posts(conn, &account.id, None)
+        .unwrap()
+        .into_iter()
+        .map(|p| (p.platform_post_id.unwrap(), p.id))
+        .collect()
+}
+
+/// Ids (platform post ids) of posts a rule with `condition` would categorize
+fn matching(condition: RuleCondition) -> Vec<String> {
+    let (mut conn, space_id, account) = setup();
+    let ids = store(
+        &mut conn,
+        &account,
+        vec![
+            TestPost {
+                id: "rust".into(),
+                content: "Shipping a new Rust release",
+                likes: 50,
+                ..Default::default()
+            },
+            TestPost {
+                id: "cat".into(),
+                author: "Bob",
+                content: "My cat photo",
+                media: true,
+                age_hours: 48,
+                ..Default::default()
+            },
+            TestPost {
+                id: "job".into(),
+                platform: "linkedin",
+                author: "Carol",
+                content: "We are hiring",
+                likes: 5,
+                age_hours: 200,
+                ..Default::default()
+            },
+        ],
+    );
+    let category = create_category(&conn, &space_id, "Picked", None, None, None).unwrap();
+    create_category_rule(&conn, &space_id, &category.id, "rule", condition, 0).unwrap();
+
+    let mut matched: Vec<String> = dry_run_rules(&conn, &space_id, RuleMatchMode::AllMatches)
+        .unwrap()
+        .into_iter()
+        .map(|entry| {
+            ids.iter()
+                .find(|(_, id)| **id == entry.post_id)
+                .unwrap()
+                .0
+                .clone()
+        })
+        .collect();
+    matched.sort();
+    matched
+}
+
+#[test]
+fn test_each_condition_type() {
+    assert_eq!(
+        matching(RuleCondition::Keyword {
+            keyword: "RUST".into()
+        }),
+        vec!["rust"]
+    );
+    assert_eq!(
+        matching(RuleCondition::AuthorEquals {
+            author: "@bob".into()
+        }),
+        vec!["cat"]
+    );
+    assert_eq!(
+        matching(RuleCondition::PlatformEquals {
+            platform: "linkedin".into()
+        }),
+        vec!["job"]
+    );
+    assert_eq!(
+        matching(RuleCondition::EngagementAbove { threshold: 5 }),
+        vec!["rust"]
+    );
+    assert_eq!(matching(RuleCondition::HasMedia), vec!["cat"]);
+    assert_eq!(
+        matching(RuleCondition::PostedWithin {
+            after: None,
+            before: None,
+            last_hours: Some(72),
+        }),
+        vec!["cat", "rust"]
+    );
+    let now = chrono::Utc::now().timestamp_millis();
+    assert_eq!(
+        matching(RuleCondition::PostedWithin {
+            after: Some(now - 100 * HOUR),
+            before: Some(now - 10 * HOUR),
+            last_hours: None,
+        }),
+        vec!["cat"]
+    );
+}
+
+#[test]
+fn test_group_semantics() {
+    let keyword = |k: &str| RuleCondition::Keyword { keyword: k.into() };
+
+    assert_eq!(
+        matching(RuleCondition::All {
+            conditions: vec![
+                RuleCondition::PlatformEquals {
+                    platform: "twitter".into()
+                },
+                RuleCondition::Any {
+                    conditions: vec![keyword("cat"), keyword("hiring")],
+                },
+            ],
+        }),
+        vec!["cat"]
+    );
+    assert_eq!(
+        matching(RuleCondition::Any {
+            conditions: vec![keyword("cat"), keyword("hiring")],
+        }),
+        vec!["cat", "job"]
+    );
+    // Empty groups: ALL matches everything, ANY matches nothing
+    assert_eq!(
+        matching(RuleCondition::All { conditions: vec![] }),
+        vec!["cat", "job", "rust"]
+    );
+    assert!(matching(RuleCondition::Any { conditions: vec![] }).is_empty());
+}
+
+#[test]
+fn test_priority_and_match_modes() {
+    let (mut conn, space_id, account) = setup();
+    let ids = store(
+        &mut conn,
+        &account,
+        vec![TestPost {
+            id: "1".into(),
+            content: "Rust and cats",
+            ..Default::default()
+        }],
+    );
+    let low = create_category(&conn, &space_id, "Low", None, None, None).unwrap();
+    let high = create_category(&conn, &space_id, "High", None, None, None).unwrap();
+    let rust = RuleCondition::Keyword {
+        keyword: "rust".into(),
+    };
+    let low_rule = create_category_rule(&conn, &space_id, &low.id, "low", rust.clone(), 1).unwrap();
+    let high_rule = create_category_rule(&conn, &space_id, &high.id, "high", rust, 10).unwrap();
+
+    let first = dry_run_rules(&conn, &space_id, RuleMatchMode::FirstMatch).unwrap();
+    assert_eq!(first.len(), 1);
+    assert_eq!(first[0].post_id, ids["1"]);
+    assert_eq!(first[0].add_category_ids, vec![high.id.clone()]);
+    assert_eq!(first[0].matched_rule_ids, vec![high_rule.id.clone()]);
+
+    let all = dry_run_rules(&conn, &space_id, RuleMatchMode::AllMatches).unwrap();
+    assert_eq!(
+        all[0].matched_rule_ids,
+        vec![high_rule.id, low_rule.id.clone()]
+    );
+    assert_eq!(all[0].add_category_ids.len(), 2);
+
+    // Disabled rules are skipped
+    set_category_rule_enabled(&conn, &low_rule.id, false).unwrap();
+    let all = dry_run_rules(&conn, &space_id, RuleMatchMode::AllMatches).unwrap();
+    assert_eq!(all[0].add_category_ids, vec![high.id]);
+}
+
+#[test]
+fn test_dry_run_matches_real_run() {
+    let (mut conn, space_id, account) = setup();
+    store(
+        &mut conn,
+        &account,
+        (0..20)
+            .map(|i| TestPost {
+                id: i.to_string(),
+                content: if i % 2 == 0 { "rust news" } else { "other" },
+                likes: i,
+                media: i % 3 == 0,
+                ..Default::default()
+            })
+            .collect(),
+    );
+    let tech = create_category(&conn, &space_id, "Tech", None, None, None).unwrap();
+    let media = create_category(&conn, &space_id, "Media", None, None, None).unwrap();
+    create_category_rule(
+        &conn,
+        &space_id,
+        &tech.id,
+        "popular rust",
+        RuleCondition::All {
+            conditions: vec![
+                RuleCondition::Keyword {
+                    keyword: "rust".into(),
+                },
+                RuleCondition::EngagementAbove { threshold: 6 },
+            ],
+        },
+        5,
+    )
+    .unwrap();
+    create_category_rule(
+        &conn,
+        &space_id,
+        &media.id,
+        "media",
+        RuleCondition::HasMedia,
+        0,
+    )
+    .unwrap();
+
+    let count = |conn: &Connection| -> i64 {
+        conn.query_row("SELECT COUNT(*) FROM social_post_category", [], |r| {
+            r.get(0)
+        })
+        .unwrap()
+    };
+
+    let plan = dry_run_rules(&conn, &space_id, RuleMatchMode::AllMatches).unwrap();
+    assert_eq!(count(&conn), 0, "dry run must not write");
+
+    let result = apply_rules(&conn, &space_id, RuleMatchMode::AllMatches).unwrap();
+    assert_eq!(result.posts_changed, plan.len());
+    assert_eq!(
+        result.assignments_added,
+        plan.iter().map(|e| e.add_category_ids.len()).sum::<usize>()
+    );
+    assert_eq!(count(&conn) as usize, result.assignments_added);
+    for entry in &plan {
+        let mut assigned: Vec<String> = get_post_categories(&conn, &entry.post_id)
+            .unwrap()
+            .into_iter()
+            .map(|c| c.id)
+            .collect();
+        assigned.sort();
+        assert_eq!(assigned, entry.add_category_ids);
+    }
+
+    // Nothing left to do on a second run
+    assert!(dry_run_rules(&conn, &space_id, RuleMatchMode::AllMatches)
+        .unwrap()
+        .is_empty());
+    assert_eq!(
+        apply_rules(&conn, &space_id, RuleMatchMode::AllMatches).unwrap(),
+        RuleRunResult::default()
+    );
+}
+
+#[test]
+fn test_rules_never_clobber_manual_assignments() {
+    let (mut conn, space_id, account) = setup();
+    let ids = store(
+        &mut conn,
+        &account,
+        vec![TestPost {
+            id: "1".into(),
+            content: "rust",
+            ..Default::default()
+        }],
+    );
+    let tech = create_category(&conn, &space_id, "Tech", None, None, None).unwrap();
+    let rule = create_category_rule(
+        &conn,
+        &space_id,
+        &tech.id,
+        "rust",
+        RuleCondition::Keyword {
+            keyword: "rust".into(),
+        },
+        0,
+    )
+    .unwrap();
+
+    apply_rules(&conn, &space_id, RuleMatchMode::FirstMatch).unwrap();
+    // The user confirms the category by hand, then the rule stops matching
+    assign_category(&conn, &ids["1"], &tech.id, "manual").unwrap();
+    set_category_rule_enabled(&conn, &rule.id, false).unwrap();
+
+    assert!(dry_run_rules(&conn, &space_id, RuleMatchMode::FirstMatch)
+        .unwrap()
+        .is_empty());
+    apply_rules(&conn, &space_id, RuleMatchMode::FirstMatch).unwrap();
+    let assigned_by: String = conn
+        .query_row(
+            "SELECT assigned_by FROM social_post_category WHERE post_id = ?1",
+            [&ids["1"]],
+            |r| r.get(0),
+        )
+        .unwrap();
+    assert_eq!(assigned_by, "manual");
+
+    // A rule assignment alone is withdrawn once its rule no longer matches
+    let other = create_category(&conn, &space_id, "Other", None, None, None).unwrap();
+    let other_rule = create_category_rule(
+        &conn,
+        &space_id,
+        &other.id,
+        "all",
+        RuleCondition::All { conditions: vec![] },
+        0,
+    )
+    .unwrap();
+    apply_rules(&conn, &space_id, RuleMatchMode::FirstMatch).unwrap();
+    assert_eq!(get_post_categories(&conn, &ids["1"]).unwrap().len(), 2);
+    delete_category_rule(&conn, &other_rule.id).unwrap();
+    let plan = dry_run_rules(&conn, &space_id, RuleMatchMode::FirstMatch).unwrap();
+    assert_eq!(plan[0].remove_category_ids, vec![other.id]);
+    apply_rules(&conn, &space_id, RuleMatchMode::FirstMatch).unwrap();
+    let remaining = get_post_categories(&conn, &ids["1"]).unwrap();
+    assert_eq!(remaining.len(), 1);
+    assert_eq!(remaining[0].id, tech.id);
+}
+
+#[test]
+fn test_legacy_auto_rules_are_evaluated() {
+    let (mut conn, space_id, account) = setup();
+    store(
+        &mut conn,
+        &account,
+        vec![TestPost {
+            id: "1".into(),
+            content: "Loving #rustlang today",
+            ..Default::default()
+        }],
+    );
+    let tech = create_category(&conn, &space_id, "Tech", None, None, None).unwrap();
+    create_auto_rule(&conn, &tech.id, RuleType::HashtagContains, "rustlang", 0).unwrap();
+
+    let result = apply_rules(&conn, &space_id, RuleMatchMode::FirstMatch).unwrap();
+    assert_eq!(result.assignments_added, 1);
+}
+
+#[test]
+fn test_export_and_import_between_vaults() {
+    let (conn, space_id, _) = setup();
+    let tech = create_category(&conn, &space_id, "Tech", None, None, None).unwrap();
+    let condition = RuleCondition::Any {
+        conditions: vec![
+            RuleCondition::AuthorEquals {
+                author: "alice".into(),
+            },
+            RuleCondition::PostedWithin {
+                after: None,
+                before: None,
+                last_hours: Some(24),
+            },
+        ],
+    };
+    create_category_rule(&conn, &space_id, &tech.id, "recent", condition.clone(), 3).unwrap();
+    let json = export_rules(&conn, &space_id).unwrap();
+
+    let (other, other_space, _) = setup();
+    create_category(&other, &other_space, "tech", None, None, None).unwrap();
+    assert_eq!(import_rules(&other, &other_space, &json).unwrap(), 1);
+
+    let rules = get_category_rules(&other, &other_space).unwrap();
+    assert_eq!(rules.len(), 1);
+    assert_eq!(rules[0].name, "recent");
+    assert_eq!(rules[0].priority, 3);
+    assert_eq!(rules[0].condition, condition);
+    // Matched to the existing category by name instead of creating a new one
+    assert_eq!(get_categories(&other, &other_space).unwrap().len(), 1);
+    assert_eq!(
+        export_rules(&other, &other_space).unwrap(),
+        json.replace("Tech", "tech")
+    );
+}
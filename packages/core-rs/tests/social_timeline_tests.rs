@@ -0,0 +1,213 @@
+use core_rs::db::migrate;
+use core_rs::social::*;
+use rusqlite::Connection;
+use std::collections::HashSet;
+
+const PLATFORMS: [&str; 3] = ["twitter", "mastodon", "bluesky"];
+
+struct Seeded {
+    conn: Connection,
+    space_id: String,
+    accounts: Vec<SocialAccount>,
+}
+
+/// 500 posts across three platforms, stored out of timestamp order and with
+/// many timestamps shared between platforms
+fn seed() -> Seeded {
+    let mut conn = Connection::open_in_memory().unwrap();
+    migrate(&mut conn).unwrap();
+    let space_id = core_rs::space::create_space(&mut conn, "Social")
+        .unwrap()
+        .to_string();
+
+    let accounts: Vec<SocialAccount> = PLATFORMS
+        .iter()
+        .map(|platform| {
+            add_social_account(&conn, &space_id, platform, "me", None, "token", &[0u8; 32]).unwrap()
+        })
+        .collect();
+
+    let tx = conn.transaction().unwrap();
+    for i in 0..500i64 {
+        let account = &accounts[(i % 3) as usize];
+        // Scatter arrival order; every 7 consecutive posts share a timestamp
+        let n = (i * 137) % 500;
+        let timestamp = 1_700_000_000_000 + (n / 7) * 1_000;
+        tx.execute(
+            "INSERT INTO social_post (id, account_id, platform, platform_post_id, author, content,
+                                      timestamp, fetched_at, first_seen_at, raw_json)
+             VALUES (?1, ?2, ?3, ?4, 'Author', ?5, ?6, ?7, ?7, '{}')",
+            rusqlite::params![
+                ulid::Ulid::new().to_string(),
+                account.id,
+                account.platform,
+                format!("post-{}", i),
+                format!("Post {}", i),
+                timestamp,
+                1_000 + i,
+            ],
+        )
+        .unwrap();
+    }
+    tx.commit().unwrap();
+
+    Seeded {
+        conn,
+        space_id,
+        accounts,
+    }
+}
+
+fn page_through(seeded: &Seeded, filters: TimelineFilters) -> Vec<TimelinePost> {
+    let mut posts = Vec::new();
+    let mut filters = filters;
+    loop {
+        let page =
+            get_unified_timeline_page(&seeded.conn, &seeded.space_id, filters.clone()).unwrap();
+        posts.extend(page.posts);
+        match page.next_cursor {
+            Some(cursor) => filters = filters.after_cursor(&cursor),
+            None => break,
+        }
+    }
+    posts
+}
+
+#[test]
+fn test_paging_visits_every_post_once_in_order() {
+    let seeded = seed();
+    let posts = page_through(
+        &seeded,
+        TimelineFilters {
+            limit: Some(37),
+            ..Default::default()
+        },
+    );
+
+    assert_eq!(posts.len(), 500);
+    let ids: HashSet<&str> = posts.iter().map(|p| p.id.as_str()).collect();
+    assert_eq!(ids.len(), 500, "no post appears twice");
+
+    // Strictly descending by (timestamp, id): stable across shared timestamps
+    for pair in posts.windows(2) {
+        assert!(
+            (pair[0].timestamp, &pair[0].id) > (pair[1].timestamp, &pair[1].id),
+            "timeline order must be total"
+        );
+    }
+
+    // Paging gives the same sequence as one big page
+    let all = get_unified_timeline(
+        &seeded.conn,
+        &seeded.space_id,
+        TimelineFilters {
+            limit: Some(1000),
+            ..Default::default()
+        },
+    )
+    .unwrap();
+    let paged: Vec<&str> = posts.iter().map(|p| p.id.as_str()).collect();
+    let single: Vec<&str> = all.iter().map(|p| p.id.as_str()).collect();
+    assert_eq!(paged, single);
+}
+
+#[test]
+fn test_paging_respects_filters() {
+    let seeded = seed();
+
+    let mastodon = page_through(
+        &seeded,
+        TimelineFilters {
+            platforms: Some(vec!["mastodon".to_string()]),
+            limit: Some(25),
+            ..Default::default()
+        },
+    );
+    assert_eq!(mastodon.len(), 167);
+    assert!(mastodon.iter().all(|p| p.platform == "mastodon"));
+
+    let bluesky_account = seeded.accounts[2].id.clone();
+    let by_account = page_through(
+        &seeded,
+        TimelineFilters {
+            accounts: Some(vec![bluesky_account]),
+            limit: Some(50),
+            ..Default::default()
+        },
+    );
+    assert_eq!(by_account.len(), 166);
+    assert!(by_account.iter().all(|p| p.platform == "bluesky"));
+
+    // The last page has no cursor
+    let last = get_unified_timeline_page(
+        &seeded.conn,
+        &seeded.space_id,
+        TimelineFilters {
+            limit: Some(500),
+            ..Default::default()
+        },
+    )
+    .unwrap();
+    assert_eq!(last.posts.len(), 500);
+    assert!(last.next_cursor.is_none());
+}
+
+#[test]
+fn test_new_count_tracks_arrivals() {
+    let seeded = seed();
+    let filters = TimelineFilters::default();
+
+    // Arrival times run 1000..1500
+    assert_eq!(
+        get_timeline_new_count(&seeded.conn, &seeded.space_id, 0, &filters).unwrap(),
+        500
+    );
+    assert_eq!(
+        get_timeline_new_count(&seeded.conn, &seeded.space_id, 1_399, &filters).unwrap(),
+        100
+    );
+
+    let twitter = TimelineFilters {
+        platforms: Some(vec!["twitter".to_string()]),
+        ..Default::default()
+    };
+    let twitter_new =
+        get_timeline_new_count(&seeded.conn, &seeded.space_id, 1_399, &twitter).unwrap();
+    assert_eq!(
+        twitter_new,
+        (400..500).filter(|i| i % 3 == 0).count() as i64
+    );
+
+    // A late arrival with an old timestamp still counts as new
+    let account = &seeded.accounts[1];
+    seeded
+        .conn
+        .execute(
+            "INSERT INTO social_post (id, account_id, platform, platform_post_id, author, content,
+                                      timestamp, fetched_at, first_seen_at, raw_json)
+             VALUES ('late', ?1, ?2, 'late', 'Author', 'Late', 1, 5000, 5000, '{}')",
+            rusqlite::params![account.id, account.platform],
+        )
+        .unwrap();
+    assert_eq!(
+        get_timeline_new_count(&seeded.conn, &seeded.space_id, 1_499, &filters).unwrap(),
+        1
+    );
+
+    // Category filters apply too
+    let category =
+        create_category(&seeded.conn, &seeded.space_id, "Late", None, None, None).unwrap();
+    let in_category = TimelineFilters {
+        categories: Some(vec![category.id.clone()]),
+        ..Default::default()
+    };
+    assert_eq!(
+        get_timeline_new_count(&seeded.conn, &seeded.space_id, 0, &in_category).unwrap(),
+        0
+    );
+    assign_category(&seeded.conn, "late", &category.id, "user").unwrap();
+    assert_eq!(
+        get_timeline_new_count(&seeded.conn, &seeded.space_id, 0, &in_category).unwrap(),
+        1
+    );
+}
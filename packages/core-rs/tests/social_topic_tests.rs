@@ -0,0 +1,387 @@
+use core_rs::ai::{Embedder, RagError};
+use core_rs::calendar::TimeRange;
+use core_rs::db::migrate;
+use core_rs::social::*;
+use rusqlite::Connection;
+use std::collections::BTreeSet;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// One axis per topic vocabulary plus a constant one, so posts of a topic
+/// point the same way and unrelated posts point nowhere in particular
+struct KeywordEmbedder {
+    calls: AtomicUsize,
+}
+
+const VOCABULARIES: [&[&str]; 3] = [
+    &["rust", "compiler", "cargo", "borrow"],
+    &["pasta", "recipe", "oven", "basil"],
+    &["football", "league", "goal", "striker"],
+];
+
+impl Embedder for KeywordEmbedder {
+    fn embed(&self, text: &str) -> Result<Vec<f32>, RagError> {
+        self.calls.fetch_add(1, Ordering::SeqCst);
+        let lower = text.to_lowercase();
+        let mut vector: Vec<f32> = VOCABULARIES
+            .iter()
+            .map(|words| words.iter().filter(|w| lower.contains(*w)).count() as f32)
+            .collect();
+        vector.push(0.1);
+        Ok(vector)
+    }
+
+    fn version(&self) -> &str {
+        "keywords-v1"
+    }
+}
+
+struct Seeded {
+    conn: Connection,
+    space_id: String,
+    account_id: String,
+    next: i64,
+}
+
+impl Seeded {
+    fn post(&mut self, content: &str, likes: i64) -> String {
+        let id = ulid::Ulid::new().to_string();
+        self.conn
+            .execute(
+                "INSERT INTO social_post (id, account_id, platform, platform_post_id, author, content,
+                                          timestamp, fetched_at, likes, shares, comments, raw_json)
+                 VALUES (?1, ?2, 'twitter', ?3, 'Author', ?4, ?5, ?5, ?6, 1, 0, '{}')",
+                rusqlite::params![
+                    id,
+                    self.account_id,
+                    format!("post-{}", self.next),
+                    content,
+                    1_700_000_000_000 + self.next * 1_000,
+                    likes
+                ],
+            )
+            .unwrap();
+        self.next += 1;
+        id
+    }
+}
+
+fn range() -> TimeRange {
+    TimeRange::new(1_699_000_000, 1_800_000_000)
+}
+
+fn options() -> TopicClusterOptions {
+    TopicClusterOptions {
+        k: 3,
+        ..Default::default()
+    }
+}
+
+/// Four posts on each of three topics, interleaved
+fn seed() -> (Seeded, Vec<Vec<String>>) {
+    let mut conn = Connection::open_in_memory().unwrap();
+    migrate(&mut conn).unwrap();
+    let space_id = core_rs::space::create_space(&mut conn, "Social")
+        .unwrap()
+        .to_string();
+    let account =
+        add_social_account(&conn, &space_id, "twitter", "me", None, "token", &[0u8; 32]).unwrap();
+    let mut seeded = Seeded {
+        conn,
+        space_id,
+        account_id: account.id,
+        next: 0,
+    };
+
+    let texts = [
+        [
+            "Rust compiler release notes",
+            "Fighting the borrow checker in Rust again",
+            "Cargo workspaces make Rust monorepos easy",
+            "The Rust compiler got faster",
+        ],
+        [
+            "Fresh basil pasta recipe",
+            "Pasta bake straight from the oven",
+            "My grandmother's pasta recipe",
+            "Oven roasted tomatoes with basil and pasta",
+        ],
+        [
+            "Football league standings after the weekend",
+            "What a goal by the striker",
+            "Football transfer rumours: a new striker",
+            "League title race heats up, football fans rejoice",
+        ],
+    ];
+    let mut ids = vec![Vec::new(), Vec::new(), Vec::new()];
+    for i in 0..4 {
+        for (topic, topic_texts) in texts.iter().enumerate() {
+            let likes = (topic as i64 + 1) * 10;
+            ids[topic].push(seeded.post(topic_texts[i], likes));
+        }
+    }
+    (seeded, ids)
+}
+
+fn topic_of(seeded: &Seeded, post_id: &str) -> Option<String> {
+    get_post_topic(&seeded.conn, post_id).unwrap()
+}
+
+/// The posts grouped by assigned topic
+fn partition(seeded: &Seeded, ids: &[Vec<String>]) -> BTreeSet<BTreeSet<String>> {
+    let mut groups: std::collections::BTreeMap<String, BTreeSet<String>> = Default::default();
+    for id in ids.iter().flatten() {
+        let topic = topic_of(seeded, id).expect("every seeded post gets a topic");
+        groups.entry(topic).or_default().insert(id.clone());
+    }
+    groups.into_values().collect()
+}
+
+#[test]
+fn test_posts_cluster_by_topic_stably() {
+    let (mut seeded, ids) = seed();
+    let embedder = KeywordEmbedder {
+        calls: AtomicUsize::new(0),
+    };
+
+    let result = cluster_timeline_topics(
+        &mut seeded.conn,
+        &seeded.space_id,
+        range(),
+        &embedder,
+        &options(),
+    )
+    .unwrap();
+    assert!(result.reclustered);
+    assert_eq!(result.clusters, 3);
+    assert_eq!(result.posts_assigned, 12);
+    assert_eq!(result.posts_embedded, 12);
+
+    let expected: BTreeSet<BTreeSet<String>> = ids
+        .iter()
+        .map(|topic| topic.iter().cloned().collect())
+        .collect();
+    assert_eq!(partition(&seeded, &ids), expected);
+
+    // Clustering from scratch again gives the same topics and reuses the
+    // stored embeddings
+    let again = cluster_timeline_topics(
+        &mut seeded.conn,
+        &seeded.space_id,
+        range(),
+        &embedder,
+        &TopicClusterOptions {
+            full_recluster: true,
+            ..options()
+        },
+    )
+    .unwrap();
+    assert!(again.reclustered);
+    assert_eq!(again.posts_embedded, 0);
+    assert_eq!(embedder.calls.load(Ordering::SeqCst), 12);
+    assert_eq!(partition(&seeded, &ids), expected);
+}
+
+#[test]
+fn test_labels_come_from_distinctive_terms() {
+    let (mut seeded, ids) = seed();
+    let embedder = KeywordEmbedder {
+        calls: AtomicUsize::new(0),
+    };
+    cluster_timeline_topics(
+        &mut seeded.conn,
+        &seeded.space_id,
+        range(),
+        &embedder,
+        &options(),
+    )
+    .unwrap();
+
+    let overview = get_topic_overview(&seeded.conn, &seeded.space_id).unwrap();
+    let label_of = |post_id: &str| {
+        let topic = topic_of(&seeded, post_id).unwrap();
+        overview
+            .iter()
+            .find(|o| o.cluster_id == topic)
+            .unwrap()
+            .label
+            .clone()
+    };
+    let rust = label_of(&ids[0][0]);
+    let cooking = label_of(&ids[1][0]);
+    let football = label_of(&ids[2][0]);
+
+    assert_eq!(rust.split(", ").count(), 3);
+    assert!(rust.split(", ").any(|term| term == "rust"), "{}", rust);
+    assert!(
+        cooking.split(", ").any(|term| term == "pasta"),
+        "{}",
+        cooking
+    );
+    assert!(
+        football.split(", ").any(|term| term == "football"),
+        "{}",
+        football
+    );
+    // Stopwords and short words never make it into a label
+    for label in [&rust, &cooking, &football] {
+        assert!(label
+            .split(", ")
+            .all(|term| term.len() >= 3 && term != "the"));
+    }
+}
+
+#[test]
+fn test_new_posts_join_existing_topics_incrementally() {
+    let (mut seeded, ids) = seed();
+    let embedder = KeywordEmbedder {
+        calls: AtomicUsize::new(0),
+    };
+    cluster_timeline_topics(
+        &mut seeded.conn,
+        &seeded.space_id,
+        range(),
+        &embedder,
+        &options(),
+    )
+    .unwrap();
+    let rust_topic = topic_of(&seeded, &ids[0][0]).unwrap();
+
+    let new_rust = seeded.post("Async Rust and the borrow checker", 50);
+    let gardening = seeded.post("Planting tulips this spring", 0);
+
+    let result = cluster_timeline_topics(
+        &mut seeded.conn,
+        &seeded.space_id,
+        range(),
+        &embedder,
+        &options(),
+    )
+    .unwrap();
+    assert!(!result.reclustered);
+    assert_eq!(result.clusters, 3);
+    assert_eq!(result.posts_embedded, 2, "only the new posts are embedded");
+    assert_eq!(result.posts_assigned, 1);
+    assert_eq!(result.posts_unassigned, 1);
+    assert_eq!(embedder.calls.load(Ordering::SeqCst), 14);
+
+    assert_eq!(topic_of(&seeded, &new_rust), Some(rust_topic.clone()));
+    assert_eq!(topic_of(&seeded, &gardening), None);
+    // Earlier assignments are left alone
+    for topic in &ids {
+        let first = topic_of(&seeded, &topic[0]);
+        assert!(topic.iter().all(|id| topic_of(&seeded, id) == first));
+    }
+
+    // Nothing new: nothing to embed
+    let idle = cluster_timeline_topics(
+        &mut seeded.conn,
+        &seeded.space_id,
+        range(),
+        &embedder,
+        &options(),
+    )
+    .unwrap();
+    assert_eq!(idle.posts_embedded, 0);
+    assert_eq!(idle.posts_assigned, 0);
+
+    // The timeline can be filtered by the discovered topic
+    let posts = get_unified_timeline(
+        &seeded.conn,
+        &seeded.space_id,
+        TimelineFilters {
+            topics: Some(vec![rust_topic.clone()]),
+            ..Default::default()
+        },
+    )
+    .unwrap();
+    let expected: BTreeSet<String> = ids[0].iter().cloned().chain([new_rust]).collect();
+    assert_eq!(
+        posts.into_iter().map(|p| p.id).collect::<BTreeSet<_>>(),
+        expected
+    );
+
+    // Sizes and engagement averages per topic, largest first
+    let overview = get_topic_overview(&seeded.conn, &seeded.space_id).unwrap();
+    assert_eq!(
+        overview.iter().map(|o| o.post_count).collect::<Vec<_>>(),
+        vec![5, 4, 4]
+    );
+    let rust = &overview[0];
+    assert_eq!(rust.cluster_id, rust_topic);
+    assert!((rust.avg_likes - 18.0).abs() < 1e-9, "(4 * 10 + 50) / 5");
+    assert!((rust.avg_shares - 1.0).abs() < 1e-9);
+    assert!((rust.avg_engagement - 19.0).abs() < 1e-9);
+}
+
+#[test]
+fn test_another_embedding_model_reclusters() {
+    struct OtherModel(KeywordEmbedder);
+    impl Embedder for OtherModel {
+        fn embed(&self, text: &str) -> Result<Vec<f32>, RagError> {
+            self.0.embed(text)
+        }
+        fn version(&self) -> &str {
+            "keywords-v2"
+        }
+    }
+
+    let (mut seeded, _) = seed();
+    let first = KeywordEmbedder {
+        calls: AtomicUsize::new(0),
+    };
+    cluster_timeline_topics(
+        &mut seeded.conn,
+        &seeded.space_id,
+        range(),
+        &first,
+        &options(),
+    )
+    .unwrap();
+
+    let second = OtherModel(KeywordEmbedder {
+        calls: AtomicUsize::new(0),
+    });
+    let result = cluster_timeline_topics(
+        &mut seeded.conn,
+        &seeded.space_id,
+        range(),
+        &second,
+        &options(),
+    )
+    .unwrap();
+    assert!(result.reclustered);
+    assert_eq!(result.posts_embedded, 12);
+}
+
+#[test]
+fn test_invalid_options_are_rejected() {
+    let (mut seeded, _) = seed();
+    let embedder = KeywordEmbedder {
+        calls: AtomicUsize::new(0),
+    };
+    let zero_k = TopicClusterOptions {
+        k: 0,
+        ..Default::default()
+    };
+    assert!(matches!(
+        cluster_timeline_topics(
+            &mut seeded.conn,
+            &seeded.space_id,
+            range(),
+            &embedder,
+            &zero_k
+        ),
+        Err(SocialError::InvalidInput(_))
+    ));
+    assert!(matches!(
+        cluster_timeline_topics(
+            &mut seeded.conn,
+            &seeded.space_id,
+            TimeRange::new(10, 10),
+            &embedder,
+            &options()
+        ),
+        Err(SocialError::InvalidInput(_))
+    ));
+    assert_eq!(embedder.calls.load(Ordering::SeqCst), 0);
+}
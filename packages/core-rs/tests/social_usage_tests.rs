@@ -0,0 +1,265 @@
+use chrono::{TimeZone, Utc, Weekday};
+use core_rs::db::migrate;
+use core_rs::social::*;
+use rusqlite::Connection;
+
+const MINUTE: i64 = 60;
+
+fn setup() -> (Connection, String, FocusMode) {
+    let mut conn = Connection::open_in_memory().unwrap();
+    migrate(&mut conn).unwrap();
+    let space_id = core_rs::space::create_space(&mut conn, "Social")
+        .unwrap()
+        .to_string();
+    set_usage_timezone(&conn, &space_id, 0).unwrap();
+
+    let mode = create_focus_mode(
+        &conn,
+        &space_id,
+        "Social Time",
+        None,
+        None,
+        vec!["tiktok".to_string()],
+        vec![],
+    )
+    .unwrap();
+    set_time_limit(
+        &conn,
+        &mode.id,
+        &TimeLimit {
+            platform: "twitter".into(),
+            daily_minutes: 30,
+            weekday: None,
+        },
+    )
+    .unwrap();
+    activate_focus_mode(&mut conn, &mode.id, &space_id).unwrap();
+    (conn, space_id, mode)
+}
+
+/// Milliseconds for a UTC wall-clock time; 2025-01-06 is a Monday
+fn utc(day: u32, hour: u32, minute: u32) -> i64 {
+    Utc.with_ymd_and_hms(2025, 1, day, hour, minute, 0)
+        .unwrap()
+        .timestamp_millis()
+}
+
+#[test]
+fn test_focus_block_takes_precedence() {
+    let (conn, space_id, mode) = setup();
+    let at = utc(6, 12, 0);
+
+    assert_eq!(
+        is_platform_blocked_at(&conn, &space_id, "TikTok", at).unwrap(),
+        PlatformAccess::BlockedByFocus {
+            focus_mode_id: mode.id.clone(),
+            focus_mode_name: "Social Time".into(),
+        }
+    );
+    assert_eq!(
+        is_platform_blocked_at(&conn, &space_id, "reddit", at).unwrap(),
+        PlatformAccess::Allowed {
+            minutes_remaining: None
+        }
+    );
+
+    deactivate_all_focus_modes(&conn, &space_id).unwrap();
+    assert!(!is_platform_blocked_at(&conn, &space_id, "tiktok", at)
+        .unwrap()
+        .is_blocked());
+}
+
+#[test]
+fn test_limit_exhausts_and_reports_remaining() {
+    let (conn, space_id, mode) = setup();
+
+    record_platform_usage_at(&conn, &space_id, "twitter", 20 * MINUTE, utc(6, 10, 0)).unwrap();
+    assert_eq!(
+        is_platform_blocked_at(&conn, &space_id, "twitter", utc(6, 10, 0)).unwrap(),
+        PlatformAccess::Allowed {
+            minutes_remaining: Some(10)
+        }
+    );
+
+    record_platform_usage_at(&conn, &space_id, "Twitter", 10 * MINUTE, utc(6, 11, 0)).unwrap();
+    let access = is_platform_blocked_at(&conn, &space_id, "twitter", utc(6, 11, 0)).unwrap();
+    assert_eq!(
+        access,
+        PlatformAccess::LimitExhausted {
+            focus_mode_id: mode.id,
+            daily_minutes: 30,
+            used_minutes: 30,
+            minutes_remaining: 0,
+            resets_at: utc(7, 0, 0),
+        }
+    );
+    assert!(access.is_blocked());
+
+    // The next day starts with a fresh budget
+    assert_eq!(
+        is_platform_blocked_at(&conn, &space_id, "twitter", utc(7, 0, 0)).unwrap(),
+        PlatformAccess::Allowed {
+            minutes_remaining: Some(30)
+        }
+    );
+}
+
+#[test]
+fn test_budget_resets_at_local_midnight() {
+    let (conn, space_id, _) = setup();
+    // UTC+2: local midnight of 7 January is 22:00 UTC on the 6th
+    set_usage_timezone(&conn, &space_id, 120).unwrap();
+
+    record_platform_usage_at(&conn, &space_id, "twitter", 30 * MINUTE, utc(6, 21, 50)).unwrap();
+    assert!(
+        is_platform_blocked_at(&conn, &space_id, "twitter", utc(6, 21, 59))
+            .unwrap()
+            .is_blocked()
+    );
+    assert!(
+        !is_platform_blocked_at(&conn, &space_id, "twitter", utc(6, 22, 0))
+            .unwrap()
+            .is_blocked()
+    );
+
+    // A session across midnight is split between the two local days
+    record_platform_usage_at(&conn, &space_id, "twitter", 20 * MINUTE, utc(6, 22, 10)).unwrap();
+    let before = get_platform_usage_at(&conn, &space_id, utc(6, 21, 59)).unwrap();
+    assert_eq!(before[0].day, "2025-01-06");
+    assert_eq!(before[0].seconds, 40 * MINUTE);
+    let after = get_platform_usage_at(&conn, &space_id, utc(6, 22, 10)).unwrap();
+    assert_eq!(after[0].day, "2025-01-07");
+    assert_eq!(after[0].seconds, 10 * MINUTE);
+    assert_eq!(after[0].minutes_remaining, Some(20));
+
+    assert!(set_usage_timezone(&conn, &space_id, 15 * 60).is_err());
+}
+
+#[test]
+fn test_weekday_budget_overrides_daily_limit() {
+    let (conn, space_id, mode) = setup();
+    set_time_limit(
+        &conn,
+        &mode.id,
+        &TimeLimit {
+            platform: "twitter".into(),
+            daily_minutes: 120,
+            weekday: Some(Weekday::Sat),
+        },
+    )
+    .unwrap();
+
+    let limits = get_time_limits(&conn, &mode.id).unwrap();
+    assert_eq!(limits.len(), 2);
+    let modes = get_focus_modes(&conn, &space_id).unwrap();
+    assert_eq!(modes[0].time_limits, limits);
+
+    // Monday uses the every-day limit, Saturday (11 January) its own budget
+    record_platform_usage_at(&conn, &space_id, "twitter", 60 * MINUTE, utc(6, 12, 0)).unwrap();
+    assert!(
+        is_platform_blocked_at(&conn, &space_id, "twitter", utc(6, 12, 0))
+            .unwrap()
+            .is_blocked()
+    );
+    record_platform_usage_at(&conn, &space_id, "twitter", 60 * MINUTE, utc(11, 12, 0)).unwrap();
+    assert_eq!(
+        is_platform_blocked_at(&conn, &space_id, "twitter", utc(11, 12, 0)).unwrap(),
+        PlatformAccess::Allowed {
+            minutes_remaining: Some(60)
+        }
+    );
+
+    remove_time_limit(&conn, &mode.id, "twitter", Some(Weekday::Sat)).unwrap();
+    assert!(
+        is_platform_blocked_at(&conn, &space_id, "twitter", utc(11, 12, 0))
+            .unwrap()
+            .is_blocked()
+    );
+}
+
+#[test]
+fn test_usage_is_accounted_per_platform() {
+    let (conn, space_id, _) = setup();
+    let at = utc(6, 15, 0);
+    record_platform_usage_at(&conn, &space_id, "twitter", 5 * MINUTE, at).unwrap();
+    record_platform_usage_at(&conn, &space_id, "reddit", 12 * MINUTE, at).unwrap();
+    record_platform_usage_at(&conn, &space_id, "twitter", 3 * MINUTE, at).unwrap();
+    record_platform_usage_at(&conn, &space_id, "youtube", 0, at).unwrap();
+
+    let usage = get_platform_usage_at(&conn, &space_id, at).unwrap();
+    assert_eq!(usage.len(), 2);
+    assert_eq!(usage[0].platform, "reddit");
+    assert_eq!(usage[0].seconds, 12 * MINUTE);
+    assert_eq!(usage[0].limit_minutes, None);
+    assert_eq!(usage[1].platform, "twitter");
+    assert_eq!(usage[1].seconds, 8 * MINUTE);
+    assert_eq!(usage[1].limit_minutes, Some(30));
+    assert_eq!(usage[1].minutes_remaining, Some(22));
+}
+
+#[test]
+fn test_usage_threshold_automation_fires_once_per_day() {
+    let (conn, space_id, mode) = setup();
+    set_time_limit(
+        &conn,
+        &mode.id,
+        &TimeLimit {
+            platform: "reddit".into(),
+            daily_minutes: 10,
+            weekday: None,
+        },
+    )
+    .unwrap();
+    let warn = create_automation_rule(
+        &conn,
+        &space_id,
+        "Twitter warning",
+        TriggerType::UsageThreshold,
+        "twitter:80",
+        ActionType::SendNotification,
+        "Twitter budget almost used",
+    )
+    .unwrap();
+    create_automation_rule(
+        &conn,
+        &space_id,
+        "Any platform",
+        TriggerType::UsageThreshold,
+        "100%",
+        ActionType::SendNotification,
+        "Budget used up",
+    )
+    .unwrap();
+
+    let fired =
+        record_platform_usage_at(&conn, &space_id, "twitter", 20 * MINUTE, utc(6, 9, 0)).unwrap();
+    assert!(fired.is_empty(), "66% is below the threshold");
+
+    let fired =
+        record_platform_usage_at(&conn, &space_id, "twitter", 5 * MINUTE, utc(6, 9, 30)).unwrap();
+    assert_eq!(fired.len(), 1);
+    assert_eq!(fired[0].rule_id, warn);
+    assert_eq!(fired[0].platform, "twitter");
+    assert_eq!(fired[0].usage_percent, 83);
+
+    let fired =
+        record_platform_usage_at(&conn, &space_id, "twitter", MINUTE, utc(6, 9, 40)).unwrap();
+    assert!(fired.is_empty(), "already fired today");
+
+    let fired =
+        record_platform_usage_at(&conn, &space_id, "reddit", 10 * MINUTE, utc(6, 10, 0)).unwrap();
+    assert_eq!(fired.len(), 1);
+    assert_eq!(fired[0].rule_name, "Any platform");
+
+    // A new day re-arms the rule
+    let fired =
+        record_platform_usage_at(&conn, &space_id, "twitter", 25 * MINUTE, utc(7, 9, 0)).unwrap();
+    assert_eq!(fired.len(), 1);
+    assert_eq!(fired[0].rule_id, warn);
+
+    // Disabled rules stay quiet
+    toggle_automation_rule(&conn, &warn, false).unwrap();
+    let fired =
+        record_platform_usage_at(&conn, &space_id, "twitter", 25 * MINUTE, utc(8, 9, 0)).unwrap();
+    assert!(fired.is_empty());
+}
@@ -0,0 +1,248 @@
+use core_rs::db::{get_setting, migrate};
+use core_rs::form::{create_form_template, get_form_templates_for_space, FormField, FormFieldType};
+use core_rs::mode::{enable_mode, get_space_modes, Mode};
+use core_rs::note::create_note;
+use core_rs::note_template::{
+    create_note_template, get_note_templates_for_space, set_daily_note_template,
+};
+use core_rs::search::saved::{create_saved_search, get_saved_searches};
+use core_rs::space::create_space;
+use core_rs::space_template::*;
+use core_rs::tag::{add_tag_to_note, create_tag};
+use rusqlite::Connection;
+use tempfile::tempdir;
+
+fn setup() -> Connection {
+    let mut conn = Connection::open_in_memory().unwrap();
+    conn.pragma_update(None, "foreign_keys", "ON").unwrap();
+    migrate(&mut conn).unwrap();
+    conn
+}
+
+fn tags(conn: &Connection, space_id: &str) -> Vec<(String, String, Option<String>)> {
+    let mut stmt = conn
+        .prepare("SELECT id, name, color FROM tag WHERE space_id = ?1 ORDER BY name")
+        .unwrap();
+    let rows = stmt
+        .query_map([space_id], |row| {
+            Ok((row.get(0)?, row.get(1)?, row.get(2)?))
+        })
+        .unwrap();
+    rows.collect::<Result<_, _>>().unwrap()
+}
+
+/// (id, title, content) of the space's notes by title
+fn notes(conn: &Connection, space_id: &str) -> Vec<(String, String, String)> {
+    let mut stmt = conn
+        .prepare("SELECT id, title, content_md FROM note WHERE space_id = ?1 ORDER BY title")
+        .unwrap();
+    let rows = stmt
+        .query_map([space_id], |row| {
+            Ok((row.get(0)?, row.get(1)?, row.get(2)?))
+        })
+        .unwrap();
+    rows.collect::<Result<_, _>>().unwrap()
+}
+
+fn links_from(conn: &Connection, note_id: &str) -> Vec<String> {
+    let mut stmt = conn
+        .prepare("SELECT target_note_id FROM link WHERE source_note_id = ?1")
+        .unwrap();
+    let rows = stmt.query_map([note_id], |row| row.get(0)).unwrap();
+    rows.collect::<Result<_, _>>().unwrap()
+}
+
+#[test]
+fn test_export_and_import_round_trip_with_new_ids() {
+    let mut conn = setup();
+    let source = create_space(&mut conn, "Client A").unwrap().to_string();
+    let health = Mode {
+        id: "health".to_string(),
+        name: "Health".to_string(),
+    };
+    enable_mode(&conn, &source, &health).unwrap();
+    let work = create_tag(&conn, &source, "work", Some("#ff0000")).unwrap();
+    create_tag(&conn, &source, "work/meetings", None).unwrap();
+    create_saved_search(&conn, "Meetings", "tag:work/meetings", Some(&source)).unwrap();
+    create_form_template(
+        &conn,
+        &source,
+        "Call log",
+        vec![FormField {
+            name: "who".to_string(),
+            label: "Who".to_string(),
+            field_type: FormFieldType::Text,
+            default_value: None,
+            required: true,
+            options: vec![],
+            mapping: None,
+        }],
+    )
+    .unwrap();
+    let daily = create_note_template(&conn, &source, "Daily", "# {{date}}\n", vec![]).unwrap();
+    set_daily_note_template(&conn, &source, Some(&daily.id)).unwrap();
+
+    let brief = create_note(&conn, &source, "Brief", "Scope and budget").unwrap();
+    let brief_id = brief.id.0.to_string();
+    let index = create_note(
+        &conn,
+        &source,
+        "Index",
+        &format!("Start with [the brief]({}).", brief_id),
+    )
+    .unwrap();
+    add_tag_to_note(&conn, &brief_id, "work").unwrap();
+    create_note(&conn, &source, "Private diary", "Not for the template").unwrap();
+
+    let dir = tempdir().unwrap();
+    let path = dir.path().join("client.json");
+    let exported = export_space_template(
+        &conn,
+        &source,
+        &path,
+        &[brief_id.clone(), index.id.0.to_string()],
+    )
+    .unwrap();
+    assert_eq!(exported.starter_notes.len(), 2);
+    assert!(!std::fs::read_to_string(&path)
+        .unwrap()
+        .contains("Private diary"));
+
+    let target = create_space_from_template(&mut conn, "Client B", &path)
+        .unwrap()
+        .to_string();
+    assert_ne!(target, source);
+    assert_eq!(
+        get_space_modes(&conn, &target).unwrap(),
+        get_space_modes(&conn, &source).unwrap()
+    );
+
+    let source_tags = tags(&conn, &source);
+    let target_tags = tags(&conn, &target);
+    let strip = |tags: &[(String, String, Option<String>)]| {
+        tags.iter()
+            .map(|(_, name, color)| (name.clone(), color.clone()))
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(strip(&target_tags), strip(&source_tags));
+    assert!(target_tags
+        .iter()
+        .all(|(id, _, _)| *id != work.id.to_string()));
+
+    let searches = get_saved_searches(&conn, Some(&target)).unwrap();
+    assert_eq!(searches.len(), 1);
+    assert_eq!(searches[0].query, "tag:work/meetings");
+    let forms = get_form_templates_for_space(&conn, &target).unwrap();
+    assert_eq!(forms.len(), 1);
+    assert_eq!(forms[0].fields[0].name, "who");
+
+    let templates = get_note_templates_for_space(&conn, &target).unwrap();
+    assert_eq!(templates.len(), 1);
+    assert_ne!(templates[0].id, daily.id);
+    assert_eq!(
+        get_setting(&conn, &format!("daily_note_template:{}", target)).unwrap(),
+        Some(templates[0].id.clone())
+    );
+
+    // Starter notes get new IDs, and links between them follow
+    let copied = notes(&conn, &target);
+    let titles = copied.iter().map(|n| n.1.as_str()).collect::<Vec<_>>();
+    assert_eq!(titles, vec!["Brief", "Index"]);
+    let (new_brief, new_index) = (&copied[0].0, &copied[1]);
+    assert_ne!(*new_brief, brief_id);
+    assert_eq!(
+        new_index.2,
+        format!("Start with [the brief]({}).", new_brief)
+    );
+    assert_eq!(links_from(&conn, &new_index.0), vec![new_brief.clone()]);
+    let brief_tags: i64 = conn
+        .query_row(
+            "SELECT COUNT(*) FROM note_tags WHERE note_id = ?1",
+            [new_brief],
+            |row| row.get(0),
+        )
+        .unwrap();
+    assert_eq!(brief_tags, 1);
+}
+
+#[test]
+fn test_builtin_templates_provision() {
+    let mut conn = setup();
+    for key in BUILTIN_SPACE_TEMPLATES {
+        let template = builtin_space_template(key).unwrap();
+        let space_id = provision_space(&mut conn, &template.name, &template)
+            .unwrap()
+            .to_string();
+
+        assert_eq!(get_space_modes(&conn, &space_id).unwrap(), template.modes);
+        assert_eq!(tags(&conn, &space_id).len(), template.tags.len());
+        assert_eq!(
+            get_note_templates_for_space(&conn, &space_id)
+                .unwrap()
+                .len(),
+            template.note_templates.len()
+        );
+        let starter = notes(&conn, &space_id);
+        assert_eq!(starter.len(), template.starter_notes.len());
+        // Each built-in's first starter note links to the second by title
+        let first = starter
+            .iter()
+            .find(|n| n.1 == template.starter_notes[0].title)
+            .unwrap();
+        let second = starter
+            .iter()
+            .find(|n| n.1 == template.starter_notes[1].title)
+            .unwrap();
+        assert_eq!(links_from(&conn, &first.0), vec![second.0.clone()]);
+    }
+    assert!(matches!(
+        builtin_space_template("wedding"),
+        Err(SpaceTemplateError::UnknownBuiltin(_))
+    ));
+}
+
+#[test]
+fn test_colliding_tag_names_are_merged() {
+    let mut conn = setup();
+    let template: SpaceTemplate = serde_json::from_str(
+        r##"{
+            "format_version": 1,
+            "name": "Hand-made",
+            "tags": [
+                { "name": "work", "color": null },
+                { "name": "#work", "color": "#00ff00" },
+                { "name": " work/meetings ", "color": null }
+            ],
+            "starter_notes": [
+                { "key": "agenda", "title": "Agenda", "content_md": "agenda", "tags": ["#work", "work/meetings"] }
+            ]
+        }"##,
+    )
+    .unwrap();
+    let space_id = provision_space(&mut conn, "Merged", &template)
+        .unwrap()
+        .to_string();
+
+    let merged = tags(&conn, &space_id)
+        .into_iter()
+        .map(|(_, name, color)| (name, color))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        merged,
+        vec![
+            ("work".to_string(), Some("#00ff00".to_string())),
+            ("work/meetings".to_string(), None),
+        ]
+    );
+    // A template without modes gets the core pack
+    assert_eq!(get_space_modes(&conn, &space_id).unwrap().len(), 4);
+
+    let newer = SpaceTemplate {
+        format_version: SPACE_TEMPLATE_FORMAT_VERSION + 1,
+        ..template
+    };
+    assert!(matches!(
+        provision_space(&mut conn, "Future", &newer),
+        Err(SpaceTemplateError::UnsupportedVersion(_))
+    ));
+}
@@ -1,6 +1,17 @@
+use core_rs::analytics::get_analytics_data;
 use core_rs::db::migrate;
-use core_rs::space::create_space;
-use rusqlite::Connection;
+use core_rs::foresight::generate_insights;
+use core_rs::note::create_note;
+use core_rs::search::{search_all, EntityType, SearchFilters, SearchQuery, SortOptions};
+use core_rs::space::{
+    archive_space, create_space, delete_space, get_all_spaces, get_space_deletion_token,
+    is_space_archived, unarchive_space, SpaceError,
+};
+use core_rs::sync::SyncAgent;
+use core_rs::tag::create_tag;
+use core_rs::task::create_task;
+use core_rs::time_tracking::{create_manual_time_entry, CreateManualEntryParams};
+use rusqlite::{params, Connection};
 
 #[test]
 fn test_create_space() {
@@ -20,3 +31,257 @@ fn test_create_space() {
     let modes = core_rs::mode::get_space_modes(&conn, &space_id.to_string()).unwrap();
     assert_eq!(modes.len(), 4);
 }
+
+fn setup_spaces() -> (Connection, String, String) {
+    let mut conn = Connection::open_in_memory().unwrap();
+    conn.pragma_update(None, "foreign_keys", "ON").unwrap();
+    migrate(&mut conn).unwrap();
+    let kept = create_space(&mut conn, "Personal").unwrap().to_string();
+    let old = create_space(&mut conn, "Client ACME").unwrap().to_string();
+    (conn, kept, old)
+}
+
+fn search(conn: &Connection, space_id: Option<&str>) -> Vec<String> {
+    let query = SearchQuery {
+        query: "budget".to_string(),
+        entity_types: vec![EntityType::All],
+        filters: SearchFilters {
+            space_id: space_id.map(|id| id.parse().unwrap()),
+            ..Default::default()
+        },
+        sort: SortOptions::default(),
+        limit: None,
+        offset: None,
+    };
+    let mut ids: Vec<String> = search_all(conn, &query)
+        .unwrap()
+        .into_iter()
+        .map(|r| r.entity_id)
+        .collect();
+    ids.sort();
+    ids
+}
+
+#[test]
+fn test_archived_space_is_hidden() {
+    let (conn, kept, old) = setup_spaces();
+    let kept_note = create_note(&conn, &kept, "Budget", "household budget").unwrap();
+    let old_note = create_note(&conn, &old, "Budget", "client budget").unwrap();
+    let task = create_task(&conn, old.parse().unwrap(), "Send invoice", None).unwrap();
+    conn.execute(
+        "UPDATE task SET due_at = ?1 WHERE id = ?2",
+        params![chrono::Utc::now().timestamp() + 3600, task.id.to_string()],
+    )
+    .unwrap();
+
+    archive_space(&conn, &old).unwrap();
+    assert!(is_space_archived(&conn, &old).unwrap());
+
+    let visible: Vec<String> = get_all_spaces(&conn, false)
+        .unwrap()
+        .iter()
+        .map(|s| s.id.to_string())
+        .collect();
+    assert_eq!(visible, vec![kept.clone()]);
+    let all = get_all_spaces(&conn, true).unwrap();
+    assert_eq!(all.len(), 2);
+    assert!(all
+        .iter()
+        .any(|s| s.id.to_string() == old && s.archived_at.is_some()));
+
+    // Search leaves the archived space out unless asked for it
+    assert_eq!(search(&conn, None), vec![kept_note.id.to_string()]);
+    assert_eq!(search(&conn, Some(&old)), vec![old_note.id.to_string()]);
+
+    let data = get_analytics_data(&conn).unwrap();
+    assert_eq!((data.note_count, data.task_count), (1, 0));
+
+    let agent = SyncAgent::new("desktop".into(), "Desktop".into(), 8765);
+    let old_ulid = old.parse().unwrap();
+    assert!(agent
+        .create_manifest(&conn, old_ulid)
+        .unwrap()
+        .entity_hashes
+        .is_empty());
+    assert!(generate_insights(&conn, old_ulid).unwrap().is_empty());
+
+    unarchive_space(&conn, &old).unwrap();
+    assert_eq!(get_all_spaces(&conn, false).unwrap().len(), 2);
+    assert_eq!(search(&conn, None).len(), 2);
+    assert_eq!(
+        agent
+            .create_manifest(&conn, old_ulid)
+            .unwrap()
+            .entity_hashes
+            .len(),
+        1
+    );
+    assert!(!generate_insights(&conn, old_ulid).unwrap().is_empty());
+
+    assert!(matches!(
+        archive_space(&conn, "missing"),
+        Err(SpaceError::NotFound(_))
+    ));
+}
+
+fn count(conn: &Connection, sql: &str, space_id: &str) -> i64 {
+    conn.query_row(sql, [space_id], |row| row.get(0)).unwrap()
+}
+
+#[test]
+fn test_delete_space_removes_all_rows() {
+    let (mut conn, kept, old) = setup_spaces();
+    create_note(&conn, &kept, "Kept", "stays").unwrap();
+
+    let note = create_note(&conn, &old, "Kickoff", "notes").unwrap();
+    let note_id = note.id.to_string();
+    let tag = create_tag(&conn, &old, "client", None).unwrap();
+    let task = create_task(&conn, old.parse().unwrap(), "Invoice", None).unwrap();
+    conn.execute(
+        "INSERT INTO note_tags (note_id, tag_id) VALUES (?1, ?2)",
+        params![note_id, tag.id.to_string()],
+    )
+    .unwrap();
+    conn.execute(
+        "INSERT INTO task_tags (task_id, tag_id) VALUES (?1, ?2)",
+        params![task.id.to_string(), tag.id.to_string()],
+    )
+    .unwrap();
+    create_manual_time_entry(
+        &conn,
+        CreateManualEntryParams {
+            space_id: old.parse().unwrap(),
+            task_id: Some(task.id),
+            project_id: None,
+            note_id: None,
+            description: None,
+            started_at: 0,
+            duration_seconds: 60,
+        },
+    )
+    .unwrap();
+    core_rs::project::create_project(&conn, &old, "Website").unwrap();
+    conn.execute(
+        "INSERT INTO social_account (id, space_id, platform, username, encrypted_credentials, created_at)
+         VALUES ('acct', ?1, 'twitter', 'acme', 'x', 0)",
+        [&old],
+    )
+    .unwrap();
+    conn.execute(
+        "INSERT INTO social_post (id, account_id, platform, author, timestamp, fetched_at, raw_json)
+         VALUES ('post', 'acct', 'twitter', 'acme', 0, 0, '{}')",
+        [],
+    )
+    .unwrap();
+    conn.execute(
+        "INSERT INTO sync_history (id, device_id, space_id, sync_time, direction, entities_pushed,
+                                   entities_pulled, conflicts_detected, success)
+         VALUES ('sync', 'peer', ?1, 0, 'push', 1, 0, 1, 1)",
+        [&old],
+    )
+    .unwrap();
+    conn.execute(
+        "INSERT INTO sync_conflict (id, entity_type, entity_id, local_version, remote_version,
+                                    conflict_type, detected_at, resolved, device_id, space_id)
+         VALUES ('conflict', 'note', ?1, x'00', x'00', 'UpdateUpdate', 0, 0, 'peer', ?2)",
+        params![note_id, old],
+    )
+    .unwrap();
+    conn.execute(
+        "INSERT INTO entity_sync_log (id, entity_type, entity_id, synced_at, device_id, operation)
+         VALUES ('log', 'note', ?1, 0, 'peer', 'update')",
+        [&note_id],
+    )
+    .unwrap();
+
+    // The token is derived from the name, not the id
+    assert!(matches!(
+        delete_space(&mut conn, &old, &old),
+        Err(SpaceError::ConfirmationMismatch { .. })
+    ));
+    assert_eq!(
+        count(&conn, "SELECT COUNT(*) FROM note WHERE space_id = ?1", &old),
+        1
+    );
+
+    let token = get_space_deletion_token(&conn, &old).unwrap();
+    assert_eq!(token, "delete-client-acme");
+    let summary = delete_space(&mut conn, &old, &token).unwrap();
+    assert_eq!(summary.name, "Client ACME");
+    let deleted = |table: &str| {
+        summary
+            .deleted
+            .iter()
+            .find(|d| d.table == table)
+            .map_or(0, |d| d.count)
+    };
+    for table in [
+        "note",
+        "task",
+        "tag",
+        "project",
+        "time_entry",
+        "social_post",
+        "space",
+    ] {
+        assert_eq!(deleted(table), 1, "{}", table);
+    }
+    assert_eq!(
+        summary.total_rows,
+        summary.deleted.iter().map(|d| d.count).sum::<i64>()
+    );
+
+    for sql in [
+        "SELECT COUNT(*) FROM space WHERE id = ?1",
+        "SELECT COUNT(*) FROM note WHERE space_id = ?1",
+        "SELECT COUNT(*) FROM task WHERE space_id = ?1",
+        "SELECT COUNT(*) FROM tag WHERE space_id = ?1",
+        "SELECT COUNT(*) FROM project WHERE space_id = ?1",
+        "SELECT COUNT(*) FROM time_entry WHERE space_id = ?1",
+        "SELECT COUNT(*) FROM social_account WHERE space_id = ?1",
+        "SELECT COUNT(*) FROM sync_history WHERE space_id = ?1",
+        "SELECT COUNT(*) FROM sync_conflict WHERE space_id = ?1",
+        "SELECT COUNT(*) FROM graph_change_log WHERE space_id = ?1",
+    ] {
+        assert_eq!(count(&conn, sql, &old), 0, "{}", sql);
+    }
+    for sql in [
+        "SELECT COUNT(*) FROM note_tags WHERE note_id = ?1",
+        "SELECT COUNT(*) FROM entity_sync_log WHERE entity_id = ?1",
+        "SELECT COUNT(*) FROM fts_note WHERE note_id = ?1",
+    ] {
+        assert_eq!(count(&conn, sql, &note_id), 0, "{}", sql);
+    }
+    assert_eq!(
+        count(
+            &conn,
+            "SELECT COUNT(*) FROM task_tags WHERE task_id = ?1",
+            &task.id.to_string()
+        ),
+        0
+    );
+    assert_eq!(
+        count(
+            &conn,
+            "SELECT COUNT(*) FROM social_post WHERE account_id = ?1",
+            "acct"
+        ),
+        0
+    );
+
+    // Other spaces are untouched
+    assert_eq!(
+        count(
+            &conn,
+            "SELECT COUNT(*) FROM note WHERE space_id = ?1",
+            &kept
+        ),
+        1
+    );
+    let remaining: Vec<String> = get_all_spaces(&conn, true)
+        .unwrap()
+        .iter()
+        .map(|s| s.id.to_string())
+        .collect();
+    assert_eq!(remaining, vec![kept]);
+}
@@ -0,0 +1,255 @@
+use chrono::{Days, NaiveTime, Utc};
+use core_rs::db::{migrate, DbError};
+use core_rs::note::create_note;
+use core_rs::space::create_space;
+use core_rs::srs::*;
+use core_rs::srs_session::*;
+use rusqlite::Connection;
+use tempfile::tempdir;
+use ulid::Ulid;
+
+const DAY: i64 = 86_400;
+
+fn setup_db() -> (tempfile::TempDir, Connection, String) {
+    let dir = tempdir().unwrap();
+    let mut conn = Connection::open(dir.path().join("test.db")).unwrap();
+    conn.pragma_update(None, "foreign_keys", "ON").unwrap();
+    migrate(&mut conn).unwrap();
+    let space_id = create_space(&mut conn, "Flashcards").unwrap().to_string();
+    (dir, conn, space_id)
+}
+
+/// 9:00 UTC tomorrow, after every card created by the test came due
+fn morning() -> i64 {
+    Utc::now()
+        .date_naive()
+        .checked_add_days(Days::new(1))
+        .unwrap()
+        .and_time(NaiveTime::from_hms_opt(9, 0, 0).unwrap())
+        .and_utc()
+        .timestamp()
+}
+
+fn new_card(conn: &Connection, space_id: &str) -> Ulid {
+    let note = create_note(conn, space_id, "Question", "Answer").unwrap();
+    create_knowledge_card(conn, note.id.0).unwrap().id
+}
+
+/// A card learned earlier and due again at `due_at`
+fn review_card_due(conn: &Connection, space_id: &str, deck: &str, due_at: i64) -> Ulid {
+    let id = new_card(conn, space_id);
+    conn.execute(
+        "UPDATE knowledge_card SET state = 'review', stability = 1.0, deck_id = ?1, due_at = ?2
+         WHERE id = ?3",
+        (deck, due_at, id.to_string()),
+    )
+    .unwrap();
+    id
+}
+
+fn config(max_reviews: usize, max_new_cards: usize, order: ReviewOrder) -> ReviewSessionConfig {
+    ReviewSessionConfig {
+        max_reviews,
+        max_new_cards,
+        order,
+    }
+}
+
+#[test]
+fn test_session_respects_daily_limits() {
+    let (_dir, conn, space_id) = setup_db();
+    let now = morning();
+    for _ in 0..5 {
+        new_card(&conn, &space_id);
+    }
+    for i in 0..4 {
+        review_card_due(&conn, &space_id, "default", now - DAY + i);
+    }
+
+    let limits = config(3, 2, ReviewOrder::DueDate);
+    let session = start_review_session_at(&conn, &space_id, &limits, now).unwrap();
+    // New cards are spread between the reviews
+    assert_eq!(
+        session.queue.iter().map(|q| q.is_new).collect::<Vec<_>>(),
+        vec![false, true, false, true, false]
+    );
+    for (i, queued) in session.queue.iter().enumerate() {
+        review_card_at(
+            &conn,
+            queued.card.id,
+            3,
+            Some(session.id),
+            now + 10 * i as i64,
+        )
+        .unwrap();
+    }
+    let stats = finish_review_session_at(&conn, session.id, now + 60).unwrap();
+    assert_eq!(stats.cards_seen, 5);
+    assert_eq!(stats.new_cards_seen, 2);
+    assert!(stats.completed);
+
+    // The day's limits hold across sessions
+    let again = start_review_session_at(&conn, &space_id, &limits, now + 120).unwrap();
+    assert!(again.queue.is_empty());
+    let more_new = config(3, 3, ReviewOrder::DueDate);
+    finish_review_session_at(&conn, again.id, now + 130).unwrap();
+    let third = start_review_session_at(&conn, &space_id, &more_new, now + 140).unwrap();
+    assert_eq!(third.queue.len(), 1);
+    assert!(third.queue[0].is_new);
+}
+
+#[test]
+fn test_interleave_alternates_decks() {
+    let (_dir, conn, space_id) = setup_db();
+    let now = morning();
+    for (deck, due_at) in [
+        ("spanish", now - 500),
+        ("spanish", now - 400),
+        ("anatomy", now - 300),
+        ("spanish", now - 200),
+        ("anatomy", now - 100),
+    ] {
+        review_card_due(&conn, &space_id, deck, due_at);
+    }
+
+    let session = start_review_session_at(
+        &conn,
+        &space_id,
+        &config(100, 0, ReviewOrder::Interleave),
+        now,
+    )
+    .unwrap();
+    let decks = session
+        .queue
+        .iter()
+        .map(|q| q.card.deck_id.clone().unwrap())
+        .collect::<Vec<_>>();
+    assert_eq!(
+        decks,
+        vec!["spanish", "anatomy", "spanish", "anatomy", "spanish"]
+    );
+    // Most overdue first within a deck
+    assert!(session.queue[0].card.due_at < session.queue[2].card.due_at);
+}
+
+#[test]
+fn test_resume_after_partial_completion() {
+    let (_dir, conn, space_id) = setup_db();
+    let now = morning();
+    let cards = (0..4)
+        .map(|i| review_card_due(&conn, &space_id, "default", now - DAY + i))
+        .collect::<Vec<_>>();
+
+    let session =
+        start_review_session_at(&conn, &space_id, &ReviewSessionConfig::default(), now).unwrap();
+    let order = session.queue.iter().map(|q| q.card.id).collect::<Vec<_>>();
+    assert_eq!(order, cards);
+    review_card_at(&conn, order[0], 3, Some(session.id), now + 30).unwrap();
+    review_card_at(&conn, order[1], 1, Some(session.id), now + 60).unwrap();
+    // Only queued cards can be answered in the session
+    let stray = review_card_due(&conn, &space_id, "default", now);
+    assert!(review_card_at(&conn, stray, 3, Some(session.id), now + 70).is_err());
+
+    // Picked up again in the afternoon; the break isn't counted
+    let resumed = start_review_session_at(
+        &conn,
+        &space_id,
+        &config(1, 1, ReviewOrder::Random),
+        now + 4 * 3600,
+    )
+    .unwrap();
+    assert!(resumed.resumed);
+    assert_eq!(resumed.id, session.id);
+    assert_eq!(resumed.answered, 2);
+    assert_eq!(
+        resumed.queue.iter().map(|q| q.card.id).collect::<Vec<_>>(),
+        order[2..]
+    );
+    assert_eq!(resumed.config, ReviewSessionConfig::default());
+    review_card_at(&conn, order[2], 3, Some(session.id), now + 4 * 3600 + 20).unwrap();
+
+    let stats = finish_review_session_at(&conn, session.id, now + 4 * 3600 + 30).unwrap();
+    assert_eq!(stats.cards_seen, 3);
+    assert_eq!(stats.correct, 2);
+    assert!((stats.accuracy - 2.0 / 3.0).abs() < 1e-9);
+    assert_eq!(stats.time_spent_secs, 30 + 30 + 20);
+    assert!(!stats.completed);
+    assert_eq!(stats.streak_days, 0);
+
+    // An unfinished session is abandoned the next day
+    let left = start_review_session_at(
+        &conn,
+        &space_id,
+        &ReviewSessionConfig::default(),
+        now + 5 * 3600,
+    )
+    .unwrap();
+    assert!(!left.resumed);
+    let next_day =
+        start_review_session_at(&conn, &space_id, &ReviewSessionConfig::default(), now + DAY)
+            .unwrap();
+    assert_ne!(next_day.id, left.id);
+    assert_eq!(
+        get_review_session(&conn, left.id).unwrap().status,
+        ReviewSessionStatus::Abandoned
+    );
+    assert!(matches!(
+        finish_review_session_at(&conn, left.id, now + DAY),
+        Err(DbError::Locked { .. })
+    ));
+    assert!(matches!(
+        review_card_at(&conn, order[3], 3, Some(left.id), now + DAY),
+        Err(DbError::Locked { .. })
+    ));
+}
+
+#[test]
+fn test_streak_across_a_week() {
+    let (_dir, conn, space_id) = setup_db();
+    let monday = morning();
+    let card = review_card_due(&conn, &space_id, "default", monday);
+
+    // Reviewed Monday to Wednesday, skipped Thursday, only started on
+    // Friday, then reviewed over the weekend
+    let mut streaks = Vec::new();
+    for (day, answer) in [
+        (0, true),
+        (1, true),
+        (2, true),
+        (4, false),
+        (5, true),
+        (6, true),
+    ] {
+        let now = monday + day * DAY;
+        conn.execute(
+            "UPDATE knowledge_card SET due_at = ?1 WHERE id = ?2",
+            (now - 60, card.to_string()),
+        )
+        .unwrap();
+        let session =
+            start_review_session_at(&conn, &space_id, &ReviewSessionConfig::default(), now)
+                .unwrap();
+        assert_eq!(session.queue.len(), 1);
+        if answer {
+            review_card_at(&conn, card, 3, Some(session.id), now + 20).unwrap();
+        }
+        let stats = finish_review_session_at(&conn, session.id, now + 30).unwrap();
+        assert_eq!(stats.completed, answer);
+        streaks.push((stats.streak_days, stats.longest_streak_days));
+    }
+    assert_eq!(
+        streaks,
+        vec![(1, 1), (2, 2), (3, 3), (0, 3), (1, 3), (2, 3)]
+    );
+
+    // Still shown the morning after, gone once a day is missed
+    let streak = get_review_streak_at(&conn, &space_id, monday + 7 * DAY).unwrap();
+    assert_eq!(streak.current_days, 2);
+    assert_eq!(streak.longest_days, 3);
+    assert_eq!(
+        get_review_streak_at(&conn, &space_id, monday + 8 * DAY)
+            .unwrap()
+            .current_days,
+        0
+    );
+}
@@ -33,7 +33,7 @@ fn test_srs_lifecycle() -> Result<(), DbError> {
     assert_eq!(card.note_id, note.id.0);
 
     // Review the card
-    review_card(&conn, card.id, 3).unwrap();
+    review_card(&conn, card.id, 3, None).unwrap();
 
     // Get the card
     let fetched_card = get_knowledge_card(&conn, card.id).unwrap().unwrap();
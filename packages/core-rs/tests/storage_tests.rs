@@ -0,0 +1,194 @@
+use core_rs::db::storage::storage_category;
+use core_rs::db::{get_storage_report, migrate, vacuum_vault, StorageCategory, StorageError};
+use core_rs::note::create_note;
+use core_rs::space::create_space;
+use rusqlite::Connection;
+use tempfile::TempDir;
+
+const BIG_NOTE: usize = 300_000;
+const BIG_POST: usize = 2 * 1024 * 1024;
+const BLOB_SIZES: [i64; 2] = [5 * 1024 * 1024, 3 * 1024 * 1024];
+
+struct Vault {
+    conn: Connection,
+    space_a: String,
+    space_b: String,
+    _dir: TempDir,
+}
+
+/// A file-backed vault with a large note, two attachments and a large social
+/// post in space A and a small note in space B
+fn setup() -> Vault {
+    let dir = tempfile::tempdir().unwrap();
+    let mut conn = Connection::open(dir.path().join("vault.db")).unwrap();
+    migrate(&mut conn).unwrap();
+    let space_a = create_space(&mut conn, "Work").unwrap().to_string();
+    let space_b = create_space(&mut conn, "Home").unwrap().to_string();
+
+    let big = create_note(
+        &conn,
+        &space_a,
+        "Thesis",
+        &"lorem ipsum ".repeat(BIG_NOTE / 12),
+    )
+    .unwrap();
+    create_note(&conn, &space_b, "Groceries", "milk, eggs").unwrap();
+    for (i, size) in BLOB_SIZES.iter().enumerate() {
+        let blob_id = format!("blob-{}", i);
+        conn.execute(
+            "INSERT INTO blob (id, size_bytes, mime_type, created_at)
+             VALUES (?1, ?2, 'application/pdf', 0)",
+            rusqlite::params![blob_id, size],
+        )
+        .unwrap();
+        conn.execute(
+            "INSERT INTO note_attachment (note_id, blob_id, filename, created_at)
+             VALUES (?1, ?2, 'paper.pdf', 0)",
+            rusqlite::params![big.id.to_string(), blob_id],
+        )
+        .unwrap();
+    }
+    conn.execute(
+        "INSERT INTO social_account
+             (id, space_id, platform, username, encrypted_credentials, created_at)
+         VALUES ('acct', ?1, 'mastodon', 'me', '', 0)",
+        [&space_a],
+    )
+    .unwrap();
+    conn.execute(
+        "INSERT INTO social_post
+             (id, account_id, platform, author, content, timestamp, fetched_at, raw_json)
+         VALUES ('post', 'acct', 'mastodon', 'me', 'hello', 0, 0, ?1)",
+        ["x".repeat(BIG_POST)],
+    )
+    .unwrap();
+
+    Vault {
+        conn,
+        space_a,
+        space_b,
+        _dir: dir,
+    }
+}
+
+#[test]
+fn test_categories_attribute_tables() {
+    assert_eq!(storage_category("note_tags"), StorageCategory::Notes);
+    assert_eq!(storage_category("blob"), StorageCategory::Attachments);
+    assert_eq!(storage_category("social_post"), StorageCategory::Social);
+    assert_eq!(
+        storage_category("social_post_fts"),
+        StorageCategory::SearchIndex
+    );
+    assert_eq!(storage_category("fts_note"), StorageCategory::SearchIndex);
+    assert_eq!(
+        storage_category("entity_sync_log"),
+        StorageCategory::SyncBookkeeping
+    );
+    assert_eq!(storage_category("undo_log"), StorageCategory::History);
+    assert_eq!(storage_category("task"), StorageCategory::Other);
+}
+
+#[test]
+fn test_report_attributes_seeded_data() {
+    let vault = setup();
+    let report = get_storage_report(&vault.conn).unwrap();
+
+    let category = |c: StorageCategory| {
+        report
+            .categories
+            .iter()
+            .find(|u| u.category == c)
+            .unwrap()
+            .clone()
+    };
+    assert_eq!(report.categories.len(), StorageCategory::ALL.len());
+    let notes = category(StorageCategory::Notes);
+    assert!(notes.bytes >= BIG_NOTE as u64, "{:?}", notes);
+    let social = category(StorageCategory::Social);
+    assert!(social.bytes >= BIG_POST as u64, "{:?}", social);
+    // The note's text is indexed for search
+    assert!(category(StorageCategory::SearchIndex).bytes >= BIG_NOTE as u64);
+    let attachments = category(StorageCategory::Attachments);
+    assert_eq!(
+        attachments.external_bytes,
+        BLOB_SIZES.iter().sum::<i64>() as u64
+    );
+    // Blob contents are outside the file; only their bookkeeping is in it
+    assert!(attachments.bytes < 1024 * 1024);
+    // Attachments are the largest category once their contents count
+    assert_eq!(report.categories[0].category, StorageCategory::Attachments);
+
+    let table = |name: &str| report.tables.iter().find(|t| t.table == name).unwrap();
+    assert_eq!(table("note").rows, 2);
+    assert_eq!(table("note").category, StorageCategory::Notes);
+    assert_eq!(table("blob").rows, 2);
+    assert_eq!(table("social_post").rows, 1);
+    assert_eq!(report.tables[0].table, "social_post");
+
+    let space = |space_id: &str, c: StorageCategory| {
+        report
+            .spaces
+            .iter()
+            .find(|s| s.space_id == space_id && s.category == c)
+            .unwrap()
+    };
+    let notes_a = space(&vault.space_a, StorageCategory::Notes);
+    let notes_b = space(&vault.space_b, StorageCategory::Notes);
+    assert_eq!(notes_a.rows, 1);
+    assert!(notes_a.bytes >= BIG_NOTE as u64);
+    assert!(notes_b.bytes < 1024);
+    assert_eq!(space(&vault.space_a, StorageCategory::Social).rows, 1);
+
+    assert!(report.file_bytes >= (BIG_NOTE + BIG_POST) as u64);
+    assert!(!report.vacuum_recommended);
+}
+
+#[test]
+fn test_vacuum_reclaims_free_pages() {
+    let vault = setup();
+    vault.conn.execute("DELETE FROM social_post", []).unwrap();
+
+    let report = get_storage_report(&vault.conn).unwrap();
+    assert!(report.free_pages * report.page_size >= BIG_POST as u64);
+    assert!(report.vacuum_recommended);
+
+    let result = vacuum_vault(&vault.conn).unwrap();
+    assert_eq!(result.file_bytes_before, report.file_bytes);
+    assert!(result.file_bytes_after + BIG_POST as u64 <= result.file_bytes_before);
+
+    let report = get_storage_report(&vault.conn).unwrap();
+    assert_eq!(report.free_pages, 0);
+    assert!(!report.vacuum_recommended);
+    assert_eq!(report.file_bytes, result.file_bytes_after);
+}
+
+#[test]
+fn test_vacuum_refuses_while_a_write_is_in_flight() {
+    let vault = setup();
+    vault.conn.execute("DELETE FROM social_post", []).unwrap();
+
+    // Another connection is in the middle of a write
+    let path = vault.conn.path().unwrap().to_string();
+    let writer = Connection::open(path).unwrap();
+    writer.execute_batch("BEGIN IMMEDIATE").unwrap();
+    writer
+        .execute("DELETE FROM note WHERE title = 'Groceries'", [])
+        .unwrap();
+    assert!(matches!(
+        vacuum_vault(&vault.conn),
+        Err(StorageError::Busy(_))
+    ));
+    writer.execute_batch("ROLLBACK").unwrap();
+
+    // Nor inside a transaction of its own
+    vault.conn.execute_batch("BEGIN").unwrap();
+    assert!(matches!(
+        vacuum_vault(&vault.conn),
+        Err(StorageError::Busy(_))
+    ));
+    vault.conn.execute_batch("ROLLBACK").unwrap();
+
+    vacuum_vault(&vault.conn).unwrap();
+    assert_eq!(get_storage_report(&vault.conn).unwrap().free_pages, 0);
+}
@@ -3,7 +3,7 @@
 
 use core_rs::db;
 use core_rs::sync_agent::{
-    init_sync_tables, ConflictResolution, ConflictType, DeviceType, SyncAgent,
+    init_sync_tables, ConflictResolution, ConflictType, DeviceType, SyncAgent, SyncTransport,
 };
 use rusqlite::Connection;
 use tempfile::{tempdir, TempDir};
@@ -346,6 +346,7 @@ fn test_record_sync_history() {
         conflicts: 0,
         success: true,
         error_message: None,
+        transport: SyncTransport::Direct,
     };
     agent
         .record_sync_history(&conn, params)
@@ -379,6 +380,7 @@ fn test_get_sync_history() {
             conflicts: 0,
             success: true,
             error_message: None,
+            transport: SyncTransport::Direct,
         };
         agent
             .record_sync_history(&conn, params)
@@ -626,6 +628,7 @@ fn test_bulk_sync_history_query() {
             conflicts: 0,
             success: true,
             error_message: None,
+            transport: SyncTransport::Direct,
         };
         agent
             .record_sync_history(&conn, params)
@@ -0,0 +1,233 @@
+use core_rs::crypto::encrypt_bytes;
+use core_rs::db::migrate;
+use core_rs::sync::{describe_conflict, get_unresolved_conflicts_described, FieldChange};
+use core_rs::sync_agent::{
+    init_sync_tables, DeviceInfo, DeviceType, SyncAgent, SyncDelta, SyncOperation,
+};
+use rusqlite::Connection;
+use std::collections::HashMap;
+use ulid::Ulid;
+
+const DEK: [u8; 32] = [7u8; 32];
+
+struct Vault {
+    conn: Connection,
+    agent: SyncAgent,
+    space_id: String,
+    last_sync: i64,
+}
+
+/// A vault that last synced with the phone an hour ago, with both devices
+/// registered under their names
+fn setup() -> Vault {
+    let mut conn = Connection::open_in_memory().unwrap();
+    migrate(&mut conn).unwrap();
+    init_sync_tables(&conn).unwrap();
+    let agent = SyncAgent::new("desktop".into(), "Desktop".into(), 0);
+    for (device_id, device_name) in [("desktop", "Work laptop"), ("phone", "Pixel 8")] {
+        agent
+            .register_device(
+                &conn,
+                &DeviceInfo {
+                    device_id: device_id.to_string(),
+                    device_name: device_name.to_string(),
+                    device_type: DeviceType::Desktop,
+                    last_seen: 0,
+                    sync_address: "127.0.0.1".to_string(),
+                    sync_port: 0,
+                    protocol_version: "2.1.0".to_string(),
+                },
+            )
+            .unwrap();
+    }
+
+    let space_id = Ulid::new().to_string();
+    conn.execute(
+        "INSERT INTO space (id, name) VALUES (?1, 'Test Space')",
+        [&space_id],
+    )
+    .unwrap();
+    let last_sync = chrono::Utc::now().timestamp() - 3600;
+    conn.execute(
+        "INSERT INTO sync_history (id, device_id, space_id, sync_time, direction, success)
+         VALUES (?1, 'phone', ?2, ?3, 'pull', 1)",
+        rusqlite::params![Ulid::new().to_string(), space_id, last_sync],
+    )
+    .unwrap();
+    Vault {
+        conn,
+        agent,
+        space_id,
+        last_sync,
+    }
+}
+
+impl Vault {
+    /// Receive a conflicting change to `entity_id` from the phone
+    fn receive(&mut self, entity_type: &str, entity_id: &str, data: Vec<u8>) {
+        let delta = SyncDelta {
+            entity_type: entity_type.to_string(),
+            entity_id: entity_id.to_string(),
+            operation: SyncOperation::Update,
+            data: Some(data),
+            timestamp: self.last_sync + 200,
+            vector_clock: HashMap::new(),
+            space_id: Some(self.space_id.clone()),
+        };
+        let conflicts = self
+            .agent
+            .apply_deltas_from(&mut self.conn, vec![delta], &DEK, "phone")
+            .unwrap();
+        assert_eq!(conflicts.len(), 1);
+    }
+}
+
+#[test]
+fn test_note_conflict_shows_where_the_bodies_differ() {
+    let mut vault = setup();
+    let note_id = Ulid::new().to_string();
+    let local = format!(
+        "# Lisbon\n\n{}Flights leave Monday.",
+        "Pack light. ".repeat(30)
+    );
+    vault
+        .conn
+        .execute(
+            "INSERT INTO note (id, space_id, title, content_md, created_at, modified_at)
+             VALUES (?1, ?2, 'Trip plan', ?3, ?4, ?4)",
+            rusqlite::params![note_id, vault.space_id, local, vault.last_sync + 100],
+        )
+        .unwrap();
+    // The phone's body arrives encrypted with the vault key
+    let remote = local.replace("Monday", "Tuesday");
+    vault.receive(
+        "note",
+        &note_id,
+        encrypt_bytes(remote.as_bytes(), &DEK).unwrap(),
+    );
+
+    let summaries = get_unresolved_conflicts_described(&vault.conn, &DEK).unwrap();
+    assert_eq!(summaries.len(), 1);
+    let summary = &summaries[0];
+    assert_eq!(summary.title.as_deref(), Some("Trip plan"));
+    assert_eq!(summary.problems, Vec::<String>::new());
+    assert_eq!(summary.local_device.as_deref(), Some("Work laptop"));
+    assert_eq!(summary.remote_device.as_deref(), Some("Pixel 8"));
+    assert_eq!(summary.local_modified_at, Some(vault.last_sync + 100));
+    assert_eq!(summary.remote_modified_at, Some(vault.last_sync + 200));
+    assert!(summary.detected_at.is_some());
+
+    assert_eq!(summary.changes.len(), 1);
+    let change = &summary.changes[0];
+    assert_eq!(change.field, "content");
+    let shown_local = change.local.as_deref().unwrap();
+    let shown_remote = change.remote.as_deref().unwrap();
+    assert!(shown_local.starts_with('…'));
+    assert!(shown_local.ends_with("Pack light. Flights leave Monday."));
+    assert!(shown_remote.ends_with("Flights leave Tuesday."));
+    assert!(shown_local.chars().count() < 60);
+
+    // The same summary for a conflict handed back by the UI
+    let described = describe_conflict(&vault.conn, &summary.conflict, &DEK).unwrap();
+    assert_eq!(described.conflict_id, summary.conflict_id);
+    assert_eq!(described.changes, summary.changes);
+}
+
+#[test]
+fn test_task_conflict_lists_changed_fields() {
+    let mut vault = setup();
+    let task_id = Ulid::new().to_string();
+    vault
+        .conn
+        .execute(
+            "INSERT INTO task (id, space_id, title, status, updated_at)
+             VALUES (?1, ?2, 'Buy milk', 'next', ?3)",
+            rusqlite::params![task_id, vault.space_id, vault.last_sync + 100],
+        )
+        .unwrap();
+    let details = "Whole, not skimmed. ".repeat(10);
+    let remote = serde_json::json!({
+        "title": "Buy oat milk",
+        "status": "done",
+        "description": details,
+        "priority": null,
+    });
+    vault.receive("task", &task_id, remote.to_string().into_bytes());
+
+    let summaries = get_unresolved_conflicts_described(&vault.conn, &DEK).unwrap();
+    let summary = &summaries[0];
+    assert_eq!(summary.title.as_deref(), Some("Buy milk"));
+    assert_eq!(
+        summary.changes,
+        [
+            FieldChange {
+                field: "description".to_string(),
+                local: None,
+                remote: Some(format!("{}…", &details[..120])),
+            },
+            FieldChange {
+                field: "status".to_string(),
+                local: Some("next".to_string()),
+                remote: Some("done".to_string()),
+            },
+            FieldChange {
+                field: "title".to_string(),
+                local: Some("Buy milk".to_string()),
+                remote: Some("Buy oat milk".to_string()),
+            },
+        ]
+    );
+}
+
+#[test]
+fn test_unreadable_versions_give_a_partial_summary() {
+    let mut vault = setup();
+    let project_id = Ulid::new().to_string();
+    vault
+        .conn
+        .execute(
+            "INSERT INTO project (id, space_id, title, status, updated_at)
+             VALUES (?1, ?2, 'Website launch', 'active', ?3)",
+            rusqlite::params![project_id, vault.space_id, vault.last_sync + 100],
+        )
+        .unwrap();
+    vault.receive("project", &project_id, br#"{"name": "Website rel"#.to_vec());
+
+    let note_id = Ulid::new().to_string();
+    vault
+        .conn
+        .execute(
+            "INSERT INTO note (id, space_id, content_md, created_at, modified_at)
+             VALUES (?1, ?2, '## Groceries\nEggs', ?3, ?3)",
+            rusqlite::params![note_id, vault.space_id, vault.last_sync + 100],
+        )
+        .unwrap();
+    // Encrypted with some other vault's key
+    let foreign = encrypt_bytes(b"Milk", &[9u8; 32]).unwrap();
+    vault.receive("note", &note_id, foreign);
+
+    let summaries = get_unresolved_conflicts_described(&vault.conn, &DEK).unwrap();
+    assert_eq!(summaries.len(), 2);
+    let project = summaries
+        .iter()
+        .find(|s| s.conflict.entity_id == project_id)
+        .unwrap();
+    assert_eq!(project.title.as_deref(), Some("Website launch"));
+    assert!(project.changes.is_empty());
+    assert_eq!(project.problems.len(), 1);
+    assert!(project.problems[0].starts_with("The remote version can't be read: invalid JSON"));
+    assert_eq!(project.remote_device.as_deref(), Some("Pixel 8"));
+    assert_eq!(project.local_modified_at, Some(vault.last_sync + 100));
+
+    let note = summaries
+        .iter()
+        .find(|s| s.conflict.entity_id == note_id)
+        .unwrap();
+    // Untitled, so named after its first heading
+    assert_eq!(note.title.as_deref(), Some("Groceries"));
+    assert!(note.changes.is_empty());
+    assert_eq!(
+        note.problems,
+        ["The remote version can't be read: neither text nor decryptable with the vault key"]
+    );
+}
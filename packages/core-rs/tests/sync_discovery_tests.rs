@@ -0,0 +1,131 @@
+use core_rs::sync::discovery::{
+    add_manual_peer, get_manual_peers, probe_peer, remove_manual_peer, DiscoveryError,
+    DiscoveryService, DiscoverySource, PeerHealthCache, Reachability,
+};
+use core_rs::sync_agent::{init_sync_tables, SyncAgent};
+use rusqlite::Connection;
+use std::net::TcpListener;
+use std::path::Path;
+use std::time::Duration;
+use tempfile::tempdir;
+
+fn open_db(path: &Path) -> Connection {
+    let conn = Connection::open(path).unwrap();
+    init_sync_tables(&conn).unwrap();
+    conn
+}
+
+/// A port nothing listens on
+fn closed_port() -> u16 {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    listener.local_addr().unwrap().port()
+}
+
+#[test]
+fn test_manual_peers_persist_across_restarts() {
+    let dir = tempdir().unwrap();
+    let db_path = dir.path().join("sync.db");
+    {
+        let conn = open_db(&db_path);
+        add_manual_peer(&conn, "192.168.1.20", 8765, "Office desktop").unwrap();
+        add_manual_peer(&conn, "laptop.local", 9000, "").unwrap();
+        // Adding the same address again only renames it
+        add_manual_peer(&conn, "192.168.1.20", 8765, "Work desktop").unwrap();
+
+        assert!(matches!(
+            add_manual_peer(&conn, "", 8765, "Nothing"),
+            Err(DiscoveryError::InvalidPeer(_))
+        ));
+        assert!(matches!(
+            add_manual_peer(&conn, "10.0.0.1", 0, "No port"),
+            Err(DiscoveryError::InvalidPeer(_))
+        ));
+    }
+
+    let conn = open_db(&db_path);
+    let peers = get_manual_peers(&conn).unwrap();
+    let summary: Vec<(&str, &str, u16)> = peers
+        .iter()
+        .map(|p| (p.name.as_str(), p.address.as_str(), p.port))
+        .collect();
+    assert_eq!(
+        summary,
+        vec![
+            ("Work desktop", "192.168.1.20", 8765),
+            ("laptop.local", "laptop.local", 9000)
+        ]
+    );
+    assert!(peers.iter().all(|p| p.source == DiscoverySource::Manual));
+
+    // mDNS-registered devices are not manual peers and cannot be removed as such
+    let agent = SyncAgent::new("desktop-b".into(), "Desktop B".into(), 8765);
+    agent
+        .register_device(&conn, &agent.get_device_info())
+        .unwrap();
+    assert_eq!(get_manual_peers(&conn).unwrap().len(), 2);
+    assert!(!remove_manual_peer(&conn, "desktop-b").unwrap());
+
+    assert!(remove_manual_peer(&conn, &peers[1].id).unwrap());
+    assert!(!remove_manual_peer(&conn, &peers[1].id).unwrap());
+    assert_eq!(get_manual_peers(&conn).unwrap().len(), 1);
+}
+
+#[test]
+fn test_probe_detects_listening_peers() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let open = listener.local_addr().unwrap().port();
+    let timeout = Duration::from_millis(500);
+
+    assert!(probe_peer("127.0.0.1", open, timeout));
+    assert!(probe_peer("localhost", open, timeout));
+    assert!(!probe_peer("127.0.0.1", closed_port(), timeout));
+    assert!(!probe_peer("not a host", open, timeout));
+}
+
+#[test]
+fn test_health_cache_reuses_recent_probes() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let port = listener.local_addr().unwrap().port();
+    let cache = PeerHealthCache::new(Duration::from_secs(60), Duration::from_millis(500));
+
+    assert!(cache.get("127.0.0.1", port).is_none());
+    let first = cache.check("127.0.0.1", port);
+    assert!(first.reachable);
+
+    // The peer goes away, but the cached result stands until invalidated
+    drop(listener);
+    assert_eq!(cache.check("127.0.0.1", port), first);
+    cache.invalidate("127.0.0.1", port);
+    assert!(!cache.check("127.0.0.1", port).reachable);
+
+    let expiring = PeerHealthCache::new(Duration::ZERO, Duration::from_millis(500));
+    expiring.check("127.0.0.1", port);
+    assert!(expiring.get("127.0.0.1", port).is_none());
+}
+
+#[test]
+fn test_discovery_merges_manual_peers_with_reachability() {
+    let dir = tempdir().unwrap();
+    let conn = open_db(&dir.path().join("sync.db"));
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let live = listener.local_addr().unwrap().port();
+    let dead = closed_port();
+    add_manual_peer(&conn, "127.0.0.1", live, "Live").unwrap();
+    add_manual_peer(&conn, "127.0.0.1", dead, "Dead").unwrap();
+
+    let service = DiscoveryService::new().unwrap();
+    let devices = service.discover_all(&conn, Duration::ZERO).unwrap();
+    let summary: Vec<(&str, DiscoverySource, Reachability)> = devices
+        .iter()
+        .map(|d| (d.name.as_str(), d.source, d.reachability))
+        .collect();
+    assert_eq!(
+        summary,
+        vec![
+            ("Dead", DiscoverySource::Manual, Reachability::Unreachable),
+            ("Live", DiscoverySource::Manual, Reachability::Reachable)
+        ]
+    );
+    assert!(devices.iter().all(|d| d.last_probe_at.is_some()));
+    assert!(service.health().get("127.0.0.1", dead).is_some());
+}
@@ -0,0 +1,167 @@
+use core_rs::db::migrate;
+use core_rs::sync::merge::{MARKER_LOCAL, MARKER_REMOTE, MARKER_SEPARATOR};
+use core_rs::sync::{has_merge_conflict, merge_three_way, merge_two_way, ConflictResolution};
+use core_rs::sync_agent::{SyncAgent, SyncDelta, SyncOperation};
+use rusqlite::Connection;
+use std::collections::HashMap;
+use ulid::Ulid;
+
+const BASE: &str =
+    "# Trip\n\nFlights are booked for Monday.\n\nHotel near the station.\n\nPack light.";
+
+fn note_delta(space_id: &str, note_id: &str, content: &str, timestamp: i64) -> SyncDelta {
+    SyncDelta {
+        entity_type: "note".to_string(),
+        entity_id: note_id.to_string(),
+        operation: SyncOperation::Update,
+        data: Some(content.as_bytes().to_vec()),
+        timestamp,
+        vector_clock: HashMap::new(),
+        space_id: Some(space_id.to_string()),
+    }
+}
+
+/// Receive `BASE` from the phone, sync, then edit the note locally and merge
+/// the phone's conflicting edit. Without `shared_base` the note was written
+/// locally and there is no shadow to merge from.
+fn merge_after_sync(local: &str, remote: &str, shared_base: bool) -> (String, bool) {
+    let mut conn = Connection::open_in_memory().unwrap();
+    migrate(&mut conn).unwrap();
+    core_rs::sync_agent::init_sync_tables(&conn).unwrap();
+    let agent = SyncAgent::new("desktop".into(), "Desktop".into(), 0);
+    let dek = [0u8; 32];
+
+    let space_id = Ulid::new().to_string();
+    let note_id = Ulid::new().to_string();
+    conn.execute(
+        "INSERT INTO space (id, name) VALUES (?1, 'Test Space')",
+        [&space_id],
+    )
+    .unwrap();
+    let last_sync = chrono::Utc::now().timestamp() - 3600;
+    if shared_base {
+        let received = note_delta(&space_id, &note_id, BASE, last_sync - 100);
+        agent
+            .apply_deltas_from(&mut conn, vec![received], &dek, "phone")
+            .unwrap();
+    } else {
+        conn.execute(
+            "INSERT INTO note (id, space_id, content_md, created_at, modified_at)
+             VALUES (?1, ?2, ?3, ?4, ?4)",
+            rusqlite::params![note_id, space_id, BASE, last_sync - 100],
+        )
+        .unwrap();
+    }
+    conn.execute(
+        "INSERT INTO sync_history (id, device_id, space_id, sync_time, direction, success)
+         VALUES (?1, 'phone', ?2, ?3, 'pull', 1)",
+        rusqlite::params![Ulid::new().to_string(), space_id, last_sync],
+    )
+    .unwrap();
+
+    conn.execute(
+        "UPDATE note SET content_md = ?1, modified_at = ?2 WHERE id = ?3",
+        rusqlite::params![local, last_sync + 100, note_id],
+    )
+    .unwrap();
+    let incoming = note_delta(&space_id, &note_id, remote, last_sync + 200);
+    let conflicts = agent
+        .apply_deltas_from(&mut conn, vec![incoming], &dek, "phone")
+        .unwrap();
+    assert_eq!(conflicts.len(), 1);
+    agent
+        .resolve_conflict(&conn, &conflicts[0], ConflictResolution::Merge, &dek)
+        .unwrap();
+
+    let merged: String = conn
+        .query_row(
+            "SELECT content_md FROM note WHERE id = ?1",
+            [&note_id],
+            |row| row.get(0),
+        )
+        .unwrap();
+    (merged, has_merge_conflict(&conn, &note_id).unwrap())
+}
+
+#[test]
+fn test_non_overlapping_paragraph_edits_merge_cleanly() {
+    let local = BASE.replace("Monday", "Tuesday");
+    let remote = BASE.replace("near the station", "by the harbour");
+
+    let merged = merge_three_way(BASE, &local, &remote);
+    assert!(!merged.has_conflicts);
+    assert_eq!(
+        merged.content,
+        "# Trip\n\nFlights are booked for Tuesday.\n\nHotel by the harbour.\n\nPack light."
+    );
+
+    let (content, flagged) = merge_after_sync(&local, &remote, true);
+    assert_eq!(content, merged.content);
+    assert!(!flagged);
+    assert!(!content.contains("MERGED REMOTE CONTENT"));
+}
+
+#[test]
+fn test_same_line_edits_produce_markers() {
+    let local = BASE.replace("Pack light.", "Pack light, it's warm.");
+    let remote = BASE.replace("Pack light.", "Pack a raincoat.");
+
+    let expected = format!(
+        "# Trip\n\nFlights are booked for Monday.\n\nHotel near the station.\n\n{}\nPack light, it's warm.\n{}\nPack a raincoat.\n{}",
+        MARKER_LOCAL, MARKER_SEPARATOR, MARKER_REMOTE
+    );
+    let three_way = merge_three_way(BASE, &local, &remote);
+    assert!(three_way.has_conflicts);
+    assert_eq!(three_way.content, expected);
+    let two_way = merge_two_way(&local, &remote);
+    assert!(two_way.has_conflicts);
+    assert_eq!(two_way.content, expected);
+
+    let (content, flagged) = merge_after_sync(&local, &remote, true);
+    assert_eq!(content, expected);
+    assert!(flagged);
+}
+
+#[test]
+fn test_ancestor_merge_keeps_both_sides_additions() {
+    let local = BASE.replace("# Trip\n", "# Trip\n\nBudget: 800 EUR\n");
+    let remote = format!("{}\n\nDon't forget the charger.", BASE);
+
+    let merged = merge_three_way(BASE, &local, &remote);
+    assert!(!merged.has_conflicts);
+    assert_eq!(
+        merged.content,
+        "# Trip\n\nBudget: 800 EUR\n\nFlights are booked for Monday.\n\nHotel near the station.\n\nPack light.\n\nDon't forget the charger."
+    );
+
+    // A line one side removed stays removed
+    let trimmed = BASE.replace("\n\nPack light.", "");
+    let merged = merge_three_way(BASE, &local, &trimmed);
+    assert!(!merged.has_conflicts);
+    assert!(merged.content.contains("Budget: 800 EUR"));
+    assert!(!merged.content.contains("Pack light."));
+}
+
+#[test]
+fn test_merge_without_shadow_keeps_lines_from_either_side() {
+    let local = BASE.replace("# Trip\n", "# Trip\n\nBudget: 800 EUR\n");
+    let remote = BASE.replace("Pack light.", "Pack a raincoat.");
+
+    // Nothing says which side changed the last line, so it's marked
+    let (content, flagged) = merge_after_sync(&local, &remote, false);
+    assert!(flagged);
+    assert!(content.starts_with("# Trip\n\nBudget: 800 EUR\n"));
+    assert!(content.ends_with(&format!(
+        "{}\nPack light.\n{}\nPack a raincoat.\n{}",
+        MARKER_LOCAL, MARKER_SEPARATOR, MARKER_REMOTE
+    )));
+
+    // Additions on either side merge cleanly
+    let remote = format!("{}\n\nDon't forget the charger.", BASE);
+    let merged = merge_two_way(&local, &remote);
+    assert!(!merged.has_conflicts);
+    assert!(merged.content.starts_with("# Trip\n\nBudget: 800 EUR\n"));
+    assert!(merged
+        .content
+        .ends_with("Pack light.\n\nDon't forget the charger."));
+}
@@ -0,0 +1,295 @@
+use chrono::Utc;
+use core_rs::sync::mobile_sync::{DeviceInfo, DeviceType};
+use core_rs::sync::p2p::{P2pError, P2pSync};
+use core_rs::sync::{PairingCoordinator, PairingError, PairingHello};
+use core_rs::sync_agent::{init_sync_tables, SyncAgent};
+use rusqlite::Connection;
+
+fn setup_db() -> Connection {
+    let conn = Connection::open_in_memory().unwrap();
+    init_sync_tables(&conn).unwrap();
+    conn
+}
+
+fn register(conn: &Connection, device_id: &str) {
+    let agent = SyncAgent::new(device_id.to_string(), device_id.to_string(), 8765);
+    agent
+        .register_device(conn, &agent.get_device_info())
+        .unwrap();
+}
+
+fn is_trusted_in_db(conn: &Connection, device_id: &str) -> bool {
+    conn.query_row(
+        "SELECT trusted FROM sync_state WHERE device_id = ?1",
+        [device_id],
+        |row| row.get(0),
+    )
+    .unwrap()
+}
+
+/// Run the key exchange between two coordinators over an honest channel
+fn pair(alice: &PairingCoordinator, bob: &PairingCoordinator) {
+    let hello = alice.initiate_pairing("bob").unwrap();
+    let reply = bob.exchange_keys(&hello).unwrap();
+    let reveal = alice.exchange_keys(&reply).unwrap();
+    bob.exchange_keys(&reveal).unwrap();
+}
+
+#[test]
+fn test_both_devices_derive_the_same_sas() {
+    let alice = PairingCoordinator::new("alice".into());
+    let bob = PairingCoordinator::new("bob".into());
+    pair(&alice, &bob);
+
+    let alice_sas = alice.get_pending_pairing_sas("bob").unwrap();
+    let bob_sas = bob.get_pending_pairing_sas("alice").unwrap();
+    assert_eq!(alice_sas, bob_sas);
+    assert_eq!(alice_sas.decimal.len(), 6);
+    assert!(alice_sas.decimal.chars().all(|c| c.is_ascii_digit()));
+    assert_eq!(alice_sas.emoji.len(), 7);
+
+    // A fresh ceremony uses new ephemeral keys and a new SAS
+    let carol = PairingCoordinator::new("carol".into());
+    let hello = carol.initiate_pairing("bob").unwrap();
+    let reply = bob.exchange_keys(&hello).unwrap();
+    let reveal = carol.exchange_keys(&reply).unwrap();
+    bob.exchange_keys(&reveal).unwrap();
+    assert_ne!(bob.get_pending_pairing_sas("carol").unwrap(), bob_sas);
+}
+
+#[test]
+fn test_substituted_keys_produce_mismatched_sas() {
+    let alice = PairingCoordinator::new("alice".into());
+    let bob = PairingCoordinator::new("bob".into());
+    // The attacker answers each side with its own keys under the peer's id
+    let mallory_as_bob = PairingCoordinator::new("bob".into());
+    let mallory_as_alice = PairingCoordinator::new("alice".into());
+
+    let hello = alice.initiate_pairing("bob").unwrap();
+    let forged_reply = mallory_as_bob.exchange_keys(&hello).unwrap();
+    let reveal = alice.exchange_keys(&forged_reply).unwrap();
+    mallory_as_bob.exchange_keys(&reveal).unwrap();
+
+    let forged_hello = mallory_as_alice.initiate_pairing("bob").unwrap();
+    let reply = bob.exchange_keys(&forged_hello).unwrap();
+    let forged_reveal = mallory_as_alice.exchange_keys(&reply).unwrap();
+    bob.exchange_keys(&forged_reveal).unwrap();
+
+    assert_ne!(
+        alice.get_pending_pairing_sas("bob").unwrap(),
+        bob.get_pending_pairing_sas("alice").unwrap()
+    );
+}
+
+#[test]
+fn test_confirm_pairing_records_trust() {
+    let conn = setup_db();
+    register(&conn, "bob");
+    let alice = PairingCoordinator::new("alice".into());
+    let bob = PairingCoordinator::new("bob".into());
+
+    assert!(matches!(
+        alice.confirm_pairing(&conn, "bob", true),
+        Err(PairingError::NoPendingPairing(_))
+    ));
+    alice.initiate_pairing("bob").unwrap();
+    assert!(matches!(
+        alice.confirm_pairing(&conn, "bob", true),
+        Err(PairingError::KeyExchangeIncomplete(_))
+    ));
+
+    pair(&alice, &bob);
+    alice.confirm_pairing(&conn, "bob", true).unwrap();
+    assert!(alice.is_trusted("bob"));
+    assert!(is_trusted_in_db(&conn, "bob"));
+    assert!(alice.get_pending_pairing_sas("bob").is_none());
+
+    // Registering the device again keeps the verified trust
+    register(&conn, "bob");
+    assert!(is_trusted_in_db(&conn, "bob"));
+
+    // A later mismatch revokes it
+    pair(&alice, &bob);
+    alice.confirm_pairing(&conn, "bob", false).unwrap();
+    assert!(!alice.is_trusted("bob"));
+    assert!(!is_trusted_in_db(&conn, "bob"));
+}
+
+#[test]
+fn test_accepting_unregistered_device_fails() {
+    let conn = setup_db();
+    let alice = PairingCoordinator::new("alice".into());
+    let bob = PairingCoordinator::new("bob".into());
+    pair(&alice, &bob);
+
+    assert!(matches!(
+        alice.confirm_pairing(&conn, "bob", true),
+        Err(PairingError::DeviceNotRegistered(_))
+    ));
+    assert!(!alice.is_trusted("bob"));
+
+    let hello = PairingHello {
+        device_id: "bob".into(),
+        public_key: vec![0; 16],
+        static_key: vec![],
+        commitment: vec![],
+    };
+    assert!(matches!(
+        alice.exchange_keys(&hello),
+        Err(PairingError::InvalidPublicKey(_))
+    ));
+}
+
+#[test]
+fn test_trusted_peers_are_loaded_from_database() {
+    let conn = setup_db();
+    register(&conn, "bob");
+    register(&conn, "carol");
+    core_rs::sync::pairing::set_device_trusted(&conn, "carol", true).unwrap();
+
+    let alice = PairingCoordinator::new("alice".into());
+    assert_eq!(alice.load_trusted(&conn).unwrap(), 1);
+    assert!(alice.is_trusted("carol"));
+    assert!(!alice.is_trusted("bob"));
+}
+
+#[tokio::test]
+async fn test_p2p_refuses_untrusted_devices() {
+    let conn = setup_db();
+    register(&conn, "bob");
+    let p2p = P2pSync::new(DeviceInfo {
+        device_id: "alice".to_string(),
+        device_name: "Alice".to_string(),
+        device_type: DeviceType::Desktop,
+        ip_address: "127.0.0.1".parse().unwrap(),
+        sync_port: 8765,
+        public_key: vec![],
+        os_version: "test".to_string(),
+        last_seen: Utc::now(),
+        is_active: true,
+    })
+    .unwrap();
+
+    assert!(matches!(
+        p2p.start_sync("bob").await,
+        Err(P2pError::UntrustedDevice(_))
+    ));
+    let bob_static_key = [9u8; 32];
+    assert!(matches!(
+        p2p.authorize_peer("bob", &bob_static_key),
+        Err(P2pError::UntrustedDevice(_))
+    ));
+
+    let bob = PairingCoordinator::new("bob".into()).with_static_key(bob_static_key.to_vec());
+    let hello = p2p.initiate_pairing("bob").await.unwrap();
+    let reply = bob.exchange_keys(&hello).unwrap();
+    let reveal = p2p.exchange_keys(&reply).unwrap();
+    assert_eq!(reveal.static_key, p2p.static_public_key());
+    bob.exchange_keys(&reveal).unwrap();
+    assert_eq!(
+        p2p.get_pending_pairing_sas("bob"),
+        bob.get_pending_pairing_sas("alice")
+    );
+    p2p.confirm_pairing(&conn, "bob", true).unwrap();
+
+    // Trusted now, but only with the transport key confirmed at pairing
+    p2p.authorize_peer("bob", &bob_static_key).unwrap();
+    assert!(matches!(
+        p2p.authorize_peer("bob", &[8u8; 32]),
+        Err(P2pError::Channel(_))
+    ));
+    let pinned: Vec<u8> = conn
+        .query_row(
+            "SELECT static_public_key FROM sync_state WHERE device_id = 'bob'",
+            [],
+            |row| row.get(0),
+        )
+        .unwrap();
+    assert_eq!(pinned, bob_static_key);
+}
+
+#[test]
+fn test_substituted_static_key_changes_sas() {
+    let alice = PairingCoordinator::new("alice".into()).with_static_key(vec![1; 32]);
+    let bob = PairingCoordinator::new("bob".into()).with_static_key(vec![2; 32]);
+
+    let hello = alice.initiate_pairing("bob").unwrap();
+    let mut reply = bob.exchange_keys(&hello).unwrap();
+    // Same ephemeral key, but an attacker swaps in its own transport key
+    reply.static_key = vec![3; 32];
+    let reveal = alice.exchange_keys(&reply).unwrap();
+    bob.exchange_keys(&reveal).unwrap();
+
+    assert_ne!(
+        alice.get_pending_pairing_sas("bob").unwrap(),
+        bob.get_pending_pairing_sas("alice").unwrap()
+    );
+}
+
+#[test]
+fn test_initiator_keys_stay_hidden_until_responder_commits() {
+    let alice = PairingCoordinator::new("alice".into()).with_static_key(vec![1; 32]);
+    let bob = PairingCoordinator::new("bob".into()).with_static_key(vec![2; 32]);
+
+    let hello = alice.initiate_pairing("bob").unwrap();
+    assert!(hello.public_key.is_empty());
+    assert!(hello.static_key.is_empty());
+    assert_eq!(hello.commitment.len(), 32);
+
+    let reply = bob.exchange_keys(&hello).unwrap();
+    // The responder cannot show a SAS before the initiator has revealed
+    assert!(bob.get_pending_pairing_sas("alice").is_none());
+    let reveal = alice.exchange_keys(&reply).unwrap();
+    assert_eq!(
+        reveal.commitment,
+        Vec::<u8>::new(),
+        "the reveal carries keys, not a new commitment"
+    );
+    bob.exchange_keys(&reveal).unwrap();
+    assert_eq!(
+        alice.get_pending_pairing_sas("bob"),
+        bob.get_pending_pairing_sas("alice")
+    );
+}
+
+#[test]
+fn test_reveal_that_differs_from_commitment_is_rejected() {
+    let alice = PairingCoordinator::new("alice".into()).with_static_key(vec![1; 32]);
+    let bob = PairingCoordinator::new("bob".into()).with_static_key(vec![2; 32]);
+    let mallory = PairingCoordinator::new("alice".into()).with_static_key(vec![1; 32]);
+
+    let hello = alice.initiate_pairing("bob").unwrap();
+    let reply = bob.exchange_keys(&hello).unwrap();
+    // An attacker who saw Bob's key swaps in a freshly chosen one of its own
+    mallory.initiate_pairing("bob").unwrap();
+    let forged_reveal = mallory.exchange_keys(&reply).unwrap();
+
+    assert!(matches!(
+        bob.exchange_keys(&forged_reveal),
+        Err(PairingError::CommitmentMismatch(_))
+    ));
+    assert!(bob.get_pending_pairing_sas("alice").is_none());
+
+    // The ceremony is dropped; the genuine reveal no longer completes it
+    let reveal = alice.exchange_keys(&reply).unwrap();
+    assert!(matches!(
+        bob.exchange_keys(&reveal),
+        Err(PairingError::MissingCommitment(_))
+    ));
+}
+
+#[test]
+fn test_hello_without_commitment_does_not_start_pairing() {
+    let alice = PairingCoordinator::new("alice".into());
+    let hello = PairingHello {
+        device_id: "bob".into(),
+        public_key: vec![7; 32],
+        static_key: vec![],
+        commitment: vec![],
+    };
+    assert!(matches!(
+        alice.exchange_keys(&hello),
+        Err(PairingError::MissingCommitment(_))
+    ));
+    assert!(alice.get_pending_pairing_sas("bob").is_none());
+}
@@ -0,0 +1,209 @@
+use core_rs::db::migrate;
+use core_rs::sync::history::SyncHistory;
+use core_rs::sync::retention::AGGREGATE_DIRECTION;
+use core_rs::sync::{prune_sync_logs, SyncAgent, SyncLogRetentionPolicy};
+use rusqlite::{params, Connection};
+use tempfile::tempdir;
+
+const DAY: i64 = 86_400;
+const SPACE: &str = "01HSPACE00000000000000000";
+
+fn setup_db() -> (Connection, tempfile::TempDir) {
+    let dir = tempdir().unwrap();
+    let mut conn = Connection::open(dir.path().join("test.db")).unwrap();
+    migrate(&mut conn).unwrap();
+    core_rs::sync_agent::init_sync_tables(&conn).unwrap();
+    conn.execute(
+        "INSERT INTO space (id, name) VALUES (?1, 'Synced')",
+        [SPACE],
+    )
+    .unwrap();
+    conn.execute(
+        "INSERT INTO sync_state (device_id, device_name, device_type, last_seen, sync_address,
+                                 sync_port, protocol_version)
+         VALUES ('peer', 'Laptop', 'Desktop', 0, '10.0.0.2', 8765, '2.1.0')",
+        [],
+    )
+    .unwrap();
+    (conn, dir)
+}
+
+fn log_entity_sync(conn: &Connection, entity_id: &str, device_id: &str, synced_at: i64) {
+    conn.execute(
+        "INSERT INTO entity_sync_log (id, entity_type, entity_id, synced_at, device_id, operation)
+         VALUES (?1, 'note', ?2, ?3, ?4, '\"Update\"')",
+        params![
+            ulid::Ulid::new().to_string(),
+            entity_id,
+            synced_at,
+            device_id
+        ],
+    )
+    .unwrap();
+}
+
+/// A year of daily syncs with the registered peer: every fifth one failed,
+/// and every synced note is logged. Syncs happen mid-day so none sits on a
+/// retention cutoff.
+fn seed_year(conn: &Connection, now: i64) {
+    for day in 0..365 {
+        let sync_time = now - day * DAY - DAY / 2;
+        conn.execute(
+            "INSERT INTO sync_history (id, device_id, space_id, sync_time, direction,
+                                       entities_pushed, entities_pulled, conflicts_detected,
+                                       success, error_message)
+             VALUES (?1, 'peer', ?2, ?3, 'bidirectional', 3, 2, ?4, ?5, NULL)",
+            params![
+                ulid::Ulid::new().to_string(),
+                SPACE,
+                sync_time,
+                (day % 30 == 0) as i64,
+                day % 5 != 0
+            ],
+        )
+        .unwrap();
+        log_entity_sync(conn, &format!("note-{}", day % 10), "peer", sync_time);
+    }
+    // Synced once, long ago, with the peer and with a device since removed
+    log_entity_sync(conn, "archived-note", "peer", now - 310 * DAY);
+    log_entity_sync(conn, "archived-note", "peer", now - 300 * DAY);
+    log_entity_sync(conn, "archived-note", "old-phone", now - 300 * DAY);
+}
+
+fn count(conn: &Connection, sql: &str, param: i64) -> i64 {
+    conn.query_row(sql, [param], |row| row.get(0)).unwrap()
+}
+
+#[test]
+fn test_prune_keeps_stats_and_recent_history() {
+    let (conn, _dir) = setup_db();
+    let now = chrono::Utc::now().timestamp();
+    seed_year(&conn, now);
+    let agent = SyncAgent::new("local".into(), "Local".into(), 8765);
+
+    let stats = SyncHistory::get_stats(&conn, SPACE).unwrap();
+    let agent_stats = agent.get_sync_stats(&conn, SPACE).unwrap();
+    let last_success = SyncHistory::get_last_sync_time(&conn, SPACE).unwrap();
+    let recent = SyncHistory::get_for_space(&conn, SPACE, 180).unwrap();
+    assert!((stats.success_rate - 0.8).abs() < 0.01);
+
+    let policy = SyncLogRetentionPolicy::default();
+    let cutoff = now - policy.history_days as i64 * DAY;
+    let result = prune_sync_logs(&conn, &policy).unwrap();
+
+    assert_eq!(result.history_rows_collapsed, 185);
+    assert_eq!(
+        count(
+            &conn,
+            "SELECT COUNT(*) FROM sync_history WHERE sync_time >= ?1",
+            cutoff
+        ),
+        180
+    );
+    let aggregates = count(
+        &conn,
+        "SELECT COUNT(*) FROM sync_history WHERE sync_time < ?1",
+        cutoff,
+    );
+    assert_eq!(
+        count(
+            &conn,
+            "SELECT COUNT(*) FROM sync_history WHERE sync_time < ?1 AND success_count IS NULL",
+            cutoff
+        ),
+        0
+    );
+    // One row per month past the cutoff
+    assert!((6..=8).contains(&aggregates), "{} aggregates", aggregates);
+
+    // Stats are unchanged and the last 180 days are untouched
+    let pruned = SyncHistory::get_stats(&conn, SPACE).unwrap();
+    assert_eq!(pruned.success_rate, stats.success_rate);
+    assert_eq!(pruned.total_synced, stats.total_synced);
+    assert_eq!(pruned.conflicts_total, stats.conflicts_total);
+    assert_eq!(pruned.last_sync_at, stats.last_sync_at);
+    let pruned_agent = agent.get_sync_stats(&conn, SPACE).unwrap();
+    assert_eq!(pruned_agent.success_rate, agent_stats.success_rate);
+    assert_eq!(pruned_agent.total_synced, agent_stats.total_synced);
+    assert_eq!(
+        SyncHistory::get_last_sync_time(&conn, SPACE).unwrap(),
+        last_success
+    );
+    let recent_ids: Vec<String> = recent.iter().map(|e| e.id.clone()).collect();
+    let kept_ids: Vec<String> = SyncHistory::get_for_space(&conn, SPACE, 180)
+        .unwrap()
+        .iter()
+        .map(|e| e.id.clone())
+        .collect();
+    assert_eq!(kept_ids, recent_ids);
+
+    // Old entity log rows go, except the last sync of an entity with a
+    // device that is still registered
+    let entity_cutoff = now - policy.entity_log_days as i64 * DAY;
+    assert_eq!(
+        count(
+            &conn,
+            "SELECT COUNT(*) FROM entity_sync_log WHERE synced_at >= ?1",
+            entity_cutoff
+        ),
+        180
+    );
+    let archived: Vec<(String, i64)> = conn
+        .prepare(
+            "SELECT device_id, synced_at FROM entity_sync_log WHERE entity_id = 'archived-note'",
+        )
+        .unwrap()
+        .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))
+        .unwrap()
+        .collect::<Result<_, _>>()
+        .unwrap();
+    assert_eq!(archived, vec![("peer".to_string(), now - 300 * DAY)]);
+    assert_eq!(result.entity_log_rows_removed, 185 + 2);
+    assert_eq!(
+        result.rows_reclaimed,
+        result.entity_log_rows_removed + result.history_rows_collapsed - aggregates as usize
+    );
+
+    // Pruning again changes nothing; a shorter window merges into the
+    // existing monthly rows
+    let again = prune_sync_logs(&conn, &policy).unwrap();
+    assert_eq!(again.rows_reclaimed, 0);
+    let shorter = SyncLogRetentionPolicy {
+        entity_log_days: 180,
+        history_days: 90,
+    };
+    prune_sync_logs(&conn, &shorter).unwrap();
+    let per_month: i64 = conn
+        .query_row(
+            "SELECT MAX(n) FROM (
+                 SELECT COUNT(*) AS n FROM sync_history WHERE direction = ?1
+                 GROUP BY strftime('%Y-%m', sync_time, 'unixepoch'))",
+            [AGGREGATE_DIRECTION],
+            |row| row.get(0),
+        )
+        .unwrap();
+    assert_eq!(per_month, 1);
+    let merged = SyncHistory::get_stats(&conn, SPACE).unwrap();
+    assert_eq!(merged.success_rate, stats.success_rate);
+    assert_eq!(merged.total_synced, stats.total_synced);
+}
+
+#[test]
+fn test_startup_maintenance_prunes_sync_logs() {
+    let (mut conn, _dir) = setup_db();
+    let now = chrono::Utc::now().timestamp();
+    seed_year(&conn, now);
+
+    let result = core_rs::social::run_startup_maintenance(&mut conn, 7).unwrap();
+    assert!(result.sync_logs.entity_log_rows_removed > 0);
+    assert!(result.sync_logs.history_rows_collapsed > 0);
+    assert!(result.sync_logs.rows_reclaimed > 0);
+    assert_eq!(
+        count(
+            &conn,
+            "SELECT COUNT(*) FROM sync_history WHERE sync_time >= ?1",
+            now - 180 * DAY
+        ),
+        180
+    );
+}
@@ -0,0 +1,209 @@
+use core_rs::db::migrate;
+use core_rs::music::create_track;
+use core_rs::note::create_note;
+use core_rs::sync::scope::SCOPE_CHANGE_DIRECTION;
+use core_rs::sync::{
+    get_device_sync_scope, set_device_sync_scope, SyncEntityType, SyncRequest, SyncScope,
+};
+use core_rs::sync_agent::{SyncAgent, SyncDelta};
+use rusqlite::Connection;
+use ulid::Ulid;
+
+const DEK: [u8; 32] = [0u8; 32];
+
+fn setup_db(space_id: Ulid) -> Connection {
+    let mut conn = Connection::open_in_memory().unwrap();
+    migrate(&mut conn).unwrap();
+    core_rs::sync_agent::init_sync_tables(&conn).unwrap();
+    conn.execute(
+        "INSERT INTO space (id, name) VALUES (?1, ?2)",
+        (space_id.to_string(), "test space"),
+    )
+    .unwrap();
+    conn
+}
+
+/// A desktop and a phone that know each other
+fn paired(space_id: Ulid) -> (SyncAgent, Connection, SyncAgent, Connection) {
+    let desktop = SyncAgent::new("desktop".into(), "Desktop".into(), 0);
+    let phone = SyncAgent::new("phone".into(), "Phone".into(), 0);
+    let desktop_conn = setup_db(space_id);
+    let phone_conn = setup_db(space_id);
+    desktop
+        .register_device(&desktop_conn, &phone.get_device_info())
+        .unwrap();
+    phone
+        .register_device(&phone_conn, &desktop.get_device_info())
+        .unwrap();
+    (desktop, desktop_conn, phone, phone_conn)
+}
+
+fn without_music() -> SyncScope {
+    SyncScope::only(
+        SyncEntityType::ALL
+            .into_iter()
+            .filter(|t| !matches!(t, SyncEntityType::Track | SyncEntityType::Playlist)),
+    )
+}
+
+fn count(conn: &Connection, table: &str) -> i64 {
+    conn.query_row(&format!("SELECT COUNT(*) FROM {}", table), [], |row| {
+        row.get(0)
+    })
+    .unwrap()
+}
+
+fn has_type(deltas: &[SyncDelta], entity_type: SyncEntityType) -> bool {
+    deltas
+        .iter()
+        .any(|d| SyncEntityType::from_delta_type(&d.entity_type) == Some(entity_type))
+}
+
+#[test]
+fn test_scope_defaults_to_all_and_round_trips() {
+    let space_id = Ulid::new();
+    let (_desktop, conn, _phone, _) = paired(space_id);
+
+    assert_eq!(
+        get_device_sync_scope(&conn, "phone").unwrap(),
+        SyncScope::All
+    );
+    assert_eq!(
+        get_device_sync_scope(&conn, "unknown").unwrap(),
+        SyncScope::All
+    );
+
+    set_device_sync_scope(&conn, "phone", &without_music()).unwrap();
+    assert_eq!(
+        get_device_sync_scope(&conn, "phone").unwrap(),
+        without_music()
+    );
+    set_device_sync_scope(&conn, "phone", &SyncScope::All).unwrap();
+    assert_eq!(
+        get_device_sync_scope(&conn, "phone").unwrap(),
+        SyncScope::All
+    );
+
+    assert!(set_device_sync_scope(&conn, "unknown", &SyncScope::All).is_err());
+}
+
+#[test]
+fn test_phone_scope_excludes_tracks_in_both_directions() {
+    let space_id = Ulid::new();
+    let (desktop, mut desktop_conn, phone, mut phone_conn) = paired(space_id);
+    set_device_sync_scope(&desktop_conn, "phone", &without_music()).unwrap();
+
+    create_note(&desktop_conn, &space_id.to_string(), "From desktop", "hi").unwrap();
+    create_track(&desktop_conn, space_id, "Desktop song", None, None).unwrap();
+    create_note(&phone_conn, &space_id.to_string(), "From phone", "hey").unwrap();
+    create_track(&phone_conn, space_id, "Phone song", None, None).unwrap();
+
+    // The phone keeps no scope for the desktop; the desktop's limit wins
+    let request = phone
+        .create_sync_request(&phone_conn, space_id, 0, "desktop")
+        .unwrap();
+    assert_eq!(request.scope, Some(SyncScope::All));
+    let response = desktop
+        .handle_sync_request(&desktop_conn, &request, &phone.get_device_info())
+        .unwrap();
+    let negotiated = response.scope.clone().unwrap();
+    assert_eq!(negotiated, without_music());
+    assert!(has_type(&response.deltas, SyncEntityType::Note));
+    assert!(!has_type(&response.deltas, SyncEntityType::Track));
+
+    phone
+        .apply_deltas_in_scope(
+            &mut phone_conn,
+            response.deltas,
+            &DEK,
+            "desktop",
+            &negotiated,
+        )
+        .unwrap();
+    assert_eq!(count(&phone_conn, "note"), 2);
+    assert_eq!(count(&phone_conn, "track"), 1);
+
+    // The phone holds its own changes to the scope the desktop answered with
+    let outgoing = phone
+        .get_deltas_for_peer_in_scope(
+            &phone_conn,
+            space_id,
+            0,
+            &desktop.get_device_info(),
+            &negotiated,
+        )
+        .unwrap();
+    assert!(has_type(&outgoing, SyncEntityType::Note));
+    assert!(!has_type(&outgoing, SyncEntityType::Track));
+    desktop
+        .apply_deltas_from(&mut desktop_conn, outgoing, &DEK, "phone")
+        .unwrap();
+    assert_eq!(count(&desktop_conn, "note"), 2);
+    assert_eq!(count(&desktop_conn, "track"), 1);
+}
+
+#[test]
+fn test_out_of_scope_deltas_from_peer_are_rejected() {
+    let space_id = Ulid::new();
+    let (desktop, mut desktop_conn, phone, phone_conn) = paired(space_id);
+    set_device_sync_scope(&desktop_conn, "phone", &without_music()).unwrap();
+
+    create_note(&phone_conn, &space_id.to_string(), "Note", "").unwrap();
+    create_track(&phone_conn, space_id, "Song", None, None).unwrap();
+
+    // A phone that ignores the scope still can't write tracks on the desktop
+    let deltas = phone
+        .get_deltas_for_peer(&phone_conn, space_id, 0, &desktop.get_device_info())
+        .unwrap();
+    assert!(has_type(&deltas, SyncEntityType::Track));
+    desktop
+        .apply_deltas_from(&mut desktop_conn, deltas, &DEK, "phone")
+        .unwrap();
+    assert_eq!(count(&desktop_conn, "note"), 1);
+    assert_eq!(count(&desktop_conn, "track"), 0);
+}
+
+#[test]
+fn test_scope_change_is_noted_in_history_without_counting_as_sync() {
+    let space_id = Ulid::new();
+    let (desktop, conn, _phone, _) = paired(space_id);
+    set_device_sync_scope(&conn, "phone", &SyncScope::only([SyncEntityType::Note])).unwrap();
+
+    let history = desktop
+        .get_sync_history(&conn, &space_id.to_string(), 10)
+        .unwrap();
+    assert_eq!(history.len(), 1);
+    assert_eq!(history[0].direction, SCOPE_CHANGE_DIRECTION);
+    assert_eq!(history[0].device_id, "phone");
+    assert_eq!(
+        history[0].note.as_deref(),
+        Some("Sync scope changed to note")
+    );
+
+    let stats = desktop
+        .get_sync_stats(&conn, &space_id.to_string())
+        .unwrap();
+    assert_eq!(stats.last_sync_at, None);
+    assert_eq!(stats.success_rate, 1.0);
+}
+
+#[test]
+fn test_requests_from_older_peers_sync_the_stored_scope() {
+    let space_id = Ulid::new();
+    let (desktop, conn, phone, _) = paired(space_id);
+    set_device_sync_scope(&conn, "phone", &without_music()).unwrap();
+    create_track(&conn, space_id, "Song", None, None).unwrap();
+
+    let legacy = format!(
+        r#"{{"device_id":"phone","space_id":"{}","since_timestamp":0,"vector_clock":{{}}}}"#,
+        space_id
+    );
+    let request: SyncRequest = serde_json::from_str(&legacy).unwrap();
+    assert_eq!(request.scope, None);
+
+    let response = desktop
+        .handle_sync_request(&conn, &request, &phone.get_device_info())
+        .unwrap();
+    assert_eq!(response.scope, Some(without_music()));
+    assert!(!has_type(&response.deltas, SyncEntityType::Track));
+}
@@ -0,0 +1,294 @@
+use chrono::Utc;
+use core_rs::sync::mobile_sync::{DeltaOperation, DeviceInfo, DeviceType, SyncDelta};
+use core_rs::sync::p2p::{P2pError, P2pSync};
+use core_rs::sync::secure_channel::{
+    load_or_create_static_key, ChannelError, SecureChannel, StaticKeypair,
+};
+use core_rs::sync_agent::{init_sync_tables, SyncAgent};
+use futures::{SinkExt, StreamExt};
+use rusqlite::Connection;
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio_tungstenite::connect_async;
+use tokio_tungstenite::tungstenite::Message;
+
+const ALICE: &str = "device-alice-5c2e91";
+const BOB: &str = "device-bob-0d7a44";
+const ENTITY_ID: &str = "note-entity-7f3c9a1e";
+
+fn setup_db(peer: &str) -> Connection {
+    let conn = Connection::open_in_memory().unwrap();
+    init_sync_tables(&conn).unwrap();
+    let agent = SyncAgent::new(peer.to_string(), peer.to_string(), 8765);
+    agent
+        .register_device(&conn, &agent.get_device_info())
+        .unwrap();
+    conn
+}
+
+fn device_info(device_id: &str) -> DeviceInfo {
+    DeviceInfo {
+        device_id: device_id.to_string(),
+        device_name: device_id.to_string(),
+        device_type: DeviceType::Desktop,
+        ip_address: "127.0.0.1".parse().unwrap(),
+        sync_port: 0,
+        public_key: vec![],
+        os_version: "test".to_string(),
+        last_seen: Utc::now(),
+        is_active: true,
+    }
+}
+
+/// Two nodes that completed a confirmed pairing with each other
+async fn paired_nodes() -> (P2pSync, P2pSync) {
+    let alice_conn = setup_db(BOB);
+    let bob_conn = setup_db(ALICE);
+    let alice = P2pSync::with_static_key(
+        device_info(ALICE),
+        load_or_create_static_key(&alice_conn).unwrap(),
+    )
+    .unwrap();
+    let bob = P2pSync::with_static_key(
+        device_info(BOB),
+        load_or_create_static_key(&bob_conn).unwrap(),
+    )
+    .unwrap();
+
+    let hello = bob.initiate_pairing(ALICE).await.unwrap();
+    let reply = alice.exchange_keys(&hello).unwrap();
+    let reveal = bob.exchange_keys(&reply).unwrap();
+    alice.exchange_keys(&reveal).unwrap();
+    alice.confirm_pairing(&alice_conn, BOB, true).unwrap();
+    bob.confirm_pairing(&bob_conn, ALICE, true).unwrap();
+    (alice, bob)
+}
+
+async fn start_server(node: &P2pSync) -> SocketAddr {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let node = node.clone();
+    tokio::spawn(async move { node.serve(listener).await });
+    addr
+}
+
+/// Forward one connection to `upstream`, recording every byte on the wire
+async fn start_capturing_proxy(upstream: SocketAddr) -> (SocketAddr, Arc<Mutex<Vec<u8>>>) {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let captured = Arc::new(Mutex::new(Vec::new()));
+    let log = captured.clone();
+    tokio::spawn(async move {
+        let (client, _) = listener.accept().await.unwrap();
+        let server = TcpStream::connect(upstream).await.unwrap();
+        let (client_read, client_write) = client.into_split();
+        let (server_read, server_write) = server.into_split();
+        let up = tokio::spawn(pump(client_read, server_write, log.clone()));
+        let down = tokio::spawn(pump(server_read, client_write, log));
+        let _ = tokio::join!(up, down);
+    });
+    (addr, captured)
+}
+
+async fn pump(
+    mut from: tokio::net::tcp::OwnedReadHalf,
+    mut to: tokio::net::tcp::OwnedWriteHalf,
+    log: Arc<Mutex<Vec<u8>>>,
+) {
+    let mut buf = vec![0u8; 16 * 1024];
+    loop {
+        let n = match from.read(&mut buf).await {
+            Ok(0) | Err(_) => break,
+            Ok(n) => n,
+        };
+        log.lock().unwrap().extend_from_slice(&buf[..n]);
+        if to.write_all(&buf[..n]).await.is_err() {
+            break;
+        }
+    }
+    let _ = to.shutdown().await;
+}
+
+fn delta(entity_id: &str, payload: Vec<u8>) -> SyncDelta {
+    SyncDelta {
+        operation: DeltaOperation::Update,
+        entity_type: "note".to_string(),
+        entity_id: entity_id.to_string(),
+        encrypted_data: Some(payload),
+        timestamp: Utc::now(),
+        data_hash: None,
+        sequence: 1,
+        vector_clock: HashMap::from([(BOB.to_string(), 1)]),
+    }
+}
+
+fn contains(haystack: &[u8], needle: &[u8]) -> bool {
+    haystack
+        .windows(needle.len())
+        .any(|window| window == needle)
+}
+
+#[tokio::test]
+async fn test_sync_traffic_is_encrypted_on_the_wire() {
+    let (alice, bob) = paired_nodes().await;
+    let server = start_server(&alice).await;
+    let (proxy, captured) = start_capturing_proxy(server).await;
+
+    // The second delta spans several Noise messages
+    let deltas = vec![
+        delta(ENTITY_ID, b"plain note body".to_vec()),
+        delta("note-entity-large", vec![0x5a; 200 * 1024]),
+    ];
+    let responses = bob
+        .exchange_deltas(&proxy.to_string(), ALICE, &deltas)
+        .await
+        .unwrap();
+    assert_eq!(responses.len(), 2);
+    assert_eq!(responses[0].entity_id, ENTITY_ID);
+    assert_eq!(
+        responses[1].encrypted_data.as_ref().unwrap().len(),
+        200 * 1024
+    );
+
+    let wire = captured.lock().unwrap().clone();
+    assert!(wire.len() > 200 * 1024);
+    for plaintext in [
+        ENTITY_ID,
+        "note-entity-large",
+        ALICE,
+        BOB,
+        "entity_type",
+        "plain note body",
+    ] {
+        assert!(
+            !contains(&wire, plaintext.as_bytes()),
+            "{} crossed the wire in plaintext",
+            plaintext
+        );
+    }
+}
+
+#[tokio::test]
+async fn test_peer_with_wrong_static_key_is_rejected() {
+    let (alice, _bob) = paired_nodes().await;
+    let server = start_server(&alice).await;
+
+    // Claims to be Bob but cannot prove Bob's pinned key
+    let mallory = StaticKeypair::generate().unwrap();
+    let (ws, _) = connect_async(format!("ws://{}", server)).await.unwrap();
+    let result =
+        SecureChannel::connect(ws, &mallory, BOB, ALICE, Some(alice.static_public_key())).await;
+    match result {
+        Err(ChannelError::Rejected(reason)) => {
+            assert!(reason.contains("does not match"), "{}", reason)
+        }
+        other => panic!("expected a rejection, got {:?}", other.err()),
+    }
+
+    // The client refuses a server that is not the device it pinned
+    let (ws, _) = connect_async(format!("ws://{}", server)).await.unwrap();
+    let result = SecureChannel::connect(ws, &mallory, BOB, ALICE, Some(&[1u8; 32])).await;
+    assert!(matches!(result, Err(ChannelError::KeyMismatch(_))));
+}
+
+#[tokio::test]
+async fn test_untrusted_devices_cannot_open_a_channel() {
+    let (alice, bob) = paired_nodes().await;
+    let server = start_server(&alice).await;
+
+    assert!(matches!(
+        bob.exchange_deltas(&server.to_string(), "device-carol", &[])
+            .await,
+        Err(P2pError::UntrustedDevice(_))
+    ));
+
+    let carol = StaticKeypair::generate().unwrap();
+    let (ws, _) = connect_async(format!("ws://{}", server)).await.unwrap();
+    let result = SecureChannel::connect(
+        ws,
+        &carol,
+        "device-carol",
+        ALICE,
+        Some(alice.static_public_key()),
+    )
+    .await;
+    match result {
+        Err(ChannelError::Rejected(reason)) => {
+            assert!(reason.contains("not trusted"), "{}", reason)
+        }
+        other => panic!("expected a rejection, got {:?}", other.err()),
+    }
+}
+
+#[tokio::test]
+async fn test_legacy_plaintext_handshake_gets_a_clear_error() {
+    let (alice, _bob) = paired_nodes().await;
+    let server = start_server(&alice).await;
+
+    let (mut ws, _) = connect_async(format!("ws://{}", server)).await.unwrap();
+    let handshake = serde_json::json!({
+        "type": "handshake",
+        "deviceId": BOB,
+        "version": "1.0",
+    });
+    ws.send(Message::Text(handshake.to_string())).await.unwrap();
+
+    let reply = match ws.next().await {
+        Some(Ok(Message::Text(text))) => serde_json::from_str::<serde_json::Value>(&text).unwrap(),
+        other => panic!("expected a text rejection, got {:?}", other),
+    };
+    assert_eq!(reply["type"], "handshake_rejected");
+    let reason = reply["reason"].as_str().unwrap();
+    assert!(reason.contains("protocol 1"), "{}", reason);
+    assert!(reason.contains("requires protocol 2"), "{}", reason);
+}
+
+#[tokio::test]
+async fn test_legacy_peers_are_served_when_allowed() {
+    let (alice, _bob) = paired_nodes().await;
+    alice.set_allow_legacy_peers(true);
+    let server = start_server(&alice).await;
+
+    let handshake = |device_id: &str| {
+        serde_json::json!({
+            "type": "handshake",
+            "deviceId": device_id,
+            "version": "1.0",
+        })
+        .to_string()
+    };
+    let reply = |message: Option<Result<Message, _>>| match message {
+        Some(Ok(Message::Text(text))) => serde_json::from_str::<serde_json::Value>(&text).unwrap(),
+        other => panic!("expected a text reply, got {:?}", other),
+    };
+
+    let (mut ws, _) = connect_async(format!("ws://{}", server)).await.unwrap();
+    ws.send(Message::Text(handshake(BOB))).await.unwrap();
+    let response = reply(ws.next().await);
+    assert_eq!(response["type"], "handshake_response");
+    assert!(response["publicKey"].is_string());
+
+    // Only trusted devices get in over the old protocol too
+    let (mut ws, _) = connect_async(format!("ws://{}", server)).await.unwrap();
+    ws.send(Message::Text(handshake("device-mallory-9b1f")))
+        .await
+        .unwrap();
+    let response = reply(ws.next().await);
+    assert_eq!(response["type"], "handshake_rejected");
+    assert!(response["reason"]
+        .as_str()
+        .unwrap()
+        .contains("device-mallory-9b1f"));
+}
+
+#[test]
+fn test_static_key_is_created_once() {
+    let conn = setup_db(BOB);
+    let first = load_or_create_static_key(&conn).unwrap();
+    let second = load_or_create_static_key(&conn).unwrap();
+    assert_eq!(first.public_key(), second.public_key());
+    assert_eq!(first.public_key().len(), 32);
+}
@@ -0,0 +1,169 @@
+use core_rs::calendar::TimeRange;
+use core_rs::db::migrate;
+use core_rs::sync::history::SyncHistory;
+use core_rs::sync::{
+    get_sync_transfer_report, SyncDelta, SyncOperation, SyncTransferSession, SyncTransport,
+    TransferCounts, TransferDirection,
+};
+use rusqlite::Connection;
+use std::collections::HashMap;
+
+const SPACE: &str = "01HSPACE00000000000000000";
+const OTHER_SPACE: &str = "01HSPACE00000000000000001";
+
+fn setup_db() -> Connection {
+    let mut conn = Connection::open_in_memory().unwrap();
+    migrate(&mut conn).unwrap();
+    core_rs::sync_agent::init_sync_tables(&conn).unwrap();
+    conn
+}
+
+/// A delta whose serialized size depends only on `payload_len`: every byte
+/// of data is the digit 7, and ids and timestamps have fixed widths
+fn delta(entity_type: &str, index: usize, payload_len: usize) -> SyncDelta {
+    SyncDelta {
+        entity_type: entity_type.to_string(),
+        entity_id: format!("{:0>26}", index),
+        operation: SyncOperation::Update,
+        data: Some(vec![7u8; payload_len]),
+        timestamp: 1_700_000_000,
+        vector_clock: HashMap::new(),
+        space_id: Some(SPACE.to_string()),
+    }
+}
+
+fn deltas(entity_type: &str, count: usize, payload_len: usize) -> Vec<SyncDelta> {
+    (0..count)
+        .map(|i| delta(entity_type, i, payload_len))
+        .collect()
+}
+
+fn size(delta: &SyncDelta) -> i64 {
+    serde_json::to_vec(delta).unwrap().len() as i64
+}
+
+fn counts(deltas: i64, bytes: i64) -> TransferCounts {
+    TransferCounts {
+        deltas,
+        payload_bytes: bytes,
+        wire_bytes: bytes,
+    }
+}
+
+fn wide_range() -> TimeRange {
+    let now = chrono::Utc::now().timestamp();
+    TimeRange::new(now - 3_600, now + 3_600)
+}
+
+#[test]
+fn test_bytes_are_attributed_per_entity_type_and_device() {
+    let conn = setup_db();
+    let notes = deltas("note", 3, 2_000);
+    let tasks = deltas("task", 5, 50);
+    let playlists = deltas("playlist", 2, 1_500);
+    let note_size = size(&notes[0]);
+    let task_size = size(&tasks[0]);
+    let playlist_size = size(&playlists[0]);
+    // Every byte of data costs two characters ("7,"), so sizes only differ by
+    // the payload
+    assert_eq!(note_size - task_size, 2 * (2_000 - 50));
+
+    // Laptop: we push notes and tasks, and receive playlists
+    let mut laptop = SyncTransferSession::new("session-1", "laptop", SyncTransport::Direct);
+    laptop.record_sent(SPACE, &notes).unwrap();
+    laptop.record_sent(SPACE, &tasks).unwrap();
+    laptop.record_received(SPACE, &playlists).unwrap();
+    assert_eq!(
+        laptop.totals(TransferDirection::Sent),
+        counts(8, 3 * note_size + 5 * task_size)
+    );
+    laptop.save(&conn).unwrap();
+
+    // Phone: tasks come in through the relay; another space's traffic stays
+    // out of the report
+    let mut phone = SyncTransferSession::new("session-2", "phone", SyncTransport::Relay);
+    phone.record_received(SPACE, &tasks[..2]).unwrap();
+    phone.record_sent(OTHER_SPACE, &notes).unwrap();
+    phone.save(&conn).unwrap();
+
+    let report = get_sync_transfer_report(&conn, SPACE, wide_range()).unwrap();
+    assert_eq!(report.sessions, 2);
+    assert_eq!(report.sent, counts(8, 3 * note_size + 5 * task_size));
+    assert_eq!(
+        report.received,
+        counts(4, 2 * playlist_size + 2 * task_size)
+    );
+    assert_eq!(report.compression_savings_bytes, 0);
+
+    let by_type: Vec<_> = report
+        .by_entity_type
+        .iter()
+        .map(|entry| (entry.key.as_str(), entry.sent, entry.received))
+        .collect();
+    assert_eq!(
+        by_type,
+        vec![
+            ("note", counts(3, 3 * note_size), counts(0, 0)),
+            ("playlist", counts(0, 0), counts(2, 2 * playlist_size)),
+            ("task", counts(5, 5 * task_size), counts(2, 2 * task_size)),
+        ],
+        "heaviest first"
+    );
+
+    assert_eq!(report.by_device.len(), 2);
+    assert_eq!(report.by_device[0].key, "laptop");
+    assert_eq!(
+        report.by_device[0].wire_bytes(),
+        3 * note_size + 5 * task_size + 2 * playlist_size
+    );
+    assert_eq!(report.by_device[1].key, "phone");
+    assert_eq!(report.by_device[1].received, counts(2, 2 * task_size));
+
+    // The per-type and per-device views add up to the same totals
+    let type_total: i64 = report.by_entity_type.iter().map(|e| e.wire_bytes()).sum();
+    let device_total: i64 = report.by_device.iter().map(|e| e.wire_bytes()).sum();
+    assert_eq!(type_total, device_total);
+    assert_eq!(
+        type_total,
+        report.sent.wire_bytes + report.received.wire_bytes
+    );
+
+    let stats = SyncHistory::get_stats(&conn, SPACE).unwrap();
+    assert_eq!(stats.total_bytes_sent, report.sent.wire_bytes);
+    assert_eq!(stats.total_bytes_received, report.received.wire_bytes);
+}
+
+#[test]
+fn test_compression_savings_and_time_range() {
+    let conn = setup_db();
+    let mut session = SyncTransferSession::new("session-1", "laptop", SyncTransport::Direct);
+    session.record(SPACE, "social_post", TransferDirection::Sent, 10_000, 2_500);
+    session.record(SPACE, "social_post", TransferDirection::Sent, 6_000, 1_500);
+    session.record(SPACE, "note", TransferDirection::Received, 1_000, 800);
+    session.save(&conn).unwrap();
+
+    let report = get_sync_transfer_report(&conn, SPACE, wide_range()).unwrap();
+    assert_eq!(
+        report.sent,
+        TransferCounts {
+            deltas: 2,
+            payload_bytes: 16_000,
+            wire_bytes: 4_000,
+        }
+    );
+    assert_eq!(report.compression_savings_bytes, 12_000 + 200);
+    assert_eq!(report.by_entity_type[0].key, "social_post");
+
+    // Only sessions recorded within the range count
+    conn.execute(
+        "UPDATE sync_transfer_stats SET recorded_at = recorded_at - 7200",
+        [],
+    )
+    .unwrap();
+    let report = get_sync_transfer_report(&conn, SPACE, wide_range()).unwrap();
+    assert_eq!(report.sessions, 0);
+    assert!(report.by_entity_type.is_empty());
+    assert_eq!(report.sent, TransferCounts::default());
+
+    assert!(get_sync_transfer_report(&conn, SPACE, TimeRange::new(10, 10)).is_err());
+}
@@ -1,6 +1,7 @@
 use core_rs::db;
+use core_rs::note;
 use core_rs::space;
-use core_rs::tag;
+use core_rs::tag::{self, TagError};
 use rusqlite::Connection;
 use tempfile::tempdir;
 
@@ -36,3 +37,174 @@ fn test_tag_lifecycle() {
     assert!(names.contains(&"Urgent".to_string()));
     assert!(names.contains(&"Work".to_string()));
 }
+
+fn tag_note(conn: &rusqlite::Connection, note_id: &str, tag_id: &str) {
+    conn.execute(
+        "INSERT INTO note_tags (note_id, tag_id) VALUES (?1, ?2)",
+        [note_id, tag_id],
+    )
+    .unwrap();
+}
+
+#[test]
+fn test_merge_tags_keeps_unique_pairs() {
+    let (mut conn, _dir) = setup_db();
+    let space_id = space::create_space(&mut conn, "Merge Space").unwrap();
+    let space_str = space_id.to_string();
+
+    let ml = tag::create_tag(&conn, &space_str, "ml", None).unwrap();
+    let ai = tag::create_tag(&conn, &space_str, "machine-learning", None).unwrap();
+    let target = tag::create_tag(&conn, &space_str, "ai/ml", None).unwrap();
+    let both = note::create_note(&conn, &space_str, "Both", "").unwrap();
+    let one = note::create_note(&conn, &space_str, "One", "").unwrap();
+    let both_id = both.id.0.to_string();
+    let one_id = one.id.0.to_string();
+    tag_note(&conn, &both_id, &ml.id.to_string());
+    tag_note(&conn, &both_id, &ai.id.to_string());
+    tag_note(&conn, &both_id, &target.id.to_string());
+    tag_note(&conn, &one_id, &ml.id.to_string());
+
+    let sources = vec![ml.id.to_string(), ai.id.to_string()];
+    let merged = tag::merge_tags(&mut conn, &sources, &target.id.to_string()).unwrap();
+    assert_eq!(merged.name, "ai/ml");
+
+    let pairs: Vec<(String, String)> = conn
+        .prepare("SELECT note_id, tag_id FROM note_tags ORDER BY note_id")
+        .unwrap()
+        .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))
+        .unwrap()
+        .collect::<Result<_, _>>()
+        .unwrap();
+    let target_id = target.id.to_string();
+    let mut expected = vec![(both_id, target_id.clone()), (one_id, target_id)];
+    expected.sort();
+    assert_eq!(pairs, expected);
+
+    let names: Vec<String> = tag::get_all_tags_in_space(&conn, space_id)
+        .unwrap()
+        .into_iter()
+        .map(|t| t.name)
+        .collect();
+    assert_eq!(names, vec!["ai/ml".to_string()]);
+
+    assert!(matches!(
+        tag::merge_tags(&mut conn, &[target.id.to_string()], &target.id.to_string()),
+        Err(TagError::InvalidMerge(_))
+    ));
+}
+
+#[test]
+fn test_tag_hierarchy_queries() {
+    let (mut conn, _dir) = setup_db();
+    let space_id = space::create_space(&mut conn, "Tree Space").unwrap();
+    let space_str = space_id.to_string();
+
+    let meetings = tag::create_tag(&conn, &space_str, "work/meetings", None).unwrap();
+    let standup = tag::create_tag(&conn, &space_str, "work/meetings/standup", None).unwrap();
+    let workshop = tag::create_tag(&conn, &space_str, "workshop", None).unwrap();
+    let a = note::create_note(&conn, &space_str, "A", "").unwrap();
+    let b = note::create_note(&conn, &space_str, "B", "").unwrap();
+    let c = note::create_note(&conn, &space_str, "C", "").unwrap();
+    tag_note(&conn, &a.id.0.to_string(), &meetings.id.to_string());
+    tag_note(&conn, &a.id.0.to_string(), &standup.id.to_string());
+    tag_note(&conn, &b.id.0.to_string(), &standup.id.to_string());
+    tag_note(&conn, &c.id.0.to_string(), &workshop.id.to_string());
+
+    // "work" only exists as a parent level
+    let tree = tag::get_tag_tree(&conn, space_id).unwrap();
+    let paths: Vec<&str> = tree.iter().map(|n| n.path.as_str()).collect();
+    assert_eq!(paths, vec!["work", "workshop"]);
+    let work = &tree[0];
+    assert!(work.tag.is_none());
+    assert_eq!(work.note_count, 0);
+    assert_eq!(work.total_note_count, 2);
+    let meetings_node = &work.children[0];
+    assert_eq!(meetings_node.segment, "meetings");
+    assert_eq!(meetings_node.note_count, 1);
+    assert_eq!(meetings_node.total_note_count, 2);
+    assert_eq!(meetings_node.children[0].path, "work/meetings/standup");
+
+    let with_descendants = tag::get_notes_with_tag(&conn, space_id, "work", true).unwrap();
+    assert_eq!(with_descendants.len(), 2);
+    assert!(tag::get_notes_with_tag(&conn, space_id, "work", false)
+        .unwrap()
+        .is_empty());
+    let exact = tag::get_notes_with_tag(&conn, space_id, "work/meetings", false).unwrap();
+    assert_eq!(exact.len(), 1);
+
+    let usage = tag::get_tag_usage_counts(&conn, space_id).unwrap();
+    let standup_usage = usage
+        .iter()
+        .find(|u| u.tag.name == "work/meetings/standup")
+        .unwrap();
+    assert_eq!(
+        (standup_usage.note_count, standup_usage.total_note_count),
+        (2, 2)
+    );
+
+    // Search's tag filter follows the hierarchy without matching "workshop"
+    let results = core_rs::search::search_notes(&conn, "tag:work", &space_str).unwrap();
+    assert_eq!(results.len(), 2);
+}
+
+#[test]
+fn test_rename_tag() {
+    let (mut conn, _dir) = setup_db();
+    let space_id = space::create_space(&mut conn, "Rename Space").unwrap();
+    let space_str = space_id.to_string();
+
+    let work = tag::create_tag(&conn, &space_str, "work", None).unwrap();
+    tag::create_tag(&conn, &space_str, "work/meetings", None).unwrap();
+    tag::create_tag(&conn, &space_str, "job", None).unwrap();
+    let note = note::create_note(
+        &conn,
+        &space_str,
+        "Inline",
+        "#work and #work/meetings but not #workshop or a#work",
+    )
+    .unwrap();
+
+    // Collides with the existing "job" tag
+    assert!(matches!(
+        tag::rename_tag(&mut conn, &work.id.to_string(), "job"),
+        Err(TagError::NameTaken(name)) if name == "job"
+    ));
+    assert!(matches!(
+        tag::rename_tag(&mut conn, &work.id.to_string(), "a//b"),
+        Err(TagError::InvalidName(_))
+    ));
+
+    let report = tag::rename_tag(&mut conn, &work.id.to_string(), "#career").unwrap();
+    assert_eq!(report.tag.name, "career");
+    assert_eq!(report.renamed_tags, 2);
+    assert_eq!(report.updated_note_ids, vec![note.id.0.to_string()]);
+    assert!(report.notes_needing_edit.is_empty());
+
+    let mut names: Vec<String> = tag::get_all_tags_in_space(&conn, space_id)
+        .unwrap()
+        .into_iter()
+        .map(|t| t.name)
+        .collect();
+    names.sort();
+    assert_eq!(names, vec!["career", "career/meetings", "job"]);
+
+    let body = note::get_note(&conn, note.id).unwrap().unwrap().content_md;
+    assert_eq!(
+        body,
+        "#career and #career/meetings but not #workshop or a#work"
+    );
+
+    // Moving a tag below itself renames its existing children first
+    let report = tag::rename_tag(&mut conn, &work.id.to_string(), "career/meetings").unwrap();
+    assert_eq!(report.renamed_tags, 2);
+    let mut names: Vec<String> = tag::get_all_tags_in_space(&conn, space_id)
+        .unwrap()
+        .into_iter()
+        .map(|t| t.name)
+        .collect();
+    names.sort();
+    assert_eq!(
+        names,
+        vec!["career/meetings", "career/meetings/meetings", "job"]
+    );
+}
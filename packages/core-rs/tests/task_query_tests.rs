@@ -0,0 +1,429 @@
+use core_rs::db::migrate;
+use core_rs::project::create_project;
+use core_rs::search::SortDirection;
+use core_rs::space::create_space;
+use core_rs::tag::create_tag;
+use core_rs::task::*;
+use rusqlite::Connection;
+use ulid::Ulid;
+
+const DAY: i64 = 86_400;
+
+/// Title, status, priority, due in days, project and tags
+type SeedTask<'a> = (
+    &'a str,
+    &'a str,
+    Option<i64>,
+    Option<i64>,
+    Option<&'a str>,
+    &'a [Ulid],
+);
+
+struct Seeded {
+    conn: Connection,
+    space_id: Ulid,
+    launch: Ulid,
+    migration: Ulid,
+    urgent: Ulid,
+    errand: Ulid,
+}
+
+/// Tasks across two projects, one of which waits on the other, with a mix
+/// of statuses, priorities, due dates and tags, plus one task in another space
+fn setup() -> Seeded {
+    let mut conn = Connection::open_in_memory().unwrap();
+    migrate(&mut conn).unwrap();
+    let space_id = create_space(&mut conn, "Work").unwrap();
+    let other_space = create_space(&mut conn, "Home").unwrap();
+    let space = space_id.to_string();
+
+    let launch = create_project(&conn, &space, "Launch").unwrap().id;
+    let migration = create_project(&conn, &space, "Migration").unwrap().id;
+    conn.execute(
+        "UPDATE project SET status = 'active' WHERE id IN (?1, ?2)",
+        [&launch, &migration],
+    )
+    .unwrap();
+    // Launch can't start until the migration is done
+    conn.execute(
+        "INSERT INTO project_dependency (project_id, depends_on_project_id) VALUES (?1, ?2)",
+        [&launch, &migration],
+    )
+    .unwrap();
+    let urgent = create_tag(&conn, &space, "urgent", None).unwrap().id;
+    let errand = create_tag(&conn, &space, "errand", None).unwrap().id;
+
+    let rows: [SeedTask; 8] = [
+        (
+            "Write press release",
+            "next",
+            Some(1),
+            Some(2),
+            Some(launch.as_str()),
+            &[urgent],
+        ),
+        (
+            "Book launch venue",
+            "inbox",
+            Some(2),
+            Some(5),
+            Some(launch.as_str()),
+            &[urgent, errand],
+        ),
+        (
+            "Plan launch party",
+            "next",
+            Some(3),
+            None,
+            Some(launch.as_str()),
+            &[],
+        ),
+        (
+            "Export old database",
+            "in_progress",
+            Some(1),
+            Some(1),
+            Some(migration.as_str()),
+            &[urgent],
+        ),
+        (
+            "Await vendor quote",
+            "waiting",
+            Some(2),
+            Some(3),
+            Some(migration.as_str()),
+            &[],
+        ),
+        (
+            "Archive legacy data",
+            "done",
+            Some(1),
+            Some(-1),
+            Some(migration.as_str()),
+            &[urgent],
+        ),
+        (
+            "Buy 100% cotton shirts",
+            "inbox",
+            None,
+            Some(10),
+            None,
+            &[errand],
+        ),
+        ("Read launch_notes draft", "next", Some(4), None, None, &[]),
+    ];
+    for (title, status, priority, due_days, project_id, tags) in rows {
+        let mut task = create_task(&conn, space_id, title, None).unwrap();
+        task.status = status.to_string();
+        task.priority = priority;
+        task.due_at = due_days.map(|d| d * DAY);
+        task.project_id = project_id.map(|p| Ulid::from_string(p).unwrap());
+        update_task(&conn, &task).unwrap();
+        for tag_id in tags {
+            conn.execute(
+                "INSERT INTO task_tags (task_id, tag_id) VALUES (?1, ?2)",
+                [task.id.to_string(), tag_id.to_string()],
+            )
+            .unwrap();
+        }
+    }
+    let mut stray = create_task(&conn, other_space, "Write press release", None).unwrap();
+    stray.status = "next".to_string();
+    stray.priority = Some(1);
+    update_task(&conn, &stray).unwrap();
+
+    Seeded {
+        conn,
+        space_id,
+        launch: Ulid::from_string(&launch).unwrap(),
+        migration: Ulid::from_string(&migration).unwrap(),
+        urgent,
+        errand,
+    }
+}
+
+fn titles(tasks: &[Task]) -> Vec<&str> {
+    tasks.iter().map(|t| t.title.as_str()).collect()
+}
+
+fn matching(seeded: &Seeded, filter: &TaskFilter) -> Vec<String> {
+    let tasks = query_tasks(
+        &seeded.conn,
+        seeded.space_id,
+        filter,
+        &TaskSort::default(),
+        None,
+        None,
+    )
+    .unwrap();
+    assert_eq!(
+        count_tasks(&seeded.conn, seeded.space_id, filter).unwrap(),
+        tasks.len() as u64
+    );
+    let mut titles: Vec<String> = tasks.into_iter().map(|t| t.title).collect();
+    titles.sort();
+    titles
+}
+
+#[test]
+fn test_empty_filter_returns_every_task_in_the_space() {
+    let seeded = setup();
+    let mut all = get_all_tasks_in_space(&seeded.conn, seeded.space_id).unwrap();
+    let mut filtered = query_tasks(
+        &seeded.conn,
+        seeded.space_id,
+        &TaskFilter::default(),
+        &TaskSort::default(),
+        None,
+        None,
+    )
+    .unwrap();
+    all.sort_by_key(|t| t.id);
+    filtered.sort_by_key(|t| t.id);
+    assert_eq!(filtered.len(), 8);
+    assert_eq!(
+        filtered.iter().map(|t| t.id).collect::<Vec<_>>(),
+        all.iter().map(|t| t.id).collect::<Vec<_>>()
+    );
+    assert_eq!(
+        count_tasks(&seeded.conn, seeded.space_id, &TaskFilter::default()).unwrap(),
+        8
+    );
+}
+
+#[test]
+fn test_filter_dimensions_combine() {
+    let seeded = setup();
+
+    // Open, high-priority, urgent tasks due within the first week
+    let filter = TaskFilter {
+        statuses: vec!["inbox".into(), "next".into(), "in_progress".into()],
+        max_priority: Some(2),
+        due_after: Some(0),
+        due_before: Some(7 * DAY),
+        tag_ids: vec![seeded.urgent],
+        ..Default::default()
+    };
+    assert_eq!(
+        matching(&seeded, &filter),
+        [
+            "Book launch venue",
+            "Export old database",
+            "Write press release"
+        ]
+    );
+
+    // ...that aren't blocked, in either project
+    let filter = TaskFilter {
+        blocked: Some(false),
+        project_ids: vec![seeded.launch, seeded.migration],
+        ..filter
+    };
+    assert_eq!(matching(&seeded, &filter), ["Export old database"]);
+
+    // Tags match any of the given ones, each task once
+    let filter = TaskFilter {
+        tag_ids: vec![seeded.urgent, seeded.errand],
+        has_due_date: Some(true),
+        min_priority: Some(2),
+        ..Default::default()
+    };
+    assert_eq!(matching(&seeded, &filter), ["Book launch venue"]);
+}
+
+#[test]
+fn test_blocked_tasks() {
+    let seeded = setup();
+    let blocked = TaskFilter {
+        blocked: Some(true),
+        ..Default::default()
+    };
+    // Launch tasks wait on the unfinished migration; the quote is waiting
+    assert_eq!(
+        matching(&seeded, &blocked),
+        [
+            "Await vendor quote",
+            "Book launch venue",
+            "Plan launch party",
+            "Write press release"
+        ]
+    );
+
+    seeded
+        .conn
+        .execute(
+            "UPDATE project SET status = 'done' WHERE id = ?1",
+            [seeded.migration.to_string()],
+        )
+        .unwrap();
+    assert_eq!(matching(&seeded, &blocked), ["Await vendor quote"]);
+
+    seeded
+        .conn
+        .execute(
+            "UPDATE project SET status = 'blocked' WHERE id = ?1",
+            [seeded.launch.to_string()],
+        )
+        .unwrap();
+    assert_eq!(
+        count_tasks(&seeded.conn, seeded.space_id, &blocked).unwrap(),
+        4
+    );
+    let unblocked = TaskFilter {
+        blocked: Some(false),
+        ..Default::default()
+    };
+    assert_eq!(
+        count_tasks(&seeded.conn, seeded.space_id, &unblocked).unwrap(),
+        4
+    );
+}
+
+#[test]
+fn test_text_and_due_date_presence() {
+    let seeded = setup();
+    let text = |t: &str| TaskFilter {
+        text: Some(t.to_string()),
+        ..Default::default()
+    };
+    assert_eq!(
+        matching(&seeded, &text("LAUNCH")),
+        [
+            "Book launch venue",
+            "Plan launch party",
+            "Read launch_notes draft"
+        ]
+    );
+    // LIKE wildcards are matched literally
+    assert_eq!(matching(&seeded, &text("100%")), ["Buy 100% cotton shirts"]);
+    assert_eq!(matching(&seeded, &text("h_n")), Vec::<String>::new());
+    assert_eq!(
+        matching(&seeded, &text("launch_")),
+        ["Read launch_notes draft"]
+    );
+
+    let undated = TaskFilter {
+        has_due_date: Some(false),
+        ..Default::default()
+    };
+    assert_eq!(
+        matching(&seeded, &undated),
+        ["Plan launch party", "Read launch_notes draft"]
+    );
+}
+
+#[test]
+fn test_sorting_and_paging() {
+    let seeded = setup();
+    let open = TaskFilter {
+        statuses: vec!["inbox".into(), "next".into()],
+        ..Default::default()
+    };
+    let page = |sort: &TaskSort, limit, offset| {
+        let tasks = query_tasks(&seeded.conn, seeded.space_id, &open, sort, limit, offset).unwrap();
+        titles(&tasks)
+            .into_iter()
+            .map(String::from)
+            .collect::<Vec<_>>()
+    };
+
+    let by_priority = TaskSort {
+        field: TaskSortField::Priority,
+        direction: SortDirection::Asc,
+    };
+    let all = page(&by_priority, None, None);
+    assert_eq!(
+        all,
+        [
+            "Write press release",
+            "Book launch venue",
+            "Plan launch party",
+            "Read launch_notes draft",
+            "Buy 100% cotton shirts"
+        ]
+    );
+    assert_eq!(page(&by_priority, Some(2), None), all[..2]);
+    assert_eq!(page(&by_priority, Some(2), Some(2)), all[2..4]);
+    assert_eq!(page(&by_priority, None, Some(4)), all[4..]);
+
+    // Tasks without a due date stay last when descending too
+    let latest_due = TaskSort {
+        field: TaskSortField::DueAt,
+        direction: SortDirection::Desc,
+    };
+    let tasks = page(&latest_due, None, None);
+    assert_eq!(tasks[0], "Buy 100% cotton shirts");
+    assert_eq!(tasks[2], "Write press release");
+    let mut undated = tasks[3..].to_vec();
+    undated.sort();
+    assert_eq!(undated, ["Plan launch party", "Read launch_notes draft"]);
+
+    let by_title = TaskSort {
+        field: TaskSortField::Title,
+        direction: SortDirection::Asc,
+    };
+    assert_eq!(page(&by_title, Some(1), None), ["Book launch venue"]);
+}
+
+#[test]
+fn test_saved_task_views() {
+    let seeded = setup();
+    let filter = TaskFilter {
+        tag_ids: vec![seeded.urgent],
+        blocked: Some(false),
+        ..Default::default()
+    };
+    let sort = TaskSort {
+        field: TaskSortField::DueAt,
+        direction: SortDirection::Desc,
+    };
+    let view = create_task_view(&seeded.conn, seeded.space_id, "Focus", &filter, &sort).unwrap();
+    create_task_view(
+        &seeded.conn,
+        seeded.space_id,
+        "Everything",
+        &TaskFilter::default(),
+        &TaskSort::default(),
+    )
+    .unwrap();
+    // Names are unique within a space
+    assert!(create_task_view(&seeded.conn, seeded.space_id, "Focus", &filter, &sort).is_err());
+
+    let id = Ulid::from_string(&view.id).unwrap();
+    let loaded = get_task_view(&seeded.conn, id).unwrap().unwrap();
+    assert_eq!(loaded, view);
+    let tasks = query_tasks(
+        &seeded.conn,
+        seeded.space_id,
+        &loaded.filter,
+        &loaded.sort,
+        None,
+        None,
+    )
+    .unwrap();
+    assert_eq!(
+        titles(&tasks),
+        ["Export old database", "Archive legacy data"]
+    );
+
+    let views = get_task_views(&seeded.conn, seeded.space_id).unwrap();
+    let names: Vec<&str> = views.iter().map(|v| v.name.as_str()).collect();
+    assert_eq!(names, ["Everything", "Focus"]);
+
+    let errands = TaskFilter {
+        tag_ids: vec![seeded.errand],
+        ..Default::default()
+    };
+    let updated =
+        update_task_view(&seeded.conn, id, "Errands", &errands, &TaskSort::default()).unwrap();
+    assert_eq!(updated.name, "Errands");
+    assert_eq!(updated.filter, errands);
+    assert_eq!(updated.created_at, view.created_at);
+
+    delete_task_view(&seeded.conn, id).unwrap();
+    assert!(get_task_view(&seeded.conn, id).unwrap().is_none());
+    assert_eq!(
+        get_task_views(&seeded.conn, seeded.space_id).unwrap().len(),
+        1
+    );
+}
@@ -73,3 +73,114 @@ fn test_major_note_detection() {
     assert_eq!(milestones.len(), 1);
     assert_eq!(milestones[0].milestone_type, "major_note");
 }
+
+const MONTH: i64 = 30 * 86400;
+const NOTES_PER_MONTH: i64 = 100;
+
+/// Seed a year of history: each month adds notes that each link to the previous one
+fn seed_year(conn: &mut Connection, space_id: &str, base: i64) {
+    let tx = conn.transaction().unwrap();
+    let mut ids: Vec<String> = Vec::new();
+    for month in 0..12 {
+        for i in 0..NOTES_PER_MONTH {
+            let id = ulid::Ulid::new().to_string();
+            let created_at = base + month * MONTH + i * 60;
+            tx.execute(
+                "INSERT INTO note (id, space_id, title, content_md, created_at, modified_at)
+                 VALUES (?1, ?2, ?3, '', ?4, ?4)",
+                rusqlite::params![id, space_id, format!("Note {}-{}", month, i), created_at],
+            )
+            .unwrap();
+            if let Some(previous) = ids.last() {
+                tx.execute(
+                    "INSERT INTO link (source_note_id, target_note_id) VALUES (?1, ?2)",
+                    [&id, previous],
+                )
+                .unwrap();
+            }
+            ids.push(id);
+        }
+    }
+    tx.commit().unwrap();
+}
+
+#[test]
+fn test_graph_evolution_over_a_year_uses_change_log() {
+    let (mut conn, _dir) = setup_db();
+    let space_id = space::create_space(&mut conn, "Year Space").unwrap();
+    let space_str = space_id.to_string();
+    let base = chrono::Utc::now().timestamp() - 2 * 365 * 86400;
+    seed_year(&mut conn, &space_str, base);
+
+    // One sample at the end of each month
+    let start = base + MONTH - 1;
+    let end = base + 12 * MONTH - 1;
+
+    let evolution = temporal_graph::get_graph_evolution(&conn, space_id, start, end, 12).unwrap();
+
+    assert_eq!(evolution.summaries_computed, 12);
+    assert_eq!(evolution.snapshots.len(), 12);
+    for (month, snapshot) in evolution.snapshots.iter().enumerate() {
+        let notes = NOTES_PER_MONTH as u32 * (month as u32 + 1);
+        assert_eq!(snapshot.timestamp, base + (month as i64 + 1) * MONTH - 1);
+        assert_eq!(snapshot.metrics.total_nodes, notes);
+        assert_eq!(snapshot.metrics.total_edges, notes - 1);
+        assert_eq!(snapshot.metrics.communities, 1);
+        assert_eq!(snapshot.top_nodes[0].degree, 2);
+    }
+
+    // The latest summary agrees with a full rebuild
+    let current = temporal_graph::build_current_graph(&conn, space_id).unwrap();
+    let last = &evolution.snapshots[11].metrics;
+    assert_eq!(last.total_nodes, current.metrics.total_nodes);
+    assert_eq!(last.total_edges, current.metrics.total_edges);
+
+    // A second request is served entirely from the summary cache
+    let cached = temporal_graph::get_graph_evolution(&conn, space_id, start, end, 12).unwrap();
+    assert_eq!(cached.summaries_computed, 0);
+    assert_eq!(
+        cached.snapshots[5].metrics.total_nodes,
+        evolution.snapshots[5].metrics.total_nodes
+    );
+
+    // A backdated note only invalidates the summaries from its month onwards
+    conn.execute(
+        "INSERT INTO note (id, space_id, title, content_md, created_at, modified_at)
+         VALUES (?1, ?2, 'Late entry', '', ?3, ?3)",
+        rusqlite::params![
+            ulid::Ulid::new().to_string(),
+            space_str,
+            base + 3 * MONTH + 10
+        ],
+    )
+    .unwrap();
+    let updated = temporal_graph::get_graph_evolution(&conn, space_id, start, end, 12).unwrap();
+    assert_eq!(updated.summaries_computed, 9);
+    assert_eq!(updated.snapshots[2].metrics.total_nodes, 300);
+    assert_eq!(updated.snapshots[3].metrics.total_nodes, 401);
+    assert_eq!(updated.snapshots[3].metrics.communities, 2);
+}
+
+#[test]
+fn test_backfill_rebuilds_history_from_notes() {
+    let (mut conn, _dir) = setup_db();
+    let space_id = space::create_space(&mut conn, "Backfill Space").unwrap();
+    let base = chrono::Utc::now().timestamp() - 365 * 86400;
+    seed_year(&mut conn, &space_id.to_string(), base);
+
+    conn.execute_batch("DELETE FROM graph_change_log; DELETE FROM graph_node_metric;")
+        .unwrap();
+    temporal_graph::backfill_graph_history(&conn).unwrap();
+
+    let end = base + 6 * MONTH - 1;
+    let evolution = temporal_graph::get_graph_evolution(&conn, space_id, end, end, 1).unwrap();
+    assert_eq!(evolution.snapshots[0].metrics.total_nodes, 600);
+    assert_eq!(evolution.snapshots[0].metrics.total_edges, 599);
+
+    let degree: i64 = conn
+        .query_row("SELECT MAX(degree) FROM graph_node_metric", [], |row| {
+            row.get(0)
+        })
+        .unwrap();
+    assert_eq!(degree, 2);
+}
@@ -1,7 +1,9 @@
+use core_rs::calendar::{self, CalendarEvent};
 use core_rs::db;
+use core_rs::project;
 use core_rs::space;
 use core_rs::task;
-use core_rs::time_tracking;
+use core_rs::time_tracking::{self, EventEntryOverrides};
 use rusqlite::Connection;
 use tempfile::tempdir;
 use ulid::Ulid;
@@ -87,3 +89,145 @@ fn test_manual_time_entry_constraints() {
     // Should fail due to FK constraint
     assert!(result.is_err());
 }
+
+/// 2024-03-04 00:00 UTC
+const DAY_START: i64 = 1_709_510_400;
+const HOUR: i64 = 60 * 60;
+
+fn event_at(conn: &Connection, space_id: Ulid, summary: &str, start_hour: i64) -> CalendarEvent {
+    calendar::create_event(
+        conn,
+        space_id,
+        summary,
+        DAY_START + start_hour * HOUR,
+        Some(DAY_START + (start_hour + 1) * HOUR),
+        None,
+        None,
+        false,
+    )
+    .unwrap()
+}
+
+fn march_4() -> chrono::NaiveDate {
+    chrono::NaiveDate::from_ymd_opt(2024, 3, 4).unwrap()
+}
+
+#[test]
+fn test_time_entry_from_event_snapshots_the_event() {
+    let (mut conn, _dir) = setup_db();
+    let space_id = space::create_space(&mut conn, "Work").unwrap();
+    let task = task::create_task(&conn, space_id, "Redesign", None).unwrap();
+    let project = project::create_project(&conn, &space_id.to_string(), "Website").unwrap();
+    let review = event_at(&conn, space_id, "Design review", 14);
+
+    // Without a task, project or note the entry has nowhere to go
+    assert!(time_tracking::create_time_entry_from_event(
+        &conn,
+        review.id,
+        EventEntryOverrides::default()
+    )
+    .is_err());
+
+    let entry = time_tracking::create_time_entry_from_event(
+        &conn,
+        review.id,
+        EventEntryOverrides {
+            task_id: Some(task.id),
+            ..Default::default()
+        },
+    )
+    .unwrap();
+    assert_eq!(entry.calendar_event_id, Some(review.id));
+    assert_eq!(entry.task_id, Some(task.id));
+    assert_eq!(entry.description.as_deref(), Some("Design review"));
+    assert_eq!(entry.started_at, DAY_START + 14 * HOUR);
+    assert_eq!(entry.duration_seconds, Some(HOUR));
+
+    // Moving the event afterwards leaves the logged time alone
+    let mut moved = review.clone();
+    moved.start += 2 * HOUR;
+    moved.end = moved.end.map(|end| end + 3 * HOUR);
+    calendar::update_event(&conn, &moved).unwrap();
+    let stored = time_tracking::get_time_entry(&conn, entry.id)
+        .unwrap()
+        .unwrap();
+    assert_eq!(stored.calendar_event_id, Some(review.id));
+    assert_eq!(stored.started_at, entry.started_at);
+    assert_eq!(stored.ended_at, entry.ended_at);
+
+    // Overrides replace the event's association, description and times
+    let standup = event_at(&conn, space_id, "Standup", 9);
+    let entry = time_tracking::create_time_entry_from_event(
+        &conn,
+        standup.id,
+        EventEntryOverrides {
+            project_id: Some(Ulid::from_string(&project.id).unwrap()),
+            description: Some("Standup, ran over".to_string()),
+            ended_at: Some(DAY_START + 10 * HOUR + 15 * 60),
+            ..Default::default()
+        },
+    )
+    .unwrap();
+    assert_eq!(entry.task_id, None);
+    assert_eq!(entry.project_id.map(|id| id.to_string()), Some(project.id));
+    assert_eq!(entry.description.as_deref(), Some("Standup, ran over"));
+    assert_eq!(entry.duration_seconds, Some(HOUR + 15 * 60));
+}
+
+#[test]
+fn test_suggest_time_entries_for_day() {
+    let (mut conn, _dir) = setup_db();
+    let space_id = space::create_space(&mut conn, "Work").unwrap();
+    let task = task::create_task(&conn, space_id, "Admin", None).unwrap();
+
+    let logged = event_at(&conn, space_id, "Planning", 9);
+    event_at(&conn, space_id, "Sync with Sam", 11);
+    let open = event_at(&conn, space_id, "Design review", 14);
+    let touching = event_at(&conn, space_id, "Retro", 16);
+    event_at(&conn, space_id, "Tomorrow", 30);
+    calendar::create_event(
+        &conn,
+        space_id,
+        "Conference",
+        DAY_START,
+        None,
+        None,
+        None,
+        true,
+    )
+    .unwrap();
+
+    time_tracking::create_time_entry_from_event(
+        &conn,
+        logged.id,
+        EventEntryOverrides {
+            task_id: Some(task.id),
+            ..Default::default()
+        },
+    )
+    .unwrap();
+    // Tracked by hand 11:30-12:30, and 15:00-16:00, which ends as Retro starts
+    for (start, duration) in [(11 * HOUR + 30 * 60, HOUR), (15 * HOUR, HOUR)] {
+        time_tracking::create_manual_time_entry(
+            &conn,
+            time_tracking::CreateManualEntryParams {
+                space_id,
+                task_id: Some(task.id),
+                project_id: None,
+                note_id: None,
+                description: None,
+                started_at: DAY_START + start,
+                duration_seconds: duration,
+            },
+        )
+        .unwrap();
+    }
+
+    let suggested: Vec<Ulid> =
+        time_tracking::suggest_time_entries_for_day(&conn, space_id, march_4())
+            .unwrap()
+            .into_iter()
+            .map(|event| event.id)
+            .collect();
+    assert_eq!(suggested, vec![open.id, touching.id]);
+}
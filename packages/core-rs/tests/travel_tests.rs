@@ -0,0 +1,327 @@
+use core_rs::dashboard::{get_dashboard_stats_at, ComparisonWindow};
+use core_rs::db::migrate;
+use core_rs::personal_modes::{create_trip, enable_travel_mode, Trip};
+use core_rs::space::create_space;
+use core_rs::travel::*;
+use rusqlite::Connection;
+use ulid::Ulid;
+
+const DAY: i64 = 24 * 60 * 60;
+const HOUR: i64 = 60 * 60;
+
+/// Midnight UTC, 2025-01-10
+const JAN_10: i64 = 1_736_467_200;
+
+fn setup_db() -> (Connection, Ulid) {
+    let mut conn = Connection::open_in_memory().unwrap();
+    conn.pragma_update(None, "foreign_keys", "ON").unwrap();
+    migrate(&mut conn).unwrap();
+    let space_id = create_space(&mut conn, "Travel").unwrap();
+    (conn, space_id)
+}
+
+/// A trip from the 10th to the 12th of January
+fn trip(conn: &Connection, space_id: Ulid, destination: &str) -> Trip {
+    create_trip(
+        conn,
+        space_id,
+        "",
+        &format!("Trip to {}", destination),
+        destination,
+        JAN_10,
+        JAN_10 + 2 * DAY,
+    )
+    .unwrap()
+}
+
+fn add(conn: &Connection, trip: &Trip, title: &str, starts_at: i64) -> ItineraryItem {
+    add_itinerary_item(
+        conn,
+        &trip.id,
+        ItineraryItemParams {
+            title,
+            item_type: "activity",
+            starts_at,
+            location: None,
+            notes: None,
+            allow_outside: false,
+        },
+    )
+    .unwrap()
+}
+
+fn day_titles(conn: &Connection, trip: &Trip, day: &str) -> Vec<String> {
+    get_trip_itinerary(conn, &trip.id)
+        .unwrap()
+        .into_iter()
+        .find(|d| d.day == day)
+        .unwrap()
+        .items
+        .into_iter()
+        .map(|i| i.title)
+        .collect()
+}
+
+#[test]
+fn test_reorder_keeps_the_rest_of_the_day_in_order() {
+    let (conn, space_id) = setup_db();
+    let lisbon = trip(&conn, space_id, "Lisbon");
+    let day = JAN_10 + DAY;
+    let museum = add(&conn, &lisbon, "Museum", day + 10 * HOUR);
+    add(&conn, &lisbon, "Lunch", day + 13 * HOUR);
+    add(&conn, &lisbon, "Tram 28", day + 15 * HOUR);
+    let dinner = add(&conn, &lisbon, "Dinner", day + 20 * HOUR);
+    // Another day's positions are separate
+    let breakfast = add(&conn, &lisbon, "Breakfast", JAN_10 + 8 * HOUR);
+    assert_eq!(breakfast.position, 0);
+    assert_eq!(dinner.position, 3);
+
+    let reordered = reorder_itinerary_item(&conn, &dinner.id, 0).unwrap();
+    let positions = reordered.iter().map(|i| i.position).collect::<Vec<_>>();
+    assert_eq!(positions, vec![0, 1, 2, 3]);
+    assert_eq!(
+        day_titles(&conn, &lisbon, "2025-01-11"),
+        vec!["Dinner", "Museum", "Lunch", "Tram 28"]
+    );
+
+    // Past the end means last
+    reorder_itinerary_item(&conn, &museum.id, 99).unwrap();
+    let order = vec!["Dinner", "Lunch", "Tram 28", "Museum"];
+    assert_eq!(day_titles(&conn, &lisbon, "2025-01-11"), order);
+    // Moving to where it already is changes nothing, reading again neither
+    reorder_itinerary_item(&conn, &museum.id, 3).unwrap();
+    assert_eq!(day_titles(&conn, &lisbon, "2025-01-11"), order);
+    assert_eq!(day_titles(&conn, &lisbon, "2025-01-11"), order);
+
+    // New items go last, whatever their time
+    add(&conn, &lisbon, "Fado", day + 9 * HOUR);
+    assert_eq!(
+        day_titles(&conn, &lisbon, "2025-01-11"),
+        vec!["Dinner", "Lunch", "Tram 28", "Museum", "Fado"]
+    );
+    assert_eq!(day_titles(&conn, &lisbon, "2025-01-10"), vec!["Breakfast"]);
+
+    assert!(matches!(
+        reorder_itinerary_item(&conn, "missing", 0),
+        Err(TripError::NotFound { .. })
+    ));
+}
+
+#[test]
+fn test_items_outside_the_trip_need_allow_outside() {
+    let (conn, space_id) = setup_db();
+    let rome = trip(&conn, space_id, "Rome");
+    let flight = |starts_at: i64, allow_outside: bool| {
+        add_itinerary_item(
+            &conn,
+            &rome.id,
+            ItineraryItemParams {
+                title: "Flight",
+                item_type: "flight",
+                starts_at,
+                location: Some("FCO"),
+                notes: None,
+                allow_outside,
+            },
+        )
+    };
+
+    match flight(JAN_10 - 1, false) {
+        Err(TripError::OutsideTripDates { day, start, end }) => {
+            assert_eq!(day, "2025-01-09");
+            assert_eq!(start, "2025-01-10");
+            assert_eq!(end, "2025-01-12");
+        }
+        other => panic!("expected OutsideTripDates, got {:?}", other),
+    }
+    assert!(matches!(
+        flight(JAN_10 + 3 * DAY, false),
+        Err(TripError::OutsideTripDates { .. })
+    ));
+    // The whole last day is part of the trip, not just its first second
+    flight(JAN_10 + 3 * DAY - 1, false).unwrap();
+    flight(JAN_10, false).unwrap();
+
+    let early = flight(JAN_10 - 1, true).unwrap();
+    assert_eq!(early.day, "2025-01-09");
+    assert_eq!(early.location.as_deref(), Some("FCO"));
+    let days = get_trip_itinerary(&conn, &rome.id)
+        .unwrap()
+        .into_iter()
+        .map(|d| d.day)
+        .collect::<Vec<_>>();
+    assert_eq!(
+        days,
+        vec!["2025-01-09", "2025-01-10", "2025-01-11", "2025-01-12"]
+    );
+
+    assert!(matches!(
+        add_itinerary_item(
+            &conn,
+            "missing",
+            ItineraryItemParams {
+                title: "Nothing",
+                item_type: "activity",
+                starts_at: JAN_10,
+                location: None,
+                notes: None,
+                allow_outside: true,
+            },
+        ),
+        Err(TripError::NotFound { entity: "trip", .. })
+    ));
+}
+
+#[test]
+fn test_itinerary_groups_by_calendar_day() {
+    let (conn, space_id) = setup_db();
+    let oslo = trip(&conn, space_id, "Oslo");
+    add(&conn, &oslo, "Late train", JAN_10 + DAY - 1);
+    add(&conn, &oslo, "Midnight snack", JAN_10 + DAY);
+    add(&conn, &oslo, "Ferry", JAN_10 + 2 * DAY + 23 * HOUR);
+
+    let itinerary = get_trip_itinerary(&conn, &oslo.id).unwrap();
+    let grouped = itinerary
+        .iter()
+        .map(|d| {
+            (
+                d.day.as_str(),
+                d.items.iter().map(|i| i.title.as_str()).collect::<Vec<_>>(),
+            )
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(
+        grouped,
+        vec![
+            ("2025-01-10", vec!["Late train"]),
+            ("2025-01-11", vec!["Midnight snack"]),
+            ("2025-01-12", vec!["Ferry"]),
+        ]
+    );
+
+    // Days without items are still part of the trip
+    let empty = trip(&conn, space_id, "Bergen");
+    let itinerary = get_trip_itinerary(&conn, &empty.id).unwrap();
+    assert_eq!(itinerary.len(), 3);
+    assert!(itinerary.iter().all(|d| d.items.is_empty()));
+}
+
+#[test]
+fn test_packing_templates_are_reused_across_trips() {
+    let (mut conn, space_id) = setup_db();
+    let first = trip(&conn, space_id, "Paris");
+    let passport = add_packing_item(&conn, &first.id, "Passport", None, 1).unwrap();
+    add_packing_item(&conn, &first.id, "Charger", Some("electronics"), 2).unwrap();
+    add_packing_item(&conn, &first.id, "Socks", Some("clothes"), 5).unwrap();
+    assert!(toggle_packed(&conn, &passport.id).unwrap());
+    assert!(matches!(
+        add_packing_item(&conn, &first.id, "  ", None, 1),
+        Err(TripError::InvalidPackingItem(_))
+    ));
+    assert!(matches!(
+        add_packing_item(&conn, &first.id, "Shoes", None, 0),
+        Err(TripError::InvalidPackingItem(_))
+    ));
+
+    let template = save_packing_template(&conn, &first.id, "City break").unwrap();
+    assert_eq!(template.items.len(), 3);
+    assert_eq!(
+        template.items[1],
+        PackingTemplateItem {
+            name: "Charger".to_string(),
+            category: Some("electronics".to_string()),
+            quantity: 2,
+        }
+    );
+
+    // A list that already has some of the items only gets the rest, unpacked
+    let second = trip(&conn, space_id, "Berlin");
+    add_packing_item(&conn, &second.id, "passport", None, 1).unwrap();
+    let added = apply_packing_template(&conn, &template.id, &second.id).unwrap();
+    let names = added.iter().map(|i| i.name.as_str()).collect::<Vec<_>>();
+    assert_eq!(names, vec!["Charger", "Socks"]);
+    assert!(added.iter().all(|i| !i.is_packed));
+    assert!(apply_packing_template(&conn, &template.id, &second.id)
+        .unwrap()
+        .is_empty());
+    let list = get_packing_list(&conn, &second.id).unwrap();
+    let positions = list.iter().map(|i| i.position).collect::<Vec<_>>();
+    assert_eq!(positions, vec![0, 1, 2]);
+
+    let third = trip(&conn, space_id, "Vienna");
+    assert_eq!(
+        apply_packing_template(&conn, &template.id, &third.id)
+            .unwrap()
+            .len(),
+        3
+    );
+
+    // Saving under the same name replaces the template
+    let replaced = save_packing_template(&conn, &second.id, "City break").unwrap();
+    let templates = get_packing_templates(&conn, space_id).unwrap();
+    assert_eq!(templates.len(), 1);
+    assert_eq!(templates[0].id, replaced.id);
+    assert_eq!(templates[0].items[0].name, "passport");
+
+    // Templates stay in their space
+    let work = create_space(&mut conn, "Work").unwrap();
+    let work_trip = trip(&conn, work, "Zurich");
+    assert!(matches!(
+        apply_packing_template(&conn, &replaced.id, &work_trip.id),
+        Err(TripError::NotFound {
+            entity: "packing_template",
+            ..
+        })
+    ));
+    delete_packing_template(&conn, &replaced.id).unwrap();
+    assert!(get_packing_templates(&conn, space_id).unwrap().is_empty());
+}
+
+#[test]
+fn test_trip_summary_on_the_dashboard() {
+    let (conn, space_id) = setup_db();
+    let space = space_id.to_string();
+    let madrid = trip(&conn, space_id, "Madrid");
+    add(&conn, &madrid, "Prado", JAN_10 + 10 * HOUR);
+    add(&conn, &madrid, "Tapas", JAN_10 + 20 * HOUR);
+    add(&conn, &madrid, "Retiro", JAN_10 + 2 * DAY + 11 * HOUR);
+    let sunscreen = add_packing_item(&conn, &madrid.id, "Sunscreen", None, 1).unwrap();
+    add_packing_item(&conn, &madrid.id, "Hat", None, 1).unwrap();
+    toggle_packed(&conn, &sunscreen.id).unwrap();
+    assert_eq!(
+        get_trip_packing_stats(&conn, &madrid.id).unwrap(),
+        PackingStats {
+            total: 2,
+            packed: 1,
+            unpacked: 1,
+        }
+    );
+    assert!(!toggle_packed(&conn, &sunscreen.id).unwrap());
+    toggle_packed(&conn, &sunscreen.id).unwrap();
+
+    let summary = get_trip_summary(&conn, &madrid.id).unwrap();
+    let counts = summary
+        .days
+        .iter()
+        .map(|d| (d.day.as_str(), d.item_count))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        counts,
+        vec![("2025-01-10", 2), ("2025-01-11", 0), ("2025-01-12", 1)]
+    );
+    assert_eq!(summary.item_count, 3);
+    assert_eq!(summary.unpacked_count, 1);
+
+    // Travel shows up once the mode is on, until the trip's last day is over
+    let on_the_last_day = JAN_10 + 2 * DAY + 23 * HOUR;
+    let stats =
+        get_dashboard_stats_at(&conn, &space, ComparisonWindow::Week, on_the_last_day).unwrap();
+    assert!(stats.travel.is_none());
+    enable_travel_mode(&conn, &space).unwrap();
+    let stats =
+        get_dashboard_stats_at(&conn, &space, ComparisonWindow::Week, on_the_last_day).unwrap();
+    assert_eq!(stats.travel.unwrap().upcoming_trips, vec![summary]);
+    let stats =
+        get_dashboard_stats_at(&conn, &space, ComparisonWindow::Week, JAN_10 + 3 * DAY).unwrap();
+    assert!(stats.travel.unwrap().upcoming_trips.is_empty());
+}
@@ -0,0 +1,173 @@
+use core_rs::db::migrate;
+use core_rs::goals::{create_goal, delete_goal, get_goals};
+use core_rs::task::{create_task, delete_task, get_task, update_task};
+use core_rs::time_tracking::{
+    create_manual_time_entry, get_task_time_entries, CreateManualEntryParams,
+};
+use core_rs::undo::*;
+use rusqlite::Connection;
+use ulid::Ulid;
+
+fn setup() -> (Connection, Ulid) {
+    let mut conn = Connection::open_in_memory().unwrap();
+    conn.pragma_update(None, "foreign_keys", "ON").unwrap();
+    migrate(&mut conn).unwrap();
+    let space_id = core_rs::space::create_space(&mut conn, "Undo").unwrap();
+    (conn, space_id)
+}
+
+fn log_time(conn: &Connection, space_id: Ulid, task_id: Ulid, started_at: i64) {
+    create_manual_time_entry(
+        conn,
+        CreateManualEntryParams {
+            space_id,
+            task_id: Some(task_id),
+            project_id: None,
+            note_id: None,
+            description: Some("Work".to_string()),
+            started_at,
+            duration_seconds: 1_800,
+        },
+    )
+    .unwrap();
+}
+
+#[test]
+fn test_delete_task_with_time_entries_can_be_undone() {
+    let (conn, space_id) = setup();
+    let task = create_task(&conn, space_id, "Write report", None).unwrap();
+    let mut subtask = create_task(&conn, space_id, "Draft outline", None).unwrap();
+    subtask.parent_task_id = Some(task.id);
+    update_task(&conn, &subtask).unwrap();
+    log_time(&conn, space_id, task.id, 1_700_000_000);
+    log_time(&conn, space_id, task.id, 1_700_010_000);
+
+    delete_task(&conn, task.id).unwrap();
+    assert!(get_task(&conn, task.id).unwrap().is_none());
+    assert!(get_task_time_entries(&conn, task.id).unwrap().is_empty());
+    assert_eq!(
+        get_task(&conn, subtask.id).unwrap().unwrap().parent_task_id,
+        None
+    );
+
+    let operations = list_undoable_operations(&conn, 10).unwrap();
+    assert_eq!(operations.len(), 1);
+    let op = &operations[0];
+    assert_eq!(op.operation, "delete_task");
+    assert_eq!(op.entity_id, task.id.to_string());
+    assert_eq!(op.space_id, space_id.to_string());
+    // The task, its two time entries and the detached subtask
+    assert_eq!(op.row_count, 4);
+
+    let undone = undo_operation(&conn, &op.id).unwrap();
+    assert_eq!(undone.id, op.id);
+    let restored = get_task(&conn, task.id).unwrap().unwrap();
+    assert_eq!(restored.title, "Write report");
+    assert!(restored.updated_at >= task.updated_at);
+    let entries = get_task_time_entries(&conn, task.id).unwrap();
+    assert_eq!(entries.len(), 2);
+    assert!(entries.iter().all(|e| e.duration_seconds == Some(1_800)));
+    assert_eq!(
+        get_task(&conn, subtask.id).unwrap().unwrap().parent_task_id,
+        Some(task.id)
+    );
+
+    // Each entry is undone once
+    assert!(list_undoable_operations(&conn, 10).unwrap().is_empty());
+    assert!(matches!(
+        undo_operation(&conn, &op.id),
+        Err(UndoError::NotFound(_))
+    ));
+}
+
+#[test]
+fn test_delete_goal_can_be_undone() {
+    let (conn, space_id) = setup();
+    let goal = create_goal(&conn, space_id, "Run 100km", 100.0, "health").unwrap();
+    create_goal(&conn, space_id, "Read 12 books", 12.0, "learning").unwrap();
+
+    delete_goal(&conn, goal.id).unwrap();
+    assert_eq!(get_goals(&conn, space_id).unwrap().len(), 1);
+
+    let op = list_undoable_operations(&conn, 10).unwrap().remove(0);
+    assert_eq!(op.operation, "delete_goal");
+    assert_eq!(op.summary, "Deleted goal \"Run 100km\"");
+    undo_operation(&conn, &op.id).unwrap();
+
+    let goals = get_goals(&conn, space_id).unwrap();
+    let restored = goals.iter().find(|g| g.id == goal.id).unwrap();
+    assert_eq!(restored.title, "Run 100km");
+    assert_eq!(restored.target, 100.0);
+    assert_eq!(restored.created_at, goal.created_at);
+}
+
+#[test]
+fn test_undo_after_conflicting_recreation_is_refused() {
+    let (conn, space_id) = setup();
+    let task = create_task(&conn, space_id, "Call plumber", None).unwrap();
+    log_time(&conn, space_id, task.id, 1_700_000_000);
+    delete_task(&conn, task.id).unwrap();
+
+    // A task with the same id arrives again, e.g. from another device
+    conn.execute(
+        "INSERT INTO task (id, space_id, title, status, updated_at)
+         VALUES (?1, ?2, 'Call plumber again', 'inbox', 0)",
+        [task.id.to_string(), space_id.to_string()],
+    )
+    .unwrap();
+
+    let op = list_undoable_operations(&conn, 10).unwrap().remove(0);
+    match undo_operation(&conn, &op.id) {
+        Err(UndoError::Conflict(UndoConflict::RowExists { table, key })) => {
+            assert_eq!(table, "task");
+            assert_eq!(key, task.id.to_string());
+        }
+        other => panic!("expected a conflict, got {:?}", other),
+    }
+
+    // Nothing was restored and the entry can be retried later
+    assert_eq!(
+        get_task(&conn, task.id).unwrap().unwrap().title,
+        "Call plumber again"
+    );
+    assert!(get_task_time_entries(&conn, task.id).unwrap().is_empty());
+    assert_eq!(list_undoable_operations(&conn, 10).unwrap().len(), 1);
+}
+
+#[test]
+fn test_expired_entries_cannot_be_undone() {
+    let (conn, space_id) = setup();
+    let goal = create_goal(&conn, space_id, "Save", 1_000.0, "finance").unwrap();
+    delete_goal(&conn, goal.id).unwrap();
+    let op_id: String = conn
+        .query_row("SELECT id FROM undo_log", [], |row| row.get(0))
+        .unwrap();
+    conn.execute("UPDATE undo_log SET expires_at = 0", [])
+        .unwrap();
+
+    assert!(list_undoable_operations(&conn, 10).unwrap().is_empty());
+    assert!(matches!(
+        undo_operation(&conn, &op_id),
+        Err(UndoError::Expired(_))
+    ));
+    assert_eq!(prune_undo_log(&conn).unwrap(), 1);
+}
+
+#[test]
+fn test_journal_keeps_the_newest_entries() {
+    let (conn, space_id) = setup();
+    for i in 0..MAX_UNDO_ENTRIES + 3 {
+        let goal = create_goal(&conn, space_id, &format!("Goal {}", i), 1.0, "misc").unwrap();
+        delete_goal(&conn, goal.id).unwrap();
+    }
+
+    let count: i64 = conn
+        .query_row("SELECT COUNT(*) FROM undo_log", [], |row| row.get(0))
+        .unwrap();
+    assert_eq!(count, MAX_UNDO_ENTRIES);
+    let newest = list_undoable_operations(&conn, 1).unwrap().remove(0);
+    assert_eq!(
+        newest.summary,
+        format!("Deleted goal \"Goal {}\"", MAX_UNDO_ENTRIES + 2)
+    );
+}
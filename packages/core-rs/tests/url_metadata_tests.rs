@@ -0,0 +1,267 @@
+use core_rs::db::migrate;
+use core_rs::note::create_note;
+use core_rs::space::create_space;
+use core_rs::url_metadata::*;
+use rusqlite::Connection;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpListener;
+use tokio::task::JoinHandle;
+
+/// Answer one request per scripted `(status, headers, body)`, closing the
+/// connection after each, and hand back the requested paths
+async fn serve(script: Vec<(&'static str, String, String)>) -> (String, JoinHandle<Vec<String>>) {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let base_url = format!("http://{}", listener.local_addr().unwrap());
+    let handle = tokio::spawn(async move {
+        let mut paths = Vec::new();
+        for (status, headers, body) in script {
+            let (mut socket, _) = listener.accept().await.unwrap();
+            paths.push(read_path(&mut socket).await);
+            let head = format!(
+                "HTTP/1.1 {}\r\n{}Content-Length: {}\r\nConnection: close\r\n\r\n",
+                status,
+                headers,
+                body.len()
+            );
+            socket.write_all(head.as_bytes()).await.unwrap();
+            // The client may hang up once it has seen the <head>
+            let _ = socket.write_all(body.as_bytes()).await;
+        }
+        paths
+    });
+    (base_url, handle)
+}
+
+async fn read_path(socket: &mut tokio::net::TcpStream) -> String {
+    let mut data = Vec::new();
+    let mut buf = [0u8; 4096];
+    while !data.windows(4).any(|w| w == b"\r\n\r\n") {
+        let n = socket.read(&mut buf).await.unwrap();
+        data.extend_from_slice(&buf[..n]);
+    }
+    let head = String::from_utf8_lossy(&data).to_string();
+    head.split(' ').nth(1).unwrap().to_string()
+}
+
+fn html(body: &str) -> (&'static str, String, String) {
+    (
+        "200 OK",
+        "Content-Type: text/html; charset=utf-8\r\n".to_string(),
+        body.to_string(),
+    )
+}
+
+fn local() -> FetchOptions {
+    FetchOptions {
+        allow_private_addresses: true,
+        ..Default::default()
+    }
+}
+
+const ARTICLE: &str = r#"<!DOCTYPE html>
+<html>
+<head>
+  <title>Fallback title</title>
+  <meta property="og:title" content="Rust &amp; SQLite in practice">
+  <meta property='og:description' content='How we   ship a local-first
+    notes app'>
+  <meta name="description" content="Plain description">
+  <meta property="og:image" content="/img/cover.png">
+  <meta property="og:site_name" content="Field Notes">
+  <link rel="stylesheet" href="/site.css">
+  <link rel="shortcut icon" href="/favicon.png">
+</head>
+<body><p>Hello</p></body>
+</html>"#;
+
+#[tokio::test]
+async fn test_open_graph_tags_are_preferred() {
+    let (base_url, server) = serve(vec![html(ARTICLE)]).await;
+    let url = format!("{}/posts/1", base_url);
+    let metadata = fetch_url_metadata_with(&url, &local()).await.unwrap();
+
+    assert_eq!(metadata.url, url);
+    assert_eq!(metadata.final_url, url);
+    assert_eq!(metadata.title.as_deref(), Some("Rust & SQLite in practice"));
+    assert_eq!(
+        metadata.description.as_deref(),
+        Some("How we ship a local-first notes app")
+    );
+    assert_eq!(
+        metadata.image_url,
+        Some(format!("{}/img/cover.png", base_url))
+    );
+    assert_eq!(metadata.site_name.as_deref(), Some("Field Notes"));
+    assert_eq!(
+        metadata.favicon_url,
+        Some(format!("{}/favicon.png", base_url))
+    );
+    assert_eq!(metadata.error, None);
+    assert!(!metadata.is_expired());
+    assert_eq!(server.await.unwrap(), ["/posts/1"]);
+}
+
+#[tokio::test]
+async fn test_missing_metadata_falls_back() {
+    let (base_url, server) = serve(vec![
+        (
+            "301 Moved Permanently",
+            "Location: /plain\r\n".to_string(),
+            String::new(),
+        ),
+        html("<html><head><TITLE>\n  Just a   title </TITLE></head></html>"),
+        html("<p>No head at all</p>"),
+        (
+            "200 OK",
+            "Content-Type: application/pdf\r\n".to_string(),
+            "%PDF-1.7".to_string(),
+        ),
+    ])
+    .await;
+
+    let plain = fetch_url_metadata_with(&format!("{}/old", base_url), &local())
+        .await
+        .unwrap();
+    assert_eq!(plain.final_url, format!("{}/plain", base_url));
+    assert_eq!(plain.title.as_deref(), Some("Just a title"));
+    assert_eq!(plain.description, None);
+    assert_eq!(plain.image_url, None);
+    assert_eq!(plain.favicon_url, None);
+    // Without og:site_name the host stands in
+    assert_eq!(plain.site_name.as_deref(), Some("127.0.0.1"));
+
+    let bare = fetch_url_metadata_with(&format!("{}/bare", base_url), &local())
+        .await
+        .unwrap();
+    assert_eq!(bare.title, None);
+
+    let pdf = fetch_url_metadata_with(&format!("{}/paper.pdf", base_url), &local()).await;
+    assert!(matches!(pdf, Err(UrlMetadataError::NotHtml(_))));
+    assert_eq!(
+        server.await.unwrap(),
+        ["/old", "/plain", "/bare", "/paper.pdf"]
+    );
+}
+
+#[tokio::test]
+async fn test_oversized_responses_and_redirect_loops_are_refused() {
+    let huge = format!("<html><head>{}", "<meta name=x content=y>".repeat(100_000));
+    let loop_hop = || {
+        (
+            "302 Found",
+            "Location: /again\r\n".to_string(),
+            String::new(),
+        )
+    };
+    let (base_url, server) = serve(vec![html(&huge), loop_hop(), loop_hop(), loop_hop()]).await;
+
+    let options = FetchOptions {
+        max_bytes: 64 * 1024,
+        max_redirects: 2,
+        ..local()
+    };
+    let result = fetch_url_metadata_with(&format!("{}/huge", base_url), &options).await;
+    assert!(matches!(result, Err(UrlMetadataError::TooLarge(_))));
+
+    let result = fetch_url_metadata_with(&format!("{}/again", base_url), &options).await;
+    assert!(matches!(result, Err(UrlMetadataError::TooManyRedirects(2))));
+    assert_eq!(server.await.unwrap().len(), 4);
+}
+
+#[tokio::test]
+async fn test_private_addresses_are_rejected() {
+    // A real server that must never be contacted
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let local_url = format!("http://{}/", listener.local_addr().unwrap());
+
+    for url in [
+        local_url.as_str(),
+        "http://localhost:9/",
+        "http://10.1.2.3/",
+        "http://192.168.0.1/admin",
+        "http://169.254.169.254/latest/meta-data",
+        "http://100.64.0.1/",
+        "http://[::1]/",
+        "http://[fd00::1]/",
+        "http://[::ffff:127.0.0.1]/",
+    ] {
+        let result = fetch_url_metadata(url).await;
+        assert!(
+            matches!(result, Err(UrlMetadataError::PrivateAddress(_))),
+            "{}: {:?}",
+            url,
+            result
+        );
+    }
+    for url in ["ftp://example.com/file", "file:///etc/passwd", "not a url"] {
+        assert!(matches!(
+            fetch_url_metadata(url).await,
+            Err(UrlMetadataError::InvalidUrl(_))
+        ));
+    }
+
+    let accepted =
+        tokio::time::timeout(std::time::Duration::from_millis(100), listener.accept()).await;
+    assert!(accepted.is_err());
+}
+
+#[test]
+fn test_note_links_are_split_by_cache_state() {
+    let mut conn = Connection::open_in_memory().unwrap();
+    migrate(&mut conn).unwrap();
+    let space_id = create_space(&mut conn, "Links").unwrap().to_string();
+    let note = create_note(
+        &conn,
+        &space_id,
+        "Reading list",
+        "See https://example.com/a, and [the docs](https://docs.example.org/guide).\n\
+         Also <https://example.com/a> again, **https://blog.example.net/post**!\n\
+         Broken: https://dead.example.com/x",
+    )
+    .unwrap();
+
+    assert_eq!(
+        extract_urls("Visit https://example.com/a. Or http://x.y/z?q=1#top; ftp://no"),
+        ["https://example.com/a", "http://x.y/z?q=1#top"]
+    );
+
+    let fetched = UrlMetadata {
+        url: "https://example.com/a".to_string(),
+        final_url: "https://example.com/a".to_string(),
+        title: Some("A".to_string()),
+        description: None,
+        image_url: None,
+        site_name: Some("example.com".to_string()),
+        favicon_url: None,
+        error: None,
+        fetched_at: 0,
+        expires_at: i64::MAX,
+    };
+    cache_url_metadata(&conn, &fetched).unwrap();
+    let stale = UrlMetadata {
+        url: "https://blog.example.net/post".to_string(),
+        expires_at: 0,
+        ..fetched.clone()
+    };
+    cache_url_metadata(&conn, &stale).unwrap();
+    let failure = UrlMetadata::failed("https://dead.example.com/x", &UrlMetadataError::Status(404));
+    cache_url_metadata(&conn, &failure).unwrap();
+
+    let previews = extract_urls_from_note(&conn, note.id.0).unwrap();
+    assert_eq!(previews.cached, [fetched, stale, failure]);
+    assert_eq!(
+        previews.needs_fetch,
+        [
+            "https://docs.example.org/guide",
+            "https://blog.example.net/post"
+        ]
+    );
+    assert_eq!(
+        get_cached_url_metadata(&conn, "https://dead.example.com/x")
+            .unwrap()
+            .unwrap()
+            .error
+            .as_deref(),
+        Some("Server responded with status 404")
+    );
+}
@@ -0,0 +1,102 @@
+use core_rs::vault::{AutoLockGuard, Clock, LockReason, VaultError};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+const MAX_IDLE: Duration = Duration::from_secs(300);
+
+/// Clock that only moves when told to
+struct MockClock {
+    start: Instant,
+    elapsed: Mutex<Duration>,
+}
+
+impl MockClock {
+    fn new() -> Arc<Self> {
+        Arc::new(MockClock {
+            start: Instant::now(),
+            elapsed: Mutex::new(Duration::ZERO),
+        })
+    }
+
+    fn advance(&self, by: Duration) {
+        *self.elapsed.lock().unwrap() += by;
+    }
+}
+
+impl Clock for MockClock {
+    fn now(&self) -> Instant {
+        self.start + *self.elapsed.lock().unwrap()
+    }
+}
+
+fn guard_with_log(clock: Arc<MockClock>) -> (AutoLockGuard, Arc<Mutex<Vec<LockReason>>>) {
+    let locks = Arc::new(Mutex::new(Vec::new()));
+    let guard = AutoLockGuard::with_clock([7u8; 32], clock).on_lock({
+        let locks = locks.clone();
+        move |reason| locks.lock().unwrap().push(reason)
+    });
+    (guard, locks)
+}
+
+#[test]
+fn test_idle_vault_locks_and_signals() {
+    let clock = MockClock::new();
+    let (guard, locks) = guard_with_log(clock.clone());
+
+    clock.advance(Duration::from_secs(299));
+    assert!(!guard.check_and_lock(MAX_IDLE));
+    assert_eq!(
+        guard.remaining_until_lock(MAX_IDLE),
+        Some(Duration::from_secs(1))
+    );
+
+    clock.advance(Duration::from_secs(1));
+    assert_eq!(guard.remaining_until_lock(MAX_IDLE), Some(Duration::ZERO));
+    assert!(guard.check_and_lock(MAX_IDLE));
+    assert!(guard.is_locked());
+    assert_eq!(guard.remaining_until_lock(MAX_IDLE), None);
+
+    // Locking again is a no-op and does not signal twice
+    assert!(!guard.check_and_lock(MAX_IDLE));
+    assert!(!guard.lock());
+    assert_eq!(*locks.lock().unwrap(), vec![LockReason::Idle]);
+}
+
+#[test]
+fn test_touch_resets_idle_timer() {
+    let clock = MockClock::new();
+    let (guard, locks) = guard_with_log(clock.clone());
+
+    for _ in 0..5 {
+        clock.advance(Duration::from_secs(200));
+        guard.touch().unwrap();
+        assert!(!guard.check_and_lock(MAX_IDLE));
+    }
+    assert_eq!(guard.remaining_until_lock(MAX_IDLE), Some(MAX_IDLE));
+
+    // Reading the key is not activity
+    clock.advance(Duration::from_secs(200));
+    assert_eq!(*guard.dek().unwrap(), [7u8; 32]);
+    clock.advance(Duration::from_secs(100));
+    assert!(guard.check_and_lock(MAX_IDLE));
+    assert_eq!(locks.lock().unwrap().len(), 1);
+}
+
+#[test]
+fn test_operations_fail_after_lock() {
+    let clock = MockClock::new();
+    let (guard, locks) = guard_with_log(clock);
+
+    let dek = guard.dek().unwrap();
+    assert!(guard.lock());
+    assert_eq!(*locks.lock().unwrap(), vec![LockReason::Manual]);
+
+    assert!(matches!(guard.dek(), Err(VaultError::Locked)));
+    assert!(matches!(guard.touch(), Err(VaultError::Locked)));
+    assert_eq!(
+        guard.dek().unwrap_err().to_string(),
+        "Vault is locked; unlock it to continue"
+    );
+    // Copies handed out earlier are the caller's to drop
+    assert_eq!(*dek, [7u8; 32]);
+}
@@ -0,0 +1,185 @@
+use core_rs::error::{codes, CoreError};
+use core_rs::vault::lock::HOLDER_FILE;
+use core_rs::vault::{
+    create_vault, rotate_dek, unlock_vault, unlock_vault_with_options, LockHolder, LockOptions,
+    VaultError, VaultLock,
+};
+use std::path::Path;
+use std::sync::{Arc, Barrier};
+use std::time::Duration;
+use tempfile::tempdir;
+
+const PASSWORD: &str = "correct horse";
+
+/// No process has this PID: Linux caps PIDs at 2^22
+const DEAD_PID: u32 = 99_999_999;
+
+/// A holder record with no process holding the lock, as a crash leaves it
+fn write_lock(dir: &Path, holder: &LockHolder) {
+    std::fs::write(dir.join(HOLDER_FILE), serde_json::to_vec(holder).unwrap()).unwrap();
+}
+
+fn holder(pid: u32, hostname: &str, age_secs: i64) -> LockHolder {
+    LockHolder {
+        pid,
+        hostname: hostname.to_string(),
+        acquired_at: chrono::Utc::now().timestamp() - age_secs,
+        instance_id: ulid::Ulid::new().to_string(),
+    }
+}
+
+/// The hostname this process writes into its locks
+fn local_hostname() -> String {
+    let dir = tempdir().unwrap();
+    let lock = VaultLock::acquire(dir.path(), &LockOptions::default()).unwrap();
+    lock.holder().hostname.clone()
+}
+
+#[test]
+fn test_second_open_is_refused_until_the_first_is_dropped() {
+    let dir = tempdir().unwrap();
+    let path = dir.path().to_str().unwrap();
+    let vault = create_vault(path, PASSWORD).unwrap();
+    assert!(dir.path().join(HOLDER_FILE).exists());
+
+    let err = unlock_vault(path, PASSWORD).err().unwrap();
+    let VaultError::VaultAlreadyOpen(holder) = &err else {
+        panic!("expected VaultAlreadyOpen, got {:?}", err);
+    };
+    assert_eq!(holder.pid, std::process::id());
+    assert_eq!(holder, vault.lock.holder());
+    assert!(err.to_string().contains(&holder.hostname));
+
+    let core = CoreError::from(err);
+    assert_eq!(core.code, codes::VAULT_ALREADY_OPEN);
+    assert_eq!(core.context["pid"], std::process::id().to_string());
+
+    // A wrong password doesn't get as far as the password check either, and
+    // the key can't be rotated under an open vault
+    assert!(matches!(
+        unlock_vault(path, "wrong"),
+        Err(VaultError::VaultAlreadyOpen(_))
+    ));
+    assert!(matches!(
+        rotate_dek(path, PASSWORD, |_| {}),
+        Err(VaultError::VaultAlreadyOpen(_))
+    ));
+    // Forcing doesn't break a lock held by a live process
+    assert!(matches!(
+        unlock_vault_with_options(
+            path,
+            PASSWORD,
+            &LockOptions {
+                force: true,
+                stale_after: Duration::ZERO,
+            },
+        ),
+        Err(VaultError::VaultAlreadyOpen(_))
+    ));
+
+    drop(vault);
+    assert!(!dir.path().join(HOLDER_FILE).exists());
+    let vault = unlock_vault(path, PASSWORD).unwrap();
+    vault.close().unwrap();
+    assert!(!dir.path().join(HOLDER_FILE).exists());
+}
+
+#[test]
+fn test_lock_left_by_a_local_process_is_taken_over() {
+    let dir = tempdir().unwrap();
+    let path = dir.path().to_str().unwrap();
+    drop(create_vault(path, PASSWORD).unwrap());
+
+    // The OS lock is free, so whatever the record says its holder is gone,
+    // even when its PID has been reused by a live process
+    let hostname = local_hostname();
+    for pid in [DEAD_PID, 1] {
+        write_lock(dir.path(), &holder(pid, &hostname, 0));
+        let vault = unlock_vault(path, PASSWORD).unwrap();
+        assert_eq!(vault.lock.holder().pid, std::process::id());
+        vault.close().unwrap();
+    }
+}
+
+#[test]
+fn test_foreign_lock_is_only_broken_when_forced_and_old() {
+    let dir = tempdir().unwrap();
+    let path = dir.path().to_str().unwrap();
+    drop(create_vault(path, PASSWORD).unwrap());
+    let force = LockOptions {
+        force: true,
+        stale_after: Duration::from_secs(600),
+    };
+
+    // Another machine's PID says nothing about processes here
+    let recent = holder(DEAD_PID, "other-laptop", 60);
+    write_lock(dir.path(), &recent);
+    assert!(matches!(
+        unlock_vault_with_options(path, PASSWORD, &force),
+        Err(VaultError::VaultAlreadyOpen(h)) if h == recent
+    ));
+
+    let old = holder(DEAD_PID, "other-laptop", 3_600);
+    write_lock(dir.path(), &old);
+    assert!(matches!(
+        unlock_vault(path, PASSWORD),
+        Err(VaultError::VaultAlreadyOpen(h)) if h == old
+    ));
+    let vault = unlock_vault_with_options(path, PASSWORD, &force).unwrap();
+    assert_ne!(vault.lock.holder(), &old);
+}
+
+#[test]
+fn test_lock_is_released_on_drop_and_explicitly() {
+    let dir = tempdir().unwrap();
+    let holder_path = dir.path().join(HOLDER_FILE);
+
+    let lock = VaultLock::acquire(dir.path(), &LockOptions::default()).unwrap();
+    let written: LockHolder =
+        serde_json::from_slice(&std::fs::read(&holder_path).unwrap()).unwrap();
+    assert_eq!(&written, lock.holder());
+    assert!(VaultLock::acquire(dir.path(), &LockOptions::default()).is_err());
+    drop(lock);
+    assert!(!holder_path.exists());
+
+    let lock = VaultLock::acquire(dir.path(), &LockOptions::default()).unwrap();
+    lock.release().unwrap();
+    assert!(!holder_path.exists());
+}
+
+#[test]
+fn test_concurrent_opens_let_exactly_one_in() {
+    let dir = tempdir().unwrap();
+    let barrier = Arc::new(Barrier::new(8));
+    let handles: Vec<_> = (0..8)
+        .map(|_| {
+            let dir = dir.path().to_path_buf();
+            let barrier = barrier.clone();
+            std::thread::spawn(move || {
+                barrier.wait();
+                VaultLock::acquire(&dir, &LockOptions::default())
+            })
+        })
+        .collect();
+    let results: Vec<_> = handles.into_iter().map(|h| h.join().unwrap()).collect();
+
+    let winners: Vec<_> = results.iter().filter_map(|r| r.as_ref().ok()).collect();
+    assert_eq!(winners.len(), 1);
+    for result in &results {
+        if let Err(e) = result {
+            assert!(
+                matches!(e, VaultError::VaultAlreadyOpen(_)),
+                "unexpected error: {:?}",
+                e
+            );
+        }
+    }
+}
+
+#[test]
+fn test_half_written_record_is_replaced() {
+    let dir = tempdir().unwrap();
+    std::fs::write(dir.path().join(HOLDER_FILE), b"{\"pid\": 12").unwrap();
+    let lock = VaultLock::acquire(dir.path(), &LockOptions::default()).unwrap();
+    assert_eq!(lock.holder().pid, std::process::id());
+}
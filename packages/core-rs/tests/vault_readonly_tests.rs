@@ -0,0 +1,189 @@
+use core_rs::db::EncryptedConnectionManager;
+use core_rs::error::{codes, CoreError};
+use core_rs::note::{create_note, update_note_content};
+use core_rs::note_lock::lock_note;
+use core_rs::space::create_space;
+use core_rs::srs::create_knowledge_card;
+use core_rs::task::{create_task, update_task};
+use core_rs::vault::{
+    biometric_token, create_vault, open_vault_readonly, rotate_dek, unlock_vault, AutoLockGuard,
+    ReadOnlyCredential, VaultError, PREVIEW_CHARS,
+};
+use std::path::Path;
+use std::sync::Arc;
+use tempfile::tempdir;
+
+const PASSWORD: &str = "password";
+
+fn passphrase() -> ReadOnlyCredential {
+    ReadOnlyCredential::Passphrase(PASSWORD.to_string())
+}
+
+#[test]
+fn test_previews_follow_edits() {
+    let dir = tempdir().unwrap();
+    let path = dir.path().to_str().unwrap();
+    let mut vault = create_vault(path, PASSWORD).unwrap();
+    let space_id = create_space(&mut vault.conn, "Personal").unwrap();
+    let long_body = "word ".repeat(100);
+    let note = create_note(&vault.conn, &space_id.to_string(), "Journal", &long_body).unwrap();
+    let mut task = create_task(&vault.conn, space_id, "File taxes", None).unwrap();
+    task.due_at = Some(chrono::Utc::now().timestamp() + 3600);
+    update_task(&vault.conn, &task).unwrap();
+    create_knowledge_card(&vault.conn, note.id.0).unwrap();
+
+    let handle = open_vault_readonly(path, &passphrase()).unwrap();
+    assert!(handle.is_read_only());
+    let notes = handle.recent_notes(10).unwrap();
+    assert_eq!(notes.len(), 1);
+    assert_eq!(notes[0].title, "Journal");
+    assert_eq!(notes[0].preview.chars().count(), PREVIEW_CHARS);
+    let tasks = handle.upcoming_tasks(10).unwrap();
+    assert_eq!(tasks.len(), 1);
+    assert_eq!(tasks[0].title, "File taxes");
+    assert_eq!(handle.due_card_count().unwrap(), 1);
+
+    // Saves made while unlocked show up without reopening
+    update_note_content(&mut vault.conn, note.id, "Diary", "Short entry").unwrap();
+    task.status = "done".to_string();
+    update_task(&vault.conn, &task).unwrap();
+    let notes = handle.recent_notes(10).unwrap();
+    assert_eq!(notes[0].title, "Diary");
+    assert_eq!(notes[0].preview, "Short entry");
+    assert!(handle.upcoming_tasks(10).unwrap().is_empty());
+
+    // A biometric token opens the same cache
+    let token = ReadOnlyCredential::BiometricToken(biometric_token(&vault));
+    let by_token = open_vault_readonly(path, &token).unwrap();
+    assert_eq!(by_token.recent_notes(10).unwrap(), notes);
+}
+
+#[test]
+fn test_read_only_handle_refuses_mutations() {
+    let dir = tempdir().unwrap();
+    let path = dir.path().to_str().unwrap();
+    drop(create_vault(path, PASSWORD).unwrap());
+
+    let mut handle = open_vault_readonly(path, &passphrase()).unwrap();
+    assert!(matches!(handle.vault(), Err(VaultError::ReadOnly)));
+    let err = handle.vault_mut().err().unwrap();
+    assert!(matches!(err, VaultError::ReadOnly));
+    assert_eq!(CoreError::from(err).code, codes::VAULT_READ_ONLY);
+    assert!(matches!(handle.into_vault(), Err(VaultError::ReadOnly)));
+
+    assert!(matches!(
+        open_vault_readonly(path, &ReadOnlyCredential::Passphrase("wrong".into())),
+        Err(VaultError::Crypto(_))
+    ));
+    assert!(matches!(
+        open_vault_readonly(
+            path,
+            &ReadOnlyCredential::BiometricToken(hex::encode([7u8; 32]))
+        ),
+        Err(VaultError::Crypto(_))
+    ));
+}
+
+#[test]
+fn test_upgrade_unlocks_in_place() {
+    let dir = tempdir().unwrap();
+    let path = dir.path().to_str().unwrap();
+    let mut vault = create_vault(path, PASSWORD).unwrap();
+    let space_id = create_space(&mut vault.conn, "Personal").unwrap();
+    drop(vault);
+
+    let mut handle = open_vault_readonly(path, &passphrase()).unwrap();
+    assert!(handle.upgrade("wrong").is_err());
+    assert!(handle.is_read_only());
+    handle.upgrade(PASSWORD).unwrap();
+    assert!(!handle.is_read_only());
+
+    let vault = handle.vault_mut().unwrap();
+    create_note(&vault.conn, &space_id.to_string(), "After upgrade", "Body").unwrap();
+    let notes = handle.recent_notes(10).unwrap();
+    assert_eq!(notes.len(), 1);
+    assert_eq!(notes[0].title, "After upgrade");
+    let vault = handle.into_vault().unwrap();
+    drop(vault);
+
+    // A new DEK invalidates the cache until the next full unlock rebuilds it
+    rotate_dek(path, PASSWORD, |_| {}).unwrap();
+    assert!(matches!(
+        open_vault_readonly(path, &passphrase()),
+        Err(VaultError::Message(_))
+    ));
+    drop(unlock_vault(path, PASSWORD).unwrap());
+    let handle = open_vault_readonly(path, &passphrase()).unwrap();
+    assert_eq!(handle.recent_notes(10).unwrap().len(), 1);
+}
+
+#[test]
+fn test_locked_notes_stay_out_of_the_cache() {
+    let dir = tempdir().unwrap();
+    let path = dir.path().to_str().unwrap();
+    let mut vault = create_vault(path, PASSWORD).unwrap();
+    let space_id = create_space(&mut vault.conn, "Personal").unwrap();
+    let secret = create_note(
+        &vault.conn,
+        &space_id.to_string(),
+        "Secret",
+        "The combination is 1234",
+    )
+    .unwrap()
+    .id
+    .0
+    .to_string();
+    create_note(&vault.conn, &space_id.to_string(), "Shopping", "Milk").unwrap();
+
+    let handle = open_vault_readonly(path, &passphrase()).unwrap();
+    assert_eq!(handle.recent_notes(10).unwrap().len(), 2);
+
+    let dek = *vault.dek;
+    lock_note(&mut vault.conn, &dek, &secret, "hunter2").unwrap();
+    let titles = |handle: &core_rs::vault::VaultHandle| {
+        handle
+            .recent_notes(10)
+            .unwrap()
+            .into_iter()
+            .map(|n| n.title)
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(titles(&handle), vec!["Shopping"]);
+    drop(vault);
+
+    // Nor does a rebuild on unlock bring it back
+    drop(unlock_vault(path, PASSWORD).unwrap());
+    let handle = open_vault_readonly(path, &passphrase()).unwrap();
+    assert_eq!(titles(&handle), vec!["Shopping"]);
+}
+
+#[test]
+fn test_saves_through_the_pool_keep_the_cache_current() {
+    let dir = tempdir().unwrap();
+    let path = dir.path().to_str().unwrap();
+    let mut vault = create_vault(path, PASSWORD).unwrap();
+    let space_id = create_space(&mut vault.conn, "Personal").unwrap();
+    let dek = *vault.dek;
+    // Like the apps, keep only the pool once the vault is unlocked
+    drop(vault);
+    drop(unlock_vault(path, PASSWORD).unwrap());
+    let manager = EncryptedConnectionManager::new(
+        Path::new(path).join("vault.sqlite3"),
+        Arc::new(AutoLockGuard::new(dek)),
+    );
+    let pool = r2d2::Pool::builder().max_size(2).build(manager).unwrap();
+
+    let mut conn = pool.get().unwrap();
+    let note = create_note(&conn, &space_id.to_string(), "Secret", "Draft").unwrap();
+    update_note_content(&mut conn, note.id, "Secret", "The combination is 1234").unwrap();
+    drop(conn);
+
+    let handle = open_vault_readonly(path, &passphrase()).unwrap();
+    let notes = handle.recent_notes(10).unwrap();
+    assert_eq!(notes.len(), 1);
+    assert_eq!(notes[0].preview, "The combination is 1234");
+
+    let mut conn = pool.get().unwrap();
+    lock_note(&mut conn, &dek, &note.id.0.to_string(), "hunter2").unwrap();
+    assert!(handle.recent_notes(10).unwrap().is_empty());
+}
@@ -0,0 +1,187 @@
+use core_rs::crypto::{derive_key, wrap_dek};
+use core_rs::db::{get_vault_backup, store_vault_backup};
+use core_rs::note::create_note;
+use core_rs::space::create_space;
+use core_rs::vault::{
+    create_vault, rotate_dek, rotate_vault_password, unlock_vault, RekeyStage, VaultError,
+};
+use std::path::Path;
+use tempfile::tempdir;
+
+/// Create a vault holding one note; returns the note id
+fn vault_with_note(path: &str, password: &str) -> String {
+    let mut vault = create_vault(path, password).unwrap();
+    let space_id = create_space(&mut vault.conn, "Rotation").unwrap();
+    create_note(
+        &vault.conn,
+        &space_id.to_string(),
+        "Kept",
+        "Survives rotation",
+    )
+    .unwrap()
+    .id
+    .to_string()
+}
+
+fn note_title(conn: &rusqlite::Connection, note_id: &str) -> String {
+    conn.query_row("SELECT title FROM note WHERE id = ?1", [note_id], |row| {
+        row.get(0)
+    })
+    .unwrap()
+}
+
+fn header(dir: &Path) -> (Vec<u8>, Vec<u8>) {
+    let cfg: serde_json::Value =
+        serde_json::from_str(&std::fs::read_to_string(dir.join("config.json")).unwrap()).unwrap();
+    (
+        hex::decode(cfg["salt"].as_str().unwrap()).unwrap(),
+        hex::decode(cfg["wrapped_dek"].as_str().unwrap()).unwrap(),
+    )
+}
+
+#[test]
+fn test_password_rotation_rewraps_dek() {
+    let dir = tempdir().unwrap();
+    let path = dir.path().to_str().unwrap();
+    let note_id = vault_with_note(path, "old password");
+    let dek_before = unlock_vault(path, "old password").unwrap().dek;
+    let (old_salt, old_wrapped) = header(dir.path());
+
+    assert!(matches!(
+        rotate_vault_password(path, "not it", "new password"),
+        Err(VaultError::Crypto(_))
+    ));
+    assert_eq!(header(dir.path()), (old_salt.clone(), old_wrapped.clone()));
+
+    rotate_vault_password(path, "old password", "new password").unwrap();
+    let (new_salt, new_wrapped) = header(dir.path());
+    assert_ne!(new_salt, old_salt);
+    assert_ne!(new_wrapped, old_wrapped);
+
+    assert!(matches!(
+        unlock_vault(path, "old password"),
+        Err(VaultError::Crypto(_))
+    ));
+    let vault = unlock_vault(path, "new password").unwrap();
+    assert_eq!(vault.dek, dek_before, "only the wrapping changes");
+    assert_eq!(note_title(&vault.conn, &note_id), "Kept");
+
+    let backup = get_vault_backup(&vault.conn).unwrap().unwrap();
+    assert_eq!((backup.salt, backup.wrapped_dek), (new_salt, new_wrapped));
+}
+
+#[test]
+fn test_password_rotation_moves_pbkdf2_headers_to_argon2id() {
+    let dir = tempdir().unwrap();
+    let path = dir.path().to_str().unwrap();
+    let note_id = vault_with_note(path, "old password");
+    let dek = unlock_vault(path, "old password").unwrap().dek;
+
+    // Rewrite the header the way vaults created before Argon2id stored it
+    let config_path = dir.path().join("config.json");
+    let mut cfg: serde_json::Value =
+        serde_json::from_str(&std::fs::read_to_string(&config_path).unwrap()).unwrap();
+    let salt: [u8; 16] = rand::random();
+    let wrapped = wrap_dek(&dek, &derive_key("old password", &salt)).unwrap();
+    let fields = cfg.as_object_mut().unwrap();
+    fields.remove("version");
+    fields.remove("kdf");
+    fields.insert("salt".into(), hex::encode(salt).into());
+    fields.insert("wrapped_dek".into(), hex::encode(wrapped).into());
+    std::fs::write(&config_path, serde_json::to_string(&cfg).unwrap()).unwrap();
+    drop(unlock_vault(path, "old password").unwrap());
+
+    rotate_vault_password(path, "old password", "new password").unwrap();
+    let cfg: serde_json::Value =
+        serde_json::from_str(&std::fs::read_to_string(&config_path).unwrap()).unwrap();
+    assert_eq!(cfg["version"], 2);
+    assert_eq!(
+        cfg["kdf"],
+        serde_json::json!({ "algorithm": "argon2id", "m_cost": 19456, "t_cost": 2, "p_cost": 1 })
+    );
+
+    let vault = unlock_vault(path, "new password").unwrap();
+    assert_eq!(vault.dek, dek);
+    assert_eq!(note_title(&vault.conn, &note_id), "Kept");
+}
+
+#[test]
+fn test_crash_before_backup_cleanup_still_unlocks() {
+    let dir = tempdir().unwrap();
+    let path = dir.path().to_str().unwrap();
+    vault_with_note(path, "old password");
+    let (old_salt, old_wrapped) = header(dir.path());
+
+    rotate_vault_password(path, "old password", "new password").unwrap();
+    // Roll the backup back to the previous generation, as if the process died
+    // after the header rename but before the backup was updated, and leave a
+    // half-written temporary header behind
+    {
+        let vault = unlock_vault(path, "new password").unwrap();
+        store_vault_backup(&vault.conn, &old_salt, &old_wrapped).unwrap();
+    }
+    std::fs::write(dir.path().join("config.json.tmp"), b"{\"salt\": \"ab").unwrap();
+
+    let vault = unlock_vault(path, "new password").unwrap();
+    let backup = get_vault_backup(&vault.conn).unwrap().unwrap();
+    assert_eq!((backup.salt, backup.wrapped_dek), header(dir.path()));
+    assert!(!dir.path().join("config.json.tmp").exists());
+}
+
+#[test]
+fn test_dek_rotation_reencrypts_database() {
+    let dir = tempdir().unwrap();
+    let path = dir.path().to_str().unwrap();
+    let note_id = vault_with_note(path, "password");
+    let old_dek = unlock_vault(path, "password").unwrap().dek;
+
+    let mut stages = Vec::new();
+    rotate_dek(path, "password", |progress| {
+        assert_eq!(progress.total_steps, 5);
+        stages.push((progress.step, progress.stage));
+    })
+    .unwrap();
+    assert_eq!(
+        stages,
+        vec![
+            (1, RekeyStage::Unlocking),
+            (2, RekeyStage::Exporting),
+            (3, RekeyStage::Verifying),
+            (4, RekeyStage::Committing),
+            (5, RekeyStage::Done),
+        ]
+    );
+
+    let vault = unlock_vault(path, "password").unwrap();
+    assert_ne!(vault.dek, old_dek);
+    assert_eq!(note_title(&vault.conn, &note_id), "Kept");
+    drop(vault);
+
+    // The old DEK no longer opens the database file
+    let conn = rusqlite::Connection::open(dir.path().join("vault.sqlite3")).unwrap();
+    conn.execute_batch(&format!("PRAGMA key = \"x'{}'\";", hex::encode(old_dek)))
+        .unwrap();
+    assert!(conn
+        .query_row("SELECT COUNT(*) FROM note", [], |row| row.get::<_, i64>(0))
+        .is_err());
+}
+
+#[test]
+fn test_uncommitted_dek_rotation_is_discarded() {
+    let dir = tempdir().unwrap();
+    let path = dir.path().to_str().unwrap();
+    let note_id = vault_with_note(path, "password");
+
+    // A crash after staging but before the database swap
+    std::fs::write(dir.path().join("vault.sqlite3.rekey"), b"partial export").unwrap();
+    std::fs::copy(
+        dir.path().join("config.json"),
+        dir.path().join("config.json.rekey"),
+    )
+    .unwrap();
+
+    let vault = unlock_vault(path, "password").unwrap();
+    assert_eq!(note_title(&vault.conn, &note_id), "Kept");
+    assert!(!dir.path().join("vault.sqlite3.rekey").exists());
+    assert!(!dir.path().join("config.json.rekey").exists());
+}
@@ -1,10 +1,19 @@
 use chrono::{Duration, Utc};
 use core_rs::db::{migrate, DbError};
+use core_rs::note::{get_note, trash_note, update_note_content, Note};
 use core_rs::space::create_space;
 use core_rs::task::create_task;
-use core_rs::weekly_review::generate_weekly_review;
+use core_rs::weekly_review::*;
 use rusqlite::Connection;
 use tempfile::tempdir;
+use ulid::Ulid;
+
+/// Tuesday 2026-01-06 12:00 UTC
+const NOW: i64 = 1_767_700_800;
+/// Monday 2026-01-05 00:00 UTC
+const MONDAY: i64 = 1_767_571_200;
+const DAY: i64 = 86_400;
+const WEEK: i64 = 7 * DAY;
 
 fn setup_db() -> (tempfile::TempDir, Connection) {
     let dir = tempdir().unwrap();
@@ -45,3 +54,138 @@ fn test_generate_weekly_review() -> Result<(), DbError> {
 
     Ok(())
 }
+
+/// Add lines under the review's Action Items heading, as the user would
+fn add_action_items(conn: &mut Connection, note: &Note, items: &str) -> Note {
+    let content = format!("{}\n{}\n", note.content_md.trim_end(), items);
+    update_note_content(conn, note.id.clone(), &note.title, &content).unwrap();
+    get_note(conn, note.id.clone()).unwrap().unwrap()
+}
+
+#[test]
+fn test_open_action_items_are_carried_over() {
+    let (_dir, mut conn) = setup_db();
+    let space_id = create_space(&mut conn, "Review").unwrap();
+    let mut overdue = create_task(&conn, space_id, "Renew passport", None).unwrap();
+    overdue.due_at = Some(NOW - DAY);
+    core_rs::task::update_task(&conn, &overdue).unwrap();
+
+    assert_eq!(week_start(NOW), MONDAY);
+    let first = generate_weekly_review_at(&conn, space_id, NOW).unwrap();
+    assert!(first.content_md.contains("- [ ] Renew passport"));
+    // Task lists are rebuilt every week, so they aren't action items
+    assert!(open_action_items(&first.content_md).is_empty());
+
+    let first = add_action_items(
+        &mut conn,
+        &first,
+        "- [ ] Call the bank\n- [x] File taxes\n* [ ] Book dentist\n- [ ] Call the bank",
+    );
+    assert_eq!(
+        open_action_items(&first.content_md),
+        vec!["Call the bank", "Book dentist"]
+    );
+
+    let second = generate_weekly_review_at(&conn, space_id, NOW + WEEK).unwrap();
+    assert_ne!(second.id.0, first.id.0);
+    let carried = "- [ ] Call the bank\n- [ ] Book dentist\n";
+    assert!(second
+        .content_md
+        .contains(&format!("## ↪️ Carried Over from Last Week\n{}", carried)));
+    assert!(!second.content_md.contains("File taxes"));
+    assert_eq!(
+        open_action_items(&second.content_md),
+        vec!["Call the bank", "Book dentist"]
+    );
+
+    let reviews = get_weekly_reviews(&conn, space_id).unwrap();
+    assert_eq!(reviews.len(), 2);
+    assert_eq!(reviews[0].period_start, MONDAY + WEEK);
+    assert_eq!(reviews[0].stats.carried_over, 2);
+    assert_eq!(reviews[0].stats.overdue_tasks, 1);
+    assert_eq!(reviews[1].stats.carried_over, 0);
+}
+
+#[test]
+fn test_regenerating_a_week_rewrites_its_note() {
+    let (_dir, mut conn) = setup_db();
+    let space_id = create_space(&mut conn, "Review").unwrap();
+    let review_note = generate_weekly_review_at(&conn, space_id, NOW).unwrap();
+    add_action_items(&mut conn, &review_note, "- [ ] Plan the sprint");
+
+    let mut done = create_task(&conn, space_id, "Ship release", None).unwrap();
+    done.status = "done".to_string();
+    done.completed_at = Some(NOW + DAY);
+    core_rs::task::update_task(&conn, &done).unwrap();
+
+    let again = generate_weekly_review_at(&conn, space_id, NOW + 2 * DAY).unwrap();
+    assert_eq!(again.id.0, review_note.id.0);
+    assert!(again.content_md.contains("- [x] Ship release"));
+    // What the user wrote survives, once
+    assert_eq!(again.content_md.matches("- [ ] Plan the sprint").count(), 1);
+    assert_eq!(again.content_md.matches("## ✅ Completed Tasks").count(), 1);
+    let reviews = get_weekly_reviews(&conn, space_id).unwrap();
+    assert_eq!(reviews.len(), 1);
+    assert_eq!(reviews[0].stats.completed_tasks, 1);
+    assert_eq!(reviews[0].generated_at, NOW + 2 * DAY);
+    let notes: i64 = conn
+        .query_row(
+            "SELECT COUNT(*) FROM note WHERE title LIKE 'Weekly Review%'",
+            [],
+            |row| row.get(0),
+        )
+        .unwrap();
+    assert_eq!(notes, 1);
+
+    // A trashed review note is replaced, the week keeps one review
+    trash_note(&conn, review_note.id.clone()).unwrap();
+    let replaced = generate_weekly_review_at(&conn, space_id, NOW + 3 * DAY).unwrap();
+    assert_ne!(replaced.id.0, review_note.id.0);
+    let reviews = get_weekly_reviews(&conn, space_id).unwrap();
+    assert_eq!(reviews.len(), 1);
+    assert_eq!(reviews[0].note_id, replaced.id.0.to_string());
+}
+
+#[test]
+fn test_review_streak_over_a_quarter() {
+    let (_dir, mut conn) = setup_db();
+    let space_id = create_space(&mut conn, "Review").unwrap();
+    let review_id = |conn: &Connection, week: i64| {
+        let review = get_weekly_reviews(conn, space_id)
+            .unwrap()
+            .into_iter()
+            .find(|r| r.period_start == MONDAY + week * WEEK)
+            .unwrap();
+        Ulid::from_string(&review.id).unwrap()
+    };
+
+    // Thirteen weeks, the fifth one reviewed but never finished
+    for week in 0..13 {
+        let at = NOW + week * WEEK;
+        generate_weekly_review_at(&conn, space_id, at).unwrap();
+        if week != 4 {
+            mark_review_completed_at(&conn, review_id(&conn, week), at + DAY).unwrap();
+        }
+    }
+    let last_week = NOW + 12 * WEEK;
+    assert_eq!(get_review_streak_at(&conn, space_id, last_week).unwrap(), 8);
+
+    // The next week doesn't break the streak until it's over
+    let this_week = last_week + WEEK;
+    generate_weekly_review_at(&conn, space_id, this_week).unwrap();
+    assert_eq!(get_review_streak_at(&conn, space_id, this_week).unwrap(), 8);
+    let completed = mark_review_completed_at(&conn, review_id(&conn, 13), this_week).unwrap();
+    assert_eq!(get_review_streak_at(&conn, space_id, this_week).unwrap(), 9);
+    // Completing it again keeps the first completion
+    let again = mark_review_completed_at(&conn, review_id(&conn, 13), this_week + DAY).unwrap();
+    assert_eq!(again.completed_at, completed.completed_at);
+
+    assert_eq!(
+        get_review_streak_at(&conn, space_id, this_week + 2 * WEEK).unwrap(),
+        0
+    );
+    assert!(matches!(
+        mark_review_completed(&conn, Ulid::new()),
+        Err(WeeklyReviewError::NotFound(_))
+    ));
+}
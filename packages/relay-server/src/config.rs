@@ -0,0 +1,54 @@
+//! Relay limits from a JSON config file and environment variables.
+//!
+//! `RELAY_CONFIG` names an optional JSON file holding any fields of
+//! [`RelayConfig`]; the variables below override single fields on top of it.
+
+use core_rs::sync::RelayConfig;
+use std::str::FromStr;
+
+pub const CONFIG_FILE_VAR: &str = "RELAY_CONFIG";
+pub const MAX_ENVELOPE_BYTES_VAR: &str = "RELAY_MAX_ENVELOPE_BYTES";
+pub const MAX_PENDING_VAR: &str = "RELAY_MAX_PENDING_PER_RECIPIENT";
+pub const MAX_SUBMISSIONS_VAR: &str = "RELAY_MAX_SUBMISSIONS_PER_MINUTE";
+pub const MESSAGE_TTL_VAR: &str = "RELAY_MESSAGE_TTL_SECS";
+
+/// Load the config from the process environment
+pub fn from_env() -> Result<RelayConfig, String> {
+    load(|name| std::env::var(name).ok())
+}
+
+/// Load the config, looking variables up with `var`
+pub fn load(var: impl Fn(&str) -> Option<String>) -> Result<RelayConfig, String> {
+    let mut config = match var(CONFIG_FILE_VAR) {
+        Some(path) => {
+            let json = std::fs::read_to_string(&path)
+                .map_err(|e| format!("Failed to read {}: {}", path, e))?;
+            serde_json::from_str(&json).map_err(|e| format!("Invalid config {}: {}", path, e))?
+        }
+        None => RelayConfig::default(),
+    };
+
+    override_with(&var, MAX_ENVELOPE_BYTES_VAR, &mut config.max_envelope_bytes)?;
+    override_with(&var, MAX_PENDING_VAR, &mut config.max_pending_per_recipient)?;
+    override_with(
+        &var,
+        MAX_SUBMISSIONS_VAR,
+        &mut config.max_submissions_per_minute,
+    )?;
+    override_with(&var, MESSAGE_TTL_VAR, &mut config.message_ttl_secs)?;
+    Ok(config)
+}
+
+fn override_with<T: FromStr>(
+    var: &impl Fn(&str) -> Option<String>,
+    name: &str,
+    field: &mut T,
+) -> Result<(), String> {
+    if let Some(value) = var(name) {
+        *field = value
+            .trim()
+            .parse()
+            .map_err(|_| format!("{} must be a non-negative integer, got {:?}", name, value))?;
+    }
+    Ok(())
+}
@@ -1,26 +1,90 @@
+pub mod config;
+
 use axum::{
-    extract::{Query, State},
+    extract::{DefaultBodyLimit, Query, State},
     http::{HeaderMap, StatusCode},
-    response::{IntoResponse, Json},
+    response::{IntoResponse, Json, Response},
     routing::{get, post},
     Router,
 };
-use core_rs::sync::relay::{BlindRelayServer, RelayEnvelope};
+use core_rs::sync::relay::{BlindRelayServer, RelayEnvelope, RelayError};
 use serde::Deserialize;
 use std::sync::Arc;
+use std::time::Duration;
+use tracing::info;
+
+/// Room in a request body for the JSON around the envelope's bytes
+const BODY_OVERHEAD_BYTES: usize = 64 * 1024;
 
+/// Relay with the default limits
 pub fn app() -> Router {
-    let state = Arc::new(BlindRelayServer::new());
+    router(Arc::new(BlindRelayServer::new()))
+}
+
+pub fn router(state: Arc<BlindRelayServer>) -> Router {
+    // Bytes travel as JSON arrays of up to four characters per byte; bodies
+    // beyond that can't hold an acceptable envelope and are refused unread
+    let body_limit = state
+        .config()
+        .max_envelope_bytes
+        .saturating_mul(4)
+        .saturating_add(BODY_OVERHEAD_BYTES);
 
     Router::new()
-        .route("/register", post(register))
+        .route("/register", post(register).delete(deregister))
+        .route("/rotate_key", post(rotate_key))
         .route("/send", post(send_message))
         .route("/fetch", get(fetch_messages))
         .route("/pending", get(check_pending))
         .route("/stats", get(get_stats))
+        .layer(DefaultBodyLimit::max(body_limit))
         .with_state(state)
 }
 
+/// Drop expired messages every `interval` for as long as the relay runs
+pub fn spawn_expiry(
+    state: Arc<BlindRelayServer>,
+    interval: Duration,
+) -> tokio::task::JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(interval);
+        loop {
+            ticker.tick().await;
+            let expired = state.cleanup_expired();
+            if expired > 0 {
+                info!("Expired {} undelivered messages", expired);
+            }
+        }
+    })
+}
+
+fn error_status(error: &RelayError) -> StatusCode {
+    match error {
+        RelayError::MessageTooLarge => StatusCode::PAYLOAD_TOO_LARGE,
+        RelayError::TooManyPending | RelayError::RateLimited => StatusCode::TOO_MANY_REQUESTS,
+        RelayError::Unauthorized => StatusCode::UNAUTHORIZED,
+        RelayError::RecipientDeregistered => StatusCode::GONE,
+        _ => StatusCode::BAD_REQUEST,
+    }
+}
+
+fn error_response(error: RelayError) -> Response {
+    (
+        error_status(&error),
+        Json(serde_json::json!({ "error": error.to_string() })),
+    )
+        .into_response()
+}
+
+/// The bearer token of a request, if it has one
+fn bearer_token(headers: &HeaderMap) -> Option<&str> {
+    headers
+        .get("Authorization")?
+        .to_str()
+        .ok()?
+        .strip_prefix("Bearer ")
+}
+
 #[derive(Deserialize)]
 struct RegisterPayload {
     device_id: String,
@@ -40,6 +104,35 @@ async fn register(
     }
 }
 
+#[derive(Deserialize)]
+struct DeregisterPayload {
+    device_id: String,
+}
+
+async fn deregister(
+    State(state): State<Arc<BlindRelayServer>>,
+    headers: HeaderMap,
+    Json(payload): Json<DeregisterPayload>,
+) -> Response {
+    let token = bearer_token(&headers).unwrap_or_default();
+    match state.deregister_device(&payload.device_id, token) {
+        Ok(purged) => Json(serde_json::json!({ "purged": purged })).into_response(),
+        Err(e) => error_response(e),
+    }
+}
+
+async fn rotate_key(
+    State(state): State<Arc<BlindRelayServer>>,
+    headers: HeaderMap,
+    Json(payload): Json<RegisterPayload>,
+) -> Response {
+    let token = bearer_token(&headers).unwrap_or_default();
+    match state.rotate_key(&payload.device_id, token, &payload.public_key_hash) {
+        Ok(token) => Json(serde_json::json!({ "token": token })).into_response(),
+        Err(e) => error_response(e),
+    }
+}
+
 async fn send_message(
     State(state): State<Arc<BlindRelayServer>>,
     headers: HeaderMap,
@@ -54,7 +147,7 @@ async fn send_message(
     match state.submit_message(envelope) {
         Ok(id) => (StatusCode::OK, Json(serde_json::json!({ "id": id }))),
         Err(e) => (
-            StatusCode::BAD_REQUEST,
+            error_status(&e),
             Json(serde_json::json!({ "error": e.to_string() })),
         ),
     }
@@ -66,13 +159,19 @@ struct FetchQuery {
     limit: Option<usize>,
 }
 
+/// Delivers only to the device itself, authenticated by its bearer token
 async fn fetch_messages(
     State(state): State<Arc<BlindRelayServer>>,
+    headers: HeaderMap,
     Query(query): Query<FetchQuery>,
-) -> impl IntoResponse {
+) -> Response {
+    let token = bearer_token(&headers).unwrap_or_default();
+    if let Err(e) = state.authenticate(&query.device_id, token) {
+        return error_response(e);
+    }
     let limit = query.limit.unwrap_or(10);
     let messages = state.fetch_messages(&query.device_id, limit);
-    Json(messages)
+    Json(messages).into_response()
 }
 
 #[derive(Deserialize)]
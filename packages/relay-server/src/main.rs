@@ -1,12 +1,23 @@
-use relay_server::app;
+use core_rs::sync::relay::BlindRelayServer;
+use relay_server::{config, router, spawn_expiry};
+use std::sync::Arc;
+use std::time::Duration;
 use tracing::info;
 
+/// How often undelivered messages are checked against the TTL
+const EXPIRY_INTERVAL: Duration = Duration::from_secs(60);
+
 #[tokio::main]
 async fn main() {
     tracing_subscriber::fmt::init();
 
+    let config = config::from_env().unwrap_or_else(|e| panic!("Invalid relay config: {}", e));
+    info!("Relay limits: {:?}", config);
+    let state = Arc::new(BlindRelayServer::with_config(config));
+    spawn_expiry(state.clone(), EXPIRY_INTERVAL);
+
     let addr = "0.0.0.0:3000";
     info!("Starting Relay Server on {}", addr);
     let listener = tokio::net::TcpListener::bind(addr).await.unwrap();
-    axum::serve(listener, app()).await.unwrap();
+    axum::serve(listener, router(state)).await.unwrap();
 }
@@ -56,3 +56,295 @@ async fn test_pending_count() {
     let body: Value = serde_json::from_slice(&body).unwrap();
     assert_eq!(body["count"], 0);
 }
+
+// --- Limits ---
+
+use core_rs::sync::relay::{BlindRelayServer, RelayConfig, RelayEnvelope};
+use relay_server::{config, router};
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+fn limited(config: RelayConfig) -> Arc<BlindRelayServer> {
+    Arc::new(BlindRelayServer::with_config(config))
+}
+
+async fn send(state: &Arc<BlindRelayServer>, from: &str, to: &str, bytes: usize) -> StatusCode {
+    let envelope = RelayEnvelope::new(from, to, vec![7; bytes], vec![], vec![], "sync_delta");
+    router(state.clone())
+        .oneshot(
+            Request::builder()
+                .method("POST")
+                .uri("/send")
+                .header("Content-Type", "application/json")
+                .body(Body::from(serde_json::to_vec(&envelope).unwrap()))
+                .unwrap(),
+        )
+        .await
+        .unwrap()
+        .status()
+}
+
+async fn stats(state: &Arc<BlindRelayServer>) -> Value {
+    let response = router(state.clone())
+        .oneshot(
+            Request::builder()
+                .uri("/stats")
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    let body = response.into_body().collect().await.unwrap().to_bytes();
+    serde_json::from_slice(&body).unwrap()
+}
+
+#[tokio::test]
+async fn test_oversized_envelope_is_rejected() {
+    let state = limited(RelayConfig {
+        max_envelope_bytes: 1024,
+        ..RelayConfig::default()
+    });
+
+    assert_eq!(send(&state, "a", "b", 1024).await, StatusCode::OK);
+    assert_eq!(
+        send(&state, "a", "b", 1025).await,
+        StatusCode::PAYLOAD_TOO_LARGE
+    );
+    // Far past the limit the body itself is refused
+    assert_eq!(
+        send(&state, "a", "b", 64 * 1024).await,
+        StatusCode::PAYLOAD_TOO_LARGE
+    );
+
+    let stats = stats(&state).await;
+    assert_eq!(stats["devices"]["a"]["submitted"], 1);
+    assert_eq!(stats["devices"]["a"]["rejected"], 1);
+    assert_eq!(stats["devices"]["b"]["pending"], 1);
+}
+
+#[tokio::test]
+async fn test_queue_depth_cap_only_affects_full_recipient() {
+    let state = limited(RelayConfig {
+        max_pending_per_recipient: 2,
+        ..RelayConfig::default()
+    });
+
+    assert_eq!(send(&state, "a", "full", 8).await, StatusCode::OK);
+    assert_eq!(send(&state, "b", "full", 8).await, StatusCode::OK);
+    assert_eq!(
+        send(&state, "c", "full", 8).await,
+        StatusCode::TOO_MANY_REQUESTS
+    );
+    assert_eq!(send(&state, "c", "other", 8).await, StatusCode::OK);
+    assert_eq!(state.pending_count("full"), 2);
+    assert_eq!(state.pending_count("other"), 1);
+
+    // Fetching makes room again
+    state.fetch_messages("full", 1);
+    assert_eq!(send(&state, "c", "full", 8).await, StatusCode::OK);
+}
+
+#[tokio::test]
+async fn test_submission_rate_is_limited_per_sender() {
+    let state = limited(RelayConfig {
+        max_submissions_per_minute: 1,
+        ..RelayConfig::default()
+    });
+
+    assert_eq!(send(&state, "a", "b", 8).await, StatusCode::OK);
+    assert_eq!(
+        send(&state, "a", "b", 8).await,
+        StatusCode::TOO_MANY_REQUESTS
+    );
+    assert_eq!(send(&state, "c", "b", 8).await, StatusCode::OK);
+}
+
+#[tokio::test]
+async fn test_ttl_expiry_removes_old_messages() {
+    let state = limited(RelayConfig {
+        message_ttl_secs: 300,
+        ..RelayConfig::default()
+    });
+    assert_eq!(send(&state, "a", "b", 8).await, StatusCode::OK);
+    assert_eq!(send(&state, "a", "b", 8).await, StatusCode::OK);
+
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_secs();
+    assert_eq!(state.cleanup_expired_at(now + 301), 2);
+    assert_eq!(state.pending_count("b"), 0);
+
+    let stats = stats(&state).await;
+    assert_eq!(stats["expired_messages"], 2);
+    assert_eq!(stats["devices"]["b"]["expired"], 2);
+    assert_eq!(stats["total_pending_messages"], 0);
+}
+
+#[test]
+fn test_config_from_file_and_env() {
+    let dir = std::env::temp_dir().join(format!("relay-config-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("relay.json");
+    std::fs::write(
+        &path,
+        r#"{"max_envelope_bytes": 2048, "message_ttl_secs": 600}"#,
+    )
+    .unwrap();
+
+    let vars = |name: &str| match name {
+        config::CONFIG_FILE_VAR => Some(path.to_str().unwrap().to_string()),
+        config::MESSAGE_TTL_VAR => Some("120".to_string()),
+        _ => None,
+    };
+    let loaded = config::load(vars).unwrap();
+    assert_eq!(loaded.max_envelope_bytes, 2048);
+    assert_eq!(loaded.message_ttl_secs, 120);
+    assert_eq!(
+        loaded.max_pending_per_recipient,
+        RelayConfig::default().max_pending_per_recipient
+    );
+
+    let bad = |name: &str| (name == config::MAX_PENDING_VAR).then(|| "lots".to_string());
+    assert!(config::load(bad).is_err());
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+// --- Registration lifecycle ---
+
+async fn call(
+    state: &Arc<BlindRelayServer>,
+    method: &str,
+    uri: &str,
+    token: Option<&str>,
+    body: Option<Value>,
+) -> (StatusCode, Value) {
+    let mut request = Request::builder().method(method).uri(uri);
+    if let Some(token) = token {
+        request = request.header("Authorization", format!("Bearer {}", token));
+    }
+    let body = match body {
+        Some(body) => {
+            request = request.header("Content-Type", "application/json");
+            Body::from(body.to_string())
+        }
+        None => Body::empty(),
+    };
+    let response = router(state.clone())
+        .oneshot(request.body(body).unwrap())
+        .await
+        .unwrap();
+    let status = response.status();
+    let body = response.into_body().collect().await.unwrap().to_bytes();
+    (status, serde_json::from_slice(&body).unwrap_or(Value::Null))
+}
+
+async fn register(state: &Arc<BlindRelayServer>, device_id: &str, hash: &str) -> String {
+    let (status, body) = call(
+        state,
+        "POST",
+        "/register",
+        None,
+        Some(json!({ "device_id": device_id, "public_key_hash": hash })),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    body["token"].as_str().unwrap().to_string()
+}
+
+async fn deregister(state: &Arc<BlindRelayServer>, token: &str) -> (StatusCode, Value) {
+    call(
+        state,
+        "DELETE",
+        "/register",
+        Some(token),
+        Some(json!({ "device_id": "lost_phone" })),
+    )
+    .await
+}
+
+#[tokio::test]
+async fn test_deregistered_device_is_cut_off() {
+    let state = limited(RelayConfig::default());
+    let token = register(&state, "lost_phone", "hash_a").await;
+    assert_eq!(
+        send(&state, "laptop", "lost_phone", 8).await,
+        StatusCode::OK
+    );
+
+    assert_eq!(
+        deregister(&state, "stolen").await.0,
+        StatusCode::UNAUTHORIZED
+    );
+    let (status, body) = deregister(&state, &token).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["purged"], 1);
+
+    let (status, _) = call(
+        &state,
+        "GET",
+        "/fetch?device_id=lost_phone",
+        Some(&token),
+        None,
+    )
+    .await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+    assert_eq!(
+        send(&state, "laptop", "lost_phone", 8).await,
+        StatusCode::GONE
+    );
+    assert_eq!(state.pending_count("lost_phone"), 0);
+
+    let stats = stats(&state).await;
+    assert_eq!(stats["registered_devices"], 0);
+    assert_eq!(stats["deregistered_devices"], 1);
+}
+
+#[tokio::test]
+async fn test_key_rotation_keeps_pending_queue() {
+    let state = limited(RelayConfig::default());
+    let old_token = register(&state, "phone", "hash_a").await;
+    assert_eq!(send(&state, "laptop", "phone", 8).await, StatusCode::OK);
+    assert_eq!(send(&state, "laptop", "phone", 8).await, StatusCode::OK);
+
+    let rotate = json!({ "device_id": "phone", "public_key_hash": "hash_b" });
+    let (status, _) = call(&state, "POST", "/rotate_key", None, Some(rotate.clone())).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+    let (status, body) = call(
+        &state,
+        "POST",
+        "/rotate_key",
+        Some(&old_token),
+        Some(rotate),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    let new_token = body["token"].as_str().unwrap().to_string();
+    assert_ne!(new_token, old_token);
+    assert_eq!(state.public_key_hash("phone").as_deref(), Some("hash_b"));
+
+    let (status, _) = call(
+        &state,
+        "GET",
+        "/fetch?device_id=phone",
+        Some(&old_token),
+        None,
+    )
+    .await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+    let (status, messages) = call(
+        &state,
+        "GET",
+        "/fetch?device_id=phone",
+        Some(&new_token),
+        None,
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(messages.as_array().unwrap().len(), 2);
+
+    let stats = stats(&state).await;
+    assert_eq!(stats["registered_devices"], 1);
+    assert_eq!(stats["devices"]["phone"]["registered"], true);
+    assert_eq!(stats["devices"]["phone"]["key_rotations"], 1);
+}
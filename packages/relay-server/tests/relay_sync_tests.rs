@@ -0,0 +1,316 @@
+use chrono::Utc;
+use core_rs::db::migrate;
+use core_rs::note::create_note;
+use core_rs::sync::mobile_sync::{DeviceInfo, DeviceType};
+use core_rs::sync::p2p::P2pSync;
+use core_rs::sync::relay::{BlindRelayServer, RelayClient, RelayConfig, RelayEnvelope};
+use core_rs::sync::relay_sync::*;
+use core_rs::sync::secure_channel::{load_or_create_static_key, StaticKeypair};
+use core_rs::sync_agent::{init_sync_tables, SyncAgent};
+use relay_server::router;
+use rusqlite::Connection;
+use std::sync::Arc;
+use tokio::net::TcpListener;
+use ulid::Ulid;
+
+const LAPTOP: &str = "device-laptop-41b9e2";
+const PHONE: &str = "device-phone-a07c53";
+const DEK: [u8; 32] = [0u8; 32];
+/// Small enough that a long note takes several envelopes
+const MAX_ENVELOPE_BYTES: usize = 4096;
+
+struct Node {
+    conn: Connection,
+    agent: SyncAgent,
+    key: StaticKeypair,
+    client: RelayClient,
+}
+
+/// A relay on a local port, as a phone on mobile data would reach it
+async fn start_relay() -> String {
+    let state = Arc::new(BlindRelayServer::with_config(RelayConfig {
+        max_envelope_bytes: MAX_ENVELOPE_BYTES,
+        ..Default::default()
+    }));
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+    tokio::spawn(async move { axum::serve(listener, router(state)).await });
+    url
+}
+
+fn setup_db(space_id: Ulid, peer: &str) -> Connection {
+    let mut conn = Connection::open_in_memory().unwrap();
+    migrate(&mut conn).unwrap();
+    init_sync_tables(&conn).unwrap();
+    conn.execute(
+        "INSERT INTO space (id, name) VALUES (?1, ?2)",
+        (space_id.to_string(), "Shared"),
+    )
+    .unwrap();
+    let peer = SyncAgent::new(peer.to_string(), peer.to_string(), 0);
+    peer.register_device(&conn, &peer.get_device_info())
+        .unwrap();
+    conn
+}
+
+fn device_info(device_id: &str) -> DeviceInfo {
+    DeviceInfo {
+        device_id: device_id.to_string(),
+        device_name: device_id.to_string(),
+        device_type: DeviceType::Desktop,
+        ip_address: "127.0.0.1".parse().unwrap(),
+        sync_port: 0,
+        public_key: vec![],
+        os_version: "test".to_string(),
+        last_seen: Utc::now(),
+        is_active: true,
+    }
+}
+
+/// A laptop and a phone that paired once on the same network and are now
+/// registered with the same relay
+async fn paired_nodes(space_id: Ulid, relay_url: &str) -> (Node, Node) {
+    let laptop_conn = setup_db(space_id, PHONE);
+    let phone_conn = setup_db(space_id, LAPTOP);
+    let laptop = P2pSync::with_static_key(
+        device_info(LAPTOP),
+        load_or_create_static_key(&laptop_conn).unwrap(),
+    )
+    .unwrap();
+    let phone = P2pSync::with_static_key(
+        device_info(PHONE),
+        load_or_create_static_key(&phone_conn).unwrap(),
+    )
+    .unwrap();
+    let hello = phone.initiate_pairing(LAPTOP).await.unwrap();
+    let reply = laptop.exchange_keys(&hello).unwrap();
+    let reveal = phone.exchange_keys(&reply).unwrap();
+    laptop.exchange_keys(&reveal).unwrap();
+    laptop.confirm_pairing(&laptop_conn, PHONE, true).unwrap();
+    phone.confirm_pairing(&phone_conn, LAPTOP, true).unwrap();
+
+    let mut nodes = Vec::new();
+    for (device_id, conn) in [(LAPTOP, laptop_conn), (PHONE, phone_conn)] {
+        let key = load_or_create_static_key(&conn).unwrap();
+        let mut client = RelayClient::new(device_id, relay_url);
+        client.register(&relay_key_hash(&key)).await.unwrap();
+        save_relay_registration(&conn, relay_url, &client).unwrap();
+        nodes.push(Node {
+            agent: SyncAgent::new(device_id.to_string(), device_id.to_string(), 0),
+            client: get_relay_client(&conn, device_id).unwrap().unwrap(),
+            conn,
+            key,
+        });
+    }
+    let phone = nodes.pop().unwrap();
+    (nodes.pop().unwrap(), phone)
+}
+
+fn relay_history(conn: &Connection, direction: &str) -> Vec<(String, u32)> {
+    let mut stmt = conn
+        .prepare(
+            "SELECT device_id, entities_pushed + entities_pulled FROM sync_history
+             WHERE direction = ?1 AND transport = 'relay' AND success = 1",
+        )
+        .unwrap();
+    stmt.query_map([direction], |row| Ok((row.get(0)?, row.get(1)?)))
+        .unwrap()
+        .collect::<Result<_, _>>()
+        .unwrap()
+}
+
+#[tokio::test]
+async fn test_sync_through_relay_between_networks() {
+    let space_id = Ulid::new();
+    let relay_url = start_relay().await;
+    let (mut laptop, mut phone) = paired_nodes(space_id, &relay_url).await;
+
+    let body = "Written on the train, far from home. ".repeat(200);
+    let note = create_note(&laptop.conn, &space_id.to_string(), "Travel log", &body).unwrap();
+
+    let outbox = prepare_relay_session(
+        &laptop.conn,
+        &laptop.agent,
+        &laptop.key,
+        PHONE,
+        MAX_ENVELOPE_BYTES,
+    )
+    .unwrap();
+    assert!(outbox.envelopes.len() > 1);
+    send_relay_session(&laptop.client, &outbox).await.unwrap();
+    record_relay_push(&laptop.conn, &outbox, None).unwrap();
+    assert_eq!(
+        relay_history(&laptop.conn, "push"),
+        vec![(PHONE.to_string(), outbox.delta_count())]
+    );
+
+    let envelopes = fetch_relay_envelopes(&phone.client).await.unwrap();
+    assert_eq!(envelopes.len(), outbox.envelopes.len());
+    let report =
+        apply_relay_envelopes(&mut phone.conn, &phone.agent, &phone.key, &DEK, envelopes).unwrap();
+    assert_eq!(report.sessions, 1);
+    assert_eq!(report.entities_pulled, outbox.delta_count());
+    assert_eq!(
+        (report.conflicts, report.rejected, report.failed),
+        (0, 0, 0)
+    );
+    assert_eq!(report.acks.len(), 1);
+    send_relay_acks(&phone.client, &report).await.unwrap();
+
+    // Nothing new to send once the phone acknowledged the session
+    let envelopes = fetch_relay_envelopes(&laptop.client).await.unwrap();
+    let report = apply_relay_envelopes(
+        &mut laptop.conn,
+        &laptop.agent,
+        &laptop.key,
+        &DEK,
+        envelopes,
+    )
+    .unwrap();
+    assert_eq!(report.acknowledged, 1);
+    let again = prepare_relay_session(
+        &laptop.conn,
+        &laptop.agent,
+        &laptop.key,
+        PHONE,
+        MAX_ENVELOPE_BYTES,
+    )
+    .unwrap();
+    assert!(again.is_empty());
+
+    let synced: String = phone
+        .conn
+        .query_row(
+            "SELECT content_md FROM note WHERE id = ?1",
+            [note.id.0.to_string()],
+            |row| row.get(0),
+        )
+        .unwrap();
+    assert_eq!(synced, note.content_md);
+    assert_eq!(
+        relay_history(&phone.conn, "pull"),
+        vec![(LAPTOP.to_string(), outbox.delta_count())]
+    );
+    let leftover: i64 = phone
+        .conn
+        .query_row("SELECT COUNT(*) FROM sync_relay_chunk", [], |row| {
+            row.get(0)
+        })
+        .unwrap();
+    assert_eq!(leftover, 0);
+}
+
+#[tokio::test]
+async fn test_relay_sync_rejects_unpaired_senders() {
+    let space_id = Ulid::new();
+    let relay_url = start_relay().await;
+    let (laptop, mut phone) = paired_nodes(space_id, &relay_url).await;
+
+    // A stranger that learned the phone's key still isn't a trusted sender
+    let stranger = StaticKeypair::generate().unwrap();
+    let phone_key = phone.key.public_key().to_vec();
+    let forged = RelayEnvelope::seal(
+        &stranger,
+        LAPTOP,
+        PHONE,
+        &phone_key,
+        RELAY_SYNC_MESSAGE,
+        b"{}",
+    )
+    .unwrap();
+    laptop.client.send(forged).await.unwrap();
+
+    let envelopes = fetch_relay_envelopes(&phone.client).await.unwrap();
+    let report =
+        apply_relay_envelopes(&mut phone.conn, &phone.agent, &phone.key, &DEK, envelopes).unwrap();
+    assert_eq!(report.rejected, 1);
+    assert_eq!(report.sessions, 0);
+}
+
+#[tokio::test]
+async fn test_unacknowledged_session_is_sent_again() {
+    let space_id = Ulid::new();
+    let relay_url = start_relay().await;
+    let (laptop, mut phone) = paired_nodes(space_id, &relay_url).await;
+    create_note(&laptop.conn, &space_id.to_string(), "Lost", "On the way").unwrap();
+
+    let outbox = prepare_relay_session(
+        &laptop.conn,
+        &laptop.agent,
+        &laptop.key,
+        PHONE,
+        MAX_ENVELOPE_BYTES,
+    )
+    .unwrap();
+    assert!(!outbox.is_empty());
+    send_relay_session(&laptop.client, &outbox).await.unwrap();
+    record_relay_push(&laptop.conn, &outbox, None).unwrap();
+    // The relay lost the session before the phone fetched it
+    fetch_relay_envelopes(&phone.client).await.unwrap();
+
+    // Without an acknowledgement the next push carries the change again
+    let again = prepare_relay_session(
+        &laptop.conn,
+        &laptop.agent,
+        &laptop.key,
+        PHONE,
+        MAX_ENVELOPE_BYTES,
+    )
+    .unwrap();
+    assert_eq!(again.delta_count(), outbox.delta_count());
+    send_relay_session(&laptop.client, &again).await.unwrap();
+    let envelopes = fetch_relay_envelopes(&phone.client).await.unwrap();
+    let report =
+        apply_relay_envelopes(&mut phone.conn, &phone.agent, &phone.key, &DEK, envelopes).unwrap();
+    assert_eq!(report.sessions, 1);
+}
+
+#[tokio::test]
+async fn test_failed_session_does_not_stop_the_others() {
+    let space_id = Ulid::new();
+    let relay_url = start_relay().await;
+    let (laptop, mut phone) = paired_nodes(space_id, &relay_url).await;
+
+    // A complete session whose payload doesn't decode, sorted first
+    let phone_key = phone.key.public_key().to_vec();
+    let broken = RelayEnvelope::seal(
+        &laptop.key,
+        LAPTOP,
+        PHONE,
+        &phone_key,
+        RELAY_SYNC_MESSAGE,
+        br#"{"session_id":"00000000000000000000000000","index":0,"count":1,"data":"!!"}"#,
+    )
+    .unwrap();
+    laptop.client.send(broken).await.unwrap();
+    create_note(
+        &laptop.conn,
+        &space_id.to_string(),
+        "Intact",
+        "Still arrives",
+    )
+    .unwrap();
+    let outbox = prepare_relay_session(
+        &laptop.conn,
+        &laptop.agent,
+        &laptop.key,
+        PHONE,
+        MAX_ENVELOPE_BYTES,
+    )
+    .unwrap();
+    send_relay_session(&laptop.client, &outbox).await.unwrap();
+
+    let envelopes = fetch_relay_envelopes(&phone.client).await.unwrap();
+    let report =
+        apply_relay_envelopes(&mut phone.conn, &phone.agent, &phone.key, &DEK, envelopes).unwrap();
+    assert_eq!(report.failed, 1);
+    assert_eq!(report.sessions, 1);
+    assert_eq!(report.acks.len(), 1);
+    let leftover: i64 = phone
+        .conn
+        .query_row("SELECT COUNT(*) FROM sync_relay_chunk", [], |row| {
+            row.get(0)
+        })
+        .unwrap();
+    assert_eq!(leftover, 0);
+}
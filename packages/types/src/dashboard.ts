@@ -1,23 +1,66 @@
+import type { HealthGoal, TripSummary } from './index';
+
+/** A metric over the current period and the one before it */
+export interface PeriodTrend {
+  current: number;
+  previous: number;
+  /** Change in percent; null when the previous period was zero */
+  delta_percent: number | null;
+}
+
+/** Sections of modes that aren't enabled in the space are null */
 export interface DashboardStats {
+  window_days: number;
+  notes: {
+    created: PeriodTrend;
+  };
+  tasks: {
+    pending_count: number;
+    completed_count: number;
+    completed: PeriodTrend;
+  };
+  time: {
+    tracked_seconds: PeriodTrend;
+  };
+  /** Consecutive weeks with a completed weekly review */
+  weekly_review_streak_weeks: number;
   health: {
     metrics_count: number;
     latest_metric: string | null;
-  };
+    /** Goals as of their last evaluation */
+    goals: HealthGoal[];
+  } | null;
+  habits: {
+    habits_count: number;
+    completed: PeriodTrend;
+  } | null;
+  srs: {
+    cards_due: PeriodTrend;
+    /** Consecutive days with a completed review session */
+    review_streak_days: number;
+  } | null;
   music: {
     track_count: number;
     playlist_count: number;
-  };
+  } | null;
   social: {
     posts_count: number;
     platforms_count: number;
-  };
-  tasks: {
-    pending_count: number;
-    completed_count: number;
-  };
+    new_posts: PeriodTrend;
+  } | null;
+  travel: {
+    /** Trips that haven't ended yet, soonest first */
+    upcoming_trips: TripSummary[];
+  } | null;
   quote: Quote | null;
 }
 
+export type ComparisonWindow = 'week' | 'month';
+
+export interface DashboardConfig {
+  comparison_window: ComparisonWindow;
+}
+
 export interface Quote {
   text: string;
   author: string;
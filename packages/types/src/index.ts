@@ -7,6 +7,62 @@ export interface Space {
   name: string;
   icon?: string;
   enabled_modes_json: string; // JSON array of mode IDs
+  /** Set while the space is archived */
+  archived_at?: number | null;
+}
+
+/** Rows removed from one table when a space is deleted */
+export interface DeletedRows {
+  table: string;
+  count: number;
+}
+
+export interface SpaceDeletionSummary {
+  space_id: ULID;
+  name: string;
+  /** Tables that had rows, in deletion order */
+  deleted: DeletedRows[];
+  total_rows: number;
+}
+
+/** What a space template provisions; starter notes only when exported with them */
+export interface SpaceTemplate {
+  format_version: number;
+  name: string;
+  description?: string | null;
+  modes: { id: string; name: string }[];
+  /** Hierarchical tags keep their full "parent/child" name */
+  tags: { name: string; color?: string | null }[];
+  saved_searches: { name: string; query: string; scope: string }[];
+  form_templates: { name: string; fields: FormField[] }[];
+  note_templates: { key: string; name: string; content: string; variables: TemplateVariable[] }[];
+  /** Key of the note template new daily notes use */
+  daily_note_template?: string | null;
+  starter_notes: { key: string; title: string; content_md: string; tags: string[] }[];
+}
+
+/** Rows a mode owns in one table */
+export interface ModeDataCount {
+  table: string;
+  /** e.g. "metrics", for "Health mode has 1,204 metrics" */
+  label: string;
+  count: number;
+}
+
+/** An enabled mode and the data it owns in the space */
+export interface ModeStatus {
+  id: string;
+  name: string;
+  data: ModeDataCount[];
+  total_rows: number;
+}
+
+/** What purging a mode would delete; pass it back unchanged to purge */
+export interface PurgePreview {
+  mode_id: string;
+  space_id: ULID;
+  data: ModeDataCount[];
+  total_rows: number;
 }
 
 export interface Note {
@@ -17,6 +73,171 @@ export interface Note {
   created_at: number; // Unix timestamp
   modified_at: number; // Unix timestamp
   is_trashed: boolean;
+  /** Locked with a passphrase; `content_md` is empty until unlocked */
+  is_locked: boolean;
+}
+
+/** Counts kept on the note row, leaving out front matter and fenced code */
+export interface NoteStats {
+  note_id: ULID;
+  word_count: number;
+  char_count: number;
+  reading_minutes: number;
+}
+
+export interface DuplicateNote {
+  id: ULID;
+  title: string;
+  created_at: number;
+  modified_at: number;
+}
+
+/** Notes similar enough to be merged, oldest first */
+export interface DuplicateCluster {
+  notes: DuplicateNote[];
+  /** Lowest similarity between the notes, 0-1 */
+  similarity: number;
+}
+
+/** `append_differing` adds the sections of the duplicates the primary lacks */
+export type MergeStrategy = 'keep_primary' | 'append_differing';
+
+export interface NoteMergeSummary {
+  merge_id: ULID;
+  primary_id: ULID;
+  duplicate_ids: ULID[];
+  appended_sections: number;
+  relinked_notes: number;
+  tags_added: number;
+  cards_moved: number;
+  time_entries_moved: number;
+  attachments_added: number;
+}
+
+/** An open editing session; its id is the token drafts are saved with */
+export interface EditSession {
+  id: ULID;
+  note_id: ULID;
+  interval_secs: number;
+  started_at: number;
+}
+
+/** A snapshot of a note taken while it was edited */
+export interface NoteVersion {
+  id: ULID;
+  note_id: ULID;
+  title: string;
+  content_md: string;
+  session_id?: ULID | null;
+  created_at: number;
+}
+
+/** `map` uses the target space's tag of the same name, creating it if missing */
+export type TagAction = 'map' | 'drop';
+
+/** `keep` leaves a note's time entries reported under its old space */
+export type TimeEntryAction = 'move' | 'keep' | 'drop';
+
+export interface MoveNoteOptions {
+  /** Notes of the same space moving along; links between them survive */
+  with_notes?: ULID[];
+  tags?: TagAction;
+  time_entries?: TimeEntryAction;
+}
+
+export interface MoveReport {
+  note_ids: ULID[];
+  from_space_id: ULID;
+  to_space_id: ULID;
+  tags_matched: number;
+  tags_created: number;
+  tags_dropped: number;
+  links_preserved: number;
+  links_dropped: number;
+  links_added: number;
+  time_entries_moved: number;
+  time_entries_kept: number;
+  time_entries_dropped: number;
+}
+
+export type BulkSkipReason =
+  | 'not_found'
+  | 'duplicate'
+  | 'trashed'
+  | 'already_trashed'
+  | 'already_in_space'
+  | 'project_in_other_space';
+
+/** What a bulk edit did to one of the selected notes or tasks */
+export type BulkItemResult =
+  | { id: ULID; status: 'succeeded' }
+  | { id: ULID; status: 'skipped'; reason: BulkSkipReason };
+
+/** A new value for a field, or clearing it */
+export type PatchValue<T> = { op: 'set'; value: T } | { op: 'clear' };
+
+/** Fields set on every selected task; omitted ones are left alone */
+export interface TaskPatch {
+  status?: string;
+  priority?: PatchValue<number>;
+  project_id?: PatchValue<ULID>;
+  due_at?: PatchValue<number>;
+}
+
+/** A note shown in another space without being copied */
+export interface NoteReference {
+  id: ULID;
+  note_id: ULID;
+  space_id: ULID;
+  created_at: number;
+}
+
+/** Counts as of the last time the review was generated */
+export interface WeeklyReviewStats {
+  completed_tasks: number;
+  overdue_tasks: number;
+  upcoming_tasks: number;
+  /** Open action items taken over from the previous review */
+  carried_over: number;
+}
+
+/** A space's review of one week; regenerating the week rewrites its note */
+export interface WeeklyReview {
+  id: ULID;
+  space_id: ULID;
+  /** Monday 00:00 UTC of the reviewed week, in seconds */
+  period_start: number;
+  note_id: ULID;
+  generated_at: number;
+  completed_at?: number | null;
+  stats: WeeklyReviewStats;
+}
+
+/** A custom template variable the user is prompted for */
+export interface TemplateVariable {
+  name: string;
+  prompt: string;
+  default_value?: string | null;
+}
+
+/**
+ * Note body with placeholders: {{date}}, {{date+1d}} (d/w/m/y), {{time}},
+ * {{space}}, {{cursor}} and the declared variables
+ */
+export interface NoteTemplate {
+  id: ULID;
+  space_id: ULID;
+  name: string;
+  content: string;
+  variables: TemplateVariable[];
+  created_at: number;
+  updated_at: number;
+}
+
+export interface NoteFromTemplate {
+  note: Note;
+  /** Where {{cursor}} was, in UTF-16 code units */
+  cursor: number | null;
 }
 
 export type TaskStatus = 'inbox' | 'next' | 'in_progress' | 'waiting' | 'done' | 'cancelled';
@@ -40,6 +261,110 @@ export interface Task {
   area?: string;
 }
 
+/** A vault-wide quick capture awaiting triage into a note or task */
+export interface InboxItem {
+  id: ULID;
+  text: string;
+  /** Where it was captured, e.g. 'mobile' or 'desktop' */
+  source: string;
+  captured_at: number; // Unix timestamp
+  updated_at: number; // Unix timestamp
+}
+
+export type PropertyType = 'text' | 'number' | 'date' | 'select' | 'checkbox';
+
+/** A custom field a space defines for its notes */
+export interface PropertyDefinition {
+  id: ULID;
+  space_id: ULID;
+  name: string;
+  property_type: PropertyType;
+  /** Choices of a select; empty for every other type */
+  options: string[];
+  created_at: number; // Unix timestamp
+}
+
+/** Numbers are numbers, checkboxes booleans, dates 'YYYY-MM-DD' strings */
+export type PropertyValue = string | number | boolean;
+
+export interface NoteProperty {
+  property_id: ULID;
+  name: string;
+  property_type: PropertyType;
+  value: PropertyValue;
+  updated_at: number; // Unix timestamp
+}
+
+export type PropertyOp = 'equals' | 'contains' | 'greater_than' | 'less_than' | 'is_set' | 'is_not_set';
+
+/** A condition on a note property, found by name in the note's space */
+export interface PropertyFilter {
+  property: string;
+  op: PropertyOp;
+  /** Unused by 'is_set' and 'is_not_set' */
+  value?: PropertyValue;
+}
+
+/** What an automation rule reacts to; a title pattern is a case-insensitive regex */
+export type AutomationTrigger =
+  | { type: 'tag_added'; tag: string }
+  | { type: 'task_completed' }
+  | { type: 'note_created'; title_pattern: string };
+
+/** Runs on the note or task the rule was triggered by; '{{title}}' is replaced in titles and messages */
+export type AutomationAction =
+  | { type: 'create_task'; title: string; description?: string | null; due_in_days?: number | null }
+  | { type: 'add_tag'; tag: string }
+  | { type: 'move_to_project'; project_id: ULID }
+  | { type: 'create_srs_card' }
+  | { type: 'prompt'; message: string };
+
+export interface AutomationRule {
+  id: ULID;
+  space_id: ULID;
+  name: string;
+  trigger: AutomationTrigger;
+  /** Run in order, all or nothing */
+  actions: AutomationAction[];
+  enabled: boolean;
+  created_at: number; // Unix timestamp
+  updated_at: number; // Unix timestamp
+}
+
+/** Every set field narrows the result; list fields match any of their values */
+export interface TaskFilter {
+  statuses?: TaskStatus[];
+  min_priority?: number;
+  max_priority?: number;
+  /** Inclusive, Unix timestamp */
+  due_after?: number;
+  /** Exclusive, Unix timestamp */
+  due_before?: number;
+  project_ids?: ULID[];
+  tag_ids?: ULID[];
+  has_due_date?: boolean;
+  text?: string;
+  /** Waiting, or in a project that is blocked or depends on an unfinished one */
+  blocked?: boolean;
+}
+
+export type TaskSortField = 'DueAt' | 'Priority' | 'UpdatedAt' | 'Title';
+
+export interface TaskSort {
+  field: TaskSortField;
+  direction: 'Asc' | 'Desc';
+}
+
+export interface TaskView {
+  id: ULID;
+  space_id: ULID;
+  name: string;
+  filter: TaskFilter;
+  sort: TaskSort;
+  created_at: number;
+  updated_at: number;
+}
+
 export type ProjectStatus = 'proposed' | 'active' | 'blocked' | 'done' | 'archived';
 
 export interface Project {
@@ -53,6 +378,54 @@ export interface Project {
   target_end_at?: number; // Unix timestamp
 }
 
+/** Replace the bytes `start..end` of note content with `replacement` */
+export interface Autofix {
+  start: number;
+  end: number;
+  replacement: string;
+}
+
+/** A problem found in note content; `start`/`end` are UTF-8 byte offsets */
+export interface NoteDiagnostic {
+  kind: 'broken_link' | 'malformed_checkbox' | 'duplicate_heading' | 'unclosed_code_fence' | 'missing_blob';
+  severity: 'error' | 'warning' | 'info';
+  start: number;
+  end: number;
+  message: string;
+  suggestions: string[];
+  fix: Autofix | null;
+}
+
+/** Preview metadata for an external URL; failed fetches are cached too, with `error` set */
+export interface UrlMetadata {
+  url: string;
+  final_url: string;
+  title?: string;
+  description?: string;
+  image_url?: string;
+  site_name?: string;
+  favicon_url?: string;
+  error?: string;
+  fetched_at: number;
+  expires_at: number;
+}
+
+/** Link previews for a note: cached metadata plus URLs still to fetch */
+export interface NoteLinkPreviews {
+  cached: UrlMetadata[];
+  needs_fetch: string[];
+}
+
+/** Result of turning a meeting note's action items into tasks */
+export interface ExtractionReport {
+  /** Ids of the tasks created by this run */
+  created: ULID[];
+  /** Items already extracted by an earlier run */
+  skipped: number;
+  ambiguous: string[];
+  method: 'heuristic' | 'llm';
+}
+
 export interface Tag {
   id: ULID;
   space_id: ULID;
@@ -60,6 +433,33 @@ export interface Tag {
   color?: string;
 }
 
+export interface TagUsage {
+  tag: Tag;
+  note_count: number;
+  task_count: number;
+  /** Distinct notes tagged with this tag or any tag nested below it */
+  total_note_count: number;
+}
+
+/** One level of the tag hierarchy built from "parent/child" names */
+export interface TagNode {
+  segment: string;
+  path: string;
+  /** null for a level that only exists as the parent of other tags */
+  tag: Tag | null;
+  note_count: number;
+  total_note_count: number;
+  children: TagNode[];
+}
+
+export interface TagRenameReport {
+  tag: Tag;
+  renamed_tags: number;
+  updated_note_ids: ULID[];
+  /** Notes mentioning the old tag that could not be rewritten */
+  notes_needing_edit: ULID[];
+}
+
 export interface Person {
   id: ULID;
   space_id?: ULID;
@@ -92,6 +492,77 @@ export interface ProjectUpdate {
   when_at: number; // Unix timestamp
   health?: 'green' | 'amber' | 'red';
   summary: string;
+  status?: ProjectHealthStatus | null;
+  percent_complete?: number | null; // 0-100
+  highlights?: string | null;
+  risks_changed?: string | null;
+  next_steps?: string | null;
+}
+
+export type ProjectStatusReport = Pick<
+  ProjectUpdate,
+  'status' | 'percent_complete' | 'highlights' | 'risks_changed' | 'next_steps'
+>;
+
+export interface SpaceStatusSummary {
+  on_track: number;
+  at_risk: number;
+  off_track: number;
+  unreported: number;
+  total: number;
+}
+
+export type ProjectHealthStatus = 'on-track' | 'at-risk' | 'off-track';
+
+export interface ProjectHealthConfig {
+  overdue_milestones_weight: number;
+  high_impact_risks_weight: number;
+  velocity_weight: number;
+  update_staleness_weight: number;
+  on_track_min_score: number;
+  at_risk_min_score: number;
+  velocity_weeks: number;
+  stale_update_days: number;
+  high_impact_risk_cap: number;
+}
+
+export interface ProjectHealthSignals {
+  milestones_with_due_date: number;
+  overdue_milestones: number;
+  overdue_milestone_penalty: number;
+  open_high_impact_risks: number;
+  risk_penalty: number;
+  open_tasks: number;
+  completed_recent: number;
+  completed_previous: number;
+  tracked_seconds_recent: number;
+  velocity_penalty: number;
+  days_since_update: number | null;
+  staleness_penalty: number;
+}
+
+export interface ProjectHealth {
+  project_id: ULID;
+  title: string;
+  score: number; // 0 (worst) to 100 (best)
+  status: ProjectHealthStatus;
+  signals: ProjectHealthSignals;
+  computed_at: number; // Unix timestamp
+}
+
+export interface ProjectHealthSummary {
+  on_track: number;
+  at_risk: number;
+  off_track: number;
+  projects: ProjectHealth[]; // Worst first
+}
+
+export interface ProjectHealthSnapshot {
+  project_id: ULID;
+  day: string; // YYYY-MM-DD
+  score: number;
+  status: ProjectHealthStatus;
+  computed_at: number;
 }
 
 export type SearchScope = 'note' | 'project' | 'space' | 'vault_all';
@@ -130,20 +601,115 @@ export interface ReviewLog {
   lapses: number;
 }
 
-export type FormFieldType = 'Text' | 'Textarea' | 'Number' | 'Checkbox' | 'Date' | 'Time';
+export type ReviewOrder = 'due_date' | 'random' | 'interleave';
+
+/** Daily limits are counted across all sessions of the day */
+export interface ReviewSessionConfig {
+  max_reviews: number;
+  max_new_cards: number;
+  order: ReviewOrder;
+}
+
+export type ReviewSessionStatus = 'active' | 'finished' | 'abandoned';
+
+export interface QueuedCard {
+  card: KnowledgeCard;
+  is_new: boolean;
+}
+
+export interface ReviewSession {
+  id: ULID;
+  space_id: ULID;
+  config: ReviewSessionConfig;
+  status: ReviewSessionStatus;
+  day: string; // YYYY-MM-DD, UTC
+  started_at: number; // Unix timestamp
+  finished_at: number | null;
+  /** Started earlier today and picked up again */
+  resumed: boolean;
+  /** Cards still to answer, in order */
+  queue: QueuedCard[];
+  answered: number;
+}
+
+export interface ReviewSessionStats {
+  session_id: ULID;
+  cards_seen: number;
+  new_cards_seen: number;
+  correct: number;
+  accuracy: number; // 0-1
+  time_spent_secs: number;
+  /** Every queued card was answered, so the day counts towards the streak */
+  completed: boolean;
+  streak_days: number;
+  longest_streak_days: number;
+}
+
+export type FormFieldType = 'Text' | 'Textarea' | 'Number' | 'Checkbox' | 'Date' | 'Time' | 'Select';
 
 export interface FormField {
   name: string;
   label: string;
   field_type: FormFieldType;
   default_value?: string;
+  required?: boolean;
+  /** Allowed values of a Select field */
+  options?: string[];
+  /** Where a mapped submission puts the value */
+  mapping?: FieldMapping;
 }
 
+export type FieldMapping =
+  | { kind: 'note_section'; heading: string }
+  | { kind: 'health_metric'; metric_type: string; unit: string | null }
+  | {
+      kind: 'transaction';
+      transaction_type: 'expense' | 'income';
+      currency: string;
+      /** Used when category_field is unset or left empty */
+      category?: string;
+      category_field?: string | null;
+      account_id?: string;
+    }
+  | { kind: 'note_property'; property: string };
+
 export interface FormTemplate {
   id: ULID;
   space_id: ULID;
   name: string;
   fields: FormField[];
+  schema_version: number;
+  /** Title of the note a mapped submission creates: {date}, {date:%A %-d %B} or {field} */
+  title_pattern: string | null;
+}
+
+export interface FormSubmission {
+  id: ULID;
+  template_id: ULID;
+  /** Template version the values were validated against */
+  schema_version: number;
+  values: Record<string, string | number | boolean>;
+  note_id: ULID | null;
+  created_at: number;
+}
+
+export interface MappedSubmission {
+  submission: FormSubmission;
+  note: Note | null;
+  health_metric_ids: ULID[];
+  transaction_ids: ULID[];
+}
+
+/** Range bounds are inclusive */
+export type FieldFilter =
+  | { op: 'equals'; field: string; value: unknown }
+  | { op: 'range'; field: string; min: unknown | null; max: unknown | null };
+
+export interface SubmissionFilters {
+  fields?: FieldFilter[];
+  created_after?: number | null;
+  created_before?: number | null;
+  limit?: number | null;
 }
 
 export interface WeeklyCount {
@@ -159,6 +725,54 @@ export interface AnalyticsData {
   notes_created_by_week: WeeklyCount[];
 }
 
+export interface ActivityOptions {
+  /** Offset of local time from UTC; buckets use local time */
+  utc_offset_minutes?: number;
+  weights?: { note_edit: number; task_completion: number; time_entry: number };
+}
+
+export interface ActivityCounts {
+  note_edits: number;
+  task_completions: number;
+  time_entries: number;
+  /** Weighted sum of the counts */
+  score: number;
+}
+
+export interface HeatmapCell extends ActivityCounts {
+  /** 0 = Monday */
+  weekday: number;
+  hour: number;
+}
+
+export interface ActivityHeatmap {
+  /** All 7 x 24 buckets, Monday 00:00 first */
+  cells: HeatmapCell[];
+  max_score: number;
+}
+
+export interface DailyActivity extends ActivityCounts {
+  /** Local date, YYYY-MM-DD */
+  date: string;
+}
+
+/** Net words per local day; a note's saves on the day are netted first */
+export interface DailyWriting {
+  /** Local date, YYYY-MM-DD */
+  date: string;
+  words_added: number;
+  words_removed: number;
+  net_words: number;
+}
+
+export interface WritingStats {
+  /** Every day of the range, with zeros when nothing was written */
+  days: DailyWriting[];
+  words_added: number;
+  words_removed: number;
+  net_words: number;
+}
+
 export interface TimeEntry {
   id: ULID;
   space_id: ULID;
@@ -170,6 +784,19 @@ export interface TimeEntry {
   ended_at?: number; // Unix timestamp
   duration_seconds?: number;
   is_running: boolean;
+  /** Event the entry was logged from; its times were copied at the time */
+  calendar_event_id?: ULID;
+}
+
+/** What to log differently from the event; exactly one of task, project or note is required */
+export interface EventEntryOverrides {
+  task_id?: ULID;
+  project_id?: ULID;
+  note_id?: ULID;
+  /** Defaults to the event's summary */
+  description?: string;
+  started_at?: number;
+  ended_at?: number;
 }
 
 export interface TimeStats {
@@ -178,6 +805,58 @@ export interface TimeStats {
   average_seconds: number;
 }
 
+/** Half-open interval of Unix timestamps in seconds */
+export interface TimeRange {
+  start: number;
+  end: number;
+}
+
+export type Weekday = 'Mon' | 'Tue' | 'Wed' | 'Thu' | 'Fri' | 'Sat' | 'Sun';
+
+/** Available part of each day in local time; times are "HH:MM:SS" strings */
+export interface WorkingHours {
+  start: string;
+  end: string;
+  weekdays: Weekday[];
+  utc_offset_minutes?: number;
+}
+
+export interface FreeBusy {
+  busy: TimeRange[];
+  free: TimeRange[];
+}
+
+export interface CalendarEvent {
+  id: string;
+  space_id: string;
+  summary: string;
+  description: string | null;
+  location: string | null;
+  start: number;
+  /** Exclusive; timed events without one last an hour, all-day events a day */
+  end: number | null;
+  /** All-day events start and end at UTC midnight */
+  all_day: boolean;
+  remind_minutes_before: number | null;
+  /** "local" or the id of the CalDAV account the event came from */
+  source: string;
+  created_at: number;
+  updated_at: number;
+}
+
+export interface DueReminder {
+  event: CalendarEvent;
+  remind_at: number;
+}
+
+export interface FocusConstraints {
+  range: TimeRange;
+  working_hours?: WorkingHours;
+  min_block_minutes?: number;
+  max_block_minutes?: number;
+  max_blocks_per_day?: number;
+}
+
 export interface SyncTask {
   id: string;
   device_id: string;
@@ -188,11 +867,66 @@ export interface SyncTask {
   created_at: number;
 }
 
+export type SyncEntityType =
+  | 'note'
+  | 'task'
+  | 'project'
+  | 'health_metric'
+  | 'track'
+  | 'playlist'
+  | 'calendar_event'
+  | 'inbox_item';
+
+/** Entity types synced with a device */
+export type SyncScope = 'all' | { only: SyncEntityType[] };
+
+/** Delta to a shared space not applied because its device's user lacked the permission */
+export interface RejectedDelta {
+  id: string;
+  device_id: string;
+  /** Null when the device isn't linked to a user */
+  user_id: string | null;
+  space_id: string;
+  entity_type: string;
+  entity_id: string;
+  operation: 'Create' | 'Update' | 'Delete';
+  permission: string;
+  rejected_at: number;
+}
+
 export interface SyncStats {
   total_synced: number;
   last_sync_at: number | null;
   success_rate: number;
   conflicts_total: number;
+  /** Bytes put on the network by syncs that recorded their transfers */
+  total_bytes_sent: number;
+  total_bytes_received: number;
+}
+
+/** Deltas and bytes moved in one direction; `wire_bytes` is after compression */
+export interface TransferCounts {
+  deltas: number;
+  payload_bytes: number;
+  wire_bytes: number;
+}
+
+export interface TransferBreakdown {
+  /** Entity type or device id */
+  key: string;
+  sent: TransferCounts;
+  received: TransferCounts;
+}
+
+export interface SyncTransferReport {
+  sessions: number;
+  sent: TransferCounts;
+  received: TransferCounts;
+  compression_savings_bytes: number;
+  /** Heaviest first */
+  by_entity_type: TransferBreakdown[];
+  /** Heaviest first */
+  by_device: TransferBreakdown[];
 }
 
 export interface DeviceInfo {
@@ -205,14 +939,32 @@ export interface DeviceInfo {
   protocol_version: string;
 }
 
+/** Key exchange message sent between devices while pairing */
+export interface PairingHello {
+  deviceId: string;
+  /** Empty in the initiator's opening hello, which only commits to its keys */
+  publicKey: number[];
+  /** Long-term sync transport key, pinned once the SAS is confirmed */
+  staticKey: number[];
+  /** SHA-256 of the initiator's keys, revealed after the responder replies */
+  commitment: number[];
+}
+
+/** Short authentication string compared on both devices before trusting a peer */
+export interface ShortAuthString {
+  decimal: string;
+  emoji: string[];
+}
+
 export interface DiscoveredDevice {
-  device_id: string;
-  device_name: string;
-  device_type: 'Desktop' | 'Mobile' | 'Tablet';
-  ip_address: string;
-  sync_port: number;
-  os_version: string;
-  last_seen: string; // DateTime serialized
+  id: string;
+  name: string;
+  address: string;
+  port: number;
+  /** 'manual' for peers added by address on networks without mDNS */
+  source: 'mdns' | 'manual';
+  reachability: 'unknown' | 'reachable' | 'unreachable';
+  last_probe_at: number | null;
 }
 
 export type ConflictType = 'UpdateUpdate' | 'DeleteUpdate' | 'UpdateDelete';
@@ -232,6 +984,152 @@ export interface SyncConflict {
   space_id?: string;
 }
 
+/** A field that differs between the two versions of a conflicting entity */
+export interface FieldChange {
+  field: string;
+  local: string | null;
+  remote: string | null;
+}
+
+/** A sync conflict described for the user; `conflict` is passed back to resolve it */
+export interface ConflictSummary {
+  conflict: SyncConflict;
+  conflict_id: string | null;
+  title: string | null;
+  /** Changed fields; empty when either version couldn't be read */
+  changes: FieldChange[];
+  detected_at: number | null;
+  local_modified_at: number | null;
+  remote_modified_at: number | null;
+  local_device: string | null;
+  remote_device: string | null;
+  /** Why the summary is partial, e.g. an undecryptable version */
+  problems: string[];
+}
+
+/** What one poll of the sync relay received */
+export interface RelayPullReport {
+  /** Sessions that arrived in full and were applied */
+  sessions: number;
+  entities_pulled: number;
+  conflicts: number;
+  /** Envelopes that failed to open or weren't meant for this device */
+  rejected: number;
+  /** Sessions that arrived in full but couldn't be applied; the sender sends them again */
+  failed: number;
+  /** Sessions of this device the peer confirmed it applied */
+  acknowledged: number;
+}
+
+/** One group of typed settings, as listed for diagnostics */
+export interface SettingsEntry {
+  /** `<domain>.<name>`, e.g. `sync.settings` */
+  key: string;
+  description: string;
+  version: number;
+  /** The settings in effect; the raw stored value when `error` is set */
+  value: unknown;
+  /** Nothing is stored, so the defaults apply */
+  is_default: boolean;
+  updated_at: number | null;
+  /** Why the stored value can't be loaded */
+  error: string | null;
+}
+
+export interface SyncSettings {
+  port: number;
+  /** Accept protocol 1 peers (the current mobile app), which sync without transport encryption */
+  allow_legacy_protocol: boolean;
+}
+
+export interface QueryStatsSettings {
+  enabled: boolean;
+  /** Queries running at least this long are logged */
+  slow_query_ms: number;
+}
+
+/** How often one query ran and how long it took, for diagnostics */
+export interface QueryStat {
+  /** The SQL with whitespace collapsed */
+  sql: string;
+  count: number;
+  total_micros: number;
+  max_micros: number;
+  /** Runs at or above the slow-query threshold */
+  slow_count: number;
+}
+
+export interface OcrSettings {
+  /** BCP-47 languages for jobs with no hints whose space has no default */
+  languages: string[];
+  concurrency: number;
+  max_attempts: number;
+  retry_backoff_secs: number;
+  poll_interval_secs: number;
+}
+
+/** Core events, forwarded by the desktop as the `core-event` Tauri event */
+export type CoreEvent =
+  | {
+      kind: 'sync_conflict_detected';
+      space_id: string;
+      conflict_id: string;
+      /** Device the conflicting change came from */
+      device_id: string;
+      entity_type: string;
+      entity_id: string;
+      conflict_type: ConflictType;
+    }
+  | { kind: 'ocr_job_completed'; job_id: string; blob_id: string }
+  | { kind: 'ocr_job_failed'; job_id: string; blob_id: string; error: string }
+  | { kind: 'backup_completed'; backup_id: string; size_bytes: number; description: string | null }
+  | { kind: 'backup_failed'; description: string | null; error: string }
+  | {
+      kind: 'insight_generated';
+      space_id: string;
+      insight_id: string;
+      insight_type: 'deadline_approaching' | 'project_stagnant' | 'high_workload' | 'habit_streak';
+      insight_severity: 'low' | 'medium' | 'high' | 'critical';
+      title: string;
+      entity_type: string | null;
+      entity_id: string | null;
+    }
+  | {
+      kind: 'health_goal_achieved';
+      space_id: string;
+      goal_id: string;
+      title: string;
+      metric_type: string;
+      value: number;
+    }
+  | {
+      kind: 'health_goal_regressed';
+      space_id: string;
+      goal_id: string;
+      title: string;
+      metric_type: string;
+      from: HealthGoalStatus;
+      to: HealthGoalStatus;
+    }
+  | { kind: 'caldav_auth_failed'; account_id: string; username: string; url: string }
+  | {
+      kind: 'automation_prompt';
+      space_id: string;
+      rule_id: string;
+      rule_name: string;
+      message: string;
+      entity_type: 'note' | 'task';
+      entity_id: string;
+    }
+  | { kind: 'settings_changed'; key: string };
+
+export type EmittedEvent = CoreEvent & {
+  id: string;
+  /** Unix milliseconds */
+  emitted_at: number;
+  severity: 'info' | 'warning' | 'error';
+};
+
 // --- New types for Personal Modes & Social ---
 
 export interface HealthMetric {
@@ -246,6 +1144,90 @@ export interface HealthMetric {
   updated_at: number;
 }
 
+/** One day of readings; `day` is the start of the UTC day */
+export interface TrendPoint {
+  day: number;
+  value: number;
+  samples: number;
+  moving_average: number;
+}
+
+export interface MetricTrend {
+  metric_type: string;
+  points: TrendPoint[];
+  min: number | null;
+  max: number | null;
+  slope_per_day: number;
+  direction: 'improving' | 'stable' | 'declining';
+  higher_is_better: boolean;
+}
+
+export interface ThresholdBreach {
+  metric_id: string;
+  metric_type: string;
+  value: number;
+  recorded_at: number;
+  min_value: number | null;
+  max_value: number | null;
+}
+
+export type HealthExportSource = 'apple_health' | 'google_fit';
+
+export interface MetricImportCounts {
+  imported: number;
+  /** Daily totals that changed since they were last imported */
+  updated: number;
+  duplicates: number;
+}
+
+export interface HealthImportReport {
+  metrics: Record<string, MetricImportCounts>;
+  skipped_duplicates: number;
+  unsupported_records: number;
+}
+
+export type GoalComparison = 'at_least' | 'at_most';
+
+/** What a health goal's readings are measured on */
+export type GoalAggregation = 'latest' | 'seven_day_average' | 'thirty_day_average';
+
+export type HealthGoalStatus = 'achieved' | 'on_track' | 'behind' | 'expired';
+
+export interface HealthGoalTarget {
+  metric_type: string;
+  comparison: GoalComparison;
+  target_value: number;
+  unit: string | null;
+  aggregation: GoalAggregation;
+  /** The target is to be met by then; null for an ongoing goal */
+  deadline: number | null;
+}
+
+export interface HealthGoal {
+  id: string;
+  space_id: string;
+  title: string;
+  target: HealthGoalTarget;
+  /** The free-form goal the target was parsed from */
+  goal_id: string | null;
+  created_at: number;
+  /** Outcome of the last evaluation; null until the goal is evaluated */
+  status: HealthGoalStatus | null;
+  current_value: number | null;
+  projected_value: number | null;
+  evaluated_at: number | null;
+}
+
+export interface HealthGoalEvaluation {
+  goal_id: string;
+  status: HealthGoalStatus;
+  previous_status: HealthGoalStatus | null;
+  current_value: number | null;
+  /** Where the trend puts the value at the deadline */
+  projected_value: number | null;
+  evaluated_at: number;
+}
+
 export interface Goal {
   id: string;
   space_id: string;
@@ -284,6 +1266,37 @@ export interface Transaction {
   created_at: number;
 }
 
+/** Monthly budget of a transaction category; months are "YYYY-MM" strings */
+export interface Budget {
+  space_id: string;
+  category: string;
+  monthly_amount: number;
+  rollover: boolean;
+  start_month: string;
+}
+
+export interface BudgetStatus {
+  category: string;
+  /** Null until something was spent in the category */
+  currency: string | null;
+  monthly_amount: number;
+  /** Left over from earlier months; negative after overspending */
+  carried_over: number;
+  budgeted: number;
+  spent: number;
+  remaining: number;
+  rollover: boolean;
+}
+
+export interface SpendingSummary {
+  /** Category name or "YYYY-MM" month */
+  key: string;
+  currency: string;
+  spent: number;
+  income: number;
+  transaction_count: number;
+}
+
 export interface Recipe {
   id: string;
   space_id: string;
@@ -292,6 +1305,64 @@ export interface Recipe {
   rating: number;
   difficulty: string;
   created_at: number;
+  /** Servings the ingredient quantities are for */
+  servings: number;
+}
+
+export type IngredientUnit =
+  | 'gram'
+  | 'kilogram'
+  | 'milliliter'
+  | 'liter'
+  | 'teaspoon'
+  | 'tablespoon'
+  | 'cup'
+  | 'ounce'
+  | 'pound'
+  | 'pinch'
+  | 'piece';
+
+export interface RecipeIngredient {
+  id: string;
+  recipe_id: string;
+  name: string;
+  quantity: number;
+  unit: IngredientUnit;
+  note: string | null;
+  position: number;
+}
+
+export interface ScaledIngredient {
+  name: string;
+  quantity: number;
+  unit: IngredientUnit;
+  note: string | null;
+}
+
+export interface ScaledRecipe {
+  recipe_id: string;
+  servings: number;
+  target_servings: number;
+  ingredients: ScaledIngredient[];
+}
+
+export interface ShoppingListItem {
+  id: string;
+  name: string;
+  quantity: number;
+  unit: IngredientUnit;
+  /** The same ingredient is also listed in a unit this one doesn't convert to */
+  unmergeable: boolean;
+  is_checked: boolean;
+  position: number;
+}
+
+export interface ShoppingList {
+  id: string;
+  space_id: string;
+  recipe_ids: string[];
+  created_at: number;
+  items: ShoppingListItem[];
 }
 
 export interface Trip {
@@ -305,6 +1376,69 @@ export interface Trip {
   created_at: number;
 }
 
+/** Itinerary entries; `day` is the YYYY-MM-DD UTC date of `starts_at` */
+export interface ItineraryItem {
+  id: string;
+  trip_id: string;
+  title: string;
+  item_type: string;
+  location: string | null;
+  notes: string | null;
+  starts_at: number;
+  day: string;
+  /** Place within the day, from 0 */
+  position: number;
+  created_at: number;
+}
+
+export interface ItineraryDay {
+  day: string;
+  items: ItineraryItem[];
+}
+
+export interface PackingItem {
+  id: string;
+  trip_id: string;
+  name: string;
+  category: string | null;
+  quantity: number;
+  is_packed: boolean;
+  position: number;
+  created_at: number;
+}
+
+export interface PackingTemplateItem {
+  name: string;
+  category: string | null;
+  quantity: number;
+}
+
+export interface PackingTemplate {
+  id: string;
+  space_id: string;
+  name: string;
+  items: PackingTemplateItem[];
+  created_at: number;
+}
+
+export interface PackingStats {
+  total: number;
+  packed: number;
+  unpacked: number;
+}
+
+export interface TripSummary {
+  trip_id: string;
+  name: string;
+  destination: string;
+  start_date: number;
+  end_date: number;
+  /** Every day of the trip, plus days outside it that have items */
+  days: { day: string; item_count: number }[];
+  item_count: number;
+  unpacked_count: number;
+}
+
 export interface GraphSnapshot {
   space_id: string;
   nodes: GraphNode[];
@@ -352,6 +1486,24 @@ export interface Role {
   permissions: string[];
 }
 
+/** A user seen in a space within the last 90 seconds */
+export interface Presence {
+  user_id: string;
+  space_id: string;
+  /** What they have open, if anything */
+  entity_id: string | null;
+  last_seen: number;
+  expires_at: number;
+}
+
+/** Advisory lock on a note or task; local edits by anyone else are refused until it expires */
+export interface EditLock {
+  entity_id: string;
+  user_id: string;
+  acquired_at: number;
+  expires_at: number;
+}
+
 export interface WebViewSession {
   id: string;
   account_id: string;
@@ -365,6 +1517,15 @@ export interface AnalyticsOverview {
   top_platform: string;
 }
 
+/** Database connection pool state of the open vault */
+export interface PoolHealth {
+  connections: number;
+  idle: number;
+  wal_size_bytes: number;
+  /** Unix timestamp of the last completed WAL checkpoint */
+  last_checkpoint: number | null;
+}
+
 export interface BackupMetadata {
   id: string;
   space_id?: string;
@@ -374,6 +1535,57 @@ export interface BackupMetadata {
   encrypted: boolean;
 }
 
+/** Background insight generation for a space; spaces without one run every 6 hours */
+export interface InsightSchedule {
+  enabled: boolean;
+  interval_minutes: number;
+}
+
+/** Scheduled backup settings; `time_of_day` is a UTC "HH:MM:SS" string */
+export interface BackupPolicy {
+  enabled: boolean;
+  interval_hours: number;
+  time_of_day: string | null;
+  max_count: number;
+  max_age_days: number;
+  target_dir: string;
+}
+
+export interface BackupRun {
+  id: string;
+  started_at: number;
+  finished_at: number | null;
+  status: 'completed' | 'skipped' | 'failed';
+  reason: string | null;
+  backup_id: string | null;
+  pruned_count: number;
+}
+
+export type NotionEntityKind = 'task' | 'project' | 'note';
+
+/** Keys are Notion database names without the id suffix; unlisted databases are detected from their columns */
+export interface NotionImportOptions {
+  database_kinds: Record<string, NotionEntityKind>;
+}
+
+export interface ImportedDatabase {
+  name: string;
+  kind: NotionEntityKind;
+  rows: number;
+}
+
+export interface ImportReport {
+  /** Pages, including the page behind every database row */
+  notes: number;
+  tasks: number;
+  projects: number;
+  /** Tasks attached to an imported project through a relation column */
+  linked_tasks: number;
+  /** Front matter values set as properties the space already defines */
+  properties: number;
+  databases: ImportedDatabase[];
+}
+
 export interface User {
   id: string;
   username: string;
@@ -387,6 +1599,198 @@ export interface Session {
   user_id: string;
 }
 
+export interface AuthAuditFilter {
+  user_id?: string;
+  /** Username, vault path or affected user */
+  subject?: string;
+  event_types?: string[];
+  success?: boolean;
+  since?: number;
+  until?: number;
+  limit?: number;
+  offset?: number;
+}
+
+export interface AuthAuditEntry {
+  id: string;
+  user_id: string | null;
+  event_type: string;
+  subject: string | null;
+  success: boolean;
+  details: Record<string, unknown> | null;
+  user_agent: string | null;
+  created_at: number;
+}
+
+/** A note, task or project change in a space's tamper-evident audit chain */
+export interface DataAuditEvent {
+  id: string;
+  space_id: string;
+  chain_seq: number;
+  actor: string | null;
+  entity_type: string;
+  entity_id: string;
+  operation: 'create' | 'update' | 'delete';
+  /** "local", or the id of the device the change synced from */
+  source: string;
+  created_at: number;
+  prev_hash: string;
+  hash: string;
+}
+
+export interface AuditChainReport {
+  space_id: string;
+  events_checked: number;
+  valid: boolean;
+  first_break: { chain_seq: number; event_id: string | null; reason: string } | null;
+}
+
+/** Auto-lock state of the open vault; `idle_timeout_secs` is null when auto-lock is off */
+export interface AutoLockStatus {
+  locked: boolean;
+  idle_timeout_secs: number | null;
+  remaining_secs: number | null;
+}
+
+export type IntegrityCheckMode = 'quick' | 'full';
+export type IssueSeverity = 'info' | 'warning' | 'error' | 'critical';
+export type IntegrityIssueKind =
+  | 'database_corruption'
+  | 'orphan_note_tag'
+  | 'orphan_task_tag'
+  | 'orphan_link'
+  | 'orphan_note_meta'
+  | 'orphan_crdt_log'
+  | 'orphan_attachment'
+  | 'orphan_caldav_mapping'
+  | 'orphan_blob_ref'
+  | 'missing_blob_ref'
+  | 'unreferenced_blob'
+  | 'stale_search_index'
+  | 'orphan_search_index'
+  | 'orphan_sync_conflict';
+
+export interface IntegrityIssue {
+  kind: IntegrityIssueKind;
+  /** Ids identifying the affected rows; their meaning depends on `kind` */
+  entity_ids: string[];
+  severity: IssueSeverity;
+  auto_fixable: boolean;
+  message: string;
+}
+
+/** Issues are sorted most severe first */
+export interface VaultIntegrityReport {
+  mode: IntegrityCheckMode;
+  issues: IntegrityIssue[];
+  checked_at: number;
+}
+
+export interface VaultRepairReport {
+  repaired: number;
+  /** Not auto-fixable, or already gone */
+  skipped: number;
+}
+
+export type StorageCategory =
+  | 'notes'
+  | 'attachments'
+  | 'social'
+  | 'sync_bookkeeping'
+  | 'search_index'
+  | 'history'
+  | 'other';
+
+export interface TableUsage {
+  table: string;
+  category: StorageCategory;
+  rows: number;
+  bytes: number;
+}
+
+export interface CategoryUsage {
+  category: StorageCategory;
+  rows: number;
+  bytes: number;
+  /** Stored outside the database file, i.e. attachment contents */
+  external_bytes: number;
+}
+
+/** Bytes are column lengths whatever the report's `size_source` */
+export interface SpaceUsage {
+  space_id: string;
+  category: StorageCategory;
+  rows: number;
+  bytes: number;
+}
+
+export interface StorageReport {
+  /** `dbstat` counts pages including indexes; `column_lengths` only the data */
+  size_source: 'dbstat' | 'column_lengths';
+  /** Largest first */
+  categories: CategoryUsage[];
+  /** Largest first */
+  tables: TableUsage[];
+  spaces: SpaceUsage[];
+  file_bytes: number;
+  wal_bytes: number;
+  page_size: number;
+  free_pages: number;
+  vacuum_recommended: boolean;
+  generated_at: number;
+}
+
+export interface VacuumResult {
+  file_bytes_before: number;
+  file_bytes_after: number;
+  duration_ms: number;
+}
+
+export interface UndoableOperation {
+  id: string;
+  space_id: string;
+  /** What removed the data, e.g. `delete_task` */
+  operation: string;
+  entity_type: string;
+  entity_id: string;
+  summary: string;
+  /** Rows the undo would restore */
+  row_count: number;
+  size_bytes: number;
+  created_at: number;
+  expires_at: number;
+}
+
+export type MaintenanceTaskStatus = 'completed' | 'failed' | 'skipped';
+
+export interface MaintenanceTaskOutcome {
+  task: string;
+  /** `skipped` when the task didn't fit in the remaining budget */
+  status: MaintenanceTaskStatus;
+  items_affected: number;
+  duration_ms: number;
+  error: string | null;
+}
+
+export interface MaintenanceRun {
+  id: string;
+  started_at: number;
+  duration_ms: number;
+  /** In the order the tasks were considered */
+  tasks: MaintenanceTaskOutcome[];
+}
+
+export interface MaintenanceHistoryEntry {
+  id: string;
+  /** Shared by the tasks of one run */
+  run_id: string;
+  task: string;
+  started_at: number;
+  duration_ms: number;
+  items_affected: number;
+  error: string | null;
+}
+
 export interface SearchResult {
   entity_type: string;
   entity_id: string;
@@ -395,6 +1799,25 @@ export interface SearchResult {
   score: number;
 }
 
+export type ErrorCategory =
+  | 'not_found'
+  | 'permission_denied'
+  | 'vault_locked'
+  | 'conflict'
+  | 'validation'
+  | 'io'
+  | 'network'
+  | 'crypto'
+  | 'internal';
+
+/** Error returned by backend commands; `code` is stable, e.g. `note.not_found` */
+export interface CoreError {
+  code: string;
+  category: ErrorCategory;
+  message: string;
+  context: Record<string, string>;
+}
+
 // Social Media Suite types
 export * from './social';
 export * from './dashboard';
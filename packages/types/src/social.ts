@@ -57,9 +57,25 @@ export interface TimelinePost {
 export interface TimelineFilters {
   platforms?: string[];
   categories?: string[];
+  accounts?: string[];
+  /** Discovered topic (cluster) ids */
+  topics?: string[];
   after?: number;
   before?: number;
   limit?: number;
+  /** Keyset cursor from a previous TimelinePage */
+  before_timestamp?: number;
+  before_id?: string;
+}
+
+export interface TimelineCursor {
+  before_timestamp: number;
+  before_id: string;
+}
+
+export interface TimelinePage {
+  posts: TimelinePost[];
+  next_cursor: TimelineCursor | null;
 }
 
 export interface SocialCategory {
@@ -71,6 +87,11 @@ export interface SocialCategory {
   created_at: number;
 }
 
+/** How posts are archived to notes; the tag defaults to `social-archive` */
+export interface ArchiveOptions {
+  tag?: string;
+}
+
 export interface TimelineStats {
   total_posts: number;
   platforms_count: number;
@@ -79,6 +100,143 @@ export interface TimelineStats {
   this_week_posts: number;
 }
 
+export type RuleCondition =
+  | { type: 'all'; conditions: RuleCondition[] }
+  | { type: 'any'; conditions: RuleCondition[] }
+  | { type: 'keyword'; keyword: string }
+  | { type: 'author_equals'; author: string }
+  | { type: 'author_contains'; pattern: string }
+  | { type: 'platform_equals'; platform: string }
+  | { type: 'engagement_above'; threshold: number }
+  | { type: 'has_media' }
+  | { type: 'posted_within'; after?: number; before?: number; last_hours?: number };
+
+export type RuleMatchMode = 'first_match' | 'all_matches';
+
+export interface CategoryRule {
+  id: string;
+  space_id: string;
+  category_id: string;
+  name: string;
+  condition: RuleCondition;
+  priority: number;
+  enabled: boolean;
+  created_at: number;
+}
+
+export interface RulePlanEntry {
+  post_id: string;
+  add_category_ids: string[];
+  remove_category_ids: string[];
+  matched_rule_ids: string[];
+}
+
+export interface RuleRunResult {
+  posts_changed: number;
+  assignments_added: number;
+  assignments_removed: number;
+}
+
+export type PlatformAccess =
+  | { status: 'allowed'; minutes_remaining: number | null }
+  | { status: 'blocked_by_focus'; focus_mode_id: string; focus_mode_name: string }
+  | {
+      status: 'limit_exhausted';
+      focus_mode_id: string;
+      daily_minutes: number;
+      used_minutes: number;
+      minutes_remaining: number;
+      resets_at: number;
+    };
+
+export interface PlatformUsage {
+  platform: string;
+  day: string;
+  seconds: number;
+  limit_minutes: number | null;
+  minutes_remaining: number | null;
+}
+
+export interface FiredAutomation {
+  rule_id: string;
+  rule_name: string;
+  action_type: 'ActivateFocusMode' | 'DisableSync' | 'SendNotification' | 'AutoCategorize';
+  action_value: string;
+  platform: string;
+  usage_percent: number;
+}
+
+/** Weeks start on Monday */
+export type TimeBucket = 'day' | 'week';
+
+export interface EngagementOptions {
+  /** Offset of local time from UTC; buckets use local time */
+  utc_offset_minutes?: number;
+  /** Only posts in any of these categories */
+  categories?: string[];
+}
+
+export interface TimeSeriesPoint {
+  /** First day of the bucket, "YYYY-MM-DD" */
+  date: string;
+  /** Posts in the bucket */
+  count: number;
+  likes: number;
+  comments: number;
+  shares: number;
+}
+
+export interface PostingTimeCell {
+  /** 0 = Monday */
+  weekday: number;
+  hour: number;
+  post_count: number;
+  /** Average likes + comments + shares per post */
+  avg_engagement: number;
+  /** Fewer than `min_samples` posts */
+  low_confidence: boolean;
+}
+
+export interface PostingTimeAnalysis {
+  /** All 7 x 24 buckets, Monday 00:00 first */
+  cells: PostingTimeCell[];
+  min_samples: number;
+  /** Highest average engagement among cells with enough posts */
+  best: PostingTimeCell | null;
+}
+
+export interface TopicClusterOptions {
+  /** Topics to form when clustering from scratch */
+  k: number;
+  max_iterations: number;
+  /** Cosine similarity a new post needs to its nearest topic to join it */
+  min_similarity: number;
+  /** Replace the existing topics by clustering every post in range again */
+  full_recluster: boolean;
+  /** Terms in each topic label */
+  label_terms: number;
+}
+
+export interface TopicClusteringResult {
+  reclustered: boolean;
+  clusters: number;
+  posts_embedded: number;
+  posts_assigned: number;
+  /** Posts not similar enough to any topic */
+  posts_unassigned: number;
+}
+
+export interface TopicOverview {
+  cluster_id: string;
+  label: string;
+  post_count: number;
+  avg_likes: number;
+  avg_shares: number;
+  avg_comments: number;
+  /** Likes, shares and comments per post */
+  avg_engagement: number;
+}
+
 export type Platform =
   | 'twitter'
   | 'instagram'